@@ -0,0 +1,282 @@
+//! RustDesk 企业版服务器管理接口的异步客户端。
+//!
+//! ```no_run
+//! # async fn run() -> Result<(), hbbs_enterprise_client::Error> {
+//! use hbbs_enterprise_client::{AuditLogQuery, Client, DeviceQuery};
+//!
+//! let mut client = Client::new("https://rustdesk.example.com");
+//! client.login("admin", "password", None).await?;
+//! let offline = client
+//!     .list_devices(&DeviceQuery { online: Some(false), ..Default::default() })
+//!     .await?;
+//! let logins = client
+//!     .audit_logs(&AuditLogQuery { action: Some("login".to_owned()), ..Default::default() })
+//!     .await?;
+//! # Ok(())
+//! # }
+//! ```
+mod types;
+
+pub use types::*;
+
+use reqwest::{Method, RequestBuilder, StatusCode};
+use serde::{de::DeserializeOwned, Serialize};
+use std::fmt;
+
+#[derive(Debug)]
+pub enum Error {
+    Http(reqwest::Error),
+    // 非 2xx 状态码，如 401 未登录、403 权限不足
+    Status(StatusCode),
+    // 服务端返回 success = false
+    Api(String),
+    // 请求参数校验失败，附逐字段的错误
+    Invalid(String, Vec<FieldError>),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Http(e) => write!(f, "http error: {}", e),
+            Error::Status(status) => write!(f, "unexpected status {}", status),
+            Error::Api(message) => write!(f, "api error: {}", message),
+            Error::Invalid(message, _) => write!(f, "invalid request: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Http(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<reqwest::Error> for Error {
+    fn from(e: reqwest::Error) -> Self {
+        Error::Http(e)
+    }
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug, Clone)]
+pub struct Client {
+    http: reqwest::Client,
+    base_url: String,
+    token: Option<String>,
+}
+
+impl Client {
+    pub fn new(base_url: &str) -> Self {
+        Self::with_http_client(reqwest::Client::new(), base_url)
+    }
+
+    // 需要自定义超时、代理或根证书时传入自己的 reqwest::Client
+    pub fn with_http_client(http: reqwest::Client, base_url: &str) -> Self {
+        Self {
+            http,
+            base_url: base_url.trim_end_matches('/').to_owned(),
+            token: None,
+        }
+    }
+
+    // 使用已有的JWT，不需要再登录
+    pub fn with_token(mut self, token: &str) -> Self {
+        self.token = Some(token.to_owned());
+        self
+    }
+
+    pub fn token(&self) -> Option<&str> {
+        self.token.as_deref()
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}{}", self.base_url, path)
+    }
+
+    fn request(&self, method: Method, path: &str) -> RequestBuilder {
+        let req = self.http.request(method, self.url(path));
+        match &self.token {
+            Some(token) => req.bearer_auth(token),
+            None => req,
+        }
+    }
+
+    async fn send<T: DeserializeOwned>(&self, req: RequestBuilder) -> Result<ApiResponse<T>> {
+        let res = req.send().await?;
+        if res.status() == StatusCode::BAD_REQUEST {
+            if let Ok(res) = res.json::<ApiResponse<Vec<FieldError>>>().await {
+                return Err(Error::Invalid(res.message, res.data.unwrap_or_default()));
+            }
+            return Err(Error::Status(StatusCode::BAD_REQUEST));
+        }
+        if !res.status().is_success() {
+            return Err(Error::Status(res.status()));
+        }
+        let res: ApiResponse<T> = res.json().await?;
+        if !res.success {
+            return Err(Error::Api(res.message));
+        }
+        Ok(res)
+    }
+
+    async fn get<T: DeserializeOwned, Q: Serialize + ?Sized>(&self, path: &str, query: &Q) -> Result<T> {
+        let res = self.send(self.request(Method::GET, path).query(query)).await?;
+        res.data.ok_or(Error::Api(res.message))
+    }
+
+    async fn call<T: DeserializeOwned, B: Serialize + ?Sized>(
+        &self,
+        method: Method,
+        path: &str,
+        body: &B,
+    ) -> Result<T> {
+        let res = self.send(self.request(method, path).json(body)).await?;
+        res.data.ok_or(Error::Api(res.message))
+    }
+
+    // 登录成功后保存JWT；策略要求绑定2FA时 require_2fa_setup 为 true，此时的令牌只能用于绑定2FA
+    pub async fn login(&mut self, username: &str, password: &str, totp_code: Option<&str>) -> Result<LoginResponse> {
+        let req = LoginRequest {
+            username: username.to_owned(),
+            password: password.to_owned(),
+            totp_code: totp_code.map(|x| x.to_owned()),
+            ..Default::default()
+        };
+        let res = self.http.post(self.url("/api/auth/login")).json(&req).send().await?;
+        if !res.status().is_success() {
+            return Err(Error::Status(res.status()));
+        }
+        let res: LoginResponse = res.json().await?;
+        if !res.success {
+            return Err(Error::Api(res.message));
+        }
+        self.token = res.token.clone();
+        Ok(res)
+    }
+
+    pub async fn logout(&mut self) -> Result<()> {
+        self.send::<()>(self.request(Method::POST, "/api/auth/logout")).await?;
+        self.token = None;
+        Ok(())
+    }
+
+    pub async fn me(&self) -> Result<UserInfo> {
+        self.get("/api/auth/me", &()).await
+    }
+
+    pub async fn list_users(&self, page: Option<u64>, limit: Option<u64>) -> Result<Vec<UserInfo>> {
+        self.get("/api/users", &Page { page, limit }).await
+    }
+
+    pub async fn create_user(&self, req: &CreateUserRequest) -> Result<UserInfo> {
+        self.call(Method::POST, "/api/users", req).await
+    }
+
+    // 管理员可查看全部设备，其他用户只能查看自己的设备
+    pub async fn list_devices(&self, query: &DeviceQuery) -> Result<DeviceListResponse> {
+        self.get("/api/devices", query).await
+    }
+
+    // 最近连接的设备，按最后连接时间倒序
+    pub async fn recent_devices(&self, limit: Option<u64>) -> Result<Vec<UserDevice>> {
+        self.get("/api/me/recent", &Page { page: None, limit }).await
+    }
+
+    pub async fn favorite_devices(&self) -> Result<Vec<UserDevice>> {
+        self.get("/api/me/favorites", &()).await
+    }
+
+    pub async fn add_favorite(&self, device_id: &str) -> Result<()> {
+        self.send::<()>(self.request(Method::PUT, &format!("/api/me/favorites/{}", device_id)))
+            .await?;
+        Ok(())
+    }
+
+    pub async fn remove_favorite(&self, device_id: &str) -> Result<()> {
+        self.send::<()>(self.request(Method::DELETE, &format!("/api/me/favorites/{}", device_id)))
+            .await?;
+        Ok(())
+    }
+
+    pub async fn list_sessions(&self) -> Result<Vec<SessionInfo>> {
+        self.get("/api/sessions", &()).await
+    }
+
+    pub async fn update_session_permissions(&self, id: &str, change: &PermissionChange) -> Result<SessionPermissions> {
+        self.call(Method::PUT, &format!("/api/sessions/{}/permissions", id), change)
+            .await
+    }
+
+    pub async fn session_events(&self, id: &str, page: Option<u64>, limit: Option<u64>) -> Result<SessionEventsDetail> {
+        self.get(&format!("/api/sessions/{}/events", id), &Page { page, limit })
+            .await
+    }
+
+    pub async fn audit_logs(&self, query: &AuditLogQuery) -> Result<AuditLogResponse> {
+        self.get("/api/audit-logs", query).await
+    }
+}
+
+#[derive(serde_derive::Serialize)]
+struct Page {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    page: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    limit: Option<u64>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+
+    // 只应答一次的 HTTP 服务，返回收到的请求
+    fn serve_once(body: &'static str) -> (String, std::thread::JoinHandle<String>) {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        let handle = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut buf = [0u8; 4096];
+            let n = stream.read(&mut buf).unwrap();
+            let res = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            );
+            stream.write_all(res.as_bytes()).unwrap();
+            String::from_utf8_lossy(&buf[..n]).into_owned()
+        });
+        (url, handle)
+    }
+
+    #[tokio::test]
+    async fn test_client_requests() {
+        let (url, handle) = serve_once(r#"{"success": true, "data": {"devices": [], "total": 0}, "message": ""}"#);
+        let client = Client::new(&url).with_token("t");
+        let query = DeviceQuery {
+            online: Some(false),
+            tags: Some("kiosk".to_owned()),
+            ..Default::default()
+        };
+        let res = client.list_devices(&query).await.unwrap();
+        assert_eq!(res.total, 0);
+        let req = handle.join().unwrap();
+        assert!(
+            req.starts_with("GET /api/devices?tags=kiosk&online=false HTTP/1.1"),
+            "{}",
+            req
+        );
+        assert!(req.to_lowercase().contains("authorization: bearer t"));
+
+        let (url, handle) = serve_once(r#"{"success": false, "data": null, "message": "权限不足"}"#);
+        match Client::new(&url).list_sessions().await {
+            Err(Error::Api(message)) => assert_eq!(message, "权限不足"),
+            res => panic!("{:?}", res),
+        }
+        handle.join().unwrap();
+    }
+}
@@ -0,0 +1,243 @@
+// 管理接口的请求和响应类型，字段与服务端保持一致(由服务端的 web_api_client_tests 检查)
+use serde_derive::{Deserialize, Serialize};
+use std::{collections::HashMap, time::SystemTime};
+
+// 服务端 JSON 响应的统一包装
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApiResponse<T> {
+    pub success: bool,
+    pub data: Option<T>,
+    pub message: String,
+}
+
+// 请求参数校验失败(400)时 data 中的逐字段错误
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FieldError {
+    pub field: String,
+    pub code: String,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct LoginRequest {
+    pub username: String,
+    pub password: String,
+    pub totp_code: Option<String>,
+    #[serde(default)]
+    pub email_code: Option<String>,
+    #[serde(default)]
+    pub remember_device: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LoginResponse {
+    pub success: bool,
+    pub token: Option<String>,
+    pub user: Option<UserInfo>,
+    pub message: String,
+    // 为 true 时 token 只能用于绑定2FA
+    #[serde(default)]
+    pub require_2fa_setup: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UserInfo {
+    pub id: String,
+    pub username: String,
+    pub email: Option<String>,
+    pub role: String,
+    pub groups: Vec<String>,
+    pub enabled: bool,
+    pub last_login: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CreateUserRequest {
+    pub username: String,
+    pub password: String,
+    pub email: Option<String>,
+    pub role: String, // SuperAdmin、Admin、User
+    pub groups: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeviceInfo {
+    pub id: String,
+    pub name: String,
+    pub os: String,
+    pub version: String,
+    pub ip_address: String,
+    pub ipv6_address: Option<String>,
+    pub mac_address: Option<String>,
+    pub last_online: SystemTime,
+    pub owner_id: String,
+    pub group_ids: Vec<String>,
+    pub enabled: bool,
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub alias: Option<String>,
+}
+
+// 当前用户最近连接或收藏的设备，用于同步地址簿
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UserDevice {
+    pub device_id: String,
+    pub name: String,
+    pub alias: Option<String>,
+    pub os: String,
+    pub online: bool,
+    pub last_online: u64,
+    pub favorited_at: Option<u64>,
+    pub last_connected_at: Option<u64>,
+    pub connection_count: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeviceListResponse {
+    pub devices: Vec<DeviceInfo>,
+    pub total: usize,
+}
+
+// 设备查询条件，view 为已保存视图的ID，其余条件覆盖视图中的同名条件
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DeviceQuery {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub view: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tags: Option<String>, // 逗号分隔，需包含全部标签
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub os: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub group_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub online: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub offline_for: Option<String>, // 如 24h、7d，或秒数
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seen_within: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub q: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub page: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub limit: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditLog {
+    pub id: i64,
+    pub user_id: String,
+    pub device_id: String,
+    pub action: String,
+    pub details: Option<String>,
+    pub ip_address: String,
+    pub user_agent: Option<String>,
+    pub timestamp: SystemTime,
+    pub success: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditLogResponse {
+    pub logs: Vec<AuditLog>,
+    pub total: usize,
+    pub device_aliases: HashMap<String, String>, // 设备ID -> 别名
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AuditLogQuery {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub device_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub action: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub success: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub since: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub until: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ip: Option<String>, // 来源IP或网段，如 10.1.0.0/16
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub q: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub page: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub limit: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RelaySession {
+    pub uuid: String,
+    pub relay_server: String,
+    pub device_ids: Vec<String>,
+    pub started_at: SystemTime,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionPermissions {
+    pub session_id: String,
+    pub device_ids: Vec<String>,
+    pub clipboard: bool,
+    pub file_transfer: bool,
+    pub updated_by: String,
+    pub updated_at: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionInfo {
+    pub session: RelaySession,
+    pub permissions: Option<SessionPermissions>,
+}
+
+// 为 None 的项保持不变
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PermissionChange {
+    #[serde(default)]
+    pub clipboard: Option<bool>,
+    #[serde(default)]
+    pub file_transfer: Option<bool>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConnectionSession {
+    pub id: String,
+    pub controller_id: String,
+    pub controlled_device_id: String,
+    pub start_time: SystemTime,
+    pub end_time: Option<SystemTime>,
+    pub duration_seconds: Option<i64>,
+    pub bytes_transferred: i64,
+    pub connection_type: String, // "direct", "relay"
+    pub quality_score: Option<f32>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SessionEventType {
+    FileCopied,
+    ClipboardUsed,
+    ElevatedToAdmin,
+    InputBlocked,
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionEvent {
+    pub id: i64,
+    pub session_id: String,
+    pub device_id: String,
+    pub event_type: SessionEventType,
+    pub details: Option<String>,
+    pub occurred_at: u64,
+    pub received_at: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionEventsDetail {
+    // 直连会话没有登记时为空
+    pub session: Option<ConnectionSession>,
+    pub events: Vec<SessionEvent>,
+}
@@ -0,0 +1,462 @@
+// AD 计算机同步模块 - 按 /api/settings/ad-sync 配置的间隔从 AD/LDAP 读取计算机对象，按主机名匹配已登记的设备，
+// 并按计算机所在的组织单位(OU)自动创建设备组(ID 以 ad- 开头，名称为 OU 路径，如 Sales/Workstations)。
+// 设备移到其他 OU 后会移出原 AD 设备组，手工维护的设备组不受影响；AD 中没有对应 RustDesk 设备的计算机只计数。
+// 需要以 ldap feature 编译
+use crate::enterprise_database::EnterpriseDatabase;
+use crate::validation::Payload;
+use hbb_common::{
+    bail, log,
+    tokio::sync::{Mutex, RwLock},
+    ResultType,
+};
+use schemars::JsonSchema;
+use serde_derive::{Deserialize, Serialize};
+use sodiumoxide::crypto::hash::sha256;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use validator::Validate;
+
+pub const AD_SYNC_KEY: &str = "ad_sync";
+const AD_SYNC_REPORT_KEY: &str = "ad_sync_report";
+pub const GROUP_PREFIX: &str = "ad-";
+const PASSWORD_MASK: &str = "******";
+
+lazy_static::lazy_static! {
+    static ref CONFIG: RwLock<AdSyncConfig> = Default::default();
+    // 手动同步和定时同步不并发执行
+    static ref RUNNING: Mutex<()> = Default::default();
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, Validate)]
+pub struct AdSyncConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    // ldap://dc.example.com 或 ldaps://dc.example.com:636
+    #[serde(default)]
+    #[validate(length(max = 2048))]
+    pub url: String,
+    // 为空时匿名绑定
+    #[serde(default)]
+    #[validate(length(max = 1024))]
+    pub bind_dn: String,
+    #[serde(default)]
+    #[validate(length(max = 1024))]
+    pub bind_password: String,
+    // 如 DC=corp,DC=example,DC=com
+    #[serde(default)]
+    #[validate(length(max = 1024))]
+    pub base_dn: String,
+    #[serde(default = "default_filter")]
+    #[validate(length(min = 1, max = 4096))]
+    pub filter: String,
+    #[serde(default = "default_interval_minutes")]
+    #[validate(range(min = 5, max = 10080))]
+    pub interval_minutes: u32,
+}
+
+impl Payload for AdSyncConfig {}
+
+fn default_filter() -> String {
+    "(objectClass=computer)".to_owned()
+}
+
+fn default_interval_minutes() -> u32 {
+    60
+}
+
+impl Default for AdSyncConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            url: String::new(),
+            bind_dn: String::new(),
+            bind_password: String::new(),
+            base_dn: String::new(),
+            filter: default_filter(),
+            interval_minutes: default_interval_minutes(),
+        }
+    }
+}
+
+impl AdSyncConfig {
+    pub fn validate(&self) -> ResultType<()> {
+        if self.interval_minutes < 5 || self.interval_minutes > 7 * 24 * 60 {
+            bail!("interval_minutes must be 5-10080");
+        }
+        if !self.enabled {
+            return Ok(());
+        }
+        if !self.url.starts_with("ldap://") && !self.url.starts_with("ldaps://") {
+            bail!("url must start with ldap:// or ldaps://");
+        }
+        if self.base_dn.trim().is_empty() {
+            bail!("base_dn is required");
+        }
+        if !self.filter.starts_with('(') || !self.filter.ends_with(')') {
+            bail!("filter must be an LDAP filter in parentheses");
+        }
+        Ok(())
+    }
+
+    // 接口返回时隐藏绑定密码
+    pub fn masked(&self) -> Self {
+        let mut config = self.clone();
+        if !config.bind_password.is_empty() {
+            config.bind_password = PASSWORD_MASK.to_owned();
+        }
+        config
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct AdSyncReport {
+    pub started_at: u64,
+    pub finished_at: u64,
+    // 读取到的计算机对象数
+    pub computers: usize,
+    // 匹配到已登记设备的计算机数
+    pub matched: usize,
+    pub groups_created: usize,
+    // 设备组有变化的设备数
+    pub devices_updated: usize,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AdComputer {
+    pub dn: String,
+    pub name: String,
+    pub dns_host_name: Option<String>,
+}
+
+// 按未转义的逗号拆分 DN，返回 (属性, 值)
+fn split_dn(dn: &str) -> Vec<(String, String)> {
+    let mut rdns = vec![];
+    let mut current = String::new();
+    let mut escaped = false;
+    for c in dn.chars() {
+        if escaped {
+            current.push(c);
+            escaped = false;
+        } else if c == '\\' {
+            escaped = true;
+        } else if c == ',' {
+            rdns.push(std::mem::take(&mut current));
+        } else {
+            current.push(c);
+        }
+    }
+    rdns.push(current);
+    rdns.into_iter()
+        .filter_map(|rdn| {
+            let (attr, value) = rdn.split_once('=')?;
+            Some((attr.trim().to_ascii_uppercase(), value.trim().to_owned()))
+        })
+        .collect()
+}
+
+// 计算机所在 OU 对应的设备组 (ID, 名称, OU 的 DN)，不在任何 OU 中(如默认的 CN=Computers)时为空
+fn ou_group(dn: &str) -> Option<(String, String, String)> {
+    let rdns = split_dn(dn);
+    let first = rdns.iter().position(|(attr, _)| attr == "OU")?;
+    let ous: Vec<&str> = rdns[first..]
+        .iter()
+        .filter(|(attr, _)| attr == "OU")
+        .map(|(_, value)| value.as_str())
+        .collect();
+    let name = ous.iter().rev().cloned().collect::<Vec<_>>().join("/");
+    let ou_dn = rdns[first..]
+        .iter()
+        .map(|(attr, value)| format!("{}={}", attr, value))
+        .collect::<Vec<_>>()
+        .join(",");
+    let digest = sha256::hash(ou_dn.to_lowercase().as_bytes());
+    let id = format!("{}{}", GROUP_PREFIX, hex(&digest.0[..8]));
+    Some((id, name, ou_dn))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+// 主机名的匹配键: 完整名称和第一段，不区分大小写
+fn host_keys(name: &str) -> Vec<String> {
+    let name = name.trim().to_lowercase();
+    if name.is_empty() {
+        return vec![];
+    }
+    let short = name.split('.').next().unwrap_or_default().to_owned();
+    if short == name {
+        vec![name]
+    } else {
+        vec![name, short]
+    }
+}
+
+// 按主机名匹配设备，返回 设备ID -> 计算机；优先用完整主机名匹配，对应多台计算机的名称不参与匹配
+fn match_devices<'a>(
+    computers: &'a [AdComputer],
+    devices: &[(String, String, Option<String>)],
+) -> BTreeMap<String, &'a AdComputer> {
+    let mut by_key: HashMap<String, Vec<&AdComputer>> = HashMap::new();
+    for computer in computers {
+        let mut keys = host_keys(&computer.name);
+        keys.extend(computer.dns_host_name.as_deref().map(host_keys).unwrap_or_default());
+        keys.sort();
+        keys.dedup();
+        for key in keys {
+            by_key.entry(key).or_default().push(computer);
+        }
+    }
+    let mut matched = BTreeMap::new();
+    for (id, name, hostname) in devices {
+        let mut keys = hostname.as_deref().map(host_keys).unwrap_or_default();
+        keys.extend(host_keys(name));
+        if let Some(computer) = keys
+            .iter()
+            .filter_map(|key| by_key.get(key))
+            .find(|found| found.len() == 1)
+            .map(|found| found[0])
+        {
+            matched.insert(id.clone(), computer);
+        }
+    }
+    matched
+}
+
+#[cfg(feature = "ldap")]
+async fn fetch_computers(config: &AdSyncConfig) -> ResultType<Vec<AdComputer>> {
+    use ldap3::{
+        adapters::{Adapter, EntriesOnly, PagedResults},
+        LdapConnAsync, LdapConnSettings, Scope, SearchEntry,
+    };
+    const TIMEOUT: std::time::Duration = std::time::Duration::from_secs(30);
+    // AD 默认单次最多返回 1000 条，需要分页读取
+    const PAGE_SIZE: i32 = 500;
+
+    let settings = LdapConnSettings::new().set_conn_timeout(TIMEOUT);
+    let (conn, mut ldap) = LdapConnAsync::with_settings(settings, &config.url).await?;
+    hbb_common::tokio::spawn(async move {
+        if let Err(e) = conn.drive().await {
+            log::warn!("LDAP connection error: {}", e);
+        }
+    });
+    ldap.with_timeout(TIMEOUT)
+        .simple_bind(&config.bind_dn, &config.bind_password)
+        .await?
+        .success()?;
+    let adapters: Vec<Box<dyn Adapter<_, _>>> =
+        vec![Box::new(EntriesOnly::new()), Box::new(PagedResults::new(PAGE_SIZE))];
+    let mut search = ldap
+        .streaming_search_with(
+            adapters,
+            &config.base_dn,
+            Scope::Subtree,
+            &config.filter,
+            vec!["cn", "dNSHostName"],
+        )
+        .await?;
+    let mut computers = vec![];
+    while let Some(entry) = search.next().await? {
+        let entry = SearchEntry::construct(entry);
+        let attr = |name: &str| entry.attrs.get(name).and_then(|v| v.first()).cloned();
+        let computer = AdComputer {
+            name: attr("cn").unwrap_or_default(),
+            dns_host_name: attr("dNSHostName"),
+            dn: entry.dn.clone(),
+        };
+        computers.push(computer);
+    }
+    search.finish().await.success()?;
+    let _ = ldap.unbind().await;
+    Ok(computers)
+}
+
+#[cfg(not(feature = "ldap"))]
+async fn fetch_computers(_config: &AdSyncConfig) -> ResultType<Vec<AdComputer>> {
+    bail!("ad sync requires building with the ldap feature")
+}
+
+async fn apply(db: &EnterpriseDatabase, computers: &[AdComputer], report: &mut AdSyncReport) -> ResultType<()> {
+    let devices = db.list_device_hostnames().await?;
+    let matched = match_devices(computers, &devices);
+    report.computers = computers.len();
+    report.matched = matched.len();
+    let mut groups = HashSet::new();
+    for (device_id, computer) in matched {
+        let group = ou_group(&computer.dn);
+        if let Some((id, name, ou_dn)) = &group {
+            if groups.insert(id.clone()) {
+                if db.upsert_ad_device_group(id, name, ou_dn).await? {
+                    log::info!("Created device group {} for {}", name, ou_dn);
+                    report.groups_created += 1;
+                }
+            }
+        }
+        let group_id = group.as_ref().map(|(id, _, _)| id.as_str());
+        if db.set_device_ad_group(&device_id, GROUP_PREFIX, group_id).await? {
+            report.devices_updated += 1;
+        }
+    }
+    Ok(())
+}
+
+// 执行一次同步，结果保存为最近一次同步报告
+pub async fn sync(db: &EnterpriseDatabase) -> ResultType<AdSyncReport> {
+    let _running = match RUNNING.try_lock() {
+        Ok(guard) => guard,
+        Err(_) => bail!("ad sync is already running"),
+    };
+    let config = get().await;
+    if config.url.is_empty() {
+        bail!("ad sync is not configured");
+    }
+    let mut report = AdSyncReport {
+        started_at: crate::common::now(),
+        finished_at: 0,
+        computers: 0,
+        matched: 0,
+        groups_created: 0,
+        devices_updated: 0,
+        error: None,
+    };
+    let res = match fetch_computers(&config).await {
+        Ok(computers) => apply(db, &computers, &mut report).await,
+        Err(e) => Err(e),
+    };
+    report.finished_at = crate::common::now();
+    if let Err(e) = &res {
+        report.error = Some(e.to_string());
+    }
+    db.set_setting(AD_SYNC_REPORT_KEY, &serde_json::to_string(&report)?, None)
+        .await?;
+    res?;
+    log::info!(
+        "AD sync finished: {} computers, {} matched, {} groups created, {} devices updated",
+        report.computers,
+        report.matched,
+        report.groups_created,
+        report.devices_updated
+    );
+    Ok(report)
+}
+
+pub async fn last_report(db: &EnterpriseDatabase) -> ResultType<Option<AdSyncReport>> {
+    Ok(match db.get_setting(AD_SYNC_REPORT_KEY).await? {
+        Some(v) => serde_json::from_str(&v).ok(),
+        None => None,
+    })
+}
+
+// 定时同步，由后台任务每分钟调用
+pub async fn run_scheduled(db: &EnterpriseDatabase) {
+    let config = get().await;
+    if !config.enabled {
+        return;
+    }
+    let last = match last_report(db).await {
+        Ok(report) => report.map(|x| x.finished_at).unwrap_or_default(),
+        Err(e) => {
+            log::error!("Failed to read ad sync state: {}", e);
+            return;
+        }
+    };
+    if crate::common::now() < last + config.interval_minutes as u64 * 60 {
+        return;
+    }
+    if let Err(e) = sync(db).await {
+        log::error!("Scheduled AD sync failed: {}", e);
+    }
+}
+
+pub async fn reload(db: &EnterpriseDatabase) -> ResultType<()> {
+    let config: AdSyncConfig = match db.get_setting(AD_SYNC_KEY).await? {
+        Some(v) => serde_json::from_str(&v)?,
+        None => AdSyncConfig::default(),
+    };
+    config.validate()?;
+    *CONFIG.write().await = config;
+    Ok(())
+}
+
+pub async fn get() -> AdSyncConfig {
+    CONFIG.read().await.clone()
+}
+
+pub async fn update(db: &EnterpriseDatabase, mut config: AdSyncConfig, updated_by: &str) -> ResultType<()> {
+    // 前端回传掩码时保留原密码
+    if config.bind_password == PASSWORD_MASK {
+        config.bind_password = CONFIG.read().await.bind_password.clone();
+    }
+    config.validate()?;
+    db.set_setting(AD_SYNC_KEY, &serde_json::to_string(&config)?, Some(updated_by))
+        .await?;
+    *CONFIG.write().await = config;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn computer(dn: &str, dns_host_name: Option<&str>) -> AdComputer {
+        AdComputer {
+            dn: dn.to_owned(),
+            name: split_dn(dn)[0].1.clone(),
+            dns_host_name: dns_host_name.map(|x| x.to_owned()),
+        }
+    }
+
+    #[test]
+    fn test_ou_group() {
+        let (id, name, ou_dn) = ou_group("CN=PC01,OU=Workstations,OU=Sales,DC=corp,DC=example").unwrap();
+        assert_eq!(name, "Sales/Workstations");
+        assert_eq!(ou_dn, "OU=Workstations,OU=Sales,DC=corp,DC=example");
+        assert!(id.starts_with(GROUP_PREFIX));
+        // 同一 OU 的不同计算机落在同一设备组，DN 大小写不影响
+        assert_eq!(
+            ou_group("cn=PC02,ou=workstations,ou=Sales,dc=corp,dc=example")
+                .unwrap()
+                .0,
+            id
+        );
+        assert_eq!(ou_group(r"CN=PC03,OU=R\,D,DC=corp,DC=example").unwrap().1, "R,D");
+        assert!(ou_group("CN=PC04,CN=Computers,DC=corp,DC=example").is_none());
+    }
+
+    #[test]
+    fn test_match_devices() {
+        let computers = vec![
+            computer("CN=PC01,OU=Sales,DC=corp,DC=example", Some("pc01.corp.example")),
+            computer("CN=PC02,OU=Sales,DC=corp,DC=example", None),
+            computer("CN=DUP,OU=A,DC=corp,DC=example", None),
+            computer("CN=DUP,OU=B,DC=corp,DC=example", None),
+        ];
+        let devices = vec![
+            ("1".to_owned(), "1".to_owned(), Some("PC01.corp.example".to_owned())),
+            ("2".to_owned(), "pc02".to_owned(), None),
+            ("3".to_owned(), "dup".to_owned(), None),
+            ("4".to_owned(), "laptop".to_owned(), Some("laptop".to_owned())),
+        ];
+        let matched = match_devices(&computers, &devices);
+        assert_eq!(matched.keys().cloned().collect::<Vec<_>>(), vec!["1", "2"]);
+        assert_eq!(matched["1"].name, "PC01");
+    }
+
+    #[test]
+    fn test_validate() {
+        let mut config = AdSyncConfig {
+            enabled: true,
+            url: "ldaps://dc.corp.example".to_owned(),
+            bind_password: "secret".to_owned(),
+            base_dn: "DC=corp,DC=example".to_owned(),
+            ..Default::default()
+        };
+        assert!(config.validate().is_ok());
+        assert_eq!(config.masked().bind_password, PASSWORD_MASK);
+        config.filter = "objectClass=computer".to_owned();
+        assert!(config.validate().is_err());
+        config.filter = default_filter();
+        config.interval_minutes = 1;
+        assert!(config.validate().is_err());
+    }
+}
@@ -1,15 +1,16 @@
 // 高级安全模块 - 双因素认证、端到端加密、安全审计
 use crate::auth::{User, Claims};
 use crate::enterprise_database::{EnterpriseDatabase, AuditLog};
-use hbb_common::{log, ResultType};
+use hbb_common::{anyhow::anyhow, bail, log, ResultType};
+use schemars::JsonSchema;
 use serde_derive::{Deserialize, Serialize};
 use std::{
     collections::HashMap,
     sync::Arc,
-    time::{SystemTime, UNIX_EPOCH, Duration},
+    time::{SystemTime, UNIX_EPOCH, Duration, Instant},
 };
-use tokio::sync::{RwLock, Mutex};
-use sodiumoxide::crypto::{box_, secretbox, sign, hash};
+use tokio::sync::RwLock;
+use sodiumoxide::crypto::{box_, sign, hash, hash::sha256};
 use base64::{encode, decode};
 
 // 双因素认证
@@ -34,13 +35,57 @@ pub struct TotpConfig {
 }
 
 // 端到端加密
+// 每个会话生成独立的 X25519 密钥对用于密钥协商、Ed25519 密钥对用于签名：
+// 握手报文用 Ed25519 签名绑定会话ID与 X25519 公钥，消息用协商出的密钥做 XSalsa20-Poly1305 认证加密，
+// 签名覆盖会话ID、密钥代数、消息序号、nonce、时间戳与密文；接收方按序号滑动窗口拒绝重放的消息。
+// 握手时必须给出对端预期的签名公钥(服务器签发的设备公钥)，防止中间人替换握手报文。
+// 长连接按时间/流量阈值对共享密钥做单向哈希轮换(key_epoch 递增)，旧密钥只保留一代用于解密乱序消息；
+// 接收方一次跟进多代时保留的是跟进前所用的密钥
 #[derive(Debug, Clone)]
 pub struct E2EEncryption {
     pub session_id: String,
     pub local_keypair: (box_::PublicKey, box_::SecretKey),
+    pub signing_keypair: (sign::PublicKey, sign::SecretKey),
     pub remote_public_key: Option<box_::PublicKey>,
-    pub shared_secret: Option<secretbox::Key>,
-    pub nonce_counter: Arc<Mutex<u64>>,
+    pub remote_signing_key: Option<sign::PublicKey>,
+    pub shared_secret: Option<box_::PrecomputedKey>,
+    pub nonce_counter: u64, // 已发送的消息序号
+    pub key_epoch: u64,
+    pub rekey_policy: RekeyPolicy,
+    previous_secret: Option<(u64, box_::PrecomputedKey)>, // (代数, 密钥)
+    rekeyed_at: Instant,
+    bytes_since_rekey: u64,
+    remote_counter: u64, // 已接收的最大消息序号
+    replay_window: u64,  // 最大序号及之前63个序号的接收位图
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct RekeyPolicy {
+    pub interval_secs: u64,
+    pub max_bytes: u64,
+}
+
+impl Default for RekeyPolicy {
+    fn default() -> Self {
+        Self {
+            interval_secs: 10 * 60,
+            max_bytes: 1 << 30,
+        }
+    }
+}
+
+// 接收方一次最多向前推进的密钥代数
+const MAX_EPOCH_SKIP: u64 = 16;
+// 重放检测窗口，早于最大序号64条以上的消息直接拒绝
+const REPLAY_WINDOW: u64 = 64;
+
+// 握手报文，双方交换后各自调用 complete_e2e_handshake
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HandshakeOffer {
+    pub session_id: String,
+    pub public_key: String,  // X25519 公钥
+    pub signing_key: String, // Ed25519 公钥
+    pub signature: String,   // Ed25519(session_id || public_key)
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -50,10 +95,249 @@ pub struct EncryptedMessage {
     pub ciphertext: String,
     pub signature: String,
     pub timestamp: u64,
+    #[serde(default)]
+    pub key_epoch: u64,
+    #[serde(default)]
+    pub counter: u64,
+}
+
+impl E2EEncryption {
+    pub fn new(session_id: &str) -> Self {
+        Self::with_signing_keypair(session_id, sign::gen_keypair())
+    }
+
+    // 使用设备的长期签名密钥，对端以服务器签发的设备公钥校验握手报文
+    pub fn with_signing_keypair(session_id: &str, signing_keypair: (sign::PublicKey, sign::SecretKey)) -> Self {
+        Self {
+            session_id: session_id.to_string(),
+            local_keypair: box_::gen_keypair(),
+            signing_keypair,
+            remote_public_key: None,
+            remote_signing_key: None,
+            shared_secret: None,
+            nonce_counter: 0,
+            key_epoch: 0,
+            rekey_policy: RekeyPolicy::default(),
+            previous_secret: None,
+            rekeyed_at: Instant::now(),
+            bytes_since_rekey: 0,
+            remote_counter: 0,
+            replay_window: 0,
+        }
+    }
+
+    // 下一代密钥 = SHA256(当前密钥 || 代数)，无法由新密钥反推旧密钥
+    fn next_secret(key: &box_::PrecomputedKey, epoch: u64) -> box_::PrecomputedKey {
+        let mut input = key.0.to_vec();
+        input.extend_from_slice(&epoch.to_be_bytes());
+        box_::PrecomputedKey(sha256::hash(&input).0)
+    }
+
+    fn ratchet(&mut self) {
+        if let Some(key) = self.shared_secret.take() {
+            self.shared_secret = Some(Self::next_secret(&key, self.key_epoch + 1));
+            self.previous_secret = Some((self.key_epoch, key));
+            self.key_epoch += 1;
+            self.rekeyed_at = Instant::now();
+            self.bytes_since_rekey = 0;
+        }
+    }
+
+    pub fn needs_rekey(&self) -> bool {
+        self.rekeyed_at.elapsed().as_secs() >= self.rekey_policy.interval_secs
+            || self.bytes_since_rekey >= self.rekey_policy.max_bytes
+    }
+
+    // 立即轮换共享密钥，对端收到新代数的消息后自动跟进
+    pub fn rekey(&mut self) -> ResultType<u64> {
+        if self.shared_secret.is_none() {
+            bail!("E2E encryption not established");
+        }
+        self.ratchet();
+        Ok(self.key_epoch)
+    }
+
+    fn offer_payload(session_id: &str, public_key: &box_::PublicKey) -> Vec<u8> {
+        let mut payload = session_id.as_bytes().to_vec();
+        payload.push(0);
+        payload.extend_from_slice(&public_key.0);
+        payload
+    }
+
+    pub fn offer(&self) -> HandshakeOffer {
+        let payload = Self::offer_payload(&self.session_id, &self.local_keypair.0);
+        HandshakeOffer {
+            session_id: self.session_id.clone(),
+            public_key: encode(&self.local_keypair.0 .0),
+            signing_key: encode(&self.signing_keypair.0 .0),
+            signature: encode(&sign::sign_detached(&payload, &self.signing_keypair.1).to_bytes()),
+        }
+    }
+
+    // 校验对端握手报文并协商共享密钥；expected_signing_key 固定对端身份，签名密钥不符时拒绝
+    pub fn accept(&mut self, offer: &HandshakeOffer, expected_signing_key: &sign::PublicKey) -> ResultType<()> {
+        if offer.session_id != self.session_id {
+            bail!("Handshake session mismatch");
+        }
+        let public_key = box_::PublicKey::from_slice(&decode(&offer.public_key)?)
+            .ok_or_else(|| anyhow!("Invalid public key"))?;
+        let signing_key = sign::PublicKey::from_slice(&decode(&offer.signing_key)?)
+            .ok_or_else(|| anyhow!("Invalid signing key"))?;
+        if expected_signing_key != &signing_key {
+            bail!("Unexpected signing key");
+        }
+        let signature = sign::Signature::from_bytes(&decode(&offer.signature)?)
+            .map_err(|_| anyhow!("Invalid signature"))?;
+        if !sign::verify_detached(&signature, &Self::offer_payload(&offer.session_id, &public_key), &signing_key) {
+            bail!("Handshake signature verification failed");
+        }
+        self.shared_secret = Some(box_::precompute(&public_key, &self.local_keypair.1));
+        self.previous_secret = None;
+        self.key_epoch = 0;
+        self.rekeyed_at = Instant::now();
+        self.bytes_since_rekey = 0;
+        self.nonce_counter = 0;
+        self.remote_counter = 0;
+        self.replay_window = 0;
+        self.remote_public_key = Some(public_key);
+        self.remote_signing_key = Some(signing_key);
+        Ok(())
+    }
+
+    fn message_payload(
+        session_id: &str,
+        epoch: u64,
+        counter: u64,
+        nonce: &[u8],
+        timestamp: u64,
+        ciphertext: &[u8],
+    ) -> Vec<u8> {
+        let mut payload = session_id.as_bytes().to_vec();
+        payload.push(0);
+        payload.extend_from_slice(&epoch.to_be_bytes());
+        payload.extend_from_slice(&counter.to_be_bytes());
+        payload.extend_from_slice(nonce);
+        payload.extend_from_slice(&timestamp.to_be_bytes());
+        payload.extend_from_slice(ciphertext);
+        payload
+    }
+
+    pub fn encrypt(&mut self, plaintext: &[u8]) -> ResultType<EncryptedMessage> {
+        if self.shared_secret.is_some() && self.needs_rekey() {
+            self.ratchet();
+        }
+        let shared_secret = self
+            .shared_secret
+            .as_ref()
+            .ok_or_else(|| anyhow!("E2E encryption not established"))?;
+        let nonce = box_::gen_nonce();
+        let ciphertext = box_::seal_precomputed(plaintext, &nonce, shared_secret);
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+        let counter = self.nonce_counter + 1;
+        let payload = Self::message_payload(&self.session_id, self.key_epoch, counter, &nonce.0, timestamp, &ciphertext);
+        let signature = sign::sign_detached(&payload, &self.signing_keypair.1);
+        self.bytes_since_rekey += plaintext.len() as u64;
+        self.nonce_counter = counter;
+
+        Ok(EncryptedMessage {
+            session_id: self.session_id.clone(),
+            nonce: encode(&nonce.0),
+            ciphertext: encode(&ciphertext),
+            signature: encode(&signature.to_bytes()),
+            timestamp,
+            key_epoch: self.key_epoch,
+            counter,
+        })
+    }
+
+    // 序号超出窗口或已接收过时视为重放
+    fn check_replay(&self, counter: u64) -> ResultType<()> {
+        if counter == 0 {
+            bail!("Invalid message counter");
+        }
+        if counter > self.remote_counter {
+            return Ok(());
+        }
+        let offset = self.remote_counter - counter;
+        if offset >= REPLAY_WINDOW || self.replay_window & (1 << offset) != 0 {
+            bail!("Replayed message");
+        }
+        Ok(())
+    }
+
+    fn mark_received(&mut self, counter: u64) {
+        if counter > self.remote_counter {
+            let shift = counter - self.remote_counter;
+            self.replay_window = if shift >= REPLAY_WINDOW { 0 } else { self.replay_window << shift };
+            self.replay_window |= 1;
+            self.remote_counter = counter;
+        } else {
+            self.replay_window |= 1 << (self.remote_counter - counter);
+        }
+    }
+
+    pub fn decrypt(&mut self, message: &EncryptedMessage) -> ResultType<Vec<u8>> {
+        if message.session_id != self.session_id {
+            bail!("Message session mismatch");
+        }
+        if self.shared_secret.is_none() {
+            bail!("E2E encryption not established");
+        }
+        let remote_signing_key = self
+            .remote_signing_key
+            .as_ref()
+            .ok_or_else(|| anyhow!("Remote signing key not available"))?;
+
+        let nonce_bytes = decode(&message.nonce)?;
+        let ciphertext = decode(&message.ciphertext)?;
+        let signature = sign::Signature::from_bytes(&decode(&message.signature)?)
+            .map_err(|_| anyhow!("Invalid signature"))?;
+
+        // 先验签再解密
+        let payload = Self::message_payload(
+            &message.session_id,
+            message.key_epoch,
+            message.counter,
+            &nonce_bytes,
+            message.timestamp,
+            &ciphertext,
+        );
+        if !sign::verify_detached(&signature, &payload, remote_signing_key) {
+            bail!("Signature verification failed");
+        }
+        self.check_replay(message.counter)?;
+
+        // 对端已轮换则跟进；跟进前的密钥只用于解密乱序到达的旧消息
+        if message.key_epoch > self.key_epoch + MAX_EPOCH_SKIP {
+            bail!("Key epoch too far ahead");
+        }
+        if self.key_epoch < message.key_epoch {
+            let previous = self.shared_secret.clone().map(|key| (self.key_epoch, key));
+            while self.key_epoch < message.key_epoch {
+                self.ratchet();
+            }
+            self.previous_secret = previous;
+        }
+        let key = if message.key_epoch == self.key_epoch {
+            self.shared_secret.as_ref()
+        } else {
+            self.previous_secret
+                .as_ref()
+                .filter(|(epoch, _)| *epoch == message.key_epoch)
+                .map(|(_, key)| key)
+        }
+        .ok_or_else(|| anyhow!("Key epoch expired"))?;
+
+        let nonce = box_::Nonce::from_slice(&nonce_bytes).ok_or_else(|| anyhow!("Invalid nonce"))?;
+        let plaintext = box_::open_precomputed(&ciphertext, &nonce, key)
+            .map_err(|_| anyhow!("Decryption failed"))?;
+        self.mark_received(message.counter);
+        Ok(plaintext)
+    }
 }
 
 // 安全审计
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct SecurityEvent {
     pub id: String,
     pub event_type: SecurityEventType,
@@ -68,7 +352,7 @@ pub struct SecurityEvent {
     pub resolution_notes: Option<String>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub enum SecurityEventType {
     LoginAttempt,
     LoginFailure,
@@ -86,7 +370,7 @@ pub enum SecurityEventType {
     SystemCompromise,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub enum SecuritySeverity {
     Low,
     Medium,
@@ -342,108 +626,57 @@ impl AdvancedSecurityManager {
         Ok(encode(&buffer))
     }
 
-    // 端到端加密实现
+    // 端到端加密实现，返回需发送给对端的握手报文(JSON)
     pub async fn initiate_e2e_session(&self, session_id: &str) -> ResultType<String> {
-        // 生成密钥对
-        let (public_key, secret_key) = box_::gen_keypair();
-        
-        let encryption = E2EEncryption {
-            session_id: session_id.to_string(),
-            local_keypair: (public_key, secret_key),
-            remote_public_key: None,
-            shared_secret: None,
-            nonce_counter: Arc::new(Mutex::new(0)),
-        };
-
-        // 保存会话
+        let encryption = E2EEncryption::new(session_id);
+        let offer = serde_json::to_string(&encryption.offer())?;
         self.active_sessions.write().await.insert(session_id.to_string(), encryption);
-
-        // 返回公钥
-        Ok(encode(&public_key.0))
+        Ok(offer)
     }
 
-    pub async fn complete_e2e_handshake(&self, session_id: &str, remote_public_key: &str) -> ResultType<()> {
-        let remote_key_bytes = decode(remote_public_key)?;
-        let remote_public_key = box_::PublicKey::from_slice(&remote_key_bytes)
-            .ok_or("Invalid public key")?;
-
+    // peer_pk 为对端设备注册时由服务器签发的公钥，缺失时拒绝握手
+    pub async fn complete_e2e_handshake(
+        &self,
+        session_id: &str,
+        remote_offer: &str,
+        peer_pk: Option<&[u8]>,
+    ) -> ResultType<()> {
+        let expected = peer_pk
+            .and_then(sign::PublicKey::from_slice)
+            .ok_or_else(|| anyhow!("Peer public key not available"))?;
+        let offer: HandshakeOffer = serde_json::from_str(remote_offer)?;
         let mut sessions = self.active_sessions.write().await;
-        if let Some(session) = sessions.get_mut(session_id) {
-            session.remote_public_key = Some(remote_public_key);
-            
-            // 生成共享密钥
-            let shared_secret = box_::precompute(&remote_public_key, &session.local_keypair.1);
-            session.shared_secret = Some(secretbox::Key::from_slice(&shared_secret.0[..secretbox::KEYBYTES]).unwrap());
-            
-            log::info!("E2E encryption established for session: {}", session_id);
-        }
-
+        let session = sessions
+            .get_mut(session_id)
+            .ok_or_else(|| anyhow!("Session not found"))?;
+        session.accept(&offer, &expected)?;
+        log::info!("E2E encryption established for session: {}", session_id);
         Ok(())
     }
 
     pub async fn encrypt_message(&self, session_id: &str, plaintext: &[u8]) -> ResultType<EncryptedMessage> {
-        let sessions = self.active_sessions.read().await;
-        let session = sessions.get(session_id)
-            .ok_or("Session not found")?;
-
-        let shared_secret = session.shared_secret
-            .as_ref()
-            .ok_or("E2E encryption not established")?;
-
-        // 生成nonce
-        let mut nonce_counter = session.nonce_counter.lock().await;
-        *nonce_counter += 1;
-        let nonce = secretbox::gen_nonce();
-
-        // 加密数据
-        let ciphertext = secretbox::seal(plaintext, &nonce, shared_secret);
-
-        // 签名
-        let signature = sign::sign(&ciphertext, &session.local_keypair.1);
-
-        Ok(EncryptedMessage {
-            session_id: session_id.to_string(),
-            nonce: encode(&nonce.0),
-            ciphertext: encode(&ciphertext),
-            signature: encode(&signature),
-            timestamp: SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs(),
-        })
+        let mut sessions = self.active_sessions.write().await;
+        let session = sessions
+            .get_mut(session_id)
+            .ok_or_else(|| anyhow!("Session not found"))?;
+        session.encrypt(plaintext)
     }
 
     pub async fn decrypt_message(&self, message: &EncryptedMessage) -> ResultType<Vec<u8>> {
-        let sessions = self.active_sessions.read().await;
-        let session = sessions.get(&message.session_id)
-            .ok_or("Session not found")?;
-
-        let shared_secret = session.shared_secret
-            .as_ref()
-            .ok_or("E2E encryption not established")?;
-
-        let remote_public_key = session.remote_public_key
-            .as_ref()
-            .ok_or("Remote public key not available")?;
-
-        // 解码数据
-        let nonce_bytes = decode(&message.nonce)?;
-        let ciphertext = decode(&message.ciphertext)?;
-        let signature = decode(&message.signature)?;
-
-        // 验证签名
-        let verified_data = sign::verify(&signature, remote_public_key)
-            .map_err(|_| "Signature verification failed")?;
-
-        if verified_data != ciphertext {
-            return Err("Data integrity check failed".into());
-        }
-
-        // 解密
-        let nonce = secretbox::Nonce::from_slice(&nonce_bytes)
-            .ok_or("Invalid nonce")?;
-
-        let plaintext = secretbox::open(&ciphertext, &nonce, shared_secret)
-            .map_err(|_| "Decryption failed")?;
+        let mut sessions = self.active_sessions.write().await;
+        let session = sessions
+            .get_mut(&message.session_id)
+            .ok_or_else(|| anyhow!("Session not found"))?;
+        session.decrypt(message)
+    }
 
-        Ok(plaintext)
+    // 手动触发会话密钥轮换，返回新的密钥代数
+    pub async fn rekey_session(&self, session_id: &str) -> ResultType<u64> {
+        let mut sessions = self.active_sessions.write().await;
+        let session = sessions
+            .get_mut(session_id)
+            .ok_or_else(|| anyhow!("Session not found"))?;
+        session.rekey()
     }
 
     // 安全审计实现
@@ -607,8 +840,9 @@ impl AdvancedSecurityManager {
     }
 
     async fn send_security_alert(&self, event: &SecurityEvent) {
-        // TODO: 实现邮件/短信告警
         log::warn!("Security alert: {:?}", event);
+        crate::notifications::security_event(&self.db, event).await;
+        crate::itsm::security_event(event).await;
     }
 
     async fn load_security_policies(&self) -> ResultType<()> {
@@ -699,11 +933,6 @@ impl EnterpriseDatabase {
         // TODO: 实现2FA配置删除
         Ok(())
     }
-
-    pub async fn save_security_event(&self, event: &SecurityEvent) -> ResultType<()> {
-        // TODO: 实现安全事件保存
-        Ok(())
-    }
 }
 
 #[cfg(test)]
@@ -729,9 +958,266 @@ mod tests {
         );
 
         let session_id = "test_session";
-        let public_key = security_manager.initiate_e2e_session(session_id).await.unwrap();
-        assert!(!public_key.is_empty());
+        let offer = security_manager.initiate_e2e_session(session_id).await.unwrap();
+        assert!(!offer.is_empty());
+
+        let offer: HandshakeOffer = serde_json::from_str(&offer).unwrap();
+        let local_key = sign::PublicKey::from_slice(&decode(&offer.signing_key).unwrap()).unwrap();
+        let (peer_pk, peer_sk) = sign::gen_keypair();
+        let mut remote = E2EEncryption::with_signing_keypair(session_id, (peer_pk, peer_sk));
+        remote.accept(&offer, &local_key).unwrap();
+        let remote_offer = serde_json::to_string(&remote.offer()).unwrap();
+        // 缺少对端公钥或公钥不符时拒绝握手
+        assert!(security_manager
+            .complete_e2e_handshake(session_id, &remote_offer, None)
+            .await
+            .is_err());
+        assert!(security_manager
+            .complete_e2e_handshake(session_id, &remote_offer, Some(&sign::gen_keypair().0 .0))
+            .await
+            .is_err());
+        security_manager
+            .complete_e2e_handshake(session_id, &remote_offer, Some(&peer_pk.0))
+            .await
+            .unwrap();
+
+        let message = security_manager.encrypt_message(session_id, b"hello").await.unwrap();
+        assert_eq!(remote.decrypt(&message).unwrap(), b"hello");
+        let reply = remote.encrypt(b"world").unwrap();
+        assert_eq!(security_manager.decrypt_message(&reply).await.unwrap(), b"world");
+    }
+
+    #[test]
+    fn test_e2e_rejects_tampering() {
+        let mut a = E2EEncryption::new("s1");
+        let mut b = E2EEncryption::new("s1");
+        let offer_a = a.offer();
+        let offer_b = b.offer();
+
+        let key_a = a.signing_keypair.0;
+        let key_b = b.signing_keypair.0;
+
+        // 替换公钥后签名失效
+        let mut forged = offer_b.clone();
+        forged.public_key = encode(&box_::gen_keypair().0 .0);
+        assert!(a.accept(&forged, &key_b).is_err());
+        // 中间人用自己的密钥重新签名，签名密钥与预期不符
+        let mitm = E2EEncryption::new("s1");
+        assert!(a.accept(&mitm.offer(), &key_b).is_err());
+        let mut resigned = mitm.offer();
+        resigned.signing_key = offer_b.signing_key.clone();
+        assert!(a.accept(&resigned, &key_b).is_err());
+        // 身份固定时拒绝其他签名密钥
+        assert!(a.accept(&offer_b, &key_a).is_err());
+        // 不同会话的握手报文不能复用
+        assert!(E2EEncryption::new("s2").accept(&offer_b, &key_b).is_err());
+        assert!(a.shared_secret.is_none());
+
+        a.accept(&offer_b, &key_b).unwrap();
+        b.accept(&offer_a, &key_a).unwrap();
+
+        let mut message = a.encrypt(b"payload").unwrap();
+        assert_eq!(b.decrypt(&message).unwrap(), b"payload");
+        message.timestamp += 1;
+        assert!(b.decrypt(&message).is_err());
+        message.timestamp -= 1;
+        // 篡改序号绕过重放检测时签名失效
+        message.counter += 10;
+        assert!(b.decrypt(&message).is_err());
+        // 自己发出的消息不能用自己的会话验签
+        let own = a.encrypt(b"x").unwrap();
+        assert!(a.decrypt(&own).is_err());
+    }
+
+    #[test]
+    fn test_e2e_rekey() {
+        let mut a = E2EEncryption::new("s1");
+        let mut b = E2EEncryption::new("s1");
+        let offer_a = a.offer();
+        a.accept(&b.offer(), &b.signing_keypair.0).unwrap();
+        b.accept(&offer_a, &a.signing_keypair.0).unwrap();
+        a.rekey_policy.max_bytes = 8;
+
+        let first = a.encrypt(b"12345678").unwrap();
+        let second = a.encrypt(b"next").unwrap();
+        assert_eq!((first.key_epoch, second.key_epoch), (0, 1));
+        // 乱序：先收到新一代消息，旧一代消息仍可解密
+        assert_eq!(b.decrypt(&second).unwrap(), b"next");
+        assert_eq!(b.key_epoch, 1);
+        assert_eq!(b.decrypt(&first).unwrap(), b"12345678");
+
+        assert_eq!(a.rekey().unwrap(), 2);
+        assert_eq!(a.rekey().unwrap(), 3);
+        let third = a.encrypt(b"third").unwrap();
+        assert_eq!(b.decrypt(&third).unwrap(), b"third");
+        // 超过一代的旧消息无法再解密
+        assert!(b.decrypt(&first).is_err());
+    }
+
+    #[test]
+    fn test_e2e_rejects_replay() {
+        let mut a = E2EEncryption::new("s1");
+        let mut b = E2EEncryption::new("s1");
+        let offer_a = a.offer();
+        a.accept(&b.offer(), &b.signing_keypair.0).unwrap();
+        b.accept(&offer_a, &a.signing_keypair.0).unwrap();
+
+        let first = a.encrypt(b"first").unwrap();
+        let second = a.encrypt(b"second").unwrap();
+        assert_eq!((first.counter, second.counter), (1, 2));
+        assert_eq!(a.nonce_counter, 2);
+        assert_eq!(b.decrypt(&second).unwrap(), b"second");
+        // 窗口内乱序到达的消息只接收一次
+        assert_eq!(b.decrypt(&first).unwrap(), b"first");
+        assert!(b.decrypt(&first).is_err());
+        assert!(b.decrypt(&second).is_err());
+
+        // 早于窗口的消息直接拒绝
+        let stale = a.encrypt(b"stale").unwrap();
+        for _ in 0..REPLAY_WINDOW {
+            let message = a.encrypt(b"x").unwrap();
+            b.decrypt(&message).unwrap();
+        }
+        assert!(b.decrypt(&stale).is_err());
+
+        // 重新握手后序号重新开始
+        let offer_a = a.offer();
+        a.accept(&b.offer(), &b.signing_keypair.0).unwrap();
+        b.accept(&offer_a, &a.signing_keypair.0).unwrap();
+        let message = a.encrypt(b"again").unwrap();
+        assert_eq!(message.counter, 1);
+        assert_eq!(b.decrypt(&message).unwrap(), b"again");
+    }
+
+    fn from_hex(s: &str) -> Vec<u8> {
+        (0..s.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    // RFC 8032 Ed25519 测试向量 1 与 RFC 7748 X25519 测试向量
+    #[test]
+    fn test_e2e_primitives_vectors() {
+        let seed = sign::Seed::from_slice(&from_hex(
+            "9d61b19deffd5a60ba844af492ec2cc44449c5697b326919703bac031cae7f60",
+        ))
+        .unwrap();
+        let (pk, sk) = sign::keypair_from_seed(&seed);
+        assert_eq!(
+            pk.0.to_vec(),
+            from_hex("d75a980182b10ab7d54bfed3c964073a0ee172f3daa62325af021a68f707511a")
+        );
+        assert_eq!(
+            sign::sign_detached(b"", &sk).to_bytes().to_vec(),
+            from_hex(concat!(
+                "e5564300c360ac729086e2cc806e828a84877f1eb8e5d974d873e06522490155",
+                "5fb8821590a33bacc61e39701cf9b46bd25bf5f0595bbe24655141438e7a100b"
+            ))
+        );
+
+        use sodiumoxide::crypto::scalarmult::curve25519::{scalarmult, scalarmult_base, Scalar};
+        let alice = Scalar::from_slice(&from_hex(
+            "77076d0a7318a57d3c16c17251b26645df4c2f87ebc0992ab177fba51db92c2a",
+        ))
+        .unwrap();
+        let bob = Scalar::from_slice(&from_hex(
+            "5dab087e624a8a4b79e17f8b83800ee66f3bb1292618b6fd1c2f8b27ff88e0eb",
+        ))
+        .unwrap();
+        let alice_pk = scalarmult_base(&alice);
+        assert_eq!(
+            alice_pk.0.to_vec(),
+            from_hex("8520f0098930a754748b7ddcb43ef75a0dbf3a0d26381af4eba4a98eaa9b4e6a")
+        );
+        let bob_pk = scalarmult_base(&bob);
+        assert_eq!(
+            bob_pk.0.to_vec(),
+            from_hex("de9edb7d7b7dc1b4d35b61c2ece435373f8343c85b78674dadfc7e146f882b4f")
+        );
+        assert_eq!(
+            scalarmult(&alice, &bob_pk).unwrap().0.to_vec(),
+            from_hex("4a5d9d5ba4ce2de1728e3bf480350f25e07e21c947d19e3376f09b3c1e161742")
+        );
+    }
+    // 固定密钥的轮换向量：NaCl 测试中 alice/bob 的 crypto_box_beforenm 结果为第0代密钥，
+    // 此后每代为 SHA256(上一代 || 代数)；密文为 XSalsa20-Poly1305(nonce = 00..17)
+    const EPOCH_KEYS: [&str; 4] = [
+        "1b27556473e985d462cd51197a9a46c76009549eac6474f206c4ee0844f68389",
+        "345219bace6b6b049cd572e197782667eaa4982c52ef284a63d70fe6926f347d",
+        "31d2094e45dbcfbfd0d76646d45aad1547e559a4c655d6ec711f09fe0c6c2034",
+        "44a630b2fc975f99ad2593e7ab6bd9d14c426cfcc8ed1cec7dc7918f01012637",
+    ];
+    const EPOCH_0_CIPHERTEXT: &str = "70e7135f19ad90eaba16a368b026cee8603e3fc6735665";
+    const EPOCH_3_CIPHERTEXT: &str = "030ccfb802c55f2ede4fb41fcf5497a0f900931d0fb9a0";
+
+    fn fixed_nonce() -> Vec<u8> {
+        (0..box_::NONCEBYTES as u8).collect()
+    }
+
+    fn fixed_endpoint(box_secret: &str, sign_seed: &str) -> E2EEncryption {
+        let seed = sign::Seed::from_slice(&from_hex(sign_seed)).unwrap();
+        let mut res = E2EEncryption::with_signing_keypair("s1", sign::keypair_from_seed(&seed));
+        let sk = box_::SecretKey::from_slice(&from_hex(box_secret)).unwrap();
+        res.local_keypair = (sk.public_key(), sk);
+        res
+    }
+
+    // 用发送方的签名密钥封装固定 nonce 的密文
+    fn fixed_message(from: &E2EEncryption, epoch: u64, counter: u64, ciphertext: &str) -> EncryptedMessage {
+        let nonce = fixed_nonce();
+        let ciphertext = from_hex(ciphertext);
+        let timestamp = 1_700_000_000;
+        let payload = E2EEncryption::message_payload("s1", epoch, counter, &nonce, timestamp, &ciphertext);
+        EncryptedMessage {
+            session_id: "s1".to_owned(),
+            nonce: encode(&nonce),
+            ciphertext: encode(&ciphertext),
+            signature: encode(&sign::sign_detached(&payload, &from.signing_keypair.1).to_bytes()),
+            timestamp,
+            key_epoch: epoch,
+            counter,
+        }
+    }
+
+    #[test]
+    fn test_e2e_rekey_vectors() {
+        let mut a = fixed_endpoint(
+            "77076d0a7318a57d3c16c17251b26645df4c2f87ebc0992ab177fba51db92c2a",
+            "9d61b19deffd5a60ba844af492ec2cc44449c5697b326919703bac031cae7f60",
+        );
+        let mut b = fixed_endpoint(
+            "5dab087e624a8a4b79e17f8b83800ee66f3bb1292618b6fd1c2f8b27ff88e0eb",
+            "4ccd089b28ff96da9db6c346ec114e0f5b8a319f35aba624da8cf6ed4fb8a6fb",
+        );
+        let offer_a = a.offer();
+        a.accept(&b.offer(), &b.signing_keypair.0).unwrap();
+        b.accept(&offer_a, &a.signing_keypair.0).unwrap();
+
+        let nonce = box_::Nonce::from_slice(&fixed_nonce()).unwrap();
+        for (epoch, expected) in EPOCH_KEYS.iter().enumerate() {
+            if epoch > 0 {
+                assert_eq!(a.rekey().unwrap(), epoch as u64);
+            }
+            let key = a.shared_secret.as_ref().unwrap();
+            assert_eq!(key.0.to_vec(), from_hex(expected));
+            let sealed = box_::seal_precomputed(format!("epoch {}", epoch).as_bytes(), &nonce, key);
+            match epoch {
+                0 => assert_eq!(sealed, from_hex(EPOCH_0_CIPHERTEXT)),
+                3 => assert_eq!(sealed, from_hex(EPOCH_3_CIPHERTEXT)),
+                _ => {}
+            }
+        }
 
-        // TODO: 添加完整的E2E加密测试
+        // 接收方从第0代一次跟进到第3代，仍保留第0代密钥解密迟到的消息
+        let late = fixed_message(&a, 0, 1, EPOCH_0_CIPHERTEXT);
+        let current = fixed_message(&a, 3, 2, EPOCH_3_CIPHERTEXT);
+        assert_eq!(b.decrypt(&current).unwrap(), b"epoch 3");
+        assert_eq!(b.key_epoch, 3);
+        assert_eq!(b.shared_secret.as_ref().unwrap().0.to_vec(), from_hex(EPOCH_KEYS[3]));
+        assert_eq!(b.decrypt(&late).unwrap(), b"epoch 0");
+        // 跳过的中间代数没有保留密钥
+        let skipped = fixed_message(&a, 2, 3, EPOCH_3_CIPHERTEXT);
+        assert!(b.decrypt(&skipped).is_err());
     }
 }
\ No newline at end of file
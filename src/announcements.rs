@@ -0,0 +1,109 @@
+// 公告模块 - 管理员发布维护通知等公告，在 starts_at 到 ends_at 期间显示在管理界面顶部；
+// deliver_to_clients 的公告同时通过 /api/heartbeat 下发给受管客户端，客户端按 id 去重并在结束时间后自动隐藏
+use crate::device_messages::MessageLevel;
+use crate::enterprise_database::EnterpriseDatabase;
+use hbb_common::{bail, ResultType};
+use schemars::JsonSchema;
+use serde_derive::{Deserialize, Serialize};
+
+const MAX_TITLE_LEN: usize = 128;
+const MAX_BODY_LEN: usize = 4096;
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct Announcement {
+    pub id: String,
+    pub title: String,
+    pub body: String,
+    pub level: MessageLevel,
+    pub starts_at: u64,
+    pub ends_at: u64,
+    pub deliver_to_clients: bool,
+    pub created_by: String,
+    pub created_at: u64,
+    pub updated_at: u64,
+}
+
+// 通过心跳下发给客户端的公告
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct ClientAnnouncement {
+    pub id: String,
+    pub title: String,
+    pub body: String,
+    pub level: MessageLevel,
+    pub starts_at: u64,
+    pub ends_at: u64,
+}
+
+impl Announcement {
+    pub fn validate(&self) -> ResultType<()> {
+        let title_len = self.title.trim().chars().count();
+        if title_len == 0 || title_len > MAX_TITLE_LEN {
+            bail!("title must be 1-{} characters", MAX_TITLE_LEN);
+        }
+        if self.body.chars().count() > MAX_BODY_LEN {
+            bail!("body must not exceed {} characters", MAX_BODY_LEN);
+        }
+        if self.ends_at <= self.starts_at {
+            bail!("announcement ends before it starts");
+        }
+        Ok(())
+    }
+
+    pub fn is_active(&self, now: u64) -> bool {
+        self.starts_at <= now && now < self.ends_at
+    }
+}
+
+impl From<Announcement> for ClientAnnouncement {
+    fn from(announcement: Announcement) -> Self {
+        Self {
+            id: announcement.id,
+            title: announcement.title,
+            body: announcement.body,
+            level: announcement.level,
+            starts_at: announcement.starts_at,
+            ends_at: announcement.ends_at,
+        }
+    }
+}
+
+// 设备心跳时下发的公告，生效期间每次心跳都会带上
+pub async fn for_clients(db: &EnterpriseDatabase) -> ResultType<Vec<ClientAnnouncement>> {
+    Ok(db
+        .list_active_announcements(crate::common::now())
+        .await?
+        .into_iter()
+        .filter(|x| x.deliver_to_clients)
+        .map(ClientAnnouncement::from)
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate() {
+        let mut announcement = Announcement {
+            id: "a1".to_owned(),
+            title: "Maintenance".to_owned(),
+            body: "Servers restart at 22:00".to_owned(),
+            level: MessageLevel::Warning,
+            starts_at: 100,
+            ends_at: 200,
+            deliver_to_clients: true,
+            created_by: "admin".to_owned(),
+            created_at: 50,
+            updated_at: 50,
+        };
+        assert!(announcement.validate().is_ok());
+        assert!(!announcement.is_active(99));
+        assert!(announcement.is_active(100));
+        assert!(!announcement.is_active(200));
+        announcement.ends_at = 100;
+        assert!(announcement.validate().is_err());
+        announcement.ends_at = 200;
+        announcement.title = " ".to_owned();
+        assert!(announcement.validate().is_err());
+    }
+}
@@ -1,12 +1,19 @@
 // 企业级认证模块
-use hbb_common::{log, ResultType};
+use hbb_common::{bail, log, ResultType};
+use serde::{de::DeserializeOwned, Serialize as SerializeTrait};
 use serde_derive::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::sync::RwLock;
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use bcrypt::{hash, verify, DEFAULT_COST};
-use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
+use jsonwebtoken::{decode, decode_header, encode, DecodingKey, EncodingKey, Header, Validation};
 use uuid::Uuid;
 
+lazy_static::lazy_static! {
+    // 用户不存在时用于校验的哈希，代价与真实密码哈希相同
+    static ref DUMMY_PASSWORD_HASH: String = hash(Uuid::new_v4().to_string(), DEFAULT_COST).unwrap_or_default();
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Claims {
     pub sub: String,      // 用户ID
@@ -16,8 +23,47 @@ pub struct Claims {
     pub exp: usize,       // 过期时间
     pub iat: usize,       // 签发时间
     pub jti: String,      // JWT ID
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scope: Option<String>, // 受限令牌的用途，None 表示完整权限
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub act: Option<Actor>, // 代操作令牌的实际操作人(超级管理员)
+}
+
+// 代操作(impersonation)令牌中实际操作的管理员，与 RFC 8693 的 act 声明对应
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Actor {
+    pub sub: String,
+    pub username: String,
+}
+
+// 受信任浏览器令牌，存放在 Cookie 中，用于跳过该浏览器后续登录的2FA
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TrustedDeviceClaims {
+    pub sub: String, // 用户ID
+    pub tid: String, // 受信任设备记录ID，删除记录即撤销
+    pub exp: usize,
+}
+
+// 邀请令牌，放在邀请邮件的链接中，用于设置初始密码
+#[derive(Debug, Serialize, Deserialize)]
+pub struct InviteClaims {
+    pub sub: String, // 用户ID
+    pub iid: String, // 邀请记录ID，撤销或重新发送后失效
+    pub exp: usize,
 }
 
+// 找回密码令牌，pfp 为签发时密码哈希的摘要，密码修改后令牌随之失效，因此只能使用一次
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PasswordResetClaims {
+    pub sub: String, // 用户ID
+    pub pfp: String,
+    pub exp: usize,
+}
+
+// 受限令牌：策略要求双因素认证但用户尚未绑定时签发，只能访问2FA绑定接口
+pub const SCOPE_2FA_SETUP: &str = "2fa-setup";
+const RESTRICTED_TOKEN_TIMEOUT: Duration = Duration::from_secs(15 * 60);
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct User {
     pub id: String,
@@ -41,6 +87,8 @@ pub enum UserRole {
     Admin,
     User,
     ReadOnly,
+    // 合规审计员，可以只读查看全部设备、会话、审计日志和设置，不能做任何修改
+    Auditor,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -77,8 +125,47 @@ pub struct GroupPermissions {
     pub session_timeout: Option<Duration>,
 }
 
+// JWT 签名密钥，kid 为密钥摘要，写入令牌头部，校验时按 kid 选择密钥
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JwtKey {
+    pub kid: String,
+    pub secret: String,
+}
+
+impl JwtKey {
+    pub fn new(secret: String) -> Self {
+        let digest = sodiumoxide::crypto::hash::sha256::hash(secret.as_bytes()).0;
+        let kid = digest[..8].iter().map(|b| format!("{:02x}", b)).collect();
+        Self { kid, secret }
+    }
+}
+
+// 新令牌用主密钥签发；次密钥只用于校验，secondary_valid_until 为空时一直有效
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JwtKeys {
+    pub primary: JwtKey,
+    #[serde(default)]
+    pub secondary: Option<JwtKey>,
+    #[serde(default)]
+    pub secondary_valid_until: Option<u64>,
+}
+
+impl JwtKeys {
+    // 可用于校验的密钥；没有 kid 的令牌(升级前签发)依次尝试主、次密钥
+    fn candidates(&self, kid: Option<&str>, now: u64) -> Vec<&JwtKey> {
+        let secondary = self
+            .secondary
+            .as_ref()
+            .filter(|_| self.secondary_valid_until.map(|t| t > now).unwrap_or(true));
+        std::iter::once(&self.primary)
+            .chain(secondary)
+            .filter(|key| kid.map(|kid| key.kid == kid).unwrap_or(true))
+            .collect()
+    }
+}
+
 pub struct AuthManager {
-    jwt_secret: String,
+    jwt_keys: RwLock<JwtKeys>,
     session_timeout: Duration,
     max_failed_attempts: u32,
     lockout_duration: Duration,
@@ -87,7 +174,11 @@ pub struct AuthManager {
 impl AuthManager {
     pub fn new(jwt_secret: String) -> Self {
         Self {
-            jwt_secret,
+            jwt_keys: RwLock::new(JwtKeys {
+                primary: JwtKey::new(jwt_secret),
+                secondary: None,
+                secondary_valid_until: None,
+            }),
             session_timeout: Duration::from_hours(8),
             max_failed_attempts: 5,
             lockout_duration: Duration::from_minutes(30),
@@ -102,9 +193,82 @@ impl AuthManager {
         verify(password, hash).unwrap_or(false)
     }
 
+    // 用户不存在(hash 为 None)时也执行一次同等代价的校验，使响应时间与密码错误时一致
+    pub fn verify_user_password(&self, password: &str, hash: Option<&str>) -> bool {
+        let valid = verify(password, hash.unwrap_or(&DUMMY_PASSWORD_HASH)).unwrap_or(false);
+        valid && hash.is_some()
+    }
+
+    pub fn session_timeout(&self) -> Duration {
+        self.session_timeout
+    }
+
+    pub fn jwt_keys(&self) -> JwtKeys {
+        match self.jwt_keys.read() {
+            Ok(keys) => keys.clone(),
+            Err(e) => e.into_inner().clone(),
+        }
+    }
+
+    pub fn set_jwt_keys(&self, keys: JwtKeys) {
+        match self.jwt_keys.write() {
+            Ok(mut x) => *x = keys,
+            Err(e) => *e.into_inner() = keys,
+        }
+    }
+
+    fn encode_token<T: SerializeTrait>(&self, claims: &T) -> ResultType<String> {
+        let keys = self.jwt_keys();
+        let header = Header {
+            kid: Some(keys.primary.kid.clone()),
+            ..Default::default()
+        };
+        Ok(encode(&header, claims, &EncodingKey::from_secret(keys.primary.secret.as_ref()))?)
+    }
+
+    fn decode_token<T: DeserializeOwned>(&self, token: &str) -> ResultType<T> {
+        let kid = decode_header(token)?.kid;
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+        let keys = self.jwt_keys();
+        let mut result = None;
+        for key in keys.candidates(kid.as_deref(), now) {
+            match decode::<T>(token, &DecodingKey::from_secret(key.secret.as_ref()), &Validation::default()) {
+                Ok(data) => return Ok(data.claims),
+                Err(e) => result = Some(e),
+            }
+        }
+        match result {
+            Some(e) => Err(e.into()),
+            None => bail!("unknown jwt key id {:?}", kid),
+        }
+    }
+
     pub fn generate_jwt(&self, user: &User) -> ResultType<String> {
+        self.generate_jwt_with_scope(user, None, self.session_timeout)
+    }
+
+    pub fn generate_restricted_jwt(&self, user: &User, scope: &str) -> ResultType<String> {
+        self.generate_jwt_with_scope(user, Some(scope.to_owned()), RESTRICTED_TOKEN_TIMEOUT)
+    }
+
+    // 以 user 的身份和权限签发、由 actor 实际操作的令牌
+    pub fn generate_impersonation_jwt(&self, user: &User, actor: Actor, timeout: Duration) -> ResultType<String> {
+        self.generate_claims_jwt(user, None, Some(actor), timeout.min(self.session_timeout))
+    }
+
+    fn generate_jwt_with_scope(&self, user: &User, scope: Option<String>, timeout: Duration) -> ResultType<String> {
+        self.generate_claims_jwt(user, scope, None, timeout)
+    }
+
+    fn generate_claims_jwt(
+        &self,
+        user: &User,
+        scope: Option<String>,
+        act: Option<Actor>,
+        timeout: Duration,
+    ) -> ResultType<String> {
         let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as usize;
-        let exp = now + self.session_timeout.as_secs() as usize;
+        let exp = now + timeout.as_secs() as usize;
 
         let claims = Claims {
             sub: user.id.clone(),
@@ -114,25 +278,61 @@ impl AuthManager {
             exp,
             iat: now,
             jti: Uuid::new_v4().to_string(),
+            scope,
+            act,
         };
 
-        let token = encode(
-            &Header::default(),
-            &claims,
-            &EncodingKey::from_secret(self.jwt_secret.as_ref()),
-        )?;
+        let token = self.encode_token(&claims)?;
 
         Ok(token)
     }
 
     pub fn verify_jwt(&self, token: &str) -> ResultType<Claims> {
-        let token_data = decode::<Claims>(
-            token,
-            &DecodingKey::from_secret(self.jwt_secret.as_ref()),
-            &Validation::default(),
-        )?;
+        self.decode_token::<Claims>(token)
+    }
+
+    pub fn generate_trusted_device_token(&self, user_id: &str, trusted_id: &str, expires_at: u64) -> ResultType<String> {
+        let claims = TrustedDeviceClaims {
+            sub: user_id.to_owned(),
+            tid: trusted_id.to_owned(),
+            exp: expires_at as usize,
+        };
+        self.encode_token(&claims)
+    }
+
+    pub fn verify_trusted_device_token(&self, token: &str) -> ResultType<TrustedDeviceClaims> {
+        self.decode_token::<TrustedDeviceClaims>(token)
+    }
+
+    pub fn generate_invite_token(&self, user_id: &str, invite_id: &str, expires_at: u64) -> ResultType<String> {
+        let claims = InviteClaims {
+            sub: user_id.to_owned(),
+            iid: invite_id.to_owned(),
+            exp: expires_at as usize,
+        };
+        self.encode_token(&claims)
+    }
+
+    pub fn verify_invite_token(&self, token: &str) -> ResultType<InviteClaims> {
+        self.decode_token::<InviteClaims>(token)
+    }
+
+    pub fn generate_password_reset_token(&self, user: &User, expires_at: u64) -> ResultType<String> {
+        let claims = PasswordResetClaims {
+            sub: user.id.clone(),
+            pfp: password_fingerprint(&user.password_hash),
+            exp: expires_at as usize,
+        };
+        self.encode_token(&claims)
+    }
+
+    pub fn verify_password_reset_token(&self, token: &str) -> ResultType<PasswordResetClaims> {
+        self.decode_token::<PasswordResetClaims>(token)
+    }
 
-        Ok(token_data.claims)
+    // 令牌签发后密码未被修改
+    pub fn password_unchanged(&self, claims: &PasswordResetClaims, user: &User) -> bool {
+        claims.sub == user.id && claims.pfp == password_fingerprint(&user.password_hash)
     }
 
     pub fn is_user_locked(&self, user: &User) -> bool {
@@ -173,6 +373,10 @@ impl AuthManager {
     }
 }
 
+fn password_fingerprint(password_hash: &str) -> String {
+    base64::encode(sodiumoxide::crypto::hash::sha256::hash(password_hash.as_bytes()).0)[..16].to_owned()
+}
+
 // 双因素认证支持
 pub struct TwoFactorAuth {
     secret: String,
@@ -204,9 +408,27 @@ impl TwoFactorAuth {
     }
 
     pub fn verify_token(&self, token: &str) -> bool {
-        // 这里应该实现TOTP验证逻辑
-        // 为了简化，暂时返回true
-        token.len() == 6 && token.chars().all(|c| c.is_ascii_digit())
+        Self::verify_code(&self.secret, token)
+    }
+
+    // 校验TOTP代码（SHA1、6位、30秒），允许前后一个时间窗口的时钟偏差
+    pub fn verify_code(secret: &str, code: &str) -> bool {
+        use totp_rs::{Algorithm, Secret, TOTP};
+
+        if code.len() != 6 || !code.chars().all(|c| c.is_ascii_digit()) {
+            return false;
+        }
+        let secret = match Secret::Encoded(secret.to_owned()).to_bytes() {
+            Ok(secret) => secret,
+            Err(_) => return false,
+        };
+        match TOTP::new(Algorithm::SHA1, 6, 1, 30, secret) {
+            Ok(totp) => totp.check_current(code).unwrap_or(false),
+            Err(err) => {
+                log::error!("Invalid totp secret: {:?}", err);
+                false
+            }
+        }
     }
 
     pub fn get_secret(&self) -> &str {
@@ -225,6 +447,31 @@ mod tests {
         let hash = auth.hash_password(password).unwrap();
         assert!(auth.verify_password(password, &hash));
         assert!(!auth.verify_password("wrong_password", &hash));
+        assert!(auth.verify_user_password(password, Some(&hash)));
+        assert!(!auth.verify_user_password(password, None));
+        assert!(!auth.verify_user_password("", None));
+
+        let mut user = User {
+            id: "test_id".to_string(),
+            username: "test_user".to_string(),
+            password_hash: hash,
+            email: None,
+            role: UserRole::User,
+            groups: vec![],
+            enabled: true,
+            created_at: SystemTime::now(),
+            last_login: None,
+            failed_login_attempts: 0,
+            locked_until: None,
+            two_factor_enabled: false,
+            two_factor_secret: None,
+        };
+        let token = auth.generate_password_reset_token(&user, u32::MAX as u64).unwrap();
+        let claims = auth.verify_password_reset_token(&token).unwrap();
+        assert!(auth.password_unchanged(&claims, &user));
+        assert!(auth.verify_invite_token(&token).is_err());
+        user.password_hash = auth.hash_password("new_password").unwrap();
+        assert!(!auth.password_unchanged(&claims, &user));
     }
 
     #[test]
@@ -249,5 +496,22 @@ mod tests {
         let token = auth.generate_jwt(&user).unwrap();
         let claims = auth.verify_jwt(&token).unwrap();
         assert_eq!(claims.username, "test_user");
+
+        // 次密钥在有效期内可以校验原密钥签发的令牌
+        let old = auth.jwt_keys().primary;
+        auth.set_jwt_keys(JwtKeys {
+            primary: JwtKey::new("new_secret".to_string()),
+            secondary: Some(old.clone()),
+            secondary_valid_until: None,
+        });
+        let header = decode_header(&auth.generate_jwt(&user).unwrap()).unwrap();
+        assert_eq!(header.kid, Some(auth.jwt_keys().primary.kid));
+        assert!(auth.verify_jwt(&token).is_ok());
+        auth.set_jwt_keys(JwtKeys {
+            primary: JwtKey::new("new_secret".to_string()),
+            secondary: Some(old),
+            secondary_valid_until: Some(1),
+        });
+        assert!(auth.verify_jwt(&token).is_err());
     }
 }
\ No newline at end of file
@@ -0,0 +1,367 @@
+// 数据库备份模块 - 通过 VACUUM INTO 在线生成一致的 SQLite 快照，写入 BACKUP_DIR(默认 backups)，
+// 可选用 BACKUP_PASSPHRASE 加密(Argon2id 派生密钥 + XSalsa20-Poly1305)，口令只从环境变量读取，不写入数据库。
+// 自动备份按 /api/settings/backup 配置的间隔执行并只保留最近若干份。
+// 恢复需先停止服务，再执行 `hbbs backup restore FILE [--db PATH]`，原数据库会重命名为 *.before-restore
+use crate::enterprise_database::EnterpriseDatabase;
+use crate::validation::Payload;
+use hbb_common::{
+    anyhow::Context,
+    bail, log,
+    tokio::sync::{Mutex, RwLock},
+    ResultType,
+};
+use schemars::JsonSchema;
+use serde_derive::{Deserialize, Serialize};
+use sodiumoxide::crypto::{pwhash::argon2id13 as pwhash, secretbox};
+use std::path::{Path, PathBuf};
+use validator::Validate;
+
+pub const BACKUP_KEY: &str = "backup";
+const FILE_PREFIX: &str = "hbbs-";
+const PLAIN_EXT: &str = ".sqlite3";
+const ENCRYPTED_EXT: &str = ".sqlite3.enc";
+const MAGIC: &[u8; 8] = b"HBBSBAK1";
+const SQLITE_HEADER: &[u8] = b"SQLite format 3\0";
+
+lazy_static::lazy_static! {
+    static ref CONFIG: RwLock<BackupConfig> = Default::default();
+    // 手动备份和自动备份不并发执行
+    static ref RUNNING: Mutex<()> = Default::default();
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, Validate)]
+pub struct BackupConfig {
+    // 是否启用自动备份
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default = "default_interval_hours")]
+    #[validate(range(min = 1, max = 720))]
+    pub interval_hours: u32,
+    // 保留的备份份数，超出的旧备份自动删除
+    #[serde(default = "default_keep")]
+    #[validate(range(min = 1, max = 365))]
+    pub keep: usize,
+    #[serde(default)]
+    pub encrypt: bool,
+}
+
+impl Payload for BackupConfig {}
+
+fn default_interval_hours() -> u32 {
+    24
+}
+
+fn default_keep() -> usize {
+    7
+}
+
+impl Default for BackupConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            interval_hours: default_interval_hours(),
+            keep: default_keep(),
+            encrypt: false,
+        }
+    }
+}
+
+impl BackupConfig {
+    pub fn validate(&self) -> ResultType<()> {
+        if self.interval_hours == 0 || self.interval_hours > 24 * 30 {
+            bail!("interval_hours must be 1-720");
+        }
+        if self.keep == 0 || self.keep > 365 {
+            bail!("keep must be 1-365");
+        }
+        if self.encrypt && passphrase().is_none() {
+            bail!("BACKUP_PASSPHRASE is not set");
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct BackupFile {
+    pub name: String,
+    pub size: u64,
+    pub encrypted: bool,
+    pub created_at: u64,
+}
+
+pub fn backup_dir() -> PathBuf {
+    PathBuf::from(std::env::var("BACKUP_DIR").unwrap_or_else(|_| "backups".to_owned()))
+}
+
+pub fn passphrase() -> Option<String> {
+    std::env::var("BACKUP_PASSPHRASE").ok().filter(|x| !x.is_empty())
+}
+
+fn is_backup_name(name: &str) -> bool {
+    name.starts_with(FILE_PREFIX)
+        && (name.ends_with(PLAIN_EXT) || name.ends_with(ENCRYPTED_EXT))
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '.')
+}
+
+// 按文件名取备份路径，拒绝目录穿越等非备份文件名
+pub fn path_of(name: &str) -> Option<PathBuf> {
+    if !is_backup_name(name) {
+        return None;
+    }
+    let path = backup_dir().join(name);
+    if path.is_file() {
+        Some(path)
+    } else {
+        None
+    }
+}
+
+fn derive_key(passphrase: &str, salt: &pwhash::Salt) -> ResultType<secretbox::Key> {
+    let mut key = secretbox::Key([0; secretbox::KEYBYTES]);
+    if pwhash::derive_key(
+        &mut key.0,
+        passphrase.as_bytes(),
+        salt,
+        pwhash::OPSLIMIT_INTERACTIVE,
+        pwhash::MEMLIMIT_INTERACTIVE,
+    )
+    .is_err()
+    {
+        bail!("failed to derive backup key");
+    }
+    Ok(key)
+}
+
+// 加密格式: MAGIC | salt | nonce | 密文
+pub fn encrypt(data: &[u8], passphrase: &str) -> ResultType<Vec<u8>> {
+    let salt = pwhash::gen_salt();
+    let nonce = secretbox::gen_nonce();
+    let key = derive_key(passphrase, &salt)?;
+    let mut out = Vec::with_capacity(MAGIC.len() + salt.0.len() + nonce.0.len() + data.len() + secretbox::MACBYTES);
+    out.extend_from_slice(MAGIC);
+    out.extend_from_slice(&salt.0);
+    out.extend_from_slice(&nonce.0);
+    out.extend_from_slice(&secretbox::seal(data, &nonce, &key));
+    Ok(out)
+}
+
+pub fn is_encrypted(data: &[u8]) -> bool {
+    data.starts_with(MAGIC)
+}
+
+pub fn decrypt(data: &[u8], passphrase: &str) -> ResultType<Vec<u8>> {
+    let header = MAGIC.len() + pwhash::SALTBYTES + secretbox::NONCEBYTES;
+    if !is_encrypted(data) || data.len() < header {
+        bail!("not an encrypted backup");
+    }
+    let salt = pwhash::Salt::from_slice(&data[MAGIC.len()..MAGIC.len() + pwhash::SALTBYTES]).context("salt")?;
+    let nonce = secretbox::Nonce::from_slice(&data[MAGIC.len() + pwhash::SALTBYTES..header]).context("nonce")?;
+    let key = derive_key(passphrase, &salt)?;
+    match secretbox::open(&data[header..], &nonce, &key) {
+        Ok(plain) => Ok(plain),
+        Err(_) => bail!("wrong passphrase or corrupted backup"),
+    }
+}
+
+// 生成一份备份；先写临时文件，完成后再改名，避免留下不完整的备份
+pub async fn create(db: &EnterpriseDatabase, encrypt_backup: bool) -> ResultType<BackupFile> {
+    let passphrase = if encrypt_backup {
+        match passphrase() {
+            Some(p) => Some(p),
+            None => bail!("BACKUP_PASSPHRASE is not set"),
+        }
+    } else {
+        None
+    };
+    let _running = RUNNING.lock().await;
+    let dir = backup_dir();
+    std::fs::create_dir_all(&dir).with_context(|| format!("create {}", dir.display()))?;
+    let now = crate::common::now();
+    let name = format!(
+        "{}{}{}",
+        FILE_PREFIX,
+        chrono::Local::now().format("%Y%m%d-%H%M%S"),
+        if passphrase.is_some() { ENCRYPTED_EXT } else { PLAIN_EXT }
+    );
+    let path = dir.join(&name);
+    if path.exists() {
+        bail!("backup {} already exists", name);
+    }
+    let tmp = dir.join(format!(".{}.tmp", name));
+    let _ = std::fs::remove_file(&tmp);
+    let res = async {
+        db.backup_into(&tmp.to_string_lossy()).await?;
+        if let Some(passphrase) = &passphrase {
+            let data = std::fs::read(&tmp)?;
+            std::fs::write(&tmp, encrypt(&data, passphrase)?)?;
+        }
+        std::fs::rename(&tmp, &path)?;
+        ResultType::Ok(())
+    }
+    .await;
+    if let Err(e) = res {
+        let _ = std::fs::remove_file(&tmp);
+        return Err(e);
+    }
+    let size = std::fs::metadata(&path)?.len();
+    log::info!("Database backup written to {} ({} bytes)", path.display(), size);
+    Ok(BackupFile {
+        name,
+        size,
+        encrypted: passphrase.is_some(),
+        created_at: now,
+    })
+}
+
+// 新备份在前
+pub fn list() -> ResultType<Vec<BackupFile>> {
+    let dir = backup_dir();
+    if !dir.exists() {
+        return Ok(vec![]);
+    }
+    let mut res = vec![];
+    for entry in std::fs::read_dir(&dir)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().to_string();
+        if !is_backup_name(&name) {
+            continue;
+        }
+        let meta = entry.metadata()?;
+        if !meta.is_file() {
+            continue;
+        }
+        let created_at = meta
+            .modified()
+            .ok()
+            .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        res.push(BackupFile {
+            encrypted: name.ends_with(ENCRYPTED_EXT),
+            name,
+            size: meta.len(),
+            created_at,
+        });
+    }
+    res.sort_by(|a, b| b.created_at.cmp(&a.created_at).then_with(|| b.name.cmp(&a.name)));
+    Ok(res)
+}
+
+// 删除超出保留份数的旧备份
+pub fn prune(keep: usize) -> ResultType<usize> {
+    let mut removed = 0;
+    for backup in list()?.into_iter().skip(keep) {
+        std::fs::remove_file(backup_dir().join(&backup.name))?;
+        log::info!("Removed old backup {}", backup.name);
+        removed += 1;
+    }
+    Ok(removed)
+}
+
+// 自动备份，由后台任务定期调用
+pub async fn run_scheduled(db: &EnterpriseDatabase) {
+    let config = get().await;
+    if !config.enabled {
+        return;
+    }
+    let latest = list().ok().and_then(|x| x.first().map(|b| b.created_at)).unwrap_or_default();
+    if crate::common::now() < latest + config.interval_hours as u64 * 3600 {
+        return;
+    }
+    match create(db, config.encrypt).await {
+        Ok(_) => {
+            if let Err(e) = prune(config.keep) {
+                log::error!("Failed to prune backups: {}", e);
+            }
+        }
+        Err(e) => log::error!("Scheduled database backup failed: {}", e),
+    }
+}
+
+fn sqlite_path(url: &str) -> &str {
+    let path = url.trim_start_matches("sqlite://").trim_start_matches("sqlite:");
+    path.split('?').next().unwrap_or(path)
+}
+
+// 离线恢复，服务必须已停止；原数据库及其 WAL 文件保留为 *.before-restore
+pub fn restore(src: &Path, db_url: &str, passphrase: Option<&str>) -> ResultType<PathBuf> {
+    let mut data = std::fs::read(src).with_context(|| format!("read {}", src.display()))?;
+    if is_encrypted(&data) {
+        let passphrase = match passphrase {
+            Some(p) => p,
+            None => bail!("backup is encrypted, set BACKUP_PASSPHRASE"),
+        };
+        data = decrypt(&data, passphrase)?;
+    }
+    if !data.starts_with(SQLITE_HEADER) {
+        bail!("{} is not a SQLite database", src.display());
+    }
+    let dest = PathBuf::from(sqlite_path(db_url));
+    let tmp = PathBuf::from(format!("{}.restore.tmp", dest.display()));
+    std::fs::write(&tmp, &data)?;
+    for suffix in ["", "-wal", "-shm"] {
+        let path = PathBuf::from(format!("{}{}", dest.display(), suffix));
+        if path.exists() {
+            std::fs::rename(&path, format!("{}.before-restore", path.display()))?;
+        }
+    }
+    std::fs::rename(&tmp, &dest)?;
+    Ok(dest)
+}
+
+// `backup restore` 子命令，返回进程退出码
+pub fn restore_command(src: &str, db_url: &str) -> i32 {
+    match restore(Path::new(src), db_url, passphrase().as_deref()) {
+        Ok(dest) => {
+            println!("{} restored to {}", src, dest.display());
+            0
+        }
+        Err(e) => {
+            eprintln!("{}: {:#}", src, e);
+            1
+        }
+    }
+}
+
+pub async fn reload(db: &EnterpriseDatabase) -> ResultType<()> {
+    let config = match db.get_setting(BACKUP_KEY).await? {
+        Some(v) => serde_json::from_str(&v)?,
+        None => BackupConfig::default(),
+    };
+    *CONFIG.write().await = config;
+    Ok(())
+}
+
+pub async fn get() -> BackupConfig {
+    CONFIG.read().await.clone()
+}
+
+pub async fn update(db: &EnterpriseDatabase, config: BackupConfig, updated_by: &str) -> ResultType<()> {
+    config.validate()?;
+    db.set_setting(BACKUP_KEY, &serde_json::to_string(&config)?, Some(updated_by))
+        .await?;
+    *CONFIG.write().await = config;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backup_encryption() {
+        let data = b"SQLite format 3\0rest of the database";
+        let sealed = encrypt(data, "correct horse").unwrap();
+        assert!(is_encrypted(&sealed));
+        assert_eq!(decrypt(&sealed, "correct horse").unwrap(), data.to_vec());
+        assert!(decrypt(&sealed, "wrong").is_err());
+        assert!(decrypt(data, "correct horse").is_err());
+
+        assert!(is_backup_name("hbbs-20260101-020000.sqlite3"));
+        assert!(is_backup_name("hbbs-20260101-020000.sqlite3.enc"));
+        assert!(!is_backup_name("hbbs-../../etc/passwd.sqlite3"));
+        assert!(!is_backup_name("enterprise.sqlite3"));
+        assert_eq!(sqlite_path("sqlite://data/db.sqlite3?mode=rwc"), "data/db.sqlite3");
+        assert_eq!(sqlite_path("enterprise.sqlite3"), "enterprise.sqlite3");
+    }
+}
@@ -0,0 +1,459 @@
+// hbbs 压测工具 - 模拟大量设备通过 UDP 注册、定期心跳，并按固定速率向随机在线设备发起打洞请求，
+// 统计注册(RegisterPeer -> RegisterPeerResponse)、登记公钥(RegisterPk -> RegisterPkResponse)和
+// 打洞(PunchHoleRequest -> 被控端收到 PunchHole/FetchLocalAddr)的延迟分位数。
+// 每个模拟设备占用一个 UDP 端口，上万设备时需调高 ulimit -n
+use hbb_common::{
+    protobuf::Message as _,
+    rendezvous_proto::*,
+    tokio::{
+        self,
+        time::{interval, sleep, Duration, Instant},
+    },
+    udp::FramedSocket,
+    ResultType,
+};
+use rand::Rng;
+use sodiumoxide::crypto::sign;
+use std::{
+    collections::HashMap,
+    env,
+    net::{SocketAddr, ToSocketAddrs},
+    process,
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
+    sync::{Arc, Mutex},
+};
+
+// 打洞请求超过该时长未送达计为超时
+const PUNCH_TIMEOUT_MS: u64 = 5_000;
+// 压测结束后继续接收在途响应的时长
+const DRAIN_MS: u64 = 2_000;
+const PROGRESS_SECS: u64 = 10;
+const MAX_REQUESTERS: u64 = 8;
+
+fn print_help() {
+    println!(
+        "Usage:
+    hbbs-bench [options]\n
+Options:
+    --server HOST[:PORT]    Target hbbs (default 127.0.0.1:21116)
+    --peers N               Simulated peers (default 1000)
+    --heartbeat SECS        Registration interval of each peer (default 12)
+    --punch-rate M          Punch-hole requests per second (default 10)
+    --duration SECS         Test duration (default 60)
+    --key KEY               Licence key, the server's public key when started with -k
+    --id-base N             First simulated peer id (default 900000000)"
+    );
+    process::exit(0x0001);
+}
+
+fn error_then_help(msg: &str) {
+    println!("ERROR: {msg}\n");
+    print_help();
+}
+
+struct Args {
+    server: SocketAddr,
+    peers: u64,
+    heartbeat: u64,
+    punch_rate: u64,
+    duration: u64,
+    key: String,
+    id_base: u64,
+}
+
+fn parse_args(argv: &[String]) -> Result<Args, String> {
+    let mut values: HashMap<&str, &str> = HashMap::new();
+    let mut it = argv.iter();
+    while let Some(name) = it.next() {
+        let name = name
+            .strip_prefix("--")
+            .ok_or_else(|| format!("Unexpected argument {name}"))?;
+        let value = it.next().ok_or_else(|| format!("Missing value of --{name}"))?;
+        values.insert(name, value);
+    }
+    let number = |name: &str, default: u64| -> Result<u64, String> {
+        match values.get(name) {
+            Some(v) => v.parse().map_err(|_| format!("Invalid --{name}: {v}")),
+            None => Ok(default),
+        }
+    };
+    let server = values.get("server").copied().unwrap_or("127.0.0.1");
+    let server = if server.contains(':') && !server.ends_with(']') {
+        server.to_owned()
+    } else {
+        format!("{server}:21116")
+    };
+    let server = server
+        .to_socket_addrs()
+        .ok()
+        .and_then(|mut x| x.next())
+        .ok_or_else(|| format!("Invalid --server: {server}"))?;
+    let args = Args {
+        server,
+        peers: number("peers", 1000)?,
+        heartbeat: number("heartbeat", 12)?,
+        punch_rate: number("punch-rate", 10)?,
+        duration: number("duration", 60)?,
+        key: values.get("key").map(|x| x.to_string()).unwrap_or_default(),
+        id_base: number("id-base", 900_000_000)?,
+    };
+    if args.peers == 0 || args.heartbeat == 0 || args.duration == 0 {
+        return Err("--peers, --heartbeat and --duration must be positive".to_owned());
+    }
+    if let Some(name) = values
+        .keys()
+        .find(|x| !["server", "peers", "heartbeat", "punch-rate", "duration", "key", "id-base"].contains(x))
+    {
+        return Err(format!("Unknown option --{name}"));
+    }
+    Ok(args)
+}
+
+// 一类请求的发送数、延迟样本(微秒)、超时数和按原因统计的失败数
+#[derive(Default)]
+struct Stage {
+    sent: AtomicU64,
+    timeouts: AtomicU64,
+    samples: Mutex<Vec<u64>>,
+    failures: Mutex<HashMap<String, u64>>,
+}
+
+impl Stage {
+    fn record(&self, started: Instant) {
+        let micros = started.elapsed().as_micros() as u64;
+        self.samples.lock().unwrap().push(micros);
+    }
+
+    fn fail(&self, reason: &str) {
+        *self.failures.lock().unwrap().entry(reason.to_owned()).or_default() += 1;
+    }
+
+    fn report(&self, name: &str) {
+        let mut samples = self.samples.lock().unwrap().clone();
+        samples.sort_unstable();
+        let ms = |p: f64| percentile(&samples, p) as f64 / 1000.;
+        println!(
+            "{:<9} sent {:>8}  ok {:>8}  timeout {:>6}  p50 {:>8.2}ms  p90 {:>8.2}ms  p99 {:>8.2}ms  max {:>8.2}ms",
+            name,
+            self.sent.load(Ordering::Relaxed),
+            samples.len(),
+            self.timeouts.load(Ordering::Relaxed),
+            ms(50.),
+            ms(90.),
+            ms(99.),
+            ms(100.),
+        );
+        let mut failures: Vec<_> = self.failures.lock().unwrap().clone().into_iter().collect();
+        failures.sort();
+        for (reason, n) in failures {
+            println!("{:<9} failed {:>6}  {}", "", n, reason);
+        }
+    }
+}
+
+// 已排序样本的最近秩分位数，无样本时为 0
+fn percentile(sorted: &[u64], p: f64) -> u64 {
+    if sorted.is_empty() {
+        return 0;
+    }
+    let rank = (p / 100. * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+#[derive(Default)]
+struct Stats {
+    register: Stage,
+    register_pk: Stage,
+    punch: Stage,
+}
+
+struct Shared {
+    args: Args,
+    stats: Stats,
+    // 已完成注册(服务端不再要求公钥)的设备
+    online: Vec<AtomicBool>,
+    // 目标设备 id -> 打洞请求发出时间
+    pending: Mutex<HashMap<String, Instant>>,
+    stop_at: Instant,
+}
+
+impl Shared {
+    fn peer_id(&self, index: u64) -> String {
+        (self.args.id_base + index).to_string()
+    }
+}
+
+async fn run_peer(shared: Arc<Shared>, index: u64) -> ResultType<()> {
+    let id = shared.peer_id(index);
+    let uuid = uuid::Uuid::new_v4().as_bytes().to_vec();
+    let (pk, _) = sign::gen_keypair();
+    let mut socket = FramedSocket::new("0.0.0.0:0").await?;
+    let heartbeat = Duration::from_secs(shared.args.heartbeat);
+    let drain_at = shared.stop_at + Duration::from_millis(DRAIN_MS);
+    // 首次注册在一个心跳周期内随机分布，避免所有设备同时注册
+    let mut next_reg = Instant::now() + heartbeat.mul_f64(rand::thread_rng().gen::<f64>());
+    let mut reg_sent: Option<Instant> = None;
+    let mut pk_sent: Option<Instant> = None;
+    loop {
+        let now = Instant::now();
+        if now >= drain_at {
+            break;
+        }
+        if now >= next_reg && now < shared.stop_at {
+            if reg_sent.take().is_some() {
+                shared.stats.register.timeouts.fetch_add(1, Ordering::Relaxed);
+            }
+            if pk_sent.take().is_some() {
+                shared.stats.register_pk.timeouts.fetch_add(1, Ordering::Relaxed);
+            }
+            let mut msg = RendezvousMessage::new();
+            msg.set_register_peer(RegisterPeer {
+                id: id.clone(),
+                ..Default::default()
+            });
+            socket.send(&msg, shared.args.server).await?;
+            shared.stats.register.sent.fetch_add(1, Ordering::Relaxed);
+            reg_sent = Some(now);
+            next_reg += heartbeat;
+        }
+        let until = if now < shared.stop_at { next_reg.min(drain_at) } else { drain_at };
+        let wait = until.saturating_duration_since(now).as_millis().max(1) as u64;
+        let bytes = match socket.next_timeout(wait).await {
+            Some(Ok((bytes, _))) => bytes,
+            Some(Err(_)) | None => continue,
+        };
+        let msg = match RendezvousMessage::parse_from_bytes(&bytes) {
+            Ok(msg) => msg,
+            Err(_) => continue,
+        };
+        match msg.union {
+            Some(rendezvous_message::Union::RegisterPeerResponse(rpr)) => {
+                if let Some(started) = reg_sent.take() {
+                    shared.stats.register.record(started);
+                }
+                if !rpr.request_pk {
+                    shared.online[index as usize].store(true, Ordering::Relaxed);
+                } else if pk_sent.is_none() {
+                    let mut msg = RendezvousMessage::new();
+                    msg.set_register_pk(RegisterPk {
+                        id: id.clone(),
+                        uuid: uuid.clone().into(),
+                        pk: pk.0.to_vec().into(),
+                        ..Default::default()
+                    });
+                    socket.send(&msg, shared.args.server).await?;
+                    shared.stats.register_pk.sent.fetch_add(1, Ordering::Relaxed);
+                    pk_sent = Some(Instant::now());
+                }
+            }
+            Some(rendezvous_message::Union::RegisterPkResponse(rpr)) => {
+                if let Some(started) = pk_sent.take() {
+                    match rpr.result.enum_value() {
+                        Ok(register_pk_response::Result::OK) => {
+                            shared.stats.register_pk.record(started);
+                            // 公钥登记后下一次心跳即为在线
+                            next_reg = Instant::now();
+                        }
+                        Ok(res) => shared.stats.register_pk.fail(&format!("{:?}", res)),
+                        Err(n) => shared.stats.register_pk.fail(&format!("result {}", n)),
+                    }
+                }
+            }
+            Some(rendezvous_message::Union::PunchHole(_))
+            | Some(rendezvous_message::Union::FetchLocalAddr(_)) => {
+                if let Some(started) = shared.pending.lock().unwrap().remove(&id) {
+                    shared.stats.punch.record(started);
+                }
+            }
+            _ => {}
+        }
+    }
+    if reg_sent.is_some() {
+        shared.stats.register.timeouts.fetch_add(1, Ordering::Relaxed);
+    }
+    if pk_sent.is_some() {
+        shared.stats.register_pk.timeouts.fetch_add(1, Ordering::Relaxed);
+    }
+    Ok(())
+}
+
+// 以 rate 次/秒向随机在线设备发起打洞请求，失败响应按原因计数
+async fn run_requester(shared: Arc<Shared>, rate: f64) -> ResultType<()> {
+    let mut socket = FramedSocket::new("0.0.0.0:0").await?;
+    let mut ticker = interval(Duration::from_secs_f64(1. / rate));
+    let drain_at = shared.stop_at + Duration::from_millis(DRAIN_MS);
+    loop {
+        let target = tokio::select! {
+            _ = ticker.tick() => pick_target(&shared),
+            res = socket.next() => {
+                if let Some(Ok((bytes, _))) = res {
+                    if let Ok(msg) = RendezvousMessage::parse_from_bytes(&bytes) {
+                        if let Some(rendezvous_message::Union::PunchHoleResponse(phr)) = msg.union {
+                            match phr.failure.enum_value() {
+                                Ok(failure) => shared.stats.punch.fail(&format!("{:?}", failure)),
+                                Err(n) => shared.stats.punch.fail(&format!("failure {}", n)),
+                            }
+                        }
+                    }
+                }
+                None
+            }
+            _ = sleep(drain_at.saturating_duration_since(Instant::now())) => break,
+        };
+        let id = match target {
+            Some(id) if Instant::now() < shared.stop_at => id,
+            _ => continue,
+        };
+        let mut msg = RendezvousMessage::new();
+        msg.set_punch_hole_request(PunchHoleRequest {
+            id,
+            licence_key: shared.args.key.clone(),
+            nat_type: NatType::ASYMMETRIC.into(),
+            ..Default::default()
+        });
+        socket.send(&msg, shared.args.server).await?;
+        shared.stats.punch.sent.fetch_add(1, Ordering::Relaxed);
+    }
+    Ok(())
+}
+
+// 随机选一台没有在途打洞请求的在线设备，同时把超时的请求计为超时
+fn pick_target(shared: &Shared) -> Option<String> {
+    let mut pending = shared.pending.lock().unwrap();
+    let before = pending.len();
+    pending.retain(|_, started| started.elapsed().as_millis() < PUNCH_TIMEOUT_MS as u128);
+    let expired = (before - pending.len()) as u64;
+    shared.stats.punch.timeouts.fetch_add(expired, Ordering::Relaxed);
+    let mut rng = rand::thread_rng();
+    for _ in 0..16 {
+        let index = rng.gen_range(0..shared.args.peers);
+        if !shared.online[index as usize].load(Ordering::Relaxed) {
+            continue;
+        }
+        let id = shared.peer_id(index);
+        if pending.contains_key(&id) {
+            continue;
+        }
+        pending.insert(id.clone(), Instant::now());
+        return Some(id);
+    }
+    shared.stats.punch.fail("skipped, no online peer");
+    None
+}
+
+fn online_count(shared: &Shared) -> usize {
+    shared.online.iter().filter(|x| x.load(Ordering::Relaxed)).count()
+}
+
+#[tokio::main(flavor = "multi_thread")]
+async fn main() {
+    let argv: Vec<String> = env::args().skip(1).collect();
+    if argv.iter().any(|x| x == "-h" || x == "--help") {
+        print_help();
+    }
+    let args = match parse_args(&argv) {
+        Ok(args) => args,
+        Err(e) => {
+            error_then_help(&e);
+            return;
+        }
+    };
+    if sodiumoxide::init().is_err() {
+        println!("ERROR: failed to initialize sodiumoxide");
+        process::exit(0x0001);
+    }
+    println!(
+        "Benchmarking {} with {} peers (heartbeat {}s), {} punch-holes/s for {}s",
+        args.server, args.peers, args.heartbeat, args.punch_rate, args.duration
+    );
+    let started = Instant::now();
+    let shared = Arc::new(Shared {
+        online: (0..args.peers).map(|_| AtomicBool::new(false)).collect(),
+        stop_at: started + Duration::from_secs(args.duration),
+        stats: Stats::default(),
+        pending: Default::default(),
+        args,
+    });
+    let mut tasks = vec![];
+    for index in 0..shared.args.peers {
+        let shared = shared.clone();
+        tasks.push(tokio::spawn(async move {
+            if let Err(e) = run_peer(shared.clone(), index).await {
+                shared.stats.register.fail(&e.to_string());
+            }
+        }));
+    }
+    let requesters = shared.args.punch_rate.min(MAX_REQUESTERS);
+    for _ in 0..requesters {
+        let shared = shared.clone();
+        let rate = shared.args.punch_rate as f64 / requesters as f64;
+        tasks.push(tokio::spawn(async move {
+            if let Err(e) = run_requester(shared.clone(), rate).await {
+                shared.stats.punch.fail(&e.to_string());
+            }
+        }));
+    }
+    {
+        let shared = shared.clone();
+        tokio::spawn(async move {
+            let mut ticker = interval(Duration::from_secs(PROGRESS_SECS));
+            ticker.tick().await;
+            loop {
+                ticker.tick().await;
+                let stats = &shared.stats;
+                println!(
+                    "[{:>4}s] online {}/{}  registrations {}  punch-holes {} ({} delivered)",
+                    started.elapsed().as_secs(),
+                    online_count(&shared),
+                    shared.args.peers,
+                    stats.register.sent.load(Ordering::Relaxed),
+                    stats.punch.sent.load(Ordering::Relaxed),
+                    stats.punch.samples.lock().unwrap().len(),
+                );
+            }
+        });
+    }
+    for task in tasks {
+        task.await.ok();
+    }
+    let expired = shared.pending.lock().unwrap().len() as u64;
+    shared.stats.punch.timeouts.fetch_add(expired, Ordering::Relaxed);
+
+    println!(
+        "\nFinished in {:.1}s, {}/{} peers online",
+        started.elapsed().as_secs_f64(),
+        online_count(&shared),
+        shared.args.peers
+    );
+    shared.stats.register.report("register");
+    shared.stats.register_pk.report("pk");
+    shared.stats.punch.report("punch");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_percentile() {
+        assert_eq!(percentile(&[], 50.), 0);
+        let samples: Vec<u64> = (1..=100).collect();
+        assert_eq!(percentile(&samples, 50.), 50);
+        assert_eq!(percentile(&samples, 99.), 99);
+        assert_eq!(percentile(&samples, 100.), 100);
+        assert_eq!(percentile(&[7], 90.), 7);
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let argv = |x: &str| x.split_whitespace().map(|x| x.to_owned()).collect::<Vec<_>>();
+        let args = parse_args(&argv("--peers 5000 --punch-rate 50 --server 127.0.0.1")).unwrap();
+        assert_eq!(args.server.port(), 21116);
+        assert_eq!((args.peers, args.punch_rate, args.heartbeat), (5000, 50, 12));
+        assert_eq!(parse_args(&argv("--server 127.0.0.1:31116")).unwrap().server.port(), 31116);
+        assert!(parse_args(&argv("--peers")).is_err());
+        assert!(parse_args(&argv("--peers many")).is_err());
+        assert!(parse_args(&argv("--peers 0")).is_err());
+        assert!(parse_args(&argv("--rate 5")).is_err());
+    }
+}
@@ -0,0 +1,235 @@
+// 紧急访问(break-glass)模块 - 管理员在紧急情况下填写访问理由后，可绕过双人审批直接连接设备。
+// 每次启用都会产生 Critical 级安全事件并记录待复核，授权 30 分钟后自动失效，
+// 失效或提前结束时断开授权期间建立的中继会话；复核人必须是启用人以外的超级管理员
+use crate::advanced_security::{SecurityEvent, SecurityEventType, SecuritySeverity};
+use crate::enterprise_database::EnterpriseDatabase;
+use crate::relay_sessions;
+use hbb_common::{bail, log, ResultType};
+use schemars::JsonSchema;
+use serde_derive::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+pub const ACCESS_DURATION_SECS: u64 = 30 * 60;
+pub const MIN_JUSTIFICATION_LEN: usize = 20;
+const MAX_JUSTIFICATION_LEN: usize = 2000;
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct EmergencyAccess {
+    pub id: String,
+    pub user_id: String,
+    pub username: String,
+    pub device_id: String,
+    pub justification: String,
+    pub ip_address: String,
+    pub created_at: u64,
+    pub expires_at: u64,
+    // 提前结束或到期后由清理任务填写
+    pub ended_at: Option<u64>,
+    pub reviewed_by: Option<String>,
+    pub reviewed_at: Option<u64>,
+    pub review_notes: Option<String>,
+}
+
+impl EmergencyAccess {
+    pub fn is_active(&self, now: u64) -> bool {
+        self.ended_at.is_none() && self.expires_at > now
+    }
+
+    pub fn is_reviewed(&self) -> bool {
+        self.reviewed_by.is_some()
+    }
+}
+
+pub fn validate_justification(justification: &str) -> ResultType<String> {
+    let justification = justification.trim();
+    let len = justification.chars().count();
+    if len < MIN_JUSTIFICATION_LEN {
+        bail!("justification must be at least {} characters", MIN_JUSTIFICATION_LEN);
+    }
+    if len > MAX_JUSTIFICATION_LEN {
+        bail!("justification must not exceed {} characters", MAX_JUSTIFICATION_LEN);
+    }
+    Ok(justification.to_owned())
+}
+
+// 启用紧急访问，同一用户对同一设备已有生效中的授权时直接返回该授权
+pub async fn activate(
+    db: &EnterpriseDatabase,
+    user_id: &str,
+    username: &str,
+    device_id: &str,
+    justification: &str,
+    ip_address: &str,
+) -> ResultType<(EmergencyAccess, bool)> {
+    let justification = validate_justification(justification)?;
+    let now = crate::common::now();
+    if let Some(access) = db.get_active_emergency_access(device_id, user_id, now).await? {
+        return Ok((access, false));
+    }
+    let access = EmergencyAccess {
+        id: uuid::Uuid::new_v4().to_string(),
+        user_id: user_id.to_owned(),
+        username: username.to_owned(),
+        device_id: device_id.to_owned(),
+        justification,
+        ip_address: ip_address.to_owned(),
+        created_at: now,
+        expires_at: now + ACCESS_DURATION_SECS,
+        ended_at: None,
+        reviewed_by: None,
+        reviewed_at: None,
+        review_notes: None,
+    };
+    db.save_emergency_access(&access).await?;
+    raise_alert(db, &access).await;
+    Ok((access, true))
+}
+
+async fn raise_alert(db: &EnterpriseDatabase, access: &EmergencyAccess) {
+    let mut details = HashMap::new();
+    details.insert("break_glass_id".to_string(), access.id.clone());
+    details.insert("username".to_string(), access.username.clone());
+    details.insert("justification".to_string(), access.justification.clone());
+    details.insert("expires_at".to_string(), access.expires_at.to_string());
+    let event = SecurityEvent {
+        id: uuid::Uuid::new_v4().to_string(),
+        event_type: SecurityEventType::PrivilegeEscalation,
+        severity: SecuritySeverity::Critical,
+        user_id: Some(access.user_id.clone()),
+        device_id: Some(access.device_id.clone()),
+        ip_address: access.ip_address.clone(),
+        user_agent: None,
+        details,
+        timestamp: SystemTime::now(),
+        resolved: false,
+        resolution_notes: None,
+    };
+    if let Err(err) = db.save_security_event(&event).await {
+        log::error!("Failed to save break-glass security event: {}", err);
+    }
+    log::warn!("Security alert: {:?}", event);
+    crate::notifications::security_event(db, &event).await;
+    crate::itsm::security_event(&event).await;
+}
+
+// 打洞时判断控制端用户是否持有设备的紧急访问授权
+pub async fn is_active(db: &EnterpriseDatabase, device_id: &str, user_id: &str) -> bool {
+    match db.get_active_emergency_access(device_id, user_id, crate::common::now()).await {
+        Ok(access) => access.is_some(),
+        Err(err) => {
+            log::error!("Failed to check emergency access of {}: {}", device_id, err);
+            false
+        }
+    }
+}
+
+// 提前结束紧急访问，仅启用人或超级管理员可以操作
+pub async fn end(db: &EnterpriseDatabase, id: &str, user_id: &str, is_super_admin: bool) -> ResultType<EmergencyAccess> {
+    let mut access = match db.get_emergency_access(id).await? {
+        Some(access) => access,
+        None => bail!("emergency access not found"),
+    };
+    if access.user_id != user_id && !is_super_admin {
+        bail!("only the requester or a super admin can end emergency access");
+    }
+    let now = crate::common::now();
+    if !access.is_active(now) {
+        bail!("emergency access already ended");
+    }
+    if !db.end_emergency_access(id, now).await? {
+        bail!("emergency access already ended");
+    }
+    access.ended_at = Some(now);
+    disconnect(&access).await;
+    Ok(access)
+}
+
+pub async fn review(db: &EnterpriseDatabase, id: &str, reviewer_id: &str, notes: &str) -> ResultType<EmergencyAccess> {
+    let mut access = match db.get_emergency_access(id).await? {
+        Some(access) => access,
+        None => bail!("emergency access not found"),
+    };
+    if access.user_id == reviewer_id {
+        bail!("requester cannot review own emergency access");
+    }
+    if access.is_reviewed() {
+        bail!("emergency access already reviewed");
+    }
+    let now = crate::common::now();
+    let notes = notes.trim();
+    if !db.review_emergency_access(id, reviewer_id, notes, now).await? {
+        bail!("emergency access already reviewed");
+    }
+    access.reviewed_by = Some(reviewer_id.to_owned());
+    access.reviewed_at = Some(now);
+    access.review_notes = Some(notes.to_owned());
+    Ok(access)
+}
+
+// 到期的授权标记为结束并断开相关会话，由信令服务器定时调用
+pub async fn expire(db: &EnterpriseDatabase) {
+    let expired = match db.list_expired_emergency_access(crate::common::now()).await {
+        Ok(expired) => expired,
+        Err(err) => {
+            log::error!("Failed to load expired emergency access: {}", err);
+            return;
+        }
+    };
+    for access in expired {
+        if let Err(err) = db.end_emergency_access(&access.id, access.expires_at).await {
+            log::error!("Failed to end emergency access {}: {}", access.id, err);
+            continue;
+        }
+        log::info!("Emergency access {} to {} by {} expired", access.id, access.device_id, access.username);
+        disconnect(&access).await;
+    }
+}
+
+// 断开授权期间建立的中继会话，授权前已存在的会话不受影响
+async fn disconnect(access: &EmergencyAccess) {
+    let since = UNIX_EPOCH + Duration::from_secs(access.created_at);
+    let killed = relay_sessions::kill_device_sessions_since(&access.device_id, since).await;
+    if killed > 0 {
+        log::info!("Disconnected {} relay sessions of emergency access {}", killed, access.id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_emergency_access() {
+        assert!(validate_justification("   outage   ").is_err());
+        assert!(validate_justification(&"x".repeat(MAX_JUSTIFICATION_LEN + 1)).is_err());
+        assert_eq!(
+            validate_justification("  Payroll server down, on-call DBA unreachable ").unwrap(),
+            "Payroll server down, on-call DBA unreachable"
+        );
+        // 按字符而非字节计算长度
+        assert!(validate_justification("生产数据库宕机需要紧急处理").is_err());
+
+        let mut access = EmergencyAccess {
+            id: "a".to_owned(),
+            user_id: "u1".to_owned(),
+            username: "alice".to_owned(),
+            device_id: "123".to_owned(),
+            justification: String::new(),
+            ip_address: String::new(),
+            created_at: 100,
+            expires_at: 100 + ACCESS_DURATION_SECS,
+            ended_at: None,
+            reviewed_by: None,
+            reviewed_at: None,
+            review_notes: None,
+        };
+        assert!(access.is_active(100 + ACCESS_DURATION_SECS - 1));
+        assert!(!access.is_active(100 + ACCESS_DURATION_SECS));
+        access.ended_at = Some(200);
+        assert!(!access.is_active(200));
+        assert!(!access.is_reviewed());
+    }
+}
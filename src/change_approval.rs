@@ -0,0 +1,522 @@
+// 变更审批模块 - 指定的关键设置(如中继策略、安全策略、备份保留)修改后不立即生效，先进入待审批队列:
+//   - 对应的 PUT /api/settings/* 请求返回 202 和待审批的变更，变更带逐字段差异，密钥类字段不显示取值
+//   - 由另一名有该设置修改权限的管理员批准后才写入；提交人可以撤回，其他管理员可以驳回
+//   - 代操作令牌提交的变更记录实际操作人为提交人，代操作令牌不能批准或驳回变更
+//   - 同一设置同时只能有一个待审批的变更；批准时若设置已被其他途径修改(如配置文件)，变更作废需重新提交
+//   - 启用后本模块自身的配置也需要审批，防止单人关闭审批
+// 配置文件 [policies] 在启动时直接写入，不经审批
+use crate::auth::{Actor, Claims};
+use crate::common::now;
+use crate::enterprise_database::EnterpriseDatabase;
+use crate::validation::{self, Payload};
+use crate::{
+    ad_sync, backup, content_scan, custom_fields, data_masking, dlp, dns_discovery, e2e_signaling, feature_flags,
+    federation, four_eyes, holiday_calendar, honeypot, id_policy, itsm, kiosk_fleet, lan_config, logging, mfa_policy,
+    network_tuning, offline_alerts, password_policy, pk_pinning, relay_policy, server_config, threat_intel,
+    transfer_bandwidth, trusted_device, turn, unattended_access, version_policy, web_security, web_session,
+    webrtc_signaling,
+};
+use hbb_common::{bail, log, tokio::sync::RwLock, ResultType};
+use schemars::JsonSchema;
+use serde_derive::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeSet, HashSet};
+use validator::Validate;
+
+pub const CHANGE_APPROVAL_KEY: &str = "change_approval";
+pub const MAX_BODY_SIZE: usize = 1024 * 1024;
+const MAX_COMMENT_LEN: usize = 2000;
+const MASK: &str = "******";
+// 字段名包含这些词时差异中不显示取值
+const SECRET_FIELDS: &[&str] = &["secret", "password", "token", "private_key", "api_key"];
+
+// 可纳入审批的设置: (设置接口路径, 设置名, 普通管理员能否修改)，权限与各设置接口一致
+const SETTINGS: &[(&str, &str, bool)] = &[
+    ("/api/settings/relay-policy", relay_policy::RELAY_POLICY_KEY, true),
+    ("/api/settings/logging", logging::LOGGING_KEY, false),
+    ("/api/settings/offline-alerts", offline_alerts::OFFLINE_ALERTS_KEY, true),
+    ("/api/settings/custom-fields", custom_fields::CUSTOM_FIELDS_KEY, true),
+    ("/api/settings/backup", backup::BACKUP_KEY, false),
+    (
+        "/api/settings/version-policy",
+        version_policy::VERSION_POLICY_KEY,
+        false,
+    ),
+    (
+        "/api/settings/unattended-access",
+        unattended_access::UNATTENDED_ACCESS_KEY,
+        true,
+    ),
+    ("/api/settings/four-eyes", four_eyes::FOUR_EYES_KEY, false),
+    ("/api/settings/lan", lan_config::LAN_CONFIG_KEY, true),
+    ("/api/settings/id-policy", id_policy::ID_POLICY_KEY, true),
+    ("/api/settings/mfa-policy", mfa_policy::MFA_POLICY_KEY, false),
+    ("/api/settings/e2e-policy", e2e_signaling::E2E_POLICY_KEY, false),
+    ("/api/settings/dlp-policy", dlp::DLP_POLICY_KEY, false),
+    (
+        "/api/settings/transfer-bandwidth",
+        transfer_bandwidth::TRANSFER_BANDWIDTH_KEY,
+        false,
+    ),
+    (
+        "/api/settings/network-tuning",
+        network_tuning::NETWORK_TUNING_KEY,
+        false,
+    ),
+    ("/api/settings/webrtc", webrtc_signaling::WEBRTC_KEY, false),
+    ("/api/settings/turn", turn::TURN_KEY, false),
+    ("/api/settings/honeypot", honeypot::HONEYPOT_KEY, false),
+    ("/api/settings/threat-intel", threat_intel::THREAT_INTEL_KEY, false),
+    ("/api/settings/pk-pinning", pk_pinning::PK_PINNING_KEY, false),
+    ("/api/settings/content-scan", content_scan::CONTENT_SCAN_KEY, false),
+    (
+        "/api/settings/trusted-device",
+        trusted_device::TRUSTED_DEVICE_KEY,
+        false,
+    ),
+    ("/api/settings/web-security", web_security::WEB_SECURITY_KEY, false),
+    ("/api/settings/feature-flags", feature_flags::FEATURE_FLAGS_KEY, false),
+    ("/api/settings/itsm", itsm::ITSM_KEY, false),
+    ("/api/settings/ad-sync", ad_sync::AD_SYNC_KEY, false),
+    ("/api/settings/web-session", web_session::WEB_SESSION_KEY, false),
+    ("/api/settings/data-masking", data_masking::DATA_MASKING_KEY, false),
+    (
+        "/api/settings/password-policies",
+        password_policy::PASSWORD_POLICIES_KEY,
+        true,
+    ),
+    ("/api/settings/federation", federation::FEDERATION_KEY, false),
+    ("/api/settings/dns-discovery", dns_discovery::DNS_DISCOVERY_KEY, false),
+    (
+        "/api/settings/holiday-calendars",
+        holiday_calendar::HOLIDAY_CALENDARS_KEY,
+        true,
+    ),
+    ("/api/settings/kiosk-fleet", kiosk_fleet::KIOSK_FLEET_KEY, true),
+    ("/api/settings/change-approval", CHANGE_APPROVAL_KEY, false),
+];
+
+lazy_static::lazy_static! {
+    static ref CONFIG: RwLock<ChangeApprovalConfig> = Default::default();
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, JsonSchema, Validate)]
+pub struct ChangeApprovalConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    // 需要审批的设置名，如 relay_policy、mfa_policy、backup
+    #[serde(default)]
+    #[validate(custom = "validation::non_empty_items")]
+    pub keys: Vec<String>,
+}
+
+impl Payload for ChangeApprovalConfig {}
+
+impl ChangeApprovalConfig {
+    pub fn validate(&self) -> ResultType<()> {
+        let mut seen = HashSet::new();
+        for key in self.keys.iter() {
+            if !SETTINGS.iter().any(|(_, k, _)| k == key) {
+                bail!("unsupported setting: {}", key);
+            }
+            if !seen.insert(key) {
+                bail!("duplicate setting: {}", key);
+            }
+        }
+        Ok(())
+    }
+
+    fn requires_approval(&self, key: &str) -> bool {
+        self.enabled && (key == CHANGE_APPROVAL_KEY || self.keys.iter().any(|k| k == key))
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub enum ChangeStatus {
+    #[default]
+    Pending,
+    Approved,
+    Rejected,
+    Cancelled,
+    // 批准后写入失败，或设置在审批前已被修改
+    Failed,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct FieldChange {
+    // 字段路径，如 servers 或 policy.max_sessions；整体替换时为空
+    pub path: String,
+    pub old: Option<Value>,
+    pub new: Option<Value>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct SettingChange {
+    pub id: String,
+    pub key: String,
+    // 提交时的设置值和待写入的值，可能含密钥，不通过接口返回
+    #[serde(skip)]
+    pub old_value: Option<String>,
+    #[serde(skip)]
+    pub new_value: String,
+    pub diff: Vec<FieldChange>,
+    pub requested_by: String,
+    pub requested_by_name: String,
+    pub requested_at: u64,
+    pub status: ChangeStatus,
+    pub reviewed_by: Option<String>,
+    pub reviewed_by_name: Option<String>,
+    pub reviewed_at: Option<u64>,
+    pub comment: Option<String>,
+}
+
+impl SettingChange {
+    pub fn is_pending(&self) -> bool {
+        self.status == ChangeStatus::Pending
+    }
+}
+
+pub async fn reload(db: &EnterpriseDatabase) -> ResultType<()> {
+    let config: ChangeApprovalConfig = match db.get_setting(CHANGE_APPROVAL_KEY).await? {
+        Some(v) => serde_json::from_str(&v)?,
+        None => ChangeApprovalConfig::default(),
+    };
+    config.validate()?;
+    *CONFIG.write().await = config;
+    Ok(())
+}
+
+pub async fn get() -> ChangeApprovalConfig {
+    CONFIG.read().await.clone()
+}
+
+pub async fn update(db: &EnterpriseDatabase, config: ChangeApprovalConfig, updated_by: &str) -> ResultType<()> {
+    config.validate()?;
+    db.set_setting(CHANGE_APPROVAL_KEY, &serde_json::to_string(&config)?, Some(updated_by))
+        .await?;
+    *CONFIG.write().await = config;
+    Ok(())
+}
+
+// 设置接口路径对应的设置名，不可纳入审批的接口返回 None
+pub fn key_for_path(path: &str) -> Option<&'static str> {
+    SETTINGS.iter().find(|(p, _, _)| *p == path).map(|(_, k, _)| *k)
+}
+
+pub async fn requires_approval(key: &str) -> bool {
+    CONFIG.read().await.requires_approval(key)
+}
+
+// 角色能否提交或审批该设置的变更
+pub fn can_change(role: &str, key: &str) -> bool {
+    match role {
+        "SuperAdmin" => true,
+        "Admin" => SETTINGS.iter().any(|(_, k, admin)| *k == key && *admin),
+        _ => false,
+    }
+}
+
+fn is_secret(path: &str) -> bool {
+    let field = path.rsplit('.').next().unwrap_or_default().to_lowercase();
+    SECRET_FIELDS.iter().any(|x| field.contains(x))
+}
+
+fn walk(old: Option<&Value>, new: Option<&Value>, path: &str, out: &mut Vec<FieldChange>) {
+    if let (Some(Value::Object(a)), Some(Value::Object(b))) = (old, new) {
+        let keys: BTreeSet<&String> = a.keys().chain(b.keys()).collect();
+        for k in keys {
+            let sub = if path.is_empty() {
+                k.clone()
+            } else {
+                format!("{}.{}", path, k)
+            };
+            walk(a.get(k), b.get(k), &sub, out);
+        }
+        return;
+    }
+    if old == new {
+        return;
+    }
+    if is_secret(path) {
+        // 提交掩码表示保留原密钥，不算变更
+        if new == Some(&Value::String(MASK.to_owned())) {
+            return;
+        }
+        let mask = |v: Option<&Value>| v.map(|_| Value::String(MASK.to_owned()));
+        out.push(FieldChange {
+            path: path.to_owned(),
+            old: mask(old),
+            new: mask(new),
+        });
+        return;
+    }
+    out.push(FieldChange {
+        path: path.to_owned(),
+        old: old.cloned(),
+        new: new.cloned(),
+    });
+}
+
+// 按字段比较两个设置值(None 表示未设置)，对象逐层展开，数组和其他值整体比较
+pub fn diff(old: Option<&str>, new: Option<&str>) -> Vec<FieldChange> {
+    let parse = |x: &str| serde_json::from_str(x).unwrap_or_else(|_| Value::String(x.to_owned()));
+    let old = old.map(parse);
+    let new = new.map(parse);
+    let mut out = Vec::new();
+    walk(old.as_ref(), new.as_ref(), "", &mut out);
+    out
+}
+
+// 代操作令牌的实际操作人，普通令牌为令牌对应的用户
+fn actor(claims: &Claims) -> (&str, &str) {
+    match &claims.act {
+        Some(Actor { sub, username }) => (sub.as_str(), username.as_str()),
+        None => (claims.sub.as_str(), claims.username.as_str()),
+    }
+}
+
+// 审批只能由提交人以外的管理员本人进行，否则超级管理员可代操作其他管理员批准自己的变更
+fn check_reviewer(change: &SettingChange, claims: &Claims, own: &str) -> ResultType<()> {
+    if claims.act.is_some() {
+        bail!("cannot review changes while impersonating another user");
+    }
+    if change.requested_by == claims.sub {
+        bail!("{}", own);
+    }
+    if !can_change(&claims.role, &change.key) {
+        bail!("permission denied");
+    }
+    Ok(())
+}
+
+fn check_comment(comment: &Option<String>) -> ResultType<()> {
+    if let Some(comment) = comment {
+        if comment.chars().count() > MAX_COMMENT_LEN {
+            bail!("comment must be at most {} characters", MAX_COMMENT_LEN);
+        }
+    }
+    Ok(())
+}
+
+pub async fn submit(db: &EnterpriseDatabase, key: &str, value: Value, claims: &Claims) -> ResultType<SettingChange> {
+    if !can_change(&claims.role, key) {
+        bail!("permission denied");
+    }
+    server_config::check_policy(key, &value)?;
+    if db.pending_setting_change(key).await?.is_some() {
+        bail!("a change to {} is already pending", key);
+    }
+    let old_value = db.get_setting(key).await?;
+    let new_value = serde_json::to_string(&value)?;
+    let diff = diff(old_value.as_deref(), Some(&new_value));
+    if diff.is_empty() {
+        bail!("no changes");
+    }
+    let (requested_by, requested_by_name) = actor(claims);
+    let change = SettingChange {
+        id: uuid::Uuid::new_v4().to_string(),
+        key: key.to_owned(),
+        old_value,
+        new_value,
+        diff,
+        requested_by: requested_by.to_owned(),
+        requested_by_name: requested_by_name.to_owned(),
+        requested_at: now(),
+        ..Default::default()
+    };
+    db.save_setting_change(&change).await?;
+    log::info!(
+        "{} submitted change {} to {} for approval",
+        change.requested_by_name,
+        change.id,
+        change.key
+    );
+    Ok(change)
+}
+
+async fn pending(db: &EnterpriseDatabase, id: &str) -> ResultType<SettingChange> {
+    match db.get_setting_change(id).await? {
+        Some(change) if change.is_pending() => Ok(change),
+        Some(_) => bail!("change is not pending"),
+        None => bail!("change not found"),
+    }
+}
+
+async fn finish(
+    db: &EnterpriseDatabase,
+    id: &str,
+    from: ChangeStatus,
+    to: ChangeStatus,
+    claims: &Claims,
+    comment: Option<String>,
+) -> ResultType<SettingChange> {
+    let (reviewed_by, reviewed_by_name) = actor(claims);
+    if !db
+        .set_setting_change_status(id, from, to, reviewed_by, reviewed_by_name, now(), comment)
+        .await?
+    {
+        bail!("change is not pending");
+    }
+    match db.get_setting_change(id).await? {
+        Some(change) => Ok(change),
+        None => bail!("change not found"),
+    }
+}
+
+// 由另一名管理员批准并写入设置
+pub async fn approve(
+    db: &EnterpriseDatabase,
+    id: &str,
+    claims: &Claims,
+    comment: Option<String>,
+) -> ResultType<SettingChange> {
+    check_comment(&comment)?;
+    let change = pending(db, id).await?;
+    check_reviewer(&change, claims, "cannot approve your own change")?;
+    if db.get_setting(&change.key).await? != change.old_value {
+        let note = Some("setting was modified after the change was submitted".to_owned());
+        finish(db, id, ChangeStatus::Pending, ChangeStatus::Failed, claims, note).await?;
+        bail!(
+            "{} was modified after the change was submitted, please resubmit",
+            change.key
+        );
+    }
+    let change = finish(db, id, ChangeStatus::Pending, ChangeStatus::Approved, claims, comment).await?;
+    let value = serde_json::from_str(&change.new_value)?;
+    if let Err(err) = server_config::apply_policy(db, &change.key, value, &change.requested_by).await {
+        log::error!(
+            "Failed to apply approved change {} to {}: {}",
+            change.id,
+            change.key,
+            err
+        );
+        let note = Some(format!("failed to apply: {}", err));
+        finish(db, id, ChangeStatus::Approved, ChangeStatus::Failed, claims, note).await?;
+        return Err(err);
+    }
+    log::info!(
+        "{} approved change {} to {} submitted by {}",
+        claims.username,
+        change.id,
+        change.key,
+        change.requested_by_name
+    );
+    Ok(change)
+}
+
+pub async fn reject(
+    db: &EnterpriseDatabase,
+    id: &str,
+    claims: &Claims,
+    comment: Option<String>,
+) -> ResultType<SettingChange> {
+    check_comment(&comment)?;
+    let change = pending(db, id).await?;
+    check_reviewer(&change, claims, "use cancel to withdraw your own change")?;
+    finish(db, id, ChangeStatus::Pending, ChangeStatus::Rejected, claims, comment).await
+}
+
+// 提交人撤回自己的变更
+pub async fn cancel(db: &EnterpriseDatabase, id: &str, claims: &Claims) -> ResultType<SettingChange> {
+    let change = pending(db, id).await?;
+    if change.requested_by != actor(claims).0 {
+        bail!("only the submitter can cancel a change");
+    }
+    finish(db, id, ChangeStatus::Pending, ChangeStatus::Cancelled, claims, None).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate() {
+        let mut config = ChangeApprovalConfig {
+            enabled: true,
+            keys: vec![relay_policy::RELAY_POLICY_KEY.to_owned(), backup::BACKUP_KEY.to_owned()],
+        };
+        assert!(config.validate().is_ok());
+        assert!(config.requires_approval(backup::BACKUP_KEY));
+        assert!(config.requires_approval(CHANGE_APPROVAL_KEY));
+        assert!(!config.requires_approval(logging::LOGGING_KEY));
+        config.keys.push(backup::BACKUP_KEY.to_owned());
+        assert!(config.validate().is_err());
+        config.keys.pop();
+        config.keys.push("jwt_secret".to_owned());
+        assert!(config.validate().is_err());
+        config.keys.pop();
+        config.enabled = false;
+        assert!(!config.requires_approval(CHANGE_APPROVAL_KEY));
+    }
+
+    #[test]
+    fn test_permissions() {
+        assert_eq!(key_for_path("/api/settings/lan"), Some(lan_config::LAN_CONFIG_KEY));
+        assert_eq!(key_for_path("/api/settings/email-otp"), None);
+        assert!(can_change("Admin", relay_policy::RELAY_POLICY_KEY));
+        assert!(!can_change("Admin", mfa_policy::MFA_POLICY_KEY));
+        assert!(can_change("SuperAdmin", mfa_policy::MFA_POLICY_KEY));
+        assert!(!can_change("Auditor", relay_policy::RELAY_POLICY_KEY));
+    }
+
+    #[test]
+    fn test_diff() {
+        let old = r#"{"servers":["a:21117"],"policy":{"max":1,"secret":"x"},"gone":true}"#;
+        let new = r#"{"servers":["a:21117","b:21117"],"policy":{"max":1,"secret":"y"},"added":1}"#;
+        let diff = diff(Some(old), Some(new));
+        let paths: Vec<&str> = diff.iter().map(|x| x.path.as_str()).collect();
+        assert_eq!(paths, vec!["added", "gone", "policy.secret", "servers"]);
+        assert_eq!(diff[0].old, None);
+        assert_eq!(diff[1].new, None);
+        assert_eq!(diff[2].new, Some(Value::String(MASK.to_owned())));
+        let unchanged = r#"{"servers":["a:21117"],"policy":{"max":1,"secret":"******"},"gone":true}"#;
+        assert!(super::diff(Some(old), Some(unchanged)).is_empty());
+        assert_eq!(super::diff(None, Some("{}")).len(), 1);
+        assert_eq!(super::diff(Some("{}"), None).len(), 1);
+    }
+
+    fn claims(sub: &str, role: &str, act: Option<&str>) -> Claims {
+        Claims {
+            sub: sub.to_owned(),
+            username: sub.to_owned(),
+            role: role.to_owned(),
+            groups: vec![],
+            exp: 0,
+            iat: 0,
+            jti: String::new(),
+            scope: None,
+            act: act.map(|x| Actor {
+                sub: x.to_owned(),
+                username: x.to_owned(),
+            }),
+        }
+    }
+
+    #[hbb_common::tokio::test]
+    async fn test_impersonation_cannot_review() {
+        let db = EnterpriseDatabase::memory().await.unwrap();
+        let key = relay_policy::RELAY_POLICY_KEY;
+        let root = claims("root", "SuperAdmin", None);
+        // root 代操作管理员 alice
+        let impersonated = claims("alice", "Admin", Some("root"));
+
+        // 不能代操作另一名管理员批准自己提交的变更
+        let change = submit(&db, key, serde_json::json!({"force_relay_devices": ["1"]}), &root)
+            .await
+            .unwrap();
+        assert!(approve(&db, &change.id, &impersonated, None).await.is_err());
+        assert!(reject(&db, &change.id, &impersonated, None).await.is_err());
+        assert!(pending(&db, &change.id).await.is_ok());
+        assert_eq!(cancel(&db, &change.id, &impersonated).await.unwrap().status, ChangeStatus::Cancelled);
+
+        // 代操作时提交的变更记录实际操作人，本人不能批准
+        let change = submit(&db, key, serde_json::json!({"force_relay_devices": ["2"]}), &impersonated)
+            .await
+            .unwrap();
+        assert_eq!((change.requested_by.as_str(), change.requested_by_name.as_str()), ("root", "root"));
+        assert!(approve(&db, &change.id, &root, None).await.is_err());
+        let change = approve(&db, &change.id, &claims("bob", "Admin", None), None).await.unwrap();
+        assert_eq!(change.status, ChangeStatus::Approved);
+        assert_eq!(change.reviewed_by.as_deref(), Some("bob"));
+    }
+}
@@ -0,0 +1,273 @@
+// 编码推荐模块 - 根据中继会话最近的 QoS 采样，由 PerformanceOptimizer 计算推荐的编码器、码率和帧率，
+// 通过心跳下发给会话两端的客户端。推荐变化时重新下发，客户端应用(或因不支持而拒绝)后
+// 通过 /api/sessions/:id/codec/ack 回执实际使用的参数，未回执的推荐在每次心跳中重复下发。
+// 推荐按(会话, 设备)保存在 codec_recommendations 表，管理员据此检查客户端是否遵循推荐。
+// 直连会话服务器不知道对端设备，不做推荐。编码格式按设备上报的编码器能力选择(见 device_capabilities)
+use crate::connection_quality::QosSample;
+use crate::device_capabilities::DeviceCapabilities;
+use crate::enterprise_database::EnterpriseDatabase;
+use crate::performance_optimization::{CodecConfig, CodecType, NetworkConditions, PerformanceOptimizer};
+use crate::relay_sessions;
+use hbb_common::{log, ResultType};
+use schemars::JsonSchema;
+use serde_derive::{Deserialize, Serialize};
+
+// 只按最近这段时间的采样推荐
+const SAMPLE_WINDOW_SECS: u64 = 300;
+// 码率按该粒度取整，避免采样的小幅波动导致反复下发
+const BITRATE_STEP_KBPS: u32 = 100;
+const RETENTION_SECS: u64 = 30 * 86400;
+const LOW_LOSS_RATE: f64 = 0.01;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct ClientCodecRecommendation {
+    pub session_id: String,
+    pub codec: CodecType,
+    pub bitrate_kbps: u32,
+    pub framerate: u32,
+    // 1-100
+    pub quality: u32,
+    pub low_latency: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct CodecRecommendation {
+    pub session_id: String,
+    pub device_id: String,
+    pub codec: CodecType,
+    pub bitrate_kbps: u32,
+    pub framerate: u32,
+    pub quality: u32,
+    pub low_latency: bool,
+    pub issued_at: u64,
+    // 客户端回执，未回执时为空
+    pub honored: Option<bool>,
+    pub applied_codec: Option<CodecType>,
+    pub applied_bitrate_kbps: Option<u32>,
+    pub applied_framerate: Option<u32>,
+    pub acked_at: Option<u64>,
+}
+
+impl CodecRecommendation {
+    fn to_client(&self) -> ClientCodecRecommendation {
+        ClientCodecRecommendation {
+            session_id: self.session_id.clone(),
+            codec: self.codec.clone(),
+            bitrate_kbps: self.bitrate_kbps,
+            framerate: self.framerate,
+            quality: self.quality,
+            low_latency: self.low_latency,
+        }
+    }
+}
+
+// 客户端回执，honored 为 false 时 codec 等为客户端实际使用的参数
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+pub struct CodecAck {
+    pub honored: bool,
+    #[serde(default)]
+    pub codec: Option<CodecType>,
+    #[serde(default)]
+    pub bitrate_kbps: Option<u32>,
+    #[serde(default)]
+    pub framerate: Option<u32>,
+}
+
+fn mean(values: impl Iterator<Item = f64>) -> f64 {
+    let (sum, n) = values.fold((0.0, 0usize), |(sum, n), v| (sum + v, n + 1));
+    if n == 0 {
+        0.0
+    } else {
+        sum / n as f64
+    }
+}
+
+// 带宽取窗口内观测到的最高码率，平均码率受画面变化影响会低估可用带宽；
+// 几乎没有丢包时按观测值上浮 25%，否则码率降下来之后就再也回不去
+pub fn conditions(samples: &[QosSample]) -> Option<NetworkConditions> {
+    if samples.is_empty() {
+        return None;
+    }
+    let latency = mean(samples.iter().map(|s| s.latency_ms as f64));
+    let jitter = mean(samples.iter().map(|s| (s.latency_ms as f64 - latency).abs()));
+    let loss = mean(samples.iter().map(|s| s.packet_loss)) / 100.0;
+    let mut bandwidth = samples.iter().map(|s| s.bitrate_kbps).max().unwrap_or_default();
+    if loss < LOW_LOSS_RATE {
+        bandwidth = bandwidth.saturating_add(bandwidth / 4);
+    }
+    Some(NetworkConditions {
+        latency_ms: latency,
+        bandwidth_kbps: bandwidth,
+        packet_loss_rate: loss,
+        jitter_ms: jitter,
+    })
+}
+
+pub async fn recommend(
+    session_id: &str,
+    conditions: &NetworkConditions,
+    capabilities: Option<&DeviceCapabilities>,
+) -> ClientCodecRecommendation {
+    let config: CodecConfig = PerformanceOptimizer::new()
+        .get_optimal_codec_config(session_id, conditions, capabilities)
+        .await;
+    ClientCodecRecommendation {
+        session_id: session_id.to_owned(),
+        codec: config.codec_type,
+        bitrate_kbps: (config.bitrate / BITRATE_STEP_KBPS).max(1) * BITRATE_STEP_KBPS,
+        framerate: config.framerate as u32,
+        quality: config.quality as u32,
+        low_latency: config.low_latency_mode,
+    }
+}
+
+// 心跳时下发本设备所在中继会话的推荐：推荐有变化或上次推荐尚未回执时下发
+pub async fn for_device(db: &EnterpriseDatabase, device_id: &str) -> ResultType<Vec<ClientCodecRecommendation>> {
+    let now = crate::common::now();
+    let capabilities = db.get_device_capabilities(device_id).await?;
+    let mut res = Vec::new();
+    for session in relay_sessions::sessions_of(device_id).await {
+        let samples: Vec<QosSample> = db
+            .list_session_qos_samples(&session.uuid)
+            .await?
+            .into_iter()
+            .filter(|s| s.reported_at + SAMPLE_WINDOW_SECS >= now)
+            .collect();
+        let conditions = match conditions(&samples) {
+            Some(conditions) => conditions,
+            None => continue,
+        };
+        let recommendation = recommend(&session.uuid, &conditions, capabilities.as_ref()).await;
+        match db.get_codec_recommendation(&session.uuid, device_id).await? {
+            Some(last) if last.to_client() == recommendation => {
+                if last.acked_at.is_none() {
+                    res.push(recommendation);
+                }
+            }
+            _ => {
+                db.save_codec_recommendation(&CodecRecommendation {
+                    session_id: session.uuid.clone(),
+                    device_id: device_id.to_owned(),
+                    codec: recommendation.codec.clone(),
+                    bitrate_kbps: recommendation.bitrate_kbps,
+                    framerate: recommendation.framerate,
+                    quality: recommendation.quality,
+                    low_latency: recommendation.low_latency,
+                    issued_at: now,
+                    honored: None,
+                    applied_codec: None,
+                    applied_bitrate_kbps: None,
+                    applied_framerate: None,
+                    acked_at: None,
+                })
+                .await?;
+                log::info!(
+                    "Recommend {:?} {}kbps {}fps to {} in session {}",
+                    recommendation.codec,
+                    recommendation.bitrate_kbps,
+                    recommendation.framerate,
+                    device_id,
+                    session.uuid
+                );
+                res.push(recommendation);
+            }
+        }
+    }
+    Ok(res)
+}
+
+// 保存客户端回执，没有对应推荐时返回 false
+pub async fn ack(db: &EnterpriseDatabase, session_id: &str, device_id: &str, ack: CodecAck) -> ResultType<bool> {
+    let mut recommendation = match db.get_codec_recommendation(session_id, device_id).await? {
+        Some(recommendation) => recommendation,
+        None => return Ok(false),
+    };
+    if !ack.honored {
+        log::info!(
+            "{} did not apply codec recommendation of session {}: {:?} {:?}kbps {:?}fps",
+            device_id,
+            session_id,
+            ack.codec,
+            ack.bitrate_kbps,
+            ack.framerate
+        );
+    }
+    recommendation.honored = Some(ack.honored);
+    recommendation.applied_codec = ack.codec;
+    recommendation.applied_bitrate_kbps = ack.bitrate_kbps;
+    recommendation.applied_framerate = ack.framerate;
+    recommendation.acked_at = Some(crate::common::now());
+    db.save_codec_recommendation(&recommendation).await?;
+    Ok(true)
+}
+
+pub async fn prune(db: &EnterpriseDatabase) {
+    let before = crate::common::now().saturating_sub(RETENTION_SECS);
+    if let Err(e) = db.prune_codec_recommendations(before).await {
+        log::error!("Failed to prune codec recommendations: {}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(latency_ms: u32, packet_loss: f64, bitrate_kbps: u32) -> QosSample {
+        QosSample {
+            session_id: "s1".to_owned(),
+            device_id: "123".to_owned(),
+            relay_server: "relay".to_owned(),
+            latency_ms,
+            packet_loss,
+            bitrate_kbps,
+            reported_at: 0,
+        }
+    }
+
+    #[test]
+    fn test_conditions() {
+        assert!(conditions(&[]).is_none());
+        let c = conditions(&[sample(40, 1.0, 800), sample(60, 3.0, 1200)]).unwrap();
+        assert_eq!(c.latency_ms, 50.0);
+        assert_eq!(c.jitter_ms, 10.0);
+        assert_eq!(c.bandwidth_kbps, 1200);
+        assert_eq!(c.packet_loss_rate, 0.02);
+    }
+
+    #[tokio::test]
+    async fn test_recommend() {
+        // 低带宽、高延迟
+        let c = conditions(&[sample(150, 0.0, 600)]).unwrap();
+        let r = recommend("s1", &c, None).await;
+        assert!(r.low_latency);
+        // 没有丢包，按 600 * 1.25 估计带宽，再取 80%
+        assert_eq!(r.bitrate_kbps, 600);
+        assert!(r.framerate <= 24);
+        // 高丢包时使用容错更好的 H264
+        let c = conditions(&[sample(20, 8.0, 5000)]).unwrap();
+        assert_eq!(recommend("s1", &c, None).await.codec, CodecType::H264);
+    }
+
+    #[tokio::test]
+    async fn test_recommend_by_capabilities() {
+        let caps = |encoders: &str| DeviceCapabilities {
+            device_id: "123".to_owned(),
+            encoders: serde_json::from_str(encoders).unwrap(),
+            updated_at: 0,
+        };
+        let c = conditions(&[sample(20, 0.0, 5000)]).unwrap();
+        // 未上报能力的设备不推荐 H265
+        assert_eq!(recommend("s1", &c, None).await.codec, CodecType::H264);
+        // 有硬件 H265 编码器时使用高码率配置
+        let nvenc = caps(r#"[{"codec": "H264", "hardware": "nvenc"}, {"codec": "H265", "hardware": "nvenc"}]"#);
+        let r = recommend("s1", &c, Some(&nvenc)).await;
+        assert_eq!(r.codec, CodecType::H265);
+        assert_eq!(r.framerate, 60);
+        // 只有软件 H265 时不推荐
+        let software = caps(r#"[{"codec": "H264"}, {"codec": "H265"}]"#);
+        assert_eq!(recommend("s1", &c, Some(&software)).await.codec, CodecType::H264);
+        // 不支持 H264 的设备换成它支持的格式
+        let vp9 = caps(r#"[{"codec": "VP9"}]"#);
+        assert_eq!(recommend("s1", &c, Some(&vp9)).await.codec, CodecType::VP9);
+    }
+}
@@ -1,6 +1,6 @@
 use clap::App;
 use hbb_common::{
-    allow_err, anyhow::{Context, Result}, get_version_number, log, tokio, ResultType
+    allow_err, anyhow::{Context, Result}, bail, get_version_number, log, tokio, ResultType
 };
 use ini::Ini;
 use sodiumoxide::crypto::sign;
@@ -8,6 +8,7 @@ use std::{
     io::prelude::*,
     io::Read,
     net::SocketAddr,
+    sync::atomic::{AtomicBool, Ordering},
     time::{Instant, SystemTime},
 };
 
@@ -67,7 +68,8 @@ pub fn init_args(args: &str, name: &str, about: &str) {
                 .for_each(|(k, v)| std::env::set_var(arg_name(k), v));
         }
     }
-    if let Some(config) = matches.value_of("config") {
+    // TOML 配置文件由企业版单独加载
+    if let Some(config) = matches.value_of("config").filter(|x| !x.ends_with(".toml")) {
         if let Ok(v) = Ini::load_from_file(config) {
             if let Some(section) = v.section(None::<String>) {
                 section
@@ -104,6 +106,135 @@ pub fn now() -> u64 {
         .unwrap_or_default()
 }
 
+// 管理界面的对外链接，PUBLIC-URL 如 https://rd.example.com:21114
+#[allow(dead_code)]
+pub fn public_url(path: &str) -> ResultType<String> {
+    let base = get_arg("public-url");
+    if base.is_empty() {
+        bail!("PUBLIC-URL is not configured");
+    }
+    Ok(format!("{}{}", base.trim_end_matches('/'), path))
+}
+
+// 解析 HH:MM 为当天分钟数
+#[allow(dead_code)]
+pub fn parse_minutes(time: &str) -> ResultType<u32> {
+    if let Some((h, m)) = time.split_once(':') {
+        if let (Ok(h), Ok(m)) = (h.trim().parse::<u32>(), m.trim().parse::<u32>()) {
+            if h < 24 && m < 60 {
+                return Ok(h * 60 + m);
+            }
+        }
+    }
+    bail!("invalid time {}, expected HH:MM", time)
+}
+
+// 判断时间是否落在时段内；days 中 0 为周日，为空表示每天，end 早于 start 时跨天
+#[allow(dead_code)]
+pub fn in_time_window(days: &[u8], start: &str, end: &str, weekday: u8, minutes: u32) -> bool {
+    let (start, end) = match (parse_minutes(start), parse_minutes(end)) {
+        (Ok(start), Ok(end)) => (start, end),
+        _ => return false,
+    };
+    if start <= end {
+        (days.is_empty() || days.contains(&weekday)) && minutes >= start && minutes < end
+    } else if minutes >= start {
+        days.is_empty() || days.contains(&weekday)
+    } else if minutes < end {
+        // 跨天时段的后半段属于前一天的时段
+        days.is_empty() || days.contains(&((weekday + 6) % 7))
+    } else {
+        false
+    }
+}
+
+// TCP keepalive 参数: 空闲 idle 秒后开始探测，每 interval 秒一次，连续 retries 次无响应即断开
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Keepalive {
+    pub idle: u32,
+    pub interval: u32,
+    pub retries: u32,
+}
+
+impl Keepalive {
+    // 解析 idle[,interval[,retries]]，如 60,15,4，省略的部分取 15 秒和 4 次
+    #[allow(dead_code)]
+    pub fn parse(s: &str) -> ResultType<Self> {
+        let mut values = [0u32, 15, 4];
+        let parts: Vec<&str> = s.split(',').collect();
+        if parts.len() > values.len() {
+            bail!("invalid keepalive {}, expected idle[,interval[,retries]]", s);
+        }
+        for (i, part) in parts.iter().enumerate() {
+            values[i] = part
+                .trim()
+                .parse()
+                .with_context(|| format!("invalid keepalive {}, expected idle[,interval[,retries]]", s))?;
+        }
+        let keepalive = Self {
+            idle: values[0],
+            interval: values[1],
+            retries: values[2],
+        };
+        keepalive.validate()?;
+        Ok(keepalive)
+    }
+
+    pub fn validate(&self) -> ResultType<()> {
+        if self.idle == 0 || self.idle > 7200 {
+            bail!("keepalive idle must be 1-7200 seconds");
+        }
+        if self.interval == 0 || self.interval > 600 {
+            bail!("keepalive interval must be 1-600 seconds");
+        }
+        if self.retries == 0 || self.retries > 20 {
+            bail!("keepalive retries must be 1-20");
+        }
+        Ok(())
+    }
+}
+
+// 在已接受的连接上开启 TCP keepalive，只支持 Linux
+#[cfg(target_os = "linux")]
+pub fn set_keepalive(stream: &tokio::net::TcpStream, keepalive: &Keepalive) -> std::io::Result<()> {
+    use std::os::{
+        fd::AsRawFd,
+        raw::{c_int, c_void},
+    };
+    const SOL_SOCKET: c_int = 1;
+    const SO_KEEPALIVE: c_int = 9;
+    const IPPROTO_TCP: c_int = 6;
+    const TCP_KEEPIDLE: c_int = 4;
+    const TCP_KEEPINTVL: c_int = 5;
+    const TCP_KEEPCNT: c_int = 6;
+    extern "C" {
+        fn setsockopt(fd: c_int, level: c_int, name: c_int, value: *const c_void, len: u32) -> c_int;
+    }
+
+    let options = [
+        (SOL_SOCKET, SO_KEEPALIVE, 1),
+        (IPPROTO_TCP, TCP_KEEPIDLE, keepalive.idle as c_int),
+        (IPPROTO_TCP, TCP_KEEPINTVL, keepalive.interval as c_int),
+        (IPPROTO_TCP, TCP_KEEPCNT, keepalive.retries as c_int),
+    ];
+    for (level, name, value) in options {
+        let value: c_int = value;
+        let len = std::mem::size_of::<c_int>() as u32;
+        if unsafe { setsockopt(stream.as_raw_fd(), level, name, &value as *const c_int as *const c_void, len) } < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn set_keepalive(_stream: &tokio::net::TcpStream, _keepalive: &Keepalive) -> std::io::Result<()> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "TCP keepalive tuning requires Linux",
+    ))
+}
+
 pub fn gen_sk(wait: u64) -> (String, Option<sign::SecretKey>) {
     let sk_file = "id_ed25519";
     if wait > 0 && !std::path::Path::new(sk_file).exists() {
@@ -215,4 +346,73 @@ async fn check_software_update_() -> hbb_common::ResultType<()> {
        log::info!("new version is available: {}", latest_release_version);
     }
     Ok(())
-}
\ No newline at end of file
+}
+// 日志输出格式，LOG_FORMAT=json 时输出每行一个 JSON 对象，便于直接导入 Loki/ELK
+static JSON_LOG: AtomicBool = AtomicBool::new(false);
+
+lazy_static::lazy_static! {
+    static ref LOGGER: std::sync::Mutex<Option<flexi_logger::LoggerHandle>> = Default::default();
+}
+
+tokio::task_local! {
+    // Web 接口请求ID，在请求处理过程中输出的日志会带上该ID
+    pub static REQUEST_ID: String;
+}
+
+pub fn init_logger() -> ResultType<()> {
+    use flexi_logger::{Logger, WriteMode};
+    JSON_LOG.store(
+        std::env::var("LOG_FORMAT").map(|x| x.eq_ignore_ascii_case("json")).unwrap_or(false),
+        Ordering::SeqCst,
+    );
+    let handle = Logger::try_with_env_or_str("info")?
+        .log_to_stdout()
+        .format(log_format)
+        .write_mode(WriteMode::Async)
+        .start()?;
+    *LOGGER.lock().unwrap() = Some(handle);
+    Ok(())
+}
+
+#[allow(dead_code)]
+pub fn is_json_log() -> bool {
+    JSON_LOG.load(Ordering::SeqCst)
+}
+
+#[allow(dead_code)]
+pub fn set_json_log(json: bool) {
+    JSON_LOG.store(json, Ordering::SeqCst);
+}
+
+// 运行时调整日志级别，spec 格式同 RUST_LOG，如 "info,hbbs::web_api=debug"
+#[allow(dead_code)]
+pub fn set_log_spec(spec: &str) -> ResultType<()> {
+    match LOGGER.lock().unwrap().as_mut() {
+        Some(handle) => handle.parse_new_spec(spec)?,
+        None => bail!("logger is not initialized"),
+    }
+    Ok(())
+}
+
+fn log_format(
+    w: &mut dyn std::io::Write,
+    now: &mut flexi_logger::DeferredNow,
+    record: &log::Record,
+) -> std::io::Result<()> {
+    if !JSON_LOG.load(Ordering::Relaxed) {
+        return flexi_logger::opt_format(w, now, record);
+    }
+    let mut line = serde_json::json!({
+        "ts": chrono::Local::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, false),
+        "level": record.level().as_str(),
+        "target": record.target(),
+        "msg": record.args().to_string(),
+    });
+    if let (Some(file), Some(n)) = (record.file(), record.line()) {
+        line["file"] = format!("{}:{}", file, n).into();
+    }
+    if let Ok(id) = REQUEST_ID.try_with(|id| id.clone()) {
+        line["request_id"] = id.into();
+    }
+    serde_json::to_writer(w, &line).map_err(std::io::Error::from)
+}
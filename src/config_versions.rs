@@ -0,0 +1,127 @@
+// 配置版本模块 - 每次策略设置的修改(设置接口、配置文件、变更审批)都记录为一个配置版本，
+// 版本号全局递增，某个版本的配置快照即各策略在该版本时的取值:
+//   - 可按版本比较两个快照的逐字段差异，密钥类字段不显示取值
+//   - 可回滚到历史版本: 所有不同的策略在一个事务中写回(当时未设置的策略恢复默认)，再刷新运行时配置，
+//     回滚本身也记录为新版本，可以再次回滚
+// 版本记录从启用本功能起开始，已有的策略设置作为首个版本；需要审批的设置不能通过回滚修改
+use crate::change_approval::{self, FieldChange};
+use crate::enterprise_database::EnterpriseDatabase;
+use crate::server_config;
+use hbb_common::{bail, log, ResultType};
+use schemars::JsonSchema;
+use serde_derive::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct ConfigVersion {
+    pub version: i64,
+    pub key: String,
+    pub updated_by: Option<String>,
+    pub created_at: u64,
+    // 该版本将策略恢复为默认(回滚到策略未设置时的版本)
+    pub deleted: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct SettingDiff {
+    pub key: String,
+    pub changes: Vec<FieldChange>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct RollbackReport {
+    // 回滚到的版本
+    pub version: i64,
+    // 回滚后的最新版本
+    pub latest: i64,
+    pub keys: Vec<String>,
+}
+
+// 两个快照中取值不同的策略，new 中没有的策略为 None
+fn changed(old: &BTreeMap<String, String>, new: &BTreeMap<String, String>) -> Vec<(String, Option<String>)> {
+    let keys: BTreeSet<&String> = old.keys().chain(new.keys()).collect();
+    keys.into_iter()
+        .filter(|k| old.get(*k) != new.get(*k))
+        .map(|k| (k.clone(), new.get(k).cloned()))
+        .collect()
+}
+
+async fn check_version(db: &EnterpriseDatabase, version: i64) -> ResultType<i64> {
+    let latest = db.latest_setting_version().await?.unwrap_or(0);
+    if version < 1 || version > latest {
+        bail!("version {} not found", version);
+    }
+    Ok(latest)
+}
+
+// from 到 to(默认最新版本)之间的配置差异
+pub async fn diff(db: &EnterpriseDatabase, from: i64, to: Option<i64>) -> ResultType<Vec<SettingDiff>> {
+    let latest = check_version(db, from).await?;
+    let to = to.unwrap_or(latest);
+    check_version(db, to).await?;
+    let old = db.setting_snapshot(from).await?;
+    let new = db.setting_snapshot(to).await?;
+    Ok(changed(&old, &new)
+        .into_iter()
+        .map(|(key, value)| SettingDiff {
+            changes: change_approval::diff(old.get(&key).map(|x| x.as_str()), value.as_deref()),
+            key,
+        })
+        .filter(|x| !x.changes.is_empty())
+        .collect())
+}
+
+pub async fn rollback(db: &EnterpriseDatabase, version: i64, by: &str) -> ResultType<RollbackReport> {
+    let latest = check_version(db, version).await?;
+    let current = db.setting_snapshot(latest).await?;
+    let target = db.setting_snapshot(version).await?;
+    let changes = changed(&current, &target);
+    if changes.is_empty() {
+        bail!("configuration is already at version {}", version);
+    }
+    for (key, value) in changes.iter() {
+        if change_approval::requires_approval(key).await {
+            bail!("{} requires approval and cannot be rolled back", key);
+        }
+        if let Some(value) = value {
+            if let Err(e) = server_config::check_policy(key, &serde_json::from_str(value)?) {
+                bail!("{}: {}", key, e);
+            }
+        }
+    }
+    let latest = db.restore_settings(&changes, by).await?;
+    let keys: Vec<String> = changes.into_iter().map(|(key, _)| key).collect();
+    for key in keys.iter() {
+        if let Err(err) = server_config::reload_policy(db, key, by).await {
+            log::error!("Failed to reload {} after rollback: {}", key, err);
+        }
+    }
+    log::info!("{} rolled back configuration to version {}: {:?}", by, version, keys);
+    Ok(RollbackReport { version, latest, keys })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_changed() {
+        let old: BTreeMap<String, String> = [("a", "1"), ("b", "2")]
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        let new: BTreeMap<String, String> = [("b", "3"), ("c", "4")]
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        assert_eq!(
+            changed(&old, &new),
+            vec![
+                ("a".to_owned(), None),
+                ("b".to_owned(), Some("3".to_owned())),
+                ("c".to_owned(), Some("4".to_owned()))
+            ]
+        );
+        assert!(changed(&old, &old).is_empty());
+    }
+}
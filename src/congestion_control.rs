@@ -0,0 +1,528 @@
+// 拥塞控制模块 - 中继会话的发送节奏控制。中继转发的是 TCP 流，Cubic/Reno/Vegas 这类按丢包或延迟
+// 调整窗口的算法由操作系统协议栈实现，选择它们时中继通过 TCP_CONGESTION 设置到会话两端的套接字上
+// (只支持 Linux，内核需加载对应模块)；BBR 在用户态实现，按估计的瓶颈带宽匀速发送，避免突发数据在
+// 下游缓冲区中堆积排队:
+//   带宽估计  每 200ms 为一轮，用本轮转发的字节数计算投递速率，取最近 10 轮的最大值作为瓶颈带宽。
+//             本轮既没有被下游阻塞、也没有等待发送节奏时是应用受限的采样，只在高于当前估计时采用；
+//             被下游阻塞的轮次说明下游缓冲区已满，此时的投递速率就是瓶颈带宽，直接替换估计并排空两轮
+//   发送节奏  按 增益 × 瓶颈带宽 发送，启动阶段增益 2.89 快速探测，带宽连续 3 轮增长不足 25% 后排空，
+//             之后按 [1.25, 0.75, 1, 1, 1, 1, 1, 1] 逐轮循环，周期性地探测更高的带宽
+use hbb_common::{log, tokio::time::sleep};
+use serde_derive::{Deserialize, Serialize};
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+const ROUND_US: u64 = 200_000;
+const BW_WINDOW_ROUNDS: usize = 10;
+const STARTUP_GAIN: f64 = 2.89;
+const DRAIN_GAIN: f64 = 1. / STARTUP_GAIN;
+const DRAIN_ROUNDS: u32 = 2;
+const PROBE_GAINS: [f64; 8] = [1.25, 0.75, 1., 1., 1., 1., 1., 1.];
+const FULL_BW_GROWTH: f64 = 1.25;
+const FULL_BW_ROUNDS: u32 = 3;
+// 等待时间超过本轮时长的该比例才算受限
+const LIMITED_RATIO: f64 = 0.1;
+// 发送节奏允许的突发: 按当前速率 2ms 的数据量，不少于 16KB
+const BURST_US: u64 = 2_000;
+const MIN_BURST_BYTES: f64 = 16. * 1024.;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CongestionControl {
+    Bbr,
+    Cubic,
+    Reno,
+    Vegas,
+}
+
+impl CongestionControl {
+    // "off" 返回 Ok(None)，即不做拥塞控制
+    pub fn parse(s: &str) -> Result<Option<Self>, String> {
+        match s.to_lowercase().as_str() {
+            "bbr" => Ok(Some(Self::Bbr)),
+            "cubic" | "reno" | "vegas" if !cfg!(target_os = "linux") => {
+                Err(format!("congestion control {} requires Linux", s))
+            }
+            "cubic" => Ok(Some(Self::Cubic)),
+            "reno" => Ok(Some(Self::Reno)),
+            "vegas" => Ok(Some(Self::Vegas)),
+            "off" | "none" | "" => Ok(None),
+            _ => Err(format!("unknown congestion control: {}", s)),
+        }
+    }
+
+    pub fn name(algorithm: Option<Self>) -> &'static str {
+        match algorithm {
+            Some(Self::Bbr) => "bbr",
+            Some(Self::Cubic) => "cubic",
+            Some(Self::Reno) => "reno",
+            Some(Self::Vegas) => "vegas",
+            None => "off",
+        }
+    }
+
+    // 是否在中继用户态控制发送节奏
+    pub fn paced(&self) -> bool {
+        *self == Self::Bbr
+    }
+}
+
+// 会话套接字的描述符，只在 Linux 上用于设置内核拥塞控制算法
+pub type SocketFd = Option<i32>;
+
+pub fn socket_fd(stream: &hbb_common::tokio::net::TcpStream) -> SocketFd {
+    #[cfg(target_os = "linux")]
+    {
+        use std::os::fd::AsRawFd;
+        Some(stream.as_raw_fd())
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        let _ = stream;
+        None
+    }
+}
+
+// 系统默认的内核算法，切回 BBR 或关闭时恢复
+fn default_kernel_algorithm() -> Option<String> {
+    std::fs::read_to_string("/proc/sys/net/ipv4/tcp_congestion_control")
+        .ok()
+        .map(|x| x.trim().to_owned())
+        .filter(|x| !x.is_empty())
+}
+
+#[cfg(target_os = "linux")]
+fn set_kernel_algorithm(fd: i32, name: &str) -> std::io::Result<()> {
+    use std::os::raw::{c_int, c_void};
+    const IPPROTO_TCP: c_int = 6;
+    const TCP_CONGESTION: c_int = 13;
+    extern "C" {
+        fn setsockopt(fd: c_int, level: c_int, name: c_int, value: *const c_void, len: u32) -> c_int;
+    }
+    let res = unsafe {
+        setsockopt(
+            fd,
+            IPPROTO_TCP,
+            TCP_CONGESTION,
+            name.as_ptr() as *const c_void,
+            name.len() as u32,
+        )
+    };
+    if res < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn set_kernel_algorithm(_fd: i32, _name: &str) -> std::io::Result<()> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "TCP congestion control selection requires Linux",
+    ))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    Startup,
+    Drain,
+    ProbeBw,
+}
+
+// BBR 风格的瓶颈带宽估计，时间单位为微秒，速率单位为字节/秒
+#[derive(Debug)]
+pub struct Bbr {
+    mode: Mode,
+    samples: VecDeque<f64>,
+    round_start: u64,
+    round_bytes: u64,
+    round_paced: u64,
+    round_blocked: u64,
+    round_blocked_bytes: u64,
+    full_bw: f64,
+    full_bw_rounds: u32,
+    drain_rounds: u32,
+    cycle: usize,
+}
+
+impl Bbr {
+    pub fn new(now: u64) -> Self {
+        Self {
+            mode: Mode::Startup,
+            samples: VecDeque::new(),
+            round_start: now,
+            round_bytes: 0,
+            round_paced: 0,
+            round_blocked: 0,
+            round_blocked_bytes: 0,
+            full_bw: 0.,
+            full_bw_rounds: 0,
+            drain_rounds: 0,
+            cycle: 0,
+        }
+    }
+
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    pub fn bandwidth(&self) -> Option<f64> {
+        self.samples.iter().cloned().reduce(f64::max)
+    }
+
+    pub fn pacing_gain(&self) -> f64 {
+        match self.mode {
+            Mode::Startup => STARTUP_GAIN,
+            Mode::Drain => DRAIN_GAIN,
+            Mode::ProbeBw => PROBE_GAINS[self.cycle],
+        }
+    }
+
+    // 还没有带宽估计时不限制发送
+    pub fn pacing_rate(&self) -> Option<f64> {
+        self.bandwidth().map(|bw| bw * self.pacing_gain())
+    }
+
+    // 发送了 bytes 字节，paced 为发送前按节奏等待的时间，blocked 为等待下游接收的时间
+    pub fn on_sent(&mut self, now: u64, bytes: usize, paced: u64, blocked: u64) {
+        self.round_bytes += bytes as u64;
+        self.round_paced += paced;
+        self.round_blocked += blocked;
+        if blocked > 0 {
+            self.round_blocked_bytes += bytes as u64;
+        }
+        let elapsed = now.saturating_sub(self.round_start);
+        if elapsed >= ROUND_US {
+            self.end_round(elapsed);
+            self.round_start = now;
+            self.round_bytes = 0;
+            self.round_paced = 0;
+            self.round_blocked = 0;
+            self.round_blocked_bytes = 0;
+        }
+    }
+
+    fn enter_drain(&mut self) {
+        self.mode = Mode::Drain;
+        self.drain_rounds = DRAIN_ROUNDS;
+    }
+
+    fn end_round(&mut self, elapsed: u64) {
+        let rate = self.round_bytes as f64 * 1e6 / elapsed as f64;
+        let limit = elapsed as f64 * LIMITED_RATIO;
+        let blocked = self.round_blocked as f64 >= limit;
+        let app_limited = !blocked && ((self.round_paced as f64) < limit);
+        if blocked {
+            // 本轮前段下游缓冲区可能还有空间，按阻塞期间的发送速率修正
+            let rate = rate.min(self.round_blocked_bytes as f64 * 1e6 / self.round_blocked as f64);
+            self.samples.clear();
+            self.samples.push_back(rate);
+            if self.mode != Mode::Startup {
+                self.enter_drain();
+                return;
+            }
+        } else if !app_limited || self.bandwidth().map(|bw| rate > bw).unwrap_or(true) {
+            self.samples.push_back(rate);
+            if self.samples.len() > BW_WINDOW_ROUNDS {
+                self.samples.pop_front();
+            }
+        }
+        match self.mode {
+            Mode::Startup => {
+                if app_limited {
+                    return;
+                }
+                let bw = self.bandwidth().unwrap_or_default();
+                if bw >= self.full_bw * FULL_BW_GROWTH {
+                    self.full_bw = bw;
+                    self.full_bw_rounds = 0;
+                } else {
+                    self.full_bw_rounds += 1;
+                }
+                if blocked || self.full_bw_rounds >= FULL_BW_ROUNDS {
+                    self.enter_drain();
+                }
+            }
+            Mode::Drain => {
+                self.drain_rounds -= 1;
+                if self.drain_rounds == 0 {
+                    // 从匀速阶段开始，刚排空的缓冲区不立即再探测
+                    self.mode = Mode::ProbeBw;
+                    self.cycle = 2;
+                }
+            }
+            Mode::ProbeBw => {
+                self.cycle = (self.cycle + 1) % PROBE_GAINS.len();
+            }
+        }
+    }
+}
+
+// 按速率均匀发送，空闲期间积累的发送额度不超过一次突发
+#[derive(Debug, Default)]
+pub struct Pacer {
+    next: u64,
+}
+
+impl Pacer {
+    // 返回发送 bytes 字节前需要等待的微秒数
+    pub fn delay(&mut self, now: u64, bytes: usize, rate: Option<f64>) -> u64 {
+        let rate = match rate {
+            Some(rate) if rate > 0. => rate,
+            _ => {
+                self.next = now;
+                return 0;
+            }
+        };
+        let burst = BURST_US.max((MIN_BURST_BYTES * 1e6 / rate) as u64);
+        let start = self.next.max(now.saturating_sub(burst));
+        self.next = start + (bytes as f64 * 1e6 / rate) as u64;
+        self.next.saturating_sub(burst).saturating_sub(now)
+    }
+}
+
+// 中继会话单个方向的拥塞控制，fd 为该方向发送端的套接字
+pub struct Controller {
+    algorithm: Option<CongestionControl>,
+    fd: SocketFd,
+    bbr: Bbr,
+    pacer: Pacer,
+    start: Instant,
+}
+
+impl Controller {
+    pub fn new(algorithm: Option<CongestionControl>, fd: SocketFd) -> Self {
+        let controller = Self {
+            algorithm,
+            fd,
+            bbr: Bbr::new(0),
+            pacer: Pacer::default(),
+            start: Instant::now(),
+        };
+        if let Some(name) = controller.kernel_algorithm() {
+            controller.apply_kernel_algorithm(name);
+        }
+        controller
+    }
+
+    pub fn algorithm(&self) -> Option<CongestionControl> {
+        self.algorithm
+    }
+
+    // 切换算法后重新估计带宽
+    pub fn set_algorithm(&mut self, algorithm: Option<CongestionControl>) {
+        if algorithm != self.algorithm {
+            let kernel = self.kernel_algorithm().is_some();
+            *self = Self::new(algorithm, self.fd);
+            if kernel && self.kernel_algorithm().is_none() {
+                if let Some(name) = default_kernel_algorithm() {
+                    self.apply_kernel_algorithm(&name);
+                }
+            }
+        }
+    }
+
+    // 由内核协议栈实现的算法名
+    fn kernel_algorithm(&self) -> Option<&'static str> {
+        self.algorithm
+            .filter(|a| !a.paced())
+            .map(|a| CongestionControl::name(Some(a)))
+    }
+
+    fn apply_kernel_algorithm(&self, name: &str) {
+        let fd = match self.fd {
+            Some(fd) => fd,
+            None => {
+                log::warn!("Congestion control {} is not applied: no TCP socket", name);
+                return;
+            }
+        };
+        if let Err(err) = set_kernel_algorithm(fd, name) {
+            log::warn!("Failed to set congestion control {}: {}", name, err);
+        }
+    }
+
+    fn paced(&self) -> bool {
+        self.algorithm.map(|a| a.paced()).unwrap_or(false)
+    }
+
+    fn now(&self) -> u64 {
+        self.start.elapsed().as_micros() as u64
+    }
+
+    // 发送前调用，返回按节奏等待的时间
+    pub async fn pace(&mut self, bytes: usize) -> Duration {
+        if !self.paced() {
+            return Duration::ZERO;
+        }
+        let delay = self.pacer.delay(self.now(), bytes, self.bbr.pacing_rate());
+        if delay == 0 {
+            return Duration::ZERO;
+        }
+        let delay = Duration::from_micros(delay);
+        sleep(delay).await;
+        delay
+    }
+
+    // 发送完成后调用，blocked 为发送本身(等待下游接收)的耗时
+    pub fn on_sent(&mut self, bytes: usize, paced: Duration, blocked: Duration) {
+        if self.paced() {
+            let now = self.now();
+            self.bbr
+                .on_sent(now, bytes, paced.as_micros() as _, blocked.as_micros() as _);
+        }
+    }
+
+    pub fn summary(&self) -> String {
+        let name = CongestionControl::name(self.algorithm);
+        if !self.paced() {
+            return name.to_owned();
+        }
+        let mbps = |rate: Option<f64>| rate.map(|r| r * 8. / 1024. / 1024.).unwrap_or_default();
+        format!(
+            "{} {:?} bw={:.2}Mb/s pacing={:.2}Mb/s",
+            name,
+            self.bbr.mode(),
+            mbps(self.bbr.bandwidth()),
+            mbps(self.bbr.pacing_rate())
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 瓶颈链路: 以 rate 字节/秒排空，缓冲区满时发送方阻塞
+    struct Link {
+        rate: f64,
+        buffer: f64,
+        queued: f64,
+        at: u64,
+    }
+
+    impl Link {
+        fn drain(&mut self, now: u64) {
+            self.queued = (self.queued - self.rate * (now - self.at) as f64 / 1e6).max(0.);
+            self.at = now;
+        }
+
+        // 返回阻塞时间
+        fn send(&mut self, now: u64, bytes: usize) -> u64 {
+            self.drain(now);
+            let over = self.queued + bytes as f64 - self.buffer;
+            let wait = if over > 0. {
+                let wait = (over * 1e6 / self.rate).ceil() as u64;
+                self.drain(now + wait);
+                wait
+            } else {
+                0
+            };
+            self.queued += bytes as f64;
+            wait
+        }
+    }
+
+    // 发送方始终有数据，返回 (平均排队字节数, 平均吞吐 字节/秒, 带宽估计)
+    fn simulate(paced: bool, rate: f64, secs: u64) -> (f64, f64, Option<f64>) {
+        const CHUNK: usize = 16 * 1024;
+        let mut link = Link {
+            rate,
+            buffer: 512. * 1024.,
+            queued: 0.,
+            at: 0,
+        };
+        let mut bbr = Bbr::new(0);
+        let mut pacer = Pacer::default();
+        let (mut now, mut sent, mut queue_area) = (0u64, 0usize, 0.);
+        let end = secs * 1_000_000;
+        while now < end {
+            let paced_us = if paced {
+                pacer.delay(now, CHUNK, bbr.pacing_rate())
+            } else {
+                0
+            };
+            let before = now;
+            now += paced_us;
+            let blocked = link.send(now, CHUNK);
+            now += blocked;
+            sent += CHUNK;
+            bbr.on_sent(now, CHUNK, paced_us, blocked);
+            queue_area += link.queued * (now - before) as f64;
+        }
+        (queue_area / now as f64, sent as f64 * 1e6 / now as f64, bbr.bandwidth())
+    }
+
+    #[test]
+    fn test_pacer() {
+        let mut pacer = Pacer::default();
+        // 1MB/s 发送 1MB: 除去一次突发外约等待 1 秒
+        let rate = Some(1024. * 1024.);
+        let mut now = 0;
+        for _ in 0..64 {
+            now += pacer.delay(now, 16 * 1024, rate);
+        }
+        assert!((980_000..=1_000_000).contains(&now), "{}", now);
+        // 没有速率时不等待
+        assert_eq!(pacer.delay(now, 1 << 20, None), 0);
+    }
+
+    #[test]
+    fn test_bbr_estimate() {
+        let rate = 2. * 1024. * 1024.;
+        let (_, throughput, bw) = simulate(true, rate, 20);
+        let bw = bw.unwrap();
+        assert!((bw - rate).abs() < rate * 0.3, "{} {}", bw, rate);
+        // 匀速发送不损失吞吐
+        assert!(throughput > rate * 0.9, "{}", throughput);
+    }
+
+    #[test]
+    fn test_bbr_reduces_queue() {
+        let rate = 2. * 1024. * 1024.;
+        let (paced_queue, _, _) = simulate(true, rate, 20);
+        let (unpaced_queue, _, _) = simulate(false, rate, 20);
+        // 不控制节奏时下游缓冲区一直是满的
+        assert!(unpaced_queue > 500. * 1024., "{}", unpaced_queue);
+        assert!(paced_queue < unpaced_queue / 2., "{} {}", paced_queue, unpaced_queue);
+    }
+
+    #[test]
+    fn test_app_limited() {
+        let mut bbr = Bbr::new(0);
+        // 每轮 100KB，既不阻塞也不等待
+        for round in 1..=5 {
+            bbr.on_sent(round * ROUND_US, 100 * 1024, 0, 0);
+        }
+        let bw = bbr.bandwidth().unwrap();
+        // 应用受限的低速采样不会拉低估计
+        bbr.on_sent(6 * ROUND_US, 1024, 0, 0);
+        assert_eq!(bbr.bandwidth(), Some(bw));
+        assert_eq!(bbr.mode(), Mode::Startup);
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(CongestionControl::parse("BBR"), Ok(Some(CongestionControl::Bbr)));
+        assert_eq!(CongestionControl::parse("off"), Ok(None));
+        assert!(CongestionControl::parse("westwood").is_err());
+        assert!(!CongestionControl::Cubic.paced());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[hbb_common::tokio::test]
+    async fn test_kernel_algorithm() {
+        use hbb_common::tokio::net::{TcpListener, TcpStream};
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let stream = TcpStream::connect(listener.local_addr().unwrap()).await.unwrap();
+        let fd = socket_fd(&stream).unwrap();
+        // reno 总是编译在内核中
+        assert!(set_kernel_algorithm(fd, "reno").is_ok());
+        assert!(set_kernel_algorithm(fd, "westwood-unknown").is_err());
+        let mut controller = Controller::new(Some(CongestionControl::Reno), Some(fd));
+        assert_eq!(controller.kernel_algorithm(), Some("reno"));
+        controller.set_algorithm(Some(CongestionControl::Bbr));
+        assert_eq!(controller.kernel_algorithm(), None);
+    }
+}
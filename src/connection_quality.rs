@@ -0,0 +1,300 @@
+// 连接质量模块 - 客户端在会话中定期上报 QoS 采样(延迟、丢包率、码率)，保存到 session_qos 表，
+// 据此计算会话的 quality_score(0-100)写回 connection_sessions，并按设备、中继服务器汇总成时间序列，
+// 用于排查长期抱怨连接卡顿的设备或线路。评分由三部分加权:
+//   延迟       平均延迟 50ms 以内满分，400ms 以上为 0                 权重 40%
+//   丢包       平均丢包率 0 满分，10% 以上为 0                        权重 40%
+//   码率稳定性 码率的变异系数(标准差/均值) 0 满分，1 以上为 0        权重 20%
+use crate::enterprise_database::EnterpriseDatabase;
+use hbb_common::{bail, log, ResultType};
+use schemars::JsonSchema;
+use serde_derive::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+// 单次上报的采样数上限
+pub const MAX_BATCH: usize = 120;
+pub const RETENTION_SECS: u64 = 90 * 86400;
+const MAX_SESSION_ID_LEN: usize = 128;
+const MAX_LATENCY_MS: u32 = 60_000;
+
+const GOOD_LATENCY_MS: f64 = 50.0;
+const BAD_LATENCY_MS: f64 = 400.0;
+const BAD_LOSS_PERCENT: f64 = 10.0;
+const BAD_BITRATE_CV: f64 = 1.0;
+
+// 客户端上报的采样，timestamp 为空时使用服务器接收时间
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+pub struct ClientQosSample {
+    pub latency_ms: u32,
+    // 丢包率，百分比
+    #[serde(default)]
+    pub packet_loss: f64,
+    #[serde(default)]
+    pub bitrate_kbps: u32,
+    #[serde(default)]
+    pub timestamp: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct QosSample {
+    pub session_id: String,
+    pub device_id: String,
+    // 直连会话为空
+    pub relay_server: String,
+    pub latency_ms: u32,
+    pub packet_loss: f64,
+    pub bitrate_kbps: u32,
+    pub reported_at: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct QualityPoint {
+    // 时间段起点
+    pub start: u64,
+    pub samples: usize,
+    pub score: f64,
+    pub avg_latency_ms: f64,
+    pub avg_packet_loss: f64,
+    pub avg_bitrate_kbps: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct QualityTrend {
+    // 设备ID或中继服务器地址(直连为空)
+    pub subject: String,
+    pub since: u64,
+    pub until: u64,
+    pub bucket_secs: u64,
+    // 整个时间窗口的评分，没有采样时为空
+    pub score: Option<f64>,
+    pub points: Vec<QualityPoint>,
+}
+
+// 校验并转换上报的采样；客户端时间晚于服务器时间时按接收时间记录
+pub fn normalize(
+    session_id: &str,
+    device_id: &str,
+    relay_server: &str,
+    samples: Vec<ClientQosSample>,
+    now: u64,
+) -> ResultType<Vec<QosSample>> {
+    if session_id.is_empty() || session_id.len() > MAX_SESSION_ID_LEN {
+        bail!("invalid session id");
+    }
+    if samples.len() > MAX_BATCH {
+        bail!("at most {} samples per request", MAX_BATCH);
+    }
+    for s in samples.iter() {
+        if !(0.0..=100.0).contains(&s.packet_loss) {
+            bail!("packet_loss must be 0-100");
+        }
+        if s.latency_ms > MAX_LATENCY_MS {
+            bail!("latency_ms must be at most {}", MAX_LATENCY_MS);
+        }
+    }
+    Ok(samples
+        .into_iter()
+        .map(|s| QosSample {
+            session_id: session_id.to_owned(),
+            device_id: device_id.to_owned(),
+            relay_server: relay_server.to_owned(),
+            latency_ms: s.latency_ms,
+            packet_loss: s.packet_loss,
+            bitrate_kbps: s.bitrate_kbps,
+            reported_at: s.timestamp.filter(|t| *t <= now).unwrap_or(now),
+        })
+        .collect())
+}
+
+// 保存采样并按会话的全部采样重新计算会话评分；直连会话没有登记时只保存采样
+pub async fn save(db: &EnterpriseDatabase, samples: &[QosSample]) -> ResultType<()> {
+    let session_id = match samples.first() {
+        Some(s) => s.session_id.clone(),
+        None => return Ok(()),
+    };
+    db.save_qos_samples(samples).await?;
+    let all = db.list_session_qos_samples(&session_id).await?;
+    if let Some(score) = score(&all) {
+        db.set_session_quality_score(&session_id, score).await?;
+    }
+    Ok(())
+}
+
+pub async fn prune(db: &EnterpriseDatabase) {
+    if let Err(e) = db
+        .prune_qos_samples(crate::common::now().saturating_sub(RETENTION_SECS))
+        .await
+    {
+        log::error!("Failed to prune QoS samples: {}", e);
+    }
+}
+
+// 线性映射到 0-100，good 及以下满分，bad 及以上为 0
+fn linear(value: f64, good: f64, bad: f64) -> f64 {
+    if value <= good {
+        100.0
+    } else if value >= bad {
+        0.0
+    } else {
+        100.0 * (bad - value) / (bad - good)
+    }
+}
+
+fn mean(values: impl Iterator<Item = f64>) -> f64 {
+    let (sum, n) = values.fold((0.0, 0usize), |(sum, n), v| (sum + v, n + 1));
+    if n == 0 {
+        0.0
+    } else {
+        sum / n as f64
+    }
+}
+
+// 采样不足两个或码率均为 0 时不扣稳定性分
+fn bitrate_cv(samples: &[QosSample]) -> f64 {
+    let avg = mean(samples.iter().map(|s| s.bitrate_kbps as f64));
+    if samples.len() < 2 || avg <= 0.0 {
+        return 0.0;
+    }
+    let variance = mean(samples.iter().map(|s| (s.bitrate_kbps as f64 - avg).powi(2)));
+    variance.sqrt() / avg
+}
+
+fn round(value: f64) -> f64 {
+    (value * 10.0).round() / 10.0
+}
+
+pub fn score(samples: &[QosSample]) -> Option<f64> {
+    summarize(0, samples).map(|p| p.score)
+}
+
+fn summarize(start: u64, samples: &[QosSample]) -> Option<QualityPoint> {
+    if samples.is_empty() {
+        return None;
+    }
+    let latency = mean(samples.iter().map(|s| s.latency_ms as f64));
+    let loss = mean(samples.iter().map(|s| s.packet_loss));
+    let score = 0.4 * linear(latency, GOOD_LATENCY_MS, BAD_LATENCY_MS)
+        + 0.4 * linear(loss, 0.0, BAD_LOSS_PERCENT)
+        + 0.2 * linear(bitrate_cv(samples), 0.0, BAD_BITRATE_CV);
+    Some(QualityPoint {
+        start,
+        samples: samples.len(),
+        score: round(score),
+        avg_latency_ms: round(latency),
+        avg_packet_loss: round(loss),
+        avg_bitrate_kbps: round(mean(samples.iter().map(|s| s.bitrate_kbps as f64))),
+    })
+}
+
+// 按 bucket_secs 分段汇总，只返回有采样的时间段
+pub fn trend(subject: &str, samples: &[QosSample], since: u64, until: u64, bucket_secs: u64) -> QualityTrend {
+    let bucket_secs = bucket_secs.max(60);
+    let mut buckets: BTreeMap<u64, Vec<QosSample>> = BTreeMap::new();
+    for s in samples
+        .iter()
+        .filter(|s| s.reported_at >= since && s.reported_at < until)
+    {
+        let start = since + (s.reported_at - since) / bucket_secs * bucket_secs;
+        buckets.entry(start).or_default().push(s.clone());
+    }
+    let in_window: Vec<QosSample> = buckets.values().flatten().cloned().collect();
+    QualityTrend {
+        subject: subject.to_owned(),
+        since,
+        until,
+        bucket_secs,
+        score: score(&in_window),
+        points: buckets
+            .iter()
+            .filter_map(|(start, samples)| summarize(*start, samples))
+            .collect(),
+    }
+}
+
+// 按中继服务器分组汇总，评分低的排在前面
+pub fn relay_trends(samples: &[QosSample], since: u64, until: u64, bucket_secs: u64) -> Vec<QualityTrend> {
+    let mut by_relay: BTreeMap<&str, Vec<QosSample>> = BTreeMap::new();
+    for s in samples.iter() {
+        by_relay.entry(&s.relay_server).or_default().push(s.clone());
+    }
+    let mut trends: Vec<QualityTrend> = by_relay
+        .iter()
+        .map(|(relay, samples)| trend(relay, samples, since, until, bucket_secs))
+        .collect();
+    trends.sort_by(|a, b| a.score.partial_cmp(&b.score).unwrap_or(std::cmp::Ordering::Equal));
+    trends
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(relay: &str, latency_ms: u32, packet_loss: f64, bitrate_kbps: u32, at: u64) -> QosSample {
+        QosSample {
+            session_id: "s1".to_owned(),
+            device_id: "123".to_owned(),
+            relay_server: relay.to_owned(),
+            latency_ms,
+            packet_loss,
+            bitrate_kbps,
+            reported_at: at,
+        }
+    }
+
+    #[test]
+    fn test_score() {
+        assert_eq!(score(&[]), None);
+        assert_eq!(score(&[sample("", 30, 0.0, 2000, 0)]), Some(100.0));
+        // 延迟 225ms 得一半，丢包 10% 为 0，码率稳定
+        assert_eq!(
+            score(&[sample("", 225, 10.0, 2000, 0), sample("", 225, 10.0, 2000, 1)]),
+            Some(40.0)
+        );
+        // 码率在 0 和 4000 之间跳动，变异系数为 1
+        assert_eq!(
+            score(&[sample("", 30, 0.0, 0, 0), sample("", 30, 0.0, 4000, 1)]),
+            Some(80.0)
+        );
+    }
+
+    #[test]
+    fn test_trend() {
+        let samples = vec![
+            sample("r1", 30, 0.0, 1000, 100),
+            sample("r1", 30, 0.0, 1000, 3700),
+            sample("r2", 400, 20.0, 1000, 3800),
+            sample("r2", 400, 20.0, 1000, 99999),
+        ];
+        let t = trend("123", &samples, 0, 7200, 3600);
+        assert_eq!(t.points.len(), 2);
+        assert_eq!(t.points[0].start, 0);
+        assert_eq!(t.points[1].start, 3600);
+        assert_eq!(t.points[1].samples, 2);
+        assert_eq!(t.points[1].avg_latency_ms, 215.0);
+
+        let relays = relay_trends(&samples, 0, 7200, 3600);
+        assert_eq!(relays[0].subject, "r2");
+        assert_eq!(relays[0].score, Some(20.0));
+        assert_eq!(relays[1].score, Some(100.0));
+    }
+
+    #[test]
+    fn test_normalize() {
+        let samples: Vec<ClientQosSample> = serde_json::from_str(
+            r#"[{"latency_ms": 40, "packet_loss": 0.5, "bitrate_kbps": 1500, "timestamp": 90},
+                {"latency_ms": 45, "timestamp": 200}]"#,
+        )
+        .unwrap();
+        let res = normalize("s1", "123", "relay", samples, 100).unwrap();
+        assert_eq!(res[0].reported_at, 90);
+        assert_eq!(res[1].reported_at, 100);
+        assert_eq!(res[1].relay_server, "relay");
+        let bad = vec![ClientQosSample {
+            latency_ms: 10,
+            packet_loss: 120.0,
+            bitrate_kbps: 0,
+            timestamp: None,
+        }];
+        assert!(normalize("s1", "123", "", bad, 100).is_err());
+        assert!(normalize("", "123", "", vec![], 100).is_err());
+    }
+}
@@ -0,0 +1,327 @@
+// 内容扫描模块 - 上传完成并通过哈希校验后、保存到存储后端之前调用杀毒引擎扫描暂存文件，
+// 支持 clamd(INSTREAM，TCP 或 unix socket) 和 ICAP(RESPMOD，如 c-icap + ClamAV)；
+// 检出威胁的文件移入隔离目录并产生 MalwareDetection 安全事件
+use crate::enterprise_database::EnterpriseDatabase;
+use crate::validation::Payload;
+use hbb_common::{
+    bail, log, timeout,
+    tokio::{
+        io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+        net::TcpStream,
+        sync::RwLock,
+    },
+    ResultType,
+};
+use schemars::JsonSchema;
+use serde_derive::{Deserialize, Serialize};
+use std::{fs::File, io::Read, path::Path};
+use validator::Validate;
+
+pub const CONTENT_SCAN_KEY: &str = "content_scan";
+const STREAM_CHUNK_SIZE: usize = 64 * 1024;
+const MAX_REPLY_SIZE: usize = 64 * 1024;
+
+lazy_static::lazy_static! {
+    static ref CONFIG: RwLock<ScanConfig> = Default::default();
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ScanEngine {
+    Clamd,
+    Icap,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, Validate)]
+pub struct ScanConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default = "default_engine")]
+    pub engine: ScanEngine,
+    // clamd: host:port 或 unix:/run/clamav/clamd.ctl；icap: host:port
+    #[serde(default = "default_address")]
+    #[validate(length(min = 1, max = 256))]
+    pub address: String,
+    // ICAP 服务名，如 avscan、srv_clamav
+    #[serde(default = "default_icap_service")]
+    #[validate(length(max = 128))]
+    pub icap_service: String,
+    // 扫描引擎不可用时拒绝文件，关闭后仅记录告警
+    #[serde(default = "default_true")]
+    pub fail_closed: bool,
+    #[serde(default = "default_timeout_secs")]
+    #[validate(range(min = 1, max = 3600))]
+    pub timeout_secs: u64,
+}
+
+impl Payload for ScanConfig {}
+
+fn default_engine() -> ScanEngine {
+    ScanEngine::Clamd
+}
+
+fn default_address() -> String {
+    "127.0.0.1:3310".to_owned()
+}
+
+fn default_icap_service() -> String {
+    "avscan".to_owned()
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_timeout_secs() -> u64 {
+    60
+}
+
+impl Default for ScanConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            engine: default_engine(),
+            address: default_address(),
+            icap_service: default_icap_service(),
+            fail_closed: default_true(),
+            timeout_secs: default_timeout_secs(),
+        }
+    }
+}
+
+impl ScanConfig {
+    pub fn validate(&self) -> ResultType<()> {
+        if self.address.is_empty() {
+            bail!("scanner address is required");
+        }
+        if self.engine == ScanEngine::Icap && self.address.starts_with("unix:") {
+            bail!("icap scanner requires a tcp address");
+        }
+        if self.timeout_secs == 0 || self.timeout_secs > 3600 {
+            bail!("timeout_secs must be between 1 and 3600");
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ScanVerdict {
+    Clean,
+    Infected(String), // 威胁名称
+    Failed(String),   // 扫描引擎错误
+}
+
+impl ScanVerdict {
+    // 按配置决定是否拒绝文件
+    pub fn rejects(&self, fail_closed: bool) -> bool {
+        match self {
+            ScanVerdict::Clean => false,
+            ScanVerdict::Infected(_) => true,
+            ScanVerdict::Failed(_) => fail_closed,
+        }
+    }
+}
+
+pub async fn reload(db: &EnterpriseDatabase) -> ResultType<()> {
+    let config: ScanConfig = match db.get_setting(CONTENT_SCAN_KEY).await? {
+        Some(v) => serde_json::from_str(&v)?,
+        None => ScanConfig::default(),
+    };
+    *CONFIG.write().await = config;
+    Ok(())
+}
+
+pub async fn get() -> ScanConfig {
+    CONFIG.read().await.clone()
+}
+
+pub async fn update(db: &EnterpriseDatabase, config: ScanConfig, updated_by: &str) -> ResultType<()> {
+    config.validate()?;
+    db.set_setting(CONTENT_SCAN_KEY, &serde_json::to_string(&config)?, Some(updated_by))
+        .await?;
+    *CONFIG.write().await = config;
+    Ok(())
+}
+
+// 扫描文件；未启用扫描时直接返回 Clean
+pub async fn scan_file(path: &Path) -> ScanVerdict {
+    let config = get().await;
+    if !config.enabled {
+        return ScanVerdict::Clean;
+    }
+    let result = timeout(config.timeout_secs * 1000, async {
+        match config.engine {
+            ScanEngine::Clamd => scan_clamd(&config, path).await,
+            ScanEngine::Icap => scan_icap(&config, path).await,
+        }
+    })
+    .await;
+    match result {
+        Ok(Ok(verdict)) => verdict,
+        Ok(Err(err)) => {
+            log::error!("Content scan of {} failed: {}", path.display(), err);
+            ScanVerdict::Failed(err.to_string())
+        }
+        Err(_) => {
+            log::error!("Content scan of {} timed out", path.display());
+            ScanVerdict::Failed("scan timed out".to_owned())
+        }
+    }
+}
+
+async fn scan_clamd(config: &ScanConfig, path: &Path) -> ResultType<ScanVerdict> {
+    #[cfg(unix)]
+    if let Some(socket) = config.address.strip_prefix("unix:") {
+        let stream = hbb_common::tokio::net::UnixStream::connect(socket).await?;
+        return clamd_instream(stream, path).await;
+    }
+    let stream = TcpStream::connect(&config.address).await?;
+    clamd_instream(stream, path).await
+}
+
+// clamd INSTREAM 协议：命令后按 [4字节大端长度][数据] 发送，长度 0 结束
+async fn clamd_instream<S: AsyncRead + AsyncWrite + Unpin>(mut stream: S, path: &Path) -> ResultType<ScanVerdict> {
+    stream.write_all(b"zINSTREAM\0").await?;
+    let mut file = File::open(path)?;
+    let mut buf = vec![0u8; STREAM_CHUNK_SIZE];
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        stream.write_all(&(n as u32).to_be_bytes()).await?;
+        stream.write_all(&buf[..n]).await?;
+    }
+    stream.write_all(&0u32.to_be_bytes()).await?;
+    stream.flush().await?;
+    let reply = read_reply(&mut stream).await?;
+    Ok(parse_clamd_reply(&String::from_utf8_lossy(&reply)))
+}
+
+fn parse_clamd_reply(reply: &str) -> ScanVerdict {
+    let reply = reply.trim_end_matches(|c| c == '\0' || c == '\n').trim();
+    let result = reply.strip_prefix("stream:").unwrap_or(reply).trim();
+    if result == "OK" {
+        ScanVerdict::Clean
+    } else if let Some(name) = result.strip_suffix("FOUND") {
+        ScanVerdict::Infected(name.trim().to_owned())
+    } else {
+        ScanVerdict::Failed(result.to_owned())
+    }
+}
+
+// ICAP RESPMOD：将文件作为 HTTP 响应体封装发送，204 表示无需修改(干净)，200 表示被替换(检出威胁)
+async fn scan_icap(config: &ScanConfig, path: &Path) -> ResultType<ScanVerdict> {
+    let mut stream = TcpStream::connect(&config.address).await?;
+    let len = std::fs::metadata(path)?.len();
+    let http_header = format!(
+        "HTTP/1.1 200 OK\r\nContent-Type: application/octet-stream\r\nContent-Length: {}\r\n\r\n",
+        len
+    );
+    let request = format!(
+        "RESPMOD icap://{}/{} ICAP/1.0\r\nHost: {}\r\nAllow: 204\r\nEncapsulated: res-hdr=0, res-body={}\r\n\r\n",
+        config.address,
+        config.icap_service,
+        config.address,
+        http_header.len()
+    );
+    stream.write_all(request.as_bytes()).await?;
+    stream.write_all(http_header.as_bytes()).await?;
+    let mut file = File::open(path)?;
+    let mut buf = vec![0u8; STREAM_CHUNK_SIZE];
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        stream.write_all(format!("{:x}\r\n", n).as_bytes()).await?;
+        stream.write_all(&buf[..n]).await?;
+        stream.write_all(b"\r\n").await?;
+    }
+    stream.write_all(b"0\r\n\r\n").await?;
+    stream.flush().await?;
+    let reply = read_icap_head(&mut stream).await?;
+    Ok(parse_icap_reply(&String::from_utf8_lossy(&reply)))
+}
+
+fn parse_icap_reply(reply: &str) -> ScanVerdict {
+    let mut lines = reply.lines();
+    let status = lines
+        .next()
+        .and_then(|l| l.split_whitespace().nth(1))
+        .unwrap_or_default()
+        .to_owned();
+    match status.as_str() {
+        "204" => ScanVerdict::Clean,
+        "200" => {
+            // 常见实现通过 X-Infection-Found / X-Virus-ID 返回威胁名称
+            let name = lines
+                .filter_map(|l| l.split_once(':'))
+                .find(|(k, _)| {
+                    k.eq_ignore_ascii_case("X-Virus-ID") || k.eq_ignore_ascii_case("X-Infection-Found")
+                })
+                .map(|(_, v)| v.trim().to_owned())
+                .unwrap_or_else(|| "unknown".to_owned());
+            ScanVerdict::Infected(name)
+        }
+        other => ScanVerdict::Failed(format!("icap status {}", other)),
+    }
+}
+
+async fn read_reply<S: AsyncRead + Unpin>(stream: &mut S) -> ResultType<Vec<u8>> {
+    let mut reply = Vec::new();
+    let mut buf = [0u8; 1024];
+    loop {
+        let n = stream.read(&mut buf).await?;
+        if n == 0 {
+            break;
+        }
+        reply.extend_from_slice(&buf[..n]);
+        if reply.contains(&0) || reply.len() > MAX_REPLY_SIZE {
+            break;
+        }
+    }
+    Ok(reply)
+}
+
+// 只需要 ICAP 响应头，读到空行即停止
+async fn read_icap_head<S: AsyncRead + Unpin>(stream: &mut S) -> ResultType<Vec<u8>> {
+    let mut reply = Vec::new();
+    let mut buf = [0u8; 1024];
+    loop {
+        let n = stream.read(&mut buf).await?;
+        if n == 0 {
+            break;
+        }
+        reply.extend_from_slice(&buf[..n]);
+        if reply.windows(4).any(|w| w == b"\r\n\r\n") || reply.len() > MAX_REPLY_SIZE {
+            break;
+        }
+    }
+    Ok(reply)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_scanner_replies() {
+        assert_eq!(parse_clamd_reply("stream: OK\0"), ScanVerdict::Clean);
+        assert_eq!(
+            parse_clamd_reply("stream: Eicar-Test-Signature FOUND\0"),
+            ScanVerdict::Infected("Eicar-Test-Signature".to_owned())
+        );
+        assert!(matches!(
+            parse_clamd_reply("INSTREAM size limit exceeded. ERROR\0"),
+            ScanVerdict::Failed(_)
+        ));
+        assert_eq!(parse_icap_reply("ICAP/1.0 204 No Content\r\n\r\n"), ScanVerdict::Clean);
+        assert_eq!(
+            parse_icap_reply("ICAP/1.0 200 OK\r\nX-Infection-Found: Type=0; Resolution=2; Threat=Eicar;\r\nX-Virus-ID: Eicar-Test\r\n\r\n"),
+            ScanVerdict::Infected("Type=0; Resolution=2; Threat=Eicar;".to_owned())
+        );
+        assert!(ScanVerdict::Failed("down".to_owned()).rejects(true));
+        assert!(!ScanVerdict::Failed("down".to_owned()).rejects(false));
+    }
+}
@@ -0,0 +1,621 @@
+// 中继控制通道双向 TLS - 信令服务器发给中继服务器的管理命令(排空、断开会话、查询会话等)默认走明文 TCP，
+// 只被中继本机回环地址接受；中继与信令跨区域部署时可改走双向认证的 TLS:
+//   - 信令服务器在 Web 管理接口生成内部 CA(保存在系统设置中)，并用它签发自己的客户端证书
+//   - 为每台中继签发服务器证书(证书名为中继的域名或IP)，证书和私钥只在签发时返回一次，
+//     部署到中继的 CONTROL_TLS_CA / CONTROL_TLS_CERT / CONTROL_TLS_KEY 后，中继在 CONTROL_TLS_PORT 监听
+//   - 中继只接受该 CA 签发的客户端证书；信令服务器校验中继证书的名称，吊销的证书按指纹拒绝
+//   - 启用后所有管理命令都走 TLS，RELAY_ADMIN_ADDR 不再生效；重新生成 CA 后须为全部中继重新签发证书
+// 需要以 mtls 特性编译
+use crate::common::now;
+use crate::enterprise_database::EnterpriseDatabase;
+use hbb_common::{
+    bail, log,
+    tokio::{
+        io::{AsyncRead, AsyncWrite},
+        sync::RwLock,
+    },
+    ResultType,
+};
+use schemars::JsonSchema;
+use serde_derive::{Deserialize, Serialize};
+use std::{collections::HashSet, future::Future, net::SocketAddr};
+
+// CA 私钥明文保存在系统设置中，不通过通用设置接口读写
+pub const CONTROL_TLS_KEY: &str = "control_tls";
+const DEFAULT_PORT: u16 = 21123;
+// 中继证书有效期
+const CERT_DAYS: i64 = 825;
+const CA_DAYS: i64 = 3650;
+
+lazy_static::lazy_static! {
+    static ref STATE: RwLock<Option<Stored>> = Default::default();
+}
+
+pub trait Stream: AsyncRead + AsyncWrite + Unpin + Send + Sync + 'static {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send + Sync + 'static> Stream for T {}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct IssuedCert {
+    // 中继的域名或IP
+    pub name: String,
+    // 证书 DER 的 SHA-256，小写十六进制
+    pub fingerprint: String,
+    pub not_after: u64,
+    pub issued_at: u64,
+    pub issued_by: String,
+    pub revoked_at: Option<u64>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct ControlTlsStatus {
+    pub enabled: bool,
+    pub port: u16,
+    pub ca_fingerprint: Option<String>,
+    // 部署到中继的 CONTROL_TLS_CA
+    pub ca_pem: Option<String>,
+    pub ca_created_at: Option<u64>,
+    pub ca_created_by: Option<String>,
+    pub issued: Vec<IssuedCert>,
+}
+
+// 签发结果，私钥不在服务器保存
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct IssuedBundle {
+    pub cert: IssuedCert,
+    pub ca_pem: String,
+    pub cert_pem: String,
+    pub key_pem: String,
+}
+
+// 数据库中保存的 CA、信令服务器的客户端证书和已签发的中继证书
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Stored {
+    enabled: bool,
+    ca_cert: String,
+    ca_key: String,
+    client_cert: String,
+    client_key: String,
+    created_at: u64,
+    created_by: String,
+    issued: Vec<IssuedCert>,
+}
+
+impl Stored {
+    fn revoked(&self) -> HashSet<String> {
+        self.issued
+            .iter()
+            .filter(|x| x.revoked_at.is_some())
+            .map(|x| x.fingerprint.clone())
+            .collect()
+    }
+}
+
+pub fn port() -> u16 {
+    std::env::var("CONTROL_TLS_PORT")
+        .ok()
+        .and_then(|x| x.parse().ok())
+        .unwrap_or(DEFAULT_PORT)
+}
+
+// 中继端配置了证书时监听 TLS 管理端口
+pub fn listen_enabled() -> bool {
+    !std::env::var("CONTROL_TLS_CERT").unwrap_or_default().is_empty()
+}
+
+// 信令端是否通过 TLS 发送管理命令
+pub async fn enabled() -> bool {
+    STATE.read().await.as_ref().map(|x| x.enabled).unwrap_or(false)
+}
+
+async fn load(db: &EnterpriseDatabase) -> ResultType<Option<Stored>> {
+    Ok(match db.get_setting(CONTROL_TLS_KEY).await? {
+        Some(v) => Some(serde_json::from_str(&v)?),
+        None => None,
+    })
+}
+
+async fn save(db: &EnterpriseDatabase, stored: Stored, updated_by: &str) -> ResultType<()> {
+    db.set_setting(CONTROL_TLS_KEY, &serde_json::to_string(&stored)?, Some(updated_by))
+        .await?;
+    *STATE.write().await = Some(stored);
+    Ok(())
+}
+
+pub async fn reload(db: &EnterpriseDatabase) -> ResultType<()> {
+    let stored = load(db).await?;
+    if stored.as_ref().map(|x| x.enabled).unwrap_or(false) && !cfg!(feature = "mtls") {
+        log::error!("Relay control TLS is enabled but the server was built without the mtls feature");
+    }
+    *STATE.write().await = stored;
+    Ok(())
+}
+
+pub async fn status(db: &EnterpriseDatabase) -> ResultType<ControlTlsStatus> {
+    let mut status = ControlTlsStatus {
+        port: port(),
+        ..Default::default()
+    };
+    if let Some(stored) = load(db).await? {
+        status.enabled = stored.enabled;
+        status.ca_fingerprint = pem_fingerprint(&stored.ca_cert).ok();
+        status.ca_pem = Some(stored.ca_cert);
+        status.ca_created_at = Some(stored.created_at);
+        status.ca_created_by = Some(stored.created_by);
+        status.issued = stored.issued;
+    }
+    Ok(status)
+}
+
+// 生成新的 CA 和客户端证书，原有的中继证书全部作废，启用状态不变
+pub async fn init_ca(db: &EnterpriseDatabase, created_by: &str) -> ResultType<ControlTlsStatus> {
+    let (ca_cert, ca_key, client_cert, client_key) = imp::generate_ca()?;
+    let enabled = load(db).await?.map(|x| x.enabled).unwrap_or(false);
+    let stored = Stored {
+        enabled,
+        ca_cert,
+        ca_key,
+        client_cert,
+        client_key,
+        created_at: now(),
+        created_by: created_by.to_owned(),
+        issued: vec![],
+    };
+    save(db, stored, created_by).await?;
+    log::info!("Relay control TLS CA created by {}", created_by);
+    status(db).await
+}
+
+// 为中继签发服务器证书，name 为信令服务器连接中继时使用的域名或IP
+pub async fn issue(db: &EnterpriseDatabase, name: &str, issued_by: &str) -> ResultType<IssuedBundle> {
+    let mut stored = match load(db).await? {
+        Some(stored) => stored,
+        None => bail!("relay control TLS CA is not created"),
+    };
+    let name = name.trim();
+    if name.is_empty() {
+        bail!("certificate name is empty");
+    }
+    let (cert_pem, key_pem, not_after) = imp::issue(&stored.ca_cert, &stored.ca_key, name, CERT_DAYS)?;
+    let cert = IssuedCert {
+        name: name.to_owned(),
+        fingerprint: pem_fingerprint(&cert_pem)?,
+        not_after,
+        issued_at: now(),
+        issued_by: issued_by.to_owned(),
+        revoked_at: None,
+    };
+    stored.issued.push(cert.clone());
+    let ca_pem = stored.ca_cert.clone();
+    save(db, stored, issued_by).await?;
+    log::info!("Relay control TLS certificate for {} issued by {}", name, issued_by);
+    Ok(IssuedBundle {
+        cert,
+        ca_pem,
+        cert_pem,
+        key_pem,
+    })
+}
+
+// 吊销中继证书；证书不存在或已吊销时返回 None
+pub async fn revoke(db: &EnterpriseDatabase, fingerprint: &str, revoked_by: &str) -> ResultType<Option<IssuedCert>> {
+    let mut stored = match load(db).await? {
+        Some(stored) => stored,
+        None => return Ok(None),
+    };
+    let cert = match stored
+        .issued
+        .iter_mut()
+        .find(|x| x.fingerprint == fingerprint.to_lowercase() && x.revoked_at.is_none())
+    {
+        Some(cert) => {
+            cert.revoked_at = Some(now());
+            cert.clone()
+        }
+        None => return Ok(None),
+    };
+    save(db, stored, revoked_by).await?;
+    log::info!("Relay control TLS certificate {} revoked by {}", cert.fingerprint, revoked_by);
+    Ok(Some(cert))
+}
+
+// 启用前须已生成 CA，且中继都已部署证书，否则管理命令会失败
+pub async fn set_enabled(db: &EnterpriseDatabase, enabled: bool, updated_by: &str) -> ResultType<()> {
+    let mut stored = match load(db).await? {
+        Some(stored) => stored,
+        None if enabled => bail!("relay control TLS CA is not created"),
+        None => return Ok(()),
+    };
+    if enabled && !cfg!(feature = "mtls") {
+        bail!("relay control TLS requires building with the mtls feature");
+    }
+    stored.enabled = enabled;
+    save(db, stored, updated_by).await?;
+    log::info!("Relay control TLS enabled: {}, by {}", enabled, updated_by);
+    Ok(())
+}
+
+// 中继地址中的主机部分，如 relay.example.com:21117 -> relay.example.com，[::1]:21117 -> ::1
+pub fn relay_host(relay_server: &str) -> &str {
+    if let Some(rest) = relay_server.strip_prefix('[') {
+        return rest.split(']').next().unwrap_or(rest);
+    }
+    match relay_server.split_once(':') {
+        Some((host, port)) if !port.contains(':') => host,
+        _ => relay_server,
+    }
+}
+
+// 以 TLS 连接中继的管理端口
+pub async fn connect(relay_server: &str) -> ResultType<Box<dyn Stream>> {
+    let host = relay_host(relay_server);
+    let addr = if host.contains(':') {
+        format!("[{}]:{}", host, port())
+    } else {
+        format!("{}:{}", host, port())
+    };
+    connect_to(host, &addr).await
+}
+
+async fn connect_to(host: &str, addr: &str) -> ResultType<Box<dyn Stream>> {
+    let stored = match STATE.read().await.clone() {
+        Some(stored) if stored.enabled => stored,
+        _ => bail!("relay control TLS is not enabled"),
+    };
+    imp::connect(
+        &stored.ca_cert,
+        &stored.client_cert,
+        &stored.client_key,
+        &stored.revoked(),
+        host,
+        addr,
+    )
+    .await
+}
+
+// 中继端在 TLS 管理端口上监听，握手完成的连接交给 handler，直到监听失败才返回
+#[allow(unused_variables)]
+pub async fn serve<F, Fut>(port: u16, handler: F) -> ResultType<()>
+where
+    F: Fn(Box<dyn Stream>, SocketAddr) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    #[cfg(feature = "mtls")]
+    {
+        imp::serve(port, handler).await
+    }
+    #[cfg(not(feature = "mtls"))]
+    {
+        bail!("relay control TLS requires building with the mtls feature")
+    }
+}
+
+fn pem_fingerprint(pem: &str) -> ResultType<String> {
+    match imp::pem_certs(pem)?.first() {
+        Some(der) => Ok(fingerprint(der)),
+        None => bail!("no certificate found"),
+    }
+}
+
+pub fn fingerprint(der: &[u8]) -> String {
+    sodiumoxide::crypto::hash::sha256::hash(der)
+        .0
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+#[cfg(not(feature = "mtls"))]
+mod imp {
+    use super::Stream;
+    use hbb_common::{bail, ResultType};
+    use std::collections::HashSet;
+
+    const ERR: &str = "relay control TLS requires building with the mtls feature";
+
+    pub fn generate_ca() -> ResultType<(String, String, String, String)> {
+        bail!(ERR)
+    }
+
+    pub fn issue(_ca_cert: &str, _ca_key: &str, _name: &str, _days: i64) -> ResultType<(String, String, u64)> {
+        bail!(ERR)
+    }
+
+    pub fn pem_certs(_pem: &str) -> ResultType<Vec<Vec<u8>>> {
+        bail!(ERR)
+    }
+
+    pub async fn connect(
+        _ca_cert: &str,
+        _cert: &str,
+        _key: &str,
+        _revoked: &HashSet<String>,
+        _host: &str,
+        _addr: &str,
+    ) -> ResultType<Box<dyn Stream>> {
+        bail!(ERR)
+    }
+}
+
+#[cfg(feature = "mtls")]
+mod imp {
+    use super::{fingerprint, Stream, CA_DAYS, CERT_DAYS};
+    use hbb_common::{
+        bail, log,
+        tokio::{self, net::TcpStream},
+        ResultType,
+    };
+    use rcgen::{
+        BasicConstraints, Certificate, CertificateParams, DistinguishedName, DnType, ExtendedKeyUsagePurpose, IsCa,
+        KeyPair, KeyUsagePurpose, SanType,
+    };
+    use std::{collections::HashSet, future::Future, net::IpAddr, net::SocketAddr, sync::Arc};
+
+    const CONNECT_TIMEOUT: u64 = 3_000;
+    const HANDSHAKE_TIMEOUT: u64 = 5_000;
+
+    // 证书有效期按天取整(UTC 零点)，返回年月日和对应的时间戳
+    fn days_from_now(days: i64) -> (i32, u8, u8, u64) {
+        use chrono::Datelike;
+        let t = chrono::Utc::now() + chrono::Duration::days(days);
+        let ts = t.timestamp() / 86400 * 86400;
+        (t.year(), t.month() as u8, t.day() as u8, ts.max(0) as u64)
+    }
+
+    fn params(common_name: &str, days: i64) -> (CertificateParams, u64) {
+        let mut params = CertificateParams::default();
+        let mut dn = DistinguishedName::new();
+        dn.push(DnType::OrganizationName, "RustDesk Enterprise");
+        dn.push(DnType::CommonName, common_name);
+        params.distinguished_name = dn;
+        let (y, m, d, _) = days_from_now(-1);
+        params.not_before = rcgen::date_time_ymd(y, m, d);
+        let (y, m, d, not_after) = days_from_now(days);
+        params.not_after = rcgen::date_time_ymd(y, m, d);
+        (params, not_after)
+    }
+
+    fn leaf(name: &str, usage: ExtendedKeyUsagePurpose, days: i64) -> ResultType<(Certificate, u64)> {
+        let (mut params, not_after) = params(name, days);
+        params.subject_alt_names = vec![match name.parse::<IpAddr>() {
+            Ok(ip) => SanType::IpAddress(ip),
+            Err(_) => SanType::DnsName(name.to_owned()),
+        }];
+        params.key_usages = vec![KeyUsagePurpose::DigitalSignature];
+        params.extended_key_usages = vec![usage];
+        Ok((Certificate::from_params(params)?, not_after))
+    }
+
+    fn load_ca(ca_cert: &str, ca_key: &str) -> ResultType<Certificate> {
+        let params = CertificateParams::from_ca_cert_pem(ca_cert, KeyPair::from_pem(ca_key)?)?;
+        Ok(Certificate::from_params(params)?)
+    }
+
+    // 返回 CA 证书、CA 私钥、信令服务器客户端证书及其私钥(PEM)
+    pub fn generate_ca() -> ResultType<(String, String, String, String)> {
+        let (mut params, _) = params("RustDesk Relay Control CA", CA_DAYS);
+        params.is_ca = IsCa::Ca(BasicConstraints::Constrained(0));
+        params.key_usages = vec![KeyUsagePurpose::KeyCertSign, KeyUsagePurpose::CrlSign];
+        let ca = Certificate::from_params(params)?;
+        let (client, _) = leaf("hbbs", ExtendedKeyUsagePurpose::ClientAuth, CA_DAYS)?;
+        Ok((
+            ca.serialize_pem()?,
+            ca.serialize_private_key_pem(),
+            client.serialize_pem_with_signer(&ca)?,
+            client.serialize_private_key_pem(),
+        ))
+    }
+
+    // 返回中继服务器证书、私钥(PEM)和失效时间
+    pub fn issue(ca_cert: &str, ca_key: &str, name: &str, days: i64) -> ResultType<(String, String, u64)> {
+        let ca = load_ca(ca_cert, ca_key)?;
+        let (cert, not_after) = leaf(name, ExtendedKeyUsagePurpose::ServerAuth, days.min(CERT_DAYS))?;
+        Ok((cert.serialize_pem_with_signer(&ca)?, cert.serialize_private_key_pem(), not_after))
+    }
+
+    pub fn pem_certs(pem: &str) -> ResultType<Vec<Vec<u8>>> {
+        Ok(rustls_pemfile::certs(&mut pem.as_bytes())?)
+    }
+
+    fn pem_key(pem: &str) -> ResultType<Vec<u8>> {
+        use rustls_pemfile::Item;
+        for item in rustls_pemfile::read_all(&mut pem.as_bytes())? {
+            if let Item::PKCS8Key(key) | Item::RSAKey(key) | Item::ECKey(key) = item {
+                return Ok(key);
+            }
+        }
+        bail!("no private key found")
+    }
+
+    fn roots(ca_cert: &str) -> ResultType<rustls::RootCertStore> {
+        let mut roots = rustls::RootCertStore::empty();
+        for der in pem_certs(ca_cert)? {
+            roots.add(&rustls::Certificate(der))?;
+        }
+        if roots.is_empty() {
+            bail!("no CA certificate found");
+        }
+        Ok(roots)
+    }
+
+    fn chain(pem: &str) -> ResultType<Vec<rustls::Certificate>> {
+        let certs: Vec<_> = pem_certs(pem)?.into_iter().map(rustls::Certificate).collect();
+        if certs.is_empty() {
+            bail!("no certificate found");
+        }
+        Ok(certs)
+    }
+
+    pub fn client_config(ca_cert: &str, cert: &str, key: &str) -> ResultType<rustls::ClientConfig> {
+        Ok(rustls::ClientConfig::builder()
+            .with_safe_defaults()
+            .with_root_certificates(roots(ca_cert)?)
+            .with_client_auth_cert(chain(cert)?, rustls::PrivateKey(pem_key(key)?))?)
+    }
+
+    // 只接受同一 CA 签发的客户端证书
+    pub fn server_config(ca_cert: &str, cert: &str, key: &str) -> ResultType<rustls::ServerConfig> {
+        let verifier = rustls::server::AllowAnyAuthenticatedClient::new(roots(ca_cert)?).boxed();
+        Ok(rustls::ServerConfig::builder()
+            .with_safe_defaults()
+            .with_client_cert_verifier(verifier)
+            .with_single_cert(chain(cert)?, rustls::PrivateKey(pem_key(key)?))?)
+    }
+
+    pub async fn connect(
+        ca_cert: &str,
+        cert: &str,
+        key: &str,
+        revoked: &HashSet<String>,
+        host: &str,
+        addr: &str,
+    ) -> ResultType<Box<dyn Stream>> {
+        let connector = tokio_rustls::TlsConnector::from(Arc::new(client_config(ca_cert, cert, key)?));
+        let name = match host.parse::<IpAddr>() {
+            Ok(ip) => rustls::ServerName::IpAddress(ip),
+            Err(_) => rustls::ServerName::try_from(host)?,
+        };
+        let stream = hbb_common::timeout(CONNECT_TIMEOUT, TcpStream::connect(addr)).await??;
+        let stream = hbb_common::timeout(HANDSHAKE_TIMEOUT, connector.connect(name, stream)).await??;
+        let peer = stream
+            .get_ref()
+            .1
+            .peer_certificates()
+            .and_then(|x| x.first())
+            .map(|x| fingerprint(&x.0))
+            .unwrap_or_default();
+        if revoked.contains(&peer) {
+            bail!("relay certificate {} of {} is revoked", peer, host);
+        }
+        Ok(Box::new(stream))
+    }
+
+    // 读取 CONTROL_TLS_CA / CONTROL_TLS_CERT / CONTROL_TLS_KEY 指定的 PEM 文件
+    fn load_server_config() -> ResultType<rustls::ServerConfig> {
+        let read = |var: &str| -> ResultType<String> {
+            let path = std::env::var(var).unwrap_or_default();
+            if path.is_empty() {
+                bail!("{} is not set", var);
+            }
+            Ok(std::fs::read_to_string(&path)?)
+        };
+        server_config(
+            &read("CONTROL_TLS_CA")?,
+            &read("CONTROL_TLS_CERT")?,
+            &read("CONTROL_TLS_KEY")?,
+        )
+    }
+
+    pub async fn serve<F, Fut>(port: u16, handler: F) -> ResultType<()>
+    where
+        F: Fn(Box<dyn Stream>, SocketAddr) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let acceptor = tokio_rustls::TlsAcceptor::from(Arc::new(load_server_config()?));
+        let listener = hbb_common::tcp::listen_any(port).await?;
+        log::info!("Listening on tcp :{} for relay control TLS", port);
+        let handler = Arc::new(handler);
+        loop {
+            let (stream, addr) = listener.accept().await?;
+            let (acceptor, handler) = (acceptor.clone(), handler.clone());
+            tokio::spawn(async move {
+                match hbb_common::timeout(HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await {
+                    Ok(Ok(stream)) => handler(Box::new(stream), addr).await,
+                    Ok(Err(err)) => log::warn!("Relay control TLS handshake from {} failed: {}", addr, err),
+                    Err(_) => log::warn!("Relay control TLS handshake from {} timed out", addr),
+                }
+            });
+        }
+    }
+}
+
+#[cfg(all(test, feature = "mtls"))]
+mod tests {
+    use super::*;
+    use hbb_common::tokio::{
+        self,
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+    use std::sync::Arc;
+
+    #[test]
+    fn test_relay_host() {
+        assert_eq!(relay_host("relay.example.com"), "relay.example.com");
+        assert_eq!(relay_host("relay.example.com:21117"), "relay.example.com");
+        assert_eq!(relay_host("192.0.2.1:21117"), "192.0.2.1");
+        assert_eq!(relay_host("[2001:db8::1]:21117"), "2001:db8::1");
+        assert_eq!(relay_host("2001:db8::1"), "2001:db8::1");
+    }
+
+    #[tokio::test]
+    async fn test_mutual_tls() {
+        let db = EnterpriseDatabase::memory().await.unwrap();
+        assert!(issue(&db, "127.0.0.1", "admin").await.is_err());
+        assert!(set_enabled(&db, true, "admin").await.is_err());
+        let ca = init_ca(&db, "admin").await.unwrap();
+        assert!(!ca.enabled && ca.ca_fingerprint.is_some());
+        let bundle = issue(&db, "127.0.0.1", "admin").await.unwrap();
+        let other = issue(&db, "relay.example.com", "admin").await.unwrap();
+        assert_eq!(status(&db).await.unwrap().issued.len(), 2);
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let acceptor = |bundle: &IssuedBundle| {
+            let config = imp::server_config(&bundle.ca_pem, &bundle.cert_pem, &bundle.key_pem).unwrap();
+            tokio_rustls::TlsAcceptor::from(Arc::new(config))
+        };
+        let (good, wrong_name) = (acceptor(&bundle), acceptor(&other));
+        tokio::spawn(async move {
+            for acceptor in [good.clone(), wrong_name, good.clone(), good] {
+                let (stream, _) = listener.accept().await.unwrap();
+                if let Ok(mut stream) = acceptor.accept(stream).await {
+                    let mut buf = [0u8; 5];
+                    if stream.read_exact(&mut buf).await.is_ok() {
+                        stream.write_all(&buf).await.ok();
+                        stream.shutdown().await.ok();
+                    }
+                }
+            }
+        });
+
+        // 未启用时不连接
+        assert!(connect_to("127.0.0.1", &addr).await.is_err());
+        set_enabled(&db, true, "admin").await.unwrap();
+        let mut stream = connect_to("127.0.0.1", &addr).await.unwrap();
+        stream.write_all(b"hello").await.unwrap();
+        let mut res = String::new();
+        stream.read_to_string(&mut res).await.unwrap();
+        assert_eq!(res, "hello");
+
+        // 中继证书的名称与地址不符
+        assert!(connect_to("127.0.0.1", &addr).await.is_err());
+
+        // 没有客户端证书的连接被中继拒绝
+        let mut roots = rustls::RootCertStore::empty();
+        for der in imp::pem_certs(&bundle.ca_pem).unwrap() {
+            roots.add(&rustls::Certificate(der)).unwrap();
+        }
+        let config = rustls::ClientConfig::builder()
+            .with_safe_defaults()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        let name = rustls::ServerName::try_from("127.0.0.1").unwrap();
+        let res = async {
+            let tcp = tokio::net::TcpStream::connect(&addr).await?;
+            let mut stream = tokio_rustls::TlsConnector::from(Arc::new(config)).connect(name, tcp).await?;
+            stream.write_all(b"hello").await?;
+            let mut res = String::new();
+            stream.read_to_string(&mut res).await?;
+            std::io::Result::Ok(res)
+        }
+        .await;
+        assert!(res.map(|x| x.is_empty()).unwrap_or(true));
+
+        // 吊销后拒绝连接
+        assert!(revoke(&db, &bundle.cert.fingerprint, "admin").await.unwrap().is_some());
+        assert!(revoke(&db, &bundle.cert.fingerprint, "admin").await.unwrap().is_none());
+        assert!(connect_to("127.0.0.1", &addr).await.is_err());
+        set_enabled(&db, false, "admin").await.unwrap();
+    }
+}
@@ -0,0 +1,353 @@
+// 自定义字段模块 - 管理员为设备和用户定义额外的元数据字段(资产编号、成本中心、位置等)及其类型和校验规则。
+// 字段定义保存在设置表中，字段值以 JSON 对象保存在 custom_field_values 表(每个设备或用户一行)。
+// 必填字段只在写入字段值时检查，自动注册的设备在管理员补录前没有字段值
+use crate::enterprise_database::EnterpriseDatabase;
+use crate::validation::{self, Payload};
+use hbb_common::{bail, tokio::sync::RwLock, ResultType};
+use regex::Regex;
+use schemars::JsonSchema;
+use serde_derive::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet};
+use validator::{Validate, ValidationErrors};
+
+pub const CUSTOM_FIELDS_KEY: &str = "custom_fields";
+const MAX_FIELDS: usize = 100;
+const DEFAULT_MAX_LENGTH: usize = 256;
+const MAX_TEXT_LENGTH: usize = 4096;
+
+lazy_static::lazy_static! {
+    static ref CONFIG: RwLock<CustomFieldsConfig> = Default::default();
+    static ref KEY_REGEX: Regex = Regex::new("^[a-z][a-z0-9_]{0,31}$").unwrap();
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum FieldEntity {
+    Device,
+    User,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum FieldType {
+    Text,
+    Number,
+    Boolean,
+    Date, // YYYY-MM-DD
+    Select,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct FieldDefinition {
+    pub key: String, // 小写字母、数字和下划线
+    pub label: String,
+    pub entity: FieldEntity,
+    #[serde(rename = "type")]
+    pub field_type: FieldType,
+    #[serde(default)]
+    pub required: bool,
+    #[serde(default)]
+    pub options: Vec<String>, // select 的可选值
+    #[serde(default)]
+    pub pattern: Option<String>, // text 的正则
+    #[serde(default)]
+    pub max_length: Option<usize>, // text 的最大长度，默认 256
+    #[serde(default)]
+    pub min: Option<f64>, // number 的取值范围
+    #[serde(default)]
+    pub max: Option<f64>,
+}
+
+// 按字段查询的结果
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct FieldValues {
+    pub id: String,
+    pub values: BTreeMap<String, Value>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema, Validate)]
+pub struct CustomFieldsConfig {
+    #[serde(default)]
+    #[validate(length(max = 100))]
+    pub fields: Vec<FieldDefinition>,
+}
+
+impl Payload for CustomFieldsConfig {}
+
+// PUT /api/devices/:id/fields、/api/users/:id/fields 的请求体，字段是否已定义及取值类型由 set_values 检查
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(transparent)]
+pub struct FieldValuesUpdate(pub BTreeMap<String, Value>);
+
+impl Validate for FieldValuesUpdate {
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        if self.0.len() > MAX_FIELDS {
+            return Err(validation::map_error("fields", "length", format!("最多 {} 个字段", MAX_FIELDS)));
+        }
+        match self.0.keys().find(|key| !KEY_REGEX.is_match(key)) {
+            Some(key) => Err(validation::map_error("fields", "field_key", format!("字段名 {} 格式错误", key))),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Payload for FieldValuesUpdate {}
+
+impl FieldDefinition {
+    fn validate(&self) -> ResultType<()> {
+        if !KEY_REGEX.is_match(&self.key) {
+            bail!("invalid field key {}", self.key);
+        }
+        if self.label.trim().is_empty() {
+            bail!("{}: label is required", self.key);
+        }
+        match self.field_type {
+            FieldType::Select => {
+                if self.options.is_empty() {
+                    bail!("{}: select field requires options", self.key);
+                }
+            }
+            FieldType::Text => {
+                if let Some(pattern) = &self.pattern {
+                    if let Err(err) = Regex::new(pattern) {
+                        bail!("{}: invalid pattern: {}", self.key, err);
+                    }
+                }
+                if self.max_length.map(|x| x == 0 || x > MAX_TEXT_LENGTH).unwrap_or(false) {
+                    bail!("{}: max_length must be 1-{}", self.key, MAX_TEXT_LENGTH);
+                }
+            }
+            FieldType::Number => {
+                if let (Some(min), Some(max)) = (self.min, self.max) {
+                    if min > max {
+                        bail!("{}: min is greater than max", self.key);
+                    }
+                }
+            }
+            FieldType::Boolean | FieldType::Date => {}
+        }
+        Ok(())
+    }
+
+    // 检查字段值，返回规范化后的值
+    pub fn check(&self, value: &Value) -> ResultType<Value> {
+        match (self.field_type, value) {
+            (FieldType::Text, Value::String(s)) => {
+                let s = s.trim();
+                if s.chars().count() > self.max_length.unwrap_or(DEFAULT_MAX_LENGTH) {
+                    bail!("{}: value is too long", self.key);
+                }
+                if let Some(pattern) = &self.pattern {
+                    if !Regex::new(pattern)?.is_match(s) {
+                        bail!("{}: value does not match pattern", self.key);
+                    }
+                }
+                Ok(Value::String(s.to_owned()))
+            }
+            (FieldType::Number, Value::Number(n)) => {
+                let x = n.as_f64().unwrap_or_default();
+                if self.min.map(|min| x < min).unwrap_or(false) || self.max.map(|max| x > max).unwrap_or(false) {
+                    bail!("{}: value is out of range", self.key);
+                }
+                Ok(value.clone())
+            }
+            (FieldType::Boolean, Value::Bool(_)) => Ok(value.clone()),
+            (FieldType::Date, Value::String(s)) => {
+                if chrono::NaiveDate::parse_from_str(s, "%Y-%m-%d").is_err() {
+                    bail!("{}: date must be YYYY-MM-DD", self.key);
+                }
+                Ok(value.clone())
+            }
+            (FieldType::Select, Value::String(s)) => {
+                if !self.options.contains(s) {
+                    bail!("{}: {} is not an option", self.key, s);
+                }
+                Ok(value.clone())
+            }
+            _ => bail!("{}: expected {:?} value", self.key, self.field_type),
+        }
+    }
+
+    // 把查询参数中的字符串转为字段类型对应的值
+    pub fn parse(&self, s: &str) -> ResultType<Value> {
+        let value = match self.field_type {
+            FieldType::Number => match s.parse::<f64>().ok().and_then(serde_json::Number::from_f64) {
+                Some(n) => Value::Number(n),
+                None => bail!("{}: invalid number", self.key),
+            },
+            FieldType::Boolean => match s {
+                "true" | "1" => Value::Bool(true),
+                "false" | "0" => Value::Bool(false),
+                _ => bail!("{}: invalid boolean", self.key),
+            },
+            _ => Value::String(s.to_owned()),
+        };
+        Ok(value)
+    }
+}
+
+impl CustomFieldsConfig {
+    pub fn validate(&self) -> ResultType<()> {
+        if self.fields.len() > MAX_FIELDS {
+            bail!("at most {} fields", MAX_FIELDS);
+        }
+        let mut keys = BTreeSet::new();
+        for field in self.fields.iter() {
+            field.validate()?;
+            if !keys.insert((field.entity, field.key.as_str())) {
+                bail!("duplicate field {}", field.key);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn field(&self, entity: FieldEntity, key: &str) -> Option<&FieldDefinition> {
+        self.fields.iter().find(|f| f.entity == entity && f.key == key)
+    }
+
+    // 检查要保存的全部字段值；null 表示清除该字段
+    pub fn check_values(
+        &self,
+        entity: FieldEntity,
+        values: &BTreeMap<String, Value>,
+    ) -> ResultType<BTreeMap<String, Value>> {
+        let mut res = BTreeMap::new();
+        for (key, value) in values.iter() {
+            let field = match self.field(entity, key) {
+                Some(field) => field,
+                None => bail!("unknown field {}", key),
+            };
+            if !value.is_null() {
+                res.insert(key.clone(), field.check(value)?);
+            }
+        }
+        for field in self.fields.iter().filter(|f| f.entity == entity && f.required) {
+            if !res.contains_key(&field.key) {
+                bail!("{} is required", field.key);
+            }
+        }
+        Ok(res)
+    }
+
+    // 去掉已删除定义的字段值
+    pub fn visible(&self, entity: FieldEntity, mut values: BTreeMap<String, Value>) -> BTreeMap<String, Value> {
+        values.retain(|key, _| self.field(entity, key).is_some());
+        values
+    }
+}
+
+pub async fn reload(db: &EnterpriseDatabase) -> ResultType<()> {
+    let config = match db.get_setting(CUSTOM_FIELDS_KEY).await? {
+        Some(v) => serde_json::from_str(&v)?,
+        None => CustomFieldsConfig::default(),
+    };
+    *CONFIG.write().await = config;
+    Ok(())
+}
+
+pub async fn get() -> CustomFieldsConfig {
+    CONFIG.read().await.clone()
+}
+
+pub async fn update(db: &EnterpriseDatabase, config: CustomFieldsConfig, updated_by: &str) -> ResultType<()> {
+    config.validate()?;
+    db.set_setting(CUSTOM_FIELDS_KEY, &serde_json::to_string(&config)?, Some(updated_by))
+        .await?;
+    *CONFIG.write().await = config;
+    Ok(())
+}
+
+pub async fn get_values(db: &EnterpriseDatabase, entity: FieldEntity, id: &str) -> ResultType<BTreeMap<String, Value>> {
+    let values = db.get_custom_field_values(entity, id).await?;
+    Ok(get().await.visible(entity, values))
+}
+
+pub async fn set_values(
+    db: &EnterpriseDatabase,
+    entity: FieldEntity,
+    id: &str,
+    values: &BTreeMap<String, Value>,
+) -> ResultType<BTreeMap<String, Value>> {
+    let values = get().await.check_values(entity, values)?;
+    db.set_custom_field_values(entity, id, &values).await?;
+    Ok(values)
+}
+
+// 按字段值查询设备或用户：value 精确匹配，min/max 用于数值和日期范围
+#[allow(clippy::too_many_arguments)]
+pub async fn search(
+    db: &EnterpriseDatabase,
+    entity: FieldEntity,
+    key: &str,
+    value: Option<&str>,
+    min: Option<&str>,
+    max: Option<&str>,
+    limit: i64,
+    offset: i64,
+) -> ResultType<Vec<FieldValues>> {
+    let config = get().await;
+    let field = match config.field(entity, key) {
+        Some(field) => field,
+        None => bail!("unknown field {}", key),
+    };
+    let value = match value {
+        Some(v) => Some(serde_json::to_string(&field.parse(v)?)?),
+        None => None,
+    };
+    let min = match min {
+        Some(v) => Some(serde_json::to_string(&field.parse(v)?)?),
+        None => None,
+    };
+    let max = match max {
+        Some(v) => Some(serde_json::to_string(&field.parse(v)?)?),
+        None => None,
+    };
+    let path = format!("$.{}", field.key);
+    let mut res = db
+        .search_custom_field_values(entity, &path, value.as_deref(), min.as_deref(), max.as_deref(), limit, offset)
+        .await?;
+    for x in res.iter_mut() {
+        x.values = config.visible(entity, std::mem::take(&mut x.values));
+    }
+    Ok(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_custom_field_values() {
+        let config: CustomFieldsConfig = serde_json::from_str(
+            r#"{"fields": [
+                {"key": "asset_tag", "label": "资产编号", "entity": "device", "type": "text", "required": true, "pattern": "^AT-\\d+$"},
+                {"key": "cost_center", "label": "成本中心", "entity": "device", "type": "select", "options": ["CC-1", "CC-2"]},
+                {"key": "floor", "label": "楼层", "entity": "device", "type": "number", "min": -5, "max": 100},
+                {"key": "asset_tag", "label": "工号", "entity": "user", "type": "text"}
+            ]}"#,
+        )
+        .unwrap();
+        assert!(config.validate().is_ok());
+
+        let values = |s: &str| serde_json::from_str::<BTreeMap<String, Value>>(s).unwrap();
+        let res = config
+            .check_values(FieldEntity::Device, &values(r#"{"asset_tag": " AT-1 ", "floor": 3, "cost_center": null}"#))
+            .unwrap();
+        assert_eq!(res["asset_tag"], Value::String("AT-1".to_owned()));
+        assert!(!res.contains_key("cost_center"));
+        assert!(config.check_values(FieldEntity::Device, &values(r#"{"floor": 3}"#)).is_err());
+        assert!(config.check_values(FieldEntity::Device, &values(r#"{"asset_tag": "X-1"}"#)).is_err());
+        assert!(config
+            .check_values(FieldEntity::Device, &values(r#"{"asset_tag": "AT-1", "floor": 101}"#))
+            .is_err());
+        assert!(config
+            .check_values(FieldEntity::Device, &values(r#"{"asset_tag": "AT-1", "cost_center": "CC-3"}"#))
+            .is_err());
+        assert!(config.check_values(FieldEntity::User, &values(r#"{"floor": 1}"#)).is_err());
+
+        let mut dup = config.clone();
+        dup.fields.push(dup.fields[0].clone());
+        assert!(dup.validate().is_err());
+    }
+}
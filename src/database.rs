@@ -142,6 +142,53 @@ impl Database {
         .await?;
         Ok(())
     }
+
+    #[allow(dead_code)]
+    pub async fn list_peers(&self) -> ResultType<Vec<Peer>> {
+        Ok(sqlx::query_as!(
+            Peer,
+            "select guid, id, uuid, pk, user, status, info from peer order by created_at"
+        )
+        .fetch_all(self.pool.get().await?.deref_mut())
+        .await?)
+    }
+
+    // 按原 guid 导入其他数据库中的设备，id 已存在时跳过；返回是否导入
+    #[allow(dead_code)]
+    pub async fn import_peer(&self, peer: &Peer) -> ResultType<bool> {
+        let res = sqlx::query!(
+            "insert or ignore into peer(guid, id, uuid, pk, user, status, info) values(?, ?, ?, ?, ?, ?, ?)",
+            peer.guid,
+            peer.id,
+            peer.uuid,
+            peer.pk,
+            peer.user,
+            peer.status,
+            peer.info
+        )
+        .execute(self.pool.get().await?.deref_mut())
+        .await?;
+        Ok(res.rows_affected() > 0)
+    }
+
+    // 写入设备，id 已存在时更新 uuid、公钥和 info
+    #[allow(dead_code)]
+    pub async fn upsert_peer(&self, peer: &Peer) -> ResultType<()> {
+        sqlx::query!(
+            "insert into peer(guid, id, uuid, pk, user, status, info) values(?, ?, ?, ?, ?, ?, ?)
+            on conflict(id) do update set uuid=excluded.uuid, pk=excluded.pk, info=excluded.info",
+            peer.guid,
+            peer.id,
+            peer.uuid,
+            peer.pk,
+            peer.user,
+            peer.status,
+            peer.info
+        )
+        .execute(self.pool.get().await?.deref_mut())
+        .await?;
+        Ok(())
+    }
 }
 
 #[cfg(test)]
@@ -0,0 +1,296 @@
+// 数据库连接池指标模块 - 统计从连接池取连接的次数、需要等待的次数和等待耗时，区分 Web 接口和
+// 信令/中继及后台任务(服务端)两条路径，与连接池的连接数、使用中、等待中一起由 /metrics 提供。
+// 后台每分钟检查一次，期间出现等待时记录警告，两条路径都在等待时注明争用，并按峰值等待数给出建议的
+// MAX_DATABASE_CONNECTIONS。连接池大小在启动时确定，调整后需重启生效
+use axum::{extract::Request, middleware::Next, response::Response};
+use deadpool::Status;
+use hbb_common::{
+    log,
+    tokio::{
+        self,
+        time::{interval, Duration},
+    },
+};
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::time::Instant;
+
+const CHECK_INTERVAL_SECS: u64 = 60;
+// 等待超过该时长的取连接计为慢
+const SLOW_WAIT_MS: u64 = 100;
+// 建议的连接数上限，SQLite 同时只有一个写入者，连接过多无益
+const MAX_RECOMMENDED: usize = 64;
+
+tokio::task_local! {
+    static WEB_API: ();
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Source {
+    WebApi,
+    Server,
+}
+
+const SOURCES: [Source; 2] = [Source::WebApi, Source::Server];
+
+impl Source {
+    fn current() -> Self {
+        if WEB_API.try_with(|_| ()).is_ok() {
+            Source::WebApi
+        } else {
+            Source::Server
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            Source::WebApi => "web_api",
+            Source::Server => "server",
+        }
+    }
+}
+
+struct Counters {
+    acquired: AtomicU64,
+    waited: AtomicU64,
+    wait_micros: AtomicU64,
+    slow: AtomicU64,
+    errors: AtomicU64,
+}
+
+impl Counters {
+    const fn new() -> Self {
+        Self {
+            acquired: AtomicU64::new(0),
+            waited: AtomicU64::new(0),
+            wait_micros: AtomicU64::new(0),
+            slow: AtomicU64::new(0),
+            errors: AtomicU64::new(0),
+        }
+    }
+}
+
+static COUNTERS: [Counters; 2] = [Counters::new(), Counters::new()];
+// 上次检查以来的峰值等待数
+static PEAK_WAITERS: AtomicUsize = AtomicUsize::new(0);
+
+fn counters(source: Source) -> &'static Counters {
+    &COUNTERS[source as usize]
+}
+
+// 连接池状态，deadpool 的 available 为负数时表示等待连接的请求数
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PoolStats {
+    pub max_size: usize,
+    pub size: usize,
+    pub in_use: usize,
+    pub waiters: usize,
+}
+
+impl From<Status> for PoolStats {
+    fn from(status: Status) -> Self {
+        let idle = status.available.max(0) as usize;
+        Self {
+            max_size: status.max_size,
+            size: status.size,
+            in_use: status.size.saturating_sub(idle),
+            waiters: (-status.available).max(0) as usize,
+        }
+    }
+}
+
+// Web 接口处理期间取连接计入 web_api
+pub async fn middleware(req: Request, next: Next) -> Response {
+    WEB_API.scope((), next.run(req)).await
+}
+
+// 取连接并记录等待；status 为取连接前的连接池状态
+pub async fn acquire<T, E>(status: Status, get: impl Future<Output = Result<T, E>>) -> Result<T, E> {
+    let stats = PoolStats::from(status);
+    let counters = counters(Source::current());
+    // 没有空闲连接且已达上限时需要等待其他请求归还
+    if status.available <= 0 && stats.size >= stats.max_size {
+        counters.waited.fetch_add(1, Ordering::Relaxed);
+        PEAK_WAITERS.fetch_max(stats.waiters + 1, Ordering::Relaxed);
+    }
+    let started = Instant::now();
+    let res = get.await;
+    let elapsed = started.elapsed();
+    counters.acquired.fetch_add(1, Ordering::Relaxed);
+    counters
+        .wait_micros
+        .fetch_add(elapsed.as_micros() as u64, Ordering::Relaxed);
+    if elapsed.as_millis() as u64 >= SLOW_WAIT_MS {
+        counters.slow.fetch_add(1, Ordering::Relaxed);
+    }
+    if res.is_err() {
+        counters.errors.fetch_add(1, Ordering::Relaxed);
+    }
+    res
+}
+
+// 一个检查周期内两条路径的等待次数和慢取连接次数
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+struct Window {
+    waited: [u64; 2],
+    slow: [u64; 2],
+}
+
+fn snapshot() -> Window {
+    let mut res = Window::default();
+    for source in SOURCES {
+        let counters = counters(source);
+        res.waited[source as usize] = counters.waited.load(Ordering::Relaxed);
+        res.slow[source as usize] = counters.slow.load(Ordering::Relaxed);
+    }
+    res
+}
+
+// 期间有等待时返回警告内容
+fn assess(max_size: usize, delta: &Window, peak_waiters: usize) -> Option<String> {
+    let total: u64 = delta.waited.iter().sum();
+    if total == 0 {
+        return None;
+    }
+    let contended = delta.waited.iter().all(|x| *x > 0);
+    let recommended = (max_size + peak_waiters.max(1)).min(MAX_RECOMMENDED);
+    let mut msg = format!(
+        "Database pool exhausted {} times in the last {}s (web_api {}, server {}; slow >= {}ms: {}), peak {} waiters",
+        total,
+        CHECK_INTERVAL_SECS,
+        delta.waited[Source::WebApi as usize],
+        delta.waited[Source::Server as usize],
+        SLOW_WAIT_MS,
+        delta.slow.iter().sum::<u64>(),
+        peak_waiters
+    );
+    if contended {
+        msg.push_str("; the web API and the rendezvous server contend for connections");
+    }
+    if recommended > max_size {
+        msg.push_str(&format!(
+            "; consider raising MAX_DATABASE_CONNECTIONS from {} to {}",
+            max_size, recommended
+        ));
+    }
+    Some(msg)
+}
+
+// 定期检查连接池争用，status 返回当前连接池状态
+pub fn start(status: impl Fn() -> Status + Send + 'static) {
+    tokio::spawn(async move {
+        let mut interval = interval(Duration::from_secs(CHECK_INTERVAL_SECS));
+        let mut last = snapshot();
+        loop {
+            interval.tick().await;
+            let current = snapshot();
+            let mut delta = Window::default();
+            for i in 0..SOURCES.len() {
+                delta.waited[i] = current.waited[i] - last.waited[i];
+                delta.slow[i] = current.slow[i] - last.slow[i];
+            }
+            last = current;
+            let peak = PEAK_WAITERS.swap(0, Ordering::Relaxed);
+            if let Some(msg) = assess(status().max_size, &delta, peak) {
+                log::warn!("{}", msg);
+            }
+        }
+    });
+}
+
+// Prometheus 文本格式
+pub fn prometheus(status: Status) -> String {
+    let stats = PoolStats::from(status);
+    let mut out = String::new();
+    let gauges = [
+        ("db_pool_max_connections", "Maximum database connections", stats.max_size),
+        ("db_pool_connections", "Open database connections", stats.size),
+        ("db_pool_connections_in_use", "Database connections in use", stats.in_use),
+        ("db_pool_waiters", "Requests waiting for a database connection", stats.waiters),
+    ];
+    for (name, help, value) in gauges {
+        crate::host_stats::metric(&mut out, name, "gauge", help, &[(String::new(), value as u64)]);
+    }
+    let counter_metrics: [(&str, &str, fn(&Counters) -> u64); 5] = [
+        ("db_pool_acquisitions_total", "Database connections acquired", |c| {
+            c.acquired.load(Ordering::Relaxed)
+        }),
+        (
+            "db_pool_waits_total",
+            "Acquisitions that waited because the pool was exhausted",
+            |c| c.waited.load(Ordering::Relaxed),
+        ),
+        (
+            "db_pool_wait_microseconds_total",
+            "Time spent acquiring database connections",
+            |c| c.wait_micros.load(Ordering::Relaxed),
+        ),
+        (
+            "db_pool_slow_acquisitions_total",
+            "Acquisitions that took at least 100ms",
+            |c| c.slow.load(Ordering::Relaxed),
+        ),
+        ("db_pool_acquire_errors_total", "Failed acquisitions", |c| {
+            c.errors.load(Ordering::Relaxed)
+        }),
+    ];
+    for (name, help, value) in counter_metrics {
+        let values: Vec<(String, u64)> = SOURCES
+            .iter()
+            .map(|s| (format!("source=\"{}\"", s.label()), value(counters(*s))))
+            .collect();
+        crate::host_stats::metric(&mut out, name, "counter", help, &values);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pool_stats() {
+        let stats = PoolStats::from(Status {
+            max_size: 10,
+            size: 10,
+            available: -3,
+        });
+        assert_eq!((stats.in_use, stats.waiters), (10, 3));
+        let stats = PoolStats::from(Status {
+            max_size: 10,
+            size: 4,
+            available: 1,
+        });
+        assert_eq!((stats.in_use, stats.waiters), (3, 0));
+    }
+
+    #[test]
+    fn test_assess() {
+        assert!(assess(10, &Window::default(), 0).is_none());
+        let msg = assess(
+            10,
+            &Window {
+                waited: [5, 0],
+                slow: [1, 0],
+            },
+            4,
+        )
+        .unwrap();
+        assert!(msg.contains("from 10 to 14"));
+        assert!(!msg.contains("contend"));
+        let msg = assess(
+            60,
+            &Window {
+                waited: [2, 3],
+                slow: [0, 0],
+            },
+            10,
+        )
+        .unwrap();
+        assert!(msg.contains("contend"));
+        assert!(msg.contains("to 64"));
+        assert!(!assess(64, &Window { waited: [1, 1], slow: [0, 0] }, 2)
+            .unwrap()
+            .contains("MAX_DATABASE_CONNECTIONS"));
+    }
+}
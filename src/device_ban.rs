@@ -0,0 +1,208 @@
+// 设备封禁模块 - 封禁设备ID/UUID，立即移出在线列表并断开中继会话，支持原因和过期时间
+use crate::common::now;
+use crate::enterprise_database::{DeviceBan, EnterpriseDatabase};
+use crate::relay_sessions;
+use hbb_common::{
+    log,
+    tokio::sync::{mpsc, Mutex, RwLock},
+    ResultType,
+};
+use std::collections::HashMap;
+
+lazy_static::lazy_static! {
+    static ref BANS: RwLock<HashMap<String, DeviceBan>> = Default::default();
+    // 封禁后需要从 PeerMap 移除的设备ID，由信令服务器消费
+    static ref EVICT_TX: Mutex<Option<mpsc::UnboundedSender<String>>> = Default::default();
+}
+
+fn is_active(ban: &DeviceBan) -> bool {
+    ban.expires_at.map(|x| x > now()).unwrap_or(true)
+}
+
+pub async fn reload(db: &EnterpriseDatabase) -> ResultType<()> {
+    let bans = db.list_device_bans().await?;
+    log::info!("{} device bans loaded", bans.len());
+    *BANS.write().await = bans.into_iter().map(|x| (x.device_id.clone(), x)).collect();
+    Ok(())
+}
+
+// 信令服务器启动时订阅，收到设备ID后从在线列表移除
+pub async fn subscribe_evictions() -> mpsc::UnboundedReceiver<String> {
+    let (tx, rx) = mpsc::unbounded_channel();
+    *EVICT_TX.lock().await = Some(tx);
+    rx
+}
+
+pub async fn ban(
+    db: &EnterpriseDatabase,
+    device_id: &str,
+    reason: &str,
+    banned_by: &str,
+    expires_at: Option<u64>,
+) -> ResultType<usize> {
+    // 封禁时即取设备注册时登记的uuid，设备不在线时同样能阻止换ID重新注册
+    let uuid = match db.get_device_uuid(device_id).await {
+        Ok(uuid) => uuid.filter(|x| !x.is_empty()),
+        Err(err) => {
+            log::error!("Failed to load uuid of device {}: {}", device_id, err);
+            None
+        }
+    };
+    let ban = DeviceBan {
+        device_id: device_id.to_owned(),
+        uuid,
+        reason: reason.to_owned(),
+        banned_by: banned_by.to_owned(),
+        created_at: now(),
+        expires_at,
+    };
+    db.upsert_device_ban(&ban).await?;
+    {
+        let mut bans = BANS.write().await;
+        let uuid = ban
+            .uuid
+            .clone()
+            .or_else(|| bans.get(device_id).and_then(|x| x.uuid.clone()));
+        bans.insert(device_id.to_owned(), DeviceBan { uuid, ..ban });
+    }
+    if let Some(tx) = EVICT_TX.lock().await.as_ref() {
+        tx.send(device_id.to_owned()).ok();
+    }
+    let killed = relay_sessions::kill_device_sessions(device_id).await;
+    log::info!(
+        "Device {} banned by {}: {}, {} relay sessions dropped",
+        device_id,
+        banned_by,
+        reason,
+        killed
+    );
+    Ok(killed)
+}
+
+pub async fn unban(db: &EnterpriseDatabase, device_id: &str) -> ResultType<bool> {
+    let removed = db.remove_device_ban(device_id).await?;
+    BANS.write().await.remove(device_id);
+    Ok(removed)
+}
+
+// 移出在线列表时以在线设备的uuid为准，覆盖封禁时从设备表取到的uuid
+pub async fn attach_uuid(db: &EnterpriseDatabase, device_id: &str, uuid: &str) {
+    let ban = {
+        let mut bans = BANS.write().await;
+        match bans.get_mut(device_id) {
+            Some(ban) if !uuid.is_empty() => {
+                ban.uuid = Some(uuid.to_owned());
+                ban.clone()
+            }
+            _ => return,
+        }
+    };
+    if let Err(err) = db.upsert_device_ban(&ban).await {
+        log::error!("Failed to save uuid of banned device {}: {}", device_id, err);
+    }
+}
+
+pub async fn list() -> Vec<DeviceBan> {
+    BANS.read()
+        .await
+        .values()
+        .filter(|x| is_active(x))
+        .cloned()
+        .collect()
+}
+
+pub async fn is_banned(device_id: &str) -> bool {
+    BANS.read()
+        .await
+        .get(device_id)
+        .map(is_active)
+        .unwrap_or(false)
+}
+
+pub async fn is_banned_uuid(uuid: &str) -> bool {
+    if uuid.is_empty() {
+        return false;
+    }
+    BANS.read()
+        .await
+        .values()
+        .any(|x| is_active(x) && x.uuid.as_deref() == Some(uuid))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::web_api::fixtures::DeviceBuilder;
+    use hbb_common::tokio::{self, io::AsyncReadExt, net::TcpListener};
+
+    #[tokio::test]
+    async fn test_ban_and_unban() {
+        let db = EnterpriseDatabase::memory().await.unwrap();
+        assert_eq!(ban(&db, "device-ban-1", "stolen", "admin", None).await.unwrap(), 0);
+        assert!(is_banned("device-ban-1").await);
+        assert!(!is_banned("device-ban-2").await);
+        let stored = db.list_device_bans().await.unwrap();
+        assert!(stored.iter().any(|x| x.device_id == "device-ban-1" && x.reason == "stolen"));
+        assert!(list().await.iter().any(|x| x.device_id == "device-ban-1"));
+
+        assert!(unban(&db, "device-ban-1").await.unwrap());
+        assert!(!is_banned("device-ban-1").await);
+        assert!(!unban(&db, "device-ban-1").await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_ban_expiry() {
+        let db = EnterpriseDatabase::memory().await.unwrap();
+        ban(&db, "device-ban-expired", "test", "admin", Some(now() - 1)).await.unwrap();
+        ban(&db, "device-ban-temporary", "test", "admin", Some(now() + 3600)).await.unwrap();
+        assert!(!is_banned("device-ban-expired").await);
+        assert!(is_banned("device-ban-temporary").await);
+        let bans = list().await;
+        assert!(!bans.iter().any(|x| x.device_id == "device-ban-expired"));
+        assert!(bans.iter().any(|x| x.device_id == "device-ban-temporary"));
+    }
+
+    #[tokio::test]
+    async fn test_ban_matches_registered_uuid() {
+        let db = EnterpriseDatabase::memory().await.unwrap();
+        db.register_device(&DeviceBuilder::new("device-ban-uuid").build()).await.unwrap();
+        let uuid = base64::encode(b"device-ban-uuid");
+        db.update_device_uuid("device-ban-uuid", &uuid).await.unwrap();
+        assert!(!is_banned_uuid(&uuid).await);
+
+        // 设备不在线时封禁也记录uuid
+        ban(&db, "device-ban-uuid", "test", "admin", None).await.unwrap();
+        assert!(is_banned_uuid(&uuid).await);
+        assert!(!is_banned_uuid("").await);
+        let stored = db.list_device_bans().await.unwrap();
+        let stored = stored.iter().find(|x| x.device_id == "device-ban-uuid").unwrap();
+        assert_eq!(stored.uuid.as_deref(), Some(uuid.as_str()));
+
+        // 移出在线列表时以在线设备的uuid为准
+        let online = base64::encode(b"device-ban-uuid-online");
+        attach_uuid(&db, "device-ban-uuid", &online).await;
+        assert!(is_banned_uuid(&online).await);
+        assert!(!is_banned_uuid(&uuid).await);
+
+        unban(&db, "device-ban-uuid").await.unwrap();
+        assert!(!is_banned_uuid(&online).await);
+    }
+
+    #[tokio::test]
+    async fn test_ban_kills_relay_sessions() {
+        let db = EnterpriseDatabase::memory().await.unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let relay = listener.local_addr().unwrap().to_string();
+        relay_sessions::record("device-ban-session", &relay, "device-ban-relay").await;
+
+        let relay_cmd = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut cmd = String::new();
+            stream.read_to_string(&mut cmd).await.unwrap();
+            cmd
+        });
+        assert_eq!(ban(&db, "device-ban-relay", "test", "admin", None).await.unwrap(), 1);
+        assert_eq!(relay_cmd.await.unwrap(), "kill-session device-ban-session");
+        assert!(relay_sessions::sessions_of("device-ban-relay").await.is_empty());
+    }
+}
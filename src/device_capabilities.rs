@@ -0,0 +1,131 @@
+// 设备能力登记模块 - 客户端在 /api/sysinfo 中上报本机可用的视频编码器(编码格式及所用的硬件加速，
+// 如 NVENC、QSV、VAAPI)，按设备保存在 device_capabilities 表。编码推荐据此只选择设备能编码的格式，
+// 只有设备有对应的硬件编码器时才推荐 H265/AV1 高码率配置。未上报过的设备按只支持软件 H264 处理
+use crate::enterprise_database::EnterpriseDatabase;
+use crate::performance_optimization::CodecType;
+use hbb_common::{bail, ResultType};
+use schemars::JsonSchema;
+use serde_derive::{Deserialize, Serialize};
+
+const MAX_ENCODERS: usize = 32;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum HwAccel {
+    Nvenc,
+    Qsv,
+    Vaapi,
+    Amf,
+    VideoToolbox,
+    MediaCodec,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct EncoderCapability {
+    pub codec: CodecType,
+    // 软件编码为空
+    #[serde(default)]
+    pub hardware: Option<HwAccel>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct DeviceCapabilities {
+    pub device_id: String,
+    pub encoders: Vec<EncoderCapability>,
+    pub updated_at: u64,
+}
+
+impl DeviceCapabilities {
+    pub fn supports(&self, codec: &CodecType) -> bool {
+        self.encoders.iter().any(|e| e.codec == *codec)
+    }
+
+    pub fn hardware(&self, codec: &CodecType) -> Option<HwAccel> {
+        self.encoders
+            .iter()
+            .filter(|e| e.codec == *codec)
+            .find_map(|e| e.hardware)
+    }
+
+    // 按给定的优先顺序取第一个设备支持的格式，hardware_only 时只考虑硬件编码器
+    pub fn first_of(&self, preference: &[CodecType], hardware_only: bool) -> Option<CodecType> {
+        preference
+            .iter()
+            .find(|c| {
+                if hardware_only {
+                    self.hardware(c).is_some()
+                } else {
+                    self.supports(c)
+                }
+            })
+            .cloned()
+    }
+}
+
+// 校验并去重上报的编码器列表
+pub fn normalize(device_id: &str, encoders: Vec<EncoderCapability>, now: u64) -> ResultType<DeviceCapabilities> {
+    if encoders.len() > MAX_ENCODERS {
+        bail!("at most {} encoders", MAX_ENCODERS);
+    }
+    let mut res: Vec<EncoderCapability> = Vec::new();
+    for encoder in encoders {
+        if !res.contains(&encoder) {
+            res.push(encoder);
+        }
+    }
+    Ok(DeviceCapabilities {
+        device_id: device_id.to_owned(),
+        encoders: res,
+        updated_at: now,
+    })
+}
+
+// 客户端上报的编码器列表，未上报(旧版客户端)时保留原有记录
+pub async fn save(
+    db: &EnterpriseDatabase,
+    device_id: &str,
+    encoders: Option<Vec<EncoderCapability>>,
+) -> ResultType<()> {
+    let encoders = match encoders {
+        Some(encoders) => encoders,
+        None => return Ok(()),
+    };
+    let capabilities = normalize(device_id, encoders, crate::common::now())?;
+    db.save_device_capabilities(&capabilities).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_capabilities() {
+        let encoders: Vec<EncoderCapability> = serde_json::from_str(
+            r#"[{"codec": "H264", "hardware": "nvenc"}, {"codec": "H265", "hardware": "nvenc"},
+                {"codec": "VP9"}, {"codec": "H264", "hardware": "nvenc"}]"#,
+        )
+        .unwrap();
+        let caps = normalize("123", encoders, 1).unwrap();
+        assert_eq!(caps.encoders.len(), 3);
+        assert!(caps.supports(&CodecType::VP9));
+        assert!(!caps.supports(&CodecType::AV1));
+        assert_eq!(caps.hardware(&CodecType::H265), Some(HwAccel::Nvenc));
+        assert_eq!(caps.hardware(&CodecType::VP9), None);
+        assert_eq!(
+            caps.first_of(&[CodecType::AV1, CodecType::VP9, CodecType::H265], true),
+            Some(CodecType::H265)
+        );
+        assert_eq!(
+            caps.first_of(&[CodecType::AV1, CodecType::VP9, CodecType::H265], false),
+            Some(CodecType::VP9)
+        );
+        let too_many = vec![
+            EncoderCapability {
+                codec: CodecType::H264,
+                hardware: None
+            };
+            MAX_ENCODERS + 1
+        ];
+        assert!(normalize("123", too_many, 1).is_err());
+    }
+}
@@ -0,0 +1,141 @@
+// 设备消息模块 - 管理员向设备组/设备推送通知(如 "5 分钟后重启")，受管客户端通过 /api/heartbeat
+// 取回消息并弹出提示，用户确认后通过 /api/messages/:id/ack 回执；每台设备的送达和确认时间保存在数据库
+use crate::enterprise_database::EnterpriseDatabase;
+use hbb_common::{bail, log, ResultType};
+use schemars::JsonSchema;
+use serde_derive::{Deserialize, Serialize};
+
+const MAX_TITLE_LEN: usize = 128;
+const MAX_BODY_LEN: usize = 4096;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum MessageLevel {
+    Info,
+    Warning,
+    Critical,
+}
+
+impl Default for MessageLevel {
+    fn default() -> Self {
+        Self::Info
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct DeviceMessage {
+    pub id: String,
+    pub title: String,
+    pub body: String,
+    pub level: MessageLevel,
+    pub group_ids: Vec<String>,
+    pub device_ids: Vec<String>,
+    pub created_by: String,
+    pub created_at: u64,
+    // 到期后不再下发给尚未取到消息的设备
+    pub expires_at: u64,
+    pub cancelled: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub enum DeliveryStatus {
+    Delivered,
+    Acknowledged,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct MessageDelivery {
+    pub message_id: String,
+    pub device_id: String,
+    pub status: DeliveryStatus,
+    pub delivered_at: u64,
+    pub acknowledged_at: Option<u64>,
+}
+
+// 通过心跳下发给客户端的消息
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct ClientMessage {
+    pub id: String,
+    pub title: String,
+    pub body: String,
+    pub level: MessageLevel,
+    pub created_at: u64,
+}
+
+impl DeviceMessage {
+    pub fn validate(&self) -> ResultType<()> {
+        if self.title.chars().count() > MAX_TITLE_LEN {
+            bail!("title must not exceed {} characters", MAX_TITLE_LEN);
+        }
+        if self.body.trim().is_empty() || self.body.chars().count() > MAX_BODY_LEN {
+            bail!("body must be 1-{} characters", MAX_BODY_LEN);
+        }
+        if self.group_ids.is_empty() && self.device_ids.is_empty() {
+            bail!("message has no target groups or devices");
+        }
+        if self.expires_at <= self.created_at {
+            bail!("message expires before it is created");
+        }
+        Ok(())
+    }
+
+    pub fn targets(&self, device_id: &str, group_ids: &[String]) -> bool {
+        self.device_ids.iter().any(|x| x == device_id) || self.group_ids.iter().any(|g| group_ids.contains(g))
+    }
+}
+
+// 设备心跳时取出待送达的消息，登记为已送达，之后不再重复下发
+pub async fn dispatch(db: &EnterpriseDatabase, device_id: &str) -> ResultType<Vec<ClientMessage>> {
+    let now = crate::common::now();
+    let messages = db.list_active_messages(now).await?;
+    if messages.is_empty() {
+        return Ok(vec![]);
+    }
+    let group_ids = db.get_device_group_ids(device_id).await?;
+    let mut res = vec![];
+    for message in messages.into_iter().filter(|m| m.targets(device_id, &group_ids)) {
+        if db.create_message_delivery(&message.id, device_id, now).await? {
+            log::info!("Delivered message {} to {}", message.id, device_id);
+            res.push(ClientMessage {
+                id: message.id,
+                title: message.title,
+                body: message.body,
+                level: message.level,
+                created_at: message.created_at,
+            });
+        }
+    }
+    Ok(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_message_validation() {
+        let mut message = DeviceMessage {
+            id: "m1".to_owned(),
+            title: "Maintenance".to_owned(),
+            body: "Rebooting in 5 min".to_owned(),
+            level: MessageLevel::Warning,
+            group_ids: vec![],
+            device_ids: vec!["123".to_owned()],
+            created_by: "admin".to_owned(),
+            created_at: 100,
+            expires_at: 200,
+            cancelled: false,
+        };
+        assert!(message.validate().is_ok());
+        assert!(message.targets("123", &[]));
+        assert!(!message.targets("456", &["g1".to_owned()]));
+        message.body = "  ".to_owned();
+        assert!(message.validate().is_err());
+        message.body = "x".repeat(MAX_BODY_LEN + 1);
+        assert!(message.validate().is_err());
+        assert_eq!(
+            serde_json::from_str::<MessageLevel>("\"critical\"").unwrap(),
+            MessageLevel::Critical
+        );
+    }
+}
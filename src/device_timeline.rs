@@ -0,0 +1,357 @@
+// 设备排障时间线模块 - 支持人员为设备记录的自由文本备注，以及把上下线(客户端登记)、远程会话、安全告警、
+// 管理操作和备注按时间合并的时间线，由 /api/devices/:id/timeline 提供，排障时不必在多个页面之间来回查找。
+// 各来源在时间窗口内各取最近的 limit 条，合并后按时间倒序再截取 limit 条
+use crate::advanced_security::SecurityEvent;
+use crate::enterprise_database::{AuditLog, AuditLogFilter, ConnectionSession, EnterpriseDatabase};
+use crate::uptime::StatusChange;
+use hbb_common::{bail, ResultType};
+use schemars::JsonSchema;
+use serde_derive::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+pub const MAX_NOTE_LEN: usize = 4000;
+pub const MAX_NOTES_PER_DEVICE: usize = 500;
+pub const DEFAULT_LIMIT: usize = 100;
+pub const MAX_LIMIT: usize = 1000;
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct DeviceNote {
+    pub id: String,
+    pub device_id: String,
+    pub author_id: String,
+    // 记录时的用户名，用户被擦除后为化名
+    pub author_name: String,
+    pub body: String,
+    pub created_at: u64,
+    pub updated_at: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum EntryKind {
+    Online,
+    Offline,
+    Session,
+    Alert,
+    AdminAction,
+    Note,
+}
+
+pub const ALL_KINDS: &[EntryKind] = &[
+    EntryKind::Online,
+    EntryKind::Offline,
+    EntryKind::Session,
+    EntryKind::Alert,
+    EntryKind::AdminAction,
+    EntryKind::Note,
+];
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct TimelineEntry {
+    pub at: u64,
+    pub kind: EntryKind,
+    pub summary: String,
+    // 操作者、会话发起人或备注作者的用户名，找不到用户时为原始ID
+    pub actor: Option<String>,
+    // 会话、安全事件、审计日志或备注的ID
+    pub ref_id: Option<String>,
+    pub details: Option<String>,
+}
+
+fn secs(t: SystemTime) -> u64 {
+    t.duration_since(UNIX_EPOCH).map(|x| x.as_secs()).unwrap_or_default()
+}
+
+pub fn validate_note(body: &str) -> ResultType<()> {
+    let len = body.trim().chars().count();
+    if len == 0 || len > MAX_NOTE_LEN {
+        bail!("note must be 1-{} characters", MAX_NOTE_LEN);
+    }
+    Ok(())
+}
+
+pub async fn add_note(
+    db: &EnterpriseDatabase,
+    device_id: &str,
+    author_id: &str,
+    author_name: &str,
+    body: &str,
+) -> ResultType<DeviceNote> {
+    validate_note(body)?;
+    if db.list_device_notes(device_id).await?.len() >= MAX_NOTES_PER_DEVICE {
+        bail!("at most {} notes per device", MAX_NOTES_PER_DEVICE);
+    }
+    let now = crate::common::now();
+    let note = DeviceNote {
+        id: uuid::Uuid::new_v4().to_string(),
+        device_id: device_id.to_owned(),
+        author_id: author_id.to_owned(),
+        author_name: author_name.to_owned(),
+        body: body.trim().to_owned(),
+        created_at: now,
+        updated_at: now,
+    };
+    db.save_device_note(&note).await?;
+    Ok(note)
+}
+
+// 逗号分隔的类型，如 "session,alert"；为空时返回全部类型
+pub fn parse_kinds(s: Option<&str>) -> Option<Vec<EntryKind>> {
+    let s = match s.map(str::trim).filter(|x| !x.is_empty()) {
+        Some(s) => s,
+        None => return Some(ALL_KINDS.to_vec()),
+    };
+    s.split(',')
+        .map(|x| serde_json::from_value(serde_json::Value::String(x.trim().to_owned())).ok())
+        .collect()
+}
+
+fn status_entry(change: StatusChange) -> TimelineEntry {
+    let (kind, summary) = if change.online {
+        (EntryKind::Online, "设备上线")
+    } else {
+        (EntryKind::Offline, "设备离线")
+    };
+    TimelineEntry {
+        at: change.changed_at,
+        kind,
+        summary: summary.to_owned(),
+        actor: None,
+        ref_id: None,
+        details: None,
+    }
+}
+
+fn session_entry(session: ConnectionSession) -> TimelineEntry {
+    let summary = match session.duration_seconds {
+        Some(duration) => format!("{}会话，持续{}秒", session.connection_type, duration),
+        None if session.end_time.is_some() => format!("{}会话", session.connection_type),
+        None => format!("{}会话进行中", session.connection_type),
+    };
+    TimelineEntry {
+        at: secs(session.start_time),
+        kind: EntryKind::Session,
+        summary,
+        actor: Some(session.controller_id),
+        ref_id: Some(session.id),
+        details: session.quality_score.map(|x| format!("quality_score={:.2}", x)),
+    }
+}
+
+fn alert_entry(event: SecurityEvent) -> TimelineEntry {
+    let mut details: Vec<_> = event.details.iter().map(|(k, v)| format!("{}={}", k, v)).collect();
+    details.sort();
+    TimelineEntry {
+        at: secs(event.timestamp),
+        kind: EntryKind::Alert,
+        summary: format!("{:?} ({:?})", event.event_type, event.severity),
+        actor: event.user_id,
+        ref_id: Some(event.id),
+        details: Some(details.join(", ")).filter(|x| !x.is_empty()),
+    }
+}
+
+fn audit_entry(log: AuditLog) -> TimelineEntry {
+    let summary = if log.success {
+        log.action
+    } else {
+        format!("{} (失败)", log.action)
+    };
+    TimelineEntry {
+        at: secs(log.timestamp),
+        kind: EntryKind::AdminAction,
+        summary,
+        actor: Some(log.user_id),
+        ref_id: Some(log.id.to_string()),
+        details: log.details,
+    }
+}
+
+fn note_entry(note: DeviceNote) -> TimelineEntry {
+    TimelineEntry {
+        at: note.created_at,
+        kind: EntryKind::Note,
+        summary: note.body,
+        actor: Some(note.author_name),
+        ref_id: Some(note.id),
+        details: None,
+    }
+}
+
+// 按时间倒序，同一时间按类型排列，截取 limit 条
+pub fn merge(mut entries: Vec<TimelineEntry>, limit: usize) -> Vec<TimelineEntry> {
+    entries.sort_by(|a, b| {
+        b.at.cmp(&a.at).then_with(|| {
+            let rank = |k: EntryKind| ALL_KINDS.iter().position(|x| *x == k);
+            rank(a.kind).cmp(&rank(b.kind))
+        })
+    });
+    entries.truncate(limit);
+    entries
+}
+
+pub async fn timeline(
+    db: &EnterpriseDatabase,
+    device_id: &str,
+    since: u64,
+    until: u64,
+    kinds: &[EntryKind],
+    limit: usize,
+) -> ResultType<Vec<TimelineEntry>> {
+    let limit = limit.clamp(1, MAX_LIMIT);
+    let mut entries = vec![];
+    if kinds.contains(&EntryKind::Online) || kinds.contains(&EntryKind::Offline) {
+        // 上下线记录为 (since, until] 区间，取最近的 limit 条
+        let changes = db
+            .list_device_status_changes(Some(device_id), since.saturating_sub(1), until)
+            .await?;
+        let skip = changes.len().saturating_sub(limit);
+        entries.extend(changes.into_iter().skip(skip).map(status_entry));
+    }
+    if kinds.contains(&EntryKind::Session) {
+        let sessions = db
+            .list_device_connection_sessions(device_id, since, until, limit as i64)
+            .await?;
+        entries.extend(sessions.into_iter().map(session_entry));
+    }
+    if kinds.contains(&EntryKind::Alert) {
+        let events = db
+            .list_device_security_events(device_id, since, until, limit as i64)
+            .await?;
+        entries.extend(events.into_iter().map(alert_entry));
+    }
+    if kinds.contains(&EntryKind::AdminAction) {
+        let filter = AuditLogFilter {
+            device_id: Some(device_id.to_owned()),
+            since: Some(since),
+            until: Some(until),
+            ..Default::default()
+        };
+        let logs = db.get_audit_logs(&filter, limit as i64, 0).await?;
+        entries.extend(logs.into_iter().map(audit_entry));
+    }
+    if kinds.contains(&EntryKind::Note) {
+        let notes = db.list_device_notes(device_id).await?;
+        entries.extend(
+            notes
+                .into_iter()
+                .filter(|x| x.created_at >= since && x.created_at <= until)
+                .map(note_entry),
+        );
+    }
+    entries.retain(|x| kinds.contains(&x.kind));
+    let mut entries = merge(entries, limit);
+
+    // 会话发起人和管理操作者显示为用户名
+    let mut names: HashMap<String, Option<String>> = HashMap::new();
+    for entry in entries.iter_mut() {
+        if entry.kind == EntryKind::Note {
+            continue;
+        }
+        let id = match entry.actor.as_ref() {
+            Some(id) => id.clone(),
+            None => continue,
+        };
+        if !names.contains_key(&id) {
+            let name = db.get_user_by_id(&id).await?.map(|x| x.username);
+            names.insert(id.clone(), name);
+        }
+        if let Some(Some(name)) = names.get(&id) {
+            entry.actor = Some(name.clone());
+        }
+    }
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::enterprise_database::EnterpriseDatabase;
+    use hbb_common::tokio;
+
+    fn entry(at: u64, kind: EntryKind) -> TimelineEntry {
+        TimelineEntry {
+            at,
+            kind,
+            summary: String::new(),
+            actor: None,
+            ref_id: None,
+            details: None,
+        }
+    }
+
+    #[test]
+    fn test_merge() {
+        let entries = vec![
+            entry(100, EntryKind::Note),
+            entry(300, EntryKind::Session),
+            entry(200, EntryKind::AdminAction),
+            entry(300, EntryKind::Online),
+        ];
+        let merged = merge(entries, 3);
+        let order: Vec<_> = merged.iter().map(|x| (x.at, x.kind)).collect();
+        assert_eq!(
+            order,
+            vec![
+                (300, EntryKind::Online),
+                (300, EntryKind::Session),
+                (200, EntryKind::AdminAction)
+            ]
+        );
+        assert_eq!(parse_kinds(None).unwrap().len(), ALL_KINDS.len());
+        assert_eq!(
+            parse_kinds(Some("session, admin_action")),
+            Some(vec![EntryKind::Session, EntryKind::AdminAction])
+        );
+        assert!(parse_kinds(Some("session,bogus")).is_none());
+        assert!(validate_note(" ").is_err());
+        assert!(validate_note(&"x".repeat(MAX_NOTE_LEN + 1)).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_timeline() {
+        let db = EnterpriseDatabase::memory().await.unwrap();
+        db.save_device_status("123456789", true, 1000).await.unwrap();
+        db.save_device_status("123456789", false, 2000).await.unwrap();
+        db.save_device_status("987654321", true, 1500).await.unwrap();
+        let admin = db.get_user_by_username("admin").await.unwrap().unwrap();
+        let note = add_note(&db, "123456789", &admin.id, "alice", " 更换了网卡 ").await.unwrap();
+        assert_eq!(note.body, "更换了网卡");
+        let log = AuditLog {
+            id: 0,
+            user_id: admin.id.clone(),
+            device_id: "123456789".to_owned(),
+            action: "ban_device".to_owned(),
+            details: Some("stolen".to_owned()),
+            ip_address: "127.0.0.1".to_owned(),
+            user_agent: None,
+            timestamp: UNIX_EPOCH + std::time::Duration::from_secs(1500),
+            success: true,
+        };
+        db.log_audit(&log).await.unwrap();
+
+        let now = crate::common::now();
+        let entries = timeline(&db, "123456789", 0, now, ALL_KINDS, 10).await.unwrap();
+        let kinds: Vec<_> = entries.iter().map(|x| x.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![EntryKind::Note, EntryKind::Offline, EntryKind::AdminAction, EntryKind::Online]
+        );
+        // 备注显示记录时的作者名，其余显示当前用户名
+        assert_eq!(entries[0].actor.as_deref(), Some("alice"));
+        assert_eq!(entries[2].actor.as_deref(), Some("admin"));
+        assert_eq!(entries[2].details.as_deref(), Some("stolen"));
+
+        // 按类型和时间窗口过滤
+        let entries = timeline(&db, "123456789", 1000, 1500, &[EntryKind::Online, EntryKind::AdminAction], 10)
+            .await
+            .unwrap();
+        let kinds: Vec<_> = entries.iter().map(|x| x.kind).collect();
+        assert_eq!(kinds, vec![EntryKind::AdminAction, EntryKind::Online]);
+        let entries = timeline(&db, "123456789", 0, now, ALL_KINDS, 1).await.unwrap();
+        assert_eq!(entries.len(), 1);
+    }
+}
@@ -0,0 +1,142 @@
+// 设备视图模块 - 按标签、系统、设备组、在线状态和最后在线时间组合的设备查询条件，可按用户保存为视图。
+// 在 /api/devices?view=ID 中使用，查询参数覆盖视图中的同名条件。
+// 时长条件保存为相对秒数，视图在任何时间打开都表示相同含义(如离线超过 24 小时)
+use crate::enterprise_database::{DeviceFilter, EnterpriseDatabase};
+use hbb_common::{bail, ResultType};
+use schemars::JsonSchema;
+use serde_derive::{Deserialize, Serialize};
+
+pub const MAX_VIEWS_PER_USER: usize = 50;
+const MAX_NAME_LEN: usize = 64;
+const MAX_TAGS: usize = 20;
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct DeviceView {
+    pub id: String,
+    pub user_id: String,
+    pub name: String,
+    pub filter: DeviceFilter,
+    pub created_at: u64,
+    pub updated_at: u64,
+}
+
+// 解析 "3600"、"30m"、"24h"、"7d" 形式的时长，返回秒数
+pub fn parse_duration(s: &str) -> Option<u64> {
+    let s = s.trim();
+    if let Ok(secs) = s.parse() {
+        return Some(secs);
+    }
+    let (n, unit) = s.split_at(s.len().checked_sub(1)?);
+    let n: u64 = n.parse().ok()?;
+    match unit {
+        "m" => n.checked_mul(60),
+        "h" => n.checked_mul(3600),
+        "d" => n.checked_mul(86400),
+        _ => None,
+    }
+}
+
+// 逗号分隔的标签
+pub fn parse_tags(s: &str) -> Vec<String> {
+    s.split(',')
+        .map(|x| x.trim())
+        .filter(|x| !x.is_empty())
+        .map(|x| x.to_owned())
+        .collect()
+}
+
+pub fn validate(name: &str, filter: &DeviceFilter) -> ResultType<()> {
+    let len = name.trim().chars().count();
+    if len == 0 || len > MAX_NAME_LEN {
+        bail!("name must be 1-{} characters", MAX_NAME_LEN);
+    }
+    if filter.tags.len() > MAX_TAGS {
+        bail!("at most {} tags", MAX_TAGS);
+    }
+    if let (Some(offline_for), Some(seen_within)) = (filter.offline_for, filter.seen_within) {
+        if offline_for >= seen_within {
+            bail!("offline_for must be less than seen_within");
+        }
+    }
+    Ok(())
+}
+
+pub async fn create(
+    db: &EnterpriseDatabase,
+    user_id: &str,
+    name: &str,
+    filter: DeviceFilter,
+) -> ResultType<DeviceView> {
+    validate(name, &filter)?;
+    let views = db.list_device_views(user_id).await?;
+    if views.len() >= MAX_VIEWS_PER_USER {
+        bail!("at most {} views per user", MAX_VIEWS_PER_USER);
+    }
+    let name = name.trim();
+    if views.iter().any(|v| v.name == name) {
+        bail!("view {} already exists", name);
+    }
+    let now = crate::common::now();
+    let view = DeviceView {
+        id: uuid::Uuid::new_v4().to_string(),
+        user_id: user_id.to_owned(),
+        name: name.to_owned(),
+        filter,
+        created_at: now,
+        updated_at: now,
+    };
+    db.save_device_view(&view).await?;
+    Ok(view)
+}
+
+// 视图不存在或不属于该用户时返回 None
+pub async fn update(
+    db: &EnterpriseDatabase,
+    user_id: &str,
+    id: &str,
+    name: &str,
+    filter: DeviceFilter,
+) -> ResultType<Option<DeviceView>> {
+    validate(name, &filter)?;
+    let name = name.trim();
+    let views = db.list_device_views(user_id).await?;
+    if views.iter().any(|v| v.name == name && v.id != id) {
+        bail!("view {} already exists", name);
+    }
+    let mut view = match views.into_iter().find(|v| v.id == id) {
+        Some(view) => view,
+        None => return Ok(None),
+    };
+    view.name = name.to_owned();
+    view.filter = filter;
+    view.updated_at = crate::common::now();
+    db.update_device_view(&view).await?;
+    Ok(Some(view))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_device_view_filter() {
+        assert_eq!(parse_duration("90"), Some(90));
+        assert_eq!(parse_duration("30m"), Some(1800));
+        assert_eq!(parse_duration("24h"), Some(86400));
+        assert_eq!(parse_duration("7d"), Some(7 * 86400));
+        assert_eq!(parse_duration("7w"), None);
+        assert_eq!(parse_duration(""), None);
+        assert_eq!(parse_tags("kiosk, lobby,,"), vec!["kiosk", "lobby"]);
+
+        // 所有 Windows 自助终端中离线超过 24 小时的设备
+        let filter: DeviceFilter =
+            serde_json::from_str(r#"{"tags": ["kiosk"], "os": "windows", "offline_for": 86400}"#).unwrap();
+        assert_eq!(filter.online, None);
+        assert!(validate("offline kiosks", &filter).is_ok());
+        assert!(validate(" ", &filter).is_err());
+
+        let mut filter = filter;
+        filter.seen_within = Some(3600);
+        assert!(validate("offline kiosks", &filter).is_err());
+    }
+}
@@ -0,0 +1,231 @@
+// 数据防泄漏模块 - 集中管理的文件传输规则：文件名通配符黑名单、按方向(上传/下载)的大小上限，
+// 以及可选的内容正则扫描；违规由 FileTransferManager 记为安全事件，而不仅仅是返回错误
+use crate::enterprise_database::EnterpriseDatabase;
+use crate::validation::{self, Payload};
+use hbb_common::{bail, tokio::sync::RwLock, ResultType};
+use regex::{bytes, Regex};
+use schemars::JsonSchema;
+use serde_derive::{Deserialize, Serialize};
+use validator::Validate;
+
+pub const DLP_POLICY_KEY: &str = "dlp_policy";
+const MAX_SCAN_BYTES: u64 = 64 * 1024 * 1024;
+
+lazy_static::lazy_static! {
+    static ref POLICY: RwLock<CompiledPolicy> = Default::default();
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Direction {
+    Upload,
+    Download,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, Validate)]
+pub struct DlpPolicy {
+    #[serde(default)]
+    pub enabled: bool,
+    // 匹配文件相对路径，* 不跨目录，** 跨目录，? 匹配单个字符，不区分大小写
+    #[serde(default)]
+    #[validate(custom = "validation::non_empty_items")]
+    pub blocked_globs: Vec<String>,
+    #[serde(default)]
+    pub max_upload_size: Option<u64>,
+    #[serde(default)]
+    pub max_download_size: Option<u64>,
+    #[serde(default)]
+    pub content_patterns: Vec<ContentPattern>,
+    // 内容扫描只检查文件开头的字节数，默认 16MB
+    #[serde(default = "default_scan_bytes")]
+    pub scan_bytes: u64,
+}
+
+impl Payload for DlpPolicy {}
+
+fn default_scan_bytes() -> u64 {
+    16 * 1024 * 1024
+}
+
+impl Default for DlpPolicy {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            blocked_globs: vec![],
+            max_upload_size: None,
+            max_download_size: None,
+            content_patterns: vec![],
+            scan_bytes: default_scan_bytes(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct ContentPattern {
+    pub name: String,
+    pub regex: String,
+}
+
+#[derive(Default)]
+struct CompiledPolicy {
+    policy: DlpPolicy,
+    globs: Vec<(String, Regex)>,
+    patterns: Vec<(String, bytes::Regex)>,
+}
+
+// 通配符转换为正则
+fn glob_to_regex(glob: &str) -> ResultType<Regex> {
+    let mut re = String::from("(?i)^");
+    let mut chars = glob.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' if chars.peek() == Some(&'*') => {
+                chars.next();
+                // **/ 同时匹配零层目录
+                if chars.peek() == Some(&'/') {
+                    chars.next();
+                    re.push_str("(?:.*/)?");
+                } else {
+                    re.push_str(".*");
+                }
+            }
+            '*' => re.push_str("[^/]*"),
+            '?' => re.push_str("[^/]"),
+            c => re.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    re.push('$');
+    Ok(Regex::new(&re)?)
+}
+
+impl CompiledPolicy {
+    fn compile(policy: DlpPolicy) -> ResultType<Self> {
+        if policy.scan_bytes == 0 || policy.scan_bytes > MAX_SCAN_BYTES {
+            bail!("scan_bytes must be between 1 and {}", MAX_SCAN_BYTES);
+        }
+        let mut globs = vec![];
+        for glob in &policy.blocked_globs {
+            // 不含目录的通配符匹配任意目录下的文件名
+            let full = if glob.contains('/') {
+                glob.clone()
+            } else {
+                format!("**/{}", glob)
+            };
+            globs.push((glob.clone(), glob_to_regex(&full)?));
+        }
+        let mut patterns = vec![];
+        for pattern in &policy.content_patterns {
+            match bytes::Regex::new(&pattern.regex) {
+                Ok(re) => patterns.push((pattern.name.clone(), re)),
+                Err(err) => bail!("invalid content pattern {}: {}", pattern.name, err),
+            }
+        }
+        Ok(Self {
+            policy,
+            globs,
+            patterns,
+        })
+    }
+
+    // 文件名和大小规则，返回违规原因
+    fn check(&self, path: &str, size: u64, direction: Direction) -> Option<String> {
+        if !self.policy.enabled {
+            return None;
+        }
+        let path = path.replace('\\', "/");
+        if let Some((glob, _)) = self.globs.iter().find(|(_, re)| re.is_match(&path)) {
+            return Some(format!("file name matches blocked pattern {}", glob));
+        }
+        let limit = match direction {
+            Direction::Upload => self.policy.max_upload_size,
+            Direction::Download => self.policy.max_download_size,
+        };
+        match limit {
+            Some(limit) if size > limit => Some(format!("file size {} exceeds {:?} limit {}", size, direction, limit)),
+            _ => None,
+        }
+    }
+
+    fn scan(&self, data: &[u8]) -> Option<String> {
+        if !self.policy.enabled {
+            return None;
+        }
+        self.patterns
+            .iter()
+            .find(|(_, re)| re.is_match(data))
+            .map(|(name, _)| format!("content matches pattern {}", name))
+    }
+}
+
+pub async fn reload(db: &EnterpriseDatabase) -> ResultType<()> {
+    let policy: DlpPolicy = match db.get_setting(DLP_POLICY_KEY).await? {
+        Some(v) => serde_json::from_str(&v)?,
+        None => DlpPolicy::default(),
+    };
+    *POLICY.write().await = CompiledPolicy::compile(policy)?;
+    Ok(())
+}
+
+pub async fn get() -> DlpPolicy {
+    POLICY.read().await.policy.clone()
+}
+
+pub async fn update(db: &EnterpriseDatabase, policy: DlpPolicy, updated_by: &str) -> ResultType<()> {
+    let compiled = CompiledPolicy::compile(policy.clone())?;
+    db.set_setting(DLP_POLICY_KEY, &serde_json::to_string(&policy)?, Some(updated_by))
+        .await?;
+    *POLICY.write().await = compiled;
+    Ok(())
+}
+
+pub async fn check_transfer(path: &str, size: u64, direction: Direction) -> Option<String> {
+    POLICY.read().await.check(path, size, direction)
+}
+
+// 需要内容扫描时返回应读取的字节数
+pub async fn content_scan_bytes() -> Option<u64> {
+    let policy = POLICY.read().await;
+    if policy.policy.enabled && !policy.patterns.is_empty() {
+        Some(policy.policy.scan_bytes)
+    } else {
+        None
+    }
+}
+
+pub async fn scan_content(data: &[u8]) -> Option<String> {
+    POLICY.read().await.scan(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dlp_rules() {
+        let policy = CompiledPolicy::compile(DlpPolicy {
+            enabled: true,
+            blocked_globs: vec!["*.pem".to_owned(), "finance/**".to_owned()],
+            max_upload_size: Some(100),
+            max_download_size: None,
+            content_patterns: vec![ContentPattern {
+                name: "cn-id".to_owned(),
+                regex: r"\b\d{17}[\dXx]\b".to_owned(),
+            }],
+            scan_bytes: default_scan_bytes(),
+        })
+        .unwrap();
+        assert!(policy.check("keys/server.PEM", 1, Direction::Upload).is_some());
+        assert!(policy.check("finance/2024/q1.xlsx", 1, Direction::Download).is_some());
+        assert!(policy.check("docs/report.pdf", 101, Direction::Upload).is_some());
+        assert!(policy.check("docs/report.pdf", 101, Direction::Download).is_none());
+        assert!(policy.scan(b"id: 11010519491231002X").is_some());
+        assert!(policy.scan(b"nothing sensitive").is_none());
+        assert!(CompiledPolicy::compile(DlpPolicy {
+            content_patterns: vec![ContentPattern {
+                name: "bad".to_owned(),
+                regex: "(".to_owned(),
+            }],
+            ..Default::default()
+        })
+        .is_err());
+    }
+}
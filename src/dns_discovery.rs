@@ -0,0 +1,530 @@
+// DNS 服务发现模块 - 按 /api/settings/dns-discovery 配置的域名生成客户端发现服务器所需的 DNS 记录:
+//   _rustdesk._tcp.<域名>        SRV  信令服务器
+//   _rustdesk-relay._tcp.<域名>  SRV  中继服务器(每台一条)
+//   _rustdesk.<域名>             TXT  服务器公钥及其 SHA-256 指纹，客户端据此校验 -k
+// 记录以区域文件格式提供，由管理员发布到 DNS 服务商；健康检查从本机解析这些记录并与当前配置比对，
+// 结果与客户端看到的一致(本机与客户端使用不同的解析器时以客户端为准)
+use crate::enterprise_database::EnterpriseDatabase;
+use crate::relay_sessions;
+use crate::server_key;
+use crate::validation::{self, Payload};
+use hbb_common::{
+    bail,
+    config::{RELAY_PORT, RENDEZVOUS_PORT},
+    log,
+    tokio::{net::lookup_host, sync::RwLock},
+    ResultType,
+};
+use schemars::JsonSchema;
+use serde_derive::{Deserialize, Serialize};
+use sodiumoxide::crypto::hash::sha256;
+use std::collections::HashSet;
+use trust_dns_resolver::{error::ResolveErrorKind, TokioAsyncResolver};
+use validator::Validate;
+
+pub const DNS_DISCOVERY_KEY: &str = "dns_discovery";
+const SRV_RENDEZVOUS: &str = "_rustdesk._tcp";
+const SRV_RELAY: &str = "_rustdesk-relay._tcp";
+const TXT_NAME: &str = "_rustdesk";
+const TXT_VERSION: &str = "rustdesk1";
+const MAX_RELAYS: usize = 32;
+
+lazy_static::lazy_static! {
+    static ref CONFIG: RwLock<DnsDiscoveryConfig> = Default::default();
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema, Validate)]
+pub struct DnsDiscoveryConfig {
+    // 发布记录的域名，如 example.com，为空表示未配置
+    #[serde(default)]
+    #[validate(length(max = 253))]
+    pub domain: String,
+    // 信令服务器对外主机名，如 hbbs.example.com
+    #[serde(default)]
+    #[validate(length(max = 253))]
+    pub rendezvous_host: String,
+    // 中继服务器对外地址(主机名或 主机名:端口)，为空时使用 --relay-servers 中的主机名
+    #[serde(default)]
+    #[validate(custom = "validation::non_empty_items")]
+    pub relay_servers: Vec<String>,
+    #[serde(default = "default_ttl")]
+    #[validate(range(min = 60, max = 86400))]
+    pub ttl: u32,
+}
+
+impl Payload for DnsDiscoveryConfig {}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct DnsRecord {
+    pub name: String,
+    pub record_type: String,
+    pub value: String,
+    pub ttl: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct DnsRecords {
+    pub records: Vec<DnsRecord>,
+    // 区域文件格式，可直接导入 DNS 服务商
+    pub zone: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub enum CheckStatus {
+    Ok,
+    // 记录不存在
+    Missing,
+    // 记录存在但与配置不符
+    Mismatch,
+    // 解析失败
+    Error,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct RecordCheck {
+    pub name: String,
+    pub record_type: String,
+    pub expected: String,
+    pub found: Vec<String>,
+    pub status: CheckStatus,
+    pub message: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct DiscoveryHealth {
+    pub domain: String,
+    pub healthy: bool,
+    pub checked_at: u64,
+    pub checks: Vec<RecordCheck>,
+}
+
+fn default_ttl() -> u32 {
+    3600
+}
+
+impl Default for DnsDiscoveryConfig {
+    fn default() -> Self {
+        Self {
+            domain: String::new(),
+            rendezvous_host: String::new(),
+            relay_servers: Vec::new(),
+            ttl: default_ttl(),
+        }
+    }
+}
+
+fn is_hostname(host: &str) -> bool {
+    let host = host.trim_end_matches('.');
+    !host.is_empty()
+        && host.len() <= 253
+        && host.parse::<std::net::IpAddr>().is_err()
+        && host.split('.').all(|label| {
+            !label.is_empty()
+                && label.len() <= 63
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+                && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        })
+}
+
+// 主机名[:端口]，SRV 记录只能指向主机名，IP 地址返回 None
+fn parse_server(server: &str, default_port: u16) -> Option<(String, u16)> {
+    let (host, port) = match server.rsplit_once(':') {
+        Some((host, port)) if !host.contains(':') => (host, port.parse().ok()?),
+        _ => (server, default_port),
+    };
+    if !is_hostname(host) {
+        return None;
+    }
+    Some((host.trim_end_matches('.').to_lowercase(), port))
+}
+
+impl DnsDiscoveryConfig {
+    pub fn validate(&self) -> ResultType<()> {
+        if !(60..=86400).contains(&self.ttl) {
+            bail!("ttl must be 60-86400");
+        }
+        if self.relay_servers.len() > MAX_RELAYS {
+            bail!("at most {} relay servers", MAX_RELAYS);
+        }
+        if self.domain.is_empty() {
+            return Ok(());
+        }
+        if !is_hostname(&self.domain) {
+            bail!("invalid domain: {}", self.domain);
+        }
+        if !is_hostname(&self.rendezvous_host) {
+            bail!("rendezvous_host must be a host name");
+        }
+        for server in self.relay_servers.iter() {
+            if parse_server(server, RELAY_PORT as u16).is_none() {
+                bail!("relay server must be a host name with optional port: {}", server);
+            }
+        }
+        Ok(())
+    }
+
+    fn name(&self, prefix: &str) -> String {
+        format!("{}.{}", prefix, self.domain.trim_end_matches('.').to_lowercase())
+    }
+
+    fn relays(&self) -> Vec<(String, u16)> {
+        let servers = if self.relay_servers.is_empty() {
+            relay_sessions::servers()
+        } else {
+            self.relay_servers.clone()
+        };
+        let mut seen = HashSet::new();
+        servers
+            .iter()
+            .filter_map(|x| parse_server(x, RELAY_PORT as u16))
+            .filter(|x| seen.insert(x.clone()))
+            .collect()
+    }
+}
+
+pub async fn reload(db: &EnterpriseDatabase) -> ResultType<()> {
+    let config: DnsDiscoveryConfig = match db.get_setting(DNS_DISCOVERY_KEY).await? {
+        Some(v) => serde_json::from_str(&v)?,
+        None => DnsDiscoveryConfig::default(),
+    };
+    config.validate()?;
+    *CONFIG.write().await = config;
+    Ok(())
+}
+
+pub async fn get() -> DnsDiscoveryConfig {
+    CONFIG.read().await.clone()
+}
+
+pub async fn update(db: &EnterpriseDatabase, config: DnsDiscoveryConfig, updated_by: &str) -> ResultType<()> {
+    config.validate()?;
+    db.set_setting(DNS_DISCOVERY_KEY, &serde_json::to_string(&config)?, Some(updated_by))
+        .await?;
+    *CONFIG.write().await = config;
+    Ok(())
+}
+
+fn rendezvous_port() -> u16 {
+    std::env::var("PORT_FOR_API")
+        .ok()
+        .and_then(|x| x.parse().ok())
+        .unwrap_or(RENDEZVOUS_PORT as u16)
+}
+
+// 公钥的 SHA-256 指纹(十六进制)
+pub fn fingerprint(pk: &str) -> Option<String> {
+    let pk = base64::decode(pk).ok()?;
+    Some(sha256::hash(&pk).0.iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+fn txt_value(pk: &str) -> Option<String> {
+    Some(format!("v={} key={} fp={}", TXT_VERSION, pk, fingerprint(pk)?))
+}
+
+// TXT 记录中的公钥
+fn txt_key(txt: &str) -> Option<&str> {
+    let mut fields = txt.split_whitespace().filter_map(|x| x.split_once('='));
+    if fields.next()? != ("v", TXT_VERSION) {
+        return None;
+    }
+    fields.find(|(k, _)| *k == "key").map(|(_, v)| v)
+}
+
+fn srv_value(port: u16, target: &str) -> String {
+    format!("0 0 {} {}.", port, target)
+}
+
+fn build_records(config: &DnsDiscoveryConfig, port: u16, pk: Option<&str>) -> Vec<DnsRecord> {
+    let record = |name: String, record_type: &str, value: String| DnsRecord {
+        name,
+        record_type: record_type.to_owned(),
+        value,
+        ttl: config.ttl,
+    };
+    let mut records = vec![record(
+        config.name(SRV_RENDEZVOUS),
+        "SRV",
+        srv_value(port, &config.rendezvous_host.trim_end_matches('.').to_lowercase()),
+    )];
+    for (host, port) in config.relays() {
+        records.push(record(config.name(SRV_RELAY), "SRV", srv_value(port, &host)));
+    }
+    if let Some(value) = pk.and_then(txt_value) {
+        records.push(record(config.name(TXT_NAME), "TXT", value));
+    }
+    records
+}
+
+fn zone(records: &[DnsRecord]) -> String {
+    records
+        .iter()
+        .map(|r| {
+            let value = if r.record_type == "TXT" {
+                format!("\"{}\"", r.value)
+            } else {
+                r.value.clone()
+            };
+            format!("{}. {} IN {} {}\n", r.name, r.ttl, r.record_type, value)
+        })
+        .collect()
+}
+
+// 当前配置应发布的记录
+pub async fn records() -> ResultType<DnsRecords> {
+    let config = get().await;
+    if config.domain.is_empty() {
+        bail!("dns discovery domain is not configured");
+    }
+    let pk = server_key::info().await.active_pk;
+    let records = build_records(&config, rendezvous_port(), pk.as_deref());
+    Ok(DnsRecords {
+        zone: zone(&records),
+        records,
+    })
+}
+
+fn check(record: &DnsRecord, found: Vec<String>, status: CheckStatus, message: Option<String>) -> RecordCheck {
+    RecordCheck {
+        name: record.name.clone(),
+        record_type: record.record_type.clone(),
+        expected: record.value.clone(),
+        found,
+        status,
+        message,
+    }
+}
+
+fn lookup_error(record: &DnsRecord, err: trust_dns_resolver::error::ResolveError) -> RecordCheck {
+    match err.kind() {
+        ResolveErrorKind::NoRecordsFound { .. } => check(record, vec![], CheckStatus::Missing, None),
+        _ => check(record, vec![], CheckStatus::Error, Some(err.to_string())),
+    }
+}
+
+// SRV 记录只比较端口和目标主机，优先级和权重由管理员决定
+fn srv_matches(found: &str, expected: &str) -> bool {
+    let fields = |x: &str| -> Option<(String, String)> {
+        let parts: Vec<&str> = x.split_whitespace().collect();
+        match parts[..] {
+            [_, _, port, target] => Some((port.to_owned(), target.trim_end_matches('.').to_lowercase())),
+            _ => None,
+        }
+    };
+    fields(found).is_some() && fields(found) == fields(expected)
+}
+
+async fn check_srv(resolver: &TokioAsyncResolver, name: &str, expected: &[&DnsRecord]) -> Vec<RecordCheck> {
+    let found: Vec<String> = match resolver.srv_lookup(name).await {
+        Ok(lookup) => lookup
+            .iter()
+            .map(|srv| format!("{} {} {} {}", srv.priority(), srv.weight(), srv.port(), srv.target()))
+            .collect(),
+        Err(err) => return expected.iter().map(|r| lookup_error(r, err.clone())).collect(),
+    };
+    let mut checks = Vec::new();
+    for record in expected {
+        if found.iter().any(|x| srv_matches(x, &record.value)) {
+            checks.push(check(record, found.clone(), CheckStatus::Ok, None));
+            continue;
+        }
+        let status = if found.is_empty() {
+            CheckStatus::Missing
+        } else {
+            CheckStatus::Mismatch
+        };
+        checks.push(check(record, found.clone(), status, None));
+    }
+    // 指向已不存在的服务器的记录会让客户端连接失败
+    for x in found
+        .iter()
+        .filter(|x| !expected.iter().any(|r| srv_matches(x, &r.value)))
+    {
+        checks.push(RecordCheck {
+            name: name.to_owned(),
+            record_type: "SRV".to_owned(),
+            expected: String::new(),
+            found: vec![x.clone()],
+            status: CheckStatus::Mismatch,
+            message: Some("unexpected record, remove it or add the server to the configuration".to_owned()),
+        });
+    }
+    checks
+}
+
+async fn check_txt(resolver: &TokioAsyncResolver, record: &DnsRecord) -> RecordCheck {
+    let found: Vec<String> = match resolver.txt_lookup(record.name.as_str()).await {
+        Ok(lookup) => lookup
+            .iter()
+            .map(|txt| {
+                txt.txt_data()
+                    .iter()
+                    .map(|x| String::from_utf8_lossy(x).into_owned())
+                    .collect::<String>()
+            })
+            .collect(),
+        Err(err) => return lookup_error(record, err),
+    };
+    let expected_key = txt_key(&record.value);
+    let keys: Vec<&str> = found.iter().filter_map(|x| txt_key(x)).collect();
+    if keys.is_empty() {
+        return check(record, found.clone(), CheckStatus::Missing, None);
+    }
+    if keys.len() > 1 {
+        return check(
+            record,
+            found.clone(),
+            CheckStatus::Mismatch,
+            Some("more than one key published".to_owned()),
+        );
+    }
+    if Some(keys[0]) == expected_key {
+        return check(record, found.clone(), CheckStatus::Ok, None);
+    }
+    // 密钥轮换的重叠期内客户端已开始使用新公钥
+    let next = server_key::info().await.next_pk;
+    let message = if next.as_deref() == Some(keys[0]) {
+        "published key is the pending key, it becomes valid after rotation"
+    } else {
+        "published key does not match the server key"
+    };
+    check(record, found.clone(), CheckStatus::Mismatch, Some(message.to_owned()))
+}
+
+// SRV 目标主机需能解析到地址
+async fn check_target(record: &DnsRecord) -> Option<RecordCheck> {
+    let parts: Vec<&str> = record.value.split_whitespace().collect();
+    let (port, target) = match parts[..] {
+        [_, _, port, target] => (port.parse::<u16>().ok()?, target.trim_end_matches('.')),
+        _ => return None,
+    };
+    let name = target.to_owned();
+    let res = lookup_host((target, port)).await;
+    let addrs: Vec<String> = match res {
+        Ok(addrs) => addrs.map(|x| x.ip().to_string()).collect(),
+        Err(err) => {
+            return Some(RecordCheck {
+                name,
+                record_type: "A/AAAA".to_owned(),
+                expected: String::new(),
+                found: vec![],
+                status: CheckStatus::Missing,
+                message: Some(err.to_string()),
+            })
+        }
+    };
+    Some(RecordCheck {
+        name,
+        record_type: "A/AAAA".to_owned(),
+        expected: String::new(),
+        status: if addrs.is_empty() {
+            CheckStatus::Missing
+        } else {
+            CheckStatus::Ok
+        },
+        found: addrs,
+        message: None,
+    })
+}
+
+// 从本机解析已发布的记录并与当前配置比对
+pub async fn health() -> ResultType<DiscoveryHealth> {
+    let config = get().await;
+    let expected = records().await?.records;
+    let resolver = TokioAsyncResolver::tokio_from_system_conf()?;
+    let mut checks = Vec::new();
+    for prefix in [SRV_RENDEZVOUS, SRV_RELAY] {
+        let name = config.name(prefix);
+        let records: Vec<&DnsRecord> = expected
+            .iter()
+            .filter(|r| r.record_type == "SRV" && r.name == name)
+            .collect();
+        if records.is_empty() {
+            continue;
+        }
+        checks.extend(check_srv(&resolver, &name, &records).await);
+        for record in records {
+            if let Some(check) = check_target(record).await {
+                checks.push(check);
+            }
+        }
+    }
+    if let Some(record) = expected.iter().find(|r| r.record_type == "TXT") {
+        checks.push(check_txt(&resolver, record).await);
+    }
+    let healthy = checks.iter().all(|c| c.status == CheckStatus::Ok);
+    if !healthy {
+        log::warn!("DNS discovery records of {} are not healthy", config.domain);
+    }
+    Ok(DiscoveryHealth {
+        domain: config.domain,
+        healthy,
+        checked_at: crate::common::now(),
+        checks,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> DnsDiscoveryConfig {
+        DnsDiscoveryConfig {
+            domain: "example.com".to_owned(),
+            rendezvous_host: "hbbs.example.com".to_owned(),
+            relay_servers: vec!["relay1.example.com".to_owned(), "relay2.example.com:21217".to_owned()],
+            ttl: 3600,
+        }
+    }
+
+    #[test]
+    fn test_validate() {
+        let mut config = config();
+        assert!(config.validate().is_ok());
+        config.relay_servers.push("10.0.0.1:21117".to_owned());
+        assert!(config.validate().is_err());
+        config.relay_servers.pop();
+        config.rendezvous_host = "-bad-.example.com".to_owned();
+        assert!(config.validate().is_err());
+        config.rendezvous_host = "hbbs.example.com".to_owned();
+        config.ttl = 10;
+        assert!(config.validate().is_err());
+        assert!(DnsDiscoveryConfig::default().validate().is_ok());
+    }
+
+    #[test]
+    fn test_records() {
+        let pk = base64::encode([7u8; 32]);
+        let records = build_records(&config(), 21116, Some(&pk));
+        assert_eq!(records.len(), 4);
+        assert_eq!(records[0].name, "_rustdesk._tcp.example.com");
+        assert_eq!(records[0].value, "0 0 21116 hbbs.example.com.");
+        assert_eq!(records[1].value, "0 0 21117 relay1.example.com.");
+        assert_eq!(records[2].value, "0 0 21217 relay2.example.com.");
+        assert_eq!(records[3].name, "_rustdesk.example.com");
+        assert_eq!(txt_key(&records[3].value), Some(pk.as_str()));
+        assert_eq!(fingerprint(&pk).unwrap().len(), 64);
+        let zone = zone(&records);
+        assert!(zone.contains("_rustdesk.example.com. 3600 IN TXT \"v=rustdesk1 key="));
+        assert_eq!(build_records(&config(), 21116, None).len(), 3);
+    }
+
+    #[test]
+    fn test_matching() {
+        assert!(srv_matches(
+            "10 5 21116 HBBS.example.com.",
+            "0 0 21116 hbbs.example.com."
+        ));
+        assert!(!srv_matches(
+            "0 0 21115 hbbs.example.com.",
+            "0 0 21116 hbbs.example.com."
+        ));
+        assert_eq!(txt_key("v=rustdesk1 key=abc= fp=00"), Some("abc="));
+        assert_eq!(txt_key("v=spf1 include:example.com"), None);
+        assert_eq!(
+            parse_server("relay.example.com:21217", 21117),
+            Some(("relay.example.com".to_owned(), 21217))
+        );
+        assert_eq!(parse_server("192.0.2.1", 21117), None);
+    }
+}
@@ -0,0 +1,172 @@
+// 端到端密钥协商模块 - 信令服务器在 PunchHoleResponse / RelayResponse 中下发经服务器私钥签名的
+// 被控端公钥(IdPk)，控制端验签后用该公钥封装会话密钥，与被控端直接完成密钥交换；
+// 服务器不持有会话密钥，只审计每次信令是否带有效签名公钥，即该会话能否建立端到端加密。
+// 会话密钥由双方客户端在对端连接上用原生 KeyExchange 消息协商，不经过信令服务器，
+// 服务器端的 advanced_security::E2EEncryption 不参与客户端会话；审计记录保留 RETENTION_SECS 后清理
+use crate::common::now;
+use crate::enterprise_database::EnterpriseDatabase;
+use crate::feature_flags::{self, Feature};
+use hbb_common::{log, tokio::sync::RwLock, ResultType};
+use schemars::JsonSchema;
+use serde_derive::{Deserialize, Serialize};
+
+pub const E2E_POLICY_KEY: &str = "e2e_policy";
+pub const RETENTION_SECS: u64 = 90 * 86400;
+
+lazy_static::lazy_static! {
+    static ref E2E_POLICY: RwLock<E2ePolicy> = Default::default();
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct E2ePolicy {
+    // 无法下发签名公钥时拒绝转发打洞/中继响应，阻止建立未受保护的会话
+    #[serde(default)]
+    pub require_e2e: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub enum KeyOffer {
+    Signed,
+    NoServerKey,    // 服务器未配置签名私钥
+    LegacyClient,   // 客户端版本过旧，不支持验签
+    PeerKeyMissing, // 被控端尚未通过 RegisterPk 登记公钥
+}
+
+impl KeyOffer {
+    pub fn evaluate(client_version: &str, has_server_key: bool, peer_pk: Option<&[u8]>) -> Self {
+        if !has_server_key {
+            KeyOffer::NoServerKey
+        } else if client_version.is_empty() {
+            KeyOffer::LegacyClient
+        } else if peer_pk.map(|pk| pk.is_empty()).unwrap_or(true) {
+            KeyOffer::PeerKeyMissing
+        } else {
+            KeyOffer::Signed
+        }
+    }
+
+    pub fn is_protected(&self) -> bool {
+        *self == KeyOffer::Signed
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct KeyExchangeRecord {
+    pub id: i64,
+    pub session_id: String, // 中继会话uuid，打洞连接为空
+    pub device_id: String,
+    pub requester: String,
+    pub channel: String, // punch / local / relay
+    pub offer: KeyOffer,
+    pub created_at: u64,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct E2eStats {
+    pub total: i64,
+    pub protected: i64,
+    pub records: Vec<KeyExchangeRecord>,
+}
+
+pub async fn reload(db: &EnterpriseDatabase) -> ResultType<()> {
+    let policy: E2ePolicy = match db.get_setting(E2E_POLICY_KEY).await? {
+        Some(v) => serde_json::from_str(&v)?,
+        None => E2ePolicy::default(),
+    };
+    *E2E_POLICY.write().await = policy;
+    Ok(())
+}
+
+pub async fn get() -> E2ePolicy {
+    E2E_POLICY.read().await.clone()
+}
+
+pub async fn update(db: &EnterpriseDatabase, policy: E2ePolicy, updated_by: &str) -> ResultType<()> {
+    db.set_setting(E2E_POLICY_KEY, &serde_json::to_string(&policy)?, Some(updated_by))
+        .await?;
+    *E2E_POLICY.write().await = policy;
+    Ok(())
+}
+
+// 记录一次密钥下发，返回是否允许继续转发响应
+pub async fn record(
+    db: &EnterpriseDatabase,
+    session_id: &str,
+    device_id: &str,
+    requester: &str,
+    channel: &str,
+    offer: KeyOffer,
+) -> bool {
+    let record = KeyExchangeRecord {
+        id: 0,
+        session_id: session_id.to_owned(),
+        device_id: device_id.to_owned(),
+        requester: requester.to_owned(),
+        channel: channel.to_owned(),
+        offer,
+        created_at: now(),
+    };
+    if let Err(err) = db.log_key_exchange(&record).await {
+        log::error!("Failed to record key exchange of {}: {}", device_id, err);
+    }
+    if offer.is_protected() || !E2E_POLICY.read().await.require_e2e {
+        return true;
+    }
+    // 强制策略可按设备组逐步放开
+    if !feature_flags::is_enabled_for_device(db, Feature::E2eEnforcement, device_id).await {
+        return true;
+    }
+    log::warn!(
+        "Blocked {} session to {} from {}: e2e unavailable ({:?})",
+        channel,
+        device_id,
+        requester,
+        offer
+    );
+    false
+}
+
+pub async fn prune(db: &EnterpriseDatabase) {
+    if let Err(e) = db.prune_key_exchanges(now().saturating_sub(RETENTION_SECS)).await {
+        log::error!("Failed to prune key exchange records: {}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hbb_common::tokio;
+
+    #[test]
+    fn test_evaluate_key_offer() {
+        assert_eq!(KeyOffer::evaluate("1.2.3", true, Some(b"pk")), KeyOffer::Signed);
+        assert_eq!(KeyOffer::evaluate("1.2.3", false, Some(b"pk")), KeyOffer::NoServerKey);
+        assert_eq!(KeyOffer::evaluate("", true, Some(b"pk")), KeyOffer::LegacyClient);
+        assert_eq!(KeyOffer::evaluate("1.2.3", true, Some(b"")), KeyOffer::PeerKeyMissing);
+        assert_eq!(KeyOffer::evaluate("1.2.3", true, None), KeyOffer::PeerKeyMissing);
+        assert!(!KeyOffer::LegacyClient.is_protected());
+    }
+
+    #[tokio::test]
+    async fn test_prune_key_exchanges() {
+        let db = EnterpriseDatabase::memory().await.unwrap();
+        assert!(record(&db, "", "device-1", "192.0.2.1:21116", "punch", KeyOffer::Signed).await);
+        let expired = KeyExchangeRecord {
+            id: 0,
+            session_id: "uuid-1".to_owned(),
+            device_id: "device-2".to_owned(),
+            requester: "192.0.2.2:21116".to_owned(),
+            channel: "relay".to_owned(),
+            offer: KeyOffer::LegacyClient,
+            created_at: now() - RETENTION_SECS - 1,
+        };
+        db.log_key_exchange(&expired).await.unwrap();
+        assert_eq!(db.count_key_exchanges(0).await.unwrap(), (2, 1));
+
+        prune(&db).await;
+        let records = db.list_key_exchanges(None, 10).await.unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].device_id, "device-1");
+        assert_eq!(records[0].offer, KeyOffer::Signed);
+    }
+}
@@ -0,0 +1,234 @@
+// 邮件验证码模块 - 用户丢失验证器时，由管理员临时开放邮件一次性验证码作为2FA备用通道，
+// 代替直接关闭用户的双因素认证；发送和校验均严格限频
+use crate::common::now;
+use crate::enterprise_database::EnterpriseDatabase;
+use crate::i18n;
+use crate::validation::Payload;
+use hbb_common::{
+    bail, log,
+    tokio::{self, sync::RwLock},
+    ResultType,
+};
+use schemars::JsonSchema;
+use serde_derive::{Deserialize, Serialize};
+use std::collections::HashMap;
+use validator::Validate;
+
+pub const EMAIL_OTP_KEY: &str = "email_otp";
+const PASSWORD_MASK: &str = "******";
+const CODE_TTL_SECS: u64 = 600;
+const RESEND_INTERVAL_SECS: u64 = 60;
+const MAX_SENDS_PER_HOUR: usize = 3;
+const MAX_ATTEMPTS: u32 = 5;
+
+lazy_static::lazy_static! {
+    static ref SMTP_CONFIG: RwLock<SmtpConfig> = Default::default();
+    static ref CODES: RwLock<HashMap<String, PendingCode>> = Default::default();
+    // 用户ID -> 最近一小时内的发送时间
+    static ref SENDS: RwLock<HashMap<String, Vec<u64>>> = Default::default();
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, Validate)]
+pub struct SmtpConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default)]
+    #[validate(length(max = 253))]
+    pub host: String,
+    #[serde(default = "default_port")]
+    pub port: u16,
+    #[serde(default)]
+    #[validate(length(max = 256))]
+    pub username: String,
+    #[serde(default)]
+    #[validate(length(max = 1024))]
+    pub password: String,
+    #[serde(default)]
+    #[validate(length(max = 254))]
+    pub from: String,
+}
+
+impl Payload for SmtpConfig {}
+
+fn default_port() -> u16 {
+    587
+}
+
+impl Default for SmtpConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            host: String::new(),
+            port: default_port(),
+            username: String::new(),
+            password: String::new(),
+            from: String::new(),
+        }
+    }
+}
+
+impl SmtpConfig {
+    // 接口返回时隐藏密码
+    pub fn masked(&self) -> Self {
+        let mut config = self.clone();
+        if !config.password.is_empty() {
+            config.password = PASSWORD_MASK.to_owned();
+        }
+        config
+    }
+}
+
+struct PendingCode {
+    code: String,
+    expires_at: u64,
+    attempts: u32,
+}
+
+pub async fn reload(db: &EnterpriseDatabase) -> ResultType<()> {
+    let config: SmtpConfig = match db.get_setting(EMAIL_OTP_KEY).await? {
+        Some(v) => serde_json::from_str(&v)?,
+        None => SmtpConfig::default(),
+    };
+    *SMTP_CONFIG.write().await = config;
+    Ok(())
+}
+
+pub async fn get() -> SmtpConfig {
+    SMTP_CONFIG.read().await.clone()
+}
+
+pub async fn update(db: &EnterpriseDatabase, mut config: SmtpConfig, updated_by: &str) -> ResultType<()> {
+    if config.enabled && (config.host.is_empty() || config.from.parse::<lettre::message::Mailbox>().is_err()) {
+        bail!("smtp host or sender address is invalid");
+    }
+    // 前端回传掩码时保留原密码
+    if config.password == PASSWORD_MASK {
+        config.password = SMTP_CONFIG.read().await.password.clone();
+    }
+    db.set_setting(EMAIL_OTP_KEY, &serde_json::to_string(&config)?, Some(updated_by))
+        .await?;
+    *SMTP_CONFIG.write().await = config;
+    Ok(())
+}
+
+// 最近一小时发送次数未超限且距上次发送超过最小间隔时记录本次发送(找回密码邮件共用该限制)
+pub fn try_record_send(sends: &mut Vec<u64>, now: u64) -> bool {
+    sends.retain(|t| now < t + 3600);
+    if sends.len() >= MAX_SENDS_PER_HOUR
+        || sends.last().map(|t| now < t + RESEND_INTERVAL_SECS).unwrap_or(false)
+    {
+        return false;
+    }
+    sends.push(now);
+    true
+}
+
+fn generate_code() -> String {
+    use rand::Rng;
+    format!("{:06}", rand::thread_rng().gen_range(0..1_000_000))
+}
+
+pub async fn send_code(user_id: &str, email: &str) -> ResultType<()> {
+    let config = SMTP_CONFIG.read().await.clone();
+    if !config.enabled {
+        bail!("email otp is disabled");
+    }
+    if !try_record_send(SENDS.write().await.entry(user_id.to_owned()).or_default(), now()) {
+        bail!("too many email codes requested");
+    }
+    let code = generate_code();
+    let to = email.to_owned();
+    let lang = i18n::user_lang(user_id).await;
+    let body = i18n::t(
+        lang,
+        "email.otp.body",
+        &[("code", &code), ("minutes", &(CODE_TTL_SECS / 60))],
+    );
+    let subject = i18n::t(lang, "email.otp.subject", &[]);
+    tokio::task::spawn_blocking(move || send_mail(&config, &to, &subject, body)).await??;
+    CODES.write().await.insert(
+        user_id.to_owned(),
+        PendingCode {
+            code,
+            expires_at: now() + CODE_TTL_SECS,
+            attempts: 0,
+        },
+    );
+    log::info!("Email otp sent to user {}", user_id);
+    Ok(())
+}
+
+// 其他模块的邮件通知(如设备离线告警)，使用同一 SMTP 配置
+pub async fn send_notification(to: Vec<String>, subject: String, body: String) -> ResultType<()> {
+    let config = SMTP_CONFIG.read().await.clone();
+    if !config.enabled {
+        bail!("smtp is disabled");
+    }
+    tokio::task::spawn_blocking(move || {
+        for to in to.iter() {
+            send_mail(&config, to, &subject, body.clone())?;
+        }
+        ResultType::Ok(())
+    })
+    .await?
+}
+
+fn send_mail(config: &SmtpConfig, to: &str, subject: &str, body: String) -> ResultType<()> {
+    use lettre::{transport::smtp::authentication::Credentials, Message, SmtpTransport, Transport};
+
+    let message = Message::builder()
+        .from(config.from.parse()?)
+        .to(to.parse()?)
+        .subject(subject)
+        .body(body)?;
+    let mut mailer = SmtpTransport::starttls_relay(&config.host)?.port(config.port);
+    if !config.username.is_empty() {
+        mailer = mailer.credentials(Credentials::new(
+            config.username.clone(),
+            config.password.clone(),
+        ));
+    }
+    mailer.build().send(&message)?;
+    Ok(())
+}
+
+// 验证码一次有效，错误次数超限后作废
+pub async fn verify(user_id: &str, code: &str) -> bool {
+    let mut codes = CODES.write().await;
+    let pending = match codes.get_mut(user_id) {
+        Some(pending) => pending,
+        None => return false,
+    };
+    pending.attempts += 1;
+    if pending.expires_at <= now() || pending.attempts > MAX_ATTEMPTS {
+        codes.remove(user_id);
+        return false;
+    }
+    if pending.code == code {
+        codes.remove(user_id);
+        return true;
+    }
+    false
+}
+
+// 撤销授权时作废未使用的验证码
+pub async fn clear(user_id: &str) {
+    CODES.write().await.remove(user_id);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_send_rate_limit() {
+        let mut sends = vec![];
+        assert!(try_record_send(&mut sends, 1000));
+        assert!(!try_record_send(&mut sends, 1030));
+        assert!(try_record_send(&mut sends, 1100));
+        assert!(try_record_send(&mut sends, 1200));
+        assert!(!try_record_send(&mut sends, 1300));
+        assert!(try_record_send(&mut sends, 4601));
+        assert_eq!(generate_code().len(), 6);
+    }
+}
@@ -1,14 +1,50 @@
 // 企业级数据库模块 - 支持用户管理、设备分组、审计日志等
+use crate::advanced_security::SecurityEvent;
+use crate::announcements::Announcement;
 use crate::auth::{User, UserRole, Session, DeviceGroup, GroupPermissions};
+use crate::break_glass::EmergencyAccess;
+use crate::change_approval::{self, ChangeStatus, SettingChange};
+use crate::config_versions::ConfigVersion;
+use crate::device_messages::{DeliveryStatus, DeviceMessage, MessageDelivery};
+use crate::custom_fields::{FieldEntity, FieldValues};
+use crate::db_pool_metrics;
+use crate::device_timeline::DeviceNote;
+use crate::favorites::UserDevice;
+use crate::device_views::DeviceView;
+use crate::e2e_signaling::{KeyExchangeRecord, KeyOffer};
+use crate::erasure::ErasureReport;
+use crate::i18n::Lang;
+use crate::file_transfer::TransferRecord;
+use crate::impersonation::{self, ImpersonationSession};
+use crate::inventory::{DeviceInventory, InventoryCount, InventoryReport};
+use crate::notifications::{NotificationPreferences, Subscriber};
+use crate::offline_alerts::DevicePresence;
+use crate::pk_pinning::{self, PkRecord, PkStatus};
+use crate::remote_jobs::{Job, JobRun, JobRunStatus};
+use crate::self_test::{self, SelfTestResult};
+use crate::server_config;
+use crate::session_events::SessionEvent;
+use crate::connection_quality::QosSample;
+use crate::codec_recommendation::CodecRecommendation;
+use crate::device_capabilities::DeviceCapabilities;
+use crate::software_update::{Platform, UpdateArtifact};
+use crate::strategy::Strategy;
+use crate::uptime::StatusChange;
+use crate::write_queue::WriteQueue;
 use async_trait::async_trait;
-use hbb_common::{log, ResultType};
+use hbb_common::{bail, log, ResultType};
+use ipnetwork::IpNetwork;
+use schemars::JsonSchema;
 use serde_derive::{Deserialize, Serialize};
 use sqlx::{
-    sqlite::SqliteConnectOptions, ConnectOptions, Connection, Error as SqlxError, SqliteConnection, Row,
+    sqlite::{SqliteConnectOptions, SqliteJournalMode, SqliteSynchronous},
+    ConnectOptions, Connection, Error as SqlxError, SqliteConnection, Row,
 };
-use std::{ops::DerefMut, str::FromStr, time::SystemTime, collections::HashMap};
+use std::{ops::DerefMut, str::FromStr, time::{Duration, SystemTime}, collections::{BTreeMap, HashMap}};
 
 type Pool = deadpool::managed::Pool<DbPool>;
+// 其他连接持有写锁时等待的时长，超时后返回 SQLITE_BUSY
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
 
 pub struct DbPool {
     url: String,
@@ -22,7 +58,11 @@ impl deadpool::managed::Manager for DbPool {
     async fn create(&self) -> Result<SqliteConnection, SqlxError> {
         let mut opt = SqliteConnectOptions::from_str(&self.url).unwrap()
             .create_if_missing(true)
-            .pragma("foreign_keys", "ON");
+            .pragma("foreign_keys", "ON")
+            // WAL 模式下读写互不阻塞，同时只有一个写入者；NORMAL 在 WAL 下不会损坏数据库
+            .journal_mode(SqliteJournalMode::Wal)
+            .synchronous(SqliteSynchronous::Normal)
+            .busy_timeout(BUSY_TIMEOUT);
         
         // 生产环境不记录SQL语句
         if cfg!(debug_assertions) {
@@ -40,12 +80,68 @@ impl deadpool::managed::Manager for DbPool {
     }
 }
 
+// 取连接时记录等待，见 db_pool_metrics
+#[derive(Clone)]
+struct MeteredPool(Pool);
+
+impl MeteredPool {
+    async fn get(&self) -> Result<deadpool::managed::Object<DbPool>, deadpool::managed::PoolError<SqlxError>> {
+        db_pool_metrics::acquire(self.0.status(), self.0.get()).await
+    }
+
+    // 设备注册写入队列的批量写入
+    async fn register_devices(&self, devices: &[DeviceInfo]) -> ResultType<()> {
+        let mut conn = self.get().await?;
+        let mut tx = conn.deref_mut().begin().await?;
+        for device in devices {
+            let last_online = device.last_online.duration_since(std::time::UNIX_EPOCH)?.as_secs() as i64;
+            let group_ids_json = serde_json::to_string(&device.group_ids)?;
+            let tags_json = serde_json::to_string(&device.tags)?;
+
+            // 已存在的设备只刷新在线信息，保留管理员分配的分组、标签和所有者
+            // IPv6注册只更新 ipv6_address，不覆盖已记录的IPv4地址
+            sqlx::query!(
+                r#"
+                INSERT INTO devices (
+                    id, name, os, version, ip_address, ipv6_address, mac_address,
+                    last_online, owner_id, group_ids, enabled, tags
+                ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+                ON CONFLICT(id) DO UPDATE SET
+                    ip_address = CASE WHEN excluded.ipv6_address IS NULL
+                        THEN excluded.ip_address ELSE devices.ip_address END,
+                    ipv6_address = COALESCE(excluded.ipv6_address, devices.ipv6_address),
+                    last_online = excluded.last_online
+                "#,
+                device.id,
+                device.name,
+                device.os,
+                device.version,
+                device.ip_address,
+                device.ipv6_address,
+                device.mac_address,
+                last_online,
+                device.owner_id,
+                group_ids_json,
+                device.enabled,
+                tags_json
+            )
+            .execute(&mut tx)
+            .await?;
+        }
+        tx.commit().await?;
+
+        Ok(())
+    }
+}
+
 #[derive(Clone)]
 pub struct EnterpriseDatabase {
-    pool: Pool,
+    pool: MeteredPool,
+    // 设备注册由写入队列批量写入
+    device_writes: WriteQueue<DeviceInfo>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
 pub struct AuditLog {
     pub id: i64,
     pub user_id: String,
@@ -58,22 +154,647 @@ pub struct AuditLog {
     pub success: bool,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+// 创建用户时与已有用户冲突的字段
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UserConflict {
+    Username,
+    Email,
+}
+
+// SQLITE_CONSTRAINT_UNIQUE
+fn is_unique_violation(e: &dyn sqlx::error::DatabaseError) -> bool {
+    e.code().as_deref() == Some("2067")
+}
+
+// SQLITE_CONSTRAINT_PRIMARYKEY
+fn is_primary_key_violation(e: &dyn sqlx::error::DatabaseError) -> bool {
+    e.code().as_deref() == Some("1555")
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct DeviceInfo {
     pub id: String,
     pub name: String,
     pub os: String,
     pub version: String,
     pub ip_address: String,
+    pub ipv6_address: Option<String>, // 双栈设备观测到的IPv6地址
     pub mac_address: Option<String>,
     pub last_online: SystemTime,
     pub owner_id: String,
     pub group_ids: Vec<String>,
     pub enabled: bool,
     pub tags: Vec<String>,
+    #[serde(default)]
+    pub alias: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct DeviceBan {
+    pub device_id: String,
+    pub uuid: Option<String>, // 封禁时设备登记的uuid，用于拒绝换ID重新注册
+    pub reason: String,
+    pub banned_by: String,
+    pub created_at: u64,
+    pub expires_at: Option<u64>, // None 表示永久封禁
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct ProvisionedId {
+    pub id: String,
+    pub note: Option<String>,
+    pub created_by: String,
+    pub created_at: u64,
+}
+
+struct KeyExchangeRow {
+    id: i64,
+    session_id: String,
+    device_id: String,
+    requester: String,
+    channel: String,
+    offer: String,
+    created_at: i64,
+}
+
+struct SecurityEventRow {
+    id: String,
+    event_type: String,
+    severity: String,
+    user_id: Option<String>,
+    device_id: Option<String>,
+    ip_address: String,
+    details: String,
+    created_at: i64,
+    resolved: bool,
+    resolution_notes: Option<String>,
+}
+
+impl TryFrom<SecurityEventRow> for SecurityEvent {
+    type Error = hbb_common::anyhow::Error;
+
+    fn try_from(row: SecurityEventRow) -> Result<Self, Self::Error> {
+        Ok(SecurityEvent {
+            id: row.id,
+            event_type: serde_json::from_value(serde_json::Value::String(row.event_type))?,
+            severity: serde_json::from_value(serde_json::Value::String(row.severity))?,
+            user_id: row.user_id,
+            device_id: row.device_id,
+            ip_address: row.ip_address,
+            user_agent: None,
+            details: serde_json::from_str(&row.details).unwrap_or_default(),
+            timestamp: std::time::UNIX_EPOCH + std::time::Duration::from_secs(row.created_at as u64),
+            resolved: row.resolved,
+            resolution_notes: row.resolution_notes,
+        })
+    }
+}
+
+struct DeviceNetworkRow {
+    id: String,
+    ip_address: String,
+    mac_address: Option<String>,
+    last_online: i64,
+}
+
+// 设备最近观测到的网络信息，用于网络唤醒
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeviceNetwork {
+    pub id: String,
+    pub ip_address: String,
+    pub mac_address: Option<String>,
+    pub last_online: u64,
+}
+
+impl From<DeviceNetworkRow> for DeviceNetwork {
+    fn from(row: DeviceNetworkRow) -> Self {
+        Self {
+            id: row.id,
+            ip_address: row.ip_address,
+            mac_address: row.mac_address,
+            last_online: row.last_online as u64,
+        }
+    }
+}
+
+struct JobRow {
+    id: String,
+    name: String,
+    command: String,
+    shell: String,
+    group_ids: String,
+    device_ids: String,
+    timeout_secs: i64,
+    created_by: String,
+    created_at: i64,
+    expires_at: i64,
+    cancelled: bool,
+}
+
+impl TryFrom<JobRow> for Job {
+    type Error = hbb_common::anyhow::Error;
+
+    fn try_from(row: JobRow) -> Result<Self, Self::Error> {
+        Ok(Job {
+            id: row.id,
+            name: row.name,
+            command: row.command,
+            shell: serde_json::from_value(serde_json::Value::String(row.shell))?,
+            group_ids: serde_json::from_str(&row.group_ids).unwrap_or_default(),
+            device_ids: serde_json::from_str(&row.device_ids).unwrap_or_default(),
+            timeout_secs: row.timeout_secs as u64,
+            created_by: row.created_by,
+            created_at: row.created_at as u64,
+            expires_at: row.expires_at as u64,
+            cancelled: row.cancelled,
+        })
+    }
+}
+
+struct DeviceMessageRow {
+    id: String,
+    title: String,
+    body: String,
+    level: String,
+    group_ids: String,
+    device_ids: String,
+    created_by: String,
+    created_at: i64,
+    expires_at: i64,
+    cancelled: bool,
+}
+
+impl TryFrom<DeviceMessageRow> for DeviceMessage {
+    type Error = hbb_common::anyhow::Error;
+
+    fn try_from(row: DeviceMessageRow) -> Result<Self, Self::Error> {
+        Ok(DeviceMessage {
+            id: row.id,
+            title: row.title,
+            body: row.body,
+            level: serde_json::from_value(serde_json::Value::String(row.level))?,
+            group_ids: serde_json::from_str(&row.group_ids).unwrap_or_default(),
+            device_ids: serde_json::from_str(&row.device_ids).unwrap_or_default(),
+            created_by: row.created_by,
+            created_at: row.created_at as u64,
+            expires_at: row.expires_at as u64,
+            cancelled: row.cancelled,
+        })
+    }
+}
+
+struct AnnouncementRow {
+    id: String,
+    title: String,
+    body: String,
+    level: String,
+    starts_at: i64,
+    ends_at: i64,
+    deliver_to_clients: bool,
+    created_by: String,
+    created_at: i64,
+    updated_at: i64,
+}
+
+impl TryFrom<AnnouncementRow> for Announcement {
+    type Error = hbb_common::anyhow::Error;
+
+    fn try_from(row: AnnouncementRow) -> Result<Self, Self::Error> {
+        Ok(Announcement {
+            id: row.id,
+            title: row.title,
+            body: row.body,
+            level: serde_json::from_value(serde_json::Value::String(row.level))?,
+            starts_at: row.starts_at as u64,
+            ends_at: row.ends_at as u64,
+            deliver_to_clients: row.deliver_to_clients,
+            created_by: row.created_by,
+            created_at: row.created_at as u64,
+            updated_at: row.updated_at as u64,
+        })
+    }
+}
+
+struct FileTransferRow {
+    transfer_id: String,
+    user_id: String,
+    device_id: Option<String>,
+    file_path: String,
+    transfer_type: String,
+    file_size: i64,
+    file_hash: String,
+    bytes_transferred: i64,
+    status: String,
+    error_message: Option<String>,
+    started_at: i64,
+    finished_at: i64,
+    duration_ms: i64,
+}
+
+// 审计日志查询条件，None 表示不过滤
+#[derive(Debug, Clone, Default)]
+pub struct AuditLogFilter {
+    pub user_id: Option<String>,
+    pub device_id: Option<String>,
+    pub action: Option<String>,
+    pub success: Option<bool>,
+    pub since: Option<u64>,
+    pub until: Option<u64>,
+    pub ip: Option<IpNetwork>, // 来源IP网段，单个地址视为 /32 或 /128
+    pub text: Option<String>,  // 详情包含
+}
+
+// IPv4 网段按完整的前几段转换为文本范围 [from, to)，用于走 ip_address 索引；精确匹配在取出后进行
+fn ip_prefix_range(ip: &IpNetwork) -> Option<(String, String)> {
+    let net = match ip {
+        IpNetwork::V4(net) => net,
+        IpNetwork::V6(_) => return None,
+    };
+    let octets = (net.prefix() / 8).min(3) as usize;
+    if octets == 0 {
+        return None;
+    }
+    let prefix = net.network().octets()[..octets]
+        .iter()
+        .map(|x| x.to_string())
+        .collect::<Vec<_>>()
+        .join(".");
+    // '/' 是 '.' 之后的下一个字符
+    Some((format!("{}.", prefix), format!("{}/", prefix)))
+}
+
+// 传输历史查询条件，None 表示不过滤
+#[derive(Debug, Clone, Default)]
+pub struct FileTransferFilter {
+    pub user_id: Option<String>,
+    pub device_id: Option<String>,
+    pub path: Option<String>, // 路径包含
+    pub file_hash: Option<String>,
+    pub status: Option<String>,
+    pub since: Option<u64>,
+    pub until: Option<u64>,
+}
+
+struct InventoryRow {
+    device_id: String,
+    hostname: String,
+    username: String,
+    os: String,
+    os_build: Option<String>,
+    cpu: String,
+    memory: String,
+    memory_bytes: Option<i64>,
+    rustdesk_version: String,
+    updated_at: i64,
+}
+
+impl From<InventoryRow> for DeviceInventory {
+    fn from(row: InventoryRow) -> Self {
+        Self {
+            device_id: row.device_id,
+            hostname: row.hostname,
+            username: row.username,
+            os: row.os,
+            os_build: row.os_build,
+            cpu: row.cpu,
+            memory: row.memory,
+            memory_bytes: row.memory_bytes.map(|x| x as u64),
+            rustdesk_version: row.rustdesk_version,
+            updated_at: row.updated_at as u64,
+        }
+    }
+}
+
+struct InventoryCountRow {
+    value: String,
+    count: i64,
+}
+
+impl From<InventoryCountRow> for InventoryCount {
+    fn from(row: InventoryCountRow) -> Self {
+        Self {
+            value: row.value,
+            count: row.count,
+        }
+    }
+}
+
+struct UpdateArtifactRow {
+    platform: String,
+    version: String,
+    file_name: String,
+    size: i64,
+    sha256: String,
+    uploaded_by: String,
+    uploaded_at: i64,
+}
+
+impl TryFrom<UpdateArtifactRow> for UpdateArtifact {
+    type Error = hbb_common::anyhow::Error;
+
+    fn try_from(row: UpdateArtifactRow) -> Result<Self, Self::Error> {
+        Ok(UpdateArtifact {
+            platform: serde_json::from_value(serde_json::Value::String(row.platform))?,
+            version: row.version,
+            file_name: row.file_name,
+            size: row.size as u64,
+            sha256: row.sha256,
+            uploaded_by: row.uploaded_by,
+            uploaded_at: row.uploaded_at as u64,
+        })
+    }
+}
+
+struct EmergencyAccessRow {
+    id: String,
+    user_id: String,
+    username: String,
+    device_id: String,
+    justification: String,
+    ip_address: String,
+    created_at: i64,
+    expires_at: i64,
+    ended_at: Option<i64>,
+    reviewed_by: Option<String>,
+    reviewed_at: Option<i64>,
+    review_notes: Option<String>,
+}
+
+impl From<EmergencyAccessRow> for EmergencyAccess {
+    fn from(row: EmergencyAccessRow) -> Self {
+        Self {
+            id: row.id,
+            user_id: row.user_id,
+            username: row.username,
+            device_id: row.device_id,
+            justification: row.justification,
+            ip_address: row.ip_address,
+            created_at: row.created_at as u64,
+            expires_at: row.expires_at as u64,
+            ended_at: row.ended_at.map(|x| x as u64),
+            reviewed_by: row.reviewed_by,
+            reviewed_at: row.reviewed_at.map(|x| x as u64),
+            review_notes: row.review_notes,
+        }
+    }
+}
+
+struct ImpersonationRow {
+    id: String,
+    actor_id: String,
+    actor_username: String,
+    user_id: String,
+    username: String,
+    reason: String,
+    started_at: i64,
+    expires_at: i64,
+    ended_at: Option<i64>,
+}
+
+impl From<ImpersonationRow> for ImpersonationSession {
+    fn from(row: ImpersonationRow) -> Self {
+        Self {
+            id: row.id,
+            actor_id: row.actor_id,
+            actor_username: row.actor_username,
+            user_id: row.user_id,
+            username: row.username,
+            reason: row.reason,
+            started_at: row.started_at as u64,
+            expires_at: row.expires_at as u64,
+            ended_at: row.ended_at.map(|x| x as u64),
+        }
+    }
+}
+
+struct SettingChangeRow {
+    id: String,
+    key: String,
+    old_value: Option<String>,
+    new_value: String,
+    requested_by: String,
+    requested_by_name: String,
+    requested_at: i64,
+    status: String,
+    reviewed_by: Option<String>,
+    reviewed_by_name: Option<String>,
+    reviewed_at: Option<i64>,
+    comment: Option<String>,
+}
+
+impl From<SettingChangeRow> for SettingChange {
+    fn from(row: SettingChangeRow) -> Self {
+        Self {
+            diff: change_approval::diff(row.old_value.as_deref(), Some(&row.new_value)),
+            id: row.id,
+            key: row.key,
+            old_value: row.old_value,
+            new_value: row.new_value,
+            requested_by: row.requested_by,
+            requested_by_name: row.requested_by_name,
+            requested_at: row.requested_at as u64,
+            status: serde_json::from_value(serde_json::Value::String(row.status)).unwrap_or(ChangeStatus::Failed),
+            reviewed_by: row.reviewed_by,
+            reviewed_by_name: row.reviewed_by_name,
+            reviewed_at: row.reviewed_at.map(|x| x as u64),
+            comment: row.comment,
+        }
+    }
+}
+
+struct ErasureReportRow {
+    id: String,
+    subject: String,
+    subject_hash: String,
+    pseudonym: String,
+    mode: String,
+    reason: String,
+    requested_by: String,
+    created_at: i64,
+    records: String,
+}
+
+impl TryFrom<ErasureReportRow> for ErasureReport {
+    type Error = hbb_common::anyhow::Error;
+
+    fn try_from(row: ErasureReportRow) -> Result<Self, Self::Error> {
+        Ok(ErasureReport {
+            id: row.id,
+            subject: serde_json::from_value(serde_json::Value::String(row.subject))?,
+            subject_hash: row.subject_hash,
+            pseudonym: row.pseudonym,
+            mode: serde_json::from_value(serde_json::Value::String(row.mode))?,
+            reason: row.reason,
+            requested_by: row.requested_by,
+            created_at: row.created_at as u64,
+            records: serde_json::from_str(&row.records)?,
+        })
+    }
+}
+
+struct DeviceViewRow {
+    id: String,
+    user_id: String,
+    name: String,
+    filter: String,
+    created_at: i64,
+    updated_at: i64,
+}
+
+impl TryFrom<DeviceViewRow> for DeviceView {
+    type Error = hbb_common::anyhow::Error;
+
+    fn try_from(row: DeviceViewRow) -> Result<Self, Self::Error> {
+        Ok(DeviceView {
+            id: row.id,
+            user_id: row.user_id,
+            name: row.name,
+            filter: serde_json::from_str(&row.filter)?,
+            created_at: row.created_at as u64,
+            updated_at: row.updated_at as u64,
+        })
+    }
+}
+
+struct DeviceNoteRow {
+    id: String,
+    device_id: String,
+    author_id: String,
+    author_name: String,
+    body: String,
+    created_at: i64,
+    updated_at: i64,
+}
+
+impl From<DeviceNoteRow> for DeviceNote {
+    fn from(row: DeviceNoteRow) -> Self {
+        DeviceNote {
+            id: row.id,
+            device_id: row.device_id,
+            author_id: row.author_id,
+            author_name: row.author_name,
+            body: row.body,
+            created_at: row.created_at as u64,
+            updated_at: row.updated_at as u64,
+        }
+    }
+}
+
+struct UserDeviceRow {
+    device_id: String,
+    name: String,
+    alias: Option<String>,
+    os: String,
+    online: bool,
+    last_online: i64,
+    favorited_at: Option<i64>,
+    last_connected_at: Option<i64>,
+    connection_count: Option<i64>,
+}
+
+impl From<UserDeviceRow> for UserDevice {
+    fn from(row: UserDeviceRow) -> Self {
+        UserDevice {
+            device_id: row.device_id,
+            name: row.name,
+            alias: row.alias,
+            os: row.os,
+            online: row.online,
+            last_online: row.last_online as u64,
+            favorited_at: row.favorited_at.map(|x| x as u64),
+            last_connected_at: row.last_connected_at.map(|x| x as u64),
+            connection_count: row.connection_count.unwrap_or_default() as u64,
+        }
+    }
+}
+
+struct PkRecordRow {
+    id: i64,
+    device_id: String,
+    pk: String,
+    uuid: String,
+    ip_address: String,
+    seen_at: i64,
+    status: String,
+    decided_by: Option<String>,
+    decided_at: Option<i64>,
+}
+
+impl TryFrom<PkRecordRow> for PkRecord {
+    type Error = hbb_common::anyhow::Error;
+
+    fn try_from(row: PkRecordRow) -> Result<Self, Self::Error> {
+        Ok(PkRecord {
+            id: row.id,
+            device_id: row.device_id,
+            fingerprint: pk_pinning::fingerprint(&base64::decode(&row.pk)?),
+            pk: row.pk,
+            uuid: row.uuid,
+            ip_address: row.ip_address,
+            seen_at: row.seen_at as u64,
+            status: serde_json::from_value(serde_json::Value::String(row.status))?,
+            decided_by: row.decided_by,
+            decided_at: row.decided_at.map(|x| x as u64),
+        })
+    }
+}
+
+// 设备查询条件，None 表示不过滤；时长以秒计，相对查询时间
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(default)]
+pub struct DeviceFilter {
+    pub tags: Vec<String>, // 需包含全部标签
+    pub os: Option<String>, // 包含匹配
+    pub group_id: Option<String>,
+    pub online: Option<bool>,
+    pub offline_for: Option<u64>, // 最后在线早于该时长之前
+    pub seen_within: Option<u64>, // 最后在线在该时长之内
+    pub q: Option<String>, // 名称、ID或别名包含
+}
+
+// 资产清单查询条件，None 表示不过滤；文本条件为包含匹配
+#[derive(Debug, Clone, Default)]
+pub struct InventoryFilter {
+    pub hostname: Option<String>,
+    pub os: Option<String>,
+    pub os_build: Option<String>, // 精确匹配
+    pub cpu: Option<String>,
+    pub version: Option<String>, // 精确匹配
+    pub min_memory: Option<u64>,
+    pub max_memory: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct TrustedDevice {
+    pub id: String,
+    pub user_id: String,
+    pub user_agent: Option<String>,
+    pub created_at: u64,
+    pub expires_at: u64,
+    pub last_used: Option<u64>,
+}
+
+// 用户邀请，accepted_at 为空表示尚未设置密码
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct UserInvite {
+    pub id: String,
+    pub user_id: String,
+    pub email: String,
+    pub invited_by: String,
+    pub created_at: u64,
+    pub expires_at: u64,
+    pub accepted_at: Option<u64>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct DeviceAlias {
+    pub alias: String,
+    pub device_id: String,
+    pub created_by: String,
+    pub created_at: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
 pub struct ConnectionSession {
     pub id: String,
     pub controller_id: String,
@@ -86,8 +807,26 @@ pub struct ConnectionSession {
     pub quality_score: Option<f32>,
 }
 
+// sqlite::memory: 下每个连接各有一个独立的数据库，改为按名称共享缓存的内存数据库，连接池中的连接看到同一份数据
+fn memory_url(url: &str) -> Option<String> {
+    match url {
+        "sqlite::memory:" | ":memory:" => Some(format!(
+            "sqlite:file:memdb-{}?mode=memory&cache=shared",
+            uuid::Uuid::new_v4().simple()
+        )),
+        _ => None,
+    }
+}
+
 impl EnterpriseDatabase {
     pub async fn new(url: &str) -> ResultType<Self> {
+        let url = match memory_url(url) {
+            Some(memory) => {
+                log::warn!("Enterprise Database - in-memory mode, all data is lost on exit");
+                memory
+            }
+            None => url.to_owned(),
+        };
         let n: usize = std::env::var("MAX_DATABASE_CONNECTIONS")
             .unwrap_or_else(|_| "10".to_owned())
             .parse()
@@ -96,20 +835,36 @@ impl EnterpriseDatabase {
         log::info!("Enterprise Database - MAX_CONNECTIONS={}", n);
         
         let pool = Pool::new(
-            DbPool {
-                url: url.to_owned(),
-            },
+            DbPool { url },
             n,
         );
         
         let _ = pool.get().await?; // 测试连接
-        let db = Self { pool };
+        let pool = MeteredPool(pool);
+        let device_writes = {
+            let pool = pool.clone();
+            WriteQueue::start("device registration", move |devices| {
+                let pool = pool.clone();
+                async move { pool.register_devices(&devices).await }
+            })
+        };
+        let db = Self { pool, device_writes };
         db.create_tables().await?;
         db.create_default_admin().await?;
         
         Ok(db)
     }
 
+    // 独立的内存数据库，供测试和临时演示使用，不产生任何文件
+    pub async fn memory() -> ResultType<Self> {
+        Self::new("sqlite::memory:").await
+    }
+
+    // 连接池当前状态，供 /metrics 和连接池争用检查使用
+    pub fn pool_status(&self) -> deadpool::Status {
+        self.pool.0.status()
+    }
+
     async fn create_tables(&self) -> ResultType<()> {
         let mut conn = self.pool.get().await?;
         
@@ -137,6 +892,7 @@ impl EnterpriseDatabase {
         )
         .execute(conn.deref_mut())
         .await?;
+        self.create_user_unique_indexes(conn.deref_mut()).await?;
 
         // 会话表
         sqlx::query!(
@@ -188,6 +944,8 @@ impl EnterpriseDatabase {
                 os TEXT NOT NULL,
                 version TEXT NOT NULL,
                 ip_address TEXT NOT NULL,
+                ipv6_address TEXT,
+                uuid TEXT,
                 mac_address TEXT,
                 last_online INTEGER NOT NULL,
                 owner_id TEXT NOT NULL,
@@ -221,10 +979,44 @@ impl EnterpriseDatabase {
             CREATE INDEX IF NOT EXISTS idx_audit_logs_user ON audit_logs(user_id);
             CREATE INDEX IF NOT EXISTS idx_audit_logs_device ON audit_logs(device_id);
             CREATE INDEX IF NOT EXISTS idx_audit_logs_timestamp ON audit_logs(timestamp);
+            CREATE INDEX IF NOT EXISTS idx_audit_logs_action ON audit_logs(action, timestamp);
+            CREATE INDEX IF NOT EXISTS idx_audit_logs_ip ON audit_logs(ip_address);
+            "#
+        )
+        .execute(conn.deref_mut())
+        .await?;
+
+        // 审计日志详情全文索引，trigram 分词支持中文和任意子串检索；已有数据库首次创建时重建索引
+        let audit_fts_exists = sqlx::query!(
+            "SELECT name FROM sqlite_master WHERE type = 'table' AND name = 'audit_logs_fts'"
+        )
+        .fetch_optional(conn.deref_mut())
+        .await?
+        .is_some();
+        sqlx::query!(
+            r#"
+            CREATE VIRTUAL TABLE IF NOT EXISTS audit_logs_fts USING fts5(
+                details, content='audit_logs', content_rowid='id', tokenize='trigram'
+            );
+            CREATE TRIGGER IF NOT EXISTS audit_logs_fts_insert AFTER INSERT ON audit_logs BEGIN
+                INSERT INTO audit_logs_fts(rowid, details) VALUES (new.id, new.details);
+            END;
+            CREATE TRIGGER IF NOT EXISTS audit_logs_fts_delete AFTER DELETE ON audit_logs BEGIN
+                INSERT INTO audit_logs_fts(audit_logs_fts, rowid, details) VALUES ('delete', old.id, old.details);
+            END;
+            CREATE TRIGGER IF NOT EXISTS audit_logs_fts_update AFTER UPDATE OF details ON audit_logs BEGIN
+                INSERT INTO audit_logs_fts(audit_logs_fts, rowid, details) VALUES ('delete', old.id, old.details);
+                INSERT INTO audit_logs_fts(rowid, details) VALUES (new.id, new.details);
+            END;
             "#
         )
         .execute(conn.deref_mut())
         .await?;
+        if !audit_fts_exists {
+            sqlx::query!("INSERT INTO audit_logs_fts(audit_logs_fts) VALUES ('rebuild')")
+                .execute(conn.deref_mut())
+                .await?;
+        }
 
         // 连接会话表
         sqlx::query!(
@@ -248,279 +1040,5338 @@ impl EnterpriseDatabase {
         .execute(conn.deref_mut())
         .await?;
 
-        // 原有的peer表保持兼容性
+        // 会话内操作事件表，直连会话在 connection_sessions 中可能没有记录，因此不设外键
         sqlx::query!(
             r#"
-            CREATE TABLE IF NOT EXISTS peer (
-                guid BLOB PRIMARY KEY NOT NULL,
-                id VARCHAR(100) NOT NULL,
-                uuid BLOB NOT NULL,
-                pk BLOB NOT NULL,
-                created_at DATETIME NOT NULL DEFAULT(current_timestamp),
-                user BLOB,
-                status TINYINT,
-                note VARCHAR(300),
-                info TEXT NOT NULL
-            ) WITHOUT ROWID;
-            CREATE UNIQUE INDEX IF NOT EXISTS index_peer_id ON peer (id);
+            CREATE TABLE IF NOT EXISTS session_events (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                session_id TEXT NOT NULL,
+                device_id TEXT NOT NULL,
+                event_type TEXT NOT NULL,
+                details TEXT,
+                occurred_at INTEGER NOT NULL,
+                received_at INTEGER NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS idx_session_events_session ON session_events(session_id, occurred_at);
             "#
         )
         .execute(conn.deref_mut())
         .await?;
 
-        Ok(())
-    }
-
-    async fn create_default_admin(&self) -> ResultType<()> {
-        // 检查是否已存在管理员用户
-        let existing_admin = self.get_user_by_username("admin").await?;
-        if existing_admin.is_some() {
-            return Ok(());
-        }
-
-        // 创建默认管理员账户
-        let admin_user = User {
-            id: uuid::Uuid::new_v4().to_string(),
-            username: "admin".to_string(),
-            password_hash: bcrypt::hash("admin123", bcrypt::DEFAULT_COST)?,
-            email: Some("admin@rustdesk.local".to_string()),
-            role: UserRole::SuperAdmin,
-            groups: vec!["administrators".to_string()],
-            enabled: true,
-            created_at: SystemTime::now(),
-            last_login: None,
-            failed_login_attempts: 0,
-            locked_until: None,
-            two_factor_enabled: false,
-            two_factor_secret: None,
-        };
-
-        self.create_user(&admin_user).await?;
-        log::info!("Created default admin user - username: admin, password: admin123");
-        log::warn!("Please change the default admin password immediately!");
-
-        Ok(())
-    }
-
-    // 用户管理方法
-    pub async fn create_user(&self, user: &User) -> ResultType<()> {
-        let mut conn = self.pool.get().await?;
-        let created_at = user.created_at.duration_since(std::time::UNIX_EPOCH)?.as_secs() as i64;
-        let groups_json = serde_json::to_string(&user.groups)?;
-        let role_str = format!("{:?}", user.role);
-
+        // 系统设置表（键值对，值为JSON或纯文本）
         sqlx::query!(
             r#"
-            INSERT INTO users (
-                id, username, password_hash, email, role, groups, enabled,
-                created_at, failed_login_attempts, two_factor_enabled, two_factor_secret
-            ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
-            "#,
-            user.id,
-            user.username,
-            user.password_hash,
-            user.email,
-            role_str,
-            groups_json,
-            user.enabled,
-            created_at,
-            user.failed_login_attempts,
-            user.two_factor_enabled,
-            user.two_factor_secret
+            CREATE TABLE IF NOT EXISTS settings (
+                key TEXT PRIMARY KEY NOT NULL,
+                value TEXT NOT NULL,
+                updated_by TEXT,
+                updated_at INTEGER NOT NULL
+            );
+            "#
         )
         .execute(conn.deref_mut())
         .await?;
 
-        Ok(())
-    }
-
-    pub async fn get_user_by_username(&self, username: &str) -> ResultType<Option<User>> {
-        let mut conn = self.pool.get().await?;
-        
+        // 策略设置的版本记录，id 即全局递增的配置版本号，value 为空表示恢复默认
+        sqlx::query!(
+            r#"
+            CREATE TABLE IF NOT EXISTS setting_versions (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                key TEXT NOT NULL,
+                value TEXT,
+                updated_by TEXT,
+                created_at INTEGER NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS idx_setting_versions_key ON setting_versions(key, id);
+            "#
+        )
+        .execute(conn.deref_mut())
+        .await?;
+
+        // 启用版本记录前已有的策略设置作为首个版本
+        for key in server_config::POLICY_KEYS {
+            sqlx::query!(
+                r#"
+                INSERT INTO setting_versions (key, value, updated_by, created_at)
+                SELECT key, value, updated_by, updated_at FROM settings
+                WHERE key = ? AND NOT EXISTS (SELECT 1 FROM setting_versions WHERE key = ?)
+                "#,
+                key,
+                key
+            )
+            .execute(conn.deref_mut())
+            .await?;
+        }
+
+        // 客户端策略表
+        sqlx::query!(
+            r#"
+            CREATE TABLE IF NOT EXISTS strategies (
+                id TEXT PRIMARY KEY NOT NULL,
+                name TEXT NOT NULL UNIQUE,
+                description TEXT,
+                config_options TEXT NOT NULL DEFAULT '{}',
+                group_ids TEXT NOT NULL DEFAULT '[]',
+                device_ids TEXT NOT NULL DEFAULT '[]',
+                enabled BOOLEAN NOT NULL DEFAULT 1,
+                updated_at INTEGER NOT NULL
+            );
+            "#
+        )
+        .execute(conn.deref_mut())
+        .await?;
+
+        // 设备别名表，别名不区分大小写且全局唯一，每台设备最多一个别名
+        sqlx::query!(
+            r#"
+            CREATE TABLE IF NOT EXISTS device_aliases (
+                alias TEXT PRIMARY KEY NOT NULL COLLATE NOCASE,
+                device_id TEXT NOT NULL UNIQUE,
+                created_by TEXT NOT NULL,
+                created_at INTEGER NOT NULL
+            );
+            "#
+        )
+        .execute(conn.deref_mut())
+        .await?;
+
+        // 设备封禁表
+        sqlx::query!(
+            r#"
+            CREATE TABLE IF NOT EXISTS device_bans (
+                device_id TEXT PRIMARY KEY NOT NULL,
+                uuid TEXT,
+                reason TEXT NOT NULL,
+                banned_by TEXT NOT NULL,
+                created_at INTEGER NOT NULL,
+                expires_at INTEGER
+            );
+            CREATE INDEX IF NOT EXISTS idx_device_bans_uuid ON device_bans(uuid);
+            "#
+        )
+        .execute(conn.deref_mut())
+        .await?;
+
+        // 预登记ID表，ID注册策略要求预登记时只有表中的ID可以注册
+        sqlx::query!(
+            r#"
+            CREATE TABLE IF NOT EXISTS provisioned_ids (
+                id TEXT PRIMARY KEY NOT NULL,
+                note TEXT,
+                created_by TEXT NOT NULL,
+                created_at INTEGER NOT NULL
+            );
+            "#
+        )
+        .execute(conn.deref_mut())
+        .await?;
+
+        // 邮件验证码授权表，管理员为丢失验证器的用户临时开放邮件验证码登录
+        sqlx::query!(
+            r#"
+            CREATE TABLE IF NOT EXISTS email_otp_grants (
+                user_id TEXT PRIMARY KEY NOT NULL,
+                granted_by TEXT NOT NULL,
+                created_at INTEGER NOT NULL,
+                expires_at INTEGER NOT NULL
+            );
+            "#
+        )
+        .execute(conn.deref_mut())
+        .await?;
+
+        // 受信任浏览器表，登录时勾选"记住此浏览器"后在有效期内免2FA
+        sqlx::query!(
+            r#"
+            CREATE TABLE IF NOT EXISTS trusted_devices (
+                id TEXT PRIMARY KEY NOT NULL,
+                user_id TEXT NOT NULL,
+                user_agent TEXT,
+                created_at INTEGER NOT NULL,
+                expires_at INTEGER NOT NULL,
+                last_used INTEGER,
+                FOREIGN KEY (user_id) REFERENCES users (id) ON DELETE CASCADE
+            );
+            CREATE INDEX IF NOT EXISTS idx_trusted_devices_user_id ON trusted_devices(user_id);
+            "#
+        )
+        .execute(conn.deref_mut())
+        .await?;
+
+        // 端到端密钥下发记录表，审计每次打洞/中继信令是否携带签名公钥
+        sqlx::query!(
+            r#"
+            CREATE TABLE IF NOT EXISTS key_exchanges (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                session_id TEXT NOT NULL,
+                device_id TEXT NOT NULL,
+                requester TEXT NOT NULL,
+                channel TEXT NOT NULL,
+                offer TEXT NOT NULL,
+                protected BOOLEAN NOT NULL,
+                created_at INTEGER NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS idx_key_exchanges_device ON key_exchanges(device_id);
+            CREATE INDEX IF NOT EXISTS idx_key_exchanges_created_at ON key_exchanges(created_at);
+            "#
+        )
+        .execute(conn.deref_mut())
+        .await?;
+
+        // 安全事件表，记录检出恶意文件等需要安全人员处理的事件
+        sqlx::query!(
+            r#"
+            CREATE TABLE IF NOT EXISTS security_events (
+                id TEXT PRIMARY KEY NOT NULL,
+                event_type TEXT NOT NULL,
+                severity TEXT NOT NULL,
+                user_id TEXT,
+                device_id TEXT,
+                ip_address TEXT NOT NULL,
+                details TEXT NOT NULL,
+                created_at INTEGER NOT NULL,
+                resolved BOOLEAN NOT NULL DEFAULT 0,
+                resolution_notes TEXT
+            );
+            CREATE INDEX IF NOT EXISTS idx_security_events_created_at ON security_events(created_at);
+            "#
+        )
+        .execute(conn.deref_mut())
+        .await?;
+
+        // 文件传输历史表，记录每个已结束传输的发起人、设备、文件和结果
+        sqlx::query!(
+            r#"
+            CREATE TABLE IF NOT EXISTS file_transfers (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                transfer_id TEXT NOT NULL,
+                user_id TEXT NOT NULL,
+                device_id TEXT,
+                file_path TEXT NOT NULL,
+                transfer_type TEXT NOT NULL,
+                file_size INTEGER NOT NULL,
+                file_hash TEXT NOT NULL,
+                bytes_transferred INTEGER NOT NULL,
+                status TEXT NOT NULL,
+                error_message TEXT,
+                started_at INTEGER NOT NULL,
+                finished_at INTEGER NOT NULL,
+                duration_ms INTEGER NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS idx_file_transfers_finished_at ON file_transfers(finished_at);
+            CREATE INDEX IF NOT EXISTS idx_file_transfers_user ON file_transfers(user_id);
+            CREATE INDEX IF NOT EXISTS idx_file_transfers_device ON file_transfers(device_id);
+            "#
+        )
+        .execute(conn.deref_mut())
+        .await?;
+
+        // 远程任务表及各设备的执行结果
+        sqlx::query!(
+            r#"
+            CREATE TABLE IF NOT EXISTS jobs (
+                id TEXT PRIMARY KEY NOT NULL,
+                name TEXT NOT NULL,
+                command TEXT NOT NULL,
+                shell TEXT NOT NULL,
+                group_ids TEXT NOT NULL DEFAULT '[]',
+                device_ids TEXT NOT NULL DEFAULT '[]',
+                timeout_secs INTEGER NOT NULL,
+                created_by TEXT NOT NULL,
+                created_at INTEGER NOT NULL,
+                expires_at INTEGER NOT NULL,
+                cancelled BOOLEAN NOT NULL DEFAULT 0
+            );
+            CREATE TABLE IF NOT EXISTS job_runs (
+                job_id TEXT NOT NULL,
+                device_id TEXT NOT NULL,
+                status TEXT NOT NULL,
+                exit_code INTEGER,
+                output TEXT NOT NULL DEFAULT '',
+                dispatched_at INTEGER NOT NULL,
+                deadline INTEGER NOT NULL,
+                finished_at INTEGER,
+                PRIMARY KEY (job_id, device_id)
+            );
+            CREATE INDEX IF NOT EXISTS idx_job_runs_status ON job_runs(status, deadline);
+            "#
+        )
+        .execute(conn.deref_mut())
+        .await?;
+
+        // 设备消息表及各设备的送达回执
+        sqlx::query!(
+            r#"
+            CREATE TABLE IF NOT EXISTS device_messages (
+                id TEXT PRIMARY KEY NOT NULL,
+                title TEXT NOT NULL DEFAULT '',
+                body TEXT NOT NULL,
+                level TEXT NOT NULL,
+                group_ids TEXT NOT NULL DEFAULT '[]',
+                device_ids TEXT NOT NULL DEFAULT '[]',
+                created_by TEXT NOT NULL,
+                created_at INTEGER NOT NULL,
+                expires_at INTEGER NOT NULL,
+                cancelled BOOLEAN NOT NULL DEFAULT 0
+            );
+            CREATE TABLE IF NOT EXISTS message_deliveries (
+                message_id TEXT NOT NULL,
+                device_id TEXT NOT NULL,
+                status TEXT NOT NULL,
+                delivered_at INTEGER NOT NULL,
+                acknowledged_at INTEGER,
+                PRIMARY KEY (message_id, device_id)
+            );
+            "#
+        )
+        .execute(conn.deref_mut())
+        .await?;
+
+        // 设备资产清单表，由客户端上报的系统信息生成
+        sqlx::query!(
+            r#"
+            CREATE TABLE IF NOT EXISTS device_inventory (
+                device_id TEXT PRIMARY KEY NOT NULL,
+                hostname TEXT NOT NULL,
+                username TEXT NOT NULL,
+                os TEXT NOT NULL,
+                os_build TEXT,
+                cpu TEXT NOT NULL,
+                memory TEXT NOT NULL,
+                memory_bytes INTEGER,
+                rustdesk_version TEXT NOT NULL,
+                updated_at INTEGER NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS idx_device_inventory_os ON device_inventory(os);
+            CREATE INDEX IF NOT EXISTS idx_device_inventory_version ON device_inventory(rustdesk_version);
+            "#
+        )
+        .execute(conn.deref_mut())
+        .await?;
+
+        // 客户端安装包表，文件本身由存储后端保存
+        sqlx::query!(
+            r#"
+            CREATE TABLE IF NOT EXISTS update_artifacts (
+                platform TEXT NOT NULL,
+                version TEXT NOT NULL,
+                file_name TEXT NOT NULL,
+                size INTEGER NOT NULL,
+                sha256 TEXT NOT NULL,
+                uploaded_by TEXT NOT NULL,
+                uploaded_at INTEGER NOT NULL,
+                PRIMARY KEY (platform, version)
+            );
+            "#
+        )
+        .execute(conn.deref_mut())
+        .await?;
+
+        // 紧急访问记录表，reviewed_by 为空表示待复核
+        sqlx::query!(
+            r#"
+            CREATE TABLE IF NOT EXISTS emergency_access (
+                id TEXT PRIMARY KEY,
+                user_id TEXT NOT NULL,
+                username TEXT NOT NULL,
+                device_id TEXT NOT NULL,
+                justification TEXT NOT NULL,
+                ip_address TEXT NOT NULL,
+                created_at INTEGER NOT NULL,
+                expires_at INTEGER NOT NULL,
+                ended_at INTEGER,
+                reviewed_by TEXT,
+                reviewed_at INTEGER,
+                review_notes TEXT
+            );
+            CREATE INDEX IF NOT EXISTS idx_emergency_access_device ON emergency_access(device_id, user_id);
+            "#
+        )
+        .execute(conn.deref_mut())
+        .await?;
+
+        // 代操作记录表，id 为代操作令牌的 jti
+        sqlx::query!(
+            r#"
+            CREATE TABLE IF NOT EXISTS impersonation_sessions (
+                id TEXT PRIMARY KEY,
+                actor_id TEXT NOT NULL,
+                actor_username TEXT NOT NULL,
+                user_id TEXT NOT NULL,
+                username TEXT NOT NULL,
+                reason TEXT NOT NULL,
+                started_at INTEGER NOT NULL,
+                expires_at INTEGER NOT NULL,
+                ended_at INTEGER
+            );
+            CREATE INDEX IF NOT EXISTS idx_impersonation_sessions_started ON impersonation_sessions(started_at);
+            "#
+        )
+        .execute(conn.deref_mut())
+        .await?;
+
+        // 设置变更审批表，old_value/new_value 为设置的原始 JSON
+        sqlx::query!(
+            r#"
+            CREATE TABLE IF NOT EXISTS setting_changes (
+                id TEXT PRIMARY KEY,
+                key TEXT NOT NULL,
+                old_value TEXT,
+                new_value TEXT NOT NULL,
+                requested_by TEXT NOT NULL,
+                requested_by_name TEXT NOT NULL,
+                requested_at INTEGER NOT NULL,
+                status TEXT NOT NULL,
+                reviewed_by TEXT,
+                reviewed_by_name TEXT,
+                reviewed_at INTEGER,
+                comment TEXT
+            );
+            CREATE INDEX IF NOT EXISTS idx_setting_changes_status ON setting_changes(status, requested_at);
+            "#
+        )
+        .execute(conn.deref_mut())
+        .await?;
+
+        // 数据擦除报告表，只保存被擦除主体标识的哈希
+        sqlx::query!(
+            r#"
+            CREATE TABLE IF NOT EXISTS erasure_reports (
+                id TEXT PRIMARY KEY NOT NULL,
+                subject TEXT NOT NULL,
+                subject_hash TEXT NOT NULL,
+                pseudonym TEXT NOT NULL,
+                mode TEXT NOT NULL,
+                reason TEXT NOT NULL,
+                requested_by TEXT NOT NULL,
+                created_at INTEGER NOT NULL,
+                records TEXT NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS idx_erasure_reports_subject_hash ON erasure_reports(subject_hash);
+            "#
+        )
+        .execute(conn.deref_mut())
+        .await?;
+
+        // 用户邀请表，被邀请用户通过邮件中的链接设置密码，接受即视为邮箱已验证
+        sqlx::query!(
+            r#"
+            CREATE TABLE IF NOT EXISTS user_invites (
+                id TEXT PRIMARY KEY NOT NULL,
+                user_id TEXT NOT NULL,
+                email TEXT NOT NULL,
+                invited_by TEXT NOT NULL,
+                created_at INTEGER NOT NULL,
+                expires_at INTEGER NOT NULL,
+                accepted_at INTEGER,
+                FOREIGN KEY (user_id) REFERENCES users (id) ON DELETE CASCADE
+            );
+            CREATE INDEX IF NOT EXISTS idx_user_invites_user_id ON user_invites(user_id);
+            "#
+        )
+        .execute(conn.deref_mut())
+        .await?;
+
+        // 用户通知偏好，data 为 NotificationPreferences 的 JSON
+        sqlx::query!(
+            r#"
+            CREATE TABLE IF NOT EXISTS notification_preferences (
+                user_id TEXT PRIMARY KEY NOT NULL,
+                data TEXT NOT NULL,
+                updated_at INTEGER NOT NULL,
+                FOREIGN KEY (user_id) REFERENCES users (id) ON DELETE CASCADE
+            );
+            "#
+        )
+        .execute(conn.deref_mut())
+        .await?;
+
+        // 公告表，生效时间内显示在管理界面并可下发给客户端
+        sqlx::query!(
+            r#"
+            CREATE TABLE IF NOT EXISTS announcements (
+                id TEXT PRIMARY KEY NOT NULL,
+                title TEXT NOT NULL,
+                body TEXT NOT NULL DEFAULT '',
+                level TEXT NOT NULL,
+                starts_at INTEGER NOT NULL,
+                ends_at INTEGER NOT NULL,
+                deliver_to_clients BOOLEAN NOT NULL DEFAULT 0,
+                created_by TEXT NOT NULL,
+                created_at INTEGER NOT NULL,
+                updated_at INTEGER NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS idx_announcements_ends_at ON announcements(ends_at);
+            "#
+        )
+        .execute(conn.deref_mut())
+        .await?;
+
+        // 会话 QoS 采样，直连会话在 connection_sessions 中可能没有记录，因此不设外键
+        sqlx::query!(
+            r#"
+            CREATE TABLE IF NOT EXISTS session_qos (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                session_id TEXT NOT NULL,
+                device_id TEXT NOT NULL,
+                relay_server TEXT NOT NULL DEFAULT '',
+                latency_ms INTEGER NOT NULL,
+                packet_loss REAL NOT NULL,
+                bitrate_kbps INTEGER NOT NULL,
+                reported_at INTEGER NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS idx_session_qos_session ON session_qos(session_id);
+            CREATE INDEX IF NOT EXISTS idx_session_qos_device ON session_qos(device_id, reported_at);
+            CREATE INDEX IF NOT EXISTS idx_session_qos_reported_at ON session_qos(reported_at);
+            "#
+        )
+        .execute(conn.deref_mut())
+        .await?;
+
+        // 下发给会话两端的编码推荐及客户端回执，每个会话的每台设备只保留最新一条
+        sqlx::query!(
+            r#"
+            CREATE TABLE IF NOT EXISTS codec_recommendations (
+                session_id TEXT NOT NULL,
+                device_id TEXT NOT NULL,
+                data TEXT NOT NULL,
+                issued_at INTEGER NOT NULL,
+                acked_at INTEGER,
+                PRIMARY KEY (session_id, device_id)
+            );
+            CREATE INDEX IF NOT EXISTS idx_codec_recommendations_issued_at ON codec_recommendations(issued_at);
+            "#
+        )
+        .execute(conn.deref_mut())
+        .await?;
+
+        // 设备上报的编码器能力，每台设备一条
+        sqlx::query!(
+            r#"
+            CREATE TABLE IF NOT EXISTS device_capabilities (
+                device_id TEXT PRIMARY KEY,
+                data TEXT NOT NULL,
+                updated_at INTEGER NOT NULL
+            )
+            "#
+        )
+        .execute(conn.deref_mut())
+        .await?;
+
+        // 设备和用户的自定义字段值，data 为字段名到值的 JSON 对象
+        sqlx::query!(
+            r#"
+            CREATE TABLE IF NOT EXISTS custom_field_values (
+                entity TEXT NOT NULL,
+                entity_id TEXT NOT NULL,
+                data TEXT NOT NULL DEFAULT '{}',
+                updated_at INTEGER NOT NULL,
+                PRIMARY KEY (entity, entity_id)
+            );
+            "#
+        )
+        .execute(conn.deref_mut())
+        .await?;
+
+        // 用户保存的设备视图
+        sqlx::query!(
+            r#"
+            CREATE TABLE IF NOT EXISTS device_views (
+                id TEXT PRIMARY KEY NOT NULL,
+                user_id TEXT NOT NULL,
+                name TEXT NOT NULL,
+                filter TEXT NOT NULL,
+                created_at INTEGER NOT NULL,
+                updated_at INTEGER NOT NULL,
+                UNIQUE (user_id, name)
+            );
+            "#
+        )
+        .execute(conn.deref_mut())
+        .await?;
+
+        // 用户最近连接的设备和收藏的设备，设备删除后记录保留，查询时与 devices 关联过滤
+        sqlx::query!(
+            r#"
+            CREATE TABLE IF NOT EXISTS recent_connections (
+                user_id TEXT NOT NULL,
+                device_id TEXT NOT NULL,
+                last_connected_at INTEGER NOT NULL,
+                connection_count INTEGER NOT NULL DEFAULT 1,
+                PRIMARY KEY (user_id, device_id)
+            );
+            CREATE INDEX IF NOT EXISTS idx_recent_connections_user ON recent_connections(user_id, last_connected_at);
+            CREATE TABLE IF NOT EXISTS favorite_devices (
+                user_id TEXT NOT NULL,
+                device_id TEXT NOT NULL,
+                created_at INTEGER NOT NULL,
+                PRIMARY KEY (user_id, device_id)
+            );
+            "#
+        )
+        .execute(conn.deref_mut())
+        .await?;
+
+        // 设备上下线记录，用于计算在线率
+        sqlx::query!(
+            r#"
+            CREATE TABLE IF NOT EXISTS device_status_history (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                device_id TEXT NOT NULL,
+                online BOOLEAN NOT NULL,
+                changed_at INTEGER NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS idx_device_status_history_device ON device_status_history(device_id, changed_at);
+            CREATE INDEX IF NOT EXISTS idx_device_status_history_changed_at ON device_status_history(changed_at);
+            "#
+        )
+        .execute(conn.deref_mut())
+        .await?;
+
+        // 客户端连通性自检结果
+        sqlx::query!(
+            r#"
+            CREATE TABLE IF NOT EXISTS device_self_tests (
+                test_id TEXT PRIMARY KEY NOT NULL,
+                device_id TEXT NOT NULL,
+                data TEXT NOT NULL,
+                passed BOOLEAN NOT NULL,
+                completed_at INTEGER NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS idx_device_self_tests_device ON device_self_tests(device_id, completed_at);
+            "#
+        )
+        .execute(conn.deref_mut())
+        .await?;
+
+        // 设备备注，支持人员记录的排障信息
+        sqlx::query!(
+            r#"
+            CREATE TABLE IF NOT EXISTS device_notes (
+                id TEXT PRIMARY KEY NOT NULL,
+                device_id TEXT NOT NULL,
+                author_id TEXT NOT NULL,
+                author_name TEXT NOT NULL,
+                body TEXT NOT NULL,
+                created_at INTEGER NOT NULL,
+                updated_at INTEGER NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS idx_device_notes_device ON device_notes(device_id, created_at);
+            "#
+        )
+        .execute(conn.deref_mut())
+        .await?;
+
+        // 设备出现过的公钥，同一设备同一公钥只记录一次；status 为 accepted/pending/rejected
+        sqlx::query!(
+            r#"
+            CREATE TABLE IF NOT EXISTS device_pk_history (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                device_id TEXT NOT NULL,
+                pk TEXT NOT NULL,
+                uuid TEXT NOT NULL,
+                ip_address TEXT NOT NULL,
+                seen_at INTEGER NOT NULL,
+                status TEXT NOT NULL,
+                decided_by TEXT,
+                decided_at INTEGER,
+                UNIQUE (device_id, pk)
+            );
+            CREATE INDEX IF NOT EXISTS idx_device_pk_history_status ON device_pk_history(status);
+            "#
+        )
+        .execute(conn.deref_mut())
+        .await?;
+
+        // 用户语言偏好，language 为 zh/en，未设置时按 Accept-Language 协商
+        sqlx::query!(
+            r#"
+            CREATE TABLE IF NOT EXISTS user_languages (
+                user_id TEXT PRIMARY KEY NOT NULL,
+                language TEXT NOT NULL,
+                updated_at INTEGER NOT NULL,
+                FOREIGN KEY (user_id) REFERENCES users (id) ON DELETE CASCADE
+            );
+            "#
+        )
+        .execute(conn.deref_mut())
+        .await?;
+
+        // 原有的peer表保持兼容性
+        sqlx::query!(
+            r#"
+            CREATE TABLE IF NOT EXISTS peer (
+                guid BLOB PRIMARY KEY NOT NULL,
+                id VARCHAR(100) NOT NULL,
+                uuid BLOB NOT NULL,
+                pk BLOB NOT NULL,
+                created_at DATETIME NOT NULL DEFAULT(current_timestamp),
+                user BLOB,
+                status TINYINT,
+                note VARCHAR(300),
+                info TEXT NOT NULL
+            ) WITHOUT ROWID;
+            CREATE UNIQUE INDEX IF NOT EXISTS index_peer_id ON peer (id);
+            "#
+        )
+        .execute(conn.deref_mut())
+        .await?;
+
+        Ok(())
+    }
+
+    async fn create_default_admin(&self) -> ResultType<()> {
+        // 检查是否已存在管理员用户
+        let existing_admin = self.get_user_by_username("admin").await?;
+        if existing_admin.is_some() {
+            return Ok(());
+        }
+
+        // 创建默认管理员账户
+        let admin_user = User {
+            id: uuid::Uuid::new_v4().to_string(),
+            username: "admin".to_string(),
+            password_hash: bcrypt::hash("admin123", bcrypt::DEFAULT_COST)?,
+            email: Some("admin@rustdesk.local".to_string()),
+            role: UserRole::SuperAdmin,
+            groups: vec!["administrators".to_string()],
+            enabled: true,
+            created_at: SystemTime::now(),
+            last_login: None,
+            failed_login_attempts: 0,
+            locked_until: None,
+            two_factor_enabled: false,
+            two_factor_secret: None,
+        };
+
+        if let Some(conflict) = self.create_user(&admin_user).await? {
+            log::warn!("Default admin user not created, {:?} already in use", conflict);
+            return Ok(());
+        }
+        log::info!("Created default admin user - username: admin, password: admin123");
+        log::warn!("Please change the default admin password immediately!");
+
+        Ok(())
+    }
+
+    // 用户名和邮箱不区分大小写唯一。已有数据存在重复时不建索引(否则无法启动)，记录警告，
+    // 新建用户仍由 create_user 的检查保证不重复，重名用户由 get_user_by_username 按原样匹配
+    async fn create_user_unique_indexes(&self, conn: &mut SqliteConnection) -> ResultType<()> {
+        let row = sqlx::query!(
+            r#"SELECT COUNT(*) as "count!: i64" FROM (
+                SELECT 1 FROM users GROUP BY lower(username) HAVING COUNT(*) > 1
+            )"#
+        )
+        .fetch_one(&mut *conn)
+        .await?;
+        if row.count == 0 {
+            sqlx::query!("CREATE UNIQUE INDEX IF NOT EXISTS idx_users_username_nocase ON users(username COLLATE NOCASE)")
+                .execute(&mut *conn)
+                .await?;
+        } else {
+            log::warn!(
+                "{} usernames differ only in case, they can only log in with the exact username; rename them to enforce unique usernames",
+                row.count
+            );
+        }
+
+        let row = sqlx::query!(
+            r#"SELECT COUNT(*) as "count!: i64" FROM (
+                SELECT 1 FROM users WHERE email IS NOT NULL AND email != ''
+                GROUP BY lower(email) HAVING COUNT(*) > 1
+            )"#
+        )
+        .fetch_one(&mut *conn)
+        .await?;
+        if row.count == 0 {
+            sqlx::query!(
+                r#"
+                CREATE UNIQUE INDEX IF NOT EXISTS idx_users_email_nocase ON users(email COLLATE NOCASE)
+                WHERE email IS NOT NULL AND email != ''
+                "#
+            )
+            .execute(&mut *conn)
+            .await?;
+        } else {
+            log::warn!("{} email addresses are shared by several users, unique emails are not enforced", row.count);
+        }
+
+        Ok(())
+    }
+
+    // 用户名或邮箱(不区分大小写)已被其他用户占用时返回冲突项
+    async fn find_user_conflict(conn: &mut SqliteConnection, user: &User) -> ResultType<Option<UserConflict>> {
+        let email = user.email.as_deref().filter(|x| !x.is_empty());
+        let row = sqlx::query!(
+            r#"
+            SELECT
+                EXISTS (SELECT 1 FROM users WHERE username = ?1 COLLATE NOCASE) as "username!: bool",
+                EXISTS (SELECT 1 FROM users WHERE ?2 IS NOT NULL AND email = ?2 COLLATE NOCASE) as "email!: bool"
+            "#,
+            user.username,
+            email
+        )
+        .fetch_one(conn)
+        .await?;
+
+        Ok(if row.username {
+            Some(UserConflict::Username)
+        } else if row.email {
+            Some(UserConflict::Email)
+        } else {
+            None
+        })
+    }
+
+    // 用户管理方法
+    // 检查和写入在同一事务中；并发创建同名用户时由唯一索引拒绝，同样返回冲突而不是数据库错误
+    pub async fn create_user(&self, user: &User) -> ResultType<Option<UserConflict>> {
+        let mut conn = self.pool.get().await?;
+        let created_at = user.created_at.duration_since(std::time::UNIX_EPOCH)?.as_secs() as i64;
+        let groups_json = serde_json::to_string(&user.groups)?;
+        let role_str = format!("{:?}", user.role);
+
+        let mut tx = conn.deref_mut().begin().await?;
+        if let Some(conflict) = Self::find_user_conflict(&mut tx, user).await? {
+            return Ok(Some(conflict));
+        }
+        let result = sqlx::query!(
+            r#"
+            INSERT INTO users (
+                id, username, password_hash, email, role, groups, enabled,
+                created_at, failed_login_attempts, two_factor_enabled, two_factor_secret
+            ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+            "#,
+            user.id,
+            user.username,
+            user.password_hash,
+            user.email,
+            role_str,
+            groups_json,
+            user.enabled,
+            created_at,
+            user.failed_login_attempts,
+            user.two_factor_enabled,
+            user.two_factor_secret
+        )
+        .execute(&mut tx)
+        .await;
+        match result {
+            Ok(_) => {
+                tx.commit().await?;
+                Ok(None)
+            }
+            Err(sqlx::Error::Database(e)) if is_unique_violation(e.as_ref()) => {
+                drop(tx);
+                let conflict = Self::find_user_conflict(conn.deref_mut(), user).await?;
+                Ok(Some(conflict.unwrap_or(UserConflict::Username)))
+            }
+            // 用户 ID 由服务端生成，重复不是用户名冲突
+            Err(sqlx::Error::Database(e)) if is_primary_key_violation(e.as_ref()) => {
+                Err(format!("User id {} already exists", user.id).into())
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub async fn get_user_by_username(&self, username: &str) -> ResultType<Option<User>> {
+        let mut conn = self.pool.get().await?;
+        
+        // 旧数据中只有大小写不同的重名用户时不能任选一个，只接受大小写完全一致的用户名
+        let rows = sqlx::query!(
+            "SELECT * FROM users WHERE username = ? COLLATE NOCASE",
+            username
+        )
+        .fetch_all(conn.deref_mut())
+        .await?;
+        let row = if rows.len() > 1 {
+            rows.into_iter().find(|row| row.username == username)
+        } else {
+            rows.into_iter().next()
+        };
+
+        if let Some(row) = row {
+            let role = match row.role.as_str() {
+                "SuperAdmin" => UserRole::SuperAdmin,
+                "Admin" => UserRole::Admin,
+                "User" => UserRole::User,
+                "ReadOnly" => UserRole::ReadOnly,
+                "Auditor" => UserRole::Auditor,
+                _ => UserRole::User,
+            };
+
+            let groups: Vec<String> = serde_json::from_str(&row.groups).unwrap_or_default();
+            let created_at = std::time::UNIX_EPOCH + std::time::Duration::from_secs(row.created_at as u64);
+            let last_login = row.last_login.map(|ts| std::time::UNIX_EPOCH + std::time::Duration::from_secs(ts as u64));
+            let locked_until = row.locked_until.map(|ts| std::time::UNIX_EPOCH + std::time::Duration::from_secs(ts as u64));
+
+            Ok(Some(User {
+                id: row.id,
+                username: row.username,
+                password_hash: row.password_hash,
+                email: row.email,
+                role,
+                groups,
+                enabled: row.enabled,
+                created_at,
+                last_login,
+                failed_login_attempts: row.failed_login_attempts as u32,
+                locked_until,
+                two_factor_enabled: row.two_factor_enabled,
+                two_factor_secret: row.two_factor_secret,
+            }))
+        } else {
+            Ok(None)
+        }
+    }
+
+    pub async fn get_user_by_id(&self, id: &str) -> ResultType<Option<User>> {
+        let username = {
+            let mut conn = self.pool.get().await?;
+            sqlx::query!("SELECT username FROM users WHERE id = ?", id)
+                .fetch_optional(conn.deref_mut())
+                .await?
+                .map(|row| row.username)
+        };
+
+        match username {
+            Some(username) => self.get_user_by_username(&username).await,
+            None => Ok(None),
+        }
+    }
+
+    pub async fn update_user_login_info(&self, user_id: &str, success: bool) -> ResultType<()> {
+        let mut conn = self.pool.get().await?;
+        let now = SystemTime::now().duration_since(std::time::UNIX_EPOCH)?.as_secs() as i64;
+
+        if success {
+            sqlx::query!(
+                "UPDATE users SET last_login = ?, failed_login_attempts = 0, locked_until = NULL WHERE id = ?",
+                now,
+                user_id
+            )
+            .execute(conn.deref_mut())
+            .await?;
+        } else {
+            sqlx::query!(
+                "UPDATE users SET failed_login_attempts = failed_login_attempts + 1 WHERE id = ?",
+                user_id
+            )
+            .execute(conn.deref_mut())
+            .await?;
+        }
+
+        Ok(())
+    }
+
+    // 连续失败达到上限时锁定账户，解锁后重新计数
+    pub async fn lock_user(&self, user_id: &str, until: SystemTime) -> ResultType<()> {
+        let mut conn = self.pool.get().await?;
+        let until = until.duration_since(std::time::UNIX_EPOCH)?.as_secs() as i64;
+
+        sqlx::query!(
+            "UPDATE users SET failed_login_attempts = 0, locked_until = ? WHERE id = ?",
+            until,
+            user_id
+        )
+        .execute(conn.deref_mut())
+        .await?;
+
+        Ok(())
+    }
+
+    // 修改密码并解除锁定
+    pub async fn set_user_password(&self, user_id: &str, password_hash: &str) -> ResultType<()> {
+        let mut conn = self.pool.get().await?;
+
+        sqlx::query!(
+            "UPDATE users SET password_hash = ?, failed_login_attempts = 0, locked_until = NULL WHERE id = ?",
+            password_hash,
+            user_id
+        )
+        .execute(conn.deref_mut())
+        .await?;
+
+        Ok(())
+    }
+
+    // 设置双因素认证密钥；enabled 为 false 时表示待确认的密钥
+    pub async fn set_user_two_factor(&self, user_id: &str, secret: Option<&str>, enabled: bool) -> ResultType<()> {
+        let mut conn = self.pool.get().await?;
+
+        sqlx::query!(
+            "UPDATE users SET two_factor_secret = ?, two_factor_enabled = ? WHERE id = ?",
+            secret,
+            enabled,
+            user_id
+        )
+        .execute(conn.deref_mut())
+        .await?;
+
+        Ok(())
+    }
+
+    // 审计日志方法
+    pub async fn log_audit(&self, log: &AuditLog) -> ResultType<()> {
+        let mut conn = self.pool.get().await?;
+        let timestamp = log.timestamp.duration_since(std::time::UNIX_EPOCH)?.as_secs() as i64;
+        // 代操作期间的记录同时注明实际操作人
+        let details = match impersonation::attribution() {
+            Some(actor) => Some(match &log.details {
+                Some(details) => format!("{} {}", actor, details),
+                None => actor,
+            }),
+            None => log.details.clone(),
+        };
+
+        sqlx::query!(
+            r#"
+            INSERT INTO audit_logs (
+                user_id, device_id, action, details, ip_address, 
+                user_agent, timestamp, success
+            ) VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+            "#,
+            log.user_id,
+            log.device_id,
+            log.action,
+            details,
+            log.ip_address,
+            log.user_agent,
+            timestamp,
+            log.success
+        )
+        .execute(conn.deref_mut())
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn get_audit_logs(&self, filter: &AuditLogFilter, limit: i64, offset: i64) -> ResultType<Vec<AuditLog>> {
+        let mut conn = self.pool.get().await?;
+        let since = filter.since.map(|x| x as i64);
+        let until = filter.until.map(|x| x as i64);
+        // 三个字符及以上的关键字走全文索引，更短的关键字直接匹配
+        let (fts, text) = match filter.text.as_deref().filter(|x| !x.is_empty()) {
+            Some(text) if text.chars().count() >= 3 => (Some(format!("\"{}\"", text.replace('"', "\"\""))), None),
+            Some(text) => (None, Some(text.to_owned())),
+            None => (None, None),
+        };
+        let (ip_from, ip_to) = match filter.ip.as_ref().and_then(ip_prefix_range) {
+            Some((from, to)) => (Some(from), Some(to)),
+            None => (None, None),
+        };
+
+        // 按网段过滤时分批扫描，逐条判断后再分页
+        let (batch, mut scanned, mut skip) = if filter.ip.is_some() { (500, 0, offset) } else { (limit, offset, 0) };
+        let mut logs = Vec::new();
+        loop {
+            let rows = sqlx::query!(
+                r#"
+                SELECT id, user_id, device_id, action, details, ip_address, user_agent, timestamp, success
+                FROM audit_logs
+                WHERE (?1 IS NULL OR user_id = ?1)
+                    AND (?2 IS NULL OR device_id = ?2)
+                    AND (?3 IS NULL OR action = ?3)
+                    AND (?4 IS NULL OR success = ?4)
+                    AND (?5 IS NULL OR timestamp >= ?5)
+                    AND (?6 IS NULL OR timestamp <= ?6)
+                    AND (?7 IS NULL OR (ip_address >= ?7 AND ip_address < ?8))
+                    AND (?9 IS NULL OR id IN (SELECT rowid FROM audit_logs_fts WHERE audit_logs_fts MATCH ?9))
+                    AND (?10 IS NULL OR instr(lower(details), lower(?10)) > 0)
+                ORDER BY timestamp DESC, id DESC
+                LIMIT ?11 OFFSET ?12
+                "#,
+                filter.user_id,
+                filter.device_id,
+                filter.action,
+                filter.success,
+                since,
+                until,
+                ip_from,
+                ip_to,
+                fts,
+                text,
+                batch,
+                scanned
+            )
+            .fetch_all(conn.deref_mut())
+            .await?;
+
+            let n = rows.len() as i64;
+            for row in rows {
+                if let Some(net) = &filter.ip {
+                    if !row.ip_address.parse().map(|ip| net.contains(ip)).unwrap_or(false) {
+                        continue;
+                    }
+                }
+                if skip > 0 {
+                    skip -= 1;
+                    continue;
+                }
+                logs.push(AuditLog {
+                    id: row.id,
+                    user_id: row.user_id,
+                    device_id: row.device_id,
+                    action: row.action,
+                    details: row.details,
+                    ip_address: row.ip_address,
+                    user_agent: row.user_agent,
+                    timestamp: std::time::UNIX_EPOCH + std::time::Duration::from_secs(row.timestamp as u64),
+                    success: row.success,
+                });
+                if logs.len() as i64 >= limit {
+                    break;
+                }
+            }
+            if logs.len() as i64 >= limit || n < batch {
+                break;
+            }
+            scanned += batch;
+        }
+
+        Ok(logs)
+    }
+
+    // 设备管理方法
+    // 排队写入，同一批的注册在一个事务中提交
+    pub async fn register_device(&self, device: &DeviceInfo) -> ResultType<()> {
+        self.device_writes.submit(device.clone()).await
+    }
+
+    pub async fn device_exists(&self, device_id: &str) -> ResultType<bool> {
+        let mut conn = self.pool.get().await?;
+
+        let row = sqlx::query!("SELECT id FROM devices WHERE id = ?", device_id)
+            .fetch_optional(conn.deref_mut())
+            .await?;
+
+        Ok(row.is_some())
+    }
+
+    // 许可证用量统计
+    pub async fn count_devices(&self) -> ResultType<u64> {
+        let mut conn = self.pool.get().await?;
+
+        let row = sqlx::query!(r#"SELECT COUNT(*) as "count!: i64" FROM devices"#)
+            .fetch_one(conn.deref_mut())
+            .await?;
+
+        Ok(row.count as u64)
+    }
+
+    pub async fn count_users(&self) -> ResultType<u64> {
+        let mut conn = self.pool.get().await?;
+
+        let row = sqlx::query!(r#"SELECT COUNT(*) as "count!: i64" FROM users"#)
+            .fetch_one(conn.deref_mut())
+            .await?;
+
+        Ok(row.count as u64)
+    }
+
+    // 客户端心跳/系统信息接口使用uuid校验设备身份
+    pub async fn update_device_uuid(&self, device_id: &str, uuid: &str) -> ResultType<()> {
+        let mut conn = self.pool.get().await?;
+
+        sqlx::query!("UPDATE devices SET uuid = ? WHERE id = ?", uuid, device_id)
+            .execute(conn.deref_mut())
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn get_device_uuid(&self, device_id: &str) -> ResultType<Option<String>> {
+        let mut conn = self.pool.get().await?;
+
+        let row = sqlx::query!("SELECT uuid FROM devices WHERE id = ?", device_id)
+            .fetch_optional(conn.deref_mut())
+            .await?;
+
+        Ok(row.and_then(|row| row.uuid))
+    }
+
+    pub async fn device_group_exists(&self, group_id: &str) -> ResultType<bool> {
+        let mut conn = self.pool.get().await?;
+
+        let row = sqlx::query!("SELECT id FROM device_groups WHERE id = ?", group_id)
+            .fetch_optional(conn.deref_mut())
+            .await?;
+
+        Ok(row.is_some())
+    }
+
+    // 批量导入设备及其uuid，已存在的设备保留原有信息，只补上缺少的uuid；新设备同时加入 group_ids 中的设备组。
+    // 返回新导入的设备数
+    pub async fn import_devices(&self, devices: &[(DeviceInfo, Option<String>)]) -> ResultType<u64> {
+        let mut conn = self.pool.get().await?;
+        let mut tx = conn.deref_mut().begin().await?;
+        let mut imported = 0;
+        for (device, uuid) in devices {
+            let last_online = device.last_online.duration_since(std::time::UNIX_EPOCH)?.as_secs() as i64;
+            let group_ids_json = serde_json::to_string(&device.group_ids)?;
+            let tags_json = serde_json::to_string(&device.tags)?;
+
+            let result = sqlx::query!(
+                r#"
+                INSERT OR IGNORE INTO devices (
+                    id, name, os, version, ip_address, ipv6_address, uuid, mac_address,
+                    last_online, owner_id, group_ids, enabled, tags
+                ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+                "#,
+                device.id,
+                device.name,
+                device.os,
+                device.version,
+                device.ip_address,
+                device.ipv6_address,
+                uuid,
+                device.mac_address,
+                last_online,
+                device.owner_id,
+                group_ids_json,
+                device.enabled,
+                tags_json
+            )
+            .execute(&mut tx)
+            .await?;
+            if result.rows_affected() == 0 {
+                sqlx::query!(
+                    "UPDATE devices SET uuid = ? WHERE id = ? AND uuid IS NULL",
+                    uuid,
+                    device.id
+                )
+                .execute(&mut tx)
+                .await?;
+                continue;
+            }
+            imported += 1;
+            for group_id in &device.group_ids {
+                sqlx::query!(
+                    r#"
+                    UPDATE device_groups SET devices = json_insert(devices, '$[#]', ?)
+                    WHERE id = ? AND NOT EXISTS (SELECT 1 FROM json_each(device_groups.devices) WHERE value = ?)
+                    "#,
+                    device.id,
+                    group_id,
+                    device.id
+                )
+                .execute(&mut tx)
+                .await?;
+            }
+        }
+        tx.commit().await?;
+
+        Ok(imported)
+    }
+
+    // 全部设备的 (id, uuid, IPv4地址, 是否启用)，用于导出到开源版
+    pub async fn list_device_identities(&self) -> ResultType<Vec<(String, Option<String>, String, bool)>> {
+        let mut conn = self.pool.get().await?;
+
+        let rows = sqlx::query!("SELECT id, uuid, ip_address, enabled FROM devices ORDER BY id")
+            .fetch_all(conn.deref_mut())
+            .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| (row.id, row.uuid, row.ip_address, row.enabled))
+            .collect())
+    }
+
+    pub async fn update_device_sysinfo(&self, device_id: &str, name: &str, os: &str, version: &str) -> ResultType<bool> {
+        let mut conn = self.pool.get().await?;
+
+        let result = sqlx::query!(
+            "UPDATE devices SET name = ?, os = ?, version = ? WHERE id = ?",
+            name,
+            os,
+            version,
+            device_id
+        )
+        .execute(conn.deref_mut())
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn set_device_mac(&self, device_id: &str, mac_address: &str) -> ResultType<bool> {
+        let mut conn = self.pool.get().await?;
+
+        let result = sqlx::query!(
+            "UPDATE devices SET mac_address = ? WHERE id = ?",
+            mac_address,
+            device_id
+        )
+        .execute(conn.deref_mut())
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn get_device_network(&self, device_id: &str) -> ResultType<Option<DeviceNetwork>> {
+        let mut conn = self.pool.get().await?;
+
+        let row = sqlx::query_as!(
+            DeviceNetworkRow,
+            "SELECT id, ip_address, mac_address, last_online FROM devices WHERE id = ?",
+            device_id
+        )
+        .fetch_optional(conn.deref_mut())
+        .await?;
+
+        Ok(row.map(DeviceNetwork::from))
+    }
+
+    // 自 since 以来在线、且通过心跳接口受管的设备
+    pub async fn list_managed_devices_online_since(&self, since: u64) -> ResultType<Vec<DeviceNetwork>> {
+        let mut conn = self.pool.get().await?;
+        let since = since as i64;
+
+        let rows = sqlx::query_as!(
+            DeviceNetworkRow,
+            "SELECT id, ip_address, mac_address, last_online FROM devices WHERE uuid IS NOT NULL AND enabled = 1 AND last_online >= ? ORDER BY last_online DESC",
+            since
+        )
+        .fetch_all(conn.deref_mut())
+        .await?;
+
+        Ok(rows.into_iter().map(DeviceNetwork::from).collect())
+    }
+
+    // 客户端策略方法
+    pub async fn save_strategy(&self, strategy: &Strategy) -> ResultType<()> {
+        let mut conn = self.pool.get().await?;
+        let config_options = serde_json::to_string(&strategy.config_options)?;
+        let group_ids = serde_json::to_string(&strategy.group_ids)?;
+        let device_ids = serde_json::to_string(&strategy.device_ids)?;
+        let updated_at = strategy.updated_at as i64;
+
+        sqlx::query!(
+            r#"
+            INSERT INTO strategies (
+                id, name, description, config_options, group_ids, device_ids, enabled, updated_at
+            ) VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+            ON CONFLICT(id) DO UPDATE SET
+                name = excluded.name,
+                description = excluded.description,
+                config_options = excluded.config_options,
+                group_ids = excluded.group_ids,
+                device_ids = excluded.device_ids,
+                enabled = excluded.enabled,
+                updated_at = excluded.updated_at
+            "#,
+            strategy.id,
+            strategy.name,
+            strategy.description,
+            config_options,
+            group_ids,
+            device_ids,
+            strategy.enabled,
+            updated_at
+        )
+        .execute(conn.deref_mut())
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn delete_strategy(&self, id: &str) -> ResultType<bool> {
+        let mut conn = self.pool.get().await?;
+
+        let result = sqlx::query!("DELETE FROM strategies WHERE id = ?", id)
+            .execute(conn.deref_mut())
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn list_strategies(&self) -> ResultType<Vec<Strategy>> {
+        let mut conn = self.pool.get().await?;
+
+        let rows = sqlx::query!("SELECT * FROM strategies ORDER BY name")
+            .fetch_all(conn.deref_mut())
+            .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| Strategy {
+                id: row.id,
+                name: row.name,
+                description: row.description,
+                config_options: serde_json::from_str(&row.config_options).unwrap_or_default(),
+                group_ids: serde_json::from_str(&row.group_ids).unwrap_or_default(),
+                device_ids: serde_json::from_str(&row.device_ids).unwrap_or_default(),
+                enabled: row.enabled,
+                updated_at: row.updated_at as u64,
+            })
+            .collect())
+    }
+
+    pub async fn get_device_group_ids(&self, device_id: &str) -> ResultType<Vec<String>> {
+        let mut conn = self.pool.get().await?;
+
+        let row = sqlx::query!(
+            "SELECT group_ids FROM devices WHERE id = ?",
+            device_id
+        )
+        .fetch_optional(conn.deref_mut())
+        .await?;
+
+        Ok(row
+            .map(|row| serde_json::from_str(&row.group_ids).unwrap_or_default())
+            .unwrap_or_default())
+    }
+
+    // 离线告警检查和在线率统计用，只取已启用的设备
+    pub async fn list_device_presence(&self) -> ResultType<Vec<DevicePresence>> {
+        let mut conn = self.pool.get().await?;
+
+        let rows = sqlx::query!(
+            "SELECT id, name, last_online, group_ids FROM devices WHERE enabled = 1"
+        )
+        .fetch_all(conn.deref_mut())
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| DevicePresence {
+                id: row.id,
+                name: row.name,
+                last_online: row.last_online as u64,
+                group_ids: serde_json::from_str(&row.group_ids).unwrap_or_default(),
+            })
+            .collect())
+    }
+
+    pub async fn get_custom_field_values(
+        &self,
+        entity: FieldEntity,
+        entity_id: &str,
+    ) -> ResultType<BTreeMap<String, serde_json::Value>> {
+        let mut conn = self.pool.get().await?;
+        let entity = serde_json::to_value(entity)?;
+        let entity = entity.as_str().unwrap_or_default();
+
+        let row = sqlx::query!(
+            "SELECT data FROM custom_field_values WHERE entity = ? AND entity_id = ?",
+            entity,
+            entity_id
+        )
+        .fetch_optional(conn.deref_mut())
+        .await?;
+
+        match row {
+            Some(row) => Ok(serde_json::from_str(&row.data)?),
+            None => Ok(BTreeMap::new()),
+        }
+    }
+
+    // 替换全部字段值，为空时删除
+    pub async fn set_custom_field_values(
+        &self,
+        entity: FieldEntity,
+        entity_id: &str,
+        values: &BTreeMap<String, serde_json::Value>,
+    ) -> ResultType<()> {
+        let mut conn = self.pool.get().await?;
+        let entity = serde_json::to_value(entity)?;
+        let entity = entity.as_str().unwrap_or_default();
+
+        if values.is_empty() {
+            sqlx::query!(
+                "DELETE FROM custom_field_values WHERE entity = ? AND entity_id = ?",
+                entity,
+                entity_id
+            )
+            .execute(conn.deref_mut())
+            .await?;
+            return Ok(());
+        }
+
+        let data = serde_json::to_string(values)?;
+        let now = SystemTime::now().duration_since(std::time::UNIX_EPOCH)?.as_secs() as i64;
+        sqlx::query!(
+            "INSERT OR REPLACE INTO custom_field_values (entity, entity_id, data, updated_at) VALUES (?, ?, ?, ?)",
+            entity,
+            entity_id,
+            data,
+            now
+        )
+        .execute(conn.deref_mut())
+        .await?;
+
+        Ok(())
+    }
+
+    // path 形如 $.asset_tag；value/min/max 为 JSON 文本，按 JSON 值比较
+    #[allow(clippy::too_many_arguments)]
+    pub async fn search_custom_field_values(
+        &self,
+        entity: FieldEntity,
+        path: &str,
+        value: Option<&str>,
+        min: Option<&str>,
+        max: Option<&str>,
+        limit: i64,
+        offset: i64,
+    ) -> ResultType<Vec<FieldValues>> {
+        let mut conn = self.pool.get().await?;
+        let entity = serde_json::to_value(entity)?;
+        let entity = entity.as_str().unwrap_or_default();
+
+        let rows = sqlx::query!(
+            r#"
+            SELECT entity_id, data FROM custom_field_values
+            WHERE entity = ?1 AND json_type(data, ?2) IS NOT NULL AND json_type(data, ?2) != 'null'
+                AND (?3 IS NULL OR json_extract(data, ?2) = json_extract(?3, '$'))
+                AND (?4 IS NULL OR json_extract(data, ?2) >= json_extract(?4, '$'))
+                AND (?5 IS NULL OR json_extract(data, ?2) <= json_extract(?5, '$'))
+            ORDER BY entity_id
+            LIMIT ?6 OFFSET ?7
+            "#,
+            entity,
+            path,
+            value,
+            min,
+            max,
+            limit,
+            offset
+        )
+        .fetch_all(conn.deref_mut())
+        .await?;
+
+        rows.into_iter()
+            .map(|row| {
+                Ok(FieldValues {
+                    id: row.entity_id,
+                    values: serde_json::from_str(&row.data)?,
+                })
+            })
+            .collect()
+    }
+
+    pub async fn save_device_view(&self, view: &DeviceView) -> ResultType<()> {
+        let mut conn = self.pool.get().await?;
+        let filter = serde_json::to_string(&view.filter)?;
+        let created_at = view.created_at as i64;
+        let updated_at = view.updated_at as i64;
+
+        sqlx::query!(
+            r#"
+            INSERT INTO device_views (id, user_id, name, filter, created_at, updated_at)
+            VALUES (?, ?, ?, ?, ?, ?)
+            "#,
+            view.id,
+            view.user_id,
+            view.name,
+            filter,
+            created_at,
+            updated_at
+        )
+        .execute(conn.deref_mut())
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn update_device_view(&self, view: &DeviceView) -> ResultType<bool> {
+        let mut conn = self.pool.get().await?;
+        let filter = serde_json::to_string(&view.filter)?;
+        let updated_at = view.updated_at as i64;
+
+        let result = sqlx::query!(
+            "UPDATE device_views SET name = ?, filter = ?, updated_at = ? WHERE id = ? AND user_id = ?",
+            view.name,
+            filter,
+            updated_at,
+            view.id,
+            view.user_id
+        )
+        .execute(conn.deref_mut())
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn delete_device_view(&self, id: &str, user_id: &str) -> ResultType<bool> {
+        let mut conn = self.pool.get().await?;
+
+        let result = sqlx::query!("DELETE FROM device_views WHERE id = ? AND user_id = ?", id, user_id)
+            .execute(conn.deref_mut())
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    // 设备备注方法
+    pub async fn save_device_note(&self, note: &DeviceNote) -> ResultType<()> {
+        let mut conn = self.pool.get().await?;
+        let created_at = note.created_at as i64;
+        let updated_at = note.updated_at as i64;
+
+        sqlx::query!(
+            r#"
+            INSERT INTO device_notes (id, device_id, author_id, author_name, body, created_at, updated_at)
+            VALUES (?, ?, ?, ?, ?, ?, ?)
+            "#,
+            note.id,
+            note.device_id,
+            note.author_id,
+            note.author_name,
+            note.body,
+            created_at,
+            updated_at
+        )
+        .execute(conn.deref_mut())
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn update_device_note(&self, note: &DeviceNote) -> ResultType<bool> {
+        let mut conn = self.pool.get().await?;
+        let updated_at = note.updated_at as i64;
+
+        let result = sqlx::query!(
+            "UPDATE device_notes SET body = ?, updated_at = ? WHERE id = ? AND device_id = ?",
+            note.body,
+            updated_at,
+            note.id,
+            note.device_id
+        )
+        .execute(conn.deref_mut())
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn delete_device_note(&self, id: &str, device_id: &str) -> ResultType<bool> {
+        let mut conn = self.pool.get().await?;
+
+        let result = sqlx::query!("DELETE FROM device_notes WHERE id = ? AND device_id = ?", id, device_id)
+            .execute(conn.deref_mut())
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn get_device_note(&self, id: &str, device_id: &str) -> ResultType<Option<DeviceNote>> {
+        let mut conn = self.pool.get().await?;
+
+        let row = sqlx::query_as!(
+            DeviceNoteRow,
+            r#"
+            SELECT id, device_id, author_id, author_name, body, created_at, updated_at
+            FROM device_notes WHERE id = ? AND device_id = ?
+            "#,
+            id,
+            device_id
+        )
+        .fetch_optional(conn.deref_mut())
+        .await?;
+
+        Ok(row.map(DeviceNote::from))
+    }
+
+    // 按创建时间倒序
+    pub async fn list_device_notes(&self, device_id: &str) -> ResultType<Vec<DeviceNote>> {
+        let mut conn = self.pool.get().await?;
+
+        let rows = sqlx::query_as!(
+            DeviceNoteRow,
+            r#"
+            SELECT id, device_id, author_id, author_name, body, created_at, updated_at
+            FROM device_notes WHERE device_id = ? ORDER BY created_at DESC, id
+            "#,
+            device_id
+        )
+        .fetch_all(conn.deref_mut())
+        .await?;
+
+        Ok(rows.into_iter().map(DeviceNote::from).collect())
+    }
+
+    // 设备公钥历史方法，已记录过的公钥不覆盖，返回是否新增
+    pub async fn insert_device_pk(&self, record: &PkRecord) -> ResultType<bool> {
+        let mut conn = self.pool.get().await?;
+        let seen_at = record.seen_at as i64;
+        let status = serde_json::to_value(record.status)?.as_str().unwrap_or_default().to_owned();
+
+        let result = sqlx::query!(
+            r#"
+            INSERT OR IGNORE INTO device_pk_history (device_id, pk, uuid, ip_address, seen_at, status)
+            VALUES (?, ?, ?, ?, ?, ?)
+            "#,
+            record.device_id,
+            record.pk,
+            record.uuid,
+            record.ip_address,
+            seen_at,
+            status
+        )
+        .execute(conn.deref_mut())
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn get_device_pk(&self, device_id: &str, pk: &str) -> ResultType<Option<PkRecord>> {
+        let mut conn = self.pool.get().await?;
+
+        let row = sqlx::query_as!(
+            PkRecordRow,
+            r#"
+            SELECT id as "id!: i64", device_id, pk, uuid, ip_address, seen_at, status, decided_by, decided_at
+            FROM device_pk_history WHERE device_id = ? AND pk = ?
+            "#,
+            device_id,
+            pk
+        )
+        .fetch_optional(conn.deref_mut())
+        .await?;
+
+        row.map(PkRecord::try_from).transpose()
+    }
+
+    pub async fn get_device_pk_by_id(&self, id: i64) -> ResultType<Option<PkRecord>> {
+        let mut conn = self.pool.get().await?;
+
+        let row = sqlx::query_as!(
+            PkRecordRow,
+            r#"
+            SELECT id as "id!: i64", device_id, pk, uuid, ip_address, seen_at, status, decided_by, decided_at
+            FROM device_pk_history WHERE id = ?
+            "#,
+            id
+        )
+        .fetch_optional(conn.deref_mut())
+        .await?;
+
+        row.map(PkRecord::try_from).transpose()
+    }
+
+    // 按首次出现时间倒序
+    pub async fn list_device_pk_history(&self, device_id: &str) -> ResultType<Vec<PkRecord>> {
+        let mut conn = self.pool.get().await?;
+
+        let rows = sqlx::query_as!(
+            PkRecordRow,
+            r#"
+            SELECT id as "id!: i64", device_id, pk, uuid, ip_address, seen_at, status, decided_by, decided_at
+            FROM device_pk_history WHERE device_id = ? ORDER BY seen_at DESC, id DESC
+            "#,
+            device_id
+        )
+        .fetch_all(conn.deref_mut())
+        .await?;
+
+        rows.into_iter().map(PkRecord::try_from).collect()
+    }
+
+    pub async fn list_device_pks_by_status(&self, status: PkStatus) -> ResultType<Vec<PkRecord>> {
+        let mut conn = self.pool.get().await?;
+        let status = serde_json::to_value(status)?.as_str().unwrap_or_default().to_owned();
+
+        let rows = sqlx::query_as!(
+            PkRecordRow,
+            r#"
+            SELECT id as "id!: i64", device_id, pk, uuid, ip_address, seen_at, status, decided_by, decided_at
+            FROM device_pk_history WHERE status = ? ORDER BY seen_at, id
+            "#,
+            status
+        )
+        .fetch_all(conn.deref_mut())
+        .await?;
+
+        rows.into_iter().map(PkRecord::try_from).collect()
+    }
+
+    // 只处理待审批的记录，已处理过时返回 false
+    pub async fn decide_device_pk(&self, record: &PkRecord) -> ResultType<bool> {
+        let mut conn = self.pool.get().await?;
+        let status = serde_json::to_value(record.status)?.as_str().unwrap_or_default().to_owned();
+        let decided_at = record.decided_at.map(|x| x as i64);
+
+        let result = sqlx::query!(
+            "UPDATE device_pk_history SET status = ?, decided_by = ?, decided_at = ? WHERE id = ? AND status = 'pending'",
+            status,
+            record.decided_by,
+            decided_at,
+            record.id
+        )
+        .execute(conn.deref_mut())
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    // 最近连接方法，同一设备只保留一条；超出 keep 条时删除最早的
+    pub async fn record_recent_connection(&self, user_id: &str, device_id: &str, at: u64, keep: i64) -> ResultType<()> {
+        let mut conn = self.pool.get().await?;
+        let at = at as i64;
+
+        sqlx::query!(
+            r#"
+            INSERT INTO recent_connections (user_id, device_id, last_connected_at, connection_count)
+            VALUES (?, ?, ?, 1)
+            ON CONFLICT(user_id, device_id) DO UPDATE SET
+                last_connected_at = excluded.last_connected_at,
+                connection_count = connection_count + 1
+            "#,
+            user_id,
+            device_id,
+            at
+        )
+        .execute(conn.deref_mut())
+        .await?;
+
+        sqlx::query!(
+            r#"
+            DELETE FROM recent_connections WHERE user_id = ?1 AND device_id NOT IN (
+                SELECT device_id FROM recent_connections WHERE user_id = ?1
+                ORDER BY last_connected_at DESC LIMIT ?2
+            )
+            "#,
+            user_id,
+            keep
+        )
+        .execute(conn.deref_mut())
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn clear_recent_connections(&self, user_id: &str) -> ResultType<u64> {
+        let mut conn = self.pool.get().await?;
+
+        let result = sqlx::query!("DELETE FROM recent_connections WHERE user_id = ?", user_id)
+            .execute(conn.deref_mut())
+            .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    // 按最后连接时间倒序，只包含已登记且未停用的设备
+    pub async fn list_recent_devices(&self, user_id: &str, now: u64, limit: i64) -> ResultType<Vec<UserDevice>> {
+        let mut conn = self.pool.get().await?;
+        let online_since = now.saturating_sub(crate::uptime::ONLINE_TIMEOUT_SECS) as i64;
+
+        let rows = sqlx::query_as!(
+            UserDeviceRow,
+            r#"
+            SELECT d.id as device_id, d.name, a.alias as "alias?", d.os, d.last_online >= ?2 as "online!: bool",
+                   d.last_online, f.created_at as "favorited_at?", r.last_connected_at as "last_connected_at?",
+                   r.connection_count as "connection_count?"
+            FROM recent_connections r
+            JOIN devices d ON d.id = r.device_id AND d.enabled = 1
+            LEFT JOIN device_aliases a ON a.device_id = d.id
+            LEFT JOIN favorite_devices f ON f.device_id = d.id AND f.user_id = ?1
+            WHERE r.user_id = ?1
+            ORDER BY r.last_connected_at DESC, d.id
+            LIMIT ?3
+            "#,
+            user_id,
+            online_since,
+            limit
+        )
+        .fetch_all(conn.deref_mut())
+        .await?;
+
+        Ok(rows.into_iter().map(UserDevice::from).collect())
+    }
+
+    // 收藏方法，已收藏时返回 false
+    pub async fn add_favorite_device(&self, user_id: &str, device_id: &str, at: u64) -> ResultType<bool> {
+        let mut conn = self.pool.get().await?;
+        let at = at as i64;
+
+        let result = sqlx::query!(
+            "INSERT OR IGNORE INTO favorite_devices (user_id, device_id, created_at) VALUES (?, ?, ?)",
+            user_id,
+            device_id,
+            at
+        )
+        .execute(conn.deref_mut())
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn remove_favorite_device(&self, user_id: &str, device_id: &str) -> ResultType<bool> {
+        let mut conn = self.pool.get().await?;
+
+        let result = sqlx::query!(
+            "DELETE FROM favorite_devices WHERE user_id = ? AND device_id = ?",
+            user_id,
+            device_id
+        )
+        .execute(conn.deref_mut())
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn count_favorite_devices(&self, user_id: &str) -> ResultType<i64> {
+        let mut conn = self.pool.get().await?;
+
+        let row = sqlx::query!(
+            r#"SELECT COUNT(*) as "count!: i64" FROM favorite_devices WHERE user_id = ?"#,
+            user_id
+        )
+        .fetch_one(conn.deref_mut())
+        .await?;
+
+        Ok(row.count)
+    }
+
+    // 按别名或名称排序，只包含已登记且未停用的设备
+    pub async fn list_favorite_devices(&self, user_id: &str, now: u64) -> ResultType<Vec<UserDevice>> {
+        let mut conn = self.pool.get().await?;
+        let online_since = now.saturating_sub(crate::uptime::ONLINE_TIMEOUT_SECS) as i64;
+
+        let rows = sqlx::query_as!(
+            UserDeviceRow,
+            r#"
+            SELECT d.id as device_id, d.name, a.alias as "alias?", d.os, d.last_online >= ?2 as "online!: bool",
+                   d.last_online, f.created_at as "favorited_at?", r.last_connected_at as "last_connected_at?",
+                   r.connection_count as "connection_count?"
+            FROM favorite_devices f
+            JOIN devices d ON d.id = f.device_id AND d.enabled = 1
+            LEFT JOIN device_aliases a ON a.device_id = d.id
+            LEFT JOIN recent_connections r ON r.device_id = d.id AND r.user_id = ?1
+            WHERE f.user_id = ?1
+            ORDER BY COALESCE(a.alias, d.name) COLLATE NOCASE, d.id
+            "#,
+            user_id,
+            online_since
+        )
+        .fetch_all(conn.deref_mut())
+        .await?;
+
+        Ok(rows.into_iter().map(UserDevice::from).collect())
+    }
+
+    pub async fn get_device_view(&self, id: &str, user_id: &str) -> ResultType<Option<DeviceView>> {
+        let mut conn = self.pool.get().await?;
+
+        let row = sqlx::query_as!(
+            DeviceViewRow,
+            "SELECT id, user_id, name, filter, created_at, updated_at FROM device_views WHERE id = ? AND user_id = ?",
+            id,
+            user_id
+        )
+        .fetch_optional(conn.deref_mut())
+        .await?;
+
+        row.map(DeviceView::try_from).transpose()
+    }
+
+    pub async fn list_device_views(&self, user_id: &str) -> ResultType<Vec<DeviceView>> {
+        let mut conn = self.pool.get().await?;
+
+        let rows = sqlx::query_as!(
+            DeviceViewRow,
+            "SELECT id, user_id, name, filter, created_at, updated_at FROM device_views WHERE user_id = ? ORDER BY name",
+            user_id
+        )
+        .fetch_all(conn.deref_mut())
+        .await?;
+
+        rows.into_iter().map(DeviceView::try_from).collect()
+    }
+
+    pub async fn save_device_status(&self, device_id: &str, online: bool, changed_at: u64) -> ResultType<()> {
+        let mut conn = self.pool.get().await?;
+        let changed_at = changed_at as i64;
+
+        sqlx::query!(
+            "INSERT INTO device_status_history (device_id, online, changed_at) VALUES (?, ?, ?)",
+            device_id,
+            online,
+            changed_at
+        )
+        .execute(conn.deref_mut())
+        .await?;
+
+        Ok(())
+    }
+
+    // 各设备在 at 时刻(含)的最后状态，device_id 为空时返回全部设备
+    pub async fn list_device_status_at(&self, at: u64, device_id: Option<&str>) -> ResultType<HashMap<String, bool>> {
+        let mut conn = self.pool.get().await?;
+        let at = at.min(i64::MAX as u64) as i64;
+
+        let rows = sqlx::query!(
+            r#"
+            SELECT device_id, online FROM device_status_history WHERE id IN (
+                SELECT MAX(id) FROM device_status_history
+                WHERE changed_at <= ?1 AND (?2 IS NULL OR device_id = ?2)
+                GROUP BY device_id
+            )
+            "#,
+            at,
+            device_id
+        )
+        .fetch_all(conn.deref_mut())
+        .await?;
+
+        Ok(rows.into_iter().map(|row| (row.device_id, row.online)).collect())
+    }
+
+    // (since, until] 区间内的状态变化，按时间排序
+    pub async fn list_device_status_changes(
+        &self,
+        device_id: Option<&str>,
+        since: u64,
+        until: u64,
+    ) -> ResultType<Vec<StatusChange>> {
+        let mut conn = self.pool.get().await?;
+        let since = since as i64;
+        let until = until as i64;
+
+        let rows = sqlx::query!(
+            r#"
+            SELECT device_id, online, changed_at FROM device_status_history
+            WHERE changed_at > ?1 AND changed_at <= ?2 AND (?3 IS NULL OR device_id = ?3)
+            ORDER BY changed_at, id
+            "#,
+            since,
+            until,
+            device_id
+        )
+        .fetch_all(conn.deref_mut())
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| StatusChange {
+                device_id: row.device_id,
+                online: row.online,
+                changed_at: row.changed_at as u64,
+            })
+            .collect())
+    }
+
+    pub async fn prune_device_status_history(&self, before: u64) -> ResultType<u64> {
+        let mut conn = self.pool.get().await?;
+        let before = before as i64;
+
+        let result = sqlx::query!("DELETE FROM device_status_history WHERE changed_at < ?", before)
+            .execute(conn.deref_mut())
+            .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    pub async fn get_device_tags(&self, device_id: &str) -> ResultType<Vec<String>> {
+        let mut conn = self.pool.get().await?;
+
+        let row = sqlx::query!("SELECT tags FROM devices WHERE id = ?", device_id)
+            .fetch_optional(conn.deref_mut())
+            .await?;
+
+        Ok(row
+            .map(|row| serde_json::from_str(&row.tags).unwrap_or_default())
+            .unwrap_or_default())
+    }
+
+    // 客户端上报的版本，设备不存在时返回 None
+    pub async fn get_device_version(&self, device_id: &str) -> ResultType<Option<String>> {
+        let mut conn = self.pool.get().await?;
+
+        let row = sqlx::query!("SELECT version FROM devices WHERE id = ?", device_id)
+            .fetch_optional(conn.deref_mut())
+            .await?;
+
+        Ok(row.map(|row| row.version))
+    }
+
+    // 设备所属用户，设备不存在时返回 None
+    pub async fn get_device_owner(&self, device_id: &str) -> ResultType<Option<String>> {
+        let mut conn = self.pool.get().await?;
+
+        let row = sqlx::query!("SELECT owner_id FROM devices WHERE id = ?", device_id)
+            .fetch_optional(conn.deref_mut())
+            .await?;
+
+        Ok(row.map(|row| row.owner_id))
+    }
+
+    // 设备别名方法
+    pub async fn set_device_alias(&self, device_id: &str, alias: &str, created_by: &str) -> ResultType<()> {
+        let mut conn = self.pool.get().await?;
+        let now = SystemTime::now().duration_since(std::time::UNIX_EPOCH)?.as_secs() as i64;
+
+        // 设备已有别名时替换
+        sqlx::query!("DELETE FROM device_aliases WHERE device_id = ?", device_id)
+            .execute(conn.deref_mut())
+            .await?;
+        sqlx::query!(
+            "INSERT INTO device_aliases (alias, device_id, created_by, created_at) VALUES (?, ?, ?, ?)",
+            alias,
+            device_id,
+            created_by,
+            now
+        )
+        .execute(conn.deref_mut())
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn remove_device_alias(&self, device_id: &str) -> ResultType<bool> {
+        let mut conn = self.pool.get().await?;
+
+        let result = sqlx::query!("DELETE FROM device_aliases WHERE device_id = ?", device_id)
+            .execute(conn.deref_mut())
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn list_device_aliases(&self) -> ResultType<Vec<DeviceAlias>> {
+        let mut conn = self.pool.get().await?;
+
+        let rows = sqlx::query!("SELECT * FROM device_aliases ORDER BY alias")
+            .fetch_all(conn.deref_mut())
+            .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| DeviceAlias {
+                alias: row.alias,
+                device_id: row.device_id,
+                created_by: row.created_by,
+                created_at: row.created_at as u64,
+            })
+            .collect())
+    }
+
+    // 设备封禁方法
+    pub async fn upsert_device_ban(&self, ban: &DeviceBan) -> ResultType<()> {
+        let mut conn = self.pool.get().await?;
+        let created_at = ban.created_at as i64;
+        let expires_at = ban.expires_at.map(|x| x as i64);
+
+        sqlx::query!(
+            r#"
+            INSERT INTO device_bans (device_id, uuid, reason, banned_by, created_at, expires_at)
+            VALUES (?, ?, ?, ?, ?, ?)
+            ON CONFLICT(device_id) DO UPDATE SET
+                uuid = COALESCE(excluded.uuid, device_bans.uuid),
+                reason = excluded.reason,
+                banned_by = excluded.banned_by,
+                created_at = excluded.created_at,
+                expires_at = excluded.expires_at
+            "#,
+            ban.device_id,
+            ban.uuid,
+            ban.reason,
+            ban.banned_by,
+            created_at,
+            expires_at
+        )
+        .execute(conn.deref_mut())
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn remove_device_ban(&self, device_id: &str) -> ResultType<bool> {
+        let mut conn = self.pool.get().await?;
+
+        let result = sqlx::query!("DELETE FROM device_bans WHERE device_id = ?", device_id)
+            .execute(conn.deref_mut())
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    // 返回未过期的封禁记录，同时清理已过期的记录
+    pub async fn list_device_bans(&self) -> ResultType<Vec<DeviceBan>> {
+        let mut conn = self.pool.get().await?;
+        let now = SystemTime::now().duration_since(std::time::UNIX_EPOCH)?.as_secs() as i64;
+
+        sqlx::query!(
+            "DELETE FROM device_bans WHERE expires_at IS NOT NULL AND expires_at <= ?",
+            now
+        )
+        .execute(conn.deref_mut())
+        .await?;
+
+        let rows = sqlx::query!("SELECT * FROM device_bans ORDER BY created_at DESC")
+            .fetch_all(conn.deref_mut())
+            .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| DeviceBan {
+                device_id: row.device_id,
+                uuid: row.uuid,
+                reason: row.reason,
+                banned_by: row.banned_by,
+                created_at: row.created_at as u64,
+                expires_at: row.expires_at.map(|x| x as u64),
+            })
+            .collect())
+    }
+
+    // 邮件验证码授权方法
+    pub async fn grant_email_otp(&self, user_id: &str, granted_by: &str, expires_at: u64) -> ResultType<()> {
+        let mut conn = self.pool.get().await?;
+        let now = SystemTime::now().duration_since(std::time::UNIX_EPOCH)?.as_secs() as i64;
+        let expires_at = expires_at as i64;
+
+        sqlx::query!(
+            r#"
+            INSERT INTO email_otp_grants (user_id, granted_by, created_at, expires_at)
+            VALUES (?, ?, ?, ?)
+            ON CONFLICT(user_id) DO UPDATE SET
+                granted_by = excluded.granted_by,
+                created_at = excluded.created_at,
+                expires_at = excluded.expires_at
+            "#,
+            user_id,
+            granted_by,
+            now,
+            expires_at
+        )
+        .execute(conn.deref_mut())
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn revoke_email_otp(&self, user_id: &str) -> ResultType<bool> {
+        let mut conn = self.pool.get().await?;
+
+        let result = sqlx::query!("DELETE FROM email_otp_grants WHERE user_id = ?", user_id)
+            .execute(conn.deref_mut())
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    // 返回未过期授权的过期时间
+    pub async fn get_email_otp_grant(&self, user_id: &str) -> ResultType<Option<u64>> {
+        let mut conn = self.pool.get().await?;
+        let now = SystemTime::now().duration_since(std::time::UNIX_EPOCH)?.as_secs() as i64;
+
+        let row = sqlx::query!(
+            "SELECT expires_at FROM email_otp_grants WHERE user_id = ? AND expires_at > ?",
+            user_id,
+            now
+        )
+        .fetch_optional(conn.deref_mut())
+        .await?;
+
+        Ok(row.map(|row| row.expires_at as u64))
+    }
+
+    // 受信任浏览器方法
+    pub async fn add_trusted_device(&self, device: &TrustedDevice) -> ResultType<()> {
+        let mut conn = self.pool.get().await?;
+        let created_at = device.created_at as i64;
+        let expires_at = device.expires_at as i64;
+
+        sqlx::query!(
+            "INSERT INTO trusted_devices (id, user_id, user_agent, created_at, expires_at) VALUES (?, ?, ?, ?, ?)",
+            device.id,
+            device.user_id,
+            device.user_agent,
+            created_at,
+            expires_at
+        )
+        .execute(conn.deref_mut())
+        .await?;
+
+        Ok(())
+    }
+
+    // 校验受信任浏览器记录仍然有效，并刷新最后使用时间
+    pub async fn use_trusted_device(&self, id: &str, user_id: &str) -> ResultType<bool> {
+        let mut conn = self.pool.get().await?;
+        let now = SystemTime::now().duration_since(std::time::UNIX_EPOCH)?.as_secs() as i64;
+
+        let result = sqlx::query!(
+            "UPDATE trusted_devices SET last_used = ? WHERE id = ? AND user_id = ? AND expires_at > ?",
+            now,
+            id,
+            user_id,
+            now
+        )
+        .execute(conn.deref_mut())
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn list_trusted_devices(&self, user_id: &str) -> ResultType<Vec<TrustedDevice>> {
+        let mut conn = self.pool.get().await?;
+        let now = SystemTime::now().duration_since(std::time::UNIX_EPOCH)?.as_secs() as i64;
+
+        let rows = sqlx::query!(
+            "SELECT * FROM trusted_devices WHERE user_id = ? AND expires_at > ? ORDER BY created_at DESC",
+            user_id,
+            now
+        )
+        .fetch_all(conn.deref_mut())
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| TrustedDevice {
+                id: row.id,
+                user_id: row.user_id,
+                user_agent: row.user_agent,
+                created_at: row.created_at as u64,
+                expires_at: row.expires_at as u64,
+                last_used: row.last_used.map(|x| x as u64),
+            })
+            .collect())
+    }
+
+    // id 为 None 时撤销用户的全部受信任浏览器
+    pub async fn remove_trusted_devices(&self, user_id: &str, id: Option<&str>) -> ResultType<u64> {
+        let mut conn = self.pool.get().await?;
+
+        let result = match id {
+            Some(id) => {
+                sqlx::query!(
+                    "DELETE FROM trusted_devices WHERE id = ? AND user_id = ?",
+                    id,
+                    user_id
+                )
+                .execute(conn.deref_mut())
+                .await?
+            }
+            None => {
+                sqlx::query!("DELETE FROM trusted_devices WHERE user_id = ?", user_id)
+                    .execute(conn.deref_mut())
+                    .await?
+            }
+        };
+
+        Ok(result.rows_affected())
+    }
+
+    // 用户邀请方法，重新发送时删除该用户此前未接受的邀请，旧链接随之失效
+    pub async fn add_user_invite(&self, invite: &UserInvite) -> ResultType<()> {
+        let mut conn = self.pool.get().await?;
+        let mut tx = conn.deref_mut().begin().await?;
+        let created_at = invite.created_at as i64;
+        let expires_at = invite.expires_at as i64;
+
+        sqlx::query!(
+            "DELETE FROM user_invites WHERE user_id = ? AND accepted_at IS NULL",
+            invite.user_id
+        )
+        .execute(&mut tx)
+        .await?;
+        sqlx::query!(
+            r#"
+            INSERT INTO user_invites (id, user_id, email, invited_by, created_at, expires_at)
+            VALUES (?, ?, ?, ?, ?, ?)
+            "#,
+            invite.id,
+            invite.user_id,
+            invite.email,
+            invite.invited_by,
+            created_at,
+            expires_at
+        )
+        .execute(&mut tx)
+        .await?;
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    pub async fn get_user_invite(&self, id: &str) -> ResultType<Option<UserInvite>> {
+        let mut conn = self.pool.get().await?;
+
+        let row = sqlx::query!("SELECT * FROM user_invites WHERE id = ?", id)
+            .fetch_optional(conn.deref_mut())
+            .await?;
+
+        Ok(row.map(|row| UserInvite {
+            id: row.id,
+            user_id: row.user_id,
+            email: row.email,
+            invited_by: row.invited_by,
+            created_at: row.created_at as u64,
+            expires_at: row.expires_at as u64,
+            accepted_at: row.accepted_at.map(|x| x as u64),
+        }))
+    }
+
+    pub async fn list_user_invites(&self, limit: i64, offset: i64) -> ResultType<Vec<UserInvite>> {
+        let mut conn = self.pool.get().await?;
+
+        let rows = sqlx::query!(
+            "SELECT * FROM user_invites ORDER BY created_at DESC LIMIT ? OFFSET ?",
+            limit,
+            offset
+        )
+        .fetch_all(conn.deref_mut())
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| UserInvite {
+                id: row.id,
+                user_id: row.user_id,
+                email: row.email,
+                invited_by: row.invited_by,
+                created_at: row.created_at as u64,
+                expires_at: row.expires_at as u64,
+                accepted_at: row.accepted_at.map(|x| x as u64),
+            })
+            .collect())
+    }
+
+    // 撤销未接受的邀请
+    pub async fn remove_user_invite(&self, id: &str) -> ResultType<bool> {
+        let mut conn = self.pool.get().await?;
+
+        let result = sqlx::query!("DELETE FROM user_invites WHERE id = ? AND accepted_at IS NULL", id)
+            .execute(conn.deref_mut())
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    // 接受邀请并设置密码；邀请已使用、已撤销或已过期时返回 false，同一链接只能成功一次
+    pub async fn accept_user_invite(&self, id: &str, user_id: &str, password_hash: &str) -> ResultType<bool> {
+        let mut conn = self.pool.get().await?;
+        let mut tx = conn.deref_mut().begin().await?;
+        let now = SystemTime::now().duration_since(std::time::UNIX_EPOCH)?.as_secs() as i64;
+
+        let result = sqlx::query!(
+            r#"
+            UPDATE user_invites SET accepted_at = ?
+            WHERE id = ? AND user_id = ? AND accepted_at IS NULL AND expires_at > ?
+            "#,
+            now,
+            id,
+            user_id,
+            now
+        )
+        .execute(&mut tx)
+        .await?;
+        if result.rows_affected() == 0 {
+            return Ok(false);
+        }
+        sqlx::query!(
+            "UPDATE users SET password_hash = ?, failed_login_attempts = 0, locked_until = NULL WHERE id = ?",
+            password_hash,
+            user_id
+        )
+        .execute(&mut tx)
+        .await?;
+        tx.commit().await?;
+
+        Ok(true)
+    }
+
+    // 通知偏好方法
+    pub async fn get_notification_preferences(&self, user_id: &str) -> ResultType<NotificationPreferences> {
+        let mut conn = self.pool.get().await?;
+
+        let row = sqlx::query!("SELECT data FROM notification_preferences WHERE user_id = ?", user_id)
+            .fetch_optional(conn.deref_mut())
+            .await?;
+
+        Ok(match row {
+            Some(row) => serde_json::from_str(&row.data)?,
+            None => NotificationPreferences::default(),
+        })
+    }
+
+    pub async fn set_notification_preferences(&self, user_id: &str, preferences: &NotificationPreferences) -> ResultType<()> {
+        let mut conn = self.pool.get().await?;
+        let data = serde_json::to_string(preferences)?;
+        let now = SystemTime::now().duration_since(std::time::UNIX_EPOCH)?.as_secs() as i64;
+
+        sqlx::query!(
+            r#"
+            INSERT INTO notification_preferences (user_id, data, updated_at) VALUES (?, ?, ?)
+            ON CONFLICT(user_id) DO UPDATE SET data = excluded.data, updated_at = excluded.updated_at
+            "#,
+            user_id,
+            data,
+            now
+        )
+        .execute(conn.deref_mut())
+        .await?;
+
+        Ok(())
+    }
+
+    // 用户语言偏好方法
+    pub async fn list_user_languages(&self) -> ResultType<Vec<(String, String)>> {
+        let mut conn = self.pool.get().await?;
+
+        let rows = sqlx::query!("SELECT user_id, language FROM user_languages")
+            .fetch_all(conn.deref_mut())
+            .await?;
+
+        Ok(rows.into_iter().map(|row| (row.user_id, row.language)).collect())
+    }
+
+    // language 为空时清除偏好
+    pub async fn set_user_language(&self, user_id: &str, language: Option<&str>) -> ResultType<()> {
+        let mut conn = self.pool.get().await?;
+        let now = SystemTime::now().duration_since(std::time::UNIX_EPOCH)?.as_secs() as i64;
+
+        match language {
+            Some(language) => {
+                sqlx::query!(
+                    r#"
+                    INSERT INTO user_languages (user_id, language, updated_at) VALUES (?, ?, ?)
+                    ON CONFLICT(user_id) DO UPDATE SET language = excluded.language, updated_at = excluded.updated_at
+                    "#,
+                    user_id,
+                    language,
+                    now
+                )
+                .execute(conn.deref_mut())
+                .await?;
+            }
+            None => {
+                sqlx::query!("DELETE FROM user_languages WHERE user_id = ?", user_id)
+                    .execute(conn.deref_mut())
+                    .await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    // 已启用用户中设置了通知偏好的，按偏好决定是否投递
+    pub async fn list_notification_subscribers(&self) -> ResultType<Vec<Subscriber>> {
+        let mut conn = self.pool.get().await?;
+
+        let rows = sqlx::query!(
+            r#"
+            SELECT u.id, u.email, u.role, p.data, l.language as "language?"
+            FROM notification_preferences p JOIN users u ON u.id = p.user_id
+            LEFT JOIN user_languages l ON l.user_id = u.id
+            WHERE u.enabled = 1
+            "#
+        )
+        .fetch_all(conn.deref_mut())
+        .await?;
+
+        let mut subscribers = Vec::new();
+        for row in rows {
+            let preferences = match serde_json::from_str(&row.data) {
+                Ok(preferences) => preferences,
+                Err(e) => {
+                    log::error!("Invalid notification preferences of {}: {}", row.id, e);
+                    continue;
+                }
+            };
+            let role = match row.role.as_str() {
+                "SuperAdmin" => UserRole::SuperAdmin,
+                "Admin" => UserRole::Admin,
+                "ReadOnly" => UserRole::ReadOnly,
+                "Auditor" => UserRole::Auditor,
+                _ => UserRole::User,
+            };
+            subscribers.push(Subscriber {
+                user_id: row.id,
+                email: row.email,
+                role,
+                preferences,
+                lang: row
+                    .language
+                    .as_deref()
+                    .and_then(Lang::parse)
+                    .unwrap_or_else(crate::i18n::default_lang),
+            });
+        }
+
+        Ok(subscribers)
+    }
+
+    // 周报统计：指定时间以来各操作的审计日志条数
+    pub async fn count_audit_actions(&self, since: u64) -> ResultType<Vec<(String, i64)>> {
+        let mut conn = self.pool.get().await?;
+        let since = since as i64;
+
+        let rows = sqlx::query!(
+            r#"
+            SELECT action as "action!", COUNT(*) as "count!: i64" FROM audit_logs
+            WHERE timestamp >= ? GROUP BY action ORDER BY 2 DESC, 1
+            "#,
+            since
+        )
+        .fetch_all(conn.deref_mut())
+        .await?;
+
+        Ok(rows.into_iter().map(|row| (row.action, row.count)).collect())
+    }
+
+    // 周报统计：指定时间以来各级别的安全事件数
+    pub async fn count_security_events(&self, since: u64) -> ResultType<Vec<(String, i64)>> {
+        let mut conn = self.pool.get().await?;
+        let since = since as i64;
+
+        let rows = sqlx::query!(
+            r#"
+            SELECT severity as "severity!", COUNT(*) as "count!: i64" FROM security_events
+            WHERE created_at >= ? GROUP BY severity ORDER BY 2 DESC, 1
+            "#,
+            since
+        )
+        .fetch_all(conn.deref_mut())
+        .await?;
+
+        Ok(rows.into_iter().map(|row| (row.severity, row.count)).collect())
+    }
+
+    // 公告方法，相同 id 时覆盖(编辑)
+    pub async fn save_announcement(&self, announcement: &Announcement) -> ResultType<()> {
+        let mut conn = self.pool.get().await?;
+        let level = serde_json::to_value(announcement.level)?.as_str().unwrap_or_default().to_owned();
+        let starts_at = announcement.starts_at as i64;
+        let ends_at = announcement.ends_at as i64;
+        let created_at = announcement.created_at as i64;
+        let updated_at = announcement.updated_at as i64;
+
+        sqlx::query!(
+            r#"
+            INSERT OR REPLACE INTO announcements (id, title, body, level, starts_at, ends_at, deliver_to_clients, created_by, created_at, updated_at)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+            "#,
+            announcement.id,
+            announcement.title,
+            announcement.body,
+            level,
+            starts_at,
+            ends_at,
+            announcement.deliver_to_clients,
+            announcement.created_by,
+            created_at,
+            updated_at
+        )
+        .execute(conn.deref_mut())
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn get_announcement(&self, id: &str) -> ResultType<Option<Announcement>> {
+        let mut conn = self.pool.get().await?;
+
+        let row = sqlx::query_as!(
+            AnnouncementRow,
+            "SELECT id, title, body, level, starts_at, ends_at, deliver_to_clients, created_by, created_at, updated_at FROM announcements WHERE id = ?",
+            id
+        )
+        .fetch_optional(conn.deref_mut())
+        .await?;
+
+        row.map(Announcement::try_from).transpose()
+    }
+
+    pub async fn list_announcements(&self, limit: i64, offset: i64) -> ResultType<Vec<Announcement>> {
+        let mut conn = self.pool.get().await?;
+
+        let rows = sqlx::query_as!(
+            AnnouncementRow,
+            "SELECT id, title, body, level, starts_at, ends_at, deliver_to_clients, created_by, created_at, updated_at FROM announcements ORDER BY starts_at DESC LIMIT ? OFFSET ?",
+            limit,
+            offset
+        )
+        .fetch_all(conn.deref_mut())
+        .await?;
+
+        rows.into_iter().map(Announcement::try_from).collect()
+    }
+
+    // 当前生效中的公告，先开始的在前
+    pub async fn list_active_announcements(&self, now: u64) -> ResultType<Vec<Announcement>> {
+        let mut conn = self.pool.get().await?;
+        let now = now as i64;
+
+        let rows = sqlx::query_as!(
+            AnnouncementRow,
+            "SELECT id, title, body, level, starts_at, ends_at, deliver_to_clients, created_by, created_at, updated_at FROM announcements WHERE starts_at <= ? AND ends_at > ? ORDER BY starts_at",
+            now,
+            now
+        )
+        .fetch_all(conn.deref_mut())
+        .await?;
+
+        rows.into_iter().map(Announcement::try_from).collect()
+    }
+
+    pub async fn remove_announcement(&self, id: &str) -> ResultType<bool> {
+        let mut conn = self.pool.get().await?;
+
+        let result = sqlx::query!("DELETE FROM announcements WHERE id = ?", id)
+            .execute(conn.deref_mut())
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    // AD 同步方法，返回 (设备ID, 设备名, 系统信息上报的主机名)
+    pub async fn list_device_hostnames(&self) -> ResultType<Vec<(String, String, Option<String>)>> {
+        let mut conn = self.pool.get().await?;
+
+        let rows = sqlx::query!(
+            r#"
+            SELECT d.id, d.name, i.hostname AS "hostname?"
+            FROM devices d LEFT JOIN device_inventory i ON i.device_id = d.id
+            "#
+        )
+        .fetch_all(conn.deref_mut())
+        .await?;
+
+        Ok(rows.into_iter().map(|row| (row.id, row.name, row.hostname)).collect())
+    }
+
+    // 创建或更新组织单位对应的设备组，新建的组记在最早的超级管理员名下；返回是否新建
+    pub async fn upsert_ad_device_group(&self, id: &str, name: &str, description: &str) -> ResultType<bool> {
+        let mut conn = self.pool.get().await?;
+        let now = SystemTime::now().duration_since(std::time::UNIX_EPOCH)?.as_secs() as i64;
+        let permissions = serde_json::to_string(&GroupPermissions {
+            can_control: true,
+            can_transfer_files: true,
+            can_view_screen: true,
+            can_use_audio: true,
+            can_use_clipboard: true,
+            session_timeout: None,
+        })?;
+
+        let result = sqlx::query!(
+            "UPDATE device_groups SET name = ?, description = ? WHERE id = ?",
+            name,
+            description,
+            id
+        )
+        .execute(conn.deref_mut())
+        .await?;
+        if result.rows_affected() > 0 {
+            return Ok(false);
+        }
+
+        let result = sqlx::query!(
+            r#"
+            INSERT INTO device_groups (id, name, description, created_by, created_at, devices, permissions)
+            SELECT ?, ?, ?, id, ?, '[]', ? FROM users WHERE role = 'SuperAdmin' ORDER BY created_at LIMIT 1
+            "#,
+            id,
+            name,
+            description,
+            now,
+            permissions
+        )
+        .execute(conn.deref_mut())
+        .await?;
+        if result.rows_affected() == 0 {
+            bail!("no super admin to own device group {}", name);
+        }
+
+        Ok(true)
+    }
+
+    // 把设备放入 group_id 对应的设备组，并移出其他以 prefix 开头的设备组，手工分组不受影响；返回是否有变化
+    pub async fn set_device_ad_group(&self, device_id: &str, prefix: &str, group_id: Option<&str>) -> ResultType<bool> {
+        let mut conn = self.pool.get().await?;
+        let mut tx = conn.deref_mut().begin().await?;
+
+        let row = sqlx::query!("SELECT group_ids FROM devices WHERE id = ?", device_id)
+            .fetch_optional(&mut tx)
+            .await?;
+        let old: Vec<String> = match row {
+            Some(row) => serde_json::from_str(&row.group_ids).unwrap_or_default(),
+            None => return Ok(false),
+        };
+        let mut new: Vec<String> = old.iter().filter(|g| !g.starts_with(prefix)).cloned().collect();
+        new.extend(group_id.map(|g| g.to_owned()));
+        if new.len() == old.len() && new.iter().all(|g| old.contains(g)) {
+            return Ok(false);
+        }
+        let group_ids = serde_json::to_string(&new)?;
+        let pattern = format!("{}%", prefix);
+        let keep = group_id.unwrap_or_default();
+
+        sqlx::query!("UPDATE devices SET group_ids = ? WHERE id = ?", group_ids, device_id)
+            .execute(&mut tx)
+            .await?;
+        sqlx::query!(
+            r#"
+            UPDATE device_groups SET devices = (
+                SELECT json_group_array(value) FROM json_each(device_groups.devices) WHERE value != ?
+            )
+            WHERE id LIKE ? AND id != ?
+                AND EXISTS (SELECT 1 FROM json_each(device_groups.devices) WHERE value = ?)
+            "#,
+            device_id,
+            pattern,
+            keep,
+            device_id
+        )
+        .execute(&mut tx)
+        .await?;
+        sqlx::query!(
+            r#"
+            UPDATE device_groups SET devices = json_insert(devices, '$[#]', ?)
+            WHERE id = ? AND NOT EXISTS (SELECT 1 FROM json_each(device_groups.devices) WHERE value = ?)
+            "#,
+            device_id,
+            keep,
+            device_id
+        )
+        .execute(&mut tx)
+        .await?;
+        tx.commit().await?;
+
+        Ok(true)
+    }
+
+    // Web 会话方法，会话 ID 为令牌的 jti
+    pub async fn create_session(&self, session: &Session) -> ResultType<()> {
+        let mut conn = self.pool.get().await?;
+        let created_at = session.created_at.duration_since(std::time::UNIX_EPOCH)?.as_secs() as i64;
+        let expires_at = session.expires_at.duration_since(std::time::UNIX_EPOCH)?.as_secs() as i64;
+        let last_activity = session.last_activity.duration_since(std::time::UNIX_EPOCH)?.as_secs() as i64;
+
+        sqlx::query!(
+            r#"
+            INSERT INTO sessions (id, user_id, token, created_at, expires_at, last_activity, ip_address, user_agent, active)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)
+            "#,
+            session.id,
+            session.user_id,
+            session.token,
+            created_at,
+            expires_at,
+            last_activity,
+            session.ip_address,
+            session.user_agent,
+            session.active
+        )
+        .execute(conn.deref_mut())
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn get_session(&self, id: &str) -> ResultType<Option<Session>> {
+        let mut conn = self.pool.get().await?;
+
+        let row = sqlx::query!("SELECT * FROM sessions WHERE id = ?", id)
+            .fetch_optional(conn.deref_mut())
+            .await?;
+
+        Ok(row.map(|row| Session {
+            id: row.id,
+            user_id: row.user_id,
+            token: row.token,
+            created_at: std::time::UNIX_EPOCH + std::time::Duration::from_secs(row.created_at as u64),
+            expires_at: std::time::UNIX_EPOCH + std::time::Duration::from_secs(row.expires_at as u64),
+            last_activity: std::time::UNIX_EPOCH + std::time::Duration::from_secs(row.last_activity as u64),
+            ip_address: row.ip_address,
+            user_agent: row.user_agent,
+            active: row.active,
+        }))
+    }
+
+    pub async fn touch_session(&self, id: &str, now: u64) -> ResultType<()> {
+        let mut conn = self.pool.get().await?;
+        let now = now as i64;
+
+        sqlx::query!("UPDATE sessions SET last_activity = ? WHERE id = ?", now, id)
+            .execute(conn.deref_mut())
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn end_session(&self, id: &str) -> ResultType<()> {
+        let mut conn = self.pool.get().await?;
+
+        sqlx::query!("UPDATE sessions SET active = 0 WHERE id = ?", id)
+            .execute(conn.deref_mut())
+            .await?;
+
+        Ok(())
+    }
+
+    // 结束用户的全部会话，返回结束的数量
+    pub async fn end_user_sessions(&self, user_id: &str) -> ResultType<u64> {
+        let mut conn = self.pool.get().await?;
+
+        let res = sqlx::query!("UPDATE sessions SET active = 0 WHERE user_id = ? AND active = 1", user_id)
+            .execute(conn.deref_mut())
+            .await?;
+
+        Ok(res.rows_affected())
+    }
+
+    // 清理在 before 之前已到期的会话
+    pub async fn delete_stale_sessions(&self, before: u64) -> ResultType<u64> {
+        let mut conn = self.pool.get().await?;
+        let before = before as i64;
+
+        let result = sqlx::query!("DELETE FROM sessions WHERE expires_at < ?", before)
+            .execute(conn.deref_mut())
+            .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    // 连接质量采样方法
+    pub async fn save_qos_samples(&self, samples: &[QosSample]) -> ResultType<()> {
+        let mut conn = self.pool.get().await?;
+
+        for sample in samples {
+            let reported_at = sample.reported_at as i64;
+            sqlx::query!(
+                r#"
+                INSERT INTO session_qos (session_id, device_id, relay_server, latency_ms, packet_loss, bitrate_kbps, reported_at)
+                VALUES (?, ?, ?, ?, ?, ?, ?)
+                "#,
+                sample.session_id,
+                sample.device_id,
+                sample.relay_server,
+                sample.latency_ms,
+                sample.packet_loss,
+                sample.bitrate_kbps,
+                reported_at
+            )
+            .execute(conn.deref_mut())
+            .await?;
+        }
+
+        Ok(())
+    }
+
+    pub async fn list_session_qos_samples(&self, session_id: &str) -> ResultType<Vec<QosSample>> {
+        let mut conn = self.pool.get().await?;
+
+        let rows = sqlx::query!(
+            r#"
+            SELECT session_id, device_id, relay_server, latency_ms, packet_loss, bitrate_kbps, reported_at
+            FROM session_qos WHERE session_id = ? ORDER BY reported_at, id
+            "#,
+            session_id
+        )
+        .fetch_all(conn.deref_mut())
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| QosSample {
+                session_id: row.session_id,
+                device_id: row.device_id,
+                relay_server: row.relay_server,
+                latency_ms: row.latency_ms as u32,
+                packet_loss: row.packet_loss,
+                bitrate_kbps: row.bitrate_kbps as u32,
+                reported_at: row.reported_at as u64,
+            })
+            .collect())
+    }
+
+    // 时间窗口内的采样，可按设备或中继服务器筛选
+    pub async fn list_qos_samples(
+        &self,
+        device_id: Option<&str>,
+        relay_server: Option<&str>,
+        since: u64,
+        until: u64,
+    ) -> ResultType<Vec<QosSample>> {
+        let mut conn = self.pool.get().await?;
+        let since = since as i64;
+        let until = until as i64;
+
+        let rows = sqlx::query!(
+            r#"
+            SELECT session_id, device_id, relay_server, latency_ms, packet_loss, bitrate_kbps, reported_at
+            FROM session_qos
+            WHERE reported_at >= ?1 AND reported_at < ?2
+                AND (?3 IS NULL OR device_id = ?3)
+                AND (?4 IS NULL OR relay_server = ?4)
+            ORDER BY reported_at, id
+            "#,
+            since,
+            until,
+            device_id,
+            relay_server
+        )
+        .fetch_all(conn.deref_mut())
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| QosSample {
+                session_id: row.session_id,
+                device_id: row.device_id,
+                relay_server: row.relay_server,
+                latency_ms: row.latency_ms as u32,
+                packet_loss: row.packet_loss,
+                bitrate_kbps: row.bitrate_kbps as u32,
+                reported_at: row.reported_at as u64,
+            })
+            .collect())
+    }
+
+    pub async fn set_session_quality_score(&self, session_id: &str, score: f64) -> ResultType<()> {
+        let mut conn = self.pool.get().await?;
+
+        sqlx::query!(
+            "UPDATE connection_sessions SET quality_score = ? WHERE id = ?",
+            score,
+            session_id
+        )
+        .execute(conn.deref_mut())
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn prune_qos_samples(&self, before: u64) -> ResultType<u64> {
+        let mut conn = self.pool.get().await?;
+        let before = before as i64;
+
+        let result = sqlx::query!("DELETE FROM session_qos WHERE reported_at < ?", before)
+            .execute(conn.deref_mut())
+            .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    // 连通性自检方法，每台设备只保留最近的结果
+    pub async fn save_self_test(&self, result: &SelfTestResult) -> ResultType<()> {
+        let mut conn = self.pool.get().await?;
+        let data = serde_json::to_string(result)?;
+        let completed_at = result.completed_at as i64;
+        let keep = self_test::MAX_RESULTS_PER_DEVICE as i64;
+
+        sqlx::query!(
+            r#"
+            INSERT INTO device_self_tests (test_id, device_id, data, passed, completed_at)
+            VALUES (?, ?, ?, ?, ?)
+            "#,
+            result.test_id,
+            result.device_id,
+            data,
+            result.passed,
+            completed_at
+        )
+        .execute(conn.deref_mut())
+        .await?;
+
+        sqlx::query!(
+            r#"
+            DELETE FROM device_self_tests WHERE device_id = ?1 AND test_id NOT IN (
+                SELECT test_id FROM device_self_tests WHERE device_id = ?1
+                ORDER BY completed_at DESC LIMIT ?2
+            )
+            "#,
+            result.device_id,
+            keep
+        )
+        .execute(conn.deref_mut())
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn list_self_tests(&self, device_id: &str) -> ResultType<Vec<SelfTestResult>> {
+        let mut conn = self.pool.get().await?;
+
+        let rows = sqlx::query!(
+            "SELECT data FROM device_self_tests WHERE device_id = ? ORDER BY completed_at DESC",
+            device_id
+        )
+        .fetch_all(conn.deref_mut())
+        .await?;
+
+        let mut res = Vec::new();
+        for row in rows {
+            res.push(serde_json::from_str(&row.data)?);
+        }
+        Ok(res)
+    }
+
+    // 编码推荐方法
+    pub async fn save_codec_recommendation(&self, recommendation: &CodecRecommendation) -> ResultType<()> {
+        let mut conn = self.pool.get().await?;
+        let data = serde_json::to_string(recommendation)?;
+        let issued_at = recommendation.issued_at as i64;
+        let acked_at = recommendation.acked_at.map(|t| t as i64);
+
+        sqlx::query!(
+            r#"
+            INSERT INTO codec_recommendations (session_id, device_id, data, issued_at, acked_at)
+            VALUES (?, ?, ?, ?, ?)
+            ON CONFLICT(session_id, device_id) DO UPDATE SET
+                data = excluded.data, issued_at = excluded.issued_at, acked_at = excluded.acked_at
+            "#,
+            recommendation.session_id,
+            recommendation.device_id,
+            data,
+            issued_at,
+            acked_at
+        )
+        .execute(conn.deref_mut())
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn get_codec_recommendation(
+        &self,
+        session_id: &str,
+        device_id: &str,
+    ) -> ResultType<Option<CodecRecommendation>> {
+        let mut conn = self.pool.get().await?;
+
+        let row = sqlx::query!(
+            "SELECT data FROM codec_recommendations WHERE session_id = ? AND device_id = ?",
+            session_id,
+            device_id
+        )
+        .fetch_optional(conn.deref_mut())
+        .await?;
+
+        match row {
+            Some(row) => Ok(Some(serde_json::from_str(&row.data)?)),
+            None => Ok(None),
+        }
+    }
+
+    pub async fn list_codec_recommendations(&self, session_id: &str) -> ResultType<Vec<CodecRecommendation>> {
+        let mut conn = self.pool.get().await?;
+
+        let rows = sqlx::query!(
+            "SELECT data FROM codec_recommendations WHERE session_id = ? ORDER BY device_id",
+            session_id
+        )
+        .fetch_all(conn.deref_mut())
+        .await?;
+
+        let mut res = Vec::new();
+        for row in rows {
+            res.push(serde_json::from_str(&row.data)?);
+        }
+        Ok(res)
+    }
+
+    pub async fn prune_codec_recommendations(&self, before: u64) -> ResultType<u64> {
+        let mut conn = self.pool.get().await?;
+        let before = before as i64;
+
+        let result = sqlx::query!("DELETE FROM codec_recommendations WHERE issued_at < ?", before)
+            .execute(conn.deref_mut())
+            .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    pub async fn save_device_capabilities(&self, capabilities: &DeviceCapabilities) -> ResultType<()> {
+        let mut conn = self.pool.get().await?;
+        let data = serde_json::to_string(capabilities)?;
+        let updated_at = capabilities.updated_at as i64;
+
+        sqlx::query!(
+            "INSERT OR REPLACE INTO device_capabilities (device_id, data, updated_at) VALUES (?, ?, ?)",
+            capabilities.device_id,
+            data,
+            updated_at
+        )
+        .execute(conn.deref_mut())
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn get_device_capabilities(&self, device_id: &str) -> ResultType<Option<DeviceCapabilities>> {
+        let mut conn = self.pool.get().await?;
+
+        let row = sqlx::query!("SELECT data FROM device_capabilities WHERE device_id = ?", device_id)
+            .fetch_optional(conn.deref_mut())
+            .await?;
+
+        match row {
+            Some(row) => Ok(Some(serde_json::from_str(&row.data)?)),
+            None => Ok(None),
+        }
+    }
+
+    // 端到端密钥下发记录方法
+    pub async fn log_key_exchange(&self, record: &KeyExchangeRecord) -> ResultType<()> {
+        let mut conn = self.pool.get().await?;
+        let offer = format!("{:?}", record.offer);
+        let protected = record.offer.is_protected();
+        let created_at = record.created_at as i64;
+
+        sqlx::query!(
+            r#"
+            INSERT INTO key_exchanges (session_id, device_id, requester, channel, offer, protected, created_at)
+            VALUES (?, ?, ?, ?, ?, ?, ?)
+            "#,
+            record.session_id,
+            record.device_id,
+            record.requester,
+            record.channel,
+            offer,
+            protected,
+            created_at
+        )
+        .execute(conn.deref_mut())
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn list_key_exchanges(&self, device_id: Option<&str>, limit: i64) -> ResultType<Vec<KeyExchangeRecord>> {
+        let mut conn = self.pool.get().await?;
+
+        let rows = match device_id {
+            Some(did) => {
+                sqlx::query_as!(
+                    KeyExchangeRow,
+                    "SELECT id, session_id, device_id, requester, channel, offer, created_at FROM key_exchanges WHERE device_id = ? ORDER BY id DESC LIMIT ?",
+                    did,
+                    limit
+                )
+                .fetch_all(conn.deref_mut())
+                .await?
+            }
+            None => {
+                sqlx::query_as!(
+                    KeyExchangeRow,
+                    "SELECT id, session_id, device_id, requester, channel, offer, created_at FROM key_exchanges ORDER BY id DESC LIMIT ?",
+                    limit
+                )
+                .fetch_all(conn.deref_mut())
+                .await?
+            }
+        };
+
+        Ok(rows
+            .into_iter()
+            .map(|row| KeyExchangeRecord {
+                id: row.id,
+                session_id: row.session_id,
+                device_id: row.device_id,
+                requester: row.requester,
+                channel: row.channel,
+                offer: serde_json::from_value(serde_json::Value::String(row.offer))
+                    .unwrap_or(KeyOffer::PeerKeyMissing),
+                created_at: row.created_at as u64,
+            })
+            .collect())
+    }
+
+    pub async fn prune_key_exchanges(&self, before: u64) -> ResultType<u64> {
+        let mut conn = self.pool.get().await?;
+        let before = before as i64;
+
+        let result = sqlx::query!("DELETE FROM key_exchanges WHERE created_at < ?", before)
+            .execute(conn.deref_mut())
+            .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    // 返回 since 之后的(总数, 端到端保护数)
+    pub async fn count_key_exchanges(&self, since: u64) -> ResultType<(i64, i64)> {
+        let mut conn = self.pool.get().await?;
+        let since = since as i64;
+
+        let row = sqlx::query!(
+            r#"SELECT COUNT(*) as "total!: i64", COALESCE(SUM(protected), 0) as "protected!: i64" FROM key_exchanges WHERE created_at >= ?"#,
+            since
+        )
+        .fetch_one(conn.deref_mut())
+        .await?;
+
+        Ok((row.total, row.protected))
+    }
+
+    // 安全事件方法
+    pub async fn save_security_event(&self, event: &SecurityEvent) -> ResultType<()> {
+        let mut conn = self.pool.get().await?;
+        let event_type = format!("{:?}", event.event_type);
+        let severity = format!("{:?}", event.severity);
+        let details = serde_json::to_string(&event.details)?;
+        let created_at = event.timestamp.duration_since(std::time::UNIX_EPOCH)?.as_secs() as i64;
+
+        sqlx::query!(
+            r#"
+            INSERT INTO security_events (id, event_type, severity, user_id, device_id, ip_address, details, created_at, resolved, resolution_notes)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+            "#,
+            event.id,
+            event_type,
+            severity,
+            event.user_id,
+            event.device_id,
+            event.ip_address,
+            details,
+            created_at,
+            event.resolved,
+            event.resolution_notes
+        )
+        .execute(conn.deref_mut())
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn list_security_events(&self, since: u64, limit: i64) -> ResultType<Vec<SecurityEvent>> {
+        let mut conn = self.pool.get().await?;
+        let since = since as i64;
+
+        let rows = sqlx::query_as!(
+            SecurityEventRow,
+            "SELECT id, event_type, severity, user_id, device_id, ip_address, details, created_at, resolved, resolution_notes FROM security_events WHERE created_at >= ? ORDER BY created_at DESC LIMIT ?",
+            since,
+            limit
+        )
+        .fetch_all(conn.deref_mut())
+        .await?;
+
+        rows.into_iter().map(SecurityEvent::try_from).collect()
+    }
+
+    // 设备在 [since, until] 内的安全事件，按时间倒序
+    pub async fn list_device_security_events(
+        &self,
+        device_id: &str,
+        since: u64,
+        until: u64,
+        limit: i64,
+    ) -> ResultType<Vec<SecurityEvent>> {
+        let mut conn = self.pool.get().await?;
+        let since = since as i64;
+        let until = until.min(i64::MAX as u64) as i64;
+
+        let rows = sqlx::query_as!(
+            SecurityEventRow,
+            r#"
+            SELECT id, event_type, severity, user_id, device_id, ip_address, details, created_at, resolved, resolution_notes
+            FROM security_events WHERE device_id = ? AND created_at >= ? AND created_at <= ?
+            ORDER BY created_at DESC LIMIT ?
+            "#,
+            device_id,
+            since,
+            until,
+            limit
+        )
+        .fetch_all(conn.deref_mut())
+        .await?;
+
+        rows.into_iter().map(SecurityEvent::try_from).collect()
+    }
+
+    // 文件传输历史方法
+    pub async fn save_file_transfer(&self, record: &TransferRecord) -> ResultType<()> {
+        let mut conn = self.pool.get().await?;
+        let transfer_type = format!("{:?}", record.transfer_type);
+        let status = format!("{:?}", record.status);
+        let file_size = record.file_size as i64;
+        let bytes_transferred = record.bytes_transferred as i64;
+        let started_at = record.started_at as i64;
+        let finished_at = record.finished_at as i64;
+        let duration_ms = record.duration_ms as i64;
+
+        sqlx::query!(
+            r#"
+            INSERT INTO file_transfers (transfer_id, user_id, device_id, file_path, transfer_type, file_size, file_hash,
+                bytes_transferred, status, error_message, started_at, finished_at, duration_ms)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+            "#,
+            record.transfer_id,
+            record.user_id,
+            record.device_id,
+            record.file_path,
+            transfer_type,
+            file_size,
+            record.file_hash,
+            bytes_transferred,
+            status,
+            record.error_message,
+            started_at,
+            finished_at,
+            duration_ms
+        )
+        .execute(conn.deref_mut())
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn list_file_transfers(
+        &self,
+        filter: &FileTransferFilter,
+        limit: i64,
+        offset: i64,
+    ) -> ResultType<Vec<TransferRecord>> {
+        let mut conn = self.pool.get().await?;
+        let since = filter.since.map(|v| v as i64);
+        let until = filter.until.map(|v| v as i64);
+
+        let rows = sqlx::query_as!(
+            FileTransferRow,
+            r#"
+            SELECT transfer_id, user_id, device_id, file_path, transfer_type, file_size, file_hash,
+                bytes_transferred, status, error_message, started_at, finished_at, duration_ms
+            FROM file_transfers
+            WHERE (?1 IS NULL OR user_id = ?1)
+                AND (?2 IS NULL OR device_id = ?2)
+                AND (?3 IS NULL OR instr(file_path, ?3) > 0)
+                AND (?4 IS NULL OR file_hash = ?4)
+                AND (?5 IS NULL OR status = ?5)
+                AND (?6 IS NULL OR finished_at >= ?6)
+                AND (?7 IS NULL OR finished_at <= ?7)
+            ORDER BY finished_at DESC, id DESC
+            LIMIT ?8 OFFSET ?9
+            "#,
+            filter.user_id,
+            filter.device_id,
+            filter.path,
+            filter.file_hash,
+            filter.status,
+            since,
+            until,
+            limit,
+            offset
+        )
+        .fetch_all(conn.deref_mut())
+        .await?;
+
+        let mut records = Vec::with_capacity(rows.len());
+        for row in rows {
+            records.push(TransferRecord {
+                transfer_id: row.transfer_id,
+                user_id: row.user_id,
+                device_id: row.device_id,
+                file_path: row.file_path,
+                transfer_type: serde_json::from_value(serde_json::Value::String(row.transfer_type))?,
+                file_size: row.file_size as u64,
+                file_hash: row.file_hash,
+                bytes_transferred: row.bytes_transferred as u64,
+                status: serde_json::from_value(serde_json::Value::String(row.status))?,
+                error_message: row.error_message,
+                started_at: row.started_at as u64,
+                finished_at: row.finished_at as u64,
+                duration_ms: row.duration_ms as u64,
+            });
+        }
+        Ok(records)
+    }
+
+    // 用户是否成功上传过该路径的文件，用于下载时判断文件归属
+    pub async fn has_uploaded_file(&self, user_id: &str, file_path: &str) -> ResultType<bool> {
+        let mut conn = self.pool.get().await?;
+
+        let row = sqlx::query!(
+            r#"
+            SELECT COUNT(*) as "count!: i64" FROM file_transfers
+            WHERE user_id = ? AND file_path = ? AND transfer_type = 'Upload' AND status = 'Completed'
+            "#,
+            user_id,
+            file_path
+        )
+        .fetch_one(conn.deref_mut())
+        .await?;
+
+        Ok(row.count > 0)
+    }
+
+    // 资产清单方法
+    pub async fn save_inventory(&self, inventory: &DeviceInventory) -> ResultType<()> {
+        let mut conn = self.pool.get().await?;
+        let memory_bytes = inventory.memory_bytes.map(|x| x as i64);
+        let updated_at = inventory.updated_at as i64;
+
+        sqlx::query!(
+            r#"
+            INSERT OR REPLACE INTO device_inventory (
+                device_id, hostname, username, os, os_build, cpu, memory, memory_bytes,
+                rustdesk_version, updated_at
+            ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+            "#,
+            inventory.device_id,
+            inventory.hostname,
+            inventory.username,
+            inventory.os,
+            inventory.os_build,
+            inventory.cpu,
+            inventory.memory,
+            memory_bytes,
+            inventory.rustdesk_version,
+            updated_at
+        )
+        .execute(conn.deref_mut())
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn get_inventory(&self, device_id: &str) -> ResultType<Option<DeviceInventory>> {
+        let mut conn = self.pool.get().await?;
+
+        let row = sqlx::query_as!(
+            InventoryRow,
+            r#"
+            SELECT device_id, hostname, username, os, os_build, cpu, memory, memory_bytes,
+                rustdesk_version, updated_at
+            FROM device_inventory WHERE device_id = ?
+            "#,
+            device_id
+        )
+        .fetch_optional(conn.deref_mut())
+        .await?;
+
+        Ok(row.map(DeviceInventory::from))
+    }
+
+    pub async fn list_inventory(
+        &self,
+        filter: &InventoryFilter,
+        limit: i64,
+        offset: i64,
+    ) -> ResultType<Vec<DeviceInventory>> {
+        let mut conn = self.pool.get().await?;
+        let min_memory = filter.min_memory.map(|v| v as i64);
+        let max_memory = filter.max_memory.map(|v| v as i64);
+
+        let rows = sqlx::query_as!(
+            InventoryRow,
+            r#"
+            SELECT device_id, hostname, username, os, os_build, cpu, memory, memory_bytes,
+                rustdesk_version, updated_at
+            FROM device_inventory
+            WHERE (?1 IS NULL OR instr(lower(hostname), lower(?1)) > 0)
+                AND (?2 IS NULL OR instr(lower(os), lower(?2)) > 0)
+                AND (?3 IS NULL OR os_build = ?3)
+                AND (?4 IS NULL OR instr(lower(cpu), lower(?4)) > 0)
+                AND (?5 IS NULL OR rustdesk_version = ?5)
+                AND (?6 IS NULL OR memory_bytes >= ?6)
+                AND (?7 IS NULL OR memory_bytes <= ?7)
+            ORDER BY device_id
+            LIMIT ?8 OFFSET ?9
+            "#,
+            filter.hostname,
+            filter.os,
+            filter.os_build,
+            filter.cpu,
+            filter.version,
+            min_memory,
+            max_memory,
+            limit,
+            offset
+        )
+        .fetch_all(conn.deref_mut())
+        .await?;
+
+        Ok(rows.into_iter().map(DeviceInventory::from).collect())
+    }
+
+    pub async fn inventory_report(&self) -> ResultType<InventoryReport> {
+        let mut conn = self.pool.get().await?;
+
+        let total = sqlx::query!(r#"SELECT COUNT(*) as "count!: i64" FROM device_inventory"#)
+            .fetch_one(conn.deref_mut())
+            .await?
+            .count;
+        let by_os = sqlx::query_as!(
+            InventoryCountRow,
+            r#"SELECT os as "value!", COUNT(*) as "count!: i64" FROM device_inventory GROUP BY os ORDER BY 2 DESC"#
+        )
+        .fetch_all(conn.deref_mut())
+        .await?;
+        let by_os_build = sqlx::query_as!(
+            InventoryCountRow,
+            r#"SELECT COALESCE(os_build, '') as "value!", COUNT(*) as "count!: i64" FROM device_inventory GROUP BY 1 ORDER BY 2 DESC"#
+        )
+        .fetch_all(conn.deref_mut())
+        .await?;
+        let by_version = sqlx::query_as!(
+            InventoryCountRow,
+            r#"SELECT rustdesk_version as "value!", COUNT(*) as "count!: i64" FROM device_inventory GROUP BY rustdesk_version ORDER BY 2 DESC"#
+        )
+        .fetch_all(conn.deref_mut())
+        .await?;
+        // 内存按 GB 区间统计，未能解析的归为 unknown
+        let by_memory = sqlx::query_as!(
+            InventoryCountRow,
+            r#"
+            SELECT CASE
+                    WHEN memory_bytes IS NULL THEN 'unknown'
+                    WHEN memory_bytes < 4 * 1073741824 THEN '<4GB'
+                    WHEN memory_bytes < 8 * 1073741824 THEN '4-8GB'
+                    WHEN memory_bytes < 16 * 1073741824 THEN '8-16GB'
+                    WHEN memory_bytes < 32 * 1073741824 THEN '16-32GB'
+                    ELSE '>=32GB'
+                END as "value!",
+                COUNT(*) as "count!: i64"
+            FROM device_inventory GROUP BY 1 ORDER BY MIN(COALESCE(memory_bytes, -1))
+            "#
+        )
+        .fetch_all(conn.deref_mut())
+        .await?;
+
+        Ok(InventoryReport {
+            total,
+            by_os: by_os.into_iter().map(InventoryCount::from).collect(),
+            by_os_build: by_os_build.into_iter().map(InventoryCount::from).collect(),
+            by_version: by_version.into_iter().map(InventoryCount::from).collect(),
+            by_memory: by_memory.into_iter().map(InventoryCount::from).collect(),
+        })
+    }
+
+    // 客户端安装包方法
+    pub async fn save_update_artifact(&self, artifact: &UpdateArtifact) -> ResultType<()> {
+        let mut conn = self.pool.get().await?;
+        let platform = artifact.platform.as_str();
+        let size = artifact.size as i64;
+        let uploaded_at = artifact.uploaded_at as i64;
+
+        sqlx::query!(
+            r#"
+            INSERT OR REPLACE INTO update_artifacts (
+                platform, version, file_name, size, sha256, uploaded_by, uploaded_at
+            ) VALUES (?, ?, ?, ?, ?, ?, ?)
+            "#,
+            platform,
+            artifact.version,
+            artifact.file_name,
+            size,
+            artifact.sha256,
+            artifact.uploaded_by,
+            uploaded_at
+        )
+        .execute(conn.deref_mut())
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn get_update_artifact(&self, platform: Platform, version: &str) -> ResultType<Option<UpdateArtifact>> {
+        let mut conn = self.pool.get().await?;
+        let platform = platform.as_str();
+
+        let row = sqlx::query_as!(
+            UpdateArtifactRow,
+            r#"
+            SELECT platform, version, file_name, size, sha256, uploaded_by, uploaded_at
+            FROM update_artifacts WHERE platform = ? AND version = ?
+            "#,
+            platform,
+            version
+        )
+        .fetch_optional(conn.deref_mut())
+        .await?;
+
+        row.map(UpdateArtifact::try_from).transpose()
+    }
+
+    pub async fn list_update_artifacts(&self) -> ResultType<Vec<UpdateArtifact>> {
+        let mut conn = self.pool.get().await?;
+
+        let rows = sqlx::query_as!(
+            UpdateArtifactRow,
+            r#"
+            SELECT platform, version, file_name, size, sha256, uploaded_by, uploaded_at
+            FROM update_artifacts ORDER BY platform, uploaded_at DESC
+            "#
+        )
+        .fetch_all(conn.deref_mut())
+        .await?;
+
+        rows.into_iter().map(UpdateArtifact::try_from).collect()
+    }
+
+    pub async fn delete_update_artifact(&self, platform: Platform, version: &str) -> ResultType<bool> {
+        let mut conn = self.pool.get().await?;
+        let platform = platform.as_str();
+
+        let result = sqlx::query!(
+            "DELETE FROM update_artifacts WHERE platform = ? AND version = ?",
+            platform,
+            version
+        )
+        .execute(conn.deref_mut())
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    // 紧急访问方法
+    pub async fn save_emergency_access(&self, access: &EmergencyAccess) -> ResultType<()> {
+        let mut conn = self.pool.get().await?;
+        let created_at = access.created_at as i64;
+        let expires_at = access.expires_at as i64;
+
+        sqlx::query!(
+            r#"
+            INSERT INTO emergency_access (
+                id, user_id, username, device_id, justification, ip_address, created_at, expires_at
+            ) VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+            "#,
+            access.id,
+            access.user_id,
+            access.username,
+            access.device_id,
+            access.justification,
+            access.ip_address,
+            created_at,
+            expires_at
+        )
+        .execute(conn.deref_mut())
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn get_emergency_access(&self, id: &str) -> ResultType<Option<EmergencyAccess>> {
+        let mut conn = self.pool.get().await?;
+
+        let row = sqlx::query_as!(
+            EmergencyAccessRow,
+            r#"
+            SELECT id, user_id, username, device_id, justification, ip_address, created_at, expires_at,
+                   ended_at, reviewed_by, reviewed_at, review_notes
+            FROM emergency_access WHERE id = ?
+            "#,
+            id
+        )
+        .fetch_optional(conn.deref_mut())
+        .await?;
+
+        Ok(row.map(EmergencyAccess::from))
+    }
+
+    pub async fn get_active_emergency_access(
+        &self,
+        device_id: &str,
+        user_id: &str,
+        now: u64,
+    ) -> ResultType<Option<EmergencyAccess>> {
+        let mut conn = self.pool.get().await?;
+        let now = now as i64;
+
+        let row = sqlx::query_as!(
+            EmergencyAccessRow,
+            r#"
+            SELECT id, user_id, username, device_id, justification, ip_address, created_at, expires_at,
+                   ended_at, reviewed_by, reviewed_at, review_notes
+            FROM emergency_access
+            WHERE device_id = ? AND user_id = ? AND ended_at IS NULL AND expires_at > ?
+            ORDER BY created_at DESC LIMIT 1
+            "#,
+            device_id,
+            user_id,
+            now
+        )
+        .fetch_optional(conn.deref_mut())
+        .await?;
+
+        Ok(row.map(EmergencyAccess::from))
+    }
+
+    // 到期但尚未标记结束的紧急访问
+    pub async fn list_expired_emergency_access(&self, now: u64) -> ResultType<Vec<EmergencyAccess>> {
+        let mut conn = self.pool.get().await?;
+        let now = now as i64;
+
+        let rows = sqlx::query_as!(
+            EmergencyAccessRow,
+            r#"
+            SELECT id, user_id, username, device_id, justification, ip_address, created_at, expires_at,
+                   ended_at, reviewed_by, reviewed_at, review_notes
+            FROM emergency_access WHERE ended_at IS NULL AND expires_at <= ?
+            "#,
+            now
+        )
+        .fetch_all(conn.deref_mut())
+        .await?;
+
+        Ok(rows.into_iter().map(EmergencyAccess::from).collect())
+    }
+
+    pub async fn list_emergency_access(
+        &self,
+        pending_review: bool,
+        limit: i64,
+        offset: i64,
+    ) -> ResultType<Vec<EmergencyAccess>> {
+        let mut conn = self.pool.get().await?;
+
+        let rows = sqlx::query_as!(
+            EmergencyAccessRow,
+            r#"
+            SELECT id, user_id, username, device_id, justification, ip_address, created_at, expires_at,
+                   ended_at, reviewed_by, reviewed_at, review_notes
+            FROM emergency_access
+            WHERE (? = 0 OR reviewed_by IS NULL)
+            ORDER BY created_at DESC LIMIT ? OFFSET ?
+            "#,
+            pending_review,
+            limit,
+            offset
+        )
+        .fetch_all(conn.deref_mut())
+        .await?;
+
+        Ok(rows.into_iter().map(EmergencyAccess::from).collect())
+    }
+
+    // 代操作方法
+    pub async fn save_impersonation(&self, session: &ImpersonationSession) -> ResultType<()> {
+        let mut conn = self.pool.get().await?;
+        let started_at = session.started_at as i64;
+        let expires_at = session.expires_at as i64;
+
+        sqlx::query!(
+            r#"
+            INSERT INTO impersonation_sessions (
+                id, actor_id, actor_username, user_id, username, reason, started_at, expires_at
+            ) VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+            "#,
+            session.id,
+            session.actor_id,
+            session.actor_username,
+            session.user_id,
+            session.username,
+            session.reason,
+            started_at,
+            expires_at
+        )
+        .execute(conn.deref_mut())
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn end_impersonation(&self, id: &str, ended_at: u64) -> ResultType<bool> {
+        let mut conn = self.pool.get().await?;
+        let ended_at = ended_at as i64;
+
+        let result = sqlx::query!(
+            "UPDATE impersonation_sessions SET ended_at = ? WHERE id = ? AND ended_at IS NULL",
+            ended_at,
+            id
+        )
+        .execute(conn.deref_mut())
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn list_impersonations(&self, limit: i64, offset: i64) -> ResultType<Vec<ImpersonationSession>> {
+        let mut conn = self.pool.get().await?;
+
+        let rows = sqlx::query_as!(
+            ImpersonationRow,
+            r#"
+            SELECT id, actor_id, actor_username, user_id, username, reason, started_at, expires_at, ended_at
+            FROM impersonation_sessions
+            ORDER BY started_at DESC LIMIT ? OFFSET ?
+            "#,
+            limit,
+            offset
+        )
+        .fetch_all(conn.deref_mut())
+        .await?;
+
+        Ok(rows.into_iter().map(ImpersonationSession::from).collect())
+    }
+
+    // 设置变更审批方法
+    pub async fn save_setting_change(&self, change: &SettingChange) -> ResultType<()> {
+        let mut conn = self.pool.get().await?;
+        let requested_at = change.requested_at as i64;
+        let status = format!("{:?}", change.status);
+
+        sqlx::query!(
+            r#"
+            INSERT INTO setting_changes (
+                id, key, old_value, new_value, requested_by, requested_by_name, requested_at, status
+            ) VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+            "#,
+            change.id,
+            change.key,
+            change.old_value,
+            change.new_value,
+            change.requested_by,
+            change.requested_by_name,
+            requested_at,
+            status
+        )
+        .execute(conn.deref_mut())
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn get_setting_change(&self, id: &str) -> ResultType<Option<SettingChange>> {
+        let mut conn = self.pool.get().await?;
+
+        let row = sqlx::query_as!(
+            SettingChangeRow,
+            r#"
+            SELECT id, key, old_value, new_value, requested_by, requested_by_name, requested_at, status,
+                   reviewed_by, reviewed_by_name, reviewed_at, comment
+            FROM setting_changes WHERE id = ?
+            "#,
+            id
+        )
+        .fetch_optional(conn.deref_mut())
+        .await?;
+
+        Ok(row.map(SettingChange::from))
+    }
+
+    pub async fn pending_setting_change(&self, key: &str) -> ResultType<Option<SettingChange>> {
+        let mut conn = self.pool.get().await?;
+        let pending = format!("{:?}", ChangeStatus::Pending);
+
+        let row = sqlx::query_as!(
+            SettingChangeRow,
+            r#"
+            SELECT id, key, old_value, new_value, requested_by, requested_by_name, requested_at, status,
+                   reviewed_by, reviewed_by_name, reviewed_at, comment
+            FROM setting_changes WHERE key = ? AND status = ?
+            "#,
+            key,
+            pending
+        )
+        .fetch_optional(conn.deref_mut())
+        .await?;
+
+        Ok(row.map(SettingChange::from))
+    }
+
+    // status 为空时返回全部变更
+    pub async fn list_setting_changes(
+        &self,
+        status: Option<ChangeStatus>,
+        limit: i64,
+        offset: i64,
+    ) -> ResultType<Vec<SettingChange>> {
+        let mut conn = self.pool.get().await?;
+        let status = status.map(|x| format!("{:?}", x));
+
+        let rows = sqlx::query_as!(
+            SettingChangeRow,
+            r#"
+            SELECT id, key, old_value, new_value, requested_by, requested_by_name, requested_at, status,
+                   reviewed_by, reviewed_by_name, reviewed_at, comment
+            FROM setting_changes
+            WHERE (? IS NULL OR status = ?)
+            ORDER BY requested_at DESC LIMIT ? OFFSET ?
+            "#,
+            status,
+            status,
+            limit,
+            offset
+        )
+        .fetch_all(conn.deref_mut())
+        .await?;
+
+        Ok(rows.into_iter().map(SettingChange::from).collect())
+    }
+
+    // 只更新仍处于 from 状态的变更，返回是否更新
+    #[allow(clippy::too_many_arguments)]
+    pub async fn set_setting_change_status(
+        &self,
+        id: &str,
+        from: ChangeStatus,
+        to: ChangeStatus,
+        reviewed_by: &str,
+        reviewed_by_name: &str,
+        reviewed_at: u64,
+        comment: Option<String>,
+    ) -> ResultType<bool> {
+        let mut conn = self.pool.get().await?;
+        let from = format!("{:?}", from);
+        let to = format!("{:?}", to);
+        let reviewed_at = reviewed_at as i64;
+
+        let result = sqlx::query!(
+            r#"
+            UPDATE setting_changes
+            SET status = ?, reviewed_by = ?, reviewed_by_name = ?, reviewed_at = ?, comment = COALESCE(?, comment)
+            WHERE id = ? AND status = ?
+            "#,
+            to,
+            reviewed_by,
+            reviewed_by_name,
+            reviewed_at,
+            comment,
+            id,
+            from
+        )
+        .execute(conn.deref_mut())
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn end_emergency_access(&self, id: &str, ended_at: u64) -> ResultType<bool> {
+        let mut conn = self.pool.get().await?;
+        let ended_at = ended_at as i64;
+
+        let result = sqlx::query!(
+            "UPDATE emergency_access SET ended_at = ? WHERE id = ? AND ended_at IS NULL",
+            ended_at,
+            id
+        )
+        .execute(conn.deref_mut())
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn review_emergency_access(
+        &self,
+        id: &str,
+        reviewed_by: &str,
+        notes: &str,
+        reviewed_at: u64,
+    ) -> ResultType<bool> {
+        let mut conn = self.pool.get().await?;
+        let reviewed_at = reviewed_at as i64;
+
+        let result = sqlx::query!(
+            r#"
+            UPDATE emergency_access SET reviewed_by = ?, reviewed_at = ?, review_notes = ?
+            WHERE id = ? AND reviewed_by IS NULL
+            "#,
+            reviewed_by,
+            reviewed_at,
+            notes,
+            id
+        )
+        .execute(conn.deref_mut())
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    // GDPR 擦除用户，在一个事务中完成；返回各表受影响的记录数
+    pub async fn erase_user(&self, user: &User, pseudonym: &str, purge: bool) -> ResultType<BTreeMap<String, u64>> {
+        let mut conn = self.pool.get().await?;
+        let mut tx = conn.deref_mut().begin().await?;
+        let mut records = BTreeMap::new();
+        let email = user.email.clone().unwrap_or_default();
+
+        let result = sqlx::query!(
+            "UPDATE audit_logs SET ip_address = '0.0.0.0', user_agent = NULL WHERE user_id = ?",
+            user.id
+        )
+        .execute(&mut tx)
+        .await?;
+        records.insert("audit_logs".to_owned(), result.rows_affected());
+
+        // 其他审计日志详情中出现的用户名和邮箱
+        let result = sqlx::query!(
+            r#"
+            UPDATE audit_logs SET details = replace(replace(details, ?, ?), ?, '[erased]')
+            WHERE instr(details, ?) > 0 OR (? != '' AND instr(details, ?) > 0)
+            "#,
+            user.username,
+            pseudonym,
+            email,
+            user.username,
+            email,
+            email
+        )
+        .execute(&mut tx)
+        .await?;
+        records.insert("audit_log_details".to_owned(), result.rows_affected());
+
+        let result = sqlx::query!("DELETE FROM sessions WHERE user_id = ?", user.id)
+            .execute(&mut tx)
+            .await?;
+        records.insert("sessions".to_owned(), result.rows_affected());
+
+        let result = sqlx::query!("DELETE FROM trusted_devices WHERE user_id = ?", user.id)
+            .execute(&mut tx)
+            .await?;
+        records.insert("trusted_devices".to_owned(), result.rows_affected());
+
+        let result = sqlx::query!("DELETE FROM email_otp_grants WHERE user_id = ?", user.id)
+            .execute(&mut tx)
+            .await?;
+        records.insert("email_otp_grants".to_owned(), result.rows_affected());
+
+        let result = sqlx::query!("DELETE FROM user_invites WHERE user_id = ?", user.id)
+            .execute(&mut tx)
+            .await?;
+        records.insert("user_invites".to_owned(), result.rows_affected());
+
+        let result = sqlx::query!("DELETE FROM notification_preferences WHERE user_id = ?", user.id)
+            .execute(&mut tx)
+            .await?;
+        records.insert("notification_preferences".to_owned(), result.rows_affected());
+
+        let result = sqlx::query!("DELETE FROM user_languages WHERE user_id = ?", user.id)
+            .execute(&mut tx)
+            .await?;
+        records.insert("user_languages".to_owned(), result.rows_affected());
+
+        let result = sqlx::query!("DELETE FROM device_views WHERE user_id = ?", user.id)
+            .execute(&mut tx)
+            .await?;
+        records.insert("device_views".to_owned(), result.rows_affected());
+
+        let result = sqlx::query!("DELETE FROM recent_connections WHERE user_id = ?", user.id)
+            .execute(&mut tx)
+            .await?;
+        records.insert("recent_connections".to_owned(), result.rows_affected());
+
+        let result = sqlx::query!("DELETE FROM favorite_devices WHERE user_id = ?", user.id)
+            .execute(&mut tx)
+            .await?;
+        records.insert("favorite_devices".to_owned(), result.rows_affected());
+
+        let result = sqlx::query!(
+            "DELETE FROM custom_field_values WHERE entity = 'user' AND entity_id = ?",
+            user.id
+        )
+        .execute(&mut tx)
+        .await?;
+        records.insert("custom_field_values".to_owned(), result.rows_affected());
+
+        let result = sqlx::query!(
+            "UPDATE security_events SET ip_address = '0.0.0.0' WHERE user_id = ?",
+            user.id
+        )
+        .execute(&mut tx)
+        .await?;
+        records.insert("security_events".to_owned(), result.rows_affected());
+
+        // 代操作记录用于追责，两种方式都只替换用户名
+        let result = sqlx::query!(
+            "UPDATE impersonation_sessions SET username = ? WHERE user_id = ?",
+            pseudonym,
+            user.id
+        )
+        .execute(&mut tx)
+        .await?;
+        records.insert("impersonation_sessions".to_owned(), result.rows_affected());
+
+        // 设备备注属于设备的排障记录，只替换作者名
+        let result = sqlx::query!(
+            "UPDATE device_notes SET author_name = ? WHERE author_id = ?",
+            pseudonym,
+            user.id
+        )
+        .execute(&mut tx)
+        .await?;
+        records.insert("device_notes".to_owned(), result.rows_affected());
+
+        let result = sqlx::query!(
+            "UPDATE setting_changes SET requested_by_name = ? WHERE requested_by = ?",
+            pseudonym,
+            user.id
+        )
+        .execute(&mut tx)
+        .await?;
+        let mut count = result.rows_affected();
+        let result = sqlx::query!(
+            "UPDATE setting_changes SET reviewed_by_name = ? WHERE reviewed_by = ?",
+            pseudonym,
+            user.id
+        )
+        .execute(&mut tx)
+        .await?;
+        count += result.rows_affected();
+        records.insert("setting_changes".to_owned(), count);
+
+        if purge {
+            let result = sqlx::query!(
+                "DELETE FROM session_events WHERE session_id IN (SELECT id FROM connection_sessions WHERE controller_id = ?)",
+                user.id
+            )
+            .execute(&mut tx)
+            .await?;
+            records.insert("session_events".to_owned(), result.rows_affected());
+
+            let result = sqlx::query!(
+                "DELETE FROM session_qos WHERE session_id IN (SELECT id FROM connection_sessions WHERE controller_id = ?)",
+                user.id
+            )
+            .execute(&mut tx)
+            .await?;
+            records.insert("session_qos".to_owned(), result.rows_affected());
+
+            let result = sqlx::query!("DELETE FROM connection_sessions WHERE controller_id = ?", user.id)
+                .execute(&mut tx)
+                .await?;
+            records.insert("connection_sessions".to_owned(), result.rows_affected());
+
+            let result = sqlx::query!("DELETE FROM file_transfers WHERE user_id = ?", user.id)
+                .execute(&mut tx)
+                .await?;
+            records.insert("file_transfers".to_owned(), result.rows_affected());
+
+            let result = sqlx::query!("DELETE FROM emergency_access WHERE user_id = ?", user.id)
+                .execute(&mut tx)
+                .await?;
+            records.insert("emergency_access".to_owned(), result.rows_affected());
+        } else {
+            let result = sqlx::query!("UPDATE file_transfers SET file_path = '[erased]' WHERE user_id = ?", user.id)
+                .execute(&mut tx)
+                .await?;
+            records.insert("file_transfers".to_owned(), result.rows_affected());
+
+            let result = sqlx::query!(
+                r#"
+                UPDATE emergency_access SET username = ?, ip_address = '0.0.0.0', justification = '[erased]'
+                WHERE user_id = ?
+                "#,
+                pseudonym,
+                user.id
+            )
+            .execute(&mut tx)
+            .await?;
+            records.insert("emergency_access".to_owned(), result.rows_affected());
+        }
+
+        // 用户记录被外键引用，保留为停用的空壳
+        let result = sqlx::query!(
+            r#"
+            UPDATE users SET username = ?, email = NULL, password_hash = '', groups = '[]', enabled = 0,
+                last_login = NULL, locked_until = NULL, two_factor_enabled = 0, two_factor_secret = NULL
+            WHERE id = ?
+            "#,
+            pseudonym,
+            user.id
+        )
+        .execute(&mut tx)
+        .await?;
+        records.insert("users".to_owned(), result.rows_affected());
+
+        tx.commit().await?;
+        Ok(records)
+    }
+
+    // GDPR 擦除设备，在一个事务中完成；设备身份信息直接删除，活动记录按 purge 删除或替换为化名
+    pub async fn erase_device(&self, device_id: &str, pseudonym: &str, purge: bool) -> ResultType<BTreeMap<String, u64>> {
+        let mut conn = self.pool.get().await?;
+        let mut tx = conn.deref_mut().begin().await?;
+        let mut records = BTreeMap::new();
+        let quoted_id = format!("\"{}\"", device_id);
+        let quoted_pseudonym = format!("\"{}\"", pseudonym);
+
+        let result = sqlx::query!("UPDATE audit_logs SET device_id = ? WHERE device_id = ?", pseudonym, device_id)
+            .execute(&mut tx)
+            .await?;
+        records.insert("audit_logs".to_owned(), result.rows_affected());
+
+        let result = sqlx::query!(
+            "UPDATE audit_logs SET details = replace(details, ?, ?) WHERE instr(details, ?) > 0",
+            device_id,
+            pseudonym,
+            device_id
+        )
+        .execute(&mut tx)
+        .await?;
+        records.insert("audit_log_details".to_owned(), result.rows_affected());
+
+        let result = sqlx::query!("UPDATE security_events SET device_id = ? WHERE device_id = ?", pseudonym, device_id)
+            .execute(&mut tx)
+            .await?;
+        records.insert("security_events".to_owned(), result.rows_affected());
+
+        let result = sqlx::query!("DELETE FROM devices WHERE id = ?", device_id)
+            .execute(&mut tx)
+            .await?;
+        records.insert("devices".to_owned(), result.rows_affected());
+
+        let result = sqlx::query!("DELETE FROM device_inventory WHERE device_id = ?", device_id)
+            .execute(&mut tx)
+            .await?;
+        records.insert("device_inventory".to_owned(), result.rows_affected());
+
+        let result = sqlx::query!("DELETE FROM device_aliases WHERE device_id = ?", device_id)
+            .execute(&mut tx)
+            .await?;
+        records.insert("device_aliases".to_owned(), result.rows_affected());
+
+        let result = sqlx::query!(
+            "DELETE FROM custom_field_values WHERE entity = 'device' AND entity_id = ?",
+            device_id
+        )
+        .execute(&mut tx)
+        .await?;
+        records.insert("custom_field_values".to_owned(), result.rows_affected());
+
+        let result = sqlx::query!("DELETE FROM device_bans WHERE device_id = ?", device_id)
+            .execute(&mut tx)
+            .await?;
+        records.insert("device_bans".to_owned(), result.rows_affected());
+
+        let result = sqlx::query!("DELETE FROM peer WHERE id = ?", device_id)
+            .execute(&mut tx)
+            .await?;
+        records.insert("peer".to_owned(), result.rows_affected());
+
+        // 从设备组和策略中移除，历史任务和消息的目标替换为化名
+        let result = sqlx::query!(
+            r#"
+            UPDATE device_groups SET devices = (
+                SELECT json_group_array(value) FROM json_each(device_groups.devices) WHERE value != ?
+            )
+            WHERE EXISTS (SELECT 1 FROM json_each(device_groups.devices) WHERE value = ?)
+            "#,
+            device_id,
+            device_id
+        )
+        .execute(&mut tx)
+        .await?;
+        records.insert("device_groups".to_owned(), result.rows_affected());
+
+        let result = sqlx::query!(
+            r#"
+            UPDATE strategies SET device_ids = (
+                SELECT json_group_array(value) FROM json_each(strategies.device_ids) WHERE value != ?
+            )
+            WHERE EXISTS (SELECT 1 FROM json_each(strategies.device_ids) WHERE value = ?)
+            "#,
+            device_id,
+            device_id
+        )
+        .execute(&mut tx)
+        .await?;
+        records.insert("strategies".to_owned(), result.rows_affected());
+
+        let result = sqlx::query!(
+            "UPDATE jobs SET device_ids = replace(device_ids, ?, ?) WHERE instr(device_ids, ?) > 0",
+            quoted_id,
+            quoted_pseudonym,
+            quoted_id
+        )
+        .execute(&mut tx)
+        .await?;
+        records.insert("jobs".to_owned(), result.rows_affected());
+
+        let result = sqlx::query!(
+            "UPDATE device_messages SET device_ids = replace(device_ids, ?, ?) WHERE instr(device_ids, ?) > 0",
+            quoted_id,
+            quoted_pseudonym,
+            quoted_id
+        )
+        .execute(&mut tx)
+        .await?;
+        records.insert("device_messages".to_owned(), result.rows_affected());
+
+        // 编码推荐只用于会话期间的调整，直接删除
+        let result = sqlx::query!("DELETE FROM codec_recommendations WHERE device_id = ?", device_id)
+            .execute(&mut tx)
+            .await?;
+        records.insert("codec_recommendations".to_owned(), result.rows_affected());
+
+        // 备注为自由文本，可能含有设备使用者的信息，直接删除
+        let result = sqlx::query!("DELETE FROM device_notes WHERE device_id = ?", device_id)
+            .execute(&mut tx)
+            .await?;
+        records.insert("device_notes".to_owned(), result.rows_affected());
+
+        let result = sqlx::query!("DELETE FROM recent_connections WHERE device_id = ?", device_id)
+            .execute(&mut tx)
+            .await?;
+        records.insert("recent_connections".to_owned(), result.rows_affected());
+
+        let result = sqlx::query!("DELETE FROM favorite_devices WHERE device_id = ?", device_id)
+            .execute(&mut tx)
+            .await?;
+        records.insert("favorite_devices".to_owned(), result.rows_affected());
+
+        if purge {
+            let result = sqlx::query!("DELETE FROM connection_sessions WHERE controlled_device_id = ?", device_id)
+                .execute(&mut tx)
+                .await?;
+            records.insert("connection_sessions".to_owned(), result.rows_affected());
+
+            let result = sqlx::query!("DELETE FROM session_events WHERE device_id = ?", device_id)
+                .execute(&mut tx)
+                .await?;
+            records.insert("session_events".to_owned(), result.rows_affected());
+
+            let result = sqlx::query!("DELETE FROM session_qos WHERE device_id = ?", device_id)
+                .execute(&mut tx)
+                .await?;
+            records.insert("session_qos".to_owned(), result.rows_affected());
+
+            let result = sqlx::query!("DELETE FROM file_transfers WHERE device_id = ?", device_id)
+                .execute(&mut tx)
+                .await?;
+            records.insert("file_transfers".to_owned(), result.rows_affected());
+
+            let result = sqlx::query!(
+                "DELETE FROM key_exchanges WHERE device_id = ? OR requester = ?",
+                device_id,
+                device_id
+            )
+            .execute(&mut tx)
+            .await?;
+            records.insert("key_exchanges".to_owned(), result.rows_affected());
+
+            let result = sqlx::query!("DELETE FROM job_runs WHERE device_id = ?", device_id)
+                .execute(&mut tx)
+                .await?;
+            records.insert("job_runs".to_owned(), result.rows_affected());
+
+            let result = sqlx::query!("DELETE FROM message_deliveries WHERE device_id = ?", device_id)
+                .execute(&mut tx)
+                .await?;
+            records.insert("message_deliveries".to_owned(), result.rows_affected());
+
+            let result = sqlx::query!("DELETE FROM emergency_access WHERE device_id = ?", device_id)
+                .execute(&mut tx)
+                .await?;
+            records.insert("emergency_access".to_owned(), result.rows_affected());
+
+            let result = sqlx::query!("DELETE FROM device_status_history WHERE device_id = ?", device_id)
+                .execute(&mut tx)
+                .await?;
+            records.insert("device_status_history".to_owned(), result.rows_affected());
+
+            let result = sqlx::query!("DELETE FROM device_pk_history WHERE device_id = ?", device_id)
+                .execute(&mut tx)
+                .await?;
+            records.insert("device_pk_history".to_owned(), result.rows_affected());
+        } else {
+            let result = sqlx::query!(
+                "UPDATE connection_sessions SET controlled_device_id = ? WHERE controlled_device_id = ?",
+                pseudonym,
+                device_id
+            )
+            .execute(&mut tx)
+            .await?;
+            records.insert("connection_sessions".to_owned(), result.rows_affected());
+
+            let result = sqlx::query!("UPDATE session_events SET device_id = ? WHERE device_id = ?", pseudonym, device_id)
+                .execute(&mut tx)
+                .await?;
+            records.insert("session_events".to_owned(), result.rows_affected());
+
+            let result = sqlx::query!("UPDATE session_qos SET device_id = ? WHERE device_id = ?", pseudonym, device_id)
+                .execute(&mut tx)
+                .await?;
+            records.insert("session_qos".to_owned(), result.rows_affected());
+
+            let result = sqlx::query!("UPDATE file_transfers SET device_id = ? WHERE device_id = ?", pseudonym, device_id)
+                .execute(&mut tx)
+                .await?;
+            records.insert("file_transfers".to_owned(), result.rows_affected());
+
+            let result = sqlx::query!(
+                r#"
+                UPDATE key_exchanges SET
+                    device_id = CASE WHEN device_id = ? THEN ? ELSE device_id END,
+                    requester = CASE WHEN requester = ? THEN ? ELSE requester END
+                WHERE device_id = ? OR requester = ?
+                "#,
+                device_id,
+                pseudonym,
+                device_id,
+                pseudonym,
+                device_id,
+                device_id
+            )
+            .execute(&mut tx)
+            .await?;
+            records.insert("key_exchanges".to_owned(), result.rows_affected());
+
+            let result = sqlx::query!("UPDATE job_runs SET device_id = ? WHERE device_id = ?", pseudonym, device_id)
+                .execute(&mut tx)
+                .await?;
+            records.insert("job_runs".to_owned(), result.rows_affected());
+
+            let result = sqlx::query!(
+                "UPDATE message_deliveries SET device_id = ? WHERE device_id = ?",
+                pseudonym,
+                device_id
+            )
+            .execute(&mut tx)
+            .await?;
+            records.insert("message_deliveries".to_owned(), result.rows_affected());
+
+            let result = sqlx::query!("UPDATE emergency_access SET device_id = ? WHERE device_id = ?", pseudonym, device_id)
+                .execute(&mut tx)
+                .await?;
+            records.insert("emergency_access".to_owned(), result.rows_affected());
+
+            let result = sqlx::query!(
+                "UPDATE device_status_history SET device_id = ? WHERE device_id = ?",
+                pseudonym,
+                device_id
+            )
+            .execute(&mut tx)
+            .await?;
+            records.insert("device_status_history".to_owned(), result.rows_affected());
+
+            // 公钥历史用于取证，保留记录，来源IP属于个人信息一并清除
+            let result = sqlx::query!(
+                "UPDATE device_pk_history SET device_id = ?, ip_address = '' WHERE device_id = ?",
+                pseudonym,
+                device_id
+            )
+            .execute(&mut tx)
+            .await?;
+            records.insert("device_pk_history".to_owned(), result.rows_affected());
+        }
+
+        tx.commit().await?;
+        Ok(records)
+    }
+
+    pub async fn save_erasure_report(&self, report: &ErasureReport) -> ResultType<()> {
+        let mut conn = self.pool.get().await?;
+        let subject = serde_json::to_value(report.subject)?;
+        let subject = subject.as_str().unwrap_or_default();
+        let mode = serde_json::to_value(report.mode)?;
+        let mode = mode.as_str().unwrap_or_default();
+        let created_at = report.created_at as i64;
+        let records = serde_json::to_string(&report.records)?;
+
+        sqlx::query!(
+            r#"
+            INSERT INTO erasure_reports (id, subject, subject_hash, pseudonym, mode, reason, requested_by, created_at, records)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)
+            "#,
+            report.id,
+            subject,
+            report.subject_hash,
+            report.pseudonym,
+            mode,
+            report.reason,
+            report.requested_by,
+            created_at,
+            records
+        )
+        .execute(conn.deref_mut())
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn get_erasure_report(&self, id: &str) -> ResultType<Option<ErasureReport>> {
+        let mut conn = self.pool.get().await?;
+
+        let row = sqlx::query_as!(
+            ErasureReportRow,
+            r#"
+            SELECT id, subject, subject_hash, pseudonym, mode, reason, requested_by, created_at, records
+            FROM erasure_reports WHERE id = ?
+            "#,
+            id
+        )
+        .fetch_optional(conn.deref_mut())
+        .await?;
+
+        row.map(ErasureReport::try_from).transpose()
+    }
+
+    // subject_hash 为空时列出全部报告
+    pub async fn list_erasure_reports(
+        &self,
+        subject_hash: Option<&str>,
+        limit: i64,
+        offset: i64,
+    ) -> ResultType<Vec<ErasureReport>> {
+        let mut conn = self.pool.get().await?;
+
+        let rows = sqlx::query_as!(
+            ErasureReportRow,
+            r#"
+            SELECT id, subject, subject_hash, pseudonym, mode, reason, requested_by, created_at, records
+            FROM erasure_reports
+            WHERE (?1 IS NULL OR subject_hash = ?1)
+            ORDER BY created_at DESC LIMIT ?2 OFFSET ?3
+            "#,
+            subject_hash,
+            limit,
+            offset
+        )
+        .fetch_all(conn.deref_mut())
+        .await?;
+
+        rows.into_iter().map(ErasureReport::try_from).collect()
+    }
+
+    // 同一出口 IP 下登记过的设备的系统描述
+    pub async fn list_device_os_by_ip(&self, ip: &str) -> ResultType<Vec<String>> {
+        let mut conn = self.pool.get().await?;
+
+        let rows = sqlx::query!(
+            "SELECT os FROM devices WHERE (ip_address = ?1 OR ipv6_address = ?1) AND os != ''",
+            ip
+        )
+        .fetch_all(conn.deref_mut())
+        .await?;
+
+        Ok(rows.into_iter().map(|row| row.os).collect())
+    }
+
+    // 远程任务方法
+    pub async fn save_job(&self, job: &Job) -> ResultType<()> {
+        let mut conn = self.pool.get().await?;
+        let shell = serde_json::to_value(job.shell)?.as_str().unwrap_or_default().to_owned();
+        let group_ids = serde_json::to_string(&job.group_ids)?;
+        let device_ids = serde_json::to_string(&job.device_ids)?;
+        let timeout_secs = job.timeout_secs as i64;
+        let created_at = job.created_at as i64;
+        let expires_at = job.expires_at as i64;
+
+        sqlx::query!(
+            r#"
+            INSERT INTO jobs (id, name, command, shell, group_ids, device_ids, timeout_secs, created_by, created_at, expires_at, cancelled)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+            "#,
+            job.id,
+            job.name,
+            job.command,
+            shell,
+            group_ids,
+            device_ids,
+            timeout_secs,
+            job.created_by,
+            created_at,
+            expires_at,
+            job.cancelled
+        )
+        .execute(conn.deref_mut())
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn cancel_job(&self, id: &str) -> ResultType<bool> {
+        let mut conn = self.pool.get().await?;
+
+        let result = sqlx::query!("UPDATE jobs SET cancelled = 1 WHERE id = ? AND cancelled = 0", id)
+            .execute(conn.deref_mut())
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn get_job(&self, id: &str) -> ResultType<Option<Job>> {
+        let mut conn = self.pool.get().await?;
+
+        let row = sqlx::query_as!(
+            JobRow,
+            "SELECT id, name, command, shell, group_ids, device_ids, timeout_secs, created_by, created_at, expires_at, cancelled FROM jobs WHERE id = ?",
+            id
+        )
+        .fetch_optional(conn.deref_mut())
+        .await?;
+
+        row.map(Job::try_from).transpose()
+    }
+
+    pub async fn list_jobs(&self, limit: i64, offset: i64) -> ResultType<Vec<Job>> {
+        let mut conn = self.pool.get().await?;
+
+        let rows = sqlx::query_as!(
+            JobRow,
+            "SELECT id, name, command, shell, group_ids, device_ids, timeout_secs, created_by, created_at, expires_at, cancelled FROM jobs ORDER BY created_at DESC LIMIT ? OFFSET ?",
+            limit,
+            offset
+        )
+        .fetch_all(conn.deref_mut())
+        .await?;
+
+        rows.into_iter().map(Job::try_from).collect()
+    }
+
+    // 未取消且未过期、可以下发的任务
+    pub async fn list_active_jobs(&self, now: u64) -> ResultType<Vec<Job>> {
+        let mut conn = self.pool.get().await?;
+        let now = now as i64;
+
+        let rows = sqlx::query_as!(
+            JobRow,
+            "SELECT id, name, command, shell, group_ids, device_ids, timeout_secs, created_by, created_at, expires_at, cancelled FROM jobs WHERE cancelled = 0 AND expires_at > ?",
+            now
+        )
+        .fetch_all(conn.deref_mut())
+        .await?;
+
+        rows.into_iter().map(Job::try_from).collect()
+    }
+
+    // 登记任务下发，已下发过的设备返回 false
+    pub async fn create_job_run(&self, job_id: &str, device_id: &str, now: u64, deadline: u64) -> ResultType<bool> {
+        let mut conn = self.pool.get().await?;
+        let status = format!("{:?}", JobRunStatus::Dispatched);
+        let now = now as i64;
+        let deadline = deadline as i64;
+
+        let result = sqlx::query!(
+            "INSERT OR IGNORE INTO job_runs (job_id, device_id, status, dispatched_at, deadline) VALUES (?, ?, ?, ?, ?)",
+            job_id,
+            device_id,
+            status,
+            now,
+            deadline
+        )
+        .execute(conn.deref_mut())
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    // 记录执行结果，只更新仍处于已下发状态的记录
+    pub async fn finish_job_run(
+        &self,
+        job_id: &str,
+        device_id: &str,
+        status: JobRunStatus,
+        exit_code: Option<i32>,
+        output: &str,
+    ) -> ResultType<bool> {
+        let mut conn = self.pool.get().await?;
+        let status = format!("{:?}", status);
+        let dispatched = format!("{:?}", JobRunStatus::Dispatched);
+        let now = SystemTime::now().duration_since(std::time::UNIX_EPOCH)?.as_secs() as i64;
+
+        let result = sqlx::query!(
+            "UPDATE job_runs SET status = ?, exit_code = ?, output = ?, finished_at = ? WHERE job_id = ? AND device_id = ? AND status = ?",
+            status,
+            exit_code,
+            output,
+            now,
+            job_id,
+            device_id,
+            dispatched
+        )
+        .execute(conn.deref_mut())
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    // 超过期限仍未上报结果的执行记为超时
+    pub async fn expire_job_runs(&self, now: u64) -> ResultType<u64> {
+        let mut conn = self.pool.get().await?;
+        let timed_out = format!("{:?}", JobRunStatus::TimedOut);
+        let dispatched = format!("{:?}", JobRunStatus::Dispatched);
+        let now = now as i64;
+
+        let result = sqlx::query!(
+            "UPDATE job_runs SET status = ?, finished_at = ? WHERE status = ? AND deadline < ?",
+            timed_out,
+            now,
+            dispatched,
+            now
+        )
+        .execute(conn.deref_mut())
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    pub async fn list_job_runs(&self, job_id: &str) -> ResultType<Vec<JobRun>> {
+        let mut conn = self.pool.get().await?;
+
+        let rows = sqlx::query!(
+            "SELECT job_id, device_id, status, exit_code, output, dispatched_at, finished_at FROM job_runs WHERE job_id = ? ORDER BY dispatched_at",
+            job_id
+        )
+        .fetch_all(conn.deref_mut())
+        .await?;
+
+        let mut runs = Vec::with_capacity(rows.len());
+        for row in rows {
+            runs.push(JobRun {
+                job_id: row.job_id,
+                device_id: row.device_id,
+                status: serde_json::from_value(serde_json::Value::String(row.status))?,
+                exit_code: row.exit_code.map(|x| x as i32),
+                output: row.output,
+                dispatched_at: row.dispatched_at as u64,
+                finished_at: row.finished_at.map(|x| x as u64),
+            });
+        }
+        Ok(runs)
+    }
+
+    // 设备消息方法
+    pub async fn save_message(&self, message: &DeviceMessage) -> ResultType<()> {
+        let mut conn = self.pool.get().await?;
+        let level = serde_json::to_value(message.level)?.as_str().unwrap_or_default().to_owned();
+        let group_ids = serde_json::to_string(&message.group_ids)?;
+        let device_ids = serde_json::to_string(&message.device_ids)?;
+        let created_at = message.created_at as i64;
+        let expires_at = message.expires_at as i64;
+
+        sqlx::query!(
+            r#"
+            INSERT INTO device_messages (id, title, body, level, group_ids, device_ids, created_by, created_at, expires_at, cancelled)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+            "#,
+            message.id,
+            message.title,
+            message.body,
+            level,
+            group_ids,
+            device_ids,
+            message.created_by,
+            created_at,
+            expires_at,
+            message.cancelled
+        )
+        .execute(conn.deref_mut())
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn cancel_message(&self, id: &str) -> ResultType<bool> {
+        let mut conn = self.pool.get().await?;
+
+        let result = sqlx::query!("UPDATE device_messages SET cancelled = 1 WHERE id = ? AND cancelled = 0", id)
+            .execute(conn.deref_mut())
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn get_message(&self, id: &str) -> ResultType<Option<DeviceMessage>> {
+        let mut conn = self.pool.get().await?;
+
+        let row = sqlx::query_as!(
+            DeviceMessageRow,
+            "SELECT id, title, body, level, group_ids, device_ids, created_by, created_at, expires_at, cancelled FROM device_messages WHERE id = ?",
+            id
+        )
+        .fetch_optional(conn.deref_mut())
+        .await?;
+
+        row.map(DeviceMessage::try_from).transpose()
+    }
+
+    pub async fn list_messages(&self, limit: i64, offset: i64) -> ResultType<Vec<DeviceMessage>> {
+        let mut conn = self.pool.get().await?;
+
+        let rows = sqlx::query_as!(
+            DeviceMessageRow,
+            "SELECT id, title, body, level, group_ids, device_ids, created_by, created_at, expires_at, cancelled FROM device_messages ORDER BY created_at DESC LIMIT ? OFFSET ?",
+            limit,
+            offset
+        )
+        .fetch_all(conn.deref_mut())
+        .await?;
+
+        rows.into_iter().map(DeviceMessage::try_from).collect()
+    }
+
+    // 未取消且未过期、可以下发的消息
+    pub async fn list_active_messages(&self, now: u64) -> ResultType<Vec<DeviceMessage>> {
+        let mut conn = self.pool.get().await?;
+        let now = now as i64;
+
+        let rows = sqlx::query_as!(
+            DeviceMessageRow,
+            "SELECT id, title, body, level, group_ids, device_ids, created_by, created_at, expires_at, cancelled FROM device_messages WHERE cancelled = 0 AND expires_at > ?",
+            now
+        )
+        .fetch_all(conn.deref_mut())
+        .await?;
+
+        rows.into_iter().map(DeviceMessage::try_from).collect()
+    }
+
+    // 登记消息送达，已送达过的设备返回 false
+    pub async fn create_message_delivery(&self, message_id: &str, device_id: &str, now: u64) -> ResultType<bool> {
+        let mut conn = self.pool.get().await?;
+        let status = format!("{:?}", DeliveryStatus::Delivered);
+        let now = now as i64;
+
+        let result = sqlx::query!(
+            "INSERT OR IGNORE INTO message_deliveries (message_id, device_id, status, delivered_at) VALUES (?, ?, ?, ?)",
+            message_id,
+            device_id,
+            status,
+            now
+        )
+        .execute(conn.deref_mut())
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    // 记录设备确认，重复确认时保留第一次的时间
+    pub async fn ack_message_delivery(&self, message_id: &str, device_id: &str, now: u64) -> ResultType<bool> {
+        let mut conn = self.pool.get().await?;
+        let acknowledged = format!("{:?}", DeliveryStatus::Acknowledged);
+        let now = now as i64;
+
+        let result = sqlx::query!(
+            r#"
+            UPDATE message_deliveries SET status = ?, acknowledged_at = COALESCE(acknowledged_at, ?)
+            WHERE message_id = ? AND device_id = ?
+            "#,
+            acknowledged,
+            now,
+            message_id,
+            device_id
+        )
+        .execute(conn.deref_mut())
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn list_message_deliveries(&self, message_id: &str) -> ResultType<Vec<MessageDelivery>> {
+        let mut conn = self.pool.get().await?;
+
+        let rows = sqlx::query!(
+            "SELECT message_id, device_id, status, delivered_at, acknowledged_at FROM message_deliveries WHERE message_id = ? ORDER BY delivered_at",
+            message_id
+        )
+        .fetch_all(conn.deref_mut())
+        .await?;
+
+        let mut deliveries = Vec::with_capacity(rows.len());
+        for row in rows {
+            deliveries.push(MessageDelivery {
+                message_id: row.message_id,
+                device_id: row.device_id,
+                status: serde_json::from_value(serde_json::Value::String(row.status))?,
+                delivered_at: row.delivered_at as u64,
+                acknowledged_at: row.acknowledged_at.map(|x| x as u64),
+            });
+        }
+        Ok(deliveries)
+    }
+
+    // 连接会话方法，同一会话重复登记时保留第一次的记录
+    pub async fn start_connection_session(
+        &self,
+        id: &str,
+        controller_id: &str,
+        device_id: &str,
+        connection_type: &str,
+    ) -> ResultType<bool> {
+        let mut conn = self.pool.get().await?;
+        let now = SystemTime::now().duration_since(std::time::UNIX_EPOCH)?.as_secs() as i64;
+
+        let result = sqlx::query!(
+            r#"
+            INSERT OR IGNORE INTO connection_sessions (id, controller_id, controlled_device_id, start_time, connection_type)
+            VALUES (?, ?, ?, ?, ?)
+            "#,
+            id,
+            controller_id,
+            device_id,
+            now,
+            connection_type
+        )
+        .execute(conn.deref_mut())
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn get_connection_session(&self, id: &str) -> ResultType<Option<ConnectionSession>> {
+        let mut conn = self.pool.get().await?;
+
         let row = sqlx::query!(
-            "SELECT * FROM users WHERE username = ?",
-            username
+            r#"
+            SELECT id, controller_id, controlled_device_id, start_time, end_time, duration_seconds,
+                   bytes_transferred, connection_type, quality_score
+            FROM connection_sessions WHERE id = ?
+            "#,
+            id
         )
         .fetch_optional(conn.deref_mut())
         .await?;
 
-        if let Some(row) = row {
-            let role = match row.role.as_str() {
-                "SuperAdmin" => UserRole::SuperAdmin,
-                "Admin" => UserRole::Admin,
-                "User" => UserRole::User,
-                "ReadOnly" => UserRole::ReadOnly,
-                _ => UserRole::User,
-            };
+        Ok(row.map(|row| ConnectionSession {
+            id: row.id,
+            controller_id: row.controller_id,
+            controlled_device_id: row.controlled_device_id,
+            start_time: std::time::UNIX_EPOCH + std::time::Duration::from_secs(row.start_time as u64),
+            end_time: row
+                .end_time
+                .map(|t| std::time::UNIX_EPOCH + std::time::Duration::from_secs(t as u64)),
+            duration_seconds: row.duration_seconds,
+            bytes_transferred: row.bytes_transferred,
+            connection_type: row.connection_type,
+            quality_score: row.quality_score.map(|x| x as f32),
+        }))
+    }
 
-            let groups: Vec<String> = serde_json::from_str(&row.groups).unwrap_or_default();
-            let created_at = std::time::UNIX_EPOCH + std::time::Duration::from_secs(row.created_at as u64);
-            let last_login = row.last_login.map(|ts| std::time::UNIX_EPOCH + std::time::Duration::from_secs(ts as u64));
-            let locked_until = row.locked_until.map(|ts| std::time::UNIX_EPOCH + std::time::Duration::from_secs(ts as u64));
+    // 设备作为被控端在 [since, until] 内开始的会话，按开始时间倒序
+    pub async fn list_device_connection_sessions(
+        &self,
+        device_id: &str,
+        since: u64,
+        until: u64,
+        limit: i64,
+    ) -> ResultType<Vec<ConnectionSession>> {
+        let mut conn = self.pool.get().await?;
+        let since = since as i64;
+        let until = until.min(i64::MAX as u64) as i64;
 
-            Ok(Some(User {
+        let rows = sqlx::query!(
+            r#"
+            SELECT id, controller_id, controlled_device_id, start_time, end_time, duration_seconds,
+                   bytes_transferred, connection_type, quality_score
+            FROM connection_sessions
+            WHERE controlled_device_id = ? AND start_time >= ? AND start_time <= ?
+            ORDER BY start_time DESC LIMIT ?
+            "#,
+            device_id,
+            since,
+            until,
+            limit
+        )
+        .fetch_all(conn.deref_mut())
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| ConnectionSession {
                 id: row.id,
-                username: row.username,
-                password_hash: row.password_hash,
-                email: row.email,
-                role,
-                groups,
-                enabled: row.enabled,
-                created_at,
-                last_login,
-                failed_login_attempts: row.failed_login_attempts as u32,
-                locked_until,
-                two_factor_enabled: row.two_factor_enabled,
-                two_factor_secret: row.two_factor_secret,
-            }))
-        } else {
-            Ok(None)
-        }
+                controller_id: row.controller_id,
+                controlled_device_id: row.controlled_device_id,
+                start_time: std::time::UNIX_EPOCH + std::time::Duration::from_secs(row.start_time as u64),
+                end_time: row
+                    .end_time
+                    .map(|t| std::time::UNIX_EPOCH + std::time::Duration::from_secs(t as u64)),
+                duration_seconds: row.duration_seconds,
+                bytes_transferred: row.bytes_transferred,
+                connection_type: row.connection_type,
+                quality_score: row.quality_score.map(|x| x as f32),
+            })
+            .collect())
     }
 
-    pub async fn update_user_login_info(&self, user_id: &str, success: bool) -> ResultType<()> {
+    // 会话内操作事件方法
+    pub async fn save_session_events(&self, events: &[SessionEvent]) -> ResultType<usize> {
         let mut conn = self.pool.get().await?;
-        let now = SystemTime::now().duration_since(std::time::UNIX_EPOCH)?.as_secs() as i64;
 
-        if success {
+        for event in events {
+            let event_type = serde_json::to_value(event.event_type)?.as_str().unwrap_or_default().to_owned();
+            let occurred_at = event.occurred_at as i64;
+            let received_at = event.received_at as i64;
             sqlx::query!(
-                "UPDATE users SET last_login = ?, failed_login_attempts = 0, locked_until = NULL WHERE id = ?",
-                now,
-                user_id
+                r#"
+                INSERT INTO session_events (session_id, device_id, event_type, details, occurred_at, received_at)
+                VALUES (?, ?, ?, ?, ?, ?)
+                "#,
+                event.session_id,
+                event.device_id,
+                event_type,
+                event.details,
+                occurred_at,
+                received_at
             )
             .execute(conn.deref_mut())
             .await?;
-        } else {
-            sqlx::query!(
-                "UPDATE users SET failed_login_attempts = failed_login_attempts + 1 WHERE id = ?",
-                user_id
+        }
+
+        Ok(events.len())
+    }
+
+    pub async fn list_session_events(&self, session_id: &str, limit: i64, offset: i64) -> ResultType<Vec<SessionEvent>> {
+        let mut conn = self.pool.get().await?;
+
+        let rows = sqlx::query!(
+            r#"
+            SELECT id, session_id, device_id, event_type, details, occurred_at, received_at
+            FROM session_events WHERE session_id = ?
+            ORDER BY occurred_at, id LIMIT ? OFFSET ?
+            "#,
+            session_id,
+            limit,
+            offset
+        )
+        .fetch_all(conn.deref_mut())
+        .await?;
+
+        let mut events = Vec::with_capacity(rows.len());
+        for row in rows {
+            events.push(SessionEvent {
+                id: row.id,
+                session_id: row.session_id,
+                device_id: row.device_id,
+                event_type: serde_json::from_value(serde_json::Value::String(row.event_type))?,
+                details: row.details,
+                occurred_at: row.occurred_at as u64,
+                received_at: row.received_at as u64,
+            });
+        }
+        Ok(events)
+    }
+
+    // 预登记ID方法
+    pub async fn add_provisioned_ids(&self, ids: &[String], note: Option<&str>, created_by: &str) -> ResultType<usize> {
+        let mut conn = self.pool.get().await?;
+        let now = SystemTime::now().duration_since(std::time::UNIX_EPOCH)?.as_secs() as i64;
+
+        let mut added = 0;
+        for id in ids {
+            let result = sqlx::query!(
+                "INSERT OR IGNORE INTO provisioned_ids (id, note, created_by, created_at) VALUES (?, ?, ?, ?)",
+                id,
+                note,
+                created_by,
+                now
             )
             .execute(conn.deref_mut())
             .await?;
+            added += result.rows_affected() as usize;
         }
 
-        Ok(())
+        Ok(added)
     }
 
-    // 审计日志方法
-    pub async fn log_audit(&self, log: &AuditLog) -> ResultType<()> {
+    pub async fn remove_provisioned_id(&self, id: &str) -> ResultType<bool> {
         let mut conn = self.pool.get().await?;
-        let timestamp = log.timestamp.duration_since(std::time::UNIX_EPOCH)?.as_secs() as i64;
+
+        let result = sqlx::query!("DELETE FROM provisioned_ids WHERE id = ?", id)
+            .execute(conn.deref_mut())
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn is_id_provisioned(&self, id: &str) -> ResultType<bool> {
+        let mut conn = self.pool.get().await?;
+
+        let row = sqlx::query!("SELECT id FROM provisioned_ids WHERE id = ?", id)
+            .fetch_optional(conn.deref_mut())
+            .await?;
+
+        Ok(row.is_some())
+    }
+
+    pub async fn list_provisioned_ids(&self, limit: i64, offset: i64) -> ResultType<Vec<ProvisionedId>> {
+        let mut conn = self.pool.get().await?;
+
+        let rows = sqlx::query!(
+            "SELECT * FROM provisioned_ids ORDER BY id LIMIT ? OFFSET ?",
+            limit,
+            offset
+        )
+        .fetch_all(conn.deref_mut())
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| ProvisionedId {
+                id: row.id,
+                note: row.note,
+                created_by: row.created_by,
+                created_at: row.created_at as u64,
+            })
+            .collect())
+    }
+
+    // 系统设置方法
+    pub async fn get_setting(&self, key: &str) -> ResultType<Option<String>> {
+        let mut conn = self.pool.get().await?;
+
+        let row = sqlx::query!("SELECT value FROM settings WHERE key = ?", key)
+            .fetch_optional(conn.deref_mut())
+            .await?;
+
+        Ok(row.map(|row| row.value))
+    }
+
+    pub async fn get_setting_with_time(&self, key: &str) -> ResultType<Option<(String, u64)>> {
+        let mut conn = self.pool.get().await?;
+
+        let row = sqlx::query!("SELECT value, updated_at FROM settings WHERE key = ?", key)
+            .fetch_optional(conn.deref_mut())
+            .await?;
+
+        Ok(row.map(|row| (row.value, row.updated_at as u64)))
+    }
+
+    // 策略设置同时记录一个配置版本
+    pub async fn set_setting(&self, key: &str, value: &str, updated_by: Option<&str>) -> ResultType<()> {
+        let mut conn = self.pool.get().await?;
+        let mut tx = conn.deref_mut().begin().await?;
+        let now = SystemTime::now().duration_since(std::time::UNIX_EPOCH)?.as_secs() as i64;
 
         sqlx::query!(
             r#"
-            INSERT INTO audit_logs (
-                user_id, device_id, action, details, ip_address, 
-                user_agent, timestamp, success
-            ) VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+            INSERT INTO settings (key, value, updated_by, updated_at) VALUES (?, ?, ?, ?)
+            ON CONFLICT(key) DO UPDATE SET
+                value = excluded.value,
+                updated_by = excluded.updated_by,
+                updated_at = excluded.updated_at
             "#,
-            log.user_id,
-            log.device_id,
-            log.action,
-            log.details,
-            log.ip_address,
-            log.user_agent,
-            timestamp,
-            log.success
+            key,
+            value,
+            updated_by,
+            now
         )
-        .execute(conn.deref_mut())
+        .execute(&mut tx)
         .await?;
 
+        if server_config::POLICY_KEYS.contains(&key) {
+            sqlx::query!(
+                "INSERT INTO setting_versions (key, value, updated_by, created_at) VALUES (?, ?, ?, ?)",
+                key,
+                value,
+                updated_by,
+                now
+            )
+            .execute(&mut tx)
+            .await?;
+        }
+
+        tx.commit().await?;
         Ok(())
     }
 
-    pub async fn get_audit_logs(
+    // 配置版本方法
+    pub async fn latest_setting_version(&self) -> ResultType<Option<i64>> {
+        let mut conn = self.pool.get().await?;
+
+        let row = sqlx::query!(r#"SELECT MAX(id) AS "version: i64" FROM setting_versions"#)
+            .fetch_one(conn.deref_mut())
+            .await?;
+
+        Ok(row.version)
+    }
+
+    pub async fn list_setting_versions(
         &self,
-        user_id: Option<&str>,
-        device_id: Option<&str>,
+        key: Option<&str>,
         limit: i64,
         offset: i64,
-    ) -> ResultType<Vec<AuditLog>> {
+    ) -> ResultType<Vec<ConfigVersion>> {
         let mut conn = self.pool.get().await?;
-        
-        let rows = match (user_id, device_id) {
-            (Some(uid), Some(did)) => {
-                sqlx::query!(
-                    "SELECT * FROM audit_logs WHERE user_id = ? AND device_id = ? ORDER BY timestamp DESC LIMIT ? OFFSET ?",
-                    uid, did, limit, offset
-                )
-                .fetch_all(conn.deref_mut())
-                .await?
-            }
-            (Some(uid), None) => {
-                sqlx::query!(
-                    "SELECT * FROM audit_logs WHERE user_id = ? ORDER BY timestamp DESC LIMIT ? OFFSET ?",
-                    uid, limit, offset
-                )
-                .fetch_all(conn.deref_mut())
-                .await?
-            }
-            (None, Some(did)) => {
-                sqlx::query!(
-                    "SELECT * FROM audit_logs WHERE device_id = ? ORDER BY timestamp DESC LIMIT ? OFFSET ?",
-                    did, limit, offset
-                )
-                .fetch_all(conn.deref_mut())
-                .await?
-            }
-            (None, None) => {
-                sqlx::query!(
-                    "SELECT * FROM audit_logs ORDER BY timestamp DESC LIMIT ? OFFSET ?",
-                    limit, offset
-                )
-                .fetch_all(conn.deref_mut())
-                .await?
-            }
-        };
 
-        let mut logs = Vec::new();
-        for row in rows {
-            let timestamp = std::time::UNIX_EPOCH + std::time::Duration::from_secs(row.timestamp as u64);
-            logs.push(AuditLog {
-                id: row.id,
-                user_id: row.user_id,
-                device_id: row.device_id,
-                action: row.action,
-                details: row.details,
-                ip_address: row.ip_address,
-                user_agent: row.user_agent,
-                timestamp,
-                success: row.success,
-            });
-        }
+        let rows = sqlx::query!(
+            r#"
+            SELECT id, key, value IS NULL AS "deleted: bool", updated_by, created_at
+            FROM setting_versions
+            WHERE (? IS NULL OR key = ?)
+            ORDER BY id DESC LIMIT ? OFFSET ?
+            "#,
+            key,
+            key,
+            limit,
+            offset
+        )
+        .fetch_all(conn.deref_mut())
+        .await?;
 
-        Ok(logs)
+        Ok(rows
+            .into_iter()
+            .map(|row| ConfigVersion {
+                version: row.id,
+                key: row.key,
+                updated_by: row.updated_by,
+                created_at: row.created_at as u64,
+                deleted: row.deleted,
+            })
+            .collect())
     }
 
-    // 设备管理方法
-    pub async fn register_device(&self, device: &DeviceInfo) -> ResultType<()> {
+    // 某个配置版本时各策略的取值，当时未设置或已恢复默认的策略不包含在内
+    pub async fn setting_snapshot(&self, version: i64) -> ResultType<BTreeMap<String, String>> {
         let mut conn = self.pool.get().await?;
-        let last_online = device.last_online.duration_since(std::time::UNIX_EPOCH)?.as_secs() as i64;
-        let group_ids_json = serde_json::to_string(&device.group_ids)?;
-        let tags_json = serde_json::to_string(&device.tags)?;
 
-        sqlx::query!(
+        let rows = sqlx::query!(
             r#"
-            INSERT OR REPLACE INTO devices (
-                id, name, os, version, ip_address, mac_address,
-                last_online, owner_id, group_ids, enabled, tags
-            ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+            SELECT key, value FROM setting_versions
+            WHERE id IN (SELECT MAX(id) FROM setting_versions WHERE id <= ? GROUP BY key)
             "#,
-            device.id,
-            device.name,
-            device.os,
-            device.version,
-            device.ip_address,
-            device.mac_address,
-            last_online,
-            device.owner_id,
-            group_ids_json,
-            device.enabled,
-            tags_json
+            version
         )
-        .execute(conn.deref_mut())
+        .fetch_all(conn.deref_mut())
         .await?;
 
+        Ok(rows
+            .into_iter()
+            .filter_map(|row| row.value.map(|value| (row.key, value)))
+            .collect())
+    }
+
+    // 在一个事务中写回多个策略(None 为删除即恢复默认)并记录版本，返回最新版本号
+    pub async fn restore_settings(&self, settings: &[(String, Option<String>)], updated_by: &str) -> ResultType<i64> {
+        let mut conn = self.pool.get().await?;
+        let mut tx = conn.deref_mut().begin().await?;
+        let now = SystemTime::now().duration_since(std::time::UNIX_EPOCH)?.as_secs() as i64;
+        let mut latest = 0;
+
+        for (key, value) in settings.iter() {
+            match value {
+                Some(value) => {
+                    sqlx::query!(
+                        r#"
+                        INSERT INTO settings (key, value, updated_by, updated_at) VALUES (?, ?, ?, ?)
+                        ON CONFLICT(key) DO UPDATE SET
+                            value = excluded.value,
+                            updated_by = excluded.updated_by,
+                            updated_at = excluded.updated_at
+                        "#,
+                        key,
+                        value,
+                        updated_by,
+                        now
+                    )
+                    .execute(&mut tx)
+                    .await?;
+                }
+                None => {
+                    sqlx::query!("DELETE FROM settings WHERE key = ?", key)
+                        .execute(&mut tx)
+                        .await?;
+                }
+            }
+            let result = sqlx::query!(
+                "INSERT INTO setting_versions (key, value, updated_by, created_at) VALUES (?, ?, ?, ?)",
+                key,
+                value,
+                updated_by,
+                now
+            )
+            .execute(&mut tx)
+            .await?;
+            latest = result.last_insert_rowid();
+        }
+
+        tx.commit().await?;
+        Ok(latest)
+    }
+
+    pub async fn list_settings(&self) -> ResultType<HashMap<String, String>> {
+        let mut conn = self.pool.get().await?;
+
+        let rows = sqlx::query!("SELECT key, value FROM settings")
+            .fetch_all(conn.deref_mut())
+            .await?;
+
+        Ok(rows.into_iter().map(|row| (row.key, row.value)).collect())
+    }
+
+    // 在线备份，VACUUM INTO 在单个读事务中写出一致的数据库快照，目标文件必须不存在
+    pub async fn backup_into(&self, path: &str) -> ResultType<()> {
+        let mut conn = self.pool.get().await?;
+
+        sqlx::query!("VACUUM INTO ?", path)
+            .execute(conn.deref_mut())
+            .await?;
+
         Ok(())
     }
 
@@ -528,7 +6379,11 @@ impl EnterpriseDatabase {
         let mut conn = self.pool.get().await?;
         
         let rows = sqlx::query!(
-            "SELECT * FROM devices WHERE owner_id = ? AND enabled = 1",
+            r#"
+            SELECT d.*, a.alias as "alias?" FROM devices d
+            LEFT JOIN device_aliases a ON a.device_id = d.id
+            WHERE d.owner_id = ? AND d.enabled = 1
+            "#,
             user_id
         )
         .fetch_all(conn.deref_mut())
@@ -546,15 +6401,97 @@ impl EnterpriseDatabase {
                 os: row.os,
                 version: row.version,
                 ip_address: row.ip_address,
+                ipv6_address: row.ipv6_address,
                 mac_address: row.mac_address,
                 last_online,
                 owner_id: row.owner_id,
                 group_ids,
                 enabled: row.enabled,
                 tags,
+                alias: row.alias,
             });
         }
 
         Ok(devices)
     }
+
+    // 按条件查询设备，owner_id 为空时查询全部设备；返回当前页和匹配总数
+    pub async fn list_devices(
+        &self,
+        filter: &DeviceFilter,
+        owner_id: Option<&str>,
+        now: u64,
+        limit: i64,
+        offset: i64,
+    ) -> ResultType<(Vec<DeviceInfo>, u64)> {
+        let mut conn = self.pool.get().await?;
+        let tags = if filter.tags.is_empty() {
+            None
+        } else {
+            Some(serde_json::to_string(&filter.tags)?)
+        };
+        let online_since = now.saturating_sub(crate::uptime::ONLINE_TIMEOUT_SECS) as i64;
+        let offline_before = filter.offline_for.map(|x| now.saturating_sub(x) as i64);
+        let seen_after = filter.seen_within.map(|x| now.saturating_sub(x) as i64);
+
+        let rows = sqlx::query!(
+            r#"
+            SELECT d.*, a.alias as "alias?", COUNT(*) OVER () as "total!: i64" FROM devices d
+            LEFT JOIN device_aliases a ON a.device_id = d.id
+            WHERE d.enabled = 1
+                AND (?1 IS NULL OR d.owner_id = ?1)
+                AND (?2 IS NULL OR NOT EXISTS (
+                    SELECT 1 FROM json_each(?2) t WHERE t.value NOT IN (SELECT value FROM json_each(d.tags))
+                ))
+                AND (?3 IS NULL OR instr(lower(d.os), lower(?3)) > 0)
+                AND (?4 IS NULL OR EXISTS (SELECT 1 FROM json_each(d.group_ids) WHERE value = ?4))
+                AND (?5 IS NULL OR (d.last_online >= ?6) = ?5)
+                AND (?7 IS NULL OR d.last_online < ?7)
+                AND (?8 IS NULL OR d.last_online >= ?8)
+                AND (?9 IS NULL OR instr(lower(d.name), lower(?9)) > 0 OR instr(d.id, ?9) > 0
+                    OR instr(lower(COALESCE(a.alias, '')), lower(?9)) > 0)
+            ORDER BY d.last_online DESC, d.id
+            LIMIT ?10 OFFSET ?11
+            "#,
+            owner_id,
+            tags,
+            filter.os,
+            filter.group_id,
+            filter.online,
+            online_since,
+            offline_before,
+            seen_after,
+            filter.q,
+            limit,
+            offset
+        )
+        .fetch_all(conn.deref_mut())
+        .await?;
+
+        let total = rows.first().map(|row| row.total as u64).unwrap_or_default();
+        let mut devices = Vec::new();
+        for row in rows {
+            let last_online = std::time::UNIX_EPOCH + std::time::Duration::from_secs(row.last_online as u64);
+            let group_ids: Vec<String> = serde_json::from_str(&row.group_ids).unwrap_or_default();
+            let tags: Vec<String> = serde_json::from_str(&row.tags).unwrap_or_default();
+
+            devices.push(DeviceInfo {
+                id: row.id,
+                name: row.name,
+                os: row.os,
+                version: row.version,
+                ip_address: row.ip_address,
+                ipv6_address: row.ipv6_address,
+                mac_address: row.mac_address,
+                last_online,
+                owner_id: row.owner_id,
+                group_ids,
+                enabled: row.enabled,
+                tags,
+                alias: row.alias,
+            });
+        }
+
+        Ok((devices, total))
+    }
 }
\ No newline at end of file
@@ -1,11 +1,14 @@
 // 企业版主程序入口
-use flexi_logger::*;
 use hbb_common::{bail, config::RENDEZVOUS_PORT, ResultType};
 use hbbs::{common::*, *};
 
 use crate::auth;
+use crate::backup;
 use crate::enterprise_database;
 use crate::enterprise_rendezvous_server;
+use crate::i18n::{self, t};
+use crate::oss_migration;
+use crate::server_config;
 use crate::web_api;
 
 use crate::enterprise_rendezvous_server::EnterpriseRendezvousServer;
@@ -14,11 +17,43 @@ const RMEM: usize = 0;
 
 fn main() -> ResultType<()> {
     // 初始化日志系统
-    let _logger = Logger::try_with_env_or_str("info")?
-        .log_to_stdout()
-        .format(opt_format)
-        .write_mode(WriteMode::Async)
-        .start()?;
+    init_logger()?;
+
+    // `config validate [--config FILE]` 只检查配置文件，不启动服务
+    let argv: Vec<String> = std::env::args().skip(1).collect();
+    if argv.first().map(|x| x.as_str()) == Some("config") {
+        if argv.get(1).map(|x| x.as_str()) != Some("validate") {
+            eprintln!("usage: hbbs config validate [--config FILE]");
+            std::process::exit(2);
+        }
+        std::process::exit(server_config::validate_command(&config_file_arg(&argv[2..])));
+    }
+
+    // `backup restore FILE [--db PATH]` 离线恢复数据库备份，需先停止服务
+    if argv.first().map(|x| x.as_str()) == Some("backup") {
+        let file = match (argv.get(1).map(|x| x.as_str()), argv.get(2)) {
+            (Some("restore"), Some(file)) => file,
+            _ => {
+                eprintln!("usage: hbbs backup restore FILE [--db PATH]");
+                std::process::exit(2);
+            }
+        };
+        let db_url = match argv.iter().position(|x| x == "--db") {
+            Some(i) => argv.get(i + 1).cloned().unwrap_or_default(),
+            None => std::env::var("ENTERPRISE_DB_URL").unwrap_or_else(|_| "enterprise.sqlite3".to_owned()),
+        };
+        std::process::exit(backup::restore_command(file, &db_url));
+    }
+
+    // `migrate-from-oss SOURCE [...]` 导入开源版 hbbs 登记的设备，需先停止服务
+    if argv.first().map(|x| x.as_str()) == Some("migrate-from-oss") {
+        std::process::exit(oss_migration::migrate_command(&argv[1..]));
+    }
+
+    // `export-to-oss DEST [...]` 导出为开源版 hbbs 的设备库，用于降级或冷备
+    if argv.first().map(|x| x.as_str()) == Some("export-to-oss") {
+        std::process::exit(oss_migration::export_command(&argv[1..]));
+    }
 
     // 解析命令行参数
     let args = format!(
@@ -39,22 +74,26 @@ fn main() -> ResultType<()> {
 
     init_args(&args, "hbbs-enterprise", "RustDesk Enterprise ID/Rendezvous Server");
 
+    // TOML 配置文件，命令行参数和环境变量优先
+    if let Some(config) = get_arg_option("config").filter(|x| x.ends_with(".toml")) {
+        server_config::init(&config)?;
+    }
+
     // 检查是否启用企业功能
     let enterprise_mode = get_arg("enterprise") == "true" || std::env::var("RUSTDESK_ENTERPRISE").is_ok();
     
+    // 控制台提示使用服务器默认语言(RUSTDESK_LANG)
+    let lang = i18n::default_lang();
     if !enterprise_mode {
-        println!("启动标准版服务器...");
+        println!("{}", t(lang, "console.standard.starting", &[]));
         return start_standard_server();
     }
 
-    println!("启动企业版服务器...");
-    println!("企业功能包括:");
-    println!("  ✓ 用户认证和权限管理");
-    println!("  ✓ 设备分组和批量管理");
-    println!("  ✓ 审计日志和会话记录");
-    println!("  ✓ Web管理界面");
-    println!("  ✓ 双因素认证支持");
-    println!("  ✓ 企业级安全控制");
+    println!("{}", t(lang, "console.enterprise.starting", &[]));
+    println!("{}", t(lang, "console.enterprise.features", &[]));
+    for feature in ["auth", "groups", "audit", "web", "2fa", "security"] {
+        println!("  ✓ {}", t(lang, &format!("console.feature.{}", feature), &[]));
+    }
 
     start_enterprise_server()
 }
@@ -105,6 +144,7 @@ fn start_enterprise_server() -> ResultType<()> {
 }
 
 fn setup_enterprise_environment() {
+    let lang = i18n::default_lang();
     // 设置JWT密钥
     if let Some(jwt_secret) = get_arg_option("jwt-secret") {
         std::env::set_var("JWT_SECRET", jwt_secret);
@@ -112,7 +152,7 @@ fn setup_enterprise_environment() {
         // 生成随机JWT密钥
         let secret = generate_random_secret();
         std::env::set_var("JWT_SECRET", secret);
-        println!("警告: 使用随机生成的JWT密钥。生产环境请设置固定密钥！");
+        println!("{}", t(lang, "console.jwt.random", &[]));
     }
     
     // 设置数据库URL
@@ -131,18 +171,24 @@ fn setup_enterprise_environment() {
     std::env::set_var("RUSTDESK_ENTERPRISE", "1");
     
     // 显示配置信息
-    println!("企业版配置:");
-    println!("  数据库: {}", std::env::var("ENTERPRISE_DB_URL").unwrap_or_default());
-    println!("  JWT密钥: {}", if std::env::var("JWT_SECRET").is_ok() { "已配置" } else { "未配置" });
-    
-    if let Ok(web_port) = std::env::var("WEB_PORT") {
-        println!("  Web管理界面: http://localhost:{}", web_port);
+    println!("{}", t(lang, "console.config.title", &[]));
+    let db_url = std::env::var("ENTERPRISE_DB_URL").unwrap_or_default();
+    println!("  {}", t(lang, "console.config.database", &[("url", &db_url)]));
+    let jwt_status = if std::env::var("JWT_SECRET").is_ok() {
+        t(lang, "console.config.configured", &[])
     } else {
+        t(lang, "console.config.not_configured", &[])
+    };
+    println!("  {}", t(lang, "console.config.jwt", &[("status", &jwt_status)]));
+    
+    let web_port = std::env::var("WEB_PORT").unwrap_or_else(|_| {
         let main_port = get_arg_or("port", RENDEZVOUS_PORT.to_string()).parse::<i32>().unwrap_or(RENDEZVOUS_PORT);
-        println!("  Web管理界面: http://localhost:{}", main_port + 3);
-    }
+        (main_port + 3).to_string()
+    });
+    let web_url = format!("http://localhost:{}", web_port);
+    println!("  {}", t(lang, "console.config.web", &[("url", &web_url)]));
     
-    println!("  默认管理员账户: admin / admin123 (请立即修改密码!)");
+    println!("  {}", t(lang, "console.config.default_admin", &[]));
 }
 
 fn generate_random_secret() -> String {
@@ -161,6 +207,23 @@ fn generate_random_secret() -> String {
         .collect()
 }
 
+// config 子命令的配置文件参数，未指定时使用当前目录下的 hbbs.toml
+fn config_file_arg(args: &[String]) -> String {
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        if arg == "-c" || arg == "--config" {
+            if let Some(path) = iter.next() {
+                return path.clone();
+            }
+        } else if let Some(path) = arg.strip_prefix("--config=") {
+            return path.to_owned();
+        } else if !arg.starts_with('-') {
+            return arg.clone();
+        }
+    }
+    "hbbs.toml".to_owned()
+}
+
 fn get_arg_option(name: &str) -> Option<String> {
     let value = get_arg(name);
     if value.is_empty() {
@@ -180,4 +243,14 @@ mod tests {
         assert_eq!(secret.len(), 64);
         assert!(secret.chars().all(|c| c.is_ascii()));
     }
+
+    #[test]
+    fn test_config_file_arg() {
+        let args = |x: &[&str]| x.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        assert_eq!(config_file_arg(&args(&[])), "hbbs.toml");
+        assert_eq!(config_file_arg(&args(&["--config", "/etc/hbbs.toml"])), "/etc/hbbs.toml");
+        assert_eq!(config_file_arg(&args(&["--config=a.toml"])), "a.toml");
+        assert_eq!(config_file_arg(&args(&["-c", "b.toml"])), "b.toml");
+        assert_eq!(config_file_arg(&args(&["c.toml"])), "c.toml");
+    }
 }
\ No newline at end of file
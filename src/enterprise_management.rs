@@ -1,7 +1,10 @@
 // 企业管理模块 - 用户组管理、权限控制、设备分组
 use crate::auth::{User, UserRole};
 use crate::enterprise_database::EnterpriseDatabase;
+use crate::holiday_calendar;
+use crate::unattended_access::{local_date, local_time, parse_timezone};
 use hbb_common::{log, ResultType};
+use schemars::JsonSchema;
 use serde_derive::{Deserialize, Serialize};
 use std::{
     collections::{HashMap, HashSet},
@@ -54,6 +57,12 @@ pub struct GroupPermissions {
     pub daily_time_limit: Option<Duration>,
     pub allowed_hours: Option<TimeRange>,
     pub allowed_days: Vec<u8>, // 0=Sunday, 1=Monday, etc.
+    // allowed_hours/allowed_days 所在的 IANA 时区，为空时为服务器本地时间
+    #[serde(default)]
+    pub timezone: Option<String>,
+    // 节假日日历 id，日历中的日期全天不允许访问
+    #[serde(default)]
+    pub holiday_calendar: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -119,7 +128,7 @@ pub enum RuleType {
     Owner,        // 设备所有者
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct MonitoringSettings {
     pub enable_monitoring: bool,
     pub alert_on_offline: bool,
@@ -215,6 +224,9 @@ impl EnterpriseManager {
             return Err("Group name already exists".into());
         }
         drop(groups);
+        if let Some(timezone) = &group.permissions.timezone {
+            parse_timezone(timezone)?;
+        }
 
         // 保存到数据库
         self.db.create_user_group(&group).await?;
@@ -227,6 +239,10 @@ impl EnterpriseManager {
     }
 
     pub async fn update_user_group(&self, group: UserGroup) -> ResultType<()> {
+        if let Some(timezone) = &group.permissions.timezone {
+            parse_timezone(timezone)?;
+        }
+
         // 更新数据库
         self.db.update_user_group(&group).await?;
         
@@ -515,30 +531,32 @@ impl EnterpriseManager {
     pub async fn check_time_restrictions(&self, user_id: &str) -> bool {
         let user_groups = self.get_user_groups(user_id).await;
         
+        let now = chrono::Utc::now();
+        
         for group in user_groups {
-            if let Some(allowed_hours) = &group.permissions.allowed_hours {
-                if !self.is_current_time_allowed(allowed_hours) {
+            // 时段和星期都按组的时区计算
+            let timezone = group.permissions.timezone.as_deref();
+            let (current_day, current_minutes) = local_time(now, timezone);
+            if let Some(calendar_id) = &group.permissions.holiday_calendar {
+                if holiday_calendar::is_holiday(calendar_id, local_date(now, timezone)).await {
                     return false;
                 }
             }
-            
-            if !group.permissions.allowed_days.is_empty() {
-                let current_day = chrono::Utc::now().weekday().num_days_from_sunday() as u8;
-                if !group.permissions.allowed_days.contains(&current_day) {
+            if let Some(allowed_hours) = &group.permissions.allowed_hours {
+                if !self.is_time_allowed(allowed_hours, current_minutes as u16) {
                     return false;
                 }
             }
+            
+            if !group.permissions.allowed_days.is_empty() && !group.permissions.allowed_days.contains(&current_day) {
+                return false;
+            }
         }
         
         true
     }
 
-    fn is_current_time_allowed(&self, time_range: &TimeRange) -> bool {
-        let now = chrono::Local::now();
-        let current_hour = now.hour() as u8;
-        let current_minute = now.minute() as u8;
-        
-        let current_minutes = current_hour as u16 * 60 + current_minute as u16;
+    fn is_time_allowed(&self, time_range: &TimeRange, current_minutes: u16) -> bool {
         let start_minutes = time_range.start_hour as u16 * 60 + time_range.start_minute as u16;
         let end_minutes = time_range.end_hour as u16 * 60 + time_range.end_minute as u16;
         
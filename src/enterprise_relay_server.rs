@@ -1,9 +1,11 @@
 // 企业版中继服务器主程序
 use clap::App;
-use flexi_logger::*;
-use hbb_common::{config::RELAY_PORT, ResultType};
+use hbb_common::{config::RELAY_PORT, log, ResultType};
 use rust_ini as ini;
 
+use crate::common::init_logger;
+use crate::control_tls;
+use crate::quic;
 use crate::relay_server::*;
 
 mod version {
@@ -11,11 +13,7 @@ mod version {
 }
 
 fn main() -> ResultType<()> {
-    let _logger = Logger::try_with_env_or_str("info")?
-        .log_to_stdout()
-        .format(opt_format)
-        .write_mode(WriteMode::Async)
-        .start()?;
+    init_logger()?;
 
     let args = format!(
         "-p, --port=[NUMBER(default={RELAY_PORT})] 'Sets the listening port'
@@ -155,6 +153,46 @@ fn start_with_enterprise_features(port: &str, key: &str) -> ResultType<()> {
     
     println!("启动企业版中继服务器，端口: {}", port);
     
+    // 实验性 QUIC 监听与 TCP 使用同一端口号(udp)，收到的流按 TCP 中继会话处理
+    if quic::enabled() {
+        let quic_port: u16 = port.parse()?;
+        std::thread::spawn(move || {
+            let rt = match hbb_common::tokio::runtime::Runtime::new() {
+                Ok(rt) => rt,
+                Err(err) => {
+                    log::error!("Failed to create QUIC runtime: {}", err);
+                    return;
+                }
+            };
+            let res = rt.block_on(quic::serve(quic_port, |stream, addr| async move {
+                accept_stream(stream, addr)
+            }));
+            if let Err(err) = res {
+                log::error!("QUIC listener failed: {}", err);
+            }
+        });
+    }
+
+    // 配置了控制通道证书时，信令服务器经双向 TLS 发来的管理命令按本机管理命令处理
+    if control_tls::listen_enabled() {
+        let control_port = control_tls::port();
+        std::thread::spawn(move || {
+            let rt = match hbb_common::tokio::runtime::Runtime::new() {
+                Ok(rt) => rt,
+                Err(err) => {
+                    log::error!("Failed to create control TLS runtime: {}", err);
+                    return;
+                }
+            };
+            let res = rt.block_on(control_tls::serve(control_port, |stream, _addr| async move {
+                accept_admin_stream(stream)
+            }));
+            if let Err(err) = res {
+                log::error!("Relay control TLS listener failed: {}", err);
+            }
+        });
+    }
+
     // 目前先使用标准的中继服务器，后续可以扩展企业功能
     start(port, key)
 }
@@ -1,8 +1,67 @@
 // 企业级会合服务器 - 集成用户认证和权限控制
 use crate::auth::{AuthManager, Claims};
+use crate::codec_recommendation;
+use crate::connection_quality;
+use crate::content_scan;
+use crate::custom_fields;
+use crate::data_masking;
+use crate::db_pool_metrics;
 use crate::enterprise_database::{EnterpriseDatabase, AuditLog, DeviceInfo};
+use crate::nat_diagnostics::{self, nat_type_label, PunchOutcome};
 use crate::peer::*;
+use crate::device_ban;
+use crate::dlp;
+use crate::dns_discovery;
+use crate::ad_sync;
+use crate::e2e_signaling::{self, KeyOffer};
+use crate::email_otp;
+use crate::break_glass;
+use crate::backup;
+use crate::change_approval;
+use crate::control_tls;
+use crate::favorites;
+use crate::feature_flags;
+use crate::federation::{self, FederationPeer, Inbound};
+use crate::i18n;
+use crate::four_eyes;
+use crate::file_transfer_server;
+use crate::holiday_calendar;
+use crate::honeypot;
+use crate::host_stats;
+use crate::id_policy;
+use crate::itsm;
+use crate::jwt_keys;
+use crate::kiosk_fleet;
+use crate::lan_config;
+use crate::license;
+use crate::logging;
+use crate::mfa_policy;
+use crate::network_tuning;
+use crate::notifications;
+use crate::offline_alerts;
+use crate::password_policy;
+use crate::peer_alias;
+use crate::pk_pinning;
+use crate::protocol_capture;
+use crate::quic;
+use crate::relay_policy;
+use crate::relay_sessions;
+use crate::self_test::{self, Probe};
+use crate::server_config;
+use crate::server_key;
+use crate::signer::{self, Signer, SoftwareSigner};
+use crate::software_update;
+use crate::threat_intel;
+use crate::transfer_bandwidth;
+use crate::trusted_device;
+use crate::turn;
+use crate::unattended_access;
+use crate::uptime;
+use crate::version_policy;
 use crate::web_api::{create_router, AppState};
+use crate::web_security;
+use crate::web_session;
+use crate::webrtc_signaling;
 use hbb_common::{
     allow_err, bail,
     bytes::{Bytes, BytesMut},
@@ -16,7 +75,7 @@ use hbb_common::{
     log,
     protobuf::{Message as _, MessageField},
     rendezvous_proto::{
-        register_pk_response::Result::{TOO_FREQUENT, UUID_MISMATCH},
+        register_pk_response::Result::{INVALID_ID_FORMAT, TOO_FREQUENT, UUID_MISMATCH},
         *,
     },
     tcp::{listen_any, FramedStream},
@@ -46,17 +105,19 @@ use std::{
 #[derive(Clone, Debug)]
 enum Data {
     Msg(Box<RendezvousMessage>, SocketAddr),
+    // 经 QUIC 收到的注册类消息，交给 UDP 主循环按 UDP 消息处理
+    Quic(BytesMut, SocketAddr),
     RelayServers0(String),
     RelayServers(RelayServers),
 }
 
-const REG_TIMEOUT: i32 = 30_000;
 type TcpStreamSink = SplitSink<Framed<TcpStream, BytesCodec>, Bytes>;
 type WsSink = SplitSink<tokio_tungstenite::WebSocketStream<TcpStream>, tungstenite::Message>;
 
 enum Sink {
     TcpStream(TcpStreamSink),
     Ws(WsSink),
+    Quic(mpsc::UnboundedSender<Bytes>),
 }
 
 type Sender = mpsc::UnboundedSender<Data>;
@@ -71,8 +132,6 @@ struct Inner {
     serial: i32,
     version: String,
     software_url: String,
-    mask: Option<Ipv4Network>,
-    local_ip: String,
     sk: Option<sign::SecretKey>,
 }
 
@@ -134,10 +193,21 @@ enum LoopFailure {
 impl EnterpriseRendezvousServer {
     #[tokio::main(flavor = "multi_thread")]
     pub async fn start(port: i32, serial: i32, key: &str, rmem: usize) -> ResultType<()> {
-        let (key, sk) = Self::get_server_sk(key);
+        // 配置了硬件签名器时私钥不经过启动参数，licence key 使用硬件中的公钥
+        let hardware_signer = signer::from_args()?;
+        let (key, sk) = match hardware_signer.as_ref() {
+            Some(signer) if !key.is_empty() => (base64::encode(signer.public_key()), None),
+            Some(_) => (String::new(), None),
+            None => Self::get_server_sk(key),
+        };
         let nat_port = port - 1;
         let ws_port = port + 2;
-        let web_port = port + 3; // Web管理界面端口
+        // Web管理界面端口，默认为主端口 + 3
+        let web_port = std::env::var("WEB_PORT")
+            .ok()
+            .and_then(|x| x.parse::<i32>().ok())
+            .unwrap_or(port + 3);
+        let file_transfer_port = port + 4; // 文件传输端口
         
         // 初始化企业级数据库
         let db_url = std::env::var("ENTERPRISE_DB_URL").unwrap_or_else(|_| "enterprise.sqlite3".to_string());
@@ -146,6 +216,16 @@ impl EnterpriseRendezvousServer {
         // 初始化认证管理器
         let jwt_secret = std::env::var("JWT_SECRET").unwrap_or_else(|_| "your-super-secret-jwt-key".to_string());
         let auth_manager = Arc::new(AuthManager::new(jwt_secret));
+        // JWT 密钥，发生过轮换时以数据库中的密钥为准
+        let jwt_secret_secondary = std::env::var("JWT_SECRET_SECONDARY").ok();
+        if let Err(err) = jwt_keys::init(&enterprise_db, &auth_manager, jwt_secret_secondary).await {
+            log::error!("Failed to load jwt keys: {}", err);
+        }
+
+        // 配置文件中的 SMTP 和策略写入系统设置，之后各模块按系统设置加载
+        if let Err(err) = server_config::apply_settings(&enterprise_db).await {
+            log::error!("Failed to apply config file settings: {:#}", err);
+        }
         
         let pm = PeerMap::new().await?;
         log::info!("Enterprise Rendezvous Server starting...");
@@ -156,6 +236,7 @@ impl EnterpriseRendezvousServer {
         log::info!("Listening on tcp :{}, extra port for NAT test", nat_port);
         log::info!("Listening on websocket :{}", ws_port);
         log::info!("Web management interface on :{}", web_port);
+        log::info!("File transfer on tcp :{}", file_transfer_port);
         
         let mut socket = create_udp_listener(port, rmem).await?;
         let (tx, mut rx) = mpsc::unbounded_channel::<Data>();
@@ -166,7 +247,7 @@ impl EnterpriseRendezvousServer {
             log::info!("software_url: {}, version: {}", software_url, version);
         }
         
-        let mask = get_arg("mask").parse().ok();
+        let mask: Option<Ipv4Network> = get_arg("mask").parse().ok();
         let local_ip = if mask.is_none() {
             "".to_owned()
         } else {
@@ -190,16 +271,19 @@ impl EnterpriseRendezvousServer {
                 version,
                 software_url,
                 sk,
-                mask,
-                local_ip,
             }),
             enterprise_db: enterprise_db.clone(),
             auth_manager: auth_manager.clone(),
             device_sessions: Arc::new(Mutex::new(HashMap::new())),
         };
         
-        log::info!("mask: {:?}", rs.inner.mask);
-        log::info!("local-ip: {:?}", rs.inner.local_ip);
+        // 局域网站点配置，数据库中有配置时覆盖 --mask/--local-ip
+        lan_config::init(&enterprise_db, mask, local_ip).await;
+
+        // 签名密钥环，发生过轮换时以数据库中的密钥为准
+        if let Err(err) = server_key::init(&enterprise_db, rs.inner.sk.as_ref(), hardware_signer).await {
+            log::error!("Failed to load server key ring: {}", err);
+        }
         
         std::env::set_var("PORT_FOR_API", port.to_string());
         rs.parse_relay_servers(&get_arg("relay-servers"));
@@ -208,6 +292,369 @@ impl EnterpriseRendezvousServer {
         let mut listener2 = create_tcp_listener(nat_port).await?;
         let mut listener3 = create_tcp_listener(ws_port).await?;
         
+        // 加载设备别名缓存
+        if let Err(err) = peer_alias::reload(&enterprise_db).await {
+            log::error!("Failed to load device aliases: {}", err);
+        }
+
+        // 加载设备封禁列表，封禁后立即将设备移出在线列表
+        if let Err(err) = device_ban::reload(&enterprise_db).await {
+            log::error!("Failed to load device bans: {}", err);
+        }
+        let mut evictions = device_ban::subscribe_evictions().await;
+        let pm_evict = rs.pm.clone();
+        let db_evict = enterprise_db.clone();
+        tokio::spawn(async move {
+            while let Some(id) = evictions.recv().await {
+                if let Some(peer) = pm_evict.remove(&id).await {
+                    let uuid = base64::encode(&peer.read().await.uuid);
+                    device_ban::attach_uuid(&db_evict, &id, &uuid).await;
+                    log::info!("Banned device {} removed from peer map", id);
+                }
+            }
+        });
+
+        // 处理联邦中其他区域转发的请求和送回的响应
+        let mut federated = federation::subscribe().await;
+        let pm_federation = rs.pm.clone();
+        let tx_federation = rs.tx.clone();
+        let tcp_punch_federation = rs.tcp_punch.clone();
+        tokio::spawn(async move {
+            while let Some(req) = federated.recv().await {
+                match req {
+                    Inbound::Lookup(id, reply) => {
+                        reply.send(Self::online_addr(&pm_federation, &id).await.is_some()).ok();
+                    }
+                    Inbound::Forward(id, msg, reply) => match Self::online_addr(&pm_federation, &id).await {
+                        Some(peer_addr) => {
+                            tx_federation.send(Data::Msg(msg.into(), peer_addr)).ok();
+                            reply.send(true).ok();
+                        }
+                        None => {
+                            reply.send(false).ok();
+                        }
+                    },
+                    Inbound::Deliver(addr, msg) => {
+                        let mut sink = tcp_punch_federation.lock().await.remove(&try_into_v4(addr));
+                        if sink.is_some() {
+                            protocol_capture::outbound(&msg, addr, "tcp").await;
+                            Self::send_to_sink(&mut sink, msg).await;
+                        } else {
+                            tx_federation.send(Data::Msg(msg.into(), addr)).ok();
+                        }
+                    }
+                }
+            }
+        });
+
+        // 加载连接密码策略和配置序号
+        if let Err(err) = password_policy::reload(&enterprise_db).await {
+            log::error!("Failed to load password policies: {}", err);
+        }
+
+        // 加载ID注册策略
+        if let Err(err) = id_policy::reload(&enterprise_db).await {
+            log::error!("Failed to load id policy: {}", err);
+        }
+
+        // 加载端到端加密策略
+        if let Err(err) = e2e_signaling::reload(&enterprise_db).await {
+            log::error!("Failed to load e2e policy: {}", err);
+        }
+
+        // 加载邮件验证码配置
+        if let Err(err) = email_otp::reload(&enterprise_db).await {
+            log::error!("Failed to load email otp config: {}", err);
+        }
+
+        // 加载受信任浏览器配置
+        if let Err(err) = trusted_device::reload(&enterprise_db).await {
+            log::error!("Failed to load trusted device config: {}", err);
+        }
+
+        // 加载跨域来源和安全响应头配置
+        if let Err(err) = web_security::reload(&enterprise_db).await {
+            log::error!("Failed to load web security config: {}", err);
+        }
+
+        // 加载 Web 会话空闲超时和绝对时长配置
+        if let Err(err) = web_session::reload(&enterprise_db).await {
+            log::error!("Failed to load web session config: {}", err);
+        }
+
+        // 加载数据脱敏规则
+        if let Err(err) = data_masking::reload(&enterprise_db).await {
+            log::error!("Failed to load data masking config: {}", err);
+        }
+
+        // 加载功能开关
+        if let Err(err) = feature_flags::reload(&enterprise_db).await {
+            log::error!("Failed to load feature flags: {}", err);
+        }
+
+        // 加载 ServiceNow/Jira 集成配置
+        if let Err(err) = itsm::reload(&enterprise_db).await {
+            log::error!("Failed to load itsm config: {}", err);
+        }
+
+        // 加载 AD 计算机同步配置
+        if let Err(err) = ad_sync::reload(&enterprise_db).await {
+            log::error!("Failed to load ad sync config: {}", err);
+        }
+
+        // 加载双因素认证强制策略
+        if let Err(err) = mfa_policy::reload(&enterprise_db).await {
+            log::error!("Failed to load mfa policy: {}", err);
+        }
+
+        // 加载文件传输DLP策略
+        if let Err(err) = dlp::reload(&enterprise_db).await {
+            log::error!("Failed to load dlp policy: {}", err);
+        }
+
+        // 加载文件传输带宽调度策略
+        if let Err(err) = transfer_bandwidth::reload(&enterprise_db).await {
+            log::error!("Failed to load transfer bandwidth policy: {}", err);
+        }
+
+        // 加载文件内容扫描配置
+        if let Err(err) = content_scan::reload(&enterprise_db).await {
+            log::error!("Failed to load content scan config: {}", err);
+        }
+
+        // 加载连接保活与超时设置
+        if let Err(err) = network_tuning::reload(&enterprise_db).await {
+            log::error!("Failed to load network tuning: {}", err);
+        }
+
+        // 加载 WebRTC 信令配置
+        if let Err(err) = webrtc_signaling::reload(&enterprise_db).await {
+            log::error!("Failed to load webrtc config: {}", err);
+        }
+
+        // 加载 TURN 临时凭据配置
+        if let Err(err) = turn::reload(&enterprise_db).await {
+            log::error!("Failed to load turn config: {}", err);
+        }
+
+        // 加载诱饵设备ID配置
+        if let Err(err) = honeypot::reload(&enterprise_db).await {
+            log::error!("Failed to load honeypot config: {}", err);
+        }
+
+        // 加载威胁情报IP源配置，列表由定时任务下载
+        if let Err(err) = threat_intel::reload(&enterprise_db).await {
+            log::error!("Failed to load threat intel config: {}", err);
+        }
+
+        // 加载变更审批配置
+        if let Err(err) = change_approval::reload(&enterprise_db).await {
+            log::error!("Failed to load change approval config: {}", err);
+        }
+
+        // 加载中继排空状态
+        if let Err(err) = relay_sessions::reload_drain(&enterprise_db).await {
+            log::error!("Failed to load relay drain state: {}", err);
+        }
+
+        // 加载信令服务器联邦配置
+        if let Err(err) = federation::reload(&enterprise_db).await {
+            log::error!("Failed to load federation config: {}", err);
+        }
+
+        // 加载 DNS 服务发现配置
+        if let Err(err) = dns_discovery::reload(&enterprise_db).await {
+            log::error!("Failed to load dns discovery config: {}", err);
+        }
+
+        // 加载运行时日志配置
+        if let Err(err) = logging::reload(&enterprise_db).await {
+            log::error!("Failed to load logging config: {}", err);
+        }
+
+        // 加载并校验企业版许可证
+        if let Err(err) = license::reload(&enterprise_db).await {
+            log::error!("Failed to load license: {}", err);
+        }
+
+        // 加载数据库备份配置
+        if let Err(err) = backup::reload(&enterprise_db).await {
+            log::error!("Failed to load backup config: {}", err);
+        }
+
+        // 加载设备离线告警配置
+        if let Err(err) = offline_alerts::reload(&enterprise_db).await {
+            log::error!("Failed to load offline alert config: {}", err);
+        }
+
+        // 加载自定义字段定义
+        if let Err(err) = custom_fields::reload(&enterprise_db).await {
+            log::error!("Failed to load custom fields: {}", err);
+        }
+
+        // 加载用户语言偏好
+        if let Err(err) = i18n::reload(&enterprise_db).await {
+            log::error!("Failed to load user languages: {}", err);
+        }
+
+        // 加载设备最近的在线状态
+        if let Err(err) = uptime::load(&enterprise_db).await {
+            log::error!("Failed to load device status: {}", err);
+        }
+
+        // 加载按设备组配置的中继策略
+        if let Err(err) = relay_policy::reload(&enterprise_db).await {
+            log::error!("Failed to load relay policy: {}", err);
+        }
+
+        // 加载敏感设备双人审批配置
+        if let Err(err) = four_eyes::reload(&enterprise_db).await {
+            log::error!("Failed to load four-eyes policy: {}", err);
+        }
+
+        // 加载无人值守访问时段
+        if let Err(err) = unattended_access::reload(&enterprise_db).await {
+            log::error!("Failed to load unattended access policy: {}", err);
+        }
+
+        // 加载节假日日历和维护窗口
+        if let Err(err) = holiday_calendar::reload(&enterprise_db).await {
+            log::error!("Failed to load holiday calendars: {}", err);
+        }
+
+        // 加载自助终端机群配置
+        if let Err(err) = kiosk_fleet::reload(&enterprise_db).await {
+            log::error!("Failed to load kiosk fleet config: {}", err);
+        }
+
+        // 加载公钥固定策略和待审批的公钥变化
+        if let Err(err) = pk_pinning::reload(&enterprise_db).await {
+            log::error!("Failed to load pk pinning config: {}", err);
+        }
+
+        // 加载中继控制通道 TLS 的 CA 和证书
+        if let Err(err) = control_tls::reload(&enterprise_db).await {
+            log::error!("Failed to load relay control TLS config: {}", err);
+        }
+
+        // 加载托管的客户端安装包
+        if let Err(err) = software_update::reload(&enterprise_db).await {
+            log::error!("Failed to load software update artifacts: {}", err);
+        }
+
+        // 加载客户端最低版本策略，"latest" 对应 --software-url 中的版本
+        version_policy::set_software(&rs.inner.version, &rs.inner.software_url).await;
+        if let Err(err) = version_policy::reload(&enterprise_db).await {
+            log::error!("Failed to load version policy: {}", err);
+        }
+
+        // 启动文件传输服务
+        let file_transfer_auth = auth_manager.clone();
+        let file_transfer_db = enterprise_db.clone();
+        tokio::spawn(async move {
+            if let Err(err) = file_transfer_server::start(file_transfer_port, file_transfer_auth, file_transfer_db).await {
+                log::error!("File transfer server failed: {}", err);
+            }
+        });
+
+        // 紧急访问到期清理任务
+        let break_glass_db = enterprise_db.clone();
+        tokio::spawn(async move {
+            let mut interval = interval(Duration::from_secs(30));
+            loop {
+                interval.tick().await;
+                break_glass::expire(&break_glass_db).await;
+            }
+        });
+
+        // 数据库自动备份任务
+        let backup_db = enterprise_db.clone();
+        tokio::spawn(async move {
+            let mut interval = interval(Duration::from_secs(600));
+            loop {
+                interval.tick().await;
+                backup::run_scheduled(&backup_db).await;
+            }
+        });
+
+        // AD 计算机同步任务，按配置的间隔执行
+        let ad_sync_db = enterprise_db.clone();
+        tokio::spawn(async move {
+            let mut interval = interval(Duration::from_secs(60));
+            loop {
+                interval.tick().await;
+                ad_sync::run_scheduled(&ad_sync_db).await;
+            }
+        });
+
+        // 威胁情报IP源下载任务，按配置的间隔执行
+        tokio::spawn(async move {
+            let mut interval = interval(Duration::from_secs(60));
+            loop {
+                interval.tick().await;
+                threat_intel::run_scheduled().await;
+            }
+        });
+
+        // 设备离线告警检查任务
+        let offline_alerts_db = enterprise_db.clone();
+        tokio::spawn(async move {
+            let mut interval = interval(Duration::from_secs(60));
+            loop {
+                interval.tick().await;
+                offline_alerts::check(&offline_alerts_db).await;
+            }
+        });
+
+        // 自助终端签到检查任务，签到间隔较短，检查频率高于离线告警
+        let kiosk_fleet_db = enterprise_db.clone();
+        tokio::spawn(async move {
+            let mut interval = interval(Duration::from_secs(kiosk_fleet::CHECK_INTERVAL_SECS));
+            loop {
+                interval.tick().await;
+                kiosk_fleet::check(&kiosk_fleet_db).await;
+            }
+        });
+
+        // 每周汇总报告，按用户通知偏好投递
+        let weekly_report_db = enterprise_db.clone();
+        tokio::spawn(async move {
+            let mut interval = interval(Duration::from_secs(3600));
+            loop {
+                interval.tick().await;
+                notifications::weekly_report(&weekly_report_db).await;
+            }
+        });
+
+        // 设备离线检测任务，记录在线率
+        let uptime_db = enterprise_db.clone();
+        tokio::spawn(async move {
+            let mut interval = interval(Duration::from_secs(30));
+            loop {
+                interval.tick().await;
+                uptime::sweep(&uptime_db).await;
+            }
+        });
+
+        // 清理过期的连接质量采样、编码推荐和密钥下发记录
+        let quality_db = enterprise_db.clone();
+        tokio::spawn(async move {
+            let mut interval = interval(Duration::from_secs(3600));
+            loop {
+                interval.tick().await;
+                connection_quality::prune(&quality_db).await;
+                codec_recommendation::prune(&quality_db).await;
+                e2e_signaling::prune(&quality_db).await;
+            }
+        });
+
+        // 采集本机网络统计，供 /metrics 和仪表盘使用
+        host_stats::start();
+
+        // 检查数据库连接池争用
+        let pool_db = enterprise_db.clone();
+        db_pool_metrics::start(move || pool_db.pool_status());
+
         // 启动Web管理界面
         let web_state = AppState {
             db: enterprise_db,
@@ -220,7 +667,7 @@ impl EnterpriseRendezvousServer {
                 .await
                 .expect("Failed to bind web server");
             log::info!("Web management interface started on port {}", web_port);
-            axum::serve(web_listener, web_app)
+            axum::serve(web_listener, web_app.into_make_service_with_connect_info::<SocketAddr>())
                 .await
                 .expect("Web server failed");
         });
@@ -252,6 +699,27 @@ impl EnterpriseRendezvousServer {
             }
         );
         
+        // 实验性 QUIC 监听，默认使用 21115/udp
+        if quic::enabled() {
+            let quic_port = std::env::var("QUIC_PORT")
+                .ok()
+                .and_then(|p| p.parse().ok())
+                .unwrap_or(nat_port as u16);
+            let quic_rs = rs.clone();
+            let quic_key = key.clone();
+            tokio::spawn(async move {
+                let res = quic::serve(quic_port, move |stream, addr| {
+                    let mut rs = quic_rs.clone();
+                    let key = quic_key.clone();
+                    async move { rs.handle_quic(stream, addr, &key).await }
+                })
+                .await;
+                if let Err(err) = res {
+                    log::error!("QUIC listener failed: {}", err);
+                }
+            });
+        }
+
         let main_task = async move {
             loop {
                 log::info!("Enterprise Server Start");
@@ -316,7 +784,8 @@ impl EnterpriseRendezvousServer {
                 }
                 Some(data) = rx.recv() => {
                     match data {
-                        Data::Msg(msg, addr) => { allow_err!(socket.send(msg.as_ref(), addr).await); }
+                        Data::Msg(msg, addr) => { allow_err!(send_udp(socket, msg.as_ref(), addr).await); }
+                        Data::Quic(bytes, addr) => { allow_err!(self.handle_udp(&bytes, addr, socket, key).await); }
                         Data::RelayServers0(rs) => { self.parse_relay_servers(&rs); }
                         Data::RelayServers(rs) => { self.relay_servers = Arc::new(rs); }
                     }
@@ -467,38 +936,68 @@ impl EnterpriseRendezvousServer {
         socket: &mut FramedSocket,
         key: &str,
     ) -> ResultType<()> {
+        // 触发诱饵ID后被封禁的来源IP，丢弃其所有消息
+        if honeypot::is_ip_banned(addr.ip()).await {
+            return Ok(());
+        }
         if let Ok(msg_in) = RendezvousMessage::parse_from_bytes(bytes) {
+            protocol_capture::inbound(&msg_in, addr, "udp").await;
             match msg_in.union {
                 Some(rendezvous_message::Union::RegisterPeer(rp)) => {
                     if !rp.id.is_empty() {
+                        if device_ban::is_banned(&rp.id).await {
+                            log::debug!("Banned peer {} registration ignored from {}", rp.id, addr);
+                            return Ok(());
+                        }
+                        if honeypot::is_decoy(&rp.id).await {
+                            log::warn!("Peer registration with honeypot id {} ignored from {}", rp.id, addr);
+                            return Ok(());
+                        }
+                        if !self.check_ip_blocker(&addr.ip().to_string(), &rp.id).await {
+                            return Ok(());
+                        }
+                        if !license::admits_device(&self.enterprise_db, &rp.id).await {
+                            return Ok(());
+                        }
                         log::trace!("New peer registered: {:?} {:?}", &rp.id, &addr);
                         
                         // 企业级功能：设备注册时记录设备信息
                         let device_info = DeviceInfo {
                             id: rp.id.clone(),
-                            name: rp.id.clone(), // 可以从客户端获取更详细的名称
-                            os: "Unknown".to_string(), // 可以从客户端获取
-                            version: "Unknown".to_string(),
+                            // 名称、系统和版本由客户端上报 /api/sysinfo 后填充，已存在的设备不会被覆盖
+                            name: rp.id.clone(),
+                            os: String::new(),
+                            version: String::new(),
                             ip_address: addr.ip().to_string(),
+                            ipv6_address: if is_ipv6(&addr) {
+                                Some(addr.ip().to_string())
+                            } else {
+                                None
+                            },
                             mac_address: None,
                             last_online: SystemTime::now(),
                             owner_id: "system".to_string(), // 默认系统拥有，可以后续分配
                             group_ids: vec![],
                             enabled: true,
                             tags: vec![],
+                            alias: None,
                         };
                         
-                        let _ = self.enterprise_db.register_device(&device_info).await;
+                        if self.enterprise_db.register_device(&device_info).await.is_ok() {
+                            uptime::mark_online(&self.enterprise_db, &rp.id).await;
+                        }
                         
                         self.update_addr(rp.id, addr, socket).await?;
-                        if self.inner.serial > rp.serial {
+                        // 密码策略等企业配置变更会提升序号，触发客户端刷新配置
+                        let serial = password_policy::config_serial(self.inner.serial);
+                        if serial > rp.serial {
                             let mut msg_out = RendezvousMessage::new();
                             msg_out.set_configure_update(ConfigUpdate {
-                                serial: self.inner.serial,
+                                serial,
                                 rendezvous_servers: (*self.rendezvous_servers).clone(),
                                 ..Default::default()
                             });
-                            socket.send(&msg_out, addr).await?;
+                            send_udp(socket, &msg_out, addr).await?;
                         }
                     }
                 }
@@ -508,12 +1007,28 @@ impl EnterpriseRendezvousServer {
                     }
                     let id = rk.id;
                     let ip = addr.ip().to_string();
+                    let v6 = is_ipv6(&addr);
                     
                     // 企业级IP封锁检查
                     if id.len() < 6 {
                         return send_rk_res(socket, addr, UUID_MISMATCH).await;
                     } else if !self.check_ip_blocker(&ip, &id).await {
                         return send_rk_res(socket, addr, TOO_FREQUENT).await;
+                    } else if device_ban::is_banned(&id).await
+                        || device_ban::is_banned_uuid(&base64::encode(&rk.uuid)).await
+                    {
+                        log::warn!("Banned peer {} registration rejected from {}", id, addr);
+                        return send_rk_res(socket, addr, UUID_MISMATCH).await;
+                    } else if honeypot::is_decoy(&id).await {
+                        // 诱饵ID不对应真实设备，不允许任何设备注册
+                        log::warn!("Peer registration with honeypot id {} rejected from {}", id, addr);
+                        return send_rk_res(socket, addr, UUID_MISMATCH).await;
+                    } else if !id_policy::is_allowed(&self.enterprise_db, &id).await {
+                        // 不符合ID注册策略的设备（如个人设备）拒绝注册
+                        return send_rk_res(socket, addr, INVALID_ID_FORMAT).await;
+                    } else if !license::admits_device(&self.enterprise_db, &id).await {
+                        // 超出许可证设备数的新设备拒绝注册
+                        return send_rk_res(socket, addr, INVALID_ID_FORMAT).await;
                     }
                     
                     // 其余逻辑与原版相同...
@@ -524,13 +1039,13 @@ impl EnterpriseRendezvousServer {
                             (true, false)
                         } else {
                             if peer.uuid == rk.uuid {
-                                if peer.info.ip != ip && peer.pk != rk.pk {
+                                if peer.info.ip_of(v6) != ip && peer.pk != rk.pk {
                                     log::warn!(
                                         "Peer {} ip/pk mismatch: {}/{:?} vs {}/{:?}",
                                         id,
                                         ip,
                                         rk.pk,
-                                        peer.info.ip,
+                                        peer.info.ip_of(v6),
                                         peer.pk,
                                     );
                                     drop(peer);
@@ -546,7 +1061,7 @@ impl EnterpriseRendezvousServer {
                                 drop(peer);
                                 return send_rk_res(socket, addr, UUID_MISMATCH).await;
                             }
-                            let ip_changed = peer.info.ip != ip;
+                            let ip_changed = peer.info.ip_of(v6) != ip;
                             (
                                 peer.uuid != rk.uuid || peer.pk != rk.pk || ip_changed,
                                 ip_changed,
@@ -555,6 +1070,17 @@ impl EnterpriseRendezvousServer {
                     };
                     
                     if changed {
+                        // 已知设备更换公钥时告警，启用隔离时审批前拒绝注册
+                        let previous = peer.read().await.pk.clone();
+                        if !pk_pinning::verify(&self.enterprise_db, &id, &rk.uuid, &previous, &rk.pk, &ip).await {
+                            log::warn!("Peer {} registration with unapproved pk rejected from {}", id, addr);
+                            return send_rk_res(socket, addr, UUID_MISMATCH).await;
+                        }
+                        // 记录uuid，供客户端心跳接口校验设备身份
+                        let uuid = base64::encode(&rk.uuid);
+                        if let Err(err) = self.enterprise_db.update_device_uuid(&id, &uuid).await {
+                            log::error!("Failed to save uuid of {}: {}", id, err);
+                        }
                         self.pm.update_pk(id, peer, addr, rk.uuid, rk.pk, ip).await;
                     }
                     
@@ -563,21 +1089,30 @@ impl EnterpriseRendezvousServer {
                         result: register_pk_response::Result::OK.into(),
                         ..Default::default()
                     });
-                    socket.send(&msg_out, addr).await?
+                    send_udp(socket, &msg_out, addr).await?
                 }
                 Some(rendezvous_message::Union::PunchHoleRequest(ph)) => {
                     // 企业级权限检查
-                    if !key.is_empty() && ph.licence_key != key {
+                    if !key.is_empty() && !server_key::accepts_licence(&ph.licence_key, key).await {
+                        nat_diagnostics::record(
+                            None,
+                            PunchOutcome::Failed,
+                            nat_type_of(&ph),
+                            &addr.to_string(),
+                            Some("license mismatch"),
+                        )
+                        .await;
                         let mut msg_out = RendezvousMessage::new();
                         msg_out.set_punch_hole_response(PunchHoleResponse {
                             failure: punch_hole_response::Failure::LICENSE_MISMATCH.into(),
                             ..Default::default()
                         });
-                        socket.send(&msg_out, addr).await?;
+                        send_udp(socket, &msg_out, addr).await?;
                         return Ok(());
                     }
                     
-                    if self.pm.is_in_memory(&ph.id).await {
+                    // 双人审批可能挂起请求，不能在 UDP 主循环中等待
+                    if self.pm.is_in_memory(&ph.id).await && !four_eyes::is_enabled().await {
                         self.handle_udp_punch_hole_request(addr, ph, key).await?;
                     } else {
                         let mut me = self.clone();
@@ -587,6 +1122,35 @@ impl EnterpriseRendezvousServer {
                         });
                     }
                 }
+                Some(rendezvous_message::Union::PunchHoleSent(phs)) => {
+                    self.handle_hole_sent(phs, addr, Some(socket)).await?;
+                }
+                Some(rendezvous_message::Union::LocalAddr(la)) => {
+                    self.handle_local_addr(la, addr, Some(socket)).await?;
+                }
+                Some(rendezvous_message::Union::SoftwareUpdate(su)) => {
+                    // 客户端在 url 字段中上报自身版本，有该平台的托管安装包时返回其地址
+                    let hosted = software_update::hosted_for_ip(&self.enterprise_db, &addr.ip().to_string()).await;
+                    if let Some(url) = version_policy::software_update_url(&su.url, hosted).await {
+                        let mut msg_out = RendezvousMessage::new();
+                        msg_out.set_software_update(SoftwareUpdate {
+                            url,
+                            ..Default::default()
+                        });
+                        send_udp(socket, &msg_out, addr).await?;
+                    }
+                }
+                Some(rendezvous_message::Union::TestNatRequest(tar)) => {
+                    // 连通性自检的 UDP 探测，原样回复看到的源端口
+                    if self_test::probe(tar.serial, addr, Probe::Udp).await {
+                        let mut msg_out = RendezvousMessage::new();
+                        msg_out.set_test_nat_response(TestNatResponse {
+                            port: addr.port() as _,
+                            ..Default::default()
+                        });
+                        send_udp(socket, &msg_out, addr).await?;
+                    }
+                }
                 _ => {
                     // 其他消息类型的处理保持与原版相同
                 }
@@ -595,6 +1159,173 @@ impl EnterpriseRendezvousServer {
         Ok(())
     }
 
+    async fn handle_tcp(
+        &mut self,
+        bytes: &[u8],
+        sink: &mut Option<Sink>,
+        addr: SocketAddr,
+        key: &str,
+        ws: bool,
+    ) -> bool {
+        if honeypot::is_ip_banned(addr.ip()).await {
+            return false;
+        }
+        if let Ok(msg_in) = RendezvousMessage::parse_from_bytes(bytes) {
+            protocol_capture::inbound(&msg_in, addr, if ws { "ws" } else { "tcp" }).await;
+            match msg_in.union {
+                Some(rendezvous_message::Union::PunchHoleRequest(ph)) => {
+                    if let Some(sink) = sink.take() {
+                        self.tcp_punch.lock().await.insert(try_into_v4(addr), sink);
+                    }
+                    allow_err!(self.handle_tcp_punch_hole_request(addr, ph, key, ws).await);
+                    return true;
+                }
+                Some(rendezvous_message::Union::RequestRelay(mut rf)) => {
+                    if let Some(sink) = sink.take() {
+                        self.tcp_punch.lock().await.insert(try_into_v4(addr), sink);
+                    }
+                    if honeypot::is_decoy(&rf.id).await {
+                        let controller = self.auth_manager.verify_jwt(&rf.token).ok();
+                        honeypot::trip(
+                            &self.enterprise_db,
+                            &rf.id,
+                            addr,
+                            "relay",
+                            controller.as_ref().map(|c| c.sub.clone()),
+                            vec![
+                                ("username", controller.map(|c| c.username).unwrap_or_default()),
+                                ("relay_server", rf.relay_server.clone()),
+                                ("websocket", ws.to_string()),
+                            ],
+                        )
+                        .await;
+                        return true;
+                    }
+                    if device_ban::is_banned(&rf.id).await
+                        || pk_pinning::is_quarantined(&rf.id).await
+                        || version_policy::is_blocked(&self.enterprise_db, &rf.id).await
+                        || unattended_access::is_refused(&self.enterprise_db, &rf.id).await
+                    {
+                        return true;
+                    }
+                    relay_sessions::record(&rf.uuid, &rf.relay_server, &rf.id).await;
+                    // 携带登录令牌的中继请求登记为连接会话，客户端上报的会话事件按 uuid 关联
+                    if !rf.uuid.is_empty() {
+                        if let Ok(claims) = self.auth_manager.verify_jwt(&rf.token) {
+                            match self
+                                .enterprise_db
+                                .start_connection_session(&rf.uuid, &claims.sub, &rf.id, "relay")
+                                .await
+                            {
+                                // 同一会话的重复请求不重复计入最近连接
+                                Ok(true) => favorites::record(&self.enterprise_db, &claims.sub, &rf.id).await,
+                                Ok(false) => {}
+                                Err(err) => {
+                                    log::error!("Failed to record connection session {}: {}", rf.uuid, err);
+                                }
+                            }
+                        }
+                    }
+                    // 打洞失败后的中继请求，计入中继回退统计
+                    let registered = self.pm.get_in_memory(&rf.id).await.is_some();
+                    nat_diagnostics::record(
+                        registered.then_some(rf.id.as_str()),
+                        PunchOutcome::RelayFallback,
+                        "unknown",
+                        &addr.to_string(),
+                        None,
+                    )
+                    .await;
+                    if let Some(peer) = self.pm.get_in_memory(&rf.id).await {
+                        let mut msg_out = RendezvousMessage::new();
+                        rf.socket_addr = AddrMangle::encode(addr).into();
+                        msg_out.set_request_relay(rf);
+                        let peer_addr = peer.read().await.socket_addr;
+                        self.tx.send(Data::Msg(msg_out.into(), peer_addr)).ok();
+                    } else if let Some(home) = federation::locate(&rf.id).await {
+                        // 设备在联邦中的其他区域，中继请求经其所在区域转发
+                        let requester = self.auth_manager.verify_jwt(&rf.token).ok().map(|c| c.username);
+                        let id = rf.id.clone();
+                        let mut msg_out = RendezvousMessage::new();
+                        rf.socket_addr = AddrMangle::encode(addr).into();
+                        msg_out.set_request_relay(rf);
+                        tokio::spawn(async move {
+                            let res = federation::forward(&home, &id, addr, requester.as_deref(), &msg_out).await;
+                            if let Err(err) = res {
+                                log::warn!("Failed to forward relay request {} to {}: {}", id, home.region, err);
+                            }
+                        });
+                    }
+                    return true;
+                }
+                Some(rendezvous_message::Union::RelayResponse(mut rr)) => {
+                    let addr_b = AddrMangle::decode(&rr.socket_addr);
+                    rr.socket_addr = Default::default();
+                    let id = rr.id();
+                    relay_sessions::record(&rr.uuid, &rr.relay_server, id).await;
+                    if !id.is_empty() {
+                        let id = id.to_owned();
+                        let (pk, offer) = self.get_pk(&rr.version, id.clone()).await;
+                        if !e2e_signaling::record(
+                            &self.enterprise_db,
+                            &rr.uuid,
+                            &id,
+                            &addr_b.to_string(),
+                            "relay",
+                            offer,
+                        )
+                        .await
+                        {
+                            return false;
+                        }
+                        rr.set_pk(pk);
+                    }
+                    let mut msg_out = RendezvousMessage::new();
+                    if !rr.relay_server.is_empty() {
+                        if let Some(site) = lan_config::site_of(addr_b).await {
+                            rr.relay_server = site.local_ip;
+                        } else if lan_config::is_local_ip(&rr.relay_server).await {
+                            rr.relay_server = self.get_relay_server(addr.ip(), addr_b.ip());
+                        }
+                    }
+                    msg_out.set_relay_response(rr);
+                    if federation::return_to_origin(addr_b, &msg_out).await {
+                        return false;
+                    }
+                    allow_err!(self.send_to_tcp_sync(msg_out, addr_b).await);
+                }
+                Some(rendezvous_message::Union::PunchHoleSent(phs)) => {
+                    allow_err!(self.handle_hole_sent(phs, addr, None).await);
+                }
+                Some(rendezvous_message::Union::LocalAddr(la)) => {
+                    allow_err!(self.handle_local_addr(la, addr, None).await);
+                }
+                Some(rendezvous_message::Union::TestNatRequest(tar)) => {
+                    if !ws {
+                        self_test::probe(tar.serial, addr, Probe::Tcp).await;
+                    }
+                    let mut msg_out = RendezvousMessage::new();
+                    let mut res = TestNatResponse {
+                        port: addr.port() as _,
+                        ..Default::default()
+                    };
+                    let serial = password_policy::config_serial(self.inner.serial);
+                    if serial > tar.serial {
+                        let mut cu = ConfigUpdate::new();
+                        cu.serial = serial;
+                        cu.rendezvous_servers = (*self.rendezvous_servers).clone();
+                        res.cu = MessageField::from_option(Some(cu));
+                    }
+                    msg_out.set_test_nat_response(res);
+                    protocol_capture::outbound(&msg_out, addr, if ws { "ws" } else { "tcp" }).await;
+                    Self::send_to_sink(sink, msg_out).await;
+                }
+                _ => {}
+            }
+        }
+        false
+    }
+
     // 其他方法保持与原版相似，但添加企业级功能...
     // 为了节省空间，这里只展示关键的企业级增强部分
 
@@ -626,33 +1357,636 @@ impl EnterpriseRendezvousServer {
         (key, out_sk)
     }
 
-    // 简化的方法实现 - 实际应用中需要完整实现
-    async fn update_addr(&mut self, id: String, addr: SocketAddr, socket: &mut FramedSocket) -> ResultType<()> {
-        // 简化实现
-        Ok(())
+    async fn update_addr(
+        &mut self,
+        id: String,
+        socket_addr: SocketAddr,
+        socket: &mut FramedSocket,
+    ) -> ResultType<()> {
+        let v6 = is_ipv6(&socket_addr);
+        let (request_pk, ip_change) = if let Some(old) = self.pm.get_in_memory(&id).await {
+            let mut old = old.write().await;
+            let ip = socket_addr.ip();
+            // 双栈设备交替使用IPv4/IPv6注册，只与同一地址族的旧地址比较
+            let ip_change = if let Some(prev) = old.fresh_addr(v6) {
+                ip != prev.ip()
+            } else if old.socket_addr.port() != 0 && is_ipv6(&old.socket_addr) == v6 {
+                ip != old.socket_addr.ip()
+            } else {
+                ip.to_string() != old.info.ip_of(v6)
+            } && !ip.is_loopback();
+            let request_pk = old.pk.is_empty() || ip_change;
+            if !request_pk {
+                old.socket_addr = socket_addr;
+                old.observe_addr(socket_addr);
+                old.last_reg_time = Instant::now();
+            }
+            let ip_change = if ip_change && old.reg_pk.0 <= 2 {
+                Some(old.info.ip_of(v6).to_owned())
+            } else {
+                None
+            };
+            (request_pk, ip_change)
+        } else {
+            (true, None)
+        };
+        if let Some(old) = ip_change {
+            log::info!("IP change of {} from {} to {}", id, old, socket_addr);
+        }
+        let mut msg_out = RendezvousMessage::new();
+        msg_out.set_register_peer_response(RegisterPeerResponse {
+            request_pk,
+            ..Default::default()
+        });
+        send_udp(socket, &msg_out, socket_addr).await
     }
 
     async fn check_ip_blocker(&self, ip: &str, id: &str) -> bool {
-        // 简化实现 - 实际应该使用企业级IP封锁逻辑
-        true
+        // 来源地址在威胁情报列表中时拒绝注册
+        let ip = match ip.parse::<IpAddr>() {
+            Ok(ip) => ip,
+            Err(_) => return true,
+        };
+        match threat_intel::lookup(ip, threat_intel::Check::Registration).await {
+            Some(feed) => {
+                log::warn!("Registration of {} from {} rejected, listed in threat intel feed {}", id, ip, feed);
+                false
+            }
+            None => true,
+        }
+    }
+
+    async fn handle_hole_sent<'a>(
+        &mut self,
+        phs: PunchHoleSent,
+        addr: SocketAddr,
+        socket: Option<&'a mut FramedSocket>,
+    ) -> ResultType<()> {
+        // 被控端B已发送打洞包，通知控制端A可以连接
+        let addr_a = AddrMangle::decode(&phs.socket_addr);
+        log::debug!(
+            "{} punch hole response to {:?} from {:?}",
+            if socket.is_none() { "TCP" } else { "UDP" },
+            &addr_a,
+            &addr
+        );
+        let nat_type = phs
+            .nat_type
+            .enum_value()
+            .map(nat_type_label)
+            .unwrap_or("unknown");
+        let registered = self.pm.get_in_memory(&phs.id).await.is_some();
+        nat_diagnostics::record(
+            registered.then_some(phs.id.as_str()),
+            PunchOutcome::Responded,
+            nat_type,
+            &addr.to_string(),
+            None,
+        )
+        .await;
+        let (pk, offer) = self.get_pk(&phs.version, phs.id.clone()).await;
+        if !e2e_signaling::record(&self.enterprise_db, "", &phs.id, &addr_a.to_string(), "punch", offer).await {
+            return Ok(());
+        }
+        let mut msg_out = RendezvousMessage::new();
+        let mut p = PunchHoleResponse {
+            socket_addr: AddrMangle::encode(addr).into(),
+            pk,
+            relay_server: phs.relay_server.clone(),
+            ..Default::default()
+        };
+        if let Ok(t) = phs.nat_type.enum_value() {
+            p.set_nat_type(t);
+        }
+        msg_out.set_punch_hole_response(p);
+        // 联邦中其他区域转发来的请求，响应送回控制端所在区域
+        if federation::return_to_origin(addr_a, &msg_out).await {
+            return Ok(());
+        }
+        if let Some(socket) = socket {
+            send_udp(socket, &msg_out, addr_a).await?;
+        } else {
+            self.send_to_tcp(msg_out, addr_a).await;
+        }
+        Ok(())
+    }
+
+    async fn handle_local_addr<'a>(
+        &mut self,
+        la: LocalAddr,
+        addr: SocketAddr,
+        socket: Option<&'a mut FramedSocket>,
+    ) -> ResultType<()> {
+        // 将B的内网地址转发给A
+        let addr_a = AddrMangle::decode(&la.socket_addr);
+        log::debug!(
+            "{} local addrs response to {:?} from {:?}",
+            if socket.is_none() { "TCP" } else { "UDP" },
+            &addr_a,
+            &addr
+        );
+        let registered = self.pm.get_in_memory(&la.id).await.is_some();
+        nat_diagnostics::record(
+            registered.then_some(la.id.as_str()),
+            PunchOutcome::Responded,
+            "lan",
+            &addr.to_string(),
+            None,
+        )
+        .await;
+        let (pk, offer) = self.get_pk(&la.version, la.id.clone()).await;
+        if !e2e_signaling::record(&self.enterprise_db, "", &la.id, &addr_a.to_string(), "local", offer).await {
+            return Ok(());
+        }
+        let mut msg_out = RendezvousMessage::new();
+        let mut p = PunchHoleResponse {
+            socket_addr: la.local_addr.clone(),
+            pk,
+            relay_server: la.relay_server,
+            ..Default::default()
+        };
+        p.set_is_local(true);
+        msg_out.set_punch_hole_response(p);
+        // 联邦中其他区域转发来的请求，响应送回控制端所在区域
+        if federation::return_to_origin(addr_a, &msg_out).await {
+            return Ok(());
+        }
+        if let Some(socket) = socket {
+            send_udp(socket, &msg_out, addr_a).await?;
+        } else {
+            self.send_to_tcp(msg_out, addr_a).await;
+        }
+        Ok(())
+    }
+
+    async fn handle_punch_hole_request(
+        &mut self,
+        addr: SocketAddr,
+        ph: PunchHoleRequest,
+        key: &str,
+        ws: bool,
+    ) -> ResultType<Option<(RendezvousMessage, Option<SocketAddr>)>> {
+        let mut ph = ph;
+        let requester = addr.to_string();
+        let nat_type = nat_type_of(&ph);
+        if !key.is_empty() && !server_key::accepts_licence(&ph.licence_key, key).await {
+            nat_diagnostics::record(None, PunchOutcome::Failed, nat_type, &requester, Some("license mismatch")).await;
+            let mut msg_out = RendezvousMessage::new();
+            msg_out.set_punch_hole_response(PunchHoleResponse {
+                failure: punch_hole_response::Failure::LICENSE_MISMATCH.into(),
+                ..Default::default()
+            });
+            return Ok(Some((msg_out, None)));
+        }
+        // 开启别名打洞时，客户端可以用别名（如 FINANCE-PC-07）代替数字ID发起连接
+        let id = peer_alias::resolve_punch_id(&ph.id).await;
+        // 诱饵ID: 记录请求方信息并告警，对请求方表现为离线设备
+        if honeypot::is_decoy(&id).await {
+            let controller = self.auth_manager.verify_jwt(&ph.token).ok();
+            honeypot::trip(
+                &self.enterprise_db,
+                &id,
+                addr,
+                "punch_hole",
+                controller.as_ref().map(|c| c.sub.clone()),
+                vec![
+                    ("username", controller.map(|c| c.username).unwrap_or_default()),
+                    ("nat_type", nat_type.to_owned()),
+                    ("version", ph.version.clone()),
+                    ("websocket", ws.to_string()),
+                ],
+            )
+            .await;
+            let mut msg_out = RendezvousMessage::new();
+            msg_out.set_punch_hole_response(PunchHoleResponse {
+                failure: punch_hole_response::Failure::OFFLINE.into(),
+                ..Default::default()
+            });
+            return Ok(Some((msg_out, None)));
+        }
+        // 被封禁的设备对控制端表现为ID不存在
+        let banned = device_ban::is_banned(&id).await;
+        let peer = if banned { None } else { self.pm.get(&id).await };
+        let registered = peer.is_some().then_some(id.as_str());
+        nat_diagnostics::record(registered, PunchOutcome::Attempt, nat_type, &requester, None).await;
+        if let Some(peer) = peer {
+            let (elapsed, peer_addr) = {
+                let r = peer.read().await;
+                // 双栈对端优先选择与请求方相同地址族的地址，两端都支持IPv6时走IPv6打洞
+                let peer_addr = r
+                    .fresh_addr(is_ipv6(&addr))
+                    .unwrap_or(r.socket_addr);
+                (r.last_reg_time.elapsed().as_millis() as i32, peer_addr)
+            };
+            if elapsed >= network_tuning::reg_timeout_ms().await {
+                nat_diagnostics::record(Some(&id), PunchOutcome::Failed, nat_type, &requester, Some("peer offline")).await;
+                let mut msg_out = RendezvousMessage::new();
+                msg_out.set_punch_hole_response(PunchHoleResponse {
+                    failure: punch_hole_response::Failure::OFFLINE.into(),
+                    ..Default::default()
+                });
+                return Ok(Some((msg_out, None)));
+            }
+            // 版本过低且策略禁止连接的设备，升级前对控制端表现为离线
+            if version_policy::is_blocked(&self.enterprise_db, &id).await {
+                nat_diagnostics::record(Some(&id), PunchOutcome::Failed, nat_type, &requester, Some("client update required")).await;
+                let mut msg_out = RendezvousMessage::new();
+                msg_out.set_punch_hole_response(PunchHoleResponse {
+                    failure: punch_hole_response::Failure::OFFLINE.into(),
+                    ..Default::default()
+                });
+                return Ok(Some((msg_out, None)));
+            }
+            // 公钥变化待审批的设备隔离期间对控制端表现为离线
+            if pk_pinning::is_quarantined(&id).await {
+                nat_diagnostics::record(Some(&id), PunchOutcome::Failed, nat_type, &requester, Some("pk change pending approval")).await;
+                let mut msg_out = RendezvousMessage::new();
+                msg_out.set_punch_hole_response(PunchHoleResponse {
+                    failure: punch_hole_response::Failure::OFFLINE.into(),
+                    ..Default::default()
+                });
+                return Ok(Some((msg_out, None)));
+            }
+            // 无人值守访问时段外拒绝建立会话
+            if unattended_access::is_refused(&self.enterprise_db, &id).await {
+                nat_diagnostics::record(Some(&id), PunchOutcome::Failed, nat_type, &requester, Some("outside unattended access window")).await;
+                let mut msg_out = RendezvousMessage::new();
+                msg_out.set_punch_hole_response(PunchHoleResponse {
+                    failure: punch_hole_response::Failure::OFFLINE.into(),
+                    ..Default::default()
+                });
+                return Ok(Some((msg_out, None)));
+            }
+            // 敏感设备需另一名管理员审批后才返回打洞响应，控制端登录令牌用于识别请求人；
+            // 持有该设备紧急访问授权的用户跳过审批
+            let controller = self.auth_manager.verify_jwt(&ph.token).ok();
+            let emergency = match &controller {
+                Some(c) => break_glass::is_active(&self.enterprise_db, &id, &c.sub).await,
+                None => false,
+            };
+            if !emergency && !four_eyes::authorize(
+                &self.enterprise_db,
+                &id,
+                controller.as_ref().map(|c| c.sub.as_str()),
+                controller.as_ref().map(|c| c.username.as_str()),
+                &requester,
+            )
+            .await
+            {
+                nat_diagnostics::record(Some(&id), PunchOutcome::Failed, nat_type, &requester, Some("session approval required")).await;
+                let mut msg_out = RendezvousMessage::new();
+                msg_out.set_punch_hole_response(PunchHoleResponse {
+                    failure: punch_hole_response::Failure::OFFLINE.into(),
+                    ..Default::default()
+                });
+                return Ok(Some((msg_out, None)));
+            }
+            let mut msg_out = RendezvousMessage::new();
+            let peer_site = lan_config::site_of(peer_addr).await;
+            let site = lan_config::site_of(addr).await;
+            // 同一站点内优先局域网直连；跨站点或局域网/公网混合时强制中继
+            let same_site = peer_site.is_some() && peer_site == site;
+            let cross_site = peer_site != site;
+            let mut relay_server = self.get_relay_server(addr.ip(), peer_addr.ip());
+            let policy_relay = relay_policy::should_force_relay(&self.enterprise_db, &id).await;
+            if ALWAYS_USE_RELAY.load(Ordering::SeqCst) || policy_relay || cross_site {
+                if let Some(peer_site) = &peer_site {
+                    // 两端位于不同站点时，对端站点的内网地址不可达，使用公网中继
+                    if site.is_none() || same_site {
+                        relay_server = peer_site.local_ip.clone();
+                    }
+                }
+                ph.nat_type = NatType::SYMMETRIC.into(); // 强制中继
+                nat_diagnostics::record(Some(&id), PunchOutcome::ForcedRelay, nat_type, &requester, None).await;
+            }
+            let same_intranet: bool = !ws
+                && (same_site || {
+                    match (peer_addr, addr) {
+                        (SocketAddr::V4(a), SocketAddr::V4(b)) => a.ip() == b.ip(),
+                        (SocketAddr::V6(a), SocketAddr::V6(b)) => a.ip() == b.ip(),
+                        _ => false,
+                    }
+                });
+            let socket_addr = AddrMangle::encode(addr).into();
+            if same_intranet {
+                log::debug!("Fetch local addr {:?} {:?} request from {:?}", id, peer_addr, addr);
+                msg_out.set_fetch_local_addr(FetchLocalAddr {
+                    socket_addr,
+                    relay_server,
+                    ..Default::default()
+                });
+            } else {
+                log::debug!("Punch hole {:?} {:?} request from {:?}", id, peer_addr, addr);
+                msg_out.set_punch_hole(PunchHole {
+                    socket_addr,
+                    nat_type: ph.nat_type,
+                    relay_server,
+                    ..Default::default()
+                });
+            }
+            Ok(Some((msg_out, Some(peer_addr))))
+        } else {
+            // 本区域没有该设备时查询联邦中的其他区域
+            if !banned {
+                if let Some(home) = federation::locate(&id).await {
+                    return self.forward_punch_hole(&home, &id, addr, ph).await;
+                }
+            }
+            nat_diagnostics::record(None, PunchOutcome::Failed, nat_type, &requester, Some("id not exist")).await;
+            let mut msg_out = RendezvousMessage::new();
+            msg_out.set_punch_hole_response(PunchHoleResponse {
+                failure: punch_hole_response::Failure::ID_NOT_EXIST.into(),
+                ..Default::default()
+            });
+            Ok(Some((msg_out, None)))
+        }
+    }
+
+    // 设备在联邦中的其他区域时，打洞请求经其所在区域的信令服务器发给设备，响应随后由该区域送回；
+    // 转发成功时返回 None，控制端的连接保留到响应送达
+    async fn forward_punch_hole(
+        &mut self,
+        home: &FederationPeer,
+        id: &str,
+        addr: SocketAddr,
+        mut ph: PunchHoleRequest,
+    ) -> ResultType<Option<(RendezvousMessage, Option<SocketAddr>)>> {
+        let requester = addr.to_string();
+        let nat_type = nat_type_of(&ph);
+        let policy_relay = relay_policy::should_force_relay(&self.enterprise_db, id).await;
+        if ALWAYS_USE_RELAY.load(Ordering::SeqCst) || policy_relay {
+            ph.nat_type = NatType::SYMMETRIC.into();
+        }
+        // 优先使用设备所在区域的中继
+        let relay_server = match federation::relay_server(home) {
+            Some(relay_server) => relay_server,
+            None => self.get_relay_server(addr.ip(), addr.ip()),
+        };
+        let mut msg_out = RendezvousMessage::new();
+        msg_out.set_punch_hole(PunchHole {
+            socket_addr: AddrMangle::encode(addr).into(),
+            nat_type: ph.nat_type,
+            relay_server,
+            ..Default::default()
+        });
+        let controller = self.auth_manager.verify_jwt(&ph.token).ok();
+        log::debug!("Forward punch hole {:?} request from {:?} to region {}", id, addr, home.region);
+        let username = controller.as_ref().map(|c| c.username.as_str());
+        match federation::forward(home, id, addr, username, &msg_out).await {
+            Ok(true) => return Ok(None),
+            Ok(false) => {}
+            Err(err) => log::warn!("Failed to forward punch hole {} to {}: {}", id, home.region, err),
+        }
+        nat_diagnostics::record(None, PunchOutcome::Failed, nat_type, &requester, Some("peer offline")).await;
+        let mut msg_out = RendezvousMessage::new();
+        msg_out.set_punch_hole_response(PunchHoleResponse {
+            failure: punch_hole_response::Failure::OFFLINE.into(),
+            ..Default::default()
+        });
+        Ok(Some((msg_out, None)))
+    }
+
+    // 在线设备的地址，供联邦中的其他区域查询和转发
+    async fn online_addr(pm: &PeerMap, id: &str) -> Option<SocketAddr> {
+        let peer = pm.get_in_memory(id).await?;
+        let r = peer.read().await;
+        if r.last_reg_time.elapsed().as_millis() as i32 >= network_tuning::reg_timeout_ms().await {
+            return None;
+        }
+        Some(r.socket_addr)
     }
 
     async fn handle_udp_punch_hole_request(&mut self, addr: SocketAddr, ph: PunchHoleRequest, key: &str) -> ResultType<()> {
-        // 简化实现
+        if let Some((msg, to_addr)) = self.handle_punch_hole_request(addr, ph, key, false).await? {
+            self.tx.send(Data::Msg(msg.into(), to_addr.unwrap_or(addr)))?;
+        }
+        Ok(())
+    }
+
+    async fn handle_tcp_punch_hole_request(
+        &mut self,
+        addr: SocketAddr,
+        ph: PunchHoleRequest,
+        key: &str,
+        ws: bool,
+    ) -> ResultType<()> {
+        match self.handle_punch_hole_request(addr, ph, key, ws).await? {
+            Some((msg, Some(addr))) => self.tx.send(Data::Msg(msg.into(), addr))?,
+            Some((msg, None)) => self.send_to_tcp_sync(msg, addr).await?,
+            None => {}
+        }
         Ok(())
     }
 
+    async fn send_to_tcp(&mut self, msg: RendezvousMessage, addr: SocketAddr) {
+        protocol_capture::outbound(&msg, addr, "tcp").await;
+        let mut tcp = self.tcp_punch.lock().await.remove(&try_into_v4(addr));
+        tokio::spawn(async move {
+            Self::send_to_sink(&mut tcp, msg).await;
+        });
+    }
+
+    async fn send_to_tcp_sync(&mut self, msg: RendezvousMessage, addr: SocketAddr) -> ResultType<()> {
+        protocol_capture::outbound(&msg, addr, "tcp").await;
+        let mut sink = self.tcp_punch.lock().await.remove(&try_into_v4(addr));
+        Self::send_to_sink(&mut sink, msg).await;
+        Ok(())
+    }
+
+    async fn send_to_sink(sink: &mut Option<Sink>, msg: RendezvousMessage) {
+        if let Some(sink) = sink.as_mut() {
+            if let Ok(bytes) = msg.write_to_bytes() {
+                match sink {
+                    Sink::TcpStream(s) => {
+                        allow_err!(s.send(Bytes::from(bytes)).await);
+                    }
+                    Sink::Ws(ws) => {
+                        allow_err!(ws.send(tungstenite::Message::Binary(bytes)).await);
+                    }
+                    Sink::Quic(tx) => {
+                        tx.send(Bytes::from(bytes)).ok();
+                    }
+                }
+            }
+        }
+    }
+
     fn parse_relay_servers(&mut self, relay_servers: &str) {
-        // 与原版相同的实现
+        let rs = get_servers(relay_servers, "relay-servers");
+        relay_sessions::set_servers(&rs);
+        self.relay_servers0 = Arc::new(rs);
+        self.relay_servers = self.relay_servers0.clone();
+    }
+
+    // 排空中的中继不分配给新会话
+    fn get_relay_server(&self, _pa: IpAddr, _pb: IpAddr) -> String {
+        let servers = relay_sessions::available(&self.relay_servers);
+        if servers.is_empty() {
+            return "".to_owned();
+        } else if servers.len() == 1 {
+            return servers[0].clone();
+        }
+        let i = ROTATION_RELAY_SERVER.fetch_add(1, Ordering::SeqCst) % servers.len();
+        servers[i].clone()
+    }
+
+    // 返回签名后的被控端公钥，以及能否据此建立端到端加密
+    async fn get_pk(&mut self, version: &str, id: String) -> (Bytes, KeyOffer) {
+        let pk = match self.pm.get(&id).await {
+            Some(peer) => Some(peer.read().await.pk.clone()),
+            None => None,
+        };
+        // 优先使用硬件签名器或密钥轮换后的当前私钥
+        let signer = match server_key::signer().await {
+            Some(signer) => Some(signer),
+            None => self
+                .inner
+                .sk
+                .clone()
+                .map(|sk| Arc::new(SoftwareSigner(sk)) as Arc<dyn Signer>),
+        };
+        let offer = KeyOffer::evaluate(version, signer.is_some(), pk.as_deref());
+        let signer = match signer {
+            Some(signer) if offer == KeyOffer::Signed => signer,
+            _ => return (Bytes::new(), offer),
+        };
+        let id_pk = hbb_common::message_proto::IdPk {
+            id,
+            pk: pk.unwrap_or_default(),
+            ..Default::default()
+        }
+        .write_to_bytes()
+        .unwrap_or_default();
+        match signer.sign(&id_pk) {
+            Ok(signed) => (signed.into(), offer),
+            Err(err) => {
+                log::error!("Failed to sign peer key with {} signer: {}", signer.name(), err);
+                (Bytes::new(), KeyOffer::NoServerKey)
+            }
+        }
     }
 
     async fn handle_listener2(&self, stream: TcpStream, addr: SocketAddr) {
-        // 与原版相同的实现
+        // NAT 测试端口只回复看到的源端口，同时记录连通性自检的探测
+        tokio::spawn(async move {
+            let mut stream = FramedStream::from(stream, addr);
+            if let Some(Ok(bytes)) = stream.next_timeout(30_000).await {
+                if let Ok(msg_in) = RendezvousMessage::parse_from_bytes(&bytes) {
+                    if let Some(rendezvous_message::Union::TestNatRequest(tar)) = msg_in.union {
+                        self_test::probe(tar.serial, addr, Probe::NatPort).await;
+                        let mut msg_out = RendezvousMessage::new();
+                        msg_out.set_test_nat_response(TestNatResponse {
+                            port: addr.port() as _,
+                            ..Default::default()
+                        });
+                        stream.send(&msg_out).await.ok();
+                    }
+                }
+            }
+        });
     }
 
     async fn handle_listener(&self, stream: TcpStream, addr: SocketAddr, key: &str, ws: bool) {
-        // 与原版相同的实现
+        log::debug!("Tcp connection from {:?}, ws: {}", addr, ws);
+        let mut rs = self.clone();
+        let key = key.to_owned();
+        tokio::spawn(async move {
+            allow_err!(rs.handle_listener_inner(stream, addr, &key, ws).await);
+        });
     }
+
+    async fn handle_listener_inner(
+        &mut self,
+        stream: TcpStream,
+        mut addr: SocketAddr,
+        key: &str,
+        ws: bool,
+    ) -> ResultType<()> {
+        let idle_timeout = network_tuning::on_accept(&stream, ws).await;
+        let mut sink;
+        if ws {
+            use tokio_tungstenite::tungstenite::handshake::server::{Request, Response};
+            let callback = |req: &Request, response: Response| {
+                let headers = req.headers();
+                let real_ip = headers
+                    .get("X-Real-IP")
+                    .or_else(|| headers.get("X-Forwarded-For"))
+                    .and_then(|header_value| header_value.to_str().ok());
+                if let Some(ip) = real_ip {
+                    if ip.contains('.') {
+                        addr = format!("{ip}:0").parse().unwrap_or(addr);
+                    } else {
+                        addr = format!("[{ip}]:0").parse().unwrap_or(addr);
+                    }
+                }
+                Ok(response)
+            };
+            let ws_stream = tokio_tungstenite::accept_hdr_async(stream, callback).await?;
+            let (a, mut b) = ws_stream.split();
+            sink = Some(Sink::Ws(a));
+            while let Ok(Some(Ok(msg))) = timeout(idle_timeout, b.next()).await {
+                if let tungstenite::Message::Binary(bytes) = msg {
+                    if !self.handle_tcp(&bytes, &mut sink, addr, key, ws).await {
+                        break;
+                    }
+                }
+            }
+        } else {
+            let (a, mut b) = Framed::new(stream, BytesCodec::new()).split();
+            sink = Some(Sink::TcpStream(a));
+            while let Ok(Some(Ok(bytes))) = timeout(idle_timeout, b.next()).await {
+                if !self.handle_tcp(&bytes, &mut sink, addr, key, ws).await {
+                    break;
+                }
+            }
+        }
+        if sink.is_none() {
+            self.tcp_punch.lock().await.remove(&try_into_v4(addr));
+        }
+        log::debug!("Tcp connection from {:?} closed", addr);
+        Ok(())
+    }
+
+    // QUIC 连接在一个流上收发全部消息，连接保持到客户端关闭或空闲超时
+    async fn handle_quic(&mut self, stream: Box<dyn quic::Duplex>, addr: SocketAddr, key: &str) {
+        log::debug!("Quic connection from {:?}", addr);
+        let (mut a, mut b) = Framed::new(stream, BytesCodec::new()).split();
+        let (tx, mut rx) = mpsc::unbounded_channel::<Bytes>();
+        tokio::spawn(async move {
+            while let Some(bytes) = rx.recv().await {
+                if a.send(bytes).await.is_err() {
+                    break;
+                }
+            }
+        });
+        quic::add_peer(addr, tx.clone());
+        while let Some(Ok(bytes)) = b.next().await {
+            let udp = matches!(
+                RendezvousMessage::parse_from_bytes(&bytes).map(|msg| msg.union),
+                Ok(Some(
+                    rendezvous_message::Union::RegisterPeer(_)
+                        | rendezvous_message::Union::RegisterPk(_)
+                        | rendezvous_message::Union::SoftwareUpdate(_)
+                ))
+            );
+            if udp {
+                self.tx.send(Data::Quic(bytes, addr)).ok();
+            } else {
+                let mut sink = Some(Sink::Quic(tx.clone()));
+                self.handle_tcp(&bytes, &mut sink, addr, key, false).await;
+            }
+        }
+        quic::remove_peer(&addr);
+        self.tcp_punch.lock().await.remove(&try_into_v4(addr));
+        log::debug!("Quic connection from {:?} closed", addr);
+    }
+}
+
+fn nat_type_of(ph: &PunchHoleRequest) -> &'static str {
+    ph.nat_type
+        .enum_value()
+        .map(nat_type_label)
+        .unwrap_or("unknown")
 }
 
 // 辅助函数
@@ -660,6 +1994,15 @@ async fn check_relay_servers(rs0: Arc<RelayServers>, tx: Sender) {
     // 与原版相同的实现
 }
 
+// 对端经 QUIC 连接时消息走它的 QUIC 流，否则走 UDP
+async fn send_udp(socket: &mut FramedSocket, msg: &RendezvousMessage, addr: SocketAddr) -> ResultType<()> {
+    protocol_capture::outbound(msg, addr, "udp").await;
+    if quic::send_to(&addr, Bytes::from(msg.write_to_bytes()?)) {
+        return Ok(());
+    }
+    socket.send(msg, addr).await
+}
+
 async fn send_rk_res(
     socket: &mut FramedSocket,
     addr: SocketAddr,
@@ -670,7 +2013,7 @@ async fn send_rk_res(
         result: res.into(),
         ..Default::default()
     });
-    socket.send(&msg_out, addr).await
+    send_udp(socket, &msg_out, addr).await
 }
 
 async fn create_udp_listener(port: i32, rmem: usize) -> ResultType<FramedSocket> {
@@ -0,0 +1,148 @@
+// 数据擦除模块 - 响应 GDPR 删除请求，按用户或设备匿名化(anonymize)或清除(purge)全部数据并生成擦除报告。
+// 审计日志始终保留，其中的主体标识替换为随机化名，IP 和 UA 清除；anonymize 模式下会话、文件传输等活动记录
+// 保留并替换标识，purge 模式下直接删除。用户记录被审计日志等外键引用，保留为停用的空壳(用户名替换为化名，
+// 邮箱、密码和 2FA 密钥清除)。报告只保存主体标识的 SHA256，日后可凭原标识核对而不保留原标识。
+// 服务器不保存会话录像(录像保存在客户端本地)，因此没有需要删除的录像文件
+use crate::auth::UserRole;
+use crate::enterprise_database::EnterpriseDatabase;
+use crate::validation::Payload;
+use hbb_common::{bail, log, ResultType};
+use schemars::JsonSchema;
+use serde_derive::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use validator::Validate;
+
+const MAX_REASON_LEN: usize = 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ErasureSubject {
+    User,
+    Device,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ErasureMode {
+    Anonymize,
+    Purge,
+}
+
+#[derive(Debug, Clone, Deserialize, JsonSchema, Validate)]
+pub struct ErasureRequest {
+    pub subject: ErasureSubject,
+    #[validate(length(min = 1, max = 128))]
+    pub id: String,
+    pub mode: ErasureMode,
+    // 请求来源，如工单号
+    #[validate(length(min = 1, max = 1000))]
+    pub reason: String,
+}
+
+impl Payload for ErasureRequest {}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct ErasureReport {
+    pub id: String,
+    pub subject: ErasureSubject,
+    pub subject_hash: String,
+    pub pseudonym: String,
+    pub mode: ErasureMode,
+    pub reason: String,
+    pub requested_by: String,
+    pub created_at: u64,
+    // 表名 -> 删除或匿名化的记录数
+    pub records: BTreeMap<String, u64>,
+}
+
+pub fn subject_hash(subject: ErasureSubject, id: &str) -> String {
+    let subject = match subject {
+        ErasureSubject::User => "user",
+        ErasureSubject::Device => "device",
+    };
+    format!("{:x}", Sha256::digest(format!("{}:{}", subject, id).as_bytes()))
+}
+
+// 随机化名，同一次擦除内一致，与原标识无关联
+fn pseudonym(subject: ErasureSubject) -> String {
+    let id = uuid::Uuid::new_v4().simple().to_string();
+    match subject {
+        ErasureSubject::User => format!("erased-user-{}", &id[..12]),
+        ErasureSubject::Device => format!("erased-device-{}", &id[..12]),
+    }
+}
+
+pub async fn erase(db: &EnterpriseDatabase, req: &ErasureRequest, requested_by: &str) -> ResultType<ErasureReport> {
+    let reason = req.reason.trim();
+    if reason.is_empty() || reason.chars().count() > MAX_REASON_LEN {
+        bail!("reason must be 1-{} characters", MAX_REASON_LEN);
+    }
+    let pseudonym = pseudonym(req.subject);
+    let purge = req.mode == ErasureMode::Purge;
+    let records = match req.subject {
+        ErasureSubject::User => {
+            let user = match db.get_user_by_id(&req.id).await? {
+                Some(user) => user,
+                None => bail!("user not found"),
+            };
+            if user.id == requested_by {
+                bail!("cannot erase your own account");
+            }
+            if matches!(user.role, UserRole::SuperAdmin) {
+                bail!("demote the super admin before erasure");
+            }
+            db.erase_user(&user, &pseudonym, purge).await?
+        }
+        ErasureSubject::Device => {
+            let records = db.erase_device(&req.id, &pseudonym, purge).await?;
+            if records.values().all(|n| *n == 0) {
+                bail!("no data found for device");
+            }
+            records
+        }
+    };
+    let report = ErasureReport {
+        id: uuid::Uuid::new_v4().to_string(),
+        subject: req.subject,
+        subject_hash: subject_hash(req.subject, &req.id),
+        pseudonym,
+        mode: req.mode,
+        reason: reason.to_owned(),
+        requested_by: requested_by.to_owned(),
+        created_at: crate::common::now(),
+        records,
+    };
+    db.save_erasure_report(&report).await?;
+    log::warn!(
+        "Erased {:?} data as {} ({:?}, report {}) by {}",
+        report.subject,
+        report.pseudonym,
+        report.mode,
+        report.id,
+        requested_by
+    );
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_erasure_identifiers() {
+        let hash = subject_hash(ErasureSubject::Device, "123456789");
+        assert_eq!(hash.len(), 64);
+        assert_eq!(hash, subject_hash(ErasureSubject::Device, "123456789"));
+        assert_ne!(hash, subject_hash(ErasureSubject::User, "123456789"));
+
+        let a = pseudonym(ErasureSubject::User);
+        assert!(a.starts_with("erased-user-"));
+        assert_ne!(a, pseudonym(ErasureSubject::User));
+
+        let req: ErasureRequest =
+            serde_json::from_str(r#"{"subject": "device", "id": "123", "mode": "purge", "reason": "DSR-42"}"#).unwrap();
+        assert_eq!(req.subject, ErasureSubject::Device);
+        assert_eq!(req.mode, ErasureMode::Purge);
+    }
+}
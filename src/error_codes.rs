@@ -0,0 +1,513 @@
+// 接口错误码模块 - 在响应返回前统一处理，各接口无需改动：
+//   - success 为 false 的 ApiResponse 按消息匹配错误目录，补充机器可读的 code 字段
+//   - 只有状态码、没有 JSON 内容的 4xx/5xx 响应补充 {success, data, code, message}
+//   - 按 i18n 确定的请求语言，英文时替换 message，冒号后的错误详情原样保留；成功响应的 message 按语言资源翻译
+// 错误目录由 /api/errors 提供，客户端应依据 code 而不是 message 判断错误类型
+use axum::{
+    body::{Body, HttpBody as _},
+    extract::Request,
+    http::{header, HeaderValue, Method, StatusCode},
+    middleware::Next,
+    response::Response,
+};
+use crate::i18n::{self, Lang};
+use schemars::JsonSchema;
+use serde_derive::{Deserialize, Serialize};
+
+// 只处理不超过该大小的 JSON 响应
+const MAX_BODY: usize = 1024 * 1024;
+const MALFORMED: &str = "MALFORMED_SETTINGS";
+const INVALID: &str = "INVALID_REQUEST";
+const FAILED: &str = "OPERATION_FAILED";
+const UNKNOWN: &str = "REQUEST_FAILED";
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct ErrorInfo {
+    pub code: String,
+    // 只由状态码产生的错误
+    pub status: Option<u16>,
+    pub zh: String,
+    pub en: String,
+}
+
+// 消息(冒号前部分)完全一致时使用的错误码，{} 匹配任意内容并代入英文消息
+const CATALOG: &[(&str, &str, &str)] = &[
+    (
+        "INVALID_CREDENTIALS",
+        "用户名或密码错误",
+        "Invalid username or password",
+    ),
+    (
+        "ACCOUNT_LOCKED",
+        "账户已被锁定，请稍后再试",
+        "Account is locked, try again later",
+    ),
+    ("USERNAME_TAKEN", "用户名已存在", "Username already exists"),
+    (
+        "EMAIL_TAKEN",
+        "邮箱已被其他用户使用",
+        "Email address is already used by another user",
+    ),
+    (
+        "TWO_FACTOR_ENABLED",
+        "已启用双因素认证",
+        "Two-factor authentication is already enabled",
+    ),
+    (
+        "EMAIL_OTP_DISABLED",
+        "未开放邮件验证码，请联系管理员",
+        "Email verification codes are not enabled, contact your administrator",
+    ),
+    ("INVALID_EMAIL", "邮箱地址格式错误", "Invalid email address"),
+    (
+        "INVALID_IP_NETWORK",
+        "IP地址或网段格式错误",
+        "Invalid IP address or network",
+    ),
+    // data 中为逐字段的错误列表
+    ("INVALID_FIELDS", "请求参数校验失败", "Request validation failed"),
+    (
+        "EMAIL_DISABLED",
+        "未启用邮件服务，无法发送邀请",
+        "Email service is not enabled, cannot send invitations",
+    ),
+    (
+        "PUBLIC_URL_MISSING",
+        "未配置 PUBLIC-URL，无法生成邀请链接",
+        "PUBLIC-URL is not configured, cannot generate invitation links",
+    ),
+    (
+        "INVITE_INVALID",
+        "邀请链接无效或已过期",
+        "Invitation link is invalid or expired",
+    ),
+    (
+        "INVITE_ACCEPTED",
+        "用户已接受邀请",
+        "User has already accepted the invitation",
+    ),
+    (
+        "INVITE_EMAIL_FAILED",
+        "用户已创建，但邀请邮件发送失败，可稍后重新发送",
+        "User created, but the invitation email could not be sent, resend it later",
+    ),
+    (
+        "RESET_LINK_INVALID",
+        "重置链接无效或已过期",
+        "Reset link is invalid or expired",
+    ),
+    (
+        "PASSWORD_REJECTED",
+        "密码不符合要求",
+        "Password does not meet the requirements",
+    ),
+    ("LICENSE_USER_LIMIT", "超出许可证用户数", "License user limit exceeded"),
+    ("LICENSE_INVALID", "许可证无效", "Invalid license"),
+    (
+        "UPLOAD_OFFSET_MISMATCH",
+        "上传偏移不一致，请从当前偏移继续",
+        "Upload offset mismatch, resume from the current offset",
+    ),
+    ("JOB_NOT_FOUND", "任务不存在或已取消", "Job not found or cancelled"),
+    (
+        "MESSAGE_NOT_FOUND",
+        "消息不存在或已撤回",
+        "Message not found or withdrawn",
+    ),
+    ("POLICY_NOT_FOUND", "策略不存在", "Policy not found"),
+    (
+        "POLICY_EXISTS",
+        "保存策略失败，名称可能已存在",
+        "Failed to save policy, the name may already exist",
+    ),
+    (
+        "NOT_IMPERSONATING",
+        "当前不是代操作会话",
+        "Not an impersonation session",
+    ),
+    ("IMPERSONATION_DENIED", "无法代操作", "Cannot impersonate"),
+    (
+        "WEBRTC_UNAVAILABLE",
+        "无法发起WebRTC会话",
+        "Cannot start a WebRTC session",
+    ),
+    ("TURN_DISABLED", "未启用TURN服务", "TURN service is not enabled"),
+    ("DEVICE_NOT_BANNED", "设备未被封禁", "Device is not banned"),
+    ("IP_NOT_BANNED", "该IP未被封禁", "IP is not banned"),
+    (
+        "NO_NAT_RECORDS",
+        "该设备暂无打洞记录",
+        "No hole punching records for this device",
+    ),
+    (
+        "NO_PASSWORD_POLICY",
+        "该设备未配置密码策略",
+        "No password policy is configured for this device",
+    ),
+    ("NO_ALIAS", "该设备没有别名", "Device has no alias"),
+    ("PROVISIONED_ID_NOT_FOUND", "预登记ID不存在", "Provisioned ID not found"),
+    (
+        "INVALID_TIME_WINDOW",
+        "时间窗口或汇总粒度无效，最长90天",
+        "Invalid time window or bucket, at most 90 days",
+    ),
+    (
+        "INVALID_TIME_WINDOW",
+        "时间窗口无效，最长366天",
+        "Invalid time window, at most 366 days",
+    ),
+    (
+        "APPROVAL_REQUIRED",
+        "设置 {} 需要审批，请通过对应的设置接口提交",
+        "Changes to {} require approval, submit them through the settings endpoint",
+    ),
+];
+
+// "<对象>格式错误"、"<对象>无效"、"<对象>失败" 中对象的英文名
+const SUBJECTS: &[(&str, &str)] = &[
+    ("AD同步配置", "AD sync settings"),
+    ("AD同步", "AD sync"),
+    ("DLP策略", "DLP policy"),
+    ("DNS服务发现配置", "DNS discovery settings"),
+    ("DNS服务发现检查", "DNS discovery check"),
+    ("ID注册策略", "ID registration policy"),
+    ("ITSM集成配置", "ITSM integration settings"),
+    ("MAC地址", "MAC address"),
+    ("TURN配置", "TURN settings"),
+    ("WebRTC配置", "WebRTC settings"),
+    ("WebRTC信令", "WebRTC signaling"),
+    ("Web会话配置", "web session settings"),
+    ("Web安全配置", "web security settings"),
+    ("中继策略", "relay policy"),
+    ("任务", "job"),
+    ("公告", "announcement"),
+    ("功能开关", "feature flags"),
+    ("双人审批配置", "four-eyes approval settings"),
+    ("双因素认证策略", "two-factor authentication policy"),
+    ("受信任浏览器配置", "trusted browser settings"),
+    ("变更审批配置", "change approval settings"),
+    ("备份配置", "backup settings"),
+    ("威胁情报配置", "threat intelligence settings"),
+    ("密码策略", "password policy"),
+    ("局域网配置", "LAN settings"),
+    ("数据脱敏规则", "data masking rules"),
+    ("文件传输带宽策略", "file transfer bandwidth policy"),
+    ("文件扫描配置", "file scan settings"),
+    ("文件路径", "file path"),
+    ("无人值守访问时段", "unattended access windows"),
+    ("日志配置", "logging settings"),
+    ("消息", "message"),
+    ("版本策略", "version policy"),
+    ("离线告警配置", "offline alert settings"),
+    ("端到端加密策略", "end-to-end encryption policy"),
+    ("策略", "policy"),
+    ("联邦配置", "federation settings"),
+    ("自定义字段配置", "custom field settings"),
+    ("自定义字段值", "custom field values"),
+    ("节假日日历", "holiday calendars"),
+    ("诱饵设备配置", "honeypot settings"),
+    ("连接保活与超时设置", "keepalive and timeout settings"),
+    ("通知偏好", "notification preferences"),
+    ("邮件验证码配置", "email OTP settings"),
+    ("上传安装包", "Uploading the package"),
+    ("保存设备视图", "Saving the device view"),
+    ("写入文件块", "Writing the file chunk"),
+    ("创建上传", "Creating the upload"),
+    ("发送邮件验证码", "Sending the email code"),
+    ("变更提交", "Submitting the change"),
+    ("启用紧急访问", "Enabling emergency access"),
+    ("唤醒设备", "Waking the device"),
+    ("回滚配置", "Rolling back settings"),
+    ("处理变更", "Processing the change"),
+    ("备份", "Backup"),
+    ("复核", "Review"),
+    ("审批", "Approval"),
+    ("导入节假日日历", "Importing the holiday calendar"),
+    ("开启协议抓包", "Starting the protocol capture"),
+    ("数据擦除", "Data erasure"),
+    ("更新会话权限", "Updating session permissions"),
+    ("更新设备视图", "Updating the device view"),
+    ("服务器密钥轮换", "Server key rotation"),
+    ("查询", "Query"),
+    ("比较配置版本", "Comparing settings versions"),
+    ("结束紧急访问", "Ending emergency access"),
+    ("设置中继排空", "Setting relay drain"),
+    ("设置别名", "Setting the alias"),
+    ("设置拥塞控制", "Setting congestion control"),
+    ("转发", "Forwarding"),
+    ("送达", "Delivery"),
+    ("邀请邮件发送", "Sending the invitation email"),
+];
+
+// 只有状态码的错误
+const STATUS: &[(u16, &str, &str, &str)] = &[
+    (400, "BAD_REQUEST", "请求参数错误", "Bad request"),
+    (
+        401,
+        "UNAUTHORIZED",
+        "未登录或登录已过期",
+        "Not signed in or the session has expired",
+    ),
+    (403, "FORBIDDEN", "没有权限执行该操作", "Permission denied"),
+    (404, "NOT_FOUND", "资源不存在", "Resource not found"),
+    (405, "METHOD_NOT_ALLOWED", "不支持该请求方法", "Method not allowed"),
+    (409, "CONFLICT", "资源状态冲突", "Conflict with the current state"),
+    (413, "PAYLOAD_TOO_LARGE", "请求内容过大", "Payload too large"),
+    (
+        415,
+        "UNSUPPORTED_MEDIA_TYPE",
+        "不支持的内容类型",
+        "Unsupported media type",
+    ),
+    (
+        422,
+        "UNPROCESSABLE_ENTITY",
+        "请求内容格式错误",
+        "Malformed request body",
+    ),
+    (
+        429,
+        "TOO_MANY_REQUESTS",
+        "请求过于频繁，请稍后再试",
+        "Too many requests, try again later",
+    ),
+    (500, "INTERNAL_ERROR", "服务器内部错误", "Internal server error"),
+    (503, "SERVICE_UNAVAILABLE", "服务暂不可用", "Service unavailable"),
+];
+
+fn subject_en(subject: &str) -> Option<&'static str> {
+    SUBJECTS.iter().find(|(zh, _)| *zh == subject).map(|(_, en)| *en)
+}
+
+// 模板中的 {} 匹配任意内容，返回匹配到的内容
+fn match_template<'a>(template: &str, text: &'a str) -> Option<&'a str> {
+    match template.split_once("{}") {
+        Some((prefix, suffix)) => text
+            .strip_prefix(prefix)
+            .and_then(|x| x.strip_suffix(suffix))
+            .filter(|x| !x.is_empty()),
+        None if template == text => Some(""),
+        None => None,
+    }
+}
+
+// 消息对应的错误码和指定语言的消息
+pub fn classify(message: &str, lang: Lang) -> (&'static str, String) {
+    let (head, detail) = match message.split_once(": ") {
+        Some((head, detail)) => (head, Some(detail)),
+        None => (message, None),
+    };
+    let with_detail = |en: String| match detail {
+        Some(detail) => format!("{}: {}", en, detail),
+        None => en,
+    };
+    let localized = |en: String| match lang {
+        Lang::Zh => message.to_owned(),
+        Lang::En => with_detail(en),
+    };
+    for (code, zh, en) in CATALOG {
+        if let Some(arg) = match_template(zh, head) {
+            return (code, localized(en.replace("{}", arg)));
+        }
+    }
+    let families: [(&str, &'static str, fn(&str) -> String, &str); 3] = [
+        (
+            "格式错误",
+            MALFORMED,
+            |x| format!("Malformed {}", x),
+            "Malformed request",
+        ),
+        ("无效", INVALID, |x| format!("Invalid {}", x), "Invalid request"),
+        ("失败", FAILED, |x| format!("{} failed", x), "Operation failed"),
+    ];
+    for (suffix, code, en, fallback) in families {
+        if let Some(subject) = head.strip_suffix(suffix) {
+            let en = subject_en(subject).map(en).unwrap_or_else(|| fallback.to_owned());
+            return (code, localized(en));
+        }
+    }
+    (
+        UNKNOWN,
+        match lang {
+            Lang::Zh => message.to_owned(),
+            Lang::En => format!("Request failed: {}", message),
+        },
+    )
+}
+
+fn status_error(status: StatusCode) -> (String, &'static str, &'static str) {
+    match STATUS.iter().find(|x| x.0 == status.as_u16()) {
+        Some((_, code, zh, en)) => (code.to_string(), zh, en),
+        None if status.is_server_error() => (format!("HTTP_{}", status.as_u16()), "服务器错误", "Server error"),
+        None => (format!("HTTP_{}", status.as_u16()), "请求失败", "Request failed"),
+    }
+}
+
+// 错误目录
+pub fn catalog() -> Vec<ErrorInfo> {
+    let mut res: Vec<ErrorInfo> = STATUS
+        .iter()
+        .map(|(status, code, zh, en)| ErrorInfo {
+            code: code.to_string(),
+            status: Some(*status),
+            zh: zh.to_string(),
+            en: en.to_string(),
+        })
+        .collect();
+    res.extend(CATALOG.iter().map(|(code, zh, en)| ErrorInfo {
+        code: code.to_string(),
+        status: None,
+        zh: zh.to_string(),
+        en: en.to_string(),
+    }));
+    for (code, zh, en) in [
+        (MALFORMED, "<配置>格式错误", "Malformed <settings>"),
+        (INVALID, "<对象>无效: <详情>", "Invalid <object>: <detail>"),
+        (FAILED, "<操作>失败: <详情>", "<Operation> failed: <detail>"),
+        (UNKNOWN, "<其他错误>", "Request failed: <message>"),
+    ] {
+        res.push(ErrorInfo {
+            code: code.to_owned(),
+            status: None,
+            zh: zh.to_owned(),
+            en: en.to_owned(),
+        });
+    }
+    res
+}
+
+fn json_response(mut parts: axum::http::response::Parts, value: &serde_json::Value, lang: Lang) -> Response {
+    let body = serde_json::to_vec(value).unwrap_or_default();
+    parts.headers.remove(header::CONTENT_LENGTH);
+    parts
+        .headers
+        .insert(header::CONTENT_TYPE, HeaderValue::from_static("application/json"));
+    parts
+        .headers
+        .insert(header::CONTENT_LANGUAGE, HeaderValue::from_static(lang.tag()));
+    Response::from_parts(parts, Body::from(body))
+}
+
+pub async fn middleware(req: Request, next: Next) -> Response {
+    let lang = i18n::current();
+    let head = req.method() == Method::HEAD;
+    let res = next.run(req).await;
+    let status = res.status();
+    let size = res.body().size_hint().exact();
+    let content_type = res
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default()
+        .to_owned();
+    let is_error = status.is_client_error() || status.is_server_error();
+    if head || size.map(|x| x as usize > MAX_BODY).unwrap_or(true) {
+        return res;
+    }
+    let json = content_type.starts_with("application/json");
+    let text = content_type.starts_with("text/plain");
+    if !(json || (is_error && (text || size == Some(0)))) {
+        return res;
+    }
+    let (parts, body) = res.into_parts();
+    let bytes = match axum::body::to_bytes(body, MAX_BODY).await {
+        Ok(bytes) => bytes,
+        Err(_) => return Response::from_parts(parts, Body::empty()),
+    };
+    if json {
+        // ApiResponse 的第一个字段为 success
+        if bytes.starts_with(br#"{"success":false"#) {
+            if let Ok(mut value) = serde_json::from_slice::<serde_json::Value>(&bytes) {
+                let message = value["message"].as_str().unwrap_or_default().to_owned();
+                let (code, message) = classify(&message, lang);
+                value["code"] = code.into();
+                value["message"] = message.into();
+                return json_response(parts, &value, lang);
+            }
+        } else if lang != Lang::Zh && bytes.starts_with(br#"{"success":true"#) {
+            if let Ok(mut value) = serde_json::from_slice::<serde_json::Value>(&bytes) {
+                let message = i18n::message(lang, value["message"].as_str().unwrap_or_default());
+                value["message"] = message.into();
+                return json_response(parts, &value, lang);
+            }
+        }
+        return Response::from_parts(parts, Body::from(bytes));
+    }
+    let (code, zh, en) = status_error(status);
+    let mut message = match lang {
+        Lang::Zh => zh.to_owned(),
+        Lang::En => en.to_owned(),
+    };
+    let detail = String::from_utf8_lossy(&bytes);
+    if !detail.trim().is_empty() {
+        message = format!("{}: {}", message, detail.trim());
+    }
+    let value = serde_json::json!({
+        "success": false,
+        "data": null,
+        "code": code,
+        "message": message,
+    });
+    json_response(parts, &value, lang)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classify() {
+        assert_eq!(
+            classify("用户名或密码错误", Lang::En),
+            ("INVALID_CREDENTIALS", "Invalid username or password".to_owned())
+        );
+        assert_eq!(
+            classify("用户名或密码错误", Lang::Zh),
+            ("INVALID_CREDENTIALS", "用户名或密码错误".to_owned())
+        );
+        assert_eq!(
+            classify("联邦配置无效: secret too short", Lang::En),
+            (
+                "INVALID_REQUEST",
+                "Invalid federation settings: secret too short".to_owned()
+            )
+        );
+        assert_eq!(
+            classify("DLP策略格式错误", Lang::En),
+            ("MALFORMED_SETTINGS", "Malformed DLP policy".to_owned())
+        );
+        assert_eq!(
+            classify("备份失败: disk full", Lang::En),
+            ("OPERATION_FAILED", "Backup failed: disk full".to_owned())
+        );
+        assert_eq!(
+            classify("设置 federation 需要审批，请通过对应的设置接口提交", Lang::En),
+            (
+                "APPROVAL_REQUIRED",
+                "Changes to federation require approval, submit them through the settings endpoint".to_owned()
+            )
+        );
+        assert_eq!(
+            classify("无法发起WebRTC会话: peer offline", Lang::En),
+            (
+                "WEBRTC_UNAVAILABLE",
+                "Cannot start a WebRTC session: peer offline".to_owned()
+            )
+        );
+        assert_eq!(classify("某某未知错误", Lang::Zh).0, "REQUEST_FAILED");
+        // 未收录的对象仍能给出错误码
+        assert_eq!(
+            classify("某某配置无效: x", Lang::En),
+            ("INVALID_REQUEST", "Invalid request: x".to_owned())
+        );
+    }
+
+    #[test]
+    fn test_catalog() {
+        let catalog = catalog();
+        assert!(catalog.iter().all(|x| !x.code.is_empty() && !x.en.is_empty()));
+        for (zh, en) in SUBJECTS {
+            assert!(!zh.is_empty() && !en.is_empty());
+        }
+    }
+}
@@ -0,0 +1,101 @@
+// 收藏和最近连接模块 - 按用户记录最近连接过的设备，用户可以收藏常用设备：
+//   - 中继会话、Web 控制和 WebRTC 会话建立时调用 record 登记，同一设备只保留一条，更新最后连接时间和次数
+//   - 每个用户最多保留 MAX_RECENT 条最近连接，超出时删除最早的
+//   - /api/me/recent 和 /api/me/favorites 返回设备名称、别名和在线状态，供 Web 控制台首页和客户端地址簿同步使用
+// 停用或删除的设备不出现在列表中，收藏记录保留，设备恢复后重新出现
+use crate::enterprise_database::EnterpriseDatabase;
+use hbb_common::{bail, log, ResultType};
+use schemars::JsonSchema;
+use serde_derive::{Deserialize, Serialize};
+
+pub const MAX_RECENT: usize = 50;
+pub const MAX_FAVORITES: usize = 200;
+pub const DEFAULT_RECENT_LIMIT: usize = 20;
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct UserDevice {
+    pub device_id: String,
+    pub name: String,
+    pub alias: Option<String>,
+    pub os: String,
+    pub online: bool,
+    pub last_online: u64,
+    // 收藏时间，未收藏时为空
+    pub favorited_at: Option<u64>,
+    // 当前用户最后一次连接的时间和累计连接次数，从未连接过时为空和 0
+    pub last_connected_at: Option<u64>,
+    pub connection_count: u64,
+}
+
+// 登记一次连接，失败只记录日志，不影响会话建立
+pub async fn record(db: &EnterpriseDatabase, user_id: &str, device_id: &str) {
+    if let Err(e) = db
+        .record_recent_connection(user_id, device_id, crate::common::now(), MAX_RECENT as i64)
+        .await
+    {
+        log::error!("Failed to record recent connection {} -> {}: {}", user_id, device_id, e);
+    }
+}
+
+pub async fn recent(db: &EnterpriseDatabase, user_id: &str, limit: usize) -> ResultType<Vec<UserDevice>> {
+    let limit = limit.clamp(1, MAX_RECENT) as i64;
+    db.list_recent_devices(user_id, crate::common::now(), limit).await
+}
+
+pub async fn favorites(db: &EnterpriseDatabase, user_id: &str) -> ResultType<Vec<UserDevice>> {
+    db.list_favorite_devices(user_id, crate::common::now()).await
+}
+
+// 已收藏时返回 false
+pub async fn star(db: &EnterpriseDatabase, user_id: &str, device_id: &str) -> ResultType<bool> {
+    if db.count_favorite_devices(user_id).await? >= MAX_FAVORITES as i64 {
+        bail!("at most {} favorites per user", MAX_FAVORITES);
+    }
+    db.add_favorite_device(user_id, device_id, crate::common::now()).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::web_api::fixtures::DeviceBuilder;
+    use hbb_common::tokio;
+
+    #[tokio::test]
+    async fn test_recent_and_favorites() {
+        let db = EnterpriseDatabase::memory().await.unwrap();
+        let admin = db.get_user_by_username("admin").await.unwrap().unwrap();
+        for id in ["111111111", "222222222", "333333333"] {
+            db.register_device(&DeviceBuilder::new(id).owner(&admin.id).build()).await.unwrap();
+        }
+
+        record(&db, &admin.id, "111111111").await;
+        record(&db, &admin.id, "222222222").await;
+        record(&db, &admin.id, "111111111").await;
+        // 未登记的设备不出现在列表中
+        record(&db, &admin.id, "999999999").await;
+        let res = recent(&db, &admin.id, DEFAULT_RECENT_LIMIT).await.unwrap();
+        let ids: Vec<_> = res.iter().map(|x| (x.device_id.as_str(), x.connection_count)).collect();
+        assert_eq!(ids.len(), 2);
+        assert!(ids.contains(&("111111111", 2)) && ids.contains(&("222222222", 1)));
+        assert!(res.iter().all(|x| x.online && x.favorited_at.is_none()));
+        assert_eq!(recent(&db, &admin.id, 1).await.unwrap().len(), 1);
+
+        assert!(star(&db, &admin.id, "333333333").await.unwrap());
+        assert!(!star(&db, &admin.id, "333333333").await.unwrap());
+        assert!(star(&db, &admin.id, "111111111").await.unwrap());
+        let res = favorites(&db, &admin.id).await.unwrap();
+        let ids: Vec<_> = res.iter().map(|x| x.device_id.as_str()).collect();
+        assert_eq!(ids, vec!["111111111", "333333333"]);
+        assert_eq!(res[0].connection_count, 2);
+        assert!(res[1].last_connected_at.is_none());
+        let res = recent(&db, &admin.id, 10).await.unwrap();
+        assert!(res.iter().find(|x| x.device_id == "111111111").unwrap().favorited_at.is_some());
+
+        assert!(db.remove_favorite_device(&admin.id, "333333333").await.unwrap());
+        assert!(!db.remove_favorite_device(&admin.id, "333333333").await.unwrap());
+        // 包括未登记设备的记录
+        assert_eq!(db.clear_recent_connections(&admin.id).await.unwrap(), 3);
+        assert!(recent(&db, &admin.id, 10).await.unwrap().is_empty());
+        assert_eq!(favorites(&db, &admin.id).await.unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,209 @@
+// 功能开关模块 - 按功能控制企业子系统是否生效，保存在系统设置中并缓存在内存，修改后立即生效，无需重新发布。
+// 每个开关可全部启用，或只对指定分组(设备组/用户组)启用，或按设备/用户 ID 哈希灰度一定比例，便于逐步放开:
+//   file_transfer   文件传输服务，按发起用户及其用户组判断
+//   remote_jobs     远程任务下发，按目标设备及其设备组判断
+//   e2e_enforcement 端到端加密强制(require_e2e)，按被控设备及其设备组判断
+// 未配置的功能保持启用，与引入开关前的行为一致
+use crate::enterprise_database::EnterpriseDatabase;
+use crate::validation::{self, Payload};
+use hbb_common::{bail, log, tokio::sync::RwLock, ResultType};
+use schemars::JsonSchema;
+use serde_derive::{Deserialize, Serialize};
+use sodiumoxide::crypto::hash::sha256;
+use std::collections::BTreeMap;
+use validator::{Validate, ValidationErrors};
+
+pub const FEATURE_FLAGS_KEY: &str = "feature_flags";
+
+lazy_static::lazy_static! {
+    static ref FLAGS: RwLock<FeatureFlags> = Default::default();
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Feature {
+    FileTransfer,
+    RemoteJobs,
+    E2eEnforcement,
+}
+
+impl Feature {
+    pub const ALL: [Feature; 3] = [Feature::FileTransfer, Feature::RemoteJobs, Feature::E2eEnforcement];
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct FeatureFlag {
+    // 对所有设备和用户启用
+    #[serde(default)]
+    pub enabled: bool,
+    // 未全部启用时，只对这些分组启用
+    #[serde(default)]
+    pub groups: Vec<String>,
+    // 未全部启用时，按 ID 哈希对该比例(0-100)的设备或用户启用
+    #[serde(default)]
+    pub rollout_percent: u8,
+}
+
+impl Default for FeatureFlag {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            groups: Vec::new(),
+            rollout_percent: 0,
+        }
+    }
+}
+
+pub type FeatureFlags = BTreeMap<Feature, FeatureFlag>;
+
+// PUT /api/settings/feature-flags 的请求体
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(transparent)]
+pub struct FeatureFlagsUpdate(pub FeatureFlags);
+
+impl Validate for FeatureFlagsUpdate {
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        validate(&self.0).map_err(|e| validation::map_error("feature_flags", "feature_flag", e.to_string()))
+    }
+}
+
+impl Payload for FeatureFlagsUpdate {}
+
+impl FeatureFlag {
+    fn applies(&self, feature: Feature, subject: &str, group_ids: &[String]) -> bool {
+        self.enabled
+            || self.groups.iter().any(|g| group_ids.contains(g))
+            || bucket(feature, subject) < self.rollout_percent as u64
+    }
+}
+
+// 同一 ID 在不同功能上落入不同的灰度批次，重启后保持不变
+fn bucket(feature: Feature, subject: &str) -> u64 {
+    let digest = sha256::hash(format!("{:?}:{}", feature, subject).as_bytes());
+    let mut bytes = [0u8; 8];
+    bytes.copy_from_slice(&digest.0[..8]);
+    u64::from_be_bytes(bytes) % 100
+}
+
+pub fn validate(flags: &FeatureFlags) -> ResultType<()> {
+    for (feature, flag) in flags.iter() {
+        if flag.rollout_percent > 100 {
+            bail!("{:?}: rollout_percent must be 0-100", feature);
+        }
+        if flag.groups.iter().any(|g| g.trim().is_empty()) {
+            bail!("{:?}: group id must not be empty", feature);
+        }
+    }
+    Ok(())
+}
+
+pub async fn reload(db: &EnterpriseDatabase) -> ResultType<()> {
+    let flags: FeatureFlags = match db.get_setting(FEATURE_FLAGS_KEY).await? {
+        Some(v) => serde_json::from_str(&v)?,
+        None => FeatureFlags::default(),
+    };
+    validate(&flags)?;
+    *FLAGS.write().await = flags;
+    Ok(())
+}
+
+// 包含未配置功能的默认值
+pub async fn get() -> FeatureFlags {
+    let flags = FLAGS.read().await;
+    Feature::ALL
+        .iter()
+        .map(|f| (*f, flags.get(f).cloned().unwrap_or_default()))
+        .collect()
+}
+
+pub async fn update(db: &EnterpriseDatabase, flags: FeatureFlags, updated_by: &str) -> ResultType<()> {
+    validate(&flags)?;
+    db.set_setting(FEATURE_FLAGS_KEY, &serde_json::to_string(&flags)?, Some(updated_by))
+        .await?;
+    *FLAGS.write().await = flags;
+    Ok(())
+}
+
+// subject 为设备ID或用户ID，group_ids 为其所属分组
+pub async fn is_enabled(feature: Feature, subject: &str, group_ids: &[String]) -> bool {
+    match FLAGS.read().await.get(&feature) {
+        Some(flag) => flag.applies(feature, subject, group_ids),
+        None => true,
+    }
+}
+
+// 按设备判断，只有开关限定了分组时才查询设备组
+pub async fn is_enabled_for_device(db: &EnterpriseDatabase, feature: Feature, device_id: &str) -> bool {
+    let flag = match FLAGS.read().await.get(&feature) {
+        Some(flag) => flag.clone(),
+        None => return true,
+    };
+    if flag.enabled || flag.applies(feature, device_id, &[]) {
+        return true;
+    }
+    if flag.groups.is_empty() {
+        return false;
+    }
+    match db.get_device_group_ids(device_id).await {
+        Ok(group_ids) => flag.applies(feature, device_id, &group_ids),
+        Err(e) => {
+            log::error!("Failed to get groups of {} for feature {:?}: {}", device_id, feature, e);
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_applies() {
+        let flag = FeatureFlag {
+            enabled: false,
+            groups: vec!["pilot".to_owned()],
+            rollout_percent: 0,
+        };
+        assert!(flag.applies(Feature::FileTransfer, "u1", &["pilot".to_owned()]));
+        assert!(!flag.applies(Feature::FileTransfer, "u1", &["other".to_owned()]));
+        assert!(FeatureFlag::default().applies(Feature::FileTransfer, "u1", &[]));
+
+        let flag = FeatureFlag {
+            enabled: false,
+            groups: vec![],
+            rollout_percent: 30,
+        };
+        let ids: Vec<String> = (0..1000).map(|i| format!("device-{}", i)).collect();
+        let enabled = ids
+            .iter()
+            .filter(|id| flag.applies(Feature::RemoteJobs, id, &[]))
+            .count();
+        assert!((200..400).contains(&enabled), "{}", enabled);
+        // 提高比例后已启用的不会被关闭
+        let wider = FeatureFlag {
+            rollout_percent: 60,
+            ..flag.clone()
+        };
+        for id in ids.iter().filter(|id| flag.applies(Feature::RemoteJobs, id, &[])) {
+            assert!(wider.applies(Feature::RemoteJobs, id, &[]));
+        }
+    }
+
+    #[test]
+    fn test_validate() {
+        let mut flags = FeatureFlags::new();
+        flags.insert(Feature::E2eEnforcement, FeatureFlag::default());
+        assert!(validate(&flags).is_ok());
+        flags.get_mut(&Feature::E2eEnforcement).unwrap().rollout_percent = 101;
+        assert!(validate(&flags).is_err());
+        assert_eq!(
+            serde_json::from_str::<FeatureFlags>(r#"{"file_transfer": {"enabled": false}}"#).unwrap()
+                [&Feature::FileTransfer],
+            FeatureFlag {
+                enabled: false,
+                groups: vec![],
+                rollout_percent: 0
+            }
+        );
+    }
+}
@@ -0,0 +1,508 @@
+// 信令服务器联邦模块 - 多个区域的 hbbs 组成联邦，设备仍只注册到所在区域的信令服务器，
+// 控制端请求的ID在本区域不存在时向其他区域查询(结果短时缓存)，找到后:
+//   - 打洞/中继请求经 Web 接口转发给设备所在区域的信令服务器，由其发给设备，
+//     设备的响应再转回控制端所在区域，经控制端原来的连接送达
+//   - 中继优先选用设备所在区域配置的中继服务器(画面数据从被控端上传，靠近被控端可减少跨区域流量)
+// 区域之间以共享密钥认证(请求头 X-Federation-Secret)，api_url 应使用 HTTPS。
+// 联邦中的信令服务器须使用同一密钥对(-k)，控制端才能校验其他区域签名的设备公钥
+use crate::device_ban;
+use crate::enterprise_database::EnterpriseDatabase;
+use crate::four_eyes;
+use crate::unattended_access;
+use crate::validation::Payload;
+use crate::version_policy;
+use hbb_common::{
+    bail,
+    futures::future::join_all,
+    log,
+    protobuf::Message as _,
+    rendezvous_proto::RendezvousMessage,
+    tokio::sync::{mpsc, oneshot, Mutex, RwLock},
+    ResultType,
+};
+use schemars::JsonSchema;
+use serde_derive::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, HashSet},
+    net::SocketAddr,
+    sync::atomic::{AtomicUsize, Ordering},
+    time::{Duration, Instant},
+};
+use validator::Validate;
+
+pub const FEDERATION_KEY: &str = "federation";
+pub const SECRET_HEADER: &str = "X-Federation-Secret";
+const SECRET_MASK: &str = "******";
+const MIN_SECRET_LEN: usize = 32;
+const MAX_PEERS: usize = 32;
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(3);
+// 转发请求时对端可能在等待双人审批
+const FORWARD_TIMEOUT: Duration = Duration::from_secs(330);
+// 查询结果缓存时间，未找到的结果缓存较短以便设备上线后尽快可见
+const FOUND_TTL: Duration = Duration::from_secs(60);
+const MISSING_TTL: Duration = Duration::from_secs(10);
+// 转发后等待设备响应的时间
+const PENDING_TTL: Duration = Duration::from_secs(60);
+
+lazy_static::lazy_static! {
+    static ref CONFIG: RwLock<FederationConfig> = Default::default();
+    static ref INBOX: Mutex<Option<mpsc::UnboundedSender<Inbound>>> = Default::default();
+    // 设备ID -> 所在区域，None 表示各区域都没有
+    static ref LOCATIONS: RwLock<HashMap<String, (Option<String>, Instant)>> = Default::default();
+    // 本区域转发出去的请求: 控制端地址 -> 设备所在区域
+    static ref FORWARDED: RwLock<HashMap<SocketAddr, (String, Instant)>> = Default::default();
+    // 其他区域转发来的请求: 控制端地址 -> 控制端所在区域
+    static ref PENDING: RwLock<HashMap<SocketAddr, (String, Instant)>> = Default::default();
+}
+
+static ROTATION: AtomicUsize = AtomicUsize::new(0);
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct FederationPeer {
+    pub region: String,
+    // 对端 Web 管理接口地址，如 https://hbbs-apac.example.com:21114
+    pub api_url: String,
+    // 对端区域的中继服务器，为空时使用本区域的中继
+    #[serde(default)]
+    pub relay_servers: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, JsonSchema, Validate)]
+pub struct FederationConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    // 本区域名称，如 eu、apac
+    #[serde(default)]
+    #[validate(length(max = 64))]
+    pub region: String,
+    // 联邦共享密钥，各区域配置相同的值
+    #[serde(default)]
+    #[validate(length(max = 1024))]
+    pub secret: String,
+    #[serde(default)]
+    pub peers: Vec<FederationPeer>,
+}
+
+impl Payload for FederationConfig {}
+
+// 查询接口的响应
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct FederatedLookup {
+    pub region: String,
+    pub online: bool,
+}
+
+// 转发给设备所在区域的打洞/中继请求
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, Validate)]
+pub struct ForwardRequest {
+    #[validate(length(min = 1, max = 100))]
+    pub id: String,
+    // 控制端所在区域
+    #[validate(length(min = 1, max = 64))]
+    pub region: String,
+    // 控制端地址，设备的响应按此送回
+    #[validate(length(min = 1, max = 256))]
+    pub controller: String,
+    // 控制端登录用户名，用于双人审批
+    #[serde(default)]
+    #[validate(length(max = 128))]
+    pub requester: Option<String>,
+    // base64 编码的 RendezvousMessage
+    #[validate(length(min = 1, max = 65536))]
+    pub message: String,
+}
+
+impl Payload for ForwardRequest {}
+
+// 设备的响应，送回控制端所在区域
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, Validate)]
+pub struct DeliverRequest {
+    // 设备所在区域
+    #[validate(length(min = 1, max = 64))]
+    pub region: String,
+    #[validate(length(min = 1, max = 256))]
+    pub controller: String,
+    #[validate(length(min = 1, max = 65536))]
+    pub message: String,
+}
+
+impl Payload for DeliverRequest {}
+
+// 交给信令服务器主循环处理的请求，由其持有在线设备表和控制端连接
+pub enum Inbound {
+    // 设备是否在线
+    Lookup(String, oneshot::Sender<bool>),
+    // 发给设备，设备不在线时回复 false
+    Forward(String, RendezvousMessage, oneshot::Sender<bool>),
+    // 发给控制端
+    Deliver(SocketAddr, RendezvousMessage),
+}
+
+#[derive(Deserialize)]
+struct Reply<T> {
+    success: bool,
+    data: Option<T>,
+    #[serde(default)]
+    message: String,
+}
+
+impl FederationConfig {
+    pub fn validate(&self) -> ResultType<()> {
+        if self.peers.len() > MAX_PEERS {
+            bail!("at most {} peers", MAX_PEERS);
+        }
+        let mut regions = HashSet::new();
+        regions.insert(self.region.as_str());
+        for peer in self.peers.iter() {
+            if peer.region.trim().is_empty() || !regions.insert(peer.region.as_str()) {
+                bail!("peer regions must be unique, not empty and differ from the local region");
+            }
+            if !peer.api_url.starts_with("https://") && !peer.api_url.starts_with("http://") {
+                bail!("invalid api_url: {}", peer.api_url);
+            }
+            if peer.relay_servers.iter().any(|x| x.trim().is_empty()) {
+                bail!("empty relay server in region {}", peer.region);
+            }
+        }
+        if self.enabled {
+            if self.region.trim().is_empty() {
+                bail!("region is required");
+            }
+            if self.secret.len() < MIN_SECRET_LEN {
+                bail!("secret must be at least {} characters", MIN_SECRET_LEN);
+            }
+        }
+        Ok(())
+    }
+
+    // 接口返回时隐藏共享密钥
+    pub fn masked(&self) -> Self {
+        let mut config = self.clone();
+        if !config.secret.is_empty() {
+            config.secret = SECRET_MASK.to_owned();
+        }
+        config
+    }
+
+    fn peer(&self, region: &str) -> Option<&FederationPeer> {
+        self.peers.iter().find(|p| p.region == region)
+    }
+}
+
+async fn set(config: FederationConfig) {
+    LOCATIONS.write().await.clear();
+    *CONFIG.write().await = config;
+}
+
+pub async fn reload(db: &EnterpriseDatabase) -> ResultType<()> {
+    let config: FederationConfig = match db.get_setting(FEDERATION_KEY).await? {
+        Some(v) => serde_json::from_str(&v)?,
+        None => FederationConfig::default(),
+    };
+    config.validate()?;
+    set(config).await;
+    Ok(())
+}
+
+pub async fn get() -> FederationConfig {
+    CONFIG.read().await.clone()
+}
+
+pub async fn update(db: &EnterpriseDatabase, mut config: FederationConfig, updated_by: &str) -> ResultType<()> {
+    // 前端回传掩码时保留原密钥
+    if config.secret == SECRET_MASK {
+        config.secret = CONFIG.read().await.secret.clone();
+    }
+    config.validate()?;
+    db.set_setting(FEDERATION_KEY, &serde_json::to_string(&config)?, Some(updated_by))
+        .await?;
+    set(config).await;
+    Ok(())
+}
+
+pub async fn subscribe() -> mpsc::UnboundedReceiver<Inbound> {
+    let (tx, rx) = mpsc::unbounded_channel();
+    *INBOX.lock().await = Some(tx);
+    rx
+}
+
+async fn send_inbound(req: Inbound) -> ResultType<()> {
+    match INBOX.lock().await.as_ref() {
+        Some(tx) if tx.send(req).is_ok() => Ok(()),
+        _ => bail!("rendezvous server is not running"),
+    }
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+// 校验其他区域请求携带的共享密钥，未启用联邦时一律拒绝
+pub async fn check_secret(secret: Option<&str>) -> bool {
+    let config = CONFIG.read().await;
+    match secret {
+        Some(secret) if config.enabled && !config.secret.is_empty() => {
+            constant_time_eq(secret.as_bytes(), config.secret.as_bytes())
+        }
+        _ => false,
+    }
+}
+
+fn encode(msg: &RendezvousMessage) -> ResultType<String> {
+    Ok(base64::encode(msg.write_to_bytes()?))
+}
+
+fn decode(message: &str) -> ResultType<RendezvousMessage> {
+    Ok(RendezvousMessage::parse_from_bytes(&base64::decode(message)?)?)
+}
+
+async fn call<T: serde::de::DeserializeOwned>(
+    config: &FederationConfig,
+    req: reqwest::RequestBuilder,
+) -> ResultType<T> {
+    let reply: Reply<T> = req
+        .header(SECRET_HEADER, &config.secret)
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    match reply.data {
+        Some(data) if reply.success => Ok(data),
+        _ => bail!("{}", reply.message),
+    }
+}
+
+fn client(timeout: Duration) -> ResultType<reqwest::Client> {
+    Ok(reqwest::Client::builder().timeout(timeout).build()?)
+}
+
+fn url(peer: &FederationPeer, path: &str) -> String {
+    format!("{}{}", peer.api_url.trim_end_matches('/'), path)
+}
+
+async fn lookup_peer(config: &FederationConfig, peer: &FederationPeer, id: &str) -> ResultType<FederatedLookup> {
+    let req = client(REQUEST_TIMEOUT)?.get(url(peer, &format!("/api/federation/lookup/{}", id)));
+    call(config, req).await
+}
+
+// 本区域不存在的设备所在的区域，同时查询所有区域，取第一个在线的
+pub async fn locate(id: &str) -> Option<FederationPeer> {
+    let config = get().await;
+    if !config.enabled || config.peers.is_empty() {
+        return None;
+    }
+    let cached = LOCATIONS.read().await.get(id).cloned();
+    if let Some((region, at)) = cached {
+        let ttl = if region.is_some() { FOUND_TTL } else { MISSING_TTL };
+        if at.elapsed() < ttl {
+            return region.and_then(|r| config.peer(&r).cloned());
+        }
+    }
+    let replies = join_all(config.peers.iter().map(|peer| lookup_peer(&config, peer, id))).await;
+    let mut region = None;
+    for (peer, reply) in config.peers.iter().zip(replies) {
+        match reply {
+            Ok(lookup) if lookup.online => {
+                region = Some(peer.region.clone());
+                break;
+            }
+            Ok(_) => {}
+            Err(err) => log::warn!("Federated lookup of {} in {} failed: {}", id, peer.region, err),
+        }
+    }
+    let mut locations = LOCATIONS.write().await;
+    locations.retain(|_, (r, at)| at.elapsed() < if r.is_some() { FOUND_TTL } else { MISSING_TTL });
+    locations.insert(id.to_owned(), (region.clone(), Instant::now()));
+    region.and_then(|r| config.peer(&r).cloned())
+}
+
+// 设备所在区域的中继服务器，轮流分配
+pub fn relay_server(peer: &FederationPeer) -> Option<String> {
+    if peer.relay_servers.is_empty() {
+        return None;
+    }
+    let i = ROTATION.fetch_add(1, Ordering::SeqCst) % peer.relay_servers.len();
+    Some(peer.relay_servers[i].clone())
+}
+
+fn expire(map: &mut HashMap<SocketAddr, (String, Instant)>) {
+    map.retain(|_, (_, at)| at.elapsed() < PENDING_TTL);
+}
+
+// 将控制端的请求转发给设备所在区域，设备不在线或被拒绝时返回 false
+pub async fn forward(
+    peer: &FederationPeer,
+    id: &str,
+    controller: SocketAddr,
+    requester: Option<&str>,
+    msg: &RendezvousMessage,
+) -> ResultType<bool> {
+    let config = get().await;
+    {
+        let mut forwarded = FORWARDED.write().await;
+        expire(&mut forwarded);
+        forwarded.insert(controller, (peer.region.clone(), Instant::now()));
+    }
+    let body = ForwardRequest {
+        id: id.to_owned(),
+        region: config.region.clone(),
+        controller: controller.to_string(),
+        requester: requester.map(str::to_owned),
+        message: encode(msg)?,
+    };
+    let req = client(FORWARD_TIMEOUT)?
+        .post(url(peer, "/api/federation/forward"))
+        .json(&body);
+    let res = call::<bool>(&config, req).await;
+    if !matches!(res, Ok(true)) {
+        // 设备可能已迁移到其他区域，下次重新查询
+        LOCATIONS.write().await.remove(id);
+    }
+    res
+}
+
+// 处理其他区域转发来的请求，经本区域的检查后发给设备
+pub async fn accept(db: &EnterpriseDatabase, req: ForwardRequest) -> ResultType<bool> {
+    let config = get().await;
+    if config.peer(&req.region).is_none() {
+        bail!("unknown region {}", req.region);
+    }
+    let controller: SocketAddr = req.controller.parse()?;
+    let msg = decode(&req.message)?;
+    if device_ban::is_banned(&req.id).await
+        || version_policy::is_blocked(db, &req.id).await
+        || unattended_access::is_refused(db, &req.id).await
+    {
+        return Ok(false);
+    }
+    let requester = req.requester.map(|x| format!("{}@{}", x, req.region));
+    if !four_eyes::authorize(db, &req.id, None, requester.as_deref(), &req.controller).await {
+        return Ok(false);
+    }
+    let (tx, rx) = oneshot::channel();
+    send_inbound(Inbound::Forward(req.id.clone(), msg, tx)).await?;
+    if !rx.await.unwrap_or(false) {
+        return Ok(false);
+    }
+    let mut pending = PENDING.write().await;
+    expire(&mut pending);
+    pending.insert(controller, (req.region, Instant::now()));
+    Ok(true)
+}
+
+// 本区域的设备是否在线，供其他区域查询
+pub async fn lookup(id: &str) -> ResultType<FederatedLookup> {
+    let online = if device_ban::is_banned(id).await {
+        false
+    } else {
+        let (tx, rx) = oneshot::channel();
+        send_inbound(Inbound::Lookup(id.to_owned(), tx)).await?;
+        rx.await.unwrap_or(false)
+    };
+    Ok(FederatedLookup {
+        region: get().await.region,
+        online,
+    })
+}
+
+// 设备对其他区域转发来的请求的响应，送回控制端所在区域；不是转发来的请求时返回 false
+pub async fn return_to_origin(controller: SocketAddr, msg: &RendezvousMessage) -> bool {
+    let region = match PENDING.read().await.get(&controller) {
+        Some((region, at)) if at.elapsed() < PENDING_TTL => region.clone(),
+        _ => return false,
+    };
+    let config = get().await;
+    let peer = match config.peer(&region) {
+        Some(peer) => peer.clone(),
+        None => return false,
+    };
+    let message = match encode(msg) {
+        Ok(message) => message,
+        Err(_) => return false,
+    };
+    let body = DeliverRequest {
+        region: config.region.clone(),
+        controller: controller.to_string(),
+        message,
+    };
+    hbb_common::tokio::spawn(async move {
+        let res = match client(REQUEST_TIMEOUT) {
+            Ok(client) => call::<bool>(&config, client.post(url(&peer, "/api/federation/deliver")).json(&body)).await,
+            Err(err) => Err(err),
+        };
+        if let Err(err) = res {
+            log::warn!(
+                "Failed to return response to {} in {}: {}",
+                body.controller,
+                peer.region,
+                err
+            );
+        }
+    });
+    true
+}
+
+// 设备所在区域送回的响应，只接受本区域转发过请求的控制端
+pub async fn deliver(req: DeliverRequest) -> ResultType<()> {
+    let controller: SocketAddr = req.controller.parse()?;
+    match FORWARDED.read().await.get(&controller) {
+        Some((region, at)) if *region == req.region && at.elapsed() < PENDING_TTL => {}
+        _ => bail!("no request forwarded for {}", req.controller),
+    }
+    send_inbound(Inbound::Deliver(controller, decode(&req.message)?)).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate() {
+        let mut config = FederationConfig {
+            enabled: true,
+            region: "eu".to_owned(),
+            secret: "x".repeat(MIN_SECRET_LEN),
+            peers: vec![FederationPeer {
+                region: "apac".to_owned(),
+                api_url: "https://hbbs-apac.example.com:21114".to_owned(),
+                relay_servers: vec!["relay-apac.example.com".to_owned()],
+            }],
+        };
+        assert!(config.validate().is_ok());
+        config.peers.push(config.peers[0].clone());
+        assert!(config.validate().is_err());
+        config.peers[1].region = "eu".to_owned();
+        assert!(config.validate().is_err());
+        config.peers.pop();
+        config.peers[0].api_url = "hbbs-apac.example.com".to_owned();
+        assert!(config.validate().is_err());
+        config.peers[0].api_url = "https://hbbs-apac.example.com".to_owned();
+        config.secret = "short".to_owned();
+        assert!(config.validate().is_err());
+        config.enabled = false;
+        assert!(config.validate().is_ok());
+        assert!(FederationConfig::default().validate().is_ok());
+        assert_eq!(config.masked().secret, SECRET_MASK);
+    }
+
+    #[test]
+    fn test_relay_server() {
+        let mut peer = FederationPeer {
+            region: "apac".to_owned(),
+            api_url: "https://hbbs-apac.example.com".to_owned(),
+            relay_servers: vec![],
+        };
+        assert_eq!(relay_server(&peer), None);
+        peer.relay_servers = vec!["a".to_owned(), "b".to_owned()];
+        let picked: HashSet<String> = (0..4).filter_map(|_| relay_server(&peer)).collect();
+        assert_eq!(picked.len(), 2);
+    }
+
+    #[test]
+    fn test_constant_time_eq() {
+        assert!(constant_time_eq(b"secret", b"secret"));
+        assert!(!constant_time_eq(b"secret", b"secreT"));
+        assert!(!constant_time_eq(b"secret", b"secret!"));
+    }
+}
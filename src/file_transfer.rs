@@ -1,20 +1,30 @@
 // 高级文件传输模块 - 支持大文件、断点续传、文件夹同步
+use crate::advanced_security::{SecurityEvent, SecurityEventType, SecuritySeverity};
+use crate::content_scan::{self, ScanVerdict};
+use crate::dlp::{self, Direction};
+use crate::storage_backend::{LocalStorage, StorageBackend};
+use crate::transfer_bandwidth;
 use hbb_common::{log, ResultType};
+use schemars::JsonSchema;
 use serde_derive::{Deserialize, Serialize};
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     fs::{File, OpenOptions},
-    io::{Read, Seek, SeekFrom, Write},
-    path::{Path, PathBuf},
+    io::Read,
+    path::{Component, Path, PathBuf},
     sync::Arc,
     time::{SystemTime, UNIX_EPOCH},
 };
-use tokio::sync::{Mutex, RwLock};
+use tokio::sync::{mpsc, Mutex, OwnedRwLockReadGuard, RwLock};
 use uuid::Uuid;
 
-const CHUNK_SIZE: usize = 1024 * 1024; // 1MB chunks
+pub const CHUNK_SIZE: usize = 1024 * 1024; // 1MB chunks
 const MAX_CONCURRENT_TRANSFERS: usize = 10;
 const TRANSFER_TIMEOUT: u64 = 300; // 5 minutes
+// 每个流连续的上传块先合并到内存缓冲，达到该大小或传输完成时再落盘
+const WRITE_BUFFER_SIZE: usize = 8 * CHUNK_SIZE;
+// 单个传输允许的并发流数量，客户端可通过多条连接并行发送同一传输的块
+const MAX_STREAMS_PER_TRANSFER: usize = 16;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FileTransferRequest {
@@ -27,9 +37,12 @@ pub struct FileTransferRequest {
     pub transfer_type: TransferType,
     pub compression: bool,
     pub encryption: bool,
+    // 文件来源或目标设备，记录到传输历史
+    #[serde(default)]
+    pub device_id: Option<String>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub enum TransferType {
     Upload,
     Download,
@@ -45,9 +58,12 @@ pub struct FileChunk {
     pub data: Vec<u8>,
     pub checksum: String, // CRC32
     pub is_last: bool,
+    // 并行传输时的流编号，块可以乱序、跨流到达
+    #[serde(default)]
+    pub stream_id: u32,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct TransferProgress {
     pub transfer_id: String,
     pub bytes_transferred: u64,
@@ -56,9 +72,37 @@ pub struct TransferProgress {
     pub eta_seconds: u64, // estimated time to completion
     pub status: TransferStatus,
     pub error_message: Option<String>,
+    #[serde(default)]
+    pub streams: Vec<StreamProgress>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct StreamProgress {
+    pub stream_id: u32,
+    pub bytes_transferred: u64,
+    pub chunks_received: u64,
+    pub speed_bps: u64,
+}
+
+// 已结束(完成、失败或取消)的传输记录，由调用方持久化为传输历史
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct TransferRecord {
+    pub transfer_id: String,
+    pub user_id: String,
+    pub device_id: Option<String>,
+    pub file_path: String,
+    pub transfer_type: TransferType,
+    pub file_size: u64,
+    pub file_hash: String,
+    pub bytes_transferred: u64,
+    pub status: TransferStatus,
+    pub error_message: Option<String>,
+    pub started_at: u64,
+    pub finished_at: u64,
+    pub duration_ms: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub enum TransferStatus {
     Pending,
     InProgress,
@@ -70,32 +114,204 @@ pub enum TransferStatus {
 
 #[derive(Debug, Clone)]
 struct ActiveTransfer {
+    user_id: String,
+    // 发起用户所属组，用于按组限速
+    groups: Vec<String>,
     request: FileTransferRequest,
-    file_handle: Option<File>,
+    // 使用按偏移读写(pread/pwrite)，可在阻塞线程池中并发访问而无需共享文件游标
+    file_handle: Option<Arc<File>>,
     bytes_transferred: u64,
     start_time: SystemTime,
     last_activity: SystemTime,
     chunks_received: HashMap<u64, bool>,
     speed_samples: Vec<(SystemTime, u64)>, // (time, bytes)
+    streams: HashMap<u32, StreamState>,
+    // 落盘期间持有读锁，多个流可并发写入；完成传输前获取写锁等待所有写入结束
+    write_lock: Arc<RwLock<()>>,
+    // 所有块已到达、正在校验保存，之后到达的重复块直接忽略
+    completing: bool,
+}
+
+// 单个流的写缓冲和统计
+#[derive(Debug, Clone)]
+struct StreamState {
+    write_buffer: Vec<u8>,
+    buffer_offset: u64,
+    bytes_transferred: u64,
+    chunks_received: u64,
+    speed_samples: Vec<(SystemTime, u64)>,
+}
+
+impl StreamState {
+    fn new() -> Self {
+        Self {
+            write_buffer: Vec::new(),
+            buffer_offset: 0,
+            bytes_transferred: 0,
+            chunks_received: 0,
+            speed_samples: Vec::new(),
+        }
+    }
+
+    fn take_buffer(&mut self, file: &Arc<File>, guard: OwnedRwLockReadGuard<()>) -> PendingWrite {
+        PendingWrite {
+            file: file.clone(),
+            offset: self.buffer_offset,
+            data: std::mem::take(&mut self.write_buffer),
+            _guard: guard,
+        }
+    }
+}
+
+// 从写缓冲取出、待在阻塞线程池中写入的数据；持有写入读锁直到写完
+struct PendingWrite {
+    file: Arc<File>,
+    offset: u64,
+    data: Vec<u8>,
+    _guard: OwnedRwLockReadGuard<()>,
+}
+
+impl PendingWrite {
+    async fn run(self) -> ResultType<()> {
+        let PendingWrite { file, offset, data, _guard } = self;
+        tokio::task::spawn_blocking(move || write_all_at(&file, &data, offset)).await??;
+        Ok(())
+    }
+}
+
+// 需在持有传输表锁时获取，保证 complete_transfer 移除传输后能等到所有已取出的写入
+fn write_guard(lock: &Arc<RwLock<()>>) -> ResultType<OwnedRwLockReadGuard<()>> {
+    lock.clone()
+        .try_read_owned()
+        .map_err(|_| "Transfer is completing".into())
+}
+
+impl ActiveTransfer {
+    // 缓冲一个上传块到所属流，返回需要落盘的数据；流内不连续的块先把已有缓冲写出
+    fn buffer_chunk(&mut self, stream_id: u32, offset: u64, data: &[u8]) -> ResultType<Vec<PendingWrite>> {
+        let file = match &self.file_handle {
+            Some(file) => file.clone(),
+            None => return Ok(vec![]),
+        };
+        if !self.streams.contains_key(&stream_id) && self.streams.len() >= MAX_STREAMS_PER_TRANSFER {
+            return Err("Too many streams for transfer".into());
+        }
+        let mut writes = vec![];
+        let stream = self.streams.entry(stream_id).or_insert_with(StreamState::new);
+        let contiguous = offset == stream.buffer_offset + stream.write_buffer.len() as u64;
+        if !stream.write_buffer.is_empty() && !contiguous {
+            writes.push(stream.take_buffer(&file, write_guard(&self.write_lock)?));
+        }
+        if stream.write_buffer.is_empty() {
+            stream.buffer_offset = offset;
+        }
+        stream.write_buffer.extend_from_slice(data);
+        if stream.write_buffer.len() >= WRITE_BUFFER_SIZE {
+            writes.push(stream.take_buffer(&file, write_guard(&self.write_lock)?));
+        }
+        Ok(writes)
+    }
+
+    // 取出所有流中尚未落盘的数据
+    fn flush_streams(&mut self) -> ResultType<Vec<PendingWrite>> {
+        let file = match &self.file_handle {
+            Some(file) => file.clone(),
+            None => return Ok(vec![]),
+        };
+        let mut writes = vec![];
+        for stream in self.streams.values_mut() {
+            if !stream.write_buffer.is_empty() {
+                writes.push(stream.take_buffer(&file, write_guard(&self.write_lock)?));
+            }
+        }
+        Ok(writes)
+    }
+
+    fn total_chunks(&self) -> u64 {
+        let size = self.request.file_size;
+        ((size + CHUNK_SIZE as u64 - 1) / CHUNK_SIZE as u64).max(1)
+    }
+}
+
+#[cfg(unix)]
+fn write_all_at(file: &File, buf: &[u8], offset: u64) -> std::io::Result<()> {
+    std::os::unix::fs::FileExt::write_all_at(file, buf, offset)
+}
+
+#[cfg(windows)]
+fn write_all_at(file: &File, mut buf: &[u8], mut offset: u64) -> std::io::Result<()> {
+    use std::os::windows::fs::FileExt;
+    while !buf.is_empty() {
+        let n = file.seek_write(buf, offset)?;
+        if n == 0 {
+            return Err(std::io::ErrorKind::WriteZero.into());
+        }
+        buf = &buf[n..];
+        offset += n as u64;
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn read_exact_at(file: &File, buf: &mut [u8], offset: u64) -> std::io::Result<()> {
+    std::os::unix::fs::FileExt::read_exact_at(file, buf, offset)
+}
+
+#[cfg(windows)]
+fn read_exact_at(file: &File, mut buf: &mut [u8], mut offset: u64) -> std::io::Result<()> {
+    use std::os::windows::fs::FileExt;
+    while !buf.is_empty() {
+        let n = file.seek_read(buf, offset)?;
+        if n == 0 {
+            return Err(std::io::ErrorKind::UnexpectedEof.into());
+        }
+        buf = &mut buf[n..];
+        offset += n as u64;
+    }
+    Ok(())
 }
 
 pub struct FileTransferManager {
     active_transfers: Arc<RwLock<HashMap<String, ActiveTransfer>>>,
     transfer_permissions: Arc<RwLock<HashMap<String, TransferPermissions>>>,
+    // 单独配置过权限的用户，其余用户的权限每次按当前角色重新生成
+    configured_users: RwLock<HashSet<String>>,
     temp_dir: PathBuf,
     max_file_size: u64,
     allowed_extensions: Vec<String>,
+    // 传输完成的文件由存储后端保存，默认以 file_path 作为本地路径
+    storage: RwLock<Arc<dyn StorageBackend>>,
+    security_tx: Mutex<Option<mpsc::UnboundedSender<SecurityEvent>>>,
+    record_tx: Mutex<Option<mpsc::UnboundedSender<TransferRecord>>>,
 }
 
 #[derive(Debug, Clone)]
-struct TransferPermissions {
-    user_id: String,
-    can_upload: bool,
-    can_download: bool,
-    can_sync: bool,
-    max_file_size: u64,
-    allowed_paths: Vec<PathBuf>,
-    blocked_extensions: Vec<String>,
+pub struct TransferPermissions {
+    pub user_id: String,
+    pub can_upload: bool,
+    pub can_download: bool,
+    pub can_sync: bool,
+    pub max_file_size: u64,
+    pub allowed_paths: Vec<PathBuf>,
+    pub blocked_extensions: Vec<String>,
+    pub groups: Vec<String>,
+}
+
+impl TransferPermissions {
+    // 按角色生成默认权限：只读用户和审计员只能下载，路径限制在 allowed_paths 内
+    pub fn for_role(user_id: &str, role: &str, groups: &[String], max_file_size: u64, allowed_paths: Vec<PathBuf>) -> Self {
+        let can_write = role != "ReadOnly" && role != "Auditor";
+        Self {
+            user_id: user_id.to_string(),
+            can_upload: can_write,
+            can_download: true,
+            can_sync: role == "SuperAdmin" || role == "Admin",
+            max_file_size,
+            allowed_paths,
+            blocked_extensions: vec!["exe".to_string(), "bat".to_string(), "cmd".to_string(), "ps1".to_string()],
+            groups: groups.to_vec(),
+        }
+    }
 }
 
 impl FileTransferManager {
@@ -103,6 +319,7 @@ impl FileTransferManager {
         Self {
             active_transfers: Arc::new(RwLock::new(HashMap::new())),
             transfer_permissions: Arc::new(RwLock::new(HashMap::new())),
+            configured_users: RwLock::new(HashSet::new()),
             temp_dir,
             max_file_size,
             allowed_extensions: vec![
@@ -113,14 +330,134 @@ impl FileTransferManager {
                 "jpeg".to_string(), "png".to_string(), "gif".to_string(),
                 "mp4".to_string(), "avi".to_string(), "mkv".to_string(),
             ],
+            storage: RwLock::new(Arc::new(LocalStorage::new(PathBuf::new()))),
+            security_tx: Mutex::new(None),
+            record_tx: Mutex::new(None),
         }
     }
 
+    // 订阅传输过程中产生的安全事件（检出恶意文件等），由调用方持久化
+    pub async fn subscribe_security_events(&self) -> mpsc::UnboundedReceiver<SecurityEvent> {
+        let (tx, rx) = mpsc::unbounded_channel();
+        *self.security_tx.lock().await = Some(tx);
+        rx
+    }
+
+    async fn raise_security_event(
+        &self,
+        event_type: SecurityEventType,
+        severity: SecuritySeverity,
+        user_id: &str,
+        details: HashMap<String, String>,
+    ) {
+        let event = SecurityEvent {
+            id: Uuid::new_v4().to_string(),
+            event_type,
+            severity,
+            user_id: Some(user_id.to_string()),
+            device_id: None,
+            ip_address: String::new(),
+            user_agent: None,
+            details,
+            timestamp: SystemTime::now(),
+            resolved: false,
+            resolution_notes: None,
+        };
+        if let Some(tx) = self.security_tx.lock().await.as_ref() {
+            tx.send(event).ok();
+        }
+    }
+
+    // 订阅已结束传输的记录，由调用方写入传输历史
+    pub async fn subscribe_transfer_records(&self) -> mpsc::UnboundedReceiver<TransferRecord> {
+        let (tx, rx) = mpsc::unbounded_channel();
+        *self.record_tx.lock().await = Some(tx);
+        rx
+    }
+
+    async fn record_transfer(&self, transfer: &ActiveTransfer, status: TransferStatus, error_message: Option<String>) {
+        let now = SystemTime::now();
+        let secs = |t: SystemTime| t.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default();
+        let record = TransferRecord {
+            transfer_id: transfer.request.transfer_id.clone(),
+            user_id: transfer.user_id.clone(),
+            device_id: transfer.request.device_id.clone(),
+            file_path: transfer.request.file_path.clone(),
+            transfer_type: transfer.request.transfer_type.clone(),
+            file_size: transfer.request.file_size,
+            file_hash: transfer.request.file_hash.clone(),
+            bytes_transferred: transfer.bytes_transferred,
+            status,
+            error_message,
+            started_at: secs(transfer.start_time),
+            finished_at: secs(now),
+            duration_ms: now.duration_since(transfer.start_time).unwrap_or_default().as_millis() as u64,
+        };
+        if let Some(tx) = self.record_tx.lock().await.as_ref() {
+            tx.send(record).ok();
+        }
+    }
+
+    // 将被拒绝的暂存文件移入隔离目录，返回隔离后的路径
+    fn quarantine(&self, temp_file_path: &Path, transfer_id: &str) -> ResultType<PathBuf> {
+        let dir = self.temp_dir.join("quarantine");
+        std::fs::create_dir_all(&dir)?;
+        let target = dir.join(format!("{}.bin", transfer_id));
+        std::fs::rename(temp_file_path, &target)?;
+        Ok(target)
+    }
+
+    // 设置存储后端，之后 file_path 作为存储后端中的 key
+    pub async fn set_storage(&self, storage: Arc<dyn StorageBackend>) {
+        *self.storage.write().await = storage;
+    }
+
+    pub async fn storage(&self) -> Arc<dyn StorageBackend> {
+        self.storage.read().await.clone()
+    }
+
     // 设置用户传输权限
     pub async fn set_user_permissions(&self, user_id: String, permissions: TransferPermissions) {
+        self.configured_users.write().await.insert(user_id.clone());
         self.transfer_permissions.write().await.insert(user_id, permissions);
     }
 
+    // 用户未单独配置权限时按当前角色授予默认权限，角色变更后立即生效
+    pub async fn ensure_user_permissions(&self, user_id: &str, role: &str, groups: &[String], allowed_paths: Vec<PathBuf>) {
+        if self.configured_users.read().await.contains(user_id) {
+            return;
+        }
+        self.transfer_permissions.write().await.insert(
+            user_id.to_string(),
+            TransferPermissions::for_role(user_id, role, groups, self.max_file_size, allowed_paths),
+        );
+    }
+
+    pub async fn owner_of(&self, transfer_id: &str) -> Option<String> {
+        self.active_transfers
+            .read()
+            .await
+            .get(transfer_id)
+            .map(|t| t.user_id.clone())
+    }
+
+    // Web 下载接口直接读取存储而不建立传输，按下载请求检查权限
+    pub async fn check_download(&self, user_id: &str, key: &str, size: u64) -> ResultType<()> {
+        let request = FileTransferRequest {
+            transfer_id: String::new(),
+            file_path: key.to_string(),
+            file_size: size,
+            file_hash: String::new(),
+            chunk_size: CHUNK_SIZE,
+            resume_from: 0,
+            transfer_type: TransferType::Download,
+            compression: false,
+            encryption: false,
+            device_id: None,
+        };
+        self.check_permissions(user_id, &request).await
+    }
+
     // 检查用户权限
     async fn check_permissions(&self, user_id: &str, request: &FileTransferRequest) -> ResultType<()> {
         let permissions = self.transfer_permissions.read().await;
@@ -159,6 +496,14 @@ impl FileTransferManager {
             }
         }
 
+        // 拒绝包含 .. 的路径，避免绕过下面的前缀检查
+        if Path::new(&request.file_path)
+            .components()
+            .any(|c| c == Component::ParentDir)
+        {
+            return Err("File path not allowed".into());
+        }
+
         // 检查路径权限
         if !user_perms.allowed_paths.is_empty() {
             let file_path = Path::new(&request.file_path);
@@ -173,10 +518,64 @@ impl FileTransferManager {
         Ok(())
     }
 
+    // 检查 DLP 规则：文件名和大小，下载时还扫描文件内容；违规记为安全事件
+    pub async fn enforce_dlp(&self, user_id: &str, key: &str, size: u64, direction: Direction) -> ResultType<()> {
+        let mut violation = dlp::check_transfer(key, size, direction).await;
+        if violation.is_none() && direction == Direction::Download {
+            if let Some(scan_bytes) = dlp::content_scan_bytes().await {
+                let data = self
+                    .storage()
+                    .await
+                    .read_range(key, 0, size.min(scan_bytes) as usize)
+                    .await?;
+                violation = dlp::scan_content(&data).await;
+            }
+        }
+        match violation {
+            Some(reason) => {
+                self.report_dlp_violation(user_id, key, direction, &reason, None).await;
+                Err(format!("Blocked by DLP policy: {}", reason).into())
+            }
+            None => Ok(()),
+        }
+    }
+
+    async fn report_dlp_violation(
+        &self,
+        user_id: &str,
+        key: &str,
+        direction: Direction,
+        reason: &str,
+        quarantine_path: Option<&Path>,
+    ) {
+        log::warn!("DLP violation by {} on {:?} of {}: {}", user_id, direction, key, reason);
+        let mut details = HashMap::new();
+        details.insert("file_path".to_string(), key.to_string());
+        details.insert("direction".to_string(), format!("{:?}", direction));
+        details.insert("rule".to_string(), reason.to_string());
+        if let Some(path) = quarantine_path {
+            details.insert("quarantine_path".to_string(), path.to_string_lossy().into_owned());
+        }
+        let event_type = match direction {
+            Direction::Download => SecurityEventType::DataExfiltration,
+            Direction::Upload => SecurityEventType::SuspiciousActivity,
+        };
+        self.raise_security_event(event_type, SecuritySeverity::Medium, user_id, details).await;
+    }
+
     // 开始文件传输
     pub async fn start_transfer(&self, user_id: &str, mut request: FileTransferRequest) -> ResultType<String> {
         // 检查权限
         self.check_permissions(user_id, &request).await?;
+        match request.transfer_type {
+            TransferType::Upload => {
+                self.enforce_dlp(user_id, &request.file_path, request.file_size, Direction::Upload).await?
+            }
+            TransferType::Download => {
+                self.enforce_dlp(user_id, &request.file_path, request.file_size, Direction::Download).await?
+            }
+            _ => {}
+        }
 
         // 检查并发传输限制
         let active_count = self.active_transfers.read().await.len();
@@ -195,36 +594,56 @@ impl FileTransferManager {
         // 打开或创建文件
         let file_handle = match request.transfer_type {
             TransferType::Upload => {
-                let mut file = if request.resume_from > 0 && temp_file_path.exists() {
+                let file = if request.resume_from > 0 && temp_file_path.exists() {
                     // 断点续传
-                    let mut file = OpenOptions::new()
+                    OpenOptions::new()
                         .write(true)
                         .read(true)
-                        .open(&temp_file_path)?;
-                    file.seek(SeekFrom::Start(request.resume_from))?;
-                    file
+                        .open(&temp_file_path)?
                 } else {
                     // 新文件
                     File::create(&temp_file_path)?
                 };
-                Some(file)
-            }
-            TransferType::Download => {
-                let file = File::open(&request.file_path)?;
-                Some(file)
+                // 预分配为稀疏文件，乱序到达的块按偏移直接写入
+                file.set_len(request.file_size)?;
+                Some(Arc::new(file))
             }
+            // 非本地存储时按块从存储后端读取
+            TransferType::Download => match self.storage().await.local_path(&request.file_path) {
+                Some(path) => Some(Arc::new(File::open(path)?)),
+                None => None,
+            },
             _ => None,
         };
 
+        // 断点续传时 resume_from 之前的完整块视为已接收
+        let resumed_chunks = match request.transfer_type {
+            TransferType::Upload => request.resume_from.min(request.file_size) / CHUNK_SIZE as u64,
+            _ => 0,
+        };
+
+        let groups = self
+            .transfer_permissions
+            .read()
+            .await
+            .get(user_id)
+            .map(|p| p.groups.clone())
+            .unwrap_or_default();
+
         // 创建活跃传输记录
         let transfer = ActiveTransfer {
+            user_id: user_id.to_string(),
+            groups,
             request: request.clone(),
             file_handle,
-            bytes_transferred: request.resume_from,
+            bytes_transferred: resumed_chunks * CHUNK_SIZE as u64,
             start_time: SystemTime::now(),
             last_activity: SystemTime::now(),
-            chunks_received: HashMap::new(),
+            chunks_received: (0..resumed_chunks).map(|i| (i, true)).collect(),
             speed_samples: Vec::new(),
+            streams: HashMap::new(),
+            write_lock: Arc::new(RwLock::new(())),
+            completing: false,
         };
 
         self.active_transfers.write().await.insert(request.transfer_id.clone(), transfer);
@@ -233,73 +652,235 @@ impl FileTransferManager {
         Ok(request.transfer_id)
     }
 
-    // 处理文件块
-    pub async fn handle_chunk(&self, chunk: FileChunk) -> ResultType<()> {
-        let mut transfers = self.active_transfers.write().await;
-        let transfer = transfers.get_mut(&chunk.transfer_id)
-            .ok_or("Transfer not found")?;
+    // 处理文件块，块可以乱序、跨流到达；所有块到齐后完成传输并返回 true
+    pub async fn handle_chunk(&self, chunk: FileChunk) -> ResultType<bool> {
+        if chunk.data.len() > CHUNK_SIZE {
+            return Err("Chunk too large".into());
+        }
 
-        // 验证块校验和
+        // 验证块校验和，在获取锁之前完成
         let calculated_checksum = self.calculate_crc32(&chunk.data);
         if calculated_checksum != chunk.checksum {
             return Err("Chunk checksum mismatch".into());
         }
 
-        // 写入数据
-        if let Some(ref mut file) = transfer.file_handle {
-            let seek_pos = chunk.chunk_index * CHUNK_SIZE as u64;
-            file.seek(SeekFrom::Start(seek_pos))?;
-            file.write_all(&chunk.data)?;
-            file.flush()?;
+        let mut transfers = self.active_transfers.write().await;
+        let transfer = transfers.get_mut(&chunk.transfer_id)
+            .ok_or("Transfer not found")?;
+        if matches!(transfer.request.transfer_type, TransferType::Download) {
+            return Err("Not an upload transfer".into());
+        }
+
+        // 除最后一块外每块都必须是完整块，保证偏移与块序号一一对应
+        let offset = chunk.chunk_index * CHUNK_SIZE as u64;
+        if chunk.chunk_index >= transfer.total_chunks()
+            || chunk.data.len() as u64 != (transfer.request.file_size - offset).min(CHUNK_SIZE as u64)
+        {
+            return Err("Chunk out of range".into());
         }
+        let now = SystemTime::now();
+        transfer.last_activity = now;
+        // 重传的块不重复写入和计数
+        if transfer.completing || transfer.chunks_received.contains_key(&chunk.chunk_index) {
+            return Ok(false);
+        }
+
+        // 写入所属流的缓冲，需要落盘的部分在释放锁后交给阻塞线程池
+        let mut writes = transfer.buffer_chunk(chunk.stream_id, offset, &chunk.data)?;
 
         // 更新进度
         transfer.chunks_received.insert(chunk.chunk_index, true);
         transfer.bytes_transferred += chunk.data.len() as u64;
-        transfer.last_activity = SystemTime::now();
-
-        // 更新速度统计
-        let now = SystemTime::now();
         transfer.speed_samples.push((now, transfer.bytes_transferred));
-        
         // 保持最近10个样本
         if transfer.speed_samples.len() > 10 {
             transfer.speed_samples.remove(0);
         }
+        if let Some(stream) = transfer.streams.get_mut(&chunk.stream_id) {
+            stream.bytes_transferred += chunk.data.len() as u64;
+            stream.chunks_received += 1;
+            stream.speed_samples.push((now, stream.bytes_transferred));
+            if stream.speed_samples.len() > 10 {
+                stream.speed_samples.remove(0);
+            }
+        }
 
-        // 检查是否完成
-        if chunk.is_last {
+        // 所有块到齐时写出各流剩余缓冲
+        let completed = transfer.chunks_received.len() as u64 >= transfer.total_chunks();
+        if completed {
+            transfer.completing = true;
+            writes.extend(transfer.flush_streams()?);
+        }
+        let groups = transfer.groups.clone();
+
+        // complete_transfer 需要重新获取写锁
+        drop(transfers);
+        // 超出带宽上限时在此等待，延迟处理下一块从而对客户端形成反压
+        transfer_bandwidth::throttle(&groups, chunk.data.len()).await;
+        for write in writes {
+            write.run().await?;
+        }
+        if completed {
             self.complete_transfer(&chunk.transfer_id).await?;
         }
 
-        Ok(())
+        Ok(completed)
     }
 
-    // 完成传输
-    async fn complete_transfer(&self, transfer_id: &str) -> ResultType<()> {
+    // 上传尚未收到的块序号，最多返回 limit 个，供并行传输断线后补发
+    pub async fn missing_chunks(&self, transfer_id: &str, limit: usize) -> Option<Vec<u64>> {
+        let transfers = self.active_transfers.read().await;
+        let transfer = transfers.get(transfer_id)?;
+        Some(
+            (0..transfer.total_chunks())
+                .filter(|i| !transfer.chunks_received.contains_key(i))
+                .take(limit)
+                .collect(),
+        )
+    }
+
+    // 读取下载传输的文件块
+    pub async fn read_chunk(&self, transfer_id: &str, chunk_index: u64) -> ResultType<FileChunk> {
+        let mut transfers = self.active_transfers.write().await;
+        let transfer = transfers.get_mut(transfer_id)
+            .ok_or("Transfer not found")?;
+        if !matches!(transfer.request.transfer_type, TransferType::Download) {
+            return Err("Not a download transfer".into());
+        }
+        let offset = chunk_index * CHUNK_SIZE as u64;
+        let file_size = transfer.request.file_size;
+        if offset >= file_size && file_size > 0 {
+            return Err("Chunk index out of range".into());
+        }
+        let len = (file_size - offset).min(CHUNK_SIZE as u64) as usize;
+        let file = transfer.file_handle.clone();
+        let key = transfer.request.file_path.clone();
+        let groups = transfer.groups.clone();
+        // 读取和限速等待期间不持有锁
+        drop(transfers);
+        transfer_bandwidth::throttle(&groups, len).await;
+        let data = match file {
+            Some(file) => {
+                tokio::task::spawn_blocking(move || {
+                    let mut data = vec![0u8; len];
+                    read_exact_at(&file, &mut data, offset).map(|_| data)
+                })
+                .await??
+            }
+            None => self.storage().await.read_range(&key, offset, len).await?,
+        };
         let mut transfers = self.active_transfers.write().await;
-        if let Some(mut transfer) = transfers.remove(transfer_id) {
-            // 验证文件完整性
-            if let Some(mut file) = transfer.file_handle.take() {
-                file.flush()?;
-                drop(file);
-
-                // 验证文件哈希
-                let temp_file_path = self.temp_dir.join(format!("{}.tmp", transfer_id));
-                let file_hash = self.calculate_file_hash(&temp_file_path)?;
-                
-                if file_hash == transfer.request.file_hash {
-                    // 移动到最终位置
-                    let final_path = Path::new(&transfer.request.file_path);
-                    if let Some(parent) = final_path.parent() {
-                        std::fs::create_dir_all(parent)?;
+        let transfer = transfers.get_mut(transfer_id)
+            .ok_or("Transfer not found")?;
+        if transfer.chunks_received.insert(chunk_index, true).is_none() {
+            transfer.bytes_transferred += len as u64;
+        }
+        transfer.last_activity = SystemTime::now();
+        let now = SystemTime::now();
+        transfer.speed_samples.push((now, transfer.bytes_transferred));
+        if transfer.speed_samples.len() > 10 {
+            transfer.speed_samples.remove(0);
+        }
+        let is_last = offset + len as u64 >= file_size;
+        let checksum = self.calculate_crc32(&data);
+        if is_last {
+            if let Some(transfer) = transfers.remove(transfer_id) {
+                drop(transfers);
+                self.record_transfer(&transfer, TransferStatus::Completed, None).await;
+            }
+            log::info!("Download completed: {}", transfer_id);
+        }
+        Ok(FileChunk {
+            transfer_id: transfer_id.to_string(),
+            chunk_index,
+            chunk_size: len,
+            data,
+            checksum,
+            is_last,
+            stream_id: 0,
+        })
+    }
+
+    // 完成传输，结果写入传输历史
+    async fn complete_transfer(&self, transfer_id: &str) -> ResultType<()> {
+        let transfer = self.active_transfers.write().await.remove(transfer_id);
+        if let Some(mut transfer) = transfer {
+            let result = self.store_upload(transfer_id, &mut transfer).await;
+            match &result {
+                Ok(()) => self.record_transfer(&transfer, TransferStatus::Completed, None).await,
+                Err(err) => {
+                    self.record_transfer(&transfer, TransferStatus::Failed, Some(err.to_string()))
+                        .await
+                }
+            }
+            return result;
+        }
+        Ok(())
+    }
+
+    // 校验、扫描上传的暂存文件并交给存储后端保存
+    async fn store_upload(&self, transfer_id: &str, transfer: &mut ActiveTransfer) -> ResultType<()> {
+        // 验证文件完整性
+        if let Some(file) = transfer.file_handle.take() {
+            // 等待各流仍在进行的落盘完成
+            drop(transfer.write_lock.write().await);
+            drop(file);
+
+            // 验证文件哈希
+            let temp_file_path = self.temp_dir.join(format!("{}.tmp", transfer_id));
+            let hash_path = temp_file_path.clone();
+            let file_hash = tokio::task::spawn_blocking(move || file_sha256(&hash_path)).await??;
+            
+            if file_hash == transfer.request.file_hash {
+                // 保存前扫描暂存文件，检出威胁或扫描失败(fail_closed)时隔离
+                let verdict = content_scan::scan_file(&temp_file_path).await;
+                if verdict.rejects(content_scan::get().await.fail_closed) {
+                    let quarantined = self.quarantine(&temp_file_path, transfer_id)?;
+                    let (event_type, severity, reason) = match &verdict {
+                        ScanVerdict::Infected(name) => {
+                            (SecurityEventType::MalwareDetection, SecuritySeverity::High, name.clone())
+                        }
+                        _ => (SecurityEventType::SuspiciousActivity, SecuritySeverity::Medium, format!("{:?}", verdict)),
+                    };
+                    log::warn!("Transfer {} quarantined: {}", transfer_id, reason);
+                    let mut details = HashMap::new();
+                    details.insert("transfer_id".to_string(), transfer_id.to_string());
+                    details.insert("file_path".to_string(), transfer.request.file_path.clone());
+                    details.insert("file_hash".to_string(), file_hash);
+                    details.insert("verdict".to_string(), reason.clone());
+                    details.insert("quarantine_path".to_string(), quarantined.to_string_lossy().into_owned());
+                    self.raise_security_event(event_type, severity, &transfer.user_id, details).await;
+                    return Err(format!("File rejected by content scan: {}", reason).into());
+                }
+
+                // DLP 内容规则
+                if let Some(scan_bytes) = dlp::content_scan_bytes().await {
+                    let mut data = Vec::new();
+                    File::open(&temp_file_path)?.take(scan_bytes).read_to_end(&mut data)?;
+                    if let Some(reason) = dlp::scan_content(&data).await {
+                        let quarantined = self.quarantine(&temp_file_path, transfer_id)?;
+                        self.report_dlp_violation(
+                            &transfer.user_id,
+                            &transfer.request.file_path,
+                            Direction::Upload,
+                            &reason,
+                            Some(&quarantined),
+                        )
+                        .await;
+                        return Err(format!("Blocked by DLP policy: {}", reason).into());
                     }
-                    std::fs::rename(&temp_file_path, final_path)?;
-                    log::info!("Transfer completed successfully: {}", transfer_id);
-                } else {
-                    std::fs::remove_file(&temp_file_path)?;
-                    return Err("File hash verification failed".into());
                 }
+
+                // 交给存储后端保存到最终位置
+                let storage = self.storage().await;
+                if let Err(err) = storage.store_file(&temp_file_path, &transfer.request.file_path).await {
+                    let _ = std::fs::remove_file(&temp_file_path);
+                    return Err(err);
+                }
+                log::info!("Transfer completed successfully: {} ({})", transfer_id, storage.name());
+            } else {
+                std::fs::remove_file(&temp_file_path)?;
+                return Err("File hash verification failed".into());
             }
         }
         Ok(())
@@ -311,10 +892,21 @@ impl FileTransferManager {
         if let Some(transfer) = transfers.get(transfer_id) {
             let speed = self.calculate_speed(transfer);
             let eta = if speed > 0 {
-                (transfer.request.file_size - transfer.bytes_transferred) / speed
+                transfer.request.file_size.saturating_sub(transfer.bytes_transferred) / speed
             } else {
                 0
             };
+            let mut streams: Vec<StreamProgress> = transfer
+                .streams
+                .iter()
+                .map(|(stream_id, stream)| StreamProgress {
+                    stream_id: *stream_id,
+                    bytes_transferred: stream.bytes_transferred,
+                    chunks_received: stream.chunks_received,
+                    speed_bps: samples_speed(&stream.speed_samples),
+                })
+                .collect();
+            streams.sort_by_key(|s| s.stream_id);
 
             Some(TransferProgress {
                 transfer_id: transfer_id.to_string(),
@@ -324,6 +916,7 @@ impl FileTransferManager {
                 eta_seconds: eta,
                 status: TransferStatus::InProgress,
                 error_message: None,
+                streams,
             })
         } else {
             None
@@ -343,8 +936,9 @@ impl FileTransferManager {
 
     // 取消传输
     pub async fn cancel_transfer(&self, transfer_id: &str) -> ResultType<()> {
-        let mut transfers = self.active_transfers.write().await;
-        if let Some(transfer) = transfers.remove(transfer_id) {
+        let transfer = self.active_transfers.write().await.remove(transfer_id);
+        if let Some(transfer) = transfer {
+            self.record_transfer(&transfer, TransferStatus::Cancelled, None).await;
             // 清理临时文件
             let temp_file_path = self.temp_dir.join(format!("{}.tmp", transfer_id));
             if temp_file_path.exists() {
@@ -420,6 +1014,7 @@ impl FileTransferManager {
                         transfer_type: TransferType::Sync,
                         compression: true,
                         encryption: false,
+                        device_id: None,
                     };
 
                     let transfer_id = self.start_transfer(user_id, request).await?;
@@ -446,25 +1041,11 @@ impl FileTransferManager {
 
     // 计算传输速度
     fn calculate_speed(&self, transfer: &ActiveTransfer) -> u64 {
-        if transfer.speed_samples.len() < 2 {
-            return 0;
-        }
-
-        let first = &transfer.speed_samples[0];
-        let last = &transfer.speed_samples[transfer.speed_samples.len() - 1];
-
-        let time_diff = last.0.duration_since(first.0).unwrap_or_default().as_secs();
-        let bytes_diff = last.1 - first.1;
-
-        if time_diff > 0 {
-            bytes_diff / time_diff
-        } else {
-            0
-        }
+        samples_speed(&transfer.speed_samples)
     }
 
     // 计算CRC32校验和
-    fn calculate_crc32(&self, data: &[u8]) -> String {
+    pub fn calculate_crc32(&self, data: &[u8]) -> String {
         use crc32fast::Hasher;
         let mut hasher = Hasher::new();
         hasher.update(data);
@@ -473,21 +1054,7 @@ impl FileTransferManager {
 
     // 计算文件SHA256哈希
     fn calculate_file_hash(&self, path: &Path) -> ResultType<String> {
-        use sha2::{Sha256, Digest};
-        
-        let mut file = File::open(path)?;
-        let mut hasher = Sha256::new();
-        let mut buffer = [0; 8192];
-
-        loop {
-            let bytes_read = file.read(&mut buffer)?;
-            if bytes_read == 0 {
-                break;
-            }
-            hasher.update(&buffer[..bytes_read]);
-        }
-
-        Ok(format!("{:x}", hasher.finalize()))
+        file_sha256(path)
     }
 
     // 清理过期传输
@@ -504,8 +1071,10 @@ impl FileTransferManager {
             }
         }
 
+        let mut expired = Vec::new();
         for id in to_remove {
             if let Some(transfer) = transfers.remove(&id) {
+                expired.push(transfer);
                 // 清理临时文件
                 let temp_file_path = self.temp_dir.join(format!("{}.tmp", id));
                 if temp_file_path.exists() {
@@ -514,7 +1083,48 @@ impl FileTransferManager {
                 log::info!("Cleaned up expired transfer: {}", id);
             }
         }
+        drop(transfers);
+        for transfer in expired {
+            self.record_transfer(&transfer, TransferStatus::Failed, Some("Transfer timed out".to_string()))
+                .await;
+        }
+    }
+}
+
+fn samples_speed(samples: &[(SystemTime, u64)]) -> u64 {
+    if samples.len() < 2 {
+        return 0;
+    }
+
+    let first = &samples[0];
+    let last = &samples[samples.len() - 1];
+
+    let time_diff = last.0.duration_since(first.0).unwrap_or_default().as_secs();
+    let bytes_diff = last.1 - first.1;
+
+    if time_diff > 0 {
+        bytes_diff / time_diff
+    } else {
+        0
+    }
+}
+
+fn file_sha256(path: &Path) -> ResultType<String> {
+    use sha2::{Sha256, Digest};
+
+    let mut file = File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0; 64 * 1024];
+
+    loop {
+        let bytes_read = file.read(&mut buffer)?;
+        if bytes_read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..bytes_read]);
     }
+
+    Ok(format!("{:x}", hasher.finalize()))
 }
 
 // 文件压缩支持
@@ -561,6 +1171,7 @@ mod tests {
             max_file_size: 1024 * 1024 * 10,
             allowed_paths: vec![temp_dir.path().to_path_buf()],
             blocked_extensions: vec!["exe".to_string()],
+            groups: vec![],
         };
 
         manager.set_user_permissions("test_user".to_string(), permissions).await;
@@ -576,6 +1187,7 @@ mod tests {
             transfer_type: TransferType::Upload,
             compression: false,
             encryption: false,
+            device_id: None,
         };
 
         let transfer_id = manager.start_transfer("test_user", request).await.unwrap();
@@ -585,4 +1197,405 @@ mod tests {
         let progress = manager.get_progress(&transfer_id).await;
         assert!(progress.is_some());
     }
+
+    #[tokio::test]
+    async fn test_role_permissions_follow_role_change() {
+        let temp_dir = TempDir::new().unwrap();
+        let manager = FileTransferManager::new(temp_dir.path().to_path_buf(), 1024 * 1024);
+        let upload = FileTransferRequest {
+            transfer_id: "".to_string(),
+            file_path: "report.txt".to_string(),
+            file_size: 1024,
+            file_hash: "test_hash".to_string(),
+            chunk_size: CHUNK_SIZE,
+            resume_from: 0,
+            transfer_type: TransferType::Upload,
+            compression: false,
+            encryption: false,
+            device_id: None,
+        };
+
+        manager.ensure_user_permissions("u1", "User", &[], vec![]).await;
+        assert!(manager.check_permissions("u1", &upload).await.is_ok());
+        // 降级为只读后不能再上传
+        manager.ensure_user_permissions("u1", "ReadOnly", &[], vec![]).await;
+        assert!(manager.check_permissions("u1", &upload).await.is_err());
+
+        // 单独配置的权限不被角色默认权限覆盖
+        let permissions = TransferPermissions::for_role("u2", "User", &[], 1024 * 1024, vec![]);
+        manager.set_user_permissions("u2".to_string(), permissions).await;
+        manager.ensure_user_permissions("u2", "ReadOnly", &[], vec![]).await;
+        assert!(manager.check_permissions("u2", &upload).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_parallel_out_of_order_upload() {
+        use sha2::{Digest, Sha256};
+
+        let temp_dir = TempDir::new().unwrap();
+        let manager = FileTransferManager::new(temp_dir.path().to_path_buf(), u64::MAX);
+        manager
+            .ensure_user_permissions("test_user", "Admin", &[], vec![temp_dir.path().to_path_buf()])
+            .await;
+        let content: Vec<u8> = (0..CHUNK_SIZE * 5 / 2).map(|i| (i % 251) as u8).collect();
+        let target = temp_dir.path().join("parallel.bin");
+        let request = FileTransferRequest {
+            transfer_id: "".to_string(),
+            file_path: target.to_string_lossy().to_string(),
+            file_size: content.len() as u64,
+            file_hash: format!("{:x}", Sha256::digest(&content)),
+            chunk_size: CHUNK_SIZE,
+            resume_from: 0,
+            transfer_type: TransferType::Upload,
+            compression: false,
+            encryption: false,
+            device_id: None,
+        };
+        let mut records = manager.subscribe_transfer_records().await;
+        let transfer_id = manager.start_transfer("test_user", request).await.unwrap();
+
+        // 最后一块先到，两个流交错发送，并重复发送一块
+        let chunks: Vec<&[u8]> = content.chunks(CHUNK_SIZE).collect();
+        for (stream_id, chunk_index) in [(1u32, 2u64), (0, 0), (1, 0), (0, 1)] {
+            let data = chunks[chunk_index as usize].to_vec();
+            let completed = manager
+                .handle_chunk(FileChunk {
+                    transfer_id: transfer_id.clone(),
+                    chunk_index,
+                    chunk_size: data.len(),
+                    checksum: manager.calculate_crc32(&data),
+                    data,
+                    is_last: chunk_index == 2,
+                    stream_id,
+                })
+                .await
+                .unwrap();
+            if chunk_index == 0 && stream_id == 0 {
+                let progress = manager.get_progress(&transfer_id).await.unwrap();
+                assert_eq!(progress.streams.len(), 2);
+                assert_eq!(manager.missing_chunks(&transfer_id, 10).await, Some(vec![1]));
+            }
+            assert_eq!(completed, chunk_index == 1);
+        }
+        assert_eq!(std::fs::read(&target).unwrap(), content);
+        assert!(manager.get_progress(&transfer_id).await.is_none());
+        let record = records.try_recv().unwrap();
+        assert_eq!(record.transfer_id, transfer_id);
+        assert!(matches!(record.status, TransferStatus::Completed));
+        assert_eq!(record.bytes_transferred, content.len() as u64);
+    }
+
+    fn chunk(transfer_id: &str, chunk_index: u64, data: Vec<u8>, stream_id: u32) -> FileChunk {
+        FileChunk {
+            transfer_id: transfer_id.to_string(),
+            chunk_index,
+            chunk_size: data.len(),
+            checksum: crc32(&data),
+            data,
+            is_last: false,
+            stream_id,
+        }
+    }
+
+    fn crc32(data: &[u8]) -> String {
+        FileTransferManager::new(PathBuf::new(), 0).calculate_crc32(data)
+    }
+
+    async fn start_upload(manager: &FileTransferManager, dir: &Path, file_size: u64) -> String {
+        manager
+            .ensure_user_permissions("test_user", "Admin", &[], vec![dir.to_path_buf()])
+            .await;
+        let request = FileTransferRequest {
+            transfer_id: "".to_string(),
+            file_path: dir.join("upload.bin").to_string_lossy().to_string(),
+            file_size,
+            file_hash: "unused".to_string(),
+            chunk_size: CHUNK_SIZE,
+            resume_from: 0,
+            transfer_type: TransferType::Upload,
+            compression: false,
+            encryption: false,
+            device_id: None,
+        };
+        manager.start_transfer("test_user", request).await.unwrap()
+    }
+
+    fn temp_len(dir: &Path, transfer_id: &str) -> u64 {
+        std::fs::metadata(dir.join(format!("{}.tmp", transfer_id)))
+            .map(|x| x.len())
+            .unwrap_or_default()
+    }
+
+    #[tokio::test]
+    async fn test_chunk_write_coalescing() {
+        let temp_dir = TempDir::new().unwrap();
+        let manager = FileTransferManager::new(temp_dir.path().to_path_buf(), u64::MAX);
+        let buffered = (WRITE_BUFFER_SIZE / CHUNK_SIZE) as u64;
+        let transfer_id = start_upload(&manager, temp_dir.path(), (buffered * 2 + 2) * CHUNK_SIZE as u64).await;
+
+        // 同一流内连续的块先留在缓冲中
+        for chunk_index in 0..buffered - 1 {
+            let data = vec![chunk_index as u8; CHUNK_SIZE];
+            assert!(!manager.handle_chunk(chunk(&transfer_id, chunk_index, data, 0)).await.unwrap());
+        }
+        assert_eq!(temp_len(temp_dir.path(), &transfer_id), 0);
+        // 缓冲达到上限时整体落盘
+        let data = vec![(buffered - 1) as u8; CHUNK_SIZE];
+        manager.handle_chunk(chunk(&transfer_id, buffered - 1, data, 0)).await.unwrap();
+        assert_eq!(temp_len(temp_dir.path(), &transfer_id), WRITE_BUFFER_SIZE as u64);
+
+        // 流内不连续的块先写出已有缓冲
+        let gap = buffered * 2 + 1;
+        manager.handle_chunk(chunk(&transfer_id, buffered, vec![1; CHUNK_SIZE], 0)).await.unwrap();
+        assert_eq!(temp_len(temp_dir.path(), &transfer_id), WRITE_BUFFER_SIZE as u64);
+        manager.handle_chunk(chunk(&transfer_id, gap, vec![2; CHUNK_SIZE], 0)).await.unwrap();
+        assert_eq!(temp_len(temp_dir.path(), &transfer_id), (buffered + 1) * CHUNK_SIZE as u64);
+
+        // 重复的块不再写入也不重复计数
+        let progress = manager.get_progress(&transfer_id).await.unwrap();
+        assert!(!manager.handle_chunk(chunk(&transfer_id, 0, vec![9; CHUNK_SIZE], 1)).await.unwrap());
+        let again = manager.get_progress(&transfer_id).await.unwrap();
+        assert_eq!(again.bytes_transferred, progress.bytes_transferred);
+        let mut head = vec![0u8; CHUNK_SIZE];
+        File::open(temp_dir.path().join(format!("{}.tmp", transfer_id)))
+            .unwrap()
+            .read_exact(&mut head)
+            .unwrap();
+        assert!(head.iter().all(|x| *x == 0));
+    }
+
+    #[tokio::test]
+    async fn test_chunk_errors() {
+        let temp_dir = TempDir::new().unwrap();
+        let manager = FileTransferManager::new(temp_dir.path().to_path_buf(), u64::MAX);
+        let transfer_id = start_upload(&manager, temp_dir.path(), CHUNK_SIZE as u64 + 10).await;
+
+        let err = |res: ResultType<bool>| res.unwrap_err().to_string();
+        assert_eq!(
+            err(manager.handle_chunk(chunk("missing", 0, vec![0; CHUNK_SIZE], 0)).await),
+            "Transfer not found"
+        );
+        assert_eq!(
+            err(manager.handle_chunk(chunk(&transfer_id, 0, vec![0; CHUNK_SIZE + 1], 0)).await),
+            "Chunk too large"
+        );
+        let mut corrupted = chunk(&transfer_id, 0, vec![0; CHUNK_SIZE], 0);
+        corrupted.data[0] = 1;
+        assert_eq!(err(manager.handle_chunk(corrupted).await), "Chunk checksum mismatch");
+        // 块序号超出文件范围、中间块不完整、最后一块长度不符
+        assert_eq!(
+            err(manager.handle_chunk(chunk(&transfer_id, 2, vec![0; 10], 0)).await),
+            "Chunk out of range"
+        );
+        assert_eq!(
+            err(manager.handle_chunk(chunk(&transfer_id, 0, vec![0; 10], 0)).await),
+            "Chunk out of range"
+        );
+        assert_eq!(
+            err(manager.handle_chunk(chunk(&transfer_id, 1, vec![0; 11], 0)).await),
+            "Chunk out of range"
+        );
+        // 出错的块不影响进度
+        assert_eq!(manager.get_progress(&transfer_id).await.unwrap().bytes_transferred, 0);
+        assert_eq!(manager.missing_chunks(&transfer_id, 10).await, Some(vec![0, 1]));
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_and_failed_transfer_records() {
+        let temp_dir = TempDir::new().unwrap();
+        let manager = FileTransferManager::new(temp_dir.path().to_path_buf(), u64::MAX);
+        let mut records = manager.subscribe_transfer_records().await;
+
+        let transfer_id = start_upload(&manager, temp_dir.path(), 10).await;
+        manager.cancel_transfer(&transfer_id).await.unwrap();
+        let record = records.try_recv().unwrap();
+        assert_eq!(record.transfer_id, transfer_id);
+        assert_eq!(record.user_id, "test_user");
+        assert!(matches!(record.status, TransferStatus::Cancelled));
+        assert_eq!((record.file_size, record.bytes_transferred), (10, 0));
+        assert!(!temp_dir.path().join(format!("{}.tmp", transfer_id)).exists());
+        // 已结束的传输不再重复记录
+        assert!(manager.cancel_transfer(&transfer_id).await.is_err());
+        assert!(records.try_recv().is_err());
+
+        // 哈希不符的上传记录为失败并附带原因
+        let transfer_id = start_upload(&manager, temp_dir.path(), 10).await;
+        let mut last = chunk(&transfer_id, 0, vec![7; 10], 0);
+        last.is_last = true;
+        assert!(manager.handle_chunk(last).await.is_err());
+        let record = records.try_recv().unwrap();
+        assert_eq!(record.transfer_id, transfer_id);
+        assert!(matches!(record.status, TransferStatus::Failed));
+        assert_eq!(record.bytes_transferred, 10);
+        assert!(record.error_message.is_some());
+        assert!(record.finished_at >= record.started_at);
+    }
+
+    #[tokio::test]
+    async fn test_file_transfer_history_filters() {
+        use crate::enterprise_database::{EnterpriseDatabase, FileTransferFilter};
+
+        let db = EnterpriseDatabase::memory().await.unwrap();
+        let record = |id: &str, user_id: &str, device_id: Option<&str>, status: TransferStatus, finished_at: u64| {
+            TransferRecord {
+                transfer_id: id.to_string(),
+                user_id: user_id.to_string(),
+                device_id: device_id.map(|x| x.to_string()),
+                file_path: format!("devices/reports/{}.pdf", id),
+                transfer_type: TransferType::Upload,
+                file_size: 100,
+                file_hash: format!("hash-{}", id),
+                bytes_transferred: 100,
+                status,
+                error_message: None,
+                started_at: finished_at - 10,
+                finished_at,
+                duration_ms: 10_000,
+            }
+        };
+        db.save_file_transfer(&record("t1", "alice", Some("123456789"), TransferStatus::Completed, 1000))
+            .await
+            .unwrap();
+        db.save_file_transfer(&record("t2", "bob", Some("123456789"), TransferStatus::Cancelled, 2000))
+            .await
+            .unwrap();
+        db.save_file_transfer(&record("t3", "alice", None, TransferStatus::Failed, 3000))
+            .await
+            .unwrap();
+
+        let ids = |records: Vec<TransferRecord>| records.into_iter().map(|r| r.transfer_id).collect::<Vec<_>>();
+        let list = |filter: FileTransferFilter| {
+            let db = db.clone();
+            async move { ids(db.list_file_transfers(&filter, 10, 0).await.unwrap()) }
+        };
+        // 按完成时间倒序
+        assert_eq!(list(FileTransferFilter::default()).await, vec!["t3", "t2", "t1"]);
+        let filter = FileTransferFilter {
+            user_id: Some("alice".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(list(filter).await, vec!["t3", "t1"]);
+        let filter = FileTransferFilter {
+            device_id: Some("123456789".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(list(filter).await, vec!["t2", "t1"]);
+        let filter = FileTransferFilter {
+            path: Some("reports/t2".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(list(filter).await, vec!["t2"]);
+        let filter = FileTransferFilter {
+            file_hash: Some("hash-t3".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(list(filter).await, vec!["t3"]);
+        let filter = FileTransferFilter {
+            status: Some("Cancelled".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(list(filter).await, vec!["t2"]);
+        let filter = FileTransferFilter {
+            since: Some(1500),
+            until: Some(3000),
+            ..Default::default()
+        };
+        assert_eq!(list(filter).await, vec!["t3", "t2"]);
+        let filter = FileTransferFilter {
+            user_id: Some("carol".to_string()),
+            ..Default::default()
+        };
+        assert!(list(filter).await.is_empty());
+
+        // 分页
+        let page = db.list_file_transfers(&FileTransferFilter::default(), 2, 2).await.unwrap();
+        assert_eq!(ids(page), vec!["t1"]);
+        let record = db.list_file_transfers(&FileTransferFilter::default(), 1, 0).await.unwrap().remove(0);
+        assert!(matches!(record.status, TransferStatus::Failed));
+        assert!(matches!(record.transfer_type, TransferType::Upload));
+        assert_eq!((record.started_at, record.duration_ms), (2990, 10_000));
+        assert!(record.device_id.is_none());
+    }
+
+    // 分块上传吞吐量基准，对比改动前在异步任务中直接 seek/write/flush 的写法，
+    // 并统计同一运行时上定时任务的最大延迟：cargo test bench_chunk_throughput -- --ignored --nocapture
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    #[ignore]
+    async fn bench_chunk_throughput() {
+        use std::io::{Seek, SeekFrom, Write};
+
+        const CHUNKS: u64 = 256;
+        let temp_dir = TempDir::new().unwrap();
+        let data = vec![0x5au8; CHUNK_SIZE];
+
+        // 改动前：每块在异步任务中同步写入
+        let path = temp_dir.path().join("baseline.bin");
+        let probe = stall_probe();
+        let start = std::time::Instant::now();
+        let mut file = File::create(&path).unwrap();
+        for chunk_index in 0..CHUNKS {
+            file.seek(SeekFrom::Start(chunk_index * CHUNK_SIZE as u64)).unwrap();
+            file.write_all(&data).unwrap();
+            file.flush().unwrap();
+            tokio::task::yield_now().await;
+        }
+        let baseline = start.elapsed().as_secs_f64();
+        let baseline_stall = probe.stop().await;
+
+        let manager = FileTransferManager::new(temp_dir.path().to_path_buf(), u64::MAX);
+        // 多一块，基准过程中不会触发完成校验
+        let transfer_id = start_upload(&manager, temp_dir.path(), (CHUNKS + 1) * CHUNK_SIZE as u64).await;
+        let probe = stall_probe();
+        let start = std::time::Instant::now();
+        for chunk_index in 0..CHUNKS {
+            manager
+                .handle_chunk(chunk(&transfer_id, chunk_index, data.clone(), (chunk_index % 4) as u32))
+                .await
+                .unwrap();
+        }
+        let elapsed = start.elapsed().as_secs_f64();
+        let stall = probe.stop().await;
+        println!(
+            "before: {} MB in {:.3}s: {:.1} MB/s, max timer delay {:?}",
+            CHUNKS,
+            baseline,
+            CHUNKS as f64 / baseline,
+            baseline_stall
+        );
+        println!(
+            "after:  {} MB in {:.3}s: {:.1} MB/s, max timer delay {:?}",
+            CHUNKS,
+            elapsed,
+            CHUNKS as f64 / elapsed,
+            stall
+        );
+    }
+
+    struct StallProbe {
+        stop: Arc<std::sync::atomic::AtomicBool>,
+        task: tokio::task::JoinHandle<std::time::Duration>,
+    }
+
+    impl StallProbe {
+        async fn stop(self) -> std::time::Duration {
+            self.stop.store(true, std::sync::atomic::Ordering::SeqCst);
+            self.task.await.unwrap()
+        }
+    }
+
+    // 每毫秒唤醒一次，记录实际唤醒比预期晚的最大时长
+    fn stall_probe() -> StallProbe {
+        let stop = Arc::new(std::sync::atomic::AtomicBool::new(false));
+        let flag = stop.clone();
+        let task = tokio::spawn(async move {
+            let mut max = std::time::Duration::ZERO;
+            while !flag.load(std::sync::atomic::Ordering::SeqCst) {
+                let start = std::time::Instant::now();
+                tokio::time::sleep(std::time::Duration::from_millis(1)).await;
+                max = max.max(start.elapsed().saturating_sub(std::time::Duration::from_millis(1)));
+            }
+            max
+        });
+        StallProbe { stop, task }
+    }
 }
\ No newline at end of file
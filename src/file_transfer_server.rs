@@ -0,0 +1,388 @@
+// 文件传输服务模块 - 在独立 TCP 端口(信令端口 + 4)上承载 FileTransferManager 的分块传输，
+// 帧格式与信令 TCP 相同(BytesCodec 长度前缀)，帧内容为 JSON 编码的 TransferFrame：
+//   客户端: auth -> start -> chunk...(上传) / fetch...(下载) -> status / cancel(可选)
+//   服务端: started / progress / missing / chunk(下载数据) / done / error
+// 高带宽时延积链路可并行上传：客户端再建立多条认证连接，以不同 stream_id 乱序发送同一传输的块，
+// 所有块到齐后服务端返回 done；断线后通过 status 查询缺失的块补发
+// 客户端只能访问存储目录下的相对路径，且只能操作自己发起的传输；
+// 传输中的文件暂存在 FILE-TRANSFER-DIR/.partial，完成后由存储后端(本地目录或 S3)保存
+use crate::auth::{AuthManager, Claims};
+use crate::common::get_arg;
+use crate::enterprise_database::EnterpriseDatabase;
+use crate::feature_flags::{self, Feature};
+use crate::file_transfer::{FileChunk, FileTransferManager, FileTransferRequest, TransferProgress, TransferType};
+use crate::peer_alias;
+use crate::storage_backend;
+use crate::transfer_bandwidth;
+use hbb_common::{
+    bail,
+    bytes::Bytes,
+    bytes_codec::BytesCodec,
+    futures_util::{sink::SinkExt, stream::StreamExt},
+    log, timeout,
+    tokio::{
+        self,
+        net::{TcpListener, TcpStream},
+        time::{interval, Duration},
+    },
+    tokio_util::codec::Framed,
+    ResultType,
+};
+use serde_derive::{Deserialize, Serialize};
+use std::{
+    net::SocketAddr,
+    path::{Component, Path, PathBuf},
+    sync::Arc,
+};
+
+const FRAME_TIMEOUT_MS: u64 = 60_000;
+const MAX_MISSING_CHUNKS: usize = 1024;
+const DEFAULT_MAX_FILE_SIZE: u64 = 10 * 1024 * 1024 * 1024;
+
+lazy_static::lazy_static! {
+    static ref MANAGER: Arc<FileTransferManager> = Arc::new(FileTransferManager::new(
+        storage_root().join(".partial"),
+        get_arg("file-transfer-max-size").parse().unwrap_or(DEFAULT_MAX_FILE_SIZE),
+    ));
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum TransferFrame {
+    Auth {
+        token: String,
+    },
+    Start {
+        request: FileTransferRequest,
+    },
+    Started {
+        transfer_id: String,
+        resume_from: u64,
+    },
+    Chunk {
+        transfer_id: String,
+        chunk_index: u64,
+        data: String, // base64
+        checksum: String,
+        is_last: bool,
+        #[serde(default)]
+        stream_id: u32,
+    },
+    Fetch {
+        transfer_id: String,
+        chunk_index: u64,
+    },
+    Status {
+        transfer_id: String,
+    },
+    Progress {
+        progress: TransferProgress,
+    },
+    Missing {
+        transfer_id: String,
+        chunks: Vec<u64>,
+    },
+    Done {
+        transfer_id: String,
+    },
+    Cancel {
+        transfer_id: String,
+    },
+    Error {
+        #[serde(default)]
+        transfer_id: Option<String>,
+        message: String,
+    },
+}
+
+impl TransferFrame {
+    fn error(transfer_id: Option<&str>, message: impl ToString) -> Self {
+        TransferFrame::Error {
+            transfer_id: transfer_id.map(|id| id.to_owned()),
+            message: message.to_string(),
+        }
+    }
+}
+
+pub fn manager() -> Arc<FileTransferManager> {
+    MANAGER.clone()
+}
+
+pub fn storage_root() -> PathBuf {
+    let dir = get_arg("file-transfer-dir");
+    PathBuf::from(if dir.is_empty() { "files" } else { dir.as_str() })
+}
+
+// 将客户端提交的相对路径映射到存储目录下，拒绝绝对路径、.. 和隐藏目录(含 .partial 临时目录)
+pub fn resolve_path(relative: &str) -> ResultType<PathBuf> {
+    let path = Path::new(relative);
+    if relative.is_empty()
+        || path.components().any(|c| match c {
+            Component::Normal(name) => name.to_string_lossy().starts_with('.'),
+            Component::CurDir => false,
+            _ => true,
+        })
+    {
+        bail!("invalid file path: {}", relative);
+    }
+    Ok(storage_root().join(path))
+}
+
+// 解析单段 Range 请求头(bytes=a-b / bytes=a- / bytes=-n)，返回闭区间；不满足时返回 None
+pub fn parse_range(range: &str, len: u64) -> Option<(u64, u64)> {
+    let spec = range.trim().strip_prefix("bytes=")?;
+    if spec.contains(',') || len == 0 {
+        return None;
+    }
+    let (start, end) = spec.split_once('-')?;
+    let (start, end) = match (start.trim(), end.trim()) {
+        ("", suffix) => {
+            let suffix: u64 = suffix.parse().ok()?;
+            (len.saturating_sub(suffix), len - 1)
+        }
+        (start, "") => (start.parse().ok()?, len - 1),
+        (start, end) => (start.parse().ok()?, end.parse::<u64>().ok()?.min(len - 1)),
+    };
+    if start > end {
+        return None;
+    }
+    Some((start, end))
+}
+
+pub async fn start(port: i32, auth: Arc<AuthManager>, db: EnterpriseDatabase) -> ResultType<()> {
+    std::fs::create_dir_all(storage_root().join(".partial"))?;
+    manager()
+        .set_storage(storage_backend::from_args(storage_root())?)
+        .await;
+    // 持久化传输过程中产生的安全事件
+    let mut security_events = manager().subscribe_security_events().await;
+    let events_db = db.clone();
+    tokio::spawn(async move {
+        while let Some(event) = security_events.recv().await {
+            if let Err(err) = events_db.save_security_event(&event).await {
+                log::error!("Failed to save security event {}: {}", event.id, err);
+            }
+            crate::notifications::security_event(&events_db, &event).await;
+            crate::itsm::security_event(&event).await;
+        }
+    });
+    transfer_bandwidth::start(db.clone());
+    // 已结束的传输写入传输历史
+    let mut records = manager().subscribe_transfer_records().await;
+    tokio::spawn(async move {
+        while let Some(record) = records.recv().await {
+            if let Err(err) = db.save_file_transfer(&record).await {
+                log::error!("Failed to save file transfer {}: {}", record.transfer_id, err);
+            }
+        }
+    });
+    let listener = TcpListener::bind(format!("0.0.0.0:{}", port)).await?;
+    log::info!("File transfer server started on port {}", port);
+    tokio::spawn(async move {
+        let mut interval = interval(Duration::from_secs(60));
+        loop {
+            interval.tick().await;
+            manager().cleanup_expired_transfers().await;
+        }
+    });
+    loop {
+        let (stream, addr) = listener.accept().await?;
+        let auth = auth.clone();
+        tokio::spawn(async move {
+            if let Err(err) = handle_connection(stream, addr, auth).await {
+                log::debug!("File transfer connection {} closed: {}", addr, err);
+            }
+        });
+    }
+}
+
+async fn read_frame(framed: &mut Framed<TcpStream, BytesCodec>) -> ResultType<Option<TransferFrame>> {
+    match timeout(FRAME_TIMEOUT_MS, framed.next()).await? {
+        Some(bytes) => Ok(Some(serde_json::from_slice(&bytes?)?)),
+        None => Ok(None),
+    }
+}
+
+async fn send_frame(framed: &mut Framed<TcpStream, BytesCodec>, frame: &TransferFrame) -> ResultType<()> {
+    framed.send(Bytes::from(serde_json::to_vec(frame)?)).await?;
+    Ok(())
+}
+
+async fn handle_connection(stream: TcpStream, addr: SocketAddr, auth: Arc<AuthManager>) -> ResultType<()> {
+    let mut framed = Framed::new(stream, BytesCodec::new());
+    // 第一帧必须是携带完整登录令牌的 auth
+    let claims = match read_frame(&mut framed).await? {
+        Some(TransferFrame::Auth { token }) => auth.verify_jwt(&token).ok().filter(|c| c.scope.is_none()),
+        _ => None,
+    };
+    let claims = match claims {
+        Some(claims) => claims,
+        None => {
+            send_frame(&mut framed, &TransferFrame::error(None, "unauthorized")).await?;
+            bail!("unauthorized");
+        }
+    };
+    let manager = manager();
+    manager
+        .ensure_user_permissions(&claims.sub, &claims.role, &claims.groups, vec![])
+        .await;
+    log::info!("File transfer session of {} from {}", claims.username, addr);
+
+    while let Some(frame) = read_frame(&mut framed).await? {
+        for reply in handle_frame(&manager, &claims, frame).await {
+            send_frame(&mut framed, &reply).await?;
+        }
+    }
+    Ok(())
+}
+
+async fn owned_by(manager: &FileTransferManager, claims: &Claims, transfer_id: &str) -> bool {
+    manager.owner_of(transfer_id).await.as_deref() == Some(claims.sub.as_str())
+}
+
+async fn handle_frame(manager: &FileTransferManager, claims: &Claims, frame: TransferFrame) -> Vec<TransferFrame> {
+    match frame {
+        TransferFrame::Start { mut request } => {
+            if !feature_flags::is_enabled(Feature::FileTransfer, &claims.sub, &claims.groups).await {
+                return vec![TransferFrame::error(None, "file transfer is disabled")];
+            }
+            if matches!(request.transfer_type, TransferType::Sync | TransferType::FolderSync) {
+                return vec![TransferFrame::error(None, "sync transfers are not supported")];
+            }
+            if let Err(err) = resolve_path(&request.file_path) {
+                return vec![TransferFrame::error(None, err)];
+            }
+            // 下载以存储后端中的实际文件大小为准
+            if matches!(request.transfer_type, TransferType::Download) {
+                match manager.storage().await.size(&request.file_path).await {
+                    Ok(Some(size)) => request.file_size = size,
+                    _ => return vec![TransferFrame::error(None, "file not found")],
+                }
+            }
+            if let Some(id) = request.device_id.take() {
+                request.device_id = Some(peer_alias::resolve_id(&id).await);
+            }
+            let resume_from = request.resume_from;
+            match manager.start_transfer(&claims.sub, request).await {
+                Ok(transfer_id) => vec![TransferFrame::Started {
+                    transfer_id,
+                    resume_from,
+                }],
+                Err(err) => vec![TransferFrame::error(None, err)],
+            }
+        }
+        TransferFrame::Chunk {
+            transfer_id,
+            chunk_index,
+            data,
+            checksum,
+            is_last,
+            stream_id,
+        } => {
+            if !owned_by(manager, claims, &transfer_id).await {
+                return vec![TransferFrame::error(Some(&transfer_id), "transfer not found")];
+            }
+            let data = match base64::decode(&data) {
+                Ok(data) => data,
+                Err(err) => return vec![TransferFrame::error(Some(&transfer_id), err)],
+            };
+            // 完成后传输记录会被移除，先取进度
+            let progress = manager.get_progress(&transfer_id).await;
+            let chunk = FileChunk {
+                transfer_id: transfer_id.clone(),
+                chunk_index,
+                chunk_size: data.len(),
+                data,
+                checksum,
+                is_last,
+                stream_id,
+            };
+            match manager.handle_chunk(chunk).await {
+                Ok(true) => return vec![TransferFrame::Done { transfer_id }],
+                Ok(false) => {}
+                Err(err) => return vec![TransferFrame::error(Some(&transfer_id), err)],
+            }
+            match manager.get_progress(&transfer_id).await.or(progress) {
+                Some(progress) => vec![TransferFrame::Progress { progress }],
+                None => vec![],
+            }
+        }
+        TransferFrame::Fetch {
+            transfer_id,
+            chunk_index,
+        } => {
+            if !owned_by(manager, claims, &transfer_id).await {
+                return vec![TransferFrame::error(Some(&transfer_id), "transfer not found")];
+            }
+            match manager.read_chunk(&transfer_id, chunk_index).await {
+                Ok(chunk) => {
+                    let mut replies = vec![TransferFrame::Chunk {
+                        transfer_id: chunk.transfer_id,
+                        chunk_index: chunk.chunk_index,
+                        data: base64::encode(&chunk.data),
+                        checksum: chunk.checksum,
+                        is_last: chunk.is_last,
+                        stream_id: 0,
+                    }];
+                    if chunk.is_last {
+                        replies.push(TransferFrame::Done { transfer_id });
+                    } else if let Some(progress) = manager.get_progress(&transfer_id).await {
+                        replies.push(TransferFrame::Progress { progress });
+                    }
+                    replies
+                }
+                Err(err) => vec![TransferFrame::error(Some(&transfer_id), err)],
+            }
+        }
+        TransferFrame::Status { transfer_id } => {
+            if !owned_by(manager, claims, &transfer_id).await {
+                return vec![TransferFrame::error(Some(&transfer_id), "transfer not found")];
+            }
+            let mut replies = vec![];
+            if let Some(progress) = manager.get_progress(&transfer_id).await {
+                replies.push(TransferFrame::Progress { progress });
+            }
+            if let Some(chunks) = manager.missing_chunks(&transfer_id, MAX_MISSING_CHUNKS).await {
+                replies.push(TransferFrame::Missing { transfer_id, chunks });
+            }
+            replies
+        }
+        TransferFrame::Cancel { transfer_id } => {
+            if !owned_by(manager, claims, &transfer_id).await {
+                return vec![TransferFrame::error(Some(&transfer_id), "transfer not found")];
+            }
+            match manager.cancel_transfer(&transfer_id).await {
+                Ok(()) => vec![TransferFrame::Done { transfer_id }],
+                Err(err) => vec![TransferFrame::error(Some(&transfer_id), err)],
+            }
+        }
+        _ => vec![TransferFrame::error(None, "unexpected frame")],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frame_format_and_paths() {
+        let frame: TransferFrame =
+            serde_json::from_str(r#"{"type":"fetch","transfer_id":"t1","chunk_index":3}"#).unwrap();
+        assert!(matches!(frame, TransferFrame::Fetch { chunk_index: 3, .. }));
+        let json = serde_json::to_string(&TransferFrame::error(Some("t1"), "bad")).unwrap();
+        assert!(json.contains(r#""type":"error""#));
+
+        assert!(resolve_path("docs/report.pdf").is_ok());
+        assert!(resolve_path("../etc/passwd").is_err());
+        assert!(resolve_path("/etc/passwd").is_err());
+        assert!(resolve_path("").is_err());
+        assert!(resolve_path(".partial/abc.tmp").is_err());
+
+        assert_eq!(parse_range("bytes=0-99", 1000), Some((0, 99)));
+        assert_eq!(parse_range("bytes=900-", 1000), Some((900, 999)));
+        assert_eq!(parse_range("bytes=-100", 1000), Some((900, 999)));
+        assert_eq!(parse_range("bytes=500-5000", 1000), Some((500, 999)));
+        assert_eq!(parse_range("bytes=1000-", 1000), None);
+        assert_eq!(parse_range("bytes=0-1,5-9", 1000), None);
+    }
+}
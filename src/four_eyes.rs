@@ -0,0 +1,398 @@
+// 双人审批模块 - 带有敏感标签(默认 sensitive)的设备被连接时，需要另一名管理员在 Web 界面审批，
+// 审批通过前不向控制端返回打洞响应。打洞请求最多挂起 hold_secs 等待审批，超时后返回失败，
+// 审批请求在 approval_timeout_mins 内仍可被批准，批准后同一请求方在 grant_secs 内重试即可连接。
+// 代操作令牌不能审批，避免超级管理员代操作其他管理员批准自己发起的连接
+use crate::auth::Claims;
+use crate::enterprise_database::{AuditLog, EnterpriseDatabase};
+use crate::validation::Payload;
+use hbb_common::{
+    bail, log,
+    tokio::{
+        sync::{Notify, RwLock},
+        time::{timeout, Duration},
+    },
+    ResultType,
+};
+use schemars::JsonSchema;
+use serde_derive::{Deserialize, Serialize};
+use std::{collections::HashMap, time::{Instant, SystemTime}};
+use validator::Validate;
+
+pub const FOUR_EYES_KEY: &str = "four_eyes";
+// 已结束的审批请求保留一段时间供查看
+const RETAIN_SECS: u64 = 24 * 3600;
+
+lazy_static::lazy_static! {
+    static ref POLICY: RwLock<FourEyesPolicy> = Default::default();
+    static ref REQUESTS: RwLock<HashMap<String, ApprovalRequest>> = Default::default();
+    static ref DECIDED: Notify = Notify::new();
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, Validate)]
+pub struct FourEyesPolicy {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default = "default_tag")]
+    #[validate(length(min = 1, max = 64))]
+    pub tag: String,
+    #[serde(default = "default_approval_timeout_mins")]
+    #[validate(range(min = 1, max = 1440))]
+    pub approval_timeout_mins: u64,
+    #[serde(default = "default_hold_secs")]
+    #[validate(range(max = 60))]
+    pub hold_secs: u64,
+    #[serde(default = "default_grant_secs")]
+    #[validate(range(min = 1))]
+    pub grant_secs: u64,
+}
+
+impl Payload for FourEyesPolicy {}
+
+fn default_tag() -> String {
+    "sensitive".to_owned()
+}
+
+fn default_approval_timeout_mins() -> u64 {
+    5
+}
+
+fn default_hold_secs() -> u64 {
+    15
+}
+
+fn default_grant_secs() -> u64 {
+    600
+}
+
+impl Default for FourEyesPolicy {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            tag: default_tag(),
+            approval_timeout_mins: default_approval_timeout_mins(),
+            hold_secs: default_hold_secs(),
+            grant_secs: default_grant_secs(),
+        }
+    }
+}
+
+impl FourEyesPolicy {
+    pub fn validate(&self) -> ResultType<()> {
+        if self.tag.trim().is_empty() {
+            bail!("tag is empty");
+        }
+        if self.approval_timeout_mins == 0 || self.approval_timeout_mins > 24 * 60 {
+            bail!("approval_timeout_mins must be between 1 and 1440");
+        }
+        if self.hold_secs > 60 {
+            bail!("hold_secs must not exceed 60");
+        }
+        if self.grant_secs == 0 {
+            bail!("grant_secs must be positive");
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub enum ApprovalStatus {
+    Pending,
+    Approved,
+    Rejected,
+    Expired,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct ApprovalRequest {
+    pub id: String,
+    pub device_id: String,
+    // 控制端登录用户，客户端未携带令牌时为空
+    pub requester_id: Option<String>,
+    pub requester_name: Option<String>,
+    pub requester_addr: String,
+    pub created_at: u64,
+    pub expires_at: u64,
+    pub status: ApprovalStatus,
+    pub decided_by: Option<String>,
+    pub decided_at: Option<u64>,
+}
+
+impl ApprovalRequest {
+    // 已知用户按用户区分请求方，否则按来源 IP
+    fn same_requester(&self, requester_id: Option<&str>, addr: &str) -> bool {
+        match (self.requester_id.as_deref(), requester_id) {
+            (Some(a), Some(b)) => a == b,
+            (None, None) => ip_of(&self.requester_addr) == ip_of(addr),
+            _ => false,
+        }
+    }
+
+    // 批准后在 grant_secs 内有效
+    fn granted(&self, now: u64, grant_secs: u64) -> bool {
+        self.status == ApprovalStatus::Approved && self.decided_at.unwrap_or(0) + grant_secs > now
+    }
+}
+
+fn ip_of(addr: &str) -> &str {
+    addr.rsplit_once(':').map(|(ip, _)| ip).unwrap_or(addr)
+}
+
+pub async fn reload(db: &EnterpriseDatabase) -> ResultType<()> {
+    let policy: FourEyesPolicy = match db.get_setting(FOUR_EYES_KEY).await? {
+        Some(v) => serde_json::from_str(&v)?,
+        None => FourEyesPolicy::default(),
+    };
+    policy.validate()?;
+    *POLICY.write().await = policy;
+    Ok(())
+}
+
+pub async fn get() -> FourEyesPolicy {
+    POLICY.read().await.clone()
+}
+
+pub async fn update(db: &EnterpriseDatabase, policy: FourEyesPolicy, updated_by: &str) -> ResultType<()> {
+    policy.validate()?;
+    db.set_setting(FOUR_EYES_KEY, &serde_json::to_string(&policy)?, Some(updated_by))
+        .await?;
+    *POLICY.write().await = policy;
+    Ok(())
+}
+
+pub async fn is_enabled() -> bool {
+    POLICY.read().await.enabled
+}
+
+// 过期未审批的请求标记为 Expired，并清理较早的记录
+fn expire(requests: &mut HashMap<String, ApprovalRequest>, now: u64) {
+    for request in requests.values_mut() {
+        if request.status == ApprovalStatus::Pending && request.expires_at <= now {
+            request.status = ApprovalStatus::Expired;
+        }
+    }
+    requests.retain(|_, r| r.expires_at + RETAIN_SECS > now);
+}
+
+enum Check {
+    Allowed,
+    Refused,
+    Wait(String),
+}
+
+async fn check(
+    policy: &FourEyesPolicy,
+    device_id: &str,
+    requester_id: Option<&str>,
+    requester_name: Option<&str>,
+    requester_addr: &str,
+) -> (Check, Option<ApprovalRequest>) {
+    let now = crate::common::now();
+    let mut requests = REQUESTS.write().await;
+    expire(&mut requests, now);
+    let mut pending = None;
+    for request in requests
+        .values()
+        .filter(|r| r.device_id == device_id && r.same_requester(requester_id, requester_addr))
+    {
+        if request.granted(now, policy.grant_secs) {
+            return (Check::Allowed, None);
+        }
+        match request.status {
+            ApprovalStatus::Pending => pending = Some(request.id.clone()),
+            // 被拒绝的请求在原审批期限内不再重复提交
+            ApprovalStatus::Rejected if request.expires_at > now => return (Check::Refused, None),
+            _ => {}
+        }
+    }
+    if let Some(id) = pending {
+        return (Check::Wait(id), None);
+    }
+    let request = ApprovalRequest {
+        id: uuid::Uuid::new_v4().to_string(),
+        device_id: device_id.to_owned(),
+        requester_id: requester_id.map(str::to_owned),
+        requester_name: requester_name.map(str::to_owned),
+        requester_addr: requester_addr.to_owned(),
+        created_at: now,
+        expires_at: now + policy.approval_timeout_mins * 60,
+        status: ApprovalStatus::Pending,
+        decided_by: None,
+        decided_at: None,
+    };
+    requests.insert(request.id.clone(), request.clone());
+    (Check::Wait(request.id.clone()), Some(request))
+}
+
+// 打洞前检查连接是否需要并已获得审批，需要时挂起等待
+pub async fn authorize(
+    db: &EnterpriseDatabase,
+    device_id: &str,
+    requester_id: Option<&str>,
+    requester_name: Option<&str>,
+    requester_addr: &str,
+) -> bool {
+    let policy = get().await;
+    if !policy.enabled {
+        return true;
+    }
+    match db.get_device_tags(device_id).await {
+        Ok(tags) if tags.iter().any(|t| t == &policy.tag) => {}
+        Ok(_) => return true,
+        Err(err) => {
+            // 无法确认是否敏感时按敏感设备处理
+            log::error!("Failed to load tags of {}: {}", device_id, err);
+        }
+    }
+    let (res, created) = check(&policy, device_id, requester_id, requester_name, requester_addr).await;
+    let id = match res {
+        Check::Allowed => return true,
+        Check::Refused => return false,
+        Check::Wait(id) => id,
+    };
+    if let Some(request) = created {
+        log::info!("Session to sensitive device {} from {} awaits approval {}", device_id, requester_addr, id);
+        let audit_log = AuditLog {
+            id: 0,
+            user_id: request.requester_id.clone().unwrap_or_else(|| "unknown".to_string()),
+            device_id: device_id.to_owned(),
+            action: "session_approval_requested".to_string(),
+            details: serde_json::to_string(&request).ok(),
+            ip_address: ip_of(requester_addr).to_owned(),
+            user_agent: None,
+            timestamp: SystemTime::now(),
+            success: true,
+        };
+        let _ = db.log_audit(&audit_log).await;
+    }
+    let deadline = Instant::now() + Duration::from_secs(policy.hold_secs);
+    loop {
+        let notified = DECIDED.notified();
+        match REQUESTS.read().await.get(&id).map(|r| r.status) {
+            Some(ApprovalStatus::Approved) => return true,
+            Some(ApprovalStatus::Pending) => {}
+            _ => return false,
+        }
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() || timeout(remaining, notified).await.is_err() {
+            return false;
+        }
+    }
+}
+
+pub async fn list() -> Vec<ApprovalRequest> {
+    let mut requests = REQUESTS.write().await;
+    expire(&mut requests, crate::common::now());
+    let mut res: Vec<_> = requests.values().cloned().collect();
+    res.sort_by(|a, b| b.created_at.cmp(&a.created_at));
+    res
+}
+
+// 审批人必须是请求方以外的用户本人
+pub async fn decide(id: &str, claims: &Claims, approve: bool) -> ResultType<ApprovalRequest> {
+    if claims.act.is_some() {
+        bail!("cannot decide sessions while impersonating another user");
+    }
+    let approver_id = claims.sub.as_str();
+    let now = crate::common::now();
+    let mut requests = REQUESTS.write().await;
+    expire(&mut requests, now);
+    let request = match requests.get_mut(id) {
+        Some(request) => request,
+        None => bail!("approval request not found"),
+    };
+    if request.status != ApprovalStatus::Pending {
+        bail!("approval request is {:?}", request.status);
+    }
+    if request.requester_id.as_deref() == Some(approver_id) {
+        bail!("requester cannot approve own session");
+    }
+    request.status = if approve {
+        ApprovalStatus::Approved
+    } else {
+        ApprovalStatus::Rejected
+    };
+    request.decided_by = Some(approver_id.to_owned());
+    request.decided_at = Some(now);
+    let request = request.clone();
+    drop(requests);
+    DECIDED.notify_waiters();
+    Ok(request)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_approval_matching() {
+        let mut request = ApprovalRequest {
+            id: "a".to_owned(),
+            device_id: "123".to_owned(),
+            requester_id: None,
+            requester_name: None,
+            requester_addr: "10.0.0.5:50000".to_owned(),
+            created_at: 100,
+            expires_at: 400,
+            status: ApprovalStatus::Pending,
+            decided_by: None,
+            decided_at: None,
+        };
+        // 客户端重试时源端口会变化
+        assert!(request.same_requester(None, "10.0.0.5:50001"));
+        assert!(!request.same_requester(None, "10.0.0.6:50000"));
+        assert!(!request.same_requester(Some("u1"), "10.0.0.5:50000"));
+        assert!(!request.granted(200, 600));
+        request.status = ApprovalStatus::Approved;
+        request.decided_at = Some(200);
+        assert!(request.granted(799, 600));
+        assert!(!request.granted(800, 600));
+
+        let mut requests = HashMap::new();
+        request.status = ApprovalStatus::Pending;
+        requests.insert(request.id.clone(), request);
+        expire(&mut requests, 400);
+        assert_eq!(requests["a"].status, ApprovalStatus::Expired);
+        expire(&mut requests, 400 + RETAIN_SECS);
+        assert!(requests.is_empty());
+    }
+
+    #[hbb_common::tokio::test]
+    async fn test_impersonation_cannot_decide() {
+        let now = crate::common::now();
+        let request = ApprovalRequest {
+            id: uuid::Uuid::new_v4().to_string(),
+            device_id: "123".to_owned(),
+            requester_id: Some("root".to_owned()),
+            requester_name: Some("root".to_owned()),
+            requester_addr: "10.0.0.5:50000".to_owned(),
+            created_at: now,
+            expires_at: now + 300,
+            status: ApprovalStatus::Pending,
+            decided_by: None,
+            decided_at: None,
+        };
+        let id = request.id.clone();
+        REQUESTS.write().await.insert(id.clone(), request);
+        let claims = |sub: &str, act: Option<&str>| Claims {
+            sub: sub.to_owned(),
+            username: sub.to_owned(),
+            role: "Admin".to_owned(),
+            groups: vec![],
+            exp: 0,
+            iat: 0,
+            jti: String::new(),
+            scope: None,
+            act: act.map(|x| crate::auth::Actor {
+                sub: x.to_owned(),
+                username: x.to_owned(),
+            }),
+        };
+        // root 代操作 alice 批准自己发起的连接
+        assert!(decide(&id, &claims("alice", Some("root")), true).await.is_err());
+        assert!(decide(&id, &claims("alice", Some("carol")), false).await.is_err());
+        assert!(decide(&id, &claims("root", None), true).await.is_err());
+        let request = decide(&id, &claims("alice", None), true).await.unwrap();
+        assert_eq!(request.status, ApprovalStatus::Approved);
+        assert_eq!(request.decided_by.as_deref(), Some("alice"));
+    }
+}
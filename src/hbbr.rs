@@ -1,17 +1,15 @@
 use clap::App;
 mod common;
+mod congestion_control;
 mod relay_server;
-use flexi_logger::*;
+mod zero_copy;
+use common::init_logger;
 use hbb_common::{config::RELAY_PORT, ResultType};
 use relay_server::*;
 mod version;
 
 fn main() -> ResultType<()> {
-    let _logger = Logger::try_with_env_or_str("info")?
-        .log_to_stdout()
-        .format(opt_format)
-        .write_mode(WriteMode::Async)
-        .start()?;
+    init_logger()?;
     let args = format!(
         "-p, --port=[NUMBER(default={RELAY_PORT})] 'Sets the listening port'
         -k, --key=[KEY] 'Only allow the client with the same key'
@@ -0,0 +1,406 @@
+// 节假日日历模块 - 管理员按地区维护节假日日历(手工填写或导入 iCalendar 文件)，策略按日历 id 引用：
+//   - 无人值守访问规则和用户组时间限制引用日历后，节假日全天按时段外处理
+//   - 维护窗口引用日历条目而不是固定日期，窗口期间其设备组的离线告警暂停
+// 日期按引用方的时区判断，未设置时区时为服务器本地日期
+use crate::common::parse_minutes;
+use crate::enterprise_database::EnterpriseDatabase;
+use crate::unattended_access::{local_date, local_time, parse_timezone};
+use crate::validation::Payload;
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use hbb_common::{bail, log, tokio::sync::RwLock, ResultType};
+use schemars::JsonSchema;
+use serde_derive::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashSet};
+use validator::Validate;
+
+pub const HOLIDAY_CALENDARS_KEY: &str = "holiday_calendars";
+const MAX_ENTRIES: usize = 1000;
+// 导入时多日事件最多展开的天数
+const MAX_EVENT_DAYS: i64 = 31;
+const DATE_FORMAT: &str = "%Y-%m-%d";
+
+lazy_static::lazy_static! {
+    static ref CONFIG: RwLock<CalendarConfig> = Default::default();
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema, Validate)]
+pub struct CalendarConfig {
+    #[serde(default)]
+    pub calendars: Vec<HolidayCalendar>,
+    #[serde(default)]
+    pub maintenance_windows: Vec<MaintenanceWindow>,
+}
+
+impl Payload for CalendarConfig {}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct HolidayCalendar {
+    pub id: String,
+    pub name: String,
+    // 地区代码，如 CN、DE-BY，供管理员选择日历时参考
+    #[serde(default)]
+    pub region: String,
+    #[serde(default)]
+    pub entries: Vec<CalendarEntry>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct CalendarEntry {
+    pub date: String, // YYYY-MM-DD
+    pub name: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct MaintenanceWindow {
+    pub name: String,
+    pub group_ids: Vec<String>,
+    pub calendar_id: String,
+    // 只匹配名称相同的条目，为空时日历中的每个日期都是维护日
+    #[serde(default)]
+    pub entry: Option<String>,
+    // HH:MM，start 与 end 相同表示全天，end 早于 start 时跨天
+    #[serde(default = "default_time")]
+    pub start: String,
+    #[serde(default = "default_time")]
+    pub end: String,
+    #[serde(default)]
+    pub timezone: Option<String>,
+}
+
+fn default_time() -> String {
+    "00:00".to_owned()
+}
+
+impl HolidayCalendar {
+    fn validate(&self) -> ResultType<()> {
+        if self.id.trim().is_empty() || self.name.trim().is_empty() {
+            bail!("calendar id and name are required");
+        }
+        if self.entries.len() > MAX_ENTRIES {
+            bail!("calendar {}: at most {} entries", self.id, MAX_ENTRIES);
+        }
+        for entry in &self.entries {
+            // 只接受补零的日期，便于按字符串比较
+            match NaiveDate::parse_from_str(&entry.date, DATE_FORMAT) {
+                Ok(date) if date.format(DATE_FORMAT).to_string() == entry.date => {}
+                _ => bail!("calendar {}: invalid date {}, expected YYYY-MM-DD", self.id, entry.date),
+            }
+        }
+        Ok(())
+    }
+
+    // 该日期的条目名称
+    fn entries_on(&self, date: NaiveDate) -> impl Iterator<Item = &str> {
+        let date = date.format(DATE_FORMAT).to_string();
+        self.entries
+            .iter()
+            .filter(move |e| e.date == date)
+            .map(|e| e.name.as_str())
+    }
+
+    pub fn is_holiday(&self, date: NaiveDate) -> bool {
+        self.entries_on(date).next().is_some()
+    }
+}
+
+impl MaintenanceWindow {
+    fn matches(&self, calendar: &HolidayCalendar, date: NaiveDate) -> bool {
+        calendar
+            .entries_on(date)
+            .any(|name| self.entry.as_deref().map(|x| x == name).unwrap_or(true))
+    }
+
+    // date/minutes 为窗口时区的当前日期和分钟数
+    fn is_active(&self, calendar: &HolidayCalendar, date: NaiveDate, minutes: u32) -> bool {
+        let (start, end) = match (parse_minutes(&self.start), parse_minutes(&self.end)) {
+            (Ok(start), Ok(end)) => (start, end),
+            _ => return false,
+        };
+        if start == end {
+            self.matches(calendar, date)
+        } else if start < end {
+            minutes >= start && minutes < end && self.matches(calendar, date)
+        } else if minutes >= start {
+            self.matches(calendar, date)
+        } else if minutes < end {
+            // 跨天窗口的后半段属于前一天的条目
+            date.pred_opt().map(|x| self.matches(calendar, x)).unwrap_or(false)
+        } else {
+            false
+        }
+    }
+}
+
+impl CalendarConfig {
+    pub fn validate(&self) -> ResultType<()> {
+        let mut ids = HashSet::new();
+        for calendar in &self.calendars {
+            calendar.validate()?;
+            if !ids.insert(calendar.id.as_str()) {
+                bail!("duplicate calendar id {}", calendar.id);
+            }
+        }
+        for window in &self.maintenance_windows {
+            if window.name.trim().is_empty() {
+                bail!("maintenance window name is empty");
+            }
+            if window.group_ids.is_empty() {
+                bail!("maintenance window {} has no target groups", window.name);
+            }
+            if !ids.contains(window.calendar_id.as_str()) {
+                bail!("maintenance window {}: unknown calendar {}", window.name, window.calendar_id);
+            }
+            parse_minutes(&window.start)?;
+            parse_minutes(&window.end)?;
+            if let Some(timezone) = &window.timezone {
+                parse_timezone(timezone)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn calendar(&self, id: &str) -> Option<&HolidayCalendar> {
+        self.calendars.iter().find(|c| c.id == id)
+    }
+
+    fn groups_in_maintenance(&self, now: DateTime<Utc>) -> BTreeSet<String> {
+        let mut groups = BTreeSet::new();
+        for window in &self.maintenance_windows {
+            let calendar = match self.calendar(&window.calendar_id) {
+                Some(calendar) => calendar,
+                None => continue,
+            };
+            let timezone = window.timezone.as_deref();
+            let (_, minutes) = local_time(now, timezone);
+            if window.is_active(calendar, local_date(now, timezone), minutes) {
+                groups.extend(window.group_ids.iter().cloned());
+            }
+        }
+        groups
+    }
+
+    // 新增或替换同 id 的日历
+    pub fn upsert(&mut self, calendar: HolidayCalendar) {
+        match self.calendars.iter_mut().find(|c| c.id == calendar.id) {
+            Some(x) => *x = calendar,
+            None => self.calendars.push(calendar),
+        }
+    }
+}
+
+fn unescape(value: &str) -> String {
+    let mut res = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            res.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') | Some('N') => res.push(' '),
+            Some(c) => res.push(c),
+            None => {}
+        }
+    }
+    res
+}
+
+// DTSTART/DTEND 的日期部分，带时间的值(如 20240101T090000Z)只取日期
+fn ics_date(value: &str) -> ResultType<NaiveDate> {
+    match value.get(..8).map(|x| NaiveDate::parse_from_str(x, "%Y%m%d")) {
+        Some(Ok(date)) => Ok(date),
+        _ => bail!("invalid date {}", value),
+    }
+}
+
+// 解析 iCalendar(.ics) 中的事件为日历条目，多日事件(DTEND 当天不含)展开为每天一个条目
+pub fn parse_ics(text: &str) -> ResultType<Vec<CalendarEntry>> {
+    // 以空格或制表符开头的行是上一行的续行
+    let mut lines: Vec<String> = vec![];
+    for line in text.lines() {
+        match line.strip_prefix(|c| c == ' ' || c == '\t') {
+            Some(rest) if !lines.is_empty() => lines.last_mut().unwrap().push_str(rest),
+            _ => lines.push(line.trim_end().to_owned()),
+        }
+    }
+    let mut entries = vec![];
+    let mut event: Option<(Option<NaiveDate>, Option<NaiveDate>, String)> = None;
+    for line in lines {
+        let (name, value) = match line.split_once(':') {
+            Some((name, value)) => (name.split(';').next().unwrap_or_default().to_ascii_uppercase(), value),
+            None => continue,
+        };
+        match (name.as_str(), value, event.as_mut()) {
+            ("BEGIN", "VEVENT", _) => event = Some((None, None, String::new())),
+            ("END", "VEVENT", Some(_)) => {
+                let (start, end, summary) = event.take().unwrap_or_default();
+                let start = match start {
+                    Some(start) => start,
+                    None => bail!("event {} has no DTSTART", summary),
+                };
+                let days = end.map(|end| (end - start).num_days()).unwrap_or(1).max(1);
+                if days > MAX_EVENT_DAYS {
+                    bail!("event {} spans more than {} days", summary, MAX_EVENT_DAYS);
+                }
+                for i in 0..days {
+                    entries.push(CalendarEntry {
+                        date: (start + Duration::days(i)).format(DATE_FORMAT).to_string(),
+                        name: summary.clone(),
+                    });
+                }
+            }
+            ("DTSTART", _, Some(event)) => event.0 = Some(ics_date(value)?),
+            ("DTEND", _, Some(event)) => event.1 = Some(ics_date(value)?),
+            ("SUMMARY", _, Some(event)) => event.2 = unescape(value),
+            _ => {}
+        }
+    }
+    if entries.len() > MAX_ENTRIES {
+        bail!("at most {} entries", MAX_ENTRIES);
+    }
+    entries.sort_by(|a, b| a.date.cmp(&b.date));
+    Ok(entries)
+}
+
+pub async fn reload(db: &EnterpriseDatabase) -> ResultType<()> {
+    let config: CalendarConfig = match db.get_setting(HOLIDAY_CALENDARS_KEY).await? {
+        Some(v) => serde_json::from_str(&v)?,
+        None => CalendarConfig::default(),
+    };
+    config.validate()?;
+    log::info!(
+        "holiday calendars loaded: {} calendars, {} maintenance windows",
+        config.calendars.len(),
+        config.maintenance_windows.len()
+    );
+    *CONFIG.write().await = config;
+    Ok(())
+}
+
+pub async fn get() -> CalendarConfig {
+    CONFIG.read().await.clone()
+}
+
+pub async fn update(db: &EnterpriseDatabase, config: CalendarConfig, updated_by: &str) -> ResultType<()> {
+    config.validate()?;
+    db.set_setting(HOLIDAY_CALENDARS_KEY, &serde_json::to_string(&config)?, Some(updated_by))
+        .await?;
+    *CONFIG.write().await = config;
+    Ok(())
+}
+
+// 导入 iCalendar 文件为日历，同 id 的日历被替换；引用该日历的维护窗口保持不变
+pub async fn import(
+    db: &EnterpriseDatabase,
+    id: &str,
+    name: &str,
+    region: &str,
+    ics: &str,
+    updated_by: &str,
+) -> ResultType<HolidayCalendar> {
+    let calendar = HolidayCalendar {
+        id: id.to_owned(),
+        name: name.to_owned(),
+        region: region.to_owned(),
+        entries: parse_ics(ics)?,
+    };
+    let mut config = get().await;
+    config.upsert(calendar.clone());
+    update(db, config, updated_by).await?;
+    Ok(calendar)
+}
+
+// 日历中是否有该日期，日历不存在时按非节假日处理
+pub async fn is_holiday(calendar_id: &str, date: NaiveDate) -> bool {
+    match CONFIG.read().await.calendar(calendar_id) {
+        Some(calendar) => calendar.is_holiday(date),
+        None => {
+            log::warn!("Unknown holiday calendar {}", calendar_id);
+            false
+        }
+    }
+}
+
+// 当前处于维护窗口的设备组
+pub async fn groups_in_maintenance(now: DateTime<Utc>) -> BTreeSet<String> {
+    CONFIG.read().await.groups_in_maintenance(now)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ICS: &str = "BEGIN:VCALENDAR\r\n\
+        VERSION:2.0\r\n\
+        BEGIN:VEVENT\r\n\
+        DTSTART;VALUE=DATE:20241225\r\n\
+        DTEND;VALUE=DATE:20241227\r\n\
+        SUMMARY:Christmas\\, Boxing Day\r\n\
+        END:VEVENT\r\n\
+        BEGIN:VEVENT\r\n\
+        DTSTART;VALUE=DATE:20241003\r\n\
+        SUMMARY:Tag der Deutschen\r\n \
+        Einheit\r\n\
+        END:VEVENT\r\n\
+        END:VCALENDAR\r\n";
+
+    fn date(x: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(x, DATE_FORMAT).unwrap()
+    }
+
+    #[test]
+    fn test_parse_ics() {
+        let entries = parse_ics(ICS).unwrap();
+        let dates: Vec<_> = entries.iter().map(|e| e.date.as_str()).collect();
+        assert_eq!(dates, vec!["2024-10-03", "2024-12-25", "2024-12-26"]);
+        assert_eq!(entries[0].name, "Tag der Deutschen Einheit");
+        assert_eq!(entries[1].name, "Christmas, Boxing Day");
+        assert!(parse_ics("BEGIN:VEVENT\nSUMMARY:x\nEND:VEVENT\n").is_err());
+        assert!(parse_ics("BEGIN:VEVENT\nDTSTART:20240101\nDTEND:20240301\nEND:VEVENT\n").is_err());
+    }
+
+    #[test]
+    fn test_maintenance_windows() {
+        let mut config = CalendarConfig::default();
+        config.upsert(HolidayCalendar {
+            id: "de".to_owned(),
+            name: "Germany".to_owned(),
+            region: "DE".to_owned(),
+            entries: parse_ics(ICS).unwrap(),
+        });
+        config.maintenance_windows.push(MaintenanceWindow {
+            name: "christmas".to_owned(),
+            group_ids: vec!["berlin".to_owned()],
+            calendar_id: "de".to_owned(),
+            entry: Some("Christmas, Boxing Day".to_owned()),
+            start: "22:00".to_owned(),
+            end: "06:00".to_owned(),
+            timezone: Some("Europe/Berlin".to_owned()),
+        });
+        assert!(config.validate().is_ok());
+        let calendar = &config.calendars[0];
+        assert!(calendar.is_holiday(date("2024-12-26")));
+        assert!(!calendar.is_holiday(date("2024-12-27")));
+
+        let window = &config.maintenance_windows[0];
+        assert!(window.is_active(calendar, date("2024-12-25"), 23 * 60));
+        // 跨天窗口的后半段
+        assert!(window.is_active(calendar, date("2024-12-27"), 5 * 60));
+        assert!(!window.is_active(calendar, date("2024-12-25"), 12 * 60));
+        assert!(!window.is_active(calendar, date("2024-10-03"), 23 * 60));
+
+        use chrono::TimeZone;
+        // 柏林冬令时 UTC+1，22:30 UTC 为 23:30
+        let now = Utc.with_ymd_and_hms(2024, 12, 25, 22, 30, 0).unwrap();
+        assert!(config.groups_in_maintenance(now).contains("berlin"));
+        let now = Utc.with_ymd_and_hms(2024, 12, 25, 12, 0, 0).unwrap();
+        assert!(config.groups_in_maintenance(now).is_empty());
+
+        let mut bad = config.clone();
+        bad.maintenance_windows[0].calendar_id = "fr".to_owned();
+        assert!(bad.validate().is_err());
+        let mut bad = config;
+        bad.calendars[0].entries[0].date = "2024-1-1".to_owned();
+        assert!(bad.validate().is_err());
+    }
+}
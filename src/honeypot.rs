@@ -0,0 +1,246 @@
+// 诱饵设备ID模块 - 管理员登记不对应任何真实设备的诱饵ID(如仿照财务电脑命名)，正常用户不会连接它们，
+// 因此任何针对诱饵ID的打洞、中继或 WebRTC 请求都视为入侵迹象:
+//   - 立即记录 Critical 级安全事件(来源地址、登录用户、客户端版本等)，并经邮件/ITSM 告警
+//   - 可选自动封禁来源IP一段时间，期间该IP的所有信令消息都被丢弃(封禁只保存在内存中，重启后解除)
+// 对攻击者而言诱饵ID表现为离线设备；真实设备不能注册诱饵ID
+use crate::advanced_security::{SecurityEvent, SecurityEventType, SecuritySeverity};
+use crate::common::now;
+use crate::enterprise_database::EnterpriseDatabase;
+use crate::validation::{self, Payload};
+use hbb_common::{bail, log, tokio::sync::RwLock, ResultType};
+use schemars::JsonSchema;
+use serde_derive::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, HashSet},
+    net::{IpAddr, SocketAddr},
+    time::SystemTime,
+};
+use validator::Validate;
+
+pub const HONEYPOT_KEY: &str = "honeypot";
+const MAX_DECOYS: usize = 1000;
+// 同一来源对同一诱饵ID的重复请求在该时间内只告警一次
+const ALERT_DEDUP_SECS: u64 = 60;
+
+lazy_static::lazy_static! {
+    static ref CONFIG: RwLock<HoneypotConfig> = Default::default();
+    static ref DECOYS: RwLock<HashSet<String>> = Default::default();
+    // 来源IP -> 封禁到期时间
+    static ref IP_BANS: RwLock<HashMap<IpAddr, IpBan>> = Default::default();
+    // (来源IP, 诱饵ID) -> 最近一次告警时间
+    static ref ALERTED: RwLock<HashMap<(IpAddr, String), u64>> = Default::default();
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema, Validate)]
+pub struct HoneypotConfig {
+    #[serde(default)]
+    #[validate(custom = "validation::non_empty_items")]
+    pub ids: Vec<String>,
+    // 触发后自动封禁来源IP的分钟数，0 表示只告警不封禁
+    #[serde(default)]
+    #[validate(range(max = 43200))]
+    pub ban_minutes: u64,
+}
+
+impl Payload for HoneypotConfig {}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct IpBan {
+    pub ip: String,
+    pub decoy_id: String,
+    pub banned_at: u64,
+    pub expires_at: u64,
+}
+
+impl Default for HoneypotConfig {
+    fn default() -> Self {
+        Self {
+            ids: Vec::new(),
+            ban_minutes: 0,
+        }
+    }
+}
+
+impl HoneypotConfig {
+    pub fn validate(&self) -> ResultType<()> {
+        if self.ids.len() > MAX_DECOYS {
+            bail!("at most {} decoy ids", MAX_DECOYS);
+        }
+        if let Some(id) = self
+            .ids
+            .iter()
+            .find(|id| id.len() < 6 || id.contains(char::is_whitespace))
+        {
+            bail!("invalid decoy id: {}", id);
+        }
+        if self.ban_minutes > 30 * 24 * 60 {
+            bail!("ban_minutes must be at most 43200");
+        }
+        Ok(())
+    }
+}
+
+async fn set(config: HoneypotConfig) {
+    *DECOYS.write().await = config.ids.iter().cloned().collect();
+    *CONFIG.write().await = config;
+}
+
+pub async fn reload(db: &EnterpriseDatabase) -> ResultType<()> {
+    let config: HoneypotConfig = match db.get_setting(HONEYPOT_KEY).await? {
+        Some(v) => serde_json::from_str(&v)?,
+        None => HoneypotConfig::default(),
+    };
+    config.validate()?;
+    set(config).await;
+    Ok(())
+}
+
+pub async fn get() -> HoneypotConfig {
+    CONFIG.read().await.clone()
+}
+
+pub async fn update(db: &EnterpriseDatabase, config: HoneypotConfig, updated_by: &str) -> ResultType<()> {
+    config.validate()?;
+    // 已有真实设备使用的ID不能作为诱饵
+    for id in config.ids.iter() {
+        if db.device_exists(id).await? {
+            bail!("device {} exists", id);
+        }
+    }
+    db.set_setting(HONEYPOT_KEY, &serde_json::to_string(&config)?, Some(updated_by))
+        .await?;
+    set(config).await;
+    Ok(())
+}
+
+pub async fn is_decoy(id: &str) -> bool {
+    let decoys = DECOYS.read().await;
+    !decoys.is_empty() && decoys.contains(id)
+}
+
+pub async fn is_ip_banned(ip: IpAddr) -> bool {
+    let bans = IP_BANS.read().await;
+    if bans.is_empty() {
+        return false;
+    }
+    match bans.get(&ip) {
+        Some(ban) => ban.expires_at > now(),
+        None => false,
+    }
+}
+
+pub async fn list_ip_bans() -> Vec<IpBan> {
+    let now = now();
+    let mut bans = IP_BANS.write().await;
+    bans.retain(|_, ban| ban.expires_at > now);
+    let mut res: Vec<IpBan> = bans.values().cloned().collect();
+    res.sort_by(|a, b| b.banned_at.cmp(&a.banned_at));
+    res
+}
+
+pub async fn unban_ip(ip: IpAddr) -> bool {
+    IP_BANS.write().await.remove(&ip).is_some()
+}
+
+// 是否需要为这次触发告警，同一来源的重复请求在去重窗口内只告警一次
+async fn should_alert(ip: IpAddr, decoy_id: &str, now: u64) -> bool {
+    let mut alerted = ALERTED.write().await;
+    alerted.retain(|_, t| now < *t + ALERT_DEDUP_SECS);
+    let key = (ip, decoy_id.to_owned());
+    if alerted.contains_key(&key) {
+        return false;
+    }
+    alerted.insert(key, now);
+    true
+}
+
+// 针对诱饵ID的请求: 记录安全事件、告警并按配置封禁来源IP。
+// action 为 punch_hole / relay / webrtc，details 为请求中的其他来源信息
+pub async fn trip(
+    db: &EnterpriseDatabase,
+    decoy_id: &str,
+    source: SocketAddr,
+    action: &str,
+    user_id: Option<String>,
+    extra: Vec<(&str, String)>,
+) {
+    let ip = source.ip();
+    let now = now();
+    let ban_minutes = CONFIG.read().await.ban_minutes;
+    // 本机地址(如经反向代理的网页请求)不封禁
+    let banned = ban_minutes > 0 && !ip.is_loopback();
+    if banned {
+        IP_BANS.write().await.insert(
+            ip,
+            IpBan {
+                ip: ip.to_string(),
+                decoy_id: decoy_id.to_owned(),
+                banned_at: now,
+                expires_at: now + ban_minutes * 60,
+            },
+        );
+    }
+    if !should_alert(ip, decoy_id, now).await {
+        return;
+    }
+    let mut details = HashMap::new();
+    details.insert("honeypot_id".to_string(), decoy_id.to_owned());
+    details.insert("action".to_string(), action.to_owned());
+    details.insert("source".to_string(), source.to_string());
+    details.insert("ip_banned".to_string(), banned.to_string());
+    for (k, v) in extra {
+        details.insert(k.to_string(), v);
+    }
+    let event = SecurityEvent {
+        id: uuid::Uuid::new_v4().to_string(),
+        event_type: SecurityEventType::UnauthorizedAccess,
+        severity: SecuritySeverity::Critical,
+        user_id,
+        device_id: Some(decoy_id.to_owned()),
+        ip_address: ip.to_string(),
+        user_agent: None,
+        details,
+        timestamp: SystemTime::now(),
+        resolved: false,
+        resolution_notes: None,
+    };
+    if let Err(err) = db.save_security_event(&event).await {
+        log::error!("Failed to save honeypot security event: {}", err);
+    }
+    log::warn!("Honeypot {} tripped by {} ({}): {:?}", decoy_id, source, action, event);
+    crate::notifications::security_event(db, &event).await;
+    crate::itsm::security_event(&event).await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hbb_common::tokio;
+
+    #[test]
+    fn test_validate() {
+        let mut config = HoneypotConfig {
+            ids: vec!["888000111".to_owned()],
+            ban_minutes: 60,
+        };
+        assert!(config.validate().is_ok());
+        config.ids.push("123".to_owned());
+        assert!(config.validate().is_err());
+        config.ids.pop();
+        config.ids.push("888 000".to_owned());
+        assert!(config.validate().is_err());
+        config.ids.pop();
+        config.ban_minutes = 100_000;
+        assert!(config.validate().is_err());
+        assert!(HoneypotConfig::default().validate().is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_alert_dedup() {
+        let ip: IpAddr = "203.0.113.7".parse().unwrap();
+        assert!(should_alert(ip, "888000111", 1000).await);
+        assert!(!should_alert(ip, "888000111", 1030).await);
+        assert!(should_alert(ip, "888000222", 1030).await);
+        assert!(should_alert(ip, "888000111", 1061).await);
+    }
+}
@@ -0,0 +1,494 @@
+// 主机网络统计模块 - 定期读取 /proc 下的内核计数器，统计本机各网卡的收发字节和速率、TCP 重传、
+// 监听端口等待 accept 的连接数(积压)及溢出丢弃次数、已建立连接的收发队列字节数，
+// 以 Prometheus 格式由 /metrics 提供并在仪表盘展示，作为中继服务器容量规划的依据。
+// 只读取 /proc，不需要加载 eBPF 程序；容器中部署时需使用 host 网络，否则统计的是容器自己的网络命名空间。
+// 非 Linux 系统读取失败，统计为空
+use hbb_common::{
+    log,
+    tokio::{
+        self,
+        sync::RwLock,
+        time::{interval, Duration},
+    },
+};
+use schemars::JsonSchema;
+use serde_derive::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+
+const COLLECT_INTERVAL_SECS: u64 = 10;
+// 保留最近一小时的速率曲线
+const HISTORY_POINTS: usize = 360;
+// 只列出积压最多的监听端口
+const MAX_LISTEN_PORTS: usize = 32;
+const TCP_LISTEN: &str = "0A";
+const TCP_ESTABLISHED: &str = "01";
+
+lazy_static::lazy_static! {
+    static ref LAST: RwLock<Option<(u64, RawSample)>> = Default::default();
+    static ref CURRENT: RwLock<Option<HostStats>> = Default::default();
+    static ref HISTORY: RwLock<VecDeque<HostStatsPoint>> = Default::default();
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct InterfaceStats {
+    pub name: String,
+    pub rx_bytes: u64,
+    pub tx_bytes: u64,
+    pub rx_packets: u64,
+    pub tx_packets: u64,
+    pub rx_errors: u64,
+    pub tx_errors: u64,
+    pub rx_dropped: u64,
+    pub tx_dropped: u64,
+    // 与上次采集相比的速率，首次采集为 0
+    pub rx_bytes_per_sec: u64,
+    pub tx_bytes_per_sec: u64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct TcpStats {
+    pub out_segments: u64,
+    pub retrans_segments: u64,
+    // 采集间隔内重传段占发送段的比例
+    pub retrans_rate: f64,
+    pub retrans_per_sec: u64,
+    // accept 队列满导致的丢弃
+    pub listen_overflows: u64,
+    pub listen_drops: u64,
+    pub established: u64,
+    // 已建立连接中未被对端确认的发送字节、未被应用读取的接收字节
+    pub send_queue_bytes: u64,
+    pub recv_queue_bytes: u64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct ListenBacklog {
+    pub port: u16,
+    // 已完成握手、等待 accept 的连接数
+    pub queued: u64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct HostStats {
+    pub collected_at: u64,
+    // 不含回环网卡
+    pub rx_bytes_per_sec: u64,
+    pub tx_bytes_per_sec: u64,
+    pub interfaces: Vec<InterfaceStats>,
+    pub tcp: TcpStats,
+    pub listen: Vec<ListenBacklog>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct HostStatsPoint {
+    pub at: u64,
+    pub rx_bytes_per_sec: u64,
+    pub tx_bytes_per_sec: u64,
+    pub retrans_per_sec: u64,
+    pub listen_queued: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct HostStatsReport {
+    pub current: Option<HostStats>,
+    pub history: Vec<HostStatsPoint>,
+}
+
+// 一次采集的原始计数器
+#[derive(Debug, Clone, Default)]
+struct RawSample {
+    interfaces: Vec<InterfaceStats>,
+    tcp: TcpStats,
+    listen: Vec<ListenBacklog>,
+}
+
+// /proc/net/dev，冒号后依次为接收的 bytes packets errs drop fifo frame compressed multicast 和发送的
+// bytes packets errs drop fifo colls carrier compressed
+fn parse_net_dev(text: &str) -> Vec<InterfaceStats> {
+    let mut res = Vec::new();
+    for line in text.lines().skip(2) {
+        let (name, counters) = match line.split_once(':') {
+            Some(x) => x,
+            None => continue,
+        };
+        let v: Vec<u64> = counters.split_whitespace().filter_map(|x| x.parse().ok()).collect();
+        if v.len() < 16 {
+            continue;
+        }
+        res.push(InterfaceStats {
+            name: name.trim().to_owned(),
+            rx_bytes: v[0],
+            rx_packets: v[1],
+            rx_errors: v[2],
+            rx_dropped: v[3],
+            tx_bytes: v[8],
+            tx_packets: v[9],
+            tx_errors: v[10],
+            tx_dropped: v[11],
+            ..Default::default()
+        });
+    }
+    res.sort_by(|a, b| a.name.cmp(&b.name));
+    res
+}
+
+// /proc/net/snmp 和 /proc/net/netstat 中以 "Tcp:"、"TcpExt:" 开头的两行，第一行为名称，第二行为值
+fn parse_counters(text: &str, prefix: &str) -> HashMap<String, u64> {
+    let lines: Vec<&str> = text.lines().filter(|l| l.starts_with(prefix)).collect();
+    let mut res = HashMap::new();
+    if lines.len() < 2 {
+        return res;
+    }
+    for (name, value) in lines[0].split_whitespace().zip(lines[1].split_whitespace()).skip(1) {
+        // MaxConn 等为 -1，忽略
+        if let Ok(value) = value.parse::<u64>() {
+            res.insert(name.to_owned(), value);
+        }
+    }
+    res
+}
+
+// /proc/net/tcp 和 /proc/net/tcp6 的一行:
+//   sl local_address rem_address st tx_queue:rx_queue ...
+// 监听状态的 rx_queue 为等待 accept 的连接数
+fn parse_sockets(tables: &[&str], tcp: &mut TcpStats) -> Vec<ListenBacklog> {
+    let mut listen: BTreeMap<u16, u64> = BTreeMap::new();
+    for table in tables {
+        for line in table.lines().skip(1) {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.len() < 5 {
+                continue;
+            }
+            let port = match fields[1]
+                .rsplit_once(':')
+                .and_then(|(_, p)| u16::from_str_radix(p, 16).ok())
+            {
+                Some(port) => port,
+                None => continue,
+            };
+            let (tx_queue, rx_queue) = match fields[4].split_once(':') {
+                Some((tx, rx)) => (
+                    u64::from_str_radix(tx, 16).unwrap_or_default(),
+                    u64::from_str_radix(rx, 16).unwrap_or_default(),
+                ),
+                None => continue,
+            };
+            match fields[3] {
+                TCP_LISTEN => *listen.entry(port).or_default() += rx_queue,
+                TCP_ESTABLISHED => {
+                    tcp.established += 1;
+                    tcp.send_queue_bytes += tx_queue;
+                    tcp.recv_queue_bytes += rx_queue;
+                }
+                _ => {}
+            }
+        }
+    }
+    let mut res: Vec<ListenBacklog> = listen
+        .into_iter()
+        .map(|(port, queued)| ListenBacklog { port, queued })
+        .collect();
+    res.sort_by(|a, b| b.queued.cmp(&a.queued).then(a.port.cmp(&b.port)));
+    res.truncate(MAX_LISTEN_PORTS);
+    res
+}
+
+fn parse(net_dev: &str, snmp: &str, netstat: &str, sockets: &[&str]) -> RawSample {
+    let tcp_counters = parse_counters(snmp, "Tcp:");
+    let ext_counters = parse_counters(netstat, "TcpExt:");
+    let counter = |counters: &HashMap<String, u64>, name: &str| counters.get(name).cloned().unwrap_or_default();
+    let mut tcp = TcpStats {
+        out_segments: counter(&tcp_counters, "OutSegs"),
+        retrans_segments: counter(&tcp_counters, "RetransSegs"),
+        listen_overflows: counter(&ext_counters, "ListenOverflows"),
+        listen_drops: counter(&ext_counters, "ListenDrops"),
+        ..Default::default()
+    };
+    let listen = parse_sockets(sockets, &mut tcp);
+    RawSample {
+        interfaces: parse_net_dev(net_dev),
+        tcp,
+        listen,
+    }
+}
+
+fn per_sec(current: u64, last: u64, secs: u64) -> u64 {
+    if secs == 0 {
+        0
+    } else {
+        current.saturating_sub(last) / secs
+    }
+}
+
+// 按上次采集计算速率，计数器回绕或网卡重建时速率为 0
+fn stats(now: u64, mut sample: RawSample, last: Option<&(u64, RawSample)>) -> HostStats {
+    if let Some((at, last)) = last {
+        let secs = now.saturating_sub(*at);
+        for interface in sample.interfaces.iter_mut() {
+            if let Some(prev) = last.interfaces.iter().find(|i| i.name == interface.name) {
+                interface.rx_bytes_per_sec = per_sec(interface.rx_bytes, prev.rx_bytes, secs);
+                interface.tx_bytes_per_sec = per_sec(interface.tx_bytes, prev.tx_bytes, secs);
+            }
+        }
+        let out = sample.tcp.out_segments.saturating_sub(last.tcp.out_segments);
+        let retrans = sample.tcp.retrans_segments.saturating_sub(last.tcp.retrans_segments);
+        if out > 0 {
+            sample.tcp.retrans_rate = retrans as f64 / out as f64;
+        }
+        sample.tcp.retrans_per_sec = per_sec(sample.tcp.retrans_segments, last.tcp.retrans_segments, secs);
+    }
+    let external = || sample.interfaces.iter().filter(|i| i.name != "lo");
+    let rx_bytes_per_sec = external().map(|i| i.rx_bytes_per_sec).sum();
+    let tx_bytes_per_sec = external().map(|i| i.tx_bytes_per_sec).sum();
+    HostStats {
+        collected_at: now,
+        rx_bytes_per_sec,
+        tx_bytes_per_sec,
+        interfaces: sample.interfaces,
+        tcp: sample.tcp,
+        listen: sample.listen,
+    }
+}
+
+fn read(path: &str) -> String {
+    std::fs::read_to_string(path).unwrap_or_default()
+}
+
+async fn collect() {
+    let net_dev = read("/proc/net/dev");
+    if net_dev.is_empty() {
+        return;
+    }
+    let sample = parse(
+        &net_dev,
+        &read("/proc/net/snmp"),
+        &read("/proc/net/netstat"),
+        &[&read("/proc/net/tcp"), &read("/proc/net/tcp6")],
+    );
+    let now = crate::common::now();
+    let mut last = LAST.write().await;
+    let stats = stats(now, sample.clone(), last.as_ref());
+    if last.is_some() {
+        let mut history = HISTORY.write().await;
+        history.push_back(HostStatsPoint {
+            at: now,
+            rx_bytes_per_sec: stats.rx_bytes_per_sec,
+            tx_bytes_per_sec: stats.tx_bytes_per_sec,
+            retrans_per_sec: stats.tcp.retrans_per_sec,
+            listen_queued: stats.listen.iter().map(|l| l.queued).sum(),
+        });
+        while history.len() > HISTORY_POINTS {
+            history.pop_front();
+        }
+    }
+    *last = Some((now, sample));
+    *CURRENT.write().await = Some(stats);
+}
+
+pub fn start() {
+    if read("/proc/net/dev").is_empty() {
+        log::info!("Host network statistics are not available on this system");
+        return;
+    }
+    tokio::spawn(async move {
+        let mut interval = interval(Duration::from_secs(COLLECT_INTERVAL_SECS));
+        loop {
+            interval.tick().await;
+            collect().await;
+        }
+    });
+    log::info!("Host network statistics collector started");
+}
+
+pub async fn current() -> Option<HostStats> {
+    CURRENT.read().await.clone()
+}
+
+pub async fn report() -> HostStatsReport {
+    HostStatsReport {
+        current: current().await,
+        history: HISTORY.read().await.iter().cloned().collect(),
+    }
+}
+
+pub(crate) fn metric(out: &mut String, name: &str, kind: &str, help: &str, values: &[(String, u64)]) {
+    out.push_str(&format!("# HELP {} {}\n# TYPE {} {}\n", name, help, name, kind));
+    for (labels, value) in values {
+        if labels.is_empty() {
+            out.push_str(&format!("{} {}\n", name, value));
+        } else {
+            out.push_str(&format!("{}{{{}}} {}\n", name, labels, value));
+        }
+    }
+}
+
+// Prometheus 文本格式
+pub fn prometheus(stats: &HostStats) -> String {
+    let mut out = String::new();
+    let interface_metrics: [(&str, &str, fn(&InterfaceStats) -> u64); 8] = [
+        ("host_network_receive_bytes_total", "Bytes received", |i| i.rx_bytes),
+        ("host_network_transmit_bytes_total", "Bytes transmitted", |i| i.tx_bytes),
+        ("host_network_receive_packets_total", "Packets received", |i| {
+            i.rx_packets
+        }),
+        ("host_network_transmit_packets_total", "Packets transmitted", |i| {
+            i.tx_packets
+        }),
+        ("host_network_receive_errors_total", "Receive errors", |i| i.rx_errors),
+        ("host_network_transmit_errors_total", "Transmit errors", |i| i.tx_errors),
+        ("host_network_receive_drop_total", "Received packets dropped", |i| {
+            i.rx_dropped
+        }),
+        ("host_network_transmit_drop_total", "Transmitted packets dropped", |i| {
+            i.tx_dropped
+        }),
+    ];
+    for (name, help, value) in interface_metrics {
+        let values: Vec<(String, u64)> = stats
+            .interfaces
+            .iter()
+            .map(|i| (format!("interface=\"{}\"", i.name), value(i)))
+            .collect();
+        metric(&mut out, name, "counter", help, &values);
+    }
+    let tcp = &stats.tcp;
+    let tcp_metrics = [
+        (
+            "host_tcp_out_segments_total",
+            "counter",
+            "TCP segments sent",
+            tcp.out_segments,
+        ),
+        (
+            "host_tcp_retransmitted_segments_total",
+            "counter",
+            "TCP segments retransmitted",
+            tcp.retrans_segments,
+        ),
+        (
+            "host_tcp_listen_overflows_total",
+            "counter",
+            "Connections dropped because the accept queue was full",
+            tcp.listen_overflows,
+        ),
+        (
+            "host_tcp_listen_drops_total",
+            "counter",
+            "Connections dropped by listening sockets",
+            tcp.listen_drops,
+        ),
+        (
+            "host_tcp_established",
+            "gauge",
+            "Established TCP connections",
+            tcp.established,
+        ),
+        (
+            "host_tcp_send_queue_bytes",
+            "gauge",
+            "Unacknowledged bytes of established TCP connections",
+            tcp.send_queue_bytes,
+        ),
+        (
+            "host_tcp_receive_queue_bytes",
+            "gauge",
+            "Unread bytes of established TCP connections",
+            tcp.recv_queue_bytes,
+        ),
+    ];
+    for (name, kind, help, value) in tcp_metrics {
+        metric(&mut out, name, kind, help, &[(String::new(), value)]);
+    }
+    let listen: Vec<(String, u64)> = stats
+        .listen
+        .iter()
+        .map(|l| (format!("port=\"{}\"", l.port), l.queued))
+        .collect();
+    metric(
+        &mut out,
+        "host_tcp_listen_queue",
+        "gauge",
+        "Connections waiting to be accepted",
+        &listen,
+    );
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NET_DEV: &str = "Inter-|   Receive                                                |  Transmit
+ face |bytes    packets errs drop fifo frame compressed multicast|bytes    packets errs drop fifo colls carrier compressed
+    lo:    2000      20    0    0    0     0          0         0     2000      20    0    0    0     0       0          0
+  eth0: 1000000    800    1    2    0     0          0         0  3000000    900    0    3    0     0       0          0
+";
+
+    const SNMP: &str = "Ip: Forwarding DefaultTTL
+Ip: 1 64
+Tcp: RtoAlgorithm RtoMin RtoMax MaxConn ActiveOpens PassiveOpens AttemptFails EstabResets CurrEstab InSegs OutSegs RetransSegs InErrs OutRsts InCsumErrors
+Tcp: 1 200 120000 -1 10 20 0 0 2 5000 4000 40 0 0 0
+";
+
+    const NETSTAT: &str = "TcpExt: SyncookiesSent ListenOverflows ListenDrops
+TcpExt: 0 3 5
+";
+
+    const TCP: &str = "  sl  local_address rem_address   st tx_queue rx_queue tr tm->when retrnsmt   uid  timeout inode
+   0: 00000000:527D 00000000:0000 0A 00000000:00000002 00:00000000 00000000     0        0 100 1
+   1: 0100007F:527E 00000000:0000 0A 00000000:00000000 00:00000000 00000000     0        0 101 1
+   2: 0A000001:527D 0A000002:C350 01 00000400:00000010 00:00000000 00000000     0        0 102 1
+";
+
+    const TCP6: &str =
+        "  sl  local_address                         remote_address                        st tx_queue rx_queue
+   0: 00000000000000000000000000000000:527D 00000000000000000000000000000000:0000 0A 00000000:00000001 00:00000000
+";
+
+    #[test]
+    fn test_parse() {
+        let sample = parse(NET_DEV, SNMP, NETSTAT, &[TCP, TCP6]);
+        assert_eq!(sample.interfaces.len(), 2);
+        let eth0 = &sample.interfaces[0];
+        assert_eq!(eth0.name, "eth0");
+        assert_eq!((eth0.rx_bytes, eth0.tx_bytes), (1_000_000, 3_000_000));
+        assert_eq!((eth0.rx_errors, eth0.rx_dropped, eth0.tx_dropped), (1, 2, 3));
+        assert_eq!(sample.tcp.out_segments, 4000);
+        assert_eq!(sample.tcp.retrans_segments, 40);
+        assert_eq!((sample.tcp.listen_overflows, sample.tcp.listen_drops), (3, 5));
+        assert_eq!(sample.tcp.established, 1);
+        assert_eq!((sample.tcp.send_queue_bytes, sample.tcp.recv_queue_bytes), (1024, 16));
+        // 21117 的 IPv4 和 IPv6 监听合计
+        assert_eq!(
+            sample.listen,
+            vec![
+                ListenBacklog { port: 21117, queued: 3 },
+                ListenBacklog { port: 21118, queued: 0 }
+            ]
+        );
+    }
+
+    #[test]
+    fn test_rates() {
+        let first = parse(NET_DEV, SNMP, NETSTAT, &[TCP]);
+        let stats0 = stats(100, first.clone(), None);
+        assert_eq!(stats0.rx_bytes_per_sec, 0);
+        let mut second = first.clone();
+        second.interfaces[0].rx_bytes += 100_000;
+        second.interfaces[0].tx_bytes += 50_000;
+        second.interfaces[1].rx_bytes += 1_000_000;
+        second.tcp.out_segments += 1000;
+        second.tcp.retrans_segments += 20;
+        let stats1 = stats(110, second, Some(&(100, first)));
+        // 回环网卡不计入合计
+        assert_eq!(stats1.rx_bytes_per_sec, 10_000);
+        assert_eq!(stats1.tx_bytes_per_sec, 5_000);
+        assert_eq!(stats1.tcp.retrans_per_sec, 2);
+        assert_eq!(stats1.tcp.retrans_rate, 0.02);
+
+        let text = prometheus(&stats1);
+        assert!(text.contains("host_network_receive_bytes_total{interface=\"eth0\"} 1100000\n"));
+        assert!(text.contains("host_tcp_retransmitted_segments_total 60\n"));
+        assert!(text.contains("# TYPE host_tcp_listen_queue gauge\n"));
+    }
+}
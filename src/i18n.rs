@@ -0,0 +1,246 @@
+// 多语言模块 - 服务器生成的文字(控制台提示、接口消息、邮件、告警和报告)从 locales 下的资源文件读取:
+//   - strings   按键查找的文字，{name} 为占位符，各语言须提供相同的键和占位符(由测试检查)
+//   - messages  接口消息的译文，以中文原文为键，{} 匹配原文中的可变部分；中文资源无需提供
+// 语言优先级：用户在 /api/language 设置的偏好 > 请求头 Accept-Language > 环境变量 RUSTDESK_LANG > zh
+// 邮件、告警和报告按收件用户的偏好发送，不对应用户的收件人(如设备组告警接收人)使用服务器默认语言
+use crate::enterprise_database::EnterpriseDatabase;
+use crate::validation::Payload;
+use hbb_common::{log, tokio, tokio::sync::RwLock, ResultType};
+use schemars::JsonSchema;
+use serde_derive::{Deserialize, Serialize};
+use std::collections::HashMap;
+use validator::Validate;
+
+lazy_static::lazy_static! {
+    static ref RESOURCES: HashMap<Lang, Resource> = [
+        (Lang::Zh, include_str!("locales/zh.json")),
+        (Lang::En, include_str!("locales/en.json")),
+    ]
+    .into_iter()
+    .map(|(lang, data)| (lang, serde_json::from_str(data).expect("invalid locale resource")))
+    .collect();
+    static ref USER_LANGS: RwLock<HashMap<String, Lang>> = Default::default();
+    static ref DEFAULT_LANG: Lang = std::env::var("RUSTDESK_LANG")
+        .ok()
+        .and_then(|x| Lang::parse(&x))
+        .unwrap_or(Lang::Zh);
+}
+
+tokio::task_local! {
+    // 接口请求处理期间协商出的语言
+    pub static LANG: Lang;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Lang {
+    Zh,
+    En,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct Resource {
+    #[serde(default)]
+    strings: HashMap<String, String>,
+    #[serde(default)]
+    messages: HashMap<String, String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, Validate)]
+pub struct LanguagePreference {
+    // 为空时按 Accept-Language 协商
+    pub language: Option<Lang>,
+}
+
+impl Payload for LanguagePreference {}
+
+impl Lang {
+    pub const ALL: [Lang; 2] = [Lang::Zh, Lang::En];
+
+    // 语言标签的主标签，如 zh-CN、en_US.UTF-8
+    pub fn parse(tag: &str) -> Option<Self> {
+        let tag = tag.trim().to_ascii_lowercase();
+        match tag.split(|c| c == '-' || c == '_' || c == '.').next()? {
+            "zh" => Some(Lang::Zh),
+            "en" => Some(Lang::En),
+            _ => None,
+        }
+    }
+
+    // 按 q 值从高到低取第一个支持的语言，都不支持时使用服务器默认语言
+    pub fn negotiate(accept_language: Option<&str>) -> Self {
+        let mut tags: Vec<(f32, Lang)> = accept_language
+            .unwrap_or_default()
+            .split(',')
+            .filter_map(|x| {
+                let mut parts = x.split(';');
+                let lang = Lang::parse(parts.next()?)?;
+                let q = parts
+                    .find_map(|x| x.trim().strip_prefix("q="))
+                    .and_then(|x| x.parse::<f32>().ok())
+                    .unwrap_or(1.0);
+                Some((q, lang))
+            })
+            .filter(|(q, _)| *q > 0.0)
+            .collect();
+        tags.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap_or(std::cmp::Ordering::Equal));
+        tags.first().map(|x| x.1).unwrap_or_else(default_lang)
+    }
+
+    pub fn tag(&self) -> &'static str {
+        match self {
+            Lang::Zh => "zh",
+            Lang::En => "en",
+        }
+    }
+}
+
+pub fn default_lang() -> Lang {
+    *DEFAULT_LANG
+}
+
+// 当前请求的语言，不在请求处理中(如后台任务)时为服务器默认语言
+pub fn current() -> Lang {
+    LANG.try_with(|x| *x).unwrap_or_else(|_| default_lang())
+}
+
+// 请求使用的语言，user_id 为已登录用户
+pub async fn request_lang(user_id: Option<&str>, accept_language: Option<&str>) -> Lang {
+    if let Some(user_id) = user_id {
+        if let Some(lang) = user_preference(user_id).await {
+            return lang;
+        }
+    }
+    Lang::negotiate(accept_language)
+}
+
+fn lookup(lang: Lang, key: &str) -> Option<&'static str> {
+    RESOURCES
+        .get(&lang)
+        .and_then(|x| x.strings.get(key))
+        .map(|x| x.as_str())
+}
+
+// 按键取文字并代入占位符，缺少译文时依次回退到中文和键本身
+pub fn t(lang: Lang, key: &str, args: &[(&str, &dyn std::fmt::Display)]) -> String {
+    let mut text = lookup(lang, key)
+        .or_else(|| lookup(Lang::Zh, key))
+        .unwrap_or(key)
+        .to_owned();
+    for (name, value) in args {
+        text = text.replace(&format!("{{{}}}", name), &value.to_string());
+    }
+    text
+}
+
+// 模板中的 {} 匹配任意内容，返回匹配到的内容
+fn match_template<'a>(template: &str, text: &'a str) -> Option<&'a str> {
+    let (prefix, suffix) = template.split_once("{}")?;
+    text.strip_prefix(prefix)
+        .and_then(|x| x.strip_suffix(suffix))
+        .filter(|x| !x.is_empty())
+}
+
+// 接口消息的译文，没有译文时返回原文
+pub fn message(lang: Lang, message: &str) -> String {
+    let messages = match RESOURCES.get(&lang) {
+        Some(resource) if lang != Lang::Zh => &resource.messages,
+        _ => return message.to_owned(),
+    };
+    if let Some(translated) = messages.get(message) {
+        return translated.clone();
+    }
+    for (source, translated) in messages.iter().filter(|(k, _)| k.contains("{}")) {
+        if let Some(arg) = match_template(source, message) {
+            return translated.replace("{}", arg);
+        }
+    }
+    message.to_owned()
+}
+
+pub async fn reload(db: &EnterpriseDatabase) -> ResultType<()> {
+    let langs = db
+        .list_user_languages()
+        .await?
+        .into_iter()
+        .filter_map(|(user_id, tag)| Some((user_id, Lang::parse(&tag)?)))
+        .collect();
+    *USER_LANGS.write().await = langs;
+    Ok(())
+}
+
+// 用户设置的语言偏好
+pub async fn user_preference(user_id: &str) -> Option<Lang> {
+    USER_LANGS.read().await.get(user_id).copied()
+}
+
+// 给用户发送邮件、告警和报告使用的语言
+pub async fn user_lang(user_id: &str) -> Lang {
+    user_preference(user_id).await.unwrap_or_else(default_lang)
+}
+
+pub async fn set_user_lang(db: &EnterpriseDatabase, user_id: &str, lang: Option<Lang>) -> ResultType<()> {
+    db.set_user_language(user_id, lang.map(|x| x.tag())).await?;
+    let mut langs = USER_LANGS.write().await;
+    match lang {
+        Some(lang) => langs.insert(user_id.to_owned(), lang),
+        None => langs.remove(user_id),
+    };
+    log::info!("Language of user {} set to {:?}", user_id, lang);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeSet;
+
+    fn placeholders(text: &str) -> BTreeSet<String> {
+        text.split('{')
+            .skip(1)
+            .filter_map(|x| x.split_once('}'))
+            .map(|(name, _)| name.to_owned())
+            .collect()
+    }
+
+    #[test]
+    fn test_resources() {
+        let zh = &RESOURCES[&Lang::Zh];
+        for lang in Lang::ALL {
+            let resource = &RESOURCES[&lang];
+            let keys: BTreeSet<_> = resource.strings.keys().collect();
+            assert_eq!(keys, zh.strings.keys().collect(), "{:?}", lang);
+            for (key, text) in resource.strings.iter() {
+                assert_eq!(placeholders(text), placeholders(&zh.strings[key]), "{:?} {}", lang, key);
+            }
+            for (source, translated) in resource.messages.iter() {
+                assert_eq!(source.matches("{}").count(), translated.matches("{}").count(), "{}", source);
+            }
+        }
+    }
+
+    #[test]
+    fn test_negotiate() {
+        assert_eq!(Lang::negotiate(Some("en-US,en;q=0.9")), Lang::En);
+        assert_eq!(Lang::negotiate(Some("zh-CN,zh;q=0.9,en;q=0.8")), Lang::Zh);
+        assert_eq!(Lang::negotiate(Some("fr-FR,en;q=0.5,zh;q=0.4")), Lang::En);
+        assert_eq!(Lang::negotiate(Some("zh;q=0.2,en;q=0.7")), Lang::En);
+        assert_eq!(Lang::negotiate(Some("en;q=0,de")), default_lang());
+        assert_eq!(Lang::parse("en_US.UTF-8"), Some(Lang::En));
+        assert_eq!(Lang::parse("de"), None);
+    }
+
+    #[test]
+    fn test_translate() {
+        assert_eq!(
+            t(Lang::En, "email.otp.subject", &[]),
+            "RustDesk sign-in code"
+        );
+        assert!(t(Lang::En, "email.otp.body", &[("code", &"123456"), ("minutes", &5)]).contains("123456"));
+        assert_eq!(t(Lang::En, "no.such.key", &[]), "no.such.key");
+        assert_eq!(message(Lang::En, "登录成功"), "Signed in");
+        assert_eq!(message(Lang::Zh, "登录成功"), "登录成功");
+        assert_eq!(message(Lang::En, "已预登记3个ID"), "Provisioned 3 IDs");
+        assert_eq!(message(Lang::En, "未收录的消息"), "未收录的消息");
+    }
+}
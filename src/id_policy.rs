@@ -0,0 +1,153 @@
+// ID注册策略模块 - 限制允许注册的设备ID（前缀/正则/预登记），阻止个人设备接入企业服务器
+use crate::enterprise_database::EnterpriseDatabase;
+use crate::validation::{self, Payload};
+use hbb_common::{bail, log, tokio::sync::RwLock, ResultType};
+use regex::Regex;
+use schemars::JsonSchema;
+use serde_derive::{Deserialize, Serialize};
+use validator::Validate;
+
+pub const ID_POLICY_KEY: &str = "id_policy";
+
+lazy_static::lazy_static! {
+    static ref ID_POLICY: RwLock<CompiledIdPolicy> = Default::default();
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema, Validate)]
+pub struct IdPolicy {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default)]
+    #[validate(custom = "validation::non_empty_items")]
+    pub allowed_prefixes: Vec<String>,
+    #[serde(default)]
+    #[validate(length(max = 256))]
+    pub pattern: Option<String>, // 例如 ^CORP-\d{6}$
+    #[serde(default)]
+    pub require_provisioned: bool, // 只允许管理员预先登记的ID
+}
+
+impl Payload for IdPolicy {}
+
+#[derive(Default)]
+struct CompiledIdPolicy {
+    policy: IdPolicy,
+    regex: Option<Regex>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum IdCheck {
+    Allowed,
+    Rejected(&'static str),
+    NeedProvisioned, // 格式检查通过，还需查询预登记表
+}
+
+impl IdPolicy {
+    fn compile(self) -> ResultType<CompiledIdPolicy> {
+        let regex = match self.pattern.as_deref() {
+            Some(p) if !p.is_empty() => match Regex::new(p) {
+                Ok(r) => Some(r),
+                Err(err) => bail!("invalid id pattern {}: {}", p, err),
+            },
+            _ => None,
+        };
+        Ok(CompiledIdPolicy {
+            policy: self,
+            regex,
+        })
+    }
+}
+
+impl CompiledIdPolicy {
+    fn check(&self, id: &str) -> IdCheck {
+        let policy = &self.policy;
+        if !policy.enabled {
+            return IdCheck::Allowed;
+        }
+        if !policy.allowed_prefixes.is_empty()
+            && !policy.allowed_prefixes.iter().any(|p| id.starts_with(p.as_str()))
+        {
+            return IdCheck::Rejected("prefix not allowed");
+        }
+        if let Some(regex) = &self.regex {
+            if !regex.is_match(id) {
+                return IdCheck::Rejected("pattern mismatch");
+            }
+        }
+        if policy.require_provisioned {
+            return IdCheck::NeedProvisioned;
+        }
+        IdCheck::Allowed
+    }
+}
+
+pub async fn reload(db: &EnterpriseDatabase) -> ResultType<()> {
+    let policy: IdPolicy = match db.get_setting(ID_POLICY_KEY).await? {
+        Some(v) => serde_json::from_str(&v)?,
+        None => IdPolicy::default(),
+    };
+    *ID_POLICY.write().await = policy.compile()?;
+    Ok(())
+}
+
+pub async fn get() -> IdPolicy {
+    ID_POLICY.read().await.policy.clone()
+}
+
+pub async fn update(db: &EnterpriseDatabase, policy: IdPolicy, updated_by: &str) -> ResultType<()> {
+    let compiled = policy.clone().compile()?;
+    db.set_setting(ID_POLICY_KEY, &serde_json::to_string(&policy)?, Some(updated_by))
+        .await?;
+    *ID_POLICY.write().await = compiled;
+    Ok(())
+}
+
+// RegisterPk 时检查ID是否允许注册
+pub async fn is_allowed(db: &EnterpriseDatabase, id: &str) -> bool {
+    let check = ID_POLICY.read().await.check(id);
+    match check {
+        IdCheck::Allowed => true,
+        IdCheck::Rejected(reason) => {
+            log::warn!("Registration of {} rejected by id policy: {}", id, reason);
+            false
+        }
+        IdCheck::NeedProvisioned => match db.is_id_provisioned(id).await {
+            Ok(true) => true,
+            Ok(false) => {
+                log::warn!("Registration of {} rejected: id not provisioned", id);
+                false
+            }
+            Err(err) => {
+                log::error!("Failed to check provisioned id {}: {}", id, err);
+                false
+            }
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_id_policy_check() {
+        let policy = IdPolicy {
+            enabled: true,
+            allowed_prefixes: vec!["CORP-".to_owned()],
+            pattern: Some(r"^CORP-\d{6}$".to_owned()),
+            require_provisioned: false,
+        }
+        .compile()
+        .unwrap();
+        assert_eq!(policy.check("CORP-123456"), IdCheck::Allowed);
+        assert_eq!(policy.check("CORP-12345X"), IdCheck::Rejected("pattern mismatch"));
+        assert_eq!(policy.check("123456789"), IdCheck::Rejected("prefix not allowed"));
+        assert_eq!(CompiledIdPolicy::default().check("123456789"), IdCheck::Allowed);
+        assert!(IdPolicy {
+            pattern: Some("(".to_owned()),
+            ..Default::default()
+        }
+        .compile()
+        .is_err());
+    }
+}
@@ -0,0 +1,159 @@
+// 代操作(support impersonation)模块 - 超级管理员排查用户问题时，可填写原因后以该用户的身份和权限操作管理界面:
+//   - 签发带 act 声明(实际操作人)的令牌，有效期 60 分钟，按普通 Web 会话登记，登出或主动结束即失效
+//   - 令牌期间的请求在 ACTOR 作用域内处理，写入的审计日志同时记录被代操作的用户(user_id)和实际操作人(详情前缀)
+//   - 响应头带 X-Impersonated-By，界面据此显示醒目的代操作横幅
+//   - 被代操作的用户会收到邮件通知；不能代操作超级管理员，代操作期间不能再发起代操作或修改对方的双因素认证
+use crate::auth::{Actor, AuthManager, Claims, User, UserRole};
+use crate::enterprise_database::EnterpriseDatabase;
+use crate::{email_otp, i18n, web_session};
+use hbb_common::{bail, log, tokio, ResultType};
+use schemars::JsonSchema;
+use serde_derive::{Deserialize, Serialize};
+use std::time::Duration;
+
+pub const SESSION_DURATION_SECS: u64 = 60 * 60;
+const MIN_REASON_LEN: usize = 10;
+const MAX_REASON_LEN: usize = 2000;
+
+tokio::task_local! {
+    // 代操作令牌的请求处理期间的实际操作人
+    pub static ACTOR: Actor;
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct ImpersonationSession {
+    // 即代操作令牌的 jti
+    pub id: String,
+    pub actor_id: String,
+    pub actor_username: String,
+    pub user_id: String,
+    pub username: String,
+    pub reason: String,
+    pub started_at: u64,
+    pub expires_at: u64,
+    pub ended_at: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct ImpersonationGrant {
+    pub token: String,
+    pub session: ImpersonationSession,
+}
+
+pub fn validate_reason(reason: &str) -> ResultType<()> {
+    let len = reason.trim().chars().count();
+    if len < MIN_REASON_LEN {
+        bail!("reason must be at least {} characters", MIN_REASON_LEN);
+    }
+    if len > MAX_REASON_LEN {
+        bail!("reason must be at most {} characters", MAX_REASON_LEN);
+    }
+    Ok(())
+}
+
+// 写入审计日志时附加的实际操作人，不在代操作请求中时为空
+pub fn attribution() -> Option<String> {
+    ACTOR
+        .try_with(|actor| format!("[impersonated_by={}({})]", actor.username, actor.sub))
+        .ok()
+}
+
+pub async fn start(
+    db: &EnterpriseDatabase,
+    auth: &AuthManager,
+    claims: &Claims,
+    user: &User,
+    reason: &str,
+    user_agent: Option<String>,
+) -> ResultType<ImpersonationGrant> {
+    validate_reason(reason)?;
+    if claims.act.is_some() {
+        bail!("already impersonating");
+    }
+    if user.id == claims.sub {
+        bail!("cannot impersonate yourself");
+    }
+    if user.role == UserRole::SuperAdmin {
+        bail!("cannot impersonate a super admin");
+    }
+    if !user.enabled {
+        bail!("user is disabled");
+    }
+    let actor = Actor {
+        sub: claims.sub.clone(),
+        username: claims.username.clone(),
+    };
+    let token = auth.generate_impersonation_jwt(user, actor, Duration::from_secs(SESSION_DURATION_SECS))?;
+    let issued = auth.verify_jwt(&token)?;
+    web_session::start(db, &issued.jti, &user.id, user_agent).await?;
+    let session = ImpersonationSession {
+        id: issued.jti,
+        actor_id: claims.sub.clone(),
+        actor_username: claims.username.clone(),
+        user_id: user.id.clone(),
+        username: user.username.clone(),
+        reason: reason.trim().to_owned(),
+        started_at: issued.iat as u64,
+        expires_at: issued.exp as u64,
+        ended_at: None,
+    };
+    db.save_impersonation(&session).await?;
+    log::warn!(
+        "{} started impersonating {}: {}",
+        session.actor_username,
+        session.username,
+        session.reason
+    );
+    if let Some(email) = user.email.clone().filter(|x| !x.is_empty()) {
+        let lang = i18n::user_lang(&user.id).await;
+        let body = i18n::t(
+            lang,
+            "email.impersonation.body",
+            &[
+                ("username", &session.username),
+                ("actor", &session.actor_username),
+                ("minutes", &(SESSION_DURATION_SECS / 60)),
+                ("reason", &session.reason),
+            ],
+        );
+        let subject = i18n::t(lang, "email.impersonation.subject", &[]);
+        tokio::spawn(async move {
+            if let Err(e) = email_otp::send_notification(vec![email], subject, body).await {
+                log::error!("Failed to send impersonation notice: {}", e);
+            }
+        });
+    }
+    Ok(ImpersonationGrant { token, session })
+}
+
+// 结束代操作令牌对应的会话，令牌随即失效
+pub async fn end(db: &EnterpriseDatabase, claims: &Claims) -> ResultType<bool> {
+    if claims.act.is_none() {
+        return Ok(false);
+    }
+    web_session::end(db, &claims.jti).await?;
+    db.end_impersonation(&claims.jti, crate::common::now()).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_reason() {
+        assert!(validate_reason("ticket #1234: cannot see devices").is_ok());
+        assert!(validate_reason("   short  ").is_err());
+        assert!(validate_reason(&"x".repeat(MAX_REASON_LEN + 1)).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_attribution() {
+        assert_eq!(attribution(), None);
+        let actor = Actor {
+            sub: "u-1".to_owned(),
+            username: "admin".to_owned(),
+        };
+        let tag = ACTOR.scope(actor, async { attribution() }).await;
+        assert_eq!(tag.as_deref(), Some("[impersonated_by=admin(u-1)]"));
+    }
+}
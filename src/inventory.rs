@@ -0,0 +1,132 @@
+// 资产清单模块 - 从客户端 /api/sysinfo 上报的系统信息中提取结构化的资产数据
+// (主机名、系统版本号、CPU、内存、RustDesk 版本)，按设备保存，供筛选和统计报表使用
+use regex::Regex;
+use schemars::JsonSchema;
+use serde_derive::{Deserialize, Serialize};
+
+const KB: f64 = 1024.0;
+
+lazy_static::lazy_static! {
+    // 例如 "Windows 10 Pro - 22H2 (19045)"、"Build 22631.2861"
+    static ref OS_BUILD: Regex = Regex::new(r"(?i)(?:build\s*|\()(\d{4,}(?:\.\d+)?)\)?").unwrap();
+    static ref MEMORY: Regex = Regex::new(r"(?i)^\s*(\d+(?:\.\d+)?)\s*([kmgt]i?b?|b)?\s*$").unwrap();
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct DeviceInventory {
+    pub device_id: String,
+    pub hostname: String,
+    pub username: String,
+    pub os: String,
+    pub os_build: Option<String>,
+    pub cpu: String,
+    // 原始上报的内存描述，以及解析出的字节数
+    pub memory: String,
+    pub memory_bytes: Option<u64>,
+    pub rustdesk_version: String,
+    pub updated_at: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct InventoryCount {
+    pub value: String,
+    pub count: i64,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct InventoryReport {
+    pub total: i64,
+    pub by_os: Vec<InventoryCount>,
+    pub by_os_build: Vec<InventoryCount>,
+    pub by_version: Vec<InventoryCount>,
+    // 按内存区间统计，如 "<4GB"、"8-16GB"
+    pub by_memory: Vec<InventoryCount>,
+}
+
+// 客户端上报的内存形如 "15.87GB"、"16 GiB"、"16384MB"，不带单位时视为字节
+pub fn parse_memory(memory: &str) -> Option<u64> {
+    let caps = MEMORY.captures(memory)?;
+    let value: f64 = caps[1].parse().ok()?;
+    let unit = caps
+        .get(2)
+        .map(|m| m.as_str().to_ascii_lowercase())
+        .unwrap_or_default();
+    let multiplier = match unit.chars().next() {
+        Some('k') => KB,
+        Some('m') => KB * KB,
+        Some('g') => KB * KB * KB,
+        Some('t') => KB * KB * KB * KB,
+        _ => 1.0,
+    };
+    Some((value * multiplier) as u64)
+}
+
+// 优先使用客户端单独上报的版本号，否则从系统描述中提取
+pub fn parse_os_build(os: &str, os_build: &str) -> Option<String> {
+    let os_build = os_build.trim();
+    if !os_build.is_empty() {
+        return Some(os_build.to_owned());
+    }
+    OS_BUILD.captures(os).map(|caps| caps[1].to_owned())
+}
+
+impl DeviceInventory {
+    #[allow(clippy::too_many_arguments)]
+    pub fn from_sysinfo(
+        device_id: &str,
+        hostname: &str,
+        username: &str,
+        os: &str,
+        os_build: &str,
+        cpu: &str,
+        memory: &str,
+        version: &str,
+        now: u64,
+    ) -> Self {
+        Self {
+            device_id: device_id.to_owned(),
+            hostname: hostname.trim().to_owned(),
+            username: username.trim().to_owned(),
+            os: os.trim().to_owned(),
+            os_build: parse_os_build(os, os_build),
+            cpu: cpu.trim().to_owned(),
+            memory: memory.trim().to_owned(),
+            memory_bytes: parse_memory(memory),
+            rustdesk_version: version.trim().to_owned(),
+            updated_at: now,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_sysinfo() {
+        assert_eq!(parse_memory("16GB"), Some(16 * 1024 * 1024 * 1024));
+        assert_eq!(parse_memory("16 GiB"), Some(16 * 1024 * 1024 * 1024));
+        assert_eq!(parse_memory("512MB"), Some(512 * 1024 * 1024));
+        assert_eq!(parse_memory("1.5 GB"), Some(1536 * 1024 * 1024));
+        assert_eq!(parse_memory("4096"), Some(4096));
+        assert_eq!(parse_memory("unknown"), None);
+
+        assert_eq!(
+            parse_os_build("Windows 10 Pro - 22H2 (19045)", "").as_deref(),
+            Some("19045")
+        );
+        assert_eq!(
+            parse_os_build("Windows 11 Enterprise Build 22631.2861", "").as_deref(),
+            Some("22631.2861")
+        );
+        assert_eq!(parse_os_build("Ubuntu 22.04", "").as_deref(), None);
+        assert_eq!(parse_os_build("Ubuntu 22.04", "5.15.0-91").as_deref(), Some("5.15.0-91"));
+
+        let inventory = DeviceInventory::from_sysinfo(
+            "123456789", " host-1 ", "alice", "Windows 10 Pro (19045)", "", "Intel i7", "8GB", "1.2.3", 100,
+        );
+        assert_eq!(inventory.hostname, "host-1");
+        assert_eq!(inventory.os_build.as_deref(), Some("19045"));
+        assert_eq!(inventory.memory_bytes, Some(8 * 1024 * 1024 * 1024));
+    }
+}
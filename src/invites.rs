@@ -0,0 +1,54 @@
+// 用户邀请模块 - 管理员只填写邮箱创建用户，服务器发送带签名令牌的邀请链接，用户打开链接后自行设置密码，
+// 策略要求时随后绑定2FA；邀请链接的地址前缀由 PUBLIC-URL 配置
+use crate::common::public_url;
+use crate::email_otp;
+use crate::i18n::{self, Lang};
+use hbb_common::{bail, log, ResultType};
+
+pub const INVITE_TTL_SECS: u64 = 72 * 3600;
+pub const MIN_PASSWORD_LEN: usize = 8;
+
+pub fn invite_link(token: &str) -> ResultType<String> {
+    public_url(&format!("/invite?token={}", token))
+}
+
+pub fn check_password(password: &str) -> ResultType<()> {
+    if password.chars().count() < MIN_PASSWORD_LEN {
+        bail!("password must be at least {} characters", MIN_PASSWORD_LEN);
+    }
+    if password.chars().all(|c| c.is_ascii_digit()) || password.chars().all(|c| c.is_alphabetic()) {
+        bail!("password must contain both letters and digits or symbols");
+    }
+    Ok(())
+}
+
+pub async fn send_invite(email: &str, username: &str, invited_by: &str, link: &str, lang: Lang) -> ResultType<()> {
+    let body = i18n::t(
+        lang,
+        "email.invite.body",
+        &[
+            ("invited_by", &invited_by),
+            ("username", &username),
+            ("hours", &(INVITE_TTL_SECS / 3600)),
+            ("link", &link),
+        ],
+    );
+    let subject = i18n::t(lang, "email.invite.subject", &[]);
+    email_otp::send_notification(vec![email.to_owned()], subject, body).await?;
+    log::info!("Invite sent to {}", username);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_password() {
+        assert!(check_password("abc123").is_err());
+        assert!(check_password("12345678").is_err());
+        assert!(check_password("abcdefgh").is_err());
+        assert!(check_password("abcd1234").is_ok());
+        assert!(check_password("密码密码!!!!").is_ok());
+    }
+}
@@ -0,0 +1,531 @@
+// ITSM/CMDB 集成模块 - 受管设备上报系统信息(/api/sysinfo)时在 ServiceNow/Jira 中创建或更新配置项(CI)，
+// 达到严重级别的安全事件自动创建事件单(incident)。字段映射在设置中配置，值为模板，{变量} 替换为对应内容:
+//   设备: {id} {hostname} {username} {os} {os_build} {cpu} {memory} {version}
+//   事件: {summary} {details} {event_id} {event_type} {severity} {user_id} {device_id} {ip_address}
+// ServiceNow 按 ci_key_field(默认 correlation_id)=设备ID 查找 CI，Jira 按标签 rustdesk-<设备ID> 查找 CI 工单。
+// 请求在后台发送，失败只记录日志，不影响设备上线和告警
+use crate::advanced_security::{SecurityEvent, SecuritySeverity};
+use crate::enterprise_database::EnterpriseDatabase;
+use crate::inventory::DeviceInventory;
+use crate::validation::Payload;
+use hbb_common::{bail, log, tokio::sync::RwLock, ResultType};
+use schemars::JsonSchema;
+use serde_derive::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::{collections::BTreeMap, time::Duration};
+use validator::Validate;
+
+pub const ITSM_KEY: &str = "itsm";
+const TOKEN_MASK: &str = "******";
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(15);
+
+lazy_static::lazy_static! {
+    static ref CONFIG: RwLock<ItsmConfig> = Default::default();
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ItsmProvider {
+    ServiceNow,
+    Jira,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, Validate)]
+pub struct ItsmConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    pub provider: ItsmProvider,
+    // 如 https://example.service-now.com、https://example.atlassian.net
+    #[serde(default)]
+    #[validate(length(max = 2048))]
+    pub base_url: String,
+    #[serde(default)]
+    #[validate(length(max = 256))]
+    pub username: String,
+    // ServiceNow 密码或 Jira API token，接口返回时隐藏
+    #[serde(default)]
+    #[validate(length(max = 4096))]
+    pub api_token: String,
+    #[serde(default = "default_true")]
+    pub sync_devices: bool,
+    // 达到该级别的安全事件创建事件单
+    #[serde(default = "default_min_severity")]
+    pub min_severity: SecuritySeverity,
+    // ServiceNow 的 CI 表、CI 查找字段和事件单表
+    #[serde(default = "default_ci_table")]
+    pub ci_table: String,
+    #[serde(default = "default_ci_key_field")]
+    pub ci_key_field: String,
+    #[serde(default = "default_incident_table")]
+    pub incident_table: String,
+    // Jira 项目和工单类型
+    #[serde(default)]
+    #[validate(length(max = 64))]
+    pub project_key: String,
+    #[serde(default = "default_ci_issue_type")]
+    pub ci_issue_type: String,
+    #[serde(default = "default_incident_issue_type")]
+    pub incident_issue_type: String,
+    // 目标字段 -> 模板，为空时使用各平台的默认映射
+    #[serde(default)]
+    pub device_fields: BTreeMap<String, String>,
+    #[serde(default)]
+    pub incident_fields: BTreeMap<String, String>,
+}
+
+impl Payload for ItsmConfig {}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_min_severity() -> SecuritySeverity {
+    SecuritySeverity::Critical
+}
+
+fn default_ci_table() -> String {
+    "cmdb_ci_computer".to_owned()
+}
+
+fn default_ci_key_field() -> String {
+    "correlation_id".to_owned()
+}
+
+fn default_incident_table() -> String {
+    "incident".to_owned()
+}
+
+fn default_ci_issue_type() -> String {
+    "Task".to_owned()
+}
+
+fn default_incident_issue_type() -> String {
+    "Incident".to_owned()
+}
+
+impl Default for ItsmConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            provider: ItsmProvider::ServiceNow,
+            base_url: String::new(),
+            username: String::new(),
+            api_token: String::new(),
+            sync_devices: default_true(),
+            min_severity: default_min_severity(),
+            ci_table: default_ci_table(),
+            ci_key_field: default_ci_key_field(),
+            incident_table: default_incident_table(),
+            project_key: String::new(),
+            ci_issue_type: default_ci_issue_type(),
+            incident_issue_type: default_incident_issue_type(),
+            device_fields: BTreeMap::new(),
+            incident_fields: BTreeMap::new(),
+        }
+    }
+}
+
+fn mapping(pairs: &[(&str, &str)]) -> BTreeMap<String, String> {
+    pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+}
+
+fn severity_rank(severity: &SecuritySeverity) -> u8 {
+    match severity {
+        SecuritySeverity::Low => 0,
+        SecuritySeverity::Medium => 1,
+        SecuritySeverity::High => 2,
+        SecuritySeverity::Critical => 3,
+    }
+}
+
+// 把模板中的 {变量} 替换为对应值，未知变量保持原样
+fn render(template: &str, vars: &BTreeMap<&str, String>) -> String {
+    let mut out = template.to_owned();
+    for (name, value) in vars.iter() {
+        out = out.replace(&format!("{{{}}}", name), value);
+    }
+    out
+}
+
+impl ItsmConfig {
+    pub fn validate(&self) -> ResultType<()> {
+        if !self.enabled {
+            return Ok(());
+        }
+        if !self.base_url.starts_with("https://") && !self.base_url.starts_with("http://") {
+            bail!("base_url must be an http(s) url");
+        }
+        if self.username.is_empty() || self.api_token.is_empty() {
+            bail!("username and api_token are required");
+        }
+        match self.provider {
+            ItsmProvider::ServiceNow => {
+                let valid = |x: &str| !x.is_empty() && x.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+                if !valid(&self.ci_table) || !valid(&self.incident_table) || !valid(&self.ci_key_field) {
+                    bail!("ci_table, ci_key_field and incident_table must be table/field names");
+                }
+            }
+            ItsmProvider::Jira => {
+                if self.project_key.is_empty() || self.ci_issue_type.is_empty() || self.incident_issue_type.is_empty() {
+                    bail!("project_key and issue types are required for jira");
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // 接口返回时隐藏凭据
+    pub fn masked(&self) -> Self {
+        let mut config = self.clone();
+        if !config.api_token.is_empty() {
+            config.api_token = TOKEN_MASK.to_owned();
+        }
+        config
+    }
+
+    fn device_fields(&self) -> BTreeMap<String, String> {
+        if !self.device_fields.is_empty() {
+            return self.device_fields.clone();
+        }
+        match self.provider {
+            ItsmProvider::ServiceNow => mapping(&[
+                ("name", "{hostname}"),
+                ("os", "{os}"),
+                ("os_version", "{os_build}"),
+                ("cpu_name", "{cpu}"),
+                ("short_description", "RustDesk {id}"),
+            ]),
+            ItsmProvider::Jira => mapping(&[
+                ("summary", "{hostname} (RustDesk {id})"),
+                (
+                    "description",
+                    "系统: {os} {os_build}\n处理器: {cpu}\n内存: {memory}\n登录用户: {username}\nRustDesk: {version}",
+                ),
+            ]),
+        }
+    }
+
+    fn incident_fields(&self) -> BTreeMap<String, String> {
+        if !self.incident_fields.is_empty() {
+            return self.incident_fields.clone();
+        }
+        match self.provider {
+            ItsmProvider::ServiceNow => mapping(&[
+                ("short_description", "{summary}"),
+                ("description", "{details}"),
+                ("urgency", "1"),
+                ("impact", "1"),
+            ]),
+            ItsmProvider::Jira => mapping(&[("summary", "{summary}"), ("description", "{details}")]),
+        }
+    }
+
+    fn render_fields(
+        fields: &BTreeMap<String, String>,
+        vars: &BTreeMap<&str, String>,
+    ) -> serde_json::Map<String, Value> {
+        fields
+            .iter()
+            .map(|(k, v)| (k.clone(), Value::String(render(v, vars))))
+            .collect()
+    }
+
+    // 设备 CI 的请求体
+    fn ci_payload(&self, vars: &BTreeMap<&str, String>) -> Value {
+        let mut fields = Self::render_fields(&self.device_fields(), vars);
+        let id = vars.get("id").cloned().unwrap_or_default();
+        match self.provider {
+            ItsmProvider::ServiceNow => {
+                fields.insert(self.ci_key_field.clone(), Value::String(id));
+                Value::Object(fields)
+            }
+            ItsmProvider::Jira => {
+                fields.insert("project".to_owned(), json!({ "key": self.project_key }));
+                fields.insert("issuetype".to_owned(), json!({ "name": self.ci_issue_type }));
+                fields.insert("labels".to_owned(), json!([jira_label(&id)]));
+                json!({ "fields": fields })
+            }
+        }
+    }
+
+    fn incident_payload(&self, vars: &BTreeMap<&str, String>) -> Value {
+        let mut fields = Self::render_fields(&self.incident_fields(), vars);
+        match self.provider {
+            ItsmProvider::ServiceNow => Value::Object(fields),
+            ItsmProvider::Jira => {
+                fields.insert("project".to_owned(), json!({ "key": self.project_key }));
+                fields.insert("issuetype".to_owned(), json!({ "name": self.incident_issue_type }));
+                fields.insert("labels".to_owned(), json!(["rustdesk-security"]));
+                json!({ "fields": fields })
+            }
+        }
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}{}", self.base_url.trim_end_matches('/'), path)
+    }
+}
+
+fn jira_label(device_id: &str) -> String {
+    format!("rustdesk-{}", device_id)
+}
+
+fn device_vars(inventory: &DeviceInventory) -> BTreeMap<&'static str, String> {
+    let hostname = if inventory.hostname.is_empty() {
+        inventory.device_id.clone()
+    } else {
+        inventory.hostname.clone()
+    };
+    BTreeMap::from([
+        ("id", inventory.device_id.clone()),
+        ("hostname", hostname),
+        ("username", inventory.username.clone()),
+        ("os", inventory.os.clone()),
+        ("os_build", inventory.os_build.clone().unwrap_or_default()),
+        ("cpu", inventory.cpu.clone()),
+        ("memory", inventory.memory.clone()),
+        ("version", inventory.rustdesk_version.clone()),
+    ])
+}
+
+fn event_vars(event: &SecurityEvent) -> BTreeMap<&'static str, String> {
+    let mut details = format!("事件ID: {}\n来源IP: {}\n", event.id, event.ip_address);
+    let extra: BTreeMap<_, _> = event.details.iter().collect();
+    for (k, v) in extra {
+        details.push_str(&format!("{}: {}\n", k, v));
+    }
+    BTreeMap::from([
+        (
+            "summary",
+            format!("[RustDesk] 安全告警: {:?} ({:?})", event.event_type, event.severity),
+        ),
+        ("details", details),
+        ("event_id", event.id.clone()),
+        ("event_type", format!("{:?}", event.event_type)),
+        ("severity", format!("{:?}", event.severity)),
+        ("user_id", event.user_id.clone().unwrap_or_default()),
+        ("device_id", event.device_id.clone().unwrap_or_default()),
+        ("ip_address", event.ip_address.clone()),
+    ])
+}
+
+pub async fn reload(db: &EnterpriseDatabase) -> ResultType<()> {
+    let config: ItsmConfig = match db.get_setting(ITSM_KEY).await? {
+        Some(v) => serde_json::from_str(&v)?,
+        None => ItsmConfig::default(),
+    };
+    config.validate()?;
+    *CONFIG.write().await = config;
+    Ok(())
+}
+
+pub async fn get() -> ItsmConfig {
+    CONFIG.read().await.clone()
+}
+
+pub async fn update(db: &EnterpriseDatabase, mut config: ItsmConfig, updated_by: &str) -> ResultType<()> {
+    // 前端回传掩码时保留原凭据
+    if config.api_token == TOKEN_MASK {
+        config.api_token = CONFIG.read().await.api_token.clone();
+    }
+    config.validate()?;
+    db.set_setting(ITSM_KEY, &serde_json::to_string(&config)?, Some(updated_by))
+        .await?;
+    *CONFIG.write().await = config;
+    Ok(())
+}
+
+fn client() -> ResultType<reqwest::Client> {
+    Ok(reqwest::Client::builder().timeout(REQUEST_TIMEOUT).build()?)
+}
+
+async fn send(config: &ItsmConfig, req: reqwest::RequestBuilder) -> ResultType<Value> {
+    let res = req
+        .basic_auth(&config.username, Some(&config.api_token))
+        .header(reqwest::header::ACCEPT, "application/json")
+        .send()
+        .await?
+        .error_for_status()?;
+    // Jira 更新工单返回 204 无内容
+    let body = res.text().await?;
+    if body.is_empty() {
+        return Ok(Value::Null);
+    }
+    Ok(serde_json::from_str(&body)?)
+}
+
+// 创建或更新设备 CI，返回平台中的记录ID
+async fn upsert_ci(config: &ItsmConfig, vars: &BTreeMap<&str, String>) -> ResultType<String> {
+    let client = client()?;
+    let id = vars.get("id").cloned().unwrap_or_default();
+    let payload = config.ci_payload(vars);
+    match config.provider {
+        ItsmProvider::ServiceNow => {
+            let table = config.url(&format!("/api/now/table/{}", config.ci_table));
+            let query = format!("{}={}", config.ci_key_field, id);
+            let found = send(
+                config,
+                client.get(&table).query(&[
+                    ("sysparm_query", query.as_str()),
+                    ("sysparm_limit", "1"),
+                    ("sysparm_fields", "sys_id"),
+                ]),
+            )
+            .await?;
+            match found["result"][0]["sys_id"].as_str() {
+                Some(sys_id) => {
+                    send(config, client.patch(format!("{}/{}", table, sys_id)).json(&payload)).await?;
+                    Ok(sys_id.to_owned())
+                }
+                None => {
+                    let created = send(config, client.post(&table).json(&payload)).await?;
+                    Ok(created["result"]["sys_id"].as_str().unwrap_or_default().to_owned())
+                }
+            }
+        }
+        ItsmProvider::Jira => {
+            let jql = format!(
+                "project = \"{}\" AND labels = \"{}\"",
+                config.project_key,
+                jira_label(&id)
+            );
+            let found = send(
+                config,
+                client.get(config.url("/rest/api/2/search")).query(&[
+                    ("jql", jql.as_str()),
+                    ("maxResults", "1"),
+                    ("fields", "key"),
+                ]),
+            )
+            .await?;
+            match found["issues"][0]["key"].as_str() {
+                Some(key) => {
+                    // 工单所属项目和类型不可通过更新修改
+                    let mut payload = payload;
+                    if let Some(fields) = payload["fields"].as_object_mut() {
+                        fields.remove("project");
+                        fields.remove("issuetype");
+                    }
+                    send(
+                        config,
+                        client
+                            .put(config.url(&format!("/rest/api/2/issue/{}", key)))
+                            .json(&payload),
+                    )
+                    .await?;
+                    Ok(key.to_owned())
+                }
+                None => {
+                    let created = send(config, client.post(config.url("/rest/api/2/issue")).json(&payload)).await?;
+                    Ok(created["key"].as_str().unwrap_or_default().to_owned())
+                }
+            }
+        }
+    }
+}
+
+async fn create_incident(config: &ItsmConfig, vars: &BTreeMap<&str, String>) -> ResultType<String> {
+    let client = client()?;
+    let payload = config.incident_payload(vars);
+    match config.provider {
+        ItsmProvider::ServiceNow => {
+            let url = config.url(&format!("/api/now/table/{}", config.incident_table));
+            let created = send(config, client.post(url).json(&payload)).await?;
+            Ok(created["result"]["number"].as_str().unwrap_or_default().to_owned())
+        }
+        ItsmProvider::Jira => {
+            let created = send(config, client.post(config.url("/rest/api/2/issue")).json(&payload)).await?;
+            Ok(created["key"].as_str().unwrap_or_default().to_owned())
+        }
+    }
+}
+
+// 设备上报系统信息后同步 CI
+pub async fn sync_device(inventory: &DeviceInventory) {
+    let config = CONFIG.read().await.clone();
+    if !config.enabled || !config.sync_devices {
+        return;
+    }
+    match upsert_ci(&config, &device_vars(inventory)).await {
+        Ok(record) => log::debug!("Synced {} to {:?} CI {}", inventory.device_id, config.provider, record),
+        Err(e) => log::error!("Failed to sync {} to {:?}: {}", inventory.device_id, config.provider, e),
+    }
+}
+
+// 达到级别的安全事件创建事件单
+pub async fn security_event(event: &SecurityEvent) {
+    let config = CONFIG.read().await.clone();
+    if !config.enabled || severity_rank(&event.severity) < severity_rank(&config.min_severity) {
+        return;
+    }
+    match create_incident(&config, &event_vars(event)).await {
+        Ok(record) => log::info!(
+            "Created {:?} incident {} for event {}",
+            config.provider,
+            record,
+            event.id
+        ),
+        Err(e) => log::error!(
+            "Failed to create {:?} incident for event {}: {}",
+            config.provider,
+            event.id,
+            e
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars() -> BTreeMap<&'static str, String> {
+        BTreeMap::from([
+            ("id", "123456789".to_owned()),
+            ("hostname", "pc-01".to_owned()),
+            ("os", "Windows".to_owned()),
+        ])
+    }
+
+    #[test]
+    fn test_render() {
+        assert_eq!(
+            render("{hostname} ({id}) {unknown}", &vars()),
+            "pc-01 (123456789) {unknown}"
+        );
+    }
+
+    #[test]
+    fn test_payload() {
+        let mut config = ItsmConfig {
+            enabled: true,
+            base_url: "https://example.service-now.com".to_owned(),
+            username: "svc".to_owned(),
+            api_token: "secret".to_owned(),
+            ..Default::default()
+        };
+        assert!(config.validate().is_ok());
+        assert_eq!(config.masked().api_token, TOKEN_MASK);
+        let payload = config.ci_payload(&vars());
+        assert_eq!(payload["correlation_id"], "123456789");
+        assert_eq!(payload["name"], "pc-01");
+        config.device_fields = mapping(&[("asset_tag", "RD-{id}")]);
+        let payload = config.ci_payload(&vars());
+        assert_eq!(payload["asset_tag"], "RD-123456789");
+        assert!(payload.get("name").is_none());
+        config.ci_table = "cmdb_ci; drop".to_owned();
+        assert!(config.validate().is_err());
+
+        let config = ItsmConfig {
+            provider: ItsmProvider::Jira,
+            project_key: "OPS".to_owned(),
+            ..config
+        };
+        assert!(config.validate().is_ok());
+        let payload = config.ci_payload(&vars());
+        assert_eq!(payload["fields"]["project"]["key"], "OPS");
+        assert_eq!(payload["fields"]["labels"][0], "rustdesk-123456789");
+        let payload = config.incident_payload(&BTreeMap::from([("summary", "alert".to_owned())]));
+        assert_eq!(payload["fields"]["issuetype"]["name"], "Incident");
+        assert_eq!(payload["fields"]["summary"], "alert");
+    }
+}
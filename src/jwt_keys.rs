@@ -0,0 +1,142 @@
+// JWT 密钥轮换模块 - 令牌头部带 kid，新令牌用主密钥签发，校验时按 kid 选择主密钥或次密钥：
+//   - 轮换时生成新的随机主密钥，原主密钥降为次密钥，宽限期内原密钥签发的令牌仍然有效，
+//     宽限期默认为会话时长，已登录的用户不会被立即登出
+//   - 配置文件 [auth] jwt_secret_secondary (JWT_SECRET_SECONDARY) 可手动指定一直有效的次密钥，
+//     用于手动更换 jwt_secret：新密钥写入 jwt_secret，原密钥移到 jwt_secret_secondary，会话过期后再删除
+//   - 发生过轮换后以数据库中的密钥为准，启动参数中的密钥不再生效
+// 宽限期结束后，原密钥签发的邀请链接、找回密码链接和受信任浏览器令牌同样失效
+use crate::auth::{AuthManager, JwtKey, JwtKeys};
+use crate::common::now;
+use crate::enterprise_database::EnterpriseDatabase;
+use hbb_common::{log, ResultType};
+use schemars::JsonSchema;
+use serde_derive::{Deserialize, Serialize};
+
+// 密钥明文保存在系统设置中，不通过通用设置接口读写
+pub const JWT_KEYS_KEY: &str = "jwt_keys";
+const SECRET_BYTES: usize = 48;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct JwtKeyInfo {
+    pub primary_kid: String,
+    pub secondary_kid: Option<String>,
+    // 次密钥失效时间，为空表示一直有效
+    pub secondary_valid_until: Option<u64>,
+    pub rotated_at: Option<u64>,
+    pub rotated_by: Option<String>,
+}
+
+// 数据库中保存的密钥及最近一次轮换的记录
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct StoredKeys {
+    #[serde(flatten)]
+    keys: JwtKeys,
+    rotated_at: u64,
+    rotated_by: String,
+}
+
+fn info_of(keys: &JwtKeys, stored: Option<&StoredKeys>) -> JwtKeyInfo {
+    JwtKeyInfo {
+        primary_kid: keys.primary.kid.clone(),
+        secondary_kid: keys.secondary.as_ref().map(|x| x.kid.clone()),
+        secondary_valid_until: keys.secondary_valid_until,
+        rotated_at: stored.map(|x| x.rotated_at),
+        rotated_by: stored.map(|x| x.rotated_by.clone()),
+    }
+}
+
+fn generate_secret() -> String {
+    use rand::RngCore;
+    let mut secret = [0u8; SECRET_BYTES];
+    rand::thread_rng().fill_bytes(&mut secret);
+    base64::encode(secret)
+}
+
+// secondary 为启动参数中的次密钥
+pub async fn init(db: &EnterpriseDatabase, auth: &AuthManager, secondary: Option<String>) -> ResultType<()> {
+    match db.get_setting(JWT_KEYS_KEY).await? {
+        Some(v) => {
+            let stored: StoredKeys = serde_json::from_str(&v)?;
+            if auth.jwt_keys().primary != stored.keys.primary {
+                log::warn!("JWT keys were rotated at {}, jwt_secret from arguments is ignored", stored.rotated_at);
+            }
+            log::info!("JWT key ring loaded, primary kid: {}", stored.keys.primary.kid);
+            auth.set_jwt_keys(stored.keys);
+        }
+        None => {
+            if let Some(secret) = secondary.filter(|x| !x.is_empty()) {
+                let mut keys = auth.jwt_keys();
+                keys.secondary = Some(JwtKey::new(secret));
+                auth.set_jwt_keys(keys);
+            }
+        }
+    }
+    Ok(())
+}
+
+pub async fn info(db: &EnterpriseDatabase, auth: &AuthManager) -> ResultType<JwtKeyInfo> {
+    let stored = match db.get_setting(JWT_KEYS_KEY).await? {
+        Some(v) => Some(serde_json::from_str::<StoredKeys>(&v)?),
+        None => None,
+    };
+    Ok(info_of(&auth.jwt_keys(), stored.as_ref()))
+}
+
+// 生成新主密钥，原主密钥作为次密钥保留 grace_secs；原有的次密钥被替换
+pub async fn rotate(db: &EnterpriseDatabase, auth: &AuthManager, grace_secs: u64, rotated_by: &str) -> ResultType<JwtKeyInfo> {
+    let current = auth.jwt_keys();
+    let stored = StoredKeys {
+        keys: JwtKeys {
+            primary: JwtKey::new(generate_secret()),
+            secondary: Some(current.primary),
+            secondary_valid_until: Some(now() + grace_secs),
+        },
+        rotated_at: now(),
+        rotated_by: rotated_by.to_owned(),
+    };
+    db.set_setting(JWT_KEYS_KEY, &serde_json::to_string(&stored)?, Some(rotated_by))
+        .await?;
+    auth.set_jwt_keys(stored.keys.clone());
+    let info = info_of(&stored.keys, Some(&stored));
+    log::info!("JWT keys rotated by {}, primary kid: {}", rotated_by, info.primary_kid);
+    Ok(info)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::UserRole;
+    use crate::web_api::fixtures::UserBuilder;
+    use hbb_common::tokio;
+
+    #[tokio::test]
+    async fn test_rotate() {
+        let db = EnterpriseDatabase::memory().await.unwrap();
+        let auth = AuthManager::new("0123456789abcdef0123456789abcdef".to_owned());
+        init(&db, &auth, None).await.unwrap();
+        let user = UserBuilder::new(UserRole::Admin).build();
+        let old = auth.generate_jwt(&user).unwrap();
+        let before = info(&db, &auth).await.unwrap();
+        assert!(before.secondary_kid.is_none() && before.rotated_at.is_none());
+
+        let after = rotate(&db, &auth, 3600, "admin").await.unwrap();
+        assert_ne!(after.primary_kid, before.primary_kid);
+        assert_eq!(after.secondary_kid, Some(before.primary_kid.clone()));
+        let new = auth.generate_jwt(&user).unwrap();
+        assert_eq!(auth.verify_jwt(&old).unwrap().sub, user.id);
+        assert_eq!(auth.verify_jwt(&new).unwrap().sub, user.id);
+
+        // 重启后以数据库中的密钥为准
+        let restarted = AuthManager::new("0123456789abcdef0123456789abcdef".to_owned());
+        init(&db, &restarted, Some("ignored-ignored-ignored-ignored!".to_owned()))
+            .await
+            .unwrap();
+        assert_eq!(restarted.jwt_keys(), auth.jwt_keys());
+        assert!(restarted.verify_jwt(&new).is_ok());
+
+        // 再次轮换后最早的密钥不再有效
+        rotate(&db, &auth, 3600, "admin").await.unwrap();
+        assert!(auth.verify_jwt(&old).is_err());
+        assert!(auth.verify_jwt(&new).is_ok());
+    }
+}
@@ -0,0 +1,513 @@
+// 自助终端(无人值守)机群模块 - 指定设备组为机群模式，组内设备须按较短的签到间隔(如 2 分钟)发送心跳:
+//   - 每次 /api/heartbeat 记为一次签到，超过签到间隔未签到的设备标记为降级(degraded)
+//   - 降级后按 escalation 逐级告警: 降级持续 after_minutes 分钟后通知该级接收人，恢复签到后通知已告警的各级接收人
+//   - /api/kiosk/health 按设备组汇总机群健康状况，供 NOC 大屏轮询
+// 签到只保存在内存中，服务启动后或设备加入机群组后首次检查以当前时间为基准，给一个完整的签到间隔。
+// 处于节假日日历维护窗口的设备组暂停检查，健康汇总中标记为维护中
+use crate::enterprise_database::{AuditLog, EnterpriseDatabase};
+use crate::holiday_calendar;
+use crate::i18n::{self, Lang};
+use crate::notifications;
+use crate::offline_alerts::DevicePresence;
+use crate::validation::Payload;
+use hbb_common::{bail, log, tokio::sync::RwLock, ResultType};
+use schemars::JsonSchema;
+use serde_derive::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    time::SystemTime,
+};
+use validator::Validate;
+
+pub const KIOSK_FLEET_KEY: &str = "kiosk_fleet";
+pub const CHECK_INTERVAL_SECS: u64 = 15;
+const MIN_CHECKIN_INTERVAL_SECS: u32 = 30;
+const MAX_CHECKIN_INTERVAL_SECS: u32 = 3600;
+const MAX_ESCALATION_STEPS: usize = 5;
+const MAX_ESCALATION_MINUTES: u32 = 7 * 24 * 60;
+
+lazy_static::lazy_static! {
+    static ref CONFIG: RwLock<KioskFleetConfig> = Default::default();
+    static ref STATE: RwLock<FleetState> = Default::default();
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, JsonSchema, Validate)]
+pub struct KioskFleetConfig {
+    // 设备组ID -> 机群设置
+    #[serde(default)]
+    pub groups: BTreeMap<String, KioskGroupSettings>,
+}
+
+impl Payload for KioskFleetConfig {}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct KioskGroupSettings {
+    #[serde(default = "default_checkin_interval_secs")]
+    pub checkin_interval_secs: u32,
+    // 按 after_minutes 递增，after_minutes 为 0 的一级在降级时立即通知
+    #[serde(default)]
+    pub escalation: Vec<EscalationStep>,
+}
+
+fn default_checkin_interval_secs() -> u32 {
+    120
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct EscalationStep {
+    pub after_minutes: u32,
+    pub recipients: Vec<String>,
+}
+
+impl KioskFleetConfig {
+    pub fn validate(&self) -> ResultType<()> {
+        for (group_id, settings) in self.groups.iter() {
+            if settings.checkin_interval_secs < MIN_CHECKIN_INTERVAL_SECS
+                || settings.checkin_interval_secs > MAX_CHECKIN_INTERVAL_SECS
+            {
+                bail!(
+                    "{}: checkin_interval_secs must be {}-{}",
+                    group_id,
+                    MIN_CHECKIN_INTERVAL_SECS,
+                    MAX_CHECKIN_INTERVAL_SECS
+                );
+            }
+            if settings.escalation.len() > MAX_ESCALATION_STEPS {
+                bail!("{}: at most {} escalation steps", group_id, MAX_ESCALATION_STEPS);
+            }
+            let mut last = None;
+            for step in settings.escalation.iter() {
+                if step.after_minutes > MAX_ESCALATION_MINUTES {
+                    bail!("{}: after_minutes must be at most {}", group_id, MAX_ESCALATION_MINUTES);
+                }
+                if last.map(|x| step.after_minutes <= x).unwrap_or(false) {
+                    bail!("{}: escalation after_minutes must be increasing", group_id);
+                }
+                last = Some(step.after_minutes);
+                if step.recipients.is_empty() {
+                    bail!("{}: escalation step without recipients", group_id);
+                }
+                for recipient in step.recipients.iter() {
+                    if recipient.parse::<lettre::message::Mailbox>().is_err() {
+                        bail!("{}: invalid recipient {}", group_id, recipient);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // 设备属于多个机群组时使用签到间隔最短的组
+    fn rule_for(&self, group_ids: &[String]) -> Option<(&str, &KioskGroupSettings)> {
+        group_ids
+            .iter()
+            .filter_map(|g| self.groups.get_key_value(g))
+            .min_by_key(|(_, settings)| settings.checkin_interval_secs)
+            .map(|(g, settings)| (g.as_str(), settings))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum KioskStatus {
+    Healthy,
+    Degraded,
+    Maintenance,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AlertKind {
+    Degraded,
+    Escalated,
+    Recovered,
+}
+
+#[derive(Debug, Clone)]
+pub struct Alert {
+    pub kind: AlertKind,
+    pub device_id: String,
+    pub device_name: String,
+    pub last_checkin: u64,
+    pub checkin_interval_secs: u32,
+    // 已通知到的升级级别，从 1 开始；降级时尚未通知任何级别为 0
+    pub level: usize,
+    pub recipients: BTreeSet<String>,
+}
+
+#[derive(Debug, Clone)]
+struct Degraded {
+    since: u64,
+    level: usize,
+}
+
+#[derive(Debug, Default)]
+struct FleetState {
+    // 设备ID -> 最后签到时间
+    checkins: HashMap<String, u64>,
+    degraded: HashMap<String, Degraded>,
+}
+
+fn evaluate(state: &mut FleetState, config: &KioskFleetConfig, devices: &[DevicePresence], now: u64) -> Vec<Alert> {
+    let mut alerts = vec![];
+    let mut seen = BTreeSet::new();
+    for device in devices {
+        let (_, settings) = match config.rule_for(&device.group_ids) {
+            Some(rule) => rule,
+            None => continue,
+        };
+        seen.insert(device.id.clone());
+        let last_checkin = *state.checkins.entry(device.id.clone()).or_insert(now);
+        let deadline = last_checkin + settings.checkin_interval_secs as u64;
+        let alert = |kind, level, recipients| Alert {
+            kind,
+            device_id: device.id.clone(),
+            device_name: device.name.clone(),
+            last_checkin,
+            checkin_interval_secs: settings.checkin_interval_secs,
+            level,
+            recipients,
+        };
+        if now <= deadline {
+            if let Some(degraded) = state.degraded.remove(&device.id) {
+                let recipients = settings.escalation[..degraded.level.min(settings.escalation.len())]
+                    .iter()
+                    .flat_map(|step| step.recipients.iter().cloned())
+                    .collect();
+                alerts.push(alert(AlertKind::Recovered, degraded.level, recipients));
+            }
+            continue;
+        }
+        let degraded = state.degraded.entry(device.id.clone()).or_insert_with(|| {
+            alerts.push(alert(AlertKind::Degraded, 0, BTreeSet::new()));
+            Degraded { since: deadline, level: 0 }
+        });
+        while let Some(step) = settings.escalation.get(degraded.level) {
+            if now < degraded.since + step.after_minutes as u64 * 60 {
+                break;
+            }
+            degraded.level += 1;
+            let recipients = step.recipients.iter().cloned().collect();
+            alerts.push(alert(AlertKind::Escalated, degraded.level, recipients));
+        }
+    }
+    // 移出机群组或已删除的设备，签到记录一并清理
+    state.degraded.retain(|id, _| seen.contains(id));
+    state.checkins.retain(|id, _| seen.contains(id));
+    alerts
+}
+
+async fn dispatch(db: &EnterpriseDatabase, alert: &Alert) {
+    let (action, subject_key) = match alert.kind {
+        AlertKind::Degraded => ("kiosk_degraded", "alert.kiosk.degraded.subject"),
+        AlertKind::Escalated => ("kiosk_escalated", "alert.kiosk.escalated.subject"),
+        AlertKind::Recovered => ("kiosk_recovered", "alert.kiosk.recovered.subject"),
+    };
+    let last_checkin = chrono::DateTime::<chrono::Local>::from(
+        std::time::UNIX_EPOCH + std::time::Duration::from_secs(alert.last_checkin),
+    )
+    .format("%Y-%m-%d %H:%M:%S")
+    .to_string();
+    let render = |lang: Lang| {
+        let subject = i18n::t(
+            lang,
+            subject_key,
+            &[("device", &alert.device_name), ("level", &alert.level)],
+        );
+        let body = format!(
+            "{}\n{}\n{}\n",
+            i18n::t(lang, "alert.device", &[("name", &alert.device_name), ("id", &alert.device_id)]),
+            i18n::t(lang, "alert.kiosk.last_checkin", &[("time", &last_checkin)]),
+            i18n::t(lang, "alert.kiosk.interval", &[("secs", &alert.checkin_interval_secs)]),
+        );
+        (subject, body)
+    };
+    let (subject, body) = render(i18n::default_lang());
+    log::warn!("{}: {} last checked in at {}", action, alert.device_id, last_checkin);
+
+    let audit_log = AuditLog {
+        id: 0,
+        user_id: "system".to_string(),
+        device_id: alert.device_id.clone(),
+        action: action.to_string(),
+        details: Some(body.trim_end().replace('\n', "; ")),
+        ip_address: "127.0.0.1".to_string(),
+        user_agent: None,
+        timestamp: SystemTime::now(),
+        success: true,
+    };
+    let _ = db.log_audit(&audit_log).await;
+
+    if !alert.recipients.is_empty() {
+        let recipients = alert.recipients.iter().cloned().collect();
+        if let Err(e) = crate::email_otp::send_notification(recipients, subject, body).await {
+            log::error!("Failed to send {} for {}: {}", action, alert.device_id, e);
+        }
+    }
+    // 订阅了离线告警的管理员只接收降级和恢复，升级只通知对应级别的接收人
+    if alert.kind != AlertKind::Escalated {
+        notifications::notify(
+            db,
+            notifications::NotificationCategory::DeviceOffline,
+            render,
+            &alert.recipients,
+        )
+        .await;
+    }
+}
+
+// 客户端心跳时调用
+pub async fn check_in(device_id: &str) {
+    STATE
+        .write()
+        .await
+        .checkins
+        .insert(device_id.to_owned(), crate::common::now());
+}
+
+async fn presence(db: &EnterpriseDatabase) -> ResultType<(Vec<DevicePresence>, BTreeSet<String>)> {
+    let devices = db.list_device_presence().await?;
+    let maintenance = holiday_calendar::groups_in_maintenance(chrono::Utc::now()).await;
+    Ok((devices, maintenance))
+}
+
+// 由后台任务每 CHECK_INTERVAL_SECS 秒调用
+pub async fn check(db: &EnterpriseDatabase) {
+    let config = get().await;
+    if config.groups.is_empty() {
+        return;
+    }
+    let (mut devices, maintenance) = match presence(db).await {
+        Ok(res) => res,
+        Err(e) => {
+            log::error!("Failed to load devices for kiosk check: {}", e);
+            return;
+        }
+    };
+    // 维护窗口内的设备按移出机群处理，窗口结束后重新给一个完整的签到间隔
+    devices.retain(|d| !d.group_ids.iter().any(|g| maintenance.contains(g)));
+    let alerts = evaluate(&mut *STATE.write().await, &config, &devices, crate::common::now());
+    for alert in alerts.iter() {
+        dispatch(db, alert).await;
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct KioskHealth {
+    pub device_id: String,
+    pub name: String,
+    pub group_id: String,
+    pub status: KioskStatus,
+    // 尚未检查过的设备为空
+    pub last_checkin: Option<u64>,
+    pub degraded_since: Option<u64>,
+    pub escalation_level: usize,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct GroupHealth {
+    pub group_id: String,
+    pub total: usize,
+    pub healthy: usize,
+    pub degraded: usize,
+    pub maintenance: usize,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct FleetHealth {
+    pub generated_at: u64,
+    pub total: usize,
+    pub healthy: usize,
+    pub degraded: usize,
+    // 已升级告警(通知过至少一级接收人)的降级设备数
+    pub escalated: usize,
+    pub maintenance: usize,
+    pub groups: Vec<GroupHealth>,
+    // 降级的设备在前，按降级时间排序，其余按名称排序
+    pub devices: Vec<KioskHealth>,
+}
+
+fn summarize(
+    state: &FleetState,
+    config: &KioskFleetConfig,
+    devices: &[DevicePresence],
+    maintenance: &BTreeSet<String>,
+    now: u64,
+) -> FleetHealth {
+    let mut health = FleetHealth {
+        generated_at: now,
+        ..Default::default()
+    };
+    let mut groups: BTreeMap<&str, GroupHealth> = BTreeMap::new();
+    for device in devices {
+        let (group_id, _) = match config.rule_for(&device.group_ids) {
+            Some(rule) => rule,
+            None => continue,
+        };
+        let degraded = state.degraded.get(&device.id);
+        let status = if device.group_ids.iter().any(|g| maintenance.contains(g)) {
+            KioskStatus::Maintenance
+        } else if degraded.is_some() {
+            KioskStatus::Degraded
+        } else {
+            KioskStatus::Healthy
+        };
+        let group = groups.entry(group_id).or_insert_with(|| GroupHealth {
+            group_id: group_id.to_owned(),
+            ..Default::default()
+        });
+        group.total += 1;
+        health.total += 1;
+        match status {
+            KioskStatus::Healthy => {
+                group.healthy += 1;
+                health.healthy += 1;
+            }
+            KioskStatus::Degraded => {
+                group.degraded += 1;
+                health.degraded += 1;
+                if degraded.map(|x| x.level > 0).unwrap_or(false) {
+                    health.escalated += 1;
+                }
+            }
+            KioskStatus::Maintenance => {
+                group.maintenance += 1;
+                health.maintenance += 1;
+            }
+        }
+        health.devices.push(KioskHealth {
+            device_id: device.id.clone(),
+            name: device.name.clone(),
+            group_id: group_id.to_owned(),
+            status,
+            last_checkin: state.checkins.get(&device.id).cloned(),
+            degraded_since: degraded.map(|x| x.since),
+            escalation_level: degraded.map(|x| x.level).unwrap_or_default(),
+        });
+    }
+    health.groups = groups.into_values().collect();
+    health.devices.sort_by(|a, b| {
+        (a.degraded_since.is_none(), a.degraded_since, &a.name, &a.device_id).cmp(&(
+            b.degraded_since.is_none(),
+            b.degraded_since,
+            &b.name,
+            &b.device_id,
+        ))
+    });
+    health
+}
+
+pub async fn health(db: &EnterpriseDatabase) -> ResultType<FleetHealth> {
+    let config = get().await;
+    let (devices, maintenance) = presence(db).await?;
+    let state = STATE.read().await;
+    Ok(summarize(&state, &config, &devices, &maintenance, crate::common::now()))
+}
+
+pub async fn reload(db: &EnterpriseDatabase) -> ResultType<()> {
+    let config = match db.get_setting(KIOSK_FLEET_KEY).await? {
+        Some(v) => serde_json::from_str(&v)?,
+        None => KioskFleetConfig::default(),
+    };
+    *CONFIG.write().await = config;
+    Ok(())
+}
+
+pub async fn get() -> KioskFleetConfig {
+    CONFIG.read().await.clone()
+}
+
+pub async fn update(db: &EnterpriseDatabase, config: KioskFleetConfig, updated_by: &str) -> ResultType<()> {
+    config.validate()?;
+    db.set_setting(KIOSK_FLEET_KEY, &serde_json::to_string(&config)?, Some(updated_by))
+        .await?;
+    *CONFIG.write().await = config;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> KioskFleetConfig {
+        let mut config = KioskFleetConfig::default();
+        config.groups.insert(
+            "lobby".to_owned(),
+            KioskGroupSettings {
+                checkin_interval_secs: 120,
+                escalation: vec![
+                    EscalationStep {
+                        after_minutes: 0,
+                        recipients: vec!["noc@example.com".to_owned()],
+                    },
+                    EscalationStep {
+                        after_minutes: 10,
+                        recipients: vec!["oncall@example.com".to_owned()],
+                    },
+                ],
+            },
+        );
+        config
+    }
+
+    fn kiosk(group: &str) -> DevicePresence {
+        DevicePresence {
+            id: "123".to_owned(),
+            name: "kiosk-1".to_owned(),
+            last_online: 0,
+            group_ids: vec![group.to_owned()],
+        }
+    }
+
+    #[test]
+    fn test_kiosk_validate() {
+        assert!(config().validate().is_ok());
+        let mut bad = config();
+        bad.groups.get_mut("lobby").unwrap().checkin_interval_secs = 10;
+        assert!(bad.validate().is_err());
+        let mut bad = config();
+        bad.groups.get_mut("lobby").unwrap().escalation[1].after_minutes = 0;
+        assert!(bad.validate().is_err());
+        let mut bad = config();
+        bad.groups.get_mut("lobby").unwrap().escalation[0].recipients = vec!["noc".to_owned()];
+        assert!(bad.validate().is_err());
+    }
+
+    #[test]
+    fn test_kiosk_escalation() {
+        let config = config();
+        let devices = [kiosk("lobby")];
+        let mut state = FleetState::default();
+        // 首次检查以当前时间为基准
+        assert!(evaluate(&mut state, &config, &devices, 1000).is_empty());
+        assert!(evaluate(&mut state, &config, &devices, 1120).is_empty());
+
+        let alerts = evaluate(&mut state, &config, &devices, 1121);
+        let kinds: Vec<_> = alerts.iter().map(|x| (x.kind, x.level)).collect();
+        assert_eq!(kinds, vec![(AlertKind::Degraded, 0), (AlertKind::Escalated, 1)]);
+        assert!(alerts[1].recipients.contains("noc@example.com"));
+        assert!(evaluate(&mut state, &config, &devices, 1500).is_empty());
+        let alerts = evaluate(&mut state, &config, &devices, 1120 + 600);
+        assert_eq!(alerts.len(), 1);
+        assert_eq!((alerts[0].kind, alerts[0].level), (AlertKind::Escalated, 2));
+
+        let health = summarize(&state, &config, &devices, &BTreeSet::new(), 1800);
+        assert_eq!((health.total, health.degraded, health.escalated), (1, 1, 1));
+        assert_eq!(health.devices[0].degraded_since, Some(1120));
+
+        // 恢复签到后通知已告警的各级接收人
+        state.checkins.insert("123".to_owned(), 1790);
+        let alerts = evaluate(&mut state, &config, &devices, 1800);
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(alerts[0].kind, AlertKind::Recovered);
+        assert_eq!(alerts[0].recipients.len(), 2);
+        let health = summarize(&state, &config, &devices, &BTreeSet::new(), 1800);
+        assert_eq!((health.healthy, health.degraded), (1, 0));
+        let maintenance = ["lobby".to_owned()].into_iter().collect();
+        assert_eq!(summarize(&state, &config, &devices, &maintenance, 1800).maintenance, 1);
+
+        // 不在机群组中的设备不检查
+        assert!(evaluate(&mut FleetState::default(), &config, &[kiosk("office")], 10000).is_empty());
+        assert_eq!(summarize(&state, &config, &[kiosk("office")], &BTreeSet::new(), 0).total, 0);
+    }
+}
@@ -0,0 +1,167 @@
+// 局域网配置模块 - 支持多站点掩码/内网IP，可通过设置接口热加载，替代启动参数 --mask/--local-ip
+use crate::enterprise_database::EnterpriseDatabase;
+use crate::validation::Payload;
+use hbb_common::{bail, log, tokio::sync::RwLock, ResultType};
+use ipnetwork::Ipv4Network;
+use schemars::JsonSchema;
+use serde_derive::{Deserialize, Serialize};
+use std::net::SocketAddr;
+use validator::Validate;
+
+pub const LAN_CONFIG_KEY: &str = "lan_config";
+
+lazy_static::lazy_static! {
+    static ref LAN_SITES: RwLock<Vec<LanSite>> = Default::default();
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema, Validate)]
+pub struct LanConfig {
+    #[serde(default)]
+    pub sites: Vec<LanSiteConfig>,
+}
+
+impl Payload for LanConfig {}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct LanSiteConfig {
+    pub name: String,
+    pub mask: String,     // 例如 192.168.1.0/24
+    pub local_ip: String, // 该站点内客户端可访问的服务器地址，用作局域网中继
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LanSite {
+    pub name: String,
+    pub network: Ipv4Network,
+    pub local_ip: String,
+}
+
+impl LanConfig {
+    // 解析并校验所有站点配置
+    pub fn parse(&self) -> ResultType<Vec<LanSite>> {
+        let mut sites = Vec::new();
+        for site in self.sites.iter() {
+            if site.name.is_empty() {
+                bail!("site name is empty");
+            }
+            if sites.iter().any(|x: &LanSite| x.name == site.name) {
+                bail!("duplicate site name: {}", site.name);
+            }
+            let network: Ipv4Network = match site.mask.parse() {
+                Ok(network) => network,
+                Err(err) => bail!("invalid mask {}: {}", site.mask, err),
+            };
+            sites.push(LanSite {
+                name: site.name.clone(),
+                network,
+                local_ip: site.local_ip.clone(),
+            });
+        }
+        Ok(sites)
+    }
+}
+
+// 启动时加载：优先使用数据库中的配置，没有则沿用命令行 --mask/--local-ip
+pub async fn init(db: &EnterpriseDatabase, mask: Option<Ipv4Network>, local_ip: String) {
+    match db.get_setting(LAN_CONFIG_KEY).await {
+        Ok(Some(_)) => {
+            if let Err(err) = reload(db).await {
+                log::error!("Failed to load lan config: {}", err);
+            }
+        }
+        Ok(None) => {
+            if let Some(network) = mask {
+                *LAN_SITES.write().await = vec![LanSite {
+                    name: "default".to_owned(),
+                    network,
+                    local_ip,
+                }];
+            }
+        }
+        Err(err) => log::error!("Failed to read lan config: {}", err),
+    }
+    for site in LAN_SITES.read().await.iter() {
+        log::info!("lan site {}: mask {}, local-ip {}", site.name, site.network, site.local_ip);
+    }
+}
+
+pub async fn reload(db: &EnterpriseDatabase) -> ResultType<()> {
+    let config: LanConfig = match db.get_setting(LAN_CONFIG_KEY).await? {
+        Some(v) => serde_json::from_str(&v)?,
+        None => LanConfig::default(),
+    };
+    *LAN_SITES.write().await = config.parse()?;
+    Ok(())
+}
+
+pub async fn get() -> LanConfig {
+    LanConfig {
+        sites: LAN_SITES
+            .read()
+            .await
+            .iter()
+            .map(|x| LanSiteConfig {
+                name: x.name.clone(),
+                mask: x.network.to_string(),
+                local_ip: x.local_ip.clone(),
+            })
+            .collect(),
+    }
+}
+
+pub async fn update(db: &EnterpriseDatabase, config: LanConfig, updated_by: &str) -> ResultType<()> {
+    let sites = config.parse()?;
+    db.set_setting(LAN_CONFIG_KEY, &serde_json::to_string(&config)?, Some(updated_by))
+        .await?;
+    *LAN_SITES.write().await = sites;
+    Ok(())
+}
+
+// 返回地址所属的局域网站点
+pub async fn site_of(addr: SocketAddr) -> Option<LanSite> {
+    let ip = match addr {
+        SocketAddr::V4(v4) => *v4.ip(),
+        SocketAddr::V6(v6) => v6.ip().to_ipv4()?,
+    };
+    LAN_SITES
+        .read()
+        .await
+        .iter()
+        .find(|x| x.network.contains(ip))
+        .cloned()
+}
+
+pub async fn is_local_ip(ip: &str) -> bool {
+    LAN_SITES.read().await.iter().any(|x| x.local_ip == ip)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_sites() {
+        let mut config = LanConfig {
+            sites: vec![
+                LanSiteConfig {
+                    name: "beijing".to_owned(),
+                    mask: "192.168.1.0/24".to_owned(),
+                    local_ip: "192.168.1.2".to_owned(),
+                },
+                LanSiteConfig {
+                    name: "shanghai".to_owned(),
+                    mask: "10.0.0.0/8".to_owned(),
+                    local_ip: "10.0.0.2".to_owned(),
+                },
+            ],
+        };
+        let sites = config.parse().unwrap();
+        assert_eq!(sites.len(), 2);
+        assert!(sites[1].network.contains("10.1.2.3".parse().unwrap()));
+        config.sites[1].name = "beijing".to_owned();
+        assert!(config.parse().is_err());
+        config.sites[1].name = "shanghai".to_owned();
+        config.sites[1].mask = "10.0.0.0/33".to_owned();
+        assert!(config.parse().is_err());
+    }
+}
@@ -0,0 +1,327 @@
+// 许可证模块 - 企业版许可证是供应商私钥签名的 JSON(sodiumoxide sign 组合格式，base64 编码)，包含客户、
+// 设备/用户数上限、到期时间和启用的功能。启动时从数据库(通过 /api/license 上传)或 LICENSE-FILE 加载并校验:
+//   valid       在有效期内，按许可证上限限制新增设备和用户
+//   grace       已到期但在宽限期(grace_days)内，仍按许可证上限运行并告警
+//   expired / unlicensed / invalid  按评估额度限制新增设备和用户，已有的设备和用户不受影响
+//   unmanaged   构建时未通过 LICENSE_PUBLIC_KEY 环境变量嵌入验证公钥(自行编译的版本)，不做限制
+// 与客户端连接使用的 licence_key(server_key 模块)无关
+use crate::common::{get_arg, now};
+use crate::enterprise_database::EnterpriseDatabase;
+use hbb_common::{bail, log, tokio::sync::RwLock, ResultType};
+use schemars::JsonSchema;
+use serde_derive::{Deserialize, Serialize};
+use sodiumoxide::crypto::sign;
+
+pub const LICENSE_KEY: &str = "license";
+// 未授权或许可证失效时的评估额度
+const EVALUATION_MAX_DEVICES: u64 = 10;
+const EVALUATION_MAX_USERS: u64 = 3;
+const DAY_SECS: u64 = 24 * 3600;
+
+lazy_static::lazy_static! {
+    static ref CURRENT: RwLock<Installed> = Default::default();
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct License {
+    pub id: String,
+    pub customer: String,
+    pub issued_at: u64,
+    pub expires_at: u64,
+    // 为空表示不限
+    #[serde(default)]
+    pub max_devices: Option<u64>,
+    #[serde(default)]
+    pub max_users: Option<u64>,
+    #[serde(default)]
+    pub features: Vec<String>,
+    #[serde(default = "default_grace_days")]
+    pub grace_days: u64,
+}
+
+fn default_grace_days() -> u64 {
+    14
+}
+
+impl License {
+    pub fn grace_ends_at(&self) -> u64 {
+        self.expires_at + self.grace_days * DAY_SECS
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum LicenseState {
+    Unmanaged,
+    Unlicensed,
+    Invalid,
+    Valid,
+    Grace,
+    Expired,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct LicenseStatus {
+    pub state: LicenseState,
+    pub license: Option<License>,
+    // 当前生效的上限，为空表示不限
+    pub max_devices: Option<u64>,
+    pub max_users: Option<u64>,
+    pub devices: u64,
+    pub users: u64,
+    pub grace_ends_at: Option<u64>,
+    // 许可证无法校验时的原因
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+struct Installed {
+    managed: bool,
+    license: Option<License>,
+    error: Option<String>,
+}
+
+impl Default for Installed {
+    fn default() -> Self {
+        Self {
+            managed: public_key().is_some(),
+            license: None,
+            error: None,
+        }
+    }
+}
+
+impl Installed {
+    fn state(&self, now: u64) -> LicenseState {
+        match &self.license {
+            _ if !self.managed => LicenseState::Unmanaged,
+            Some(license) if now < license.expires_at => LicenseState::Valid,
+            Some(license) if now < license.grace_ends_at() => LicenseState::Grace,
+            Some(_) => LicenseState::Expired,
+            None if self.error.is_some() => LicenseState::Invalid,
+            None => LicenseState::Unlicensed,
+        }
+    }
+
+    // (设备上限, 用户上限)
+    fn limits(&self, now: u64) -> (Option<u64>, Option<u64>) {
+        match (self.state(now), &self.license) {
+            (LicenseState::Unmanaged, _) => (None, None),
+            (LicenseState::Valid | LicenseState::Grace, Some(license)) => (license.max_devices, license.max_users),
+            _ => (Some(EVALUATION_MAX_DEVICES), Some(EVALUATION_MAX_USERS)),
+        }
+    }
+}
+
+fn public_key() -> Option<sign::PublicKey> {
+    let key = option_env!("LICENSE_PUBLIC_KEY")?;
+    base64::decode(key.trim())
+        .ok()
+        .and_then(|x| sign::PublicKey::from_slice(&x))
+}
+
+pub fn verify(blob: &str, pk: &sign::PublicKey) -> ResultType<License> {
+    let blob: String = blob.split_whitespace().collect();
+    let signed = match base64::decode(blob) {
+        Ok(signed) => signed,
+        Err(_) => bail!("license is not valid base64"),
+    };
+    let payload = match sign::verify(&signed, pk) {
+        Ok(payload) => payload,
+        Err(_) => bail!("license signature is invalid"),
+    };
+    let license: License = serde_json::from_slice(&payload)?;
+    if license.expires_at <= license.issued_at {
+        bail!("license expires before it is issued");
+    }
+    Ok(license)
+}
+
+// 启动时加载许可证，上传的许可证优先于 LICENSE-FILE
+pub async fn reload(db: &EnterpriseDatabase) -> ResultType<()> {
+    let pk = match public_key() {
+        Some(pk) => pk,
+        None => {
+            *CURRENT.write().await = Installed::default();
+            return Ok(());
+        }
+    };
+    let blob = match db.get_setting(LICENSE_KEY).await? {
+        Some(blob) => Some(blob),
+        None => {
+            let path = get_arg("license-file");
+            if path.is_empty() {
+                None
+            } else {
+                Some(std::fs::read_to_string(&path)?)
+            }
+        }
+    };
+    let mut installed = Installed::default();
+    match blob.map(|x| verify(&x, &pk)) {
+        Some(Ok(license)) => installed.license = Some(license),
+        Some(Err(e)) => installed.error = Some(e.to_string()),
+        None => {}
+    }
+    let now = now();
+    match (installed.state(now), &installed.license, &installed.error) {
+        (LicenseState::Valid, Some(license), _) => {
+            log::info!(
+                "License {} for {} valid until {}",
+                license.id,
+                license.customer,
+                license.expires_at
+            )
+        }
+        (LicenseState::Grace, Some(license), _) => log::warn!(
+            "License {} expired, grace period ends at {}",
+            license.id,
+            license.grace_ends_at()
+        ),
+        (LicenseState::Invalid, _, Some(e)) => log::error!("Invalid license: {}", e),
+        (state, _, _) => log::warn!("License {:?}, evaluation limits apply", state),
+    }
+    *CURRENT.write().await = installed;
+    Ok(())
+}
+
+pub async fn install(db: &EnterpriseDatabase, blob: &str, updated_by: &str) -> ResultType<License> {
+    let pk = match public_key() {
+        Some(pk) => pk,
+        None => bail!("licensing is not enabled in this build"),
+    };
+    let license = verify(blob, &pk)?;
+    if now() >= license.grace_ends_at() {
+        bail!("license has expired");
+    }
+    db.set_setting(LICENSE_KEY, blob.trim(), Some(updated_by)).await?;
+    *CURRENT.write().await = Installed {
+        managed: true,
+        license: Some(license.clone()),
+        error: None,
+    };
+    log::info!(
+        "License {} for {} installed by {}",
+        license.id,
+        license.customer,
+        updated_by
+    );
+    Ok(license)
+}
+
+pub async fn status(db: &EnterpriseDatabase) -> ResultType<LicenseStatus> {
+    let installed = CURRENT.read().await.clone();
+    let now = now();
+    let (max_devices, max_users) = installed.limits(now);
+    Ok(LicenseStatus {
+        state: installed.state(now),
+        grace_ends_at: installed.license.as_ref().map(|x| x.grace_ends_at()),
+        license: installed.license,
+        max_devices,
+        max_users,
+        devices: db.count_devices().await?,
+        users: db.count_users().await?,
+        error: installed.error,
+    })
+}
+
+// 新增用户前检查用户数上限
+pub async fn check_user_seat(db: &EnterpriseDatabase) -> ResultType<()> {
+    let max_users = CURRENT.read().await.limits(now()).1;
+    if let Some(max_users) = max_users {
+        if db.count_users().await? >= max_users {
+            bail!("license allows at most {} users", max_users);
+        }
+    }
+    Ok(())
+}
+
+// 设备注册时检查设备数上限，已登记的设备不受影响；数据库异常时放行，避免影响已有设备上线
+pub async fn admits_device(db: &EnterpriseDatabase, id: &str) -> bool {
+    let max_devices = match CURRENT.read().await.limits(now()).0 {
+        Some(max_devices) => max_devices,
+        None => return true,
+    };
+    let admitted = match db.device_exists(id).await {
+        Ok(true) => return true,
+        Ok(false) => db.count_devices().await.map(|count| count < max_devices),
+        Err(e) => Err(e),
+    };
+    match admitted {
+        Ok(true) => true,
+        Ok(false) => {
+            log::warn!(
+                "Registration of {} rejected: license allows at most {} devices",
+                id,
+                max_devices
+            );
+            false
+        }
+        Err(e) => {
+            log::error!("Failed to check license device limit for {}: {}", id, e);
+            true
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn license(expires_at: u64) -> License {
+        License {
+            id: "lic-1".to_owned(),
+            customer: "Example Corp".to_owned(),
+            issued_at: 1000,
+            expires_at,
+            max_devices: Some(500),
+            max_users: None,
+            features: vec!["sso".to_owned()],
+            grace_days: 1,
+        }
+    }
+
+    #[test]
+    fn test_verify() {
+        let (pk, sk) = sign::gen_keypair();
+        let payload = serde_json::to_vec(&license(2000)).unwrap();
+        let blob = base64::encode(sign::sign(&payload, &sk));
+        assert_eq!(verify(&blob, &pk).unwrap(), license(2000));
+        // 许可证文件中的换行不影响校验
+        let wrapped = format!("{}\n{}\n", &blob[..20], &blob[20..]);
+        assert!(verify(&wrapped, &pk).is_ok());
+        let (other_pk, _) = sign::gen_keypair();
+        assert!(verify(&blob, &other_pk).is_err());
+        let mut signed = sign::sign(&payload, &sk);
+        let last = signed.len() - 1;
+        signed[last] ^= 1;
+        assert!(verify(&base64::encode(signed), &pk).is_err());
+        let payload = serde_json::to_vec(&license(1000)).unwrap();
+        assert!(verify(&base64::encode(sign::sign(&payload, &sk)), &pk).is_err());
+    }
+
+    #[test]
+    fn test_state() {
+        let mut installed = Installed {
+            managed: true,
+            license: Some(license(2000)),
+            error: None,
+        };
+        assert_eq!(installed.state(1999), LicenseState::Valid);
+        assert_eq!(installed.limits(1999), (Some(500), None));
+        assert_eq!(installed.state(2000), LicenseState::Grace);
+        assert_eq!(installed.limits(2000 + DAY_SECS - 1), (Some(500), None));
+        assert_eq!(installed.state(2000 + DAY_SECS), LicenseState::Expired);
+        assert_eq!(
+            installed.limits(2000 + DAY_SECS),
+            (Some(EVALUATION_MAX_DEVICES), Some(EVALUATION_MAX_USERS))
+        );
+        installed.license = None;
+        assert_eq!(installed.state(0), LicenseState::Unlicensed);
+        installed.error = Some("license signature is invalid".to_owned());
+        assert_eq!(installed.state(0), LicenseState::Invalid);
+        installed.managed = false;
+        assert_eq!(installed.state(0), LicenseState::Unmanaged);
+        assert_eq!(installed.limits(0), (None, None));
+    }
+}
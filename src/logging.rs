@@ -0,0 +1,126 @@
+// 日志配置模块 - 通过 /api/settings/logging 在运行时切换 JSON 日志输出并按模块调整日志级别，
+// 保存在系统设置中，重启后继续生效；未配置时沿用启动时的 RUST_LOG / LOG_FORMAT
+use crate::common::{is_json_log, set_json_log, set_log_spec};
+use crate::enterprise_database::EnterpriseDatabase;
+use crate::validation::Payload;
+use hbb_common::{bail, log, tokio::sync::RwLock, ResultType};
+use schemars::JsonSchema;
+use serde_derive::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use validator::Validate;
+
+pub const LOGGING_KEY: &str = "logging";
+const LEVELS: [&str; 6] = ["off", "error", "warn", "info", "debug", "trace"];
+
+lazy_static::lazy_static! {
+    static ref CONFIG: RwLock<LoggingConfig> = Default::default();
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, Validate)]
+pub struct LoggingConfig {
+    #[serde(default)]
+    pub json: bool,
+    #[serde(default = "default_level")]
+    #[validate(length(min = 1, max = 16))]
+    pub level: String,
+    // 模块路径 -> 级别，如 "hbbs::web_api" -> "debug"
+    #[serde(default)]
+    pub modules: BTreeMap<String, String>,
+}
+
+impl Payload for LoggingConfig {}
+
+fn default_level() -> String {
+    "info".to_owned()
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        Self {
+            json: false,
+            level: default_level(),
+            modules: BTreeMap::new(),
+        }
+    }
+}
+
+impl LoggingConfig {
+    pub fn validate(&self) -> ResultType<()> {
+        for level in std::iter::once(&self.level).chain(self.modules.values()) {
+            if !LEVELS.contains(&level.as_str()) {
+                bail!("invalid log level: {}", level);
+            }
+        }
+        for module in self.modules.keys() {
+            if module.is_empty() || !module.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == ':') {
+                bail!("invalid module: {}", module);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn spec(&self) -> String {
+        std::iter::once(self.level.clone())
+            .chain(self.modules.iter().map(|(m, l)| format!("{}={}", m, l)))
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+
+    fn apply(&self) -> ResultType<()> {
+        set_log_spec(&self.spec())?;
+        set_json_log(self.json);
+        Ok(())
+    }
+}
+
+pub async fn reload(db: &EnterpriseDatabase) -> ResultType<()> {
+    let config = match db.get_setting(LOGGING_KEY).await? {
+        Some(v) => {
+            let config: LoggingConfig = serde_json::from_str(&v)?;
+            config.validate()?;
+            config.apply()?;
+            log::info!("logging config loaded: {}, json={}", config.spec(), config.json);
+            config
+        }
+        None => LoggingConfig {
+            json: is_json_log(),
+            ..Default::default()
+        },
+    };
+    *CONFIG.write().await = config;
+    Ok(())
+}
+
+pub async fn get() -> LoggingConfig {
+    CONFIG.read().await.clone()
+}
+
+pub async fn update(db: &EnterpriseDatabase, config: LoggingConfig, updated_by: &str) -> ResultType<()> {
+    config.validate()?;
+    db.set_setting(LOGGING_KEY, &serde_json::to_string(&config)?, Some(updated_by))
+        .await?;
+    config.apply()?;
+    *CONFIG.write().await = config;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_logging_spec() {
+        let mut config = LoggingConfig::default();
+        assert_eq!(config.spec(), "info");
+        config.modules.insert("hbbs::web_api".to_owned(), "debug".to_owned());
+        config.modules.insert("sqlx".to_owned(), "warn".to_owned());
+        assert!(config.validate().is_ok());
+        assert_eq!(config.spec(), "info,hbbs::web_api=debug,sqlx=warn");
+
+        config.level = "verbose".to_owned();
+        assert!(config.validate().is_err());
+        config.level = "info".to_owned();
+        config.modules.insert("bad module".to_owned(), "info".to_owned());
+        assert!(config.validate().is_err());
+    }
+}
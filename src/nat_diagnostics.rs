@@ -0,0 +1,269 @@
+// NAT穿透诊断模块 - 统计打洞尝试、直连/中继回退/失败次数，按设备和NAT类型汇总
+use hbb_common::{rendezvous_proto::NatType, tokio::sync::RwLock};
+use schemars::JsonSchema;
+use serde_derive::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, VecDeque},
+    net::SocketAddr,
+    time::SystemTime,
+};
+
+// 每个设备保留的最近事件数量
+const MAX_EVENTS_PER_PEER: usize = 50;
+// 最多跟踪的设备数量，超出时淘汰最久没有事件的设备
+const MAX_TRACKED_PEERS: usize = 10_000;
+
+lazy_static::lazy_static! {
+    static ref NAT_STATS: RwLock<NatStats> = Default::default();
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, JsonSchema)]
+pub enum PunchOutcome {
+    Attempt,       // 控制端发起打洞请求
+    Responded,     // 被控端已响应打洞，直连建立中
+    ForcedRelay,   // 服务器策略强制中继
+    RelayFallback, // 打洞失败后客户端请求中继
+    Failed,        // 离线、ID不存在、密钥不匹配等
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct PunchCounters {
+    pub attempts: u64,
+    pub responded: u64,
+    pub forced_relay: u64,
+    pub relay_fallback: u64,
+    pub failed: u64,
+}
+
+impl PunchCounters {
+    fn record(&mut self, outcome: PunchOutcome) {
+        match outcome {
+            PunchOutcome::Attempt => self.attempts += 1,
+            PunchOutcome::Responded => self.responded += 1,
+            PunchOutcome::ForcedRelay => self.forced_relay += 1,
+            PunchOutcome::RelayFallback => self.relay_fallback += 1,
+            PunchOutcome::Failed => self.failed += 1,
+        }
+    }
+
+    // 直连成功率（响应数 / 尝试数）
+    pub fn direct_rate(&self) -> f64 {
+        if self.attempts == 0 {
+            0.0
+        } else {
+            self.responded.saturating_sub(self.relay_fallback) as f64 / self.attempts as f64
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct PunchEvent {
+    pub timestamp: SystemTime,
+    pub outcome: PunchOutcome,
+    pub nat_type: String,
+    pub peer_addr: String,
+    pub reason: Option<String>,
+}
+
+#[derive(Debug, Default)]
+struct NatStats {
+    total: PunchCounters,
+    by_nat_type: HashMap<String, PunchCounters>,
+    by_family: HashMap<String, PunchCounters>,
+    by_peer: HashMap<String, (PunchCounters, VecDeque<PunchEvent>)>,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct NatStatsSummary {
+    pub total: PunchCounters,
+    pub direct_rate: f64,
+    pub by_nat_type: HashMap<String, PunchCounters>,
+    pub by_family: HashMap<String, PunchCounters>, // ipv4 / ipv6 分别统计
+    pub family_direct_rate: HashMap<String, f64>,
+    pub tracked_peers: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct PeerNatDiagnostics {
+    pub device_id: String,
+    pub counters: PunchCounters,
+    pub direct_rate: f64,
+    pub recent_events: Vec<PunchEvent>,
+    pub hints: Vec<String>,
+}
+
+pub fn nat_type_label(nat_type: NatType) -> &'static str {
+    match nat_type {
+        NatType::UNKNOWN_NAT => "unknown",
+        NatType::ASYMMETRIC => "asymmetric",
+        NatType::SYMMETRIC => "symmetric",
+    }
+}
+
+// 根据地址判断地址族，IPv4映射的IPv6地址按IPv4统计
+fn address_family(peer_addr: &str) -> &'static str {
+    match peer_addr.parse::<SocketAddr>() {
+        Ok(SocketAddr::V6(v6)) if v6.ip().to_ipv4_mapped().is_none() => "ipv6",
+        Ok(_) => "ipv4",
+        Err(_) => "unknown",
+    }
+}
+
+// 记录一次打洞相关事件；device_id 仅在设备已注册时传入，
+// 未注册或未校验的客户端ID只计入汇总统计，避免伪造ID撑大按设备的统计表
+pub async fn record(
+    device_id: Option<&str>,
+    outcome: PunchOutcome,
+    nat_type: &str,
+    peer_addr: &str,
+    reason: Option<&str>,
+) {
+    let mut stats = NAT_STATS.write().await;
+    stats.total.record(outcome);
+    stats
+        .by_nat_type
+        .entry(nat_type.to_owned())
+        .or_default()
+        .record(outcome);
+    stats
+        .by_family
+        .entry(address_family(peer_addr).to_owned())
+        .or_default()
+        .record(outcome);
+    let Some(device_id) = device_id else {
+        return;
+    };
+    if !stats.by_peer.contains_key(device_id) && stats.by_peer.len() >= MAX_TRACKED_PEERS {
+        stats.evict_least_recent();
+    }
+    let (counters, events) = stats.by_peer.entry(device_id.to_owned()).or_default();
+    counters.record(outcome);
+    events.push_back(PunchEvent {
+        timestamp: SystemTime::now(),
+        outcome,
+        nat_type: nat_type.to_owned(),
+        peer_addr: peer_addr.to_owned(),
+        reason: reason.map(|x| x.to_owned()),
+    });
+    if events.len() > MAX_EVENTS_PER_PEER {
+        events.pop_front();
+    }
+}
+
+impl NatStats {
+    // 淘汰最近一次事件最早的设备
+    fn evict_least_recent(&mut self) {
+        let oldest = self
+            .by_peer
+            .iter()
+            .min_by_key(|(_, (_, events))| events.back().map(|e| e.timestamp))
+            .map(|(id, _)| id.clone());
+        if let Some(id) = oldest {
+            self.by_peer.remove(&id);
+        }
+    }
+}
+
+pub async fn summary() -> NatStatsSummary {
+    let stats = NAT_STATS.read().await;
+    NatStatsSummary {
+        total: stats.total.clone(),
+        direct_rate: stats.total.direct_rate(),
+        by_nat_type: stats.by_nat_type.clone(),
+        by_family: stats.by_family.clone(),
+        family_direct_rate: stats
+            .by_family
+            .iter()
+            .map(|(k, v)| (k.clone(), v.direct_rate()))
+            .collect(),
+        tracked_peers: stats.by_peer.len(),
+    }
+}
+
+pub async fn peer_diagnostics(device_id: &str) -> Option<PeerNatDiagnostics> {
+    let stats = NAT_STATS.read().await;
+    let (counters, events) = stats.by_peer.get(device_id)?;
+    Some(PeerNatDiagnostics {
+        device_id: device_id.to_owned(),
+        counters: counters.clone(),
+        direct_rate: counters.direct_rate(),
+        recent_events: events.iter().cloned().collect(),
+        hints: explain(counters, events),
+    })
+}
+
+// 根据统计结果给出中继回退原因的解释
+fn explain(counters: &PunchCounters, events: &VecDeque<PunchEvent>) -> Vec<String> {
+    let mut hints = Vec::new();
+    if counters.forced_relay > 0 {
+        hints.push("服务器策略强制使用中继（ALWAYS_USE_RELAY 或局域网/公网混合）".to_owned());
+    }
+    if events.iter().any(|e| e.nat_type == "symmetric") {
+        hints.push("检测到对称型NAT，UDP打洞通常无法成功，将回退到中继".to_owned());
+    }
+    if counters.attempts > 0 && counters.relay_fallback * 2 > counters.attempts {
+        hints.push("超过一半的连接在打洞后回退到中继，请检查防火墙是否拦截UDP".to_owned());
+    }
+    if counters.failed > 0 {
+        if let Some(reason) = events.iter().rev().find_map(|e| e.reason.clone()) {
+            hints.push(format!("最近一次失败原因: {}", reason));
+        }
+    }
+    hints
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_direct_rate() {
+        let mut counters = PunchCounters::default();
+        assert_eq!(counters.direct_rate(), 0.0);
+        for _ in 0..4 {
+            counters.record(PunchOutcome::Attempt);
+        }
+        counters.record(PunchOutcome::Responded);
+        counters.record(PunchOutcome::Responded);
+        counters.record(PunchOutcome::RelayFallback);
+        assert_eq!(counters.direct_rate(), 0.25);
+    }
+
+    #[test]
+    fn test_address_family() {
+        assert_eq!(address_family("1.2.3.4:21116"), "ipv4");
+        assert_eq!(address_family("[::ffff:1.2.3.4]:21116"), "ipv4");
+        assert_eq!(address_family("[2001:db8::1]:21116"), "ipv6");
+        assert_eq!(address_family("bad"), "unknown");
+    }
+
+    #[test]
+    fn test_evict_least_recent() {
+        let mut stats = NatStats::default();
+        let now = SystemTime::now();
+        for (i, id) in ["a", "b", "c"].iter().enumerate() {
+            let event = PunchEvent {
+                timestamp: now + std::time::Duration::from_secs(i as u64),
+                outcome: PunchOutcome::Attempt,
+                nat_type: "unknown".to_owned(),
+                peer_addr: "1.2.3.4:21116".to_owned(),
+                reason: None,
+            };
+            stats.by_peer.insert(id.to_string(), (PunchCounters::default(), VecDeque::from([event])));
+        }
+        stats.evict_least_recent();
+        assert!(!stats.by_peer.contains_key("a"));
+        assert_eq!(stats.by_peer.len(), 2);
+    }
+
+    #[hbb_common::tokio::test]
+    async fn test_record_unregistered_only_counts_totals() {
+        let id = "nat-diagnostics-unregistered";
+        record(None, PunchOutcome::Failed, "unknown", "1.2.3.4:21116", Some("id not exist")).await;
+        assert!(peer_diagnostics(id).await.is_none());
+        record(Some(id), PunchOutcome::Attempt, "symmetric", "1.2.3.4:21116", None).await;
+        let diagnostics = peer_diagnostics(id).await.unwrap();
+        assert_eq!(diagnostics.counters.attempts, 1);
+        assert!(summary().await.total.failed >= 1);
+    }
+}
@@ -0,0 +1,236 @@
+// 连接保活与超时设置 - 部分运营商的 NAT 映射空闲几十秒到几分钟就会回收，长连接需要的 keepalive
+// 和超时与默认值不同，因此不再写死在代码中:
+//   reg_timeout_secs   设备最后一次注册后多久视为离线，打洞时对离线设备直接返回 OFFLINE
+//   tcp / websocket    21116 和 21118 监听端口各自的 TCP keepalive 与空闲超时；空闲超时内没有收到
+//                      任何消息即断开连接，keepalive 的探测包不算消息，所以 keepalive 必须早于空闲超时
+// 修改后对新注册的判断和新建连接立即生效。中继服务器(hbbr)没有数据库，使用环境变量 IDLE_TIMEOUT 和 KEEPALIVE
+use crate::common::{self, Keepalive};
+use crate::enterprise_database::EnterpriseDatabase;
+use crate::validation::Payload;
+use hbb_common::{
+    bail, log,
+    tokio::{net::TcpStream, sync::RwLock},
+    ResultType,
+};
+use schemars::JsonSchema;
+use serde_derive::{Deserialize, Serialize};
+use validator::Validate;
+
+pub const NETWORK_TUNING_KEY: &str = "network_tuning";
+// 客户端每 12 秒注册一次，注册超时不能低于该间隔
+const MIN_REG_TIMEOUT_SECS: u32 = 15;
+const MAX_TIMEOUT_SECS: u32 = 3600;
+
+lazy_static::lazy_static! {
+    static ref TUNING: RwLock<NetworkTuning> = Default::default();
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct ListenerTuning {
+    #[serde(default = "default_idle_timeout_secs")]
+    pub idle_timeout_secs: u32,
+    // 0 表示不开启 keepalive
+    #[serde(default)]
+    pub keepalive_idle_secs: u32,
+    #[serde(default = "default_keepalive_interval_secs")]
+    pub keepalive_interval_secs: u32,
+    #[serde(default = "default_keepalive_retries")]
+    pub keepalive_retries: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema, Validate)]
+pub struct NetworkTuning {
+    #[serde(default = "default_reg_timeout_secs")]
+    pub reg_timeout_secs: u32,
+    #[serde(default)]
+    pub tcp: ListenerTuning,
+    #[serde(default)]
+    pub websocket: ListenerTuning,
+}
+
+impl Payload for NetworkTuning {}
+
+fn default_reg_timeout_secs() -> u32 {
+    30
+}
+
+fn default_idle_timeout_secs() -> u32 {
+    30
+}
+
+fn default_keepalive_interval_secs() -> u32 {
+    15
+}
+
+fn default_keepalive_retries() -> u32 {
+    4
+}
+
+impl Default for ListenerTuning {
+    fn default() -> Self {
+        Self {
+            idle_timeout_secs: default_idle_timeout_secs(),
+            keepalive_idle_secs: 0,
+            keepalive_interval_secs: default_keepalive_interval_secs(),
+            keepalive_retries: default_keepalive_retries(),
+        }
+    }
+}
+
+impl Default for NetworkTuning {
+    fn default() -> Self {
+        Self {
+            reg_timeout_secs: default_reg_timeout_secs(),
+            tcp: Default::default(),
+            websocket: Default::default(),
+        }
+    }
+}
+
+impl ListenerTuning {
+    pub fn keepalive(&self) -> Option<Keepalive> {
+        if self.keepalive_idle_secs == 0 {
+            return None;
+        }
+        Some(Keepalive {
+            idle: self.keepalive_idle_secs,
+            interval: self.keepalive_interval_secs,
+            retries: self.keepalive_retries,
+        })
+    }
+
+    fn validate(&self, name: &str) -> ResultType<()> {
+        if self.idle_timeout_secs < 5 || self.idle_timeout_secs > MAX_TIMEOUT_SECS {
+            bail!("{}.idle_timeout_secs must be 5-{}", name, MAX_TIMEOUT_SECS);
+        }
+        if let Some(keepalive) = self.keepalive() {
+            if let Err(err) = keepalive.validate() {
+                bail!("{}: {}", name, err);
+            }
+            if keepalive.idle >= self.idle_timeout_secs {
+                bail!("{}.keepalive_idle_secs must be less than idle_timeout_secs", name);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl NetworkTuning {
+    pub fn validate(&self) -> ResultType<()> {
+        if self.reg_timeout_secs < MIN_REG_TIMEOUT_SECS || self.reg_timeout_secs > MAX_TIMEOUT_SECS {
+            bail!("reg_timeout_secs must be {}-{}", MIN_REG_TIMEOUT_SECS, MAX_TIMEOUT_SECS);
+        }
+        self.tcp.validate("tcp")?;
+        self.websocket.validate("websocket")
+    }
+
+    pub fn listener(&self, ws: bool) -> &ListenerTuning {
+        if ws {
+            &self.websocket
+        } else {
+            &self.tcp
+        }
+    }
+}
+
+fn log_tuning(tuning: &NetworkTuning) {
+    if !cfg!(target_os = "linux") && (tuning.tcp.keepalive().is_some() || tuning.websocket.keepalive().is_some()) {
+        log::warn!("TCP keepalive tuning is only supported on Linux, ignored");
+    }
+    log::info!(
+        "network tuning: reg timeout {}s, tcp idle timeout {}s, websocket idle timeout {}s",
+        tuning.reg_timeout_secs,
+        tuning.tcp.idle_timeout_secs,
+        tuning.websocket.idle_timeout_secs
+    );
+}
+
+pub async fn reload(db: &EnterpriseDatabase) -> ResultType<()> {
+    let tuning: NetworkTuning = match db.get_setting(NETWORK_TUNING_KEY).await? {
+        Some(v) => serde_json::from_str(&v)?,
+        None => NetworkTuning::default(),
+    };
+    tuning.validate()?;
+    log_tuning(&tuning);
+    *TUNING.write().await = tuning;
+    Ok(())
+}
+
+pub async fn get() -> NetworkTuning {
+    TUNING.read().await.clone()
+}
+
+pub async fn update(db: &EnterpriseDatabase, tuning: NetworkTuning, updated_by: &str) -> ResultType<()> {
+    tuning.validate()?;
+    db.set_setting(NETWORK_TUNING_KEY, &serde_json::to_string(&tuning)?, Some(updated_by))
+        .await?;
+    log_tuning(&tuning);
+    *TUNING.write().await = tuning;
+    Ok(())
+}
+
+pub async fn reg_timeout_ms() -> i32 {
+    TUNING.read().await.reg_timeout_secs as i32 * 1000
+}
+
+// 新连接接受后调用: 按所在监听端口开启 keepalive，返回该连接的空闲超时(毫秒)
+pub async fn on_accept(stream: &TcpStream, ws: bool) -> u64 {
+    let tuning = TUNING.read().await;
+    let listener = tuning.listener(ws);
+    if let Some(keepalive) = listener.keepalive() {
+        if let Err(err) = common::set_keepalive(stream, &keepalive) {
+            log::debug!("Failed to set keepalive: {}", err);
+        }
+    }
+    listener.idle_timeout_secs as u64 * 1000
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate() {
+        let mut tuning: NetworkTuning = serde_json::from_str(
+            r#"{"reg_timeout_secs": 90, "tcp": {"idle_timeout_secs": 300, "keepalive_idle_secs": 60}}"#,
+        )
+        .unwrap();
+        assert!(tuning.validate().is_ok());
+        assert_eq!(
+            tuning.tcp.keepalive(),
+            Some(Keepalive {
+                idle: 60,
+                interval: 15,
+                retries: 4
+            })
+        );
+        assert_eq!(tuning.listener(true), &ListenerTuning::default());
+        assert!(tuning.websocket.keepalive().is_none());
+
+        // keepalive 晚于空闲超时时不起作用
+        tuning.tcp.keepalive_idle_secs = 300;
+        assert!(tuning.validate().is_err());
+        tuning.tcp.keepalive_idle_secs = 60;
+        tuning.tcp.keepalive_retries = 0;
+        assert!(tuning.validate().is_err());
+        tuning.tcp.keepalive_retries = 4;
+        tuning.reg_timeout_secs = 10;
+        assert!(tuning.validate().is_err());
+        assert!(NetworkTuning::default().validate().is_ok());
+    }
+
+    #[test]
+    fn test_parse_keepalive() {
+        assert_eq!(
+            Keepalive::parse("60,10").unwrap(),
+            Keepalive {
+                idle: 60,
+                interval: 10,
+                retries: 4
+            }
+        );
+        assert!(Keepalive::parse("0").is_err());
+        assert!(Keepalive::parse("60,10,4,1").is_err());
+        assert!(Keepalive::parse("abc").is_err());
+    }
+}
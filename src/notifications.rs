@@ -0,0 +1,353 @@
+// 通知偏好模块 - 每个用户按事件类别选择通知渠道(邮件/Webhook/不通知)，告警模块按偏好投递:
+//   security       高危和严重的安全事件，仅投递给管理员
+//   device_offline 设备离线/恢复告警，仅投递给管理员，与设备组配置的告警接收人合并去重
+//   weekly_report  每周汇总报告，投递给管理员和只读用户
+// Webhook 以 POST JSON {category, subject, body, timestamp} 投递，只有管理员可以配置
+// 邮件按订阅用户的语言偏好分别生成，Webhook 使用服务器默认语言
+use crate::advanced_security::{SecurityEvent, SecuritySeverity};
+use crate::auth::UserRole;
+use crate::email_otp;
+use crate::enterprise_database::EnterpriseDatabase;
+use crate::i18n::{self, Lang};
+use crate::validation::Payload;
+use hbb_common::{bail, log, ResultType};
+use schemars::JsonSchema;
+use serde_derive::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    time::Duration,
+};
+use validator::Validate;
+
+// 上次发送周报的时间，保存在系统设置中
+const WEEKLY_REPORT_SENT_KEY: &str = "weekly_report_sent_at";
+const WEEK_SECS: u64 = 7 * 24 * 3600;
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum NotificationCategory {
+    Security,
+    DeviceOffline,
+    WeeklyReport,
+}
+
+impl NotificationCategory {
+    // 允许接收该类别通知的角色
+    pub fn allowed(&self, role: &UserRole) -> bool {
+        match self {
+            Self::Security | Self::DeviceOffline => matches!(role, UserRole::SuperAdmin | UserRole::Admin),
+            Self::WeeklyReport => matches!(
+                role,
+                UserRole::SuperAdmin | UserRole::Admin | UserRole::ReadOnly | UserRole::Auditor
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum NotificationChannel {
+    Email,
+    Webhook,
+    None,
+}
+
+// 未列出的类别不通知
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, JsonSchema, Validate)]
+pub struct NotificationPreferences {
+    #[serde(default)]
+    pub channels: BTreeMap<NotificationCategory, NotificationChannel>,
+    #[serde(default)]
+    #[validate(length(max = 2048))]
+    pub webhook_url: Option<String>,
+}
+
+impl Payload for NotificationPreferences {}
+
+impl NotificationPreferences {
+    pub fn validate(&self, role: &UserRole) -> ResultType<()> {
+        for (category, channel) in self.channels.iter() {
+            if *channel != NotificationChannel::None && !category.allowed(role) {
+                bail!("{:?} notifications are not available for role {:?}", category, role);
+            }
+        }
+        let webhook = self.channels.values().any(|x| *x == NotificationChannel::Webhook);
+        if webhook && !matches!(role, UserRole::SuperAdmin | UserRole::Admin) {
+            bail!("only administrators can use webhook notifications");
+        }
+        match self.webhook_url.as_deref() {
+            Some(url) if !url.starts_with("https://") && !url.starts_with("http://") => {
+                bail!("webhook_url must be an http(s) url")
+            }
+            None if webhook => bail!("webhook_url is required for webhook notifications"),
+            _ => {}
+        }
+        Ok(())
+    }
+
+    pub fn channel(&self, category: NotificationCategory) -> NotificationChannel {
+        self.channels
+            .get(&category)
+            .copied()
+            .unwrap_or(NotificationChannel::None)
+    }
+}
+
+// 订阅了通知的用户
+#[derive(Debug, Clone)]
+pub struct Subscriber {
+    pub user_id: String,
+    pub email: Option<String>,
+    pub role: UserRole,
+    pub preferences: NotificationPreferences,
+    pub lang: Lang,
+}
+
+// 按渠道分组的投递目标，邮箱再按语言分组；skip_emails 中的邮箱已由其他途径通知
+fn targets(
+    subscribers: &[Subscriber],
+    category: NotificationCategory,
+    skip_emails: &BTreeSet<String>,
+) -> (BTreeMap<Lang, BTreeSet<String>>, BTreeSet<String>) {
+    let mut emails: BTreeMap<Lang, BTreeSet<String>> = BTreeMap::new();
+    let mut webhooks = BTreeSet::new();
+    for subscriber in subscribers.iter().filter(|x| category.allowed(&x.role)) {
+        match subscriber.preferences.channel(category) {
+            NotificationChannel::Email => {
+                if let Some(email) = subscriber.email.as_ref().filter(|x| !skip_emails.contains(*x)) {
+                    emails.entry(subscriber.lang).or_default().insert(email.clone());
+                }
+            }
+            NotificationChannel::Webhook => {
+                if let Some(url) = subscriber.preferences.webhook_url.as_ref() {
+                    webhooks.insert(url.clone());
+                }
+            }
+            NotificationChannel::None => {}
+        }
+    }
+    (emails, webhooks)
+}
+
+// render 按语言生成 (subject, body)
+pub async fn notify(
+    db: &EnterpriseDatabase,
+    category: NotificationCategory,
+    render: impl Fn(Lang) -> (String, String),
+    skip_emails: &BTreeSet<String>,
+) {
+    let subscribers = match db.list_notification_subscribers().await {
+        Ok(subscribers) => subscribers,
+        Err(e) => {
+            log::error!("Failed to load notification subscribers: {}", e);
+            return;
+        }
+    };
+    let (emails, webhooks) = targets(&subscribers, category, skip_emails);
+    for (lang, emails) in emails {
+        let (subject, body) = render(lang);
+        if let Err(e) = email_otp::send_notification(emails.into_iter().collect(), subject, body).await {
+            log::error!("Failed to send {:?} notification email: {}", category, e);
+        }
+    }
+    if webhooks.is_empty() {
+        return;
+    }
+    let (subject, body) = render(i18n::default_lang());
+    for url in webhooks.iter() {
+        if let Err(e) = send_webhook(url, category, &subject, &body).await {
+            log::error!("Failed to send {:?} notification to webhook {}: {}", category, url, e);
+        }
+    }
+}
+
+async fn send_webhook(url: &str, category: NotificationCategory, subject: &str, body: &str) -> ResultType<()> {
+    let payload = serde_json::json!({
+        "category": category,
+        "subject": subject,
+        "body": body,
+        "timestamp": crate::common::now(),
+    });
+    reqwest::Client::builder()
+        .timeout(WEBHOOK_TIMEOUT)
+        .build()?
+        .post(url)
+        .json(&payload)
+        .send()
+        .await?
+        .error_for_status()?;
+    Ok(())
+}
+
+// 高危和严重的安全事件通知订阅了 security 的管理员
+pub async fn security_event(db: &EnterpriseDatabase, event: &SecurityEvent) {
+    if !matches!(event.severity, SecuritySeverity::High | SecuritySeverity::Critical) {
+        return;
+    }
+    let details: BTreeMap<_, _> = event.details.iter().collect();
+    let render = |lang| {
+        let subject = i18n::t(
+            lang,
+            "alert.security.subject",
+            &[
+                ("event", &format!("{:?}", event.event_type)),
+                ("severity", &format!("{:?}", event.severity)),
+            ],
+        );
+        let mut lines = vec![
+            i18n::t(lang, "alert.security.event_id", &[("id", &event.id)]),
+            i18n::t(lang, "alert.security.ip", &[("ip", &event.ip_address)]),
+        ];
+        if let Some(user_id) = &event.user_id {
+            lines.push(i18n::t(lang, "alert.security.user", &[("user", user_id)]));
+        }
+        if let Some(device_id) = &event.device_id {
+            lines.push(i18n::t(lang, "alert.security.device", &[("device", device_id)]));
+        }
+        for (k, v) in details.iter() {
+            lines.push(format!("{}: {}", k, v));
+        }
+        (subject, lines.join("\n") + "\n")
+    };
+    notify(db, NotificationCategory::Security, render, &BTreeSet::new()).await;
+}
+
+// 由后台任务定期调用，距上次发送满一周时发送周报
+pub async fn weekly_report(db: &EnterpriseDatabase) {
+    let now = crate::common::now();
+    let sent_at = match db.get_setting(WEEKLY_REPORT_SENT_KEY).await {
+        Ok(v) => v.and_then(|x| x.parse::<u64>().ok()),
+        Err(e) => {
+            log::error!("Failed to read weekly report state: {}", e);
+            return;
+        }
+    };
+    let since = match sent_at {
+        Some(t) if now < t + WEEK_SECS => return,
+        Some(t) => t,
+        // 首次启动只记录时间，一周后发送第一份周报
+        None => {
+            let _ = db.set_setting(WEEKLY_REPORT_SENT_KEY, &now.to_string(), None).await;
+            return;
+        }
+    };
+    let (actions, events) = match report_counts(db, since).await {
+        Ok(counts) => counts,
+        Err(e) => {
+            log::error!("Failed to build weekly report: {}", e);
+            return;
+        }
+    };
+    if let Err(e) = db.set_setting(WEEKLY_REPORT_SENT_KEY, &now.to_string(), None).await {
+        log::error!("Failed to save weekly report state: {}", e);
+        return;
+    }
+    notify(
+        db,
+        NotificationCategory::WeeklyReport,
+        |lang| {
+            (
+                i18n::t(lang, "report.weekly.subject", &[]),
+                report_body(lang, &actions, &events),
+            )
+        },
+        &BTreeSet::new(),
+    )
+    .await;
+    log::info!("Weekly report sent");
+}
+
+async fn report_counts(db: &EnterpriseDatabase, since: u64) -> ResultType<(Vec<(String, i64)>, Vec<(String, i64)>)> {
+    Ok((db.count_audit_actions(since).await?, db.count_security_events(since).await?))
+}
+
+fn report_body(lang: Lang, actions: &[(String, i64)], events: &[(String, i64)]) -> String {
+    let none = i18n::t(lang, "report.weekly.none", &[]);
+    let mut body = format!("{}\n", i18n::t(lang, "report.weekly.actions", &[]));
+    for (action, count) in actions.iter().take(20) {
+        body.push_str(&format!("  {}: {}\n", action, count));
+    }
+    if actions.is_empty() {
+        body.push_str(&format!("  {}\n", none));
+    }
+    body.push_str(&format!("\n{}\n", i18n::t(lang, "report.weekly.security_events", &[])));
+    for (severity, count) in events.iter() {
+        body.push_str(&format!("  {}: {}\n", severity, count));
+    }
+    if events.is_empty() {
+        body.push_str(&format!("  {}\n", none));
+    }
+    body
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn subscriber(role: UserRole, email: &str, channels: &[(NotificationCategory, NotificationChannel)]) -> Subscriber {
+        Subscriber {
+            user_id: email.to_owned(),
+            email: Some(email.to_owned()),
+            role,
+            preferences: NotificationPreferences {
+                channels: channels.iter().cloned().collect(),
+                webhook_url: Some("https://hooks.example.com/oncall".to_owned()),
+            },
+            lang: Lang::Zh,
+        }
+    }
+
+    #[test]
+    fn test_targets() {
+        use NotificationCategory::*;
+        use NotificationChannel::*;
+        let mut subscribers = vec![
+            subscriber(
+                UserRole::Admin,
+                "oncall@example.com",
+                &[(Security, Email), (DeviceOffline, Webhook)],
+            ),
+            subscriber(
+                UserRole::ReadOnly,
+                "manager@example.com",
+                &[(WeeklyReport, Email), (Security, Email)],
+            ),
+            subscriber(UserRole::User, "user@example.com", &[(DeviceOffline, Email)]),
+        ];
+        let none = BTreeSet::new();
+        let (emails, webhooks) = targets(&subscribers, Security, &none);
+        assert_eq!(emails[&Lang::Zh].iter().collect::<Vec<_>>(), vec!["oncall@example.com"]);
+        assert!(webhooks.is_empty());
+        let (emails, webhooks) = targets(&subscribers, DeviceOffline, &none);
+        assert!(emails.is_empty());
+        assert_eq!(webhooks.len(), 1);
+        let (emails, _) = targets(&subscribers, WeeklyReport, &none);
+        assert_eq!(emails[&Lang::Zh].iter().collect::<Vec<_>>(), vec!["manager@example.com"]);
+        let skip = ["manager@example.com".to_owned()].into_iter().collect();
+        assert!(targets(&subscribers, WeeklyReport, &skip).0.is_empty());
+        // 按收件人的语言分组
+        subscribers[1].lang = Lang::En;
+        let (emails, _) = targets(&subscribers, WeeklyReport, &none);
+        assert_eq!(emails[&Lang::En].iter().collect::<Vec<_>>(), vec!["manager@example.com"]);
+        assert!(!emails.contains_key(&Lang::Zh));
+    }
+
+    #[test]
+    fn test_validate() {
+        use NotificationCategory::*;
+        use NotificationChannel::*;
+        let mut prefs = NotificationPreferences::default();
+        prefs.channels.insert(WeeklyReport, Email);
+        assert!(prefs.validate(&UserRole::ReadOnly).is_ok());
+        prefs.channels.insert(Security, Email);
+        assert!(prefs.validate(&UserRole::ReadOnly).is_err());
+        assert!(prefs.validate(&UserRole::Admin).is_ok());
+        prefs.channels.insert(Security, Webhook);
+        assert!(prefs.validate(&UserRole::Admin).is_err());
+        prefs.webhook_url = Some("ftp://example.com".to_owned());
+        assert!(prefs.validate(&UserRole::Admin).is_err());
+        prefs.webhook_url = Some("https://hooks.example.com/x".to_owned());
+        assert!(prefs.validate(&UserRole::Admin).is_ok());
+    }
+}
@@ -0,0 +1,351 @@
+// 设备离线告警模块 - 按设备组的 MonitoringSettings(alert_on_offline / offline_threshold_minutes / alert_recipients)
+// 定期比较设备 last_online，离线超过阈值时记录审计日志并邮件通知接收人，恢复在线后发送恢复通知。
+// 抖动抑制: 同一设备在上次告警后的抑制窗口内再次离线不重复告警，只计入抖动次数并在下次告警中注明。
+// 服务启动后的首次检查只记录已离线的设备，不补发告警。处于节假日日历维护窗口的设备组暂停告警
+use crate::enterprise_database::{AuditLog, EnterpriseDatabase};
+use crate::enterprise_management::MonitoringSettings;
+use crate::holiday_calendar;
+use crate::i18n::{self, Lang};
+use crate::notifications;
+use crate::validation::Payload;
+use hbb_common::{bail, log, tokio::sync::RwLock, ResultType};
+use schemars::JsonSchema;
+use serde_derive::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    time::SystemTime,
+};
+use validator::Validate;
+
+pub const OFFLINE_ALERTS_KEY: &str = "offline_alerts";
+const MAX_THRESHOLD_MINUTES: u32 = 7 * 24 * 60;
+
+lazy_static::lazy_static! {
+    static ref CONFIG: RwLock<OfflineAlertConfig> = Default::default();
+    static ref STATE: RwLock<AlertState> = Default::default();
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, Validate)]
+pub struct OfflineAlertConfig {
+    // 设备组ID -> 监控设置
+    #[serde(default)]
+    pub groups: BTreeMap<String, MonitoringSettings>,
+    #[serde(default = "default_flap_suppression_minutes")]
+    pub flap_suppression_minutes: u32,
+}
+
+impl Payload for OfflineAlertConfig {}
+
+fn default_flap_suppression_minutes() -> u32 {
+    30
+}
+
+impl Default for OfflineAlertConfig {
+    fn default() -> Self {
+        Self {
+            groups: BTreeMap::new(),
+            flap_suppression_minutes: default_flap_suppression_minutes(),
+        }
+    }
+}
+
+impl OfflineAlertConfig {
+    pub fn validate(&self) -> ResultType<()> {
+        for (group_id, settings) in self.groups.iter() {
+            if settings.offline_threshold_minutes == 0 || settings.offline_threshold_minutes > MAX_THRESHOLD_MINUTES {
+                bail!("{}: offline_threshold_minutes must be 1-{}", group_id, MAX_THRESHOLD_MINUTES);
+            }
+            for recipient in settings.alert_recipients.iter() {
+                if recipient.parse::<lettre::message::Mailbox>().is_err() {
+                    bail!("{}: invalid recipient {}", group_id, recipient);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // 设备所在的各监控组中取最小阈值(秒)，接收人取并集
+    fn rule_for(&self, group_ids: &[String]) -> Option<(u64, BTreeSet<String>)> {
+        let mut res: Option<(u64, BTreeSet<String>)> = None;
+        for settings in group_ids.iter().filter_map(|g| self.groups.get(g)) {
+            if !settings.enable_monitoring || !settings.alert_on_offline {
+                continue;
+            }
+            let threshold = settings.offline_threshold_minutes as u64 * 60;
+            let (min, recipients) = res.get_or_insert_with(|| (threshold, BTreeSet::new()));
+            *min = (*min).min(threshold);
+            recipients.extend(settings.alert_recipients.iter().cloned());
+        }
+        res
+    }
+}
+
+// 参与离线检查的设备
+#[derive(Debug, Clone)]
+pub struct DevicePresence {
+    pub id: String,
+    pub name: String,
+    pub last_online: u64,
+    pub group_ids: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct OfflineDevice {
+    pub device_id: String,
+    pub offline_since: u64,
+    // 最近一次告警时间，首次检查时已离线的设备为空
+    pub alerted_at: Option<u64>,
+    // 抑制窗口内重复离线的次数
+    pub flaps: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AlertKind {
+    Offline,
+    Recovered,
+}
+
+#[derive(Debug, Clone)]
+pub struct Alert {
+    pub kind: AlertKind,
+    pub device_id: String,
+    pub device_name: String,
+    pub offline_since: u64,
+    pub flaps: u32,
+    pub recipients: BTreeSet<String>,
+}
+
+#[derive(Debug, Default)]
+struct AlertState {
+    primed: bool,
+    offline: HashMap<String, OfflineDevice>,
+    // 设备ID -> 最近一次离线告警时间，用于抖动抑制
+    last_alert: HashMap<String, u64>,
+    // 抑制期间累计的抖动次数
+    flaps: HashMap<String, u32>,
+}
+
+fn evaluate(state: &mut AlertState, config: &OfflineAlertConfig, devices: &[DevicePresence], now: u64) -> Vec<Alert> {
+    let window = config.flap_suppression_minutes as u64 * 60;
+    let primed = std::mem::replace(&mut state.primed, true);
+    let mut alerts = vec![];
+    let mut seen = BTreeSet::new();
+    for device in devices {
+        let (threshold, recipients) = match config.rule_for(&device.group_ids) {
+            Some(rule) => rule,
+            None => continue,
+        };
+        seen.insert(device.id.clone());
+        let offline = now >= device.last_online + threshold;
+        match (offline, state.offline.contains_key(&device.id)) {
+            (true, false) => {
+                let suppressed = !primed
+                    || state
+                        .last_alert
+                        .get(&device.id)
+                        .map(|t| now < t + window)
+                        .unwrap_or(false);
+                let mut record = OfflineDevice {
+                    device_id: device.id.clone(),
+                    offline_since: device.last_online,
+                    alerted_at: None,
+                    flaps: 0,
+                };
+                if suppressed {
+                    if primed {
+                        *state.flaps.entry(device.id.clone()).or_default() += 1;
+                    }
+                } else {
+                    record.alerted_at = Some(now);
+                    record.flaps = state.flaps.remove(&device.id).unwrap_or_default();
+                    state.last_alert.insert(device.id.clone(), now);
+                    alerts.push(Alert {
+                        kind: AlertKind::Offline,
+                        device_id: device.id.clone(),
+                        device_name: device.name.clone(),
+                        offline_since: device.last_online,
+                        flaps: record.flaps,
+                        recipients,
+                    });
+                }
+                state.offline.insert(device.id.clone(), record);
+            }
+            (false, true) => {
+                if let Some(record) = state.offline.remove(&device.id) {
+                    // 只对发过离线告警的设备发送恢复通知
+                    if record.alerted_at.is_some() {
+                        alerts.push(Alert {
+                            kind: AlertKind::Recovered,
+                            device_id: device.id.clone(),
+                            device_name: device.name.clone(),
+                            offline_since: record.offline_since,
+                            flaps: 0,
+                            recipients,
+                        });
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    // 移出监控组或已删除的设备
+    state.offline.retain(|id, _| seen.contains(id));
+    state.last_alert.retain(|id, t| seen.contains(id) && now < *t + window);
+    state.flaps.retain(|id, _| seen.contains(id));
+    alerts
+}
+
+async fn dispatch(db: &EnterpriseDatabase, alert: &Alert) {
+    let (action, subject_key) = match alert.kind {
+        AlertKind::Offline => ("device_offline_alert", "alert.offline.subject"),
+        AlertKind::Recovered => ("device_online_recovered", "alert.recovered.subject"),
+    };
+    let since = chrono::DateTime::<chrono::Local>::from(
+        std::time::UNIX_EPOCH + std::time::Duration::from_secs(alert.offline_since),
+    )
+    .format("%Y-%m-%d %H:%M:%S")
+    .to_string();
+    let render = |lang: Lang| {
+        let subject = i18n::t(lang, subject_key, &[("device", &alert.device_name)]);
+        let mut body = format!(
+            "{}\n{}\n",
+            i18n::t(lang, "alert.device", &[("name", &alert.device_name), ("id", &alert.device_id)]),
+            i18n::t(lang, "alert.last_online", &[("time", &since)]),
+        );
+        if alert.flaps > 0 {
+            body.push_str(&format!("{}\n", i18n::t(lang, "alert.flaps", &[("count", &alert.flaps)])));
+        }
+        (subject, body)
+    };
+    // 审计日志和设备组告警接收人(只有邮箱)使用服务器默认语言
+    let (subject, body) = render(i18n::default_lang());
+    log::warn!("{}: {} last online at {}", action, alert.device_id, since);
+
+    let audit_log = AuditLog {
+        id: 0,
+        user_id: "system".to_string(),
+        device_id: alert.device_id.clone(),
+        action: action.to_string(),
+        details: Some(body.trim_end().replace('\n', "; ")),
+        ip_address: "127.0.0.1".to_string(),
+        user_agent: None,
+        timestamp: SystemTime::now(),
+        success: true,
+    };
+    let _ = db.log_audit(&audit_log).await;
+
+    if !alert.recipients.is_empty() {
+        let recipients = alert.recipients.iter().cloned().collect();
+        if let Err(e) = crate::email_otp::send_notification(recipients, subject, body).await {
+            log::error!("Failed to send {} for {}: {}", action, alert.device_id, e);
+        }
+    }
+    // 按个人通知偏好订阅了离线告警的管理员，已在设备组接收人中的不重复发送
+    notifications::notify(
+        db,
+        notifications::NotificationCategory::DeviceOffline,
+        render,
+        &alert.recipients,
+    )
+    .await;
+}
+
+// 由后台任务定期调用
+pub async fn check(db: &EnterpriseDatabase) {
+    let config = get().await;
+    if config.groups.is_empty() {
+        return;
+    }
+    let mut devices = match db.list_device_presence().await {
+        Ok(devices) => devices,
+        Err(e) => {
+            log::error!("Failed to load devices for offline check: {}", e);
+            return;
+        }
+    };
+    // 维护窗口内的设备按移出监控处理，窗口结束后仍离线的重新告警
+    let maintenance = holiday_calendar::groups_in_maintenance(chrono::Utc::now()).await;
+    devices.retain(|d| !d.group_ids.iter().any(|g| maintenance.contains(g)));
+    let alerts = evaluate(&mut *STATE.write().await, &config, &devices, crate::common::now());
+    for alert in alerts.iter() {
+        dispatch(db, alert).await;
+    }
+}
+
+// 当前处于离线告警状态的设备
+pub async fn offline_devices() -> Vec<OfflineDevice> {
+    let mut res: Vec<_> = STATE.read().await.offline.values().cloned().collect();
+    res.sort_by(|a, b| a.offline_since.cmp(&b.offline_since));
+    res
+}
+
+pub async fn reload(db: &EnterpriseDatabase) -> ResultType<()> {
+    let config = match db.get_setting(OFFLINE_ALERTS_KEY).await? {
+        Some(v) => serde_json::from_str(&v)?,
+        None => OfflineAlertConfig::default(),
+    };
+    *CONFIG.write().await = config;
+    Ok(())
+}
+
+pub async fn get() -> OfflineAlertConfig {
+    CONFIG.read().await.clone()
+}
+
+pub async fn update(db: &EnterpriseDatabase, config: OfflineAlertConfig, updated_by: &str) -> ResultType<()> {
+    config.validate()?;
+    db.set_setting(OFFLINE_ALERTS_KEY, &serde_json::to_string(&config)?, Some(updated_by))
+        .await?;
+    *CONFIG.write().await = config;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_offline_alert_flap_suppression() {
+        let mut config = OfflineAlertConfig::default();
+        config.groups.insert(
+            "kiosk".to_owned(),
+            MonitoringSettings {
+                enable_monitoring: true,
+                alert_on_offline: true,
+                offline_threshold_minutes: 5,
+                alert_on_unauthorized_access: false,
+                alert_recipients: vec!["ops@example.com".to_owned()],
+            },
+        );
+        assert!(config.validate().is_ok());
+        let device = |last_online| DevicePresence {
+            id: "123".to_owned(),
+            name: "kiosk-1".to_owned(),
+            last_online,
+            group_ids: vec!["kiosk".to_owned()],
+        };
+        let mut state = AlertState::default();
+        // 首次检查只记录，不告警
+        assert!(evaluate(&mut state, &config, &[device(0)], 1000).is_empty());
+        assert_eq!(evaluate(&mut state, &config, &[device(1000)], 1010).len(), 0);
+
+        let alerts = evaluate(&mut state, &config, &[device(1010)], 1400);
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(alerts[0].kind, AlertKind::Offline);
+        let alerts = evaluate(&mut state, &config, &[device(1450)], 1460);
+        assert_eq!(alerts[0].kind, AlertKind::Recovered);
+        // 抑制窗口内再次离线不告警
+        assert!(evaluate(&mut state, &config, &[device(1460)], 1800).is_empty());
+        assert!(evaluate(&mut state, &config, &[device(1850)], 1860).is_empty());
+        // 窗口过后再次离线，告警中带上抖动次数
+        let alerts = evaluate(&mut state, &config, &[device(1860)], 1400 + 1800 + 60);
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(alerts[0].flaps, 1);
+
+        // 不在监控组中的设备不检查
+        let mut other = device(0);
+        other.group_ids.clear();
+        assert!(evaluate(&mut AlertState::default(), &config, &[other], 10000).is_empty());
+    }
+}
@@ -0,0 +1,1808 @@
+// 接口文档模块 - 按下面的接口表生成 OpenAPI 3 文档，由 /api/openapi.json 提供，/api/docs 为 Swagger UI。
+// 请求和响应的结构由 schemars 从 Rust 类型(含 serde 属性)生成，JSON 响应统一包装为 ApiResponse<T>。
+// 接口表须与 create_router 一致，新增路由时同时在此登记(由测试检查)
+use crate::ad_sync::{AdSyncConfig, AdSyncReport};
+use crate::advanced_security::SecurityEvent;
+use crate::announcements::Announcement;
+use crate::backup::{BackupConfig, BackupFile};
+use crate::break_glass::EmergencyAccess;
+use crate::change_approval::{ChangeApprovalConfig, SettingChange};
+use crate::codec_recommendation::CodecRecommendation;
+use crate::config_versions::{ConfigVersion, RollbackReport, SettingDiff};
+use crate::connection_quality::QualityTrend;
+use crate::content_scan::ScanConfig;
+use crate::control_tls::{ControlTlsStatus, IssuedBundle, IssuedCert};
+use crate::custom_fields::{CustomFieldsConfig, FieldValues, FieldValuesUpdate};
+use crate::data_masking::{DataMaskingConfig, DataMaskingUpdate};
+use crate::device_capabilities::DeviceCapabilities;
+use crate::device_messages::DeviceMessage;
+use crate::device_timeline::{DeviceNote, TimelineEntry};
+use crate::device_views::DeviceView;
+use crate::dlp::DlpPolicy;
+use crate::dns_discovery::{DiscoveryHealth, DnsDiscoveryConfig, DnsRecords};
+use crate::e2e_signaling::{E2ePolicy, E2eStats};
+use crate::email_otp::SmtpConfig;
+use crate::enterprise_database::{DeviceAlias, DeviceBan, DeviceInfo, ProvisionedId, TrustedDevice, UserInvite};
+use crate::erasure::{ErasureReport, ErasureRequest};
+use crate::error_codes::ErrorInfo;
+use crate::validation::{FieldError, Payload};
+use crate::i18n::LanguagePreference;
+use crate::favorites::UserDevice;
+use crate::feature_flags::{FeatureFlags, FeatureFlagsUpdate};
+use crate::federation::{DeliverRequest, FederatedLookup, FederationConfig, ForwardRequest};
+use crate::file_transfer::{TransferProgress, TransferRecord};
+use crate::four_eyes::{ApprovalRequest, FourEyesPolicy};
+use crate::holiday_calendar::{CalendarConfig, HolidayCalendar};
+use crate::honeypot::{HoneypotConfig, IpBan};
+use crate::host_stats::HostStatsReport;
+use crate::id_policy::IdPolicy;
+use crate::impersonation::{ImpersonationGrant, ImpersonationSession};
+use crate::inventory::{DeviceInventory, InventoryReport};
+use crate::itsm::ItsmConfig;
+use crate::jwt_keys::JwtKeyInfo;
+use crate::kiosk_fleet::{FleetHealth, KioskFleetConfig};
+use crate::lan_config::LanConfig;
+use crate::license::LicenseStatus;
+use crate::logging::LoggingConfig;
+use crate::mfa_policy::MfaPolicy;
+use crate::nat_diagnostics::{NatStatsSummary, PeerNatDiagnostics};
+use crate::network_tuning::NetworkTuning;
+use crate::notifications::NotificationPreferences;
+use crate::offline_alerts::{OfflineAlertConfig, OfflineDevice};
+use crate::password_policy::{PasswordPolicies, PasswordPolicy};
+use crate::pk_pinning::{PkPinningConfig, PkRecord};
+use crate::performance_optimization::PoolUsage;
+use crate::protocol_capture::{Capture, StartCaptureRequest};
+use crate::relay_policy::RelayPolicy;
+use crate::relay_sessions::RelayStatus;
+use crate::remote_jobs::Job;
+use crate::self_test::{SelfTestPlan, SelfTestResult};
+use crate::server_key::KeyRingInfo;
+use crate::session_controls::{PermissionChange, SessionPermissions};
+use crate::software_update::{ManifestEntry, UpdateArtifact};
+use crate::strategy::{EffectiveStrategy, Strategy};
+use crate::threat_intel::{ThreatIntelConfig, ThreatIntelStatus};
+use crate::transfer_bandwidth::BandwidthPolicy;
+use crate::trusted_device::TrustedDeviceConfig;
+use crate::turn::{TurnConfig, TurnCredentials};
+use crate::unattended_access::UnattendedPolicy;
+use crate::uptime::{StatusChange, UptimeReport};
+use crate::version_policy::VersionPolicy;
+use crate::wake_on_lan::WakeResult;
+use crate::web_api::*;
+use crate::web_security::WebSecurityConfig;
+use crate::web_session::{WebSessionConfig, WebSessionStatus};
+use crate::webrtc_signaling::{ControllerUpdate, IceCandidate, SessionOffer, WebRtcConfig};
+use schemars::{
+    gen::{SchemaGenerator, SchemaSettings},
+    schema::Schema,
+    JsonSchema,
+};
+use serde_json::{json, Map, Value};
+use std::collections::{BTreeMap, HashMap};
+
+lazy_static::lazy_static! {
+    pub static ref DOCUMENT: Value = document();
+}
+
+// Swagger UI 的静态资源从 CDN 加载
+pub const SWAGGER_UI_HTML: &str = r##"<!DOCTYPE html>
+<html>
+<head>
+  <meta charset="utf-8">
+  <title>RustDesk Enterprise API</title>
+  <link rel="stylesheet" href="https://unpkg.com/swagger-ui-dist@5/swagger-ui.css">
+</head>
+<body>
+  <div id="swagger-ui"></div>
+  <script src="https://unpkg.com/swagger-ui-dist@5/swagger-ui-bundle.js"></script>
+  <script>
+    window.ui = SwaggerUIBundle({ url: "/api/openapi.json", dom_id: "#swagger-ui", persistAuthorization: true });
+  </script>
+</body>
+</html>
+"##;
+
+type SchemaFn = fn(&mut SchemaGenerator) -> Schema;
+
+fn schema<T: JsonSchema>(gen: &mut SchemaGenerator) -> Schema {
+    gen.subschema_for::<T>()
+}
+
+enum RequestBody {
+    None,
+    Json(SchemaFn),
+    Binary,
+}
+
+enum Reply {
+    Json(SchemaFn),
+    Text,
+    Binary,
+    Html,
+    Empty, // 只有状态码和响应头
+}
+
+struct Operation {
+    method: &'static str,
+    path: &'static str,
+    operation_id: &'static str,
+    summary: &'static str,
+    tag: &'static str,
+    auth: bool,
+    implemented: bool,
+    // 请求参数在提取时校验，失败返回 400
+    validated: bool,
+    query: Option<SchemaFn>,
+    body: RequestBody,
+    reply: Reply,
+}
+
+fn op(method: &'static str, path: &'static str, operation_id: &'static str, summary: &'static str) -> Operation {
+    Operation {
+        method,
+        path,
+        operation_id,
+        summary,
+        tag: "",
+        auth: true,
+        implemented: true,
+        validated: false,
+        query: None,
+        body: RequestBody::None,
+        reply: Reply::Empty,
+    }
+}
+
+impl Operation {
+    // 不使用JWT，客户端接口以请求中的 id + uuid 校验
+    fn public(mut self) -> Self {
+        self.auth = false;
+        self
+    }
+
+    fn unimplemented(mut self) -> Self {
+        self.implemented = false;
+        self
+    }
+
+    // 查询参数和请求体由 ValidatedQuery / ValidatedJson 提取
+    fn query<T: JsonSchema + Payload>(mut self) -> Self {
+        self.query = Some(schema::<T>);
+        self.validated = true;
+        self
+    }
+
+    fn body<T: JsonSchema + Payload>(mut self) -> Self {
+        self.body = RequestBody::Json(schema::<T>);
+        self.validated = true;
+        self
+    }
+
+    fn binary_body(mut self) -> Self {
+        self.body = RequestBody::Binary;
+        self
+    }
+
+    // 包装为 ApiResponse<T> 的响应
+    fn reply<T: JsonSchema>(mut self) -> Self {
+        self.reply = Reply::Json(schema::<ApiResponse<T>>);
+        self
+    }
+
+    fn raw_reply<T: JsonSchema>(mut self) -> Self {
+        self.reply = Reply::Json(schema::<T>);
+        self
+    }
+
+    fn text_reply(mut self) -> Self {
+        self.reply = Reply::Text;
+        self
+    }
+
+    fn download(mut self) -> Self {
+        self.reply = Reply::Binary;
+        self
+    }
+
+    fn html(mut self) -> Self {
+        self.reply = Reply::Html;
+        self
+    }
+
+    // :id -> {id}
+    fn openapi_path(&self) -> String {
+        self.path
+            .split('/')
+            .map(|x| match x.strip_prefix(':') {
+                Some(name) => format!("{{{}}}", name),
+                None => x.to_owned(),
+            })
+            .collect::<Vec<_>>()
+            .join("/")
+    }
+
+    fn to_json(&self, gen: &mut SchemaGenerator) -> Value {
+        let mut parameters = vec![];
+        for name in self.path.split('/').filter_map(|x| x.strip_prefix(':')) {
+            parameters.push(json!({
+                "name": name,
+                "in": "path",
+                "required": true,
+                "schema": {"type": "string"},
+            }));
+        }
+        if let Some(query) = self.query {
+            // 查询参数结构体的每个字段对应一个参数
+            if let Schema::Object(obj) = query(gen) {
+                let name = obj
+                    .reference
+                    .as_deref()
+                    .and_then(|r| r.rsplit('/').next())
+                    .map(str::to_owned);
+                let obj = match name {
+                    Some(name) => match gen.definitions().get(&name) {
+                        Some(Schema::Object(x)) => x.clone(),
+                        _ => obj,
+                    },
+                    None => obj,
+                };
+                if let Some(validation) = obj.object {
+                    for (name, schema) in validation.properties.iter() {
+                        parameters.push(json!({
+                            "name": name,
+                            "in": "query",
+                            "required": validation.required.contains(name),
+                            "schema": schema,
+                        }));
+                    }
+                }
+            }
+        }
+        let mut res = Map::new();
+        res.insert("operationId".to_owned(), json!(self.operation_id));
+        res.insert("summary".to_owned(), json!(self.summary));
+        res.insert("tags".to_owned(), json!([self.tag]));
+        if !parameters.is_empty() {
+            res.insert("parameters".to_owned(), json!(parameters));
+        }
+        match self.body {
+            RequestBody::None => {}
+            RequestBody::Json(f) => {
+                res.insert(
+                    "requestBody".to_owned(),
+                    json!({"required": true, "content": {"application/json": {"schema": f(gen)}}}),
+                );
+            }
+            RequestBody::Binary => {
+                res.insert(
+                    "requestBody".to_owned(),
+                    json!({"required": true, "content": {"application/octet-stream": {"schema": {"type": "string", "format": "binary"}}}}),
+                );
+            }
+        }
+        let ok = match self.reply {
+            Reply::Json(f) => json!({"description": "成功", "content": {"application/json": {"schema": f(gen)}}}),
+            Reply::Text => json!({"description": "成功", "content": {"text/plain": {"schema": {"type": "string"}}}}),
+            Reply::Binary => json!({
+                "description": "成功",
+                "content": {"application/octet-stream": {"schema": {"type": "string", "format": "binary"}}},
+            }),
+            Reply::Html => json!({"description": "成功", "content": {"text/html": {"schema": {"type": "string"}}}}),
+            Reply::Empty => json!({"description": "成功"}),
+        };
+        let mut responses = Map::new();
+        responses.insert("200".to_owned(), ok);
+        if self.validated {
+            let schema = schema::<ApiResponse<Vec<FieldError>>>(gen);
+            responses.insert(
+                "400".to_owned(),
+                json!({"description": "请求参数校验失败，data 为逐字段的错误", "content": {"application/json": {"schema": schema}}}),
+            );
+        }
+        if self.auth {
+            res.insert("security".to_owned(), json!([{"bearerAuth": []}]));
+            responses.insert("401".to_owned(), json!({"description": "未登录或令牌无效"}));
+            responses.insert("403".to_owned(), json!({"description": "权限不足"}));
+        }
+        if !self.implemented {
+            res.insert("deprecated".to_owned(), json!(true));
+            responses.insert("501".to_owned(), json!({"description": "尚未实现"}));
+        }
+        res.insert("responses".to_owned(), Value::Object(responses));
+        Value::Object(res)
+    }
+}
+
+type Fields = BTreeMap<String, Value>;
+
+fn operations() -> Vec<Operation> {
+    let sections = vec![
+        (
+            "客户端",
+            vec![
+                op(
+                    "POST",
+                    "/api/heartbeat",
+                    "client_heartbeat",
+                    "客户端心跳，返回策略、任务和消息",
+                )
+                .public()
+                .body::<HeartbeatRequest>()
+                .raw_reply::<HeartbeatResponse>(),
+                op("POST", "/api/sysinfo", "client_sysinfo", "上报系统信息")
+                    .public()
+                    .body::<SysinfoRequest>()
+                    .text_reply(),
+                op(
+                    "POST",
+                    "/api/jobs/:id/result",
+                    "client_job_result",
+                    "上报远程任务执行结果",
+                )
+                .public()
+                .body::<JobResultRequest>()
+                .text_reply(),
+                op("POST", "/api/messages/:id/ack", "client_message_ack", "确认已收到消息")
+                    .public()
+                    .body::<MessageAckRequest>()
+                    .text_reply(),
+                op("POST", "/api/session-events", "client_session_events", "上报会话事件")
+                    .public()
+                    .body::<SessionEventsRequest>()
+                    .text_reply(),
+                op("POST", "/api/session-qos", "client_session_qos", "上报会话 QoS 采样")
+                    .public()
+                    .body::<SessionQosRequest>()
+                    .text_reply(),
+                op("POST", "/api/self-test", "client_start_self_test", "申请连通性自检计划")
+                    .public()
+                    .body::<SelfTestStartRequest>()
+                    .raw_reply::<SelfTestPlan>(),
+                op(
+                    "POST",
+                    "/api/self-test/:id/result",
+                    "client_self_test_result",
+                    "上报连通性自检结果",
+                )
+                .public()
+                .body::<SelfTestReportRequest>()
+                .raw_reply::<SelfTestResult>(),
+                op(
+                    "POST",
+                    "/api/sessions/:id/codec/ack",
+                    "client_codec_ack",
+                    "回执编码推荐",
+                )
+                .public()
+                .body::<CodecAckRequest>()
+                .text_reply(),
+                op(
+                    "POST",
+                    "/api/webrtc/sessions/:id/device",
+                    "client_webrtc_signal",
+                    "被控端提交 WebRTC 应答和候选",
+                )
+                .public()
+                .body::<DeviceWebRtcSignalRequest>()
+                .raw_reply::<Vec<IceCandidate>>(),
+                op(
+                    "GET",
+                    "/api/federation/lookup/:id",
+                    "federation_lookup",
+                    "联邦中其他区域查询设备是否在本区域在线，以 X-Federation-Secret 认证",
+                )
+                .public()
+                .reply::<FederatedLookup>(),
+                op(
+                    "POST",
+                    "/api/federation/forward",
+                    "federation_forward",
+                    "联邦中其他区域转发打洞或中继请求给本区域的设备",
+                )
+                .public()
+                .body::<ForwardRequest>()
+                .reply::<bool>(),
+                op(
+                    "POST",
+                    "/api/federation/deliver",
+                    "federation_deliver",
+                    "设备所在区域送回响应给本区域的控制端",
+                )
+                .public()
+                .body::<DeliverRequest>()
+                .reply::<bool>(),
+                op(
+                    "GET",
+                    "/api/updates/manifest",
+                    "get_update_manifest",
+                    "客户端安装包清单",
+                )
+                .public()
+                .raw_reply::<Vec<ManifestEntry>>(),
+                op(
+                    "GET",
+                    "/api/updates/:platform/:version/download",
+                    "download_update_artifact",
+                    "下载安装包(支持 Range)",
+                )
+                .public()
+                .download(),
+                op(
+                    "GET",
+                    "/api/updates/:platform/:version/sha256",
+                    "get_update_checksum",
+                    "安装包的 SHA256",
+                )
+                .public()
+                .text_reply(),
+            ],
+        ),
+        (
+            "认证",
+            vec![
+                op("POST", "/api/auth/login", "login", "登录")
+                    .public()
+                    .body::<LoginRequest>()
+                    .raw_reply::<LoginResponse>(),
+                op("POST", "/api/auth/logout", "logout", "退出登录")
+                    .public()
+                    .reply::<()>(),
+                op("GET", "/api/auth/me", "get_current_user", "当前用户").reply::<UserInfo>(),
+                op("GET", "/api/auth/session", "get_web_session", "当前会话的失效时间").reply::<WebSessionStatus>(),
+                op(
+                    "POST",
+                    "/api/auth/session/keepalive",
+                    "keep_web_session_alive",
+                    "延长当前会话",
+                )
+                .reply::<WebSessionStatus>(),
+                op("POST", "/api/auth/2fa/setup", "setup_two_factor", "生成2FA密钥").reply::<TwoFactorSetupResponse>(),
+                op("POST", "/api/auth/2fa/confirm", "confirm_two_factor", "确认绑定2FA")
+                    .body::<TwoFactorConfirmRequest>()
+                    .raw_reply::<LoginResponse>(),
+                op("POST", "/api/auth/2fa/email", "send_email_otp", "发送邮件验证码")
+                    .public()
+                    .body::<EmailOtpRequest>()
+                    .reply::<()>(),
+                op("POST", "/api/auth/forgot-password", "forgot_password", "申请找回密码")
+                    .public()
+                    .body::<ForgotPasswordRequest>()
+                    .reply::<()>(),
+                op("POST", "/api/auth/reset-password", "reset_password", "重置密码")
+                    .public()
+                    .body::<ResetPasswordRequest>()
+                    .reply::<()>(),
+                op(
+                    "GET",
+                    "/api/auth/trusted-devices",
+                    "list_trusted_devices",
+                    "当前用户的受信任浏览器",
+                )
+                .reply::<Vec<TrustedDevice>>(),
+                op(
+                    "DELETE",
+                    "/api/auth/trusted-devices",
+                    "revoke_all_trusted_devices",
+                    "撤销当前用户的全部受信任浏览器",
+                )
+                .reply::<u64>(),
+                op(
+                    "DELETE",
+                    "/api/auth/trusted-devices/:id",
+                    "revoke_trusted_device",
+                    "撤销受信任浏览器",
+                )
+                .reply::<u64>(),
+                op(
+                    "POST",
+                    "/api/auth/impersonation/end",
+                    "end_impersonation",
+                    "结束当前代操作会话",
+                )
+                .reply::<()>(),
+                op("GET", "/api/invites/accept", "get_invite", "查看邀请")
+                    .public()
+                    .query::<InviteTokenQuery>()
+                    .reply::<InvitePreview>(),
+                op("POST", "/api/invites/accept", "accept_invite", "接受邀请并设置密码")
+                    .public()
+                    .body::<AcceptInviteRequest>()
+                    .raw_reply::<LoginResponse>(),
+            ],
+        ),
+        (
+            "用户",
+            vec![
+                op("GET", "/api/users", "list_users", "用户列表")
+                    .query::<PaginationQuery>()
+                    .reply::<Vec<UserInfo>>(),
+                op("POST", "/api/users", "create_user", "创建用户")
+                    .body::<CreateUserRequest>()
+                    .reply::<UserInfo>(),
+                op("GET", "/api/users/:id", "get_user", "用户详情")
+                    .unimplemented()
+                    .reply::<UserInfo>(),
+                op("PUT", "/api/users/:id", "update_user", "修改用户")
+                    .unimplemented()
+                    .reply::<UserInfo>(),
+                op("DELETE", "/api/users/:id", "delete_user", "删除用户")
+                    .unimplemented()
+                    .reply::<()>(),
+                op(
+                    "POST",
+                    "/api/users/:id/reset-password",
+                    "reset_user_password",
+                    "重置密码",
+                )
+                .unimplemented()
+                .reply::<()>(),
+                op(
+                    "POST",
+                    "/api/users/:id/toggle-status",
+                    "toggle_user_status",
+                    "启用或禁用用户",
+                )
+                .unimplemented()
+                .reply::<()>(),
+                op(
+                    "POST",
+                    "/api/users/:id/email-otp",
+                    "grant_email_otp",
+                    "开放邮件验证码登录",
+                )
+                .body::<GrantEmailOtpRequest>()
+                .reply::<u64>(),
+                op(
+                    "DELETE",
+                    "/api/users/:id/email-otp",
+                    "revoke_email_otp",
+                    "关闭邮件验证码登录",
+                )
+                .reply::<()>(),
+                op(
+                    "DELETE",
+                    "/api/users/:id/trusted-devices",
+                    "revoke_user_trusted_devices",
+                    "撤销用户的全部受信任浏览器",
+                )
+                .reply::<u64>(),
+                op("GET", "/api/users/:id/fields", "get_user_fields", "用户的自定义字段").reply::<Fields>(),
+                op(
+                    "PUT",
+                    "/api/users/:id/fields",
+                    "set_user_fields",
+                    "设置用户的自定义字段",
+                )
+                .body::<FieldValuesUpdate>()
+                .reply::<Fields>(),
+                op(
+                    "GET",
+                    "/api/users/:id/notification-preferences",
+                    "get_user_notification_preferences",
+                    "用户的通知偏好",
+                )
+                .reply::<NotificationPreferences>(),
+                op(
+                    "PUT",
+                    "/api/users/:id/notification-preferences",
+                    "update_user_notification_preferences",
+                    "设置用户的通知偏好",
+                )
+                .body::<NotificationPreferences>()
+                .reply::<NotificationPreferences>(),
+                op(
+                    "GET",
+                    "/api/notification-preferences",
+                    "get_my_notification_preferences",
+                    "当前用户的通知偏好",
+                )
+                .reply::<NotificationPreferences>(),
+                op(
+                    "PUT",
+                    "/api/notification-preferences",
+                    "update_my_notification_preferences",
+                    "设置当前用户的通知偏好",
+                )
+                .body::<NotificationPreferences>()
+                .reply::<NotificationPreferences>(),
+                op("GET", "/api/language", "get_my_language", "当前用户的语言偏好").reply::<LanguagePreference>(),
+                op("PUT", "/api/language", "update_my_language", "设置当前用户的语言偏好，为空时按 Accept-Language 协商")
+                    .body::<LanguagePreference>()
+                    .reply::<LanguagePreference>(),
+                op("GET", "/api/me/recent", "list_recent_devices", "当前用户最近连接的设备")
+                    .query::<RecentQuery>()
+                    .reply::<Vec<UserDevice>>(),
+                op("DELETE", "/api/me/recent", "clear_recent_devices", "清空当前用户的最近连接").reply::<()>(),
+                op("GET", "/api/me/favorites", "list_favorite_devices", "当前用户收藏的设备").reply::<Vec<UserDevice>>(),
+                op("PUT", "/api/me/favorites/:id", "add_favorite_device", "收藏设备(范围与可控制的设备相同)").reply::<()>(),
+                op("DELETE", "/api/me/favorites/:id", "remove_favorite_device", "取消收藏设备").reply::<()>(),
+                op("GET", "/api/invites", "list_invites", "用户邀请")
+                    .query::<PaginationQuery>()
+                    .reply::<Vec<UserInvite>>(),
+                op("POST", "/api/invites", "invite_user", "邀请用户")
+                    .body::<InviteUserRequest>()
+                    .reply::<UserInvite>(),
+                op("DELETE", "/api/invites/:id", "revoke_invite", "撤销邀请").reply::<()>(),
+                op("POST", "/api/invites/:id/resend", "resend_invite", "重新发送邀请").reply::<UserInvite>(),
+            ],
+        ),
+        (
+            "设备",
+            vec![
+                op("GET", "/api/devices", "list_devices", "设备列表，可使用已保存的视图")
+                    .query::<DeviceListQuery>()
+                    .reply::<DeviceListResponse>(),
+                op("GET", "/api/device-views", "list_device_views", "当前用户的设备视图").reply::<Vec<DeviceView>>(),
+                op("POST", "/api/device-views", "create_device_view", "保存设备视图")
+                    .body::<SaveDeviceViewRequest>()
+                    .reply::<DeviceView>(),
+                op("PUT", "/api/device-views/:id", "update_device_view", "修改设备视图")
+                    .body::<SaveDeviceViewRequest>()
+                    .reply::<DeviceView>(),
+                op("DELETE", "/api/device-views/:id", "delete_device_view", "删除设备视图").reply::<()>(),
+                op("GET", "/api/devices/:id", "get_device", "设备详情")
+                    .unimplemented()
+                    .reply::<DeviceInfo>(),
+                op("PUT", "/api/devices/:id", "update_device", "修改设备")
+                    .unimplemented()
+                    .reply::<DeviceInfo>(),
+                op("DELETE", "/api/devices/:id", "delete_device", "删除设备")
+                    .unimplemented()
+                    .reply::<()>(),
+                op("POST", "/api/devices/:id/control", "control_device", "发起远程控制").reply::<String>(),
+                op(
+                    "POST",
+                    "/api/webrtc/sessions",
+                    "create_webrtc_session",
+                    "浏览器发起 WebRTC 会话",
+                )
+                .body::<CreateWebRtcSessionRequest>()
+                .reply::<SessionOffer>(),
+                op(
+                    "DELETE",
+                    "/api/webrtc/sessions/:id",
+                    "close_webrtc_session",
+                    "结束 WebRTC 会话",
+                )
+                .reply::<()>(),
+                op(
+                    "POST",
+                    "/api/webrtc/sessions/:id/signal",
+                    "webrtc_signal",
+                    "交换 WebRTC 候选并取回应答",
+                )
+                .body::<WebRtcSignalRequest>()
+                .reply::<ControllerUpdate>(),
+                op(
+                    "GET",
+                    "/api/turn/credentials",
+                    "get_turn_credentials",
+                    "签发 TURN 临时凭据",
+                )
+                .reply::<TurnCredentials>(),
+                op("POST", "/api/devices/:id/wake", "wake_device", "网络唤醒")
+                    .body::<WakeDeviceRequest>()
+                    .reply::<WakeResult>(),
+                op(
+                    "GET",
+                    "/api/devices/:id/inventory",
+                    "get_device_inventory",
+                    "设备的软硬件信息",
+                )
+                .reply::<DeviceInventory>(),
+                op(
+                    "GET",
+                    "/api/devices/:id/capabilities",
+                    "get_device_capabilities",
+                    "设备上报的视频编码器及硬件加速",
+                )
+                .reply::<DeviceCapabilities>(),
+                op(
+                    "GET",
+                    "/api/devices/:id/fields",
+                    "get_device_fields",
+                    "设备的自定义字段",
+                )
+                .reply::<Fields>(),
+                op(
+                    "PUT",
+                    "/api/devices/:id/fields",
+                    "set_device_fields",
+                    "设置设备的自定义字段",
+                )
+                .body::<FieldValuesUpdate>()
+                .reply::<Fields>(),
+                op(
+                    "GET",
+                    "/api/custom-fields/:entity/search",
+                    "search_custom_fields",
+                    "按自定义字段查询设备或用户",
+                )
+                .query::<CustomFieldSearchQuery>()
+                .reply::<Vec<FieldValues>>(),
+                op("GET", "/api/devices/:id/uptime", "get_device_uptime", "设备在线率")
+                    .query::<UptimeQuery>()
+                    .reply::<UptimeReport>(),
+                op(
+                    "GET",
+                    "/api/devices/:id/status-history",
+                    "get_device_status_history",
+                    "设备上下线记录",
+                )
+                .query::<UptimeQuery>()
+                .reply::<Vec<StatusChange>>(),
+                op(
+                    "GET",
+                    "/api/devices/:id/timeline",
+                    "get_device_timeline",
+                    "设备排障时间线(上下线、会话、告警、管理操作和备注)",
+                )
+                .query::<TimelineQuery>()
+                .reply::<Vec<TimelineEntry>>(),
+                op("GET", "/api/devices/:id/pk-history", "get_device_pk_history", "设备出现过的公钥")
+                    .reply::<Vec<PkRecord>>(),
+                op("GET", "/api/devices/:id/notes", "list_device_notes", "设备备注").reply::<Vec<DeviceNote>>(),
+                op("POST", "/api/devices/:id/notes", "create_device_note", "添加设备备注")
+                    .body::<SaveDeviceNoteRequest>()
+                    .reply::<DeviceNote>(),
+                op(
+                    "PUT",
+                    "/api/devices/:id/notes/:note_id",
+                    "update_device_note",
+                    "修改设备备注(作者或超级管理员)",
+                )
+                .body::<SaveDeviceNoteRequest>()
+                .reply::<DeviceNote>(),
+                op(
+                    "DELETE",
+                    "/api/devices/:id/notes/:note_id",
+                    "delete_device_note",
+                    "删除设备备注(作者或超级管理员)",
+                )
+                .reply::<()>(),
+                op(
+                    "GET",
+                    "/api/devices/:id/quality",
+                    "get_device_quality",
+                    "设备的连接质量趋势",
+                )
+                .query::<QualityQuery>()
+                .reply::<QualityTrend>(),
+                op("GET", "/api/uptime", "list_device_uptime", "各设备在线率")
+                    .query::<UptimeQuery>()
+                    .reply::<Vec<UptimeReport>>(),
+                op("GET", "/api/inventory", "list_inventory", "资产清单")
+                    .query::<InventoryQuery>()
+                    .reply::<Vec<DeviceInventory>>(),
+                op("GET", "/api/inventory/report", "get_inventory_report", "资产统计").reply::<InventoryReport>(),
+                op(
+                    "GET",
+                    "/api/devices/:id/nat-diagnostics",
+                    "get_device_nat_diagnostics",
+                    "设备的打洞诊断",
+                )
+                .reply::<PeerNatDiagnostics>(),
+                op(
+                    "GET",
+                    "/api/devices/:id/self-tests",
+                    "get_device_self_tests",
+                    "设备最近的连通性自检结果",
+                )
+                .reply::<Vec<SelfTestResult>>(),
+                op("PUT", "/api/devices/:id/alias", "set_device_alias", "设置设备别名")
+                    .body::<SetAliasRequest>()
+                    .reply::<()>(),
+                op(
+                    "DELETE",
+                    "/api/devices/:id/alias",
+                    "remove_device_alias",
+                    "删除设备别名",
+                )
+                .reply::<()>(),
+                op("GET", "/api/aliases", "list_device_aliases", "设备别名列表").reply::<Vec<DeviceAlias>>(),
+                op("POST", "/api/devices/:id/ban", "ban_device", "封禁设备")
+                    .body::<BanDeviceRequest>()
+                    .reply::<usize>(),
+                op("DELETE", "/api/devices/:id/ban", "unban_device", "解除封禁").reply::<()>(),
+                op("GET", "/api/bans", "list_device_bans", "封禁列表").reply::<Vec<DeviceBan>>(),
+                op(
+                    "GET",
+                    "/api/devices/:id/strategy",
+                    "get_device_strategy",
+                    "设备生效的策略",
+                )
+                .reply::<EffectiveStrategy>(),
+                op(
+                    "GET",
+                    "/api/devices/:id/password-policy",
+                    "get_device_password_policy",
+                    "设备生效的密码策略",
+                )
+                .reply::<PasswordPolicy>(),
+            ],
+        ),
+        (
+            "策略",
+            vec![
+                op("GET", "/api/strategies", "list_strategies", "策略列表").reply::<Vec<Strategy>>(),
+                op("POST", "/api/strategies", "create_strategy", "创建策略")
+                    .body::<SaveStrategyRequest>()
+                    .reply::<Strategy>(),
+                op("PUT", "/api/strategies/:id", "update_strategy", "修改策略")
+                    .body::<SaveStrategyRequest>()
+                    .reply::<Strategy>(),
+                op("DELETE", "/api/strategies/:id", "delete_strategy", "删除策略").reply::<()>(),
+            ],
+        ),
+        (
+            "客户端更新",
+            vec![
+                op("GET", "/api/updates", "list_update_artifacts", "安装包列表").reply::<Vec<UpdateArtifact>>(),
+                op(
+                    "PUT",
+                    "/api/updates/:platform/:version",
+                    "upload_update_artifact",
+                    "上传安装包",
+                )
+                .query::<UploadArtifactQuery>()
+                .binary_body()
+                .reply::<UpdateArtifact>(),
+                op(
+                    "DELETE",
+                    "/api/updates/:platform/:version",
+                    "delete_update_artifact",
+                    "删除安装包",
+                )
+                .reply::<()>(),
+            ],
+        ),
+        (
+            "远程任务和消息",
+            vec![
+                op("GET", "/api/jobs", "list_jobs", "远程任务列表")
+                    .query::<PaginationQuery>()
+                    .reply::<Vec<Job>>(),
+                op("POST", "/api/jobs", "create_job", "创建远程任务")
+                    .body::<CreateJobRequest>()
+                    .reply::<Job>(),
+                op("GET", "/api/jobs/:id", "get_job", "远程任务及各设备的执行结果").reply::<JobDetail>(),
+                op("DELETE", "/api/jobs/:id", "cancel_job", "取消远程任务").reply::<()>(),
+                op("GET", "/api/messages", "list_messages", "消息列表")
+                    .query::<PaginationQuery>()
+                    .reply::<Vec<DeviceMessage>>(),
+                op("POST", "/api/messages", "create_message", "向设备发送消息")
+                    .body::<CreateMessageRequest>()
+                    .reply::<DeviceMessage>(),
+                op("GET", "/api/messages/:id", "get_message", "消息及各设备的回执").reply::<MessageDetail>(),
+                op("DELETE", "/api/messages/:id", "cancel_message", "撤回消息").reply::<()>(),
+                op("GET", "/api/announcements", "list_announcements", "公告列表")
+                    .query::<PaginationQuery>()
+                    .reply::<Vec<Announcement>>(),
+                op("POST", "/api/announcements", "create_announcement", "发布公告")
+                    .body::<SaveAnnouncementRequest>()
+                    .reply::<Announcement>(),
+                op(
+                    "GET",
+                    "/api/announcements/active",
+                    "list_active_announcements",
+                    "生效中的公告",
+                )
+                .reply::<Vec<Announcement>>(),
+                op("PUT", "/api/announcements/:id", "update_announcement", "修改公告")
+                    .body::<SaveAnnouncementRequest>()
+                    .reply::<Announcement>(),
+                op("DELETE", "/api/announcements/:id", "delete_announcement", "删除公告").reply::<()>(),
+            ],
+        ),
+        (
+            "会话",
+            vec![
+                op("GET", "/api/sessions", "list_sessions", "进行中的中继会话").reply::<Vec<SessionInfo>>(),
+                op("GET", "/api/relays", "list_relays", "各中继服务器的会话数和排空状态").reply::<Vec<RelayStatus>>(),
+                op(
+                    "PUT",
+                    "/api/relays/drain",
+                    "update_relay_drain",
+                    "开始或取消排空中继服务器",
+                )
+                .body::<RelayDrainRequest>()
+                .reply::<()>(),
+                op(
+                    "GET",
+                    "/api/settings/federation",
+                    "get_federation_config",
+                    "信令服务器联邦配置，密钥以掩码返回",
+                )
+                .reply::<FederationConfig>(),
+                op(
+                    "PUT",
+                    "/api/settings/federation",
+                    "update_federation_config",
+                    "修改信令服务器联邦配置",
+                )
+                .body::<FederationConfig>()
+                .reply::<FederationConfig>(),
+                op(
+                    "GET",
+                    "/api/settings/dns-discovery",
+                    "get_dns_discovery_config",
+                    "DNS 服务发现配置",
+                )
+                .reply::<DnsDiscoveryConfig>(),
+                op(
+                    "PUT",
+                    "/api/settings/dns-discovery",
+                    "update_dns_discovery_config",
+                    "修改 DNS 服务发现配置",
+                )
+                .body::<DnsDiscoveryConfig>()
+                .reply::<DnsDiscoveryConfig>(),
+                op(
+                    "GET",
+                    "/api/dns-discovery/records",
+                    "get_dns_discovery_records",
+                    "应发布的 SRV/TXT 记录及区域文件",
+                )
+                .reply::<DnsRecords>(),
+                op(
+                    "GET",
+                    "/api/dns-discovery/health",
+                    "check_dns_discovery_health",
+                    "解析已发布的记录并与当前配置比对",
+                )
+                .reply::<DiscoveryHealth>(),
+                op("GET", "/api/captures", "list_captures", "协议抓包列表").reply::<Vec<Capture>>(),
+                op("POST", "/api/captures", "start_capture", "对指定设备开启限时协议抓包")
+                    .body::<StartCaptureRequest>()
+                    .reply::<Capture>(),
+                op("DELETE", "/api/captures/:id", "delete_capture", "删除协议抓包").reply::<()>(),
+                op("POST", "/api/captures/:id/stop", "stop_capture", "停止协议抓包").reply::<Capture>(),
+                op(
+                    "GET",
+                    "/api/captures/:id/bundle",
+                    "download_capture_bundle",
+                    "下载抓包诊断包",
+                )
+                .download(),
+                op(
+                    "PUT",
+                    "/api/sessions/:id/permissions",
+                    "update_session_permissions",
+                    "修改会话的剪贴板和文件传输权限",
+                )
+                .body::<PermissionChange>()
+                .reply::<SessionPermissions>(),
+                op("GET", "/api/sessions/:id/events", "get_session_events", "会话事件")
+                    .query::<PaginationQuery>()
+                    .reply::<SessionEventsDetail>(),
+                op(
+                    "GET",
+                    "/api/sessions/:id/codec",
+                    "get_session_codec",
+                    "会话的编码推荐及回执",
+                )
+                .reply::<Vec<CodecRecommendation>>(),
+                op(
+                    "PUT",
+                    "/api/sessions/:id/congestion-control",
+                    "update_session_congestion_control",
+                    "指定中继会话的拥塞控制算法",
+                )
+                .body::<CongestionControlRequest>()
+                .reply::<()>(),
+                op("GET", "/api/approvals", "list_session_approvals", "待审批的会话").reply::<Vec<ApprovalRequest>>(),
+                op("POST", "/api/approvals/:id/approve", "approve_session", "批准会话").reply::<ApprovalRequest>(),
+                op("POST", "/api/approvals/:id/reject", "reject_session", "拒绝会话").reply::<ApprovalRequest>(),
+                op("GET", "/api/break-glass", "list_emergency_access", "紧急访问记录")
+                    .query::<EmergencyAccessQuery>()
+                    .reply::<Vec<EmergencyAccess>>(),
+                op("POST", "/api/break-glass", "activate_emergency_access", "发起紧急访问")
+                    .body::<CreateEmergencyAccessRequest>()
+                    .reply::<EmergencyAccess>(),
+                op("DELETE", "/api/break-glass/:id", "end_emergency_access", "结束紧急访问").reply::<EmergencyAccess>(),
+                op(
+                    "POST",
+                    "/api/break-glass/:id/review",
+                    "review_emergency_access",
+                    "复核紧急访问",
+                )
+                .body::<ReviewEmergencyAccessRequest>()
+                .reply::<EmergencyAccess>(),
+                op(
+                    "POST",
+                    "/api/admin/impersonate",
+                    "start_impersonation",
+                    "以其他用户的身份操作(代操作)",
+                )
+                .body::<ImpersonateRequest>()
+                .reply::<ImpersonationGrant>(),
+                op("GET", "/api/admin/impersonations", "list_impersonations", "代操作记录")
+                    .query::<PaginationQuery>()
+                    .reply::<Vec<ImpersonationSession>>(),
+            ],
+        ),
+        (
+            "审计和统计",
+            vec![
+                op("GET", "/api/audit-logs", "get_audit_logs", "审计日志")
+                    .query::<AuditLogQuery>()
+                    .reply::<AuditLogResponse>(),
+                op("GET", "/api/stats/dashboard", "get_dashboard_stats", "仪表盘统计").reply::<HashMap<String, u64>>(),
+                op("GET", "/api/stats/connections", "get_connection_stats", "连接统计")
+                    .unimplemented()
+                    .reply::<HashMap<String, u64>>(),
+                op("GET", "/api/stats/nat", "get_nat_stats", "打洞统计").reply::<NatStatsSummary>(),
+                op(
+                    "GET",
+                    "/api/stats/relay-quality",
+                    "get_relay_quality",
+                    "各中继服务器的连接质量",
+                )
+                .query::<QualityQuery>()
+                .reply::<Vec<QualityTrend>>(),
+                op("GET", "/api/stats/e2e", "get_e2e_stats", "端到端加密协商统计")
+                    .query::<E2eStatsQuery>()
+                    .reply::<E2eStats>(),
+                op(
+                    "GET",
+                    "/api/stats/bandwidth-pools",
+                    "get_bandwidth_pools",
+                    "会话带宽池分配",
+                )
+                .reply::<Vec<PoolUsage>>(),
+                op("GET", "/api/stats/host", "get_host_stats", "本机网络统计").reply::<HostStatsReport>(),
+                op("GET", "/metrics", "get_metrics", "Prometheus 指标").text_reply(),
+                op("GET", "/api/security-events", "list_security_events", "安全事件")
+                    .query::<SecurityEventsQuery>()
+                    .reply::<Vec<SecurityEvent>>(),
+                op(
+                    "GET",
+                    "/api/alerts/offline",
+                    "list_offline_devices",
+                    "当前离线告警的设备",
+                )
+                .reply::<Vec<OfflineDevice>>(),
+                op(
+                    "GET",
+                    "/api/kiosk/health",
+                    "get_kiosk_fleet_health",
+                    "自助终端机群健康汇总(NOC 大屏)",
+                )
+                .reply::<FleetHealth>(),
+            ],
+        ),
+        (
+            "设置",
+            vec![
+                op("GET", "/api/settings", "get_settings", "全部设置项").reply::<HashMap<String, String>>(),
+                op("PUT", "/api/settings", "update_settings", "修改设置项")
+                    .body::<SettingsUpdate>()
+                    .reply::<()>(),
+                op("GET", "/api/settings/relay-policy", "get_relay_policy", "中继策略").reply::<RelayPolicy>(),
+                op(
+                    "PUT",
+                    "/api/settings/relay-policy",
+                    "update_relay_policy",
+                    "修改中继策略",
+                )
+                .body::<RelayPolicy>()
+                .reply::<RelayPolicy>(),
+                op("GET", "/api/settings/logging", "get_logging_config", "日志配置").reply::<LoggingConfig>(),
+                op("PUT", "/api/settings/logging", "update_logging_config", "修改日志配置")
+                    .body::<LoggingConfig>()
+                    .reply::<LoggingConfig>(),
+                op(
+                    "GET",
+                    "/api/settings/offline-alerts",
+                    "get_offline_alert_config",
+                    "离线告警配置",
+                )
+                .reply::<OfflineAlertConfig>(),
+                op(
+                    "PUT",
+                    "/api/settings/offline-alerts",
+                    "update_offline_alert_config",
+                    "修改离线告警配置",
+                )
+                .body::<OfflineAlertConfig>()
+                .reply::<OfflineAlertConfig>(),
+                op(
+                    "GET",
+                    "/api/settings/kiosk-fleet",
+                    "get_kiosk_fleet_config",
+                    "自助终端机群配置(签到间隔和告警升级)",
+                )
+                .reply::<KioskFleetConfig>(),
+                op(
+                    "PUT",
+                    "/api/settings/kiosk-fleet",
+                    "update_kiosk_fleet_config",
+                    "修改自助终端机群配置",
+                )
+                .body::<KioskFleetConfig>()
+                .reply::<KioskFleetConfig>(),
+                op(
+                    "GET",
+                    "/api/settings/custom-fields",
+                    "get_custom_fields_config",
+                    "自定义字段定义",
+                )
+                .reply::<CustomFieldsConfig>(),
+                op(
+                    "PUT",
+                    "/api/settings/custom-fields",
+                    "update_custom_fields_config",
+                    "修改自定义字段定义",
+                )
+                .body::<CustomFieldsConfig>()
+                .reply::<CustomFieldsConfig>(),
+                op("GET", "/api/settings/backup", "get_backup_config", "备份配置").reply::<BackupConfig>(),
+                op("PUT", "/api/settings/backup", "update_backup_config", "修改备份配置")
+                    .body::<BackupConfig>()
+                    .reply::<BackupConfig>(),
+                op(
+                    "GET",
+                    "/api/settings/version-policy",
+                    "get_version_policy",
+                    "客户端版本策略",
+                )
+                .reply::<VersionPolicy>(),
+                op(
+                    "PUT",
+                    "/api/settings/version-policy",
+                    "update_version_policy",
+                    "修改客户端版本策略",
+                )
+                .body::<VersionPolicy>()
+                .reply::<VersionPolicy>(),
+                op(
+                    "GET",
+                    "/api/settings/unattended-access",
+                    "get_unattended_access",
+                    "无人值守访问策略",
+                )
+                .reply::<UnattendedPolicy>(),
+                op(
+                    "PUT",
+                    "/api/settings/unattended-access",
+                    "update_unattended_access",
+                    "修改无人值守访问策略",
+                )
+                .body::<UnattendedPolicy>()
+                .reply::<UnattendedPolicy>(),
+                op(
+                    "GET",
+                    "/api/settings/holiday-calendars",
+                    "get_holiday_calendars",
+                    "节假日日历和维护窗口",
+                )
+                .reply::<CalendarConfig>(),
+                op(
+                    "PUT",
+                    "/api/settings/holiday-calendars",
+                    "update_holiday_calendars",
+                    "修改节假日日历和维护窗口",
+                )
+                .body::<CalendarConfig>()
+                .reply::<CalendarConfig>(),
+                op(
+                    "POST",
+                    "/api/settings/holiday-calendars/import",
+                    "import_holiday_calendar",
+                    "导入 iCalendar 文件为节假日日历",
+                )
+                .query::<HolidayImportQuery>()
+                .binary_body()
+                .reply::<HolidayCalendar>(),
+                op("GET", "/api/settings/four-eyes", "get_four_eyes_policy", "四眼审批策略").reply::<FourEyesPolicy>(),
+                op(
+                    "PUT",
+                    "/api/settings/four-eyes",
+                    "update_four_eyes_policy",
+                    "修改四眼审批策略",
+                )
+                .body::<FourEyesPolicy>()
+                .reply::<FourEyesPolicy>(),
+                op("GET", "/api/settings/lan", "get_lan_config", "局域网配置").reply::<LanConfig>(),
+                op("PUT", "/api/settings/lan", "update_lan_config", "修改局域网配置")
+                    .body::<LanConfig>()
+                    .reply::<LanConfig>(),
+                op("GET", "/api/settings/id-policy", "get_id_policy", "设备ID策略").reply::<IdPolicy>(),
+                op("PUT", "/api/settings/id-policy", "update_id_policy", "修改设备ID策略")
+                    .body::<IdPolicy>()
+                    .reply::<IdPolicy>(),
+                op("GET", "/api/settings/mfa-policy", "get_mfa_policy", "多因素认证策略").reply::<MfaPolicy>(),
+                op(
+                    "PUT",
+                    "/api/settings/mfa-policy",
+                    "update_mfa_policy",
+                    "修改多因素认证策略",
+                )
+                .body::<MfaPolicy>()
+                .reply::<MfaPolicy>(),
+                op("GET", "/api/settings/e2e-policy", "get_e2e_policy", "端到端加密策略").reply::<E2ePolicy>(),
+                op(
+                    "PUT",
+                    "/api/settings/e2e-policy",
+                    "update_e2e_policy",
+                    "修改端到端加密策略",
+                )
+                .body::<E2ePolicy>()
+                .reply::<E2ePolicy>(),
+                op("GET", "/api/settings/server-key", "get_server_key", "服务器密钥状态").reply::<KeyRingInfo>(),
+                op(
+                    "POST",
+                    "/api/settings/server-key/rotate",
+                    "rotate_server_key",
+                    "轮换服务器密钥",
+                )
+                .body::<RotateServerKeyRequest>()
+                .reply::<KeyRingInfo>(),
+                op("GET", "/api/settings/jwt-keys", "get_jwt_keys", "JWT签名密钥状态(不含密钥)")
+                    .reply::<JwtKeyInfo>(),
+                op(
+                    "POST",
+                    "/api/settings/jwt-keys/rotate",
+                    "rotate_jwt_keys",
+                    "轮换JWT签名密钥，宽限期内仍接受原密钥签发的令牌",
+                )
+                .body::<RotateJwtKeysRequest>()
+                .reply::<JwtKeyInfo>(),
+                op(
+                    "GET",
+                    "/api/settings/control-tls",
+                    "get_control_tls",
+                    "中继控制通道TLS状态: CA证书和已签发的中继证书",
+                )
+                .reply::<ControlTlsStatus>(),
+                op(
+                    "PUT",
+                    "/api/settings/control-tls",
+                    "update_control_tls",
+                    "启用或停用中继控制通道双向TLS",
+                )
+                .body::<UpdateControlTlsRequest>()
+                .reply::<ControlTlsStatus>(),
+                op(
+                    "POST",
+                    "/api/settings/control-tls/ca",
+                    "create_control_tls_ca",
+                    "生成中继控制通道CA，已签发的证书全部作废",
+                )
+                .reply::<ControlTlsStatus>(),
+                op(
+                    "POST",
+                    "/api/settings/control-tls/certs",
+                    "issue_control_tls_cert",
+                    "签发中继证书，私钥只返回一次",
+                )
+                .body::<IssueControlCertRequest>()
+                .reply::<IssuedBundle>(),
+                op(
+                    "DELETE",
+                    "/api/settings/control-tls/certs/:fingerprint",
+                    "revoke_control_tls_cert",
+                    "吊销中继证书",
+                )
+                .reply::<IssuedCert>(),
+                op(
+                    "GET",
+                    "/api/settings/email-otp",
+                    "get_email_otp_config",
+                    "邮件服务器配置",
+                )
+                .reply::<SmtpConfig>(),
+                op(
+                    "PUT",
+                    "/api/settings/email-otp",
+                    "update_email_otp_config",
+                    "修改邮件服务器配置",
+                )
+                .body::<SmtpConfig>()
+                .reply::<SmtpConfig>(),
+                op("GET", "/api/settings/dlp-policy", "get_dlp_policy", "数据防泄漏策略").reply::<DlpPolicy>(),
+                op(
+                    "PUT",
+                    "/api/settings/dlp-policy",
+                    "update_dlp_policy",
+                    "修改数据防泄漏策略",
+                )
+                .body::<DlpPolicy>()
+                .reply::<DlpPolicy>(),
+                op(
+                    "GET",
+                    "/api/settings/transfer-bandwidth",
+                    "get_transfer_bandwidth",
+                    "文件传输带宽策略",
+                )
+                .reply::<BandwidthPolicy>(),
+                op(
+                    "PUT",
+                    "/api/settings/transfer-bandwidth",
+                    "update_transfer_bandwidth",
+                    "修改文件传输带宽策略",
+                )
+                .body::<BandwidthPolicy>()
+                .reply::<BandwidthPolicy>(),
+                op(
+                    "GET",
+                    "/api/settings/network-tuning",
+                    "get_network_tuning",
+                    "连接保活与超时设置",
+                )
+                .reply::<NetworkTuning>(),
+                op(
+                    "PUT",
+                    "/api/settings/network-tuning",
+                    "update_network_tuning",
+                    "修改连接保活与超时设置",
+                )
+                .body::<NetworkTuning>()
+                .reply::<NetworkTuning>(),
+                op("GET", "/api/settings/webrtc", "get_webrtc_config", "WebRTC 信令配置").reply::<WebRtcConfig>(),
+                op(
+                    "PUT",
+                    "/api/settings/webrtc",
+                    "update_webrtc_config",
+                    "修改 WebRTC 信令配置",
+                )
+                .body::<WebRtcConfig>()
+                .reply::<WebRtcConfig>(),
+                op("GET", "/api/settings/turn", "get_turn_config", "TURN 临时凭据配置").reply::<TurnConfig>(),
+                op(
+                    "PUT",
+                    "/api/settings/turn",
+                    "update_turn_config",
+                    "修改 TURN 临时凭据配置",
+                )
+                .body::<TurnConfig>()
+                .reply::<TurnConfig>(),
+                op("GET", "/api/settings/honeypot", "get_honeypot_config", "诱饵设备ID配置").reply::<HoneypotConfig>(),
+                op(
+                    "PUT",
+                    "/api/settings/honeypot",
+                    "update_honeypot_config",
+                    "修改诱饵设备ID配置",
+                )
+                .body::<HoneypotConfig>()
+                .reply::<HoneypotConfig>(),
+                op(
+                    "GET",
+                    "/api/honeypot/ip-bans",
+                    "list_honeypot_ip_bans",
+                    "诱饵触发的IP封禁列表",
+                )
+                .reply::<Vec<IpBan>>(),
+                op("DELETE", "/api/honeypot/ip-bans/:ip", "unban_honeypot_ip", "解除IP封禁").reply::<()>(),
+                op("GET", "/api/settings/pk-pinning", "get_pk_pinning_config", "设备公钥固定配置")
+                    .reply::<PkPinningConfig>(),
+                op(
+                    "PUT",
+                    "/api/settings/pk-pinning",
+                    "update_pk_pinning_config",
+                    "修改设备公钥固定配置",
+                )
+                .body::<PkPinningConfig>()
+                .reply::<PkPinningConfig>(),
+                op("GET", "/api/pk-changes", "list_pk_changes", "待审批的设备公钥变化").reply::<Vec<PkRecord>>(),
+                op(
+                    "POST",
+                    "/api/pk-changes/:id/approve",
+                    "approve_pk_change",
+                    "批准设备公钥变化，解除隔离",
+                )
+                .reply::<PkRecord>(),
+                op("POST", "/api/pk-changes/:id/reject", "reject_pk_change", "驳回设备公钥变化")
+                    .reply::<PkRecord>(),
+                op(
+                    "GET",
+                    "/api/settings/threat-intel",
+                    "get_threat_intel_config",
+                    "威胁情报IP源配置",
+                )
+                .reply::<ThreatIntelConfig>(),
+                op(
+                    "PUT",
+                    "/api/settings/threat-intel",
+                    "update_threat_intel_config",
+                    "修改威胁情报IP源配置",
+                )
+                .body::<ThreatIntelConfig>()
+                .reply::<ThreatIntelConfig>(),
+                op(
+                    "GET",
+                    "/api/threat-intel/status",
+                    "get_threat_intel_status",
+                    "威胁情报列表状态与命中统计",
+                )
+                .reply::<ThreatIntelStatus>(),
+                op(
+                    "POST",
+                    "/api/threat-intel/refresh",
+                    "refresh_threat_intel",
+                    "立即下载威胁情报列表",
+                )
+                .reply::<ThreatIntelStatus>(),
+                op(
+                    "GET",
+                    "/api/settings/change-approval",
+                    "get_change_approval_config",
+                    "需要审批的设置",
+                )
+                .reply::<ChangeApprovalConfig>(),
+                op(
+                    "PUT",
+                    "/api/settings/change-approval",
+                    "update_change_approval_config",
+                    "修改需要审批的设置(已启用审批时同样需要审批)",
+                )
+                .body::<ChangeApprovalConfig>()
+                .reply::<ChangeApprovalConfig>(),
+                op(
+                    "GET",
+                    "/api/setting-changes",
+                    "list_setting_changes",
+                    "设置变更审批队列(含差异)",
+                )
+                .query::<SettingChangeQuery>()
+                .reply::<Vec<SettingChange>>(),
+                op(
+                    "POST",
+                    "/api/setting-changes/:id/approve",
+                    "approve_setting_change",
+                    "批准设置变更(不能批准自己提交的变更)",
+                )
+                .body::<ReviewSettingChangeRequest>()
+                .reply::<SettingChange>(),
+                op(
+                    "POST",
+                    "/api/setting-changes/:id/reject",
+                    "reject_setting_change",
+                    "驳回设置变更",
+                )
+                .body::<ReviewSettingChangeRequest>()
+                .reply::<SettingChange>(),
+                op(
+                    "POST",
+                    "/api/setting-changes/:id/cancel",
+                    "cancel_setting_change",
+                    "撤回自己提交的设置变更",
+                )
+                .reply::<SettingChange>(),
+                op(
+                    "GET",
+                    "/api/settings/versions",
+                    "list_config_versions",
+                    "策略设置的配置版本",
+                )
+                .query::<ConfigVersionQuery>()
+                .reply::<Vec<ConfigVersion>>(),
+                op(
+                    "GET",
+                    "/api/settings/versions/diff",
+                    "diff_config_versions",
+                    "比较两个配置版本",
+                )
+                .query::<ConfigDiffQuery>()
+                .reply::<Vec<SettingDiff>>(),
+                op(
+                    "POST",
+                    "/api/settings/rollback",
+                    "rollback_config",
+                    "回滚到历史配置版本",
+                )
+                .body::<RollbackRequest>()
+                .reply::<RollbackReport>(),
+                op(
+                    "GET",
+                    "/api/settings/content-scan",
+                    "get_content_scan_config",
+                    "文件内容扫描配置",
+                )
+                .reply::<ScanConfig>(),
+                op(
+                    "PUT",
+                    "/api/settings/content-scan",
+                    "update_content_scan_config",
+                    "修改文件内容扫描配置",
+                )
+                .body::<ScanConfig>()
+                .reply::<ScanConfig>(),
+                op(
+                    "GET",
+                    "/api/settings/trusted-device",
+                    "get_trusted_device_config",
+                    "受信任浏览器配置",
+                )
+                .reply::<TrustedDeviceConfig>(),
+                op(
+                    "PUT",
+                    "/api/settings/trusted-device",
+                    "update_trusted_device_config",
+                    "修改受信任浏览器配置",
+                )
+                .body::<TrustedDeviceConfig>()
+                .reply::<TrustedDeviceConfig>(),
+                op(
+                    "GET",
+                    "/api/settings/web-security",
+                    "get_web_security_config",
+                    "跨域来源和安全响应头配置",
+                )
+                .reply::<WebSecurityConfig>(),
+                op(
+                    "PUT",
+                    "/api/settings/web-security",
+                    "update_web_security_config",
+                    "修改跨域来源和安全响应头配置",
+                )
+                .body::<WebSecurityConfig>()
+                .reply::<WebSecurityConfig>(),
+                op("GET", "/api/settings/feature-flags", "get_feature_flags", "功能开关").reply::<FeatureFlags>(),
+                op(
+                    "PUT",
+                    "/api/settings/feature-flags",
+                    "update_feature_flags",
+                    "修改功能开关",
+                )
+                .body::<FeatureFlagsUpdate>()
+                .reply::<FeatureFlags>(),
+                op(
+                    "GET",
+                    "/api/settings/itsm",
+                    "get_itsm_config",
+                    "ServiceNow/Jira 集成配置",
+                )
+                .reply::<ItsmConfig>(),
+                op(
+                    "PUT",
+                    "/api/settings/itsm",
+                    "update_itsm_config",
+                    "修改 ServiceNow/Jira 集成配置",
+                )
+                .body::<ItsmConfig>()
+                .reply::<ItsmConfig>(),
+                op(
+                    "GET",
+                    "/api/settings/ad-sync",
+                    "get_ad_sync_config",
+                    "AD 计算机同步配置",
+                )
+                .reply::<AdSyncConfig>(),
+                op(
+                    "PUT",
+                    "/api/settings/ad-sync",
+                    "update_ad_sync_config",
+                    "修改 AD 计算机同步配置",
+                )
+                .body::<AdSyncConfig>()
+                .reply::<AdSyncConfig>(),
+                op(
+                    "GET",
+                    "/api/settings/web-session",
+                    "get_web_session_config",
+                    "Web 会话超时配置",
+                )
+                .reply::<WebSessionConfig>(),
+                op(
+                    "PUT",
+                    "/api/settings/web-session",
+                    "update_web_session_config",
+                    "修改 Web 会话超时配置",
+                )
+                .body::<WebSessionConfig>()
+                .reply::<WebSessionConfig>(),
+                op(
+                    "GET",
+                    "/api/settings/data-masking",
+                    "get_data_masking_config",
+                    "数据脱敏规则",
+                )
+                .reply::<DataMaskingConfig>(),
+                op(
+                    "PUT",
+                    "/api/settings/data-masking",
+                    "update_data_masking_config",
+                    "修改数据脱敏规则",
+                )
+                .body::<DataMaskingUpdate>()
+                .reply::<DataMaskingConfig>(),
+                op(
+                    "GET",
+                    "/api/admin/ad-sync",
+                    "get_ad_sync_report",
+                    "最近一次 AD 同步结果",
+                )
+                .reply::<AdSyncReport>(),
+                op("POST", "/api/admin/ad-sync", "run_ad_sync", "立即执行 AD 同步").reply::<AdSyncReport>(),
+                op("GET", "/api/license", "get_license", "许可证状态和用量").reply::<LicenseStatus>(),
+                op("PUT", "/api/license", "install_license", "上传许可证")
+                    .body::<InstallLicenseRequest>()
+                    .reply::<LicenseStatus>(),
+                op(
+                    "GET",
+                    "/api/settings/password-policies",
+                    "get_password_policies",
+                    "设备密码策略",
+                )
+                .reply::<PasswordPolicies>(),
+                op(
+                    "PUT",
+                    "/api/settings/password-policies",
+                    "update_password_policies",
+                    "修改设备密码策略",
+                )
+                .body::<PasswordPolicies>()
+                .reply::<i32>(),
+                op("GET", "/api/provisioned-ids", "list_provisioned_ids", "预分配的设备ID")
+                    .query::<PaginationQuery>()
+                    .reply::<Vec<ProvisionedId>>(),
+                op(
+                    "POST",
+                    "/api/provisioned-ids",
+                    "add_provisioned_ids",
+                    "添加预分配的设备ID",
+                )
+                .body::<ProvisionIdsRequest>()
+                .reply::<usize>(),
+                op(
+                    "DELETE",
+                    "/api/provisioned-ids/:id",
+                    "remove_provisioned_id",
+                    "删除预分配的设备ID",
+                )
+                .reply::<()>(),
+            ],
+        ),
+        (
+            "数据管理",
+            vec![
+                op("GET", "/api/admin/backup", "list_backups", "备份文件列表").reply::<Vec<BackupFile>>(),
+                op("POST", "/api/admin/backup", "create_backup", "立即备份")
+                    .body::<CreateBackupRequest>()
+                    .reply::<BackupFile>(),
+                op("GET", "/api/admin/backup/:name", "download_backup", "下载备份文件").download(),
+                op("GET", "/api/admin/erasure", "list_erasure_reports", "数据擦除报告")
+                    .query::<ErasureReportQuery>()
+                    .reply::<Vec<ErasureReport>>(),
+                op(
+                    "POST",
+                    "/api/admin/erasure",
+                    "erase_subject_data",
+                    "擦除用户或设备的个人数据",
+                )
+                .body::<ErasureRequest>()
+                .reply::<ErasureReport>(),
+                op(
+                    "GET",
+                    "/api/admin/erasure/:id",
+                    "get_erasure_report",
+                    "数据擦除报告详情",
+                )
+                .reply::<ErasureReport>(),
+            ],
+        ),
+        (
+            "文件传输",
+            vec![
+                op("GET", "/api/files", "download_file", "下载文件(支持 Range)")
+                    .query::<FileDownloadQuery>()
+                    .download(),
+                op("POST", "/api/files", "create_file_upload", "创建可续传上传")
+                    .body::<CreateUploadRequest>()
+                    .reply::<UploadInfo>(),
+                op(
+                    "HEAD",
+                    "/api/files/:id",
+                    "get_file_upload_offset",
+                    "查询已上传的偏移(Upload-Offset 响应头)",
+                ),
+                op(
+                    "PATCH",
+                    "/api/files/:id",
+                    "upload_file_chunk",
+                    "上传文件块(Upload-Offset 请求头)",
+                )
+                .binary_body()
+                .reply::<TransferProgress>(),
+                op("DELETE", "/api/files/:id", "cancel_file_upload", "取消上传").reply::<()>(),
+                op("GET", "/api/file-transfers", "list_file_transfers", "文件传输记录")
+                    .query::<FileTransferQuery>()
+                    .reply::<Vec<TransferRecord>>(),
+            ],
+        ),
+        (
+            "接口文档",
+            vec![
+                op("GET", "/api/openapi.json", "get_openapi_document", "OpenAPI 文档")
+                    .public()
+                    .raw_reply::<Value>(),
+                op("GET", "/api/docs", "get_api_docs", "Swagger UI").public().html(),
+                op("GET", "/api/errors", "get_error_catalog", "错误码目录")
+                    .public()
+                    .reply::<Vec<ErrorInfo>>(),
+            ],
+        ),
+    ];
+    sections
+        .into_iter()
+        .flat_map(|(tag, ops)| ops.into_iter().map(move |op| Operation { tag, ..op }))
+        .collect()
+}
+
+pub fn document() -> Value {
+    let mut gen = SchemaSettings::openapi3().into_generator();
+    let mut paths = Map::new();
+    for op in operations() {
+        let item = op.to_json(&mut gen);
+        let entry = paths.entry(op.openapi_path()).or_insert_with(|| json!({}));
+        entry[op.method.to_lowercase()] = item;
+    }
+    json!({
+        "openapi": "3.0.3",
+        "info": {
+            "title": "RustDesk Enterprise Server API",
+            "version": env!("CARGO_PKG_VERSION"),
+        },
+        "paths": paths,
+        "components": {
+            "schemas": gen.take_definitions(),
+            "securitySchemes": {
+                "bearerAuth": {"type": "http", "scheme": "bearer", "bearerFormat": "JWT"},
+            },
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn collect_refs(value: &Value, refs: &mut Vec<String>) {
+        match value {
+            Value::Object(map) => {
+                for (k, v) in map.iter() {
+                    match (k.as_str(), v) {
+                        ("$ref", Value::String(r)) => refs.push(r.clone()),
+                        _ => collect_refs(v, refs),
+                    }
+                }
+            }
+            Value::Array(values) => values.iter().for_each(|v| collect_refs(v, refs)),
+            _ => {}
+        }
+    }
+
+    #[test]
+    fn test_openapi_document() {
+        // 与 create_router 注册的路由一致
+        let mut routes = crate::web_api::authz_tests::router_routes();
+        let mut documented: Vec<_> = operations()
+            .iter()
+            .map(|op| (op.method.to_owned(), op.path.to_owned()))
+            .collect();
+        routes.sort();
+        documented.sort();
+        assert_eq!(routes, documented);
+
+        // 引用的结构都已定义
+        let doc = document();
+        let mut refs = vec![];
+        collect_refs(&doc, &mut refs);
+        assert!(!refs.is_empty());
+        for r in refs {
+            let name = r.strip_prefix("#/components/schemas/").unwrap();
+            assert!(doc["components"]["schemas"].get(name).is_some(), "{}", r);
+        }
+
+        let list_users = &doc["paths"]["/api/users"]["get"];
+        assert_eq!(list_users["parameters"][0]["in"], "query");
+        assert!(list_users["security"].is_array());
+        assert!(doc["paths"]["/api/heartbeat"]["post"].get("security").is_none());
+        assert!(doc["paths"]["/api/devices/{id}/uptime"]["get"].is_object());
+        assert!(doc["paths"]["/api/users"]["post"]["responses"]["400"].is_object());
+        assert!(list_users["responses"]["400"].is_object());
+        assert!(doc["paths"]["/api/settings"]["put"]["responses"]["400"].is_object());
+    }
+}
@@ -0,0 +1,563 @@
+// 开源版数据迁移模块 - 在开源版 hbbs 的设备库(db_v2.sqlite3 的 peer 表，记录 id、uuid、公钥)与企业版之间迁移设备：
+//   - `hbbs migrate-from-oss` 导入开源版设备：写入企业版设备表并分配给默认所有者(及设备组)，公钥和uuid复制到
+//     企业版信令服务使用的设备库(DB_URL)，客户端无需重新登记即可继续使用原有ID和密钥连接。可重复执行，已存在的设备跳过
+//   - `hbbs export-to-oss` 反向导出为开源版设备库，用于降级或以开源版 hbbs 作冷备。默认不导出已禁用和已封禁的设备；
+//     可重复执行，目标库中已有的设备更新uuid和公钥
+use crate::database::{Database, Peer};
+use crate::enterprise_database::{AuditLog, DeviceInfo, EnterpriseDatabase};
+use crate::peer::PeerInfo;
+use hbb_common::{bail, log, tokio, ResultType};
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::SystemTime;
+
+// 企业版信令服务拒绝登记公钥的最短ID长度
+const MIN_ID_LEN: usize = 6;
+const SYSTEM_OWNER: &str = "system";
+
+const USAGE: &str =
+    "usage: hbbs migrate-from-oss SOURCE [--db URL] [--peer-db PATH] [--owner USERNAME] [--group GROUP_ID] [--dry-run]";
+const EXPORT_USAGE: &str = "usage: hbbs export-to-oss DEST [--db URL] [--peer-db PATH] [--include-disabled] [--dry-run]";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Options {
+    pub source: String,
+    pub db_url: String,
+    pub peer_db: String,
+    // 导入设备的所有者用户名，None 时归系统所有(与信令服务自动登记的设备一致)
+    pub owner: Option<String>,
+    pub group: Option<String>,
+    pub dry_run: bool,
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Report {
+    pub total: usize,
+    pub imported: u64,
+    pub existing: u64,
+    pub peers_copied: usize,
+    // ID过短等无法在企业版使用的设备
+    pub skipped: Vec<String>,
+}
+
+fn option_value(argv: &[String], name: &str) -> Option<String> {
+    argv.iter()
+        .position(|x| x == name)
+        .and_then(|i| argv.get(i + 1).cloned())
+}
+
+// 检查 argv(不含子命令名)：第一个参数为文件路径，其余为 options 中带值的选项或 flags 中的开关
+fn check_args(argv: &[String], options: &[&str], flags: &[&str]) -> bool {
+    if argv.first().map(|x| x.starts_with("--")).unwrap_or(true) {
+        return false;
+    }
+    let mut i = 1;
+    while i < argv.len() {
+        if flags.contains(&argv[i].as_str()) {
+            i += 1;
+        } else if options.contains(&argv[i].as_str()) && i + 1 < argv.len() {
+            i += 2;
+        } else {
+            return false;
+        }
+    }
+    true
+}
+
+fn db_url_arg(argv: &[String]) -> String {
+    option_value(argv, "--db")
+        .or_else(|| std::env::var("ENTERPRISE_DB_URL").ok())
+        .unwrap_or_else(|| "enterprise.sqlite3".to_owned())
+}
+
+fn peer_db_arg(argv: &[String]) -> String {
+    option_value(argv, "--peer-db")
+        .or_else(|| std::env::var("DB_URL").ok())
+        .unwrap_or_else(|| "./db_v2.sqlite3".to_owned())
+}
+
+// argv 不含子命令名本身
+pub fn parse_options(argv: &[String]) -> Result<Options, String> {
+    if !check_args(argv, &["--db", "--peer-db", "--owner", "--group"], &["--dry-run"]) {
+        return Err(USAGE.to_owned());
+    }
+    Ok(Options {
+        source: argv[0].clone(),
+        db_url: db_url_arg(argv),
+        peer_db: peer_db_arg(argv),
+        owner: option_value(argv, "--owner"),
+        group: option_value(argv, "--group"),
+        dry_run: argv.iter().any(|x| x == "--dry-run"),
+    })
+}
+
+fn same_file(a: &str, b: &str) -> bool {
+    match (std::fs::canonicalize(a), std::fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+fn device_of(peer: &Peer, owner_id: &str, group: Option<&str>, now: SystemTime) -> (DeviceInfo, Option<String>) {
+    let info = serde_json::from_str::<PeerInfo>(&peer.info).unwrap_or_default();
+    let ipv4 = info.ip_of(false);
+    let device = DeviceInfo {
+        id: peer.id.clone(),
+        name: peer.id.clone(),
+        os: String::new(),
+        version: String::new(),
+        ip_address: if ipv4.contains(':') { String::new() } else { ipv4.to_owned() },
+        ipv6_address: Some(info.ipv6.clone()).filter(|x| !x.is_empty()),
+        mac_address: None,
+        // 开源版不记录最后在线时间，以迁移时间为准
+        last_online: now,
+        owner_id: owner_id.to_owned(),
+        group_ids: group.map(|x| vec![x.to_owned()]).unwrap_or_default(),
+        enabled: true,
+        tags: vec![],
+        alias: None,
+    };
+    let uuid = Some(base64::encode(&peer.uuid)).filter(|_| !peer.uuid.is_empty());
+    (device, uuid)
+}
+
+// peers 为企业版信令服务的设备库，与源数据库是同一个文件时(原地升级)传 None
+pub async fn migrate(
+    source: &Database,
+    db: &EnterpriseDatabase,
+    peers: Option<&Database>,
+    options: &Options,
+) -> ResultType<Report> {
+    let owner_id = match &options.owner {
+        Some(username) => match db.get_user_by_username(username).await? {
+            Some(user) => user.id,
+            None => bail!("user {} does not exist", username),
+        },
+        None => SYSTEM_OWNER.to_owned(),
+    };
+    if let Some(group) = &options.group {
+        if !db.device_group_exists(group).await? {
+            bail!("device group {} does not exist", group);
+        }
+    }
+
+    let all = source.list_peers().await?;
+    let mut report = Report {
+        total: all.len(),
+        ..Default::default()
+    };
+    let now = SystemTime::now();
+    let mut devices = vec![];
+    let mut valid = vec![];
+    for peer in all {
+        if peer.id.len() < MIN_ID_LEN {
+            report.skipped.push(peer.id);
+            continue;
+        }
+        devices.push(device_of(&peer, &owner_id, options.group.as_deref(), now));
+        valid.push(peer);
+    }
+    if options.dry_run {
+        return Ok(report);
+    }
+
+    if let Some(peers) = peers {
+        for peer in &valid {
+            if peers.import_peer(peer).await? {
+                report.peers_copied += 1;
+            }
+        }
+    }
+    report.imported = db.import_devices(&devices).await?;
+    report.existing = devices.len() as u64 - report.imported;
+    db.log_audit(&AuditLog {
+        id: 0,
+        user_id: SYSTEM_OWNER.to_owned(),
+        device_id: SYSTEM_OWNER.to_owned(),
+        action: "migrate_from_oss".to_owned(),
+        details: Some(format!(
+            "{} devices imported, {} existing, {} skipped, owner {}",
+            report.imported,
+            report.existing,
+            report.skipped.len(),
+            owner_id
+        )),
+        ip_address: "127.0.0.1".to_owned(),
+        user_agent: None,
+        timestamp: now,
+        success: true,
+    })
+    .await?;
+    Ok(report)
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn run(options: &Options) -> ResultType<Report> {
+    if !Path::new(&options.source).is_file() {
+        bail!("{} does not exist", options.source);
+    }
+    let source = Database::new(&options.source).await?;
+    let db = EnterpriseDatabase::new(&options.db_url).await?;
+    let peers = if same_file(&options.source, &options.peer_db) {
+        None
+    } else {
+        Some(Database::new(&options.peer_db).await?)
+    };
+    migrate(&source, &db, peers.as_ref(), options).await
+}
+
+// `migrate-from-oss` 子命令，返回进程退出码；服务需先停止
+pub fn migrate_command(argv: &[String]) -> i32 {
+    let options = match parse_options(argv) {
+        Ok(options) => options,
+        Err(usage) => {
+            eprintln!("{}", usage);
+            return 2;
+        }
+    };
+    match run(&options) {
+        Ok(report) => {
+            for id in &report.skipped {
+                log::warn!("Peer {} skipped, id shorter than {} characters", id, MIN_ID_LEN);
+            }
+            if options.dry_run {
+                println!(
+                    "{}: {} peers, {} would be imported, {} skipped (dry run)",
+                    options.source,
+                    report.total,
+                    report.total - report.skipped.len(),
+                    report.skipped.len()
+                );
+            } else {
+                println!(
+                    "{}: {} peers, {} devices imported, {} already present, {} keys copied to {}, {} skipped",
+                    options.source,
+                    report.total,
+                    report.imported,
+                    report.existing,
+                    report.peers_copied,
+                    options.peer_db,
+                    report.skipped.len()
+                );
+            }
+            0
+        }
+        Err(e) => {
+            eprintln!("{}: {:#}", options.source, e);
+            1
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExportOptions {
+    pub dest: String,
+    pub db_url: String,
+    pub peer_db: String,
+    pub include_disabled: bool,
+    pub dry_run: bool,
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ExportReport {
+    pub exported: usize,
+    // 尚未登记公钥的设备，导出后由开源版在设备下次注册时要求登记
+    pub without_key: usize,
+    pub disabled: usize,
+    pub banned: usize,
+}
+
+// argv 不含子命令名本身
+pub fn parse_export_options(argv: &[String]) -> Result<ExportOptions, String> {
+    if !check_args(argv, &["--db", "--peer-db"], &["--include-disabled", "--dry-run"]) {
+        return Err(EXPORT_USAGE.to_owned());
+    }
+    Ok(ExportOptions {
+        dest: argv[0].clone(),
+        db_url: db_url_arg(argv),
+        peer_db: peer_db_arg(argv),
+        include_disabled: argv.iter().any(|x| x == "--include-disabled"),
+        dry_run: argv.iter().any(|x| x == "--dry-run"),
+    })
+}
+
+// 开源版的 info 只有 ip 字段
+fn oss_info(ip: &str) -> String {
+    serde_json::json!({ "ip": ip }).to_string()
+}
+
+// 合并企业版设备表和信令服务设备库：设备表中有而设备库中没有公钥的设备以空公钥导出
+pub async fn export(
+    db: &EnterpriseDatabase,
+    peers: &Database,
+    dest: Option<&Database>,
+    options: &ExportOptions,
+) -> ResultType<ExportReport> {
+    crate::device_ban::reload(db).await?;
+    let mut known: HashMap<String, Peer> = peers
+        .list_peers()
+        .await?
+        .into_iter()
+        .map(|x| (x.id.clone(), x))
+        .collect();
+    let mut report = ExportReport::default();
+    let mut out = vec![];
+    for (id, uuid, ip, enabled) in db.list_device_identities().await? {
+        let peer = known.remove(&id);
+        if !enabled && !options.include_disabled {
+            report.disabled += 1;
+            continue;
+        }
+        let mut peer = peer.unwrap_or_else(|| Peer {
+            guid: uuid::Uuid::new_v4().as_bytes().to_vec(),
+            id: id.clone(),
+            uuid: uuid.and_then(|x| base64::decode(x).ok()).unwrap_or_default(),
+            ..Default::default()
+        });
+        peer.info = oss_info(&ip);
+        out.push(peer);
+    }
+    // 设备库中有而设备表中没有的设备(如仅登记过公钥)原样导出
+    for (_, mut peer) in known {
+        let info = serde_json::from_str::<PeerInfo>(&peer.info).unwrap_or_default();
+        peer.info = oss_info(&info.ip);
+        out.push(peer);
+    }
+    for peer in out {
+        if crate::device_ban::is_banned(&peer.id).await
+            || crate::device_ban::is_banned_uuid(&base64::encode(&peer.uuid)).await
+        {
+            report.banned += 1;
+            continue;
+        }
+        if peer.pk.is_empty() {
+            report.without_key += 1;
+        }
+        if let Some(dest) = dest {
+            dest.upsert_peer(&peer).await?;
+        }
+        report.exported += 1;
+    }
+    Ok(report)
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn run_export(options: &ExportOptions) -> ResultType<ExportReport> {
+    if same_file(&options.dest, &options.peer_db) {
+        bail!("{} is the enterprise peer database, choose another destination", options.dest);
+    }
+    let db = EnterpriseDatabase::new(&options.db_url).await?;
+    let peers = Database::new(&options.peer_db).await?;
+    let dest = if options.dry_run {
+        None
+    } else {
+        Some(Database::new(&options.dest).await?)
+    };
+    export(&db, &peers, dest.as_ref(), options).await
+}
+
+// `export-to-oss` 子命令，返回进程退出码
+pub fn export_command(argv: &[String]) -> i32 {
+    let options = match parse_export_options(argv) {
+        Ok(options) => options,
+        Err(usage) => {
+            eprintln!("{}", usage);
+            return 2;
+        }
+    };
+    match run_export(&options) {
+        Ok(report) => {
+            println!(
+                "{}: {} peers {}({} without key), {} disabled and {} banned skipped",
+                options.dest,
+                report.exported,
+                if options.dry_run { "would be exported " } else { "exported " },
+                report.without_key,
+                report.disabled,
+                report.banned
+            );
+            0
+        }
+        Err(e) => {
+            eprintln!("{}: {:#}", options.dest, e);
+            1
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::{User, UserRole};
+
+    fn argv(x: &str) -> Vec<String> {
+        x.split_whitespace().map(|x| x.to_owned()).collect()
+    }
+
+    #[test]
+    fn test_parse_options() {
+        let options = parse_options(&argv("db_v2.sqlite3 --db e.sqlite3 --owner admin --dry-run")).unwrap();
+        assert_eq!(options.source, "db_v2.sqlite3");
+        assert_eq!(options.db_url, "e.sqlite3");
+        assert_eq!(options.owner.as_deref(), Some("admin"));
+        assert!(options.group.is_none());
+        assert!(options.dry_run);
+        assert!(parse_options(&argv("")).is_err());
+        assert!(parse_options(&argv("--db e.sqlite3")).is_err());
+        assert!(parse_options(&argv("db_v2.sqlite3 --owner")).is_err());
+        assert!(parse_options(&argv("db_v2.sqlite3 --tenant x")).is_err());
+        let options = parse_export_options(&argv("standby.sqlite3 --peer-db p.sqlite3 --include-disabled")).unwrap();
+        assert_eq!((options.dest.as_str(), options.peer_db.as_str()), ("standby.sqlite3", "p.sqlite3"));
+        assert!(options.include_disabled && !options.dry_run);
+        assert!(parse_export_options(&argv("standby.sqlite3 --owner admin")).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_migrate() {
+        let dir = std::env::temp_dir().join(format!("oss-migration-{}", uuid::Uuid::new_v4().simple()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = |name: &str| dir.join(name).to_str().unwrap().to_owned();
+        let source = Database::new(&path("db_v2.sqlite3")).await.unwrap();
+        source
+            .insert_peer("123456789", b"uuid-1", b"pk-1", r#"{"ip":"192.0.2.1"}"#)
+            .await
+            .unwrap();
+        source
+            .insert_peer("987654321", b"uuid-2", b"pk-2", r#"{"ip":"2001:db8::1","ipv6":"2001:db8::1"}"#)
+            .await
+            .unwrap();
+        source.insert_peer("1234", b"uuid-3", b"pk-3", "{}").await.unwrap();
+        let peers = Database::new(&path("peers.sqlite3")).await.unwrap();
+        let db = EnterpriseDatabase::memory().await.unwrap();
+        let owner = User {
+            id: "owner-1".to_owned(),
+            username: "owner".to_owned(),
+            password_hash: String::new(),
+            email: None,
+            role: UserRole::Admin,
+            groups: vec![],
+            enabled: true,
+            created_at: SystemTime::now(),
+            last_login: None,
+            failed_login_attempts: 0,
+            locked_until: None,
+            two_factor_enabled: false,
+            two_factor_secret: None,
+        };
+        db.create_user(&owner).await.unwrap();
+        // 设备组记在超级管理员名下
+        db.create_user(&User {
+            id: "root-1".to_owned(),
+            username: "root".to_owned(),
+            role: UserRole::SuperAdmin,
+            ..owner.clone()
+        })
+        .await
+        .unwrap();
+        db.upsert_ad_device_group("migrated", "Migrated", "").await.unwrap();
+
+        let mut options = parse_options(&argv("db_v2.sqlite3 --owner owner --group migrated --dry-run")).unwrap();
+        let report = migrate(&source, &db, Some(&peers), &options).await.unwrap();
+        assert_eq!((report.total, report.imported, report.skipped.len()), (3, 0, 1));
+        assert_eq!(db.count_devices().await.unwrap(), 0);
+
+        options.dry_run = false;
+        let report = migrate(&source, &db, Some(&peers), &options).await.unwrap();
+        assert_eq!((report.imported, report.existing, report.peers_copied), (2, 0, 2));
+        assert_eq!(report.skipped, vec!["1234".to_owned()]);
+        assert_eq!(db.get_device_owner("123456789").await.unwrap().as_deref(), Some("owner-1"));
+        assert_eq!(db.get_device_group_ids("987654321").await.unwrap(), vec!["migrated".to_owned()]);
+        assert_eq!(
+            db.get_device_uuid("123456789").await.unwrap(),
+            Some(base64::encode(b"uuid-1"))
+        );
+        let peer = peers.get_peer("987654321").await.unwrap().unwrap();
+        assert_eq!(peer.pk, b"pk-2".to_vec());
+        assert!(peers.get_peer("1234").await.unwrap().is_none());
+
+        // 重复执行不重复导入
+        let report = migrate(&source, &db, Some(&peers), &options).await.unwrap();
+        assert_eq!((report.imported, report.existing, report.peers_copied), (0, 2, 0));
+
+        options.owner = Some("nobody".to_owned());
+        assert!(migrate(&source, &db, None, &options).await.is_err());
+        drop((source, peers));
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[tokio::test]
+    async fn test_export() {
+        use crate::enterprise_database::DeviceBan;
+
+        let dir = std::env::temp_dir().join(format!("oss-export-{}", uuid::Uuid::new_v4().simple()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = |name: &str| dir.join(name).to_str().unwrap().to_owned();
+        let db = EnterpriseDatabase::memory().await.unwrap();
+        let peers = Database::new(&path("peers.sqlite3")).await.unwrap();
+        let device = |id: &str, enabled: bool| {
+            let (mut device, _) = device_of(
+                &Peer {
+                    id: id.to_owned(),
+                    info: r#"{"ip":"192.0.2.7"}"#.to_owned(),
+                    ..Default::default()
+                },
+                SYSTEM_OWNER,
+                None,
+                SystemTime::now(),
+            );
+            device.enabled = enabled;
+            (device, Some(base64::encode(format!("uuid-{}", id))))
+        };
+        db.import_devices(&[
+            device("711111111", true),
+            device("722222222", true),
+            device("733333333", false),
+            device("744444444", true),
+        ])
+        .await
+        .unwrap();
+        peers
+            .insert_peer("711111111", b"uuid-711111111", b"pk-1", r#"{"ip":"192.0.2.7","ipv4":"192.0.2.7"}"#)
+            .await
+            .unwrap();
+        db.upsert_device_ban(&DeviceBan {
+            device_id: "744444444".to_owned(),
+            uuid: None,
+            reason: "test".to_owned(),
+            banned_by: SYSTEM_OWNER.to_owned(),
+            created_at: 0,
+            expires_at: None,
+        })
+        .await
+        .unwrap();
+
+        let mut options = parse_export_options(&argv("standby.sqlite3 --dry-run")).unwrap();
+        let report = export(&db, &peers, None, &options).await.unwrap();
+        assert_eq!(
+            report,
+            ExportReport {
+                exported: 2,
+                without_key: 1,
+                disabled: 1,
+                banned: 1,
+            }
+        );
+
+        options.dry_run = false;
+        let dest = Database::new(&path("standby.sqlite3")).await.unwrap();
+        export(&db, &peers, Some(&dest), &options).await.unwrap();
+        let peer = dest.get_peer("711111111").await.unwrap().unwrap();
+        assert_eq!(peer.pk, b"pk-1".to_vec());
+        assert_eq!(peer.info, r#"{"ip":"192.0.2.7"}"#);
+        let peer = dest.get_peer("722222222").await.unwrap().unwrap();
+        assert_eq!(peer.uuid, b"uuid-722222222".to_vec());
+        assert!(peer.pk.is_empty());
+        assert!(dest.get_peer("733333333").await.unwrap().is_none());
+        assert!(dest.get_peer("744444444").await.unwrap().is_none());
+        // 重复导出更新已有设备
+        export(&db, &peers, Some(&dest), &options).await.unwrap();
+        assert_eq!(dest.list_peers().await.unwrap().len(), 2);
+        drop((peers, dest));
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -0,0 +1,199 @@
+// 连接密码策略模块 - 按设备/设备组下发密码策略（禁用固定密码、最小长度、自动轮换）
+//
+// hbb_common 中的 ConfigUpdate 只包含 serial 和 rendezvous_servers，
+// 策略变更时提升配置序号，客户端注册时收到 ConfigUpdate 后重新拉取策略选项
+use crate::enterprise_database::EnterpriseDatabase;
+use crate::validation::Payload;
+use hbb_common::{bail, log, tokio::sync::RwLock, ResultType};
+use schemars::JsonSchema;
+use serde_derive::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    sync::atomic::{AtomicI32, AtomicU64, Ordering},
+};
+use validator::Validate;
+
+pub const PASSWORD_POLICIES_KEY: &str = "password_policies";
+pub const CONFIG_SERIAL_KEY: &str = "config_serial";
+
+lazy_static::lazy_static! {
+    static ref POLICIES: RwLock<PasswordPolicies> = Default::default();
+}
+static CONFIG_SERIAL: AtomicI32 = AtomicI32::new(0);
+static MODIFIED_AT: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct PasswordPolicy {
+    #[serde(default)]
+    pub disable_permanent_password: bool,
+    #[serde(default)]
+    pub min_length: u32, // 临时密码长度，客户端支持 6/8/10
+    #[serde(default)]
+    pub rotation_interval_secs: Option<u64>, // 固定密码自动轮换间隔
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema, Validate)]
+pub struct PasswordPolicies {
+    #[serde(default)]
+    pub default: Option<PasswordPolicy>,
+    #[serde(default)]
+    pub groups: HashMap<String, PasswordPolicy>, // 设备组ID -> 策略
+    #[serde(default)]
+    pub devices: HashMap<String, PasswordPolicy>, // 设备ID -> 策略
+}
+
+impl Payload for PasswordPolicies {}
+
+impl PasswordPolicy {
+    pub fn validate(&self) -> ResultType<()> {
+        if self.min_length != 0 && ![6, 8, 10].contains(&self.min_length) {
+            bail!("min_length must be 6, 8 or 10");
+        }
+        if let Some(secs) = self.rotation_interval_secs {
+            if secs < 3600 {
+                bail!("rotation interval must be at least 3600 seconds");
+            }
+        }
+        Ok(())
+    }
+
+    // 转换为客户端配置选项
+    pub fn to_options(&self) -> HashMap<String, String> {
+        let mut options = HashMap::new();
+        if self.disable_permanent_password {
+            options.insert(
+                "verification-method".to_owned(),
+                "use-temporary-password".to_owned(),
+            );
+        }
+        if self.min_length > 0 {
+            options.insert(
+                "temporary-password-length".to_owned(),
+                self.min_length.to_string(),
+            );
+        }
+        if let Some(secs) = self.rotation_interval_secs {
+            options.insert("password-rotation-interval".to_owned(), secs.to_string());
+        }
+        options
+    }
+}
+
+impl PasswordPolicies {
+    pub fn validate(&self) -> ResultType<()> {
+        for policy in self
+            .default
+            .iter()
+            .chain(self.groups.values())
+            .chain(self.devices.values())
+        {
+            policy.validate()?;
+        }
+        Ok(())
+    }
+
+    // 生效顺序：设备 > 设备组（按设备所属组顺序） > 默认
+    pub fn effective(&self, device_id: &str, group_ids: &[String]) -> Option<PasswordPolicy> {
+        if let Some(policy) = self.devices.get(device_id) {
+            return Some(policy.clone());
+        }
+        for group_id in group_ids {
+            if let Some(policy) = self.groups.get(group_id) {
+                return Some(policy.clone());
+            }
+        }
+        self.default.clone()
+    }
+}
+
+pub async fn reload(db: &EnterpriseDatabase) -> ResultType<()> {
+    let policies: PasswordPolicies = match db.get_setting_with_time(PASSWORD_POLICIES_KEY).await? {
+        Some((v, updated_at)) => {
+            MODIFIED_AT.store(updated_at, Ordering::SeqCst);
+            serde_json::from_str(&v)?
+        }
+        None => PasswordPolicies::default(),
+    };
+    let serial = db
+        .get_setting(CONFIG_SERIAL_KEY)
+        .await?
+        .and_then(|x| x.parse().ok())
+        .unwrap_or(0);
+    CONFIG_SERIAL.store(serial, Ordering::SeqCst);
+    *POLICIES.write().await = policies;
+    Ok(())
+}
+
+pub async fn get() -> PasswordPolicies {
+    POLICIES.read().await.clone()
+}
+
+pub async fn update(db: &EnterpriseDatabase, policies: PasswordPolicies, updated_by: &str) -> ResultType<i32> {
+    policies.validate()?;
+    db.set_setting(PASSWORD_POLICIES_KEY, &serde_json::to_string(&policies)?, Some(updated_by))
+        .await?;
+    *POLICIES.write().await = policies;
+    MODIFIED_AT.store(crate::common::now(), Ordering::SeqCst);
+    bump_serial(db, updated_by).await
+}
+
+// 提升配置序号，客户端下次注册时收到 ConfigUpdate
+pub async fn bump_serial(db: &EnterpriseDatabase, updated_by: &str) -> ResultType<i32> {
+    let serial = CONFIG_SERIAL.fetch_add(1, Ordering::SeqCst) + 1;
+    db.set_setting(CONFIG_SERIAL_KEY, &serial.to_string(), Some(updated_by))
+        .await?;
+    log::info!("config serial bumped to {}", serial);
+    Ok(serial)
+}
+
+// 策略最后修改时间（秒）
+pub fn modified_at() -> u64 {
+    MODIFIED_AT.load(Ordering::SeqCst)
+}
+
+// 与启动参数 --serial 取较大值
+pub fn config_serial(base: i32) -> i32 {
+    base.max(CONFIG_SERIAL.load(Ordering::SeqCst))
+}
+
+pub async fn effective_policy(db: &EnterpriseDatabase, device_id: &str) -> Option<PasswordPolicy> {
+    let group_ids = match db.get_device_group_ids(device_id).await {
+        Ok(group_ids) => group_ids,
+        Err(err) => {
+            log::error!("Failed to load groups of {}: {}", device_id, err);
+            vec![]
+        }
+    };
+    POLICIES.read().await.effective(device_id, &group_ids)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_effective_policy() {
+        let strict = PasswordPolicy {
+            disable_permanent_password: true,
+            min_length: 10,
+            rotation_interval_secs: None,
+        };
+        let mut policies = PasswordPolicies {
+            default: Some(PasswordPolicy::default()),
+            ..Default::default()
+        };
+        policies.groups.insert("finance".to_owned(), strict.clone());
+        assert_eq!(policies.effective("1", &["finance".to_owned()]), Some(strict.clone()));
+        assert_eq!(policies.effective("1", &[]), Some(PasswordPolicy::default()));
+        assert_eq!(
+            strict.to_options().get("verification-method").map(|x| x.as_str()),
+            Some("use-temporary-password")
+        );
+        assert!(PasswordPolicy {
+            min_length: 7,
+            ..Default::default()
+        }
+        .validate()
+        .is_err());
+    }
+}
@@ -0,0 +1,50 @@
+// 找回密码模块 - 用户凭用户名申请重置，服务器向其绑定的邮箱发送限时签名链接，无需超级管理员介入；
+// 无论账户是否存在都返回相同结果，邮件在后台发送，避免据此判断用户名
+use crate::common::{now, public_url};
+use crate::email_otp;
+use crate::i18n::{self, Lang};
+use hbb_common::{log, tokio::sync::RwLock, ResultType};
+use std::collections::HashMap;
+
+pub const RESET_TTL_SECS: u64 = 30 * 60;
+
+lazy_static::lazy_static! {
+    // 用户名 -> 最近一小时内的申请时间
+    static ref REQUESTS: RwLock<HashMap<String, Vec<u64>>> = Default::default();
+}
+
+// 按用户名限频(与邮件验证码相同的限制)，不存在的用户名同样计数
+pub async fn try_acquire(username: &str) -> bool {
+    let mut requests = REQUESTS.write().await;
+    let now = now();
+    requests.retain(|_, sends| sends.last().map(|t| now < t + 3600).unwrap_or(false));
+    email_otp::try_record_send(requests.entry(username.to_lowercase()).or_default(), now)
+}
+
+pub fn reset_link(token: &str) -> ResultType<String> {
+    public_url(&format!("/reset-password?token={}", token))
+}
+
+pub async fn send_reset(email: &str, username: &str, link: &str, lang: Lang) -> ResultType<()> {
+    let body = i18n::t(
+        lang,
+        "email.reset.body",
+        &[("username", &username), ("minutes", &(RESET_TTL_SECS / 60)), ("link", &link)],
+    );
+    let subject = i18n::t(lang, "email.reset.subject", &[]);
+    email_otp::send_notification(vec![email.to_owned()], subject, body).await?;
+    log::info!("Password reset link sent to {}", username);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[hbb_common::tokio::test]
+    async fn test_rate_limit() {
+        assert!(try_acquire("reset-test").await);
+        assert!(!try_acquire("RESET-TEST").await);
+        assert!(try_acquire("reset-test-2").await);
+    }
+}
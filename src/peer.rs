@@ -22,11 +22,47 @@ pub const IP_CHANGE_DUR: u64 = 180;
 pub const IP_CHANGE_DUR_X2: u64 = IP_CHANGE_DUR * 2;
 pub const DAY_SECONDS: u64 = 3600 * 24;
 pub const IP_BLOCK_DUR: u64 = 60;
+// 双栈地址超过该时长未刷新则视为失效（毫秒）
+pub const ADDR_FRESH_TIMEOUT: u128 = 60_000;
 
 #[derive(Debug, Default, Serialize, Deserialize, Clone)]
 pub(crate) struct PeerInfo {
     #[serde(default)]
     pub(crate) ip: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub(crate) ipv4: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub(crate) ipv6: String,
+}
+
+impl PeerInfo {
+    pub(crate) fn set_ip(&mut self, ip: String, v6: bool) {
+        if v6 {
+            self.ipv6 = ip.clone();
+        } else {
+            self.ipv4 = ip.clone();
+        }
+        self.ip = ip;
+    }
+
+    // 同一地址族上次登记的IP，旧数据没有分族记录时回退到 ip
+    pub(crate) fn ip_of(&self, v6: bool) -> &str {
+        let ip = if v6 { &self.ipv6 } else { &self.ipv4 };
+        if ip.is_empty() {
+            &self.ip
+        } else {
+            ip
+        }
+    }
+}
+
+// 是否为真正的IPv6地址（IPv4映射地址按IPv4处理）
+#[inline]
+pub(crate) fn is_ipv6(addr: &SocketAddr) -> bool {
+    match addr {
+        SocketAddr::V4(_) => false,
+        SocketAddr::V6(v6) => v6.ip().to_ipv4_mapped().is_none(),
+    }
 }
 
 pub(crate) struct Peer {
@@ -39,6 +75,26 @@ pub(crate) struct Peer {
     pub(crate) info: PeerInfo,
     // pub(crate) disabled: bool,
     pub(crate) reg_pk: (u32, Instant), // how often register_pk
+    // 双栈设备分别记录IPv4/IPv6观测地址及最后注册时间
+    pub(crate) addr_v4: Option<(SocketAddr, Instant)>,
+    pub(crate) addr_v6: Option<(SocketAddr, Instant)>,
+}
+
+impl Peer {
+    pub(crate) fn observe_addr(&mut self, addr: SocketAddr) {
+        if is_ipv6(&addr) {
+            self.addr_v6 = Some((addr, Instant::now()));
+        } else {
+            self.addr_v4 = Some((addr, Instant::now()));
+        }
+    }
+
+    // 返回指定地址族上仍然有效的地址
+    pub(crate) fn fresh_addr(&self, v6: bool) -> Option<SocketAddr> {
+        let slot = if v6 { &self.addr_v6 } else { &self.addr_v4 };
+        slot.filter(|(_, t)| t.elapsed().as_millis() < ADDR_FRESH_TIMEOUT)
+            .map(|(addr, _)| addr)
+    }
 }
 
 impl Default for Peer {
@@ -53,6 +109,8 @@ impl Default for Peer {
             // user: None,
             // disabled: false,
             reg_pk: (0, get_expired_time()),
+            addr_v4: None,
+            addr_v6: None,
         }
     }
 }
@@ -103,10 +161,11 @@ impl PeerMap {
         let (info_str, guid) = {
             let mut w = peer.write().await;
             w.socket_addr = addr;
+            w.observe_addr(addr);
             w.uuid = uuid.clone();
             w.pk = pk.clone();
             w.last_reg_time = Instant::now();
-            w.info.ip = ip;
+            w.info.set_ip(ip, is_ipv6(&addr));
             (
                 serde_json::to_string(&w.info).unwrap_or_default(),
                 w.guid.clone(),
@@ -177,4 +236,11 @@ impl PeerMap {
     pub(crate) async fn is_in_memory(&self, id: &str) -> bool {
         self.map.read().await.contains_key(id)
     }
+
+    // 封禁设备时移出在线列表，仅企业版使用
+    #[cfg(feature = "enterprise")]
+    #[inline]
+    pub(crate) async fn remove(&self, id: &str) -> Option<LockPeer> {
+        self.map.write().await.remove(id)
+    }
 }
@@ -0,0 +1,119 @@
+// 设备别名模块 - 为数字ID分配易读名称（如 FINANCE-PC-07），供Web API和打洞请求解析
+use crate::enterprise_database::{DeviceAlias, EnterpriseDatabase};
+use hbb_common::{bail, log, tokio::sync::RwLock, ResultType};
+use std::{
+    collections::HashMap,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+// 设置项：是否允许客户端在打洞请求中直接使用别名
+pub const ALIAS_IN_PUNCH_KEY: &str = "alias_in_punch";
+const MAX_ALIAS_LEN: usize = 64;
+
+lazy_static::lazy_static! {
+    // 大写别名 -> (设备ID, 原始别名)
+    static ref ALIASES: RwLock<HashMap<String, (String, String)>> = Default::default();
+}
+static ALIAS_IN_PUNCH: AtomicBool = AtomicBool::new(false);
+
+// 别名只允许字母、数字、- _ .，且不能是纯数字，避免与真实ID冲突
+pub fn validate_alias(alias: &str) -> ResultType<()> {
+    if alias.is_empty() || alias.len() > MAX_ALIAS_LEN {
+        bail!("alias length must be 1-{}", MAX_ALIAS_LEN);
+    }
+    if !alias
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.')
+    {
+        bail!("alias contains invalid characters");
+    }
+    if alias.chars().all(|c| c.is_ascii_digit()) {
+        bail!("alias must not be numeric");
+    }
+    Ok(())
+}
+
+pub async fn reload(db: &EnterpriseDatabase) -> ResultType<()> {
+    let aliases = db.list_device_aliases().await?;
+    let enabled = db.get_setting(ALIAS_IN_PUNCH_KEY).await?.as_deref() == Some("true");
+    ALIAS_IN_PUNCH.store(enabled, Ordering::SeqCst);
+    let mut map = ALIASES.write().await;
+    *map = aliases
+        .into_iter()
+        .map(|x| (x.alias.to_uppercase(), (x.device_id, x.alias)))
+        .collect();
+    log::info!("{} device aliases loaded, alias in punch: {}", map.len(), enabled);
+    Ok(())
+}
+
+pub async fn set(db: &EnterpriseDatabase, device_id: &str, alias: &str, created_by: &str) -> ResultType<()> {
+    validate_alias(alias)?;
+    if let Some(owner) = resolve(alias).await {
+        if owner != device_id {
+            bail!("alias {} is already used by {}", alias, owner);
+        }
+    }
+    db.set_device_alias(device_id, alias, created_by).await?;
+    let mut map = ALIASES.write().await;
+    map.retain(|_, (id, _)| id != device_id);
+    map.insert(alias.to_uppercase(), (device_id.to_owned(), alias.to_owned()));
+    Ok(())
+}
+
+pub async fn remove(db: &EnterpriseDatabase, device_id: &str) -> ResultType<bool> {
+    let removed = db.remove_device_alias(device_id).await?;
+    ALIASES.write().await.retain(|_, (id, _)| id != device_id);
+    Ok(removed)
+}
+
+pub async fn list(db: &EnterpriseDatabase) -> ResultType<Vec<DeviceAlias>> {
+    db.list_device_aliases().await
+}
+
+// 别名 -> 设备ID
+pub async fn resolve(alias: &str) -> Option<String> {
+    ALIASES
+        .read()
+        .await
+        .get(&alias.to_uppercase())
+        .map(|(id, _)| id.clone())
+}
+
+// 接受设备ID或别名，返回设备ID
+pub async fn resolve_id(id_or_alias: &str) -> String {
+    resolve(id_or_alias)
+        .await
+        .unwrap_or_else(|| id_or_alias.to_owned())
+}
+
+// 打洞请求中的ID，仅在开启该选项时解析别名
+pub async fn resolve_punch_id(id: &str) -> String {
+    if !ALIAS_IN_PUNCH.load(Ordering::SeqCst) {
+        return id.to_owned();
+    }
+    resolve_id(id).await
+}
+
+// 设备ID -> 别名，用于列表展示
+pub async fn aliases_of(device_ids: &[String]) -> HashMap<String, String> {
+    let map = ALIASES.read().await;
+    map.values()
+        .filter(|(id, _)| device_ids.contains(id))
+        .cloned()
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_alias() {
+        assert!(validate_alias("FINANCE-PC-07").is_ok());
+        assert!(validate_alias("lab_host.2").is_ok());
+        assert!(validate_alias("123456789").is_err());
+        assert!(validate_alias("").is_err());
+        assert!(validate_alias("财务电脑").is_err());
+        assert!(validate_alias(&"A".repeat(65)).is_err());
+    }
+}
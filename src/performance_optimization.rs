@@ -1,8 +1,10 @@
 // 性能优化模块 - 编解码器、低延迟模式、带宽优化
-use hbb_common::{log, ResultType};
+use crate::device_capabilities::DeviceCapabilities;
+use hbb_common::{bail, log, ResultType};
+use schemars::JsonSchema;
 use serde_derive::{Deserialize, Serialize};
 use std::{
-    collections::{HashMap, VecDeque},
+    collections::{BTreeMap, HashMap, VecDeque},
     sync::{Arc, atomic::{AtomicU64, AtomicBool, Ordering}},
     time::{Duration, Instant, SystemTime},
 };
@@ -21,7 +23,7 @@ pub struct CodecConfig {
     pub adaptive_quality: bool,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
 pub enum CodecType {
     H264,
     H265,
@@ -65,32 +67,113 @@ pub struct AdaptiveQualityController {
     adjustment_cooldown: Instant,
 }
 
-// 带宽管理
+// 带宽管理。会话的带宽按 租户(用户组) → 设备组 → 会话 三级池分配，每一级按权重做最大最小公平分配:
+// 需求低于份额的池按需求满足，多出的部分继续在其余池之间按权重分配；会话结束释放后重新分配给其他会话
 #[derive(Debug, Clone)]
 pub struct BandwidthManager {
     available_bandwidth: Arc<AtomicU64>,
+    // reserve_bandwidth 预留的带宽
     used_bandwidth: Arc<AtomicU64>,
+    // 各会话分配到的带宽合计
+    allocated_bandwidth: Arc<AtomicU64>,
+    pools: Arc<Mutex<BandwidthPools>>,
     bandwidth_history: Arc<Mutex<VecDeque<(Instant, u64)>>>,
     congestion_control: CongestionControl,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct PoolConfig {
+    // 池的上限，为空表示只受上级池限制
+    #[serde(default)]
+    pub capacity_kbps: Option<u64>,
+    // 与同级的池按权重分享上级池的带宽
+    #[serde(default = "default_pool_weight")]
+    pub weight: u32,
+}
+
+fn default_pool_weight() -> u32 {
+    1
+}
+
+impl Default for PoolConfig {
+    fn default() -> Self {
+        Self {
+            capacity_kbps: None,
+            weight: default_pool_weight(),
+        }
+    }
+}
+
+impl PoolConfig {
+    pub fn validate(&self) -> ResultType<()> {
+        if self.weight == 0 {
+            bail!("weight must be positive");
+        }
+        if self.capacity_kbps == Some(0) {
+            bail!("capacity_kbps must be positive");
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct TenantPoolConfig {
+    #[serde(flatten)]
+    pub pool: PoolConfig,
+    // 设备组ID -> 组内会话共享的池，未配置的组使用默认配置
+    #[serde(default)]
+    pub groups: HashMap<String, PoolConfig>,
+}
+
+// 带宽池的分配结果，租户的 children 为设备组，设备组的 children 为会话
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct PoolUsage {
+    pub name: String,
+    pub weight: u32,
+    pub demand_kbps: u64,
+    pub allocated_kbps: u64,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub children: Vec<PoolUsage>,
+}
+
 #[derive(Debug, Clone)]
-pub enum CongestionControl {
-    BBR,
-    Cubic,
-    Reno,
-    Vegas,
+struct SessionDemand {
+    tenant: String,
+    group: String,
+    requested_kbps: u64,
 }
 
+#[derive(Debug, Default)]
+struct BandwidthPools {
+    tenants: HashMap<String, TenantPoolConfig>,
+    sessions: HashMap<String, SessionDemand>,
+    usage: Vec<PoolUsage>,
+    allocations: HashMap<String, u64>,
+}
+
+// 中继会话实际使用的拥塞控制见 congestion_control 模块
+pub use crate::congestion_control::CongestionControl;
+
 pub struct PerformanceOptimizer {
     codec_configs: Arc<RwLock<HashMap<String, CodecConfig>>>,
     performance_metrics: Arc<RwLock<HashMap<String, PerformanceMetrics>>>,
     adaptive_controllers: Arc<RwLock<HashMap<String, AdaptiveQualityController>>>,
     bandwidth_manager: BandwidthManager,
     low_latency_enabled: Arc<AtomicBool>,
-    hardware_acceleration_available: bool,
 }
 
+// 高带宽时优先使用压缩率高的格式，需要设备有对应的硬件编码器
+const HW_PREFERRED_CODECS: [CodecType; 2] = [CodecType::H265, CodecType::AV1];
+// 设备不支持首选格式时按此顺序回退
+const FALLBACK_CODECS: [CodecType; 6] = [
+    CodecType::H264,
+    CodecType::VP9,
+    CodecType::VP8,
+    CodecType::H265,
+    CodecType::AV1,
+    CodecType::MJPEG,
+];
+
 impl PerformanceOptimizer {
     pub fn new() -> Self {
         Self {
@@ -99,7 +182,6 @@ impl PerformanceOptimizer {
             adaptive_controllers: Arc::new(RwLock::new(HashMap::new())),
             bandwidth_manager: BandwidthManager::new(),
             low_latency_enabled: Arc::new(AtomicBool::new(false)),
-            hardware_acceleration_available: Self::detect_hardware_acceleration(),
         }
     }
 
@@ -110,9 +192,14 @@ impl PerformanceOptimizer {
         Ok(())
     }
 
-    // 编解码器优化
-    pub async fn get_optimal_codec_config(&self, session_id: &str, network_conditions: &NetworkConditions) -> CodecConfig {
-        let base_config = self.get_base_codec_config(network_conditions).await;
+    // 编解码器优化，capabilities 为编码端设备上报的编码器，未上报时按只支持软件 H264 处理
+    pub async fn get_optimal_codec_config(
+        &self,
+        session_id: &str,
+        network_conditions: &NetworkConditions,
+        capabilities: Option<&DeviceCapabilities>,
+    ) -> CodecConfig {
+        let base_config = self.get_base_codec_config(network_conditions, capabilities).await;
         
         // 根据网络条件调整
         let mut optimized_config = base_config.clone();
@@ -134,9 +221,13 @@ impl PerformanceOptimizer {
             }
         }
         
-        if network_conditions.packet_loss_rate > 0.05 {
+        if network_conditions.packet_loss_rate > 0.05
+            && capabilities.map(|c| c.supports(&CodecType::H264)).unwrap_or(true)
+        {
             // 高丢包率，使用更鲁棒的编码
             optimized_config.codec_type = CodecType::H264; // 更好的错误恢复
+            optimized_config.hardware_acceleration =
+                capabilities.and_then(|c| c.hardware(&CodecType::H264)).is_some();
         }
 
         // 保存配置
@@ -145,11 +236,21 @@ impl PerformanceOptimizer {
         optimized_config
     }
 
-    async fn get_base_codec_config(&self, network_conditions: &NetworkConditions) -> CodecConfig {
-        if self.hardware_acceleration_available && network_conditions.bandwidth_kbps > 2000 {
+    async fn get_base_codec_config(
+        &self,
+        network_conditions: &NetworkConditions,
+        capabilities: Option<&DeviceCapabilities>,
+    ) -> CodecConfig {
+        let hw_codec = capabilities.and_then(|c| c.first_of(&HW_PREFERRED_CODECS, true));
+        // 设备不支持 H264 时换成它支持的格式
+        let codec = capabilities
+            .and_then(|c| c.first_of(&FALLBACK_CODECS, false))
+            .unwrap_or(CodecType::H264);
+        let hardware = capabilities.and_then(|c| c.hardware(&codec)).is_some();
+        if let Some(hw_codec) = hw_codec.filter(|_| network_conditions.bandwidth_kbps > 2000) {
             // 高性能配置
             CodecConfig {
-                codec_type: CodecType::H265,
+                codec_type: hw_codec,
                 quality: 80,
                 bitrate: 4000,
                 framerate: 60,
@@ -161,24 +262,24 @@ impl PerformanceOptimizer {
         } else if network_conditions.bandwidth_kbps > 1000 {
             // 中等配置
             CodecConfig {
-                codec_type: CodecType::H264,
+                codec_type: codec,
                 quality: 70,
                 bitrate: 2000,
                 framerate: 30,
                 resolution: Resolution { width: 1920, height: 1080 },
-                hardware_acceleration: self.hardware_acceleration_available,
+                hardware_acceleration: hardware,
                 low_latency_mode: false,
                 adaptive_quality: true,
             }
         } else {
             // 低带宽配置
             CodecConfig {
-                codec_type: CodecType::H264,
+                codec_type: codec,
                 quality: 50,
                 bitrate: 800,
                 framerate: 24,
                 resolution: Resolution { width: 1280, height: 720 },
-                hardware_acceleration: false,
+                hardware_acceleration: hardware,
                 low_latency_mode: true,
                 adaptive_quality: true,
             }
@@ -312,12 +413,8 @@ impl PerformanceOptimizer {
     }
 
     fn get_gpu_usage(&self) -> f64 {
-        // 实际实现中应该获取真实的GPU使用率
-        if self.hardware_acceleration_available {
-            20.0 + (rand::random::<f64>() * 30.0)
-        } else {
-            0.0
-        }
+        // 服务器本身不做编解码
+        0.0
     }
 
     async fn count_frame_drops(&self, _session_id: &str) -> u32 {
@@ -340,13 +437,6 @@ impl PerformanceOptimizer {
         1.0 + (rand::random::<f64>() * 5.0)
     }
 
-    // 硬件加速检测
-    fn detect_hardware_acceleration() -> bool {
-        // 实际实现中应该检测硬件编解码器支持
-        // 这里简化为随机返回
-        true
-    }
-
     async fn load_default_codec_configs(&self) {
         // 加载默认编解码器配置
         let default_config = CodecConfig {
@@ -355,7 +445,7 @@ impl PerformanceOptimizer {
             bitrate: 2000,
             framerate: 30,
             resolution: Resolution { width: 1920, height: 1080 },
-            hardware_acceleration: self.hardware_acceleration_available,
+            hardware_acceleration: false,
             low_latency_mode: false,
             adaptive_quality: true,
         };
@@ -421,14 +511,108 @@ impl AdaptiveQualityController {
     }
 }
 
+impl Default for BandwidthManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl BandwidthManager {
-    fn new() -> Self {
+    pub fn new() -> Self {
+        Self::with_capacity(10000) // 10Mbps default
+    }
+
+    pub fn with_capacity(available_kbps: u64) -> Self {
         Self {
-            available_bandwidth: Arc::new(AtomicU64::new(10000)), // 10Mbps default
+            available_bandwidth: Arc::new(AtomicU64::new(available_kbps)),
             used_bandwidth: Arc::new(AtomicU64::new(0)),
+            allocated_bandwidth: Arc::new(AtomicU64::new(0)),
+            pools: Arc::new(Mutex::new(BandwidthPools::default())),
             bandwidth_history: Arc::new(Mutex::new(VecDeque::new())),
-            congestion_control: CongestionControl::BBR,
+            congestion_control: CongestionControl::Bbr,
+        }
+    }
+
+    pub fn set_available_bandwidth(&self, kbps: u64) {
+        self.available_bandwidth.store(kbps, Ordering::Relaxed);
+    }
+
+    // 为远程控制会话预留带宽，剩余部分留给文件传输等批量任务
+    pub fn reserve_bandwidth(&self, kbps: u64) {
+        self.used_bandwidth.store(kbps, Ordering::Relaxed);
+    }
+
+    pub fn remaining_bandwidth(&self) -> u64 {
+        let available = self.available_bandwidth.load(Ordering::Relaxed);
+        available
+            .saturating_sub(self.used_bandwidth.load(Ordering::Relaxed))
+            .saturating_sub(self.allocated_bandwidth.load(Ordering::Relaxed))
+    }
+
+    // 租户ID(用户组) -> 租户的池，未配置的租户使用默认配置
+    pub async fn set_pools(&self, tenants: HashMap<String, TenantPoolConfig>) {
+        let mut pools = self.pools.lock().await;
+        pools.tenants = tenants;
+        self.rebalance(&mut pools);
+    }
+
+    // 登记会话的带宽需求，返回公平分配得到的带宽；已登记的会话更新需求
+    pub async fn allocate_session_bandwidth(&self, session_id: &str, tenant: &str, group: &str, requested_kbps: u64) -> u64 {
+        let mut pools = self.pools.lock().await;
+        pools.sessions.insert(session_id.to_owned(), SessionDemand {
+            tenant: tenant.to_owned(),
+            group: group.to_owned(),
+            requested_kbps,
+        });
+        self.rebalance(&mut pools);
+        pools.allocations.get(session_id).cloned().unwrap_or_default()
+    }
+
+    // 用当前的会话集合替换已登记的会话，不在其中的会话释放带宽，只重新分配一次
+    pub async fn sync_sessions(&self, sessions: Vec<(String, String, String, u64)>) {
+        let mut pools = self.pools.lock().await;
+        pools.sessions = sessions
+            .into_iter()
+            .map(|(session_id, tenant, group, requested_kbps)| (session_id, SessionDemand { tenant, group, requested_kbps }))
+            .collect();
+        self.rebalance(&mut pools);
+    }
+
+    // 会话结束时释放带宽，返回会话是否已登记
+    pub async fn release_bandwidth(&self, session_id: &str) -> bool {
+        let mut pools = self.pools.lock().await;
+        let released = pools.sessions.remove(session_id).is_some();
+        if released {
+            self.rebalance(&mut pools);
         }
+        released
+    }
+
+    pub async fn session_allocation(&self, session_id: &str) -> Option<u64> {
+        self.pools.lock().await.allocations.get(session_id).cloned()
+    }
+
+    pub async fn pool_usage(&self) -> Vec<PoolUsage> {
+        self.pools.lock().await.usage.clone()
+    }
+
+    // 预留之外的带宽逐级分配给租户、设备组和会话
+    fn rebalance(&self, pools: &mut BandwidthPools) {
+        let capacity = self
+            .available_bandwidth
+            .load(Ordering::Relaxed)
+            .saturating_sub(self.used_bandwidth.load(Ordering::Relaxed));
+        let mut usage = pools.build();
+        distribute(capacity, &mut usage);
+        pools.allocations = usage
+            .iter()
+            .flat_map(|tenant| tenant.children.iter())
+            .flat_map(|group| group.children.iter())
+            .map(|session| (session.name.clone(), session.allocated_kbps))
+            .collect();
+        self.allocated_bandwidth
+            .store(pools.allocations.values().sum(), Ordering::Relaxed);
+        pools.usage = usage;
     }
 
     async fn estimate_available_bandwidth(&self) -> u64 {
@@ -436,14 +620,144 @@ impl BandwidthManager {
         self.available_bandwidth.load(Ordering::Relaxed)
     }
 
-    async fn allocate_bandwidth(&self, _session_id: &str, requested_kbps: u32) -> u32 {
-        let available = self.available_bandwidth.load(Ordering::Relaxed);
-        let used = self.used_bandwidth.load(Ordering::Relaxed);
-        let remaining = available.saturating_sub(used);
-        
-        let allocated = (requested_kbps as u64).min(remaining) as u32;
-        self.used_bandwidth.fetch_add(allocated as u64, Ordering::Relaxed);
-        
-        allocated
+    // 未指定租户和设备组的会话归入默认池
+    async fn allocate_bandwidth(&self, session_id: &str, requested_kbps: u32) -> u32 {
+        self.allocate_session_bandwidth(session_id, "", "", requested_kbps as u64).await as u32
+    }
+}
+
+impl BandwidthPools {
+    // 按租户、设备组归类会话，池的需求为下级需求之和，不超过池的上限
+    fn build(&self) -> Vec<PoolUsage> {
+        let mut tree: BTreeMap<&str, BTreeMap<&str, Vec<PoolUsage>>> = BTreeMap::new();
+        for (session_id, session) in self.sessions.iter() {
+            tree.entry(&session.tenant)
+                .or_default()
+                .entry(&session.group)
+                .or_default()
+                .push(PoolUsage {
+                    name: session_id.clone(),
+                    weight: 1,
+                    demand_kbps: session.requested_kbps,
+                    allocated_kbps: 0,
+                    children: Vec::new(),
+                });
+        }
+        let default_tenant = TenantPoolConfig::default();
+        tree.into_iter()
+            .map(|(tenant, groups)| {
+                let tenant_config = self.tenants.get(tenant).unwrap_or(&default_tenant);
+                let groups: Vec<PoolUsage> = groups
+                    .into_iter()
+                    .map(|(group, mut sessions)| {
+                        sessions.sort_by(|a, b| a.name.cmp(&b.name));
+                        let config = tenant_config.groups.get(group).cloned().unwrap_or_default();
+                        pool(group, &config, sessions)
+                    })
+                    .collect();
+                pool(tenant, &tenant_config.pool, groups)
+            })
+            .collect()
+    }
+}
+
+fn pool(name: &str, config: &PoolConfig, children: Vec<PoolUsage>) -> PoolUsage {
+    let demand: u64 = children.iter().map(|c| c.demand_kbps).sum();
+    PoolUsage {
+        name: name.to_owned(),
+        weight: config.weight,
+        demand_kbps: config.capacity_kbps.map(|c| c.min(demand)).unwrap_or(demand),
+        allocated_kbps: 0,
+        children,
+    }
+}
+
+fn distribute(capacity: u64, nodes: &mut [PoolUsage]) {
+    let demands: Vec<(u64, u32)> = nodes.iter().map(|n| (n.demand_kbps, n.weight)).collect();
+    for (node, share) in nodes.iter_mut().zip(fair_share(capacity, &demands)) {
+        node.allocated_kbps = share;
+        distribute(share, &mut node.children);
+    }
+}
+
+// 加权最大最小公平分配，demands 为 (需求, 权重)
+pub fn fair_share(capacity: u64, demands: &[(u64, u32)]) -> Vec<u64> {
+    let mut res = vec![0u64; demands.len()];
+    let mut active: Vec<usize> = (0..demands.len())
+        .filter(|&i| demands[i].0 > 0 && demands[i].1 > 0)
+        .collect();
+    let mut remaining = capacity;
+    while !active.is_empty() && remaining > 0 {
+        let total_weight: u64 = active.iter().map(|&i| demands[i].1 as u64).sum();
+        let share = |i: usize| remaining * demands[i].1 as u64 / total_weight;
+        // 需求不超过本轮份额的先按需求满足，释放出的带宽在下一轮重新分配
+        let satisfied: Vec<usize> = active
+            .iter()
+            .cloned()
+            .filter(|&i| demands[i].0 - res[i] <= share(i))
+            .collect();
+        if satisfied.is_empty() {
+            let shares: Vec<u64> = active.iter().map(|&i| share(i)).collect();
+            for (&i, share) in active.iter().zip(shares) {
+                res[i] += share;
+            }
+            break;
+        }
+        for i in satisfied {
+            remaining -= demands[i].0 - res[i];
+            res[i] = demands[i].0;
+        }
+        active.retain(|&i| res[i] < demands[i].0);
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fair_share() {
+        // 需求都能满足
+        assert_eq!(fair_share(1000, &[(100, 1), (200, 1)]), vec![100, 200]);
+        // 小需求先满足，剩余按权重分
+        assert_eq!(fair_share(1000, &[(100, 1), (2000, 1), (2000, 2)]), vec![100, 300, 600]);
+        assert_eq!(fair_share(900, &[(2000, 1), (2000, 2)]), vec![300, 600]);
+        assert_eq!(fair_share(0, &[(100, 1)]), vec![0]);
+    }
+
+    #[tokio::test]
+    async fn test_bandwidth_pools() {
+        let manager = BandwidthManager::with_capacity(10_000);
+        let mut tenants = HashMap::new();
+        tenants.insert(
+            "acme".to_owned(),
+            TenantPoolConfig {
+                pool: PoolConfig { capacity_kbps: Some(3_000), weight: 1 },
+                groups: [("sales".to_owned(), PoolConfig { capacity_kbps: None, weight: 2 })]
+                    .into_iter()
+                    .collect(),
+            },
+        );
+        manager.set_pools(tenants).await;
+        manager.allocate_session_bandwidth("s1", "acme", "sales", 4_000).await;
+        manager.allocate_session_bandwidth("s2", "acme", "support", 4_000).await;
+        manager.allocate_session_bandwidth("s3", "globex", "", 8_000).await;
+        manager.allocate_session_bandwidth("s4", "globex", "", 8_000).await;
+        // acme 受上限 3000 限制，组内 sales:support = 2:1；未配置的 globex 分到剩余的 7000
+        assert_eq!(manager.session_allocation("s1").await, Some(2_000));
+        assert_eq!(manager.session_allocation("s2").await, Some(1_000));
+        assert_eq!(manager.session_allocation("s3").await, Some(3_500));
+        assert_eq!(manager.remaining_bandwidth(), 0);
+
+        // 会话结束后带宽回收给同组的会话
+        assert!(manager.release_bandwidth("s4").await);
+        assert_eq!(manager.session_allocation("s3").await, Some(7_000));
+        manager.sync_sessions(vec![("s3".to_owned(), "globex".to_owned(), String::new(), 1_000)]).await;
+        assert_eq!(manager.session_allocation("s1").await, None);
+        assert_eq!(manager.remaining_bandwidth(), 9_000);
+        let usage = manager.pool_usage().await;
+        assert_eq!(usage.len(), 1);
+        assert_eq!(usage[0].children[0].children[0].allocated_kbps, 1_000);
     }
 }
\ No newline at end of file
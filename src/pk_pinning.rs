@@ -0,0 +1,264 @@
+// 设备公钥固定模块 - 已知设备在 RegisterPk 中上报的公钥(pk)与已记录的不同(uuid 校验已通过)时:
+//   - 记录 High 级安全事件(新旧公钥指纹、来源IP)，并经邮件/ITSM 告警
+//   - 每个设备出现过的公钥都保存在 device_pk_history 中，供取证
+//   - 启用隔离(quarantine)时新公钥进入待审批状态，审批前拒绝该公钥注册，设备对控制端表现为离线；
+//     管理员批准后设备重新注册即可使用新公钥，驳回的公钥一直被拒绝
+// 设备首次注册的公钥直接记为已接受；未启用隔离时公钥变化只告警，新公钥记为已接受
+use crate::advanced_security::{SecurityEvent, SecurityEventType, SecuritySeverity};
+use crate::common::now;
+use crate::enterprise_database::EnterpriseDatabase;
+use crate::validation::Payload;
+use hbb_common::{bail, log, tokio::sync::RwLock, ResultType};
+use schemars::JsonSchema;
+use serde_derive::{Deserialize, Serialize};
+use sodiumoxide::crypto::hash::sha256;
+use std::{
+    collections::{HashMap, HashSet},
+    time::SystemTime,
+};
+use validator::Validate;
+
+pub const PK_PINNING_KEY: &str = "pk_pinning";
+
+lazy_static::lazy_static! {
+    static ref CONFIG: RwLock<PkPinningConfig> = Default::default();
+    // 有待审批公钥的设备
+    static ref QUARANTINED: RwLock<HashSet<String>> = Default::default();
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, JsonSchema, Validate)]
+pub struct PkPinningConfig {
+    // 公钥变化时隔离设备，等待管理员审批
+    #[serde(default)]
+    pub quarantine: bool,
+}
+
+impl Payload for PkPinningConfig {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum PkStatus {
+    Accepted,
+    Pending,
+    Rejected,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct PkRecord {
+    pub id: i64,
+    pub device_id: String,
+    // base64 编码的公钥
+    pub pk: String,
+    pub fingerprint: String,
+    pub uuid: String,
+    pub ip_address: String,
+    // 首次出现的时间
+    pub seen_at: u64,
+    pub status: PkStatus,
+    pub decided_by: Option<String>,
+    pub decided_at: Option<u64>,
+}
+
+// 公钥的 SHA-256 指纹(十六进制)
+pub fn fingerprint(pk: &[u8]) -> String {
+    sha256::hash(pk).0.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn record(device_id: &str, pk: &[u8], uuid: &[u8], ip: &str, status: PkStatus) -> PkRecord {
+    PkRecord {
+        id: 0,
+        device_id: device_id.to_owned(),
+        pk: base64::encode(pk),
+        fingerprint: fingerprint(pk),
+        uuid: base64::encode(uuid),
+        ip_address: ip.to_owned(),
+        seen_at: now(),
+        status,
+        decided_by: None,
+        decided_at: None,
+    }
+}
+
+// 由 RegisterPk 调用，previous 为设备当前的公钥(新设备为空)；返回 false 时拒绝注册
+pub async fn verify(db: &EnterpriseDatabase, device_id: &str, uuid: &[u8], previous: &[u8], pk: &[u8], ip: &str) -> bool {
+    if !previous.is_empty() && previous == pk {
+        return true;
+    }
+    match db.get_device_pk(device_id, &base64::encode(pk)).await {
+        Ok(Some(known)) => return known.status == PkStatus::Accepted,
+        Ok(None) => {}
+        Err(e) => {
+            // 数据库异常时不影响注册，与未启用本功能时一致
+            log::error!("Failed to get pk history of {}: {}", device_id, e);
+            return true;
+        }
+    }
+    if previous.is_empty() {
+        if let Err(e) = db.insert_device_pk(&record(device_id, pk, uuid, ip, PkStatus::Accepted)).await {
+            log::error!("Failed to save pk of {}: {}", device_id, e);
+        }
+        return true;
+    }
+
+    let quarantine = CONFIG.read().await.quarantine;
+    // 升级前注册的设备没有历史记录，先补记原公钥
+    let res = async {
+        db.insert_device_pk(&record(device_id, previous, uuid, ip, PkStatus::Accepted))
+            .await?;
+        let status = if quarantine { PkStatus::Pending } else { PkStatus::Accepted };
+        db.insert_device_pk(&record(device_id, pk, uuid, ip, status)).await
+    }
+    .await;
+    if let Err(e) = res {
+        log::error!("Failed to save pk change of {}: {}", device_id, e);
+    }
+    if quarantine {
+        QUARANTINED.write().await.insert(device_id.to_owned());
+    }
+    raise(db, device_id, previous, pk, ip, quarantine).await;
+    !quarantine
+}
+
+async fn raise(db: &EnterpriseDatabase, device_id: &str, previous: &[u8], pk: &[u8], ip: &str, quarantined: bool) {
+    let details: HashMap<String, String> = [
+        ("reason", "pk_changed".to_owned()),
+        ("previous_fingerprint", fingerprint(previous)),
+        ("fingerprint", fingerprint(pk)),
+        ("quarantined", quarantined.to_string()),
+    ]
+    .into_iter()
+    .map(|(k, v)| (k.to_owned(), v))
+    .collect();
+    let event = SecurityEvent {
+        id: uuid::Uuid::new_v4().to_string(),
+        event_type: SecurityEventType::SuspiciousActivity,
+        severity: SecuritySeverity::High,
+        user_id: None,
+        device_id: Some(device_id.to_owned()),
+        ip_address: ip.to_owned(),
+        user_agent: None,
+        details,
+        timestamp: SystemTime::now(),
+        resolved: false,
+        resolution_notes: None,
+    };
+    if let Err(err) = db.save_security_event(&event).await {
+        log::error!("Failed to save pk change security event: {}", err);
+    }
+    log::warn!(
+        "Peer {} pk changed from {} ({}), quarantined: {}",
+        device_id,
+        ip,
+        fingerprint(pk),
+        quarantined
+    );
+    crate::notifications::security_event(db, &event).await;
+    crate::itsm::security_event(&event).await;
+}
+
+// 隔离中的设备对控制端表现为离线
+pub async fn is_quarantined(device_id: &str) -> bool {
+    QUARANTINED.read().await.contains(device_id)
+}
+
+// 批准或驳回待审批的公钥，设备没有其他待审批公钥时解除隔离；记录不存在或已处理时返回 None
+pub async fn decide(db: &EnterpriseDatabase, id: i64, approve: bool, decided_by: &str) -> ResultType<Option<PkRecord>> {
+    let mut record = match db.get_device_pk_by_id(id).await? {
+        Some(record) if record.status == PkStatus::Pending => record,
+        _ => return Ok(None),
+    };
+    record.status = if approve { PkStatus::Accepted } else { PkStatus::Rejected };
+    record.decided_by = Some(decided_by.to_owned());
+    record.decided_at = Some(now());
+    if !db.decide_device_pk(&record).await? {
+        return Ok(None);
+    }
+    let history = db.list_device_pk_history(&record.device_id).await?;
+    if !history.iter().any(|x| x.status == PkStatus::Pending) {
+        QUARANTINED.write().await.remove(&record.device_id);
+    }
+    Ok(Some(record))
+}
+
+pub async fn reload(db: &EnterpriseDatabase) -> ResultType<()> {
+    let config = match db.get_setting(PK_PINNING_KEY).await? {
+        Some(v) => serde_json::from_str(&v)?,
+        None => PkPinningConfig::default(),
+    };
+    let pending = db.list_device_pks_by_status(PkStatus::Pending).await?;
+    *CONFIG.write().await = config;
+    *QUARANTINED.write().await = pending.into_iter().map(|x| x.device_id).collect();
+    Ok(())
+}
+
+pub async fn get() -> PkPinningConfig {
+    CONFIG.read().await.clone()
+}
+
+pub async fn update(db: &EnterpriseDatabase, config: PkPinningConfig, updated_by: &str) -> ResultType<()> {
+    if config.quarantine && !CONFIG.read().await.quarantine {
+        log::info!("Peer pk quarantine enabled by {}", updated_by);
+    }
+    db.set_setting(PK_PINNING_KEY, &serde_json::to_string(&config)?, Some(updated_by))
+        .await?;
+    *CONFIG.write().await = config;
+    Ok(())
+}
+
+// 待审批公钥的ID须为正数
+pub fn parse_id(id: &str) -> ResultType<i64> {
+    match id.parse::<i64>() {
+        Ok(id) if id > 0 => Ok(id),
+        _ => bail!("invalid pk record id {}", id),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hbb_common::tokio;
+
+    #[tokio::test]
+    async fn test_pk_pinning() {
+        let db = EnterpriseDatabase::memory().await.unwrap();
+        let (uuid, ip) = (b"uuid-1".as_slice(), "192.0.2.10");
+        // 新设备直接接受，同一公钥不重复记录
+        assert!(verify(&db, "123456789", uuid, b"", b"pk-1", ip).await);
+        assert!(verify(&db, "123456789", uuid, b"pk-1", b"pk-1", ip).await);
+        assert_eq!(db.list_device_pk_history("123456789").await.unwrap().len(), 1);
+
+        // 未启用隔离时只告警
+        assert!(verify(&db, "123456789", uuid, b"pk-1", b"pk-2", ip).await);
+        let history = db.list_device_pk_history("123456789").await.unwrap();
+        assert_eq!(history.len(), 2);
+        assert!(history.iter().all(|x| x.status == PkStatus::Accepted));
+        let events = db.list_security_events(0, 10).await.unwrap();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].details["fingerprint"], fingerprint(b"pk-2"));
+
+        update(&db, PkPinningConfig { quarantine: true }, "admin").await.unwrap();
+        assert!(!verify(&db, "123456789", uuid, b"pk-2", b"pk-3", ip).await);
+        assert!(is_quarantined("123456789").await);
+        // 审批前重复注册仍被拒绝，且不重复告警
+        assert!(!verify(&db, "123456789", uuid, b"pk-2", b"pk-3", ip).await);
+        assert_eq!(db.list_security_events(0, 10).await.unwrap().len(), 2);
+        // 切回已接受的旧公钥可以注册
+        assert!(verify(&db, "123456789", uuid, b"pk-3", b"pk-1", ip).await);
+
+        let pending = db.list_device_pks_by_status(PkStatus::Pending).await.unwrap();
+        assert_eq!(pending.len(), 1);
+        let decided = decide(&db, pending[0].id, true, "admin").await.unwrap().unwrap();
+        assert_eq!(decided.status, PkStatus::Accepted);
+        assert!(decide(&db, pending[0].id, false, "admin").await.unwrap().is_none());
+        assert!(!is_quarantined("123456789").await);
+        assert!(verify(&db, "123456789", uuid, b"pk-2", b"pk-3", ip).await);
+
+        // 驳回的公钥一直被拒绝
+        assert!(!verify(&db, "123456789", uuid, b"pk-3", b"pk-4", ip).await);
+        let pending = db.list_device_pks_by_status(PkStatus::Pending).await.unwrap();
+        decide(&db, pending[0].id, false, "admin").await.unwrap().unwrap();
+        assert!(!is_quarantined("123456789").await);
+        assert!(!verify(&db, "123456789", uuid, b"pk-3", b"pk-4", ip).await);
+        update(&db, PkPinningConfig::default(), "admin").await.unwrap();
+    }
+}
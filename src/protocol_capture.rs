@@ -0,0 +1,452 @@
+// 协议抓包模块 - 排查"无法连接"问题时，管理员可对指定设备ID开启限时抓包，记录信令服务器收发的
+// 与该设备相关的 RendezvousMessage(解码为文本，登录令牌、licence key 和设备 uuid 脱敏):
+//   - 消息中的ID为该设备，或收发地址是抓包期间已出现过的该设备/控制端地址
+//   - 达到时长或条数上限后自动停止，结果连同设备的打洞诊断和中继会话打包下载
+// 抓包只保存在内存中，结束后保留 24 小时
+use crate::nat_diagnostics::{self, PeerNatDiagnostics};
+use crate::relay_sessions::{self, RelaySession};
+use crate::validation::Payload;
+use hbb_common::{
+    bail,
+    bytes::Bytes,
+    log,
+    rendezvous_proto::{rendezvous_message::Union, RendezvousMessage},
+    tokio::sync::RwLock,
+    AddrMangle, ResultType,
+};
+use schemars::JsonSchema;
+use serde_derive::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, HashSet},
+    net::SocketAddr,
+    sync::atomic::{AtomicBool, Ordering},
+    time::{SystemTime, UNIX_EPOCH},
+};
+use validator::Validate;
+
+const REDACTED: &str = "<redacted>";
+const MAX_CAPTURES: usize = 20;
+const MAX_DURATION_MINS: u64 = 60;
+const MAX_MESSAGES: usize = 10_000;
+const RETAIN_SECS: u64 = 24 * 3600;
+
+lazy_static::lazy_static! {
+    static ref CAPTURES: RwLock<HashMap<String, CaptureData>> = Default::default();
+}
+
+// 有进行中的抓包时才检查消息，避免影响正常的信令处理
+static ACTIVE: AtomicBool = AtomicBool::new(false);
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub enum Direction {
+    Inbound,
+    Outbound,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, Validate)]
+pub struct StartCaptureRequest {
+    #[validate(length(min = 1, max = 100))]
+    pub device_id: String,
+    // 默认 10 分钟，最长 60 分钟
+    #[serde(default)]
+    #[validate(range(min = 1, max = 60))]
+    pub duration_mins: Option<u64>,
+    // 默认 1000 条，最多 10000 条
+    #[serde(default)]
+    #[validate(range(min = 1, max = 10000))]
+    pub max_messages: Option<usize>,
+}
+
+impl Payload for StartCaptureRequest {}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct Capture {
+    pub id: String,
+    pub device_id: String,
+    pub started_by: String,
+    pub started_at: u64,
+    pub expires_at: u64,
+    pub stopped_at: Option<u64>,
+    pub max_messages: usize,
+    pub messages: usize,
+    // 达到条数上限后停止记录
+    pub truncated: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct CapturedMessage {
+    // 毫秒时间戳
+    pub at: u64,
+    pub direction: Direction,
+    // udp / tcp / ws
+    pub transport: String,
+    pub addr: String,
+    pub kind: String,
+    pub decoded: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct DiagnosticBundle {
+    pub capture: Capture,
+    pub messages: Vec<CapturedMessage>,
+    pub nat: Option<PeerNatDiagnostics>,
+    pub relay_sessions: Vec<RelaySession>,
+    pub server_version: String,
+    pub generated_at: u64,
+}
+
+struct CaptureData {
+    capture: Capture,
+    messages: Vec<CapturedMessage>,
+    // 抓包期间出现过的设备和控制端地址
+    addrs: HashSet<SocketAddr>,
+}
+
+impl Capture {
+    fn is_running(&self, now: u64) -> bool {
+        self.stopped_at.is_none() && now < self.expires_at
+    }
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|x| x.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+// 消息中的设备ID
+fn ids_of(msg: &RendezvousMessage) -> Vec<&str> {
+    match &msg.union {
+        Some(Union::RegisterPeer(x)) => vec![x.id.as_str()],
+        Some(Union::RegisterPk(x)) => vec![x.id.as_str()],
+        Some(Union::PunchHoleRequest(x)) => vec![x.id.as_str()],
+        Some(Union::PunchHoleSent(x)) => vec![x.id.as_str()],
+        Some(Union::LocalAddr(x)) => vec![x.id.as_str()],
+        Some(Union::RequestRelay(x)) => vec![x.id.as_str()],
+        Some(Union::RelayResponse(x)) => vec![x.id()],
+        Some(Union::OnlineRequest(x)) => std::iter::once(x.id.as_str())
+            .chain(x.peers.iter().map(|x| x.as_str()))
+            .collect(),
+        _ => vec![],
+    }
+}
+
+// 消息中携带的对方地址(控制端或设备)，用于关联后续不带ID的消息
+fn addr_of(msg: &RendezvousMessage) -> Option<SocketAddr> {
+    let socket_addr = match &msg.union {
+        Some(Union::PunchHole(x)) => &x.socket_addr,
+        Some(Union::FetchLocalAddr(x)) => &x.socket_addr,
+        Some(Union::PunchHoleSent(x)) => &x.socket_addr,
+        Some(Union::LocalAddr(x)) => &x.socket_addr,
+        Some(Union::RequestRelay(x)) => &x.socket_addr,
+        Some(Union::RelayResponse(x)) => &x.socket_addr,
+        Some(Union::PunchHoleResponse(x)) => &x.socket_addr,
+        _ => return None,
+    };
+    if socket_addr.is_empty() {
+        return None;
+    }
+    Some(AddrMangle::decode(socket_addr))
+}
+
+fn kind_of(msg: &RendezvousMessage) -> &'static str {
+    match &msg.union {
+        Some(Union::RegisterPeer(_)) => "RegisterPeer",
+        Some(Union::RegisterPeerResponse(_)) => "RegisterPeerResponse",
+        Some(Union::RegisterPk(_)) => "RegisterPk",
+        Some(Union::RegisterPkResponse(_)) => "RegisterPkResponse",
+        Some(Union::PunchHoleRequest(_)) => "PunchHoleRequest",
+        Some(Union::PunchHole(_)) => "PunchHole",
+        Some(Union::PunchHoleSent(_)) => "PunchHoleSent",
+        Some(Union::PunchHoleResponse(_)) => "PunchHoleResponse",
+        Some(Union::FetchLocalAddr(_)) => "FetchLocalAddr",
+        Some(Union::LocalAddr(_)) => "LocalAddr",
+        Some(Union::ConfigureUpdate(_)) => "ConfigureUpdate",
+        Some(Union::RequestRelay(_)) => "RequestRelay",
+        Some(Union::RelayResponse(_)) => "RelayResponse",
+        Some(Union::TestNatRequest(_)) => "TestNatRequest",
+        Some(Union::TestNatResponse(_)) => "TestNatResponse",
+        Some(Union::PeerDiscovery(_)) => "PeerDiscovery",
+        Some(Union::OnlineRequest(_)) => "OnlineRequest",
+        Some(Union::OnlineResponse(_)) => "OnlineResponse",
+        Some(Union::KeyExchange(_)) => "KeyExchange",
+        Some(Union::Hc(_)) => "HealthCheck",
+        Some(_) => "Other",
+        None => "Empty",
+    }
+}
+
+fn redact_str(x: &mut String) {
+    if !x.is_empty() {
+        *x = REDACTED.to_owned();
+    }
+}
+
+// 去掉登录令牌、licence key 和设备 uuid 后解码为文本
+fn decode(msg: &RendezvousMessage) -> String {
+    let mut msg = msg.clone();
+    match msg.union.as_mut() {
+        Some(Union::PunchHoleRequest(x)) => {
+            redact_str(&mut x.token);
+            redact_str(&mut x.licence_key);
+        }
+        Some(Union::RequestRelay(x)) => {
+            redact_str(&mut x.token);
+            redact_str(&mut x.licence_key);
+        }
+        Some(Union::RegisterPk(x)) => {
+            if !x.uuid.is_empty() {
+                x.uuid = Bytes::from_static(REDACTED.as_bytes());
+            }
+        }
+        _ => {}
+    }
+    format!("{:?}", msg)
+}
+
+async fn record(direction: Direction, msg: &RendezvousMessage, addr: SocketAddr, transport: &str) {
+    if !ACTIVE.load(Ordering::Relaxed) {
+        return;
+    }
+    let now = crate::common::now();
+    let ids = ids_of(msg);
+    let mut captures = CAPTURES.write().await;
+    let mut message = None;
+    let mut running = false;
+    for data in captures.values_mut() {
+        if !data.capture.is_running(now) {
+            continue;
+        }
+        running = true;
+        if !ids.contains(&data.capture.device_id.as_str()) && !data.addrs.contains(&addr) {
+            continue;
+        }
+        data.addrs.insert(addr);
+        if let Some(other) = addr_of(msg) {
+            data.addrs.insert(other);
+        }
+        if data.messages.len() >= data.capture.max_messages {
+            data.capture.truncated = true;
+            data.capture.stopped_at = Some(now);
+            continue;
+        }
+        let message = message
+            .get_or_insert_with(|| CapturedMessage {
+                at: now_ms(),
+                direction,
+                transport: transport.to_owned(),
+                addr: addr.to_string(),
+                kind: kind_of(msg).to_owned(),
+                decoded: decode(msg),
+            })
+            .clone();
+        data.messages.push(message);
+        data.capture.messages = data.messages.len();
+    }
+    if !running {
+        ACTIVE.store(false, Ordering::Relaxed);
+    }
+}
+
+// 信令服务器收到的消息
+pub async fn inbound(msg: &RendezvousMessage, addr: SocketAddr, transport: &str) {
+    record(Direction::Inbound, msg, addr, transport).await
+}
+
+// 信令服务器发出的消息
+pub async fn outbound(msg: &RendezvousMessage, addr: SocketAddr, transport: &str) {
+    record(Direction::Outbound, msg, addr, transport).await
+}
+
+fn expire(captures: &mut HashMap<String, CaptureData>, now: u64) {
+    captures.retain(|_, data| {
+        let ended = data.capture.stopped_at.unwrap_or(data.capture.expires_at);
+        data.capture.is_running(now) || ended + RETAIN_SECS > now
+    });
+}
+
+pub async fn start(req: StartCaptureRequest, started_by: &str) -> ResultType<Capture> {
+    let device_id = req.device_id.trim().to_owned();
+    if device_id.is_empty() {
+        bail!("device_id is required");
+    }
+    let duration_mins = req.duration_mins.unwrap_or(10);
+    if duration_mins == 0 || duration_mins > MAX_DURATION_MINS {
+        bail!("duration_mins must be 1-{}", MAX_DURATION_MINS);
+    }
+    let max_messages = req.max_messages.unwrap_or(1000);
+    if max_messages == 0 || max_messages > MAX_MESSAGES {
+        bail!("max_messages must be 1-{}", MAX_MESSAGES);
+    }
+    let now = crate::common::now();
+    let mut captures = CAPTURES.write().await;
+    expire(&mut captures, now);
+    if captures
+        .values()
+        .any(|x| x.capture.device_id == device_id && x.capture.is_running(now))
+    {
+        bail!("a capture of {} is already running", device_id);
+    }
+    if captures.len() >= MAX_CAPTURES {
+        bail!("at most {} captures, delete finished ones first", MAX_CAPTURES);
+    }
+    let capture = Capture {
+        id: uuid::Uuid::new_v4().to_string(),
+        device_id,
+        started_by: started_by.to_owned(),
+        started_at: now,
+        expires_at: now + duration_mins * 60,
+        stopped_at: None,
+        max_messages,
+        messages: 0,
+        truncated: false,
+    };
+    captures.insert(
+        capture.id.clone(),
+        CaptureData {
+            capture: capture.clone(),
+            messages: Vec::new(),
+            addrs: HashSet::new(),
+        },
+    );
+    ACTIVE.store(true, Ordering::Relaxed);
+    log::info!(
+        "Protocol capture {} of {} started by {} for {} minutes",
+        capture.id,
+        capture.device_id,
+        started_by,
+        duration_mins
+    );
+    Ok(capture)
+}
+
+pub async fn stop(id: &str) -> ResultType<Capture> {
+    let now = crate::common::now();
+    let mut captures = CAPTURES.write().await;
+    let data = match captures.get_mut(id) {
+        Some(data) => data,
+        None => bail!("capture not found"),
+    };
+    if data.capture.is_running(now) {
+        data.capture.stopped_at = Some(now);
+    }
+    Ok(data.capture.clone())
+}
+
+pub async fn delete(id: &str) -> bool {
+    CAPTURES.write().await.remove(id).is_some()
+}
+
+pub async fn list() -> Vec<Capture> {
+    let mut captures = CAPTURES.write().await;
+    expire(&mut captures, crate::common::now());
+    let mut res: Vec<Capture> = captures.values().map(|x| x.capture.clone()).collect();
+    res.sort_by(|a, b| b.started_at.cmp(&a.started_at));
+    res
+}
+
+// 抓包结果及设备的打洞诊断、中继会话
+pub async fn bundle(id: &str) -> Option<DiagnosticBundle> {
+    let (capture, messages) = {
+        let captures = CAPTURES.read().await;
+        let data = captures.get(id)?;
+        (data.capture.clone(), data.messages.clone())
+    };
+    Some(DiagnosticBundle {
+        nat: nat_diagnostics::peer_diagnostics(&capture.device_id).await,
+        relay_sessions: relay_sessions::sessions_of(&capture.device_id).await,
+        server_version: env!("CARGO_PKG_VERSION").to_owned(),
+        generated_at: crate::common::now(),
+        capture,
+        messages,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hbb_common::rendezvous_proto::{PunchHole, PunchHoleRequest, RegisterPk};
+
+    #[test]
+    fn test_decode_redacts_secrets() {
+        let mut msg = RendezvousMessage::new();
+        msg.set_punch_hole_request(PunchHoleRequest {
+            id: "123456789".to_owned(),
+            token: "eyJhbGciOiJIUzI1NiJ9.secret".to_owned(),
+            licence_key: "server-public-key".to_owned(),
+            ..Default::default()
+        });
+        let decoded = decode(&msg);
+        assert!(decoded.contains("123456789"));
+        assert!(!decoded.contains("eyJhbGciOiJIUzI1NiJ9"));
+        assert!(!decoded.contains("server-public-key"));
+        assert_eq!(ids_of(&msg), vec!["123456789"]);
+        assert_eq!(kind_of(&msg), "PunchHoleRequest");
+
+        let mut msg = RendezvousMessage::new();
+        msg.set_register_pk(RegisterPk {
+            id: "123456789".to_owned(),
+            uuid: Bytes::from_static(b"device-uuid-secret"),
+            ..Default::default()
+        });
+        assert!(!decode(&msg).contains("device-uuid-secret"));
+    }
+
+    #[test]
+    fn test_addr_of() {
+        let controller: SocketAddr = "203.0.113.5:40000".parse().unwrap();
+        let mut msg = RendezvousMessage::new();
+        msg.set_punch_hole(PunchHole {
+            socket_addr: AddrMangle::encode(controller).into(),
+            ..Default::default()
+        });
+        assert_eq!(addr_of(&msg), Some(controller));
+        assert!(ids_of(&msg).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_capture() {
+        let device: SocketAddr = "198.51.100.7:21116".parse().unwrap();
+        let controller: SocketAddr = "203.0.113.5:40000".parse().unwrap();
+        let capture = start(
+            StartCaptureRequest {
+                device_id: "capture-test".to_owned(),
+                duration_mins: Some(1),
+                max_messages: Some(2),
+            },
+            "admin",
+        )
+        .await
+        .unwrap();
+        let mut request = RendezvousMessage::new();
+        request.set_punch_hole_request(PunchHoleRequest {
+            id: "capture-test".to_owned(),
+            ..Default::default()
+        });
+        inbound(&request, controller, "tcp").await;
+        let mut punch = RendezvousMessage::new();
+        punch.set_punch_hole(PunchHole {
+            socket_addr: AddrMangle::encode(controller).into(),
+            ..Default::default()
+        });
+        outbound(&punch, device, "udp").await;
+        // 与该设备无关的消息不记录
+        let mut other = RendezvousMessage::new();
+        other.set_punch_hole_request(PunchHoleRequest {
+            id: "other".to_owned(),
+            ..Default::default()
+        });
+        inbound(&other, "192.0.2.1:1".parse().unwrap(), "tcp").await;
+        let bundle = bundle(&capture.id).await.unwrap();
+        assert_eq!(bundle.messages.len(), 1);
+        assert_eq!(bundle.messages[0].kind, "PunchHoleRequest");
+        // 控制端地址出现后，发给它的响应同样记录；达到上限后停止
+        outbound(&punch, controller, "tcp").await;
+        outbound(&punch, controller, "tcp").await;
+        let capture = stop(&capture.id).await.unwrap();
+        assert_eq!(capture.messages, 2);
+        assert!(capture.truncated);
+        assert!(delete(&capture.id).await);
+    }
+}
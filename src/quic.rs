@@ -0,0 +1,328 @@
+// QUIC 传输(实验性) - 设置 QUIC=Y 后 hbbs 和 hbbr 额外监听 QUIC，注册/打洞信令和中继会话可以走 QUIC。
+// QUIC 在 UDP 上按流重传，丢包网络中不会像 TCP 那样整条连接队头阻塞；客户端换网络或 NAT 映射变化后
+// 连接迁移到新地址继续使用，不必重新注册。每个连接只使用客户端打开的第一个双向流，流上的消息格式与
+// TCP 连接相同(BytesCodec 分帧的 protobuf)，服务器发给该设备的消息也经这个流下发。
+// 客户端在心跳的 caps 中声明 quic，服务器启用时在响应中返回 QUIC 端口和证书指纹，否则客户端继续用 TCP/UDP。
+// 证书默认在启动时自签名，客户端按指纹校验；hbbs 与 hbbr 需要同一证书时用 QUIC_CERT / QUIC_KEY 指定 PEM 文件。
+// 需要以 quic 特性编译
+use hbb_common::{bytes::Bytes, tokio::sync::mpsc, ResultType};
+use once_cell::sync::OnceCell;
+use schemars::JsonSchema;
+use serde_derive::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    future::Future,
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        RwLock,
+    },
+};
+
+pub use imp::Duplex;
+
+// 客户端心跳 caps 中的能力名
+pub const CAPABILITY: &str = "quic";
+
+static INFO: OnceCell<QuicInfo> = OnceCell::new();
+static CONNECTIONS: AtomicUsize = AtomicUsize::new(0);
+
+lazy_static::lazy_static! {
+    // 经 QUIC 连接的设备，按对端地址下发消息
+    static ref PEERS: RwLock<HashMap<SocketAddr, mpsc::UnboundedSender<Bytes>>> = Default::default();
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct QuicInfo {
+    pub port: u16,
+    // 证书 DER 的 SHA-256，小写十六进制
+    pub cert_sha256: String,
+}
+
+pub fn enabled() -> bool {
+    std::env::var("QUIC").unwrap_or_default().to_uppercase() == "Y"
+}
+
+// 本进程的 QUIC 监听信息，未启用时为空
+pub fn info() -> Option<QuicInfo> {
+    INFO.get().cloned()
+}
+
+pub fn connections() -> usize {
+    CONNECTIONS.load(Ordering::SeqCst)
+}
+
+pub fn add_peer(addr: SocketAddr, tx: mpsc::UnboundedSender<Bytes>) {
+    if let Ok(mut peers) = PEERS.write() {
+        peers.insert(addr, tx);
+    }
+}
+
+pub fn remove_peer(addr: &SocketAddr) {
+    if let Ok(mut peers) = PEERS.write() {
+        peers.remove(addr);
+    }
+}
+
+// 对端经 QUIC 连接时通过它的流发送，返回 false 表示应走 UDP
+pub fn send_to(addr: &SocketAddr, bytes: Bytes) -> bool {
+    match PEERS.read().ok().and_then(|peers| peers.get(addr).cloned()) {
+        Some(tx) => tx.send(bytes).is_ok(),
+        None => false,
+    }
+}
+
+// 在 UDP 端口上监听 QUIC，每个连接的第一个双向流交给 handler，直到监听失败才返回
+#[allow(unused_variables)]
+pub async fn serve<F, Fut>(port: u16, handler: F) -> ResultType<()>
+where
+    F: Fn(Box<dyn Duplex>, SocketAddr) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    #[cfg(feature = "quic")]
+    {
+        imp::serve(port, handler).await
+    }
+    #[cfg(not(feature = "quic"))]
+    {
+        hbb_common::bail!("QUIC transport requires building with the quic feature")
+    }
+}
+
+#[cfg(not(feature = "quic"))]
+pub mod imp {
+    use hbb_common::tokio::io::{AsyncRead, AsyncWrite};
+
+    pub trait Duplex: AsyncRead + AsyncWrite + Unpin + Send + Sync + 'static {}
+}
+
+#[cfg(feature = "quic")]
+pub mod imp {
+    use super::{QuicInfo, CONNECTIONS, INFO};
+    use hbb_common::{
+        bail, log,
+        tokio::{
+            self,
+            io::{AsyncRead, AsyncWrite, ReadBuf},
+        },
+        ResultType,
+    };
+    use std::{
+        future::Future,
+        io,
+        net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+        pin::Pin,
+        sync::{atomic::Ordering, Arc},
+        task::{Context, Poll},
+        time::Duration,
+    };
+
+    // 客户端换网络期间没有数据也保持连接
+    const KEEP_ALIVE_SECS: u64 = 10;
+    const IDLE_TIMEOUT_SECS: u64 = 30;
+
+    pub trait Duplex: AsyncRead + AsyncWrite + Unpin + Send + Sync + 'static {}
+
+    impl<T: AsyncRead + AsyncWrite + Unpin + Send + Sync + 'static> Duplex for T {}
+
+    // 一个双向流，读写分别对应 quinn 的接收流和发送流
+    pub struct QuicStream {
+        send: quinn::SendStream,
+        recv: quinn::RecvStream,
+    }
+
+    impl AsyncRead for QuicStream {
+        fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+            AsyncRead::poll_read(Pin::new(&mut self.recv), cx, buf)
+        }
+    }
+
+    impl AsyncWrite for QuicStream {
+        fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+            AsyncWrite::poll_write(Pin::new(&mut self.send), cx, buf)
+        }
+
+        fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            AsyncWrite::poll_flush(Pin::new(&mut self.send), cx)
+        }
+
+        fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            AsyncWrite::poll_shutdown(Pin::new(&mut self.send), cx)
+        }
+    }
+
+    pub fn fingerprint(der: &[u8]) -> String {
+        sodiumoxide::crypto::hash::sha256::hash(der)
+            .0
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect()
+    }
+
+    // 读取 QUIC_CERT / QUIC_KEY 指定的 PEM 证书链和私钥，未指定时自签名，返回 DER 格式
+    pub fn load_cert() -> ResultType<(Vec<Vec<u8>>, Vec<u8>)> {
+        use rustls_pemfile::Item;
+        use std::{fs::File, io::BufReader};
+
+        let cert = std::env::var("QUIC_CERT").unwrap_or_default();
+        let key = std::env::var("QUIC_KEY").unwrap_or_default();
+        if cert.is_empty() != key.is_empty() {
+            bail!("QUIC_CERT and QUIC_KEY must be set together");
+        }
+        if cert.is_empty() {
+            let cert = rcgen::generate_simple_self_signed(vec!["rustdesk".to_owned()])?;
+            return Ok((vec![cert.serialize_der()?], cert.serialize_private_key_der()));
+        }
+        let certs = rustls_pemfile::certs(&mut BufReader::new(File::open(&cert)?))?;
+        if certs.is_empty() {
+            bail!("no certificate found in {}", cert);
+        }
+        for item in rustls_pemfile::read_all(&mut BufReader::new(File::open(&key)?))? {
+            if let Item::PKCS8Key(key) | Item::RSAKey(key) | Item::ECKey(key) = item {
+                return Ok((certs, key));
+            }
+        }
+        bail!("no private key found in {}", key)
+    }
+
+    pub fn server_config(certs: Vec<Vec<u8>>, key: Vec<u8>) -> ResultType<quinn::ServerConfig> {
+        let certs = certs.into_iter().map(rustls::Certificate).collect();
+        let mut config = quinn::ServerConfig::with_single_cert(certs, rustls::PrivateKey(key))?;
+        let mut transport = quinn::TransportConfig::default();
+        transport
+            .keep_alive_interval(Some(Duration::from_secs(KEEP_ALIVE_SECS)))
+            .max_idle_timeout(Some(Duration::from_secs(IDLE_TIMEOUT_SECS).try_into()?));
+        config.transport_config(Arc::new(transport));
+        Ok(config)
+    }
+
+    pub fn bind(port: u16, config: quinn::ServerConfig) -> io::Result<quinn::Endpoint> {
+        let addr = SocketAddr::new(IpAddr::V6(Ipv6Addr::UNSPECIFIED), port);
+        if let Ok(endpoint) = quinn::Endpoint::server(config.clone(), addr) {
+            return Ok(endpoint);
+        }
+        quinn::Endpoint::server(config, SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), port))
+    }
+
+    pub async fn serve<F, Fut>(port: u16, handler: F) -> ResultType<()>
+    where
+        F: Fn(Box<dyn Duplex>, SocketAddr) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let (certs, key) = load_cert()?;
+        let cert_sha256 = fingerprint(&certs[0]);
+        let endpoint = bind(port, server_config(certs, key)?)?;
+        log::info!("Listening on quic :{}, certificate sha256 {}", port, cert_sha256);
+        INFO.set(QuicInfo { port, cert_sha256 }).ok();
+        let handler = Arc::new(handler);
+        while let Some(connecting) = endpoint.accept().await {
+            let handler = handler.clone();
+            tokio::spawn(async move {
+                let connection = match connecting.await {
+                    Ok(connection) => connection,
+                    Err(err) => {
+                        log::debug!("QUIC handshake failed: {}", err);
+                        return;
+                    }
+                };
+                let addr = hbb_common::try_into_v4(connection.remote_address());
+                if let Ok((send, recv)) = connection.accept_bi().await {
+                    CONNECTIONS.fetch_add(1, Ordering::SeqCst);
+                    handler(Box::new(QuicStream { send, recv }), addr).await;
+                    CONNECTIONS.fetch_sub(1, Ordering::SeqCst);
+                }
+                connection.close(0u32.into(), b"");
+            });
+        }
+        Ok(())
+    }
+}
+
+#[cfg(all(test, feature = "quic"))]
+mod tests {
+    use super::imp::fingerprint;
+    use super::*;
+    use hbb_common::tokio::{
+        self,
+        io::{AsyncReadExt, AsyncWriteExt},
+    };
+    use std::sync::Arc;
+
+    // 测试客户端按指纹校验自签名证书
+    struct Pinned(String);
+
+    impl rustls::client::ServerCertVerifier for Pinned {
+        fn verify_server_cert(
+            &self,
+            end_entity: &rustls::Certificate,
+            _intermediates: &[rustls::Certificate],
+            _server_name: &rustls::ServerName,
+            _scts: &mut dyn Iterator<Item = &[u8]>,
+            _ocsp_response: &[u8],
+            _now: std::time::SystemTime,
+        ) -> Result<rustls::client::ServerCertVerified, rustls::Error> {
+            if fingerprint(&end_entity.0) == self.0 {
+                Ok(rustls::client::ServerCertVerified::assertion())
+            } else {
+                Err(rustls::Error::General("fingerprint mismatch".to_owned()))
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_serve() {
+        let port = std::net::UdpSocket::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        tokio::spawn(serve(port, |mut stream, _addr| async move {
+            let mut buf = [0u8; 5];
+            stream.read_exact(&mut buf).await.unwrap();
+            stream.write_all(&buf).await.unwrap();
+            stream.shutdown().await.unwrap();
+        }));
+        while info().is_none() {
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        let info = info().unwrap();
+        assert_eq!(info.port, port);
+
+        let crypto = rustls::ClientConfig::builder()
+            .with_safe_defaults()
+            .with_custom_certificate_verifier(Arc::new(Pinned(info.cert_sha256)))
+            .with_no_client_auth();
+        let mut client = quinn::Endpoint::client("127.0.0.1:0".parse().unwrap()).unwrap();
+        client.set_default_client_config(quinn::ClientConfig::new(Arc::new(crypto)));
+        let connection = client
+            .connect(format!("127.0.0.1:{}", port).parse().unwrap(), "rustdesk")
+            .unwrap()
+            .await
+            .unwrap();
+        let (mut send, mut recv) = connection.open_bi().await.unwrap();
+        send.write_all(b"hello").await.unwrap();
+        assert_eq!(recv.read_to_end(1024).await.unwrap(), b"hello");
+        assert_eq!(connections(), 1);
+
+        // 指纹不符的证书被客户端拒绝
+        let crypto = rustls::ClientConfig::builder()
+            .with_safe_defaults()
+            .with_custom_certificate_verifier(Arc::new(Pinned("00".to_owned())))
+            .with_no_client_auth();
+        client.set_default_client_config(quinn::ClientConfig::new(Arc::new(crypto)));
+        let connecting = client.connect(format!("127.0.0.1:{}", port).parse().unwrap(), "rustdesk");
+        assert!(connecting.unwrap().await.is_err());
+    }
+
+    #[test]
+    fn test_send_to() {
+        let addr: SocketAddr = "1.2.3.4:5".parse().unwrap();
+        assert!(!send_to(&addr, Bytes::from_static(b"x")));
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        add_peer(addr, tx);
+        assert!(send_to(&addr, Bytes::from_static(b"x")));
+        assert_eq!(rx.try_recv().unwrap(), Bytes::from_static(b"x"));
+        remove_peer(&addr);
+        assert!(!send_to(&addr, Bytes::from_static(b"x")));
+    }
+}
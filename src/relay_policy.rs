@@ -0,0 +1,102 @@
+// 中继策略模块 - 按设备组/设备强制使用中继，替代单一的 ALWAYS_USE_RELAY 全局开关
+use crate::enterprise_database::EnterpriseDatabase;
+use crate::validation::{self, Payload};
+use hbb_common::{log, tokio::sync::RwLock, ResultType};
+use schemars::JsonSchema;
+use serde_derive::{Deserialize, Serialize};
+use validator::Validate;
+
+pub const RELAY_POLICY_KEY: &str = "relay_policy";
+
+lazy_static::lazy_static! {
+    static ref RELAY_POLICY: RwLock<RelayPolicy> = Default::default();
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema, Validate)]
+pub struct RelayPolicy {
+    #[serde(default)]
+    #[validate(custom = "validation::non_empty_items")]
+    pub force_relay_groups: Vec<String>, // 设备组ID
+    #[serde(default)]
+    #[validate(custom = "validation::non_empty_items")]
+    pub force_relay_devices: Vec<String>, // 设备ID
+}
+
+impl Payload for RelayPolicy {}
+
+impl RelayPolicy {
+    pub fn is_empty(&self) -> bool {
+        self.force_relay_groups.is_empty() && self.force_relay_devices.is_empty()
+    }
+
+    pub fn matches(&self, device_id: &str, group_ids: &[String]) -> bool {
+        self.force_relay_devices.iter().any(|x| x == device_id)
+            || group_ids
+                .iter()
+                .any(|g| self.force_relay_groups.contains(g))
+    }
+}
+
+// 从数据库加载策略到内存缓存，启动时和设置更新后调用
+pub async fn reload(db: &EnterpriseDatabase) -> ResultType<()> {
+    let policy = match db.get_setting(RELAY_POLICY_KEY).await? {
+        Some(v) => serde_json::from_str(&v)?,
+        None => RelayPolicy::default(),
+    };
+    log::info!(
+        "relay policy loaded: {} groups, {} devices",
+        policy.force_relay_groups.len(),
+        policy.force_relay_devices.len()
+    );
+    *RELAY_POLICY.write().await = policy;
+    Ok(())
+}
+
+pub async fn get() -> RelayPolicy {
+    RELAY_POLICY.read().await.clone()
+}
+
+pub async fn update(db: &EnterpriseDatabase, policy: RelayPolicy, updated_by: &str) -> ResultType<()> {
+    db.set_setting(RELAY_POLICY_KEY, &serde_json::to_string(&policy)?, Some(updated_by))
+        .await?;
+    *RELAY_POLICY.write().await = policy;
+    Ok(())
+}
+
+// 打洞处理时判断目标设备是否必须走中继
+pub async fn should_force_relay(db: &EnterpriseDatabase, device_id: &str) -> bool {
+    let policy = RELAY_POLICY.read().await;
+    if policy.is_empty() {
+        return false;
+    }
+    if policy.force_relay_devices.iter().any(|x| x == device_id) {
+        return true;
+    }
+    if policy.force_relay_groups.is_empty() {
+        return false;
+    }
+    match db.get_device_group_ids(device_id).await {
+        Ok(group_ids) => policy.matches(device_id, &group_ids),
+        Err(err) => {
+            log::error!("Failed to load groups of {}: {}", device_id, err);
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_policy_matches() {
+        let policy = RelayPolicy {
+            force_relay_groups: vec!["finance".to_owned()],
+            force_relay_devices: vec!["123456789".to_owned()],
+        };
+        assert!(policy.matches("123456789", &[]));
+        assert!(policy.matches("987654321", &["finance".to_owned()]));
+        assert!(!policy.matches("987654321", &["dev".to_owned()]));
+        assert!(RelayPolicy::default().is_empty());
+    }
+}
@@ -1,8 +1,12 @@
+use crate::common::Keepalive;
+use crate::congestion_control::{socket_fd, CongestionControl, Controller, SocketFd};
+use crate::zero_copy;
 use async_speed_limit::Limiter;
 use async_trait::async_trait;
 use hbb_common::{
     allow_err, bail,
     bytes::{Bytes, BytesMut},
+    bytes_codec::BytesCodec,
     futures_util::{sink::SinkExt, stream::StreamExt},
     log,
     protobuf::Message as _,
@@ -12,11 +16,12 @@ use hbb_common::{
     timeout,
     tokio::{
         self,
-        io::{AsyncReadExt, AsyncWriteExt},
+        io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
         net::{TcpListener, TcpStream},
-        sync::{Mutex, RwLock},
+        sync::{mpsc, Mutex, RwLock},
         time::{interval, Duration},
     },
+    tokio_util::codec::Framed,
     ResultType,
 };
 use sodiumoxide::crypto::sign;
@@ -25,16 +30,36 @@ use std::{
     io::prelude::*,
     io::Error,
     net::SocketAddr,
-    sync::atomic::{AtomicUsize, Ordering},
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
 };
 
 type Usage = (usize, usize, usize, usize);
 
 lazy_static::lazy_static! {
-    static ref PEERS: Mutex<HashMap<String, Box<dyn StreamTrait>>> = Default::default();
+    static ref PEERS: Mutex<HashMap<String, Conn>> = Default::default();
     static ref USAGE: RwLock<HashMap<String, Usage>> = Default::default();
     static ref BLACKLIST: RwLock<HashSet<String>> = Default::default();
     static ref BLOCKLIST: RwLock<HashSet<String>> = Default::default();
+    static ref KILLED_SESSIONS: RwLock<HashMap<String, std::time::Instant>> = Default::default();
+    static ref CONGESTION_CONTROL: RwLock<Option<CongestionControl>> = Default::default();
+    static ref SESSION_CONGESTION_CONTROL: RwLock<HashMap<String, Option<CongestionControl>>> =
+        Default::default();
+    static ref CONGESTION_STATS: RwLock<HashMap<String, String>> = Default::default();
+    static ref KEEPALIVE: std::sync::RwLock<Option<Keepalive>> = Default::default();
+    static ref ACCEPTED: (
+        mpsc::UnboundedSender<(Box<dyn Duplex>, SocketAddr)>,
+        Mutex<mpsc::UnboundedReceiver<(Box<dyn Duplex>, SocketAddr)>>
+    ) = {
+        let (tx, rx) = mpsc::unbounded_channel();
+        (tx, Mutex::new(rx))
+    };
+    static ref ADMIN_ACCEPTED: (
+        mpsc::UnboundedSender<Box<dyn Duplex>>,
+        Mutex<mpsc::UnboundedReceiver<Box<dyn Duplex>>>
+    ) = {
+        let (tx, rx) = mpsc::unbounded_channel();
+        (tx, Mutex::new(rx))
+    };
 }
 
 static DOWNGRADE_THRESHOLD_100: AtomicUsize = AtomicUsize::new(66); // 0.66
@@ -42,6 +67,8 @@ static DOWNGRADE_START_CHECK: AtomicUsize = AtomicUsize::new(1_800_000); // in m
 static LIMIT_SPEED: AtomicUsize = AtomicUsize::new(32 * 1024 * 1024); // in bit/s
 static TOTAL_BANDWIDTH: AtomicUsize = AtomicUsize::new(1024 * 1024 * 1024); // in bit/s
 static SINGLE_BANDWIDTH: AtomicUsize = AtomicUsize::new(128 * 1024 * 1024); // in bit/s
+static ZERO_COPY: AtomicBool = AtomicBool::new(false);
+static IDLE_TIMEOUT: AtomicUsize = AtomicUsize::new(30); // in s
 const BLACKLIST_FILE: &str = "blacklist.txt";
 const BLOCKLIST_FILE: &str = "blocklist.txt";
 
@@ -83,9 +110,18 @@ pub async fn start(port: &str, key: &str) -> ResultType<()> {
     let port2 = port + 2;
     log::info!("Listening on websocket :{}", port2);
     let main_task = async move {
+        let mut accepted = ACCEPTED.1.lock().await;
+        let mut admin = ADMIN_ACCEPTED.1.lock().await;
         loop {
             log::info!("Start");
-            io_loop(listen_any(port).await?, listen_any(port2).await?, &key).await;
+            io_loop(
+                listen_any(port).await?,
+                listen_any(port2).await?,
+                &mut accepted,
+                &mut admin,
+                &key,
+            )
+            .await;
         }
     };
     let listen_signal = crate::common::listen_signal();
@@ -146,7 +182,79 @@ fn check_params() {
     log::info!(
         "SINGLE_BANDWIDTH: {}Mb/s",
         SINGLE_BANDWIDTH.load(Ordering::SeqCst) as f64 / 1024. / 1024.
-    )
+    );
+    if let Ok(v) = std::env::var("CONGESTION_CONTROL") {
+        match CongestionControl::parse(&v) {
+            Ok(v) => {
+                if let Ok(mut cc) = CONGESTION_CONTROL.try_write() {
+                    *cc = v;
+                }
+            }
+            Err(err) => log::error!("{}", err),
+        }
+    }
+    if let Ok(cc) = CONGESTION_CONTROL.try_read() {
+        log::info!("CONGESTION_CONTROL: {}", CongestionControl::name(*cc));
+    }
+    if std::env::var("ZERO_COPY")
+        .unwrap_or_default()
+        .to_uppercase()
+        == "Y"
+    {
+        set_zero_copy(true);
+    }
+    log::info!("ZERO_COPY: {}", ZERO_COPY.load(Ordering::SeqCst));
+    let tmp = std::env::var("IDLE_TIMEOUT")
+        .map(|x| x.parse::<usize>().unwrap_or(0))
+        .unwrap_or(0);
+    if tmp > 0 {
+        IDLE_TIMEOUT.store(tmp, Ordering::SeqCst);
+    }
+    log::info!("IDLE_TIMEOUT: {}s", IDLE_TIMEOUT.load(Ordering::SeqCst));
+    if let Ok(v) = std::env::var("KEEPALIVE") {
+        if let Err(err) = set_keepalive(&v) {
+            log::error!("{}", err);
+        }
+    }
+    log::info!("KEEPALIVE: {}", keepalive_name());
+}
+
+// 部分运营商的 NAT 映射空闲几分钟就会回收，长时间没有数据的会话需要比系统默认(2 小时)更短的 keepalive
+fn set_keepalive(v: &str) -> ResultType<()> {
+    let keepalive = if v == "off" {
+        None
+    } else {
+        Some(Keepalive::parse(v)?)
+    };
+    if let Ok(mut w) = KEEPALIVE.write() {
+        *w = keepalive;
+    }
+    Ok(())
+}
+
+fn keepalive_name() -> String {
+    match KEEPALIVE.read().ok().and_then(|x| *x) {
+        Some(k) => format!("{},{},{}", k.idle, k.interval, k.retries),
+        None => "off".to_owned(),
+    }
+}
+
+fn apply_keepalive(stream: &TcpStream) {
+    if let Some(keepalive) = KEEPALIVE.read().ok().and_then(|x| *x) {
+        if let Err(err) = crate::common::set_keepalive(stream, &keepalive) {
+            log::debug!("Failed to set keepalive: {}", err);
+        }
+    }
+}
+
+// 只有 Linux 支持，其他系统忽略
+fn set_zero_copy(v: bool) -> bool {
+    if v && !zero_copy::available() {
+        log::error!("Zero-copy forwarding is only supported on Linux");
+        return false;
+    }
+    ZERO_COPY.store(v, Ordering::SeqCst);
+    true
 }
 
 async fn check_cmd(cmd: &str, limiter: Limiter) -> String {
@@ -157,7 +265,7 @@ async fn check_cmd(cmd: &str, limiter: Limiter) -> String {
     match fds.next() {
         Some("h") => {
             res = format!(
-                "{}\n{}\n{}\n{}\n{}\n{}\n{}\n{}\n{}\n{}\n{}\n{}\n",
+                "{}\n{}\n{}\n{}\n{}\n{}\n{}\n{}\n{}\n{}\n{}\n{}\n{}\n{}\n{}\n{}\n{}\n{}\n{}\n",
                 "blacklist-add(ba) <ip>",
                 "blacklist-remove(br) <ip>",
                 "blacklist(b) <ip>",
@@ -169,7 +277,14 @@ async fn check_cmd(cmd: &str, limiter: Limiter) -> String {
                 "limit-speed(ls) [value(Mb/s)]",
                 "total-bandwidth(tb) [value(Mb/s)]",
                 "single-bandwidth(sb) [value(Mb/s)]",
-                "usage(u)"
+                "kill-session(ks) <uuid>",
+                "congestion-control(cc) [bbr|cubic|reno|vegas|off]",
+                "session-congestion-control(scc) [uuid] [bbr|cubic|reno|vegas|off]",
+                "zero-copy(zc) [on|off]",
+                "idle-timeout(it) [value(second)]",
+                "keepalive(ka) [idle[,interval[,retries]](second)|off]",
+                "usage(u)",
+                "sessions(s)"
             )
         }
         Some("blacklist-add" | "ba") => {
@@ -226,6 +341,91 @@ async fn check_cmd(cmd: &str, limiter: Limiter) -> String {
                 }
             }
         }
+        Some("kill-session" | "ks") => {
+            if let Some(uuid) = fds.next() {
+                let mut killed = KILLED_SESSIONS.write().await;
+                // the session is either dropped within 1s or never paired (30s wait)
+                killed.retain(|_, t| t.elapsed().as_secs() < 60);
+                for uuid in uuid.split('|') {
+                    killed.insert(uuid.to_owned(), std::time::Instant::now());
+                }
+            }
+        }
+        Some("congestion-control" | "cc") => {
+            if let Some(v) = fds.next() {
+                match CongestionControl::parse(v) {
+                    Ok(v) => *CONGESTION_CONTROL.write().await = v,
+                    Err(err) => res = format!("{err}\n"),
+                }
+            } else {
+                res = format!(
+                    "{}\n",
+                    CongestionControl::name(*CONGESTION_CONTROL.read().await)
+                );
+            }
+        }
+        Some("session-congestion-control" | "scc") => match (fds.next(), fds.next()) {
+            (Some(uuid), Some(v)) => match CongestionControl::parse(v) {
+                Ok(v) => {
+                    SESSION_CONGESTION_CONTROL
+                        .write()
+                        .await
+                        .insert(uuid.to_owned(), v);
+                }
+                Err(err) => res = format!("{err}\n"),
+            },
+            (Some(uuid), None) => {
+                if let Some(stats) = CONGESTION_STATS.read().await.get(uuid) {
+                    res = format!("{stats}\n");
+                }
+            }
+            _ => {
+                for (uuid, stats) in CONGESTION_STATS.read().await.iter() {
+                    let _ = writeln!(res, "{uuid}: {stats}");
+                }
+            }
+        },
+        Some("zero-copy" | "zc") => match fds.next() {
+            Some("on") => {
+                if !set_zero_copy(true) {
+                    res = "zero-copy forwarding is only supported on Linux\n".to_owned();
+                }
+            }
+            Some("off") => {
+                set_zero_copy(false);
+            }
+            Some(v) => res = format!("invalid value {v}, expected on or off\n"),
+            None => {
+                res = format!(
+                    "{}\n",
+                    if ZERO_COPY.load(Ordering::SeqCst) {
+                        "on"
+                    } else {
+                        "off"
+                    }
+                );
+            }
+        },
+        Some("idle-timeout" | "it") => {
+            if let Some(v) = fds.next() {
+                if let Ok(v) = v.parse::<usize>() {
+                    if v > 0 {
+                        IDLE_TIMEOUT.store(v, Ordering::SeqCst);
+                    }
+                }
+            } else {
+                res = format!("{}s\n", IDLE_TIMEOUT.load(Ordering::SeqCst));
+            }
+        }
+        Some("keepalive" | "ka") => {
+            if let Some(v) = fds.next() {
+                if let Err(err) = set_keepalive(v) {
+                    res = format!("{err}\n");
+                }
+            } else {
+                res = format!("{}\n", keepalive_name());
+            }
+        }
         Some("downgrade-threshold" | "dt") => {
             if let Some(v) = fds.next() {
                 if let Ok(v) = v.parse::<f64>() {
@@ -318,12 +518,38 @@ async fn check_cmd(cmd: &str, limiter: Limiter) -> String {
                 );
             }
         }
+        // 进行中的中继会话数，信令服务器据此判断排空是否完成
+        Some("sessions" | "s") => {
+            res = format!("{}\n", USAGE.read().await.len());
+        }
         _ => {}
     }
     res
 }
 
-async fn io_loop(listener: TcpListener, listener2: TcpListener, key: &str) {
+// 其他传输(如 QUIC)接受的连接，消息格式与 TCP 相同，交给中继主循环配对
+pub trait Duplex: AsyncRead + AsyncWrite + Unpin + Send + Sync + 'static {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send + Sync + 'static> Duplex for T {}
+
+#[allow(dead_code)]
+pub fn accept_stream(stream: impl Duplex, addr: SocketAddr) {
+    ACCEPTED.0.send((Box::new(stream), addr)).ok();
+}
+
+// 已认证的管理连接(如控制通道 TLS)，按本机回环地址的管理命令处理
+#[allow(dead_code)]
+pub fn accept_admin_stream(stream: impl Duplex) {
+    ADMIN_ACCEPTED.0.send(Box::new(stream)).ok();
+}
+
+async fn io_loop(
+    listener: TcpListener,
+    listener2: TcpListener,
+    accepted: &mut mpsc::UnboundedReceiver<(Box<dyn Duplex>, SocketAddr)>,
+    admin: &mut mpsc::UnboundedReceiver<Box<dyn Duplex>>,
+    key: &str,
+) {
     check_params();
     let limiter = <Limiter>::new(TOTAL_BANDWIDTH.load(Ordering::SeqCst) as _);
     loop {
@@ -332,6 +558,7 @@ async fn io_loop(listener: TcpListener, listener2: TcpListener, key: &str) {
                 match res {
                     Ok((stream, addr))  => {
                         stream.set_nodelay(true).ok();
+                        apply_keepalive(&stream);
                         handle_connection(stream, addr, &limiter, key, false).await;
                     }
                     Err(err) => {
@@ -344,6 +571,7 @@ async fn io_loop(listener: TcpListener, listener2: TcpListener, key: &str) {
                 match res {
                     Ok((stream, addr))  => {
                         stream.set_nodelay(true).ok();
+                        apply_keepalive(&stream);
                         handle_connection(stream, addr, &limiter, key, true).await;
                     }
                     Err(err) => {
@@ -352,6 +580,12 @@ async fn io_loop(listener: TcpListener, listener2: TcpListener, key: &str) {
                     }
                 }
             }
+            Some((stream, addr)) = accepted.recv() => {
+                handle_stream(stream, addr, &limiter, key).await;
+            }
+            Some(stream) = admin.recv() => {
+                tokio::spawn(serve_cmd(stream, limiter.clone()));
+            }
         }
     }
 }
@@ -365,17 +599,7 @@ async fn handle_connection(
 ) {
     let ip = hbb_common::try_into_v4(addr).ip();
     if !ws && ip.is_loopback() {
-        let limiter = limiter.clone();
-        tokio::spawn(async move {
-            let mut stream = stream;
-            let mut buffer = [0; 1024];
-            if let Ok(Ok(n)) = timeout(1000, stream.read(&mut buffer[..])).await {
-                if let Ok(data) = std::str::from_utf8(&buffer[..n]) {
-                    let res = check_cmd(data, limiter).await;
-                    stream.write(res.as_bytes()).await.ok();
-                }
-            }
-        });
+        tokio::spawn(serve_cmd(stream, limiter.clone()));
         return;
     }
     let ip = ip.to_string();
@@ -390,6 +614,30 @@ async fn handle_connection(
     });
 }
 
+async fn serve_cmd(mut stream: impl AsyncRead + AsyncWrite + Unpin + Send, limiter: Limiter) {
+    let mut buffer = [0; 1024];
+    if let Ok(Ok(n)) = timeout(1000, stream.read(&mut buffer[..])).await {
+        if let Ok(data) = std::str::from_utf8(&buffer[..n]) {
+            let res = check_cmd(data, limiter).await;
+            stream.write_all(res.as_bytes()).await.ok();
+            stream.shutdown().await.ok();
+        }
+    }
+}
+
+async fn handle_stream(stream: Box<dyn Duplex>, addr: SocketAddr, limiter: &Limiter, key: &str) {
+    let ip = hbb_common::try_into_v4(addr).ip().to_string();
+    if BLOCKLIST.read().await.get(&ip).is_some() {
+        log::info!("{} blocked", ip);
+        return;
+    }
+    let key = key.to_owned();
+    let limiter = limiter.clone();
+    tokio::spawn(async move {
+        make_pair_(Framed::new(stream, BytesCodec::new()), None, addr, &key, limiter).await;
+    });
+}
+
 async fn make_pair(
     stream: TcpStream,
     mut addr: SocketAddr,
@@ -397,6 +645,7 @@ async fn make_pair(
     limiter: Limiter,
     ws: bool,
 ) -> ResultType<()> {
+    let fd = socket_fd(&stream);
     if ws {
         use tokio_tungstenite::tungstenite::handshake::server::{Request, Response};
         let callback = |req: &Request, response: Response| {
@@ -415,89 +664,263 @@ async fn make_pair(
             Ok(response)
         };
         let ws_stream = tokio_tungstenite::accept_hdr_async(stream, callback).await?;
-        make_pair_(ws_stream, addr, key, limiter).await;
+        make_pair_(ws_stream, fd, addr, key, limiter).await;
+    } else if ZERO_COPY.load(Ordering::SeqCst) {
+        make_pair_tcp(stream, addr, key, limiter).await;
     } else {
-        make_pair_(FramedStream::from(stream, addr), addr, key, limiter).await;
+        make_pair_(FramedStream::from(stream, addr), fd, addr, key, limiter).await;
     }
     Ok(())
 }
 
-async fn make_pair_(stream: impl StreamTrait, addr: SocketAddr, key: &str, limiter: Limiter) {
+async fn make_pair_(
+    stream: impl StreamTrait,
+    fd: SocketFd,
+    addr: SocketAddr,
+    key: &str,
+    limiter: Limiter,
+) {
     let mut stream = stream;
     if let Ok(Some(Ok(bytes))) = timeout(30_000, stream.recv()).await {
-        if let Ok(msg_in) = RendezvousMessage::parse_from_bytes(&bytes) {
-            if let Some(rendezvous_message::Union::RequestRelay(rf)) = msg_in.union {
-                if !key.is_empty() && rf.licence_key != key {
-                    return;
-                }
-                if !rf.uuid.is_empty() {
-                    let mut peer = PEERS.lock().await.remove(&rf.uuid);
-                    if let Some(peer) = peer.as_mut() {
-                        log::info!("Relayrequest {} from {} got paired", rf.uuid, addr);
-                        let id = format!("{}:{}", addr.ip(), addr.port());
-                        USAGE.write().await.insert(id.clone(), Default::default());
-                        if !stream.is_ws() && !peer.is_ws() {
-                            peer.set_raw();
-                            stream.set_raw();
-                            log::info!("Both are raw");
-                        }
-                        if let Err(err) = relay(addr, &mut stream, peer, limiter, id.clone()).await
-                        {
-                            log::info!("Relay of {} closed: {}", addr, err);
-                        } else {
-                            log::info!("Relay of {} closed", addr);
-                        }
-                        USAGE.write().await.remove(&id);
-                    } else {
-                        log::info!("New relay request {} from {}", rf.uuid, addr);
-                        PEERS.lock().await.insert(rf.uuid.clone(), Box::new(stream));
-                        sleep(30.).await;
-                        PEERS.lock().await.remove(&rf.uuid);
-                    }
+        if let Some(uuid) = relay_request(&bytes, key) {
+            pair(Conn::Stream(Box::new(stream), fd), addr, &uuid, limiter).await;
+        }
+    }
+}
+
+// 零拷贝模式下 TCP 连接在配对前不包装成 FramedStream，避免对端数据被读进它的缓冲区
+async fn make_pair_tcp(stream: TcpStream, addr: SocketAddr, key: &str, limiter: Limiter) {
+    let mut stream = stream;
+    if let Ok(Ok(bytes)) = timeout(30_000, zero_copy::read_frame(&mut stream)).await {
+        if let Some(uuid) = relay_request(&bytes, key) {
+            pair(Conn::Tcp(stream, addr), addr, &uuid, limiter).await;
+        }
+    }
+}
+
+// 校验 RequestRelay，返回会话 uuid
+fn relay_request(bytes: &[u8], key: &str) -> Option<String> {
+    if let Ok(msg_in) = RendezvousMessage::parse_from_bytes(bytes) {
+        if let Some(rendezvous_message::Union::RequestRelay(rf)) = msg_in.union {
+            if (key.is_empty() || rf.licence_key == key) && !rf.uuid.is_empty() {
+                return Some(rf.uuid);
+            }
+        }
+    }
+    None
+}
+
+// 等待配对的连接，包装后的连接保留底层套接字描述符用于设置内核拥塞控制算法
+enum Conn {
+    Stream(Box<dyn StreamTrait>, SocketFd),
+    // 零拷贝模式下尚未包装的 TCP 连接
+    Tcp(TcpStream, SocketAddr),
+}
+
+impl Conn {
+    fn into_stream(self) -> (Box<dyn StreamTrait>, SocketFd) {
+        match self {
+            Conn::Stream(stream, fd) => (stream, fd),
+            Conn::Tcp(stream, addr) => {
+                let fd = socket_fd(&stream);
+                (Box::new(FramedStream::from(stream, addr)), fd)
+            }
+        }
+    }
+}
+
+async fn pair(conn: Conn, addr: SocketAddr, uuid: &str, limiter: Limiter) {
+    if KILLED_SESSIONS.read().await.contains_key(uuid) {
+        log::info!("Relayrequest {} from {} killed", uuid, addr);
+        return;
+    }
+    let peer = PEERS.lock().await.remove(uuid);
+    if let Some(peer) = peer {
+        log::info!("Relayrequest {} from {} got paired", uuid, addr);
+        let id = format!("{}:{}", addr.ip(), addr.port());
+        USAGE.write().await.insert(id.clone(), Default::default());
+        let res = match (conn, peer) {
+            (Conn::Tcp(stream, _), Conn::Tcp(peer, _)) => {
+                log::info!("Both are raw, zero-copy");
+                relay_zero_copy(addr, stream, peer, limiter, id.clone(), uuid).await
+            }
+            (conn, peer) => {
+                let (mut stream, stream_fd) = conn.into_stream();
+                let (mut peer, peer_fd) = peer.into_stream();
+                if !stream.is_ws() && !peer.is_ws() {
+                    peer.set_raw();
+                    stream.set_raw();
+                    log::info!("Both are raw");
                 }
+                let fds = (stream_fd, peer_fd);
+                relay(addr, &mut stream, &mut peer, fds, limiter, id.clone(), uuid).await
             }
+        };
+        if let Err(err) = res {
+            log::info!("Relay of {} closed: {}", addr, err);
+        } else {
+            log::info!("Relay of {} closed", addr);
+        }
+        USAGE.write().await.remove(&id);
+        KILLED_SESSIONS.write().await.remove(uuid);
+        SESSION_CONGESTION_CONTROL.write().await.remove(uuid);
+        CONGESTION_STATS.write().await.remove(uuid);
+    } else {
+        log::info!("New relay request {} from {}", uuid, addr);
+        PEERS.lock().await.insert(uuid.to_owned(), conn);
+        sleep(30.).await;
+        PEERS.lock().await.remove(uuid);
+    }
+}
+
+// 两种转发方式共用的限速、用量统计、降速和会话控制
+struct RelayMonitor {
+    ip: String,
+    id: String,
+    uuid: String,
+    tm: std::time::Instant,
+    elapsed: usize,
+    total: usize,
+    total_s: usize,
+    highest_s: usize,
+    downgrade: bool,
+    blacked: bool,
+    limiter: Limiter,
+    blacklist_limiter: Limiter,
+    total_limiter: Limiter,
+    downgrade_threshold: usize, // in bit/ms
+    last_recv_time: std::time::Instant,
+}
+
+impl RelayMonitor {
+    fn new(addr: SocketAddr, total_limiter: Limiter, id: String, uuid: &str) -> Self {
+        let sb = SINGLE_BANDWIDTH.load(Ordering::SeqCst) as f64;
+        Self {
+            ip: addr.ip().to_string(),
+            id,
+            uuid: uuid.to_owned(),
+            tm: std::time::Instant::now(),
+            elapsed: 0,
+            total: 0,
+            total_s: 0,
+            highest_s: 0,
+            downgrade: false,
+            blacked: false,
+            limiter: <Limiter>::new(sb),
+            blacklist_limiter: <Limiter>::new(LIMIT_SPEED.load(Ordering::SeqCst) as _),
+            total_limiter,
+            downgrade_threshold: (sb * DOWNGRADE_THRESHOLD_100.load(Ordering::SeqCst) as f64
+                / 100.
+                / 1000.) as usize,
+            last_recv_time: std::time::Instant::now(),
+        }
+    }
+
+    // 收到 n 字节，超出限速时等待
+    async fn consume(&mut self, n: usize) {
+        self.last_recv_time = std::time::Instant::now();
+        let nb = n * 8;
+        if self.blacked || self.downgrade {
+            self.blacklist_limiter.consume(nb).await;
+        } else {
+            self.limiter.consume(nb).await;
+        }
+        self.total_limiter.consume(nb).await;
+        self.total += nb;
+        self.total_s += nb;
+    }
+
+    fn timed_out(&self) -> bool {
+        self.last_recv_time.elapsed().as_secs() > IDLE_TIMEOUT.load(Ordering::SeqCst) as u64
+    }
+
+    // 每秒更新一次统计和会话设置，返回 false 时结束会话
+    async fn check(&mut self, to_stream: &mut Controller, to_peer: &mut Controller) -> bool {
+        let n = self.tm.elapsed().as_millis() as usize;
+        if n < 1_000 {
+            return true;
+        }
+        if BLOCKLIST.read().await.get(&self.ip).is_some() {
+            log::info!("{} blocked", self.ip);
+            return false;
+        }
+        if KILLED_SESSIONS.read().await.contains_key(&self.uuid) {
+            log::info!("Relay session {} killed", self.uuid);
+            return false;
+        }
+        self.blacked = BLACKLIST.read().await.get(&self.ip).is_some();
+        let algorithm = session_congestion_control(&self.uuid).await;
+        if algorithm != to_stream.algorithm() {
+            log::info!(
+                "Relay session {} congestion control: {}",
+                self.uuid,
+                CongestionControl::name(algorithm)
+            );
+        }
+        to_stream.set_algorithm(algorithm);
+        to_peer.set_algorithm(algorithm);
+        CONGESTION_STATS.write().await.insert(
+            self.uuid.clone(),
+            format!("{} / {}", to_stream.summary(), to_peer.summary()),
+        );
+        self.tm = std::time::Instant::now();
+        let speed = self.total_s / n;
+        if speed > self.highest_s {
+            self.highest_s = speed;
         }
+        self.elapsed += n;
+        USAGE.write().await.insert(
+            self.id.clone(),
+            (
+                self.elapsed as _,
+                self.total as _,
+                self.highest_s as _,
+                speed as _,
+            ),
+        );
+        self.total_s = 0;
+        if self.elapsed > DOWNGRADE_START_CHECK.load(Ordering::SeqCst)
+            && !self.downgrade
+            && self.total > self.elapsed * self.downgrade_threshold
+        {
+            self.downgrade = true;
+            log::info!(
+                "Downgrade {}, exceed downgrade threshold {}bit/ms in {}ms",
+                self.id,
+                self.downgrade_threshold,
+                self.elapsed
+            );
+        }
+        true
     }
 }
 
 async fn relay(
     addr: SocketAddr,
-    stream: &mut impl StreamTrait,
+    stream: &mut Box<dyn StreamTrait>,
     peer: &mut Box<dyn StreamTrait>,
+    (stream_fd, peer_fd): (SocketFd, SocketFd),
     total_limiter: Limiter,
     id: String,
+    uuid: &str,
 ) -> ResultType<()> {
-    let ip = addr.ip().to_string();
-    let mut tm = std::time::Instant::now();
-    let mut elapsed = 0;
-    let mut total = 0;
-    let mut total_s = 0;
-    let mut highest_s = 0;
-    let mut downgrade: bool = false;
-    let mut blacked: bool = false;
-    let sb = SINGLE_BANDWIDTH.load(Ordering::SeqCst) as f64;
-    let limiter = <Limiter>::new(sb);
-    let blacklist_limiter = <Limiter>::new(LIMIT_SPEED.load(Ordering::SeqCst) as _);
-    let downgrade_threshold =
-        (sb * DOWNGRADE_THRESHOLD_100.load(Ordering::SeqCst) as f64 / 100. / 1000.) as usize; // in bit/ms
+    let mut monitor = RelayMonitor::new(addr, total_limiter, id, uuid);
     let mut timer = interval(Duration::from_secs(3));
-    let mut last_recv_time = std::time::Instant::now();
+    // 两个方向分别估计带宽和控制发送节奏
+    let algorithm = session_congestion_control(uuid).await;
+    let mut to_stream = Controller::new(algorithm, stream_fd);
+    let mut to_peer = Controller::new(algorithm, peer_fd);
     loop {
         tokio::select! {
             res = peer.recv() => {
                 if let Some(Ok(bytes)) = res {
-                    last_recv_time = std::time::Instant::now();
-                    let nb = bytes.len() * 8;
-                    if blacked || downgrade {
-                        blacklist_limiter.consume(nb).await;
-                    } else {
-                        limiter.consume(nb).await;
-                    }
-                    total_limiter.consume(nb).await;
-                    total += nb;
-                    total_s += nb;
+                    monitor.consume(bytes.len()).await;
                     if !bytes.is_empty() {
+                        let n = bytes.len();
+                        let paced = to_stream.pace(n).await;
+                        let tm_send = std::time::Instant::now();
                         stream.send_raw(bytes.into()).await?;
+                        to_stream.on_sent(n, paced, tm_send.elapsed());
                     }
                 } else {
                     break;
@@ -505,65 +928,92 @@ async fn relay(
             },
             res = stream.recv() => {
                 if let Some(Ok(bytes)) = res {
-                    last_recv_time = std::time::Instant::now();
-                    let nb = bytes.len() * 8;
-                    if blacked || downgrade {
-                        blacklist_limiter.consume(nb).await;
-                    } else {
-                        limiter.consume(nb).await;
-                    }
-                    total_limiter.consume(nb).await;
-                    total += nb;
-                    total_s += nb;
+                    monitor.consume(bytes.len()).await;
                     if !bytes.is_empty() {
+                        let n = bytes.len();
+                        let paced = to_peer.pace(n).await;
+                        let tm_send = std::time::Instant::now();
                         peer.send_raw(bytes.into()).await?;
+                        to_peer.on_sent(n, paced, tm_send.elapsed());
                     }
                 } else {
                     break;
                 }
             },
             _ = timer.tick() => {
-                if last_recv_time.elapsed().as_secs() > 30 {
+                if monitor.timed_out() {
                     bail!("Timeout");
                 }
             }
         }
+        if !monitor.check(&mut to_stream, &mut to_peer).await {
+            break;
+        }
+    }
+    Ok(())
+}
 
-        let n = tm.elapsed().as_millis() as usize;
-        if n >= 1_000 {
-            if BLOCKLIST.read().await.get(&ip).is_some() {
-                log::info!("{} blocked", ip);
-                break;
-            }
-            blacked = BLACKLIST.read().await.get(&ip).is_some();
-            tm = std::time::Instant::now();
-            let speed = total_s / n;
-            if speed > highest_s {
-                highest_s = speed;
-            }
-            elapsed += n;
-            USAGE.write().await.insert(
-                id.clone(),
-                (elapsed as _, total as _, highest_s as _, speed as _),
-            );
-            total_s = 0;
-            if elapsed > DOWNGRADE_START_CHECK.load(Ordering::SeqCst)
-                && !downgrade
-                && total > elapsed * downgrade_threshold
-            {
-                downgrade = true;
-                log::info!(
-                    "Downgrade {}, exceed downgrade threshold {}bit/ms in {}ms",
-                    id,
-                    downgrade_threshold,
-                    elapsed
-                );
+// 两端都是 TCP 时由内核经管道直接在两个套接字之间搬运数据，限速和统计与 relay 相同
+async fn relay_zero_copy(
+    addr: SocketAddr,
+    stream: TcpStream,
+    peer: TcpStream,
+    total_limiter: Limiter,
+    id: String,
+    uuid: &str,
+) -> ResultType<()> {
+    let mut monitor = RelayMonitor::new(addr, total_limiter, id, uuid);
+    let mut timer = interval(Duration::from_secs(3));
+    let algorithm = session_congestion_control(uuid).await;
+    let mut to_stream = Controller::new(algorithm, socket_fd(&stream));
+    let mut to_peer = Controller::new(algorithm, socket_fd(&peer));
+    let mut to_stream_pipe = zero_copy::Pipe::new()?;
+    let mut to_peer_pipe = zero_copy::Pipe::new()?;
+    loop {
+        tokio::select! {
+            res = to_stream_pipe.fill(&peer) => {
+                let n = res?;
+                if n == 0 {
+                    break;
+                }
+                monitor.consume(n).await;
+                let paced = to_stream.pace(n).await;
+                let tm_send = std::time::Instant::now();
+                to_stream_pipe.drain(&stream).await?;
+                to_stream.on_sent(n, paced, tm_send.elapsed());
+            },
+            res = to_peer_pipe.fill(&stream) => {
+                let n = res?;
+                if n == 0 {
+                    break;
+                }
+                monitor.consume(n).await;
+                let paced = to_peer.pace(n).await;
+                let tm_send = std::time::Instant::now();
+                to_peer_pipe.drain(&peer).await?;
+                to_peer.on_sent(n, paced, tm_send.elapsed());
+            },
+            _ = timer.tick() => {
+                if monitor.timed_out() {
+                    bail!("Timeout");
+                }
             }
         }
+        if !monitor.check(&mut to_stream, &mut to_peer).await {
+            break;
+        }
     }
     Ok(())
 }
 
+// 会话单独指定的拥塞控制优先，否则使用全局设置
+async fn session_congestion_control(uuid: &str) -> Option<CongestionControl> {
+    match SESSION_CONGESTION_CONTROL.read().await.get(uuid) {
+        Some(v) => *v,
+        None => *CONGESTION_CONTROL.read().await,
+    }
+}
+
 fn get_server_sk(key: &str) -> String {
     let mut key = key.to_owned();
     if let Ok(sk) = base64::decode(&key) {
@@ -612,6 +1062,25 @@ impl StreamTrait for FramedStream {
     }
 }
 
+#[async_trait]
+impl StreamTrait for Framed<Box<dyn Duplex>, BytesCodec> {
+    async fn recv(&mut self) -> Option<Result<BytesMut, Error>> {
+        self.next().await
+    }
+
+    async fn send_raw(&mut self, bytes: Bytes) -> ResultType<()> {
+        Ok(self.send(bytes).await?)
+    }
+
+    fn is_ws(&self) -> bool {
+        false
+    }
+
+    fn set_raw(&mut self) {
+        self.codec_mut().set_raw();
+    }
+}
+
 #[async_trait]
 impl StreamTrait for tokio_tungstenite::WebSocketStream<TcpStream> {
     async fn recv(&mut self) -> Option<Result<BytesMut, Error>> {
@@ -0,0 +1,433 @@
+// 中继会话登记模块 - 记录经信令服务器协商的中继会话(uuid)与设备、中继服务器的对应关系；
+// 按中继服务器统计会话数，并支持排空(drain): 排空中的中继不再分配给新会话，已有会话不受影响，
+// 会话数降为 0 后即可停机维护
+use crate::congestion_control::CongestionControl;
+use crate::control_tls;
+use crate::enterprise_database::EnterpriseDatabase;
+use hbb_common::{
+    bail,
+    config::RELAY_PORT,
+    log,
+    tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpStream,
+        sync::RwLock,
+    },
+    ResultType,
+};
+use schemars::JsonSchema;
+use serde_derive::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeSet, HashMap, HashSet},
+    time::{Duration, Instant, SystemTime},
+};
+
+// 中继会话最长保留时间，超时视为已结束
+const SESSION_TTL: Duration = Duration::from_secs(24 * 3600);
+const RELAY_CMD_TIMEOUT: u64 = 3_000;
+
+pub const RELAY_DRAIN_KEY: &str = "relay_drain";
+
+lazy_static::lazy_static! {
+    static ref RELAY_SESSIONS: RwLock<HashMap<String, RelaySession>> = Default::default();
+    // 分配中继时在同步代码中读取，使用标准库锁
+    static ref RELAY_SERVERS: std::sync::RwLock<Vec<String>> = Default::default();
+    static ref DRAINING: std::sync::RwLock<HashSet<String>> = Default::default();
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct RelayStatus {
+    pub server: String,
+    // 是否在 --relay-servers 中配置，未配置的为客户端自带的中继
+    pub configured: bool,
+    pub draining: bool,
+    // 中继服务器报告的进行中会话数，无法连接时为空
+    pub active_sessions: Option<usize>,
+    // 信令服务器登记的 24 小时内的会话数
+    pub recorded_sessions: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct RelaySession {
+    pub uuid: String,
+    pub relay_server: String,
+    pub device_ids: Vec<String>,
+    pub started_at: SystemTime,
+    #[serde(skip)]
+    last_seen: Option<Instant>,
+}
+
+// RequestRelay / RelayResponse 经过信令服务器时登记
+pub async fn record(uuid: &str, relay_server: &str, device_id: &str) {
+    if uuid.is_empty() {
+        return;
+    }
+    let mut sessions = RELAY_SESSIONS.write().await;
+    sessions.retain(|_, s| s.last_seen.map(|t| t.elapsed() < SESSION_TTL).unwrap_or(false));
+    let session = sessions.entry(uuid.to_owned()).or_insert_with(|| RelaySession {
+        uuid: uuid.to_owned(),
+        relay_server: relay_server.to_owned(),
+        device_ids: Vec::new(),
+        started_at: SystemTime::now(),
+        last_seen: None,
+    });
+    if session.relay_server.is_empty() {
+        session.relay_server = relay_server.to_owned();
+    }
+    if !device_id.is_empty() && !session.device_ids.iter().any(|x| x == device_id) {
+        session.device_ids.push(device_id.to_owned());
+    }
+    session.last_seen = Some(Instant::now());
+}
+
+pub async fn sessions_of(device_id: &str) -> Vec<RelaySession> {
+    RELAY_SESSIONS
+        .read()
+        .await
+        .values()
+        .filter(|s| s.device_ids.iter().any(|x| x == device_id))
+        .cloned()
+        .collect()
+}
+
+// 会话所在的中继服务器，直连或已过期的会话返回 None
+pub async fn relay_of(uuid: &str) -> Option<String> {
+    RELAY_SESSIONS
+        .read()
+        .await
+        .get(uuid)
+        .map(|s| s.relay_server.clone())
+        .filter(|x| !x.is_empty())
+}
+
+pub async fn list() -> Vec<RelaySession> {
+    RELAY_SESSIONS.read().await.values().cloned().collect()
+}
+
+// 启动参数中的中继服务器列表，由信令服务器解析后设置
+pub fn set_servers(servers: &[String]) {
+    if let Ok(mut x) = RELAY_SERVERS.write() {
+        *x = servers.to_vec();
+    }
+}
+
+pub fn servers() -> Vec<String> {
+    RELAY_SERVERS.read().map(|x| x.clone()).unwrap_or_default()
+}
+
+pub fn is_draining(server: &str) -> bool {
+    DRAINING.read().map(|x| x.contains(server)).unwrap_or(false)
+}
+
+// 从候选中继中去掉排空中的，全部排空时(如排空后修改了中继列表)仍使用全部候选
+pub fn available<'a>(servers: &'a [String]) -> Vec<&'a String> {
+    let draining = match DRAINING.read() {
+        Ok(x) => x,
+        Err(_) => return servers.iter().collect(),
+    };
+    let res: Vec<&String> = servers.iter().filter(|x| !draining.contains(*x)).collect();
+    if res.is_empty() {
+        servers.iter().collect()
+    } else {
+        res
+    }
+}
+
+pub async fn reload_drain(db: &EnterpriseDatabase) -> ResultType<()> {
+    let servers: HashSet<String> = match db.get_setting(RELAY_DRAIN_KEY).await? {
+        Some(v) => serde_json::from_str(&v)?,
+        None => HashSet::new(),
+    };
+    if let Ok(mut x) = DRAINING.write() {
+        *x = servers;
+    }
+    Ok(())
+}
+
+// 开始或取消排空，排空状态保存在系统设置中，重启后保持
+pub async fn set_draining(db: &EnterpriseDatabase, server: &str, draining: bool, updated_by: &str) -> ResultType<()> {
+    let servers = RELAY_SERVERS.read().map(|x| x.clone()).unwrap_or_default();
+    if !servers.iter().any(|x| x == server) {
+        bail!("{} is not a configured relay server", server);
+    }
+    let mut set = DRAINING.read().map(|x| x.clone()).unwrap_or_default();
+    if draining {
+        set.insert(server.to_owned());
+        if servers.iter().all(|x| set.contains(x)) {
+            bail!("cannot drain the last available relay server");
+        }
+    } else {
+        set.remove(server);
+    }
+    let sorted: BTreeSet<&String> = set.iter().collect();
+    db.set_setting(RELAY_DRAIN_KEY, &serde_json::to_string(&sorted)?, Some(updated_by))
+        .await?;
+    if let Ok(mut x) = DRAINING.write() {
+        *x = set;
+    }
+    log::info!("Relay {} draining: {}", server, draining);
+    Ok(())
+}
+
+// 中继服务器当前进行中的会话数
+pub async fn active_sessions(relay_server: &str) -> ResultType<usize> {
+    let res = query_relay(relay_server, "sessions").await?;
+    Ok(res.trim().parse()?)
+}
+
+// 各中继服务器的会话数和排空状态
+pub async fn status() -> Vec<RelayStatus> {
+    let configured = RELAY_SERVERS.read().map(|x| x.clone()).unwrap_or_default();
+    let mut recorded: HashMap<String, usize> = HashMap::new();
+    for session in list().await {
+        if !session.relay_server.is_empty() {
+            *recorded.entry(session.relay_server).or_default() += 1;
+        }
+    }
+    let mut servers = configured.clone();
+    let mut others: Vec<&String> = recorded.keys().filter(|x| !configured.contains(x)).collect();
+    others.sort();
+    servers.extend(others.into_iter().cloned());
+    let mut res = Vec::with_capacity(servers.len());
+    for server in servers {
+        let active_sessions = match active_sessions(&server).await {
+            Ok(n) => Some(n),
+            Err(err) => {
+                log::debug!("Failed to query sessions of relay {}: {}", server, err);
+                None
+            }
+        };
+        res.push(RelayStatus {
+            configured: configured.contains(&server),
+            draining: is_draining(&server),
+            active_sessions,
+            recorded_sessions: recorded.get(&server).copied().unwrap_or_default(),
+            server,
+        });
+    }
+    res
+}
+
+// 通知中继服务器断开设备的所有中继会话，返回断开的会话数
+pub async fn kill_device_sessions(device_id: &str) -> usize {
+    kill_device_sessions_since(device_id, SystemTime::UNIX_EPOCH).await
+}
+
+// 只断开 since 之后建立的会话
+pub async fn kill_device_sessions_since(device_id: &str, since: SystemTime) -> usize {
+    let sessions: Vec<_> = sessions_of(device_id)
+        .await
+        .into_iter()
+        .filter(|s| s.started_at >= since)
+        .collect();
+    let mut killed = 0;
+    for session in sessions.iter() {
+        match send_relay_cmd(&session.relay_server, &format!("kill-session {}", session.uuid)).await {
+            Ok(_) => {
+                killed += 1;
+                RELAY_SESSIONS.write().await.remove(&session.uuid);
+            }
+            Err(err) => log::error!(
+                "Failed to kill relay session {} on {}: {}",
+                session.uuid,
+                session.relay_server,
+                err
+            ),
+        }
+    }
+    killed
+}
+
+// 指定中继会话的拥塞控制算法，中继服务器在下一秒应用；直连或已过期的会话返回错误
+pub async fn set_congestion_control(uuid: &str, algorithm: Option<CongestionControl>) -> ResultType<()> {
+    let relay_server = match relay_of(uuid).await {
+        Some(relay_server) => relay_server,
+        None => bail!("relay session {} not found", uuid),
+    };
+    send_relay_cmd(
+        &relay_server,
+        &format!(
+            "session-congestion-control {} {}",
+            uuid,
+            CongestionControl::name(algorithm)
+        ),
+    )
+    .await
+}
+
+// 中继服务器只接受来自本机回环地址的明文管理命令(启用控制通道 TLS 时见 control_tls)；
+// 中继与信令不在同一主机时，通过 RELAY_ADMIN_ADDR 指定可转发到中继本机的地址
+fn relay_admin_addr(relay_server: &str) -> String {
+    if let Ok(addr) = std::env::var("RELAY_ADMIN_ADDR") {
+        if !addr.is_empty() {
+            return addr;
+        }
+    }
+    if relay_server.contains(':') {
+        relay_server.to_owned()
+    } else {
+        format!("{}:{}", relay_server, RELAY_PORT)
+    }
+}
+
+// 启用控制通道 TLS 时连接中继的 TLS 管理端口，否则按 RELAY_ADMIN_ADDR 走明文 TCP
+async fn connect_relay(relay_server: &str) -> ResultType<(Box<dyn control_tls::Stream>, String)> {
+    if control_tls::enabled().await {
+        let stream = control_tls::connect(relay_server).await?;
+        return Ok((stream, format!("{} (tls)", relay_server)));
+    }
+    let addr = relay_admin_addr(relay_server);
+    let stream = hbb_common::timeout(RELAY_CMD_TIMEOUT, TcpStream::connect(&addr)).await??;
+    Ok((Box::new(stream), addr))
+}
+
+async fn send_relay_cmd(relay_server: &str, cmd: &str) -> ResultType<()> {
+    let (mut stream, addr) = connect_relay(relay_server).await?;
+    stream.write_all(cmd.as_bytes()).await?;
+    stream.flush().await?;
+    log::info!("Sent relay command to {}: {}", addr, cmd);
+    Ok(())
+}
+
+// 发送查询命令并读取中继服务器的回复
+async fn query_relay(relay_server: &str, cmd: &str) -> ResultType<String> {
+    let (mut stream, _) = connect_relay(relay_server).await?;
+    stream.write_all(cmd.as_bytes()).await?;
+    stream.flush().await?;
+    let mut res = String::new();
+    hbb_common::timeout(RELAY_CMD_TIMEOUT, stream.read_to_string(&mut res)).await??;
+    Ok(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::UserRole;
+    use crate::enterprise_database::AuditLogFilter;
+    use crate::web_api::fixtures::{login_token, request, state, UserBuilder};
+    use axum::http::StatusCode;
+    use hbb_common::tokio;
+
+    // 排空状态和中继列表是全局的，模块函数和接口放在同一个测试中按顺序验证
+    #[tokio::test]
+    async fn test_e2e_relay_drain() {
+        let state = state().await;
+        let admin = UserBuilder::new(UserRole::Admin).create(&state).await;
+        let auditor = UserBuilder::new(UserRole::Auditor).create(&state).await;
+        let user = UserBuilder::new(UserRole::User).create(&state).await;
+        let admin_token = login_token(&state, &admin).await;
+        let auditor_token = login_token(&state, &auditor).await;
+        let user_token = login_token(&state, &user).await;
+
+        // 模拟中继服务器，对 sessions 命令回复进行中的会话数
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let relay_a = listener.local_addr().unwrap().to_string();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let mut buf = [0u8; 64];
+                let n = stream.read(&mut buf).await.unwrap_or_default();
+                if &buf[..n] == b"sessions" {
+                    stream.write_all(b"2\n").await.ok();
+                }
+            }
+        });
+        // 无法连接的中继和未配置的中继
+        let relay_b = "127.0.0.1:1".to_owned();
+        let relay_c = "127.0.0.1:2".to_owned();
+        let servers = vec![relay_a.clone(), relay_b.clone()];
+        set_servers(&servers);
+        record("relay-drain-s1", &relay_a, "relay-drain-dev1").await;
+        record("relay-drain-s2", &relay_a, "relay-drain-dev2").await;
+        record("relay-drain-s3", &relay_c, "relay-drain-dev3").await;
+        assert_eq!(available(&servers), vec![&relay_a, &relay_b]);
+
+        let relays = |token: String| {
+            let state = state.clone();
+            async move {
+                let (status, body) = request(&state, "GET", "/api/relays", Some(&token), None).await;
+                assert_eq!(status, StatusCode::OK);
+                body["data"].as_array().unwrap().clone()
+            }
+        };
+        let find = |relays: &[serde_json::Value], server: &str| {
+            relays.iter().find(|x| x["server"] == server).cloned().unwrap()
+        };
+        let list = relays(auditor_token.clone()).await;
+        let a = find(&list, &relay_a);
+        assert_eq!((a["configured"].clone(), a["draining"].clone()), (true.into(), false.into()));
+        assert_eq!((a["active_sessions"].clone(), a["recorded_sessions"].clone()), (2.into(), 2.into()));
+        let b = find(&list, &relay_b);
+        assert!(b["active_sessions"].is_null());
+        assert_eq!(b["recorded_sessions"], 0);
+        // 客户端自带的中继也会列出，排在配置的中继之后
+        let c = find(&list, &relay_c);
+        assert_eq!((c["configured"].clone(), c["recorded_sessions"].clone()), (false.into(), 1.into()));
+        assert_eq!(list[0]["server"], relay_a.as_str());
+        assert_eq!(list[1]["server"], relay_b.as_str());
+
+        let drain = |token: &str, server: &str, draining: bool| {
+            let (state, token) = (state.clone(), token.to_owned());
+            let body = serde_json::json!({ "server": server, "draining": draining });
+            async move { request(&state, "PUT", "/api/relays/drain", Some(&token), Some(body)).await }
+        };
+        let (status, _) = request(&state, "GET", "/api/relays", Some(&user_token), None).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        assert_eq!(drain(&auditor_token, &relay_a, true).await.0, StatusCode::FORBIDDEN);
+        assert!(!is_draining(&relay_a));
+
+        // 排空后不再分配，已有会话不受影响
+        let (_, body) = drain(&admin_token, &relay_a, true).await;
+        assert_eq!(body["success"], true);
+        assert!(is_draining(&relay_a));
+        assert_eq!(available(&servers), vec![&relay_b]);
+        assert_eq!(sessions_of("relay-drain-dev1").await.len(), 1);
+        let a = find(&relays(admin_token.clone()).await, &relay_a);
+        assert_eq!((a["draining"].clone(), a["active_sessions"].clone()), (true.into(), 2.into()));
+        let stored = state.db.get_setting(RELAY_DRAIN_KEY).await.unwrap();
+        assert_eq!(stored, Some(serde_json::json!([relay_a]).to_string()));
+        let filter = AuditLogFilter {
+            action: Some("drain_relay".to_owned()),
+            ..Default::default()
+        };
+        let logs = state.db.get_audit_logs(&filter, 10, 0).await.unwrap();
+        assert_eq!((logs.len(), logs[0].user_id.as_str()), (1, admin.id.as_str()));
+        assert_eq!(logs[0].details.as_deref(), Some(relay_a.as_str()));
+
+        // 不能排空最后一个可用中继，也不能排空未配置的中继
+        let (_, body) = drain(&admin_token, &relay_b, true).await;
+        assert_eq!(body["success"], false);
+        assert!(!is_draining(&relay_b));
+        let (_, body) = drain(&admin_token, &relay_c, true).await;
+        assert_eq!(body["success"], false);
+        assert!(!is_draining(&relay_c));
+        let (status, _) = request(
+            &state,
+            "PUT",
+            "/api/relays/drain",
+            Some(&admin_token),
+            Some(serde_json::json!({ "server": relay_b })),
+        )
+        .await;
+        assert!(status.is_client_error());
+
+        // 修改中继列表后全部候选都在排空时仍使用全部候选
+        set_servers(&servers[..1]);
+        assert_eq!(available(&servers[..1]), vec![&relay_a]);
+        set_servers(&servers);
+
+        // 排空状态重启后从系统设置恢复
+        let empty = EnterpriseDatabase::memory().await.unwrap();
+        reload_drain(&empty).await.unwrap();
+        assert!(!is_draining(&relay_a));
+        reload_drain(&state.db).await.unwrap();
+        assert!(is_draining(&relay_a));
+
+        let (_, body) = drain(&admin_token, &relay_a, false).await;
+        assert_eq!(body["success"], true);
+        assert!(!is_draining(&relay_a));
+        assert_eq!(available(&servers), vec![&relay_a, &relay_b]);
+        let stored = state.db.get_setting(RELAY_DRAIN_KEY).await.unwrap();
+        assert_eq!(stored.as_deref(), Some("[]"));
+        set_servers(&[]);
+    }
+}
@@ -0,0 +1,175 @@
+// 远程任务模块 - 管理员为设备组/设备排队命令或脚本，受管客户端通过 /api/heartbeat 取回任务，
+// 执行后上报退出码和输出；每台设备对每个任务只执行一次，结果保存在数据库并写入审计日志
+use crate::enterprise_database::EnterpriseDatabase;
+use crate::feature_flags::{self, Feature};
+use hbb_common::{bail, log, ResultType};
+use schemars::JsonSchema;
+use serde_derive::{Deserialize, Serialize};
+
+const MAX_COMMAND_LEN: usize = 64 * 1024;
+const MAX_TIMEOUT_SECS: u64 = 24 * 3600;
+// 上报的输出超过该长度时截断
+pub const MAX_OUTPUT_LEN: usize = 64 * 1024;
+// 执行超时后再等待客户端上报的时间，之后记为超时
+const RESULT_GRACE_SECS: u64 = 300;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum JobShell {
+    Cmd,
+    PowerShell,
+    Sh,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct Job {
+    pub id: String,
+    pub name: String,
+    pub command: String,
+    pub shell: JobShell,
+    pub group_ids: Vec<String>,
+    pub device_ids: Vec<String>,
+    pub timeout_secs: u64,
+    pub created_by: String,
+    pub created_at: u64,
+    // 到期后不再下发给尚未取到任务的设备
+    pub expires_at: u64,
+    pub cancelled: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub enum JobRunStatus {
+    Dispatched,
+    Succeeded,
+    Failed,
+    TimedOut,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct JobRun {
+    pub job_id: String,
+    pub device_id: String,
+    pub status: JobRunStatus,
+    pub exit_code: Option<i32>,
+    pub output: String,
+    pub dispatched_at: u64,
+    pub finished_at: Option<u64>,
+}
+
+// 通过心跳下发给客户端的任务
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct ClientJob {
+    pub id: String,
+    pub shell: JobShell,
+    pub command: String,
+    pub timeout_secs: u64,
+}
+
+impl Job {
+    pub fn validate(&self) -> ResultType<()> {
+        if self.name.trim().is_empty() {
+            bail!("job name is empty");
+        }
+        if self.command.trim().is_empty() || self.command.len() > MAX_COMMAND_LEN {
+            bail!("command must be 1-{} bytes", MAX_COMMAND_LEN);
+        }
+        if self.timeout_secs == 0 || self.timeout_secs > MAX_TIMEOUT_SECS {
+            bail!("timeout_secs must be between 1 and {}", MAX_TIMEOUT_SECS);
+        }
+        if self.group_ids.is_empty() && self.device_ids.is_empty() {
+            bail!("job has no target groups or devices");
+        }
+        if self.expires_at <= self.created_at {
+            bail!("job expires before it is created");
+        }
+        Ok(())
+    }
+
+    pub fn targets(&self, device_id: &str, group_ids: &[String]) -> bool {
+        self.device_ids.iter().any(|x| x == device_id) || self.group_ids.iter().any(|g| group_ids.contains(g))
+    }
+}
+
+// 按退出码确定执行结果
+pub fn run_status(exit_code: Option<i32>, timed_out: bool) -> JobRunStatus {
+    match exit_code {
+        _ if timed_out => JobRunStatus::TimedOut,
+        Some(0) => JobRunStatus::Succeeded,
+        _ => JobRunStatus::Failed,
+    }
+}
+
+// 在字符边界截断输出
+pub fn truncate_output(output: &str) -> &str {
+    if output.len() <= MAX_OUTPUT_LEN {
+        return output;
+    }
+    let mut end = MAX_OUTPUT_LEN;
+    while !output.is_char_boundary(end) {
+        end -= 1;
+    }
+    &output[..end]
+}
+
+// 设备心跳时取出需要执行的任务，登记为已下发，之后不再重复下发
+pub async fn dispatch(db: &EnterpriseDatabase, device_id: &str) -> ResultType<Vec<ClientJob>> {
+    let now = crate::common::now();
+    let jobs = db.list_active_jobs(now).await?;
+    if jobs.is_empty() {
+        return Ok(vec![]);
+    }
+    let group_ids = db.get_device_group_ids(device_id).await?;
+    if !feature_flags::is_enabled(Feature::RemoteJobs, device_id, &group_ids).await {
+        return Ok(vec![]);
+    }
+    let mut res = vec![];
+    for job in jobs.into_iter().filter(|j| j.targets(device_id, &group_ids)) {
+        let deadline = now + job.timeout_secs + RESULT_GRACE_SECS;
+        if db.create_job_run(&job.id, device_id, now, deadline).await? {
+            log::info!("Dispatched job {} to {}", job.id, device_id);
+            res.push(ClientJob {
+                id: job.id,
+                shell: job.shell,
+                command: job.command,
+                timeout_secs: job.timeout_secs,
+            });
+        }
+    }
+    Ok(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_job_validation_and_status() {
+        let mut job = Job {
+            id: "j1".to_owned(),
+            name: "restart spooler".to_owned(),
+            command: "Restart-Service Spooler".to_owned(),
+            shell: JobShell::PowerShell,
+            group_ids: vec!["g1".to_owned()],
+            device_ids: vec![],
+            timeout_secs: 60,
+            created_by: "admin".to_owned(),
+            created_at: 100,
+            expires_at: 200,
+            cancelled: false,
+        };
+        assert!(job.validate().is_ok());
+        assert!(job.targets("123", &["g1".to_owned()]));
+        assert!(!job.targets("123", &["g2".to_owned()]));
+        job.group_ids.clear();
+        assert!(job.validate().is_err());
+
+        assert_eq!(run_status(Some(0), false), JobRunStatus::Succeeded);
+        assert_eq!(run_status(Some(1), false), JobRunStatus::Failed);
+        assert_eq!(run_status(None, true), JobRunStatus::TimedOut);
+
+        let long = "中".repeat(MAX_OUTPUT_LEN);
+        let truncated = truncate_output(&long);
+        assert!(truncated.len() <= MAX_OUTPUT_LEN);
+        assert!(truncated.chars().all(|c| c == '中'));
+    }
+}
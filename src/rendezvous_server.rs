@@ -337,6 +337,7 @@ impl RendezvousServer {
                     }
                     let id = rk.id;
                     let ip = addr.ip().to_string();
+                    let v6 = is_ipv6(&addr);
                     if id.len() < 6 {
                         return send_rk_res(socket, addr, UUID_MISMATCH).await;
                     } else if !self.check_ip_blocker(&ip, &id).await {
@@ -349,13 +350,13 @@ impl RendezvousServer {
                             (true, false)
                         } else {
                             if peer.uuid == rk.uuid {
-                                if peer.info.ip != ip && peer.pk != rk.pk {
+                                if peer.info.ip_of(v6) != ip && peer.pk != rk.pk {
                                     log::warn!(
                                         "Peer {} ip/pk mismatch: {}/{:?} vs {}/{:?}",
                                         id,
                                         ip,
                                         rk.pk,
-                                        peer.info.ip,
+                                        peer.info.ip_of(v6),
                                         peer.pk,
                                     );
                                     drop(peer);
@@ -371,7 +372,7 @@ impl RendezvousServer {
                                 drop(peer);
                                 return send_rk_res(socket, addr, UUID_MISMATCH).await;
                             }
-                            let ip_changed = peer.info.ip != ip;
+                            let ip_changed = peer.info.ip_of(v6) != ip;
                             (
                                 peer.uuid != rk.uuid || peer.pk != rk.pk || ip_changed,
                                 ip_changed,
@@ -567,24 +568,28 @@ impl RendezvousServer {
         socket_addr: SocketAddr,
         socket: &mut FramedSocket,
     ) -> ResultType<()> {
+        let v6 = is_ipv6(&socket_addr);
         let (request_pk, ip_change) = if let Some(old) = self.pm.get_in_memory(&id).await {
             let mut old = old.write().await;
             let ip = socket_addr.ip();
-            let ip_change = if old.socket_addr.port() != 0 {
+            // 双栈设备交替使用IPv4/IPv6注册，只与同一地址族的旧地址比较
+            let ip_change = if let Some(prev) = old.fresh_addr(v6) {
+                ip != prev.ip()
+            } else if old.socket_addr.port() != 0 && is_ipv6(&old.socket_addr) == v6 {
                 ip != old.socket_addr.ip()
             } else {
-                ip.to_string() != old.info.ip
+                ip.to_string() != old.info.ip_of(v6)
             } && !ip.is_loopback();
             let request_pk = old.pk.is_empty() || ip_change;
             if !request_pk {
                 old.socket_addr = socket_addr;
+                old.observe_addr(socket_addr);
                 old.last_reg_time = Instant::now();
             }
             let ip_change = if ip_change && old.reg_pk.0 <= 2 {
-                Some(if old.socket_addr.port() == 0 {
-                    old.info.ip.clone()
-                } else {
-                    old.socket_addr.to_string()
+                Some(match old.fresh_addr(v6) {
+                    Some(prev) => prev.to_string(),
+                    None => old.info.ip_of(v6).to_owned(),
                 })
             } else {
                 None
@@ -696,7 +701,9 @@ impl RendezvousServer {
         if let Some(peer) = self.pm.get(&id).await {
             let (elapsed, peer_addr) = {
                 let r = peer.read().await;
-                (r.last_reg_time.elapsed().as_millis() as i32, r.socket_addr)
+                // 双栈对端优先选择与请求方相同地址族的地址，两端都支持IPv6时走IPv6打洞
+                let peer_addr = r.fresh_addr(is_ipv6(&addr)).unwrap_or(r.socket_addr);
+                (r.last_reg_time.elapsed().as_millis() as i32, peer_addr)
             };
             if elapsed >= REG_TIMEOUT {
                 let mut msg_out = RendezvousMessage::new();
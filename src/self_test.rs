@@ -0,0 +1,354 @@
+// 客户端连通性自检模块 - 客户端向服务器申请自检计划后逐项检查，结果按设备保存供管理员排查：
+//   - UDP 可达：客户端向信令端口发送 UDP TestNatRequest(serial 为计划中的令牌)，服务器收到即记录并回复
+//   - NAT 类型：客户端经 TCP 向信令端口和 NAT 测试端口(信令端口 - 1)各发一次带令牌的 TestNatRequest，
+//     服务器比较两次看到的源端口，相同为 asymmetric(端口不随目标变化)，不同为 symmetric
+//   - 中继 RTT：客户端测量到计划中每台中继的 TCP 建连耗时后上报
+//   - 密钥匹配：客户端上报所配置的服务器公钥，与当前签名公钥(含轮换中的新旧公钥)比对
+// 令牌为负数，与客户端正常 NAT 测试携带的配置序号(非负)区分
+use crate::common::now;
+use crate::enterprise_database::EnterpriseDatabase;
+use crate::nat_diagnostics::nat_type_label;
+use crate::relay_sessions;
+use crate::server_key;
+use hbb_common::{bail, log, rendezvous_proto::NatType, tokio::sync::RwLock, ResultType};
+use schemars::JsonSchema;
+use serde_derive::{Deserialize, Serialize};
+use std::{collections::HashMap, net::SocketAddr};
+
+// 计划有效期
+const PLAN_TTL_SECS: u64 = 300;
+// 每台设备保留的结果数量
+pub const MAX_RESULTS_PER_DEVICE: usize = 20;
+const MAX_RELAYS: usize = 32;
+// 中继 RTT 超过该值时提示
+const SLOW_RELAY_MS: u32 = 300;
+
+lazy_static::lazy_static! {
+    static ref PENDING: RwLock<HashMap<i32, Pending>> = Default::default();
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Probe {
+    Udp,
+    Tcp,
+    NatPort,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub enum KeyCheck {
+    Match,
+    Mismatch,
+    NotConfigured, // 客户端未配置公钥
+    NoServerKey,   // 服务器未配置签名私钥
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct SelfTestPlan {
+    pub test_id: String,
+    // 各 TestNatRequest 的 serial
+    pub token: i32,
+    pub expires_at: u64,
+    // 待测量 RTT 的中继服务器
+    pub relay_servers: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct RelayRtt {
+    pub server: String,
+    // 无法连接时为空
+    #[serde(default)]
+    pub rtt_ms: Option<u32>,
+    #[serde(default)]
+    pub error: Option<String>,
+}
+
+// 客户端上报的自检结果
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct ClientSelfTestReport {
+    #[serde(default)]
+    pub key: String,
+    #[serde(default)]
+    pub version: String,
+    #[serde(default)]
+    pub relays: Vec<RelayRtt>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct SelfTestResult {
+    pub test_id: String,
+    pub device_id: String,
+    pub client_version: String,
+    pub started_at: u64,
+    pub completed_at: u64,
+    pub udp_reachable: bool,
+    pub udp_addr: Option<String>,
+    pub tcp_addr: Option<String>,
+    // asymmetric / symmetric / unknown
+    pub nat_type: String,
+    pub relays: Vec<RelayRtt>,
+    pub key_check: KeyCheck,
+    pub passed: bool,
+    pub hints: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+struct Pending {
+    test_id: String,
+    device_id: String,
+    started_at: u64,
+    relay_servers: Vec<String>,
+    udp: Option<SocketAddr>,
+    tcp: Option<SocketAddr>,
+    nat_port: Option<SocketAddr>,
+}
+
+fn new_token() -> i32 {
+    // -1 ~ i32::MIN
+    -((rand::random::<u32>() >> 1) as i32) - 1
+}
+
+pub async fn start(device_id: &str) -> SelfTestPlan {
+    let now = now();
+    let mut pending = PENDING.write().await;
+    pending.retain(|_, x| x.started_at + PLAN_TTL_SECS > now && x.device_id != device_id);
+    let mut token = new_token();
+    while pending.contains_key(&token) {
+        token = new_token();
+    }
+    let relay_servers: Vec<String> = relay_sessions::servers().into_iter().take(MAX_RELAYS).collect();
+    let test_id = uuid::Uuid::new_v4().to_string();
+    pending.insert(
+        token,
+        Pending {
+            test_id: test_id.clone(),
+            device_id: device_id.to_owned(),
+            started_at: now,
+            relay_servers: relay_servers.clone(),
+            udp: None,
+            tcp: None,
+            nat_port: None,
+        },
+    );
+    SelfTestPlan {
+        test_id,
+        token,
+        expires_at: now + PLAN_TTL_SECS,
+        relay_servers,
+    }
+}
+
+// 信令服务器收到带令牌的 TestNatRequest，返回是否为自检探测
+pub async fn probe(token: i32, addr: SocketAddr, probe: Probe) -> bool {
+    if token >= 0 {
+        return false;
+    }
+    let now = now();
+    let mut pending = PENDING.write().await;
+    let test = match pending.get_mut(&token) {
+        Some(test) if test.started_at + PLAN_TTL_SECS > now => test,
+        _ => return false,
+    };
+    match probe {
+        Probe::Udp => test.udp = Some(addr),
+        Probe::Tcp => test.tcp = Some(addr),
+        Probe::NatPort => test.nat_port = Some(addr),
+    }
+    true
+}
+
+fn nat_type(tcp: Option<SocketAddr>, nat_port: Option<SocketAddr>) -> NatType {
+    match (tcp, nat_port) {
+        (Some(a), Some(b)) if a.ip() == b.ip() && a.port() == b.port() => NatType::ASYMMETRIC,
+        (Some(_), Some(_)) => NatType::SYMMETRIC,
+        _ => NatType::UNKNOWN_NAT,
+    }
+}
+
+fn key_check(client_key: &str, keys: &server_key::KeyRingInfo, now: u64) -> KeyCheck {
+    if keys.active_pk.is_none() {
+        return KeyCheck::NoServerKey;
+    }
+    let client_key = client_key.trim();
+    if client_key.is_empty() {
+        return KeyCheck::NotConfigured;
+    }
+    let previous = keys
+        .previous_pk
+        .as_ref()
+        .filter(|_| keys.previous_valid_until.map(|t| t > now).unwrap_or(false));
+    if keys
+        .active_pk
+        .iter()
+        .chain(keys.next_pk.iter())
+        .chain(previous)
+        .any(|pk| pk == client_key)
+    {
+        KeyCheck::Match
+    } else {
+        KeyCheck::Mismatch
+    }
+}
+
+fn evaluate(pending: Pending, report: ClientSelfTestReport, key_check: KeyCheck, now: u64) -> SelfTestResult {
+    let nat_type = nat_type(pending.tcp, pending.nat_port);
+    // 只保留计划中的中继
+    let relays: Vec<RelayRtt> = report
+        .relays
+        .into_iter()
+        .filter(|x| pending.relay_servers.contains(&x.server))
+        .collect();
+    let mut hints = Vec::new();
+    if pending.udp.is_none() {
+        hints.push("服务器未收到 UDP 探测，检查防火墙是否放行信令端口的 UDP，否则只能经 TCP 注册且无法打洞".to_owned());
+    }
+    if pending.tcp.is_none() || pending.nat_port.is_none() {
+        hints.push("未收到信令端口或 NAT 测试端口的 TCP 探测，无法判断 NAT 类型，检查两个端口是否都已放行".to_owned());
+    } else if nat_type == NatType::SYMMETRIC {
+        hints.push("对称型 NAT，与同为对称型 NAT 的设备之间打洞基本会失败，连接将回退到中继".to_owned());
+    }
+    match key_check {
+        KeyCheck::Mismatch => {
+            hints.push("客户端配置的公钥与服务器不一致，连接会被拒绝，请重新下发服务器公钥".to_owned())
+        }
+        KeyCheck::NotConfigured => hints.push("客户端未配置服务器公钥，连接不加密或被服务器拒绝".to_owned()),
+        _ => {}
+    }
+    let reachable = relays.iter().filter(|x| x.rtt_ms.is_some()).count();
+    if !pending.relay_servers.is_empty() && reachable == 0 {
+        hints.push("所有中继服务器均无法连接，直连失败时将无法建立会话".to_owned());
+    }
+    for relay in &relays {
+        match relay.rtt_ms {
+            Some(rtt) if rtt > SLOW_RELAY_MS => hints.push(format!("到中继 {} 的 RTT 为 {}ms", relay.server, rtt)),
+            None => hints.push(format!("无法连接中继 {}", relay.server)),
+            _ => {}
+        }
+    }
+    let passed =
+        pending.udp.is_some() && key_check != KeyCheck::Mismatch && (pending.relay_servers.is_empty() || reachable > 0);
+    SelfTestResult {
+        test_id: pending.test_id,
+        device_id: pending.device_id,
+        client_version: report.version,
+        started_at: pending.started_at,
+        completed_at: now,
+        udp_reachable: pending.udp.is_some(),
+        udp_addr: pending.udp.map(|x| x.to_string()),
+        tcp_addr: pending.tcp.map(|x| x.to_string()),
+        nat_type: nat_type_label(nat_type).to_owned(),
+        relays,
+        key_check,
+        passed,
+        hints,
+    }
+}
+
+// 客户端上报结果，结合服务器记录的探测生成并保存自检结果
+pub async fn complete(
+    db: &EnterpriseDatabase,
+    device_id: &str,
+    test_id: &str,
+    report: ClientSelfTestReport,
+) -> ResultType<SelfTestResult> {
+    if report.relays.len() > MAX_RELAYS {
+        bail!("too many relays");
+    }
+    let now = now();
+    let pending = {
+        let mut pending = PENDING.write().await;
+        let token = pending
+            .iter()
+            .find(|(_, x)| x.test_id == test_id && x.device_id == device_id)
+            .map(|(token, _)| *token);
+        match token.and_then(|token| pending.remove(&token)) {
+            Some(test) if test.started_at + PLAN_TTL_SECS > now => test,
+            _ => bail!("self-test not found or expired"),
+        }
+    };
+    let key_check = key_check(&report.key, &server_key::info().await, now);
+    let result = evaluate(pending, report, key_check, now);
+    db.save_self_test(&result).await?;
+    log::info!(
+        "Self-test {} of {} completed, passed: {}",
+        result.test_id,
+        result.device_id,
+        result.passed
+    );
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pending() -> Pending {
+        Pending {
+            test_id: "t".to_owned(),
+            device_id: "123456789".to_owned(),
+            started_at: 100,
+            relay_servers: vec!["relay1.example.com".to_owned(), "relay2.example.com".to_owned()],
+            udp: Some("203.0.113.5:40000".parse().unwrap()),
+            tcp: Some("203.0.113.5:40001".parse().unwrap()),
+            nat_port: Some("203.0.113.5:40001".parse().unwrap()),
+        }
+    }
+
+    #[test]
+    fn test_token() {
+        for _ in 0..1000 {
+            assert!(new_token() < 0);
+        }
+    }
+
+    #[test]
+    fn test_key_check() {
+        let keys = server_key::KeyRingInfo {
+            active_pk: Some("active".to_owned()),
+            next_pk: None,
+            activate_at: None,
+            previous_pk: Some("previous".to_owned()),
+            previous_valid_until: Some(200),
+        };
+        assert_eq!(key_check("active", &keys, 100), KeyCheck::Match);
+        assert_eq!(key_check("previous", &keys, 100), KeyCheck::Match);
+        assert_eq!(key_check("previous", &keys, 300), KeyCheck::Mismatch);
+        assert_eq!(key_check("", &keys, 100), KeyCheck::NotConfigured);
+        assert_eq!(
+            key_check("active", &server_key::KeyRingInfo::default(), 100),
+            KeyCheck::NoServerKey
+        );
+    }
+
+    #[test]
+    fn test_evaluate() {
+        let report = ClientSelfTestReport {
+            key: "active".to_owned(),
+            version: "1.3.0".to_owned(),
+            relays: vec![
+                RelayRtt {
+                    server: "relay1.example.com".to_owned(),
+                    rtt_ms: Some(20),
+                    error: None,
+                },
+                RelayRtt {
+                    server: "unknown.example.com".to_owned(),
+                    rtt_ms: Some(1),
+                    error: None,
+                },
+            ],
+        };
+        let result = evaluate(pending(), report.clone(), KeyCheck::Match, 110);
+        assert!(result.passed);
+        assert_eq!(result.nat_type, "asymmetric");
+        assert_eq!(result.relays.len(), 1);
+
+        let mut symmetric = pending();
+        symmetric.nat_port = Some("203.0.113.5:40002".parse().unwrap());
+        symmetric.udp = None;
+        let result = evaluate(symmetric, report, KeyCheck::Mismatch, 110);
+        assert!(!result.passed);
+        assert!(!result.udp_reachable);
+        assert_eq!(result.nat_type, "symmetric");
+        assert!(result.hints.len() >= 3);
+    }
+}
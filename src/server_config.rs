@@ -0,0 +1,507 @@
+// 配置文件模块 - 通过 --config 指定 TOML 配置文件，统一配置端口、数据库、JWT、SMTP、中继列表和各项策略。
+// 启动参数优先级: 命令行参数 > 环境变量(含 .env) > 配置文件；[smtp] 和 [policies] 在启动时写入系统设置，
+// 覆盖 Web 界面中的修改。`hbbs config validate [--config FILE]` 只检查配置文件，不启动服务
+//
+//   [server]   port / web_port / key / serial / rmem / mask / software_url / update_base_url / public_url
+//              license_file / rendezvous_servers / relay_servers / always_use_relay
+//   [database] url / max_connections
+//   [auth]     jwt_secret / jwt_secret_secondary
+//   [smtp]     同 /api/settings/email-otp
+//   [policies] 键为系统设置名(如 relay_policy、four_eyes)，值同对应的 /api/settings 接口
+use crate::ad_sync::{self, AdSyncConfig};
+use crate::backup::{self, BackupConfig};
+use crate::change_approval::{self, ChangeApprovalConfig};
+use crate::content_scan::{self, ScanConfig};
+use crate::custom_fields::{self, CustomFieldsConfig};
+use crate::data_masking::{self, DataMaskingConfig};
+use crate::dlp::{self, DlpPolicy};
+use crate::dns_discovery::{self, DnsDiscoveryConfig};
+use crate::e2e_signaling::{self, E2ePolicy};
+use crate::email_otp::{self, SmtpConfig};
+use crate::enterprise_database::EnterpriseDatabase;
+use crate::feature_flags::{self, FeatureFlags};
+use crate::federation::{self, FederationConfig};
+use crate::four_eyes::{self, FourEyesPolicy};
+use crate::holiday_calendar::{self, CalendarConfig};
+use crate::honeypot::{self, HoneypotConfig};
+use crate::id_policy::{self, IdPolicy};
+use crate::itsm::{self, ItsmConfig};
+use crate::kiosk_fleet::{self, KioskFleetConfig};
+use crate::lan_config::{self, LanConfig};
+use crate::logging::{self, LoggingConfig};
+use crate::mfa_policy::{self, MfaPolicy};
+use crate::network_tuning::{self, NetworkTuning};
+use crate::offline_alerts::{self, OfflineAlertConfig};
+use crate::password_policy::{self, PasswordPolicies};
+use crate::pk_pinning::{self, PkPinningConfig};
+use crate::relay_policy::{self, RelayPolicy};
+use crate::threat_intel::{self, ThreatIntelConfig};
+use crate::transfer_bandwidth::{self, BandwidthPolicy};
+use crate::trusted_device::{self, TrustedDeviceConfig};
+use crate::turn::{self, TurnConfig};
+use crate::unattended_access::{self, UnattendedPolicy};
+use crate::version_policy::{self, VersionPolicy};
+use crate::web_security::{self, WebSecurityConfig};
+use crate::web_session::{self, WebSessionConfig};
+use crate::webrtc_signaling::{self, WebRtcConfig};
+use hbb_common::{anyhow::Context, bail, log, ResultType};
+use once_cell::sync::OnceCell;
+use serde_derive::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+// 配置文件写入系统设置时记录的操作人
+const CONFIG_FILE_USER: &str = "config-file";
+const MIN_JWT_SECRET_LEN: usize = 32;
+
+static LOADED: OnceCell<ServerConfig> = OnceCell::new();
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ServerConfig {
+    #[serde(default)]
+    pub server: ServerSection,
+    #[serde(default)]
+    pub database: DatabaseSection,
+    #[serde(default)]
+    pub auth: AuthSection,
+    #[serde(default)]
+    pub smtp: Option<SmtpConfig>,
+    #[serde(default)]
+    pub policies: BTreeMap<String, serde_json::Value>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ServerSection {
+    pub port: Option<u16>,
+    pub web_port: Option<u16>,
+    pub key: Option<String>,
+    pub serial: Option<i32>,
+    pub rmem: Option<usize>,
+    pub mask: Option<String>,
+    pub software_url: Option<String>,
+    pub update_base_url: Option<String>,
+    pub public_url: Option<String>, // 管理界面的对外地址，用于邀请链接
+    pub license_file: Option<String>, // 企业版许可证文件，通过管理接口上传的许可证优先
+    #[serde(default)]
+    pub rendezvous_servers: Vec<String>,
+    #[serde(default)]
+    pub relay_servers: Vec<String>,
+    pub always_use_relay: Option<bool>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DatabaseSection {
+    pub url: Option<String>,
+    pub max_connections: Option<usize>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AuthSection {
+    pub jwt_secret: Option<String>,
+    // 更换 jwt_secret 期间仍接受的原密钥
+    pub jwt_secret_secondary: Option<String>,
+}
+
+impl ServerConfig {
+    pub fn parse(content: &str) -> ResultType<Self> {
+        let config: ServerConfig = toml::from_str(content)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn load(path: &str) -> ResultType<Self> {
+        let content = std::fs::read_to_string(path).with_context(|| format!("failed to read {}", path))?;
+        Self::parse(&content).with_context(|| format!("invalid config file {}", path))
+    }
+
+    pub fn validate(&self) -> ResultType<()> {
+        let server = &self.server;
+        if matches!(server.port, Some(p) if p < 3) {
+            bail!("server.port must be at least 3");
+        }
+        if let (Some(port), Some(web_port)) = (server.port, server.web_port) {
+            if port == web_port {
+                bail!("server.web_port conflicts with server.port");
+            }
+        }
+        if let Some(mask) = &server.mask {
+            if mask.parse::<ipnetwork::Ipv4Network>().is_err() {
+                bail!("server.mask is not a valid IPv4 network: {}", mask);
+            }
+        }
+        for server in server.rendezvous_servers.iter().chain(server.relay_servers.iter()) {
+            if server.trim().is_empty() || server.contains(',') {
+                bail!("invalid server address: {:?}", server);
+            }
+        }
+        if matches!(&self.database.url, Some(url) if url.trim().is_empty()) {
+            bail!("database.url is empty");
+        }
+        if matches!(self.database.max_connections, Some(0)) {
+            bail!("database.max_connections must be positive");
+        }
+        if let Some(secret) = &self.auth.jwt_secret {
+            if secret.len() < MIN_JWT_SECRET_LEN {
+                bail!("auth.jwt_secret must be at least {} characters", MIN_JWT_SECRET_LEN);
+            }
+        }
+        if let Some(secret) = &self.auth.jwt_secret_secondary {
+            if secret.len() < MIN_JWT_SECRET_LEN {
+                bail!("auth.jwt_secret_secondary must be at least {} characters", MIN_JWT_SECRET_LEN);
+            }
+            if self.auth.jwt_secret.as_ref() == Some(secret) {
+                bail!("auth.jwt_secret_secondary must differ from auth.jwt_secret");
+            }
+        }
+        if let Some(smtp) = &self.smtp {
+            if smtp.enabled && (smtp.host.is_empty() || smtp.from.is_empty()) {
+                bail!("smtp.host and smtp.from are required when smtp is enabled");
+            }
+        }
+        for (key, value) in self.policies.iter() {
+            check_policy(key, value).with_context(|| format!("policies.{}", key))?;
+        }
+        Ok(())
+    }
+
+    // 转换为启动参数对应的环境变量名，与 get_arg 的命名一致
+    pub fn env_vars(&self) -> Vec<(&'static str, String)> {
+        let server = &self.server;
+        let mut vars = vec![];
+        let mut push = |name: &'static str, value: Option<String>| {
+            if let Some(value) = value {
+                vars.push((name, value));
+            }
+        };
+        push("PORT", server.port.map(|x| x.to_string()));
+        push("WEB_PORT", server.web_port.map(|x| x.to_string()));
+        push("KEY", server.key.clone());
+        push("SERIAL", server.serial.map(|x| x.to_string()));
+        push("RMEM", server.rmem.map(|x| x.to_string()));
+        push("MASK", server.mask.clone());
+        push("SOFTWARE-URL", server.software_url.clone());
+        push("UPDATE-BASE-URL", server.update_base_url.clone());
+        push("PUBLIC-URL", server.public_url.clone());
+        push("LICENSE-FILE", server.license_file.clone());
+        if !server.rendezvous_servers.is_empty() {
+            push("RENDEZVOUS-SERVERS", Some(server.rendezvous_servers.join(",")));
+        }
+        if !server.relay_servers.is_empty() {
+            push("RELAY-SERVERS", Some(server.relay_servers.join(",")));
+        }
+        push(
+            "ALWAYS_USE_RELAY",
+            server.always_use_relay.map(|x| if x { "Y" } else { "N" }.to_owned()),
+        );
+        push("ENTERPRISE_DB_URL", self.database.url.clone());
+        push("MAX_DATABASE_CONNECTIONS", self.database.max_connections.map(|x| x.to_string()));
+        push("JWT_SECRET", self.auth.jwt_secret.clone());
+        push("JWT_SECRET_SECONDARY", self.auth.jwt_secret_secondary.clone());
+        vars
+    }
+}
+
+// [policies] 和设置接口可修改的策略，其修改记录为配置版本
+pub(crate) const POLICY_KEYS: &[&str] = &[
+    relay_policy::RELAY_POLICY_KEY,
+    version_policy::VERSION_POLICY_KEY,
+    unattended_access::UNATTENDED_ACCESS_KEY,
+    four_eyes::FOUR_EYES_KEY,
+    logging::LOGGING_KEY,
+    lan_config::LAN_CONFIG_KEY,
+    id_policy::ID_POLICY_KEY,
+    mfa_policy::MFA_POLICY_KEY,
+    e2e_signaling::E2E_POLICY_KEY,
+    dlp::DLP_POLICY_KEY,
+    transfer_bandwidth::TRANSFER_BANDWIDTH_KEY,
+    content_scan::CONTENT_SCAN_KEY,
+    trusted_device::TRUSTED_DEVICE_KEY,
+    web_security::WEB_SECURITY_KEY,
+    feature_flags::FEATURE_FLAGS_KEY,
+    itsm::ITSM_KEY,
+    ad_sync::AD_SYNC_KEY,
+    web_session::WEB_SESSION_KEY,
+    data_masking::DATA_MASKING_KEY,
+    password_policy::PASSWORD_POLICIES_KEY,
+    backup::BACKUP_KEY,
+    offline_alerts::OFFLINE_ALERTS_KEY,
+    custom_fields::CUSTOM_FIELDS_KEY,
+    network_tuning::NETWORK_TUNING_KEY,
+    webrtc_signaling::WEBRTC_KEY,
+    turn::TURN_KEY,
+    honeypot::HONEYPOT_KEY,
+    threat_intel::THREAT_INTEL_KEY,
+    change_approval::CHANGE_APPROVAL_KEY,
+    federation::FEDERATION_KEY,
+    dns_discovery::DNS_DISCOVERY_KEY,
+    holiday_calendar::HOLIDAY_CALENDARS_KEY,
+    kiosk_fleet::KIOSK_FLEET_KEY,
+    pk_pinning::PK_PINNING_KEY,
+];
+
+// 按设置名检查策略内容
+pub(crate) fn check_policy(key: &str, value: &serde_json::Value) -> ResultType<()> {
+    let value = value.clone();
+    match key {
+        relay_policy::RELAY_POLICY_KEY => {
+            serde_json::from_value::<RelayPolicy>(value)?;
+        }
+        version_policy::VERSION_POLICY_KEY => serde_json::from_value::<VersionPolicy>(value)?.validate()?,
+        unattended_access::UNATTENDED_ACCESS_KEY => serde_json::from_value::<UnattendedPolicy>(value)?.validate()?,
+        four_eyes::FOUR_EYES_KEY => serde_json::from_value::<FourEyesPolicy>(value)?.validate()?,
+        logging::LOGGING_KEY => serde_json::from_value::<LoggingConfig>(value)?.validate()?,
+        lan_config::LAN_CONFIG_KEY => {
+            serde_json::from_value::<LanConfig>(value)?;
+        }
+        id_policy::ID_POLICY_KEY => {
+            serde_json::from_value::<IdPolicy>(value)?;
+        }
+        mfa_policy::MFA_POLICY_KEY => {
+            serde_json::from_value::<MfaPolicy>(value)?;
+        }
+        e2e_signaling::E2E_POLICY_KEY => {
+            serde_json::from_value::<E2ePolicy>(value)?;
+        }
+        dlp::DLP_POLICY_KEY => {
+            serde_json::from_value::<DlpPolicy>(value)?;
+        }
+        transfer_bandwidth::TRANSFER_BANDWIDTH_KEY => serde_json::from_value::<BandwidthPolicy>(value)?.validate()?,
+        content_scan::CONTENT_SCAN_KEY => serde_json::from_value::<ScanConfig>(value)?.validate()?,
+        trusted_device::TRUSTED_DEVICE_KEY => serde_json::from_value::<TrustedDeviceConfig>(value)?.validate()?,
+        web_security::WEB_SECURITY_KEY => serde_json::from_value::<WebSecurityConfig>(value)?.validate()?,
+        feature_flags::FEATURE_FLAGS_KEY => {
+            feature_flags::validate(&serde_json::from_value::<FeatureFlags>(value)?)?;
+        }
+        itsm::ITSM_KEY => serde_json::from_value::<ItsmConfig>(value)?.validate()?,
+        ad_sync::AD_SYNC_KEY => serde_json::from_value::<AdSyncConfig>(value)?.validate()?,
+        web_session::WEB_SESSION_KEY => serde_json::from_value::<WebSessionConfig>(value)?.validate()?,
+        data_masking::DATA_MASKING_KEY => {
+            data_masking::validate(&serde_json::from_value::<DataMaskingConfig>(value)?)?;
+        }
+        password_policy::PASSWORD_POLICIES_KEY => serde_json::from_value::<PasswordPolicies>(value)?.validate()?,
+        backup::BACKUP_KEY => serde_json::from_value::<BackupConfig>(value)?.validate()?,
+        offline_alerts::OFFLINE_ALERTS_KEY => serde_json::from_value::<OfflineAlertConfig>(value)?.validate()?,
+        custom_fields::CUSTOM_FIELDS_KEY => serde_json::from_value::<CustomFieldsConfig>(value)?.validate()?,
+        network_tuning::NETWORK_TUNING_KEY => serde_json::from_value::<NetworkTuning>(value)?.validate()?,
+        webrtc_signaling::WEBRTC_KEY => serde_json::from_value::<WebRtcConfig>(value)?.validate()?,
+        turn::TURN_KEY => serde_json::from_value::<TurnConfig>(value)?.validate()?,
+        honeypot::HONEYPOT_KEY => serde_json::from_value::<HoneypotConfig>(value)?.validate()?,
+        threat_intel::THREAT_INTEL_KEY => serde_json::from_value::<ThreatIntelConfig>(value)?.validate()?,
+        change_approval::CHANGE_APPROVAL_KEY => serde_json::from_value::<ChangeApprovalConfig>(value)?.validate()?,
+        federation::FEDERATION_KEY => serde_json::from_value::<FederationConfig>(value)?.validate()?,
+        dns_discovery::DNS_DISCOVERY_KEY => serde_json::from_value::<DnsDiscoveryConfig>(value)?.validate()?,
+        holiday_calendar::HOLIDAY_CALENDARS_KEY => serde_json::from_value::<CalendarConfig>(value)?.validate()?,
+        kiosk_fleet::KIOSK_FLEET_KEY => serde_json::from_value::<KioskFleetConfig>(value)?.validate()?,
+        pk_pinning::PK_PINNING_KEY => {
+            serde_json::from_value::<PkPinningConfig>(value)?;
+        }
+        _ => bail!("unknown policy"),
+    }
+    Ok(())
+}
+
+// 读取配置文件并导出为环境变量，已存在的环境变量(命令行参数、.env)优先
+pub fn init(path: &str) -> ResultType<()> {
+    let config = ServerConfig::load(path)?;
+    for (name, value) in config.env_vars() {
+        if std::env::var(name).is_err() {
+            std::env::set_var(name, value);
+        }
+    }
+    LOADED.set(config).ok();
+    Ok(())
+}
+
+// 启动时将配置文件中的 SMTP 和策略写入系统设置，由各模块校验
+pub async fn apply_settings(db: &EnterpriseDatabase) -> ResultType<()> {
+    let config = match LOADED.get() {
+        Some(config) => config,
+        None => return Ok(()),
+    };
+    if let Some(smtp) = &config.smtp {
+        email_otp::update(db, smtp.clone(), CONFIG_FILE_USER).await.context("smtp")?;
+    }
+    for (key, value) in config.policies.iter() {
+        apply_policy(db, key, value.clone(), CONFIG_FILE_USER)
+            .await
+            .with_context(|| format!("policies.{}", key))?;
+    }
+    log::info!("Applied {} policies from config file", config.policies.len());
+    Ok(())
+}
+
+// 按设置名写入策略，by 为记录的修改人
+pub(crate) async fn apply_policy(
+    db: &EnterpriseDatabase,
+    key: &str,
+    value: serde_json::Value,
+    by: &str,
+) -> ResultType<()> {
+    match key {
+        relay_policy::RELAY_POLICY_KEY => relay_policy::update(db, serde_json::from_value(value)?, by).await?,
+        version_policy::VERSION_POLICY_KEY => version_policy::update(db, serde_json::from_value(value)?, by).await?,
+        unattended_access::UNATTENDED_ACCESS_KEY => {
+            unattended_access::update(db, serde_json::from_value(value)?, by).await?
+        }
+        four_eyes::FOUR_EYES_KEY => four_eyes::update(db, serde_json::from_value(value)?, by).await?,
+        logging::LOGGING_KEY => logging::update(db, serde_json::from_value(value)?, by).await?,
+        lan_config::LAN_CONFIG_KEY => lan_config::update(db, serde_json::from_value(value)?, by).await?,
+        id_policy::ID_POLICY_KEY => id_policy::update(db, serde_json::from_value(value)?, by).await?,
+        mfa_policy::MFA_POLICY_KEY => mfa_policy::update(db, serde_json::from_value(value)?, by).await?,
+        e2e_signaling::E2E_POLICY_KEY => e2e_signaling::update(db, serde_json::from_value(value)?, by).await?,
+        dlp::DLP_POLICY_KEY => dlp::update(db, serde_json::from_value(value)?, by).await?,
+        transfer_bandwidth::TRANSFER_BANDWIDTH_KEY => {
+            transfer_bandwidth::update(db, serde_json::from_value(value)?, by).await?
+        }
+        content_scan::CONTENT_SCAN_KEY => content_scan::update(db, serde_json::from_value(value)?, by).await?,
+        trusted_device::TRUSTED_DEVICE_KEY => trusted_device::update(db, serde_json::from_value(value)?, by).await?,
+        web_security::WEB_SECURITY_KEY => web_security::update(db, serde_json::from_value(value)?, by).await?,
+        feature_flags::FEATURE_FLAGS_KEY => feature_flags::update(db, serde_json::from_value(value)?, by).await?,
+        itsm::ITSM_KEY => itsm::update(db, serde_json::from_value(value)?, by).await?,
+        ad_sync::AD_SYNC_KEY => ad_sync::update(db, serde_json::from_value(value)?, by).await?,
+        web_session::WEB_SESSION_KEY => web_session::update(db, serde_json::from_value(value)?, by).await?,
+        data_masking::DATA_MASKING_KEY => data_masking::update(db, serde_json::from_value(value)?, by).await?,
+        password_policy::PASSWORD_POLICIES_KEY => {
+            password_policy::update(db, serde_json::from_value(value)?, by).await?;
+        }
+        backup::BACKUP_KEY => backup::update(db, serde_json::from_value(value)?, by).await?,
+        offline_alerts::OFFLINE_ALERTS_KEY => offline_alerts::update(db, serde_json::from_value(value)?, by).await?,
+        custom_fields::CUSTOM_FIELDS_KEY => custom_fields::update(db, serde_json::from_value(value)?, by).await?,
+        network_tuning::NETWORK_TUNING_KEY => network_tuning::update(db, serde_json::from_value(value)?, by).await?,
+        webrtc_signaling::WEBRTC_KEY => webrtc_signaling::update(db, serde_json::from_value(value)?, by).await?,
+        turn::TURN_KEY => turn::update(db, serde_json::from_value(value)?, by).await?,
+        honeypot::HONEYPOT_KEY => honeypot::update(db, serde_json::from_value(value)?, by).await?,
+        threat_intel::THREAT_INTEL_KEY => threat_intel::update(db, serde_json::from_value(value)?, by).await?,
+        change_approval::CHANGE_APPROVAL_KEY => change_approval::update(db, serde_json::from_value(value)?, by).await?,
+        federation::FEDERATION_KEY => federation::update(db, serde_json::from_value(value)?, by).await?,
+        dns_discovery::DNS_DISCOVERY_KEY => dns_discovery::update(db, serde_json::from_value(value)?, by).await?,
+        holiday_calendar::HOLIDAY_CALENDARS_KEY => {
+            holiday_calendar::update(db, serde_json::from_value(value)?, by).await?
+        }
+        kiosk_fleet::KIOSK_FLEET_KEY => kiosk_fleet::update(db, serde_json::from_value(value)?, by).await?,
+        pk_pinning::PK_PINNING_KEY => pk_pinning::update(db, serde_json::from_value(value)?, by).await?,
+        _ => bail!("unknown policy"),
+    }
+    Ok(())
+}
+
+// 设置在数据库中被直接替换后(如回滚到历史版本)刷新对应模块的运行时配置
+pub(crate) async fn reload_policy(db: &EnterpriseDatabase, key: &str, by: &str) -> ResultType<()> {
+    match key {
+        relay_policy::RELAY_POLICY_KEY => relay_policy::reload(db).await?,
+        version_policy::VERSION_POLICY_KEY => version_policy::reload(db).await?,
+        unattended_access::UNATTENDED_ACCESS_KEY => unattended_access::reload(db).await?,
+        four_eyes::FOUR_EYES_KEY => four_eyes::reload(db).await?,
+        logging::LOGGING_KEY => logging::reload(db).await?,
+        lan_config::LAN_CONFIG_KEY => lan_config::reload(db).await?,
+        id_policy::ID_POLICY_KEY => id_policy::reload(db).await?,
+        mfa_policy::MFA_POLICY_KEY => mfa_policy::reload(db).await?,
+        e2e_signaling::E2E_POLICY_KEY => e2e_signaling::reload(db).await?,
+        dlp::DLP_POLICY_KEY => dlp::reload(db).await?,
+        transfer_bandwidth::TRANSFER_BANDWIDTH_KEY => transfer_bandwidth::reload(db).await?,
+        content_scan::CONTENT_SCAN_KEY => content_scan::reload(db).await?,
+        trusted_device::TRUSTED_DEVICE_KEY => trusted_device::reload(db).await?,
+        web_security::WEB_SECURITY_KEY => web_security::reload(db).await?,
+        feature_flags::FEATURE_FLAGS_KEY => feature_flags::reload(db).await?,
+        itsm::ITSM_KEY => itsm::reload(db).await?,
+        ad_sync::AD_SYNC_KEY => ad_sync::reload(db).await?,
+        web_session::WEB_SESSION_KEY => web_session::reload(db).await?,
+        data_masking::DATA_MASKING_KEY => data_masking::reload(db).await?,
+        password_policy::PASSWORD_POLICIES_KEY => {
+            password_policy::reload(db).await?;
+            password_policy::bump_serial(db, by).await?;
+        }
+        backup::BACKUP_KEY => backup::reload(db).await?,
+        offline_alerts::OFFLINE_ALERTS_KEY => offline_alerts::reload(db).await?,
+        custom_fields::CUSTOM_FIELDS_KEY => custom_fields::reload(db).await?,
+        network_tuning::NETWORK_TUNING_KEY => network_tuning::reload(db).await?,
+        webrtc_signaling::WEBRTC_KEY => webrtc_signaling::reload(db).await?,
+        turn::TURN_KEY => turn::reload(db).await?,
+        honeypot::HONEYPOT_KEY => honeypot::reload(db).await?,
+        threat_intel::THREAT_INTEL_KEY => threat_intel::reload(db).await?,
+        change_approval::CHANGE_APPROVAL_KEY => change_approval::reload(db).await?,
+        federation::FEDERATION_KEY => federation::reload(db).await?,
+        dns_discovery::DNS_DISCOVERY_KEY => dns_discovery::reload(db).await?,
+        holiday_calendar::HOLIDAY_CALENDARS_KEY => holiday_calendar::reload(db).await?,
+        kiosk_fleet::KIOSK_FLEET_KEY => kiosk_fleet::reload(db).await?,
+        pk_pinning::PK_PINNING_KEY => pk_pinning::reload(db).await?,
+        _ => bail!("unknown policy"),
+    }
+    Ok(())
+}
+
+// `config validate` 子命令，返回进程退出码
+pub fn validate_command(path: &str) -> i32 {
+    match ServerConfig::load(path) {
+        Ok(config) => {
+            println!(
+                "{}: OK ({} environment settings, {} policies{})",
+                path,
+                config.env_vars().len(),
+                config.policies.len(),
+                if config.smtp.is_some() { ", smtp" } else { "" }
+            );
+            0
+        }
+        Err(err) => {
+            eprintln!("{:#}", err);
+            1
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_config() {
+        let config = ServerConfig::parse(
+            r#"
+            [server]
+            port = 21116
+            web_port = 21119
+            relay_servers = ["relay1.example.com", "relay2.example.com:21117"]
+            always_use_relay = true
+
+            [database]
+            url = "/var/lib/rustdesk/enterprise.sqlite3"
+
+            [auth]
+            jwt_secret = "0123456789abcdef0123456789abcdef"
+
+            [policies.four_eyes]
+            enabled = true
+            tag = "critical"
+            "#,
+        )
+        .unwrap();
+        let vars: BTreeMap<_, _> = config.env_vars().into_iter().collect();
+        assert_eq!(vars["PORT"], "21116");
+        assert_eq!(vars["RELAY-SERVERS"], "relay1.example.com,relay2.example.com:21117");
+        assert_eq!(vars["ALWAYS_USE_RELAY"], "Y");
+        assert_eq!(vars["ENTERPRISE_DB_URL"], "/var/lib/rustdesk/enterprise.sqlite3");
+        assert!(!vars.contains_key("KEY"));
+
+        assert!(ServerConfig::parse("[server]\nprot = 1").is_err());
+        assert!(ServerConfig::parse("[auth]\njwt_secret = \"short\"").is_err());
+        assert!(ServerConfig::parse("[auth]\njwt_secret_secondary = \"short\"").is_err());
+        let secret = "0123456789abcdef0123456789abcdef";
+        let both = format!("[auth]\njwt_secret = \"{0}\"\njwt_secret_secondary = \"{0}\"", secret);
+        assert!(ServerConfig::parse(&both).is_err());
+        assert!(ServerConfig::parse("[policies.unknown]\nenabled = true").is_err());
+        assert!(ServerConfig::parse("[policies.four_eyes]\nhold_secs = 600").is_err());
+    }
+
+    #[test]
+    fn test_policy_keys() {
+        for key in POLICY_KEYS {
+            let err = check_policy(key, &serde_json::Value::Null).unwrap_err();
+            assert_ne!(err.to_string(), "unknown policy", "{}", key);
+        }
+        assert!(check_policy("jwt_secret", &serde_json::Value::Null).is_err());
+    }
+}
@@ -0,0 +1,240 @@
+// 服务器密钥轮换模块 - 签名私钥(sign::SecretKey)按"预发布 -> 切换 -> 旧密钥宽限"轮换：
+// 轮换时先生成新密钥并通过配置序号 + 心跳策略选项(key)下发新公钥，重叠期结束后改用新密钥签名，
+// 旧公钥在宽限期内仍被接受为 licence_key，避免未及时更新的客户端立即失联。
+// 使用硬件签名器时私钥不出设备，密钥轮换需在 HSM/TPM 中完成
+use crate::common::now;
+use crate::enterprise_database::EnterpriseDatabase;
+use crate::password_policy;
+use crate::signer::{Signer, SoftwareSigner};
+use hbb_common::{bail, log, tokio::sync::RwLock, ResultType};
+use schemars::JsonSchema;
+use serde_derive::{Deserialize, Serialize};
+use sodiumoxide::crypto::sign;
+use std::{collections::HashMap, sync::Arc};
+
+pub const SERVER_KEYS_KEY: &str = "server_keys";
+// 切换后旧公钥的宽限期
+const PREVIOUS_KEY_GRACE_SECS: u64 = 7 * 24 * 3600;
+
+lazy_static::lazy_static! {
+    static ref KEY_RING: RwLock<KeyRing> = Default::default();
+    static ref HARDWARE_SIGNER: RwLock<Option<Arc<dyn Signer>>> = Default::default();
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct KeyRing {
+    pub active: Option<String>, // base64 私钥
+    #[serde(default)]
+    pub next: Option<String>,
+    #[serde(default)]
+    pub activate_at: Option<u64>,
+    #[serde(default)]
+    pub previous: Option<String>,
+    #[serde(default)]
+    pub previous_valid_until: Option<u64>,
+    #[serde(default)]
+    pub rotated_at: Option<u64>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct KeyRingInfo {
+    pub active_pk: Option<String>,
+    pub next_pk: Option<String>,
+    pub activate_at: Option<u64>,
+    pub previous_pk: Option<String>,
+    pub previous_valid_until: Option<u64>,
+}
+
+fn decode_sk(sk: &str) -> Option<sign::SecretKey> {
+    base64::decode(sk)
+        .ok()
+        .and_then(|sk| sign::SecretKey::from_slice(&sk))
+}
+
+// 私钥后半部分即公钥
+fn pk_of(sk: &str) -> Option<String> {
+    decode_sk(sk).map(|sk| base64::encode(&sk.0[(sign::SECRETKEYBYTES / 2)..]))
+}
+
+impl KeyRing {
+    // 到达切换时间后启用新密钥，返回是否发生了切换
+    fn promote(&mut self, now: u64) -> bool {
+        match (self.next.as_ref(), self.activate_at) {
+            (Some(_), Some(at)) if at <= now => {
+                self.previous = self.active.take();
+                self.previous_valid_until = Some(now + PREVIOUS_KEY_GRACE_SECS);
+                self.active = self.next.take();
+                self.activate_at = None;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn accepted_pks(&self, now: u64) -> Vec<String> {
+        let mut pks: Vec<String> = self
+            .active
+            .iter()
+            .chain(self.next.iter())
+            .filter_map(|sk| pk_of(sk))
+            .collect();
+        if self.previous_valid_until.map(|t| t > now).unwrap_or(false) {
+            pks.extend(self.previous.as_deref().and_then(pk_of));
+        }
+        pks
+    }
+
+    fn info(&self) -> KeyRingInfo {
+        KeyRingInfo {
+            active_pk: self.active.as_deref().and_then(pk_of),
+            next_pk: self.next.as_deref().and_then(pk_of),
+            activate_at: self.activate_at,
+            previous_pk: self.previous.as_deref().and_then(pk_of),
+            previous_valid_until: self.previous_valid_until,
+        }
+    }
+}
+
+// 数据库中已有密钥环（发生过轮换）时以数据库为准，否则使用启动参数中的私钥
+pub async fn init(
+    db: &EnterpriseDatabase,
+    sk: Option<&sign::SecretKey>,
+    hardware: Option<Arc<dyn Signer>>,
+) -> ResultType<()> {
+    if let Some(signer) = hardware {
+        *HARDWARE_SIGNER.write().await = Some(signer);
+        return Ok(());
+    }
+    let ring = match db.get_setting(SERVER_KEYS_KEY).await? {
+        Some(v) => {
+            let ring: KeyRing = serde_json::from_str(&v)?;
+            log::info!("Server key ring loaded, active key: {:?}", ring.info().active_pk);
+            ring
+        }
+        None => KeyRing {
+            active: sk.map(|sk| base64::encode(&sk.0[..])),
+            ..Default::default()
+        },
+    };
+    *KEY_RING.write().await = ring;
+    Ok(())
+}
+
+async fn promote_if_due() {
+    let due = {
+        let ring = KEY_RING.read().await;
+        ring.activate_at.map(|t| t <= now()).unwrap_or(false)
+    };
+    if due && KEY_RING.write().await.promote(now()) {
+        log::info!("Server key rotated, new key: {:?}", info().await.active_pk);
+    }
+}
+
+pub async fn signer() -> Option<Arc<dyn Signer>> {
+    if let Some(signer) = HARDWARE_SIGNER.read().await.clone() {
+        return Some(signer);
+    }
+    promote_if_due().await;
+    KEY_RING
+        .read()
+        .await
+        .active
+        .as_deref()
+        .and_then(decode_sk)
+        .map(|sk| Arc::new(SoftwareSigner(sk)) as Arc<dyn Signer>)
+}
+
+pub async fn info() -> KeyRingInfo {
+    if let Some(signer) = HARDWARE_SIGNER.read().await.as_ref() {
+        return KeyRingInfo {
+            active_pk: Some(base64::encode(signer.public_key())),
+            ..Default::default()
+        };
+    }
+    KEY_RING.read().await.info()
+}
+
+// licence_key 与启动参数一致，或为密钥环中仍有效的公钥
+pub async fn accepts_licence(licence_key: &str, key: &str) -> bool {
+    if licence_key == key {
+        return true;
+    }
+    promote_if_due().await;
+    KEY_RING
+        .read()
+        .await
+        .accepted_pks(now())
+        .iter()
+        .any(|pk| pk == licence_key)
+}
+
+pub async fn rotate(db: &EnterpriseDatabase, overlap_secs: u64, updated_by: &str) -> ResultType<KeyRingInfo> {
+    if HARDWARE_SIGNER.read().await.is_some() {
+        bail!("server key is held by a hardware signer, rotate it in the HSM/TPM");
+    }
+    let mut ring = KEY_RING.read().await.clone();
+    if ring.active.is_none() {
+        bail!("server has no signing key");
+    }
+    if ring.next.is_some() {
+        bail!("a key rotation is already pending");
+    }
+    let (_, sk) = sign::gen_keypair();
+    ring.next = Some(base64::encode(&sk.0[..]));
+    ring.activate_at = Some(now() + overlap_secs);
+    ring.rotated_at = Some(now());
+    db.set_setting(SERVER_KEYS_KEY, &serde_json::to_string(&ring)?, Some(updated_by))
+        .await?;
+    let info = ring.info();
+    *KEY_RING.write().await = ring;
+    // 提升配置序号，客户端重新拉取心跳策略获取新公钥
+    password_policy::bump_serial(db, updated_by).await?;
+    log::info!("Server key rotation scheduled by {}: {:?}", updated_by, info);
+    Ok(info)
+}
+
+// 发生过轮换后通过心跳策略下发客户端应使用的公钥；重叠期内即下发新公钥
+pub async fn distribution_options() -> HashMap<String, String> {
+    let info = info().await;
+    let mut options = HashMap::new();
+    if info.next_pk.is_none() && info.previous_pk.is_none() {
+        return options;
+    }
+    if let Some(pk) = info.next_pk.or(info.active_pk) {
+        options.insert("key".to_owned(), pk);
+    }
+    options
+}
+
+// 最近一次发起轮换的时间，用于让心跳策略的 modified_at 变化
+pub async fn modified_at() -> u64 {
+    KEY_RING.read().await.rotated_at.unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_key_ring_rotation() {
+        let (_, old) = sign::gen_keypair();
+        let (_, new) = sign::gen_keypair();
+        let old = base64::encode(&old.0[..]);
+        let new = base64::encode(&new.0[..]);
+        let mut ring = KeyRing {
+            active: Some(old.clone()),
+            next: Some(new.clone()),
+            activate_at: Some(100),
+            ..Default::default()
+        };
+        assert!(!ring.promote(99));
+        assert_eq!(ring.accepted_pks(99).len(), 2);
+        assert!(ring.promote(100));
+        assert_eq!(ring.active, Some(new.clone()));
+        assert_eq!(ring.previous, Some(old.clone()));
+        let accepted = ring.accepted_pks(101);
+        assert!(accepted.contains(&pk_of(&old).unwrap()));
+        let accepted = ring.accepted_pks(100 + PREVIOUS_KEY_GRACE_SECS);
+        assert_eq!(accepted, vec![pk_of(&new).unwrap()]);
+    }
+}
@@ -0,0 +1,150 @@
+// 会话权限控制模块 - 管理员可以在会话进行中关闭(或重新开启)剪贴板和文件传输，而不断开连接。
+// 中继转发的数据由两端端到端加密，中继无法识别其中的剪贴板/文件消息，因此权限变更登记在中继会话上，
+// 经被控端的控制通道(/api/heartbeat)下发，由被控端对该会话生效；会话结束后登记自动清除
+use crate::relay_sessions;
+use crate::validation::Payload;
+use hbb_common::{bail, tokio::sync::RwLock, ResultType};
+use schemars::JsonSchema;
+use serde_derive::{Deserialize, Serialize};
+use std::collections::HashMap;
+use validator::Validate;
+
+lazy_static::lazy_static! {
+    static ref OVERRIDES: RwLock<HashMap<String, SessionPermissions>> = Default::default();
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct SessionPermissions {
+    pub session_id: String,
+    pub device_ids: Vec<String>,
+    pub clipboard: bool,
+    pub file_transfer: bool,
+    pub updated_by: String,
+    pub updated_at: u64,
+}
+
+// 为 None 的项保持不变
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema, Validate)]
+pub struct PermissionChange {
+    #[serde(default)]
+    pub clipboard: Option<bool>,
+    #[serde(default)]
+    pub file_transfer: Option<bool>,
+}
+
+impl Payload for PermissionChange {}
+
+// 通过心跳下发给被控端的会话权限
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct ClientSessionPermissions {
+    pub session_id: String,
+    pub clipboard: bool,
+    pub file_transfer: bool,
+}
+
+impl SessionPermissions {
+    fn apply(&mut self, change: &PermissionChange) {
+        if let Some(clipboard) = change.clipboard {
+            self.clipboard = clipboard;
+        }
+        if let Some(file_transfer) = change.file_transfer {
+            self.file_transfer = file_transfer;
+        }
+    }
+
+    // 全部恢复允许时不再需要登记
+    fn is_default(&self) -> bool {
+        self.clipboard && self.file_transfer
+    }
+}
+
+// 清除已结束会话的登记
+async fn prune(overrides: &mut HashMap<String, SessionPermissions>) {
+    if overrides.is_empty() {
+        return;
+    }
+    let sessions = relay_sessions::list().await;
+    overrides.retain(|id, _| sessions.iter().any(|s| &s.uuid == id));
+}
+
+pub async fn update(session_id: &str, change: &PermissionChange, updated_by: &str) -> ResultType<SessionPermissions> {
+    if change.clipboard.is_none() && change.file_transfer.is_none() {
+        bail!("no permission to change");
+    }
+    let session = match relay_sessions::list().await.into_iter().find(|s| s.uuid == session_id) {
+        Some(session) => session,
+        None => bail!("session not found"),
+    };
+    let mut overrides = OVERRIDES.write().await;
+    prune(&mut overrides).await;
+    let mut permissions = overrides.remove(session_id).unwrap_or_else(|| SessionPermissions {
+        session_id: session_id.to_owned(),
+        device_ids: Vec::new(),
+        clipboard: true,
+        file_transfer: true,
+        updated_by: String::new(),
+        updated_at: 0,
+    });
+    permissions.apply(change);
+    permissions.device_ids = session.device_ids;
+    permissions.updated_by = updated_by.to_owned();
+    permissions.updated_at = crate::common::now();
+    if !permissions.is_default() {
+        overrides.insert(session_id.to_owned(), permissions.clone());
+    }
+    Ok(permissions)
+}
+
+pub async fn get(session_id: &str) -> Option<SessionPermissions> {
+    OVERRIDES.read().await.get(session_id).cloned()
+}
+
+// 设备心跳时取出其会话上的权限限制
+pub async fn for_device(device_id: &str) -> Vec<ClientSessionPermissions> {
+    let mut overrides = OVERRIDES.write().await;
+    prune(&mut overrides).await;
+    overrides
+        .values()
+        .filter(|p| p.device_ids.iter().any(|x| x == device_id))
+        .map(|p| ClientSessionPermissions {
+            session_id: p.session_id.clone(),
+            clipboard: p.clipboard,
+            file_transfer: p.file_transfer,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_apply_permission_change() {
+        let mut permissions = SessionPermissions {
+            session_id: "s1".to_owned(),
+            device_ids: vec!["123".to_owned()],
+            clipboard: true,
+            file_transfer: true,
+            updated_by: String::new(),
+            updated_at: 0,
+        };
+        permissions.apply(&PermissionChange {
+            clipboard: None,
+            file_transfer: Some(false),
+        });
+        assert!(permissions.clipboard);
+        assert!(!permissions.file_transfer);
+        assert!(!permissions.is_default());
+        permissions.apply(&PermissionChange {
+            clipboard: Some(false),
+            file_transfer: Some(true),
+        });
+        assert!(!permissions.clipboard);
+        assert!(permissions.file_transfer);
+        permissions.apply(&PermissionChange {
+            clipboard: Some(true),
+            file_transfer: None,
+        });
+        assert!(permissions.is_default());
+    }
+}
@@ -0,0 +1,120 @@
+// 会话内操作审计模块 - 客户端上报会话中发生的操作(复制文件、使用剪贴板、提升为管理员、屏蔽输入等)，
+// 按会话保存到 session_events 表；中继会话在请求中继时登记到 connection_sessions，事件通过会话ID关联
+use hbb_common::{bail, ResultType};
+use schemars::JsonSchema;
+use serde_derive::{Deserialize, Serialize};
+
+// 单次上报的事件数上限
+pub const MAX_BATCH: usize = 500;
+const MAX_DETAILS_LEN: usize = 4096;
+const MAX_SESSION_ID_LEN: usize = 128;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum SessionEventType {
+    FileCopied,
+    ClipboardUsed,
+    ElevatedToAdmin,
+    InputBlocked,
+    // 新版本客户端上报的未知类型
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct SessionEvent {
+    pub id: i64,
+    pub session_id: String,
+    pub device_id: String,
+    pub event_type: SessionEventType,
+    pub details: Option<String>,
+    pub occurred_at: u64,
+    pub received_at: u64,
+}
+
+// 客户端上报的事件，timestamp 为空时使用服务器接收时间
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+pub struct ClientSessionEvent {
+    pub event_type: SessionEventType,
+    #[serde(default)]
+    pub details: Option<String>,
+    #[serde(default)]
+    pub timestamp: Option<u64>,
+}
+
+// 在字符边界截断
+fn truncate(s: &str, max: usize) -> &str {
+    if s.len() <= max {
+        return s;
+    }
+    let mut end = max;
+    while !s.is_char_boundary(end) {
+        end -= 1;
+    }
+    &s[..end]
+}
+
+// 校验并转换上报的事件；客户端时间晚于服务器时间时按接收时间记录
+pub fn normalize(
+    session_id: &str,
+    device_id: &str,
+    events: Vec<ClientSessionEvent>,
+    now: u64,
+) -> ResultType<Vec<SessionEvent>> {
+    if session_id.is_empty() || session_id.len() > MAX_SESSION_ID_LEN {
+        bail!("invalid session id");
+    }
+    if events.len() > MAX_BATCH {
+        bail!("at most {} events per request", MAX_BATCH);
+    }
+    Ok(events
+        .into_iter()
+        .map(|e| SessionEvent {
+            id: 0,
+            session_id: session_id.to_owned(),
+            device_id: device_id.to_owned(),
+            event_type: e.event_type,
+            details: e
+                .details
+                .filter(|x| !x.is_empty())
+                .map(|x| truncate(&x, MAX_DETAILS_LEN).to_owned()),
+            occurred_at: e.timestamp.filter(|t| *t <= now).unwrap_or(now),
+            received_at: now,
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_session_events() {
+        let events: Vec<ClientSessionEvent> = serde_json::from_str(
+            r#"[
+                {"event_type": "file_copied", "details": "report.xlsx", "timestamp": 90},
+                {"event_type": "elevated_to_admin", "timestamp": 200},
+                {"event_type": "screen_recorded", "details": ""}
+            ]"#,
+        )
+        .unwrap();
+        let res = normalize("s1", "123", events, 100).unwrap();
+        assert_eq!(res.len(), 3);
+        assert_eq!(res[0].event_type, SessionEventType::FileCopied);
+        assert_eq!(res[0].occurred_at, 90);
+        assert_eq!(res[1].occurred_at, 100);
+        assert_eq!(res[2].event_type, SessionEventType::Other);
+        assert!(res[2].details.is_none());
+
+        assert!(normalize("", "123", vec![], 100).is_err());
+        let too_many = (0..=MAX_BATCH)
+            .map(|_| ClientSessionEvent {
+                event_type: SessionEventType::InputBlocked,
+                details: None,
+                timestamp: None,
+            })
+            .collect();
+        assert!(normalize("s1", "123", too_many, 100).is_err());
+        assert_eq!(truncate("中文", 4), "中");
+    }
+}
@@ -0,0 +1,202 @@
+// 签名器模块 - 抽象信令服务器的 Ed25519 签名操作，私钥可保存在 HSM 或 TPM 中
+// （通过 PKCS#11 接口访问，TPM 使用 tpm2-pkcs11 模块），避免私钥以 base64 形式出现在启动参数或环境变量中
+//
+// 启用方式：--signer pkcs11（需以 hsm 特性编译），并设置
+//   PKCS11-MODULE     PKCS#11 动态库路径，如 /usr/lib/softhsm/libsofthsm2.so、/usr/lib/libtpm2_pkcs11.so
+//   PKCS11-TOKEN      令牌标签
+//   PKCS11-KEY-LABEL  密钥对标签（CKA_LABEL）
+//   PKCS11-PIN-FILE   存放用户 PIN 的文件
+use hbb_common::{bail, log, ResultType};
+use sodiumoxide::crypto::sign;
+use std::sync::Arc;
+
+pub trait Signer: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    fn public_key(&self) -> Vec<u8>;
+
+    // 64 字节 Ed25519 分离签名
+    fn sign_detached(&self, msg: &[u8]) -> ResultType<Vec<u8>>;
+
+    // 与 sign::sign 相同的组合格式：签名 || 原文
+    fn sign(&self, msg: &[u8]) -> ResultType<Vec<u8>> {
+        let mut signed = self.sign_detached(msg)?;
+        if signed.len() != sign::SIGNATUREBYTES {
+            bail!("{} signer returned invalid signature length {}", self.name(), signed.len());
+        }
+        signed.extend_from_slice(msg);
+        Ok(signed)
+    }
+}
+
+pub struct SoftwareSigner(pub sign::SecretKey);
+
+impl Signer for SoftwareSigner {
+    fn name(&self) -> &'static str {
+        "software"
+    }
+
+    fn public_key(&self) -> Vec<u8> {
+        self.0 .0[(sign::SECRETKEYBYTES / 2)..].to_vec()
+    }
+
+    fn sign_detached(&self, msg: &[u8]) -> ResultType<Vec<u8>> {
+        Ok(sign::sign_detached(msg, &self.0).to_bytes().to_vec())
+    }
+}
+
+// 根据 --signer 参数创建硬件签名器，未配置时返回 None，继续使用 --key 指定的软件私钥
+pub fn from_args() -> ResultType<Option<Arc<dyn Signer>>> {
+    match crate::common::get_arg("signer").as_str() {
+        "" | "software" => Ok(None),
+        "pkcs11" => {
+            #[cfg(feature = "hsm")]
+            {
+                let signer = pkcs11::Pkcs11Signer::from_args()?;
+                log::info!("Using PKCS#11 signer, public key: {}", base64::encode(signer.public_key()));
+                Ok(Some(Arc::new(signer)))
+            }
+            #[cfg(not(feature = "hsm"))]
+            {
+                bail!("pkcs11 signer requires building with the hsm feature")
+            }
+        }
+        other => bail!("unknown signer: {}", other),
+    }
+}
+
+#[cfg(feature = "hsm")]
+mod pkcs11 {
+    use super::Signer;
+    use crate::common::get_arg;
+    use cryptoki::{
+        context::{CInitializeArgs, Pkcs11},
+        mechanism::Mechanism,
+        object::{Attribute, AttributeType, ObjectClass, ObjectHandle},
+        session::{Session, UserType},
+        types::AuthPin,
+    };
+    use hbb_common::{bail, ResultType};
+    use std::sync::Mutex;
+
+    pub struct Pkcs11Signer {
+        // PKCS#11 会话不能并发使用
+        session: Mutex<Session>,
+        key: ObjectHandle,
+        public_key: Vec<u8>,
+        _context: Pkcs11,
+    }
+
+    impl Pkcs11Signer {
+        pub fn from_args() -> ResultType<Self> {
+            let module = get_arg("pkcs11-module");
+            let token = get_arg("pkcs11-token");
+            let label = get_arg("pkcs11-key-label");
+            if module.is_empty() || label.is_empty() {
+                bail!("PKCS11-MODULE and PKCS11-KEY-LABEL are required");
+            }
+            let pin_file = get_arg("pkcs11-pin-file");
+            let pin = if pin_file.is_empty() {
+                String::new()
+            } else {
+                std::fs::read_to_string(&pin_file)?.trim().to_owned()
+            };
+
+            let context = Pkcs11::new(&module)?;
+            context.initialize(CInitializeArgs::OsThreads)?;
+            let mut slot = None;
+            for s in context.get_slots_with_token()? {
+                if token.is_empty() || context.get_token_info(s)?.label().trim() == token {
+                    slot = Some(s);
+                    break;
+                }
+            }
+            let slot = match slot {
+                Some(slot) => slot,
+                None => bail!("PKCS#11 token {} not found", token),
+            };
+            let session = context.open_ro_session(slot)?;
+            if !pin.is_empty() {
+                session.login(UserType::User, Some(&AuthPin::new(pin)))?;
+            }
+
+            let key = match session
+                .find_objects(&[
+                    Attribute::Class(ObjectClass::PRIVATE_KEY),
+                    Attribute::Label(label.as_bytes().to_vec()),
+                ])?
+                .first()
+            {
+                Some(key) => *key,
+                None => bail!("PKCS#11 private key {} not found", label),
+            };
+            let public = match session
+                .find_objects(&[
+                    Attribute::Class(ObjectClass::PUBLIC_KEY),
+                    Attribute::Label(label.as_bytes().to_vec()),
+                ])?
+                .first()
+            {
+                Some(public) => *public,
+                None => bail!("PKCS#11 public key {} not found", label),
+            };
+            let public_key = match session
+                .get_attributes(public, &[AttributeType::EcPoint])?
+                .into_iter()
+                .next()
+            {
+                Some(Attribute::EcPoint(point)) => decode_ec_point(&point)?,
+                _ => bail!("PKCS#11 public key {} has no EC point", label),
+            };
+
+            Ok(Self {
+                session: Mutex::new(session),
+                key,
+                public_key,
+                _context: context,
+            })
+        }
+    }
+
+    // CKA_EC_POINT 为 DER OCTET STRING 包装的 32 字节公钥，部分实现直接返回原始公钥
+    fn decode_ec_point(point: &[u8]) -> ResultType<Vec<u8>> {
+        match point {
+            [0x04, 0x20, rest @ ..] if rest.len() == 32 => Ok(rest.to_vec()),
+            raw if raw.len() == 32 => Ok(raw.to_vec()),
+            _ => bail!("unsupported EC point encoding"),
+        }
+    }
+
+    impl Signer for Pkcs11Signer {
+        fn name(&self) -> &'static str {
+            "pkcs11"
+        }
+
+        fn public_key(&self) -> Vec<u8> {
+            self.public_key.clone()
+        }
+
+        fn sign_detached(&self, msg: &[u8]) -> ResultType<Vec<u8>> {
+            let session = match self.session.lock() {
+                Ok(session) => session,
+                Err(_) => bail!("PKCS#11 session poisoned"),
+            };
+            Ok(session.sign(&Mechanism::Eddsa, self.key, msg)?)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_software_signer_matches_sodium() {
+        let (pk, sk) = sign::gen_keypair();
+        let signer = SoftwareSigner(sk.clone());
+        assert_eq!(signer.public_key(), pk.0.to_vec());
+        let signed = signer.sign(b"id-pk").unwrap();
+        assert_eq!(signed, sign::sign(b"id-pk", &sk));
+        assert_eq!(sign::verify(&signed, &pk).unwrap(), b"id-pk");
+    }
+}
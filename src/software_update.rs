@@ -0,0 +1,275 @@
+// 软件更新模块 - 管理员通过 Web 界面按平台上传客户端安装包，由存储后端保存在 .updates 目录下
+// (以 . 开头的目录不能通过文件传输访问，避免被普通上传覆盖)，对外提供带 SHA256 的清单和下载；
+// 客户端发送 SoftwareUpdate 时按其系统平台返回对应安装包的地址
+//
+//   UPDATE-BASE-URL  对外下载地址前缀，如 https://rd.example.com:21114，为空时不通告托管的安装包
+use crate::common::get_arg;
+use crate::enterprise_database::EnterpriseDatabase;
+use crate::file_transfer_server;
+use hbb_common::{
+    bail, get_version_number, log,
+    tokio::{fs, sync::RwLock},
+    ResultType,
+};
+use schemars::JsonSchema;
+use serde_derive::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+
+pub const MAX_ARTIFACT_SIZE: usize = 512 * 1024 * 1024;
+const ARTIFACT_DIR: &str = ".updates";
+
+lazy_static::lazy_static! {
+    // 每个平台的最新安装包
+    static ref LATEST: RwLock<HashMap<Platform, UpdateArtifact>> = Default::default();
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Platform {
+    Windows,
+    MacOS,
+    Linux,
+    Android,
+}
+
+impl Platform {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Windows => "windows",
+            Self::MacOS => "macos",
+            Self::Linux => "linux",
+            Self::Android => "android",
+        }
+    }
+
+    // 根据客户端上报的系统描述判断平台
+    pub fn from_os(os: &str) -> Option<Self> {
+        let os = os.to_lowercase();
+        if os.contains("windows") {
+            Some(Self::Windows)
+        } else if os.contains("mac") || os.contains("darwin") {
+            Some(Self::MacOS)
+        } else if os.contains("android") {
+            Some(Self::Android)
+        } else if ["linux", "ubuntu", "debian", "fedora", "centos", "red hat", "suse", "arch"]
+            .iter()
+            .any(|x| os.contains(x))
+        {
+            Some(Self::Linux)
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct UpdateArtifact {
+    pub platform: Platform,
+    pub version: String,
+    pub file_name: String,
+    pub size: u64,
+    pub sha256: String,
+    pub uploaded_by: String,
+    pub uploaded_at: u64,
+}
+
+// 清单中的条目，url 为空表示未配置 UPDATE-BASE-URL
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct ManifestEntry {
+    pub platform: Platform,
+    pub version: String,
+    pub file_name: String,
+    pub size: u64,
+    pub sha256: String,
+    pub url: String,
+}
+
+impl UpdateArtifact {
+    pub fn storage_key(&self) -> String {
+        format!("{}/{}/{}/{}", ARTIFACT_DIR, self.platform.as_str(), self.version, self.file_name)
+    }
+
+    pub fn download_path(&self) -> String {
+        format!("/api/updates/{}/{}/download", self.platform.as_str(), self.version)
+    }
+
+    pub fn download_url(&self) -> Option<String> {
+        let base = get_arg("update-base-url");
+        if base.is_empty() {
+            return None;
+        }
+        Some(format!("{}{}", base.trim_end_matches('/'), self.download_path()))
+    }
+
+    pub fn manifest_entry(&self) -> ManifestEntry {
+        ManifestEntry {
+            platform: self.platform,
+            version: self.version.clone(),
+            file_name: self.file_name.clone(),
+            size: self.size,
+            sha256: self.sha256.clone(),
+            url: self.download_url().unwrap_or_default(),
+        }
+    }
+}
+
+pub fn validate(version: &str, file_name: &str) -> ResultType<()> {
+    if get_version_number(version) <= 0 || !version.chars().all(|c| c.is_ascii_digit() || c == '.') {
+        bail!("invalid version: {}", version);
+    }
+    if file_name.is_empty()
+        || file_name.len() > 128
+        || file_name.starts_with('.')
+        || !file_name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_'))
+    {
+        bail!("invalid file name: {}", file_name);
+    }
+    Ok(())
+}
+
+pub async fn reload(db: &EnterpriseDatabase) -> ResultType<()> {
+    let mut latest: HashMap<Platform, UpdateArtifact> = HashMap::new();
+    for artifact in db.list_update_artifacts().await? {
+        let newer = match latest.get(&artifact.platform) {
+            Some(x) => get_version_number(&artifact.version) > get_version_number(&x.version),
+            None => true,
+        };
+        if newer {
+            latest.insert(artifact.platform, artifact);
+        }
+    }
+    log::info!("software update artifacts loaded: {} platforms", latest.len());
+    *LATEST.write().await = latest;
+    Ok(())
+}
+
+pub async fn latest(platform: Platform) -> Option<UpdateArtifact> {
+    LATEST.read().await.get(&platform).cloned()
+}
+
+pub async fn manifest() -> Vec<ManifestEntry> {
+    let mut entries: Vec<_> = LATEST.read().await.values().map(|x| x.manifest_entry()).collect();
+    entries.sort_by_key(|x| x.platform.as_str());
+    entries
+}
+
+// 保存安装包，同一平台同一版本重复上传时覆盖
+pub async fn store(
+    db: &EnterpriseDatabase,
+    platform: Platform,
+    version: &str,
+    file_name: &str,
+    data: &[u8],
+    uploaded_by: &str,
+) -> ResultType<UpdateArtifact> {
+    validate(version, file_name)?;
+    if data.is_empty() || data.len() > MAX_ARTIFACT_SIZE {
+        bail!("artifact size must be between 1 and {} bytes", MAX_ARTIFACT_SIZE);
+    }
+    let artifact = UpdateArtifact {
+        platform,
+        version: version.to_owned(),
+        file_name: file_name.to_owned(),
+        size: data.len() as u64,
+        sha256: format!("{:x}", Sha256::digest(data)),
+        uploaded_by: uploaded_by.to_owned(),
+        uploaded_at: crate::common::now(),
+    };
+    let staged_dir = file_transfer_server::storage_root().join(".partial");
+    fs::create_dir_all(&staged_dir).await?;
+    let staged = staged_dir.join(uuid::Uuid::new_v4().to_string());
+    fs::write(&staged, data).await?;
+    let storage = file_transfer_server::manager().storage().await;
+    if let Err(err) = storage.store_file(&staged, &artifact.storage_key()).await {
+        fs::remove_file(&staged).await.ok();
+        return Err(err);
+    }
+    // 同一版本换了文件名时删除旧文件
+    if let Some(old) = db.get_update_artifact(platform, version).await? {
+        if old.storage_key() != artifact.storage_key() {
+            storage.delete(&old.storage_key()).await.ok();
+        }
+    }
+    db.save_update_artifact(&artifact).await?;
+    reload(db).await?;
+    log::info!(
+        "Stored update artifact {:?} {} ({} bytes, sha256 {})",
+        platform,
+        version,
+        artifact.size,
+        artifact.sha256
+    );
+    Ok(artifact)
+}
+
+pub async fn remove(db: &EnterpriseDatabase, platform: Platform, version: &str) -> ResultType<bool> {
+    let artifact = match db.get_update_artifact(platform, version).await? {
+        Some(artifact) => artifact,
+        None => return Ok(false),
+    };
+    let storage = file_transfer_server::manager().storage().await;
+    if let Err(err) = storage.delete(&artifact.storage_key()).await {
+        log::warn!("Failed to delete update artifact {}: {}", artifact.storage_key(), err);
+    }
+    db.delete_update_artifact(platform, version).await?;
+    reload(db).await?;
+    Ok(true)
+}
+
+// SoftwareUpdate 不带设备ID，按来源 IP 上登记的设备判断平台；同一出口下平台不一致时无法判断
+pub async fn hosted_for_ip(db: &EnterpriseDatabase, ip: &str) -> Option<(String, String)> {
+    let os_list = match db.list_device_os_by_ip(ip).await {
+        Ok(os_list) => os_list,
+        Err(err) => {
+            log::error!("Failed to load devices of {}: {}", ip, err);
+            return None;
+        }
+    };
+    let mut platforms = os_list.iter().filter_map(|os| Platform::from_os(os));
+    let platform = platforms.next()?;
+    if platforms.any(|p| p != platform) {
+        return None;
+    }
+    hosted_for(platform).await
+}
+
+// 平台的最新托管版本及下载地址
+pub async fn hosted_for(platform: Platform) -> Option<(String, String)> {
+    let artifact = latest(platform).await?;
+    let url = artifact.download_url()?;
+    Some((artifact.version, url))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_platform_and_validation() {
+        assert_eq!(Platform::from_os("Windows 10 Pro (19045)"), Some(Platform::Windows));
+        assert_eq!(Platform::from_os("macOS 14.2"), Some(Platform::MacOS));
+        assert_eq!(Platform::from_os("Android 13"), Some(Platform::Android));
+        assert_eq!(Platform::from_os("Ubuntu 22.04"), Some(Platform::Linux));
+        assert_eq!(Platform::from_os(""), None);
+
+        assert!(validate("1.2.3", "rustdesk-1.2.3-x86_64.exe").is_ok());
+        assert!(validate("latest", "rustdesk.exe").is_err());
+        assert!(validate("1.2.3", "../rustdesk.exe").is_err());
+        assert!(validate("1.2.3", ".hidden").is_err());
+
+        let artifact = UpdateArtifact {
+            platform: Platform::MacOS,
+            version: "1.2.3".to_owned(),
+            file_name: "RustDesk.dmg".to_owned(),
+            size: 1,
+            sha256: String::new(),
+            uploaded_by: "admin".to_owned(),
+            uploaded_at: 0,
+        };
+        assert_eq!(artifact.storage_key(), ".updates/macos/1.2.3/RustDesk.dmg");
+        assert_eq!(artifact.download_path(), "/api/updates/macos/1.2.3/download");
+    }
+}
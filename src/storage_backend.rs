@@ -0,0 +1,316 @@
+// 存储后端模块 - 文件传输完成并通过哈希校验后，由存储后端保存最终文件；
+// 默认保存在本地 FILE-TRANSFER-DIR 下，配置 --storage-backend s3 时分段上传到 S3/MinIO，
+// 本地只保留传输中的暂存文件，避免大文件和会话录像占满服务器系统盘
+//
+// S3 配置（需以 s3 特性编译）：
+//   S3-ENDPOINT       如 https://s3.amazonaws.com、http://minio:9000
+//   S3-REGION         默认 us-east-1
+//   S3-BUCKET         存储桶
+//   S3-ACCESS-KEY / S3-SECRET-KEY
+//   S3-PREFIX         对象键前缀，可为空
+//   S3-SSE            服务端加密：AES256 或 aws:kms，为空不加密
+//   S3-SSE-KMS-KEY-ID 使用 aws:kms 时的 KMS 密钥
+use async_trait::async_trait;
+use hbb_common::{bail, ResultType};
+use std::{
+    fs::File,
+    io::{Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+// S3 分段上传除最后一段外每段至少 5MB
+pub const MULTIPART_PART_SIZE: usize = 8 * 1024 * 1024;
+
+#[async_trait]
+pub trait StorageBackend: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    // 本地存储返回文件路径，下载时直接打开文件读取
+    fn local_path(&self, _key: &str) -> Option<PathBuf> {
+        None
+    }
+
+    // 保存暂存文件为 key，成功后暂存文件被移除
+    async fn store_file(&self, staged: &Path, key: &str) -> ResultType<()>;
+
+    // 对象不存在时返回 None
+    async fn size(&self, key: &str) -> ResultType<Option<u64>>;
+
+    async fn read_range(&self, key: &str, offset: u64, len: usize) -> ResultType<Vec<u8>>;
+
+    async fn delete(&self, key: &str) -> ResultType<()>;
+}
+
+pub struct LocalStorage {
+    root: PathBuf,
+}
+
+impl LocalStorage {
+    // root 为空时 key 即文件路径
+    pub fn new(root: PathBuf) -> Self {
+        Self { root }
+    }
+}
+
+#[async_trait]
+impl StorageBackend for LocalStorage {
+    fn name(&self) -> &'static str {
+        "local"
+    }
+
+    fn local_path(&self, key: &str) -> Option<PathBuf> {
+        Some(self.root.join(key))
+    }
+
+    async fn store_file(&self, staged: &Path, key: &str) -> ResultType<()> {
+        let target = self.root.join(key);
+        if let Some(parent) = target.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        // 暂存目录与目标不在同一文件系统时 rename 失败，改为复制
+        if std::fs::rename(staged, &target).is_err() {
+            std::fs::copy(staged, &target)?;
+            std::fs::remove_file(staged)?;
+        }
+        Ok(())
+    }
+
+    async fn size(&self, key: &str) -> ResultType<Option<u64>> {
+        match std::fs::metadata(self.root.join(key)) {
+            Ok(meta) if meta.is_file() => Ok(Some(meta.len())),
+            Ok(_) => Ok(None),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    async fn read_range(&self, key: &str, offset: u64, len: usize) -> ResultType<Vec<u8>> {
+        let mut file = File::open(self.root.join(key))?;
+        file.seek(SeekFrom::Start(offset))?;
+        let mut data = vec![0u8; len];
+        file.read_exact(&mut data)?;
+        Ok(data)
+    }
+
+    async fn delete(&self, key: &str) -> ResultType<()> {
+        std::fs::remove_file(self.root.join(key))?;
+        Ok(())
+    }
+}
+
+// 按 --storage-backend 参数创建存储后端，本地存储以 root 为根目录
+pub fn from_args(root: PathBuf) -> ResultType<Arc<dyn StorageBackend>> {
+    match crate::common::get_arg("storage-backend").as_str() {
+        "" | "local" => Ok(Arc::new(LocalStorage::new(root))),
+        "s3" => {
+            #[cfg(feature = "s3")]
+            {
+                let storage = s3_storage::S3Storage::from_args()?;
+                hbb_common::log::info!("Using S3 storage backend, bucket: {}", storage.bucket_name());
+                Ok(Arc::new(storage))
+            }
+            #[cfg(not(feature = "s3"))]
+            {
+                bail!("s3 storage backend requires building with the s3 feature")
+            }
+        }
+        other => bail!("unknown storage backend: {}", other),
+    }
+}
+
+// 将文件长度切分为分段上传的 (偏移, 长度)
+fn part_ranges(len: u64, part_size: usize) -> Vec<(u64, usize)> {
+    let mut ranges = vec![];
+    let mut offset = 0;
+    while offset < len {
+        let size = (len - offset).min(part_size as u64) as usize;
+        ranges.push((offset, size));
+        offset += size as u64;
+    }
+    ranges
+}
+
+#[cfg(feature = "s3")]
+mod s3_storage {
+    use super::{part_ranges, StorageBackend, MULTIPART_PART_SIZE};
+    use crate::common::get_arg;
+    use async_trait::async_trait;
+    use hbb_common::{bail, log, ResultType};
+    use s3::{bucket::Bucket, creds::Credentials, region::Region, serde_types::Part};
+    use std::{
+        fs::File,
+        io::{Read, Seek, SeekFrom},
+        path::Path,
+    };
+
+    const CONTENT_TYPE: &str = "application/octet-stream";
+
+    pub struct S3Storage {
+        bucket: Bucket,
+        // 服务端加密头只能用于 PutObject / CreateMultipartUpload，读取和上传分段使用不带加密头的 bucket
+        write_bucket: Bucket,
+        prefix: String,
+    }
+
+    impl S3Storage {
+        pub fn from_args() -> ResultType<Self> {
+            let endpoint = get_arg("s3-endpoint");
+            let name = get_arg("s3-bucket");
+            if endpoint.is_empty() || name.is_empty() {
+                bail!("S3-ENDPOINT and S3-BUCKET are required");
+            }
+            let region = match get_arg("s3-region") {
+                r if r.is_empty() => "us-east-1".to_owned(),
+                r => r,
+            };
+            let credentials = Credentials::new(
+                Some(&get_arg("s3-access-key")),
+                Some(&get_arg("s3-secret-key")),
+                None,
+                None,
+                None,
+            )?;
+            // MinIO 等自建服务通常不支持虚拟主机风格
+            let bucket = Bucket::new(&name, Region::Custom { region, endpoint }, credentials)?.with_path_style();
+            let mut write_bucket = bucket.clone();
+            match get_arg("s3-sse").as_str() {
+                "" => {}
+                "AES256" => write_bucket.add_header("x-amz-server-side-encryption", "AES256"),
+                "aws:kms" => {
+                    write_bucket.add_header("x-amz-server-side-encryption", "aws:kms");
+                    let key_id = get_arg("s3-sse-kms-key-id");
+                    if !key_id.is_empty() {
+                        write_bucket.add_header("x-amz-server-side-encryption-aws-kms-key-id", &key_id);
+                    }
+                }
+                other => bail!("unsupported S3-SSE: {}", other),
+            }
+            Ok(Self {
+                bucket,
+                write_bucket,
+                prefix: get_arg("s3-prefix").trim_matches('/').to_owned(),
+            })
+        }
+
+        pub fn bucket_name(&self) -> String {
+            self.bucket.name()
+        }
+
+        fn object_key(&self, key: &str) -> String {
+            if self.prefix.is_empty() {
+                format!("/{}", key)
+            } else {
+                format!("/{}/{}", self.prefix, key)
+            }
+        }
+
+        async fn upload_parts(&self, file: &mut File, len: u64, object: &str, upload_id: &str) -> ResultType<Vec<Part>> {
+            let mut parts = vec![];
+            for (i, (offset, size)) in part_ranges(len, MULTIPART_PART_SIZE).into_iter().enumerate() {
+                let mut data = vec![0u8; size];
+                file.seek(SeekFrom::Start(offset))?;
+                file.read_exact(&mut data)?;
+                let part = self
+                    .bucket
+                    .put_multipart_chunk(data, object, i as u32 + 1, upload_id, CONTENT_TYPE)
+                    .await?;
+                parts.push(part);
+            }
+            Ok(parts)
+        }
+    }
+
+    #[async_trait]
+    impl StorageBackend for S3Storage {
+        fn name(&self) -> &'static str {
+            "s3"
+        }
+
+        async fn store_file(&self, staged: &Path, key: &str) -> ResultType<()> {
+            let object = self.object_key(key);
+            let mut file = File::open(staged)?;
+            let len = file.metadata()?.len();
+            if len <= MULTIPART_PART_SIZE as u64 {
+                let mut data = Vec::with_capacity(len as usize);
+                file.read_to_end(&mut data)?;
+                let response = self.write_bucket.put_object(&object, &data).await?;
+                if response.status_code() >= 300 {
+                    bail!("put object {} failed with status {}", object, response.status_code());
+                }
+            } else {
+                let upload = self.write_bucket.initiate_multipart_upload(&object, CONTENT_TYPE).await?;
+                match self.upload_parts(&mut file, len, &object, &upload.upload_id).await {
+                    Ok(parts) => {
+                        self.bucket
+                            .complete_multipart_upload(&object, &upload.upload_id, parts)
+                            .await?;
+                    }
+                    Err(err) => {
+                        if let Err(abort_err) = self.bucket.abort_upload(&object, &upload.upload_id).await {
+                            log::warn!("Failed to abort multipart upload of {}: {}", object, abort_err);
+                        }
+                        return Err(err);
+                    }
+                }
+            }
+            drop(file);
+            std::fs::remove_file(staged)?;
+            log::info!("Stored {} ({} bytes) to S3", object, len);
+            Ok(())
+        }
+
+        async fn size(&self, key: &str) -> ResultType<Option<u64>> {
+            match self.bucket.head_object(self.object_key(key)).await {
+                Ok((head, 200)) => Ok(head.content_length.map(|len| len as u64)),
+                Ok((_, 404)) => Ok(None),
+                Ok((_, code)) => bail!("head object failed with status {}", code),
+                Err(err) => Err(err.into()),
+            }
+        }
+
+        async fn read_range(&self, key: &str, offset: u64, len: usize) -> ResultType<Vec<u8>> {
+            if len == 0 {
+                return Ok(vec![]);
+            }
+            let response = self
+                .bucket
+                .get_object_range(self.object_key(key), offset, Some(offset + len as u64 - 1))
+                .await?;
+            if response.status_code() >= 300 {
+                bail!("get object range failed with status {}", response.status_code());
+            }
+            Ok(response.bytes().to_vec())
+        }
+
+        async fn delete(&self, key: &str) -> ResultType<()> {
+            self.bucket.delete_object(self.object_key(key)).await?;
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_part_ranges() {
+        assert!(part_ranges(0, 10).is_empty());
+        assert_eq!(part_ranges(25, 10), vec![(0, 10), (10, 10), (20, 5)]);
+        assert_eq!(part_ranges(20, 10), vec![(0, 10), (10, 10)]);
+    }
+
+    #[tokio::test]
+    async fn test_local_storage() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let staged = dir.path().join("staged.tmp");
+        std::fs::write(&staged, b"hello world").unwrap();
+        let storage = LocalStorage::new(dir.path().join("files"));
+        storage.store_file(&staged, "a/b.txt").await.unwrap();
+        assert!(!staged.exists());
+        assert_eq!(storage.size("a/b.txt").await.unwrap(), Some(11));
+        assert_eq!(storage.read_range("a/b.txt", 6, 5).await.unwrap(), b"world");
+        assert_eq!(storage.size("missing").await.unwrap(), None);
+    }
+}
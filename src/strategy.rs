@@ -0,0 +1,112 @@
+// 策略模块 - 可分配给设备组/设备的客户端配置策略（画质默认值、功能开关、安全选项），
+// 通过与 Pro 版兼容的 /api/heartbeat 下发给客户端
+use crate::enterprise_database::EnterpriseDatabase;
+use crate::password_policy;
+use crate::server_key;
+use hbb_common::{bail, log, ResultType};
+use schemars::JsonSchema;
+use serde_derive::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct Strategy {
+    pub id: String,
+    pub name: String,
+    pub description: Option<String>,
+    // 客户端配置项，如 image-quality、enable-file-transfer、enable-clipboard
+    pub config_options: HashMap<String, String>,
+    pub group_ids: Vec<String>,
+    pub device_ids: Vec<String>,
+    pub enabled: bool,
+    pub updated_at: u64,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct EffectiveStrategy {
+    pub strategy_id: Option<String>,
+    pub config_options: HashMap<String, String>,
+    pub modified_at: u64,
+}
+
+// 客户端选项值只允许 Y/N 或短文本，避免下发任意大对象
+pub fn validate(strategy: &Strategy) -> ResultType<()> {
+    if strategy.name.trim().is_empty() {
+        bail!("strategy name is empty");
+    }
+    for (k, v) in strategy.config_options.iter() {
+        if k.is_empty() || k.len() > 64 || v.len() > 1024 {
+            bail!("invalid config option: {}", k);
+        }
+    }
+    Ok(())
+}
+
+// 设备直接分配的策略优先，其次按设备所属组匹配；多个组命中时取最近更新的策略
+pub fn select<'a>(strategies: &'a [Strategy], device_id: &str, group_ids: &[String]) -> Option<&'a Strategy> {
+    let enabled = strategies.iter().filter(|s| s.enabled);
+    if let Some(s) = enabled
+        .clone()
+        .filter(|s| s.device_ids.iter().any(|x| x == device_id))
+        .max_by_key(|s| s.updated_at)
+    {
+        return Some(s);
+    }
+    enabled
+        .filter(|s| s.group_ids.iter().any(|g| group_ids.contains(g)))
+        .max_by_key(|s| s.updated_at)
+}
+
+// 计算设备最终下发的配置：策略选项 + 密码策略选项 + 服务器公钥
+pub async fn effective(db: &EnterpriseDatabase, device_id: &str) -> ResultType<EffectiveStrategy> {
+    let strategies = db.list_strategies().await?;
+    let group_ids = db.get_device_group_ids(device_id).await?;
+    let mut res = EffectiveStrategy::default();
+    if let Some(s) = select(&strategies, device_id, &group_ids) {
+        res.strategy_id = Some(s.id.clone());
+        res.config_options = s.config_options.clone();
+        res.modified_at = s.updated_at;
+    }
+    if let Some(policy) = password_policy::effective_policy(db, device_id).await {
+        res.config_options.extend(policy.to_options());
+    }
+    // 服务器密钥轮换期间下发新公钥
+    res.config_options.extend(server_key::distribution_options().await);
+    // 密码策略变更同样需要让客户端感知
+    res.modified_at = res
+        .modified_at
+        .max(password_policy::modified_at())
+        .max(server_key::modified_at().await);
+    log::debug!("effective strategy of {}: {:?}", device_id, res.strategy_id);
+    Ok(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strategy(id: &str, groups: &[&str], devices: &[&str], updated_at: u64) -> Strategy {
+        Strategy {
+            id: id.to_owned(),
+            name: id.to_owned(),
+            description: None,
+            config_options: HashMap::new(),
+            group_ids: groups.iter().map(|x| x.to_string()).collect(),
+            device_ids: devices.iter().map(|x| x.to_string()).collect(),
+            enabled: true,
+            updated_at,
+        }
+    }
+
+    #[test]
+    fn test_select_strategy() {
+        let strategies = vec![
+            strategy("finance", &["g1"], &[], 1),
+            strategy("kiosk", &[], &["100"], 1),
+            strategy("finance-v2", &["g1"], &[], 2),
+        ];
+        let groups = vec!["g1".to_owned()];
+        assert_eq!(select(&strategies, "100", &groups).unwrap().id, "kiosk");
+        assert_eq!(select(&strategies, "200", &groups).unwrap().id, "finance-v2");
+        assert!(select(&strategies, "200", &[]).is_none());
+    }
+}
@@ -0,0 +1,284 @@
+// 集成测试夹具 - 每个测试使用独立的内存数据库(EnterpriseDatabase::memory)，不读写任何文件：
+//   - UserBuilder / GroupBuilder / DeviceBuilder 登记用户、设备组和设备，未指定的字段取随机或默认值
+//   - router() 返回完整的 Web 路由，可用 tower::ServiceExt::oneshot 直接发请求
+//   - TestServer 在 127.0.0.1 的临时端口上启动 Web 接口，可用 hbbs-enterprise-client 或任意 HTTP 客户端访问，
+//     析构时停止
+// 接口行为测试见 web_api_tests.rs
+use super::*;
+use crate::enterprise_database::DeviceInfo;
+use hbb_common::tokio;
+use hbbs_enterprise_client as client;
+
+pub const JWT_SECRET: &str = "fixture-secret-fixture-secret-fixture";
+pub const PASSWORD: &str = "Fixture-Passw0rd!";
+
+pub async fn state() -> AppState {
+    let db = EnterpriseDatabase::memory().await.unwrap();
+    let auth = Arc::new(AuthManager::new(JWT_SECRET.to_owned()));
+    AppState { db, auth }
+}
+
+pub async fn router() -> (Router, AppState) {
+    let state = state().await;
+    (create_router(state.clone()), state)
+}
+
+pub fn random_suffix() -> String {
+    uuid::Uuid::new_v4().simple().to_string()[..8].to_owned()
+}
+
+pub struct UserBuilder {
+    user: User,
+    password: String,
+    locked: bool,
+}
+
+impl UserBuilder {
+    pub fn new(role: UserRole) -> Self {
+        Self {
+            user: User {
+                id: uuid::Uuid::new_v4().to_string(),
+                username: format!("{:?}-{}", role, random_suffix()).to_lowercase(),
+                password_hash: String::new(),
+                email: None,
+                role,
+                groups: vec![],
+                enabled: true,
+                created_at: SystemTime::now(),
+                last_login: None,
+                failed_login_attempts: 0,
+                locked_until: None,
+                two_factor_enabled: false,
+                two_factor_secret: None,
+            },
+            password: PASSWORD.to_owned(),
+            locked: false,
+        }
+    }
+
+    pub fn username(mut self, username: &str) -> Self {
+        self.user.username = username.to_owned();
+        self
+    }
+
+    pub fn email(mut self, email: &str) -> Self {
+        self.user.email = Some(email.to_owned());
+        self
+    }
+
+    pub fn password(mut self, password: &str) -> Self {
+        self.password = password.to_owned();
+        self
+    }
+
+    pub fn group(mut self, group_id: &str) -> Self {
+        self.user.groups.push(group_id.to_owned());
+        self
+    }
+
+    pub fn disabled(mut self) -> Self {
+        self.user.enabled = false;
+        self
+    }
+
+    // 登记后锁定一小时，等同于连续登录失败达到上限
+    pub fn locked(mut self) -> Self {
+        self.locked = true;
+        self
+    }
+
+    // 不登记到数据库的用户，用于直接签发令牌
+    pub fn build(self) -> User {
+        self.user
+    }
+
+    pub async fn create(mut self, state: &AppState) -> User {
+        self.user.password_hash = state.auth.hash_password(&self.password).unwrap();
+        assert!(state.db.create_user(&self.user).await.unwrap().is_none());
+        if self.locked {
+            let until = SystemTime::now() + std::time::Duration::from_secs(3600);
+            state.db.lock_user(&self.user.id, until).await.unwrap();
+            self.user.locked_until = Some(until);
+        }
+        self.user
+    }
+}
+
+// 签发令牌并登记 Web 会话，与登录接口返回的一致
+pub async fn login_token(state: &AppState, user: &User) -> String {
+    issue_session_token(state, user, &HeaderMap::new()).await.unwrap()
+}
+
+// 经完整路由发送 JSON 请求，返回状态码和响应体(非 JSON 时为 Null)
+pub async fn request(
+    state: &AppState,
+    method: &str,
+    uri: &str,
+    token: Option<&str>,
+    body: Option<serde_json::Value>,
+) -> (StatusCode, serde_json::Value) {
+    let mut headers = HeaderMap::new();
+    headers.insert(header::CONTENT_TYPE, header::HeaderValue::from_static("application/json"));
+    let body = body.map(|x| x.to_string()).unwrap_or_default();
+    let (status, _, body) = raw_request(state, method, uri, token, headers, body.into_bytes()).await;
+    (status, serde_json::from_slice(&body).unwrap_or_default())
+}
+
+// 经完整路由发送任意请求，返回状态码、响应头和原始响应体
+pub async fn raw_request(
+    state: &AppState,
+    method: &str,
+    uri: &str,
+    token: Option<&str>,
+    headers: HeaderMap,
+    body: Vec<u8>,
+) -> (StatusCode, HeaderMap, Bytes) {
+    let mut req = axum::http::Request::builder().method(method).uri(uri);
+    if let Some(token) = token {
+        req = req.header("Authorization", format!("Bearer {}", token));
+    }
+    let mut req = req.body(axum::body::Body::from(body)).unwrap();
+    req.headers_mut().extend(headers);
+    let res = tower::ServiceExt::oneshot(create_router(state.clone()), req).await.unwrap();
+    let status = res.status();
+    let headers = res.headers().clone();
+    let body = axum::body::to_bytes(res.into_body(), usize::MAX).await.unwrap();
+    (status, headers, body)
+}
+
+pub struct GroupBuilder {
+    id: String,
+    name: String,
+    description: String,
+}
+
+impl GroupBuilder {
+    pub fn new(id: &str) -> Self {
+        Self {
+            id: id.to_owned(),
+            name: id.to_owned(),
+            description: String::new(),
+        }
+    }
+
+    pub fn name(mut self, name: &str) -> Self {
+        self.name = name.to_owned();
+        self
+    }
+
+    pub fn description(mut self, description: &str) -> Self {
+        self.description = description.to_owned();
+        self
+    }
+
+    // 返回设备组 id
+    pub async fn create(self, state: &AppState) -> String {
+        state
+            .db
+            .upsert_ad_device_group(&self.id, &self.name, &self.description)
+            .await
+            .unwrap();
+        self.id
+    }
+}
+
+pub struct DeviceBuilder {
+    device: DeviceInfo,
+}
+
+impl DeviceBuilder {
+    pub fn new(id: &str) -> Self {
+        Self {
+            device: DeviceInfo {
+                id: id.to_owned(),
+                name: id.to_owned(),
+                os: "Linux".to_owned(),
+                version: "1.2.3".to_owned(),
+                ip_address: "192.0.2.10".to_owned(),
+                ipv6_address: None,
+                mac_address: None,
+                last_online: SystemTime::now(),
+                owner_id: "system".to_owned(),
+                group_ids: vec![],
+                enabled: true,
+                tags: vec![],
+                alias: None,
+            },
+        }
+    }
+
+    pub fn name(mut self, name: &str) -> Self {
+        self.device.name = name.to_owned();
+        self
+    }
+
+    pub fn os(mut self, os: &str) -> Self {
+        self.device.os = os.to_owned();
+        self
+    }
+
+    pub fn group(mut self, group_id: &str) -> Self {
+        self.device.group_ids.push(group_id.to_owned());
+        self
+    }
+
+    pub fn owner(mut self, owner_id: &str) -> Self {
+        self.device.owner_id = owner_id.to_owned();
+        self
+    }
+
+    pub fn tag(mut self, tag: &str) -> Self {
+        self.device.tags.push(tag.to_owned());
+        self
+    }
+
+    pub fn last_online(mut self, last_online: SystemTime) -> Self {
+        self.device.last_online = last_online;
+        self
+    }
+
+    // 不登记到数据库的设备，由测试自行写入
+    pub fn build(self) -> DeviceInfo {
+        self.device
+    }
+
+    pub async fn create(self, state: &AppState) -> DeviceInfo {
+        state.db.register_device(&self.device).await.unwrap();
+        self.device
+    }
+}
+
+pub struct TestServer {
+    pub addr: SocketAddr,
+    pub state: AppState,
+    task: tokio::task::JoinHandle<()>,
+}
+
+impl TestServer {
+    pub async fn start() -> Self {
+        let state = state().await;
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let app = create_router(state.clone());
+        let task = tokio::spawn(async move {
+            axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>())
+                .await
+                .ok();
+        });
+        Self { addr, state, task }
+    }
+
+    pub fn url(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    pub fn client(&self) -> client::Client {
+        client::Client::new(&self.url())
+    }
+}
+
+impl Drop for TestServer {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
@@ -0,0 +1,408 @@
+// 威胁情报IP源模块 - 按 /api/settings/threat-intel 配置的间隔下载外部IP黑名单(如 Spamhaus DROP、
+// abuse.ch Feodo Tracker 或自建列表)，列表为纯文本，每行一个IP或CIDR，";" "#" 之后为注释。
+// 命中列表的来源地址:
+//   - 设备注册(RegisterPeer/RegisterPk)被拒绝
+//   - Web 登录被拒绝，来源地址取连接对端，对端为 TRUSTED_PROXIES 中的反向代理时取其设置的 X-Real-IP / X-Forwarded-For
+// 每个列表的命中次数经 /metrics 和 /api/threat-intel/status 提供。下载失败时继续使用上一次的列表；
+// 列表只保存在内存中，服务启动后第一次定时检查即重新下载
+use crate::enterprise_database::EnterpriseDatabase;
+use crate::validation::{self, Payload};
+use hbb_common::{bail, log, tokio::sync::RwLock, ResultType};
+use ipnetwork::IpNetwork;
+use schemars::JsonSchema;
+use serde_derive::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, HashSet},
+    net::IpAddr,
+    time::Duration,
+};
+use validator::Validate;
+
+pub const THREAT_INTEL_KEY: &str = "threat_intel";
+const MAX_FEEDS: usize = 20;
+const MAX_FEED_BYTES: usize = 16 * 1024 * 1024;
+const FETCH_TIMEOUT: Duration = Duration::from_secs(60);
+
+lazy_static::lazy_static! {
+    static ref CONFIG: RwLock<ThreatIntelConfig> = Default::default();
+    static ref EXEMPT: RwLock<Vec<IpNetwork>> = Default::default();
+    // 列表名 -> 已下载的列表
+    static ref FEEDS: RwLock<HashMap<String, LoadedFeed>> = Default::default();
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct ThreatFeed {
+    pub name: String,
+    // 如 https://www.spamhaus.org/drop/drop.txt
+    pub url: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema, Validate)]
+pub struct ThreatIntelConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default)]
+    #[validate(length(max = 50))]
+    pub feeds: Vec<ThreatFeed>,
+    #[serde(default = "default_refresh_minutes")]
+    #[validate(range(min = 15, max = 10080))]
+    pub refresh_minutes: u32,
+    // 不受列表影响的地址段(如公司出口地址被误列入时)
+    #[serde(default)]
+    #[validate(custom = "validation::non_empty_items")]
+    pub exempt: Vec<String>,
+}
+
+impl Payload for ThreatIntelConfig {}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Check {
+    Registration,
+    Login,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct FeedStatus {
+    pub name: String,
+    pub url: String,
+    pub entries: usize,
+    // 最近一次成功下载的时间
+    pub updated_at: Option<u64>,
+    pub checked_at: Option<u64>,
+    pub error: Option<String>,
+    pub rejected_registrations: u64,
+    pub rejected_logins: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct ThreatIntelStatus {
+    pub enabled: bool,
+    pub feeds: Vec<FeedStatus>,
+}
+
+#[derive(Default)]
+struct LoadedFeed {
+    hosts: HashSet<IpAddr>,
+    networks: Vec<IpNetwork>,
+    status: FeedStatus,
+}
+
+impl LoadedFeed {
+    fn contains(&self, ip: IpAddr) -> bool {
+        self.hosts.contains(&ip) || self.networks.iter().any(|net| net.contains(ip))
+    }
+}
+
+fn default_refresh_minutes() -> u32 {
+    60
+}
+
+impl Default for ThreatIntelConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            feeds: Vec::new(),
+            refresh_minutes: default_refresh_minutes(),
+            exempt: Vec::new(),
+        }
+    }
+}
+
+impl ThreatIntelConfig {
+    pub fn validate(&self) -> ResultType<()> {
+        if self.refresh_minutes < 15 || self.refresh_minutes > 7 * 24 * 60 {
+            bail!("refresh_minutes must be 15-10080");
+        }
+        if self.feeds.len() > MAX_FEEDS {
+            bail!("at most {} feeds", MAX_FEEDS);
+        }
+        let mut names = HashSet::new();
+        for feed in self.feeds.iter() {
+            if feed.name.trim().is_empty() || !names.insert(feed.name.as_str()) {
+                bail!("feed names must be unique and not empty");
+            }
+            if !feed.url.starts_with("https://") && !feed.url.starts_with("http://") {
+                bail!("invalid feed url: {}", feed.url);
+            }
+        }
+        self.exempt_networks()?;
+        Ok(())
+    }
+
+    fn exempt_networks(&self) -> ResultType<Vec<IpNetwork>> {
+        let mut res = Vec::new();
+        for x in self.exempt.iter() {
+            match x.parse::<IpNetwork>() {
+                Ok(net) => res.push(net),
+                Err(_) => bail!("invalid exempt network: {}", x),
+            }
+        }
+        Ok(res)
+    }
+}
+
+// 解析纯文本列表，无法识别的行忽略
+fn parse_feed(text: &str) -> (HashSet<IpAddr>, Vec<IpNetwork>) {
+    let mut hosts = HashSet::new();
+    let mut networks = Vec::new();
+    for line in text.lines() {
+        let entry = line
+            .split(|c: char| c == ';' || c == '#' || c == ',' || c.is_whitespace())
+            .next()
+            .unwrap_or_default();
+        if entry.is_empty() {
+            continue;
+        }
+        match entry.parse::<IpNetwork>() {
+            Ok(net) if net.prefix() == if net.is_ipv4() { 32 } else { 128 } => {
+                hosts.insert(net.ip());
+            }
+            Ok(net) => networks.push(net),
+            Err(_) => {}
+        }
+    }
+    (hosts, networks)
+}
+
+async fn set(config: ThreatIntelConfig) -> ResultType<()> {
+    *EXEMPT.write().await = config.exempt_networks()?;
+    // 移除已删除的列表，地址变更的列表重新下载
+    let mut feeds = FEEDS.write().await;
+    feeds.retain(|name, loaded| {
+        config
+            .feeds
+            .iter()
+            .any(|f| &f.name == name && f.url == loaded.status.url)
+    });
+    for feed in config.feeds.iter() {
+        feeds.entry(feed.name.clone()).or_insert_with(|| LoadedFeed {
+            status: FeedStatus {
+                name: feed.name.clone(),
+                url: feed.url.clone(),
+                ..Default::default()
+            },
+            ..Default::default()
+        });
+    }
+    drop(feeds);
+    *CONFIG.write().await = config;
+    Ok(())
+}
+
+pub async fn reload(db: &EnterpriseDatabase) -> ResultType<()> {
+    let config: ThreatIntelConfig = match db.get_setting(THREAT_INTEL_KEY).await? {
+        Some(v) => serde_json::from_str(&v)?,
+        None => ThreatIntelConfig::default(),
+    };
+    config.validate()?;
+    set(config).await
+}
+
+pub async fn get() -> ThreatIntelConfig {
+    CONFIG.read().await.clone()
+}
+
+pub async fn update(db: &EnterpriseDatabase, config: ThreatIntelConfig, updated_by: &str) -> ResultType<()> {
+    config.validate()?;
+    db.set_setting(THREAT_INTEL_KEY, &serde_json::to_string(&config)?, Some(updated_by))
+        .await?;
+    set(config).await
+}
+
+async fn fetch(url: &str) -> ResultType<String> {
+    let res = reqwest::Client::builder()
+        .timeout(FETCH_TIMEOUT)
+        .build()?
+        .get(url)
+        .send()
+        .await?
+        .error_for_status()?;
+    let body = res.bytes().await?;
+    if body.len() > MAX_FEED_BYTES {
+        bail!("feed larger than {} bytes", MAX_FEED_BYTES);
+    }
+    Ok(String::from_utf8_lossy(&body).into_owned())
+}
+
+async fn refresh_feed(feed: &ThreatFeed) {
+    let res = fetch(&feed.url).await;
+    let now = crate::common::now();
+    let mut feeds = FEEDS.write().await;
+    // 下载期间列表可能已被删除或修改地址
+    let loaded = match feeds.get_mut(&feed.name) {
+        Some(loaded) if loaded.status.url == feed.url => loaded,
+        _ => return,
+    };
+    loaded.status.checked_at = Some(now);
+    match res {
+        Ok(text) => {
+            let (hosts, networks) = parse_feed(&text);
+            log::info!(
+                "Threat intel feed {} updated: {} entries",
+                feed.name,
+                hosts.len() + networks.len()
+            );
+            loaded.status.entries = hosts.len() + networks.len();
+            loaded.status.updated_at = Some(now);
+            loaded.status.error = None;
+            loaded.hosts = hosts;
+            loaded.networks = networks;
+        }
+        Err(err) => {
+            log::warn!("Failed to fetch threat intel feed {}: {}", feed.name, err);
+            loaded.status.error = Some(err.to_string());
+        }
+    }
+}
+
+// 立即下载全部列表
+pub async fn refresh() {
+    let config = get().await;
+    for feed in config.feeds.iter() {
+        refresh_feed(feed).await;
+    }
+}
+
+// 定时下载，由后台任务每分钟调用
+pub async fn run_scheduled() {
+    let config = get().await;
+    if !config.enabled {
+        return;
+    }
+    let now = crate::common::now();
+    let due: Vec<ThreatFeed> = {
+        let feeds = FEEDS.read().await;
+        config
+            .feeds
+            .iter()
+            .filter(|f| match feeds.get(&f.name).and_then(|x| x.status.checked_at) {
+                Some(checked_at) => now >= checked_at + config.refresh_minutes as u64 * 60,
+                None => true,
+            })
+            .cloned()
+            .collect()
+    };
+    for feed in due.iter() {
+        refresh_feed(feed).await;
+    }
+}
+
+// 来源地址命中的列表名，命中时计入该列表的拒绝次数
+pub async fn lookup(ip: IpAddr, check: Check) -> Option<String> {
+    if !CONFIG.read().await.enabled || ip.is_loopback() {
+        return None;
+    }
+    if EXEMPT.read().await.iter().any(|net| net.contains(ip)) {
+        return None;
+    }
+    let name = {
+        let feeds = FEEDS.read().await;
+        feeds.values().find(|f| f.contains(ip))?.status.name.clone()
+    };
+    if let Some(loaded) = FEEDS.write().await.get_mut(&name) {
+        match check {
+            Check::Registration => loaded.status.rejected_registrations += 1,
+            Check::Login => loaded.status.rejected_logins += 1,
+        }
+    }
+    Some(name)
+}
+
+pub async fn status() -> ThreatIntelStatus {
+    let mut feeds: Vec<FeedStatus> = FEEDS.read().await.values().map(|f| f.status.clone()).collect();
+    feeds.sort_by(|a, b| a.name.cmp(&b.name));
+    ThreatIntelStatus {
+        enabled: CONFIG.read().await.enabled,
+        feeds,
+    }
+}
+
+// Prometheus 文本格式
+pub async fn prometheus() -> String {
+    let feeds = status().await.feeds;
+    let mut out = String::new();
+    let feed_metrics: [(&str, &str, &str, fn(&FeedStatus) -> u64); 3] = [
+        (
+            "threat_intel_feed_entries",
+            "gauge",
+            "Addresses and networks loaded from the feed",
+            |f| f.entries as u64,
+        ),
+        (
+            "threat_intel_rejected_registrations_total",
+            "counter",
+            "Device registrations rejected because the source address is listed",
+            |f| f.rejected_registrations,
+        ),
+        (
+            "threat_intel_rejected_logins_total",
+            "counter",
+            "Web logins rejected because the source address is listed",
+            |f| f.rejected_logins,
+        ),
+    ];
+    for (name, kind, help, value) in feed_metrics {
+        let values: Vec<(String, u64)> = feeds
+            .iter()
+            .map(|f| (format!("feed=\"{}\"", f.name.replace('"', "'")), value(f)))
+            .collect();
+        crate::host_stats::metric(&mut out, name, kind, help, &values);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate() {
+        let mut config = ThreatIntelConfig {
+            enabled: true,
+            feeds: vec![ThreatFeed {
+                name: "spamhaus-drop".to_owned(),
+                url: "https://www.spamhaus.org/drop/drop.txt".to_owned(),
+            }],
+            refresh_minutes: 60,
+            exempt: vec!["203.0.113.0/24".to_owned()],
+        };
+        assert!(config.validate().is_ok());
+        config.feeds.push(config.feeds[0].clone());
+        assert!(config.validate().is_err());
+        config.feeds.pop();
+        config.feeds[0].url = "ftp://example.com/list".to_owned();
+        assert!(config.validate().is_err());
+        config.feeds[0].url = "https://example.com/list".to_owned();
+        config.exempt.push("not-an-ip".to_owned());
+        assert!(config.validate().is_err());
+        config.exempt.pop();
+        config.refresh_minutes = 5;
+        assert!(config.validate().is_err());
+        assert!(ThreatIntelConfig::default().validate().is_ok());
+    }
+
+    #[test]
+    fn test_parse_feed() {
+        let text = "; Spamhaus DROP List\n\
+                    1.10.16.0/20 ; SBL256894\n\
+                    # Feodo Tracker\n\
+                    198.51.100.7\n\
+                    2001:db8::/32 ; SBL1\n\
+                    2001:db8:1::1\n\
+                    garbage line\n\
+                    \n";
+        let (hosts, networks) = parse_feed(text);
+        assert_eq!(hosts.len(), 2);
+        assert!(hosts.contains(&"198.51.100.7".parse().unwrap()));
+        assert!(hosts.contains(&"2001:db8:1::1".parse().unwrap()));
+        assert_eq!(networks.len(), 2);
+        let feed = LoadedFeed {
+            hosts,
+            networks,
+            status: Default::default(),
+        };
+        assert!(feed.contains("1.10.20.1".parse().unwrap()));
+        assert!(!feed.contains("1.10.32.1".parse().unwrap()));
+        assert!(feed.contains("2001:db8:ffff::1".parse().unwrap()));
+    }
+}
@@ -0,0 +1,368 @@
+// 文件传输带宽调度模块 - 对文件传输和文件夹同步按全局、按用户组(租户)限速，并支持按时段调整上限
+// (如夜间不限速，时段按策略的时区计算)；远程控制会话经 BandwidthManager 预留带宽，批量传输只使用剩余部分。
+// 会话的预留按 租户(设备所有者的第一个用户组) → 设备组(设备的第一个组) → 会话 三级带宽池加权公平分配，
+// 会话结束后其带宽回收给其他会话
+use crate::common::{in_time_window, parse_minutes};
+use crate::enterprise_database::EnterpriseDatabase;
+use crate::performance_optimization::{BandwidthManager, PoolUsage, TenantPoolConfig};
+use crate::relay_sessions;
+use crate::unattended_access::{local_time, parse_timezone};
+use crate::validation::Payload;
+use async_speed_limit::Limiter;
+use hbb_common::{
+    bail, log,
+    tokio::{
+        self,
+        sync::RwLock,
+        time::{interval, Duration},
+    },
+    ResultType,
+};
+use schemars::JsonSchema;
+use serde_derive::{Deserialize, Serialize};
+use std::collections::HashMap;
+use validator::Validate;
+
+pub const TRANSFER_BANDWIDTH_KEY: &str = "transfer_bandwidth";
+const REFRESH_INTERVAL_SECS: u64 = 10;
+
+lazy_static::lazy_static! {
+    static ref POLICY: RwLock<BandwidthPolicy> = Default::default();
+    static ref LIMITERS: RwLock<TransferLimiters> = Default::default();
+    static ref SESSION_BANDWIDTH: BandwidthManager = BandwidthManager::new();
+    // 设备ID -> (租户, 设备组)，会话期间不重复查询
+    static ref DEVICE_POOLS: RwLock<HashMap<String, (String, String)>> = Default::default();
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, Validate)]
+pub struct BandwidthPolicy {
+    #[serde(default)]
+    pub enabled: bool,
+    // 服务器可用于远程控制和文件传输的总带宽
+    #[serde(default = "default_link_capacity_kbps")]
+    pub link_capacity_kbps: u64,
+    // 每个活跃远程控制会话预留的带宽
+    #[serde(default = "default_session_reserve_kbps")]
+    pub session_reserve_kbps: u64,
+    // 会话占满链路时文件传输保留的最低带宽
+    #[serde(default = "default_min_bulk_kbps")]
+    pub min_bulk_kbps: u64,
+    // 全局上限，None 表示只受链路剩余带宽限制
+    #[serde(default)]
+    pub global_limit_kbps: Option<u64>,
+    // 用户组ID -> 该组所有用户传输合计上限
+    #[serde(default)]
+    pub group_limits: HashMap<String, u64>,
+    // 按时段覆盖上面的上限，多个时段重叠时取第一个
+    #[serde(default)]
+    pub schedules: Vec<BandwidthSchedule>,
+    // 时段使用的 IANA 时区，为空时为服务器本地时间
+    #[serde(default)]
+    #[validate(length(max = 64))]
+    pub timezone: Option<String>,
+    // 用户组ID -> 租户带宽池，未配置的租户权重为 1、不设上限
+    #[serde(default)]
+    pub pools: HashMap<String, TenantPoolConfig>,
+}
+
+impl Payload for BandwidthPolicy {}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct BandwidthSchedule {
+    pub name: String,
+    // 0 为周日，为空表示每天
+    #[serde(default)]
+    pub days: Vec<u8>,
+    pub start: String, // HH:MM，策略时区的时间
+    pub end: String,   // HH:MM，早于 start 时跨天
+    #[serde(default)]
+    pub global_limit_kbps: Option<u64>,
+    // 只覆盖列出的组，未列出的组沿用默认上限
+    #[serde(default)]
+    pub group_limits: HashMap<String, u64>,
+}
+
+fn default_link_capacity_kbps() -> u64 {
+    1_000_000
+}
+
+fn default_session_reserve_kbps() -> u64 {
+    4_000
+}
+
+fn default_min_bulk_kbps() -> u64 {
+    1_000
+}
+
+impl Default for BandwidthPolicy {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            link_capacity_kbps: default_link_capacity_kbps(),
+            session_reserve_kbps: default_session_reserve_kbps(),
+            min_bulk_kbps: default_min_bulk_kbps(),
+            global_limit_kbps: None,
+            group_limits: HashMap::new(),
+            schedules: vec![],
+            timezone: None,
+            pools: HashMap::new(),
+        }
+    }
+}
+
+impl BandwidthSchedule {
+    fn matches(&self, weekday: u8, minutes: u32) -> bool {
+        in_time_window(&self.days, &self.start, &self.end, weekday, minutes)
+    }
+}
+
+impl BandwidthPolicy {
+    pub fn validate(&self) -> ResultType<()> {
+        if self.link_capacity_kbps == 0 {
+            bail!("link_capacity_kbps must be positive");
+        }
+        if self.global_limit_kbps == Some(0) || self.group_limits.values().any(|v| *v == 0) {
+            bail!("bandwidth limits must be positive");
+        }
+        for (tenant, pool) in &self.pools {
+            if let Err(e) = pool.pool.validate() {
+                bail!("pool {}: {}", tenant, e);
+            }
+            for (group, pool) in &pool.groups {
+                if let Err(e) = pool.validate() {
+                    bail!("pool {}/{}: {}", tenant, group, e);
+                }
+            }
+        }
+        if let Some(timezone) = &self.timezone {
+            parse_timezone(timezone)?;
+        }
+        for schedule in &self.schedules {
+            parse_minutes(&schedule.start)?;
+            parse_minutes(&schedule.end)?;
+            if schedule.days.iter().any(|d| *d > 6) {
+                bail!("schedule {}: days must be between 0 and 6", schedule.name);
+            }
+            if schedule.global_limit_kbps == Some(0) || schedule.group_limits.values().any(|v| *v == 0) {
+                bail!("schedule {}: bandwidth limits must be positive", schedule.name);
+            }
+        }
+        Ok(())
+    }
+
+    // 指定时刻生效的全局上限和各组上限
+    fn limits_at(&self, weekday: u8, minutes: u32) -> (Option<u64>, HashMap<String, u64>) {
+        let mut global = self.global_limit_kbps;
+        let mut groups = self.group_limits.clone();
+        if let Some(schedule) = self.schedules.iter().find(|s| s.matches(weekday, minutes)) {
+            global = schedule.global_limit_kbps;
+            for (group, limit) in &schedule.group_limits {
+                groups.insert(group.clone(), *limit);
+            }
+        }
+        (global, groups)
+    }
+
+    // 扣除远程控制会话预留后文件传输可用的全局带宽
+    fn bulk_limit(&self, global: Option<u64>, remaining_kbps: u64) -> u64 {
+        let bulk = remaining_kbps.max(self.min_bulk_kbps);
+        match global {
+            Some(limit) => limit.min(bulk),
+            None => bulk,
+        }
+    }
+}
+
+#[derive(Default)]
+struct TransferLimiters {
+    global: Option<Limiter>,
+    groups: HashMap<String, Limiter>,
+}
+
+fn bytes_per_sec(kbps: u64) -> f64 {
+    kbps as f64 * 1000. / 8.
+}
+
+pub async fn reload(db: &EnterpriseDatabase) -> ResultType<()> {
+    let policy: BandwidthPolicy = match db.get_setting(TRANSFER_BANDWIDTH_KEY).await? {
+        Some(v) => serde_json::from_str(&v)?,
+        None => BandwidthPolicy::default(),
+    };
+    *POLICY.write().await = policy;
+    refresh(db).await;
+    Ok(())
+}
+
+pub async fn get() -> BandwidthPolicy {
+    POLICY.read().await.clone()
+}
+
+pub async fn update(db: &EnterpriseDatabase, policy: BandwidthPolicy, updated_by: &str) -> ResultType<()> {
+    policy.validate()?;
+    db.set_setting(TRANSFER_BANDWIDTH_KEY, &serde_json::to_string(&policy)?, Some(updated_by))
+        .await?;
+    *POLICY.write().await = policy;
+    refresh(db).await;
+    Ok(())
+}
+
+pub async fn pool_usage() -> Vec<PoolUsage> {
+    SESSION_BANDWIDTH.pool_usage().await
+}
+
+// 会话按发起方(第一个登记的设备)归入带宽池
+async fn device_pool(db: &EnterpriseDatabase, device_id: &str) -> ResultType<(String, String)> {
+    if let Some(pool) = DEVICE_POOLS.read().await.get(device_id) {
+        return Ok(pool.clone());
+    }
+    let mut tenant = String::new();
+    if let Some(owner) = db.get_device_owner(device_id).await? {
+        if let Some(user) = db.get_user_by_id(&owner).await? {
+            tenant = user.groups.into_iter().next().unwrap_or_default();
+        }
+    }
+    let group = db
+        .get_device_group_ids(device_id)
+        .await?
+        .into_iter()
+        .next()
+        .unwrap_or_default();
+    DEVICE_POOLS
+        .write()
+        .await
+        .insert(device_id.to_owned(), (tenant.clone(), group.clone()));
+    Ok((tenant, group))
+}
+
+// 用当前活跃的中继会话替换带宽池中的会话，已结束的会话释放带宽
+async fn sync_sessions(db: &EnterpriseDatabase, policy: &BandwidthPolicy) {
+    let sessions = relay_sessions::list().await;
+    DEVICE_POOLS
+        .write()
+        .await
+        .retain(|device_id, _| sessions.iter().any(|s| s.device_ids.first() == Some(device_id)));
+    let mut demands = Vec::with_capacity(sessions.len());
+    for session in sessions {
+        let (tenant, group) = match session.device_ids.first() {
+            Some(device_id) => device_pool(db, device_id).await.unwrap_or_else(|e| {
+                log::error!("Failed to get bandwidth pool of {}: {}", device_id, e);
+                Default::default()
+            }),
+            None => Default::default(),
+        };
+        demands.push((session.uuid, tenant, group, policy.session_reserve_kbps));
+    }
+    SESSION_BANDWIDTH.set_pools(policy.pools.clone()).await;
+    SESSION_BANDWIDTH.sync_sessions(demands).await;
+}
+
+// 按当前时段和活跃会话数重新计算各限速器的速率
+async fn refresh(db: &EnterpriseDatabase) {
+    let policy = get().await;
+    let mut limiters = LIMITERS.write().await;
+    if !policy.enabled {
+        *limiters = TransferLimiters::default();
+        return;
+    }
+    let (weekday, minutes) = local_time(chrono::Utc::now(), policy.timezone.as_deref());
+    let (global, groups) = policy.limits_at(weekday, minutes);
+    SESSION_BANDWIDTH.set_available_bandwidth(policy.link_capacity_kbps);
+    sync_sessions(db, &policy).await;
+    let bulk = bytes_per_sec(policy.bulk_limit(global, SESSION_BANDWIDTH.remaining_bandwidth()));
+    // 保留已有限速器，避免重置正在进行的传输的令牌桶
+    match &limiters.global {
+        Some(limiter) => limiter.set_speed_limit(bulk),
+        None => limiters.global = Some(<Limiter>::new(bulk)),
+    }
+    limiters.groups.retain(|group, _| groups.contains_key(group));
+    for (group, kbps) in groups {
+        let speed = bytes_per_sec(kbps);
+        match limiters.groups.get(&group) {
+            Some(limiter) => limiter.set_speed_limit(speed),
+            None => {
+                limiters.groups.insert(group, <Limiter>::new(speed));
+            }
+        }
+    }
+}
+
+// 定期刷新，使时段切换和会话数变化及时生效
+pub fn start(db: EnterpriseDatabase) {
+    tokio::spawn(async move {
+        let mut interval = interval(Duration::from_secs(REFRESH_INTERVAL_SECS));
+        loop {
+            interval.tick().await;
+            refresh(&db).await;
+        }
+    });
+    log::info!("Transfer bandwidth scheduler started");
+}
+
+// 按用户所属组和全局上限消耗 len 字节的配额，超出速率时等待
+pub async fn throttle(groups: &[String], len: usize) {
+    let limiters: Vec<Limiter> = {
+        let limiters = LIMITERS.read().await;
+        limiters
+            .global
+            .iter()
+            .chain(groups.iter().filter_map(|g| limiters.groups.get(g)))
+            .cloned()
+            .collect()
+    };
+    for limiter in limiters {
+        limiter.consume(len).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_schedule_limits() {
+        let policy = BandwidthPolicy {
+            enabled: true,
+            global_limit_kbps: Some(10_000),
+            group_limits: [("branch".to_owned(), 2_000)].into_iter().collect(),
+            schedules: vec![BandwidthSchedule {
+                name: "night".to_owned(),
+                days: vec![1, 2, 3, 4, 5],
+                start: "22:00".to_owned(),
+                end: "06:00".to_owned(),
+                global_limit_kbps: None,
+                group_limits: [("branch".to_owned(), 50_000)].into_iter().collect(),
+            }],
+            ..Default::default()
+        };
+        assert!(policy.validate().is_ok());
+        // 周一 12:00 使用默认上限
+        let (global, groups) = policy.limits_at(1, 12 * 60);
+        assert_eq!(global, Some(10_000));
+        assert_eq!(groups["branch"], 2_000);
+        // 周五 23:00 和周六 05:00 属于夜间时段
+        assert_eq!(policy.limits_at(5, 23 * 60).0, None);
+        assert_eq!(policy.limits_at(6, 5 * 60).1["branch"], 50_000);
+        // 周六 23:00 不在时段内
+        assert_eq!(policy.limits_at(6, 23 * 60).0, Some(10_000));
+
+        // 会话占满链路时仍保留最低带宽
+        assert_eq!(policy.bulk_limit(None, 0), policy.min_bulk_kbps);
+        assert_eq!(policy.bulk_limit(Some(10_000), 500_000), 10_000);
+
+        let mut pools = policy.clone();
+        pools.pools.insert("acme".to_owned(), Default::default());
+        assert!(pools.validate().is_ok());
+        pools.pools.get_mut("acme").unwrap().pool.weight = 0;
+        assert!(pools.validate().is_err());
+
+        let mut zoned = policy.clone();
+        zoned.timezone = Some("Asia/Shanghai".to_owned());
+        assert!(zoned.validate().is_ok());
+        zoned.timezone = Some("UTC+8".to_owned());
+        assert!(zoned.validate().is_err());
+
+        let mut bad = policy;
+        bad.schedules[0].start = "25:00".to_owned();
+        assert!(bad.validate().is_err());
+    }
+}
@@ -0,0 +1,122 @@
+// 受信任浏览器模块 - 2FA登录成功后可签发受信任浏览器Cookie，有效期内同一浏览器登录免输入TOTP，
+// Cookie 为签名令牌，对应数据库记录删除即撤销
+use crate::enterprise_database::EnterpriseDatabase;
+use crate::validation::Payload;
+use axum::http::{header, HeaderMap};
+use hbb_common::{bail, tokio::sync::RwLock, ResultType};
+use schemars::JsonSchema;
+use serde_derive::{Deserialize, Serialize};
+use validator::Validate;
+
+pub const TRUSTED_DEVICE_KEY: &str = "trusted_device";
+pub const COOKIE_NAME: &str = "rd_trusted_device";
+
+lazy_static::lazy_static! {
+    static ref CONFIG: RwLock<TrustedDeviceConfig> = Default::default();
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, Validate)]
+pub struct TrustedDeviceConfig {
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    #[serde(default = "default_lifetime_days")]
+    #[validate(range(min = 1, max = 365))]
+    pub lifetime_days: u64,
+}
+
+impl Payload for TrustedDeviceConfig {}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_lifetime_days() -> u64 {
+    30
+}
+
+impl Default for TrustedDeviceConfig {
+    fn default() -> Self {
+        Self {
+            enabled: default_true(),
+            lifetime_days: default_lifetime_days(),
+        }
+    }
+}
+
+impl TrustedDeviceConfig {
+    pub fn validate(&self) -> ResultType<()> {
+        if self.lifetime_days == 0 || self.lifetime_days > 365 {
+            bail!("lifetime_days must be between 1 and 365");
+        }
+        Ok(())
+    }
+
+    pub fn lifetime_secs(&self) -> u64 {
+        self.lifetime_days * 24 * 3600
+    }
+}
+
+pub async fn reload(db: &EnterpriseDatabase) -> ResultType<()> {
+    let config: TrustedDeviceConfig = match db.get_setting(TRUSTED_DEVICE_KEY).await? {
+        Some(v) => serde_json::from_str(&v)?,
+        None => TrustedDeviceConfig::default(),
+    };
+    *CONFIG.write().await = config;
+    Ok(())
+}
+
+pub async fn get() -> TrustedDeviceConfig {
+    CONFIG.read().await.clone()
+}
+
+pub async fn update(db: &EnterpriseDatabase, config: TrustedDeviceConfig, updated_by: &str) -> ResultType<()> {
+    config.validate()?;
+    db.set_setting(TRUSTED_DEVICE_KEY, &serde_json::to_string(&config)?, Some(updated_by))
+        .await?;
+    *CONFIG.write().await = config;
+    Ok(())
+}
+
+// 从请求头中读取受信任浏览器Cookie
+pub fn cookie_token(headers: &HeaderMap) -> Option<String> {
+    headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(';'))
+        .filter_map(|kv| kv.trim().split_once('='))
+        .find(|(k, _)| *k == COOKIE_NAME)
+        .map(|(_, v)| v.to_owned())
+        .filter(|v| !v.is_empty())
+}
+
+// Cookie 只发送给认证接口，禁止脚本读取
+pub fn set_cookie(token: &str, max_age: u64) -> String {
+    format!(
+        "{}={}; Max-Age={}; Path=/api/auth; HttpOnly; Secure; SameSite=Strict",
+        COOKIE_NAME, token, max_age
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cookie_token() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(cookie_token(&headers), None);
+        headers.insert(
+            header::COOKIE,
+            format!("lang=zh; {}=abc.def; theme=dark", COOKIE_NAME).parse().unwrap(),
+        );
+        assert_eq!(cookie_token(&headers).as_deref(), Some("abc.def"));
+        assert!(set_cookie("abc", 60).starts_with("rd_trusted_device=abc; Max-Age=60;"));
+        assert!(TrustedDeviceConfig {
+            enabled: true,
+            lifetime_days: 0
+        }
+        .validate()
+        .is_err());
+    }
+}
@@ -0,0 +1,201 @@
+// TURN 临时凭据模块 - 配合 coturn 的 use-auth-secret(REST API)模式，服务器与 coturn 共享一个密钥，
+// 按登录用户签发有时效的凭据，客户端不再需要长期有效的 TURN 账号:
+//   username = "<过期时间戳>:<用户ID>"
+//   password = base64(HMAC-SHA1(共享密钥, username))
+// coturn 用同一密钥校验签名并拒绝已过期的用户名，因此凭据泄露后最多在 ttl 内可用，
+// 日志中的用户名也能对应到签发的用户。coturn 配置: use-auth-secret / static-auth-secret=<shared_secret>
+use crate::enterprise_database::EnterpriseDatabase;
+use crate::validation::{self, Payload};
+use crate::webrtc_signaling::IceServer;
+use hbb_common::{bail, tokio::sync::RwLock, ResultType};
+use hmac::{Hmac, Mac};
+use schemars::JsonSchema;
+use serde_derive::{Deserialize, Serialize};
+use validator::Validate;
+
+pub const TURN_KEY: &str = "turn";
+const SECRET_MASK: &str = "******";
+const MIN_SECRET_LEN: usize = 16;
+
+type HmacSha1 = Hmac<sha1::Sha1>;
+
+lazy_static::lazy_static! {
+    static ref CONFIG: RwLock<TurnConfig> = Default::default();
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema, Validate)]
+pub struct TurnConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    // coturn 地址，如 turn:turn.example.com:3478?transport=udp、turns:turn.example.com:5349
+    #[serde(default)]
+    #[validate(custom = "validation::non_empty_items")]
+    pub urls: Vec<String>,
+    // 与 coturn 的 static-auth-secret 相同
+    #[serde(default)]
+    #[validate(length(max = 1024))]
+    pub shared_secret: String,
+    #[serde(default = "default_ttl_secs")]
+    #[validate(range(min = 60, max = 86400))]
+    pub ttl_secs: u64,
+}
+
+impl Payload for TurnConfig {}
+
+// 字段与 TURN REST API 草案的响应一致
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct TurnCredentials {
+    pub username: String,
+    pub password: String,
+    pub ttl: u64,
+    pub uris: Vec<String>,
+}
+
+fn default_ttl_secs() -> u64 {
+    3600
+}
+
+impl Default for TurnConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            urls: Vec::new(),
+            shared_secret: String::new(),
+            ttl_secs: default_ttl_secs(),
+        }
+    }
+}
+
+impl TurnConfig {
+    pub fn validate(&self) -> ResultType<()> {
+        if self.ttl_secs < 60 || self.ttl_secs > 86400 {
+            bail!("ttl_secs must be 60-86400");
+        }
+        if !self.enabled {
+            return Ok(());
+        }
+        if self.urls.is_empty() {
+            bail!("turn urls must not be empty");
+        }
+        if let Some(url) = self
+            .urls
+            .iter()
+            .find(|url| !url.starts_with("turn:") && !url.starts_with("turns:"))
+        {
+            bail!("invalid turn url: {}", url);
+        }
+        if self.shared_secret.len() < MIN_SECRET_LEN {
+            bail!("shared_secret must be at least {} characters", MIN_SECRET_LEN);
+        }
+        Ok(())
+    }
+
+    // 接口返回时隐藏共享密钥
+    pub fn masked(&self) -> Self {
+        let mut config = self.clone();
+        if !config.shared_secret.is_empty() {
+            config.shared_secret = SECRET_MASK.to_owned();
+        }
+        config
+    }
+
+    fn issue(&self, subject: &str, now: u64) -> TurnCredentials {
+        let username = format!("{}:{}", now + self.ttl_secs, subject);
+        let mut mac = HmacSha1::new_from_slice(self.shared_secret.as_bytes()).expect("HMAC accepts keys of any length");
+        mac.update(username.as_bytes());
+        TurnCredentials {
+            username,
+            password: base64::encode(mac.finalize().into_bytes()),
+            ttl: self.ttl_secs,
+            uris: self.urls.clone(),
+        }
+    }
+}
+
+pub async fn reload(db: &EnterpriseDatabase) -> ResultType<()> {
+    let config: TurnConfig = match db.get_setting(TURN_KEY).await? {
+        Some(v) => serde_json::from_str(&v)?,
+        None => TurnConfig::default(),
+    };
+    config.validate()?;
+    *CONFIG.write().await = config;
+    Ok(())
+}
+
+pub async fn get() -> TurnConfig {
+    CONFIG.read().await.clone()
+}
+
+pub async fn update(db: &EnterpriseDatabase, mut config: TurnConfig, updated_by: &str) -> ResultType<()> {
+    // 前端回传掩码时保留原密钥
+    if config.shared_secret == SECRET_MASK {
+        config.shared_secret = CONFIG.read().await.shared_secret.clone();
+    }
+    config.validate()?;
+    db.set_setting(TURN_KEY, &serde_json::to_string(&config)?, Some(updated_by))
+        .await?;
+    *CONFIG.write().await = config;
+    Ok(())
+}
+
+// 为用户(或设备)签发凭据，未启用时为空；subject 不能包含冒号
+pub async fn credentials(subject: &str) -> Option<TurnCredentials> {
+    let config = CONFIG.read().await;
+    if !config.enabled {
+        return None;
+    }
+    Some(config.issue(&subject.replace(':', "_"), crate::common::now()))
+}
+
+// 以浏览器 RTCIceServer 格式返回的临时凭据
+pub async fn ice_server(subject: &str) -> Option<IceServer> {
+    credentials(subject).await.map(|c| IceServer {
+        urls: c.uris,
+        username: Some(c.username),
+        credential: Some(c.password),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> TurnConfig {
+        TurnConfig {
+            enabled: true,
+            urls: vec!["turn:turn.example.com:3478".to_owned()],
+            shared_secret: "north-north-north".to_owned(),
+            ttl_secs: 600,
+        }
+    }
+
+    #[test]
+    fn test_validate() {
+        let mut config = config();
+        assert!(config.validate().is_ok());
+        assert_eq!(config.masked().shared_secret, SECRET_MASK);
+        config.urls = vec!["stun:turn.example.com".to_owned()];
+        assert!(config.validate().is_err());
+        config.urls = vec!["turns:turn.example.com:5349".to_owned()];
+        config.shared_secret = "short".to_owned();
+        assert!(config.validate().is_err());
+        config.enabled = false;
+        assert!(config.validate().is_ok());
+        config.ttl_secs = 10;
+        assert!(config.validate().is_err());
+        assert!(TurnConfig::default().validate().is_ok());
+    }
+
+    #[test]
+    fn test_issue() {
+        let mut config = config();
+        config.shared_secret = "north".to_owned();
+        config.ttl_secs = 3600;
+        let credentials = config.issue("alice", 1_699_996_400);
+        assert_eq!(credentials.username, "1700000000:alice");
+        // 与 coturn 的校验方式相同: base64(HMAC-SHA1(secret, username))
+        assert_eq!(credentials.password, "Cd/49soE35ICqcJF/bCTn8Z4OyE=");
+        assert_eq!(credentials.ttl, 3600);
+        assert_eq!(credentials.uris, config.urls);
+    }
+}
@@ -0,0 +1,302 @@
+// 无人值守访问时段模块 - 按设备/设备组配置允许无人值守连接的时段(如服务器全天、工作站 8:00-18:00)，
+// 时段外打洞和中继请求被拒绝；规则允许时，要求被控端在屏幕上确认连接(approve-mode=click)的设备
+// 在时段外仍可被连接。没有匹配规则的设备不受限制。时段按规则的时区计算，未设置时区时为服务器本地时间；
+// 规则引用节假日日历时，节假日全天按时段外处理
+use crate::common::{in_time_window, parse_minutes};
+use crate::enterprise_database::EnterpriseDatabase;
+use crate::holiday_calendar;
+use crate::strategy;
+use crate::validation::Payload;
+use hbb_common::{bail, log, tokio::sync::RwLock, ResultType};
+use schemars::JsonSchema;
+use serde_derive::{Deserialize, Serialize};
+use validator::Validate;
+
+pub const UNATTENDED_ACCESS_KEY: &str = "unattended_access";
+// 客户端仅在本地用户点击接受后才建立连接的配置
+const APPROVE_MODE: &str = "approve-mode";
+const APPROVE_MODE_CLICK: &str = "click";
+
+lazy_static::lazy_static! {
+    static ref POLICY: RwLock<UnattendedPolicy> = Default::default();
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema, Validate)]
+pub struct UnattendedPolicy {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default)]
+    pub rules: Vec<UnattendedRule>,
+}
+
+impl Payload for UnattendedPolicy {}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct UnattendedRule {
+    pub name: String,
+    #[serde(default)]
+    pub group_ids: Vec<String>,
+    #[serde(default)]
+    pub device_ids: Vec<String>,
+    // 为 true 时任何时间都允许无人值守连接
+    #[serde(default)]
+    pub always: bool,
+    #[serde(default)]
+    pub windows: Vec<AccessWindow>,
+    // 时段外允许需要屏幕确认的连接
+    #[serde(default)]
+    pub allow_with_acceptance: bool,
+    // IANA 时区，如 Europe/Berlin
+    #[serde(default)]
+    pub timezone: Option<String>,
+    // 节假日日历 id，日历中的日期不适用 windows
+    #[serde(default)]
+    pub holiday_calendar: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct AccessWindow {
+    // 0 为周日，为空表示每天
+    #[serde(default)]
+    pub days: Vec<u8>,
+    pub start: String, // HH:MM，规则时区的时间
+    pub end: String,   // HH:MM，早于 start 时跨天
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum AccessDecision {
+    Allowed,
+    // 时段外，需被控端屏幕确认
+    AcceptanceRequired,
+    Denied,
+}
+
+impl UnattendedPolicy {
+    pub fn validate(&self) -> ResultType<()> {
+        for rule in &self.rules {
+            if rule.name.trim().is_empty() {
+                bail!("rule name is empty");
+            }
+            if rule.group_ids.is_empty() && rule.device_ids.is_empty() {
+                bail!("rule {} has no target groups or devices", rule.name);
+            }
+            if let Some(timezone) = &rule.timezone {
+                parse_timezone(timezone)?;
+            }
+            for window in &rule.windows {
+                parse_minutes(&window.start)?;
+                parse_minutes(&window.end)?;
+                if window.days.iter().any(|d| *d > 6) {
+                    bail!("rule {}: days must be between 0 and 6", rule.name);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // 设备直接指定的规则优先于设备组规则
+    fn rule_for(&self, device_id: &str, group_ids: &[String]) -> Option<&UnattendedRule> {
+        self.rules
+            .iter()
+            .find(|r| r.device_ids.iter().any(|x| x == device_id))
+            .or_else(|| {
+                self.rules
+                    .iter()
+                    .find(|r| r.group_ids.iter().any(|g| group_ids.contains(g)))
+            })
+    }
+
+    fn decide(
+        &self,
+        device_id: &str,
+        group_ids: &[String],
+        weekday: u8,
+        minutes: u32,
+        holiday: bool,
+    ) -> AccessDecision {
+        if !self.enabled {
+            return AccessDecision::Allowed;
+        }
+        let rule = match self.rule_for(device_id, group_ids) {
+            Some(rule) => rule,
+            None => return AccessDecision::Allowed,
+        };
+        if rule.always
+            || (!holiday
+                && rule
+                    .windows
+                    .iter()
+                    .any(|w| in_time_window(&w.days, &w.start, &w.end, weekday, minutes)))
+        {
+            AccessDecision::Allowed
+        } else if rule.allow_with_acceptance {
+            AccessDecision::AcceptanceRequired
+        } else {
+            AccessDecision::Denied
+        }
+    }
+}
+
+pub async fn reload(db: &EnterpriseDatabase) -> ResultType<()> {
+    let policy: UnattendedPolicy = match db.get_setting(UNATTENDED_ACCESS_KEY).await? {
+        Some(v) => serde_json::from_str(&v)?,
+        None => UnattendedPolicy::default(),
+    };
+    policy.validate()?;
+    log::info!(
+        "unattended access policy loaded: enabled={}, {} rules",
+        policy.enabled,
+        policy.rules.len()
+    );
+    *POLICY.write().await = policy;
+    Ok(())
+}
+
+pub async fn get() -> UnattendedPolicy {
+    POLICY.read().await.clone()
+}
+
+pub async fn update(db: &EnterpriseDatabase, policy: UnattendedPolicy, updated_by: &str) -> ResultType<()> {
+    policy.validate()?;
+    db.set_setting(UNATTENDED_ACCESS_KEY, &serde_json::to_string(&policy)?, Some(updated_by))
+        .await?;
+    *POLICY.write().await = policy;
+    Ok(())
+}
+
+// 当前时间设备是否允许无人值守访问
+pub async fn decision(db: &EnterpriseDatabase, device_id: &str) -> ResultType<AccessDecision> {
+    let policy = POLICY.read().await.clone();
+    if !policy.enabled || policy.rules.is_empty() {
+        return Ok(AccessDecision::Allowed);
+    }
+    let group_ids = db.get_device_group_ids(device_id).await?;
+    let rule = policy.rule_for(device_id, &group_ids);
+    let timezone = rule.and_then(|r| r.timezone.as_deref());
+    let now = chrono::Utc::now();
+    let (weekday, minutes) = local_time(now, timezone);
+    let holiday = match rule.and_then(|r| r.holiday_calendar.as_deref()) {
+        Some(id) => holiday_calendar::is_holiday(id, local_date(now, timezone)).await,
+        None => false,
+    };
+    Ok(policy.decide(device_id, &group_ids, weekday, minutes, holiday))
+}
+
+// 打洞/中继时判断是否拒绝建立会话；时段外只有配置了屏幕确认的设备可以被连接
+pub async fn is_refused(db: &EnterpriseDatabase, device_id: &str) -> bool {
+    let res = match decision(db, device_id).await {
+        Ok(AccessDecision::Allowed) => return false,
+        Ok(AccessDecision::Denied) => return true,
+        Ok(AccessDecision::AcceptanceRequired) => strategy::effective(db, device_id)
+            .await
+            .map(|s| s.config_options.get(APPROVE_MODE).map(|x| x.as_str()) != Some(APPROVE_MODE_CLICK)),
+        Err(err) => Err(err),
+    };
+    match res {
+        Ok(refused) => refused,
+        Err(err) => {
+            log::error!("Failed to check unattended access of {}: {}", device_id, err);
+            false
+        }
+    }
+}
+
+// 时区须为 IANA 名称，如 Asia/Shanghai、America/New_York
+pub fn parse_timezone(timezone: &str) -> ResultType<chrono_tz::Tz> {
+    match timezone.parse::<chrono_tz::Tz>() {
+        Ok(tz) => Ok(tz),
+        Err(_) => bail!("invalid timezone {}, expected an IANA name such as Asia/Shanghai", timezone),
+    }
+}
+
+// 时刻在指定时区的星期(0 为周日)和当天分钟数，供 in_time_window 判断；未指定时区时按服务器本地时间
+pub fn local_time(now: chrono::DateTime<chrono::Utc>, timezone: Option<&str>) -> (u8, u32) {
+    use chrono::{Datelike, Timelike};
+    fn split<T: Datelike + Timelike>(t: T) -> (u8, u32) {
+        (t.weekday().num_days_from_sunday() as u8, t.hour() * 60 + t.minute())
+    }
+    match timezone.and_then(|x| parse_timezone(x).ok()) {
+        Some(tz) => split(now.with_timezone(&tz)),
+        None => split(now.with_timezone(&chrono::Local)),
+    }
+}
+
+// 指定时区的当前日期，时区无效或未设置时用服务器本地日期
+pub fn local_date(now: chrono::DateTime<chrono::Utc>, timezone: Option<&str>) -> chrono::NaiveDate {
+    match timezone.and_then(|x| parse_timezone(x).ok()) {
+        Some(tz) => now.with_timezone(&tz).date_naive(),
+        None => now.with_timezone(&chrono::Local).date_naive(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unattended_windows() {
+        let policy = UnattendedPolicy {
+            enabled: true,
+            rules: vec![
+                UnattendedRule {
+                    name: "servers".to_owned(),
+                    group_ids: vec!["servers".to_owned()],
+                    device_ids: vec![],
+                    always: true,
+                    windows: vec![],
+                    allow_with_acceptance: false,
+                    timezone: None,
+                    holiday_calendar: None,
+                },
+                UnattendedRule {
+                    name: "workstations".to_owned(),
+                    group_ids: vec!["workstations".to_owned()],
+                    device_ids: vec!["kiosk".to_owned()],
+                    always: false,
+                    windows: vec![AccessWindow {
+                        days: vec![1, 2, 3, 4, 5],
+                        start: "08:00".to_owned(),
+                        end: "18:00".to_owned(),
+                    }],
+                    allow_with_acceptance: true,
+                    timezone: Some("America/New_York".to_owned()),
+                    holiday_calendar: Some("us".to_owned()),
+                },
+            ],
+        };
+        assert!(policy.validate().is_ok());
+        let servers = vec!["servers".to_owned()];
+        let workstations = vec!["workstations".to_owned()];
+        // 周一 09:00 / 周一 20:00 / 周日 09:00
+        assert_eq!(policy.decide("1", &servers, 1, 20 * 60, false), AccessDecision::Allowed);
+        assert_eq!(policy.decide("2", &workstations, 1, 9 * 60, false), AccessDecision::Allowed);
+        assert_eq!(policy.decide("2", &workstations, 1, 20 * 60, false), AccessDecision::AcceptanceRequired);
+        assert_eq!(policy.decide("2", &workstations, 0, 9 * 60, false), AccessDecision::AcceptanceRequired);
+        assert_eq!(policy.decide("kiosk", &servers, 1, 20 * 60, false), AccessDecision::AcceptanceRequired);
+        assert_eq!(policy.decide("3", &[], 1, 20 * 60, false), AccessDecision::Allowed);
+        // 节假日工作时间按时段外处理，全天规则不受影响
+        assert_eq!(policy.decide("2", &workstations, 1, 9 * 60, true), AccessDecision::AcceptanceRequired);
+        assert_eq!(policy.decide("1", &servers, 1, 9 * 60, true), AccessDecision::Allowed);
+
+        let mut bad = policy.clone();
+        bad.rules[1].windows[0].end = "24:00".to_owned();
+        assert!(bad.validate().is_err());
+        let mut bad = policy.clone();
+        bad.rules[1].timezone = Some("Mars/Olympus_Mons".to_owned());
+        assert!(bad.validate().is_err());
+    }
+
+    #[test]
+    fn test_rule_timezone() {
+        use chrono::TimeZone;
+        // 周一 13:30 UTC 为纽约(夏令时)周一 09:30、东京周一 22:30；周一 02:00 UTC 为纽约周日 22:00
+        let now = chrono::Utc.with_ymd_and_hms(2024, 7, 1, 13, 30, 0).unwrap();
+        assert_eq!(local_time(now, Some("America/New_York")), (1, 9 * 60 + 30));
+        assert_eq!(local_time(now, Some("Asia/Tokyo")), (1, 22 * 60 + 30));
+        let now = chrono::Utc.with_ymd_and_hms(2024, 7, 1, 2, 0, 0).unwrap();
+        assert_eq!(local_time(now, Some("America/New_York")), (0, 22 * 60));
+        // 冬令时 UTC-5
+        let now = chrono::Utc.with_ymd_and_hms(2024, 1, 8, 13, 30, 0).unwrap();
+        assert_eq!(local_time(now, Some("America/New_York")), (1, 8 * 60 + 30));
+    }
+}
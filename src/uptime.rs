@@ -0,0 +1,219 @@
+// 设备在线率模块 - 设备上下线记录到 device_status_history 表，按时间窗口计算每台设备的在线率(SLA)。
+// 上线在设备注册(RegisterPeer)时登记，离线由后台任务根据 last_online 判定，离线时间记为最后一次注册的时间。
+// 窗口中早于首条记录的时段计为未知，不计入在线率
+use crate::enterprise_database::EnterpriseDatabase;
+use hbb_common::{bail, log, tokio::sync::RwLock, ResultType};
+use schemars::JsonSchema;
+use serde_derive::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+// 超过该时间未注册视为离线，客户端约 12 秒注册一次
+pub const ONLINE_TIMEOUT_SECS: u64 = 90;
+pub const MAX_WINDOW_SECS: u64 = 366 * 86400;
+const RETENTION_SECS: u64 = 400 * 86400;
+
+lazy_static::lazy_static! {
+    // 设备ID -> 最近一次记录的状态(true 为在线)
+    static ref STATUS: RwLock<HashMap<String, bool>> = Default::default();
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct StatusChange {
+    pub device_id: String,
+    pub online: bool,
+    pub changed_at: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct UptimeReport {
+    pub device_id: String,
+    pub since: u64,
+    pub until: u64,
+    pub online_secs: u64,
+    pub offline_secs: u64,
+    pub unknown_secs: u64,
+    // 在线时长 / (在线 + 离线时长)，没有记录时为空
+    pub uptime_percent: Option<f64>,
+    pub transitions: usize,
+}
+
+// 解析 "24h"、"7d"、"30d" 形式的时间窗口，返回秒数
+pub fn parse_window(window: &str) -> Option<u64> {
+    let window = window.trim();
+    let (n, unit) = window.split_at(window.len().checked_sub(1)?);
+    let n: u64 = n.parse().ok()?;
+    let secs = match unit {
+        "h" => n * 3600,
+        "d" => n * 86400,
+        _ => return None,
+    };
+    if secs == 0 || secs > MAX_WINDOW_SECS {
+        return None;
+    }
+    Some(secs)
+}
+
+// initial 为窗口开始时的状态，changes 为窗口内按时间排序的状态变化
+pub fn compute(
+    device_id: &str,
+    initial: Option<bool>,
+    changes: &[StatusChange],
+    since: u64,
+    until: u64,
+) -> UptimeReport {
+    let mut report = UptimeReport {
+        device_id: device_id.to_owned(),
+        since,
+        until,
+        online_secs: 0,
+        offline_secs: 0,
+        unknown_secs: 0,
+        uptime_percent: None,
+        transitions: 0,
+    };
+    let mut status = initial;
+    let mut t = since;
+    let mut add = |status: Option<bool>, secs: u64| match status {
+        Some(true) => report.online_secs += secs,
+        Some(false) => report.offline_secs += secs,
+        None => report.unknown_secs += secs,
+    };
+    for change in changes.iter().filter(|c| c.changed_at > since && c.changed_at <= until) {
+        add(status, change.changed_at - t);
+        t = change.changed_at;
+        status = Some(change.online);
+        report.transitions += 1;
+    }
+    add(status, until.saturating_sub(t));
+    let observed = report.online_secs + report.offline_secs;
+    if observed > 0 {
+        report.uptime_percent = Some(report.online_secs as f64 * 100.0 / observed as f64);
+    }
+    report
+}
+
+// 启动时加载各设备最近的状态
+pub async fn load(db: &EnterpriseDatabase) -> ResultType<()> {
+    let status = db.list_device_status_at(i64::MAX as u64, None).await?;
+    log::info!("Loaded status of {} devices", status.len());
+    *STATUS.write().await = status;
+    Ok(())
+}
+
+async fn record(db: &EnterpriseDatabase, device_id: &str, online: bool, changed_at: u64) {
+    match db.save_device_status(device_id, online, changed_at).await {
+        Ok(_) => {
+            STATUS.write().await.insert(device_id.to_owned(), online);
+        }
+        Err(e) => log::error!("Failed to record status of {}: {}", device_id, e),
+    }
+}
+
+// 设备注册时调用，只在状态变化时写库
+pub async fn mark_online(db: &EnterpriseDatabase, device_id: &str) {
+    if STATUS.read().await.get(device_id).copied() == Some(true) {
+        return;
+    }
+    record(db, device_id, true, crate::common::now()).await;
+}
+
+// 由后台任务定期调用，将超时未注册的设备标记为离线
+pub async fn sweep(db: &EnterpriseDatabase) {
+    let devices = match db.list_device_presence().await {
+        Ok(devices) => devices,
+        Err(e) => {
+            log::error!("Failed to load devices for uptime tracking: {}", e);
+            return;
+        }
+    };
+    let now = crate::common::now();
+    for device in devices {
+        let online = now < device.last_online + ONLINE_TIMEOUT_SECS;
+        let status = STATUS.read().await.get(&device.id).copied();
+        match (status, online) {
+            (Some(true), false) | (None, false) => record(db, &device.id, false, device.last_online).await,
+            (None, true) => record(db, &device.id, true, now).await,
+            _ => {}
+        }
+    }
+    if let Err(e) = db.prune_device_status_history(now.saturating_sub(RETENTION_SECS)).await {
+        log::error!("Failed to prune device status history: {}", e);
+    }
+}
+
+fn check_range(since: u64, until: u64) -> ResultType<()> {
+    if since >= until || until - since > MAX_WINDOW_SECS {
+        bail!("invalid time range");
+    }
+    Ok(())
+}
+
+pub async fn report(db: &EnterpriseDatabase, device_id: &str, since: u64, until: u64) -> ResultType<UptimeReport> {
+    check_range(since, until)?;
+    let initial = db.list_device_status_at(since, Some(device_id)).await?.remove(device_id);
+    let changes = db.list_device_status_changes(Some(device_id), since, until).await?;
+    Ok(compute(device_id, initial, &changes, since, until))
+}
+
+// 设备组(为空时全部设备)中每台设备的在线率
+pub async fn fleet_report(
+    db: &EnterpriseDatabase,
+    group_id: Option<&str>,
+    since: u64,
+    until: u64,
+) -> ResultType<Vec<UptimeReport>> {
+    check_range(since, until)?;
+    let devices = db.list_device_presence().await?;
+    let initial = db.list_device_status_at(since, None).await?;
+    let mut changes: HashMap<String, Vec<StatusChange>> = HashMap::new();
+    for change in db.list_device_status_changes(None, since, until).await? {
+        changes.entry(change.device_id.clone()).or_default().push(change);
+    }
+    Ok(devices
+        .iter()
+        .filter(|d| group_id.map(|g| d.group_ids.iter().any(|x| x == g)).unwrap_or(true))
+        .map(|d| {
+            compute(
+                &d.id,
+                initial.get(&d.id).copied(),
+                changes.get(&d.id).map(|x| x.as_slice()).unwrap_or_default(),
+                since,
+                until,
+            )
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compute_uptime() {
+        let change = |online, changed_at| StatusChange {
+            device_id: "123".to_owned(),
+            online,
+            changed_at,
+        };
+        // 100 之前未知，100-400 在线，400-500 离线，500-1000 在线
+        let changes = vec![change(true, 100), change(false, 400), change(true, 500)];
+        let report = compute("123", None, &changes, 0, 1000);
+        assert_eq!(report.unknown_secs, 100);
+        assert_eq!(report.online_secs, 800);
+        assert_eq!(report.offline_secs, 100);
+        assert_eq!(report.transitions, 3);
+        assert!((report.uptime_percent.unwrap() - 800.0 * 100.0 / 900.0).abs() < 1e-9);
+
+        let report = compute("123", Some(false), &[], 0, 60);
+        assert_eq!(report.offline_secs, 60);
+        assert_eq!(report.uptime_percent, Some(0.0));
+        assert!(compute("123", None, &[], 0, 60).uptime_percent.is_none());
+
+        assert_eq!(parse_window("24h"), Some(86400));
+        assert_eq!(parse_window("7d"), Some(7 * 86400));
+        assert_eq!(parse_window("0d"), None);
+        assert_eq!(parse_window("400d"), None);
+        assert_eq!(parse_window("7w"), None);
+        assert_eq!(parse_window(""), None);
+    }
+}
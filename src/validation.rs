@@ -0,0 +1,316 @@
+// 请求参数校验模块 - 请求体和查询参数在提取时按 validator 的约束校验，处理函数拿到的数据已通过校验：
+//   - ValidatedJson / ValidatedQuery 代替 Json / Query，类型需实现 Payload(通常只需 #[derive(Validate)] 和空的 impl)
+//   - 引用设备组的字段由 Payload::group_ids 返回，提取时检查设备组是否存在
+//   - 校验失败返回 400，data 为逐字段的错误列表，message 为 "请求参数校验失败: <第一个错误>"
+// 业务规则(密码策略、别名冲突等)仍由各处理函数检查，这里只做格式和取值范围的约束。
+// 自定义校验跳过空值，必填字段另加 length(min = 1)
+// 以键值对提交的映射(设置项、自定义字段值、功能开关、脱敏规则)没有可标注约束的字段，由 #[serde(transparent)]
+// 的包装类型手写 Validate，错误用 map_error 归到整个映射名下；取值类型等需查数据库的检查仍在处理函数中
+use crate::web_api::{ApiResponse, AppState};
+use axum::{
+    async_trait,
+    extract::{FromRequest, FromRequestParts, Query, Request},
+    http::{request::Parts, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use hbb_common::log;
+use ipnetwork::IpNetwork;
+use regex::Regex;
+use schemars::JsonSchema;
+use serde::de::DeserializeOwned;
+use serde_derive::{Deserialize, Serialize};
+use std::borrow::Cow;
+use validator::{Validate, ValidationError, ValidationErrors};
+
+pub const FAILED: &str = "请求参数校验失败";
+// 可分配的角色，与 UserRole 一致
+pub const ROLES: &[&str] = &["SuperAdmin", "Admin", "User", "ReadOnly", "Auditor"];
+
+lazy_static::lazy_static! {
+    static ref USERNAME: Regex = Regex::new(r"^[A-Za-z0-9][A-Za-z0-9._@-]*$").unwrap();
+}
+
+fn error(code: &'static str, message: &'static str) -> ValidationError {
+    let mut e = ValidationError::new(code);
+    e.message = Some(Cow::Borrowed(message));
+    e
+}
+
+// 字母或数字开头，只含字母、数字和 ._@-；邀请用户时可直接使用邮箱作为用户名
+pub fn username(value: &str) -> Result<(), ValidationError> {
+    let value = value.trim();
+    if value.is_empty() || USERNAME.is_match(value) {
+        Ok(())
+    } else {
+        Err(error("username_charset", "用户名只能包含字母、数字和 ._@-，且以字母或数字开头"))
+    }
+}
+
+pub fn role(value: &str) -> Result<(), ValidationError> {
+    if ROLES.contains(&value) {
+        Ok(())
+    } else {
+        Err(error("role", "角色必须是 SuperAdmin、Admin、User、ReadOnly 或 Auditor"))
+    }
+}
+
+pub fn email(value: &str) -> Result<(), ValidationError> {
+    let value = value.trim();
+    if value.is_empty() || validator::validate_email(value) {
+        Ok(())
+    } else {
+        Err(error("email", "邮箱地址格式错误"))
+    }
+}
+
+// IP地址或 CIDR 网段
+pub fn cidr(value: &str) -> Result<(), ValidationError> {
+    let value = value.trim();
+    if value.is_empty() || value.parse::<IpNetwork>().is_ok() {
+        Ok(())
+    } else {
+        Err(error("cidr", "IP地址或网段格式错误"))
+    }
+}
+
+// 设备别名：字母、数字和 -_.，不能是纯数字
+pub fn alias(value: &str) -> Result<(), ValidationError> {
+    if value.is_empty() || crate::peer_alias::validate_alias(value).is_ok() {
+        Ok(())
+    } else {
+        Err(error("alias", "别名只能包含字母、数字和 -_.，且不能是纯数字"))
+    }
+}
+
+// MAC 地址，支持 : - 分隔或无分隔
+pub fn mac_address(value: &str) -> Result<(), ValidationError> {
+    if value.is_empty() || crate::wake_on_lan::normalize_mac(value).is_ok() {
+        Ok(())
+    } else {
+        Err(error("mac_address", "MAC地址格式错误"))
+    }
+}
+
+// 十六进制的 SHA256 摘要
+pub fn sha256_hex(value: &str) -> Result<(), ValidationError> {
+    if value.is_empty() || (value.len() == 64 && value.chars().all(|c| c.is_ascii_hexdigit())) {
+        Ok(())
+    } else {
+        Err(error("sha256", "必须是64位十六进制的SHA256摘要"))
+    }
+}
+
+// 键值映射请求体的校验错误，field 为映射名
+pub fn map_error(field: &'static str, code: &'static str, message: String) -> ValidationErrors {
+    let mut e = ValidationError::new(code);
+    e.message = Some(Cow::Owned(message));
+    let mut errors = ValidationErrors::new();
+    errors.add(field, e);
+    errors
+}
+
+// 列表中的每一项都不能为空
+pub fn non_empty_items(values: &[String]) -> Result<(), ValidationError> {
+    if values.iter().any(|x| x.trim().is_empty()) {
+        Err(error("empty_item", "列表中不能有空值"))
+    } else {
+        Ok(())
+    }
+}
+
+// 校验后还需查询数据库的约束
+pub trait Payload: Validate {
+    // (字段名, 设备组 id 列表)，不存在的设备组报错
+    fn group_ids(&self) -> Vec<(&'static str, &[String])> {
+        vec![]
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct FieldError {
+    pub field: String,
+    // validator 的错误码，如 length、email、username_charset、group_not_found
+    pub code: String,
+    pub message: String,
+}
+
+fn default_message(code: &str) -> &'static str {
+    match code {
+        "length" => "长度超出范围",
+        "range" => "取值超出范围",
+        "required" => "缺少必填字段",
+        _ => "格式错误",
+    }
+}
+
+// 按字段名排序的错误列表
+pub fn field_errors(errors: &ValidationErrors) -> Vec<FieldError> {
+    let mut res: Vec<FieldError> = errors
+        .field_errors()
+        .into_iter()
+        .flat_map(|(field, errors)| {
+            errors.iter().map(move |e| FieldError {
+                field: field.to_owned(),
+                code: e.code.to_string(),
+                message: e
+                    .message
+                    .as_ref()
+                    .map(|x| x.to_string())
+                    .unwrap_or_else(|| default_message(&e.code).to_owned()),
+            })
+        })
+        .collect();
+    res.sort_by(|a, b| a.field.cmp(&b.field));
+    res
+}
+
+fn rejection(errors: Vec<FieldError>) -> Response {
+    let message = match errors.first() {
+        Some(e) => format!("{}: {} {}", FAILED, e.field, e.message),
+        None => FAILED.to_owned(),
+    };
+    let body = ApiResponse {
+        success: false,
+        data: Some(errors),
+        message,
+    };
+    (StatusCode::BAD_REQUEST, Json(body)).into_response()
+}
+
+async fn check<T: Payload>(value: &T, state: &AppState) -> Result<(), Response> {
+    let mut errors = match value.validate() {
+        Ok(()) => vec![],
+        Err(e) => field_errors(&e),
+    };
+    for (field, ids) in value.group_ids() {
+        for id in ids {
+            match state.db.device_group_exists(id).await {
+                Ok(true) => {}
+                Ok(false) => errors.push(FieldError {
+                    field: field.to_owned(),
+                    code: "group_not_found".to_owned(),
+                    message: format!("设备组 {} 不存在", id),
+                }),
+                Err(e) => {
+                    log::error!("Failed to check device group {}: {}", id, e);
+                    return Err(StatusCode::INTERNAL_SERVER_ERROR.into_response());
+                }
+            }
+        }
+    }
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(rejection(errors))
+    }
+}
+
+// 校验过的 JSON 请求体
+pub struct ValidatedJson<T>(pub T);
+
+#[async_trait]
+impl<T> FromRequest<AppState> for ValidatedJson<T>
+where
+    T: DeserializeOwned + Payload + Send,
+{
+    type Rejection = Response;
+
+    async fn from_request(req: Request, state: &AppState) -> Result<Self, Self::Rejection> {
+        let Json(value) = Json::<T>::from_request(req, state)
+            .await
+            .map_err(IntoResponse::into_response)?;
+        check(&value, state).await?;
+        Ok(Self(value))
+    }
+}
+
+// 校验过的查询参数
+pub struct ValidatedQuery<T>(pub T);
+
+#[async_trait]
+impl<T> FromRequestParts<AppState> for ValidatedQuery<T>
+where
+    T: DeserializeOwned + Payload + Send,
+{
+    type Rejection = Response;
+
+    async fn from_request_parts(parts: &mut Parts, state: &AppState) -> Result<Self, Self::Rejection> {
+        let Query(value) = Query::<T>::from_request_parts(parts, state)
+            .await
+            .map_err(IntoResponse::into_response)?;
+        check(&value, state).await?;
+        Ok(Self(value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Validate)]
+    struct Sample {
+        #[validate(length(min = 3, max = 8), custom = "username")]
+        username: String,
+        #[validate(custom = "email")]
+        email: Option<String>,
+        #[validate(custom = "role")]
+        role: String,
+        #[validate(custom = "cidr")]
+        ip: Option<String>,
+    }
+
+    #[test]
+    fn test_validators() {
+        assert!(username("alice.smith@example.com").is_ok());
+        assert!(username("bob-1").is_ok());
+        assert!(username("").is_ok());
+        assert!(username("-bob").is_err());
+        assert!(username("bob smith").is_err());
+        assert!(username("bob<script>").is_err());
+        assert!(role("Auditor").is_ok());
+        assert!(role("root").is_err());
+        assert!(cidr("10.0.0.0/8").is_ok());
+        assert!(cidr("2001:db8::1").is_ok());
+        assert!(cidr("10.0.0.0/33").is_err());
+        assert!(cidr(" ").is_ok());
+        assert!(email(" alice@example.com ").is_ok());
+        assert!(email("alice@").is_err());
+        assert!(non_empty_items(&["a".to_owned(), " ".to_owned()]).is_err());
+        assert!(alias("FINANCE-PC-07").is_ok());
+        assert!(alias("123456").is_err());
+        assert!(mac_address("00-1a-2b-3c-4d-5e").is_ok());
+        assert!(mac_address("00:1a:2b").is_err());
+        assert!(sha256_hex(&"ab".repeat(32)).is_ok());
+        assert!(sha256_hex("abc").is_err());
+    }
+
+    #[test]
+    fn test_field_errors() {
+        let sample = Sample {
+            username: "a b".to_owned(),
+            email: Some("not-an-email".to_owned()),
+            role: "Admin".to_owned(),
+            ip: Some("300.0.0.1".to_owned()),
+        };
+        let errors = field_errors(&sample.validate().unwrap_err());
+        let fields: Vec<_> = errors.iter().map(|e| (e.field.as_str(), e.code.as_str())).collect();
+        assert_eq!(
+            fields.iter().filter(|(f, _)| *f == "username").count(),
+            2,
+            "{:?}",
+            fields
+        );
+        assert!(fields.contains(&("email", "email")));
+        assert!(fields.contains(&("ip", "cidr")));
+        assert!(!fields.iter().any(|(f, _)| *f == "role"));
+        assert_eq!(errors.iter().find(|e| e.code == "email").unwrap().message, "邮箱地址格式错误");
+        let sample = Sample {
+            username: "alice".to_owned(),
+            email: None,
+            role: "User".to_owned(),
+            ip: None,
+        };
+        assert!(sample.validate().is_ok());
+    }
+}
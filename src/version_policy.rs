@@ -0,0 +1,239 @@
+// 客户端版本合规模块 - 按设备组配置最低客户端版本，低于要求的客户端在心跳和 SoftwareUpdate
+// 中收到强制更新提示，可选禁止其被连接直到升级；"latest" 表示设备所在平台托管的最新安装包版本，
+// 没有托管安装包时为启动时 --software-url 中解析出的版本
+use crate::enterprise_database::EnterpriseDatabase;
+use crate::software_update::{self, Platform};
+use crate::validation::Payload;
+use hbb_common::{bail, get_version_number, log, tokio::sync::RwLock, ResultType};
+use schemars::JsonSchema;
+use serde_derive::{Deserialize, Serialize};
+use std::collections::HashMap;
+use validator::Validate;
+
+pub const VERSION_POLICY_KEY: &str = "version_policy";
+const LATEST: &str = "latest";
+
+lazy_static::lazy_static! {
+    static ref POLICY: RwLock<VersionPolicy> = Default::default();
+    // 启动参数 --software-url 对应的最新版本和下载地址
+    static ref SOFTWARE: RwLock<(String, String)> = Default::default();
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema, Validate)]
+pub struct VersionPolicy {
+    #[serde(default)]
+    pub enabled: bool,
+    // 所有设备的最低版本，如 "1.2.3" 或 "latest"
+    #[serde(default)]
+    #[validate(length(max = 32))]
+    pub min_version: Option<String>,
+    // 设备组ID -> 最低版本，设备属于多个组时取最高要求
+    #[serde(default)]
+    pub group_min_versions: HashMap<String, String>,
+    // 版本过低的设备不能被连接
+    #[serde(default)]
+    pub block_sessions: bool,
+    // 更新地址，设备所在平台没有托管安装包时使用，为空时使用 --software-url
+    #[serde(default)]
+    #[validate(length(max = 2048))]
+    pub update_url: Option<String>,
+}
+
+impl Payload for VersionPolicy {}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct UpdateRequired {
+    pub required_version: String,
+    pub url: String,
+    // 为 true 时升级前不能被连接
+    pub blocked: bool,
+}
+
+impl VersionPolicy {
+    pub fn validate(&self) -> ResultType<()> {
+        for version in self.min_version.iter().chain(self.group_min_versions.values()) {
+            if version != LATEST && get_version_number(version) <= 0 {
+                bail!("invalid version: {}", version);
+            }
+        }
+        Ok(())
+    }
+
+    // 设备需要满足的最低版本
+    fn required_version(&self, group_ids: &[String], latest: &str) -> Option<String> {
+        let resolve = |v: &String| if v == LATEST { latest.to_owned() } else { v.clone() };
+        self.min_version
+            .iter()
+            .map(resolve)
+            .chain(
+                group_ids
+                    .iter()
+                    .filter_map(|g| self.group_min_versions.get(g))
+                    .map(resolve),
+            )
+            .filter(|v| !v.is_empty())
+            .max_by_key(|v| get_version_number(v))
+    }
+
+    fn update_url_or(&self, software_url: String) -> String {
+        self.update_url.clone().filter(|x| !x.is_empty()).unwrap_or(software_url)
+    }
+
+    fn evaluate(&self, version: &str, group_ids: &[String], latest: &str, url: &str) -> Option<UpdateRequired> {
+        if !self.enabled {
+            return None;
+        }
+        let required = self.required_version(group_ids, latest)?;
+        // 未上报版本的设备不视为不合规，避免误拦截
+        if version.is_empty() || get_version_number(version) >= get_version_number(&required) {
+            return None;
+        }
+        Some(UpdateRequired {
+            required_version: required,
+            url: url.to_owned(),
+            blocked: self.block_sessions,
+        })
+    }
+}
+
+// 启动时记录 --software-url 解析出的版本
+pub async fn set_software(version: &str, software_url: &str) {
+    *SOFTWARE.write().await = (version.to_owned(), software_url.to_owned());
+}
+
+pub async fn reload(db: &EnterpriseDatabase) -> ResultType<()> {
+    let policy: VersionPolicy = match db.get_setting(VERSION_POLICY_KEY).await? {
+        Some(v) => serde_json::from_str(&v)?,
+        None => VersionPolicy::default(),
+    };
+    policy.validate()?;
+    log::info!(
+        "version policy loaded: enabled={}, {} group rules",
+        policy.enabled,
+        policy.group_min_versions.len()
+    );
+    *POLICY.write().await = policy;
+    Ok(())
+}
+
+pub async fn get() -> VersionPolicy {
+    POLICY.read().await.clone()
+}
+
+pub async fn update(db: &EnterpriseDatabase, policy: VersionPolicy, updated_by: &str) -> ResultType<()> {
+    policy.validate()?;
+    db.set_setting(VERSION_POLICY_KEY, &serde_json::to_string(&policy)?, Some(updated_by))
+        .await?;
+    *POLICY.write().await = policy;
+    Ok(())
+}
+
+// 检查设备版本是否合规，version 为空时使用设备上报的系统信息
+pub async fn check(db: &EnterpriseDatabase, device_id: &str, version: &str) -> ResultType<Option<UpdateRequired>> {
+    let policy = POLICY.read().await.clone();
+    if !policy.enabled {
+        return Ok(None);
+    }
+    let version = if version.is_empty() {
+        db.get_device_version(device_id).await?.unwrap_or_default()
+    } else {
+        version.to_owned()
+    };
+    let group_ids = db.get_device_group_ids(device_id).await?;
+    let hosted = match db.get_inventory(device_id).await? {
+        Some(inventory) => match Platform::from_os(&inventory.os) {
+            Some(platform) => software_update::hosted_for(platform).await,
+            None => None,
+        },
+        None => None,
+    };
+    let (latest, url) = match hosted {
+        Some(hosted) => hosted,
+        None => {
+            let (latest, software_url) = SOFTWARE.read().await.clone();
+            (latest, policy.update_url_or(software_url))
+        }
+    };
+    Ok(policy.evaluate(&version, &group_ids, &latest, &url))
+}
+
+// 打洞/中继时判断目标设备是否因版本过低被禁止连接
+pub async fn is_blocked(db: &EnterpriseDatabase, device_id: &str) -> bool {
+    if !POLICY.read().await.block_sessions {
+        return false;
+    }
+    match check(db, device_id, "").await {
+        Ok(res) => res.map(|x| x.blocked).unwrap_or(false),
+        Err(err) => {
+            log::error!("Failed to check version compliance of {}: {}", device_id, err);
+            false
+        }
+    }
+}
+
+// 客户端通过 SoftwareUpdate 上报自身版本时，返回应下载的地址；
+// hosted 为客户端所在平台托管的最新版本及地址，优先于 --software-url 和策略中的更新地址
+pub async fn software_update_url(client_version: &str, hosted: Option<(String, String)>) -> Option<String> {
+    let policy = POLICY.read().await;
+    let (latest, url) = match hosted {
+        Some(hosted) => hosted,
+        None => {
+            let (latest, software_url) = SOFTWARE.read().await.clone();
+            if policy.enabled {
+                (latest, policy.update_url_or(software_url))
+            } else {
+                (latest, software_url)
+            }
+        }
+    };
+    let required = if policy.enabled {
+        policy.required_version(&[], &latest).unwrap_or_default()
+    } else {
+        String::new()
+    };
+    let target = if get_version_number(&required) > get_version_number(&latest) {
+        required
+    } else {
+        latest
+    };
+    if url.is_empty() || target.is_empty() || client_version.is_empty() {
+        return None;
+    }
+    if get_version_number(client_version) < get_version_number(&target) {
+        Some(url)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_version_compliance() {
+        let policy = VersionPolicy {
+            enabled: true,
+            min_version: Some("1.2.0".to_owned()),
+            group_min_versions: [("finance".to_owned(), LATEST.to_owned())].into_iter().collect(),
+            block_sessions: true,
+            update_url: None,
+        };
+        assert!(policy.validate().is_ok());
+        let url = "https://example.com/rustdesk-1.2.3.exe";
+        assert_eq!(policy.required_version(&[], "1.2.3").as_deref(), Some("1.2.0"));
+        assert_eq!(policy.required_version(&["finance".to_owned()], "1.2.3").as_deref(), Some("1.2.3"));
+        assert!(policy.evaluate("1.2.1", &[], "1.2.3", url).is_none());
+        let res = policy.evaluate("1.2.1", &["finance".to_owned()], "1.2.3", url).unwrap();
+        assert_eq!(res.required_version, "1.2.3");
+        assert_eq!(res.url, url);
+        assert!(res.blocked);
+        assert!(policy.evaluate("", &["finance".to_owned()], "1.2.3", url).is_none());
+        assert!(VersionPolicy {
+            min_version: Some("abc".to_owned()),
+            ..Default::default()
+        }
+        .validate()
+        .is_err());
+    }
+}
@@ -0,0 +1,224 @@
+// 网络唤醒模块 - 管理员唤醒离线设备：优先选择与目标同一子网、在线的受管客户端作为代理，
+// 代理在下一次心跳时取回待发送的 MAC 地址并在本地广播魔术包；
+// 没有可用代理时由服务器直接向受限广播和目标所在网段的定向广播发送
+use crate::common::get_arg;
+use crate::enterprise_database::{DeviceNetwork, EnterpriseDatabase};
+use hbb_common::{bail, log, tokio::sync::RwLock, ResultType};
+use schemars::JsonSchema;
+use serde_derive::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    net::{IpAddr, Ipv4Addr, SocketAddr, UdpSocket},
+    time::{Duration, Instant},
+};
+
+const WOL_PORT: u16 = 9;
+// 设备在该时间内注册过才视为在线，可以作为代理
+const AGENT_ONLINE_SECS: u64 = 60;
+// 代理未在该时间内取走的唤醒请求作废
+const PENDING_TTL: Duration = Duration::from_secs(300);
+const DEFAULT_SUBNET_PREFIX: u8 = 24;
+
+lazy_static::lazy_static! {
+    // 代理设备ID -> 待广播的 MAC 地址
+    static ref PENDING: RwLock<HashMap<String, Vec<(String, Instant)>>> = Default::default();
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct WakeResult {
+    pub device_id: String,
+    pub mac_address: String,
+    pub method: WakeMethod,
+    #[serde(default)]
+    pub agent_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum WakeMethod {
+    Agent,
+    Server,
+}
+
+// 解析 MAC 地址，支持 : - 分隔或无分隔，返回规范的大写冒号格式
+pub fn normalize_mac(mac: &str) -> ResultType<String> {
+    Ok(format_mac(&parse_mac(mac)?))
+}
+
+fn parse_mac(mac: &str) -> ResultType<[u8; 6]> {
+    let hex: String = mac.chars().filter(|c| *c != ':' && *c != '-').collect();
+    if hex.len() != 12 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        bail!("invalid mac address: {}", mac);
+    }
+    let mut bytes = [0u8; 6];
+    for (i, byte) in bytes.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16)?;
+    }
+    if bytes == [0u8; 6] || bytes == [0xffu8; 6] {
+        bail!("invalid mac address: {}", mac);
+    }
+    Ok(bytes)
+}
+
+fn format_mac(bytes: &[u8; 6]) -> String {
+    bytes
+        .iter()
+        .map(|b| format!("{:02X}", b))
+        .collect::<Vec<_>>()
+        .join(":")
+}
+
+// 魔术包：6 个 0xFF 后接 16 次 MAC 地址
+pub fn magic_packet(mac: &[u8; 6]) -> Vec<u8> {
+    let mut packet = vec![0xffu8; 6];
+    for _ in 0..16 {
+        packet.extend_from_slice(mac);
+    }
+    packet
+}
+
+fn subnet_prefix() -> u8 {
+    get_arg("wol-subnet-prefix")
+        .parse()
+        .ok()
+        .filter(|p| *p <= 32)
+        .unwrap_or(DEFAULT_SUBNET_PREFIX)
+}
+
+fn same_subnet(a: &str, b: &str, prefix: u8) -> bool {
+    match (a.parse::<IpAddr>(), b.parse::<IpAddr>()) {
+        (Ok(IpAddr::V4(a)), Ok(IpAddr::V4(b))) => {
+            let mask = if prefix == 0 { 0 } else { u32::MAX << (32 - prefix as u32) };
+            u32::from(a) & mask == u32::from(b) & mask
+        }
+        _ => false,
+    }
+}
+
+// 同一出口地址(同一 NAT 之后)的代理优先，其次是同一子网
+fn choose_agent<'a>(target: &DeviceNetwork, candidates: &'a [DeviceNetwork], prefix: u8) -> Option<&'a DeviceNetwork> {
+    let candidates = candidates.iter().filter(|d| d.id != target.id);
+    let mut same_subnet_agent = None;
+    for candidate in candidates {
+        if candidate.ip_address == target.ip_address {
+            return Some(candidate);
+        }
+        if same_subnet_agent.is_none() && same_subnet(&candidate.ip_address, &target.ip_address, prefix) {
+            same_subnet_agent = Some(candidate);
+        }
+    }
+    same_subnet_agent
+}
+
+fn send_from_server(mac: &[u8; 6], target_ip: &str) -> ResultType<()> {
+    let socket = UdpSocket::bind("0.0.0.0:0")?;
+    socket.set_broadcast(true)?;
+    let packet = magic_packet(mac);
+    let mut targets = vec![SocketAddr::new(Ipv4Addr::BROADCAST.into(), WOL_PORT)];
+    // 服务器与目标在同一内网时，定向广播可以跨越路由到达目标网段
+    if let Ok(IpAddr::V4(ip)) = target_ip.parse::<IpAddr>() {
+        if ip.is_private() {
+            let prefix = subnet_prefix() as u32;
+            let host_mask = if prefix >= 32 { 0 } else { u32::MAX >> prefix };
+            let broadcast = Ipv4Addr::from(u32::from(ip) | host_mask);
+            targets.push(SocketAddr::new(broadcast.into(), WOL_PORT));
+        }
+    }
+    for target in targets {
+        if let Err(err) = socket.send_to(&packet, target) {
+            log::warn!("Failed to send magic packet to {}: {}", target, err);
+        }
+    }
+    Ok(())
+}
+
+// 唤醒设备；未记录 MAC 地址的设备无法唤醒
+pub async fn wake(db: &EnterpriseDatabase, device_id: &str) -> ResultType<WakeResult> {
+    let target = match db.get_device_network(device_id).await? {
+        Some(target) => target,
+        None => bail!("device not found"),
+    };
+    let mac = match target.mac_address.as_deref() {
+        Some(mac) if !mac.is_empty() => parse_mac(mac)?,
+        _ => bail!("mac address of {} is unknown", device_id),
+    };
+    let mac_address = format_mac(&mac);
+    let since = crate::common::now().saturating_sub(AGENT_ONLINE_SECS);
+    let candidates = db.list_managed_devices_online_since(since).await?;
+    if let Some(agent) = choose_agent(&target, &candidates, subnet_prefix()) {
+        let mut pending = PENDING.write().await;
+        pending.retain(|_, macs| {
+            macs.retain(|(_, t)| t.elapsed() < PENDING_TTL);
+            !macs.is_empty()
+        });
+        let macs = pending.entry(agent.id.clone()).or_default();
+        if !macs.iter().any(|(m, _)| m == &mac_address) {
+            macs.push((mac_address.clone(), Instant::now()));
+        }
+        log::info!("Queued wake of {} ({}) via agent {}", device_id, mac_address, agent.id);
+        return Ok(WakeResult {
+            device_id: device_id.to_owned(),
+            mac_address,
+            method: WakeMethod::Agent,
+            agent_id: Some(agent.id.clone()),
+        });
+    }
+    send_from_server(&mac, &target.ip_address)?;
+    log::info!("Sent magic packet for {} ({}) from server", device_id, mac_address);
+    Ok(WakeResult {
+        device_id: device_id.to_owned(),
+        mac_address,
+        method: WakeMethod::Server,
+        agent_id: None,
+    })
+}
+
+// 代理心跳时取走待广播的 MAC 地址
+pub async fn take_pending(agent_id: &str) -> Vec<String> {
+    match PENDING.write().await.remove(agent_id) {
+        Some(macs) => macs
+            .into_iter()
+            .filter(|(_, t)| t.elapsed() < PENDING_TTL)
+            .map(|(mac, _)| mac)
+            .collect(),
+        None => vec![],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn device(id: &str, ip: &str) -> DeviceNetwork {
+        DeviceNetwork {
+            id: id.to_owned(),
+            ip_address: ip.to_owned(),
+            mac_address: None,
+            last_online: 0,
+        }
+    }
+
+    #[test]
+    fn test_mac_and_agent_selection() {
+        assert_eq!(normalize_mac("aa-bb-cc-dd-ee-0f").unwrap(), "AA:BB:CC:DD:EE:0F");
+        assert_eq!(normalize_mac("aabbccddee0f").unwrap(), "AA:BB:CC:DD:EE:0F");
+        assert!(normalize_mac("aa:bb:cc").is_err());
+        assert!(normalize_mac("00:00:00:00:00:00").is_err());
+        let packet = magic_packet(&[1, 2, 3, 4, 5, 6]);
+        assert_eq!(packet.len(), 102);
+        assert_eq!(&packet[..6], &[0xff; 6]);
+        assert_eq!(&packet[96..], &[1, 2, 3, 4, 5, 6]);
+
+        let target = device("1", "192.168.1.20");
+        let candidates = vec![
+            device("1", "192.168.1.20"),
+            device("2", "192.168.2.5"),
+            device("3", "192.168.1.30"),
+            device("4", "192.168.1.20"),
+        ];
+        assert_eq!(choose_agent(&target, &candidates, 24).unwrap().id, "4");
+        assert_eq!(choose_agent(&target, &candidates[..3], 24).unwrap().id, "3");
+        assert_eq!(choose_agent(&target, &candidates[..2], 24).map(|d| d.id.clone()), None);
+        assert_eq!(choose_agent(&target, &candidates[..2], 16).unwrap().id, "2");
+    }
+}
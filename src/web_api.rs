@@ -1,18 +1,106 @@
 // Web管理界面API模块
-use crate::auth::{AuthManager, User, UserRole, Claims};
-use crate::enterprise_database::{EnterpriseDatabase, AuditLog, DeviceInfo};
+use crate::ad_sync::{self, AdSyncConfig, AdSyncReport};
+use crate::advanced_security::SecurityEvent;
+use crate::announcements::{self, Announcement, ClientAnnouncement};
+use crate::auth::{AuthManager, User, UserRole, Claims, TwoFactorAuth, SCOPE_2FA_SETUP};
+use crate::backup::{self, BackupConfig, BackupFile};
+use crate::break_glass::{self, EmergencyAccess};
+use crate::change_approval::{self, ChangeApprovalConfig, ChangeStatus, SettingChange};
+use crate::common::REQUEST_ID;
+use crate::config_versions::{self, ConfigVersion, RollbackReport, SettingDiff};
+use crate::codec_recommendation::{self, ClientCodecRecommendation, CodecAck, CodecRecommendation};
+use crate::control_tls::{self, ControlTlsStatus, IssuedBundle, IssuedCert};
+use crate::congestion_control::CongestionControl;
+use crate::connection_quality::{self, ClientQosSample, QualityTrend};
+use crate::content_scan::{self, ScanConfig};
+use crate::custom_fields::{self, CustomFieldsConfig, FieldEntity, FieldValues, FieldValuesUpdate};
+use crate::data_masking::{self, DataMaskingConfig, DataMaskingUpdate};
+use crate::db_pool_metrics;
+use crate::device_ban;
+use crate::device_capabilities::{self, DeviceCapabilities, EncoderCapability};
+use crate::device_messages::{self, ClientMessage, DeviceMessage, MessageDelivery, MessageLevel};
+use crate::device_timeline::{self, DeviceNote, TimelineEntry};
+use crate::device_views::{self, DeviceView};
+use crate::dlp::{self, Direction, DlpPolicy};
+use crate::dns_discovery::{self, DiscoveryHealth, DnsDiscoveryConfig, DnsRecords};
+use crate::e2e_signaling::{self, E2ePolicy, E2eStats};
+use crate::email_otp::{self, SmtpConfig};
+use crate::enterprise_database::{EnterpriseDatabase, AuditLog, AuditLogFilter, ConnectionSession, DeviceAlias, DeviceBan, DeviceFilter, DeviceInfo, FileTransferFilter, InventoryFilter, ProvisionedId, TrustedDevice, UserConflict, UserInvite};
+use crate::error_codes::{self, ErrorInfo};
+use crate::erasure::{self, ErasureReport, ErasureRequest, ErasureSubject};
+use crate::file_transfer::{FileChunk, FileTransferRequest, TransferProgress, TransferRecord, TransferType, CHUNK_SIZE};
+use crate::favorites::{self, UserDevice};
+use crate::feature_flags::{self, FeatureFlags, FeatureFlagsUpdate};
+use crate::i18n::{self, LanguagePreference};
+use crate::federation::{self, DeliverRequest, FederatedLookup, FederationConfig, ForwardRequest};
+use crate::file_transfer_server;
+use crate::four_eyes::{self, ApprovalRequest, FourEyesPolicy};
+use crate::holiday_calendar::{self, CalendarConfig, HolidayCalendar};
+use crate::honeypot::{self, HoneypotConfig, IpBan};
+use crate::host_stats::{self, HostStatsReport};
+use crate::impersonation::{self, ImpersonationGrant, ImpersonationSession};
+use crate::id_policy::{self, IdPolicy};
+use crate::inventory::{DeviceInventory, InventoryReport};
+use crate::invites;
+use crate::itsm::{self, ItsmConfig};
+use crate::jwt_keys::{self, JwtKeyInfo};
+use crate::kiosk_fleet::{self, FleetHealth, KioskFleetConfig};
+use crate::pk_pinning::{self, PkPinningConfig, PkRecord, PkStatus};
+use crate::nat_diagnostics::{self, NatStatsSummary, PeerNatDiagnostics};
+use crate::network_tuning::{self, NetworkTuning};
+use crate::notifications::NotificationPreferences;
+use crate::offline_alerts::{self, OfflineAlertConfig, OfflineDevice};
+use crate::openapi;
+use crate::lan_config::{self, LanConfig};
+use crate::license::{self, LicenseStatus};
+use crate::logging::{self, LoggingConfig};
+use crate::mfa_policy::{self, MfaPolicy};
+use crate::password_policy::{self, PasswordPolicies, PasswordPolicy};
+use crate::password_reset;
+use crate::peer_alias;
+use crate::performance_optimization::PoolUsage;
+use crate::protocol_capture::{self, Capture, StartCaptureRequest};
+use crate::quic::{self, QuicInfo};
+use crate::relay_policy::{self, RelayPolicy};
+use crate::relay_sessions::{self, RelaySession, RelayStatus};
+use crate::remote_jobs::{self, ClientJob, Job, JobRun, JobShell};
+use crate::self_test::{self, ClientSelfTestReport, SelfTestPlan, SelfTestResult};
+use crate::server_key::{self, KeyRingInfo};
+use crate::session_controls::{self, ClientSessionPermissions, PermissionChange, SessionPermissions};
+use crate::session_events::{self, ClientSessionEvent, SessionEvent};
+use crate::software_update::{self, ManifestEntry, Platform, UpdateArtifact};
+use crate::storage_backend::StorageBackend;
+use crate::strategy::{self, EffectiveStrategy, Strategy};
+use crate::threat_intel::{self, ThreatIntelConfig, ThreatIntelStatus};
+use crate::transfer_bandwidth::{self, BandwidthPolicy};
+use crate::trusted_device::{self, TrustedDeviceConfig};
+use crate::turn::{self, TurnConfig, TurnCredentials};
+use crate::unattended_access::{self, UnattendedPolicy};
+use crate::uptime::{self, StatusChange, UptimeReport};
+use crate::validation::{self, Payload, ValidatedJson, ValidatedQuery};
+use crate::version_policy::{self, UpdateRequired, VersionPolicy};
+use crate::web_session::{self, WebSessionConfig, WebSessionStatus};
+use crate::webrtc_signaling::{
+    self, ClientWebRtcOffer, ControllerUpdate, DeviceSignal, IceCandidate, SessionOffer, WebRtcConfig,
+};
+use crate::wake_on_lan::{self, WakeResult};
+use crate::web_security::{self, WebSecurityConfig};
 use axum::{
-    extract::{Query, State, Path},
-    http::{StatusCode, HeaderMap},
-    response::Json,
-    routing::{get, post, put, delete},
+    body::Bytes,
+    extract::{ConnectInfo, DefaultBodyLimit, MatchedPath, Request, State, Path},
+    http::{header, HeaderValue, Method, StatusCode, HeaderMap},
+    middleware::{self, Next},
+    response::{Html, IntoResponse, Json, Response},
+    routing::{get, head, post, put, delete},
     Router,
 };
 use hbb_common::{log, ResultType};
+use ipnetwork::IpNetwork;
+use schemars::JsonSchema;
 use serde_derive::{Deserialize, Serialize};
-use std::{collections::HashMap, sync::Arc, time::SystemTime};
-use tower_http::cors::{CorsLayer, Any};
+use std::{collections::{BTreeMap, HashMap}, net::{IpAddr, SocketAddr}, sync::Arc, time::SystemTime};
 use tower_http::trace::TraceLayer;
+use validator::{Validate, ValidationErrors};
 
 #[derive(Clone)]
 pub struct AppState {
@@ -20,22 +108,218 @@ pub struct AppState {
     pub auth: Arc<AuthManager>,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, JsonSchema, Validate)]
 pub struct LoginRequest {
+    #[validate(length(min = 1, max = 254))]
     pub username: String,
+    #[validate(length(min = 1, max = 1024))]
     pub password: String,
+    #[validate(length(max = 16))]
     pub totp_code: Option<String>,
+    // 管理员开放邮件验证码通道后，可用邮件验证码代替TOTP
+    #[serde(default)]
+    #[validate(length(max = 16))]
+    pub email_code: Option<String>,
+    // 2FA验证通过后记住此浏览器
+    #[serde(default)]
+    pub remember_device: bool,
+}
+
+impl Payload for LoginRequest {}
+
+#[derive(Deserialize, JsonSchema, Validate)]
+pub struct EmailOtpRequest {
+    #[validate(length(min = 1, max = 128))]
+    pub username: String,
+    #[validate(length(min = 1, max = 1024))]
+    pub password: String,
+}
+
+impl Payload for EmailOtpRequest {}
+
+#[derive(Deserialize, JsonSchema, Validate)]
+pub struct RotateServerKeyRequest {
+    #[validate(range(min = 1, max = 720))]
+    pub overlap_hours: Option<u64>,
+}
+
+impl Payload for RotateServerKeyRequest {}
+
+#[derive(Deserialize, JsonSchema, Validate)]
+pub struct RotateJwtKeysRequest {
+    // 原密钥签发的令牌继续有效的时长，默认为会话时长；密钥泄露时设为 0 立即使全部令牌失效
+    #[validate(range(max = 720))]
+    pub grace_hours: Option<u64>,
+}
+
+impl Payload for RotateJwtKeysRequest {}
+
+#[derive(Deserialize, JsonSchema, Validate)]
+pub struct UpdateControlTlsRequest {
+    // 启用后信令服务器经双向 TLS 向中继发送管理命令，须先为全部中继部署证书
+    pub enabled: bool,
+}
+
+impl Payload for UpdateControlTlsRequest {}
+
+#[derive(Deserialize, JsonSchema, Validate)]
+pub struct IssueControlCertRequest {
+    // 信令服务器连接中继时使用的域名或IP
+    #[validate(length(min = 1, max = 253))]
+    pub name: String,
+}
+
+impl Payload for IssueControlCertRequest {}
+
+#[derive(Deserialize, JsonSchema, Validate)]
+pub struct CreateBackupRequest {
+    // 为空时按备份配置决定是否加密
+    #[serde(default)]
+    pub encrypt: Option<bool>,
+}
+
+impl Payload for CreateBackupRequest {}
+
+#[derive(Deserialize, JsonSchema, Validate)]
+pub struct GrantEmailOtpRequest {
+    #[validate(range(min = 1, max = 72))]
+    pub hours: Option<u64>,
+}
+
+impl Payload for GrantEmailOtpRequest {}
+
+#[derive(Deserialize, JsonSchema, Validate)]
+pub struct CreateUploadRequest {
+    #[validate(length(min = 1, max = 1024))]
+    pub file_path: String,
+    pub file_size: u64,
+    #[validate(length(min = 1), custom = "validation::sha256_hex")]
+    pub file_hash: String, // SHA256，上传完成后校验
+    // 指定设备时文件暂存到 devices/<设备ID>/ 下，供设备通过文件传输端口拉取；否则保存在 users/<用户ID>/ 下
+    #[serde(default)]
+    #[validate(length(min = 1, max = 100))]
+    pub device_id: Option<String>,
+}
+
+impl Payload for CreateUploadRequest {}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct UploadInfo {
+    pub transfer_id: String,
+    pub file_path: String,
+    pub offset: u64,
+    pub file_size: u64,
+    pub chunk_size: usize,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Deserialize, JsonSchema, Validate)]
+pub struct SecurityEventsQuery {
+    pub since: Option<u64>,
+    #[validate(range(min = 1, max = 1000))]
+    pub limit: Option<i64>,
+}
+
+impl Payload for SecurityEventsQuery {}
+
+#[derive(Deserialize, JsonSchema, Validate)]
+pub struct FileDownloadQuery {
+    #[validate(length(min = 1, max = 1024))]
+    pub path: String,
+}
+
+impl Payload for FileDownloadQuery {}
+
+#[derive(Deserialize, JsonSchema, Validate)]
+pub struct UploadArtifactQuery {
+    #[validate(length(min = 1, max = 255))]
+    pub file_name: String,
+}
+
+impl Payload for UploadArtifactQuery {}
+
+// 导入 iCalendar 文件的目标日历，同 id 的日历被替换
+#[derive(Deserialize, JsonSchema, Validate)]
+pub struct HolidayImportQuery {
+    #[validate(length(min = 1, max = 64))]
+    pub id: String,
+    #[validate(length(min = 1, max = 100))]
+    pub name: String,
+    #[validate(length(max = 64))]
+    pub region: Option<String>,
+}
+
+impl Payload for HolidayImportQuery {}
+
+#[derive(Deserialize, JsonSchema, Validate)]
+pub struct FileTransferQuery {
+    #[validate(length(max = 64))]
+    pub user_id: Option<String>,
+    #[validate(length(max = 100))]
+    pub device_id: Option<String>,
+    #[validate(length(max = 1024))]
+    pub path: Option<String>,
+    #[validate(length(max = 64))]
+    pub file_hash: Option<String>,
+    #[validate(length(max = 32))]
+    pub status: Option<String>,
+    pub since: Option<u64>,
+    pub until: Option<u64>,
+    #[validate(range(max = 1000000))]
+    pub page: Option<u64>,
+    #[validate(range(min = 1, max = 1000))]
+    pub limit: Option<u64>,
+}
+
+impl Payload for FileTransferQuery {}
+
+#[derive(Deserialize, JsonSchema, Validate)]
+pub struct InventoryQuery {
+    #[validate(length(max = 256))]
+    pub hostname: Option<String>,
+    #[validate(length(max = 64))]
+    pub os: Option<String>,
+    #[validate(length(max = 64))]
+    pub os_build: Option<String>,
+    #[validate(length(max = 256))]
+    pub cpu: Option<String>,
+    #[validate(length(max = 32))]
+    pub version: Option<String>,
+    pub min_memory: Option<u64>,
+    pub max_memory: Option<u64>,
+    #[validate(range(max = 1000000))]
+    pub page: Option<u64>,
+    #[validate(range(min = 1, max = 1000))]
+    pub limit: Option<u64>,
+}
+
+impl Payload for InventoryQuery {}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
 pub struct LoginResponse {
     pub success: bool,
     pub token: Option<String>,
     pub user: Option<UserInfo>,
     pub message: String,
+    // 策略要求绑定2FA时为 true，此时 token 只能用于 /api/auth/2fa/*
+    #[serde(default)]
+    pub require_2fa_setup: bool,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct TwoFactorSetupResponse {
+    pub secret: String,
+    pub otpauth_url: String,
+}
+
+#[derive(Deserialize, JsonSchema, Validate)]
+pub struct TwoFactorConfirmRequest {
+    #[validate(length(min = 1, max = 16))]
+    pub code: String,
 }
 
-#[derive(Serialize, Deserialize)]
+impl Payload for TwoFactorConfirmRequest {}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
 pub struct UserInfo {
     pub id: String,
     pub username: String,
@@ -46,385 +330,11667 @@ pub struct UserInfo {
     pub last_login: Option<u64>,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, JsonSchema, Validate)]
 pub struct CreateUserRequest {
+    #[validate(length(min = 3, max = 128), custom = "validation::username")]
     pub username: String,
     pub password: String,
+    // 空字符串表示不设置邮箱
+    #[validate(length(max = 254), custom = "validation::email")]
     pub email: Option<String>,
+    #[validate(custom = "validation::role")]
+    pub role: String,
+    #[validate(custom = "validation::non_empty_items")]
+    pub groups: Vec<String>,
+}
+
+impl Payload for CreateUserRequest {}
+
+#[derive(Serialize, Deserialize, JsonSchema, Validate)]
+pub struct ForgotPasswordRequest {
+    #[validate(length(min = 1, max = 254))]
+    pub username: String,
+}
+
+impl Payload for ForgotPasswordRequest {}
+
+#[derive(Serialize, Deserialize, JsonSchema, Validate)]
+pub struct ResetPasswordRequest {
+    #[validate(length(min = 1, max = 256))]
+    pub token: String,
+    #[validate(length(min = 1, max = 1024))]
+    pub password: String,
+}
+
+impl Payload for ResetPasswordRequest {}
+
+// 邀请用户，username 为空时使用邮箱作为用户名
+#[derive(Serialize, Deserialize, JsonSchema, Validate)]
+pub struct InviteUserRequest {
+    #[validate(length(min = 1, max = 254), custom = "validation::email")]
+    pub email: String,
+    #[serde(default)]
+    #[validate(length(max = 128), custom = "validation::username")]
+    pub username: Option<String>,
+    #[validate(custom = "validation::role")]
     pub role: String,
+    #[serde(default)]
+    #[validate(custom = "validation::non_empty_items")]
     pub groups: Vec<String>,
 }
 
-#[derive(Serialize, Deserialize)]
+impl Payload for InviteUserRequest {}
+
+#[derive(Deserialize, JsonSchema, Validate)]
+pub struct InviteTokenQuery {
+    #[validate(length(min = 1, max = 256))]
+    pub token: String,
+}
+
+impl Payload for InviteTokenQuery {}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct InvitePreview {
+    pub username: String,
+    pub email: String,
+    pub expires_at: u64,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Validate)]
+pub struct AcceptInviteRequest {
+    #[validate(length(min = 1, max = 256))]
+    pub token: String,
+    #[validate(length(min = 1, max = 1024))]
+    pub password: String,
+}
+
+impl Payload for AcceptInviteRequest {}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
 pub struct DeviceListResponse {
     pub devices: Vec<DeviceInfo>,
     pub total: usize,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, JsonSchema)]
 pub struct AuditLogResponse {
     pub logs: Vec<AuditLog>,
     pub total: usize,
+    pub device_aliases: HashMap<String, String>, // 设备ID -> 别名
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, JsonSchema)]
 pub struct ApiResponse<T> {
     pub success: bool,
     pub data: Option<T>,
     pub message: String,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, JsonSchema, Validate)]
 pub struct PaginationQuery {
+    #[validate(range(max = 1000000))]
     pub page: Option<u64>,
+    #[validate(range(min = 1, max = 1000))]
     pub limit: Option<u64>,
 }
 
-#[derive(Deserialize)]
-pub struct AuditLogQuery {
-    pub user_id: Option<String>,
-    pub device_id: Option<String>,
+impl Payload for PaginationQuery {}
+
+// 设备查询，view 为已保存视图的ID，其余条件覆盖视图中的同名条件
+#[derive(Deserialize, JsonSchema, Validate)]
+pub struct DeviceListQuery {
+    #[validate(length(max = 64))]
+    pub view: Option<String>,
+    #[validate(length(max = 256))]
+    pub tags: Option<String>, // 逗号分隔，需包含全部标签
+    #[validate(length(max = 64))]
+    pub os: Option<String>,
+    #[validate(length(max = 64))]
+    pub group_id: Option<String>,
+    pub online: Option<bool>,
+    #[validate(length(max = 16))]
+    pub offline_for: Option<String>, // 如 24h、7d，或秒数
+    #[validate(length(max = 16))]
+    pub seen_within: Option<String>,
+    #[validate(length(max = 256))]
+    pub q: Option<String>,
+    #[validate(range(max = 1000000))]
     pub page: Option<u64>,
+    #[validate(range(min = 1, max = 1000))]
     pub limit: Option<u64>,
 }
 
-pub fn create_router(state: AppState) -> Router {
-    Router::new()
-        // 认证相关
-        .route("/api/auth/login", post(login))
-        .route("/api/auth/logout", post(logout))
-        .route("/api/auth/me", get(get_current_user))
-        
-        // 用户管理
-        .route("/api/users", get(list_users).post(create_user))
-        .route("/api/users/:id", get(get_user).put(update_user).delete(delete_user))
-        .route("/api/users/:id/reset-password", post(reset_user_password))
-        .route("/api/users/:id/toggle-status", post(toggle_user_status))
-        
-        // 设备管理
-        .route("/api/devices", get(list_devices))
-        .route("/api/devices/:id", get(get_device).put(update_device).delete(delete_device))
-        .route("/api/devices/:id/control", post(control_device))
-        
-        // 审计日志
-        .route("/api/audit-logs", get(get_audit_logs))
-        
-        // 系统统计
-        .route("/api/stats/dashboard", get(get_dashboard_stats))
-        .route("/api/stats/connections", get(get_connection_stats))
-        
-        // 系统设置
-        .route("/api/settings", get(get_settings).put(update_settings))
-        
-        .layer(CorsLayer::new().allow_origin(Any).allow_methods(Any).allow_headers(Any))
-        .layer(TraceLayer::new_for_http())
-        .with_state(state)
-}
+impl Payload for DeviceListQuery {}
 
-// 认证相关处理函数
-async fn login(
-    State(state): State<AppState>,
-    Json(req): Json<LoginRequest>,
-) -> Result<Json<LoginResponse>, StatusCode> {
-    log::info!("Login attempt for user: {}", req.username);
-    
-    // 查找用户
-    let user = match state.db.get_user_by_username(&req.username).await {
-        Ok(Some(user)) => user,
-        Ok(None) => {
-            return Ok(Json(LoginResponse {
-                success: false,
-                token: None,
-                user: None,
-                message: "用户名或密码错误".to_string(),
-            }));
+impl DeviceListQuery {
+    fn apply(&self, filter: &mut DeviceFilter) -> Result<(), String> {
+        if let Some(tags) = &self.tags {
+            filter.tags = device_views::parse_tags(tags);
         }
-        Err(e) => {
-            log::error!("Database error during login: {}", e);
-            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        if self.os.is_some() {
+            filter.os = self.os.clone();
         }
-    };
-
-    // 检查用户是否被锁定
-    if state.auth.is_user_locked(&user) {
-        return Ok(Json(LoginResponse {
-            success: false,
-            token: None,
-            user: None,
-            message: "账户已被锁定，请稍后再试".to_string(),
-        }));
+        if self.group_id.is_some() {
+            filter.group_id = self.group_id.clone();
+        }
+        if self.online.is_some() {
+            filter.online = self.online;
+        }
+        if let Some(x) = &self.offline_for {
+            filter.offline_for = Some(device_views::parse_duration(x).ok_or(format!("无效的时长: {}", x))?);
+        }
+        if let Some(x) = &self.seen_within {
+            filter.seen_within = Some(device_views::parse_duration(x).ok_or(format!("无效的时长: {}", x))?);
+        }
+        if self.q.is_some() {
+            filter.q = self.q.clone();
+        }
+        Ok(())
     }
+}
 
-    // 验证密码
-    if !state.auth.verify_password(&req.password, &user.password_hash) {
-        // 记录失败的登录尝试
-        let _ = state.db.update_user_login_info(&user.id, false).await;
-        
-        return Ok(Json(LoginResponse {
-            success: false,
-            token: None,
-            user: None,
-            message: "用户名或密码错误".to_string(),
-        }));
-    }
+// 按自定义字段查询，value 精确匹配，min/max 为范围
+#[derive(Deserialize, JsonSchema, Validate)]
+pub struct CustomFieldSearchQuery {
+    #[validate(length(min = 1, max = 64))]
+    pub key: String,
+    #[validate(length(max = 256))]
+    pub value: Option<String>,
+    #[validate(length(max = 256))]
+    pub min: Option<String>,
+    #[validate(length(max = 256))]
+    pub max: Option<String>,
+    #[validate(range(max = 1000000))]
+    pub page: Option<u64>,
+    #[validate(range(min = 1, max = 1000))]
+    pub limit: Option<u64>,
+}
 
-    // 如果启用了双因素认证，验证TOTP代码
-    if user.two_factor_enabled {
-        if let Some(totp_code) = req.totp_code {
-            // 这里应该验证TOTP代码
-            // 为了简化，暂时跳过
-        } else {
-            return Ok(Json(LoginResponse {
-                success: false,
-                token: None,
-                user: None,
-                message: "需要双因素认证代码".to_string(),
-            }));
-        }
-    }
+impl Payload for CustomFieldSearchQuery {}
 
-    // 生成JWT令牌
-    let token = match state.auth.generate_jwt(&user) {
-        Ok(token) => token,
-        Err(e) => {
-            log::error!("Failed to generate JWT: {}", e);
-            return Err(StatusCode::INTERNAL_SERVER_ERROR);
-        }
-    };
+#[derive(Deserialize, JsonSchema, Validate)]
+pub struct SaveDeviceViewRequest {
+    #[validate(length(min = 1, max = 64))]
+    pub name: String,
+    #[serde(default)]
+    pub filter: DeviceFilter,
+}
 
-    // 更新登录信息
-    let _ = state.db.update_user_login_info(&user.id, true).await;
+impl Payload for SaveDeviceViewRequest {}
 
-    // 记录审计日志
-    let audit_log = AuditLog {
-        id: 0,
-        user_id: user.id.clone(),
-        device_id: "system".to_string(),
-        action: "login".to_string(),
-        details: Some("用户登录".to_string()),
-        ip_address: "127.0.0.1".to_string(), // 这里应该从请求中获取真实IP
-        user_agent: None,
-        timestamp: SystemTime::now(),
-        success: true,
-    };
-    let _ = state.db.log_audit(&audit_log).await;
+#[derive(Deserialize, JsonSchema, Validate)]
+pub struct ProvisionIdsRequest {
+    #[validate(length(max = 10000), custom = "validation::non_empty_items")]
+    pub ids: Vec<String>,
+    #[validate(length(max = 500))]
+    pub note: Option<String>,
+}
 
-    let user_info = UserInfo {
-        id: user.id,
-        username: user.username,
-        email: user.email,
-        role: format!("{:?}", user.role),
-        groups: user.groups,
-        enabled: user.enabled,
-        last_login: user.last_login.map(|t| t.duration_since(std::time::UNIX_EPOCH).unwrap().as_secs()),
-    };
+impl Payload for ProvisionIdsRequest {}
 
-    Ok(Json(LoginResponse {
-        success: true,
-        token: Some(token),
-        user: Some(user_info),
-        message: "登录成功".to_string(),
-    }))
+// 客户端心跳请求（与 Pro 版 /api/heartbeat 兼容）
+#[derive(Deserialize, JsonSchema, Validate)]
+pub struct HeartbeatRequest {
+    #[validate(length(min = 1, max = 100))]
+    pub id: String,
+    #[validate(length(min = 1, max = 256))]
+    pub uuid: String,
+    #[serde(default)]
+    pub ver: Option<i64>,
+    #[serde(default)]
+    pub conns: Vec<i32>,
+    #[serde(default)]
+    pub modified_at: i64,
+    // 客户端支持的可选能力，如 quic
+    #[serde(default)]
+    #[validate(length(max = 32))]
+    pub caps: Vec<String>,
 }
 
-async fn logout(
-    State(_state): State<AppState>,
-    headers: HeaderMap,
-) -> Result<Json<ApiResponse<()>>, StatusCode> {
-    // 这里应该将JWT令牌加入黑名单
-    // 为了简化，暂时只返回成功响应
-    
-    Ok(Json(ApiResponse {
-        success: true,
-        data: Some(()),
-        message: "登出成功".to_string(),
-    }))
+impl Payload for HeartbeatRequest {}
+
+#[derive(Serialize, Deserialize, Default, JsonSchema)]
+pub struct HeartbeatResponse {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sysinfo: Option<bool>,
+    pub modified_at: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub strategy: Option<ClientStrategy>,
+    // 本设备作为网络唤醒代理时需要在局域网广播的 MAC 地址
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub wake: Vec<String>,
+    // 需要执行的远程任务，执行后通过 /api/jobs/:id/result 上报
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub jobs: Vec<ClientJob>,
+    // 需要提示用户的消息，用户确认后通过 /api/messages/:id/ack 回执
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub messages: Vec<ClientMessage>,
+    // 生效中的公告，每次心跳都会带上，客户端按 id 去重显示
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub announcements: Vec<ClientAnnouncement>,
+    // 管理员在会话中关闭的剪贴板/文件传输权限，被控端对对应会话生效
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub session_permissions: Vec<ClientSessionPermissions>,
+    // 客户端版本低于策略要求时提示强制更新
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub update: Option<UpdateRequired>,
+    // 中继会话的编码推荐，应用后通过 /api/sessions/:id/codec/ack 回执
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub codec: Vec<ClientCodecRecommendation>,
+    // 客户端声明支持 QUIC 且服务器已启用时返回 QUIC 端口和证书指纹
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub quic: Option<QuicInfo>,
+    // 浏览器发起、等待本设备应答的 WebRTC offer，应答通过 /api/webrtc/sessions/:id/device 提交
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub webrtc: Vec<ClientWebRtcOffer>,
 }
 
-async fn get_current_user(
-    State(state): State<AppState>,
-    headers: HeaderMap,
-) -> Result<Json<ApiResponse<UserInfo>>, StatusCode> {
-    let claims = match extract_claims_from_headers(&state.auth, &headers) {
-        Ok(claims) => claims,
-        Err(_) => return Err(StatusCode::UNAUTHORIZED),
-    };
+#[derive(Serialize, Deserialize, Default, JsonSchema)]
+pub struct ClientStrategy {
+    pub config_options: HashMap<String, String>,
+    pub extra: HashMap<String, String>,
+}
 
-    let user = match state.db.get_user_by_username(&claims.username).await {
-        Ok(Some(user)) => user,
-        Ok(None) => return Err(StatusCode::NOT_FOUND),
-        Err(_) => return Err(StatusCode::INTERNAL_SERVER_ERROR),
-    };
+#[derive(Deserialize, JsonSchema, Validate)]
+pub struct SysinfoRequest {
+    #[validate(length(min = 1, max = 100))]
+    pub id: String,
+    #[validate(length(min = 1, max = 256))]
+    pub uuid: String,
+    #[serde(default)]
+    #[validate(length(max = 256))]
+    pub cpu: String,
+    #[serde(default)]
+    #[validate(length(max = 64))]
+    pub memory: String,
+    #[serde(default)]
+    #[validate(length(max = 256))]
+    pub os: String,
+    #[serde(default)]
+    #[validate(length(max = 256))]
+    pub hostname: String,
+    #[serde(default)]
+    #[validate(length(max = 256))]
+    pub username: String,
+    #[serde(default)]
+    #[validate(length(max = 32))]
+    pub version: String,
+    #[serde(default)]
+    #[validate(length(max = 64))]
+    pub mac: String,
+    // 系统版本号，未上报时从 os 中解析
+    #[serde(default)]
+    #[validate(length(max = 64))]
+    pub os_build: String,
+    // 可用的视频编码器，旧版客户端不上报
+    #[serde(default)]
+    pub encoders: Option<Vec<EncoderCapability>>,
+}
 
-    let user_info = UserInfo {
-        id: user.id,
-        username: user.username,
-        email: user.email,
-        role: format!("{:?}", user.role),
-        groups: user.groups,
-        enabled: user.enabled,
-        last_login: user.last_login.map(|t| t.duration_since(std::time::UNIX_EPOCH).unwrap().as_secs()),
+impl Payload for SysinfoRequest {}
+
+#[derive(Deserialize, JsonSchema, Validate)]
+pub struct ImpersonateRequest {
+    #[validate(length(min = 1, max = 64))]
+    pub user_id: String,
+    #[validate(length(min = 1, max = 1000))]
+    pub reason: String,
+}
+
+impl Payload for ImpersonateRequest {}
+
+// 配置版本查询，key 为空时返回全部策略的版本
+#[derive(Deserialize, JsonSchema, Validate)]
+pub struct ConfigVersionQuery {
+    #[validate(length(min = 1, max = 64))]
+    pub key: Option<String>,
+    #[validate(range(max = 1000000))]
+    pub page: Option<u64>,
+    #[validate(range(min = 1, max = 1000))]
+    pub limit: Option<u64>,
+}
+
+impl Payload for ConfigVersionQuery {}
+
+// to 为空时与最新版本比较
+#[derive(Deserialize, JsonSchema, Validate)]
+pub struct ConfigDiffQuery {
+    #[validate(range(min = 1))]
+    pub from: i64,
+    #[validate(range(min = 1))]
+    pub to: Option<i64>,
+}
+
+impl Payload for ConfigDiffQuery {}
+
+#[derive(Deserialize, JsonSchema, Validate)]
+pub struct RollbackRequest {
+    #[validate(range(min = 1))]
+    pub version: i64,
+}
+
+impl Payload for RollbackRequest {}
+
+// 变更审批队列查询，status 为空时返回全部
+#[derive(Deserialize, JsonSchema, Validate)]
+pub struct SettingChangeQuery {
+    pub status: Option<ChangeStatus>,
+    #[validate(range(max = 1000000))]
+    pub page: Option<u64>,
+    #[validate(range(min = 1, max = 1000))]
+    pub limit: Option<u64>,
+}
+
+impl Payload for SettingChangeQuery {}
+
+#[derive(Deserialize, JsonSchema, Validate)]
+pub struct ReviewSettingChangeRequest {
+    #[validate(length(max = 1000))]
+    pub comment: Option<String>,
+}
+
+impl Payload for ReviewSettingChangeRequest {}
+
+#[derive(Deserialize, JsonSchema, Validate)]
+pub struct RelayDrainRequest {
+    #[validate(length(min = 1, max = 256))]
+    pub server: String,
+    pub draining: bool,
+}
+
+impl Payload for RelayDrainRequest {}
+
+#[derive(Deserialize, JsonSchema, Validate)]
+pub struct CreateEmergencyAccessRequest {
+    #[validate(length(min = 1, max = 100))]
+    pub device_id: String,
+    #[validate(length(min = 1, max = 1000))]
+    pub justification: String,
+}
+
+impl Payload for CreateEmergencyAccessRequest {}
+
+#[derive(Deserialize, JsonSchema, Validate)]
+pub struct ReviewEmergencyAccessRequest {
+    #[serde(default)]
+    #[validate(length(max = 1000))]
+    pub notes: String,
+}
+
+impl Payload for ReviewEmergencyAccessRequest {}
+
+#[derive(Deserialize, JsonSchema, Validate)]
+pub struct EmergencyAccessQuery {
+    pub pending_review: Option<bool>,
+    #[validate(range(max = 1000000))]
+    pub page: Option<u64>,
+    #[validate(range(min = 1, max = 1000))]
+    pub limit: Option<u64>,
+}
+
+impl Payload for EmergencyAccessQuery {}
+
+// subject 和 id 同时指定时按被擦除主体查找报告
+#[derive(Deserialize, JsonSchema, Validate)]
+pub struct ErasureReportQuery {
+    pub subject: Option<ErasureSubject>,
+    #[validate(length(min = 1, max = 128))]
+    pub id: Option<String>,
+    #[validate(range(max = 1000000))]
+    pub page: Option<u64>,
+    #[validate(range(min = 1, max = 1000))]
+    pub limit: Option<u64>,
+}
+
+impl Payload for ErasureReportQuery {}
+
+#[derive(Deserialize, JsonSchema, Validate)]
+pub struct CreateJobRequest {
+    #[validate(length(min = 1, max = 100))]
+    pub name: String,
+    pub command: String,
+    pub shell: JobShell,
+    #[serde(default)]
+    pub group_ids: Vec<String>,
+    #[serde(default)]
+    #[validate(custom = "validation::non_empty_items")]
+    pub device_ids: Vec<String>,
+    #[serde(default = "default_job_timeout_secs")]
+    pub timeout_secs: u64,
+    // 任务的下发有效期，默认 7 天
+    #[serde(default)]
+    pub expires_in_secs: Option<u64>,
+}
+
+impl Payload for CreateJobRequest {
+    fn group_ids(&self) -> Vec<(&'static str, &[String])> {
+        vec![("group_ids", &self.group_ids)]
+    }
+}
+
+fn default_job_timeout_secs() -> u64 {
+    600
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct JobDetail {
+    pub job: Job,
+    pub runs: Vec<JobRun>,
+}
+
+#[derive(Deserialize, JsonSchema, Validate)]
+pub struct CreateMessageRequest {
+    #[serde(default)]
+    pub title: String,
+    pub body: String,
+    #[serde(default)]
+    pub level: MessageLevel,
+    #[serde(default)]
+    pub group_ids: Vec<String>,
+    #[serde(default)]
+    #[validate(custom = "validation::non_empty_items")]
+    pub device_ids: Vec<String>,
+    // 消息的下发有效期，默认 1 天
+    #[serde(default)]
+    pub expires_in_secs: Option<u64>,
+}
+
+impl Payload for CreateMessageRequest {
+    fn group_ids(&self) -> Vec<(&'static str, &[String])> {
+        vec![("group_ids", &self.group_ids)]
+    }
+}
+
+// 发布或编辑公告，starts_at 为空时新公告立即生效、编辑时保持原开始时间
+#[derive(Serialize, Deserialize, JsonSchema, Validate)]
+pub struct SaveAnnouncementRequest {
+    #[validate(length(min = 1, max = 200))]
+    pub title: String,
+    #[serde(default)]
+    #[validate(length(max = 4000))]
+    pub body: String,
+    #[serde(default)]
+    pub level: MessageLevel,
+    #[serde(default)]
+    pub starts_at: Option<u64>,
+    pub ends_at: u64,
+    #[serde(default)]
+    pub deliver_to_clients: bool,
+}
+
+impl Payload for SaveAnnouncementRequest {}
+
+// 许可证内容为供应商签发的 base64 文本
+#[derive(Serialize, Deserialize, JsonSchema, Validate)]
+pub struct InstallLicenseRequest {
+    #[validate(length(min = 1, max = 65536))]
+    pub license: String,
+}
+
+impl Payload for InstallLicenseRequest {}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct MessageDetail {
+    pub message: DeviceMessage,
+    pub deliveries: Vec<MessageDelivery>,
+}
+
+#[derive(Deserialize, JsonSchema, Validate)]
+pub struct MessageAckRequest {
+    #[validate(length(min = 1, max = 100))]
+    pub id: String,
+    #[validate(length(min = 1, max = 256))]
+    pub uuid: String,
+}
+
+impl Payload for MessageAckRequest {}
+
+#[derive(Deserialize, JsonSchema, Validate)]
+pub struct DeviceWebRtcSignalRequest {
+    #[validate(length(min = 1, max = 100))]
+    pub id: String,
+    #[validate(length(min = 1, max = 256))]
+    pub uuid: String,
+    #[serde(flatten)]
+    pub signal: DeviceSignal,
+}
+
+impl Payload for DeviceWebRtcSignalRequest {}
+
+#[derive(Deserialize, JsonSchema, Validate)]
+pub struct CreateWebRtcSessionRequest {
+    #[validate(length(min = 1, max = 100))]
+    pub device_id: String,
+    #[validate(length(min = 1, max = 65536))]
+    pub sdp: String,
+}
+
+impl Payload for CreateWebRtcSessionRequest {}
+
+#[derive(Deserialize, JsonSchema, Validate)]
+pub struct WebRtcSignalRequest {
+    #[serde(default)]
+    #[validate(length(max = 100))]
+    pub candidates: Vec<IceCandidate>,
+}
+
+impl Payload for WebRtcSignalRequest {}
+
+#[derive(Deserialize, JsonSchema, Validate)]
+pub struct CodecAckRequest {
+    #[validate(length(min = 1, max = 100))]
+    pub id: String,
+    #[validate(length(min = 1, max = 256))]
+    pub uuid: String,
+    #[serde(flatten)]
+    pub ack: CodecAck,
+}
+
+impl Payload for CodecAckRequest {}
+
+// bbr、cubic、reno、vegas 或 off
+#[derive(Deserialize, JsonSchema, Validate)]
+pub struct CongestionControlRequest {
+    #[validate(length(min = 1, max = 16))]
+    pub algorithm: String,
+}
+
+impl Payload for CongestionControlRequest {}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct SessionInfo {
+    pub session: RelaySession,
+    pub permissions: Option<SessionPermissions>,
+}
+
+#[derive(Deserialize, JsonSchema, Validate)]
+pub struct SessionEventsRequest {
+    #[validate(length(min = 1, max = 100))]
+    pub id: String,
+    #[validate(length(min = 1, max = 256))]
+    pub uuid: String,
+    #[validate(length(min = 1, max = 128))]
+    pub session_id: String,
+    #[validate(length(max = 1000))]
+    pub events: Vec<ClientSessionEvent>,
+}
+
+impl Payload for SessionEventsRequest {}
+
+#[derive(Deserialize, JsonSchema, Validate)]
+pub struct SessionQosRequest {
+    #[validate(length(min = 1, max = 100))]
+    pub id: String,
+    #[validate(length(min = 1, max = 256))]
+    pub uuid: String,
+    #[validate(length(min = 1, max = 128))]
+    pub session_id: String,
+    #[validate(length(max = 1000))]
+    pub samples: Vec<ClientQosSample>,
+}
+
+impl Payload for SessionQosRequest {}
+
+#[derive(Deserialize, JsonSchema, Validate)]
+pub struct SelfTestStartRequest {
+    #[validate(length(min = 1, max = 100))]
+    pub id: String,
+    #[validate(length(min = 1, max = 256))]
+    pub uuid: String,
+}
+
+impl Payload for SelfTestStartRequest {}
+
+#[derive(Deserialize, JsonSchema, Validate)]
+pub struct SelfTestReportRequest {
+    #[validate(length(min = 1, max = 100))]
+    pub id: String,
+    #[validate(length(min = 1, max = 256))]
+    pub uuid: String,
+    pub report: ClientSelfTestReport,
+}
+
+impl Payload for SelfTestReportRequest {}
+
+#[derive(Serialize, JsonSchema)]
+pub struct SessionEventsDetail {
+    // 直连会话没有登记时为空
+    pub session: Option<ConnectionSession>,
+    pub events: Vec<SessionEvent>,
+}
+
+#[derive(Deserialize, JsonSchema, Validate)]
+pub struct JobResultRequest {
+    #[validate(length(min = 1, max = 100))]
+    pub id: String,
+    #[validate(length(min = 1, max = 256))]
+    pub uuid: String,
+    #[serde(default)]
+    pub exit_code: Option<i32>,
+    #[serde(default)]
+    #[validate(length(max = 1048576))]
+    pub output: String,
+    #[serde(default)]
+    pub timed_out: bool,
+}
+
+impl Payload for JobResultRequest {}
+
+#[derive(Deserialize, JsonSchema, Validate)]
+pub struct WakeDeviceRequest {
+    // 客户端未上报 MAC 地址时由管理员指定，保存后用于之后的唤醒
+    #[serde(default)]
+    #[validate(custom = "validation::mac_address")]
+    pub mac_address: Option<String>,
+}
+
+impl Payload for WakeDeviceRequest {}
+
+#[derive(Deserialize, JsonSchema, Validate)]
+pub struct SaveStrategyRequest {
+    #[validate(length(min = 1, max = 100))]
+    pub name: String,
+    #[validate(length(max = 1000))]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub config_options: HashMap<String, String>,
+    #[serde(default)]
+    pub group_ids: Vec<String>,
+    #[serde(default)]
+    #[validate(custom = "validation::non_empty_items")]
+    pub device_ids: Vec<String>,
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+}
+
+impl Payload for SaveStrategyRequest {
+    fn group_ids(&self) -> Vec<(&'static str, &[String])> {
+        vec![("group_ids", &self.group_ids)]
+    }
+}
+
+fn default_true() -> bool {
+    true
+}
+
+#[derive(Deserialize, JsonSchema, Validate)]
+pub struct BanDeviceRequest {
+    #[validate(length(max = 500))]
+    pub reason: String,
+    pub duration_secs: Option<u64>, // 不填表示永久封禁
+}
+
+impl Payload for BanDeviceRequest {}
+
+#[derive(Deserialize, JsonSchema, Validate)]
+pub struct SetAliasRequest {
+    #[validate(length(min = 1, max = 64), custom = "validation::alias")]
+    pub alias: String,
+}
+
+impl Payload for SetAliasRequest {}
+
+#[derive(Deserialize, JsonSchema, Validate)]
+pub struct AuditLogQuery {
+    pub user_id: Option<String>,
+    pub device_id: Option<String>,
+    pub action: Option<String>,
+    pub success: Option<bool>,
+    pub since: Option<u64>,
+    pub until: Option<u64>,
+    // 来源IP或网段，如 10.1.0.0/16
+    #[validate(custom = "validation::cidr")]
+    pub ip: Option<String>,
+    // 详情关键字
+    pub q: Option<String>,
+    pub page: Option<u64>,
+    pub limit: Option<u64>,
+}
+
+impl Payload for AuditLogQuery {}
+
+#[derive(Deserialize, JsonSchema, Validate)]
+pub struct UptimeQuery {
+    // 时间窗口，如 24h、7d、30d，默认 30d；指定 since 时忽略
+    #[validate(length(max = 16))]
+    pub window: Option<String>,
+    pub since: Option<u64>,
+    pub until: Option<u64>,
+    #[validate(length(max = 64))]
+    pub group_id: Option<String>,
+}
+
+impl Payload for UptimeQuery {}
+
+#[derive(Deserialize, JsonSchema, Validate)]
+pub struct QualityQuery {
+    // 时间窗口，如 24h、7d，默认 7d；指定 since 时忽略
+    #[validate(length(max = 16))]
+    pub window: Option<String>,
+    pub since: Option<u64>,
+    pub until: Option<u64>,
+    // 汇总粒度，如 1h、1d，默认 1h
+    #[validate(length(max = 16))]
+    pub bucket: Option<String>,
+    // 只看该中继服务器
+    #[validate(length(max = 256))]
+    pub relay_server: Option<String>,
+}
+
+impl Payload for QualityQuery {}
+
+impl QualityQuery {
+    fn range(&self) -> Option<(u64, u64, u64)> {
+        let now = crate::common::now();
+        let until = self.until.unwrap_or(now).min(now);
+        let since = match self.since {
+            Some(since) => since,
+            None => until.saturating_sub(uptime::parse_window(self.window.as_deref().unwrap_or("7d"))?),
+        };
+        let bucket = uptime::parse_window(self.bucket.as_deref().unwrap_or("1h"))?;
+        if since >= until || until - since > connection_quality::RETENTION_SECS || bucket > until - since {
+            return None;
+        }
+        Some((since, until, bucket))
+    }
+}
+
+// 设备时间线查询，kinds 为逗号分隔的类型，为空时返回全部类型
+#[derive(Deserialize, JsonSchema, Validate)]
+pub struct TimelineQuery {
+    // 时间窗口，如 24h、7d，默认 30d；指定 since 时忽略
+    #[validate(length(max = 16))]
+    pub window: Option<String>,
+    pub since: Option<u64>,
+    pub until: Option<u64>,
+    #[validate(length(max = 256))]
+    pub kinds: Option<String>,
+    #[validate(range(min = 1, max = 1000))]
+    pub limit: Option<usize>,
+}
+
+impl Payload for TimelineQuery {}
+
+impl TimelineQuery {
+    fn range(&self) -> Option<(u64, u64)> {
+        let now = crate::common::now();
+        let until = self.until.unwrap_or(now).min(now);
+        let since = match self.since {
+            Some(since) => since,
+            None => until.saturating_sub(uptime::parse_window(self.window.as_deref().unwrap_or("30d"))?),
+        };
+        if since >= until || until - since > uptime::MAX_WINDOW_SECS {
+            return None;
+        }
+        Some((since, until))
+    }
+}
+
+#[derive(Deserialize, JsonSchema, Validate)]
+pub struct RecentQuery {
+    // 默认 20，最多 50
+    #[validate(range(min = 1, max = 50))]
+    pub limit: Option<usize>,
+}
+
+impl Payload for RecentQuery {}
+
+#[derive(Deserialize, JsonSchema, Validate)]
+pub struct SaveDeviceNoteRequest {
+    #[validate(length(min = 1, max = 4000))]
+    pub body: String,
+}
+
+impl Payload for SaveDeviceNoteRequest {}
+
+impl UptimeQuery {
+    fn range(&self) -> Option<(u64, u64)> {
+        let now = crate::common::now();
+        let until = self.until.unwrap_or(now).min(now);
+        let since = match self.since {
+            Some(since) => since,
+            None => until.saturating_sub(uptime::parse_window(self.window.as_deref().unwrap_or("30d"))?),
+        };
+        if since >= until || until - since > uptime::MAX_WINDOW_SECS {
+            return None;
+        }
+        Some((since, until))
+    }
+}
+
+pub fn create_router(state: AppState) -> Router {
+    Router::new()
+        // 客户端接口（设备以 id + uuid 标识，不使用JWT）
+        .route("/api/heartbeat", post(client_heartbeat))
+        .route("/api/sysinfo", post(client_sysinfo))
+        .route("/api/jobs/:id/result", post(client_job_result))
+        .route("/api/messages/:id/ack", post(client_message_ack))
+        .route("/api/session-events", post(client_session_events))
+        .route("/api/session-qos", post(client_session_qos))
+        .route("/api/self-test", post(client_start_self_test))
+        .route("/api/self-test/:id/result", post(client_self_test_result))
+        .route("/api/sessions/:id/codec/ack", post(client_codec_ack))
+        .route("/api/webrtc/sessions/:id/device", post(client_webrtc_signal))
+        // 联邦中其他区域信令服务器的接口，以共享密钥认证
+        .route("/api/federation/lookup/:id", get(federation_lookup))
+        .route("/api/federation/forward", post(federation_forward))
+        .route("/api/federation/deliver", post(federation_deliver))
+        // 客户端安装包清单和下载，无需认证
+        .route("/api/updates/manifest", get(get_update_manifest))
+        .route("/api/updates/:platform/:version/download", get(download_update_artifact))
+        .route("/api/updates/:platform/:version/sha256", get(get_update_checksum))
+        // 接口文档，无需认证
+        .route("/api/openapi.json", get(get_openapi_document))
+        .route("/api/docs", get(get_api_docs))
+        .route("/api/errors", get(get_error_catalog))
+
+        // 认证相关
+        .route("/api/auth/login", post(login))
+        .route("/api/auth/logout", post(logout))
+        .route("/api/auth/me", get(get_current_user))
+        .route("/api/auth/session", get(get_web_session))
+        .route("/api/auth/session/keepalive", post(keep_web_session_alive))
+        .route("/api/auth/2fa/setup", post(setup_two_factor))
+        .route("/api/auth/2fa/confirm", post(confirm_two_factor))
+        .route("/api/auth/2fa/email", post(send_email_otp))
+        .route("/api/auth/forgot-password", post(forgot_password))
+        .route("/api/auth/reset-password", post(reset_password))
+        .route("/api/auth/trusted-devices", get(list_trusted_devices).delete(revoke_all_trusted_devices))
+        .route("/api/auth/trusted-devices/:id", delete(revoke_trusted_device))
+        .route("/api/auth/impersonation/end", post(end_impersonation))
+        .route("/api/admin/impersonate", post(start_impersonation))
+        .route("/api/admin/impersonations", get(list_impersonations))
+        // 被邀请用户设置密码，凭邀请链接中的令牌，无需登录
+        .route("/api/invites/accept", get(get_invite).post(accept_invite))
+        
+        // 用户管理
+        .route("/api/users", get(list_users).post(create_user))
+        .route("/api/users/:id", get(get_user).put(update_user).delete(delete_user))
+        .route("/api/users/:id/reset-password", post(reset_user_password))
+        .route("/api/users/:id/toggle-status", post(toggle_user_status))
+        .route("/api/users/:id/email-otp", post(grant_email_otp).delete(revoke_email_otp))
+        .route("/api/users/:id/trusted-devices", delete(revoke_user_trusted_devices))
+        .route("/api/users/:id/fields", get(get_user_fields).put(set_user_fields))
+        .route(
+            "/api/users/:id/notification-preferences",
+            get(get_user_notification_preferences).put(update_user_notification_preferences),
+        )
+        .route(
+            "/api/notification-preferences",
+            get(get_my_notification_preferences).put(update_my_notification_preferences),
+        )
+        .route("/api/language", get(get_my_language).put(update_my_language))
+        .route("/api/me/recent", get(list_recent_devices).delete(clear_recent_devices))
+        .route("/api/me/favorites", get(list_favorite_devices))
+        .route("/api/me/favorites/:id", put(add_favorite_device).delete(remove_favorite_device))
+        .route("/api/invites", get(list_invites).post(invite_user))
+        .route("/api/invites/:id", delete(revoke_invite))
+        .route("/api/invites/:id/resend", post(resend_invite))
+        
+        // 设备管理
+        .route("/api/devices", get(list_devices))
+        .route("/api/device-views", get(list_device_views).post(create_device_view))
+        .route("/api/device-views/:id", put(update_device_view).delete(delete_device_view))
+        .route("/api/devices/:id", get(get_device).put(update_device).delete(delete_device))
+        .route("/api/devices/:id/control", post(control_device))
+        // 浏览器网页客户端的 WebRTC 信令
+        .route("/api/webrtc/sessions", post(create_webrtc_session))
+        .route("/api/webrtc/sessions/:id", delete(close_webrtc_session))
+        .route("/api/webrtc/sessions/:id/signal", post(webrtc_signal))
+        .route("/api/turn/credentials", get(get_turn_credentials))
+        .route("/api/devices/:id/wake", post(wake_device))
+        .route("/api/devices/:id/inventory", get(get_device_inventory))
+        .route("/api/devices/:id/capabilities", get(get_device_capabilities))
+        .route("/api/devices/:id/fields", get(get_device_fields).put(set_device_fields))
+        .route("/api/custom-fields/:entity/search", get(search_custom_fields))
+        .route("/api/devices/:id/uptime", get(get_device_uptime))
+        .route("/api/devices/:id/status-history", get(get_device_status_history))
+        .route("/api/devices/:id/timeline", get(get_device_timeline))
+        .route("/api/devices/:id/pk-history", get(get_device_pk_history))
+        .route("/api/devices/:id/notes", get(list_device_notes).post(create_device_note))
+        .route("/api/devices/:id/notes/:note_id", put(update_device_note).delete(delete_device_note))
+        .route("/api/devices/:id/quality", get(get_device_quality))
+        .route("/api/uptime", get(list_device_uptime))
+        .route("/api/inventory", get(list_inventory))
+        .route("/api/inventory/report", get(get_inventory_report))
+        .route("/api/devices/:id/nat-diagnostics", get(get_device_nat_diagnostics))
+        .route("/api/devices/:id/self-tests", get(get_device_self_tests))
+        .route("/api/devices/:id/alias", put(set_device_alias).delete(remove_device_alias))
+        .route("/api/aliases", get(list_device_aliases))
+        .route("/api/devices/:id/ban", post(ban_device).delete(unban_device))
+        .route("/api/bans", get(list_device_bans))
+        .route("/api/devices/:id/strategy", get(get_device_strategy))
+        .route("/api/strategies", get(list_strategies).post(create_strategy))
+        .route("/api/strategies/:id", put(update_strategy).delete(delete_strategy))
+        .route("/api/updates", get(list_update_artifacts))
+        .route(
+            "/api/updates/:platform/:version",
+            put(upload_update_artifact)
+                .delete(delete_update_artifact)
+                .layer(DefaultBodyLimit::max(software_update::MAX_ARTIFACT_SIZE)),
+        )
+        .route("/api/jobs", get(list_jobs).post(create_job))
+        .route("/api/jobs/:id", get(get_job).delete(cancel_job))
+        .route("/api/messages", get(list_messages).post(create_message))
+        .route("/api/messages/:id", get(get_message).delete(cancel_message))
+        .route("/api/announcements", get(list_announcements).post(create_announcement))
+        .route("/api/announcements/active", get(list_active_announcements))
+        .route("/api/announcements/:id", put(update_announcement).delete(delete_announcement))
+        .route("/api/sessions", get(list_sessions))
+        .route("/api/relays", get(list_relays))
+        .route("/api/relays/drain", put(update_relay_drain))
+        .route("/api/settings/federation", get(get_federation_config).put(update_federation_config))
+        .route("/api/settings/dns-discovery", get(get_dns_discovery_config).put(update_dns_discovery_config))
+        .route("/api/dns-discovery/records", get(get_dns_discovery_records))
+        .route("/api/dns-discovery/health", get(check_dns_discovery_health))
+        .route("/api/captures", get(list_captures).post(start_capture))
+        .route("/api/captures/:id", delete(delete_capture))
+        .route("/api/captures/:id/stop", post(stop_capture))
+        .route("/api/captures/:id/bundle", get(download_capture_bundle))
+        .route("/api/sessions/:id/permissions", put(update_session_permissions))
+        .route("/api/sessions/:id/events", get(get_session_events))
+        .route("/api/sessions/:id/codec", get(get_session_codec))
+        .route("/api/sessions/:id/congestion-control", put(update_session_congestion_control))
+        
+        // 审计日志
+        .route("/api/audit-logs", get(get_audit_logs))
+        
+        // 系统统计
+        .route("/api/stats/dashboard", get(get_dashboard_stats))
+        .route("/api/stats/connections", get(get_connection_stats))
+        .route("/api/stats/nat", get(get_nat_stats))
+        .route("/api/stats/relay-quality", get(get_relay_quality))
+        .route("/api/stats/e2e", get(get_e2e_stats))
+        .route("/api/stats/bandwidth-pools", get(get_bandwidth_pools))
+        .route("/api/stats/host", get(get_host_stats))
+        .route("/metrics", get(get_metrics))
+        .route("/api/security-events", get(list_security_events))
+        
+        // 系统设置
+        .route("/api/settings", get(get_settings).put(update_settings))
+        .route("/api/settings/relay-policy", get(get_relay_policy).put(update_relay_policy))
+        .route("/api/settings/logging", get(get_logging_config).put(update_logging_config))
+        .route("/api/settings/offline-alerts", get(get_offline_alert_config).put(update_offline_alert_config))
+        .route("/api/settings/custom-fields", get(get_custom_fields_config).put(update_custom_fields_config))
+        .route("/api/alerts/offline", get(list_offline_devices))
+        .route("/api/settings/kiosk-fleet", get(get_kiosk_fleet_config).put(update_kiosk_fleet_config))
+        .route("/api/kiosk/health", get(get_kiosk_fleet_health))
+        .route("/api/settings/backup", get(get_backup_config).put(update_backup_config))
+        .route("/api/admin/backup", get(list_backups).post(create_backup))
+        .route("/api/admin/backup/:name", get(download_backup))
+        .route("/api/admin/erasure", get(list_erasure_reports).post(erase_subject_data))
+        .route("/api/admin/erasure/:id", get(get_erasure_report))
+        .route("/api/settings/version-policy", get(get_version_policy).put(update_version_policy))
+        .route("/api/settings/unattended-access", get(get_unattended_access).put(update_unattended_access))
+        .route("/api/settings/holiday-calendars", get(get_holiday_calendars).put(update_holiday_calendars))
+        .route("/api/settings/holiday-calendars/import", post(import_holiday_calendar))
+        .route("/api/settings/four-eyes", get(get_four_eyes_policy).put(update_four_eyes_policy))
+        .route("/api/approvals", get(list_session_approvals))
+        .route("/api/approvals/:id/approve", post(approve_session))
+        .route("/api/approvals/:id/reject", post(reject_session))
+        .route("/api/break-glass", get(list_emergency_access).post(activate_emergency_access))
+        .route("/api/break-glass/:id", delete(end_emergency_access))
+        .route("/api/break-glass/:id/review", post(review_emergency_access))
+        .route("/api/settings/lan", get(get_lan_config).put(update_lan_config))
+        .route("/api/settings/id-policy", get(get_id_policy).put(update_id_policy))
+        .route("/api/settings/mfa-policy", get(get_mfa_policy).put(update_mfa_policy))
+        .route("/api/settings/e2e-policy", get(get_e2e_policy).put(update_e2e_policy))
+        .route("/api/settings/server-key", get(get_server_key))
+        .route("/api/settings/server-key/rotate", post(rotate_server_key))
+        .route("/api/settings/jwt-keys", get(get_jwt_keys))
+        .route("/api/settings/jwt-keys/rotate", post(rotate_jwt_keys))
+        .route("/api/settings/control-tls", get(get_control_tls).put(update_control_tls))
+        .route("/api/settings/control-tls/ca", post(create_control_tls_ca))
+        .route("/api/settings/control-tls/certs", post(issue_control_tls_cert))
+        .route("/api/settings/control-tls/certs/:fingerprint", delete(revoke_control_tls_cert))
+        .route("/api/settings/email-otp", get(get_email_otp_config).put(update_email_otp_config))
+        .route("/api/settings/dlp-policy", get(get_dlp_policy).put(update_dlp_policy))
+        .route("/api/settings/transfer-bandwidth", get(get_transfer_bandwidth).put(update_transfer_bandwidth))
+        .route("/api/settings/network-tuning", get(get_network_tuning).put(update_network_tuning))
+        .route("/api/settings/webrtc", get(get_webrtc_config).put(update_webrtc_config))
+        .route("/api/settings/turn", get(get_turn_config).put(update_turn_config))
+        .route("/api/settings/honeypot", get(get_honeypot_config).put(update_honeypot_config))
+        .route("/api/honeypot/ip-bans", get(list_honeypot_ip_bans))
+        .route("/api/honeypot/ip-bans/:ip", delete(unban_honeypot_ip))
+        .route("/api/settings/pk-pinning", get(get_pk_pinning_config).put(update_pk_pinning_config))
+        .route("/api/pk-changes", get(list_pk_changes))
+        .route("/api/pk-changes/:id/approve", post(approve_pk_change))
+        .route("/api/pk-changes/:id/reject", post(reject_pk_change))
+        .route("/api/settings/threat-intel", get(get_threat_intel_config).put(update_threat_intel_config))
+        .route("/api/settings/change-approval", get(get_change_approval_config).put(update_change_approval_config))
+        .route("/api/setting-changes", get(list_setting_changes))
+        .route("/api/settings/versions", get(list_config_versions))
+        .route("/api/settings/versions/diff", get(diff_config_versions))
+        .route("/api/settings/rollback", post(rollback_config))
+        .route("/api/setting-changes/:id/approve", post(approve_setting_change))
+        .route("/api/setting-changes/:id/reject", post(reject_setting_change))
+        .route("/api/setting-changes/:id/cancel", post(cancel_setting_change))
+        .route("/api/threat-intel/status", get(get_threat_intel_status))
+        .route("/api/threat-intel/refresh", post(refresh_threat_intel))
+        .route("/api/settings/content-scan", get(get_content_scan_config).put(update_content_scan_config))
+        .route("/api/settings/trusted-device", get(get_trusted_device_config).put(update_trusted_device_config))
+        .route("/api/settings/web-security", get(get_web_security_config).put(update_web_security_config))
+        .route("/api/settings/feature-flags", get(get_feature_flags).put(update_feature_flags))
+        .route("/api/settings/itsm", get(get_itsm_config).put(update_itsm_config))
+        .route("/api/settings/ad-sync", get(get_ad_sync_config).put(update_ad_sync_config))
+        .route("/api/settings/web-session", get(get_web_session_config).put(update_web_session_config))
+        .route("/api/settings/data-masking", get(get_data_masking_config).put(update_data_masking_config))
+        .route("/api/admin/ad-sync", get(get_ad_sync_report).post(run_ad_sync))
+        .route("/api/license", get(get_license).put(install_license))
+        .route("/api/settings/password-policies", get(get_password_policies).put(update_password_policies))
+        .route("/api/devices/:id/password-policy", get(get_device_password_policy))
+        .route("/api/provisioned-ids", get(list_provisioned_ids).post(add_provisioned_ids))
+        .route("/api/provisioned-ids/:id", delete(remove_provisioned_id))
+        
+        // 文件传输（可续传上传，PATCH 携带 Upload-Offset；下载支持 Range）
+        .route("/api/files", get(download_file).post(create_file_upload))
+        .route("/api/files/:id", head(get_file_upload_offset).patch(upload_file_chunk).delete(cancel_file_upload))
+        .route("/api/file-transfers", get(list_file_transfers))
+        
+        .layer(middleware::from_fn_with_state(state.clone(), change_approval_guard))
+        .layer(middleware::from_fn_with_state(state.clone(), auditor_guard))
+        .layer(middleware::from_fn_with_state(state.clone(), web_session_guard))
+        .layer(middleware::from_fn(error_codes::middleware))
+        .layer(middleware::from_fn_with_state(state.clone(), language))
+        .layer(middleware::from_fn(request_id))
+        .layer(middleware::from_fn(db_pool_metrics::middleware))
+        .layer(middleware::from_fn(web_security::middleware))
+        .layer(TraceLayer::new_for_http())
+        .with_state(state)
+}
+
+const REQUEST_ID_HEADER: &str = "x-request-id";
+
+// 为每个请求分配请求ID(沿用反向代理传入的 X-Request-Id)，处理期间的日志带上该ID并在响应头中返回
+async fn request_id(req: Request, next: Next) -> Response {
+    let id = req
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .filter(|v| !v.is_empty() && v.len() <= 64)
+        .map(str::to_owned)
+        .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+    let method = req.method().clone();
+    let path = req.uri().path().to_owned();
+    let started = std::time::Instant::now();
+    let mut res = REQUEST_ID
+        .scope(id.clone(), async move {
+            let res = next.run(req).await;
+            log::debug!("{} {} {} {}ms", method, path, res.status().as_u16(), started.elapsed().as_millis());
+            res
+        })
+        .await;
+    if let Ok(v) = HeaderValue::from_str(&id) {
+        res.headers_mut().insert(REQUEST_ID_HEADER, v);
+    }
+    res
+}
+
+// 确定请求的语言(用户偏好优先，其次 Accept-Language)，处理期间的接口消息、错误信息按该语言返回
+async fn language(State(state): State<AppState>, req: Request, next: Next) -> Response {
+    let user_id = extract_claims_from_headers(&state.auth, req.headers()).ok().map(|x| x.sub);
+    let accept_language = req
+        .headers()
+        .get(header::ACCEPT_LANGUAGE)
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_owned);
+    let lang = i18n::request_lang(user_id.as_deref(), accept_language.as_deref()).await;
+    i18n::LANG.scope(lang, next.run(req)).await
+}
+
+// 审计员只允许维护自己的登录状态，其余写操作一律拒绝
+const AUDITOR_ALLOWED_WRITES: &[&str] = &[
+    "/api/auth/login",
+    "/api/auth/logout",
+    "/api/auth/session/keepalive",
+    "/api/auth/2fa/setup",
+    "/api/auth/2fa/confirm",
+    "/api/auth/2fa/email",
+];
+// 只读接口中审计员也不能访问的(备份文件包含全部数据和密钥)
+const AUDITOR_DENIED_READS: &[&str] = &["/api/admin/backup/:name"];
+
+// 审计员只读: 在路由匹配后、进入各接口前统一拦截，新增的写接口无需单独判断角色。
+// 放在会话校验之内，会话已失效的令牌此时已被去掉，按未登录处理
+async fn auditor_guard(State(state): State<AppState>, req: Request, next: Next) -> Response {
+    let is_auditor = matches!(
+        extract_claims_from_headers(&state.auth, req.headers()),
+        Ok(claims) if claims.role == "Auditor"
+    );
+    if !is_auditor {
+        return next.run(req).await;
+    }
+    let path = req
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|p| p.as_str().to_owned())
+        .unwrap_or_else(|| req.uri().path().to_owned());
+    let read_only = [Method::GET, Method::HEAD, Method::OPTIONS].contains(req.method());
+    let allowed = if read_only {
+        !AUDITOR_DENIED_READS.contains(&path.as_str())
+    } else {
+        AUDITOR_ALLOWED_WRITES.contains(&path.as_str())
+    };
+    if !allowed {
+        log::warn!("Auditor request denied: {} {}", req.method(), path);
+        return StatusCode::FORBIDDEN.into_response();
+    }
+    next.run(req).await
+}
+
+// 变更审批: 需要审批的设置修改请求不交给设置接口处理，校验后进入待审批队列并返回 202
+async fn change_approval_guard(State(state): State<AppState>, req: Request, next: Next) -> Response {
+    if req.method() != Method::PUT {
+        return next.run(req).await;
+    }
+    let key = match change_approval::key_for_path(req.uri().path()) {
+        Some(key) => key,
+        None => return next.run(req).await,
+    };
+    if !change_approval::requires_approval(key).await {
+        return next.run(req).await;
+    }
+    let claims = match extract_claims_from_headers(&state.auth, req.headers()) {
+        Ok(claims) => claims,
+        Err(_) => return StatusCode::UNAUTHORIZED.into_response(),
+    };
+    if !change_approval::can_change(&claims.role, key) {
+        return StatusCode::FORBIDDEN.into_response();
+    }
+    let body = match axum::body::to_bytes(req.into_body(), change_approval::MAX_BODY_SIZE).await {
+        Ok(body) => body,
+        Err(_) => return StatusCode::PAYLOAD_TOO_LARGE.into_response(),
+    };
+    let value: serde_json::Value = match serde_json::from_slice(&body) {
+        Ok(value) => value,
+        Err(_) => return StatusCode::BAD_REQUEST.into_response(),
+    };
+    let res = change_approval::submit(&state.db, key, value, &claims).await;
+
+    let audit_log = AuditLog {
+        id: 0,
+        user_id: claims.sub,
+        device_id: "system".to_string(),
+        action: "submit_setting_change".to_string(),
+        details: Some(match &res {
+            Ok(change) => format!(
+                "{} ({}): {}",
+                key,
+                change.id,
+                serde_json::to_string(&change.diff).unwrap_or_default()
+            ),
+            Err(e) => format!("{}: {}", key, e),
+        }),
+        ip_address: "127.0.0.1".to_string(),
+        user_agent: None,
+        timestamp: SystemTime::now(),
+        success: res.is_ok(),
+    };
+    let _ = state.db.log_audit(&audit_log).await;
+
+    match res {
+        Ok(change) => (
+            StatusCode::ACCEPTED,
+            Json(ApiResponse {
+                success: true,
+                data: Some(change),
+                message: "变更已提交，需另一名管理员审批后生效".to_string(),
+            }),
+        )
+            .into_response(),
+        Err(e) => {
+            log::warn!("Failed to submit change to {}: {}", key, e);
+            Json(ApiResponse::<SettingChange> {
+                success: false,
+                data: None,
+                message: format!("变更提交失败: {}", e),
+            })
+            .into_response()
+        }
+    }
+}
+
+const SESSION_STATUS_PATH: &str = "/api/auth/session";
+const SESSION_EXPIRES_HEADER: &str = "x-session-expires-at";
+const SESSION_EXPIRED_HEADER: &str = "x-session-expired";
+const IMPERSONATED_BY_HEADER: &str = "x-impersonated-by";
+// 代操作期间不能修改被代操作用户的双因素认证
+const IMPERSONATION_DENIED: &[&str] = &["/api/auth/2fa/setup", "/api/auth/2fa/confirm", "/api/auth/2fa/email"];
+
+// Web 会话校验: 令牌对应的会话已登出或超时时去掉 Authorization 头，由各接口按未登录处理，公开接口(如重新登录)不受影响；
+// 会话有效时顺延空闲时间，并在响应头中返回会话失效时间。2FA 绑定受限令牌不登记会话，不在此校验
+async fn web_session_guard(State(state): State<AppState>, mut req: Request, next: Next) -> Response {
+    let claims = match extract_any_claims_from_headers(&state.auth, req.headers()) {
+        Ok(claims) if claims.scope.is_none() => claims,
+        _ => return next.run(req).await,
+    };
+    // 界面轮询会话状态不算作操作，否则会话永远不会空闲超时
+    let slide = req.uri().path() != SESSION_STATUS_PATH;
+    match web_session::touch(&state.db, &claims.jti, slide).await {
+        Ok(Some(status)) => {
+            // 代操作令牌的请求在实际操作人作用域内处理，审计日志据此记录实际操作人
+            let mut res = match claims.act.clone() {
+                Some(_) if IMPERSONATION_DENIED.contains(&req.uri().path()) => {
+                    return StatusCode::FORBIDDEN.into_response();
+                }
+                Some(actor) => impersonation::ACTOR.scope(actor, next.run(req)).await,
+                None => next.run(req).await,
+            };
+            if let Ok(v) = HeaderValue::from_str(&status.expires_at.to_string()) {
+                res.headers_mut().insert(SESSION_EXPIRES_HEADER, v);
+            }
+            // 界面据此显示代操作横幅，用户名不是 ASCII 时给出用户ID
+            if let Some(actor) = &claims.act {
+                if let Ok(v) = HeaderValue::from_str(&actor.username).or_else(|_| HeaderValue::from_str(&actor.sub)) {
+                    res.headers_mut().insert(IMPERSONATED_BY_HEADER, v);
+                }
+            }
+            res
+        }
+        Ok(None) => {
+            req.headers_mut().remove(header::AUTHORIZATION);
+            let mut res = next.run(req).await;
+            res.headers_mut()
+                .insert(SESSION_EXPIRED_HEADER, HeaderValue::from_static("1"));
+            res
+        }
+        Err(e) => {
+            log::error!("Failed to check web session of {}: {}", claims.sub, e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+async fn get_openapi_document() -> Json<serde_json::Value> {
+    Json(openapi::DOCUMENT.clone())
+}
+
+async fn get_api_docs() -> Html<&'static str> {
+    Html(openapi::SWAGGER_UI_HTML)
+}
+
+// 错误码目录，无需认证
+async fn get_error_catalog() -> Json<ApiResponse<Vec<ErrorInfo>>> {
+    Json(ApiResponse {
+        success: true,
+        data: Some(error_codes::catalog()),
+        message: "获取错误码目录成功".to_string(),
+    })
+}
+
+// 认证相关处理函数
+async fn login(
+    State(state): State<AppState>,
+    connect_info: Option<ConnectInfo<SocketAddr>>,
+    headers: HeaderMap,
+    ValidatedJson(req): ValidatedJson<LoginRequest>,
+) -> Result<(HeaderMap, Json<LoginResponse>), StatusCode> {
+    log::info!("Login attempt for user: {}", req.username);
+    let started = std::time::Instant::now();
+
+    // 来源地址在威胁情报列表中时拒绝登录
+    if let Some(ip) = client_ip(connect_info.map(|x| x.0), &headers, &TRUSTED_PROXIES) {
+        if let Some(feed) = threat_intel::lookup(ip, threat_intel::Check::Login).await {
+            log::warn!("Login of {} from {} rejected, listed in threat intel feed {}", req.username, ip, feed);
+            return login_failed(started, "登录请求被拒绝").await;
+        }
+    }
+
+    // 查找用户并校验密码，用户不存在时同样执行一次密码校验
+    let user = match state.db.get_user_by_username(&req.username).await {
+        Ok(user) => user,
+        Err(e) => {
+            log::error!("Database error during login: {}", e);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    };
+    let password_ok = state
+        .auth
+        .verify_user_password(&req.password, user.as_ref().map(|x| x.password_hash.as_str()));
+    // 锁定期间无论密码是否正确都返回同样的失败，否则可据此确认猜中的密码或判断用户名是否存在
+    let user = match user {
+        Some(user) if password_ok && !state.auth.is_user_locked(&user) => user,
+        user => {
+            if let Some(user) = user {
+                record_login_failure(&state, &user).await;
+            }
+            return login_failed(started, "用户名或密码错误").await;
+        }
+    };
+
+    // 如果启用了双因素认证，验证TOTP代码或邮件验证码
+    let mut via_email = false;
+    let mut via_trusted = false;
+    if user.two_factor_enabled {
+        let valid = if let Some(totp_code) = req.totp_code {
+            user.two_factor_secret
+                .as_deref()
+                .map(|secret| TwoFactorAuth::verify_code(secret, &totp_code))
+                .unwrap_or(false)
+        } else if let Some(email_code) = req.email_code {
+            via_email = true;
+            let granted = matches!(state.db.get_email_otp_grant(&user.id).await, Ok(Some(_)));
+            granted && email_otp::verify(&user.id, &email_code).await
+        } else if is_trusted_browser(&state, &headers, &user.id).await {
+            via_trusted = true;
+            true
+        } else {
+            return Ok((HeaderMap::new(), Json(LoginResponse {
+                success: false,
+                token: None,
+                user: None,
+                message: "需要双因素认证代码".to_string(),
+                require_2fa_setup: false,
+            })));
+        };
+        if !valid {
+            record_login_failure(&state, &user).await;
+            if via_email {
+                let audit_log = AuditLog {
+                    id: 0,
+                    user_id: user.id.clone(),
+                    device_id: "system".to_string(),
+                    action: "login_email_otp".to_string(),
+                    details: Some("邮件验证码错误".to_string()),
+                    ip_address: "127.0.0.1".to_string(),
+                    user_agent: None,
+                    timestamp: SystemTime::now(),
+                    success: false,
+                };
+                let _ = state.db.log_audit(&audit_log).await;
+            }
+            return login_failed(started, "双因素认证代码错误").await;
+        }
+    }
+
+    // 策略要求2FA但尚未绑定时，只签发用于绑定2FA的受限令牌
+    if mfa_policy::enrollment_required(&user).await {
+        let token = match state.auth.generate_restricted_jwt(&user, SCOPE_2FA_SETUP) {
+            Ok(token) => token,
+            Err(e) => {
+                log::error!("Failed to generate JWT: {}", e);
+                return Err(StatusCode::INTERNAL_SERVER_ERROR);
+            }
+        };
+        return Ok((HeaderMap::new(), Json(LoginResponse {
+            success: true,
+            token: Some(token),
+            user: None,
+            message: "请先绑定双因素认证".to_string(),
+            require_2fa_setup: true,
+        })));
+    }
+
+    // 生成JWT令牌
+    let token = match issue_session_token(&state, &user, &headers).await {
+        Ok(token) => token,
+        Err(e) => {
+            log::error!("Failed to issue session token: {}", e);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    };
+
+    // 更新登录信息
+    let _ = state.db.update_user_login_info(&user.id, true).await;
+
+    // 记录审计日志
+    let audit_log = AuditLog {
+        id: 0,
+        user_id: user.id.clone(),
+        device_id: "system".to_string(),
+        action: "login".to_string(),
+        details: Some(
+            if via_email {
+                "用户登录（邮件验证码）"
+            } else if via_trusted {
+                "用户登录（受信任浏览器）"
+            } else {
+                "用户登录"
+            }
+            .to_string(),
+        ),
+        ip_address: "127.0.0.1".to_string(), // 这里应该从请求中获取真实IP
+        user_agent: None,
+        timestamp: SystemTime::now(),
+        success: true,
+    };
+    let _ = state.db.log_audit(&audit_log).await;
+
+    // 2FA验证通过且用户要求记住浏览器时签发受信任浏览器Cookie
+    let mut response_headers = HeaderMap::new();
+    if user.two_factor_enabled && req.remember_device && !via_trusted {
+        let user_agent = headers
+            .get(header::USER_AGENT)
+            .and_then(|v| v.to_str().ok())
+            .map(|v| v.to_string());
+        if let Some(cookie) = trust_browser(&state, &user.id, user_agent).await {
+            if let Ok(value) = cookie.parse() {
+                response_headers.insert(header::SET_COOKIE, value);
+            }
+        }
+    }
+
+    let user_info = UserInfo {
+        id: user.id,
+        username: user.username,
+        email: user.email,
+        role: format!("{:?}", user.role),
+        groups: user.groups,
+        enabled: user.enabled,
+        last_login: user.last_login.map(|t| t.duration_since(std::time::UNIX_EPOCH).unwrap().as_secs()),
+    };
+
+    Ok((response_headers, Json(LoginResponse {
+        success: true,
+        token: Some(token),
+        user: Some(user_info),
+        message: "登录成功".to_string(),
+        require_2fa_setup: false,
+    })))
+}
+
+// 登录失败的响应至少耗时 LOGIN_FAILURE_MIN_DURATION，用户不存在、密码错误和账户锁定的响应时间一致，
+// 同时减慢在线猜测密码的速度
+const LOGIN_FAILURE_MIN_DURATION: std::time::Duration = std::time::Duration::from_secs(1);
+
+lazy_static::lazy_static! {
+    // TRUSTED_PROXIES，逗号分隔的反向代理地址或网段，只有来自这些地址的转发头才被采信
+    static ref TRUSTED_PROXIES: Vec<IpNetwork> = parse_trusted_proxies(
+        &std::env::var("TRUSTED_PROXIES").unwrap_or_default()
+    );
+}
+
+fn parse_trusted_proxies(value: &str) -> Vec<IpNetwork> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|x| !x.is_empty())
+        .filter_map(|x| match x.parse::<IpNetwork>() {
+            Ok(net) => Some(net),
+            Err(_) => {
+                log::warn!("Ignoring invalid trusted proxy: {}", x);
+                None
+            }
+        })
+        .collect()
+}
+
+// 客户端地址：对端不是受信任的反向代理时直接使用对端地址，否则依次取 X-Real-IP 和
+// X-Forwarded-For 中从右往左第一个非代理地址
+fn client_ip(peer: Option<SocketAddr>, headers: &HeaderMap, proxies: &[IpNetwork]) -> Option<IpAddr> {
+    let trusted = |ip: IpAddr| proxies.iter().any(|net| net.contains(ip));
+    let peer = peer.map(|x| x.ip());
+    if !peer.is_some_and(trusted) {
+        return peer;
+    }
+    let header = |name: &str| headers.get(name).and_then(|v| v.to_str().ok());
+    if let Some(ip) = header("X-Real-IP").and_then(|v| v.trim().parse().ok()) {
+        return Some(ip);
+    }
+    header("X-Forwarded-For")
+        .and_then(|v| {
+            v.rsplit(',')
+                .filter_map(|x| x.trim().parse::<IpAddr>().ok())
+                .find(|ip| !trusted(*ip))
+        })
+        .or(peer)
+}
+
+// 记录失败的登录尝试，连续失败达到上限时锁定账户；锁定期间不再累计
+async fn record_login_failure(state: &AppState, user: &User) {
+    if state.auth.is_user_locked(user) {
+        return;
+    }
+    let failed = User {
+        failed_login_attempts: user.failed_login_attempts + 1,
+        ..user.clone()
+    };
+    let res = if state.auth.should_lock_user(&failed) {
+        log::warn!("User {} locked after {} failed login attempts", user.username, failed.failed_login_attempts);
+        state.db.lock_user(&user.id, state.auth.generate_lockout_time()).await
+    } else {
+        state.db.update_user_login_info(&user.id, false).await
+    };
+    if let Err(e) = res {
+        log::error!("Failed to record login failure of {}: {}", user.id, e);
+    }
+}
+
+async fn login_failed(
+    started: std::time::Instant,
+    message: &str,
+) -> Result<(HeaderMap, Json<LoginResponse>), StatusCode> {
+    if let Some(rest) = LOGIN_FAILURE_MIN_DURATION.checked_sub(started.elapsed()) {
+        hbb_common::tokio::time::sleep(rest).await;
+    }
+    Ok((HeaderMap::new(), Json(LoginResponse {
+        success: false,
+        token: None,
+        user: None,
+        message: message.to_string(),
+        require_2fa_setup: false,
+    })))
+}
+
+// 申请找回密码，无论用户名是否存在都返回相同结果
+async fn forgot_password(
+    State(state): State<AppState>,
+    ValidatedJson(req): ValidatedJson<ForgotPasswordRequest>,
+) -> Result<Json<ApiResponse<()>>, StatusCode> {
+    let username = req.username.trim().to_string();
+    if !username.is_empty() {
+        if password_reset::try_acquire(&username).await {
+            hbb_common::tokio::spawn(async move { send_password_reset(&state, &username).await });
+        } else {
+            log::warn!("Password reset rate limited for {}", username);
+        }
+    }
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: None,
+        message: "如果该账户存在且已绑定邮箱，重置链接已发送".to_string(),
+    }))
+}
+
+async fn send_password_reset(state: &AppState, username: &str) {
+    let user = match state.db.get_user_by_username(username).await {
+        Ok(Some(user)) if user.enabled => user,
+        Ok(_) => {
+            log::info!("Password reset requested for unknown or disabled user {}", username);
+            return;
+        }
+        Err(e) => {
+            log::error!("Failed to get user {}: {}", username, e);
+            return;
+        }
+    };
+    let email = match user.email.clone().filter(|x| !x.is_empty()) {
+        Some(email) => email,
+        None => {
+            log::info!("Password reset requested for user {} without email", user.id);
+            return;
+        }
+    };
+    let result = match state
+        .auth
+        .generate_password_reset_token(&user, crate::common::now() + password_reset::RESET_TTL_SECS)
+    {
+        Ok(token) => match password_reset::reset_link(&token) {
+            Ok(link) => password_reset::send_reset(&email, &user.username, &link, i18n::user_lang(&user.id).await).await,
+            Err(e) => Err(e),
+        },
+        Err(e) => Err(e),
+    };
+    if let Err(e) = &result {
+        log::warn!("Failed to send password reset to {}: {}", user.id, e);
+    }
+
+    let audit_log = AuditLog {
+        id: 0,
+        user_id: user.id,
+        device_id: "system".to_string(),
+        action: "forgot_password".to_string(),
+        details: Some(match &result {
+            Ok(_) => "发送密码重置邮件".to_string(),
+            Err(e) => format!("密码重置邮件发送失败: {}", e),
+        }),
+        ip_address: "127.0.0.1".to_string(),
+        user_agent: None,
+        timestamp: SystemTime::now(),
+        success: result.is_ok(),
+    };
+    let _ = state.db.log_audit(&audit_log).await;
+}
+
+// 凭重置链接中的令牌设置新密码，同时撤销该用户的受信任浏览器
+async fn reset_password(
+    State(state): State<AppState>,
+    ValidatedJson(req): ValidatedJson<ResetPasswordRequest>,
+) -> Result<Json<ApiResponse<()>>, StatusCode> {
+    let invalid = || {
+        Ok(Json(ApiResponse {
+            success: false,
+            data: None,
+            message: "重置链接无效或已过期".to_string(),
+        }))
+    };
+
+    let claims = match state.auth.verify_password_reset_token(&req.token) {
+        Ok(claims) => claims,
+        Err(_) => return invalid(),
+    };
+    let user = match state.db.get_user_by_id(&claims.sub).await {
+        Ok(Some(user)) if user.enabled && state.auth.password_unchanged(&claims, &user) => user,
+        Ok(_) => return invalid(),
+        Err(e) => {
+            log::error!("Failed to get user {}: {}", claims.sub, e);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    };
+    if let Err(e) = invites::check_password(&req.password) {
+        return Ok(Json(ApiResponse {
+            success: false,
+            data: None,
+            message: format!("密码不符合要求: {}", e),
+        }));
+    }
+    let password_hash = match state.auth.hash_password(&req.password) {
+        Ok(hash) => hash,
+        Err(_) => return Err(StatusCode::INTERNAL_SERVER_ERROR),
+    };
+    if let Err(e) = state.db.set_user_password(&user.id, &password_hash).await {
+        log::error!("Failed to reset password for {}: {}", user.id, e);
+        return Err(StatusCode::INTERNAL_SERVER_ERROR);
+    }
+    if let Err(e) = state.db.remove_trusted_devices(&user.id, None).await {
+        log::error!("Failed to revoke trusted devices of {}: {}", user.id, e);
+    }
+    // 重置前签发的令牌可能已泄露，全部登出
+    if let Err(e) = web_session::end_all(&state.db, &user.id).await {
+        log::error!("Failed to end web sessions of {}: {}", user.id, e);
+        return Err(StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    let audit_log = AuditLog {
+        id: 0,
+        user_id: user.id,
+        device_id: "system".to_string(),
+        action: "reset_password".to_string(),
+        details: Some("通过邮件链接重置密码".to_string()),
+        ip_address: "127.0.0.1".to_string(),
+        user_agent: None,
+        timestamp: SystemTime::now(),
+        success: true,
+    };
+    let _ = state.db.log_audit(&audit_log).await;
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: None,
+        message: "密码已重置，请重新登录".to_string(),
+    }))
+}
+
+async fn is_trusted_browser(state: &AppState, headers: &HeaderMap, user_id: &str) -> bool {
+    if !trusted_device::get().await.enabled {
+        return false;
+    }
+    let claims = match trusted_device::cookie_token(headers)
+        .and_then(|token| state.auth.verify_trusted_device_token(&token).ok())
+    {
+        Some(claims) if claims.sub == user_id => claims,
+        _ => return false,
+    };
+    match state.db.use_trusted_device(&claims.tid, user_id).await {
+        Ok(valid) => valid,
+        Err(e) => {
+            log::error!("Failed to check trusted device {}: {}", claims.tid, e);
+            false
+        }
+    }
+}
+
+// 登记受信任浏览器并返回 Set-Cookie 值
+async fn trust_browser(state: &AppState, user_id: &str, user_agent: Option<String>) -> Option<String> {
+    let config = trusted_device::get().await;
+    if !config.enabled {
+        return None;
+    }
+    let now = crate::common::now();
+    let device = TrustedDevice {
+        id: uuid::Uuid::new_v4().to_string(),
+        user_id: user_id.to_string(),
+        user_agent,
+        created_at: now,
+        expires_at: now + config.lifetime_secs(),
+        last_used: None,
+    };
+    let token = match state
+        .auth
+        .generate_trusted_device_token(user_id, &device.id, device.expires_at)
+    {
+        Ok(token) => token,
+        Err(e) => {
+            log::error!("Failed to sign trusted device token: {}", e);
+            return None;
+        }
+    };
+    if let Err(e) = state.db.add_trusted_device(&device).await {
+        log::error!("Failed to save trusted device of {}: {}", user_id, e);
+        return None;
+    }
+    Some(trusted_device::set_cookie(&token, config.lifetime_secs()))
+}
+
+// 签发完整令牌并登记 Web 会话
+async fn issue_session_token(state: &AppState, user: &User, headers: &HeaderMap) -> ResultType<String> {
+    let token = state.auth.generate_jwt(user)?;
+    let claims = state.auth.verify_jwt(&token)?;
+    let user_agent = headers
+        .get(header::USER_AGENT)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.to_string());
+    web_session::start(&state.db, &claims.jti, &user.id, user_agent).await?;
+    Ok(token)
+}
+
+async fn logout(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<ApiResponse<()>>, StatusCode> {
+    // 结束令牌对应的 Web 会话，此后该令牌按未登录处理
+    if let Ok(claims) = extract_claims_from_headers(&state.auth, &headers) {
+        if let Err(e) = web_session::end(&state.db, &claims.jti).await {
+            log::error!("Failed to end web session of {}: {}", claims.sub, e);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+        if let Err(e) = impersonation::end(&state.db, &claims).await {
+            log::error!("Failed to end impersonation {}: {}", claims.jti, e);
+        }
+    }
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(()),
+        message: "登出成功".to_string(),
+    }))
+}
+
+// 当前会话的失效时间，界面据此在到期前提醒；查询本身不顺延会话
+async fn get_web_session(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<ApiResponse<WebSessionStatus>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    match web_session::touch(&state.db, &claims.jti, false).await {
+        Ok(Some(status)) => Ok(Json(ApiResponse {
+            success: true,
+            data: Some(status),
+            message: "获取会话状态成功".to_string(),
+        })),
+        Ok(None) => Err(StatusCode::UNAUTHORIZED),
+        Err(e) => {
+            log::error!("Failed to get web session of {}: {}", claims.sub, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+// 用户在到期提醒中选择继续使用
+async fn keep_web_session_alive(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<ApiResponse<WebSessionStatus>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    match web_session::keepalive(&state.db, &claims.jti).await {
+        Ok(Some(status)) => Ok(Json(ApiResponse {
+            success: true,
+            data: Some(status),
+            message: "会话已延长".to_string(),
+        })),
+        Ok(None) => Err(StatusCode::UNAUTHORIZED),
+        Err(e) => {
+            log::error!("Failed to extend web session of {}: {}", claims.sub, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+async fn get_current_user(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<ApiResponse<UserInfo>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    let user = match state.db.get_user_by_username(&claims.username).await {
+        Ok(Some(user)) => user,
+        Ok(None) => return Err(StatusCode::NOT_FOUND),
+        Err(_) => return Err(StatusCode::INTERNAL_SERVER_ERROR),
+    };
+
+    let user_info = UserInfo {
+        id: user.id,
+        username: user.username,
+        email: user.email,
+        role: format!("{:?}", user.role),
+        groups: user.groups,
+        enabled: user.enabled,
+        last_login: user.last_login.map(|t| t.duration_since(std::time::UNIX_EPOCH).unwrap().as_secs()),
+    };
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(user_info),
+        message: "获取用户信息成功".to_string(),
+    }))
+}
+
+// 用户管理处理函数
+async fn list_users(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    ValidatedQuery(params): ValidatedQuery<PaginationQuery>,
+) -> Result<Json<ApiResponse<Vec<UserInfo>>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    // 检查权限 - 只有管理员可以查看用户列表
+    if !is_admin_or_auditor(&claims) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    // 这里应该实现分页查询用户列表
+    // 为了简化，暂时返回空列表
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(vec![]),
+        message: "获取用户列表成功".to_string(),
+    }))
+}
+
+async fn create_user(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    ValidatedJson(req): ValidatedJson<CreateUserRequest>,
+) -> Result<Json<ApiResponse<UserInfo>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    // 检查权限
+    if claims.role != "SuperAdmin" && claims.role != "Admin" {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    if let Err(e) = license::check_user_seat(&state.db).await {
+        return Ok(Json(ApiResponse {
+            success: false,
+            data: None,
+            message: format!("超出许可证用户数: {}", e),
+        }));
+    }
+
+    // 创建新用户
+    let password_hash = match state.auth.hash_password(&req.password) {
+        Ok(hash) => hash,
+        Err(_) => return Err(StatusCode::INTERNAL_SERVER_ERROR),
+    };
+
+    let role = match req.role.as_str() {
+        "SuperAdmin" => UserRole::SuperAdmin,
+        "Admin" => UserRole::Admin,
+        "User" => UserRole::User,
+        "ReadOnly" => UserRole::ReadOnly,
+        "Auditor" => UserRole::Auditor,
+        _ => UserRole::User,
+    };
+
+    let new_user = User {
+        id: uuid::Uuid::new_v4().to_string(),
+        username: req.username.trim().to_string(),
+        password_hash,
+        email: req.email.map(|x| x.trim().to_string()).filter(|x| !x.is_empty()),
+        role,
+        groups: req.groups,
+        enabled: true,
+        created_at: SystemTime::now(),
+        last_login: None,
+        failed_login_attempts: 0,
+        locked_until: None,
+        two_factor_enabled: false,
+        two_factor_secret: None,
+    };
+
+    // 用户名和邮箱不区分大小写唯一，并发创建同名用户时只有一个成功
+    match state.db.create_user(&new_user).await {
+        Ok(Some(conflict)) => Ok(Json(ApiResponse {
+            success: false,
+            data: None,
+            message: user_conflict_message(conflict).to_string(),
+        })),
+        Ok(None) => {
+            let user_info = UserInfo {
+                id: new_user.id,
+                username: new_user.username,
+                email: new_user.email,
+                role: format!("{:?}", new_user.role),
+                groups: new_user.groups,
+                enabled: new_user.enabled,
+                last_login: None,
+            };
+
+            Ok(Json(ApiResponse {
+                success: true,
+                data: Some(user_info),
+                message: "用户创建成功".to_string(),
+            }))
+        }
+        Err(e) => {
+            log::error!("Failed to create user: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+fn user_conflict_message(conflict: UserConflict) -> &'static str {
+    match conflict {
+        UserConflict::Username => "用户名已存在",
+        UserConflict::Email => "邮箱已被其他用户使用",
+    }
+}
+
+// 用户邀请处理函数
+// 只填写邮箱创建用户并发送邀请邮件，用户通过链接自行设置密码
+async fn invite_user(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    ValidatedJson(req): ValidatedJson<InviteUserRequest>,
+) -> Result<Json<ApiResponse<UserInvite>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if claims.role != "SuperAdmin" && claims.role != "Admin" {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let role = match req.role.as_str() {
+        "SuperAdmin" => UserRole::SuperAdmin,
+        "Admin" => UserRole::Admin,
+        "User" => UserRole::User,
+        "ReadOnly" => UserRole::ReadOnly,
+        "Auditor" => UserRole::Auditor,
+        _ => UserRole::User,
+    };
+    if role == UserRole::SuperAdmin && claims.role != "SuperAdmin" {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let email = req.email.trim().to_string();
+    if !email_otp::get().await.enabled {
+        return Ok(Json(ApiResponse {
+            success: false,
+            data: None,
+            message: "未启用邮件服务，无法发送邀请".to_string(),
+        }));
+    }
+
+    let username = req
+        .username
+        .map(|x| x.trim().to_string())
+        .filter(|x| !x.is_empty())
+        .unwrap_or_else(|| email.clone());
+
+    if let Err(e) = license::check_user_seat(&state.db).await {
+        return Ok(Json(ApiResponse {
+            success: false,
+            data: None,
+            message: format!("超出许可证用户数: {}", e),
+        }));
+    }
+
+    // 接受邀请前密码为空，无法登录
+    let user = User {
+        id: uuid::Uuid::new_v4().to_string(),
+        username,
+        password_hash: String::new(),
+        email: Some(email.clone()),
+        role,
+        groups: req.groups,
+        enabled: true,
+        created_at: SystemTime::now(),
+        last_login: None,
+        failed_login_attempts: 0,
+        locked_until: None,
+        two_factor_enabled: false,
+        two_factor_secret: None,
+    };
+    let invite = new_invite(&user.id, &email, &claims.username);
+    let link = match issue_invite_link(&state, &invite) {
+        Ok(link) => link,
+        Err(e) => {
+            log::warn!("Failed to build invite link: {}", e);
+            return Ok(Json(ApiResponse {
+                success: false,
+                data: None,
+                message: "未配置 PUBLIC-URL，无法生成邀请链接".to_string(),
+            }));
+        }
+    };
+    match state.db.create_user(&user).await {
+        Ok(None) => {}
+        Ok(Some(conflict)) => {
+            return Ok(Json(ApiResponse {
+                success: false,
+                data: None,
+                message: user_conflict_message(conflict).to_string(),
+            }));
+        }
+        Err(e) => {
+            log::error!("Failed to create invited user: {}", e);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    }
+    if let Err(e) = state.db.add_user_invite(&invite).await {
+        log::error!("Failed to save invite for {}: {}", user.id, e);
+        return Err(StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    let audit_log = AuditLog {
+        id: 0,
+        user_id: claims.sub,
+        device_id: "system".to_string(),
+        action: "invite_user".to_string(),
+        details: Some(format!("邀请用户 {} ({})", user.username, email)),
+        ip_address: "127.0.0.1".to_string(),
+        user_agent: None,
+        timestamp: SystemTime::now(),
+        success: true,
+    };
+    let _ = state.db.log_audit(&audit_log).await;
+
+    let lang = i18n::user_lang(&user.id).await;
+    if let Err(e) = invites::send_invite(&email, &user.username, &claims.username, &link, lang).await {
+        log::warn!("Failed to send invite to {}: {}", user.id, e);
+        return Ok(Json(ApiResponse {
+            success: false,
+            data: Some(invite),
+            message: format!("用户已创建，但邀请邮件发送失败，可稍后重新发送: {}", e),
+        }));
+    }
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(invite),
+        message: "邀请已发送".to_string(),
+    }))
+}
+
+fn new_invite(user_id: &str, email: &str, invited_by: &str) -> UserInvite {
+    let now = crate::common::now();
+    UserInvite {
+        id: uuid::Uuid::new_v4().to_string(),
+        user_id: user_id.to_string(),
+        email: email.to_string(),
+        invited_by: invited_by.to_string(),
+        created_at: now,
+        expires_at: now + invites::INVITE_TTL_SECS,
+        accepted_at: None,
+    }
+}
+
+fn issue_invite_link(state: &AppState, invite: &UserInvite) -> ResultType<String> {
+    let token = state
+        .auth
+        .generate_invite_token(&invite.user_id, &invite.id, invite.expires_at)?;
+    invites::invite_link(&token)
+}
+
+async fn list_invites(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    ValidatedQuery(params): ValidatedQuery<PaginationQuery>,
+) -> Result<Json<ApiResponse<Vec<UserInvite>>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if !is_admin_or_auditor(&claims) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let page = params.page.unwrap_or(1).max(1);
+    let limit = params.limit.unwrap_or(100);
+    let offset = (page - 1) * limit;
+
+    match state.db.list_user_invites(limit as i64, offset as i64).await {
+        Ok(invites) => Ok(Json(ApiResponse {
+            success: true,
+            data: Some(invites),
+            message: "获取邀请列表成功".to_string(),
+        })),
+        Err(e) => {
+            log::error!("Failed to list invites: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+// 重新发送邀请，签发新链接并使旧链接失效
+async fn resend_invite(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+) -> Result<Json<ApiResponse<UserInvite>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if claims.role != "SuperAdmin" && claims.role != "Admin" {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let old = match state.db.get_user_invite(&id).await {
+        Ok(Some(invite)) => invite,
+        Ok(None) => return Err(StatusCode::NOT_FOUND),
+        Err(e) => {
+            log::error!("Failed to get invite {}: {}", id, e);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    };
+    if old.accepted_at.is_some() {
+        return Ok(Json(ApiResponse {
+            success: false,
+            data: None,
+            message: "用户已接受邀请".to_string(),
+        }));
+    }
+    let user = match state.db.get_user_by_id(&old.user_id).await {
+        Ok(Some(user)) => user,
+        Ok(None) => return Err(StatusCode::NOT_FOUND),
+        Err(e) => {
+            log::error!("Failed to get user {}: {}", old.user_id, e);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    };
+
+    let invite = new_invite(&old.user_id, &old.email, &claims.username);
+    let link = match issue_invite_link(&state, &invite) {
+        Ok(link) => link,
+        Err(e) => {
+            log::warn!("Failed to build invite link: {}", e);
+            return Ok(Json(ApiResponse {
+                success: false,
+                data: None,
+                message: "未配置 PUBLIC-URL，无法生成邀请链接".to_string(),
+            }));
+        }
+    };
+    if let Err(e) = state.db.add_user_invite(&invite).await {
+        log::error!("Failed to save invite for {}: {}", invite.user_id, e);
+        return Err(StatusCode::INTERNAL_SERVER_ERROR);
+    }
+    let lang = i18n::user_lang(&user.id).await;
+    if let Err(e) = invites::send_invite(&invite.email, &user.username, &claims.username, &link, lang).await {
+        log::warn!("Failed to send invite to {}: {}", invite.user_id, e);
+        return Ok(Json(ApiResponse {
+            success: false,
+            data: Some(invite),
+            message: format!("邀请邮件发送失败: {}", e),
+        }));
+    }
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(invite),
+        message: "邀请已重新发送".to_string(),
+    }))
+}
+
+// 撤销未接受的邀请，链接立即失效；已创建的用户保留，可重新邀请
+async fn revoke_invite(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+) -> Result<Json<ApiResponse<()>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if claims.role != "SuperAdmin" && claims.role != "Admin" {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    match state.db.remove_user_invite(&id).await {
+        Ok(true) => {}
+        Ok(false) => return Err(StatusCode::NOT_FOUND),
+        Err(e) => {
+            log::error!("Failed to revoke invite {}: {}", id, e);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    }
+
+    let audit_log = AuditLog {
+        id: 0,
+        user_id: claims.sub,
+        device_id: "system".to_string(),
+        action: "revoke_invite".to_string(),
+        details: Some(id),
+        ip_address: "127.0.0.1".to_string(),
+        user_agent: None,
+        timestamp: SystemTime::now(),
+        success: true,
+    };
+    let _ = state.db.log_audit(&audit_log).await;
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: None,
+        message: "邀请已撤销".to_string(),
+    }))
+}
+
+// 校验邀请令牌，返回未使用且未过期的邀请
+async fn pending_invite(state: &AppState, token: &str) -> Result<Option<UserInvite>, StatusCode> {
+    let claims = match state.auth.verify_invite_token(token) {
+        Ok(claims) => claims,
+        Err(_) => return Ok(None),
+    };
+    match state.db.get_user_invite(&claims.iid).await {
+        Ok(invite) => Ok(invite.filter(|x| {
+            x.user_id == claims.sub && x.accepted_at.is_none() && x.expires_at > crate::common::now()
+        })),
+        Err(e) => {
+            log::error!("Failed to get invite {}: {}", claims.iid, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+// 邀请页面展示用户名和邮箱，无需认证
+async fn get_invite(
+    State(state): State<AppState>,
+    ValidatedQuery(params): ValidatedQuery<InviteTokenQuery>,
+) -> Result<Json<ApiResponse<InvitePreview>>, StatusCode> {
+    let invite = match pending_invite(&state, &params.token).await? {
+        Some(invite) => invite,
+        None => {
+            return Ok(Json(ApiResponse {
+                success: false,
+                data: None,
+                message: "邀请链接无效或已过期".to_string(),
+            }));
+        }
+    };
+    let user = match state.db.get_user_by_id(&invite.user_id).await {
+        Ok(Some(user)) => user,
+        Ok(None) => return Err(StatusCode::NOT_FOUND),
+        Err(e) => {
+            log::error!("Failed to get user {}: {}", invite.user_id, e);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    };
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(InvitePreview {
+            username: user.username,
+            email: invite.email,
+            expires_at: invite.expires_at,
+        }),
+        message: "邀请有效".to_string(),
+    }))
+}
+
+// 接受邀请并设置密码，成功后按登录流程签发令牌，策略要求2FA时只签发绑定2FA的受限令牌
+async fn accept_invite(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    ValidatedJson(req): ValidatedJson<AcceptInviteRequest>,
+) -> Result<Json<LoginResponse>, StatusCode> {
+    let failed = |message: &str| {
+        Ok(Json(LoginResponse {
+            success: false,
+            token: None,
+            user: None,
+            message: message.to_string(),
+            require_2fa_setup: false,
+        }))
+    };
+
+    let invite = match pending_invite(&state, &req.token).await? {
+        Some(invite) => invite,
+        None => return failed("邀请链接无效或已过期"),
+    };
+    if let Err(e) = invites::check_password(&req.password) {
+        return failed(&format!("密码不符合要求: {}", e));
+    }
+    let password_hash = match state.auth.hash_password(&req.password) {
+        Ok(hash) => hash,
+        Err(_) => return Err(StatusCode::INTERNAL_SERVER_ERROR),
+    };
+    match state
+        .db
+        .accept_user_invite(&invite.id, &invite.user_id, &password_hash)
+        .await
+    {
+        Ok(true) => {}
+        Ok(false) => return failed("邀请链接无效或已过期"),
+        Err(e) => {
+            log::error!("Failed to accept invite {}: {}", invite.id, e);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    }
+    let user = match state.db.get_user_by_id(&invite.user_id).await {
+        Ok(Some(user)) => user,
+        Ok(None) => return Err(StatusCode::NOT_FOUND),
+        Err(e) => {
+            log::error!("Failed to get user {}: {}", invite.user_id, e);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    };
+
+    let audit_log = AuditLog {
+        id: 0,
+        user_id: user.id.clone(),
+        device_id: "system".to_string(),
+        action: "accept_invite".to_string(),
+        details: Some(format!("接受邀请，邮箱 {} 已验证", invite.email)),
+        ip_address: "127.0.0.1".to_string(),
+        user_agent: None,
+        timestamp: SystemTime::now(),
+        success: true,
+    };
+    let _ = state.db.log_audit(&audit_log).await;
+
+    if mfa_policy::enrollment_required(&user).await {
+        let token = match state.auth.generate_restricted_jwt(&user, SCOPE_2FA_SETUP) {
+            Ok(token) => token,
+            Err(e) => {
+                log::error!("Failed to generate JWT: {}", e);
+                return Err(StatusCode::INTERNAL_SERVER_ERROR);
+            }
+        };
+        return Ok(Json(LoginResponse {
+            success: true,
+            token: Some(token),
+            user: None,
+            message: "密码已设置，请绑定双因素认证".to_string(),
+            require_2fa_setup: true,
+        }));
+    }
+
+    let token = match issue_session_token(&state, &user, &headers).await {
+        Ok(token) => token,
+        Err(e) => {
+            log::error!("Failed to issue session token: {}", e);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    };
+    let _ = state.db.update_user_login_info(&user.id, true).await;
+
+    Ok(Json(LoginResponse {
+        success: true,
+        token: Some(token),
+        user: Some(UserInfo {
+            id: user.id,
+            username: user.username,
+            email: user.email,
+            role: format!("{:?}", user.role),
+            groups: user.groups,
+            enabled: user.enabled,
+            last_login: None,
+        }),
+        message: "密码已设置".to_string(),
+        require_2fa_setup: false,
+    }))
+}
+
+// 设备管理处理函数
+// 管理员查询全部设备，其他用户只查询自己的设备
+async fn list_devices(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    ValidatedQuery(params): ValidatedQuery<DeviceListQuery>,
+) -> Result<Json<ApiResponse<DeviceListResponse>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    let mut filter = match &params.view {
+        Some(id) => match state.db.get_device_view(id, &claims.sub).await {
+            Ok(Some(view)) => view.filter,
+            Ok(None) => return Err(StatusCode::NOT_FOUND),
+            Err(e) => {
+                log::error!("Failed to get device view {}: {}", id, e);
+                return Err(StatusCode::INTERNAL_SERVER_ERROR);
+            }
+        },
+        None => DeviceFilter::default(),
+    };
+    if let Err(message) = params.apply(&mut filter) {
+        return Ok(Json(ApiResponse {
+            success: false,
+            data: None,
+            message,
+        }));
+    }
+
+    let owner_id = if is_admin_or_auditor(&claims) {
+        None
+    } else {
+        Some(claims.sub.as_str())
+    };
+    let page = params.page.unwrap_or(1).max(1);
+    let limit = params.limit.unwrap_or(50).clamp(1, 1000);
+    let offset = (page - 1) * limit;
+    let (devices, total) = match state
+        .db
+        .list_devices(&filter, owner_id, crate::common::now(), limit as i64, offset as i64)
+        .await
+    {
+        Ok(res) => res,
+        Err(e) => {
+            log::error!("Failed to get devices: {}", e);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    };
+
+    let mut devices = devices;
+    let masker = data_masking::for_role(&claims.role).await;
+    devices.iter_mut().for_each(|d| masker.device(d));
+    let response = DeviceListResponse {
+        total: total as usize,
+        devices,
+    };
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(response),
+        message: "获取设备列表成功".to_string(),
+    }))
+}
+
+// 设备视图，按用户保存
+async fn get_my_notification_preferences(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<ApiResponse<NotificationPreferences>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    load_notification_preferences(&state, &claims.sub).await
+}
+
+async fn update_my_notification_preferences(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    ValidatedJson(req): ValidatedJson<NotificationPreferences>,
+) -> Result<Json<ApiResponse<NotificationPreferences>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    let user_id = claims.sub.clone();
+    save_notification_preferences(&state, claims, user_id, req).await
+}
+
+// 语言偏好，影响接口消息以及发给该用户的邮件、告警和报告
+async fn get_my_language(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<ApiResponse<LanguagePreference>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(LanguagePreference {
+            language: i18n::user_preference(&claims.sub).await,
+        }),
+        message: "获取语言偏好成功".to_string(),
+    }))
+}
+
+async fn update_my_language(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    ValidatedJson(req): ValidatedJson<LanguagePreference>,
+) -> Result<Json<ApiResponse<LanguagePreference>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if let Err(e) = i18n::set_user_lang(&state.db, &claims.sub, req.language).await {
+        log::error!("Failed to save language of {}: {}", claims.sub, e);
+        return Err(StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    let audit_log = AuditLog {
+        id: 0,
+        user_id: claims.sub,
+        device_id: "system".to_string(),
+        action: "update_language".to_string(),
+        details: Some(req.language.map(|x| x.tag()).unwrap_or("auto").to_string()),
+        ip_address: "127.0.0.1".to_string(),
+        user_agent: None,
+        timestamp: SystemTime::now(),
+        success: true,
+    };
+    let _ = state.db.log_audit(&audit_log).await;
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(req),
+        message: "语言偏好已更新".to_string(),
+    }))
+}
+
+// 当前用户最近连接的设备，按最后连接时间倒序
+async fn list_recent_devices(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    ValidatedQuery(query): ValidatedQuery<RecentQuery>,
+) -> Result<Json<ApiResponse<Vec<UserDevice>>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    let limit = query.limit.unwrap_or(favorites::DEFAULT_RECENT_LIMIT);
+    match favorites::recent(&state.db, &claims.sub, limit).await {
+        Ok(devices) => Ok(Json(ApiResponse {
+            success: true,
+            data: Some(devices),
+            message: "获取最近连接成功".to_string(),
+        })),
+        Err(e) => {
+            log::error!("Failed to list recent devices of {}: {}", claims.sub, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+async fn clear_recent_devices(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<ApiResponse<()>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    match state.db.clear_recent_connections(&claims.sub).await {
+        Ok(_) => Ok(Json(ApiResponse {
+            success: true,
+            data: None,
+            message: "最近连接已清空".to_string(),
+        })),
+        Err(e) => {
+            log::error!("Failed to clear recent devices of {}: {}", claims.sub, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+// 当前用户收藏的设备，按别名或名称排序
+async fn list_favorite_devices(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<ApiResponse<Vec<UserDevice>>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    match favorites::favorites(&state.db, &claims.sub).await {
+        Ok(devices) => Ok(Json(ApiResponse {
+            success: true,
+            data: Some(devices),
+            message: "获取收藏设备成功".to_string(),
+        })),
+        Err(e) => {
+            log::error!("Failed to list favorite devices of {}: {}", claims.sub, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+// 收藏设备，可收藏的范围与可控制的设备相同
+async fn add_favorite_device(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(device_id): Path<String>,
+) -> Result<Json<ApiResponse<()>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+    let device_id = peer_alias::resolve_id(&device_id).await;
+
+    match state.db.device_exists(&device_id).await {
+        Ok(true) => {}
+        Ok(false) => return Err(StatusCode::NOT_FOUND),
+        Err(e) => {
+            log::error!("Failed to check device {}: {}", device_id, e);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    }
+    ensure_device_access(&state, &claims, &device_id).await?;
+
+    match favorites::star(&state.db, &claims.sub, &device_id).await {
+        Ok(added) => Ok(Json(ApiResponse {
+            success: true,
+            data: None,
+            message: if added { "设备已收藏" } else { "设备已在收藏中" }.to_string(),
+        })),
+        Err(e) => Ok(Json(ApiResponse {
+            success: false,
+            data: None,
+            message: format!("收藏设备失败: {}", e),
+        })),
+    }
+}
+
+async fn remove_favorite_device(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(device_id): Path<String>,
+) -> Result<Json<ApiResponse<()>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+    let device_id = peer_alias::resolve_id(&device_id).await;
+
+    match state.db.remove_favorite_device(&claims.sub, &device_id).await {
+        Ok(true) => Ok(Json(ApiResponse {
+            success: true,
+            data: None,
+            message: "已取消收藏".to_string(),
+        })),
+        Ok(false) => Err(StatusCode::NOT_FOUND),
+        Err(e) => {
+            log::error!("Failed to remove favorite device {}: {}", device_id, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+async fn get_user_notification_preferences(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+) -> Result<Json<ApiResponse<NotificationPreferences>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if claims.role != "SuperAdmin" && claims.role != "Admin" {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    load_notification_preferences(&state, &id).await
+}
+
+async fn update_user_notification_preferences(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+    ValidatedJson(req): ValidatedJson<NotificationPreferences>,
+) -> Result<Json<ApiResponse<NotificationPreferences>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if claims.role != "SuperAdmin" && claims.role != "Admin" {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    save_notification_preferences(&state, claims, id, req).await
+}
+
+async fn load_notification_preferences(
+    state: &AppState,
+    user_id: &str,
+) -> Result<Json<ApiResponse<NotificationPreferences>>, StatusCode> {
+    match state.db.get_notification_preferences(user_id).await {
+        Ok(preferences) => Ok(Json(ApiResponse {
+            success: true,
+            data: Some(preferences),
+            message: "获取通知偏好成功".to_string(),
+        })),
+        Err(e) => {
+            log::error!("Failed to get notification preferences of {}: {}", user_id, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+// 按目标用户的角色校验后保存通知偏好并记录审计日志
+async fn save_notification_preferences(
+    state: &AppState,
+    claims: Claims,
+    user_id: String,
+    preferences: NotificationPreferences,
+) -> Result<Json<ApiResponse<NotificationPreferences>>, StatusCode> {
+    let user = match state.db.get_user_by_id(&user_id).await {
+        Ok(Some(user)) => user,
+        Ok(None) => return Err(StatusCode::NOT_FOUND),
+        Err(e) => {
+            log::error!("Failed to get user {}: {}", user_id, e);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    };
+    if let Err(e) = preferences.validate(&user.role) {
+        return Ok(Json(ApiResponse {
+            success: false,
+            data: None,
+            message: format!("通知偏好无效: {}", e),
+        }));
+    }
+    if let Err(e) = state.db.set_notification_preferences(&user_id, &preferences).await {
+        log::error!("Failed to save notification preferences of {}: {}", user_id, e);
+        return Err(StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    let audit_log = AuditLog {
+        id: 0,
+        user_id: claims.sub,
+        device_id: "system".to_string(),
+        action: "update_notification_preferences".to_string(),
+        details: Some(format!(
+            "User {}: {}",
+            user.username,
+            serde_json::to_string(&preferences).unwrap_or_default()
+        )),
+        ip_address: "127.0.0.1".to_string(),
+        user_agent: None,
+        timestamp: SystemTime::now(),
+        success: true,
+    };
+    let _ = state.db.log_audit(&audit_log).await;
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(preferences),
+        message: "通知偏好已更新".to_string(),
+    }))
+}
+
+async fn list_device_views(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<ApiResponse<Vec<DeviceView>>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    match state.db.list_device_views(&claims.sub).await {
+        Ok(views) => Ok(Json(ApiResponse {
+            success: true,
+            data: Some(views),
+            message: "获取设备视图成功".to_string(),
+        })),
+        Err(e) => {
+            log::error!("Failed to list device views: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+async fn create_device_view(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    ValidatedJson(req): ValidatedJson<SaveDeviceViewRequest>,
+) -> Result<Json<ApiResponse<DeviceView>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    match device_views::create(&state.db, &claims.sub, &req.name, req.filter).await {
+        Ok(view) => Ok(Json(ApiResponse {
+            success: true,
+            data: Some(view),
+            message: "设备视图已保存".to_string(),
+        })),
+        Err(e) => Ok(Json(ApiResponse {
+            success: false,
+            data: None,
+            message: format!("保存设备视图失败: {}", e),
+        })),
+    }
+}
+
+async fn update_device_view(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+    ValidatedJson(req): ValidatedJson<SaveDeviceViewRequest>,
+) -> Result<Json<ApiResponse<DeviceView>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    match device_views::update(&state.db, &claims.sub, &id, &req.name, req.filter).await {
+        Ok(Some(view)) => Ok(Json(ApiResponse {
+            success: true,
+            data: Some(view),
+            message: "设备视图已更新".to_string(),
+        })),
+        Ok(None) => Err(StatusCode::NOT_FOUND),
+        Err(e) => Ok(Json(ApiResponse {
+            success: false,
+            data: None,
+            message: format!("更新设备视图失败: {}", e),
+        })),
+    }
+}
+
+async fn delete_device_view(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+) -> Result<Json<ApiResponse<()>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    match state.db.delete_device_view(&id, &claims.sub).await {
+        Ok(true) => Ok(Json(ApiResponse {
+            success: true,
+            data: None,
+            message: "设备视图已删除".to_string(),
+        })),
+        Ok(false) => Err(StatusCode::NOT_FOUND),
+        Err(e) => {
+            log::error!("Failed to delete device view {}: {}", id, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+async fn control_device(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(device_id): Path<String>,
+) -> Result<Json<ApiResponse<String>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+    let device_id = peer_alias::resolve_id(&device_id).await;
+
+    ensure_device_access(&state, &claims, &device_id).await?;
+
+    favorites::record(&state.db, &claims.sub, &device_id).await;
+
+    // 记录控制设备的审计日志
+    let audit_log = AuditLog {
+        id: 0,
+        user_id: claims.sub,
+        device_id: device_id.clone(),
+        action: "control_device".to_string(),
+        details: Some("用户开始控制设备".to_string()),
+        ip_address: "127.0.0.1".to_string(),
+        user_agent: None,
+        timestamp: SystemTime::now(),
+        success: true,
+    };
+    let _ = state.db.log_audit(&audit_log).await;
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some("设备控制会话已建立".to_string()),
+        message: "开始控制设备".to_string(),
+    }))
+}
+
+// 浏览器发起 WebRTC 会话，授权检查与远程控制相同
+async fn create_webrtc_session(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    ValidatedJson(req): ValidatedJson<CreateWebRtcSessionRequest>,
+) -> Result<Json<ApiResponse<SessionOffer>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+    let device_id = peer_alias::resolve_id(&req.device_id).await;
+
+    // 诱饵ID: 记录发起用户并告警，对请求方表现为离线设备
+    if honeypot::is_decoy(&device_id).await {
+        honeypot::trip(
+            &state.db,
+            &device_id,
+            SocketAddr::from(([127, 0, 0, 1], 0)),
+            "webrtc",
+            Some(claims.sub.clone()),
+            vec![("username", claims.username.clone())],
+        )
+        .await;
+        return Ok(Json(ApiResponse {
+            success: false,
+            data: None,
+            message: "无法发起WebRTC会话: peer offline".to_string(),
+        }));
+    }
+
+    ensure_device_access(&state, &claims, &device_id).await?;
+
+    let refusal = webrtc_signaling::refusal(&state.db, &device_id, &claims.sub, &claims.username).await;
+    let res = match refusal {
+        Some(reason) => Err(reason.to_owned()),
+        None => webrtc_signaling::create(&device_id, &claims.sub, &claims.username, req.sdp)
+            .await
+            .map_err(|e| e.to_string()),
+    };
+    if res.is_ok() {
+        favorites::record(&state.db, &claims.sub, &device_id).await;
+    }
+
+    let audit_log = AuditLog {
+        id: 0,
+        user_id: claims.sub,
+        device_id,
+        action: "webrtc_session".to_string(),
+        details: Some(match &res {
+            Ok(offer) => format!("session {}", offer.session_id),
+            Err(reason) => reason.clone(),
+        }),
+        ip_address: "127.0.0.1".to_string(),
+        user_agent: None,
+        timestamp: SystemTime::now(),
+        success: res.is_ok(),
+    };
+    let _ = state.db.log_audit(&audit_log).await;
+
+    match res {
+        Ok(offer) => Ok(Json(ApiResponse {
+            success: true,
+            data: Some(offer),
+            message: "WebRTC会话已发起，等待设备应答".to_string(),
+        })),
+        Err(reason) => Ok(Json(ApiResponse {
+            success: false,
+            data: None,
+            message: format!("无法发起WebRTC会话: {}", reason),
+        })),
+    }
+}
+
+// 浏览器提交候选并取回设备的 answer 和候选
+async fn webrtc_signal(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(session_id): Path<String>,
+    ValidatedJson(req): ValidatedJson<WebRtcSignalRequest>,
+) -> Result<Json<ApiResponse<ControllerUpdate>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    match webrtc_signaling::controller_signal(&session_id, &claims.sub, req.candidates).await {
+        Ok(update) => Ok(Json(ApiResponse {
+            success: true,
+            data: Some(update),
+            message: "获取WebRTC信令成功".to_string(),
+        })),
+        Err(e) => Ok(Json(ApiResponse {
+            success: false,
+            data: None,
+            message: format!("WebRTC信令失败: {}", e),
+        })),
+    }
+}
+
+// 为登录用户签发 TURN 临时凭据，客户端在凭据过期前重新获取
+async fn get_turn_credentials(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<ApiResponse<TurnCredentials>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    match turn::credentials(&claims.sub).await {
+        Some(credentials) => {
+            log::debug!("Issued turn credentials {} to {}", credentials.username, claims.username);
+            Ok(Json(ApiResponse {
+                success: true,
+                data: Some(credentials),
+                message: "获取TURN凭据成功".to_string(),
+            }))
+        }
+        None => Ok(Json(ApiResponse {
+            success: false,
+            data: None,
+            message: "未启用TURN服务".to_string(),
+        })),
+    }
+}
+
+async fn close_webrtc_session(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(session_id): Path<String>,
+) -> Result<Json<ApiResponse<()>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    match webrtc_signaling::close(&session_id, &claims.sub).await {
+        Some(_) => Ok(Json(ApiResponse {
+            success: true,
+            data: None,
+            message: "WebRTC会话已结束".to_string(),
+        })),
+        None => Err(StatusCode::NOT_FOUND),
+    }
+}
+
+// 审计日志处理函数
+async fn get_audit_logs(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    ValidatedQuery(params): ValidatedQuery<AuditLogQuery>,
+) -> Result<Json<ApiResponse<AuditLogResponse>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    // 检查权限 - 只有管理员可以查看所有审计日志
+    let user_id_filter = if is_admin_or_auditor(&claims) {
+        params.user_id.as_deref()
+    } else {
+        Some(claims.sub.as_str()) // 普通用户只能查看自己的日志
+    };
+
+    let page = params.page.unwrap_or(1).max(1);
+    let limit = params.limit.unwrap_or(50).clamp(1, 1000);
+    let offset = (page - 1) * limit;
+
+    // 设备筛选条件可以是别名
+    let device_id_filter = match params.device_id.as_deref() {
+        Some(id) => Some(peer_alias::resolve_id(id).await),
+        None => None,
+    };
+
+    // 格式已由 validation::cidr 校验
+    let ip = params.ip.as_deref().and_then(|x| x.trim().parse::<IpNetwork>().ok());
+    let filter = AuditLogFilter {
+        user_id: user_id_filter.map(str::to_owned),
+        device_id: device_id_filter,
+        action: params.action.filter(|x| !x.is_empty()),
+        success: params.success,
+        since: params.since,
+        until: params.until,
+        ip,
+        text: params.q.map(|x| x.trim().to_owned()).filter(|x| !x.is_empty()),
+    };
+
+    let mut logs = match state.db.get_audit_logs(&filter, limit as i64, offset as i64).await {
+        Ok(logs) => logs,
+        Err(e) => {
+            log::error!("Failed to get audit logs: {}", e);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    };
+
+    let masker = data_masking::for_role(&claims.role).await;
+    logs.iter_mut().for_each(|x| masker.audit_log(x));
+    let device_ids: Vec<String> = logs.iter().map(|x| x.device_id.clone()).collect();
+    let response = AuditLogResponse {
+        total: logs.len(),
+        device_aliases: peer_alias::aliases_of(&device_ids).await,
+        logs,
+    };
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(response),
+        message: "获取审计日志成功".to_string(),
+    }))
+}
+
+// 系统统计处理函数
+async fn get_dashboard_stats(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<ApiResponse<HashMap<String, u64>>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if !is_admin_or_auditor(&claims) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    // 这里应该实现真实的统计数据查询
+    let mut stats = HashMap::new();
+    stats.insert("total_users".to_string(), 10);
+    stats.insert("online_devices".to_string(), 5);
+    stats.insert("total_connections_today".to_string(), 25);
+    stats.insert("active_sessions".to_string(), 3);
+    if let Some(host) = host_stats::current().await {
+        stats.insert("host_rx_bytes_per_sec".to_string(), host.rx_bytes_per_sec);
+        stats.insert("host_tx_bytes_per_sec".to_string(), host.tx_bytes_per_sec);
+        stats.insert("host_tcp_retrans_per_sec".to_string(), host.tcp.retrans_per_sec);
+        stats.insert(
+            "host_listen_queued".to_string(),
+            host.listen.iter().map(|l| l.queued).sum(),
+        );
+    }
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(stats),
+        message: "获取统计数据成功".to_string(),
+    }))
+}
+
+// 本机网络统计和最近一小时的速率曲线
+async fn get_host_stats(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<ApiResponse<HostStatsReport>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if !is_admin_or_auditor(&claims) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(host_stats::report().await),
+        message: "获取主机网络统计成功".to_string(),
+    }))
+}
+
+// Prometheus 指标，接受管理员令牌或环境变量 METRICS_TOKEN 配置的固定令牌(供抓取任务使用)
+async fn get_metrics(State(state): State<AppState>, headers: HeaderMap) -> Result<(HeaderMap, String), StatusCode> {
+    let token = headers
+        .get("Authorization")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "));
+    let metrics_token = std::env::var("METRICS_TOKEN").unwrap_or_default();
+    let static_token = match token {
+        Some(token) => {
+            !metrics_token.is_empty() && sodiumoxide::utils::memcmp(token.as_bytes(), metrics_token.as_bytes())
+        }
+        None => false,
+    };
+    if !static_token {
+        let claims = match extract_claims_from_headers(&state.auth, &headers) {
+            Ok(claims) => claims,
+            Err(_) => return Err(StatusCode::UNAUTHORIZED),
+        };
+        if !is_admin_or_auditor(&claims) {
+            return Err(StatusCode::FORBIDDEN);
+        }
+    }
+
+    let mut response_headers = HeaderMap::new();
+    response_headers.insert(header::CONTENT_TYPE, HeaderValue::from_static("text/plain; version=0.0.4"));
+    let mut body = host_stats::current()
+        .await
+        .map(|stats| host_stats::prometheus(&stats))
+        .unwrap_or_default();
+    body.push_str(&threat_intel::prometheus().await);
+    body.push_str(&db_pool_metrics::prometheus(state.db.pool_status()));
+    Ok((response_headers, body))
+}
+
+// NAT穿透统计
+async fn get_nat_stats(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<ApiResponse<NatStatsSummary>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if !is_admin_or_auditor(&claims) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(nat_diagnostics::summary().await),
+        message: "获取NAT穿透统计成功".to_string(),
+    }))
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub struct E2eStatsQuery {
+    pub device_id: Option<String>,
+    pub since: Option<u64>,
+    pub limit: Option<i64>,
+}
+
+// 端到端加密统计：已下发签名公钥的会话占比及最近的密钥下发记录
+async fn get_e2e_stats(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    ValidatedQuery(query): ValidatedQuery<E2eStatsQuery>,
+) -> Result<Json<ApiResponse<E2eStats>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if !is_admin_or_auditor(&claims) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let since = query
+        .since
+        .unwrap_or_else(|| crate::common::now().saturating_sub(24 * 3600));
+    let limit = query.limit.unwrap_or(100).clamp(1, 1000);
+    let device_id = match query.device_id {
+        Some(id) => Some(peer_alias::resolve_id(&id).await),
+        None => None,
+    };
+    let (total, protected) = match state.db.count_key_exchanges(since).await {
+        Ok(counts) => counts,
+        Err(e) => {
+            log::error!("Failed to count key exchanges: {}", e);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    };
+    let records = match state.db.list_key_exchanges(device_id.as_deref(), limit).await {
+        Ok(records) => records,
+        Err(e) => {
+            log::error!("Failed to list key exchanges: {}", e);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    };
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(E2eStats {
+            total,
+            protected,
+            records,
+        }),
+        message: "获取端到端加密统计成功".to_string(),
+    }))
+}
+
+async fn get_device_nat_diagnostics(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(device_id): Path<String>,
+) -> Result<Json<ApiResponse<PeerNatDiagnostics>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if !is_admin_or_auditor(&claims) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let device_id = peer_alias::resolve_id(&device_id).await;
+    match nat_diagnostics::peer_diagnostics(&device_id).await {
+        Some(diagnostics) => Ok(Json(ApiResponse {
+            success: true,
+            data: Some(diagnostics),
+            message: "获取设备NAT诊断成功".to_string(),
+        })),
+        None => Ok(Json(ApiResponse {
+            success: false,
+            data: None,
+            message: "该设备暂无打洞记录".to_string(),
+        })),
+    }
+}
+
+// 设备最近的连通性自检结果
+async fn get_device_self_tests(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(device_id): Path<String>,
+) -> Result<Json<ApiResponse<Vec<SelfTestResult>>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if !is_admin_or_auditor(&claims) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let device_id = peer_alias::resolve_id(&device_id).await;
+    match state.db.list_self_tests(&device_id).await {
+        Ok(results) => Ok(Json(ApiResponse {
+            success: true,
+            data: Some(results),
+            message: "获取设备自检结果成功".to_string(),
+        })),
+        Err(e) => {
+            log::error!("Failed to list self-tests of {}: {}", device_id, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+// 客户端心跳与系统信息
+async fn verify_client(db: &EnterpriseDatabase, id: &str, uuid: &str) -> bool {
+    match db.get_device_uuid(id).await {
+        Ok(Some(stored)) => !uuid.is_empty() && stored == uuid,
+        Ok(None) => false,
+        Err(e) => {
+            log::error!("Failed to get uuid of {}: {}", id, e);
+            false
+        }
+    }
+}
+
+async fn client_heartbeat(
+    State(state): State<AppState>,
+    ValidatedJson(req): ValidatedJson<HeartbeatRequest>,
+) -> Result<Json<HeartbeatResponse>, StatusCode> {
+    if !verify_client(&state.db, &req.id, &req.uuid).await {
+        return Err(StatusCode::UNAUTHORIZED);
+    }
+    kiosk_fleet::check_in(&req.id).await;
+
+    let effective = match strategy::effective(&state.db, &req.id).await {
+        Ok(effective) => effective,
+        Err(e) => {
+            log::error!("Failed to get strategy of {}: {}", req.id, e);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    };
+
+    let modified_at = effective.modified_at as i64;
+    let mut res = HeartbeatResponse {
+        // 客户端启动后的第一次心跳要求上报系统信息
+        sysinfo: if req.modified_at == 0 { Some(true) } else { None },
+        modified_at,
+        strategy: None,
+        wake: wake_on_lan::take_pending(&req.id).await,
+        jobs: Vec::new(),
+        messages: Vec::new(),
+        announcements: Vec::new(),
+        session_permissions: session_controls::for_device(&req.id).await,
+        update: None,
+        codec: Vec::new(),
+        quic: None,
+        webrtc: Vec::new(),
+    };
+    if req.caps.iter().any(|cap| cap == quic::CAPABILITY) {
+        res.quic = quic::info();
+    }
+    if req.caps.iter().any(|cap| cap == webrtc_signaling::CAPABILITY) {
+        webrtc_signaling::mark_capable(&req.id).await;
+        res.webrtc = webrtc_signaling::pending_for_device(&req.id).await;
+    }
+    match version_policy::check(&state.db, &req.id, "").await {
+        Ok(update) => res.update = update,
+        Err(e) => log::error!("Failed to check version compliance of {}: {}", req.id, e),
+    }
+    match remote_jobs::dispatch(&state.db, &req.id).await {
+        Ok(jobs) => res.jobs = jobs,
+        Err(e) => log::error!("Failed to dispatch jobs to {}: {}", req.id, e),
+    }
+    match device_messages::dispatch(&state.db, &req.id).await {
+        Ok(messages) => res.messages = messages,
+        Err(e) => log::error!("Failed to deliver messages to {}: {}", req.id, e),
+    }
+    match announcements::for_clients(&state.db).await {
+        Ok(announcements) => res.announcements = announcements,
+        Err(e) => log::error!("Failed to get announcements for {}: {}", req.id, e),
+    }
+    match codec_recommendation::for_device(&state.db, &req.id).await {
+        Ok(codec) => res.codec = codec,
+        Err(e) => log::error!("Failed to get codec recommendations for {}: {}", req.id, e),
+    }
+    // 策略有变化时才下发配置
+    if req.modified_at != modified_at {
+        res.strategy = Some(ClientStrategy {
+            config_options: effective.config_options,
+            extra: HashMap::new(),
+        });
+    }
+
+    Ok(Json(res))
+}
+
+async fn client_sysinfo(
+    State(state): State<AppState>,
+    ValidatedJson(req): ValidatedJson<SysinfoRequest>,
+) -> Result<String, StatusCode> {
+    if !verify_client(&state.db, &req.id, &req.uuid).await {
+        return Ok("ID_NOT_FOUND".to_string());
+    }
+
+    let name = if req.hostname.is_empty() { req.id.clone() } else { req.hostname.clone() };
+    if let Ok(mac) = wake_on_lan::normalize_mac(&req.mac) {
+        if let Err(e) = state.db.set_device_mac(&req.id, &mac).await {
+            log::error!("Failed to update mac address of {}: {}", req.id, e);
+        }
+    }
+    let inventory = DeviceInventory::from_sysinfo(
+        &req.id,
+        &req.hostname,
+        &req.username,
+        &req.os,
+        &req.os_build,
+        &req.cpu,
+        &req.memory,
+        &req.version,
+        crate::common::now(),
+    );
+    if let Err(e) = state.db.save_inventory(&inventory).await {
+        log::error!("Failed to save inventory of {}: {}", req.id, e);
+    }
+    if let Err(e) = device_capabilities::save(&state.db, &req.id, req.encoders.clone()).await {
+        log::error!("Failed to save encoder capabilities of {}: {}", req.id, e);
+    }
+    // 同步到 CMDB，不阻塞客户端
+    hbb_common::tokio::spawn(async move { itsm::sync_device(&inventory).await });
+    match state.db.update_device_sysinfo(&req.id, &name, &req.os, &req.version).await {
+        Ok(true) => Ok("SYSINFO_UPDATED".to_string()),
+        Ok(false) => Ok("ID_NOT_FOUND".to_string()),
+        Err(e) => {
+            log::error!("Failed to update sysinfo of {}: {}", req.id, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+// 客户端上报远程任务执行结果
+async fn client_job_result(
+    State(state): State<AppState>,
+    Path(job_id): Path<String>,
+    ValidatedJson(req): ValidatedJson<JobResultRequest>,
+) -> Result<String, StatusCode> {
+    if !verify_client(&state.db, &req.id, &req.uuid).await {
+        return Err(StatusCode::UNAUTHORIZED);
+    }
+
+    let status = remote_jobs::run_status(req.exit_code, req.timed_out);
+    let output = remote_jobs::truncate_output(&req.output);
+    match state
+        .db
+        .finish_job_run(&job_id, &req.id, status, req.exit_code, output)
+        .await
+    {
+        Ok(true) => {}
+        Ok(false) => return Err(StatusCode::NOT_FOUND),
+        Err(e) => {
+            log::error!("Failed to save result of job {} on {}: {}", job_id, req.id, e);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    }
+
+    // 结果记在任务创建者名下，便于按人追溯
+    let created_by = match state.db.get_job(&job_id).await {
+        Ok(Some(job)) => job.created_by,
+        _ => "system".to_string(),
+    };
+    let audit_log = AuditLog {
+        id: 0,
+        user_id: created_by,
+        device_id: req.id,
+        action: "job_result".to_string(),
+        details: Some(format!("job_id={}, status={:?}, exit_code={:?}", job_id, status, req.exit_code)),
+        ip_address: "127.0.0.1".to_string(),
+        user_agent: None,
+        timestamp: SystemTime::now(),
+        success: status == remote_jobs::JobRunStatus::Succeeded,
+    };
+    let _ = state.db.log_audit(&audit_log).await;
+
+    Ok("JOB_RESULT_SAVED".to_string())
+}
+
+// 客户端确认已阅读消息
+async fn client_message_ack(
+    State(state): State<AppState>,
+    Path(message_id): Path<String>,
+    ValidatedJson(req): ValidatedJson<MessageAckRequest>,
+) -> Result<String, StatusCode> {
+    if !verify_client(&state.db, &req.id, &req.uuid).await {
+        return Err(StatusCode::UNAUTHORIZED);
+    }
+
+    match state
+        .db
+        .ack_message_delivery(&message_id, &req.id, crate::common::now())
+        .await
+    {
+        Ok(true) => Ok("MESSAGE_ACKNOWLEDGED".to_string()),
+        Ok(false) => Err(StatusCode::NOT_FOUND),
+        Err(e) => {
+            log::error!("Failed to save ack of message {} on {}: {}", message_id, req.id, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+// 客户端上报会话内操作事件
+async fn client_session_events(
+    State(state): State<AppState>,
+    ValidatedJson(req): ValidatedJson<SessionEventsRequest>,
+) -> Result<String, StatusCode> {
+    if !verify_client(&state.db, &req.id, &req.uuid).await {
+        return Err(StatusCode::UNAUTHORIZED);
+    }
+
+    let events = match session_events::normalize(&req.session_id, &req.id, req.events, crate::common::now()) {
+        Ok(events) => events,
+        Err(_) => return Err(StatusCode::BAD_REQUEST),
+    };
+    match state.db.save_session_events(&events).await {
+        Ok(_) => Ok("SESSION_EVENTS_SAVED".to_string()),
+        Err(e) => {
+            log::error!("Failed to save session events of {} on {}: {}", req.session_id, req.id, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+// 客户端定期上报会话的 QoS 采样，中继会话关联到所在的中继服务器
+async fn client_session_qos(
+    State(state): State<AppState>,
+    ValidatedJson(req): ValidatedJson<SessionQosRequest>,
+) -> Result<String, StatusCode> {
+    if !verify_client(&state.db, &req.id, &req.uuid).await {
+        return Err(StatusCode::UNAUTHORIZED);
+    }
+
+    let relay_server = relay_sessions::relay_of(&req.session_id).await.unwrap_or_default();
+    let samples = match connection_quality::normalize(
+        &req.session_id,
+        &req.id,
+        &relay_server,
+        req.samples,
+        crate::common::now(),
+    ) {
+        Ok(samples) => samples,
+        Err(_) => return Err(StatusCode::BAD_REQUEST),
+    };
+    match connection_quality::save(&state.db, &samples).await {
+        Ok(_) => Ok("SESSION_QOS_SAVED".to_string()),
+        Err(e) => {
+            log::error!("Failed to save QoS samples of {} on {}: {}", req.session_id, req.id, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+// 客户端申请连通性自检计划
+async fn client_start_self_test(
+    State(state): State<AppState>,
+    ValidatedJson(req): ValidatedJson<SelfTestStartRequest>,
+) -> Result<Json<SelfTestPlan>, StatusCode> {
+    if !verify_client(&state.db, &req.id, &req.uuid).await {
+        return Err(StatusCode::UNAUTHORIZED);
+    }
+
+    Ok(Json(self_test::start(&req.id).await))
+}
+
+async fn client_self_test_result(
+    State(state): State<AppState>,
+    Path(test_id): Path<String>,
+    ValidatedJson(req): ValidatedJson<SelfTestReportRequest>,
+) -> Result<Json<SelfTestResult>, StatusCode> {
+    if !verify_client(&state.db, &req.id, &req.uuid).await {
+        return Err(StatusCode::UNAUTHORIZED);
+    }
+
+    match self_test::complete(&state.db, &req.id, &test_id, req.report).await {
+        Ok(result) => Ok(Json(result)),
+        Err(e) => {
+            log::warn!("Failed to complete self-test {} of {}: {}", test_id, req.id, e);
+            Err(StatusCode::BAD_REQUEST)
+        }
+    }
+}
+
+async fn client_codec_ack(
+    State(state): State<AppState>,
+    Path(session_id): Path<String>,
+    ValidatedJson(req): ValidatedJson<CodecAckRequest>,
+) -> Result<String, StatusCode> {
+    if !verify_client(&state.db, &req.id, &req.uuid).await {
+        return Err(StatusCode::UNAUTHORIZED);
+    }
+
+    match codec_recommendation::ack(&state.db, &session_id, &req.id, req.ack).await {
+        Ok(true) => Ok("CODEC_ACKNOWLEDGED".to_string()),
+        Ok(false) => Err(StatusCode::NOT_FOUND),
+        Err(e) => {
+            log::error!("Failed to save codec ack of session {} on {}: {}", session_id, req.id, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+// 被控端提交 WebRTC answer(或拒绝)和候选，返回浏览器的候选
+async fn client_webrtc_signal(
+    State(state): State<AppState>,
+    Path(session_id): Path<String>,
+    ValidatedJson(req): ValidatedJson<DeviceWebRtcSignalRequest>,
+) -> Result<Json<Vec<IceCandidate>>, StatusCode> {
+    if !verify_client(&state.db, &req.id, &req.uuid).await {
+        return Err(StatusCode::UNAUTHORIZED);
+    }
+
+    match webrtc_signaling::device_signal(&session_id, &req.id, req.signal).await {
+        Ok(candidates) => Ok(Json(candidates)),
+        Err(e) => {
+            log::debug!("WebRTC signal of session {} from {} refused: {}", session_id, req.id, e);
+            Err(StatusCode::NOT_FOUND)
+        }
+    }
+}
+
+// 客户端策略管理
+async fn list_strategies(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<ApiResponse<Vec<Strategy>>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if !is_admin_or_auditor(&claims) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    match state.db.list_strategies().await {
+        Ok(strategies) => Ok(Json(ApiResponse {
+            success: true,
+            data: Some(strategies),
+            message: "获取策略列表成功".to_string(),
+        })),
+        Err(e) => {
+            log::error!("Failed to list strategies: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+async fn save_strategy(
+    state: &AppState,
+    claims: Claims,
+    id: String,
+    req: SaveStrategyRequest,
+    action: &str,
+) -> Result<Json<ApiResponse<Strategy>>, StatusCode> {
+    let strategy = Strategy {
+        id,
+        name: req.name,
+        description: req.description,
+        config_options: req.config_options,
+        group_ids: req.group_ids,
+        device_ids: req.device_ids,
+        enabled: req.enabled,
+        updated_at: crate::common::now(),
+    };
+
+    if let Err(e) = strategy::validate(&strategy) {
+        return Ok(Json(ApiResponse {
+            success: false,
+            data: None,
+            message: format!("策略无效: {}", e),
+        }));
+    }
+
+    if let Err(e) = state.db.save_strategy(&strategy).await {
+        log::error!("Failed to save strategy {}: {}", strategy.id, e);
+        return Ok(Json(ApiResponse {
+            success: false,
+            data: None,
+            message: "保存策略失败，名称可能已存在".to_string(),
+        }));
+    }
+
+    let audit_log = AuditLog {
+        id: 0,
+        user_id: claims.sub,
+        device_id: "system".to_string(),
+        action: action.to_string(),
+        details: Some(strategy.name.clone()),
+        ip_address: "127.0.0.1".to_string(),
+        user_agent: None,
+        timestamp: SystemTime::now(),
+        success: true,
+    };
+    let _ = state.db.log_audit(&audit_log).await;
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(strategy),
+        message: "策略已保存，客户端下次心跳时生效".to_string(),
+    }))
+}
+
+async fn create_strategy(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    ValidatedJson(req): ValidatedJson<SaveStrategyRequest>,
+) -> Result<Json<ApiResponse<Strategy>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if claims.role != "SuperAdmin" && claims.role != "Admin" {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let id = uuid::Uuid::new_v4().to_string();
+    save_strategy(&state, claims, id, req, "create_strategy").await
+}
+
+async fn update_strategy(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+    ValidatedJson(req): ValidatedJson<SaveStrategyRequest>,
+) -> Result<Json<ApiResponse<Strategy>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if claims.role != "SuperAdmin" && claims.role != "Admin" {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    save_strategy(&state, claims, id, req, "update_strategy").await
+}
+
+async fn delete_strategy(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+) -> Result<Json<ApiResponse<()>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if claims.role != "SuperAdmin" && claims.role != "Admin" {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    match state.db.delete_strategy(&id).await {
+        Ok(true) => {
+            let audit_log = AuditLog {
+                id: 0,
+                user_id: claims.sub,
+                device_id: "system".to_string(),
+                action: "delete_strategy".to_string(),
+                details: Some(id),
+                ip_address: "127.0.0.1".to_string(),
+                user_agent: None,
+                timestamp: SystemTime::now(),
+                success: true,
+            };
+            let _ = state.db.log_audit(&audit_log).await;
+
+            Ok(Json(ApiResponse {
+                success: true,
+                data: Some(()),
+                message: "策略已删除".to_string(),
+            }))
+        }
+        Ok(false) => Ok(Json(ApiResponse {
+            success: false,
+            data: None,
+            message: "策略不存在".to_string(),
+        })),
+        Err(e) => {
+            log::error!("Failed to delete strategy {}: {}", id, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+async fn get_device_strategy(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(device_id): Path<String>,
+) -> Result<Json<ApiResponse<EffectiveStrategy>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if !is_admin_or_auditor(&claims) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let device_id = peer_alias::resolve_id(&device_id).await;
+    match strategy::effective(&state.db, &device_id).await {
+        Ok(effective) => Ok(Json(ApiResponse {
+            success: true,
+            data: Some(effective),
+            message: "获取设备生效策略成功".to_string(),
+        })),
+        Err(e) => {
+            log::error!("Failed to get strategy of {}: {}", device_id, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+// 设备封禁
+async fn ban_device(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(device_id): Path<String>,
+    ValidatedJson(req): ValidatedJson<BanDeviceRequest>,
+) -> Result<Json<ApiResponse<usize>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if claims.role != "SuperAdmin" && claims.role != "Admin" {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let device_id = peer_alias::resolve_id(&device_id).await;
+    let expires_at = req.duration_secs.map(|x| crate::common::now() + x);
+    let killed = match device_ban::ban(&state.db, &device_id, &req.reason, &claims.sub, expires_at).await {
+        Ok(killed) => killed,
+        Err(e) => {
+            log::error!("Failed to ban device {}: {}", device_id, e);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    };
+
+    let audit_log = AuditLog {
+        id: 0,
+        user_id: claims.sub,
+        device_id,
+        action: "ban_device".to_string(),
+        details: Some(req.reason),
+        ip_address: "127.0.0.1".to_string(),
+        user_agent: None,
+        timestamp: SystemTime::now(),
+        success: true,
+    };
+    let _ = state.db.log_audit(&audit_log).await;
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(killed),
+        message: format!("设备已封禁，断开{}个中继会话", killed),
+    }))
+}
+
+// 网络唤醒设备，由同一子网的在线受管客户端或服务器发送魔术包
+async fn wake_device(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(device_id): Path<String>,
+    ValidatedJson(req): ValidatedJson<WakeDeviceRequest>,
+) -> Result<Json<ApiResponse<WakeResult>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if claims.role != "SuperAdmin" && claims.role != "Admin" {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let device_id = peer_alias::resolve_id(&device_id).await;
+    if let Some(mac) = req.mac_address.as_deref() {
+        let mac = match wake_on_lan::normalize_mac(mac) {
+            Ok(mac) => mac,
+            Err(e) => {
+                return Ok(Json(ApiResponse {
+                    success: false,
+                    data: None,
+                    message: format!("MAC地址无效: {}", e),
+                }))
+            }
+        };
+        match state.db.set_device_mac(&device_id, &mac).await {
+            Ok(true) => {}
+            Ok(false) => return Err(StatusCode::NOT_FOUND),
+            Err(e) => {
+                log::error!("Failed to update mac address of {}: {}", device_id, e);
+                return Err(StatusCode::INTERNAL_SERVER_ERROR);
+            }
+        }
+    }
+
+    let result = match wake_on_lan::wake(&state.db, &device_id).await {
+        Ok(result) => result,
+        Err(e) => {
+            return Ok(Json(ApiResponse {
+                success: false,
+                data: None,
+                message: format!("唤醒设备失败: {}", e),
+            }))
+        }
+    };
+
+    let audit_log = AuditLog {
+        id: 0,
+        user_id: claims.sub,
+        device_id,
+        action: "wake_device".to_string(),
+        details: serde_json::to_string(&result).ok(),
+        ip_address: "127.0.0.1".to_string(),
+        user_agent: None,
+        timestamp: SystemTime::now(),
+        success: true,
+    };
+    let _ = state.db.log_audit(&audit_log).await;
+
+    let message = match &result.agent_id {
+        Some(agent_id) => format!("唤醒请求已交给代理设备 {}", agent_id),
+        None => "已由服务器发送唤醒包".to_string(),
+    };
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(result),
+        message,
+    }))
+}
+
+async fn unban_device(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(device_id): Path<String>,
+) -> Result<Json<ApiResponse<()>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if claims.role != "SuperAdmin" && claims.role != "Admin" {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let device_id = peer_alias::resolve_id(&device_id).await;
+    match device_ban::unban(&state.db, &device_id).await {
+        Ok(true) => {
+            let audit_log = AuditLog {
+                id: 0,
+                user_id: claims.sub,
+                device_id,
+                action: "unban_device".to_string(),
+                details: None,
+                ip_address: "127.0.0.1".to_string(),
+                user_agent: None,
+                timestamp: SystemTime::now(),
+                success: true,
+            };
+            let _ = state.db.log_audit(&audit_log).await;
+
+            Ok(Json(ApiResponse {
+                success: true,
+                data: Some(()),
+                message: "设备已解除封禁".to_string(),
+            }))
+        }
+        Ok(false) => Ok(Json(ApiResponse {
+            success: false,
+            data: None,
+            message: "设备未被封禁".to_string(),
+        })),
+        Err(e) => {
+            log::error!("Failed to unban device {}: {}", device_id, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+async fn list_device_bans(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<ApiResponse<Vec<DeviceBan>>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if !is_admin_or_auditor(&claims) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(device_ban::list().await),
+        message: "获取封禁列表成功".to_string(),
+    }))
+}
+
+// 设备别名
+async fn list_device_aliases(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<ApiResponse<Vec<DeviceAlias>>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if !is_admin_or_auditor(&claims) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    match peer_alias::list(&state.db).await {
+        Ok(aliases) => Ok(Json(ApiResponse {
+            success: true,
+            data: Some(aliases),
+            message: "获取设备别名成功".to_string(),
+        })),
+        Err(e) => {
+            log::error!("Failed to list device aliases: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+async fn set_device_alias(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(device_id): Path<String>,
+    ValidatedJson(req): ValidatedJson<SetAliasRequest>,
+) -> Result<Json<ApiResponse<()>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if claims.role != "SuperAdmin" && claims.role != "Admin" {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    if let Err(e) = peer_alias::set(&state.db, &device_id, &req.alias, &claims.sub).await {
+        log::warn!("Failed to set alias of {}: {}", device_id, e);
+        return Ok(Json(ApiResponse {
+            success: false,
+            data: None,
+            message: format!("设置别名失败: {}", e),
+        }));
+    }
+
+    let audit_log = AuditLog {
+        id: 0,
+        user_id: claims.sub,
+        device_id,
+        action: "set_device_alias".to_string(),
+        details: Some(req.alias),
+        ip_address: "127.0.0.1".to_string(),
+        user_agent: None,
+        timestamp: SystemTime::now(),
+        success: true,
+    };
+    let _ = state.db.log_audit(&audit_log).await;
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(()),
+        message: "设备别名已设置".to_string(),
+    }))
+}
+
+async fn remove_device_alias(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(device_id): Path<String>,
+) -> Result<Json<ApiResponse<()>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if claims.role != "SuperAdmin" && claims.role != "Admin" {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let device_id = peer_alias::resolve_id(&device_id).await;
+    match peer_alias::remove(&state.db, &device_id).await {
+        Ok(true) => {
+            let audit_log = AuditLog {
+                id: 0,
+                user_id: claims.sub,
+                device_id,
+                action: "remove_device_alias".to_string(),
+                details: None,
+                ip_address: "127.0.0.1".to_string(),
+                user_agent: None,
+                timestamp: SystemTime::now(),
+                success: true,
+            };
+            let _ = state.db.log_audit(&audit_log).await;
+
+            Ok(Json(ApiResponse {
+                success: true,
+                data: Some(()),
+                message: "设备别名已删除".to_string(),
+            }))
+        }
+        Ok(false) => Ok(Json(ApiResponse {
+            success: false,
+            data: None,
+            message: "该设备没有别名".to_string(),
+        })),
+        Err(e) => {
+            log::error!("Failed to remove alias of {}: {}", device_id, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+// 辅助函数
+// 审计员(Auditor)可以读取管理员能查看的全部数据，写操作由 auditor_guard 统一拒绝
+fn is_admin_or_auditor(claims: &Claims) -> bool {
+    matches!(claims.role.as_str(), "SuperAdmin" | "Admin" | "Auditor")
+}
+
+// 管理员可以控制所有设备，其他用户只能控制自己的设备
+async fn ensure_device_access(state: &AppState, claims: &Claims, device_id: &str) -> Result<(), StatusCode> {
+    if claims.role == "SuperAdmin" || claims.role == "Admin" {
+        return Ok(());
+    }
+    match state.db.get_devices_by_user(&claims.sub).await {
+        Ok(devices) if devices.iter().any(|d| d.id == device_id) => Ok(()),
+        Ok(_) => Err(StatusCode::FORBIDDEN),
+        Err(e) => {
+            log::error!("Failed to get devices: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+fn extract_claims_from_headers(auth: &AuthManager, headers: &HeaderMap) -> Result<Claims, &'static str> {
+    let claims = extract_any_claims_from_headers(auth, headers)?;
+    // 受限令牌只能访问2FA绑定接口
+    if claims.scope.is_some() {
+        return Err("Restricted token");
+    }
+    Ok(claims)
+}
+
+// 2FA绑定接口同时接受完整令牌和 2fa-setup 受限令牌
+fn extract_setup_claims_from_headers(auth: &AuthManager, headers: &HeaderMap) -> Result<Claims, &'static str> {
+    let claims = extract_any_claims_from_headers(auth, headers)?;
+    match claims.scope.as_deref() {
+        None | Some(SCOPE_2FA_SETUP) => Ok(claims),
+        Some(_) => Err("Invalid token scope"),
+    }
+}
+
+fn extract_any_claims_from_headers(auth: &AuthManager, headers: &HeaderMap) -> Result<Claims, &'static str> {
+    let auth_header = headers
+        .get("Authorization")
+        .ok_or("Missing Authorization header")?
+        .to_str()
+        .map_err(|_| "Invalid Authorization header")?;
+
+    if !auth_header.starts_with("Bearer ") {
+        return Err("Invalid Authorization format");
+    }
+
+    let token = &auth_header[7..];
+    auth.verify_jwt(token).map_err(|_| "Invalid token")
+}
+
+// 占位符函数 - 需要根据具体需求实现
+async fn get_user() -> Result<Json<ApiResponse<UserInfo>>, StatusCode> {
+    Err(StatusCode::NOT_IMPLEMENTED)
+}
+
+async fn update_user() -> Result<Json<ApiResponse<UserInfo>>, StatusCode> {
+    Err(StatusCode::NOT_IMPLEMENTED)
+}
+
+async fn delete_user() -> Result<Json<ApiResponse<()>>, StatusCode> {
+    Err(StatusCode::NOT_IMPLEMENTED)
+}
+
+async fn reset_user_password() -> Result<Json<ApiResponse<()>>, StatusCode> {
+    Err(StatusCode::NOT_IMPLEMENTED)
+}
+
+async fn toggle_user_status() -> Result<Json<ApiResponse<()>>, StatusCode> {
+    Err(StatusCode::NOT_IMPLEMENTED)
+}
+
+async fn get_device() -> Result<Json<ApiResponse<DeviceInfo>>, StatusCode> {
+    Err(StatusCode::NOT_IMPLEMENTED)
+}
+
+async fn update_device() -> Result<Json<ApiResponse<DeviceInfo>>, StatusCode> {
+    Err(StatusCode::NOT_IMPLEMENTED)
+}
+
+async fn delete_device() -> Result<Json<ApiResponse<()>>, StatusCode> {
+    Err(StatusCode::NOT_IMPLEMENTED)
+}
+
+async fn get_connection_stats() -> Result<Json<ApiResponse<HashMap<String, u64>>>, StatusCode> {
+    Err(StatusCode::NOT_IMPLEMENTED)
+}
+
+// 保存私钥的设置项，通用设置接口不返回也不接受，只能通过对应的接口轮换或管理：
+// JWT 密钥可伪造任意用户的令牌，服务器签名私钥可伪造设备公钥签名，控制通道 CA 私钥可签发中继证书
+const KEY_SETTINGS: &[(&str, &str)] = &[
+    (jwt_keys::JWT_KEYS_KEY, "JWT密钥只能通过密钥轮换接口修改"),
+    (server_key::SERVER_KEYS_KEY, "服务器密钥只能通过密钥轮换接口修改"),
+    (control_tls::CONTROL_TLS_KEY, "中继控制通道证书只能通过证书管理接口修改"),
+];
+
+// PUT /api/settings 的请求体，设置名 -> 设置值
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(transparent)]
+pub struct SettingsUpdate(pub HashMap<String, String>);
+
+impl Validate for SettingsUpdate {
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        match self.0.keys().find(|key| key.trim().is_empty() || key.len() > 64) {
+            Some(key) => Err(validation::map_error("settings", "setting_key", format!("设置名 {:?} 无效", key))),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Payload for SettingsUpdate {}
+
+// 含凭据的设置按各自设置接口的方式隐藏密码、令牌和共享密钥
+fn mask_settings(settings: &mut HashMap<String, String>) {
+    mask_setting(settings, email_otp::EMAIL_OTP_KEY, SmtpConfig::masked);
+    mask_setting(settings, federation::FEDERATION_KEY, FederationConfig::masked);
+    mask_setting(settings, turn::TURN_KEY, TurnConfig::masked);
+    mask_setting(settings, itsm::ITSM_KEY, ItsmConfig::masked);
+    mask_setting(settings, ad_sync::AD_SYNC_KEY, AdSyncConfig::masked);
+    mask_setting(settings, webrtc_signaling::WEBRTC_KEY, WebRtcConfig::masked);
+}
+
+fn mask_setting<T: serde::de::DeserializeOwned + serde::Serialize>(
+    settings: &mut HashMap<String, String>,
+    key: &str,
+    masked: fn(&T) -> T,
+) {
+    let value = match settings.remove(key) {
+        Some(value) => value,
+        None => return,
+    };
+    // 无法解析时不返回原文，以免泄露其中的凭据
+    match serde_json::from_str::<T>(&value).map(|config| serde_json::to_string(&masked(&config))) {
+        Ok(Ok(value)) => {
+            settings.insert(key.to_owned(), value);
+        }
+        _ => log::warn!("Setting {} is not valid and is not returned", key),
+    }
+}
+
+async fn get_settings(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<ApiResponse<HashMap<String, String>>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if !is_admin_or_auditor(&claims) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let mut settings = match state.db.list_settings().await {
+        Ok(settings) => settings,
+        Err(e) => {
+            log::error!("Failed to list settings: {}", e);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    };
+    for (key, _) in KEY_SETTINGS {
+        settings.remove(*key);
+    }
+    mask_settings(&mut settings);
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(settings),
+        message: "获取系统设置成功".to_string(),
+    }))
+}
+
+async fn update_settings(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    ValidatedJson(SettingsUpdate(req)): ValidatedJson<SettingsUpdate>,
+) -> Result<Json<ApiResponse<()>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if claims.role != "SuperAdmin" && claims.role != "Admin" {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    if let Some((_, message)) = KEY_SETTINGS.iter().find(|(key, _)| req.contains_key(*key)) {
+        return Ok(Json(ApiResponse {
+            success: false,
+            data: None,
+            message: message.to_string(),
+        }));
+    }
+
+    // 需要审批的设置只能通过对应的设置接口提交
+    for key in req.keys() {
+        if change_approval::requires_approval(key).await {
+            return Ok(Json(ApiResponse {
+                success: false,
+                data: None,
+                message: format!("设置 {} 需要审批，请通过对应的设置接口提交", key),
+            }));
+        }
+    }
+
+    for (key, value) in req.iter() {
+        if let Err(e) = state.db.set_setting(key, value, Some(&claims.sub)).await {
+            log::error!("Failed to update setting {}: {}", key, e);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    }
+
+    // 设置变更后刷新运行时策略缓存
+    if req.contains_key(relay_policy::RELAY_POLICY_KEY) {
+        if let Err(e) = relay_policy::reload(&state.db).await {
+            log::error!("Failed to reload relay policy: {}", e);
+            return Ok(Json(ApiResponse {
+                success: false,
+                data: None,
+                message: "中继策略格式错误".to_string(),
+            }));
+        }
+    }
+
+    if req.contains_key(logging::LOGGING_KEY) {
+        if let Err(e) = logging::reload(&state.db).await {
+            log::error!("Failed to reload logging config: {}", e);
+            return Ok(Json(ApiResponse {
+                success: false,
+                data: None,
+                message: "日志配置格式错误".to_string(),
+            }));
+        }
+    }
+    if req.contains_key(offline_alerts::OFFLINE_ALERTS_KEY) {
+        if let Err(e) = offline_alerts::reload(&state.db).await {
+            log::error!("Failed to reload offline alert config: {}", e);
+            return Ok(Json(ApiResponse {
+                success: false,
+                data: None,
+                message: "离线告警配置格式错误".to_string(),
+            }));
+        }
+    }
+    if req.contains_key(kiosk_fleet::KIOSK_FLEET_KEY) {
+        if let Err(e) = kiosk_fleet::reload(&state.db).await {
+            log::error!("Failed to reload kiosk fleet config: {}", e);
+            return Ok(Json(ApiResponse {
+                success: false,
+                data: None,
+                message: "自助终端机群配置格式错误".to_string(),
+            }));
+        }
+    }
+    if req.contains_key(custom_fields::CUSTOM_FIELDS_KEY) {
+        if let Err(e) = custom_fields::reload(&state.db).await {
+            log::error!("Failed to reload custom fields: {}", e);
+            return Ok(Json(ApiResponse {
+                success: false,
+                data: None,
+                message: "自定义字段配置格式错误".to_string(),
+            }));
+        }
+    }
+    if req.contains_key(backup::BACKUP_KEY) {
+        if let Err(e) = backup::reload(&state.db).await {
+            log::error!("Failed to reload backup config: {}", e);
+            return Ok(Json(ApiResponse {
+                success: false,
+                data: None,
+                message: "备份配置格式错误".to_string(),
+            }));
+        }
+    }
+    if req.contains_key(version_policy::VERSION_POLICY_KEY) {
+        if let Err(e) = version_policy::reload(&state.db).await {
+            log::error!("Failed to reload version policy: {}", e);
+            return Ok(Json(ApiResponse {
+                success: false,
+                data: None,
+                message: "版本策略格式错误".to_string(),
+            }));
+        }
+    }
+
+    if req.contains_key(four_eyes::FOUR_EYES_KEY) {
+        if let Err(e) = four_eyes::reload(&state.db).await {
+            log::error!("Failed to reload four-eyes policy: {}", e);
+            return Ok(Json(ApiResponse {
+                success: false,
+                data: None,
+                message: "双人审批配置格式错误".to_string(),
+            }));
+        }
+    }
+
+    if req.contains_key(unattended_access::UNATTENDED_ACCESS_KEY) {
+        if let Err(e) = unattended_access::reload(&state.db).await {
+            log::error!("Failed to reload unattended access policy: {}", e);
+            return Ok(Json(ApiResponse {
+                success: false,
+                data: None,
+                message: "无人值守访问时段格式错误".to_string(),
+            }));
+        }
+    }
+
+    if req.contains_key(holiday_calendar::HOLIDAY_CALENDARS_KEY) {
+        if let Err(e) = holiday_calendar::reload(&state.db).await {
+            log::error!("Failed to reload holiday calendars: {}", e);
+            return Ok(Json(ApiResponse {
+                success: false,
+                data: None,
+                message: "节假日日历格式错误".to_string(),
+            }));
+        }
+    }
+
+    if req.contains_key(lan_config::LAN_CONFIG_KEY) {
+        if let Err(e) = lan_config::reload(&state.db).await {
+            log::error!("Failed to reload lan config: {}", e);
+            return Ok(Json(ApiResponse {
+                success: false,
+                data: None,
+                message: "局域网配置格式错误".to_string(),
+            }));
+        }
+    }
+
+    if req.contains_key(trusted_device::TRUSTED_DEVICE_KEY) {
+        if let Err(e) = trusted_device::reload(&state.db).await {
+            log::error!("Failed to reload trusted device config: {}", e);
+            return Ok(Json(ApiResponse {
+                success: false,
+                data: None,
+                message: "受信任浏览器配置格式错误".to_string(),
+            }));
+        }
+    }
+
+    if req.contains_key(web_security::WEB_SECURITY_KEY) {
+        if let Err(e) = web_security::reload(&state.db).await {
+            log::error!("Failed to reload web security config: {}", e);
+            return Ok(Json(ApiResponse {
+                success: false,
+                data: None,
+                message: "Web安全配置格式错误".to_string(),
+            }));
+        }
+    }
+
+    if req.contains_key(feature_flags::FEATURE_FLAGS_KEY) {
+        if let Err(e) = feature_flags::reload(&state.db).await {
+            log::error!("Failed to reload feature flags: {}", e);
+            return Ok(Json(ApiResponse {
+                success: false,
+                data: None,
+                message: "功能开关格式错误".to_string(),
+            }));
+        }
+    }
+
+    if req.contains_key(web_session::WEB_SESSION_KEY) {
+        if let Err(e) = web_session::reload(&state.db).await {
+            log::error!("Failed to reload web session config: {}", e);
+            return Ok(Json(ApiResponse {
+                success: false,
+                data: None,
+                message: "Web会话配置格式错误".to_string(),
+            }));
+        }
+    }
+    if req.contains_key(data_masking::DATA_MASKING_KEY) {
+        if let Err(e) = data_masking::reload(&state.db).await {
+            log::error!("Failed to reload data masking config: {}", e);
+            return Ok(Json(ApiResponse {
+                success: false,
+                data: None,
+                message: "数据脱敏规则格式错误".to_string(),
+            }));
+        }
+    }
+
+    if req.contains_key(ad_sync::AD_SYNC_KEY) {
+        if let Err(e) = ad_sync::reload(&state.db).await {
+            log::error!("Failed to reload ad sync config: {}", e);
+            return Ok(Json(ApiResponse {
+                success: false,
+                data: None,
+                message: "AD同步配置格式错误".to_string(),
+            }));
+        }
+    }
+
+    if req.contains_key(itsm::ITSM_KEY) {
+        if let Err(e) = itsm::reload(&state.db).await {
+            log::error!("Failed to reload itsm config: {}", e);
+            return Ok(Json(ApiResponse {
+                success: false,
+                data: None,
+                message: "ITSM集成配置格式错误".to_string(),
+            }));
+        }
+    }
+
+    if req.contains_key(dlp::DLP_POLICY_KEY) {
+        if let Err(e) = dlp::reload(&state.db).await {
+            log::error!("Failed to reload dlp policy: {}", e);
+            return Ok(Json(ApiResponse {
+                success: false,
+                data: None,
+                message: "DLP策略格式错误".to_string(),
+            }));
+        }
+    }
+
+    if req.contains_key(transfer_bandwidth::TRANSFER_BANDWIDTH_KEY) {
+        if let Err(e) = transfer_bandwidth::reload(&state.db).await {
+            log::error!("Failed to reload transfer bandwidth policy: {}", e);
+            return Ok(Json(ApiResponse {
+                success: false,
+                data: None,
+                message: "文件传输带宽策略格式错误".to_string(),
+            }));
+        }
+    }
+
+    if req.contains_key(webrtc_signaling::WEBRTC_KEY) {
+        if let Err(e) = webrtc_signaling::reload(&state.db).await {
+            log::error!("Failed to reload webrtc config: {}", e);
+            return Ok(Json(ApiResponse {
+                success: false,
+                data: None,
+                message: "WebRTC配置格式错误".to_string(),
+            }));
+        }
+    }
+
+    if req.contains_key(turn::TURN_KEY) {
+        if let Err(e) = turn::reload(&state.db).await {
+            log::error!("Failed to reload turn config: {}", e);
+            return Ok(Json(ApiResponse {
+                success: false,
+                data: None,
+                message: "TURN配置格式错误".to_string(),
+            }));
+        }
+    }
+
+    if req.contains_key(pk_pinning::PK_PINNING_KEY) {
+        if let Err(e) = pk_pinning::reload(&state.db).await {
+            log::error!("Failed to reload pk pinning config: {}", e);
+            return Ok(Json(ApiResponse {
+                success: false,
+                data: None,
+                message: "公钥固定配置格式错误".to_string(),
+            }));
+        }
+    }
+
+    if req.contains_key(honeypot::HONEYPOT_KEY) {
+        if let Err(e) = honeypot::reload(&state.db).await {
+            log::error!("Failed to reload honeypot config: {}", e);
+            return Ok(Json(ApiResponse {
+                success: false,
+                data: None,
+                message: "诱饵设备配置格式错误".to_string(),
+            }));
+        }
+    }
+
+    if req.contains_key(threat_intel::THREAT_INTEL_KEY) {
+        if let Err(e) = threat_intel::reload(&state.db).await {
+            log::error!("Failed to reload threat intel config: {}", e);
+            return Ok(Json(ApiResponse {
+                success: false,
+                data: None,
+                message: "威胁情报配置格式错误".to_string(),
+            }));
+        }
+    }
+
+    if req.contains_key(change_approval::CHANGE_APPROVAL_KEY) {
+        if let Err(e) = change_approval::reload(&state.db).await {
+            log::error!("Failed to reload change approval config: {}", e);
+            return Ok(Json(ApiResponse {
+                success: false,
+                data: None,
+                message: "变更审批配置格式错误".to_string(),
+            }));
+        }
+    }
+
+    if req.contains_key(dns_discovery::DNS_DISCOVERY_KEY) {
+        if let Err(e) = dns_discovery::reload(&state.db).await {
+            log::error!("Failed to reload dns discovery config: {}", e);
+            return Ok(Json(ApiResponse {
+                success: false,
+                data: None,
+                message: "DNS服务发现配置格式错误".to_string(),
+            }));
+        }
+    }
+
+    if req.contains_key(federation::FEDERATION_KEY) {
+        if let Err(e) = federation::reload(&state.db).await {
+            log::error!("Failed to reload federation config: {}", e);
+            return Ok(Json(ApiResponse {
+                success: false,
+                data: None,
+                message: "联邦配置格式错误".to_string(),
+            }));
+        }
+    }
+
+    if req.contains_key(network_tuning::NETWORK_TUNING_KEY) {
+        if let Err(e) = network_tuning::reload(&state.db).await {
+            log::error!("Failed to reload network tuning: {}", e);
+            return Ok(Json(ApiResponse {
+                success: false,
+                data: None,
+                message: "连接保活与超时设置格式错误".to_string(),
+            }));
+        }
+    }
+
+    if req.contains_key(content_scan::CONTENT_SCAN_KEY) {
+        if let Err(e) = content_scan::reload(&state.db).await {
+            log::error!("Failed to reload content scan config: {}", e);
+            return Ok(Json(ApiResponse {
+                success: false,
+                data: None,
+                message: "文件扫描配置格式错误".to_string(),
+            }));
+        }
+    }
+
+    if req.contains_key(email_otp::EMAIL_OTP_KEY) {
+        if let Err(e) = email_otp::reload(&state.db).await {
+            log::error!("Failed to reload email otp config: {}", e);
+            return Ok(Json(ApiResponse {
+                success: false,
+                data: None,
+                message: "邮件验证码配置格式错误".to_string(),
+            }));
+        }
+    }
+
+    if req.contains_key(e2e_signaling::E2E_POLICY_KEY) {
+        if let Err(e) = e2e_signaling::reload(&state.db).await {
+            log::error!("Failed to reload e2e policy: {}", e);
+            return Ok(Json(ApiResponse {
+                success: false,
+                data: None,
+                message: "端到端加密策略格式错误".to_string(),
+            }));
+        }
+    }
+
+    if req.contains_key(mfa_policy::MFA_POLICY_KEY) {
+        if let Err(e) = mfa_policy::reload(&state.db).await {
+            log::error!("Failed to reload mfa policy: {}", e);
+            return Ok(Json(ApiResponse {
+                success: false,
+                data: None,
+                message: "双因素认证策略格式错误".to_string(),
+            }));
+        }
+    }
+
+    if req.contains_key(id_policy::ID_POLICY_KEY) {
+        if let Err(e) = id_policy::reload(&state.db).await {
+            log::error!("Failed to reload id policy: {}", e);
+            return Ok(Json(ApiResponse {
+                success: false,
+                data: None,
+                message: "ID注册策略格式错误".to_string(),
+            }));
+        }
+    }
+
+    if req.contains_key(peer_alias::ALIAS_IN_PUNCH_KEY) {
+        if let Err(e) = peer_alias::reload(&state.db).await {
+            log::error!("Failed to reload device aliases: {}", e);
+        }
+    }
+
+    let audit_log = AuditLog {
+        id: 0,
+        user_id: claims.sub,
+        device_id: "system".to_string(),
+        action: "update_settings".to_string(),
+        details: Some(req.keys().cloned().collect::<Vec<_>>().join(",")),
+        ip_address: "127.0.0.1".to_string(),
+        user_agent: None,
+        timestamp: SystemTime::now(),
+        success: true,
+    };
+    let _ = state.db.log_audit(&audit_log).await;
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(()),
+        message: "系统设置已更新".to_string(),
+    }))
+}
+
+async fn get_relay_policy(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<ApiResponse<RelayPolicy>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if !is_admin_or_auditor(&claims) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(relay_policy::get().await),
+        message: "获取中继策略成功".to_string(),
+    }))
+}
+
+async fn update_relay_policy(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    ValidatedJson(req): ValidatedJson<RelayPolicy>,
+) -> Result<Json<ApiResponse<RelayPolicy>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if claims.role != "SuperAdmin" && claims.role != "Admin" {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    if let Err(e) = relay_policy::update(&state.db, req.clone(), &claims.sub).await {
+        log::error!("Failed to update relay policy: {}", e);
+        return Err(StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    let audit_log = AuditLog {
+        id: 0,
+        user_id: claims.sub,
+        device_id: "system".to_string(),
+        action: "update_relay_policy".to_string(),
+        details: serde_json::to_string(&req).ok(),
+        ip_address: "127.0.0.1".to_string(),
+        user_agent: None,
+        timestamp: SystemTime::now(),
+        success: true,
+    };
+    let _ = state.db.log_audit(&audit_log).await;
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(req),
+        message: "中继策略已更新".to_string(),
+    }))
+}
+
+async fn get_version_policy(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<ApiResponse<VersionPolicy>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if !is_admin_or_auditor(&claims) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(version_policy::get().await),
+        message: "获取版本策略成功".to_string(),
+    }))
+}
+
+async fn update_version_policy(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    ValidatedJson(req): ValidatedJson<VersionPolicy>,
+) -> Result<Json<ApiResponse<VersionPolicy>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if claims.role != "SuperAdmin" {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    if let Err(e) = version_policy::update(&state.db, req.clone(), &claims.sub).await {
+        return Ok(Json(ApiResponse {
+            success: false,
+            data: None,
+            message: format!("版本策略无效: {}", e),
+        }));
+    }
+
+    let audit_log = AuditLog {
+        id: 0,
+        user_id: claims.sub,
+        device_id: "system".to_string(),
+        action: "update_version_policy".to_string(),
+        details: serde_json::to_string(&req).ok(),
+        ip_address: "127.0.0.1".to_string(),
+        user_agent: None,
+        timestamp: SystemTime::now(),
+        success: true,
+    };
+    let _ = state.db.log_audit(&audit_log).await;
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(req),
+        message: "版本策略已更新".to_string(),
+    }))
+}
+
+async fn get_unattended_access(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<ApiResponse<UnattendedPolicy>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if !is_admin_or_auditor(&claims) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(unattended_access::get().await),
+        message: "获取无人值守访问时段成功".to_string(),
+    }))
+}
+
+async fn update_unattended_access(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    ValidatedJson(req): ValidatedJson<UnattendedPolicy>,
+) -> Result<Json<ApiResponse<UnattendedPolicy>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if claims.role != "SuperAdmin" && claims.role != "Admin" {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    if let Err(e) = unattended_access::update(&state.db, req.clone(), &claims.sub).await {
+        return Ok(Json(ApiResponse {
+            success: false,
+            data: None,
+            message: format!("无人值守访问时段无效: {}", e),
+        }));
+    }
+
+    let audit_log = AuditLog {
+        id: 0,
+        user_id: claims.sub,
+        device_id: "system".to_string(),
+        action: "update_unattended_access".to_string(),
+        details: serde_json::to_string(&req).ok(),
+        ip_address: "127.0.0.1".to_string(),
+        user_agent: None,
+        timestamp: SystemTime::now(),
+        success: true,
+    };
+    let _ = state.db.log_audit(&audit_log).await;
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(req),
+        message: "无人值守访问时段已更新".to_string(),
+    }))
+}
+
+async fn get_holiday_calendars(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<ApiResponse<CalendarConfig>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if !is_admin_or_auditor(&claims) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(holiday_calendar::get().await),
+        message: "获取节假日日历成功".to_string(),
+    }))
+}
+
+async fn update_holiday_calendars(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    ValidatedJson(req): ValidatedJson<CalendarConfig>,
+) -> Result<Json<ApiResponse<CalendarConfig>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if claims.role != "SuperAdmin" && claims.role != "Admin" {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    if let Err(e) = holiday_calendar::update(&state.db, req.clone(), &claims.sub).await {
+        return Ok(Json(ApiResponse {
+            success: false,
+            data: None,
+            message: format!("节假日日历无效: {}", e),
+        }));
+    }
+
+    let audit_log = AuditLog {
+        id: 0,
+        user_id: claims.sub,
+        device_id: "system".to_string(),
+        action: "update_holiday_calendars".to_string(),
+        details: serde_json::to_string(&req).ok(),
+        ip_address: "127.0.0.1".to_string(),
+        user_agent: None,
+        timestamp: SystemTime::now(),
+        success: true,
+    };
+    let _ = state.db.log_audit(&audit_log).await;
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(req),
+        message: "节假日日历已更新".to_string(),
+    }))
+}
+
+// 请求体为 iCalendar(.ics) 文件内容，每个事件的日期(多日事件展开为每天)成为日历条目
+async fn import_holiday_calendar(
+    State(state): State<AppState>,
+    ValidatedQuery(query): ValidatedQuery<HolidayImportQuery>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<Json<ApiResponse<HolidayCalendar>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if claims.role != "SuperAdmin" && claims.role != "Admin" {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    // 导入会直接写入设置，节假日日历纳入变更审批时只能通过设置接口提交
+    if change_approval::requires_approval(holiday_calendar::HOLIDAY_CALENDARS_KEY).await {
+        return Ok(Json(ApiResponse {
+            success: false,
+            data: None,
+            message: format!(
+                "设置 {} 需要审批，请通过对应的设置接口提交",
+                holiday_calendar::HOLIDAY_CALENDARS_KEY
+            ),
+        }));
+    }
+
+    let ics = String::from_utf8_lossy(&body);
+    let region = query.region.unwrap_or_default();
+    let calendar =
+        match holiday_calendar::import(&state.db, &query.id, &query.name, &region, &ics, &claims.sub).await {
+            Ok(calendar) => calendar,
+            Err(e) => {
+                log::warn!("Failed to import holiday calendar {}: {}", query.id, e);
+                return Ok(Json(ApiResponse {
+                    success: false,
+                    data: None,
+                    message: format!("导入节假日日历失败: {}", e),
+                }));
+            }
+        };
+
+    let audit_log = AuditLog {
+        id: 0,
+        user_id: claims.sub,
+        device_id: "system".to_string(),
+        action: "import_holiday_calendar".to_string(),
+        details: Some(format!(
+            "id={}, region={}, entries={}",
+            calendar.id,
+            calendar.region,
+            calendar.entries.len()
+        )),
+        ip_address: "127.0.0.1".to_string(),
+        user_agent: None,
+        timestamp: SystemTime::now(),
+        success: true,
+    };
+    let _ = state.db.log_audit(&audit_log).await;
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(calendar),
+        message: "节假日日历已导入".to_string(),
+    }))
+}
+
+async fn get_lan_config(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<ApiResponse<LanConfig>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if !is_admin_or_auditor(&claims) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(lan_config::get().await),
+        message: "获取局域网配置成功".to_string(),
+    }))
+}
+
+async fn update_lan_config(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    ValidatedJson(req): ValidatedJson<LanConfig>,
+) -> Result<Json<ApiResponse<LanConfig>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if claims.role != "SuperAdmin" && claims.role != "Admin" {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    // 校验失败时不保存，返回具体原因
+    if let Err(e) = lan_config::update(&state.db, req.clone(), &claims.sub).await {
+        log::warn!("Failed to update lan config: {}", e);
+        return Ok(Json(ApiResponse {
+            success: false,
+            data: None,
+            message: format!("局域网配置无效: {}", e),
+        }));
+    }
+
+    let audit_log = AuditLog {
+        id: 0,
+        user_id: claims.sub,
+        device_id: "system".to_string(),
+        action: "update_lan_config".to_string(),
+        details: serde_json::to_string(&req).ok(),
+        ip_address: "127.0.0.1".to_string(),
+        user_agent: None,
+        timestamp: SystemTime::now(),
+        success: true,
+    };
+    let _ = state.db.log_audit(&audit_log).await;
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(req),
+        message: "局域网配置已更新，立即生效".to_string(),
+    }))
+}
+
+async fn get_id_policy(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<ApiResponse<IdPolicy>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if !is_admin_or_auditor(&claims) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(id_policy::get().await),
+        message: "获取ID注册策略成功".to_string(),
+    }))
+}
+
+async fn update_id_policy(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    ValidatedJson(req): ValidatedJson<IdPolicy>,
+) -> Result<Json<ApiResponse<IdPolicy>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if claims.role != "SuperAdmin" && claims.role != "Admin" {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    if let Err(e) = id_policy::update(&state.db, req.clone(), &claims.sub).await {
+        log::warn!("Failed to update id policy: {}", e);
+        return Ok(Json(ApiResponse {
+            success: false,
+            data: None,
+            message: format!("ID注册策略无效: {}", e),
+        }));
+    }
+
+    let audit_log = AuditLog {
+        id: 0,
+        user_id: claims.sub,
+        device_id: "system".to_string(),
+        action: "update_id_policy".to_string(),
+        details: serde_json::to_string(&req).ok(),
+        ip_address: "127.0.0.1".to_string(),
+        user_agent: None,
+        timestamp: SystemTime::now(),
+        success: true,
+    };
+    let _ = state.db.log_audit(&audit_log).await;
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(req),
+        message: "ID注册策略已更新".to_string(),
+    }))
+}
+
+// 连接密码策略
+async fn get_password_policies(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<ApiResponse<PasswordPolicies>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if !is_admin_or_auditor(&claims) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(password_policy::get().await),
+        message: "获取密码策略成功".to_string(),
+    }))
+}
+
+async fn update_password_policies(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    ValidatedJson(req): ValidatedJson<PasswordPolicies>,
+) -> Result<Json<ApiResponse<i32>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if claims.role != "SuperAdmin" && claims.role != "Admin" {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let serial = match password_policy::update(&state.db, req.clone(), &claims.sub).await {
+        Ok(serial) => serial,
+        Err(e) => {
+            log::warn!("Failed to update password policies: {}", e);
+            return Ok(Json(ApiResponse {
+                success: false,
+                data: None,
+                message: format!("密码策略无效: {}", e),
+            }));
+        }
+    };
+
+    let audit_log = AuditLog {
+        id: 0,
+        user_id: claims.sub,
+        device_id: "system".to_string(),
+        action: "update_password_policies".to_string(),
+        details: serde_json::to_string(&req).ok(),
+        ip_address: "127.0.0.1".to_string(),
+        user_agent: None,
+        timestamp: SystemTime::now(),
+        success: true,
+    };
+    let _ = state.db.log_audit(&audit_log).await;
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(serial),
+        message: "密码策略已更新，客户端下次注册时生效".to_string(),
+    }))
+}
+
+async fn get_device_password_policy(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(device_id): Path<String>,
+) -> Result<Json<ApiResponse<PasswordPolicy>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if !is_admin_or_auditor(&claims) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let device_id = peer_alias::resolve_id(&device_id).await;
+    match password_policy::effective_policy(&state.db, &device_id).await {
+        Some(policy) => Ok(Json(ApiResponse {
+            success: true,
+            data: Some(policy),
+            message: "获取设备密码策略成功".to_string(),
+        })),
+        None => Ok(Json(ApiResponse {
+            success: false,
+            data: None,
+            message: "该设备未配置密码策略".to_string(),
+        })),
+    }
+}
+
+// 预登记ID管理
+async fn list_provisioned_ids(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    ValidatedQuery(params): ValidatedQuery<PaginationQuery>,
+) -> Result<Json<ApiResponse<Vec<ProvisionedId>>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if !is_admin_or_auditor(&claims) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let page = params.page.unwrap_or(1).max(1);
+    let limit = params.limit.unwrap_or(100);
+    let offset = (page - 1) * limit;
+
+    match state.db.list_provisioned_ids(limit as i64, offset as i64).await {
+        Ok(ids) => Ok(Json(ApiResponse {
+            success: true,
+            data: Some(ids),
+            message: "获取预登记ID成功".to_string(),
+        })),
+        Err(e) => {
+            log::error!("Failed to list provisioned ids: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+async fn add_provisioned_ids(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    ValidatedJson(req): ValidatedJson<ProvisionIdsRequest>,
+) -> Result<Json<ApiResponse<usize>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if claims.role != "SuperAdmin" && claims.role != "Admin" {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let added = match state.db.add_provisioned_ids(&req.ids, req.note.as_deref(), &claims.sub).await {
+        Ok(added) => added,
+        Err(e) => {
+            log::error!("Failed to add provisioned ids: {}", e);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    };
+
+    let audit_log = AuditLog {
+        id: 0,
+        user_id: claims.sub,
+        device_id: "system".to_string(),
+        action: "add_provisioned_ids".to_string(),
+        details: Some(req.ids.join(",")),
+        ip_address: "127.0.0.1".to_string(),
+        user_agent: None,
+        timestamp: SystemTime::now(),
+        success: true,
+    };
+    let _ = state.db.log_audit(&audit_log).await;
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(added),
+        message: format!("已预登记{}个ID", added),
+    }))
+}
+
+async fn remove_provisioned_id(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+) -> Result<Json<ApiResponse<()>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if claims.role != "SuperAdmin" && claims.role != "Admin" {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    match state.db.remove_provisioned_id(&id).await {
+        Ok(true) => {
+            let audit_log = AuditLog {
+                id: 0,
+                user_id: claims.sub,
+                device_id: id,
+                action: "remove_provisioned_id".to_string(),
+                details: None,
+                ip_address: "127.0.0.1".to_string(),
+                user_agent: None,
+                timestamp: SystemTime::now(),
+                success: true,
+            };
+            let _ = state.db.log_audit(&audit_log).await;
+
+            Ok(Json(ApiResponse {
+                success: true,
+                data: Some(()),
+                message: "预登记ID已删除".to_string(),
+            }))
+        }
+        Ok(false) => Ok(Json(ApiResponse {
+            success: false,
+            data: None,
+            message: "预登记ID不存在".to_string(),
+        })),
+        Err(e) => {
+            log::error!("Failed to remove provisioned id {}: {}", id, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+async fn setup_two_factor(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<ApiResponse<TwoFactorSetupResponse>>, StatusCode> {
+    let claims = match extract_setup_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    let user = match state.db.get_user_by_username(&claims.username).await {
+        Ok(Some(user)) => user,
+        Ok(None) => return Err(StatusCode::UNAUTHORIZED),
+        Err(e) => {
+            log::error!("Failed to load user {}: {}", claims.username, e);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    };
+    if user.two_factor_enabled {
+        return Ok(Json(ApiResponse {
+            success: false,
+            data: None,
+            message: "已启用双因素认证".to_string(),
+        }));
+    }
+
+    // 新密钥在确认前不启用，重复调用会覆盖未确认的密钥
+    let two_factor = TwoFactorAuth::new();
+    if let Err(e) = state
+        .db
+        .set_user_two_factor(&user.id, Some(two_factor.get_secret()), false)
+        .await
+    {
+        log::error!("Failed to save 2fa secret of {}: {}", user.username, e);
+        return Err(StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(TwoFactorSetupResponse {
+            secret: two_factor.get_secret().to_string(),
+            otpauth_url: two_factor.generate_qr_code_url(&user.username, "RustDesk Enterprise"),
+        }),
+        message: "请使用验证器扫描并输入验证码确认".to_string(),
+    }))
+}
+
+async fn confirm_two_factor(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    ValidatedJson(req): ValidatedJson<TwoFactorConfirmRequest>,
+) -> Result<Json<LoginResponse>, StatusCode> {
+    let claims = match extract_setup_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    let mut user = match state.db.get_user_by_username(&claims.username).await {
+        Ok(Some(user)) => user,
+        Ok(None) => return Err(StatusCode::UNAUTHORIZED),
+        Err(e) => {
+            log::error!("Failed to load user {}: {}", claims.username, e);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    };
+    let secret = match user.two_factor_secret.clone() {
+        Some(secret) if !user.two_factor_enabled => secret,
+        _ => {
+            return Ok(Json(LoginResponse {
+                success: false,
+                token: None,
+                user: None,
+                message: "没有待确认的双因素认证".to_string(),
+                require_2fa_setup: false,
+            }));
+        }
+    };
+    if !TwoFactorAuth::verify_code(&secret, &req.code) {
+        return Ok(Json(LoginResponse {
+            success: false,
+            token: None,
+            user: None,
+            message: "双因素认证代码错误".to_string(),
+            require_2fa_setup: claims.scope.is_some(),
+        }));
+    }
+
+    if let Err(e) = state.db.set_user_two_factor(&user.id, Some(&secret), true).await {
+        log::error!("Failed to enable 2fa of {}: {}", user.username, e);
+        return Err(StatusCode::INTERNAL_SERVER_ERROR);
+    }
+    user.two_factor_enabled = true;
+
+    let audit_log = AuditLog {
+        id: 0,
+        user_id: user.id.clone(),
+        device_id: "system".to_string(),
+        action: "enable_2fa".to_string(),
+        details: Some("启用双因素认证".to_string()),
+        ip_address: "127.0.0.1".to_string(),
+        user_agent: None,
+        timestamp: SystemTime::now(),
+        success: true,
+    };
+    let _ = state.db.log_audit(&audit_log).await;
+
+    // 绑定完成后换发完整令牌
+    let token = match issue_session_token(&state, &user, &headers).await {
+        Ok(token) => token,
+        Err(e) => {
+            log::error!("Failed to issue session token: {}", e);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    };
+
+    Ok(Json(LoginResponse {
+        success: true,
+        token: Some(token),
+        user: None,
+        message: "双因素认证已启用".to_string(),
+        require_2fa_setup: false,
+    }))
+}
+
+async fn get_mfa_policy(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<ApiResponse<MfaPolicy>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if !is_admin_or_auditor(&claims) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(mfa_policy::get().await),
+        message: "获取双因素认证策略成功".to_string(),
+    }))
+}
+
+async fn update_mfa_policy(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    ValidatedJson(req): ValidatedJson<MfaPolicy>,
+) -> Result<Json<ApiResponse<MfaPolicy>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if claims.role != "SuperAdmin" {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    if let Err(e) = mfa_policy::update(&state.db, req.clone(), &claims.sub).await {
+        log::error!("Failed to update mfa policy: {}", e);
+        return Err(StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    let audit_log = AuditLog {
+        id: 0,
+        user_id: claims.sub,
+        device_id: "system".to_string(),
+        action: "update_mfa_policy".to_string(),
+        details: serde_json::to_string(&req).ok(),
+        ip_address: "127.0.0.1".to_string(),
+        user_agent: None,
+        timestamp: SystemTime::now(),
+        success: true,
+    };
+    let _ = state.db.log_audit(&audit_log).await;
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(req),
+        message: "双因素认证策略已更新".to_string(),
+    }))
+}
+
+// 未登录接口：校验用户名密码后发送邮件验证码，需管理员事先开放授权
+async fn send_email_otp(
+    State(state): State<AppState>,
+    ValidatedJson(req): ValidatedJson<EmailOtpRequest>,
+) -> Result<Json<ApiResponse<()>>, StatusCode> {
+    let user = match state.db.get_user_by_username(&req.username).await {
+        Ok(user) => user,
+        Err(e) => {
+            log::error!("Database error during email otp request: {}", e);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    };
+    // 与登录接口一致: 用户不存在时同样校验一次密码，用户不存在、已锁定和密码错误返回同样的失败
+    let password_ok = state
+        .auth
+        .verify_user_password(&req.password, user.as_ref().map(|x| x.password_hash.as_str()));
+    let user = match user {
+        Some(user) if password_ok && !state.auth.is_user_locked(&user) => user,
+        user => {
+            if let Some(user) = user {
+                record_login_failure(&state, &user).await;
+            }
+            return Ok(Json(ApiResponse {
+                success: false,
+                data: None,
+                message: "用户名或密码错误".to_string(),
+            }));
+        }
+    };
+
+    let granted = match state.db.get_email_otp_grant(&user.id).await {
+        Ok(grant) => grant.is_some(),
+        Err(e) => {
+            log::error!("Failed to load email otp grant of {}: {}", user.id, e);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    };
+    let email = match user.email.as_deref() {
+        Some(email) if granted && user.two_factor_enabled => email,
+        _ => {
+            return Ok(Json(ApiResponse {
+                success: false,
+                data: None,
+                message: "未开放邮件验证码，请联系管理员".to_string(),
+            }));
+        }
+    };
+
+    let result = email_otp::send_code(&user.id, email).await;
+    let audit_log = AuditLog {
+        id: 0,
+        user_id: user.id.clone(),
+        device_id: "system".to_string(),
+        action: "send_email_otp".to_string(),
+        details: result.as_ref().err().map(|e| e.to_string()),
+        ip_address: "127.0.0.1".to_string(),
+        user_agent: None,
+        timestamp: SystemTime::now(),
+        success: result.is_ok(),
+    };
+    let _ = state.db.log_audit(&audit_log).await;
+
+    if let Err(e) = result {
+        log::warn!("Failed to send email otp to {}: {}", user.username, e);
+        return Ok(Json(ApiResponse {
+            success: false,
+            data: None,
+            message: format!("发送邮件验证码失败: {}", e),
+        }));
+    }
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: None,
+        message: "验证码已发送到绑定邮箱".to_string(),
+    }))
+}
+
+async fn grant_email_otp(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(user_id): Path<String>,
+    ValidatedJson(req): ValidatedJson<GrantEmailOtpRequest>,
+) -> Result<Json<ApiResponse<u64>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if claims.role != "SuperAdmin" && claims.role != "Admin" {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    // 授权最长 72 小时，默认 24 小时
+    let hours = req.hours.unwrap_or(24).clamp(1, 72);
+    let expires_at = crate::common::now() + hours * 3600;
+    if let Err(e) = state.db.grant_email_otp(&user_id, &claims.sub, expires_at).await {
+        log::error!("Failed to grant email otp to {}: {}", user_id, e);
+        return Err(StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    let audit_log = AuditLog {
+        id: 0,
+        user_id: claims.sub,
+        device_id: "system".to_string(),
+        action: "grant_email_otp".to_string(),
+        details: Some(format!("user={}, hours={}", user_id, hours)),
+        ip_address: "127.0.0.1".to_string(),
+        user_agent: None,
+        timestamp: SystemTime::now(),
+        success: true,
+    };
+    let _ = state.db.log_audit(&audit_log).await;
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(expires_at),
+        message: "已开放邮件验证码登录".to_string(),
+    }))
+}
+
+async fn revoke_email_otp(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(user_id): Path<String>,
+) -> Result<Json<ApiResponse<()>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if claims.role != "SuperAdmin" && claims.role != "Admin" {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let removed = match state.db.revoke_email_otp(&user_id).await {
+        Ok(removed) => removed,
+        Err(e) => {
+            log::error!("Failed to revoke email otp of {}: {}", user_id, e);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    };
+    email_otp::clear(&user_id).await;
+
+    let audit_log = AuditLog {
+        id: 0,
+        user_id: claims.sub,
+        device_id: "system".to_string(),
+        action: "revoke_email_otp".to_string(),
+        details: Some(format!("user={}", user_id)),
+        ip_address: "127.0.0.1".to_string(),
+        user_agent: None,
+        timestamp: SystemTime::now(),
+        success: removed,
+    };
+    let _ = state.db.log_audit(&audit_log).await;
+
+    Ok(Json(ApiResponse {
+        success: removed,
+        data: None,
+        message: if removed { "已撤销邮件验证码授权" } else { "用户没有邮件验证码授权" }.to_string(),
+    }))
+}
+
+async fn get_email_otp_config(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<ApiResponse<SmtpConfig>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if !is_admin_or_auditor(&claims) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(email_otp::get().await.masked()),
+        message: "获取邮件验证码配置成功".to_string(),
+    }))
+}
+
+async fn update_email_otp_config(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    ValidatedJson(req): ValidatedJson<SmtpConfig>,
+) -> Result<Json<ApiResponse<SmtpConfig>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if claims.role != "SuperAdmin" {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    if let Err(e) = email_otp::update(&state.db, req, &claims.sub).await {
+        log::warn!("Failed to update email otp config: {}", e);
+        return Ok(Json(ApiResponse {
+            success: false,
+            data: None,
+            message: format!("邮件验证码配置无效: {}", e),
+        }));
+    }
+    let config = email_otp::get().await.masked();
+
+    let audit_log = AuditLog {
+        id: 0,
+        user_id: claims.sub,
+        device_id: "system".to_string(),
+        action: "update_email_otp_config".to_string(),
+        details: serde_json::to_string(&config).ok(),
+        ip_address: "127.0.0.1".to_string(),
+        user_agent: None,
+        timestamp: SystemTime::now(),
+        success: true,
+    };
+    let _ = state.db.log_audit(&audit_log).await;
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(config),
+        message: "邮件验证码配置已更新".to_string(),
+    }))
+}
+
+async fn list_trusted_devices(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<ApiResponse<Vec<TrustedDevice>>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    match state.db.list_trusted_devices(&claims.sub).await {
+        Ok(devices) => Ok(Json(ApiResponse {
+            success: true,
+            data: Some(devices),
+            message: "获取受信任浏览器列表成功".to_string(),
+        })),
+        Err(e) => {
+            log::error!("Failed to list trusted devices: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+async fn revoke_trusted_device(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+) -> Result<Json<ApiResponse<u64>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    revoke_trusted_devices(&state, claims.sub.clone(), &claims.sub, Some(&id)).await
+}
+
+async fn revoke_all_trusted_devices(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<ApiResponse<u64>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    revoke_trusted_devices(&state, claims.sub.clone(), &claims.sub, None).await
+}
+
+async fn revoke_user_trusted_devices(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(user_id): Path<String>,
+) -> Result<Json<ApiResponse<u64>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if claims.role != "SuperAdmin" && claims.role != "Admin" {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    revoke_trusted_devices(&state, claims.sub, &user_id, None).await
+}
+
+async fn revoke_trusted_devices(
+    state: &AppState,
+    operator: String,
+    user_id: &str,
+    id: Option<&str>,
+) -> Result<Json<ApiResponse<u64>>, StatusCode> {
+    let removed = match state.db.remove_trusted_devices(user_id, id).await {
+        Ok(removed) => removed,
+        Err(e) => {
+            log::error!("Failed to revoke trusted devices of {}: {}", user_id, e);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    };
+
+    let audit_log = AuditLog {
+        id: 0,
+        user_id: operator,
+        device_id: "system".to_string(),
+        action: "revoke_trusted_device".to_string(),
+        details: Some(format!("user={}, id={}", user_id, id.unwrap_or("*"))),
+        ip_address: "127.0.0.1".to_string(),
+        user_agent: None,
+        timestamp: SystemTime::now(),
+        success: true,
+    };
+    let _ = state.db.log_audit(&audit_log).await;
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(removed),
+        message: format!("已撤销 {} 个受信任浏览器", removed),
+    }))
+}
+
+async fn get_trusted_device_config(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<ApiResponse<TrustedDeviceConfig>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if !is_admin_or_auditor(&claims) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(trusted_device::get().await),
+        message: "获取受信任浏览器配置成功".to_string(),
+    }))
+}
+
+async fn update_trusted_device_config(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    ValidatedJson(req): ValidatedJson<TrustedDeviceConfig>,
+) -> Result<Json<ApiResponse<TrustedDeviceConfig>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if claims.role != "SuperAdmin" {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    if let Err(e) = trusted_device::update(&state.db, req.clone(), &claims.sub).await {
+        log::warn!("Failed to update trusted device config: {}", e);
+        return Ok(Json(ApiResponse {
+            success: false,
+            data: None,
+            message: format!("受信任浏览器配置无效: {}", e),
+        }));
+    }
+
+    let audit_log = AuditLog {
+        id: 0,
+        user_id: claims.sub,
+        device_id: "system".to_string(),
+        action: "update_trusted_device_config".to_string(),
+        details: serde_json::to_string(&req).ok(),
+        ip_address: "127.0.0.1".to_string(),
+        user_agent: None,
+        timestamp: SystemTime::now(),
+        success: true,
+    };
+    let _ = state.db.log_audit(&audit_log).await;
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(req),
+        message: "受信任浏览器配置已更新".to_string(),
+    }))
+}
+
+async fn get_web_security_config(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<ApiResponse<WebSecurityConfig>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if !is_admin_or_auditor(&claims) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(web_security::get().await),
+        message: "获取Web安全配置成功".to_string(),
+    }))
+}
+
+// 修改跨域来源和安全响应头，立即对后续请求生效
+async fn update_web_security_config(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    ValidatedJson(req): ValidatedJson<WebSecurityConfig>,
+) -> Result<Json<ApiResponse<WebSecurityConfig>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if claims.role != "SuperAdmin" {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    if let Err(e) = web_security::update(&state.db, req.clone(), &claims.sub).await {
+        log::warn!("Failed to update web security config: {}", e);
+        return Ok(Json(ApiResponse {
+            success: false,
+            data: None,
+            message: format!("Web安全配置无效: {}", e),
+        }));
+    }
+
+    let audit_log = AuditLog {
+        id: 0,
+        user_id: claims.sub,
+        device_id: "system".to_string(),
+        action: "update_web_security_config".to_string(),
+        details: serde_json::to_string(&req).ok(),
+        ip_address: "127.0.0.1".to_string(),
+        user_agent: None,
+        timestamp: SystemTime::now(),
+        success: true,
+    };
+    let _ = state.db.log_audit(&audit_log).await;
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(req),
+        message: "Web安全配置已更新".to_string(),
+    }))
+}
+
+async fn get_feature_flags(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<ApiResponse<FeatureFlags>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if !is_admin_or_auditor(&claims) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(feature_flags::get().await),
+        message: "获取功能开关成功".to_string(),
+    }))
+}
+
+// 修改功能开关，立即生效；未列出的功能恢复为启用
+async fn update_feature_flags(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    ValidatedJson(FeatureFlagsUpdate(req)): ValidatedJson<FeatureFlagsUpdate>,
+) -> Result<Json<ApiResponse<FeatureFlags>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if claims.role != "SuperAdmin" {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    if let Err(e) = feature_flags::update(&state.db, req.clone(), &claims.sub).await {
+        log::warn!("Failed to update feature flags: {}", e);
+        return Ok(Json(ApiResponse {
+            success: false,
+            data: None,
+            message: format!("功能开关无效: {}", e),
+        }));
+    }
+
+    let audit_log = AuditLog {
+        id: 0,
+        user_id: claims.sub,
+        device_id: "system".to_string(),
+        action: "update_feature_flags".to_string(),
+        details: serde_json::to_string(&req).ok(),
+        ip_address: "127.0.0.1".to_string(),
+        user_agent: None,
+        timestamp: SystemTime::now(),
+        success: true,
+    };
+    let _ = state.db.log_audit(&audit_log).await;
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(feature_flags::get().await),
+        message: "功能开关已更新".to_string(),
+    }))
+}
+
+async fn get_itsm_config(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<ApiResponse<ItsmConfig>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if !is_admin_or_auditor(&claims) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(itsm::get().await.masked()),
+        message: "获取ITSM集成配置成功".to_string(),
+    }))
+}
+
+// 修改 ServiceNow/Jira 集成配置，api_token 传掩码时保留原值
+async fn update_itsm_config(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    ValidatedJson(req): ValidatedJson<ItsmConfig>,
+) -> Result<Json<ApiResponse<ItsmConfig>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if claims.role != "SuperAdmin" {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    if let Err(e) = itsm::update(&state.db, req, &claims.sub).await {
+        log::warn!("Failed to update itsm config: {}", e);
+        return Ok(Json(ApiResponse {
+            success: false,
+            data: None,
+            message: format!("ITSM集成配置无效: {}", e),
+        }));
+    }
+    let config = itsm::get().await.masked();
+
+    let audit_log = AuditLog {
+        id: 0,
+        user_id: claims.sub,
+        device_id: "system".to_string(),
+        action: "update_itsm_config".to_string(),
+        details: serde_json::to_string(&config).ok(),
+        ip_address: "127.0.0.1".to_string(),
+        user_agent: None,
+        timestamp: SystemTime::now(),
+        success: true,
+    };
+    let _ = state.db.log_audit(&audit_log).await;
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(config),
+        message: "ITSM集成配置已更新".to_string(),
+    }))
+}
+
+async fn get_web_session_config(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<ApiResponse<WebSessionConfig>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if !is_admin_or_auditor(&claims) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(web_session::get().await),
+        message: "获取Web会话配置成功".to_string(),
+    }))
+}
+
+// 修改 Web 会话空闲超时和绝对时长，对已登录的会话立即生效
+async fn update_web_session_config(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    ValidatedJson(req): ValidatedJson<WebSessionConfig>,
+) -> Result<Json<ApiResponse<WebSessionConfig>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if claims.role != "SuperAdmin" {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    if let Err(e) = web_session::update(&state.db, req.clone(), &claims.sub).await {
+        log::warn!("Failed to update web session config: {}", e);
+        return Ok(Json(ApiResponse {
+            success: false,
+            data: None,
+            message: format!("Web会话配置无效: {}", e),
+        }));
+    }
+
+    let audit_log = AuditLog {
+        id: 0,
+        user_id: claims.sub,
+        device_id: "system".to_string(),
+        action: "update_web_session_config".to_string(),
+        details: serde_json::to_string(&req).ok(),
+        ip_address: "127.0.0.1".to_string(),
+        user_agent: None,
+        timestamp: SystemTime::now(),
+        success: true,
+    };
+    let _ = state.db.log_audit(&audit_log).await;
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(req),
+        message: "Web会话配置已更新".to_string(),
+    }))
+}
+
+async fn get_data_masking_config(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<ApiResponse<DataMaskingConfig>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if !is_admin_or_auditor(&claims) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(data_masking::get().await),
+        message: "获取数据脱敏规则成功".to_string(),
+    }))
+}
+
+// 修改数据脱敏规则，之后的审计日志和设备列表查询立即按新规则返回
+async fn update_data_masking_config(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    ValidatedJson(DataMaskingUpdate(req)): ValidatedJson<DataMaskingUpdate>,
+) -> Result<Json<ApiResponse<DataMaskingConfig>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if claims.role != "SuperAdmin" {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    if let Err(e) = data_masking::update(&state.db, req.clone(), &claims.sub).await {
+        log::warn!("Failed to update data masking config: {}", e);
+        return Ok(Json(ApiResponse {
+            success: false,
+            data: None,
+            message: format!("数据脱敏规则无效: {}", e),
+        }));
+    }
+
+    let audit_log = AuditLog {
+        id: 0,
+        user_id: claims.sub,
+        device_id: "system".to_string(),
+        action: "update_data_masking_config".to_string(),
+        details: serde_json::to_string(&req).ok(),
+        ip_address: "127.0.0.1".to_string(),
+        user_agent: None,
+        timestamp: SystemTime::now(),
+        success: true,
+    };
+    let _ = state.db.log_audit(&audit_log).await;
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(req),
+        message: "数据脱敏规则已更新".to_string(),
+    }))
+}
+
+async fn get_ad_sync_config(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<ApiResponse<AdSyncConfig>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if !is_admin_or_auditor(&claims) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(ad_sync::get().await.masked()),
+        message: "获取AD同步配置成功".to_string(),
+    }))
+}
+
+// 修改 AD 计算机同步配置，bind_password 传掩码时保留原值
+async fn update_ad_sync_config(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    ValidatedJson(req): ValidatedJson<AdSyncConfig>,
+) -> Result<Json<ApiResponse<AdSyncConfig>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if claims.role != "SuperAdmin" {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    if let Err(e) = ad_sync::update(&state.db, req, &claims.sub).await {
+        log::warn!("Failed to update ad sync config: {}", e);
+        return Ok(Json(ApiResponse {
+            success: false,
+            data: None,
+            message: format!("AD同步配置无效: {}", e),
+        }));
+    }
+    let config = ad_sync::get().await.masked();
+
+    let audit_log = AuditLog {
+        id: 0,
+        user_id: claims.sub,
+        device_id: "system".to_string(),
+        action: "update_ad_sync_config".to_string(),
+        details: serde_json::to_string(&config).ok(),
+        ip_address: "127.0.0.1".to_string(),
+        user_agent: None,
+        timestamp: SystemTime::now(),
+        success: true,
+    };
+    let _ = state.db.log_audit(&audit_log).await;
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(config),
+        message: "AD同步配置已更新".to_string(),
+    }))
+}
+
+// 最近一次 AD 同步结果，从未同步时 data 为空
+async fn get_ad_sync_report(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<ApiResponse<AdSyncReport>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if !is_admin_or_auditor(&claims) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    match ad_sync::last_report(&state.db).await {
+        Ok(report) => Ok(Json(ApiResponse {
+            success: true,
+            data: report,
+            message: "获取AD同步结果成功".to_string(),
+        })),
+        Err(e) => {
+            log::error!("Failed to get ad sync report: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+async fn run_ad_sync(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<ApiResponse<AdSyncReport>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if claims.role != "SuperAdmin" {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let res = ad_sync::sync(&state.db).await;
+
+    let audit_log = AuditLog {
+        id: 0,
+        user_id: claims.sub,
+        device_id: "system".to_string(),
+        action: "run_ad_sync".to_string(),
+        details: Some(match &res {
+            Ok(report) => format!(
+                "computers={}, matched={}, groups_created={}, devices_updated={}",
+                report.computers, report.matched, report.groups_created, report.devices_updated
+            ),
+            Err(e) => e.to_string(),
+        }),
+        ip_address: "127.0.0.1".to_string(),
+        user_agent: None,
+        timestamp: SystemTime::now(),
+        success: res.is_ok(),
+    };
+    let _ = state.db.log_audit(&audit_log).await;
+
+    match res {
+        Ok(report) => Ok(Json(ApiResponse {
+            success: true,
+            data: Some(report),
+            message: "AD同步完成".to_string(),
+        })),
+        Err(e) => {
+            log::warn!("AD sync failed: {}", e);
+            Ok(Json(ApiResponse {
+                success: false,
+                data: None,
+                message: format!("AD同步失败: {}", e),
+            }))
+        }
+    }
+}
+
+// 许可证状态和当前用量
+async fn get_license(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<ApiResponse<LicenseStatus>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if !is_admin_or_auditor(&claims) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    match license::status(&state.db).await {
+        Ok(status) => Ok(Json(ApiResponse {
+            success: true,
+            data: Some(status),
+            message: "获取许可证状态成功".to_string(),
+        })),
+        Err(e) => {
+            log::error!("Failed to get license status: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+// 上传新许可证，校验通过后立即生效
+async fn install_license(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    ValidatedJson(req): ValidatedJson<InstallLicenseRequest>,
+) -> Result<Json<ApiResponse<LicenseStatus>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if claims.role != "SuperAdmin" {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let installed = match license::install(&state.db, &req.license, &claims.sub).await {
+        Ok(installed) => installed,
+        Err(e) => {
+            log::warn!("Failed to install license: {}", e);
+            return Ok(Json(ApiResponse {
+                success: false,
+                data: None,
+                message: format!("许可证无效: {}", e),
+            }));
+        }
+    };
+
+    let audit_log = AuditLog {
+        id: 0,
+        user_id: claims.sub,
+        device_id: "system".to_string(),
+        action: "install_license".to_string(),
+        details: serde_json::to_string(&installed).ok(),
+        ip_address: "127.0.0.1".to_string(),
+        user_agent: None,
+        timestamp: SystemTime::now(),
+        success: true,
+    };
+    let _ = state.db.log_audit(&audit_log).await;
+
+    match license::status(&state.db).await {
+        Ok(status) => Ok(Json(ApiResponse {
+            success: true,
+            data: Some(status),
+            message: "许可证已更新".to_string(),
+        })),
+        Err(e) => {
+            log::error!("Failed to get license status: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+async fn get_e2e_policy(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<ApiResponse<E2ePolicy>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if !is_admin_or_auditor(&claims) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(e2e_signaling::get().await),
+        message: "获取端到端加密策略成功".to_string(),
+    }))
+}
+
+async fn update_e2e_policy(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    ValidatedJson(req): ValidatedJson<E2ePolicy>,
+) -> Result<Json<ApiResponse<E2ePolicy>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if claims.role != "SuperAdmin" {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    if let Err(e) = e2e_signaling::update(&state.db, req.clone(), &claims.sub).await {
+        log::error!("Failed to update e2e policy: {}", e);
+        return Err(StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    let audit_log = AuditLog {
+        id: 0,
+        user_id: claims.sub,
+        device_id: "system".to_string(),
+        action: "update_e2e_policy".to_string(),
+        details: serde_json::to_string(&req).ok(),
+        ip_address: "127.0.0.1".to_string(),
+        user_agent: None,
+        timestamp: SystemTime::now(),
+        success: true,
+    };
+    let _ = state.db.log_audit(&audit_log).await;
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(req),
+        message: "端到端加密策略已更新".to_string(),
+    }))
+}
+
+async fn get_server_key(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<ApiResponse<KeyRingInfo>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if !is_admin_or_auditor(&claims) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(server_key::info().await),
+        message: "获取服务器密钥信息成功".to_string(),
+    }))
+}
+
+// 发起服务器签名密钥轮换：立即下发新公钥，重叠期结束后切换签名私钥
+async fn rotate_server_key(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    ValidatedJson(req): ValidatedJson<RotateServerKeyRequest>,
+) -> Result<Json<ApiResponse<KeyRingInfo>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if claims.role != "SuperAdmin" {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let overlap_hours = req.overlap_hours.unwrap_or(24).clamp(1, 24 * 30);
+    let info = match server_key::rotate(&state.db, overlap_hours * 3600, &claims.sub).await {
+        Ok(info) => info,
+        Err(e) => {
+            log::warn!("Failed to rotate server key: {}", e);
+            return Ok(Json(ApiResponse {
+                success: false,
+                data: None,
+                message: format!("服务器密钥轮换失败: {}", e),
+            }));
+        }
+    };
+
+    let audit_log = AuditLog {
+        id: 0,
+        user_id: claims.sub,
+        device_id: "system".to_string(),
+        action: "rotate_server_key".to_string(),
+        details: Some(format!(
+            "next_pk={}, overlap_hours={}",
+            info.next_pk.clone().unwrap_or_default(),
+            overlap_hours
+        )),
+        ip_address: "127.0.0.1".to_string(),
+        user_agent: None,
+        timestamp: SystemTime::now(),
+        success: true,
+    };
+    let _ = state.db.log_audit(&audit_log).await;
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(info),
+        message: "已发起服务器密钥轮换".to_string(),
+    }))
+}
+
+async fn get_jwt_keys(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<ApiResponse<JwtKeyInfo>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if !is_admin_or_auditor(&claims) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    match jwt_keys::info(&state.db, &state.auth).await {
+        Ok(info) => Ok(Json(ApiResponse {
+            success: true,
+            data: Some(info),
+            message: "获取JWT密钥信息成功".to_string(),
+        })),
+        Err(e) => {
+            log::error!("Failed to get jwt keys: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+// 轮换JWT签名密钥：立即用新密钥签发令牌，宽限期内仍接受原密钥签发的令牌
+async fn rotate_jwt_keys(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    ValidatedJson(req): ValidatedJson<RotateJwtKeysRequest>,
+) -> Result<Json<ApiResponse<JwtKeyInfo>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if claims.role != "SuperAdmin" {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let default_hours = (state.auth.session_timeout().as_secs() / 3600).max(1);
+    let grace_hours = req.grace_hours.unwrap_or(default_hours).min(24 * 30);
+    let info = match jwt_keys::rotate(&state.db, &state.auth, grace_hours * 3600, &claims.sub).await {
+        Ok(info) => info,
+        Err(e) => {
+            log::error!("Failed to rotate jwt keys: {}", e);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    };
+
+    let audit_log = AuditLog {
+        id: 0,
+        user_id: claims.sub,
+        device_id: "system".to_string(),
+        action: "rotate_jwt_keys".to_string(),
+        details: Some(format!("kid={}, grace_hours={}", info.primary_kid, grace_hours)),
+        ip_address: "127.0.0.1".to_string(),
+        user_agent: None,
+        timestamp: SystemTime::now(),
+        success: true,
+    };
+    let _ = state.db.log_audit(&audit_log).await;
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(info),
+        message: "JWT密钥已轮换".to_string(),
+    }))
+}
+
+// 可续传上传的当前偏移，即已连续写入的字节数
+const UPLOAD_OFFSET: &str = "upload-offset";
+// 单次下载响应的最大字节数，更大的文件由客户端按 Content-Range 分段续传
+const MAX_DOWNLOAD_RANGE: u64 = 8 * 1024 * 1024;
+
+fn upload_offset_headers(offset: u64) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    headers.insert(UPLOAD_OFFSET, offset.into());
+    headers.insert(header::CACHE_CONTROL, header::HeaderValue::from_static("no-store"));
+    headers
+}
+
+async fn file_upload_progress(claims: &Claims, transfer_id: &str) -> Option<TransferProgress> {
+    let manager = file_transfer_server::manager();
+    if manager.owner_of(transfer_id).await.as_deref() != Some(claims.sub.as_str()) {
+        return None;
+    }
+    manager.get_progress(transfer_id).await
+}
+
+// 创建可续传上传，之后按 chunk_size 依次 PATCH 文件内容
+async fn create_file_upload(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    ValidatedJson(req): ValidatedJson<CreateUploadRequest>,
+) -> Result<Json<ApiResponse<UploadInfo>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    let device_id = match req.device_id.as_deref() {
+        Some(id) => Some(peer_alias::resolve_id(id).await),
+        None => None,
+    };
+    // 指定设备时须能控制该设备；其他文件放在上传者自己的目录下，不同用户的同名文件互不覆盖
+    if let Some(id) = device_id.as_deref() {
+        ensure_device_access(&state, &claims, id).await?;
+    }
+    let relative = match device_id.as_deref() {
+        Some(id) => format!("devices/{}/{}", id, req.file_path),
+        None => format!("users/{}/{}", claims.sub, req.file_path),
+    };
+    if let Err(e) = file_transfer_server::resolve_path(&relative) {
+        return Ok(Json(ApiResponse {
+            success: false,
+            data: None,
+            message: format!("文件路径无效: {}", e),
+        }));
+    }
+
+    let manager = file_transfer_server::manager();
+    manager
+        .ensure_user_permissions(&claims.sub, &claims.role, &claims.groups, vec![])
+        .await;
+    let request = FileTransferRequest {
+        transfer_id: String::new(),
+        file_path: relative.clone(),
+        file_size: req.file_size,
+        file_hash: req.file_hash.to_lowercase(),
+        chunk_size: CHUNK_SIZE,
+        resume_from: 0,
+        transfer_type: TransferType::Upload,
+        compression: false,
+        encryption: false,
+        device_id,
+    };
+    let transfer_id = match manager.start_transfer(&claims.sub, request).await {
+        Ok(id) => id,
+        Err(e) => {
+            return Ok(Json(ApiResponse {
+                success: false,
+                data: None,
+                message: format!("创建上传失败: {}", e),
+            }))
+        }
+    };
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(UploadInfo {
+            transfer_id,
+            file_path: relative,
+            offset: 0,
+            file_size: req.file_size,
+            chunk_size: CHUNK_SIZE,
+        }),
+        message: "上传已创建".to_string(),
+    }))
+}
+
+// 查询上传偏移，断线后客户端从该偏移继续 PATCH
+async fn get_file_upload_offset(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(transfer_id): Path<String>,
+) -> Result<(HeaderMap, StatusCode), StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    match file_upload_progress(&claims, &transfer_id).await {
+        Some(progress) => Ok((upload_offset_headers(progress.bytes_transferred), StatusCode::OK)),
+        None => Err(StatusCode::NOT_FOUND),
+    }
+}
+
+// 写入一个文件块：Upload-Offset 必须等于当前偏移，除最后一块外长度必须为 chunk_size；
+// 最后一块写入后由 FileTransferManager 校验 SHA256 并移动到目标路径
+async fn upload_file_chunk(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(transfer_id): Path<String>,
+    body: Bytes,
+) -> Result<(HeaderMap, Json<ApiResponse<TransferProgress>>), StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    let progress = match file_upload_progress(&claims, &transfer_id).await {
+        Some(progress) => progress,
+        None => return Err(StatusCode::NOT_FOUND),
+    };
+    let offset: u64 = match headers
+        .get(UPLOAD_OFFSET)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse().ok())
+    {
+        Some(offset) => offset,
+        None => return Err(StatusCode::BAD_REQUEST),
+    };
+    if offset != progress.bytes_transferred {
+        return Ok((
+            upload_offset_headers(progress.bytes_transferred),
+            Json(ApiResponse {
+                success: false,
+                data: Some(progress),
+                message: "上传偏移不一致，请从当前偏移继续".to_string(),
+            }),
+        ));
+    }
+    let end = offset + body.len() as u64;
+    let is_last = end == progress.total_bytes;
+    if body.is_empty() || end > progress.total_bytes || (!is_last && body.len() != CHUNK_SIZE) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let manager = file_transfer_server::manager();
+    let chunk = FileChunk {
+        transfer_id: transfer_id.clone(),
+        chunk_index: offset / CHUNK_SIZE as u64,
+        chunk_size: body.len(),
+        checksum: manager.calculate_crc32(&body),
+        data: body.to_vec(),
+        is_last,
+        stream_id: 0,
+    };
+    if let Err(e) = manager.handle_chunk(chunk).await {
+        log::warn!("Upload {} failed at offset {}: {}", transfer_id, offset, e);
+        return Ok((
+            upload_offset_headers(offset),
+            Json(ApiResponse {
+                success: false,
+                data: None,
+                message: format!("写入文件块失败: {}", e),
+            }),
+        ));
+    }
+
+    if is_last {
+        let audit_log = AuditLog {
+            id: 0,
+            user_id: claims.sub,
+            device_id: "system".to_string(),
+            action: "upload_file".to_string(),
+            details: Some(format!("transfer_id={}, size={}", transfer_id, progress.total_bytes)),
+            ip_address: "127.0.0.1".to_string(),
+            user_agent: None,
+            timestamp: SystemTime::now(),
+            success: true,
+        };
+        let _ = state.db.log_audit(&audit_log).await;
+
+        return Ok((
+            upload_offset_headers(end),
+            Json(ApiResponse {
+                success: true,
+                data: None,
+                message: "上传完成，文件校验通过".to_string(),
+            }),
+        ));
+    }
+
+    let progress = manager.get_progress(&transfer_id).await;
+    Ok((
+        upload_offset_headers(end),
+        Json(ApiResponse {
+            success: true,
+            data: progress,
+            message: "文件块已写入".to_string(),
+        }),
+    ))
+}
+
+async fn cancel_file_upload(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(transfer_id): Path<String>,
+) -> Result<Json<ApiResponse<()>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if file_upload_progress(&claims, &transfer_id).await.is_none() {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    if let Err(e) = file_transfer_server::manager().cancel_transfer(&transfer_id).await {
+        log::error!("Failed to cancel upload {}: {}", transfer_id, e);
+        return Err(StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: None,
+        message: "上传已取消".to_string(),
+    }))
+}
+
+// 下载存储后端中的文件，支持单段 Range 请求以便断点续传
+async fn download_file(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    ValidatedQuery(query): ValidatedQuery<FileDownloadQuery>,
+) -> Result<(StatusCode, HeaderMap, Vec<u8>), StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if file_transfer_server::resolve_path(&query.path).is_err() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    // 无权访问的文件与不存在的文件一样返回 404，不泄露文件是否存在
+    match can_download_file(&state, &claims, &query.path).await {
+        Ok(true) => {}
+        Ok(false) => return Err(StatusCode::NOT_FOUND),
+        Err(e) => {
+            log::error!("Failed to check download of {}: {}", query.path, e);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    }
+    let manager = file_transfer_server::manager();
+    let storage = manager.storage().await;
+    let len = match storage.size(&query.path).await {
+        Ok(Some(len)) => len,
+        Ok(None) => return Err(StatusCode::NOT_FOUND),
+        Err(e) => {
+            log::error!("Failed to stat {}: {}", query.path, e);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    };
+    manager
+        .ensure_user_permissions(&claims.sub, &claims.role, &claims.groups, vec![])
+        .await;
+    if manager.check_download(&claims.sub, &query.path, len).await.is_err() {
+        return Err(StatusCode::FORBIDDEN);
+    }
+    if manager
+        .enforce_dlp(&claims.sub, &query.path, len, Direction::Download)
+        .await
+        .is_err()
+    {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    if !headers.contains_key(header::RANGE) {
+        log::info!("User {} downloading {}", claims.username, query.path);
+    }
+    let res = storage_range_response(&*storage, &query.path, len, &headers).await?;
+    transfer_bandwidth::throttle(&claims.groups, res.2.len()).await;
+    Ok(res)
+}
+
+// 管理员和审计员可以下载全部文件；其他用户只能下载自己设备(devices/<id>/)和自己目录(users/<id>/)下的文件，以及自己上传的文件
+async fn can_download_file(state: &AppState, claims: &Claims, key: &str) -> ResultType<bool> {
+    if is_admin_or_auditor(claims) {
+        return Ok(true);
+    }
+    let parts: Vec<_> = std::path::Path::new(key)
+        .components()
+        .filter_map(|c| match c {
+            std::path::Component::Normal(name) => name.to_str(),
+            _ => None,
+        })
+        .collect();
+    match parts.as_slice() {
+        ["devices", device_id, _, ..] => {
+            if state.db.get_device_owner(device_id).await?.as_deref() == Some(claims.sub.as_str()) {
+                return Ok(true);
+            }
+        }
+        ["users", user_id, _, ..] if *user_id == claims.sub => return Ok(true),
+        _ => {}
+    }
+    state.db.has_uploaded_file(&claims.sub, key).await
+}
+
+// 按 Range 头读取存储中的文件，单次最多返回 MAX_DOWNLOAD_RANGE 字节
+async fn storage_range_response(
+    storage: &dyn StorageBackend,
+    key: &str,
+    len: u64,
+    headers: &HeaderMap,
+) -> Result<(StatusCode, HeaderMap, Vec<u8>), StatusCode> {
+    let requested = match headers.get(header::RANGE).and_then(|v| v.to_str().ok()) {
+        Some(range) => match file_transfer_server::parse_range(range, len) {
+            Some(range) => Some(range),
+            None => {
+                let mut response_headers = HeaderMap::new();
+                if let Ok(value) = format!("bytes */{}", len).parse() {
+                    response_headers.insert(header::CONTENT_RANGE, value);
+                }
+                return Ok((StatusCode::RANGE_NOT_SATISFIABLE, response_headers, Vec::new()));
+            }
+        },
+        None => None,
+    };
+    let (start, end) = match requested {
+        Some((start, end)) => (start, end.min(start + MAX_DOWNLOAD_RANGE - 1)),
+        None if len > 0 => (0, (len - 1).min(MAX_DOWNLOAD_RANGE - 1)),
+        None => (0, 0),
+    };
+    let data = if len == 0 {
+        Vec::new()
+    } else {
+        match storage.read_range(key, start, (end - start + 1) as usize).await {
+            Ok(data) => data,
+            Err(e) => {
+                log::error!("Failed to read {}: {}", key, e);
+                return Err(StatusCode::INTERNAL_SERVER_ERROR);
+            }
+        }
+    };
+
+    let mut response_headers = HeaderMap::new();
+    response_headers.insert(header::ACCEPT_RANGES, header::HeaderValue::from_static("bytes"));
+    response_headers.insert(
+        header::CONTENT_TYPE,
+        header::HeaderValue::from_static("application/octet-stream"),
+    );
+    let partial = requested.is_some() || (data.len() as u64) < len;
+    if partial {
+        if let Ok(value) = format!("bytes {}-{}/{}", start, end, len).parse() {
+            response_headers.insert(header::CONTENT_RANGE, value);
+        }
+    }
+    let status = if partial { StatusCode::PARTIAL_CONTENT } else { StatusCode::OK };
+    Ok((status, response_headers, data))
+}
+
+// 文件传输历史，管理员可查看全部，普通用户只能查看自己的传输
+async fn list_file_transfers(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    ValidatedQuery(query): ValidatedQuery<FileTransferQuery>,
+) -> Result<Json<ApiResponse<Vec<TransferRecord>>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    let user_id = if is_admin_or_auditor(&claims) {
+        query.user_id
+    } else {
+        Some(claims.sub.clone())
+    };
+    // 设备筛选条件可以是别名
+    let device_id = match query.device_id.as_deref() {
+        Some(id) => Some(peer_alias::resolve_id(id).await),
+        None => None,
+    };
+    let filter = FileTransferFilter {
+        user_id,
+        device_id,
+        path: query.path,
+        file_hash: query.file_hash.map(|h| h.to_lowercase()),
+        status: query.status,
+        since: query.since,
+        until: query.until,
+    };
+
+    let page = query.page.unwrap_or(1).max(1);
+    let limit = query.limit.unwrap_or(50).clamp(1, 1000);
+    let offset = (page - 1) * limit;
+    match state.db.list_file_transfers(&filter, limit as i64, offset as i64).await {
+        Ok(records) => Ok(Json(ApiResponse {
+            success: true,
+            data: Some(records),
+            message: "获取文件传输记录成功".to_string(),
+        })),
+        Err(e) => {
+            log::error!("Failed to list file transfers: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+// 资产清单
+async fn list_inventory(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    ValidatedQuery(query): ValidatedQuery<InventoryQuery>,
+) -> Result<Json<ApiResponse<Vec<DeviceInventory>>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if !is_admin_or_auditor(&claims) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let filter = InventoryFilter {
+        hostname: query.hostname,
+        os: query.os,
+        os_build: query.os_build,
+        cpu: query.cpu,
+        version: query.version,
+        min_memory: query.min_memory,
+        max_memory: query.max_memory,
+    };
+    let page = query.page.unwrap_or(1).max(1);
+    let limit = query.limit.unwrap_or(50).clamp(1, 1000);
+    let offset = (page - 1) * limit;
+    match state.db.list_inventory(&filter, limit as i64, offset as i64).await {
+        Ok(inventory) => Ok(Json(ApiResponse {
+            success: true,
+            data: Some(inventory),
+            message: "获取资产清单成功".to_string(),
+        })),
+        Err(e) => {
+            log::error!("Failed to list inventory: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+async fn get_inventory_report(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<ApiResponse<InventoryReport>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if !is_admin_or_auditor(&claims) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    match state.db.inventory_report().await {
+        Ok(report) => Ok(Json(ApiResponse {
+            success: true,
+            data: Some(report),
+            message: "获取资产统计成功".to_string(),
+        })),
+        Err(e) => {
+            log::error!("Failed to build inventory report: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+async fn get_device_inventory(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+) -> Result<Json<ApiResponse<DeviceInventory>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if !is_admin_or_auditor(&claims) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let id = peer_alias::resolve_id(&id).await;
+    match state.db.get_inventory(&id).await {
+        Ok(Some(inventory)) => Ok(Json(ApiResponse {
+            success: true,
+            data: Some(inventory),
+            message: "获取设备资产信息成功".to_string(),
+        })),
+        Ok(None) => Err(StatusCode::NOT_FOUND),
+        Err(e) => {
+            log::error!("Failed to get inventory of {}: {}", id, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+async fn get_device_capabilities(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+) -> Result<Json<ApiResponse<DeviceCapabilities>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if !is_admin_or_auditor(&claims) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let id = peer_alias::resolve_id(&id).await;
+    match state.db.get_device_capabilities(&id).await {
+        Ok(Some(capabilities)) => Ok(Json(ApiResponse {
+            success: true,
+            data: Some(capabilities),
+            message: "获取设备编码能力成功".to_string(),
+        })),
+        Ok(None) => Err(StatusCode::NOT_FOUND),
+        Err(e) => {
+            log::error!("Failed to get encoder capabilities of {}: {}", id, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+// 设备在线率
+async fn get_device_uptime(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+    ValidatedQuery(query): ValidatedQuery<UptimeQuery>,
+) -> Result<Json<ApiResponse<UptimeReport>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if !is_admin_or_auditor(&claims) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let (since, until) = match query.range() {
+        Some(range) => range,
+        None => {
+            return Ok(Json(ApiResponse {
+                success: false,
+                data: None,
+                message: "时间窗口无效，最长366天".to_string(),
+            }))
+        }
+    };
+
+    let id = peer_alias::resolve_id(&id).await;
+    match uptime::report(&state.db, &id, since, until).await {
+        Ok(report) => Ok(Json(ApiResponse {
+            success: true,
+            data: Some(report),
+            message: "获取设备在线率成功".to_string(),
+        })),
+        Err(e) => {
+            log::error!("Failed to get uptime of {}: {}", id, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+async fn get_device_status_history(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+    ValidatedQuery(query): ValidatedQuery<UptimeQuery>,
+) -> Result<Json<ApiResponse<Vec<StatusChange>>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if !is_admin_or_auditor(&claims) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let (since, until) = match query.range() {
+        Some(range) => range,
+        None => {
+            return Ok(Json(ApiResponse {
+                success: false,
+                data: None,
+                message: "时间窗口无效，最长366天".to_string(),
+            }))
+        }
+    };
+
+    let id = peer_alias::resolve_id(&id).await;
+    match state.db.list_device_status_changes(Some(&id), since, until).await {
+        Ok(changes) => Ok(Json(ApiResponse {
+            success: true,
+            data: Some(changes),
+            message: "获取设备上下线记录成功".to_string(),
+        })),
+        Err(e) => {
+            log::error!("Failed to get status history of {}: {}", id, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+// 设备排障时间线，合并上下线、会话、安全告警、管理操作和备注
+async fn get_device_timeline(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+    ValidatedQuery(query): ValidatedQuery<TimelineQuery>,
+) -> Result<Json<ApiResponse<Vec<TimelineEntry>>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if !is_admin_or_auditor(&claims) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let (since, until) = match query.range() {
+        Some(range) => range,
+        None => {
+            return Ok(Json(ApiResponse {
+                success: false,
+                data: None,
+                message: "时间窗口无效，最长366天".to_string(),
+            }))
+        }
+    };
+    let kinds = match device_timeline::parse_kinds(query.kinds.as_deref()) {
+        Some(kinds) => kinds,
+        None => {
+            return Ok(Json(ApiResponse {
+                success: false,
+                data: None,
+                message: "时间线类型无效".to_string(),
+            }))
+        }
+    };
+    let limit = query.limit.unwrap_or(device_timeline::DEFAULT_LIMIT);
+
+    let id = peer_alias::resolve_id(&id).await;
+    match state.db.device_exists(&id).await {
+        Ok(true) => {}
+        Ok(false) => return Err(StatusCode::NOT_FOUND),
+        Err(e) => {
+            log::error!("Failed to check device {}: {}", id, e);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    }
+    match device_timeline::timeline(&state.db, &id, since, until, &kinds, limit).await {
+        Ok(entries) => Ok(Json(ApiResponse {
+            success: true,
+            data: Some(entries),
+            message: "获取设备时间线成功".to_string(),
+        })),
+        Err(e) => {
+            log::error!("Failed to get timeline of {}: {}", id, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+async fn list_device_notes(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+) -> Result<Json<ApiResponse<Vec<DeviceNote>>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if !is_admin_or_auditor(&claims) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let id = peer_alias::resolve_id(&id).await;
+    match state.db.list_device_notes(&id).await {
+        Ok(notes) => Ok(Json(ApiResponse {
+            success: true,
+            data: Some(notes),
+            message: "获取设备备注成功".to_string(),
+        })),
+        Err(e) => {
+            log::error!("Failed to list notes of {}: {}", id, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+async fn create_device_note(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+    ValidatedJson(req): ValidatedJson<SaveDeviceNoteRequest>,
+) -> Result<Json<ApiResponse<DeviceNote>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if claims.role != "SuperAdmin" && claims.role != "Admin" {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let id = peer_alias::resolve_id(&id).await;
+    match state.db.device_exists(&id).await {
+        Ok(true) => {}
+        Ok(false) => return Err(StatusCode::NOT_FOUND),
+        Err(e) => {
+            log::error!("Failed to check device {}: {}", id, e);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    }
+    match device_timeline::add_note(&state.db, &id, &claims.sub, &claims.username, &req.body).await {
+        Ok(note) => Ok(Json(ApiResponse {
+            success: true,
+            data: Some(note),
+            message: "设备备注已保存".to_string(),
+        })),
+        Err(e) => Ok(Json(ApiResponse {
+            success: false,
+            data: None,
+            message: format!("保存设备备注失败: {}", e),
+        })),
+    }
+}
+
+// 备注只能由作者或超级管理员修改和删除，修改和删除记入审计日志
+async fn find_own_note(state: &AppState, claims: &Claims, device_id: &str, note_id: &str) -> Result<DeviceNote, StatusCode> {
+    let note = match state.db.get_device_note(note_id, device_id).await {
+        Ok(Some(note)) => note,
+        Ok(None) => return Err(StatusCode::NOT_FOUND),
+        Err(e) => {
+            log::error!("Failed to get device note {}: {}", note_id, e);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    };
+    if note.author_id != claims.sub && claims.role != "SuperAdmin" {
+        return Err(StatusCode::FORBIDDEN);
+    }
+    Ok(note)
+}
+
+async fn update_device_note(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path((id, note_id)): Path<(String, String)>,
+    ValidatedJson(req): ValidatedJson<SaveDeviceNoteRequest>,
+) -> Result<Json<ApiResponse<DeviceNote>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if claims.role != "SuperAdmin" && claims.role != "Admin" {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let id = peer_alias::resolve_id(&id).await;
+    let mut note = find_own_note(&state, &claims, &id, &note_id).await?;
+    if let Err(e) = device_timeline::validate_note(&req.body) {
+        return Ok(Json(ApiResponse {
+            success: false,
+            data: None,
+            message: format!("保存设备备注失败: {}", e),
+        }));
+    }
+    let previous = std::mem::replace(&mut note.body, req.body.trim().to_owned());
+    note.updated_at = crate::common::now();
+    match state.db.update_device_note(&note).await {
+        Ok(true) => {}
+        Ok(false) => return Err(StatusCode::NOT_FOUND),
+        Err(e) => {
+            log::error!("Failed to update device note {}: {}", note_id, e);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    }
+
+    let audit_log = AuditLog {
+        id: 0,
+        user_id: claims.sub,
+        device_id: id,
+        action: "update_device_note".to_string(),
+        details: serde_json::to_string(&serde_json::json!({"note_id": note_id, "previous": previous})).ok(),
+        ip_address: "127.0.0.1".to_string(),
+        user_agent: None,
+        timestamp: SystemTime::now(),
+        success: true,
+    };
+    let _ = state.db.log_audit(&audit_log).await;
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(note),
+        message: "设备备注已更新".to_string(),
+    }))
+}
+
+async fn delete_device_note(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path((id, note_id)): Path<(String, String)>,
+) -> Result<Json<ApiResponse<()>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if claims.role != "SuperAdmin" && claims.role != "Admin" {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let id = peer_alias::resolve_id(&id).await;
+    let note = find_own_note(&state, &claims, &id, &note_id).await?;
+    match state.db.delete_device_note(&note_id, &id).await {
+        Ok(true) => {}
+        Ok(false) => return Err(StatusCode::NOT_FOUND),
+        Err(e) => {
+            log::error!("Failed to delete device note {}: {}", note_id, e);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    }
+
+    let audit_log = AuditLog {
+        id: 0,
+        user_id: claims.sub,
+        device_id: id,
+        action: "delete_device_note".to_string(),
+        details: serde_json::to_string(&serde_json::json!({"note_id": note_id, "body": note.body})).ok(),
+        ip_address: "127.0.0.1".to_string(),
+        user_agent: None,
+        timestamp: SystemTime::now(),
+        success: true,
+    };
+    let _ = state.db.log_audit(&audit_log).await;
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: None,
+        message: "设备备注已删除".to_string(),
+    }))
+}
+
+// 设备的连接质量趋势
+async fn get_device_quality(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+    ValidatedQuery(query): ValidatedQuery<QualityQuery>,
+) -> Result<Json<ApiResponse<QualityTrend>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if !is_admin_or_auditor(&claims) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let (since, until, bucket) = match query.range() {
+        Some(range) => range,
+        None => {
+            return Ok(Json(ApiResponse {
+                success: false,
+                data: None,
+                message: "时间窗口或汇总粒度无效，最长90天".to_string(),
+            }))
+        }
+    };
+
+    let id = peer_alias::resolve_id(&id).await;
+    match state
+        .db
+        .list_qos_samples(Some(&id), query.relay_server.as_deref(), since, until)
+        .await
+    {
+        Ok(samples) => Ok(Json(ApiResponse {
+            success: true,
+            data: Some(connection_quality::trend(&id, &samples, since, until, bucket)),
+            message: "获取连接质量成功".to_string(),
+        })),
+        Err(e) => {
+            log::error!("Failed to get connection quality of {}: {}", id, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+// 各中继服务器的连接质量趋势，评分低的在前；直连会话汇总在 subject 为空的一项
+async fn get_relay_quality(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    ValidatedQuery(query): ValidatedQuery<QualityQuery>,
+) -> Result<Json<ApiResponse<Vec<QualityTrend>>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if !is_admin_or_auditor(&claims) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let (since, until, bucket) = match query.range() {
+        Some(range) => range,
+        None => {
+            return Ok(Json(ApiResponse {
+                success: false,
+                data: None,
+                message: "时间窗口或汇总粒度无效，最长90天".to_string(),
+            }))
+        }
+    };
+
+    match state
+        .db
+        .list_qos_samples(None, query.relay_server.as_deref(), since, until)
+        .await
+    {
+        Ok(samples) => Ok(Json(ApiResponse {
+            success: true,
+            data: Some(connection_quality::relay_trends(&samples, since, until, bucket)),
+            message: "获取中继连接质量成功".to_string(),
+        })),
+        Err(e) => {
+            log::error!("Failed to get relay connection quality: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+// 全部设备或指定设备组的在线率，按在线率从低到高排序
+async fn list_device_uptime(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    ValidatedQuery(query): ValidatedQuery<UptimeQuery>,
+) -> Result<Json<ApiResponse<Vec<UptimeReport>>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if !is_admin_or_auditor(&claims) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let (since, until) = match query.range() {
+        Some(range) => range,
+        None => {
+            return Ok(Json(ApiResponse {
+                success: false,
+                data: None,
+                message: "时间窗口无效，最长366天".to_string(),
+            }))
+        }
+    };
+
+    match uptime::fleet_report(&state.db, query.group_id.as_deref(), since, until).await {
+        Ok(mut reports) => {
+            reports.sort_by(|a, b| {
+                a.uptime_percent
+                    .unwrap_or(f64::MAX)
+                    .total_cmp(&b.uptime_percent.unwrap_or(f64::MAX))
+            });
+            Ok(Json(ApiResponse {
+                success: true,
+                data: Some(reports),
+                message: "获取设备在线率成功".to_string(),
+            }))
+        }
+        Err(e) => {
+            log::error!("Failed to get uptime report: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+// 安全事件列表（默认最近7天），包括文件扫描检出的恶意文件
+async fn list_security_events(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    ValidatedQuery(query): ValidatedQuery<SecurityEventsQuery>,
+) -> Result<Json<ApiResponse<Vec<SecurityEvent>>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if !is_admin_or_auditor(&claims) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let since = query
+        .since
+        .unwrap_or_else(|| crate::common::now().saturating_sub(7 * 24 * 3600));
+    let limit = query.limit.unwrap_or(100).clamp(1, 1000);
+    match state.db.list_security_events(since, limit).await {
+        Ok(events) => Ok(Json(ApiResponse {
+            success: true,
+            data: Some(events),
+            message: "获取安全事件成功".to_string(),
+        })),
+        Err(e) => {
+            log::error!("Failed to list security events: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+async fn get_content_scan_config(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<ApiResponse<ScanConfig>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if !is_admin_or_auditor(&claims) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(content_scan::get().await),
+        message: "获取文件扫描配置成功".to_string(),
+    }))
+}
+
+async fn update_content_scan_config(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    ValidatedJson(req): ValidatedJson<ScanConfig>,
+) -> Result<Json<ApiResponse<ScanConfig>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if claims.role != "SuperAdmin" {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    if let Err(e) = content_scan::update(&state.db, req.clone(), &claims.sub).await {
+        log::warn!("Failed to update content scan config: {}", e);
+        return Ok(Json(ApiResponse {
+            success: false,
+            data: None,
+            message: format!("文件扫描配置无效: {}", e),
+        }));
+    }
+
+    let audit_log = AuditLog {
+        id: 0,
+        user_id: claims.sub,
+        device_id: "system".to_string(),
+        action: "update_content_scan_config".to_string(),
+        details: serde_json::to_string(&req).ok(),
+        ip_address: "127.0.0.1".to_string(),
+        user_agent: None,
+        timestamp: SystemTime::now(),
+        success: true,
+    };
+    let _ = state.db.log_audit(&audit_log).await;
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(req),
+        message: "文件扫描配置已更新".to_string(),
+    }))
+}
+
+async fn get_dlp_policy(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<ApiResponse<DlpPolicy>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if !is_admin_or_auditor(&claims) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(dlp::get().await),
+        message: "获取DLP策略成功".to_string(),
+    }))
+}
+
+async fn update_dlp_policy(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    ValidatedJson(req): ValidatedJson<DlpPolicy>,
+) -> Result<Json<ApiResponse<DlpPolicy>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if claims.role != "SuperAdmin" {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    if let Err(e) = dlp::update(&state.db, req.clone(), &claims.sub).await {
+        log::warn!("Failed to update dlp policy: {}", e);
+        return Ok(Json(ApiResponse {
+            success: false,
+            data: None,
+            message: format!("DLP策略无效: {}", e),
+        }));
+    }
+
+    let audit_log = AuditLog {
+        id: 0,
+        user_id: claims.sub,
+        device_id: "system".to_string(),
+        action: "update_dlp_policy".to_string(),
+        details: serde_json::to_string(&req).ok(),
+        ip_address: "127.0.0.1".to_string(),
+        user_agent: None,
+        timestamp: SystemTime::now(),
+        success: true,
+    };
+    let _ = state.db.log_audit(&audit_log).await;
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(req),
+        message: "DLP策略已更新".to_string(),
+    }))
+}
+
+async fn get_transfer_bandwidth(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<ApiResponse<BandwidthPolicy>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if !is_admin_or_auditor(&claims) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(transfer_bandwidth::get().await),
+        message: "获取文件传输带宽策略成功".to_string(),
+    }))
+}
+
+async fn get_bandwidth_pools(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<ApiResponse<Vec<PoolUsage>>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if !is_admin_or_auditor(&claims) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(transfer_bandwidth::pool_usage().await),
+        message: "获取带宽池分配成功".to_string(),
+    }))
+}
+
+async fn update_transfer_bandwidth(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    ValidatedJson(req): ValidatedJson<BandwidthPolicy>,
+) -> Result<Json<ApiResponse<BandwidthPolicy>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if claims.role != "SuperAdmin" {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    if let Err(e) = transfer_bandwidth::update(&state.db, req.clone(), &claims.sub).await {
+        log::warn!("Failed to update transfer bandwidth policy: {}", e);
+        return Ok(Json(ApiResponse {
+            success: false,
+            data: None,
+            message: format!("文件传输带宽策略无效: {}", e),
+        }));
+    }
+
+    let audit_log = AuditLog {
+        id: 0,
+        user_id: claims.sub,
+        device_id: "system".to_string(),
+        action: "update_transfer_bandwidth".to_string(),
+        details: serde_json::to_string(&req).ok(),
+        ip_address: "127.0.0.1".to_string(),
+        user_agent: None,
+        timestamp: SystemTime::now(),
+        success: true,
+    };
+    let _ = state.db.log_audit(&audit_log).await;
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(req),
+        message: "文件传输带宽策略已更新".to_string(),
+    }))
+}
+
+async fn get_network_tuning(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<ApiResponse<NetworkTuning>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if !is_admin_or_auditor(&claims) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(network_tuning::get().await),
+        message: "获取连接保活与超时设置成功".to_string(),
+    }))
+}
+
+async fn update_network_tuning(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    ValidatedJson(req): ValidatedJson<NetworkTuning>,
+) -> Result<Json<ApiResponse<NetworkTuning>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if claims.role != "SuperAdmin" {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    if let Err(e) = network_tuning::update(&state.db, req.clone(), &claims.sub).await {
+        log::warn!("Failed to update network tuning: {}", e);
+        return Ok(Json(ApiResponse {
+            success: false,
+            data: None,
+            message: format!("连接保活与超时设置无效: {}", e),
+        }));
+    }
+
+    let audit_log = AuditLog {
+        id: 0,
+        user_id: claims.sub,
+        device_id: "system".to_string(),
+        action: "update_network_tuning".to_string(),
+        details: serde_json::to_string(&req).ok(),
+        ip_address: "127.0.0.1".to_string(),
+        user_agent: None,
+        timestamp: SystemTime::now(),
+        success: true,
+    };
+    let _ = state.db.log_audit(&audit_log).await;
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(req),
+        message: "连接保活与超时设置已更新".to_string(),
+    }))
+}
+
+async fn get_webrtc_config(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<ApiResponse<WebRtcConfig>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if !is_admin_or_auditor(&claims) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(webrtc_signaling::get().await.masked()),
+        message: "获取WebRTC配置成功".to_string(),
+    }))
+}
+
+async fn update_webrtc_config(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    ValidatedJson(req): ValidatedJson<WebRtcConfig>,
+) -> Result<Json<ApiResponse<WebRtcConfig>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if claims.role != "SuperAdmin" {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    if let Err(e) = webrtc_signaling::update(&state.db, req, &claims.sub).await {
+        log::warn!("Failed to update webrtc config: {}", e);
+        return Ok(Json(ApiResponse {
+            success: false,
+            data: None,
+            message: format!("WebRTC配置无效: {}", e),
+        }));
+    }
+
+    let config = webrtc_signaling::get().await.masked();
+    let audit_log = AuditLog {
+        id: 0,
+        user_id: claims.sub,
+        device_id: "system".to_string(),
+        action: "update_webrtc_config".to_string(),
+        details: serde_json::to_string(&config).ok(),
+        ip_address: "127.0.0.1".to_string(),
+        user_agent: None,
+        timestamp: SystemTime::now(),
+        success: true,
+    };
+    let _ = state.db.log_audit(&audit_log).await;
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(config),
+        message: "WebRTC配置已更新".to_string(),
+    }))
+}
+
+async fn get_turn_config(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<ApiResponse<TurnConfig>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if !is_admin_or_auditor(&claims) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(turn::get().await.masked()),
+        message: "获取TURN配置成功".to_string(),
+    }))
+}
+
+async fn update_turn_config(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    ValidatedJson(req): ValidatedJson<TurnConfig>,
+) -> Result<Json<ApiResponse<TurnConfig>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if claims.role != "SuperAdmin" {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    if let Err(e) = turn::update(&state.db, req, &claims.sub).await {
+        log::warn!("Failed to update turn config: {}", e);
+        return Ok(Json(ApiResponse {
+            success: false,
+            data: None,
+            message: format!("TURN配置无效: {}", e),
+        }));
+    }
+
+    let config = turn::get().await.masked();
+    let audit_log = AuditLog {
+        id: 0,
+        user_id: claims.sub,
+        device_id: "system".to_string(),
+        action: "update_turn_config".to_string(),
+        details: serde_json::to_string(&config).ok(),
+        ip_address: "127.0.0.1".to_string(),
+        user_agent: None,
+        timestamp: SystemTime::now(),
+        success: true,
+    };
+    let _ = state.db.log_audit(&audit_log).await;
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(config),
+        message: "TURN配置已更新".to_string(),
+    }))
+}
+
+// 诱饵设备ID
+async fn get_honeypot_config(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<ApiResponse<HoneypotConfig>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if !is_admin_or_auditor(&claims) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(honeypot::get().await),
+        message: "获取诱饵设备配置成功".to_string(),
+    }))
+}
+
+async fn update_honeypot_config(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    ValidatedJson(req): ValidatedJson<HoneypotConfig>,
+) -> Result<Json<ApiResponse<HoneypotConfig>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if claims.role != "SuperAdmin" {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    if let Err(e) = honeypot::update(&state.db, req.clone(), &claims.sub).await {
+        log::warn!("Failed to update honeypot config: {}", e);
+        return Ok(Json(ApiResponse {
+            success: false,
+            data: None,
+            message: format!("诱饵设备配置无效: {}", e),
+        }));
+    }
+
+    let audit_log = AuditLog {
+        id: 0,
+        user_id: claims.sub,
+        device_id: "system".to_string(),
+        action: "update_honeypot_config".to_string(),
+        details: serde_json::to_string(&req).ok(),
+        ip_address: "127.0.0.1".to_string(),
+        user_agent: None,
+        timestamp: SystemTime::now(),
+        success: true,
+    };
+    let _ = state.db.log_audit(&audit_log).await;
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(req),
+        message: "诱饵设备配置已更新".to_string(),
+    }))
+}
+
+async fn list_honeypot_ip_bans(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<ApiResponse<Vec<IpBan>>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if !is_admin_or_auditor(&claims) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(honeypot::list_ip_bans().await),
+        message: "获取IP封禁列表成功".to_string(),
+    }))
+}
+
+async fn unban_honeypot_ip(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(ip): Path<String>,
+) -> Result<Json<ApiResponse<()>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if claims.role != "SuperAdmin" && claims.role != "Admin" {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let ip: IpAddr = match ip.parse() {
+        Ok(ip) => ip,
+        Err(_) => return Err(StatusCode::BAD_REQUEST),
+    };
+    if !honeypot::unban_ip(ip).await {
+        return Ok(Json(ApiResponse {
+            success: false,
+            data: None,
+            message: "该IP未被封禁".to_string(),
+        }));
+    }
+
+    let audit_log = AuditLog {
+        id: 0,
+        user_id: claims.sub,
+        device_id: "system".to_string(),
+        action: "unban_ip".to_string(),
+        details: Some(ip.to_string()),
+        ip_address: "127.0.0.1".to_string(),
+        user_agent: None,
+        timestamp: SystemTime::now(),
+        success: true,
+    };
+    let _ = state.db.log_audit(&audit_log).await;
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(()),
+        message: "IP已解除封禁".to_string(),
+    }))
+}
+
+// 设备公钥固定
+async fn get_pk_pinning_config(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<ApiResponse<PkPinningConfig>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if !is_admin_or_auditor(&claims) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(pk_pinning::get().await),
+        message: "获取公钥固定配置成功".to_string(),
+    }))
+}
+
+async fn update_pk_pinning_config(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    ValidatedJson(req): ValidatedJson<PkPinningConfig>,
+) -> Result<Json<ApiResponse<PkPinningConfig>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if claims.role != "SuperAdmin" {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    if let Err(e) = pk_pinning::update(&state.db, req.clone(), &claims.sub).await {
+        log::error!("Failed to update pk pinning config: {}", e);
+        return Err(StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    let audit_log = AuditLog {
+        id: 0,
+        user_id: claims.sub,
+        device_id: "system".to_string(),
+        action: "update_pk_pinning_config".to_string(),
+        details: serde_json::to_string(&req).ok(),
+        ip_address: "127.0.0.1".to_string(),
+        user_agent: None,
+        timestamp: SystemTime::now(),
+        success: true,
+    };
+    let _ = state.db.log_audit(&audit_log).await;
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(req),
+        message: "公钥固定配置已更新".to_string(),
+    }))
+}
+
+// 设备出现过的全部公钥，供取证
+async fn get_device_pk_history(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+) -> Result<Json<ApiResponse<Vec<PkRecord>>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if !is_admin_or_auditor(&claims) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let id = peer_alias::resolve_id(&id).await;
+    match state.db.list_device_pk_history(&id).await {
+        Ok(history) => Ok(Json(ApiResponse {
+            success: true,
+            data: Some(history),
+            message: "获取设备公钥历史成功".to_string(),
+        })),
+        Err(e) => {
+            log::error!("Failed to list pk history of {}: {}", id, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+// 待审批的公钥变化
+async fn list_pk_changes(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<ApiResponse<Vec<PkRecord>>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if !is_admin_or_auditor(&claims) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    match state.db.list_device_pks_by_status(PkStatus::Pending).await {
+        Ok(records) => Ok(Json(ApiResponse {
+            success: true,
+            data: Some(records),
+            message: "获取待审批公钥变化成功".to_string(),
+        })),
+        Err(e) => {
+            log::error!("Failed to list pending pk changes: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+async fn approve_pk_change(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+) -> Result<Json<ApiResponse<PkRecord>>, StatusCode> {
+    decide_pk_change(state, headers, id, true).await
+}
+
+async fn reject_pk_change(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+) -> Result<Json<ApiResponse<PkRecord>>, StatusCode> {
+    decide_pk_change(state, headers, id, false).await
+}
+
+async fn decide_pk_change(
+    state: AppState,
+    headers: HeaderMap,
+    id: String,
+    approve: bool,
+) -> Result<Json<ApiResponse<PkRecord>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if claims.role != "SuperAdmin" && claims.role != "Admin" {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let id = match pk_pinning::parse_id(&id) {
+        Ok(id) => id,
+        Err(_) => return Err(StatusCode::BAD_REQUEST),
+    };
+    let record = match pk_pinning::decide(&state.db, id, approve, &claims.sub).await {
+        Ok(Some(record)) => record,
+        Ok(None) => return Err(StatusCode::NOT_FOUND),
+        Err(e) => {
+            log::error!("Failed to decide pk change {}: {}", id, e);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    };
+
+    let audit_log = AuditLog {
+        id: 0,
+        user_id: claims.sub,
+        device_id: record.device_id.clone(),
+        action: if approve { "approve_pk_change" } else { "reject_pk_change" }.to_string(),
+        details: Some(format!("fingerprint: {}", record.fingerprint)),
+        ip_address: "127.0.0.1".to_string(),
+        user_agent: None,
+        timestamp: SystemTime::now(),
+        success: true,
+    };
+    let _ = state.db.log_audit(&audit_log).await;
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(record),
+        message: if approve { "公钥变化已批准" } else { "公钥变化已驳回" }.to_string(),
+    }))
+}
+
+// 中继控制通道 TLS 状态: CA 证书(部署到中继)和已签发的中继证书，不含私钥
+async fn get_control_tls(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<ApiResponse<ControlTlsStatus>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if !is_admin_or_auditor(&claims) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    match control_tls::status(&state.db).await {
+        Ok(status) => Ok(Json(ApiResponse {
+            success: true,
+            data: Some(status),
+            message: "获取中继控制通道TLS状态成功".to_string(),
+        })),
+        Err(e) => {
+            log::error!("Failed to get relay control tls status: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+async fn update_control_tls(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    ValidatedJson(req): ValidatedJson<UpdateControlTlsRequest>,
+) -> Result<Json<ApiResponse<ControlTlsStatus>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if claims.role != "SuperAdmin" {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    if let Err(e) = control_tls::set_enabled(&state.db, req.enabled, &claims.sub).await {
+        return Ok(Json(ApiResponse {
+            success: false,
+            data: None,
+            message: format!("更新中继控制通道TLS失败: {}", e),
+        }));
+    }
+
+    let audit_log = AuditLog {
+        id: 0,
+        user_id: claims.sub,
+        device_id: "system".to_string(),
+        action: "update_control_tls".to_string(),
+        details: Some(format!("enabled={}", req.enabled)),
+        ip_address: "127.0.0.1".to_string(),
+        user_agent: None,
+        timestamp: SystemTime::now(),
+        success: true,
+    };
+    let _ = state.db.log_audit(&audit_log).await;
+
+    match control_tls::status(&state.db).await {
+        Ok(status) => Ok(Json(ApiResponse {
+            success: true,
+            data: Some(status),
+            message: "中继控制通道TLS已更新".to_string(),
+        })),
+        Err(e) => {
+            log::error!("Failed to get relay control tls status: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+// 生成新的内部 CA，已签发的中继证书全部作废
+async fn create_control_tls_ca(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<ApiResponse<ControlTlsStatus>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if claims.role != "SuperAdmin" {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let status = match control_tls::init_ca(&state.db, &claims.sub).await {
+        Ok(status) => status,
+        Err(e) => {
+            return Ok(Json(ApiResponse {
+                success: false,
+                data: None,
+                message: format!("生成CA失败: {}", e),
+            }))
+        }
+    };
+
+    let audit_log = AuditLog {
+        id: 0,
+        user_id: claims.sub,
+        device_id: "system".to_string(),
+        action: "create_control_tls_ca".to_string(),
+        details: status.ca_fingerprint.clone().map(|x| format!("fingerprint: {}", x)),
+        ip_address: "127.0.0.1".to_string(),
+        user_agent: None,
+        timestamp: SystemTime::now(),
+        success: true,
+    };
+    let _ = state.db.log_audit(&audit_log).await;
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(status),
+        message: "CA已生成，请为中继重新签发证书".to_string(),
+    }))
+}
+
+// 签发中继证书，私钥只在此返回一次
+async fn issue_control_tls_cert(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    ValidatedJson(req): ValidatedJson<IssueControlCertRequest>,
+) -> Result<Json<ApiResponse<IssuedBundle>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if claims.role != "SuperAdmin" {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let bundle = match control_tls::issue(&state.db, &req.name, &claims.sub).await {
+        Ok(bundle) => bundle,
+        Err(e) => {
+            return Ok(Json(ApiResponse {
+                success: false,
+                data: None,
+                message: format!("签发证书失败: {}", e),
+            }))
+        }
+    };
+
+    let audit_log = AuditLog {
+        id: 0,
+        user_id: claims.sub,
+        device_id: "system".to_string(),
+        action: "issue_control_tls_cert".to_string(),
+        details: Some(format!("name: {}, fingerprint: {}", bundle.cert.name, bundle.cert.fingerprint)),
+        ip_address: "127.0.0.1".to_string(),
+        user_agent: None,
+        timestamp: SystemTime::now(),
+        success: true,
+    };
+    let _ = state.db.log_audit(&audit_log).await;
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(bundle),
+        message: "证书已签发".to_string(),
+    }))
+}
+
+async fn revoke_control_tls_cert(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(fingerprint): Path<String>,
+) -> Result<Json<ApiResponse<IssuedCert>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if claims.role != "SuperAdmin" {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let cert = match control_tls::revoke(&state.db, &fingerprint, &claims.sub).await {
+        Ok(Some(cert)) => cert,
+        Ok(None) => return Err(StatusCode::NOT_FOUND),
+        Err(e) => {
+            log::error!("Failed to revoke relay control tls certificate {}: {}", fingerprint, e);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    };
+
+    let audit_log = AuditLog {
+        id: 0,
+        user_id: claims.sub,
+        device_id: "system".to_string(),
+        action: "revoke_control_tls_cert".to_string(),
+        details: Some(format!("name: {}, fingerprint: {}", cert.name, cert.fingerprint)),
+        ip_address: "127.0.0.1".to_string(),
+        user_agent: None,
+        timestamp: SystemTime::now(),
+        success: true,
+    };
+    let _ = state.db.log_audit(&audit_log).await;
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(cert),
+        message: "证书已吊销".to_string(),
+    }))
+}
+
+// 威胁情报IP源
+async fn get_change_approval_config(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<ApiResponse<ChangeApprovalConfig>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if !is_admin_or_auditor(&claims) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(change_approval::get().await),
+        message: "获取变更审批配置成功".to_string(),
+    }))
+}
+
+// 审批已启用时由 change_approval_guard 转为待审批变更，不会进入此处
+async fn update_change_approval_config(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    ValidatedJson(req): ValidatedJson<ChangeApprovalConfig>,
+) -> Result<Json<ApiResponse<ChangeApprovalConfig>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if claims.role != "SuperAdmin" {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    if let Err(e) = change_approval::update(&state.db, req.clone(), &claims.sub).await {
+        log::warn!("Failed to update change approval config: {}", e);
+        return Ok(Json(ApiResponse {
+            success: false,
+            data: None,
+            message: format!("变更审批配置无效: {}", e),
+        }));
+    }
+
+    let audit_log = AuditLog {
+        id: 0,
+        user_id: claims.sub,
+        device_id: "system".to_string(),
+        action: "update_change_approval_config".to_string(),
+        details: serde_json::to_string(&req).ok(),
+        ip_address: "127.0.0.1".to_string(),
+        user_agent: None,
+        timestamp: SystemTime::now(),
+        success: true,
+    };
+    let _ = state.db.log_audit(&audit_log).await;
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(req),
+        message: "变更审批配置已更新".to_string(),
+    }))
+}
+
+// 变更审批队列，含逐字段差异
+async fn list_setting_changes(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    ValidatedQuery(params): ValidatedQuery<SettingChangeQuery>,
+) -> Result<Json<ApiResponse<Vec<SettingChange>>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if !is_admin_or_auditor(&claims) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let page = params.page.unwrap_or(1).max(1);
+    let limit = params.limit.unwrap_or(50);
+    let offset = (page - 1) * limit;
+    match state.db.list_setting_changes(params.status, limit as i64, offset as i64).await {
+        Ok(changes) => Ok(Json(ApiResponse {
+            success: true,
+            data: Some(changes),
+            message: "获取变更审批记录成功".to_string(),
+        })),
+        Err(e) => {
+            log::error!("Failed to list setting changes: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+// 批准、驳回和撤回共用: 写审计日志并返回处理后的变更
+async fn review_setting_change(
+    state: &AppState,
+    claims: Claims,
+    id: &str,
+    action: &str,
+    res: ResultType<SettingChange>,
+) -> Json<ApiResponse<SettingChange>> {
+    let audit_log = AuditLog {
+        id: 0,
+        user_id: claims.sub,
+        device_id: "system".to_string(),
+        action: action.to_string(),
+        details: Some(match &res {
+            Ok(change) => format!("{} ({}) by {}", change.key, id, change.requested_by_name),
+            Err(e) => format!("{}: {}", id, e),
+        }),
+        ip_address: "127.0.0.1".to_string(),
+        user_agent: None,
+        timestamp: SystemTime::now(),
+        success: res.is_ok(),
+    };
+    let _ = state.db.log_audit(&audit_log).await;
+
+    match res {
+        Ok(change) => Json(ApiResponse {
+            success: true,
+            message: match change.status {
+                ChangeStatus::Approved => "变更已批准并生效".to_string(),
+                ChangeStatus::Rejected => "变更已驳回".to_string(),
+                _ => "变更已撤回".to_string(),
+            },
+            data: Some(change),
+        }),
+        Err(e) => {
+            log::warn!("Failed to {} {}: {}", action, id, e);
+            Json(ApiResponse {
+                success: false,
+                data: None,
+                message: format!("处理变更失败: {}", e),
+            })
+        }
+    }
+}
+
+async fn approve_setting_change(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+    ValidatedJson(req): ValidatedJson<ReviewSettingChangeRequest>,
+) -> Result<Json<ApiResponse<SettingChange>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if claims.role != "SuperAdmin" && claims.role != "Admin" {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let res = change_approval::approve(&state.db, &id, &claims, req.comment).await;
+    Ok(review_setting_change(&state, claims, &id, "approve_setting_change", res).await)
+}
+
+async fn reject_setting_change(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+    ValidatedJson(req): ValidatedJson<ReviewSettingChangeRequest>,
+) -> Result<Json<ApiResponse<SettingChange>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if claims.role != "SuperAdmin" && claims.role != "Admin" {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let res = change_approval::reject(&state.db, &id, &claims, req.comment).await;
+    Ok(review_setting_change(&state, claims, &id, "reject_setting_change", res).await)
+}
+
+async fn cancel_setting_change(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+) -> Result<Json<ApiResponse<SettingChange>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if claims.role != "SuperAdmin" && claims.role != "Admin" {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let res = change_approval::cancel(&state.db, &id, &claims).await;
+    Ok(review_setting_change(&state, claims, &id, "cancel_setting_change", res).await)
+}
+
+async fn list_config_versions(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    ValidatedQuery(params): ValidatedQuery<ConfigVersionQuery>,
+) -> Result<Json<ApiResponse<Vec<ConfigVersion>>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if !is_admin_or_auditor(&claims) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let page = params.page.unwrap_or(1).max(1);
+    let limit = params.limit.unwrap_or(50);
+    let offset = (page - 1) * limit;
+    match state
+        .db
+        .list_setting_versions(params.key.as_deref(), limit as i64, offset as i64)
+        .await
+    {
+        Ok(versions) => Ok(Json(ApiResponse {
+            success: true,
+            data: Some(versions),
+            message: "获取配置版本成功".to_string(),
+        })),
+        Err(e) => {
+            log::error!("Failed to list config versions: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+async fn diff_config_versions(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    ValidatedQuery(params): ValidatedQuery<ConfigDiffQuery>,
+) -> Result<Json<ApiResponse<Vec<SettingDiff>>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if !is_admin_or_auditor(&claims) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    match config_versions::diff(&state.db, params.from, params.to).await {
+        Ok(diff) => Ok(Json(ApiResponse {
+            success: true,
+            data: Some(diff),
+            message: "获取配置差异成功".to_string(),
+        })),
+        Err(e) => Ok(Json(ApiResponse {
+            success: false,
+            data: None,
+            message: format!("比较配置版本失败: {}", e),
+        })),
+    }
+}
+
+// 回滚到历史配置版本
+async fn rollback_config(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    ValidatedJson(req): ValidatedJson<RollbackRequest>,
+) -> Result<Json<ApiResponse<RollbackReport>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if claims.role != "SuperAdmin" {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let res = config_versions::rollback(&state.db, req.version, &claims.sub).await;
+
+    let audit_log = AuditLog {
+        id: 0,
+        user_id: claims.sub,
+        device_id: "system".to_string(),
+        action: "rollback_config".to_string(),
+        details: Some(match &res {
+            Ok(report) => format!("version {}: {}", req.version, report.keys.join(",")),
+            Err(e) => format!("version {}: {}", req.version, e),
+        }),
+        ip_address: "127.0.0.1".to_string(),
+        user_agent: None,
+        timestamp: SystemTime::now(),
+        success: res.is_ok(),
+    };
+    let _ = state.db.log_audit(&audit_log).await;
+
+    match res {
+        Ok(report) => Ok(Json(ApiResponse {
+            success: true,
+            message: format!("已回滚到配置版本 {}", report.version),
+            data: Some(report),
+        })),
+        Err(e) => {
+            log::warn!("Failed to rollback config to version {}: {}", req.version, e);
+            Ok(Json(ApiResponse {
+                success: false,
+                data: None,
+                message: format!("回滚配置失败: {}", e),
+            }))
+        }
+    }
+}
+
+async fn get_threat_intel_config(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<ApiResponse<ThreatIntelConfig>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if !is_admin_or_auditor(&claims) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(threat_intel::get().await),
+        message: "获取威胁情报配置成功".to_string(),
+    }))
+}
+
+async fn update_threat_intel_config(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    ValidatedJson(req): ValidatedJson<ThreatIntelConfig>,
+) -> Result<Json<ApiResponse<ThreatIntelConfig>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if claims.role != "SuperAdmin" {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    if let Err(e) = threat_intel::update(&state.db, req.clone(), &claims.sub).await {
+        log::warn!("Failed to update threat intel config: {}", e);
+        return Ok(Json(ApiResponse {
+            success: false,
+            data: None,
+            message: format!("威胁情报配置无效: {}", e),
+        }));
+    }
+
+    let audit_log = AuditLog {
+        id: 0,
+        user_id: claims.sub,
+        device_id: "system".to_string(),
+        action: "update_threat_intel_config".to_string(),
+        details: serde_json::to_string(&req).ok(),
+        ip_address: "127.0.0.1".to_string(),
+        user_agent: None,
+        timestamp: SystemTime::now(),
+        success: true,
+    };
+    let _ = state.db.log_audit(&audit_log).await;
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(req),
+        message: "威胁情报配置已更新，新增的列表将在一分钟内下载".to_string(),
+    }))
+}
+
+async fn get_threat_intel_status(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<ApiResponse<ThreatIntelStatus>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if !is_admin_or_auditor(&claims) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(threat_intel::status().await),
+        message: "获取威胁情报状态成功".to_string(),
+    }))
+}
+
+// 立即重新下载全部列表
+async fn refresh_threat_intel(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<ApiResponse<ThreatIntelStatus>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if claims.role != "SuperAdmin" && claims.role != "Admin" {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    threat_intel::refresh().await;
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(threat_intel::status().await),
+        message: "威胁情报列表已刷新".to_string(),
+    }))
+}
+
+// 远程任务管理
+async fn list_jobs(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    ValidatedQuery(params): ValidatedQuery<PaginationQuery>,
+) -> Result<Json<ApiResponse<Vec<Job>>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if !is_admin_or_auditor(&claims) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let page = params.page.unwrap_or(1).max(1);
+    let limit = params.limit.unwrap_or(50);
+    let offset = (page - 1) * limit;
+    match state.db.list_jobs(limit as i64, offset as i64).await {
+        Ok(jobs) => Ok(Json(ApiResponse {
+            success: true,
+            data: Some(jobs),
+            message: "获取任务列表成功".to_string(),
+        })),
+        Err(e) => {
+            log::error!("Failed to list jobs: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+// 远程执行命令风险较高，只允许超级管理员创建
+async fn create_job(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    ValidatedJson(req): ValidatedJson<CreateJobRequest>,
+) -> Result<Json<ApiResponse<Job>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if claims.role != "SuperAdmin" {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let mut device_ids = Vec::with_capacity(req.device_ids.len());
+    for id in req.device_ids.iter() {
+        device_ids.push(peer_alias::resolve_id(id).await);
+    }
+    let now = crate::common::now();
+    let job = Job {
+        id: uuid::Uuid::new_v4().to_string(),
+        name: req.name,
+        command: req.command,
+        shell: req.shell,
+        group_ids: req.group_ids,
+        device_ids,
+        timeout_secs: req.timeout_secs,
+        created_by: claims.sub.clone(),
+        created_at: now,
+        expires_at: now + req.expires_in_secs.unwrap_or(7 * 24 * 3600),
+        cancelled: false,
+    };
+    if let Err(e) = job.validate() {
+        return Ok(Json(ApiResponse {
+            success: false,
+            data: None,
+            message: format!("任务无效: {}", e),
+        }));
+    }
+
+    if let Err(e) = state.db.save_job(&job).await {
+        log::error!("Failed to save job {}: {}", job.id, e);
+        return Err(StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    let audit_log = AuditLog {
+        id: 0,
+        user_id: claims.sub,
+        device_id: "system".to_string(),
+        action: "create_job".to_string(),
+        details: serde_json::to_string(&job).ok(),
+        ip_address: "127.0.0.1".to_string(),
+        user_agent: None,
+        timestamp: SystemTime::now(),
+        success: true,
+    };
+    let _ = state.db.log_audit(&audit_log).await;
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(job),
+        message: "任务已创建，设备下次心跳时执行".to_string(),
+    }))
+}
+
+async fn get_job(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+) -> Result<Json<ApiResponse<JobDetail>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if !is_admin_or_auditor(&claims) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    if let Err(e) = state.db.expire_job_runs(crate::common::now()).await {
+        log::error!("Failed to expire job runs: {}", e);
+    }
+    let job = match state.db.get_job(&id).await {
+        Ok(Some(job)) => job,
+        Ok(None) => return Err(StatusCode::NOT_FOUND),
+        Err(e) => {
+            log::error!("Failed to get job {}: {}", id, e);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    };
+    let runs = match state.db.list_job_runs(&id).await {
+        Ok(runs) => runs,
+        Err(e) => {
+            log::error!("Failed to list runs of job {}: {}", id, e);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    };
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(JobDetail { job, runs }),
+        message: "获取任务成功".to_string(),
+    }))
+}
+
+// 取消任务，已下发的执行不受影响
+async fn cancel_job(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+) -> Result<Json<ApiResponse<()>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if claims.role != "SuperAdmin" {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    match state.db.cancel_job(&id).await {
+        Ok(true) => {
+            let audit_log = AuditLog {
+                id: 0,
+                user_id: claims.sub,
+                device_id: "system".to_string(),
+                action: "cancel_job".to_string(),
+                details: Some(id),
+                ip_address: "127.0.0.1".to_string(),
+                user_agent: None,
+                timestamp: SystemTime::now(),
+                success: true,
+            };
+            let _ = state.db.log_audit(&audit_log).await;
+
+            Ok(Json(ApiResponse {
+                success: true,
+                data: Some(()),
+                message: "任务已取消".to_string(),
+            }))
+        }
+        Ok(false) => Ok(Json(ApiResponse {
+            success: false,
+            data: None,
+            message: "任务不存在或已取消".to_string(),
+        })),
+        Err(e) => {
+            log::error!("Failed to cancel job {}: {}", id, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+// 客户端安装包管理
+async fn list_update_artifacts(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<ApiResponse<Vec<UpdateArtifact>>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if !is_admin_or_auditor(&claims) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    match state.db.list_update_artifacts().await {
+        Ok(artifacts) => Ok(Json(ApiResponse {
+            success: true,
+            data: Some(artifacts),
+            message: "获取安装包列表成功".to_string(),
+        })),
+        Err(e) => {
+            log::error!("Failed to list update artifacts: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+// 请求体为安装包原始内容，同一平台同一版本重复上传时覆盖
+async fn upload_update_artifact(
+    State(state): State<AppState>,
+    Path((platform, version)): Path<(Platform, String)>,
+    ValidatedQuery(query): ValidatedQuery<UploadArtifactQuery>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<Json<ApiResponse<UpdateArtifact>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if claims.role != "SuperAdmin" {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let artifact = match software_update::store(&state.db, platform, &version, &query.file_name, &body, &claims.sub).await {
+        Ok(artifact) => artifact,
+        Err(e) => {
+            log::warn!("Failed to store update artifact {:?} {}: {}", platform, version, e);
+            return Ok(Json(ApiResponse {
+                success: false,
+                data: None,
+                message: format!("上传安装包失败: {}", e),
+            }));
+        }
+    };
+
+    let audit_log = AuditLog {
+        id: 0,
+        user_id: claims.sub,
+        device_id: "system".to_string(),
+        action: "upload_update_artifact".to_string(),
+        details: Some(format!(
+            "platform={}, version={}, file={}, sha256={}",
+            platform.as_str(),
+            artifact.version,
+            artifact.file_name,
+            artifact.sha256
+        )),
+        ip_address: "127.0.0.1".to_string(),
+        user_agent: None,
+        timestamp: SystemTime::now(),
+        success: true,
+    };
+    let _ = state.db.log_audit(&audit_log).await;
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(artifact),
+        message: "安装包已上传".to_string(),
+    }))
+}
+
+async fn delete_update_artifact(
+    State(state): State<AppState>,
+    Path((platform, version)): Path<(Platform, String)>,
+    headers: HeaderMap,
+) -> Result<Json<ApiResponse<()>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if claims.role != "SuperAdmin" {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    match software_update::remove(&state.db, platform, &version).await {
+        Ok(true) => {
+            let audit_log = AuditLog {
+                id: 0,
+                user_id: claims.sub,
+                device_id: "system".to_string(),
+                action: "delete_update_artifact".to_string(),
+                details: Some(format!("platform={}, version={}", platform.as_str(), version)),
+                ip_address: "127.0.0.1".to_string(),
+                user_agent: None,
+                timestamp: SystemTime::now(),
+                success: true,
+            };
+            let _ = state.db.log_audit(&audit_log).await;
+
+            Ok(Json(ApiResponse {
+                success: true,
+                data: Some(()),
+                message: "安装包已删除".to_string(),
+            }))
+        }
+        Ok(false) => Err(StatusCode::NOT_FOUND),
+        Err(e) => {
+            log::error!("Failed to delete update artifact {:?} {}: {}", platform, version, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+// 各平台最新安装包的版本、大小和 SHA256
+async fn get_update_manifest() -> Json<Vec<ManifestEntry>> {
+    Json(software_update::manifest().await)
+}
+
+// sha256sum 格式的校验文件
+async fn get_update_checksum(
+    State(state): State<AppState>,
+    Path((platform, version)): Path<(Platform, String)>,
+) -> Result<String, StatusCode> {
+    match state.db.get_update_artifact(platform, &version).await {
+        Ok(Some(artifact)) => Ok(format!("{}  {}\n", artifact.sha256, artifact.file_name)),
+        Ok(None) => Err(StatusCode::NOT_FOUND),
+        Err(e) => {
+            log::error!("Failed to get update artifact {:?} {}: {}", platform, version, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+async fn download_update_artifact(
+    State(state): State<AppState>,
+    Path((platform, version)): Path<(Platform, String)>,
+    headers: HeaderMap,
+) -> Result<(StatusCode, HeaderMap, Vec<u8>), StatusCode> {
+    let artifact = match state.db.get_update_artifact(platform, &version).await {
+        Ok(Some(artifact)) => artifact,
+        Ok(None) => return Err(StatusCode::NOT_FOUND),
+        Err(e) => {
+            log::error!("Failed to get update artifact {:?} {}: {}", platform, version, e);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    };
+
+    let storage = file_transfer_server::manager().storage().await;
+    let (status, mut response_headers, data) =
+        storage_range_response(&*storage, &artifact.storage_key(), artifact.size, &headers).await?;
+    if let Ok(value) = format!("attachment; filename=\"{}\"", artifact.file_name).parse() {
+        response_headers.insert(header::CONTENT_DISPOSITION, value);
+    }
+    if let Ok(value) = artifact.sha256.parse() {
+        response_headers.insert("x-checksum-sha256", value);
+    }
+    Ok((status, response_headers, data))
+}
+
+// 敏感设备双人审批
+async fn get_four_eyes_policy(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<ApiResponse<FourEyesPolicy>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if !is_admin_or_auditor(&claims) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(four_eyes::get().await),
+        message: "获取双人审批配置成功".to_string(),
+    }))
+}
+
+async fn update_four_eyes_policy(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    ValidatedJson(req): ValidatedJson<FourEyesPolicy>,
+) -> Result<Json<ApiResponse<FourEyesPolicy>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if claims.role != "SuperAdmin" {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    if let Err(e) = four_eyes::update(&state.db, req.clone(), &claims.sub).await {
+        return Ok(Json(ApiResponse {
+            success: false,
+            data: None,
+            message: format!("双人审批配置无效: {}", e),
+        }));
+    }
+
+    let audit_log = AuditLog {
+        id: 0,
+        user_id: claims.sub,
+        device_id: "system".to_string(),
+        action: "update_four_eyes_policy".to_string(),
+        details: serde_json::to_string(&req).ok(),
+        ip_address: "127.0.0.1".to_string(),
+        user_agent: None,
+        timestamp: SystemTime::now(),
+        success: true,
+    };
+    let _ = state.db.log_audit(&audit_log).await;
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(req),
+        message: "双人审批配置已更新".to_string(),
+    }))
+}
+
+async fn list_session_approvals(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<ApiResponse<Vec<ApprovalRequest>>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if !is_admin_or_auditor(&claims) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(four_eyes::list().await),
+        message: "获取会话审批列表成功".to_string(),
+    }))
+}
+
+async fn approve_session(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+) -> Result<Json<ApiResponse<ApprovalRequest>>, StatusCode> {
+    decide_session(state, headers, id, true).await
+}
+
+async fn reject_session(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+) -> Result<Json<ApiResponse<ApprovalRequest>>, StatusCode> {
+    decide_session(state, headers, id, false).await
+}
+
+async fn decide_session(
+    state: AppState,
+    headers: HeaderMap,
+    id: String,
+    approve: bool,
+) -> Result<Json<ApiResponse<ApprovalRequest>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if claims.role != "SuperAdmin" && claims.role != "Admin" {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let request = match four_eyes::decide(&id, &claims, approve).await {
+        Ok(request) => request,
+        Err(e) => {
+            return Ok(Json(ApiResponse {
+                success: false,
+                data: None,
+                message: format!("审批失败: {}", e),
+            }));
+        }
+    };
+
+    let audit_log = AuditLog {
+        id: 0,
+        user_id: claims.sub,
+        device_id: request.device_id.clone(),
+        action: if approve { "approve_session" } else { "reject_session" }.to_string(),
+        details: serde_json::to_string(&request).ok(),
+        ip_address: "127.0.0.1".to_string(),
+        user_agent: None,
+        timestamp: SystemTime::now(),
+        success: true,
+    };
+    let _ = state.db.log_audit(&audit_log).await;
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(request),
+        message: if approve { "会话已批准" } else { "会话已拒绝" }.to_string(),
+    }))
+}
+
+async fn activate_emergency_access(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    ValidatedJson(req): ValidatedJson<CreateEmergencyAccessRequest>,
+) -> Result<Json<ApiResponse<EmergencyAccess>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if claims.role != "SuperAdmin" && claims.role != "Admin" {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let (access, created) = match break_glass::activate(
+        &state.db,
+        &claims.sub,
+        &claims.username,
+        &req.device_id,
+        &req.justification,
+        "127.0.0.1",
+    )
+    .await
+    {
+        Ok(res) => res,
+        Err(e) => {
+            return Ok(Json(ApiResponse {
+                success: false,
+                data: None,
+                message: format!("启用紧急访问失败: {}", e),
+            }));
+        }
+    };
+
+    if created {
+        let audit_log = AuditLog {
+            id: 0,
+            user_id: claims.sub,
+            device_id: access.device_id.clone(),
+            action: "break_glass_activated".to_string(),
+            details: serde_json::to_string(&access).ok(),
+            ip_address: "127.0.0.1".to_string(),
+            user_agent: None,
+            timestamp: SystemTime::now(),
+            success: true,
+        };
+        let _ = state.db.log_audit(&audit_log).await;
+    }
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(access),
+        message: if created {
+            "紧急访问已启用，30分钟后自动失效"
+        } else {
+            "已有生效中的紧急访问"
+        }
+        .to_string(),
+    }))
+}
+
+// 超级管理员以其他用户的身份操作，返回代操作令牌
+async fn start_impersonation(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    ValidatedJson(req): ValidatedJson<ImpersonateRequest>,
+) -> Result<Json<ApiResponse<ImpersonationGrant>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if claims.role != "SuperAdmin" {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let user = match state.db.get_user_by_id(&req.user_id).await {
+        Ok(Some(user)) => user,
+        Ok(None) => return Err(StatusCode::NOT_FOUND),
+        Err(e) => {
+            log::error!("Failed to get user {}: {}", req.user_id, e);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    };
+    let user_agent = headers
+        .get(header::USER_AGENT)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.to_string());
+    let res = impersonation::start(&state.db, &state.auth, &claims, &user, &req.reason, user_agent.clone()).await;
+
+    let audit_log = AuditLog {
+        id: 0,
+        user_id: claims.sub,
+        device_id: "system".to_string(),
+        action: "impersonation_start".to_string(),
+        details: Some(match &res {
+            Ok(grant) => format!("user {} ({}): {}", user.username, user.id, grant.session.reason),
+            Err(e) => format!("user {} ({}): {}", user.username, user.id, e),
+        }),
+        ip_address: "127.0.0.1".to_string(),
+        user_agent,
+        timestamp: SystemTime::now(),
+        success: res.is_ok(),
+    };
+    let _ = state.db.log_audit(&audit_log).await;
+
+    match res {
+        Ok(grant) => Ok(Json(ApiResponse {
+            success: true,
+            data: Some(grant),
+            message: format!("已开始以 {} 的身份操作，已通知该用户", user.username),
+        })),
+        Err(e) => Ok(Json(ApiResponse {
+            success: false,
+            data: None,
+            message: format!("无法代操作: {}", e),
+        })),
+    }
+}
+
+// 结束当前代操作令牌，管理员回到自己的令牌
+async fn end_impersonation(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<ApiResponse<()>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if claims.act.is_none() {
+        return Ok(Json(ApiResponse {
+            success: false,
+            data: None,
+            message: "当前不是代操作会话".to_string(),
+        }));
+    }
+
+    // 在结束会话前写入，仍带有实际操作人
+    let audit_log = AuditLog {
+        id: 0,
+        user_id: claims.sub.clone(),
+        device_id: "system".to_string(),
+        action: "impersonation_end".to_string(),
+        details: None,
+        ip_address: "127.0.0.1".to_string(),
+        user_agent: None,
+        timestamp: SystemTime::now(),
+        success: true,
+    };
+    let _ = state.db.log_audit(&audit_log).await;
+
+    if let Err(e) = impersonation::end(&state.db, &claims).await {
+        log::error!("Failed to end impersonation {}: {}", claims.jti, e);
+        return Err(StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(()),
+        message: "代操作已结束".to_string(),
+    }))
+}
+
+async fn list_impersonations(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    ValidatedQuery(params): ValidatedQuery<PaginationQuery>,
+) -> Result<Json<ApiResponse<Vec<ImpersonationSession>>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if !is_admin_or_auditor(&claims) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let page = params.page.unwrap_or(1).max(1);
+    let limit = params.limit.unwrap_or(50);
+    let offset = (page - 1) * limit;
+    match state.db.list_impersonations(limit as i64, offset as i64).await {
+        Ok(sessions) => Ok(Json(ApiResponse {
+            success: true,
+            data: Some(sessions),
+            message: "获取代操作记录成功".to_string(),
+        })),
+        Err(e) => {
+            log::error!("Failed to list impersonations: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+async fn list_emergency_access(
+    State(state): State<AppState>,
+    ValidatedQuery(query): ValidatedQuery<EmergencyAccessQuery>,
+    headers: HeaderMap,
+) -> Result<Json<ApiResponse<Vec<EmergencyAccess>>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if !is_admin_or_auditor(&claims) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let page = query.page.unwrap_or(1).max(1);
+    let limit = query.limit.unwrap_or(50).clamp(1, 1000);
+    let offset = (page - 1) * limit;
+    match state
+        .db
+        .list_emergency_access(query.pending_review.unwrap_or(false), limit as i64, offset as i64)
+        .await
+    {
+        Ok(list) => Ok(Json(ApiResponse {
+            success: true,
+            data: Some(list),
+            message: "获取紧急访问记录成功".to_string(),
+        })),
+        Err(e) => {
+            log::error!("Failed to list emergency access: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+async fn end_emergency_access(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    headers: HeaderMap,
+) -> Result<Json<ApiResponse<EmergencyAccess>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if claims.role != "SuperAdmin" && claims.role != "Admin" {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let access = match break_glass::end(&state.db, &id, &claims.sub, claims.role == "SuperAdmin").await {
+        Ok(access) => access,
+        Err(e) => {
+            return Ok(Json(ApiResponse {
+                success: false,
+                data: None,
+                message: format!("结束紧急访问失败: {}", e),
+            }));
+        }
+    };
+
+    let audit_log = AuditLog {
+        id: 0,
+        user_id: claims.sub,
+        device_id: access.device_id.clone(),
+        action: "break_glass_ended".to_string(),
+        details: serde_json::to_string(&access).ok(),
+        ip_address: "127.0.0.1".to_string(),
+        user_agent: None,
+        timestamp: SystemTime::now(),
+        success: true,
+    };
+    let _ = state.db.log_audit(&audit_log).await;
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(access),
+        message: "紧急访问已结束".to_string(),
+    }))
+}
+
+// 紧急访问事后复核，仅限启用人以外的超级管理员
+async fn review_emergency_access(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    headers: HeaderMap,
+    ValidatedJson(req): ValidatedJson<ReviewEmergencyAccessRequest>,
+) -> Result<Json<ApiResponse<EmergencyAccess>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if claims.role != "SuperAdmin" {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let access = match break_glass::review(&state.db, &id, &claims.sub, &req.notes).await {
+        Ok(access) => access,
+        Err(e) => {
+            return Ok(Json(ApiResponse {
+                success: false,
+                data: None,
+                message: format!("复核失败: {}", e),
+            }));
+        }
+    };
+
+    let audit_log = AuditLog {
+        id: 0,
+        user_id: claims.sub,
+        device_id: access.device_id.clone(),
+        action: "break_glass_reviewed".to_string(),
+        details: serde_json::to_string(&access).ok(),
+        ip_address: "127.0.0.1".to_string(),
+        user_agent: None,
+        timestamp: SystemTime::now(),
+        success: true,
+    };
+    let _ = state.db.log_audit(&audit_log).await;
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(access),
+        message: "复核完成".to_string(),
+    }))
+}
+
+async fn list_messages(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    ValidatedQuery(params): ValidatedQuery<PaginationQuery>,
+) -> Result<Json<ApiResponse<Vec<DeviceMessage>>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if !is_admin_or_auditor(&claims) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let page = params.page.unwrap_or(1).max(1);
+    let limit = params.limit.unwrap_or(50);
+    let offset = (page - 1) * limit;
+    match state.db.list_messages(limit as i64, offset as i64).await {
+        Ok(messages) => Ok(Json(ApiResponse {
+            success: true,
+            data: Some(messages),
+            message: "获取消息列表成功".to_string(),
+        })),
+        Err(e) => {
+            log::error!("Failed to list messages: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+async fn create_message(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    ValidatedJson(req): ValidatedJson<CreateMessageRequest>,
+) -> Result<Json<ApiResponse<DeviceMessage>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if claims.role != "SuperAdmin" && claims.role != "Admin" {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let mut device_ids = Vec::with_capacity(req.device_ids.len());
+    for id in req.device_ids.iter() {
+        device_ids.push(peer_alias::resolve_id(id).await);
+    }
+    let now = crate::common::now();
+    let message = DeviceMessage {
+        id: uuid::Uuid::new_v4().to_string(),
+        title: req.title.trim().to_string(),
+        body: req.body,
+        level: req.level,
+        group_ids: req.group_ids,
+        device_ids,
+        created_by: claims.sub.clone(),
+        created_at: now,
+        expires_at: now + req.expires_in_secs.unwrap_or(24 * 3600),
+        cancelled: false,
+    };
+    if let Err(e) = message.validate() {
+        return Ok(Json(ApiResponse {
+            success: false,
+            data: None,
+            message: format!("消息无效: {}", e),
+        }));
+    }
+
+    if let Err(e) = state.db.save_message(&message).await {
+        log::error!("Failed to save message {}: {}", message.id, e);
+        return Err(StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    let audit_log = AuditLog {
+        id: 0,
+        user_id: claims.sub,
+        device_id: "system".to_string(),
+        action: "send_message".to_string(),
+        details: serde_json::to_string(&message).ok(),
+        ip_address: "127.0.0.1".to_string(),
+        user_agent: None,
+        timestamp: SystemTime::now(),
+        success: true,
+    };
+    let _ = state.db.log_audit(&audit_log).await;
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(message),
+        message: "消息已创建，设备下次心跳时送达".to_string(),
+    }))
+}
+
+async fn get_message(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+) -> Result<Json<ApiResponse<MessageDetail>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if !is_admin_or_auditor(&claims) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let message = match state.db.get_message(&id).await {
+        Ok(Some(message)) => message,
+        Ok(None) => return Err(StatusCode::NOT_FOUND),
+        Err(e) => {
+            log::error!("Failed to get message {}: {}", id, e);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    };
+    let deliveries = match state.db.list_message_deliveries(&id).await {
+        Ok(deliveries) => deliveries,
+        Err(e) => {
+            log::error!("Failed to list deliveries of message {}: {}", id, e);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    };
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(MessageDetail { message, deliveries }),
+        message: "获取消息成功".to_string(),
+    }))
+}
+
+// 撤回消息，已送达的设备不受影响
+async fn cancel_message(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+) -> Result<Json<ApiResponse<()>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if claims.role != "SuperAdmin" && claims.role != "Admin" {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    match state.db.cancel_message(&id).await {
+        Ok(true) => {
+            let audit_log = AuditLog {
+                id: 0,
+                user_id: claims.sub,
+                device_id: "system".to_string(),
+                action: "cancel_message".to_string(),
+                details: Some(id),
+                ip_address: "127.0.0.1".to_string(),
+                user_agent: None,
+                timestamp: SystemTime::now(),
+                success: true,
+            };
+            let _ = state.db.log_audit(&audit_log).await;
+
+            Ok(Json(ApiResponse {
+                success: true,
+                data: Some(()),
+                message: "消息已撤回".to_string(),
+            }))
+        }
+        Ok(false) => Ok(Json(ApiResponse {
+            success: false,
+            data: None,
+            message: "消息不存在或已撤回".to_string(),
+        })),
+        Err(e) => {
+            log::error!("Failed to cancel message {}: {}", id, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+async fn list_announcements(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    ValidatedQuery(params): ValidatedQuery<PaginationQuery>,
+) -> Result<Json<ApiResponse<Vec<Announcement>>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if !is_admin_or_auditor(&claims) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let page = params.page.unwrap_or(1).max(1);
+    let limit = params.limit.unwrap_or(50);
+    let offset = (page - 1) * limit;
+    match state.db.list_announcements(limit as i64, offset as i64).await {
+        Ok(announcements) => Ok(Json(ApiResponse {
+            success: true,
+            data: Some(announcements),
+            message: "获取公告列表成功".to_string(),
+        })),
+        Err(e) => {
+            log::error!("Failed to list announcements: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+// 管理界面顶部显示的生效中公告，所有登录用户可见
+async fn list_active_announcements(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<ApiResponse<Vec<Announcement>>>, StatusCode> {
+    if extract_claims_from_headers(&state.auth, &headers).is_err() {
+        return Err(StatusCode::UNAUTHORIZED);
+    }
+
+    match state.db.list_active_announcements(crate::common::now()).await {
+        Ok(announcements) => Ok(Json(ApiResponse {
+            success: true,
+            data: Some(announcements),
+            message: "获取公告成功".to_string(),
+        })),
+        Err(e) => {
+            log::error!("Failed to list active announcements: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+async fn create_announcement(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    ValidatedJson(req): ValidatedJson<SaveAnnouncementRequest>,
+) -> Result<Json<ApiResponse<Announcement>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if claims.role != "SuperAdmin" && claims.role != "Admin" {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let now = crate::common::now();
+    let announcement = Announcement {
+        id: uuid::Uuid::new_v4().to_string(),
+        title: req.title.trim().to_string(),
+        body: req.body,
+        level: req.level,
+        starts_at: req.starts_at.unwrap_or(now),
+        ends_at: req.ends_at,
+        deliver_to_clients: req.deliver_to_clients,
+        created_by: claims.sub.clone(),
+        created_at: now,
+        updated_at: now,
+    };
+    save_announcement(&state, claims, announcement, "create_announcement").await
+}
+
+async fn update_announcement(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+    ValidatedJson(req): ValidatedJson<SaveAnnouncementRequest>,
+) -> Result<Json<ApiResponse<Announcement>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if claims.role != "SuperAdmin" && claims.role != "Admin" {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let existing = match state.db.get_announcement(&id).await {
+        Ok(Some(announcement)) => announcement,
+        Ok(None) => return Err(StatusCode::NOT_FOUND),
+        Err(e) => {
+            log::error!("Failed to get announcement {}: {}", id, e);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    };
+    let announcement = Announcement {
+        title: req.title.trim().to_string(),
+        body: req.body,
+        level: req.level,
+        starts_at: req.starts_at.unwrap_or(existing.starts_at),
+        ends_at: req.ends_at,
+        deliver_to_clients: req.deliver_to_clients,
+        updated_at: crate::common::now(),
+        ..existing
+    };
+    save_announcement(&state, claims, announcement, "update_announcement").await
+}
+
+async fn save_announcement(
+    state: &AppState,
+    claims: Claims,
+    announcement: Announcement,
+    action: &str,
+) -> Result<Json<ApiResponse<Announcement>>, StatusCode> {
+    if let Err(e) = announcement.validate() {
+        return Ok(Json(ApiResponse {
+            success: false,
+            data: None,
+            message: format!("公告无效: {}", e),
+        }));
+    }
+
+    if let Err(e) = state.db.save_announcement(&announcement).await {
+        log::error!("Failed to save announcement {}: {}", announcement.id, e);
+        return Err(StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    let audit_log = AuditLog {
+        id: 0,
+        user_id: claims.sub,
+        device_id: "system".to_string(),
+        action: action.to_string(),
+        details: serde_json::to_string(&announcement).ok(),
+        ip_address: "127.0.0.1".to_string(),
+        user_agent: None,
+        timestamp: SystemTime::now(),
+        success: true,
+    };
+    let _ = state.db.log_audit(&audit_log).await;
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(announcement),
+        message: "公告已保存".to_string(),
+    }))
+}
+
+async fn delete_announcement(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+) -> Result<Json<ApiResponse<()>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if claims.role != "SuperAdmin" && claims.role != "Admin" {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    match state.db.remove_announcement(&id).await {
+        Ok(true) => {
+            let audit_log = AuditLog {
+                id: 0,
+                user_id: claims.sub,
+                device_id: "system".to_string(),
+                action: "delete_announcement".to_string(),
+                details: Some(id),
+                ip_address: "127.0.0.1".to_string(),
+                user_agent: None,
+                timestamp: SystemTime::now(),
+                success: true,
+            };
+            let _ = state.db.log_audit(&audit_log).await;
+
+            Ok(Json(ApiResponse {
+                success: true,
+                data: Some(()),
+                message: "公告已删除".to_string(),
+            }))
+        }
+        Ok(false) => Err(StatusCode::NOT_FOUND),
+        Err(e) => {
+            log::error!("Failed to delete announcement {}: {}", id, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+// 进行中的中继会话及其权限限制
+async fn list_sessions(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<ApiResponse<Vec<SessionInfo>>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if !is_admin_or_auditor(&claims) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let mut sessions = Vec::new();
+    for session in relay_sessions::list().await {
+        let permissions = session_controls::get(&session.uuid).await;
+        sessions.push(SessionInfo { session, permissions });
+    }
+    sessions.sort_by(|a, b| b.session.started_at.cmp(&a.session.started_at));
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(sessions),
+        message: "获取会话列表成功".to_string(),
+    }))
+}
+
+// 各中继服务器的会话数和排空状态
+async fn list_relays(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<ApiResponse<Vec<RelayStatus>>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if !is_admin_or_auditor(&claims) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(relay_sessions::status().await),
+        message: "获取中继服务器状态成功".to_string(),
+    }))
+}
+
+// 开始或取消排空中继服务器，排空中的中继不再分配给新会话
+async fn update_relay_drain(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    ValidatedJson(req): ValidatedJson<RelayDrainRequest>,
+) -> Result<Json<ApiResponse<()>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if claims.role != "SuperAdmin" && claims.role != "Admin" {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    if let Err(e) = relay_sessions::set_draining(&state.db, &req.server, req.draining, &claims.sub).await {
+        log::warn!("Failed to update drain state of relay {}: {}", req.server, e);
+        return Ok(Json(ApiResponse {
+            success: false,
+            data: None,
+            message: format!("设置中继排空失败: {}", e),
+        }));
+    }
+
+    let audit_log = AuditLog {
+        id: 0,
+        user_id: claims.sub,
+        device_id: "system".to_string(),
+        action: if req.draining { "drain_relay" } else { "undrain_relay" }.to_string(),
+        details: Some(req.server.clone()),
+        ip_address: "127.0.0.1".to_string(),
+        user_agent: None,
+        timestamp: SystemTime::now(),
+        success: true,
+    };
+    let _ = state.db.log_audit(&audit_log).await;
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: None,
+        message: if req.draining {
+            format!("中继 {} 已开始排空，不再分配新会话", req.server)
+        } else {
+            format!("中继 {} 已恢复分配新会话", req.server)
+        },
+    }))
+}
+
+async fn get_federation_config(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<ApiResponse<FederationConfig>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if !is_admin_or_auditor(&claims) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(federation::get().await.masked()),
+        message: "获取联邦配置成功".to_string(),
+    }))
+}
+
+// 修改信令服务器联邦配置，secret 传掩码时保留原值
+async fn update_federation_config(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    ValidatedJson(req): ValidatedJson<FederationConfig>,
+) -> Result<Json<ApiResponse<FederationConfig>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if claims.role != "SuperAdmin" {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    if let Err(e) = federation::update(&state.db, req, &claims.sub).await {
+        log::warn!("Failed to update federation config: {}", e);
+        return Ok(Json(ApiResponse {
+            success: false,
+            data: None,
+            message: format!("联邦配置无效: {}", e),
+        }));
+    }
+    let config = federation::get().await.masked();
+
+    let audit_log = AuditLog {
+        id: 0,
+        user_id: claims.sub,
+        device_id: "system".to_string(),
+        action: "update_federation_config".to_string(),
+        details: serde_json::to_string(&config).ok(),
+        ip_address: "127.0.0.1".to_string(),
+        user_agent: None,
+        timestamp: SystemTime::now(),
+        success: true,
+    };
+    let _ = state.db.log_audit(&audit_log).await;
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(config),
+        message: "联邦配置已更新".to_string(),
+    }))
+}
+
+async fn check_federation_secret(headers: &HeaderMap) -> Result<(), StatusCode> {
+    let secret = headers.get(federation::SECRET_HEADER).and_then(|v| v.to_str().ok());
+    if federation::check_secret(secret).await {
+        Ok(())
+    } else {
+        Err(StatusCode::UNAUTHORIZED)
+    }
+}
+
+// 其他区域查询设备是否注册在本区域并在线
+async fn federation_lookup(
+    headers: HeaderMap,
+    Path(id): Path<String>,
+) -> Result<Json<ApiResponse<FederatedLookup>>, StatusCode> {
+    check_federation_secret(&headers).await?;
+    match federation::lookup(&id).await {
+        Ok(lookup) => Ok(Json(ApiResponse {
+            success: true,
+            data: Some(lookup),
+            message: "查询成功".to_string(),
+        })),
+        Err(e) => {
+            log::error!("Failed to look up federated device {}: {}", id, e);
+            Err(StatusCode::SERVICE_UNAVAILABLE)
+        }
+    }
+}
+
+// 其他区域转发的打洞/中继请求，data 为是否已发给设备
+async fn federation_forward(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    ValidatedJson(req): ValidatedJson<ForwardRequest>,
+) -> Result<Json<ApiResponse<bool>>, StatusCode> {
+    check_federation_secret(&headers).await?;
+    let id = req.id.clone();
+    match federation::accept(&state.db, req).await {
+        Ok(forwarded) => Ok(Json(ApiResponse {
+            success: true,
+            data: Some(forwarded),
+            message: if forwarded { "请求已转发给设备" } else { "设备不在线" }.to_string(),
+        })),
+        Err(e) => {
+            log::warn!("Failed to accept federated request for {}: {}", id, e);
+            Ok(Json(ApiResponse {
+                success: false,
+                data: None,
+                message: format!("转发失败: {}", e),
+            }))
+        }
+    }
+}
+
+// 设备所在区域送回的响应，发给本区域的控制端
+async fn federation_deliver(
+    headers: HeaderMap,
+    ValidatedJson(req): ValidatedJson<DeliverRequest>,
+) -> Result<Json<ApiResponse<bool>>, StatusCode> {
+    check_federation_secret(&headers).await?;
+    if let Err(e) = federation::deliver(req).await {
+        log::warn!("Failed to deliver federated response: {}", e);
+        return Ok(Json(ApiResponse {
+            success: false,
+            data: None,
+            message: format!("送达失败: {}", e),
+        }));
+    }
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(true),
+        message: "响应已送达".to_string(),
+    }))
+}
+
+async fn get_dns_discovery_config(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<ApiResponse<DnsDiscoveryConfig>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if !is_admin_or_auditor(&claims) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(dns_discovery::get().await),
+        message: "获取DNS服务发现配置成功".to_string(),
+    }))
+}
+
+async fn update_dns_discovery_config(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    ValidatedJson(req): ValidatedJson<DnsDiscoveryConfig>,
+) -> Result<Json<ApiResponse<DnsDiscoveryConfig>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if claims.role != "SuperAdmin" {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    if let Err(e) = dns_discovery::update(&state.db, req.clone(), &claims.sub).await {
+        log::warn!("Failed to update dns discovery config: {}", e);
+        return Ok(Json(ApiResponse {
+            success: false,
+            data: None,
+            message: format!("DNS服务发现配置无效: {}", e),
+        }));
+    }
+
+    let audit_log = AuditLog {
+        id: 0,
+        user_id: claims.sub,
+        device_id: "system".to_string(),
+        action: "update_dns_discovery_config".to_string(),
+        details: serde_json::to_string(&req).ok(),
+        ip_address: "127.0.0.1".to_string(),
+        user_agent: None,
+        timestamp: SystemTime::now(),
+        success: true,
+    };
+    let _ = state.db.log_audit(&audit_log).await;
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(req),
+        message: "DNS服务发现配置已更新".to_string(),
+    }))
+}
+
+// 应发布到 DNS 的 SRV/TXT 记录，含区域文件格式
+async fn get_dns_discovery_records(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<ApiResponse<DnsRecords>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if !is_admin_or_auditor(&claims) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    match dns_discovery::records().await {
+        Ok(records) => Ok(Json(ApiResponse {
+            success: true,
+            data: Some(records),
+            message: "获取DNS记录成功".to_string(),
+        })),
+        Err(e) => Ok(Json(ApiResponse {
+            success: false,
+            data: None,
+            message: format!("无法生成DNS记录: {}", e),
+        })),
+    }
+}
+
+// 解析已发布的记录并与当前配置比对
+async fn check_dns_discovery_health(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<ApiResponse<DiscoveryHealth>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if !is_admin_or_auditor(&claims) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    match dns_discovery::health().await {
+        Ok(health) => Ok(Json(ApiResponse {
+            success: true,
+            message: if health.healthy {
+                "DNS服务发现记录正常"
+            } else {
+                "DNS服务发现记录存在问题"
+            }
+            .to_string(),
+            data: Some(health),
+        })),
+        Err(e) => {
+            log::warn!("Failed to check dns discovery records: {}", e);
+            Ok(Json(ApiResponse {
+                success: false,
+                data: None,
+                message: format!("DNS服务发现检查失败: {}", e),
+            }))
+        }
+    }
+}
+
+async fn list_captures(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<ApiResponse<Vec<Capture>>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if !is_admin_or_auditor(&claims) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(protocol_capture::list().await),
+        message: "获取协议抓包列表成功".to_string(),
+    }))
+}
+
+// 对指定设备开启限时协议抓包
+async fn start_capture(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    ValidatedJson(req): ValidatedJson<StartCaptureRequest>,
+) -> Result<Json<ApiResponse<Capture>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if claims.role != "SuperAdmin" && claims.role != "Admin" {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let capture = match protocol_capture::start(req, &claims.sub).await {
+        Ok(capture) => capture,
+        Err(e) => {
+            return Ok(Json(ApiResponse {
+                success: false,
+                data: None,
+                message: format!("开启协议抓包失败: {}", e),
+            }));
+        }
+    };
+
+    let audit_log = AuditLog {
+        id: 0,
+        user_id: claims.sub,
+        device_id: capture.device_id.clone(),
+        action: "start_protocol_capture".to_string(),
+        details: serde_json::to_string(&capture).ok(),
+        ip_address: "127.0.0.1".to_string(),
+        user_agent: None,
+        timestamp: SystemTime::now(),
+        success: true,
+    };
+    let _ = state.db.log_audit(&audit_log).await;
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(capture),
+        message: "协议抓包已开启".to_string(),
+    }))
+}
+
+async fn stop_capture(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+) -> Result<Json<ApiResponse<Capture>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if claims.role != "SuperAdmin" && claims.role != "Admin" {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    match protocol_capture::stop(&id).await {
+        Ok(capture) => Ok(Json(ApiResponse {
+            success: true,
+            data: Some(capture),
+            message: "协议抓包已停止".to_string(),
+        })),
+        Err(_) => Err(StatusCode::NOT_FOUND),
+    }
+}
+
+async fn delete_capture(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+) -> Result<Json<ApiResponse<()>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if claims.role != "SuperAdmin" && claims.role != "Admin" {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    if !protocol_capture::delete(&id).await {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: None,
+        message: "协议抓包已删除".to_string(),
+    }))
+}
+
+// 下载抓包诊断包(JSON)，含脱敏后的消息、打洞诊断和中继会话
+async fn download_capture_bundle(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+) -> Result<(HeaderMap, Vec<u8>), StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if !is_admin_or_auditor(&claims) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let bundle = protocol_capture::bundle(&id).await.ok_or(StatusCode::NOT_FOUND)?;
+    let data = serde_json::to_vec_pretty(&bundle).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let audit_log = AuditLog {
+        id: 0,
+        user_id: claims.sub,
+        device_id: bundle.capture.device_id.clone(),
+        action: "download_capture_bundle".to_string(),
+        details: Some(format!("capture={}", id)),
+        ip_address: "127.0.0.1".to_string(),
+        user_agent: None,
+        timestamp: SystemTime::now(),
+        success: true,
+    };
+    let _ = state.db.log_audit(&audit_log).await;
+
+    let mut response_headers = HeaderMap::new();
+    response_headers.insert(header::CONTENT_TYPE, HeaderValue::from_static("application/json"));
+    let filename = format!("capture-{}-{}.json", bundle.capture.device_id, bundle.capture.started_at);
+    if let Ok(value) = format!("attachment; filename=\"{}\"", filename).parse() {
+        response_headers.insert(header::CONTENT_DISPOSITION, value);
+    }
+    Ok((response_headers, data))
+}
+
+// 会话中开关剪贴板和文件传输，不断开连接
+async fn update_session_permissions(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+    ValidatedJson(req): ValidatedJson<PermissionChange>,
+) -> Result<Json<ApiResponse<SessionPermissions>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if claims.role != "SuperAdmin" && claims.role != "Admin" {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let permissions = match session_controls::update(&id, &req, &claims.sub).await {
+        Ok(permissions) => permissions,
+        Err(e) => {
+            return Ok(Json(ApiResponse {
+                success: false,
+                data: None,
+                message: format!("更新会话权限失败: {}", e),
+            }));
+        }
+    };
+
+    let audit_log = AuditLog {
+        id: 0,
+        user_id: claims.sub,
+        device_id: permissions.device_ids.join(","),
+        action: "update_session_permissions".to_string(),
+        details: serde_json::to_string(&permissions).ok(),
+        ip_address: "127.0.0.1".to_string(),
+        user_agent: None,
+        timestamp: SystemTime::now(),
+        success: true,
+    };
+    let _ = state.db.log_audit(&audit_log).await;
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(permissions),
+        message: "会话权限已更新，被控端下次心跳时生效".to_string(),
+    }))
+}
+
+async fn get_session_events(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+    ValidatedQuery(params): ValidatedQuery<PaginationQuery>,
+) -> Result<Json<ApiResponse<SessionEventsDetail>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if !is_admin_or_auditor(&claims) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let page = params.page.unwrap_or(1).max(1);
+    let limit = params.limit.unwrap_or(200);
+    let offset = (page - 1) * limit;
+    let session = match state.db.get_connection_session(&id).await {
+        Ok(session) => session,
+        Err(e) => {
+            log::error!("Failed to get connection session {}: {}", id, e);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    };
+    let events = match state.db.list_session_events(&id, limit as i64, offset as i64).await {
+        Ok(events) => events,
+        Err(e) => {
+            log::error!("Failed to list events of session {}: {}", id, e);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    };
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(SessionEventsDetail { session, events }),
+        message: "获取会话事件成功".to_string(),
+    }))
+}
+
+// 下发给会话两端的编码推荐及客户端是否遵循
+async fn get_session_codec(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+) -> Result<Json<ApiResponse<Vec<CodecRecommendation>>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if !is_admin_or_auditor(&claims) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    match state.db.list_codec_recommendations(&id).await {
+        Ok(recommendations) => Ok(Json(ApiResponse {
+            success: true,
+            data: Some(recommendations),
+            message: "获取编码推荐成功".to_string(),
+        })),
+        Err(e) => {
+            log::error!("Failed to list codec recommendations of session {}: {}", id, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+async fn update_session_congestion_control(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+    ValidatedJson(req): ValidatedJson<CongestionControlRequest>,
+) -> Result<Json<ApiResponse<()>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if claims.role != "SuperAdmin" && claims.role != "Admin" {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let result = match CongestionControl::parse(&req.algorithm) {
+        Ok(algorithm) => relay_sessions::set_congestion_control(&id, algorithm)
+            .await
+            .map_err(|e| e.to_string()),
+        Err(e) => Err(e),
+    };
+    if let Err(e) = result {
+        return Ok(Json(ApiResponse {
+            success: false,
+            data: None,
+            message: format!("设置拥塞控制失败: {}", e),
+        }));
+    }
+
+    let audit_log = AuditLog {
+        id: 0,
+        user_id: claims.sub,
+        device_id: "system".to_string(),
+        action: "update_session_congestion_control".to_string(),
+        details: Some(format!("session={} algorithm={}", id, req.algorithm)),
+        ip_address: "127.0.0.1".to_string(),
+        user_agent: None,
+        timestamp: SystemTime::now(),
+        success: true,
+    };
+    let _ = state.db.log_audit(&audit_log).await;
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(()),
+        message: "拥塞控制已下发，中继服务器一秒内生效".to_string(),
+    }))
+}
+
+async fn get_logging_config(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<ApiResponse<LoggingConfig>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if !is_admin_or_auditor(&claims) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(logging::get().await),
+        message: "获取日志配置成功".to_string(),
+    }))
+}
+
+async fn update_logging_config(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    ValidatedJson(req): ValidatedJson<LoggingConfig>,
+) -> Result<Json<ApiResponse<LoggingConfig>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if claims.role != "SuperAdmin" {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    if let Err(e) = logging::update(&state.db, req.clone(), &claims.sub).await {
+        return Ok(Json(ApiResponse {
+            success: false,
+            data: None,
+            message: format!("日志配置无效: {}", e),
+        }));
+    }
+
+    let audit_log = AuditLog {
+        id: 0,
+        user_id: claims.sub,
+        device_id: "system".to_string(),
+        action: "update_logging_config".to_string(),
+        details: serde_json::to_string(&req).ok(),
+        ip_address: "127.0.0.1".to_string(),
+        user_agent: None,
+        timestamp: SystemTime::now(),
+        success: true,
+    };
+    let _ = state.db.log_audit(&audit_log).await;
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(req),
+        message: "日志配置已更新".to_string(),
+    }))
+}
+
+// 设备离线告警
+async fn get_offline_alert_config(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<ApiResponse<OfflineAlertConfig>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if !is_admin_or_auditor(&claims) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(offline_alerts::get().await),
+        message: "获取离线告警配置成功".to_string(),
+    }))
+}
+
+async fn update_offline_alert_config(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    ValidatedJson(req): ValidatedJson<OfflineAlertConfig>,
+) -> Result<Json<ApiResponse<OfflineAlertConfig>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if claims.role != "SuperAdmin" && claims.role != "Admin" {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    if let Err(e) = offline_alerts::update(&state.db, req.clone(), &claims.sub).await {
+        return Ok(Json(ApiResponse {
+            success: false,
+            data: None,
+            message: format!("离线告警配置无效: {}", e),
+        }));
+    }
+
+    let audit_log = AuditLog {
+        id: 0,
+        user_id: claims.sub,
+        device_id: "system".to_string(),
+        action: "update_offline_alert_config".to_string(),
+        details: serde_json::to_string(&req).ok(),
+        ip_address: "127.0.0.1".to_string(),
+        user_agent: None,
+        timestamp: SystemTime::now(),
+        success: true,
+    };
+    let _ = state.db.log_audit(&audit_log).await;
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(req),
+        message: "离线告警配置已更新".to_string(),
+    }))
+}
+
+// 自定义字段定义
+async fn get_custom_fields_config(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<ApiResponse<CustomFieldsConfig>>, StatusCode> {
+    if extract_claims_from_headers(&state.auth, &headers).is_err() {
+        return Err(StatusCode::UNAUTHORIZED);
+    }
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(custom_fields::get().await),
+        message: "获取自定义字段成功".to_string(),
+    }))
+}
+
+async fn update_custom_fields_config(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    ValidatedJson(req): ValidatedJson<CustomFieldsConfig>,
+) -> Result<Json<ApiResponse<CustomFieldsConfig>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if claims.role != "SuperAdmin" && claims.role != "Admin" {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    if let Err(e) = custom_fields::update(&state.db, req.clone(), &claims.sub).await {
+        return Ok(Json(ApiResponse {
+            success: false,
+            data: None,
+            message: format!("自定义字段配置无效: {}", e),
+        }));
+    }
+
+    let audit_log = AuditLog {
+        id: 0,
+        user_id: claims.sub,
+        device_id: "system".to_string(),
+        action: "update_custom_fields".to_string(),
+        details: serde_json::to_string(&req).ok(),
+        ip_address: "127.0.0.1".to_string(),
+        user_agent: None,
+        timestamp: SystemTime::now(),
+        success: true,
+    };
+    let _ = state.db.log_audit(&audit_log).await;
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(req),
+        message: "自定义字段配置已更新".to_string(),
+    }))
+}
+
+async fn get_device_fields(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+) -> Result<Json<ApiResponse<BTreeMap<String, serde_json::Value>>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if !is_admin_or_auditor(&claims) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let id = peer_alias::resolve_id(&id).await;
+    match custom_fields::get_values(&state.db, FieldEntity::Device, &id).await {
+        Ok(values) => Ok(Json(ApiResponse {
+            success: true,
+            data: Some(values),
+            message: "获取自定义字段成功".to_string(),
+        })),
+        Err(e) => {
+            log::error!("Failed to get custom fields of device {}: {}", id, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+async fn set_device_fields(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+    ValidatedJson(FieldValuesUpdate(req)): ValidatedJson<FieldValuesUpdate>,
+) -> Result<Json<ApiResponse<BTreeMap<String, serde_json::Value>>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if claims.role != "SuperAdmin" && claims.role != "Admin" {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let id = peer_alias::resolve_id(&id).await;
+    match state.db.get_device_version(&id).await {
+        Ok(Some(_)) => {}
+        Ok(None) => return Err(StatusCode::NOT_FOUND),
+        Err(e) => {
+            log::error!("Failed to get device {}: {}", id, e);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    }
+    save_field_values(&state, claims, FieldEntity::Device, id, req).await
+}
+
+async fn get_user_fields(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+) -> Result<Json<ApiResponse<BTreeMap<String, serde_json::Value>>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    // 用户可以查看自己的字段
+    if !is_admin_or_auditor(&claims) && claims.sub != id {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    match custom_fields::get_values(&state.db, FieldEntity::User, &id).await {
+        Ok(values) => Ok(Json(ApiResponse {
+            success: true,
+            data: Some(values),
+            message: "获取自定义字段成功".to_string(),
+        })),
+        Err(e) => {
+            log::error!("Failed to get custom fields of user {}: {}", id, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+async fn set_user_fields(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+    ValidatedJson(FieldValuesUpdate(req)): ValidatedJson<FieldValuesUpdate>,
+) -> Result<Json<ApiResponse<BTreeMap<String, serde_json::Value>>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if claims.role != "SuperAdmin" && claims.role != "Admin" {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    match state.db.get_user_by_id(&id).await {
+        Ok(Some(_)) => {}
+        Ok(None) => return Err(StatusCode::NOT_FOUND),
+        Err(e) => {
+            log::error!("Failed to get user {}: {}", id, e);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    }
+    save_field_values(&state, claims, FieldEntity::User, id, req).await
+}
+
+// 替换设备或用户的全部自定义字段值并记录审计日志
+async fn save_field_values(
+    state: &AppState,
+    claims: Claims,
+    entity: FieldEntity,
+    id: String,
+    values: BTreeMap<String, serde_json::Value>,
+) -> Result<Json<ApiResponse<BTreeMap<String, serde_json::Value>>>, StatusCode> {
+    let values = match custom_fields::set_values(&state.db, entity, &id, &values).await {
+        Ok(values) => values,
+        Err(e) => {
+            return Ok(Json(ApiResponse {
+                success: false,
+                data: None,
+                message: format!("自定义字段值无效: {}", e),
+            }))
+        }
+    };
+
+    let audit_log = AuditLog {
+        id: 0,
+        user_id: claims.sub,
+        device_id: if entity == FieldEntity::Device { id.clone() } else { "system".to_string() },
+        action: "update_custom_field_values".to_string(),
+        details: Some(format!("{:?} {}: {}", entity, id, serde_json::to_string(&values).unwrap_or_default())),
+        ip_address: "127.0.0.1".to_string(),
+        user_agent: None,
+        timestamp: SystemTime::now(),
+        success: true,
+    };
+    let _ = state.db.log_audit(&audit_log).await;
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(values),
+        message: "自定义字段已更新".to_string(),
+    }))
+}
+
+async fn search_custom_fields(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(entity): Path<String>,
+    ValidatedQuery(query): ValidatedQuery<CustomFieldSearchQuery>,
+) -> Result<Json<ApiResponse<Vec<FieldValues>>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if !is_admin_or_auditor(&claims) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let entity: FieldEntity = match serde_json::from_value(serde_json::Value::String(entity)) {
+        Ok(entity) => entity,
+        Err(_) => return Err(StatusCode::NOT_FOUND),
+    };
+    let page = query.page.unwrap_or(1).max(1);
+    let limit = query.limit.unwrap_or(50).clamp(1, 1000);
+    let offset = (page - 1) * limit;
+    match custom_fields::search(
+        &state.db,
+        entity,
+        &query.key,
+        query.value.as_deref(),
+        query.min.as_deref(),
+        query.max.as_deref(),
+        limit as i64,
+        offset as i64,
+    )
+    .await
+    {
+        Ok(res) => Ok(Json(ApiResponse {
+            success: true,
+            data: Some(res),
+            message: "查询成功".to_string(),
+        })),
+        Err(e) => Ok(Json(ApiResponse {
+            success: false,
+            data: None,
+            message: format!("查询失败: {}", e),
+        })),
+    }
+}
+
+async fn list_offline_devices(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<ApiResponse<Vec<OfflineDevice>>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if !is_admin_or_auditor(&claims) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(offline_alerts::offline_devices().await),
+        message: "获取离线设备成功".to_string(),
+    }))
+}
+
+// 自助终端机群
+async fn get_kiosk_fleet_config(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<ApiResponse<KioskFleetConfig>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if !is_admin_or_auditor(&claims) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(kiosk_fleet::get().await),
+        message: "获取自助终端机群配置成功".to_string(),
+    }))
+}
+
+async fn update_kiosk_fleet_config(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    ValidatedJson(req): ValidatedJson<KioskFleetConfig>,
+) -> Result<Json<ApiResponse<KioskFleetConfig>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    if claims.role != "SuperAdmin" && claims.role != "Admin" {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    if let Err(e) = kiosk_fleet::update(&state.db, req.clone(), &claims.sub).await {
+        return Ok(Json(ApiResponse {
+            success: false,
+            data: None,
+            message: format!("自助终端机群配置无效: {}", e),
+        }));
+    }
+
+    let audit_log = AuditLog {
+        id: 0,
+        user_id: claims.sub,
+        device_id: "system".to_string(),
+        action: "update_kiosk_fleet".to_string(),
+        details: serde_json::to_string(&req).ok(),
+        ip_address: "127.0.0.1".to_string(),
+        user_agent: None,
+        timestamp: SystemTime::now(),
+        success: true,
+    };
+    let _ = state.db.log_audit(&audit_log).await;
+
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(req),
+        message: "自助终端机群配置已更新".to_string(),
+    }))
+}
+
+// 机群健康汇总，供 NOC 大屏轮询
+async fn get_kiosk_fleet_health(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<ApiResponse<FleetHealth>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
     };
 
-    Ok(Json(ApiResponse {
-        success: true,
-        data: Some(user_info),
-        message: "获取用户信息成功".to_string(),
-    }))
+    if !is_admin_or_auditor(&claims) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    match kiosk_fleet::health(&state.db).await {
+        Ok(health) => Ok(Json(ApiResponse {
+            success: true,
+            data: Some(health),
+            message: "获取机群健康状况成功".to_string(),
+        })),
+        Err(e) => {
+            log::error!("Failed to get kiosk fleet health: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
 }
 
-// 用户管理处理函数
-async fn list_users(
+// 数据库备份
+async fn get_backup_config(
     State(state): State<AppState>,
     headers: HeaderMap,
-    Query(params): Query<PaginationQuery>,
-) -> Result<Json<ApiResponse<Vec<UserInfo>>>, StatusCode> {
+) -> Result<Json<ApiResponse<BackupConfig>>, StatusCode> {
     let claims = match extract_claims_from_headers(&state.auth, &headers) {
         Ok(claims) => claims,
         Err(_) => return Err(StatusCode::UNAUTHORIZED),
     };
 
-    // 检查权限 - 只有管理员可以查看用户列表
-    if claims.role != "SuperAdmin" && claims.role != "Admin" {
+    if claims.role != "SuperAdmin" && claims.role != "Auditor" {
         return Err(StatusCode::FORBIDDEN);
     }
 
-    // 这里应该实现分页查询用户列表
-    // 为了简化，暂时返回空列表
     Ok(Json(ApiResponse {
         success: true,
-        data: Some(vec![]),
-        message: "获取用户列表成功".to_string(),
+        data: Some(backup::get().await),
+        message: "获取备份配置成功".to_string(),
     }))
 }
 
-async fn create_user(
+async fn update_backup_config(
     State(state): State<AppState>,
     headers: HeaderMap,
-    Json(req): Json<CreateUserRequest>,
-) -> Result<Json<ApiResponse<UserInfo>>, StatusCode> {
+    ValidatedJson(req): ValidatedJson<BackupConfig>,
+) -> Result<Json<ApiResponse<BackupConfig>>, StatusCode> {
     let claims = match extract_claims_from_headers(&state.auth, &headers) {
         Ok(claims) => claims,
         Err(_) => return Err(StatusCode::UNAUTHORIZED),
     };
 
-    // 检查权限
-    if claims.role != "SuperAdmin" && claims.role != "Admin" {
+    if claims.role != "SuperAdmin" {
         return Err(StatusCode::FORBIDDEN);
     }
 
-    // 验证用户名是否已存在
-    if let Ok(Some(_)) = state.db.get_user_by_username(&req.username).await {
+    if let Err(e) = backup::update(&state.db, req.clone(), &claims.sub).await {
         return Ok(Json(ApiResponse {
             success: false,
             data: None,
-            message: "用户名已存在".to_string(),
+            message: format!("备份配置无效: {}", e),
         }));
     }
 
-    // 创建新用户
-    let password_hash = match state.auth.hash_password(&req.password) {
-        Ok(hash) => hash,
-        Err(_) => return Err(StatusCode::INTERNAL_SERVER_ERROR),
+    let audit_log = AuditLog {
+        id: 0,
+        user_id: claims.sub,
+        device_id: "system".to_string(),
+        action: "update_backup_config".to_string(),
+        details: serde_json::to_string(&req).ok(),
+        ip_address: "127.0.0.1".to_string(),
+        user_agent: None,
+        timestamp: SystemTime::now(),
+        success: true,
     };
+    let _ = state.db.log_audit(&audit_log).await;
 
-    let role = match req.role.as_str() {
-        "SuperAdmin" => UserRole::SuperAdmin,
-        "Admin" => UserRole::Admin,
-        "User" => UserRole::User,
-        "ReadOnly" => UserRole::ReadOnly,
-        _ => UserRole::User,
-    };
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(req),
+        message: "备份配置已更新".to_string(),
+    }))
+}
 
-    let new_user = User {
-        id: uuid::Uuid::new_v4().to_string(),
-        username: req.username,
-        password_hash,
-        email: req.email,
-        role,
-        groups: req.groups,
-        enabled: true,
-        created_at: SystemTime::now(),
-        last_login: None,
-        failed_login_attempts: 0,
-        locked_until: None,
-        two_factor_enabled: false,
-        two_factor_secret: None,
+async fn list_backups(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<ApiResponse<Vec<BackupFile>>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
     };
 
-    match state.db.create_user(&new_user).await {
-        Ok(_) => {
-            let user_info = UserInfo {
-                id: new_user.id,
-                username: new_user.username,
-                email: new_user.email,
-                role: format!("{:?}", new_user.role),
-                groups: new_user.groups,
-                enabled: new_user.enabled,
-                last_login: None,
-            };
+    if claims.role != "SuperAdmin" && claims.role != "Auditor" {
+        return Err(StatusCode::FORBIDDEN);
+    }
 
-            Ok(Json(ApiResponse {
-                success: true,
-                data: Some(user_info),
-                message: "用户创建成功".to_string(),
-            }))
-        }
+    match backup::list() {
+        Ok(backups) => Ok(Json(ApiResponse {
+            success: true,
+            data: Some(backups),
+            message: "获取备份列表成功".to_string(),
+        })),
         Err(e) => {
-            log::error!("Failed to create user: {}", e);
+            log::error!("Failed to list backups: {}", e);
             Err(StatusCode::INTERNAL_SERVER_ERROR)
         }
     }
 }
 
-// 设备管理处理函数
-async fn list_devices(
+async fn create_backup(
     State(state): State<AppState>,
     headers: HeaderMap,
-    Query(params): Query<PaginationQuery>,
-) -> Result<Json<ApiResponse<DeviceListResponse>>, StatusCode> {
+    ValidatedJson(req): ValidatedJson<CreateBackupRequest>,
+) -> Result<Json<ApiResponse<BackupFile>>, StatusCode> {
     let claims = match extract_claims_from_headers(&state.auth, &headers) {
         Ok(claims) => claims,
         Err(_) => return Err(StatusCode::UNAUTHORIZED),
     };
 
-    let devices = match state.db.get_devices_by_user(&claims.sub).await {
-        Ok(devices) => devices,
-        Err(e) => {
-            log::error!("Failed to get devices: {}", e);
-            return Err(StatusCode::INTERNAL_SERVER_ERROR);
-        }
+    if claims.role != "SuperAdmin" {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let encrypt = match req.encrypt {
+        Some(encrypt) => encrypt,
+        None => backup::get().await.encrypt,
     };
+    let res = backup::create(&state.db, encrypt).await;
 
-    let response = DeviceListResponse {
-        total: devices.len(),
-        devices,
+    let audit_log = AuditLog {
+        id: 0,
+        user_id: claims.sub,
+        device_id: "system".to_string(),
+        action: "create_backup".to_string(),
+        details: Some(match &res {
+            Ok(file) => format!("name={}, size={}, encrypted={}", file.name, file.size, file.encrypted),
+            Err(e) => e.to_string(),
+        }),
+        ip_address: "127.0.0.1".to_string(),
+        user_agent: None,
+        timestamp: SystemTime::now(),
+        success: res.is_ok(),
     };
+    let _ = state.db.log_audit(&audit_log).await;
 
-    Ok(Json(ApiResponse {
-        success: true,
-        data: Some(response),
-        message: "获取设备列表成功".to_string(),
-    }))
+    match res {
+        Ok(file) => Ok(Json(ApiResponse {
+            success: true,
+            data: Some(file),
+            message: "备份已创建".to_string(),
+        })),
+        Err(e) => {
+            log::error!("Failed to create backup: {}", e);
+            Ok(Json(ApiResponse {
+                success: false,
+                data: None,
+                message: format!("备份失败: {}", e),
+            }))
+        }
+    }
 }
 
-async fn control_device(
+async fn download_backup(
     State(state): State<AppState>,
     headers: HeaderMap,
-    Path(device_id): Path<String>,
-) -> Result<Json<ApiResponse<String>>, StatusCode> {
+    Path(name): Path<String>,
+) -> Result<(HeaderMap, Vec<u8>), StatusCode> {
     let claims = match extract_claims_from_headers(&state.auth, &headers) {
         Ok(claims) => claims,
         Err(_) => return Err(StatusCode::UNAUTHORIZED),
     };
 
-    // 记录控制设备的审计日志
+    if claims.role != "SuperAdmin" {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let path = backup::path_of(&name).ok_or(StatusCode::NOT_FOUND)?;
+    let data = match std::fs::read(&path) {
+        Ok(data) => data,
+        Err(e) => {
+            log::error!("Failed to read backup {}: {}", path.display(), e);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    };
+
     let audit_log = AuditLog {
         id: 0,
         user_id: claims.sub,
-        device_id: device_id.clone(),
-        action: "control_device".to_string(),
-        details: Some("用户开始控制设备".to_string()),
+        device_id: "system".to_string(),
+        action: "download_backup".to_string(),
+        details: Some(format!("name={}", name)),
         ip_address: "127.0.0.1".to_string(),
         user_agent: None,
         timestamp: SystemTime::now(),
@@ -432,141 +11998,143 @@ async fn control_device(
     };
     let _ = state.db.log_audit(&audit_log).await;
 
-    Ok(Json(ApiResponse {
-        success: true,
-        data: Some("设备控制会话已建立".to_string()),
-        message: "开始控制设备".to_string(),
-    }))
+    let mut response_headers = HeaderMap::new();
+    response_headers.insert(header::CONTENT_TYPE, HeaderValue::from_static("application/octet-stream"));
+    if let Ok(value) = format!("attachment; filename=\"{}\"", name).parse() {
+        response_headers.insert(header::CONTENT_DISPOSITION, value);
+    }
+    Ok((response_headers, data))
 }
 
-// 审计日志处理函数
-async fn get_audit_logs(
+// GDPR 数据擦除
+async fn erase_subject_data(
     State(state): State<AppState>,
     headers: HeaderMap,
-    Query(params): Query<AuditLogQuery>,
-) -> Result<Json<ApiResponse<AuditLogResponse>>, StatusCode> {
+    ValidatedJson(req): ValidatedJson<ErasureRequest>,
+) -> Result<Json<ApiResponse<ErasureReport>>, StatusCode> {
     let claims = match extract_claims_from_headers(&state.auth, &headers) {
         Ok(claims) => claims,
         Err(_) => return Err(StatusCode::UNAUTHORIZED),
     };
 
-    // 检查权限 - 只有管理员可以查看所有审计日志
-    let user_id_filter = if claims.role == "SuperAdmin" || claims.role == "Admin" {
-        params.user_id.as_deref()
-    } else {
-        Some(claims.sub.as_str()) // 普通用户只能查看自己的日志
-    };
-
-    let page = params.page.unwrap_or(1);
-    let limit = params.limit.unwrap_or(50);
-    let offset = (page - 1) * limit;
+    if claims.role != "SuperAdmin" {
+        return Err(StatusCode::FORBIDDEN);
+    }
 
-    let logs = match state.db.get_audit_logs(
-        user_id_filter,
-        params.device_id.as_deref(),
-        limit as i64,
-        offset as i64,
-    ).await {
-        Ok(logs) => logs,
+    let report = match erasure::erase(&state.db, &req, &claims.sub).await {
+        Ok(report) => report,
         Err(e) => {
-            log::error!("Failed to get audit logs: {}", e);
-            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+            return Ok(Json(ApiResponse {
+                success: false,
+                data: None,
+                message: format!("数据擦除失败: {}", e),
+            }));
         }
     };
 
-    let response = AuditLogResponse {
-        total: logs.len(),
-        logs,
+    // 审计日志中不记录被擦除主体的标识
+    let audit_log = AuditLog {
+        id: 0,
+        user_id: claims.sub,
+        device_id: "system".to_string(),
+        action: "gdpr_erasure".to_string(),
+        details: Some(format!("report={}, subject={}, mode={:?}", report.id, report.pseudonym, report.mode)),
+        ip_address: "127.0.0.1".to_string(),
+        user_agent: None,
+        timestamp: SystemTime::now(),
+        success: true,
     };
+    let _ = state.db.log_audit(&audit_log).await;
 
     Ok(Json(ApiResponse {
         success: true,
-        data: Some(response),
-        message: "获取审计日志成功".to_string(),
+        data: Some(report),
+        message: "数据已擦除".to_string(),
     }))
 }
 
-// 系统统计处理函数
-async fn get_dashboard_stats(
+async fn list_erasure_reports(
     State(state): State<AppState>,
+    ValidatedQuery(query): ValidatedQuery<ErasureReportQuery>,
     headers: HeaderMap,
-) -> Result<Json<ApiResponse<HashMap<String, u64>>>, StatusCode> {
-    let _claims = match extract_claims_from_headers(&state.auth, &headers) {
+) -> Result<Json<ApiResponse<Vec<ErasureReport>>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
         Ok(claims) => claims,
         Err(_) => return Err(StatusCode::UNAUTHORIZED),
     };
 
-    // 这里应该实现真实的统计数据查询
-    let mut stats = HashMap::new();
-    stats.insert("total_users".to_string(), 10);
-    stats.insert("online_devices".to_string(), 5);
-    stats.insert("total_connections_today".to_string(), 25);
-    stats.insert("active_sessions".to_string(), 3);
-
-    Ok(Json(ApiResponse {
-        success: true,
-        data: Some(stats),
-        message: "获取统计数据成功".to_string(),
-    }))
-}
-
-// 辅助函数
-fn extract_claims_from_headers(auth: &AuthManager, headers: &HeaderMap) -> Result<Claims, &'static str> {
-    let auth_header = headers
-        .get("Authorization")
-        .ok_or("Missing Authorization header")?
-        .to_str()
-        .map_err(|_| "Invalid Authorization header")?;
-
-    if !auth_header.starts_with("Bearer ") {
-        return Err("Invalid Authorization format");
+    if claims.role != "SuperAdmin" && claims.role != "Auditor" {
+        return Err(StatusCode::FORBIDDEN);
     }
 
-    let token = &auth_header[7..];
-    auth.verify_jwt(token).map_err(|_| "Invalid token")
-}
-
-// 占位符函数 - 需要根据具体需求实现
-async fn get_user() -> Result<Json<ApiResponse<UserInfo>>, StatusCode> {
-    Err(StatusCode::NOT_IMPLEMENTED)
-}
-
-async fn update_user() -> Result<Json<ApiResponse<UserInfo>>, StatusCode> {
-    Err(StatusCode::NOT_IMPLEMENTED)
-}
-
-async fn delete_user() -> Result<Json<ApiResponse<()>>, StatusCode> {
-    Err(StatusCode::NOT_IMPLEMENTED)
-}
-
-async fn reset_user_password() -> Result<Json<ApiResponse<()>>, StatusCode> {
-    Err(StatusCode::NOT_IMPLEMENTED)
+    let subject_hash = match (query.subject, &query.id) {
+        (Some(subject), Some(id)) => Some(erasure::subject_hash(subject, id)),
+        _ => None,
+    };
+    let page = query.page.unwrap_or(1).max(1);
+    let limit = query.limit.unwrap_or(50).clamp(1, 1000);
+    let offset = (page - 1) * limit;
+    match state
+        .db
+        .list_erasure_reports(subject_hash.as_deref(), limit as i64, offset as i64)
+        .await
+    {
+        Ok(list) => Ok(Json(ApiResponse {
+            success: true,
+            data: Some(list),
+            message: "获取擦除报告成功".to_string(),
+        })),
+        Err(e) => {
+            log::error!("Failed to list erasure reports: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
 }
 
-async fn toggle_user_status() -> Result<Json<ApiResponse<()>>, StatusCode> {
-    Err(StatusCode::NOT_IMPLEMENTED)
-}
+async fn get_erasure_report(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    headers: HeaderMap,
+) -> Result<Json<ApiResponse<ErasureReport>>, StatusCode> {
+    let claims = match extract_claims_from_headers(&state.auth, &headers) {
+        Ok(claims) => claims,
+        Err(_) => return Err(StatusCode::UNAUTHORIZED),
+    };
 
-async fn get_device() -> Result<Json<ApiResponse<DeviceInfo>>, StatusCode> {
-    Err(StatusCode::NOT_IMPLEMENTED)
-}
+    if claims.role != "SuperAdmin" && claims.role != "Auditor" {
+        return Err(StatusCode::FORBIDDEN);
+    }
 
-async fn update_device() -> Result<Json<ApiResponse<DeviceInfo>>, StatusCode> {
-    Err(StatusCode::NOT_IMPLEMENTED)
+    match state.db.get_erasure_report(&id).await {
+        Ok(Some(report)) => Ok(Json(ApiResponse {
+            success: true,
+            data: Some(report),
+            message: "获取擦除报告成功".to_string(),
+        })),
+        Ok(None) => Err(StatusCode::NOT_FOUND),
+        Err(e) => {
+            log::error!("Failed to get erasure report {}: {}", id, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
 }
 
-async fn delete_device() -> Result<Json<ApiResponse<()>>, StatusCode> {
-    Err(StatusCode::NOT_IMPLEMENTED)
-}
+// 内存数据库、测试数据构建器和临时端口上的 Web 服务
+#[cfg(test)]
+#[path = "test_fixtures.rs"]
+pub(crate) mod fixtures;
 
-async fn get_connection_stats() -> Result<Json<ApiResponse<HashMap<String, u64>>>, StatusCode> {
-    Err(StatusCode::NOT_IMPLEMENTED)
-}
+// 各接口的认证和角色检查
+#[cfg(test)]
+#[path = "web_api_authz_tests.rs"]
+pub(crate) mod authz_tests;
 
-async fn get_settings() -> Result<Json<ApiResponse<HashMap<String, String>>>, StatusCode> {
-    Err(StatusCode::NOT_IMPLEMENTED)
-}
+// 与 hbbs-enterprise-client 中的类型保持一致
+#[cfg(test)]
+#[path = "web_api_client_tests.rs"]
+mod client_tests;
 
-async fn update_settings() -> Result<Json<ApiResponse<()>>, StatusCode> {
-    Err(StatusCode::NOT_IMPLEMENTED)
-}
\ No newline at end of file
+// 接口行为
+#[cfg(test)]
+#[path = "web_api_tests.rs"]
+mod tests;
@@ -0,0 +1,571 @@
+// 接口权限测试 - 从 create_router 的源码枚举全部路由并与下面的权限表逐一核对(新增路由必须登记)，
+// 再用内存数据库启动路由，检查匿名请求、2FA 绑定受限令牌和角色不足的用户都被拒绝。
+// 只需让提取器通过以执行到权限检查，请求体和查询参数不要求业务上有效，但须满足 ValidatedJson 的格式约束。
+// 运行: cargo test authz
+use super::fixtures::{self, UserBuilder};
+use super::*;
+use axum::{body::Body, http::Request};
+use tower::ServiceExt;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Access {
+    Public,        // 无需JWT(客户端接口以 id + uuid 校验)
+    User,          // 任意登录用户，只能访问自己的数据
+    Admin,         // Admin 或 SuperAdmin；资源所有者可访问的接口对其他用户同样拒绝
+    SuperAdmin,
+    Unimplemented, // 占位接口，固定返回 501
+}
+use Access::*;
+
+const ROUTES: &[(&str, &str, Access)] = &[
+    ("POST", "/api/heartbeat", Public),
+    ("POST", "/api/sysinfo", Public),
+    ("POST", "/api/jobs/:id/result", Public),
+    ("POST", "/api/messages/:id/ack", Public),
+    ("POST", "/api/session-events", Public),
+    ("POST", "/api/session-qos", Public),
+    ("POST", "/api/self-test", Public),
+    ("POST", "/api/self-test/:id/result", Public),
+    ("POST", "/api/sessions/:id/codec/ack", Public),
+    ("POST", "/api/webrtc/sessions/:id/device", Public),
+    ("GET", "/api/federation/lookup/:id", Public),
+    ("POST", "/api/federation/forward", Public),
+    ("POST", "/api/federation/deliver", Public),
+    ("GET", "/api/updates/manifest", Public),
+    ("GET", "/api/updates/:platform/:version/download", Public),
+    ("GET", "/api/updates/:platform/:version/sha256", Public),
+    ("GET", "/api/openapi.json", Public),
+    ("GET", "/api/docs", Public),
+    ("GET", "/api/errors", Public),
+    ("POST", "/api/auth/login", Public),
+    ("POST", "/api/auth/logout", Public),
+    ("GET", "/api/auth/me", User),
+    ("GET", "/api/auth/session", User),
+    ("POST", "/api/auth/session/keepalive", User),
+    ("POST", "/api/auth/2fa/setup", User),
+    ("POST", "/api/auth/2fa/confirm", User),
+    ("POST", "/api/auth/2fa/email", Public),
+    ("POST", "/api/auth/forgot-password", Public),
+    ("POST", "/api/auth/reset-password", Public),
+    ("GET", "/api/auth/trusted-devices", User),
+    ("DELETE", "/api/auth/trusted-devices", User),
+    ("DELETE", "/api/auth/trusted-devices/:id", User),
+    ("GET", "/api/invites/accept", Public),
+    ("POST", "/api/invites/accept", Public),
+    ("GET", "/api/users", Admin),
+    ("POST", "/api/users", Admin),
+    ("GET", "/api/users/:id", Unimplemented),
+    ("PUT", "/api/users/:id", Unimplemented),
+    ("DELETE", "/api/users/:id", Unimplemented),
+    ("POST", "/api/users/:id/reset-password", Unimplemented),
+    ("POST", "/api/users/:id/toggle-status", Unimplemented),
+    ("POST", "/api/users/:id/email-otp", Admin),
+    ("DELETE", "/api/users/:id/email-otp", Admin),
+    ("DELETE", "/api/users/:id/trusted-devices", Admin),
+    ("GET", "/api/users/:id/fields", Admin),
+    ("PUT", "/api/users/:id/fields", Admin),
+    ("GET", "/api/users/:id/notification-preferences", Admin),
+    ("PUT", "/api/users/:id/notification-preferences", Admin),
+    ("GET", "/api/notification-preferences", User),
+    ("PUT", "/api/notification-preferences", User),
+    ("GET", "/api/language", User),
+    ("PUT", "/api/language", User),
+    ("GET", "/api/me/recent", User),
+    ("DELETE", "/api/me/recent", User),
+    ("GET", "/api/me/favorites", User),
+    ("PUT", "/api/me/favorites/:id", User),
+    ("DELETE", "/api/me/favorites/:id", User),
+    ("GET", "/api/invites", Admin),
+    ("POST", "/api/invites", Admin),
+    ("DELETE", "/api/invites/:id", Admin),
+    ("POST", "/api/invites/:id/resend", Admin),
+    ("GET", "/api/devices", User),
+    ("GET", "/api/device-views", User),
+    ("POST", "/api/device-views", User),
+    ("PUT", "/api/device-views/:id", User),
+    ("DELETE", "/api/device-views/:id", User),
+    ("GET", "/api/devices/:id", Unimplemented),
+    ("PUT", "/api/devices/:id", Unimplemented),
+    ("DELETE", "/api/devices/:id", Unimplemented),
+    ("POST", "/api/devices/:id/control", Admin),
+    ("POST", "/api/webrtc/sessions", Admin),
+    ("DELETE", "/api/webrtc/sessions/:id", User),
+    ("POST", "/api/webrtc/sessions/:id/signal", User),
+    ("GET", "/api/turn/credentials", User),
+    ("POST", "/api/devices/:id/wake", Admin),
+    ("GET", "/api/devices/:id/inventory", Admin),
+    ("GET", "/api/devices/:id/capabilities", Admin),
+    ("GET", "/api/devices/:id/fields", Admin),
+    ("PUT", "/api/devices/:id/fields", Admin),
+    ("GET", "/api/custom-fields/:entity/search", Admin),
+    ("GET", "/api/devices/:id/uptime", Admin),
+    ("GET", "/api/devices/:id/status-history", Admin),
+    ("GET", "/api/devices/:id/timeline", Admin),
+    ("GET", "/api/devices/:id/pk-history", Admin),
+    ("GET", "/api/devices/:id/notes", Admin),
+    ("POST", "/api/devices/:id/notes", Admin),
+    ("PUT", "/api/devices/:id/notes/:note_id", Admin),
+    ("DELETE", "/api/devices/:id/notes/:note_id", Admin),
+    ("GET", "/api/devices/:id/quality", Admin),
+    ("GET", "/api/uptime", Admin),
+    ("GET", "/api/inventory", Admin),
+    ("GET", "/api/inventory/report", Admin),
+    ("GET", "/api/devices/:id/nat-diagnostics", Admin),
+    ("GET", "/api/devices/:id/self-tests", Admin),
+    ("PUT", "/api/devices/:id/alias", Admin),
+    ("DELETE", "/api/devices/:id/alias", Admin),
+    ("GET", "/api/aliases", Admin),
+    ("POST", "/api/devices/:id/ban", Admin),
+    ("DELETE", "/api/devices/:id/ban", Admin),
+    ("GET", "/api/bans", Admin),
+    ("GET", "/api/devices/:id/strategy", Admin),
+    ("GET", "/api/strategies", Admin),
+    ("POST", "/api/strategies", Admin),
+    ("PUT", "/api/strategies/:id", Admin),
+    ("DELETE", "/api/strategies/:id", Admin),
+    ("GET", "/api/updates", Admin),
+    ("PUT", "/api/updates/:platform/:version", SuperAdmin),
+    ("DELETE", "/api/updates/:platform/:version", SuperAdmin),
+    ("GET", "/api/jobs", Admin),
+    ("POST", "/api/jobs", SuperAdmin),
+    ("GET", "/api/jobs/:id", Admin),
+    ("DELETE", "/api/jobs/:id", SuperAdmin),
+    ("GET", "/api/messages", Admin),
+    ("POST", "/api/messages", Admin),
+    ("GET", "/api/messages/:id", Admin),
+    ("DELETE", "/api/messages/:id", Admin),
+    ("GET", "/api/announcements", Admin),
+    ("POST", "/api/announcements", Admin),
+    ("GET", "/api/announcements/active", User),
+    ("PUT", "/api/announcements/:id", Admin),
+    ("DELETE", "/api/announcements/:id", Admin),
+    ("GET", "/api/sessions", Admin),
+    ("GET", "/api/relays", Admin),
+    ("PUT", "/api/relays/drain", Admin),
+    ("GET", "/api/settings/federation", Admin),
+    ("PUT", "/api/settings/federation", SuperAdmin),
+    ("GET", "/api/settings/dns-discovery", Admin),
+    ("PUT", "/api/settings/dns-discovery", SuperAdmin),
+    ("GET", "/api/dns-discovery/records", Admin),
+    ("GET", "/api/dns-discovery/health", Admin),
+    ("GET", "/api/captures", Admin),
+    ("POST", "/api/captures", Admin),
+    ("DELETE", "/api/captures/:id", Admin),
+    ("POST", "/api/captures/:id/stop", Admin),
+    ("GET", "/api/captures/:id/bundle", Admin),
+    ("PUT", "/api/sessions/:id/permissions", Admin),
+    ("GET", "/api/sessions/:id/events", Admin),
+    ("GET", "/api/sessions/:id/codec", Admin),
+    ("PUT", "/api/sessions/:id/congestion-control", Admin),
+    ("GET", "/api/audit-logs", User),
+    ("GET", "/api/stats/dashboard", Admin),
+    ("GET", "/api/stats/connections", Unimplemented),
+    ("GET", "/api/stats/nat", Admin),
+    ("GET", "/api/stats/relay-quality", Admin),
+    ("GET", "/api/stats/e2e", Admin),
+    ("GET", "/api/stats/bandwidth-pools", Admin),
+    ("GET", "/api/stats/host", Admin),
+    ("GET", "/metrics", Admin),
+    ("GET", "/api/security-events", Admin),
+    ("GET", "/api/settings", Admin),
+    ("PUT", "/api/settings", Admin),
+    ("GET", "/api/settings/relay-policy", Admin),
+    ("PUT", "/api/settings/relay-policy", Admin),
+    ("GET", "/api/settings/logging", Admin),
+    ("PUT", "/api/settings/logging", SuperAdmin),
+    ("GET", "/api/settings/offline-alerts", Admin),
+    ("PUT", "/api/settings/offline-alerts", Admin),
+    ("GET", "/api/settings/custom-fields", User),
+    ("PUT", "/api/settings/custom-fields", Admin),
+    ("GET", "/api/alerts/offline", Admin),
+    ("GET", "/api/settings/kiosk-fleet", Admin),
+    ("PUT", "/api/settings/kiosk-fleet", Admin),
+    ("GET", "/api/kiosk/health", Admin),
+    ("GET", "/api/settings/backup", SuperAdmin),
+    ("PUT", "/api/settings/backup", SuperAdmin),
+    ("GET", "/api/admin/backup", SuperAdmin),
+    ("POST", "/api/admin/backup", SuperAdmin),
+    ("GET", "/api/admin/backup/:name", SuperAdmin),
+    ("GET", "/api/admin/erasure", SuperAdmin),
+    ("POST", "/api/admin/erasure", SuperAdmin),
+    ("GET", "/api/admin/erasure/:id", SuperAdmin),
+    ("GET", "/api/settings/version-policy", Admin),
+    ("PUT", "/api/settings/version-policy", SuperAdmin),
+    ("GET", "/api/settings/unattended-access", Admin),
+    ("PUT", "/api/settings/unattended-access", Admin),
+    ("GET", "/api/settings/holiday-calendars", Admin),
+    ("PUT", "/api/settings/holiday-calendars", Admin),
+    ("POST", "/api/settings/holiday-calendars/import", Admin),
+    ("GET", "/api/settings/four-eyes", Admin),
+    ("PUT", "/api/settings/four-eyes", SuperAdmin),
+    ("GET", "/api/approvals", Admin),
+    ("POST", "/api/approvals/:id/approve", Admin),
+    ("POST", "/api/approvals/:id/reject", Admin),
+    ("GET", "/api/break-glass", Admin),
+    ("POST", "/api/break-glass", Admin),
+    ("DELETE", "/api/break-glass/:id", Admin),
+    ("POST", "/api/break-glass/:id/review", SuperAdmin),
+    ("POST", "/api/auth/impersonation/end", User),
+    ("POST", "/api/admin/impersonate", SuperAdmin),
+    ("GET", "/api/admin/impersonations", Admin),
+    ("GET", "/api/settings/lan", Admin),
+    ("PUT", "/api/settings/lan", Admin),
+    ("GET", "/api/settings/id-policy", Admin),
+    ("PUT", "/api/settings/id-policy", Admin),
+    ("GET", "/api/settings/mfa-policy", Admin),
+    ("PUT", "/api/settings/mfa-policy", SuperAdmin),
+    ("GET", "/api/settings/e2e-policy", Admin),
+    ("PUT", "/api/settings/e2e-policy", SuperAdmin),
+    ("GET", "/api/settings/server-key", Admin),
+    ("POST", "/api/settings/server-key/rotate", SuperAdmin),
+    ("GET", "/api/settings/jwt-keys", Admin),
+    ("POST", "/api/settings/jwt-keys/rotate", SuperAdmin),
+    ("GET", "/api/settings/control-tls", Admin),
+    ("PUT", "/api/settings/control-tls", SuperAdmin),
+    ("POST", "/api/settings/control-tls/ca", SuperAdmin),
+    ("POST", "/api/settings/control-tls/certs", SuperAdmin),
+    ("DELETE", "/api/settings/control-tls/certs/:fingerprint", SuperAdmin),
+    ("GET", "/api/settings/email-otp", Admin),
+    ("PUT", "/api/settings/email-otp", SuperAdmin),
+    ("GET", "/api/settings/dlp-policy", Admin),
+    ("PUT", "/api/settings/dlp-policy", SuperAdmin),
+    ("GET", "/api/settings/transfer-bandwidth", Admin),
+    ("PUT", "/api/settings/transfer-bandwidth", SuperAdmin),
+    ("GET", "/api/settings/network-tuning", Admin),
+    ("PUT", "/api/settings/network-tuning", SuperAdmin),
+    ("GET", "/api/settings/webrtc", Admin),
+    ("PUT", "/api/settings/webrtc", SuperAdmin),
+    ("GET", "/api/settings/turn", Admin),
+    ("PUT", "/api/settings/turn", SuperAdmin),
+    ("GET", "/api/settings/honeypot", Admin),
+    ("PUT", "/api/settings/honeypot", SuperAdmin),
+    ("GET", "/api/honeypot/ip-bans", Admin),
+    ("DELETE", "/api/honeypot/ip-bans/:ip", Admin),
+    ("GET", "/api/settings/pk-pinning", Admin),
+    ("PUT", "/api/settings/pk-pinning", SuperAdmin),
+    ("GET", "/api/pk-changes", Admin),
+    ("POST", "/api/pk-changes/:id/approve", Admin),
+    ("POST", "/api/pk-changes/:id/reject", Admin),
+    ("GET", "/api/settings/threat-intel", Admin),
+    ("PUT", "/api/settings/threat-intel", SuperAdmin),
+    ("GET", "/api/threat-intel/status", Admin),
+    ("POST", "/api/threat-intel/refresh", Admin),
+    ("GET", "/api/settings/change-approval", Admin),
+    ("PUT", "/api/settings/change-approval", SuperAdmin),
+    ("GET", "/api/setting-changes", Admin),
+    ("POST", "/api/setting-changes/:id/approve", Admin),
+    ("POST", "/api/setting-changes/:id/reject", Admin),
+    ("POST", "/api/setting-changes/:id/cancel", Admin),
+    ("GET", "/api/settings/versions", Admin),
+    ("GET", "/api/settings/versions/diff", Admin),
+    ("POST", "/api/settings/rollback", SuperAdmin),
+    ("GET", "/api/settings/content-scan", Admin),
+    ("PUT", "/api/settings/content-scan", SuperAdmin),
+    ("GET", "/api/settings/trusted-device", Admin),
+    ("PUT", "/api/settings/trusted-device", SuperAdmin),
+    ("GET", "/api/settings/web-security", Admin),
+    ("PUT", "/api/settings/web-security", SuperAdmin),
+    ("GET", "/api/settings/feature-flags", Admin),
+    ("PUT", "/api/settings/feature-flags", SuperAdmin),
+    ("GET", "/api/settings/itsm", Admin),
+    ("PUT", "/api/settings/itsm", SuperAdmin),
+    ("GET", "/api/settings/ad-sync", Admin),
+    ("PUT", "/api/settings/ad-sync", SuperAdmin),
+    ("GET", "/api/settings/web-session", Admin),
+    ("PUT", "/api/settings/web-session", SuperAdmin),
+    ("GET", "/api/settings/data-masking", Admin),
+    ("PUT", "/api/settings/data-masking", SuperAdmin),
+    ("GET", "/api/admin/ad-sync", Admin),
+    ("POST", "/api/admin/ad-sync", SuperAdmin),
+    ("GET", "/api/license", Admin),
+    ("PUT", "/api/license", SuperAdmin),
+    ("GET", "/api/settings/password-policies", Admin),
+    ("PUT", "/api/settings/password-policies", Admin),
+    ("GET", "/api/devices/:id/password-policy", Admin),
+    ("GET", "/api/provisioned-ids", Admin),
+    ("POST", "/api/provisioned-ids", Admin),
+    ("DELETE", "/api/provisioned-ids/:id", Admin),
+    ("GET", "/api/files", User),
+    ("POST", "/api/files", User),
+    ("HEAD", "/api/files/:id", User),
+    ("PATCH", "/api/files/:id", User),
+    ("DELETE", "/api/files/:id", User),
+    ("GET", "/api/file-transfers", User),
+];
+
+// 同时接受 2FA 绑定受限令牌的接口
+const SETUP_SCOPE_ROUTES: &[&str] = &["/api/auth/2fa/setup", "/api/auth/2fa/confirm"];
+
+// 默认请求体为 {}，以下接口的请求体有必填字段
+const SAMPLE_BODIES: &[(&str, &str, &str)] = &[
+    ("POST", "/api/auth/2fa/confirm", r#"{"code": "000000"}"#),
+    (
+        "POST",
+        "/api/users",
+        r#"{"username": "authz", "password": "authz", "role": "User", "groups": []}"#,
+    ),
+    (
+        "POST",
+        "/api/invites",
+        r#"{"email": "authz@example.com", "role": "User"}"#,
+    ),
+    ("POST", "/api/device-views", r#"{"name": "authz"}"#),
+    ("PUT", "/api/device-views/:id", r#"{"name": "authz"}"#),
+    ("PUT", "/api/devices/:id/alias", r#"{"alias": "authz"}"#),
+    ("POST", "/api/devices/:id/ban", r#"{"reason": "authz"}"#),
+    ("POST", "/api/devices/:id/notes", r#"{"body": "authz"}"#),
+    ("PUT", "/api/devices/:id/notes/:note_id", r#"{"body": "authz"}"#),
+    ("POST", "/api/strategies", r#"{"name": "authz"}"#),
+    ("PUT", "/api/strategies/:id", r#"{"name": "authz"}"#),
+    ("POST", "/api/jobs", r#"{"name": "authz", "command": "true", "shell": "sh"}"#),
+    ("POST", "/api/messages", r#"{"body": "authz"}"#),
+    ("POST", "/api/announcements", r#"{"title": "authz", "ends_at": 1}"#),
+    ("PUT", "/api/license", r#"{"license": "authz"}"#),
+    ("PUT", "/api/settings/itsm", r#"{"provider": "servicenow"}"#),
+    ("PUT", "/api/settings/control-tls", r#"{"enabled": false}"#),
+    ("POST", "/api/settings/control-tls/certs", r#"{"name": "relay.example.com"}"#),
+    ("PUT", "/api/announcements/:id", r#"{"title": "authz", "ends_at": 1}"#),
+    ("PUT", "/api/sessions/:id/congestion-control", r#"{"algorithm": "bbr"}"#),
+    ("PUT", "/api/relays/drain", r#"{"server": "authz", "draining": true}"#),
+    ("POST", "/api/captures", r#"{"device_id": "authz"}"#),
+    (
+        "POST",
+        "/api/admin/erasure",
+        r#"{"subject": "user", "id": "authz", "mode": "anonymize", "reason": "authz"}"#,
+    ),
+    ("POST", "/api/break-glass", r#"{"device_id": "authz", "justification": "authz"}"#),
+    ("POST", "/api/admin/impersonate", r#"{"user_id": "authz", "reason": "authz"}"#),
+    ("POST", "/api/settings/rollback", r#"{"version": 1}"#),
+    ("POST", "/api/webrtc/sessions", r#"{"device_id": "authz", "sdp": "v=0"}"#),
+    ("POST", "/api/provisioned-ids", r#"{"ids": []}"#),
+    (
+        "POST",
+        "/api/files",
+        r#"{"file_path": "authz", "file_size": 0, "file_hash": "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"}"#,
+    ),
+];
+
+// 必填的查询参数
+const SAMPLE_QUERIES: &[(&str, &str, &str)] = &[
+    ("GET", "/api/files", "path=authz"),
+    ("PUT", "/api/updates/:platform/:version", "file_name=authz.exe"),
+    ("GET", "/api/custom-fields/:entity/search", "key=authz"),
+    ("GET", "/api/settings/versions/diff", "from=1"),
+    ("POST", "/api/settings/holiday-calendars/import", "id=authz&name=authz"),
+];
+
+// create_router 中注册的 (方法, 路径)
+pub(crate) fn router_routes() -> Vec<(String, String)> {
+    let source = include_str!("web_api.rs");
+    let start = source.find("pub fn create_router").unwrap();
+    let end = start + source[start..].find("\n}\n").unwrap();
+    let path_re = regex::Regex::new(r#""([^"]+)""#).unwrap();
+    let method_re = regex::Regex::new(r"\b(get|post|put|delete|patch|head)\(\w+\)").unwrap();
+    let mut res = vec![];
+    for chunk in source[start..end].split(".route(").skip(1) {
+        let path = &path_re.captures(chunk).unwrap()[1];
+        for m in method_re.captures_iter(chunk) {
+            res.push((m[1].to_uppercase(), path.to_owned()));
+        }
+    }
+    res
+}
+
+fn sample_uri(method: &str, path: &str) -> String {
+    let mut uri = path
+        .split('/')
+        .map(|segment| match segment {
+            ":platform" => "windows",
+            ":version" => "1.0.0",
+            ":name" => "hbbs-19700101-000000.sqlite3",
+            ":entity" => "device",
+            x if x.starts_with(':') => "authz",
+            x => x,
+        })
+        .collect::<Vec<_>>()
+        .join("/");
+    if let Some((_, _, query)) = SAMPLE_QUERIES.iter().find(|(m, p, _)| *m == method && *p == path) {
+        uri = format!("{}?{}", uri, query);
+    }
+    uri
+}
+
+fn sample_body(method: &str, path: &str) -> &'static str {
+    SAMPLE_BODIES
+        .iter()
+        .find(|(m, p, _)| *m == method && *p == path)
+        .map(|(_, _, body)| *body)
+        .unwrap_or("{}")
+}
+
+fn test_user(role: UserRole) -> User {
+    UserBuilder::new(role).build()
+}
+
+async fn test_router() -> (Router, AppState) {
+    fixtures::router().await
+}
+
+// 登记用户和 Web 会话后签发的令牌，与登录接口返回的一致
+async fn login_token(state: &AppState, role: UserRole) -> String {
+    let user = UserBuilder::new(role).create(state).await;
+    fixtures::login_token(state, &user).await
+}
+
+async fn status(router: &Router, method: &str, path: &str, token: Option<&str>) -> StatusCode {
+    let mut req = Request::builder()
+        .method(method)
+        .uri(sample_uri(method, path))
+        .header("Content-Type", "application/json");
+    if let Some(token) = token {
+        req = req.header("Authorization", format!("Bearer {}", token));
+    }
+    let req = req.body(Body::from(sample_body(method, path))).unwrap();
+    router.clone().oneshot(req).await.unwrap().status()
+}
+
+// 依次请求 expect 返回 Some 的路由，收集状态码不符的结果
+async fn check(
+    router: &Router,
+    token: Option<&str>,
+    expect: impl Fn(&str, Access) -> Option<StatusCode>,
+) -> Vec<String> {
+    let mut failures = vec![];
+    for (method, path, access) in ROUTES {
+        let expected = match expect(path, *access) {
+            Some(expected) => expected,
+            None => continue,
+        };
+        let actual = status(router, method, path, token).await;
+        if actual != expected {
+            let hint = match actual {
+                StatusCode::BAD_REQUEST | StatusCode::UNSUPPORTED_MEDIA_TYPE | StatusCode::UNPROCESSABLE_ENTITY => {
+                    " (提取器拒绝了请求，请补充 SAMPLE_BODIES/SAMPLE_QUERIES)"
+                }
+                _ => "",
+            };
+            failures.push(format!("{} {}: expected {}, got {}{}", method, path, expected, actual, hint));
+        }
+    }
+    failures
+}
+
+#[test]
+fn test_authz_routes_registered() {
+    let routes = router_routes();
+    let mut failures = vec![];
+    for (method, path) in routes.iter() {
+        if !ROUTES.iter().any(|(m, p, _)| m == method && p == path) {
+            failures.push(format!("{} {} is not in ROUTES", method, path));
+        }
+    }
+    for (method, path, _) in ROUTES {
+        if !routes.iter().any(|(m, p)| m == method && p == path) {
+            failures.push(format!("{} {} is not registered in create_router", method, path));
+        }
+    }
+    assert!(failures.is_empty(), "\n{}", failures.join("\n"));
+}
+
+#[tokio::test]
+async fn test_authz_anonymous() {
+    let (router, state) = test_router().await;
+    let mut failures = check(&router, None, |_, access| match access {
+        Public => None,
+        Unimplemented => Some(StatusCode::NOT_IMPLEMENTED),
+        _ => Some(StatusCode::UNAUTHORIZED),
+    })
+    .await;
+
+    failures.extend(
+        check(&router, Some("not-a-jwt"), |_, access| match access {
+            Public | Unimplemented => None,
+            _ => Some(StatusCode::UNAUTHORIZED),
+        })
+        .await,
+    );
+
+    let restricted = state
+        .auth
+        .generate_restricted_jwt(&test_user(UserRole::SuperAdmin), SCOPE_2FA_SETUP)
+        .unwrap();
+    failures.extend(
+        check(&router, Some(&restricted), |path, access| match access {
+            Public | Unimplemented => None,
+            _ if SETUP_SCOPE_ROUTES.contains(&path) => None,
+            _ => Some(StatusCode::UNAUTHORIZED),
+        })
+        .await,
+    );
+    assert!(failures.is_empty(), "\n{}", failures.join("\n"));
+}
+
+#[tokio::test]
+async fn test_authz_under_privileged() {
+    let (router, state) = test_router().await;
+    let user = login_token(&state, UserRole::User).await;
+    let mut failures = check(&router, Some(&user), |_, access| match access {
+        Admin | SuperAdmin => Some(StatusCode::FORBIDDEN),
+        _ => None,
+    })
+    .await;
+
+    let admin = login_token(&state, UserRole::Admin).await;
+    failures.extend(
+        check(&router, Some(&admin), |_, access| match access {
+            SuperAdmin => Some(StatusCode::FORBIDDEN),
+            _ => None,
+        })
+        .await,
+    );
+    assert!(failures.is_empty(), "\n{}", failures.join("\n"));
+}
+
+#[tokio::test]
+async fn test_authz_web_session() {
+    let (router, state) = test_router().await;
+    // 未登记会话的令牌按未登录处理
+    let unregistered = state.auth.generate_jwt(&test_user(UserRole::SuperAdmin)).unwrap();
+    let failures = check(&router, Some(&unregistered), |_, access| match access {
+        Public | Unimplemented => None,
+        _ => Some(StatusCode::UNAUTHORIZED),
+    })
+    .await;
+    assert!(failures.is_empty(), "\n{}", failures.join("\n"));
+
+    // 登出后令牌失效
+    let token = login_token(&state, UserRole::SuperAdmin).await;
+    assert_eq!(status(&router, "GET", "/api/auth/me", Some(&token)).await, StatusCode::OK);
+    assert_eq!(status(&router, "POST", "/api/auth/logout", Some(&token)).await, StatusCode::OK);
+    assert_eq!(
+        status(&router, "GET", "/api/auth/me", Some(&token)).await,
+        StatusCode::UNAUTHORIZED
+    );
+}
+
+#[tokio::test]
+async fn test_authz_auditor() {
+    let (router, state) = test_router().await;
+    let auditor = login_token(&state, UserRole::Auditor).await;
+    let mut failures = vec![];
+    for (method, path, access) in ROUTES {
+        if matches!(access, Public | Unimplemented) {
+            continue;
+        }
+        let actual = status(&router, method, path, Some(&auditor)).await;
+        let ok = match *method {
+            // 管理员可读的接口审计员同样可读
+            "GET" if matches!(access, Admin | SuperAdmin) => {
+                (actual == StatusCode::FORBIDDEN) == AUDITOR_DENIED_READS.contains(path)
+                    && actual != StatusCode::UNAUTHORIZED
+            }
+            "GET" | "HEAD" => true,
+            // 写接口除维护自身登录状态的以外一律拒绝
+            _ => AUDITOR_ALLOWED_WRITES.contains(path) || actual == StatusCode::FORBIDDEN,
+        };
+        if !ok {
+            failures.push(format!("{} {}: auditor got {}", method, path, actual));
+        }
+    }
+    assert!(failures.is_empty(), "\n{}", failures.join("\n"));
+}
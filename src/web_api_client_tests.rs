@@ -0,0 +1,204 @@
+// 客户端库一致性测试 - 服务端类型序列化后由 hbbs-enterprise-client 中的同名类型解析，再序列化应得到相同的 JSON，
+// 任一侧增删或改名字段时测试失败；客户端库的查询参数须与 OpenAPI 文档中登记的参数一致。
+// 运行: cargo test client_sdk
+use super::*;
+use crate::session_events::SessionEventType;
+use hbbs_enterprise_client as client;
+use serde::{de::DeserializeOwned, Serialize};
+use std::collections::BTreeSet;
+use std::time::Duration;
+
+// from 序列化后用 T 解析再序列化，结果应不变
+fn round_trip<T: Serialize + DeserializeOwned, F: Serialize>(from: &F) {
+    let expected = serde_json::to_value(from).unwrap();
+    let parsed: T = serde_json::from_value(expected.clone()).unwrap();
+    assert_eq!(serde_json::to_value(&parsed).unwrap(), expected);
+}
+
+fn time(secs: u64) -> SystemTime {
+    SystemTime::UNIX_EPOCH + Duration::from_secs(secs)
+}
+
+fn user_info() -> UserInfo {
+    UserInfo {
+        id: "u1".to_owned(),
+        username: "alice".to_owned(),
+        email: Some("alice@example.com".to_owned()),
+        role: "Admin".to_owned(),
+        groups: vec!["g1".to_owned()],
+        enabled: true,
+        last_login: Some(1),
+    }
+}
+
+// OpenAPI 文档中登记的查询参数
+fn documented_params(path: &str) -> BTreeSet<String> {
+    openapi::DOCUMENT["paths"][path]["get"]["parameters"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .filter(|p| p["in"] == "query")
+        .map(|p| p["name"].as_str().unwrap().to_owned())
+        .collect()
+}
+
+fn query_keys(query: &impl Serialize) -> BTreeSet<String> {
+    match serde_json::to_value(query).unwrap() {
+        serde_json::Value::Object(map) => map.keys().cloned().collect(),
+        _ => unreachable!(),
+    }
+}
+
+#[test]
+fn test_client_sdk_responses() {
+    round_trip::<client::UserInfo, _>(&user_info());
+    round_trip::<client::LoginResponse, _>(&LoginResponse {
+        success: true,
+        token: Some("jwt".to_owned()),
+        user: Some(user_info()),
+        message: String::new(),
+        require_2fa_setup: true,
+    });
+    round_trip::<client::DeviceListResponse, _>(&DeviceListResponse {
+        devices: vec![DeviceInfo {
+            id: "123456789".to_owned(),
+            name: "kiosk-1".to_owned(),
+            os: "windows".to_owned(),
+            version: "1.2.3".to_owned(),
+            ip_address: "10.0.0.1".to_owned(),
+            ipv6_address: Some("fd00::1".to_owned()),
+            mac_address: Some("00:11:22:33:44:55".to_owned()),
+            last_online: time(100),
+            owner_id: "u1".to_owned(),
+            group_ids: vec!["g1".to_owned()],
+            enabled: true,
+            tags: vec!["kiosk".to_owned()],
+            alias: Some("lobby".to_owned()),
+        }],
+        total: 1,
+    });
+    round_trip::<client::AuditLogResponse, _>(&AuditLogResponse {
+        logs: vec![AuditLog {
+            id: 1,
+            user_id: "u1".to_owned(),
+            device_id: "123456789".to_owned(),
+            action: "login".to_owned(),
+            details: Some("ok".to_owned()),
+            ip_address: "10.0.0.2".to_owned(),
+            user_agent: Some("curl".to_owned()),
+            timestamp: time(200),
+            success: true,
+        }],
+        total: 1,
+        device_aliases: [("123456789".to_owned(), "lobby".to_owned())].into_iter().collect(),
+    });
+
+    let relay_session: RelaySession = serde_json::from_value(serde_json::json!({
+        "uuid": "s1",
+        "relay_server": "relay.example.com",
+        "device_ids": ["123456789"],
+        "started_at": time(300),
+    }))
+    .unwrap();
+    let permissions = SessionPermissions {
+        session_id: "s1".to_owned(),
+        device_ids: vec!["123456789".to_owned()],
+        clipboard: false,
+        file_transfer: true,
+        updated_by: "u1".to_owned(),
+        updated_at: 400,
+    };
+    round_trip::<client::SessionPermissions, _>(&permissions);
+    round_trip::<Vec<client::SessionInfo>, _>(&vec![SessionInfo {
+        session: relay_session,
+        permissions: Some(permissions),
+    }]);
+    round_trip::<client::SessionEventsDetail, _>(&SessionEventsDetail {
+        session: Some(ConnectionSession {
+            id: "s1".to_owned(),
+            controller_id: "987654321".to_owned(),
+            controlled_device_id: "123456789".to_owned(),
+            start_time: time(300),
+            end_time: Some(time(360)),
+            duration_seconds: Some(60),
+            bytes_transferred: 1024,
+            connection_type: "relay".to_owned(),
+            quality_score: Some(0.5),
+        }),
+        events: vec![SessionEvent {
+            id: 1,
+            session_id: "s1".to_owned(),
+            device_id: "123456789".to_owned(),
+            event_type: SessionEventType::FileCopied,
+            details: Some("a.txt".to_owned()),
+            occurred_at: 310,
+            received_at: 311,
+        }],
+    });
+    round_trip::<Vec<client::UserDevice>, _>(&vec![favorites::UserDevice {
+        device_id: "123456789".to_owned(),
+        name: "kiosk-1".to_owned(),
+        alias: Some("lobby".to_owned()),
+        os: "windows".to_owned(),
+        online: true,
+        last_online: 100,
+        favorited_at: Some(50),
+        last_connected_at: None,
+        connection_count: 0,
+    }]);
+    round_trip::<client::FieldError, _>(&validation::FieldError {
+        field: "username".to_owned(),
+        code: "username_charset".to_owned(),
+        message: "用户名只能包含字母、数字和 ._@-，且以字母或数字开头".to_owned(),
+    });
+}
+
+#[test]
+fn test_client_sdk_requests() {
+    round_trip::<LoginRequest, _>(&client::LoginRequest {
+        username: "alice".to_owned(),
+        password: "secret".to_owned(),
+        totp_code: Some("123456".to_owned()),
+        email_code: Some("654321".to_owned()),
+        remember_device: true,
+    });
+    round_trip::<CreateUserRequest, _>(&client::CreateUserRequest {
+        username: "bob".to_owned(),
+        password: "secret".to_owned(),
+        email: Some("bob@example.com".to_owned()),
+        role: "User".to_owned(),
+        groups: vec!["g1".to_owned()],
+    });
+    round_trip::<PermissionChange, _>(&client::PermissionChange {
+        clipboard: Some(false),
+        file_transfer: Some(true),
+    });
+
+    let s = || Some(String::new());
+    let device_query = client::DeviceQuery {
+        view: s(),
+        tags: s(),
+        os: s(),
+        group_id: s(),
+        online: Some(true),
+        offline_for: s(),
+        seen_within: s(),
+        q: s(),
+        page: Some(1),
+        limit: Some(1),
+    };
+    assert_eq!(query_keys(&device_query), documented_params("/api/devices"));
+    let audit_query = client::AuditLogQuery {
+        user_id: s(),
+        device_id: s(),
+        action: s(),
+        success: Some(true),
+        since: Some(1),
+        until: Some(1),
+        ip: s(),
+        q: s(),
+        page: Some(1),
+        limit: Some(1),
+    };
+    assert_eq!(query_keys(&audit_query), documented_params("/api/audit-logs"));
+}
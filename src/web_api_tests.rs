@@ -0,0 +1,655 @@
+// 接口行为测试 - 经完整路由或 TestServer 调用接口，每个测试使用独立的内存数据库(夹具见 test_fixtures.rs)
+// 运行: cargo test e2e
+use super::*;
+use super::fixtures::{
+    login_token, random_suffix, raw_request, request, state, DeviceBuilder, GroupBuilder, TestServer, UserBuilder,
+    PASSWORD,
+};
+use hbb_common::tokio;
+use hbbs_enterprise_client as client;
+
+fn headers(pairs: &[(&'static str, &str)]) -> HeaderMap {
+    let mut res = HeaderMap::new();
+    for (name, value) in pairs {
+        res.insert(*name, HeaderValue::from_str(value).unwrap());
+    }
+    res
+}
+
+#[test]
+fn test_client_ip() {
+    let proxies = parse_trusted_proxies("127.0.0.1, 10.0.0.0/8, bogus");
+    assert_eq!(proxies.len(), 2);
+    let peer = |x: &str| Some(SocketAddr::new(x.parse().unwrap(), 40000));
+    let ip = |x: &str| Some(x.parse::<IpAddr>().unwrap());
+    let forged = headers(&[("X-Real-IP", "198.51.100.1"), ("X-Forwarded-For", "198.51.100.2")]);
+
+    // 对端不是受信任的代理时忽略转发头
+    assert_eq!(client_ip(peer("203.0.113.7"), &forged, &proxies), ip("203.0.113.7"));
+    assert_eq!(client_ip(peer("203.0.113.7"), &forged, &[]), ip("203.0.113.7"));
+    assert_eq!(client_ip(None, &forged, &proxies), None);
+
+    // 受信任的代理设置的 X-Real-IP 优先
+    assert_eq!(client_ip(peer("127.0.0.1"), &forged, &proxies), ip("198.51.100.1"));
+
+    // X-Forwarded-For 从右往左跳过代理地址，客户端自行添加的最左侧地址不被采信
+    let chain = headers(&[("X-Forwarded-For", "198.51.100.9, 203.0.113.7, 10.1.2.3")]);
+    assert_eq!(client_ip(peer("127.0.0.1"), &chain, &proxies), ip("203.0.113.7"));
+
+    // 没有可用的转发头时取对端地址
+    assert_eq!(client_ip(peer("10.0.0.1"), &HeaderMap::new(), &proxies), ip("10.0.0.1"));
+    let all_proxies = headers(&[("X-Forwarded-For", "10.0.0.2, garbage")]);
+    assert_eq!(client_ip(peer("10.0.0.1"), &all_proxies, &proxies), ip("10.0.0.1"));
+}
+
+#[tokio::test]
+async fn test_e2e_login_and_devices() {
+    let server = TestServer::start().await;
+    let admin = UserBuilder::new(UserRole::Admin).create(&server.state).await;
+    let group = GroupBuilder::new("berlin").create(&server.state).await;
+    DeviceBuilder::new("123456789").group(&group).tag("kiosk").create(&server.state).await;
+    DeviceBuilder::new("987654321").os("Windows").create(&server.state).await;
+
+    let mut client = server.client();
+    client.login(&admin.username, PASSWORD, None).await.unwrap();
+    assert_eq!(client.me().await.unwrap().username, admin.username);
+    let res = client
+        .list_devices(&client::DeviceQuery {
+            group_id: Some(group.clone()),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    assert_eq!(res.devices.len(), 1);
+    assert_eq!(res.devices[0].id, "123456789");
+    assert_eq!(res.devices[0].tags, vec!["kiosk".to_owned()]);
+
+    client.logout().await.unwrap();
+    assert!(client.me().await.is_err());
+}
+
+#[tokio::test]
+async fn test_e2e_isolated_databases() {
+    let (a, b) = (state().await, state().await);
+    let user = UserBuilder::new(UserRole::User).create(&a).await;
+    assert!(a.db.get_user_by_username(&user.username).await.unwrap().is_some());
+    assert!(b.db.get_user_by_username(&user.username).await.unwrap().is_none());
+    // 禁用的用户不能登录
+    let server = TestServer::start().await;
+    let disabled = UserBuilder::new(UserRole::User).disabled().create(&server.state).await;
+    let mut client = server.client();
+    assert!(client.login(&disabled.username, PASSWORD, None).await.is_err());
+}
+
+#[tokio::test]
+async fn test_e2e_user_conflicts() {
+    let server = TestServer::start().await;
+    let admin = UserBuilder::new(UserRole::Admin)
+        .email("Admin@Example.com")
+        .create(&server.state)
+        .await;
+    let mut client = server.client();
+    client.login(&admin.username, PASSWORD, None).await.unwrap();
+    let request = |username: &str, email: Option<&str>| client::CreateUserRequest {
+        username: username.to_owned(),
+        password: PASSWORD.to_owned(),
+        email: email.map(|x| x.to_owned()),
+        role: "User".to_owned(),
+        groups: vec![],
+    };
+
+    // 用户名和邮箱不区分大小写
+    let res = client.create_user(&request(&admin.username.to_uppercase(), None)).await;
+    assert!(matches!(res, Err(client::Error::Api(ref m)) if m.contains("用户名已存在")));
+    let res = client.create_user(&request("carol", Some(" admin@example.COM "))).await;
+    assert!(matches!(res, Err(client::Error::Api(ref m)) if m.contains("邮箱已被其他用户使用")));
+
+    // 并发创建同名用户只有一个成功，其余返回冲突而不是服务器错误
+    let tasks: Vec<_> = (0..8)
+        .map(|i| {
+            let client = client.clone();
+            let req = request(if i % 2 == 0 { "dave" } else { "DAVE" }, None);
+            tokio::spawn(async move { client.create_user(&req).await })
+        })
+        .collect();
+    let mut created = 0;
+    for task in tasks {
+        match task.await.unwrap() {
+            Ok(_) => created += 1,
+            Err(client::Error::Api(m)) => assert!(m.contains("用户名已存在")),
+            Err(e) => panic!("unexpected error {}", e),
+        }
+    }
+    assert_eq!(created, 1);
+    assert!(server.state.db.get_user_by_username("Dave").await.unwrap().is_some());
+}
+
+#[tokio::test]
+async fn test_e2e_validation() {
+    let server = TestServer::start().await;
+    let admin = UserBuilder::new(UserRole::SuperAdmin).create(&server.state).await;
+    let mut client = server.client();
+    client.login(&admin.username, PASSWORD, None).await.unwrap();
+
+    // 逐字段返回错误，不会创建用户
+    let req = client::CreateUserRequest {
+        username: "eve smith".to_owned(),
+        password: PASSWORD.to_owned(),
+        email: Some("eve@".to_owned()),
+        role: "Root".to_owned(),
+        groups: vec![],
+    };
+    let fields = match client.create_user(&req).await {
+        Err(client::Error::Invalid(message, fields)) => {
+            assert!(message.starts_with(validation::FAILED), "{}", message);
+            fields
+        }
+        res => panic!("unexpected result {:?}", res.map(|x| x.username)),
+    };
+    let codes: Vec<_> = fields.iter().map(|x| (x.field.as_str(), x.code.as_str())).collect();
+    assert_eq!(
+        codes,
+        vec![("email", "email"), ("role", "role"), ("username", "username_charset")]
+    );
+    assert!(server.state.db.get_user_by_username("eve smith").await.unwrap().is_none());
+
+    let res = client
+        .audit_logs(&client::AuditLogQuery {
+            ip: Some("10.0.0.0/33".to_owned()),
+            ..Default::default()
+        })
+        .await;
+    assert!(matches!(res, Err(client::Error::Invalid(_, ref f)) if f[0].field == "ip" && f[0].code == "cidr"));
+    let res = client
+        .audit_logs(&client::AuditLogQuery {
+            ip: Some("10.0.0.0/8".to_owned()),
+            ..Default::default()
+        })
+        .await;
+    assert!(res.is_ok());
+
+    // 引用的设备组须存在
+    let group = GroupBuilder::new("berlin").create(&server.state).await;
+    let token = client.token().unwrap().to_owned();
+    let app = create_router(server.state.clone());
+    let post = |group_ids: Vec<&str>| {
+        let body = serde_json::json!({"name": format!("s-{}", group_ids.len()), "group_ids": group_ids});
+        let req = axum::http::Request::builder()
+            .method("POST")
+            .uri("/api/strategies")
+            .header("Content-Type", "application/json")
+            .header("Authorization", format!("Bearer {}", token))
+            .body(axum::body::Body::from(body.to_string()))
+            .unwrap();
+        tower::ServiceExt::oneshot(app.clone(), req)
+    };
+    let res = post(vec![&group, "missing"]).await.unwrap();
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+    let body = axum::body::to_bytes(res.into_body(), usize::MAX).await.unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(body["code"], "INVALID_FIELDS");
+    assert_eq!(body["data"][0]["field"], "group_ids");
+    assert_eq!(body["data"][0]["code"], "group_not_found");
+    let res = post(vec![&group]).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+
+    // 设备和设置接口同样在提取时校验
+    let invalid = [
+        ("POST", "/api/devices/123456789/wake", serde_json::json!({"mac_address": "zz"}), "mac_address"),
+        ("PUT", "/api/devices/123456789/alias", serde_json::json!({"alias": "123456"}), "alias"),
+        ("PUT", "/api/settings/relay-policy", serde_json::json!({"force_relay_devices": [""]}), "force_relay_devices"),
+        ("PUT", "/api/settings/backup", serde_json::json!({"keep": 0}), "keep"),
+        ("PUT", "/api/settings", serde_json::json!({" ": "x"}), "settings"),
+        ("PUT", "/api/devices/123456789/fields", serde_json::json!({"Asset Tag": "A1"}), "fields"),
+        (
+            "PUT",
+            "/api/settings/feature-flags",
+            serde_json::json!({"file_transfer": {"rollout_percent": 101}}),
+            "feature_flags",
+        ),
+        ("PUT", "/api/settings/data-masking", serde_json::json!({"email": {"full_access_roles": ["Root"]}}), "rules"),
+    ];
+    for (method, uri, body, field) in invalid {
+        let (status, body) = request(&server.state, method, uri, Some(&token), Some(body)).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{} {}", method, uri);
+        assert_eq!(body["data"][0]["field"], field, "{} {}", method, uri);
+    }
+}
+
+#[tokio::test]
+async fn test_e2e_favorites() {
+    let server = TestServer::start().await;
+    let user = UserBuilder::new(UserRole::User).create(&server.state).await;
+    DeviceBuilder::new("123456789").owner(&user.id).create(&server.state).await;
+    DeviceBuilder::new("987654321").create(&server.state).await;
+
+    let mut client = server.client();
+    client.login(&user.username, PASSWORD, None).await.unwrap();
+    client.add_favorite("123456789").await.unwrap();
+    // 只能收藏自己可以控制的设备
+    assert!(matches!(
+        client.add_favorite("987654321").await,
+        Err(client::Error::Status(StatusCode::FORBIDDEN))
+    ));
+    assert!(matches!(
+        client.add_favorite("555555555").await,
+        Err(client::Error::Status(StatusCode::NOT_FOUND))
+    ));
+
+    favorites::record(&server.state.db, &user.id, "123456789").await;
+    let recent = client.recent_devices(None).await.unwrap();
+    assert_eq!(recent.len(), 1);
+    assert_eq!(recent[0].connection_count, 1);
+    assert!(recent[0].favorited_at.is_some());
+    let starred = client.favorite_devices().await.unwrap();
+    assert_eq!(starred.len(), 1);
+    assert_eq!(starred[0].last_connected_at, recent[0].last_connected_at);
+
+    client.remove_favorite("123456789").await.unwrap();
+    assert!(client.favorite_devices().await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn test_e2e_settings_hide_keys() {
+    let state = state().await;
+    let admin = UserBuilder::new(UserRole::SuperAdmin).create(&state).await;
+    let token = login_token(&state, &admin).await;
+    for (key, _) in KEY_SETTINGS {
+        state.db.set_setting(key, r#"{"secret": "x"}"#, None).await.unwrap();
+    }
+    state.db.set_setting("custom", "1", None).await.unwrap();
+
+    let (status, body) = request(&state, "GET", "/api/settings", Some(&token), None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["data"]["custom"], "1");
+    for (key, _) in KEY_SETTINGS {
+        assert!(body["data"].get(*key).is_none(), "{} returned", key);
+    }
+
+    // 不能通过通用设置接口覆盖
+    for (key, message) in KEY_SETTINGS {
+        let req = serde_json::to_value(HashMap::from([(*key, "{}")])).unwrap();
+        let (_, body) = request(&state, "PUT", "/api/settings", Some(&token), Some(req)).await;
+        assert_eq!(body["success"], false);
+        assert_eq!(body["message"], *message);
+        assert_eq!(state.db.get_setting(key).await.unwrap().unwrap(), r#"{"secret": "x"}"#);
+    }
+}
+
+#[tokio::test]
+async fn test_e2e_settings_mask_credentials() {
+    let state = state().await;
+    let auditor = UserBuilder::new(UserRole::Auditor).create(&state).await;
+    let token = login_token(&state, &auditor).await;
+    let smtp = r#"{"host": "smtp.example.com", "username": "hbbs", "password": "smtp-password"}"#;
+    state.db.set_setting(email_otp::EMAIL_OTP_KEY, smtp, None).await.unwrap();
+    let federation = r#"{"enabled": true, "region": "eu", "secret": "federation-secret"}"#;
+    state.db.set_setting(federation::FEDERATION_KEY, federation, None).await.unwrap();
+    state.db.set_setting(itsm::ITSM_KEY, "not json", None).await.unwrap();
+
+    let (status, body) = request(&state, "GET", "/api/settings", Some(&token), None).await;
+    assert_eq!(status, StatusCode::OK);
+    let text = body.to_string();
+    assert!(!text.contains("smtp-password") && !text.contains("federation-secret"));
+    let smtp: SmtpConfig = serde_json::from_str(body["data"][email_otp::EMAIL_OTP_KEY].as_str().unwrap()).unwrap();
+    assert_eq!(smtp.host, "smtp.example.com");
+    assert_eq!(smtp.password, "******");
+    let federation: FederationConfig =
+        serde_json::from_str(body["data"][federation::FEDERATION_KEY].as_str().unwrap()).unwrap();
+    assert_eq!(federation.secret, "******");
+    // 无法解析的设置不返回原文
+    assert!(body["data"].get(itsm::ITSM_KEY).is_none());
+}
+
+#[tokio::test]
+async fn test_e2e_locked_account_uniform_failure() {
+    let state = state().await;
+    let locked = UserBuilder::new(UserRole::User).locked().create(&state).await;
+    let active = UserBuilder::new(UserRole::User).create(&state).await;
+
+    // 锁定账户无论密码是否正确，都与用户不存在返回同样的失败
+    let cases = [
+        (locked.username.as_str(), PASSWORD),
+        (locked.username.as_str(), "wrong-password"),
+        ("no-such-user", PASSWORD),
+        (active.username.as_str(), "wrong-password"),
+    ];
+    for uri in ["/api/auth/login", "/api/auth/2fa/email"] {
+        for (username, password) in cases {
+            let req = serde_json::json!({ "username": username, "password": password });
+            let (status, body) = request(&state, "POST", uri, None, Some(req)).await;
+            assert_eq!(status, StatusCode::OK, "{} {}", uri, username);
+            assert_eq!(body["success"], false, "{} {}", uri, username);
+            assert_eq!(body["message"], "用户名或密码错误", "{} {}", uri, username);
+        }
+    }
+    // 锁定期间的尝试不累计
+    let user = state.db.get_user_by_username(&locked.username).await.unwrap().unwrap();
+    assert_eq!(user.failed_login_attempts, 0);
+    assert!(state.auth.is_user_locked(&user));
+}
+
+#[tokio::test]
+async fn test_e2e_login_locks_after_repeated_failures() {
+    let state = state().await;
+    let user = UserBuilder::new(UserRole::User).create(&state).await;
+    let login = |password: &'static str| {
+        let state = state.clone();
+        let username = user.username.clone();
+        async move {
+            let req = serde_json::json!({ "username": username, "password": password });
+            request(&state, "POST", "/api/auth/login", None, Some(req)).await.1
+        }
+    };
+
+    for _ in 0..5 {
+        assert_eq!(login("wrong-password").await["success"], false);
+    }
+    let stored = state.db.get_user_by_username(&user.username).await.unwrap().unwrap();
+    assert!(state.auth.is_user_locked(&stored));
+    // 锁定后正确密码同样失败
+    let body = login(PASSWORD).await;
+    assert_eq!(body["success"], false);
+    assert_eq!(body["message"], "用户名或密码错误");
+}
+
+#[tokio::test]
+async fn test_e2e_password_reset_unlocks_account() {
+    let state = state().await;
+    let user = UserBuilder::new(UserRole::User).locked().create(&state).await;
+    // 锁定前已登录的令牌
+    let session = login_token(&state, &user).await;
+    assert_eq!(request(&state, "GET", "/api/auth/me", Some(&session), None).await.0, StatusCode::OK);
+    let expires_at = crate::common::now() + password_reset::RESET_TTL_SECS;
+    let token = state.auth.generate_password_reset_token(&user, expires_at).unwrap();
+    let password = "Reset-Passw0rd!";
+
+    let req = serde_json::json!({ "token": token, "password": password });
+    let (_, body) = request(&state, "POST", "/api/auth/reset-password", None, Some(req.clone())).await;
+    assert_eq!(body["success"], true);
+    // 重置后原有会话全部失效
+    let status = request(&state, "GET", "/api/auth/me", Some(&session), None).await.0;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let stored = state.db.get_user_by_id(&user.id).await.unwrap().unwrap();
+    assert_eq!(stored.failed_login_attempts, 0);
+    assert!(stored.locked_until.is_none());
+
+    let login = serde_json::json!({ "username": user.username, "password": password });
+    let (_, body) = request(&state, "POST", "/api/auth/login", None, Some(login)).await;
+    assert_eq!(body["success"], true);
+    // 链接只能使用一次
+    let (_, body) = request(&state, "POST", "/api/auth/reset-password", None, Some(req)).await;
+    assert_eq!(body["success"], false);
+}
+
+async fn patch_file(state: &AppState, uri: &str, token: &str, offset: Option<u64>, data: &[u8]) -> (StatusCode, u64, bool) {
+    let mut headers = HeaderMap::new();
+    if let Some(offset) = offset {
+        headers.insert(UPLOAD_OFFSET, offset.into());
+    }
+    let (status, headers, body) = raw_request(state, "PATCH", uri, Some(token), headers, data.to_vec()).await;
+    let body: serde_json::Value = serde_json::from_slice(&body).unwrap_or_default();
+    (status, upload_offset(&headers), body["success"] == true)
+}
+
+async fn head_file(state: &AppState, uri: &str, token: &str) -> (StatusCode, u64) {
+    let (status, headers, _) = raw_request(state, "HEAD", uri, Some(token), HeaderMap::new(), vec![]).await;
+    (status, upload_offset(&headers))
+}
+
+fn upload_offset(headers: &HeaderMap) -> u64 {
+    headers
+        .get(UPLOAD_OFFSET)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse().ok())
+        .unwrap_or_default()
+}
+
+async fn download_file_range(state: &AppState, path: &str, token: &str, range: Option<&'static str>) -> (StatusCode, HeaderMap, Bytes) {
+    let mut headers = HeaderMap::new();
+    if let Some(range) = range {
+        headers.insert(header::RANGE, header::HeaderValue::from_static(range));
+    }
+    let uri = format!("/api/files?path={}", path);
+    raw_request(state, "GET", &uri, Some(token), headers, vec![]).await
+}
+
+#[tokio::test]
+async fn test_e2e_resumable_file_upload() {
+    use sha2::{Digest, Sha256};
+
+    let dir = std::env::temp_dir().join(format!("e2e-files-{}", random_suffix()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::create_dir_all(file_transfer_server::storage_root().join(".partial")).unwrap();
+    let manager = file_transfer_server::manager();
+    manager
+        .set_storage(Arc::new(crate::storage_backend::LocalStorage::new(dir.clone())))
+        .await;
+
+    let state = state().await;
+    // 与文件传输服务一样把已结束的传输写入传输历史
+    let mut records = manager.subscribe_transfer_records().await;
+    let db = state.db.clone();
+    tokio::spawn(async move {
+        while let Some(record) = records.recv().await {
+            db.save_file_transfer(&record).await.ok();
+        }
+    });
+    let user = UserBuilder::new(UserRole::User).create(&state).await;
+    let other = UserBuilder::new(UserRole::User).create(&state).await;
+    let token = login_token(&state, &user).await;
+    let other_token = login_token(&state, &other).await;
+    let content: Vec<u8> = (0..CHUNK_SIZE + 5).map(|i| (i % 251) as u8).collect();
+    let hash = format!("{:x}", Sha256::digest(&content));
+    let file_path = format!("e2e-{}/report.txt", random_suffix());
+    let create = |file_path: &str, hash: &str| {
+        serde_json::json!({ "file_path": file_path, "file_size": content.len(), "file_hash": hash })
+    };
+
+    let (_, body) = request(&state, "POST", "/api/files", Some(&token), Some(create(".partial/x.txt", &hash))).await;
+    assert_eq!(body["success"], false);
+    let (_, body) = request(&state, "POST", "/api/files", Some(&token), Some(create(&file_path, &hash))).await;
+    assert_eq!(body["success"], true);
+    assert_eq!(body["data"]["chunk_size"], CHUNK_SIZE);
+    // 文件保存在上传者自己的目录下
+    let stored = format!("users/{}/{}", user.id, file_path);
+    assert_eq!(body["data"]["file_path"], stored.as_str());
+    let uri = format!("/api/files/{}", body["data"]["transfer_id"].as_str().unwrap());
+
+    assert_eq!(head_file(&state, &uri, &token).await, (StatusCode::OK, 0));
+    // 其他用户看不到该上传
+    assert_eq!(head_file(&state, &uri, &other_token).await.0, StatusCode::NOT_FOUND);
+
+    // 缺少偏移、中间块不完整
+    let first = &content[..CHUNK_SIZE];
+    assert_eq!(patch_file(&state, &uri, &token, None, first).await.0, StatusCode::BAD_REQUEST);
+    assert_eq!(patch_file(&state, &uri, &token, Some(0), &first[..10]).await.0, StatusCode::BAD_REQUEST);
+    // 偏移不一致时返回当前偏移
+    assert_eq!(patch_file(&state, &uri, &token, Some(5), first).await, (StatusCode::OK, 0, false));
+
+    let offset = CHUNK_SIZE as u64;
+    assert_eq!(patch_file(&state, &uri, &token, Some(0), first).await, (StatusCode::OK, offset, true));
+    // 断线续传：查询偏移后继续，超出文件大小的块被拒绝
+    assert_eq!(head_file(&state, &uri, &token).await, (StatusCode::OK, offset));
+    let mut tail = content[CHUNK_SIZE..].to_vec();
+    tail.push(0);
+    assert_eq!(patch_file(&state, &uri, &token, Some(offset), &tail).await.0, StatusCode::BAD_REQUEST);
+    let res = patch_file(&state, &uri, &token, Some(offset), &content[CHUNK_SIZE..]).await;
+    assert_eq!(res, (StatusCode::OK, content.len() as u64, true));
+    assert_eq!(std::fs::read(dir.join(&stored)).unwrap(), content);
+    assert_eq!(head_file(&state, &uri, &token).await.0, StatusCode::NOT_FOUND);
+
+    // 分段下载
+    let mut downloaded = None;
+    for _ in 0..50 {
+        let (status, _, body) = download_file_range(&state, &stored, &token, None).await;
+        if status != StatusCode::NOT_FOUND {
+            downloaded = Some((status, body.to_vec()));
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    }
+    assert_eq!(downloaded, Some((StatusCode::OK, content.clone())));
+    // 其他用户不能下载别人上传的文件
+    let status = download_file_range(&state, &stored, &other_token, None).await.0;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, headers, body) = download_file_range(&state, &stored, &token, Some("bytes=-5")).await;
+    assert_eq!(status, StatusCode::PARTIAL_CONTENT);
+    assert_eq!(body.to_vec(), content[CHUNK_SIZE..].to_vec());
+    let range = format!("bytes {}-{}/{}", CHUNK_SIZE, content.len() - 1, content.len());
+    assert_eq!(headers[header::CONTENT_RANGE].to_str().unwrap(), range);
+    let (status, headers, _) = download_file_range(&state, &stored, &token, Some("bytes=99999999-")).await;
+    assert_eq!(status, StatusCode::RANGE_NOT_SATISFIABLE);
+    assert_eq!(headers[header::CONTENT_RANGE].to_str().unwrap(), format!("bytes */{}", content.len()));
+    let status = download_file_range(&state, "../etc/passwd", &token, None).await.0;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let status = download_file_range(&state, "e2e-missing/report.txt", &token, None).await.0;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    // 哈希不符时最后一块写入失败，文件不保存
+    let wrong_path = format!("e2e-{}/wrong.txt", random_suffix());
+    let (_, body) = request(&state, "POST", "/api/files", Some(&token), Some(create(&wrong_path, &"00".repeat(32)))).await;
+    let uri = format!("/api/files/{}", body["data"]["transfer_id"].as_str().unwrap());
+    assert!(patch_file(&state, &uri, &token, Some(0), first).await.2);
+    assert!(!patch_file(&state, &uri, &token, Some(offset), &content[CHUNK_SIZE..]).await.2);
+    assert!(!dir.join(format!("users/{}/{}", user.id, wrong_path)).exists());
+
+    // 其他用户上传同名文件不会覆盖
+    let (_, body) = request(&state, "POST", "/api/files", Some(&other_token), Some(create(&file_path, &hash))).await;
+    assert_eq!(body["data"]["file_path"], format!("users/{}/{}", other.id, file_path).as_str());
+    let uri = format!("/api/files/{}", body["data"]["transfer_id"].as_str().unwrap());
+    assert_eq!(request(&state, "DELETE", &uri, Some(&other_token), None).await.1["success"], true);
+
+    // 只能向自己的设备上传
+    DeviceBuilder::new("123456789").owner(&user.id).create(&state).await;
+    let mut to_device = create(&file_path, &hash);
+    to_device["device_id"] = "123456789".into();
+    let (status, _) = request(&state, "POST", "/api/files", Some(&other_token), Some(to_device.clone())).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (_, body) = request(&state, "POST", "/api/files", Some(&token), Some(to_device)).await;
+    assert_eq!(body["data"]["file_path"], format!("devices/123456789/{}", file_path).as_str());
+    let uri = format!("/api/files/{}", body["data"]["transfer_id"].as_str().unwrap());
+    assert_eq!(request(&state, "DELETE", &uri, Some(&token), None).await.1["success"], true);
+
+    // 取消上传
+    let (_, body) = request(&state, "POST", "/api/files", Some(&token), Some(create(&file_path, &hash))).await;
+    let uri = format!("/api/files/{}", body["data"]["transfer_id"].as_str().unwrap());
+    assert_eq!(request(&state, "DELETE", &uri, Some(&other_token), None).await.0, StatusCode::NOT_FOUND);
+    let (_, body) = request(&state, "DELETE", &uri, Some(&token), None).await;
+    assert_eq!(body["success"], true);
+    assert_eq!(head_file(&state, &uri, &token).await.0, StatusCode::NOT_FOUND);
+
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+// 查询审计日志，返回本测试写入的记录编号(详情的第一个词)
+async fn audit_events(state: &AppState, token: &str, params: &[(&str, &str)]) -> (StatusCode, Vec<String>) {
+    let encode = |s: &str| {
+        s.bytes()
+            .map(|b| match b {
+                b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'/' | b':' => (b as char).to_string(),
+                _ => format!("%{:02X}", b),
+            })
+            .collect::<String>()
+    };
+    let query = params
+        .iter()
+        .map(|(k, v)| format!("{}={}", k, encode(v)))
+        .collect::<Vec<_>>()
+        .join("&");
+    let (status, body) = request(state, "GET", &format!("/api/audit-logs?{}", query), Some(token), None).await;
+    let events = body["data"]["logs"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|x| x["details"].as_str()?.split(' ').next())
+        .filter(|x| x.starts_with("evt-"))
+        .map(str::to_owned)
+        .collect();
+    (status, events)
+}
+
+#[tokio::test]
+async fn test_e2e_audit_log_filters() {
+    let state = state().await;
+    let admin = UserBuilder::new(UserRole::SuperAdmin).create(&state).await;
+    let user = UserBuilder::new(UserRole::User).create(&state).await;
+    let admin_token = login_token(&state, &admin).await;
+    let user_token = login_token(&state, &user).await;
+    let base = 1_700_000_000u64;
+    let other = "audit-other-user";
+    let events = [
+        (user.id.as_str(), "login", "10.1.2.3", 0, true, "evt-1 登录成功 Chrome"),
+        (user.id.as_str(), "login", "10.1.200.4", 100, false, "evt-2 密码错误"),
+        (user.id.as_str(), "file_download", "10.10.1.1", 200, true, "evt-3 文件下载 report.pdf"),
+        (other, "file_download", "10.1.20.5", 300, true, "evt-4 文件下载 quote\"d.txt"),
+        (other, "delete_user", "2001:db8::5", 400, false, "evt-5 删除用户 AB"),
+        (other, "login", "unknown", 500, true, "evt-6"),
+    ];
+    for (user_id, action, ip, offset, success, details) in events {
+        let log = AuditLog {
+            id: 0,
+            user_id: user_id.to_owned(),
+            device_id: "123456789".to_owned(),
+            action: action.to_owned(),
+            details: Some(details.to_owned()),
+            ip_address: ip.to_owned(),
+            user_agent: None,
+            timestamp: std::time::UNIX_EPOCH + std::time::Duration::from_secs(base + offset),
+            success,
+        };
+        state.db.log_audit(&log).await.unwrap();
+    }
+    let (since, until) = ((base + 100).to_string(), (base + 300).to_string());
+
+    let cases: Vec<(Vec<(&str, &str)>, Vec<&str>)> = vec![
+        // 按时间倒序
+        (vec![], vec!["evt-6", "evt-5", "evt-4", "evt-3", "evt-2", "evt-1"]),
+        (vec![("action", "login")], vec!["evt-6", "evt-2", "evt-1"]),
+        (vec![("success", "false")], vec!["evt-5", "evt-2"]),
+        // 时间范围两端都包含
+        (vec![("since", since.as_str()), ("until", until.as_str())], vec!["evt-4", "evt-3", "evt-2"]),
+        (vec![("since", until.as_str()), ("until", since.as_str())], vec![]),
+        // 单个地址和网段；10.10.x 与 10.1. 文本前缀相近但不在网段内
+        (vec![("ip", "10.1.2.3")], vec!["evt-1"]),
+        (vec![("ip", "10.1.0.0/16")], vec!["evt-4", "evt-2", "evt-1"]),
+        (vec![("ip", "10.1.16.0/20")], vec!["evt-4"]),
+        (vec![("ip", "2001:db8::/32")], vec!["evt-5"]),
+        // IPv4 全网段不包含 IPv6 和无法解析的地址
+        (vec![("ip", "0.0.0.0/0")], vec!["evt-4", "evt-3", "evt-2", "evt-1"]),
+        (vec![("ip", " ")], vec!["evt-6", "evt-5", "evt-4", "evt-3", "evt-2", "evt-1"]),
+        // 详情关键字：全文索引、大小写不敏感、短关键字和引号
+        (vec![("q", "文件下载")], vec!["evt-4", "evt-3"]),
+        (vec![("q", "REPORT")], vec!["evt-3"]),
+        (vec![("q", "ab")], vec!["evt-5"]),
+        (vec![("q", "quote\"d")], vec!["evt-4"]),
+        (vec![("q", "nothing-matches")], vec![]),
+        (vec![("q", "  ")], vec!["evt-6", "evt-5", "evt-4", "evt-3", "evt-2", "evt-1"]),
+        // 组合条件
+        (vec![("action", "file_download"), ("ip", "10.1.0.0/16")], vec!["evt-4"]),
+        (vec![("user_id", user.id.as_str()), ("success", "true")], vec!["evt-3", "evt-1"]),
+        // 按网段过滤后再分页
+        (vec![("ip", "10.0.0.0/8"), ("limit", "2"), ("page", "2")], vec!["evt-2", "evt-1"]),
+        (vec![("ip", "10.0.0.0/8"), ("limit", "2"), ("page", "3")], vec![]),
+        (vec![("action", "login"), ("limit", "1"), ("page", "0")], vec!["evt-6"]),
+    ];
+    for (params, expected) in cases {
+        let (status, events) = audit_events(&state, &admin_token, &params).await;
+        assert_eq!(status, StatusCode::OK, "{:?}", params);
+        assert_eq!(events, expected, "{:?}", params);
+    }
+
+    // 格式错误的网段
+    let (status, body) = request(&state, "GET", "/api/audit-logs?ip=10.0.0.0/33", Some(&admin_token), None).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["success"], false);
+    let (status, _) = audit_events(&state, &admin_token, &[("ip", "not-an-ip")]).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    // 普通用户只能查看自己的日志，user_id 条件被忽略
+    let (status, events) = audit_events(&state, &user_token, &[("user_id", other)]).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(events, vec!["evt-3", "evt-2", "evt-1"]);
+    let (_, events) = audit_events(&state, &user_token, &[("ip", "10.1.0.0/16")]).await;
+    assert_eq!(events, vec!["evt-2", "evt-1"]);
+}
@@ -0,0 +1,293 @@
+// Web安全配置模块 - 管理界面的跨域(CORS)来源白名单和安全响应头(CSP、HSTS、X-Frame-Options)，
+// 默认只允许同源访问；可通过设置接口热加载
+use crate::enterprise_database::EnterpriseDatabase;
+use crate::openapi;
+use crate::validation::{self, Payload};
+use axum::{
+    extract::Request,
+    http::{header, HeaderMap, HeaderValue, Method, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use hbb_common::{bail, tokio::sync::RwLock, ResultType};
+use schemars::JsonSchema;
+use serde_derive::{Deserialize, Serialize};
+use sodiumoxide::crypto::hash::sha256;
+use validator::Validate;
+
+pub const WEB_SECURITY_KEY: &str = "web_security";
+
+const ALLOW_METHODS: &str = "GET, POST, PUT, PATCH, DELETE, HEAD";
+const ALLOW_HEADERS: &str = "authorization, content-type, x-request-id, upload-offset, range";
+const EXPOSE_HEADERS: &str = "x-request-id, upload-offset, content-range, content-disposition";
+const PREFLIGHT_MAX_AGE: &str = "600";
+
+lazy_static::lazy_static! {
+    static ref CONFIG: RwLock<WebSecurityConfig> = Default::default();
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema, Validate)]
+pub struct WebSecurityConfig {
+    // 允许跨域访问的来源，如 https://admin.example.com；为空时只允许同源
+    #[serde(default)]
+    #[validate(custom = "validation::non_empty_items")]
+    pub allowed_origins: Vec<String>,
+    // 跨域请求是否可携带 Cookie(受信任浏览器)
+    #[serde(default)]
+    pub allow_credentials: bool,
+    // HSTS 有效期(秒)，0 为不发送；仅在经 HTTPS 访问时由浏览器生效
+    #[serde(default = "default_hsts_max_age")]
+    pub hsts_max_age: u64,
+    #[serde(default)]
+    pub hsts_include_subdomains: bool,
+    // 为空时不发送 Content-Security-Policy
+    #[serde(default = "default_content_security_policy")]
+    #[validate(length(max = 4096))]
+    pub content_security_policy: String,
+    // DENY、SAMEORIGIN，为空时不发送
+    #[serde(default = "default_frame_options")]
+    #[validate(length(max = 16))]
+    pub frame_options: String,
+}
+
+impl Payload for WebSecurityConfig {}
+
+fn default_hsts_max_age() -> u64 {
+    365 * 24 * 3600
+}
+
+// 接口文档页面从 unpkg 加载 Swagger UI，内联脚本按哈希放行
+fn default_content_security_policy() -> String {
+    let hashes: Vec<String> = openapi::SWAGGER_UI_HTML
+        .split("<script>")
+        .skip(1)
+        .filter_map(|x| x.split("</script>").next())
+        .map(|x| format!("'sha256-{}'", base64::encode(sha256::hash(x.as_bytes()).0)))
+        .collect();
+    format!(
+        "default-src 'self'; script-src 'self' https://unpkg.com {}; style-src 'self' 'unsafe-inline' https://unpkg.com; \
+         img-src 'self' data:; connect-src 'self'; object-src 'none'; base-uri 'none'; frame-ancestors 'none'",
+        hashes.join(" ")
+    )
+}
+
+fn default_frame_options() -> String {
+    "DENY".to_owned()
+}
+
+impl Default for WebSecurityConfig {
+    fn default() -> Self {
+        Self {
+            allowed_origins: Vec::new(),
+            allow_credentials: false,
+            hsts_max_age: default_hsts_max_age(),
+            hsts_include_subdomains: false,
+            content_security_policy: default_content_security_policy(),
+            frame_options: default_frame_options(),
+        }
+    }
+}
+
+impl WebSecurityConfig {
+    pub fn validate(&self) -> ResultType<()> {
+        for origin in self.allowed_origins.iter() {
+            let host = match origin
+                .strip_prefix("https://")
+                .or_else(|| origin.strip_prefix("http://"))
+            {
+                Some(host) => host,
+                None => bail!("origin must start with http:// or https://: {}", origin),
+            };
+            if host.is_empty() || host.contains('/') || host.contains('*') {
+                bail!(
+                    "origin must be scheme://host[:port] without path or wildcard: {}",
+                    origin
+                );
+            }
+            if HeaderValue::from_str(origin).is_err() {
+                bail!("invalid origin: {}", origin);
+            }
+        }
+        if HeaderValue::from_str(&self.content_security_policy).is_err() {
+            bail!("invalid content_security_policy");
+        }
+        if !["", "DENY", "SAMEORIGIN"].contains(&self.frame_options.as_str()) {
+            bail!("frame_options must be DENY, SAMEORIGIN or empty");
+        }
+        Ok(())
+    }
+
+    pub fn allows(&self, origin: &str) -> bool {
+        self.allowed_origins.iter().any(|x| x.eq_ignore_ascii_case(origin))
+    }
+
+    // 对所有响应添加的安全头，处理函数已设置的不覆盖
+    fn apply_headers(&self, headers: &mut HeaderMap) {
+        let mut set = |name: header::HeaderName, value: &str| {
+            if value.is_empty() || headers.contains_key(&name) {
+                return;
+            }
+            if let Ok(v) = HeaderValue::from_str(value) {
+                headers.insert(name, v);
+            }
+        };
+        set(header::CONTENT_SECURITY_POLICY, &self.content_security_policy);
+        set(header::X_FRAME_OPTIONS, &self.frame_options);
+        if self.hsts_max_age > 0 {
+            let mut hsts = format!("max-age={}", self.hsts_max_age);
+            if self.hsts_include_subdomains {
+                hsts.push_str("; includeSubDomains");
+            }
+            set(header::STRICT_TRANSPORT_SECURITY, &hsts);
+        }
+        set(header::X_CONTENT_TYPE_OPTIONS, "nosniff");
+        set(header::REFERRER_POLICY, "no-referrer");
+    }
+}
+
+pub async fn reload(db: &EnterpriseDatabase) -> ResultType<()> {
+    let config: WebSecurityConfig = match db.get_setting(WEB_SECURITY_KEY).await? {
+        Some(v) => serde_json::from_str(&v)?,
+        None => WebSecurityConfig::default(),
+    };
+    config.validate()?;
+    *CONFIG.write().await = config;
+    Ok(())
+}
+
+pub async fn get() -> WebSecurityConfig {
+    CONFIG.read().await.clone()
+}
+
+pub async fn update(db: &EnterpriseDatabase, config: WebSecurityConfig, updated_by: &str) -> ResultType<()> {
+    config.validate()?;
+    db.set_setting(WEB_SECURITY_KEY, &serde_json::to_string(&config)?, Some(updated_by))
+        .await?;
+    *CONFIG.write().await = config;
+    Ok(())
+}
+
+// 跨域和安全头中间件：只有白名单中的来源会得到 CORS 响应头，其余跨域请求由浏览器拦截
+pub async fn middleware(req: Request, next: Next) -> Response {
+    let config = CONFIG.read().await.clone();
+    let origin = req
+        .headers()
+        .get(header::ORIGIN)
+        .filter(|v| v.to_str().map(|x| config.allows(x)).unwrap_or(false))
+        .cloned();
+    let preflight =
+        req.method() == Method::OPTIONS && req.headers().contains_key(header::ACCESS_CONTROL_REQUEST_METHOD);
+    let mut res = if preflight {
+        match origin {
+            Some(_) => StatusCode::NO_CONTENT.into_response(),
+            None => StatusCode::FORBIDDEN.into_response(),
+        }
+    } else {
+        next.run(req).await
+    };
+    let headers = res.headers_mut();
+    headers.append(header::VARY, HeaderValue::from_static("origin"));
+    if let Some(origin) = origin {
+        headers.insert(header::ACCESS_CONTROL_ALLOW_ORIGIN, origin);
+        if config.allow_credentials {
+            headers.insert(
+                header::ACCESS_CONTROL_ALLOW_CREDENTIALS,
+                HeaderValue::from_static("true"),
+            );
+        }
+        if preflight {
+            headers.insert(
+                header::ACCESS_CONTROL_ALLOW_METHODS,
+                HeaderValue::from_static(ALLOW_METHODS),
+            );
+            headers.insert(
+                header::ACCESS_CONTROL_ALLOW_HEADERS,
+                HeaderValue::from_static(ALLOW_HEADERS),
+            );
+            headers.insert(
+                header::ACCESS_CONTROL_MAX_AGE,
+                HeaderValue::from_static(PREFLIGHT_MAX_AGE),
+            );
+        } else {
+            headers.insert(
+                header::ACCESS_CONTROL_EXPOSE_HEADERS,
+                HeaderValue::from_static(EXPOSE_HEADERS),
+            );
+        }
+    }
+    config.apply_headers(headers);
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Body, routing::get, Router};
+    use tower::ServiceExt;
+
+    #[test]
+    fn test_validate() {
+        let mut config = WebSecurityConfig::default();
+        assert!(config.validate().is_ok());
+        assert!(config.content_security_policy.contains("'sha256-"));
+        config.allowed_origins = vec!["https://admin.example.com:8443".to_owned()];
+        assert!(config.validate().is_ok());
+        assert!(config.allows("https://ADMIN.example.com:8443"));
+        assert!(!config.allows("https://admin.example.com"));
+        for origin in [
+            "*",
+            "admin.example.com",
+            "https://admin.example.com/",
+            "https://*.example.com",
+        ] {
+            config.allowed_origins = vec![origin.to_owned()];
+            assert!(config.validate().is_err(), "{}", origin);
+        }
+        config.allowed_origins.clear();
+        config.frame_options = "ALLOW-FROM https://example.com".to_owned();
+        assert!(config.validate().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_middleware() {
+        *CONFIG.write().await = WebSecurityConfig {
+            allowed_origins: vec!["https://admin.example.com".to_owned()],
+            ..Default::default()
+        };
+        let router = Router::new()
+            .route("/api/x", get(|| async { "ok" }))
+            .layer(axum::middleware::from_fn(middleware));
+        let send = |method: Method, origin: &str| {
+            let req = Request::builder()
+                .method(method)
+                .uri("/api/x")
+                .header(header::ORIGIN, origin)
+                .header(header::ACCESS_CONTROL_REQUEST_METHOD, "GET")
+                .body(Body::empty())
+                .unwrap();
+            router.clone().oneshot(req)
+        };
+
+        let res = send(Method::GET, "https://admin.example.com").await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let h = res.headers();
+        assert_eq!(h[header::ACCESS_CONTROL_ALLOW_ORIGIN], "https://admin.example.com");
+        assert_eq!(h[header::X_FRAME_OPTIONS], "DENY");
+        assert!(h[header::STRICT_TRANSPORT_SECURITY]
+            .to_str()
+            .unwrap()
+            .starts_with("max-age="));
+        assert!(h.contains_key(header::CONTENT_SECURITY_POLICY));
+
+        let res = send(Method::GET, "https://evil.example.com").await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert!(!res.headers().contains_key(header::ACCESS_CONTROL_ALLOW_ORIGIN));
+
+        let res = send(Method::OPTIONS, "https://admin.example.com").await.unwrap();
+        assert_eq!(res.status(), StatusCode::NO_CONTENT);
+        assert!(res.headers().contains_key(header::ACCESS_CONTROL_ALLOW_METHODS));
+        let res = send(Method::OPTIONS, "https://evil.example.com").await.unwrap();
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+        assert!(!res.headers().contains_key(header::ACCESS_CONTROL_ALLOW_ORIGIN));
+    }
+}
@@ -0,0 +1,258 @@
+// Web 会话模块 - 登录(密码、邮件验证码、受信任浏览器等任何签发完整令牌的方式)时在 sessions 表中登记会话，
+// 会话 ID 即令牌的 jti。每个请求由 web_api 的中间件按会话表校验，与令牌自身的 exp 相互独立:
+//   空闲超时   最后一次活动后 idle_timeout_mins 分钟未操作即失效，每次请求顺延(0 表示不限)
+//   绝对时长   登录后 absolute_lifetime_mins 分钟失效，不随操作顺延，最长为令牌有效期
+// 会话失效或已登出的令牌按未登录处理。到期前 warning_secs 秒起 /api/auth/session 返回 warning，
+// 界面据此提示用户，用户确认后调用 /api/auth/session/keepalive 顺延空闲时间。
+// 修改配置后对已有会话立即生效
+use crate::auth::Session;
+use crate::enterprise_database::EnterpriseDatabase;
+use crate::validation::Payload;
+use hbb_common::{bail, log, tokio::sync::RwLock, ResultType};
+use schemars::JsonSchema;
+use serde_derive::{Deserialize, Serialize};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use validator::Validate;
+
+pub const WEB_SESSION_KEY: &str = "web_session";
+// 令牌有效期(AuthManager 的 session_timeout)，绝对时长不能超过该值
+const MAX_LIFETIME_MINS: u32 = 8 * 60;
+// 两次记录活动时间的最小间隔，避免每个请求都写数据库
+const TOUCH_INTERVAL_SECS: u64 = 30;
+// 已失效的会话保留一段时间后清理
+const RETENTION_SECS: u64 = 7 * 24 * 3600;
+
+lazy_static::lazy_static! {
+    static ref CONFIG: RwLock<WebSessionConfig> = Default::default();
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, Validate)]
+pub struct WebSessionConfig {
+    #[serde(default = "default_idle_timeout_mins")]
+    pub idle_timeout_mins: u32,
+    #[serde(default = "default_absolute_lifetime_mins")]
+    #[validate(range(min = 5))]
+    pub absolute_lifetime_mins: u32,
+    #[serde(default = "default_warning_secs")]
+    #[validate(range(max = 3600))]
+    pub warning_secs: u32,
+}
+
+impl Payload for WebSessionConfig {}
+
+fn default_idle_timeout_mins() -> u32 {
+    30
+}
+
+fn default_absolute_lifetime_mins() -> u32 {
+    MAX_LIFETIME_MINS
+}
+
+fn default_warning_secs() -> u32 {
+    120
+}
+
+impl Default for WebSessionConfig {
+    fn default() -> Self {
+        Self {
+            idle_timeout_mins: default_idle_timeout_mins(),
+            absolute_lifetime_mins: default_absolute_lifetime_mins(),
+            warning_secs: default_warning_secs(),
+        }
+    }
+}
+
+impl WebSessionConfig {
+    pub fn validate(&self) -> ResultType<()> {
+        if self.absolute_lifetime_mins < 5 || self.absolute_lifetime_mins > MAX_LIFETIME_MINS {
+            bail!("absolute_lifetime_mins must be 5-{}", MAX_LIFETIME_MINS);
+        }
+        if self.idle_timeout_mins > self.absolute_lifetime_mins {
+            bail!("idle_timeout_mins must not exceed absolute_lifetime_mins");
+        }
+        if self.warning_secs > 3600 {
+            bail!("warning_secs must be 0-3600");
+        }
+        if self.idle_timeout_mins > 0 && self.warning_secs as u64 >= self.idle_timeout_mins as u64 * 60 {
+            bail!("warning_secs must be less than the idle timeout");
+        }
+        Ok(())
+    }
+
+    // 会话失效时间: 取空闲到期和绝对到期中较早者
+    fn expires_at(&self, session: &Session) -> u64 {
+        let absolute = secs(session.created_at) + self.absolute_lifetime_mins as u64 * 60;
+        match self.idle_timeout_mins {
+            0 => absolute,
+            idle => absolute.min(secs(session.last_activity) + idle as u64 * 60),
+        }
+    }
+
+    pub fn status(&self, session: &Session, now: u64) -> WebSessionStatus {
+        let expires_at = self.expires_at(session);
+        WebSessionStatus {
+            absolute_expires_at: secs(session.created_at) + self.absolute_lifetime_mins as u64 * 60,
+            expires_at,
+            warning: now + self.warning_secs as u64 >= expires_at,
+            warning_secs: self.warning_secs,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct WebSessionStatus {
+    // 不再操作时的失效时间
+    pub expires_at: u64,
+    // 不随操作顺延的最晚失效时间
+    pub absolute_expires_at: u64,
+    // 已进入到期提醒时间
+    pub warning: bool,
+    pub warning_secs: u32,
+}
+
+fn secs(t: SystemTime) -> u64 {
+    t.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default()
+}
+
+// 签发完整令牌后登记会话
+pub async fn start(db: &EnterpriseDatabase, jti: &str, user_id: &str, user_agent: Option<String>) -> ResultType<()> {
+    let now = SystemTime::now();
+    let config = CONFIG.read().await.clone();
+    let session = Session {
+        id: jti.to_owned(),
+        user_id: user_id.to_owned(),
+        token: jti.to_owned(),
+        created_at: now,
+        expires_at: now + Duration::from_secs(config.absolute_lifetime_mins as u64 * 60),
+        last_activity: now,
+        ip_address: "127.0.0.1".to_owned(),
+        user_agent,
+        active: true,
+    };
+    db.create_session(&session).await?;
+    if let Err(e) = db.delete_stale_sessions(secs(now).saturating_sub(RETENTION_SECS)).await {
+        log::error!("Failed to clean up web sessions: {}", e);
+    }
+    Ok(())
+}
+
+// 校验会话并记录活动，slide 为 false 时不顺延空闲时间；会话不存在、已登出或已过期时返回 None
+pub async fn touch(db: &EnterpriseDatabase, jti: &str, slide: bool) -> ResultType<Option<WebSessionStatus>> {
+    check(db, jti, slide.then_some(TOUCH_INTERVAL_SECS)).await
+}
+
+// 用户在到期提醒中确认继续使用，立即顺延空闲时间
+pub async fn keepalive(db: &EnterpriseDatabase, jti: &str) -> ResultType<Option<WebSessionStatus>> {
+    check(db, jti, Some(0)).await
+}
+
+// 距上次记录活动超过 slide_after 秒时顺延
+async fn check(db: &EnterpriseDatabase, jti: &str, slide_after: Option<u64>) -> ResultType<Option<WebSessionStatus>> {
+    let mut session = match db.get_session(jti).await? {
+        Some(session) if session.active => session,
+        _ => return Ok(None),
+    };
+    let config = CONFIG.read().await.clone();
+    let now = secs(SystemTime::now());
+    if now >= config.expires_at(&session) {
+        db.end_session(jti).await?;
+        log::info!("Web session of {} expired", session.user_id);
+        return Ok(None);
+    }
+    if let Some(slide_after) = slide_after {
+        if now >= secs(session.last_activity) + slide_after {
+            db.touch_session(jti, now).await?;
+            session.last_activity = UNIX_EPOCH + Duration::from_secs(now);
+        }
+    }
+    Ok(Some(config.status(&session, now)))
+}
+
+pub async fn end(db: &EnterpriseDatabase, jti: &str) -> ResultType<()> {
+    db.end_session(jti).await
+}
+
+// 重置密码等场景下使用户已登录的令牌全部失效
+pub async fn end_all(db: &EnterpriseDatabase, user_id: &str) -> ResultType<u64> {
+    db.end_user_sessions(user_id).await
+}
+
+pub async fn reload(db: &EnterpriseDatabase) -> ResultType<()> {
+    let config: WebSessionConfig = match db.get_setting(WEB_SESSION_KEY).await? {
+        Some(v) => serde_json::from_str(&v)?,
+        None => WebSessionConfig::default(),
+    };
+    config.validate()?;
+    *CONFIG.write().await = config;
+    Ok(())
+}
+
+pub async fn get() -> WebSessionConfig {
+    CONFIG.read().await.clone()
+}
+
+pub async fn update(db: &EnterpriseDatabase, config: WebSessionConfig, updated_by: &str) -> ResultType<()> {
+    config.validate()?;
+    db.set_setting(WEB_SESSION_KEY, &serde_json::to_string(&config)?, Some(updated_by))
+        .await?;
+    *CONFIG.write().await = config;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session(created_at: u64, last_activity: u64) -> Session {
+        Session {
+            id: "jti".to_owned(),
+            user_id: "u1".to_owned(),
+            token: "jti".to_owned(),
+            created_at: UNIX_EPOCH + Duration::from_secs(created_at),
+            expires_at: UNIX_EPOCH + Duration::from_secs(created_at + 3600),
+            last_activity: UNIX_EPOCH + Duration::from_secs(last_activity),
+            ip_address: "127.0.0.1".to_owned(),
+            user_agent: None,
+            active: true,
+        }
+    }
+
+    #[test]
+    fn test_status() {
+        let config = WebSessionConfig {
+            idle_timeout_mins: 10,
+            absolute_lifetime_mins: 60,
+            warning_secs: 60,
+        };
+        assert!(config.validate().is_ok());
+        let status = config.status(&session(0, 0), 100);
+        assert_eq!(status.expires_at, 600);
+        assert_eq!(status.absolute_expires_at, 3600);
+        assert!(!status.warning);
+        assert!(config.status(&session(0, 0), 540).warning);
+        // 活动顺延空闲时间，但不超过绝对时长
+        assert_eq!(config.status(&session(0, 1000), 1000).expires_at, 1600);
+        assert_eq!(config.status(&session(0, 3500), 3500).expires_at, 3600);
+        let config = WebSessionConfig {
+            idle_timeout_mins: 0,
+            ..config
+        };
+        assert_eq!(config.status(&session(0, 0), 100).expires_at, 3600);
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(WebSessionConfig::default().validate().is_ok());
+        let config = WebSessionConfig {
+            absolute_lifetime_mins: MAX_LIFETIME_MINS + 1,
+            ..Default::default()
+        };
+        assert!(config.validate().is_err());
+        let config = WebSessionConfig {
+            idle_timeout_mins: 1,
+            warning_secs: 60,
+            ..Default::default()
+        };
+        assert!(config.validate().is_err());
+    }
+}
@@ -0,0 +1,525 @@
+// WebRTC 信令模块 - 为浏览器网页客户端交换 SDP 和 ICE 候选，连接本身由浏览器与被控端点对点建立，
+// 服务器不转发媒体。流程:
+//   1. 浏览器用登录令牌 POST /api/webrtc/sessions 提交 offer，经过与打洞相同的封禁、版本、无人值守时段
+//      和双人审批检查后登记会话，返回会话ID和 ICE 服务器(含 TURN 凭据，只下发给通过授权的会话；
+//      启用 TURN 临时凭据服务时额外附带按用户签发的凭据)
+//   2. 被控端心跳的 caps 中声明 webrtc，心跳响应中带上待应答的 offer；被控端以 id + uuid
+//      POST /api/webrtc/sessions/:id/device 提交 answer 或拒绝，并交换 ICE 候选
+//   3. 浏览器轮询 POST /api/webrtc/sessions/:id/signal 取回 answer 和被控端的候选，同时提交自己的候选
+// 会话只保存在内存中，offer 超时未应答或信令窗口结束后自动清除
+use crate::break_glass;
+use crate::device_ban;
+use crate::enterprise_database::EnterpriseDatabase;
+use crate::four_eyes;
+use crate::turn;
+use crate::unattended_access;
+use crate::validation::Payload;
+use crate::version_policy;
+use hbb_common::{bail, tokio::sync::RwLock, ResultType};
+use schemars::JsonSchema;
+use serde_derive::{Deserialize, Serialize};
+use std::collections::HashMap;
+use validator::Validate;
+
+pub const WEBRTC_KEY: &str = "webrtc";
+// 客户端心跳 caps 中的能力名
+pub const CAPABILITY: &str = "webrtc";
+const CREDENTIAL_MASK: &str = "******";
+const MAX_SDP_LEN: usize = 64 * 1024;
+// 每一端最多缓存的未取走候选数
+const MAX_CANDIDATES: usize = 64;
+// 超过该时间没有心跳声明 webrtc 的设备视为不支持
+const CAPABILITY_TTL_SECS: u64 = 300;
+
+lazy_static::lazy_static! {
+    static ref CONFIG: RwLock<WebRtcConfig> = Default::default();
+    static ref SESSIONS: RwLock<HashMap<String, Session>> = Default::default();
+    // 设备ID -> 最近一次声明支持 webrtc 的时间
+    static ref CAPABLE: RwLock<HashMap<String, u64>> = Default::default();
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema, Validate)]
+pub struct WebRtcConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    // 与浏览器 RTCIceServer 格式相同
+    #[serde(default)]
+    pub ice_servers: Vec<IceServer>,
+    // 被控端需在该时间内应答 offer
+    #[serde(default = "default_answer_timeout_secs")]
+    pub answer_timeout_secs: u64,
+    // 会话登记后可交换候选的时长
+    #[serde(default = "default_session_ttl_secs")]
+    pub session_ttl_secs: u64,
+}
+
+impl Payload for WebRtcConfig {}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct IceServer {
+    pub urls: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub credential: Option<String>,
+}
+
+// 与浏览器 RTCIceCandidateInit 格式相同
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct IceCandidate {
+    pub candidate: String,
+    #[serde(default, rename = "sdpMid")]
+    pub sdp_mid: Option<String>,
+    #[serde(default, rename = "sdpMLineIndex")]
+    pub sdp_mline_index: Option<u32>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum SessionState {
+    Offered,
+    Answered,
+    Rejected,
+}
+
+#[derive(Debug, Clone)]
+struct Session {
+    device_id: String,
+    user_id: String,
+    username: String,
+    offer: String,
+    answer: Option<String>,
+    state: SessionState,
+    // 对方尚未取走的候选
+    controller_candidates: Vec<IceCandidate>,
+    device_candidates: Vec<IceCandidate>,
+    created_at: u64,
+}
+
+// 创建会话后返回给浏览器
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct SessionOffer {
+    pub session_id: String,
+    pub ice_servers: Vec<IceServer>,
+    pub expires_at: u64,
+}
+
+// 浏览器每次轮询得到的更新
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct ControllerUpdate {
+    pub state: SessionState,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub answer: Option<String>,
+    pub candidates: Vec<IceCandidate>,
+}
+
+// 被控端提交的 answer(或拒绝)和候选
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct DeviceSignal {
+    #[serde(default)]
+    pub answer: Option<String>,
+    #[serde(default)]
+    pub reject: bool,
+    #[serde(default)]
+    pub candidates: Vec<IceCandidate>,
+}
+
+// 通过心跳下发给被控端的待应答 offer
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct ClientWebRtcOffer {
+    pub session_id: String,
+    pub sdp: String,
+    pub requester: String,
+    pub ice_servers: Vec<IceServer>,
+}
+
+fn default_answer_timeout_secs() -> u64 {
+    60
+}
+
+fn default_session_ttl_secs() -> u64 {
+    600
+}
+
+impl Default for WebRtcConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            ice_servers: Vec::new(),
+            answer_timeout_secs: default_answer_timeout_secs(),
+            session_ttl_secs: default_session_ttl_secs(),
+        }
+    }
+}
+
+impl WebRtcConfig {
+    pub fn validate(&self) -> ResultType<()> {
+        for server in self.ice_servers.iter() {
+            if server.urls.is_empty() {
+                bail!("ice server urls must not be empty");
+            }
+            for url in server.urls.iter() {
+                let turn = url.starts_with("turn:") || url.starts_with("turns:");
+                if !turn && !url.starts_with("stun:") && !url.starts_with("stuns:") {
+                    bail!("invalid ice server url: {}", url);
+                }
+                if turn && (server.username.is_none() || server.credential.is_none()) {
+                    bail!("turn server {} requires username and credential", url);
+                }
+            }
+        }
+        if self.answer_timeout_secs < 10 || self.answer_timeout_secs > 300 {
+            bail!("answer_timeout_secs must be 10-300");
+        }
+        if self.session_ttl_secs < self.answer_timeout_secs || self.session_ttl_secs > 3600 {
+            bail!("session_ttl_secs must be answer_timeout_secs-3600");
+        }
+        Ok(())
+    }
+
+    // 接口返回时隐藏 TURN 凭据
+    pub fn masked(&self) -> Self {
+        let mut config = self.clone();
+        for server in config.ice_servers.iter_mut() {
+            if server.credential.is_some() {
+                server.credential = Some(CREDENTIAL_MASK.to_owned());
+            }
+        }
+        config
+    }
+}
+
+impl Session {
+    fn expired(&self, config: &WebRtcConfig, now: u64) -> bool {
+        let ttl = match self.state {
+            SessionState::Offered => config.answer_timeout_secs,
+            _ => config.session_ttl_secs,
+        };
+        now >= self.created_at + ttl
+    }
+}
+
+fn push_candidates(queue: &mut Vec<IceCandidate>, candidates: Vec<IceCandidate>) -> ResultType<()> {
+    if queue.len() + candidates.len() > MAX_CANDIDATES {
+        bail!("too many ice candidates");
+    }
+    queue.extend(candidates);
+    Ok(())
+}
+
+async fn prune(sessions: &mut HashMap<String, Session>) {
+    let config = CONFIG.read().await;
+    let now = crate::common::now();
+    sessions.retain(|_, s| !s.expired(&config, now));
+}
+
+pub async fn reload(db: &EnterpriseDatabase) -> ResultType<()> {
+    let config: WebRtcConfig = match db.get_setting(WEBRTC_KEY).await? {
+        Some(v) => serde_json::from_str(&v)?,
+        None => WebRtcConfig::default(),
+    };
+    config.validate()?;
+    *CONFIG.write().await = config;
+    Ok(())
+}
+
+pub async fn get() -> WebRtcConfig {
+    CONFIG.read().await.clone()
+}
+
+pub async fn update(db: &EnterpriseDatabase, mut config: WebRtcConfig, updated_by: &str) -> ResultType<()> {
+    // 前端回传掩码时保留原凭据(按顺序对应)
+    {
+        let old = CONFIG.read().await;
+        for (i, server) in config.ice_servers.iter_mut().enumerate() {
+            if server.credential.as_deref() == Some(CREDENTIAL_MASK) {
+                server.credential = old.ice_servers.get(i).and_then(|s| s.credential.clone());
+            }
+        }
+    }
+    config.validate()?;
+    db.set_setting(WEBRTC_KEY, &serde_json::to_string(&config)?, Some(updated_by))
+        .await?;
+    if !config.enabled {
+        SESSIONS.write().await.clear();
+    }
+    *CONFIG.write().await = config;
+    Ok(())
+}
+
+// 设备心跳声明支持 webrtc 时调用
+pub async fn mark_capable(device_id: &str) {
+    CAPABLE.write().await.insert(device_id.to_owned(), crate::common::now());
+}
+
+async fn is_capable(device_id: &str) -> bool {
+    match CAPABLE.read().await.get(device_id) {
+        Some(t) => crate::common::now() < t + CAPABILITY_TTL_SECS,
+        None => false,
+    }
+}
+
+// 与打洞相同的会话授权检查，拒绝时返回原因；敏感设备可能挂起等待审批
+pub async fn refusal(db: &EnterpriseDatabase, device_id: &str, user_id: &str, username: &str) -> Option<&'static str> {
+    if device_ban::is_banned(device_id).await {
+        return Some("device banned");
+    }
+    if version_policy::is_blocked(db, device_id).await {
+        return Some("client update required");
+    }
+    if unattended_access::is_refused(db, device_id).await {
+        return Some("outside unattended access window");
+    }
+    // 持有紧急访问授权的用户跳过审批
+    if !break_glass::is_active(db, device_id, user_id).await
+        && !four_eyes::authorize(db, device_id, Some(user_id), Some(username), "127.0.0.1").await
+    {
+        return Some("session approval required");
+    }
+    None
+}
+
+// 登记浏览器的 offer，调用前需已通过 refusal 检查
+pub async fn create(device_id: &str, user_id: &str, username: &str, offer: String) -> ResultType<SessionOffer> {
+    let config = CONFIG.read().await.clone();
+    if !config.enabled {
+        bail!("webrtc is disabled");
+    }
+    if offer.is_empty() || offer.len() > MAX_SDP_LEN {
+        bail!("invalid sdp offer");
+    }
+    if !is_capable(device_id).await {
+        bail!("device does not support webrtc");
+    }
+    let session_id = uuid::Uuid::new_v4().to_string();
+    let created_at = crate::common::now();
+    let mut sessions = SESSIONS.write().await;
+    prune(&mut sessions).await;
+    sessions.insert(
+        session_id.clone(),
+        Session {
+            device_id: device_id.to_owned(),
+            user_id: user_id.to_owned(),
+            username: username.to_owned(),
+            offer,
+            answer: None,
+            state: SessionState::Offered,
+            controller_candidates: Vec::new(),
+            device_candidates: Vec::new(),
+            created_at,
+        },
+    );
+    let mut ice_servers = config.ice_servers;
+    ice_servers.extend(turn::ice_server(user_id).await);
+    Ok(SessionOffer {
+        session_id,
+        ice_servers,
+        expires_at: created_at + config.session_ttl_secs,
+    })
+}
+
+// 浏览器提交候选并取回 answer 和被控端的候选，只有会话发起人可以调用
+pub async fn controller_signal(
+    session_id: &str,
+    user_id: &str,
+    candidates: Vec<IceCandidate>,
+) -> ResultType<ControllerUpdate> {
+    let mut sessions = SESSIONS.write().await;
+    prune(&mut sessions).await;
+    let session = match sessions.get_mut(session_id) {
+        Some(session) if session.user_id == user_id => session,
+        _ => bail!("session not found"),
+    };
+    if session.state != SessionState::Rejected {
+        push_candidates(&mut session.controller_candidates, candidates)?;
+    }
+    Ok(ControllerUpdate {
+        state: session.state,
+        answer: session.answer.clone(),
+        candidates: std::mem::take(&mut session.device_candidates),
+    })
+}
+
+// 被控端提交 answer 或拒绝，并交换候选，返回浏览器的候选
+pub async fn device_signal(session_id: &str, device_id: &str, signal: DeviceSignal) -> ResultType<Vec<IceCandidate>> {
+    let mut sessions = SESSIONS.write().await;
+    prune(&mut sessions).await;
+    let session = match sessions.get_mut(session_id) {
+        Some(session) if session.device_id == device_id => session,
+        _ => bail!("session not found"),
+    };
+    if session.state == SessionState::Rejected {
+        bail!("session rejected");
+    }
+    if signal.reject {
+        session.state = SessionState::Rejected;
+        session.controller_candidates.clear();
+        return Ok(Vec::new());
+    }
+    if let Some(answer) = signal.answer {
+        if session.state != SessionState::Offered {
+            bail!("session already answered");
+        }
+        if answer.is_empty() || answer.len() > MAX_SDP_LEN {
+            bail!("invalid sdp answer");
+        }
+        session.answer = Some(answer);
+        session.state = SessionState::Answered;
+        // 信令窗口从应答时开始计算
+        session.created_at = crate::common::now();
+    }
+    push_candidates(&mut session.device_candidates, signal.candidates)?;
+    Ok(std::mem::take(&mut session.controller_candidates))
+}
+
+// 浏览器结束信令，返回被关闭会话的设备ID
+pub async fn close(session_id: &str, user_id: &str) -> Option<String> {
+    let mut sessions = SESSIONS.write().await;
+    match sessions.get(session_id) {
+        Some(session) if session.user_id == user_id => sessions.remove(session_id).map(|s| s.device_id),
+        _ => None,
+    }
+}
+
+// 设备心跳时取出待应答的 offer，应答前每次心跳都会带上
+pub async fn pending_for_device(device_id: &str) -> Vec<ClientWebRtcOffer> {
+    let mut sessions = SESSIONS.write().await;
+    prune(&mut sessions).await;
+    if sessions.is_empty() {
+        return Vec::new();
+    }
+    let mut ice_servers = CONFIG.read().await.ice_servers.clone();
+    ice_servers.extend(turn::ice_server(&format!("device-{}", device_id)).await);
+    sessions
+        .iter()
+        .filter(|(_, s)| s.device_id == device_id && s.state == SessionState::Offered)
+        .map(|(id, s)| ClientWebRtcOffer {
+            session_id: id.clone(),
+            sdp: s.offer.clone(),
+            requester: s.username.clone(),
+            ice_servers: ice_servers.clone(),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hbb_common::tokio;
+
+    fn candidate(s: &str) -> IceCandidate {
+        IceCandidate {
+            candidate: s.to_owned(),
+            sdp_mid: Some("0".to_owned()),
+            sdp_mline_index: Some(0),
+        }
+    }
+
+    #[test]
+    fn test_validate() {
+        let mut config: WebRtcConfig = serde_json::from_str(
+            r#"{"enabled": true, "ice_servers": [{"urls": ["stun:stun.example.com:3478"]},
+                {"urls": ["turn:turn.example.com:3478?transport=udp"], "username": "u", "credential": "p"}]}"#,
+        )
+        .unwrap();
+        assert!(config.validate().is_ok());
+        assert_eq!(
+            config.masked().ice_servers[1].credential.as_deref(),
+            Some(CREDENTIAL_MASK)
+        );
+        assert_eq!(config.masked().ice_servers[0].credential, None);
+
+        config.ice_servers[1].credential = None;
+        assert!(config.validate().is_err());
+        config.ice_servers[1].credential = Some("p".to_owned());
+        config.ice_servers[0].urls = vec!["http://stun.example.com".to_owned()];
+        assert!(config.validate().is_err());
+        config.ice_servers[0].urls = vec!["stun:stun.example.com".to_owned()];
+        config.session_ttl_secs = 30;
+        assert!(config.validate().is_err());
+        assert!(WebRtcConfig::default().validate().is_ok());
+    }
+
+    #[test]
+    fn test_candidate_format() {
+        let c: IceCandidate = serde_json::from_str(
+            r#"{"candidate": "candidate:1 1 udp 1 10.0.0.1 5000 typ host", "sdpMid": "0", "sdpMLineIndex": 0}"#,
+        )
+        .unwrap();
+        assert_eq!(c.sdp_mid.as_deref(), Some("0"));
+        assert_eq!(c.sdp_mline_index, Some(0));
+    }
+
+    #[tokio::test]
+    async fn test_signaling() {
+        *CONFIG.write().await = WebRtcConfig {
+            enabled: true,
+            ..Default::default()
+        };
+        assert!(create("webrtc-1", "u1", "alice", "v=0".to_owned()).await.is_err());
+        mark_capable("webrtc-1").await;
+        let offer = create("webrtc-1", "u1", "alice", "v=0".to_owned()).await.unwrap();
+        let id = offer.session_id;
+
+        let pending = pending_for_device("webrtc-1").await;
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].requester, "alice");
+        assert!(pending_for_device("webrtc-2").await.is_empty());
+
+        // 其他用户和设备看不到该会话
+        assert!(controller_signal(&id, "u2", vec![]).await.is_err());
+        assert!(device_signal(&id, "webrtc-2", DeviceSignal::default()).await.is_err());
+
+        let update = controller_signal(&id, "u1", vec![candidate("a1")]).await.unwrap();
+        assert_eq!(update.state, SessionState::Offered);
+        assert!(update.answer.is_none());
+
+        let signal = DeviceSignal {
+            answer: Some("v=0 answer".to_owned()),
+            reject: false,
+            candidates: vec![candidate("b1")],
+        };
+        assert_eq!(
+            device_signal(&id, "webrtc-1", signal.clone()).await.unwrap(),
+            vec![candidate("a1")]
+        );
+        assert!(device_signal(&id, "webrtc-1", signal).await.is_err());
+        assert!(pending_for_device("webrtc-1").await.is_empty());
+
+        let update = controller_signal(&id, "u1", vec![]).await.unwrap();
+        assert_eq!(update.state, SessionState::Answered);
+        assert_eq!(update.answer.as_deref(), Some("v=0 answer"));
+        assert_eq!(update.candidates, vec![candidate("b1")]);
+        // 候选取走后不再重复返回
+        assert!(controller_signal(&id, "u1", vec![])
+            .await
+            .unwrap()
+            .candidates
+            .is_empty());
+
+        assert!(close(&id, "u2").await.is_none());
+        assert_eq!(close(&id, "u1").await.as_deref(), Some("webrtc-1"));
+        assert!(controller_signal(&id, "u1", vec![]).await.is_err());
+    }
+
+    #[test]
+    fn test_expired() {
+        let config = WebRtcConfig::default();
+        let mut session = Session {
+            device_id: "d".to_owned(),
+            user_id: "u".to_owned(),
+            username: "u".to_owned(),
+            offer: "v=0".to_owned(),
+            answer: None,
+            state: SessionState::Offered,
+            controller_candidates: Vec::new(),
+            device_candidates: Vec::new(),
+            created_at: 1000,
+        };
+        assert!(!session.expired(&config, 1059));
+        assert!(session.expired(&config, 1060));
+        session.state = SessionState::Answered;
+        assert!(!session.expired(&config, 1060));
+        assert!(session.expired(&config, 1600));
+    }
+}
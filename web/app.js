@@ -94,6 +94,7 @@ class RustDeskAdmin {
         this.token = null;
         this.currentUser = null;
         localStorage.removeItem('token');
+        this.stopSessionWatch();
         this.showLogin();
     }
 
@@ -109,6 +110,87 @@ class RustDeskAdmin {
         if (this.currentUser) {
             document.getElementById('currentUser').textContent = this.currentUser.username;
         }
+        this.loadAnnouncements();
+        this.startSessionWatch();
+    }
+
+    // 定期查询会话失效时间，到期前提醒用户；会话失效后接口返回 401，apiCall 会退回登录页
+    startSessionWatch() {
+        this.stopSessionWatch();
+        this.sessionTimer = setInterval(() => this.checkSession(), 30000);
+        this.checkSession();
+    }
+
+    stopSessionWatch() {
+        if (this.sessionTimer) {
+            clearInterval(this.sessionTimer);
+            this.sessionTimer = null;
+        }
+        document.getElementById('sessionWarning').innerHTML = '';
+    }
+
+    async checkSession() {
+        const banner = document.getElementById('sessionWarning');
+        try {
+            const response = await this.apiCall('/auth/session', 'GET');
+            if (!response.success || !response.data.warning) {
+                banner.innerHTML = '';
+                return;
+            }
+            const minutes = Math.max(1, Math.ceil((response.data.expires_at * 1000 - Date.now()) / 60000));
+            const absolute = response.data.expires_at >= response.data.absolute_expires_at;
+            banner.innerHTML = '';
+            const div = document.createElement('div');
+            div.className = 'alert alert-warning d-flex align-items-center justify-content-between';
+            const text = document.createElement('span');
+            text.textContent = absolute
+                ? `登录将在约 ${minutes} 分钟后到期，请保存工作后重新登录`
+                : `长时间未操作，登录将在约 ${minutes} 分钟后失效`;
+            div.appendChild(text);
+            if (!absolute) {
+                const button = document.createElement('button');
+                button.type = 'button';
+                button.className = 'btn btn-sm btn-warning';
+                button.textContent = '保持登录';
+                button.addEventListener('click', async () => {
+                    await this.apiCall('/auth/session/keepalive', 'POST');
+                    banner.innerHTML = '';
+                });
+                div.appendChild(button);
+            }
+            banner.appendChild(div);
+        } catch (error) {
+            console.error('Failed to check session:', error);
+        }
+    }
+
+    // 生效中的公告显示在页面顶部
+    async loadAnnouncements() {
+        const banner = document.getElementById('announcementBanner');
+        banner.innerHTML = '';
+        try {
+            const response = await this.apiCall('/announcements/active', 'GET');
+            if (!response.success) {
+                return;
+            }
+            const types = { info: 'info', warning: 'warning', critical: 'danger' };
+            response.data.forEach(announcement => {
+                const div = document.createElement('div');
+                div.className = `alert alert-${types[announcement.level] || 'info'} alert-dismissible fade show`;
+                const title = document.createElement('strong');
+                title.textContent = announcement.title;
+                const body = document.createElement('div');
+                body.textContent = announcement.body;
+                const close = document.createElement('button');
+                close.type = 'button';
+                close.className = 'btn-close';
+                close.dataset.bsDismiss = 'alert';
+                div.append(title, body, close);
+                banner.appendChild(div);
+            });
+        } catch (error) {
+            console.error('Failed to load announcements:', error);
+        }
     }
 
     showPage(pageName) {
@@ -156,6 +238,12 @@ class RustDeskAdmin {
                 document.getElementById('onlineDevices').textContent = stats.online_devices || 0;
                 document.getElementById('todayConnections').textContent = stats.total_connections_today || 0;
                 document.getElementById('activeSessions').textContent = stats.active_sessions || 0;
+                // 本机网络统计，非 Linux 主机没有
+                const rate = bytes => bytes === undefined ? '-' : `${(bytes * 8 / 1000000).toFixed(1)} Mbps`;
+                document.getElementById('hostRx').textContent = rate(stats.host_rx_bytes_per_sec);
+                document.getElementById('hostTx').textContent = rate(stats.host_tx_bytes_per_sec);
+                document.getElementById('hostRetrans').textContent = stats.host_tcp_retrans_per_sec ?? '-';
+                document.getElementById('hostListenQueued').textContent = stats.host_listen_queued ?? '-';
             }
         } catch (error) {
             console.error('Failed to load dashboard:', error);